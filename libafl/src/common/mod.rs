@@ -13,6 +13,9 @@ use libafl_bolts::{
     Error,
 };
 /// Trait for elements offering metadata
+///
+/// There is at most one metadata of each type, [`SerdeAnyMap::values`] lists them for debugging.
+/// For metadata of components with several instances, see [`HasNamedMetadata`].
 pub trait HasMetadata {
     /// A map, storing all metadata
     fn metadata_map(&self) -> &SerdeAnyMap;
@@ -45,6 +48,32 @@ pub trait HasMetadata {
         self.metadata_map_mut().remove::<M>()
     }
 
+    /// Replace a metadata in the metadata map, returning the previous one, if any
+    #[inline]
+    fn replace_metadata<M>(&mut self, meta: M) -> Option<Box<M>>
+    where
+        M: SerdeAny,
+    {
+        let old = self.metadata_map_mut().remove::<M>();
+        self.metadata_map_mut().insert(meta);
+        old
+    }
+
+    /// Replace the metadata of type `M` by the metadata of type `N` that `migrate` converts it to,
+    /// e.g. when a component gets reconfigured at runtime.
+    /// Returns `false`, and adds nothing, if there is no metadata of type `M`.
+    fn migrate_metadata<M, N>(&mut self, migrate: impl FnOnce(M) -> N) -> bool
+    where
+        M: SerdeAny,
+        N: SerdeAny,
+    {
+        let Some(old) = self.metadata_map_mut().remove::<M>() else {
+            return false;
+        };
+        self.metadata_map_mut().insert(migrate(*old));
+        true
+    }
+
     /// Check for a metadata
     ///
     /// # Note
@@ -81,6 +110,11 @@ pub trait HasMetadata {
 }
 
 /// Trait for elements offering named metadata
+///
+/// The name is the namespace of a component: components of which several instances may exist,
+/// e.g. feedbacks or stages, store their metadata under their name, so the instances don't collide.
+/// All metadata of a component can be dropped with [`Self::remove_all_named_metadata`], and
+/// [`NamedSerdeAnyMap::entries`] lists the stored metadata for debugging.
 pub trait HasNamedMetadata {
     /// A map, storing all metadata
     fn named_metadata_map(&self) -> &NamedSerdeAnyMap;
@@ -105,6 +139,39 @@ pub trait HasNamedMetadata {
         self.named_metadata_map_mut().remove::<M>(name)
     }
 
+    /// Remove all metadata with the given name, of any type, e.g. of a removed component.
+    /// Returns the number of removed metadata.
+    #[inline]
+    fn remove_all_named_metadata(&mut self, name: &str) -> usize {
+        self.named_metadata_map_mut().remove_all(name)
+    }
+
+    /// Replace a metadata in the metadata map, returning the previous one, if any
+    #[inline]
+    fn replace_named_metadata<M>(&mut self, name: &str, meta: M) -> Option<Box<M>>
+    where
+        M: SerdeAny,
+    {
+        let old = self.named_metadata_map_mut().remove::<M>(name);
+        self.named_metadata_map_mut().insert(name, meta);
+        old
+    }
+
+    /// Replace the metadata of type `M` by the metadata of type `N` that `migrate` converts it to,
+    /// keeping its name.
+    /// Returns `false`, and adds nothing, if there is no metadata of type `M` with this name.
+    fn migrate_named_metadata<M, N>(&mut self, name: &str, migrate: impl FnOnce(M) -> N) -> bool
+    where
+        M: SerdeAny,
+        N: SerdeAny,
+    {
+        let Some(old) = self.named_metadata_map_mut().remove::<M>(name) else {
+            return false;
+        };
+        self.named_metadata_map_mut().insert(name, migrate(*old));
+        true
+    }
+
     /// Gets metadata, or inserts it using the given construction function `default`
     fn named_metadata_or_insert_with<M>(
         &mut self,
@@ -152,3 +219,79 @@ pub trait HasNamedMetadata {
             .ok_or_else(|| Error::key_not_found(format!("{} not found", type_name::<M>())))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::serdeany::{NamedSerdeAnyMap, SerdeAnyMap};
+    use serde::{Deserialize, Serialize};
+
+    use super::{HasMetadata, HasNamedMetadata};
+
+    #[derive(Debug, Default)]
+    struct Component {
+        metadata: SerdeAnyMap,
+        named_metadata: NamedSerdeAnyMap,
+    }
+
+    impl HasMetadata for Component {
+        fn metadata_map(&self) -> &SerdeAnyMap {
+            &self.metadata
+        }
+
+        fn metadata_map_mut(&mut self) -> &mut SerdeAnyMap {
+            &mut self.metadata
+        }
+    }
+
+    impl HasNamedMetadata for Component {
+        fn named_metadata_map(&self) -> &NamedSerdeAnyMap {
+            &self.named_metadata
+        }
+
+        fn named_metadata_map_mut(&mut self) -> &mut NamedSerdeAnyMap {
+            &mut self.named_metadata
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct OldMetadata(u32);
+    libafl_bolts::impl_serdeany!(OldMetadata);
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct NewMetadata(u64);
+    libafl_bolts::impl_serdeany!(NewMetadata);
+
+    #[test]
+    fn test_migrate_metadata() {
+        #[cfg(miri)]
+        unsafe {
+            libafl_bolts::serdeany::RegistryBuilder::register::<OldMetadata>();
+            libafl_bolts::serdeany::RegistryBuilder::register::<NewMetadata>();
+        }
+
+        let mut component = Component::default();
+        assert!(!component.migrate_metadata(|old: OldMetadata| NewMetadata(old.0.into())));
+        component.add_metadata(OldMetadata(1));
+        assert_eq!(component.replace_metadata(OldMetadata(2)).unwrap().0, 1);
+        assert!(component.migrate_metadata(|old: OldMetadata| NewMetadata(old.0.into())));
+        assert!(!component.has_metadata::<OldMetadata>());
+        assert_eq!(component.metadata::<NewMetadata>().unwrap().0, 2);
+
+        // Instances of the same component don't collide
+        component.add_named_metadata("first", OldMetadata(1));
+        component.add_named_metadata("second", OldMetadata(2));
+        component.add_named_metadata("first", NewMetadata(3));
+        assert!(component
+            .migrate_named_metadata("second", |old: OldMetadata| NewMetadata(old.0.into())));
+        assert_eq!(
+            component.named_metadata::<OldMetadata>("first").unwrap().0,
+            1
+        );
+        assert_eq!(
+            component.named_metadata::<NewMetadata>("second").unwrap().0,
+            2
+        );
+        assert_eq!(component.remove_all_named_metadata("first"), 2);
+        assert_eq!(component.named_metadata_map().entries().count(), 1);
+    }
+}
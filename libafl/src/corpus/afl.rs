@@ -0,0 +1,267 @@
+//! Import and export of corpora in the `AFL++` queue format, see [`crate::corpus::Corpus::export_afl`]
+//! and [`crate::corpus::Corpus::import_afl`].
+//!
+//! `AFL++` encodes the provenance of each queue entry in its filename, e.g.
+//! `id:000042,src:000007+000013,time:1337,execs:4711,op:splice,rep:4,+cov`.
+//! On import, the provenance is kept in the [`AflProvenanceMetadata`] of each [`Testcase`],
+//! so it survives a round trip through `LibAFL`.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+use std::{fs, path::Path};
+
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId, Testcase},
+    feedbacks::MapNoveltiesMetadata,
+    inputs::Input,
+    mutators::LogMutationMetadata,
+    Error, HasMetadata,
+};
+
+/// The provenance of a testcase, as encoded in an `AFL++` queue filename
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AflProvenanceMetadata {
+    /// The `id` of the entry in the `AFL++` queue it was imported from
+    pub id: Option<usize>,
+    /// The `id`s of the parent entries (`src`), two for splicing
+    pub src: Vec<usize>,
+    /// The time of discovery in milliseconds since the start of the campaign (`time`)
+    pub time: Option<u64>,
+    /// The number of executions at discovery (`execs`)
+    pub execs: Option<u64>,
+    /// The mutation stage that found the entry (`op`), e.g. `havoc` or `flip1`
+    pub op: Option<String>,
+    /// The signal that crashed the target, for crashes (`sig`)
+    pub sig: Option<i32>,
+    /// The original name of an initial seed (`orig`)
+    pub orig: Option<String>,
+    /// If the entry found new coverage (`+cov`)
+    pub cov: bool,
+    /// The remaining fields, e.g. `rep:4` or `pos:12`, in order
+    pub extra: Vec<String>,
+}
+
+libafl_bolts::impl_serdeany!(AflProvenanceMetadata);
+
+impl AflProvenanceMetadata {
+    /// Parses an `AFL++` queue filename, returning `None` if it does not start with `id:`
+    #[must_use]
+    pub fn parse(filename: &str) -> Option<Self> {
+        if !filename.starts_with("id:") {
+            return None;
+        }
+        let mut meta = Self::default();
+        let mut rest = filename;
+        while !rest.is_empty() {
+            // `orig` is always last and may contain commas
+            if let Some(orig) = rest.strip_prefix("orig:") {
+                meta.orig = Some(orig.to_string());
+                break;
+            }
+            let (field, next) = rest.split_once(',').unwrap_or((rest, ""));
+            rest = next;
+            match field.split_once(':') {
+                Some(("id", id)) => meta.id = Some(id.parse().ok()?),
+                Some(("src", src)) => {
+                    meta.src = src
+                        .split('+')
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()
+                        .ok()?;
+                }
+                Some(("time", time)) => meta.time = time.parse().ok(),
+                Some(("execs", execs)) => meta.execs = execs.parse().ok(),
+                Some(("op", op)) => meta.op = Some(op.to_string()),
+                Some(("sig", sig)) => meta.sig = sig.parse().ok(),
+                _ if field == "+cov" => meta.cov = true,
+                _ => meta.extra.push(field.to_string()),
+            }
+        }
+        Some(meta)
+    }
+
+    /// Formats the `AFL++` queue filename for an entry with the given `id` and `src`,
+    /// keeping the other fields of this metadata
+    #[must_use]
+    pub fn filename(&self, id: usize, src: &[usize]) -> String {
+        let mut name = format!("id:{id:06}");
+        if let Some(sig) = self.sig {
+            let _ = write!(name, ",sig:{sig:02}");
+        }
+        if !src.is_empty() {
+            let src = src
+                .iter()
+                .map(|src| format!("{src:06}"))
+                .collect::<Vec<_>>()
+                .join("+");
+            let _ = write!(name, ",src:{src}");
+        }
+        if let Some(time) = self.time {
+            let _ = write!(name, ",time:{time}");
+        }
+        if let Some(execs) = self.execs {
+            let _ = write!(name, ",execs:{execs}");
+        }
+        if let Some(op) = &self.op {
+            let _ = write!(name, ",op:{op}");
+        }
+        for extra in &self.extra {
+            let _ = write!(name, ",{extra}");
+        }
+        if self.cov {
+            name.push_str(",+cov");
+        }
+        if let Some(orig) = &self.orig {
+            let _ = write!(name, ",orig:{orig}");
+        }
+        name
+    }
+}
+
+/// Writes the enabled testcases of the corpus to `dir` with `AFL++` queue filenames.
+/// See [`Corpus::export_afl`].
+pub fn export_afl<C, P>(corpus: &C, dir: P) -> Result<usize, Error>
+where
+    C: Corpus,
+    P: AsRef<Path>,
+{
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+
+    // `AFL++` ids are dense, so number the exported entries first
+    let afl_ids: HashMap<CorpusId, usize> = corpus
+        .ids()
+        .enumerate()
+        .map(|(afl_id, id)| (id, afl_id))
+        .collect();
+
+    for id in corpus.ids() {
+        let mut testcase = corpus.get(id)?.borrow_mut();
+        let mut meta = testcase
+            .metadata_map()
+            .get::<AflProvenanceMetadata>()
+            .cloned()
+            .unwrap_or_default();
+
+        let src = match testcase.parent_id() {
+            Some(parent_id) => afl_ids.get(&parent_id).into_iter().copied().collect(),
+            None => Vec::new(),
+        };
+        if meta.execs.is_none() && *testcase.executions() > 0 {
+            meta.execs = Some(*testcase.executions());
+        }
+        if meta.op.is_none() {
+            if let Some(log) = testcase.metadata_map().get::<LogMutationMetadata>() {
+                meta.op = Some(log.list.join("_"));
+            }
+        }
+        if testcase.has_metadata::<MapNoveltiesMetadata>() {
+            meta.cov = true;
+        }
+        if src.is_empty() && meta.orig.is_none() && meta.op.is_none() {
+            meta.orig.clone_from(testcase.filename());
+        }
+
+        let filename = meta.filename(afl_ids[&id], &src);
+        testcase.load_input(corpus)?.to_file(dir.join(filename))?;
+    }
+    Ok(afl_ids.len())
+}
+
+/// Adds the files in `dir` to the corpus, parsing `AFL++` queue filenames.
+/// See [`Corpus::import_afl`].
+pub fn import_afl<C, P>(corpus: &mut C, dir: P) -> Result<Vec<CorpusId>, Error>
+where
+    C: Corpus,
+    P: AsRef<Path>,
+{
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let filename = entry.file_name().to_string_lossy().to_string();
+        if filename.starts_with('.') || !entry.file_type()?.is_file() {
+            continue;
+        }
+        let meta = AflProvenanceMetadata::parse(&filename);
+        entries.push((meta, filename, entry.path()));
+    }
+    // Parents first, then the files not named by `AFL++`
+    entries.sort_by(|(meta1, name1, _), (meta2, name2, _)| {
+        let key = |meta: &Option<AflProvenanceMetadata>| {
+            meta.as_ref().and_then(|meta| meta.id).unwrap_or(usize::MAX)
+        };
+        key(meta1).cmp(&key(meta2)).then_with(|| name1.cmp(name2))
+    });
+
+    let mut ids = Vec::with_capacity(entries.len());
+    let mut corpus_ids: HashMap<usize, CorpusId> = HashMap::new();
+    for (meta, filename, path) in entries {
+        let input = C::Input::from_file(&path)?;
+        let mut testcase = Testcase::new(input);
+        if let Some(meta) = meta {
+            if let Some(parent_id) = meta.src.first().and_then(|src| corpus_ids.get(src)) {
+                testcase.set_parent_id(*parent_id);
+            }
+            if let Some(execs) = meta.execs {
+                *testcase.executions_mut() = execs;
+            }
+            let afl_id = meta.id;
+            testcase.add_metadata(meta);
+            let id = corpus.add(testcase)?;
+            if let Some(afl_id) = afl_id {
+                corpus_ids.insert(afl_id, id);
+            }
+            ids.push(id);
+        } else {
+            *testcase.filename_mut() = Some(filename);
+            ids.push(corpus.add(testcase)?);
+        }
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use super::AflProvenanceMetadata;
+
+    #[test]
+    fn test_afl_filename_roundtrip() {
+        let name = "id:000042,src:000007+000013,time:1337,execs:4711,op:splice,rep:4,+cov";
+        let meta = AflProvenanceMetadata::parse(name).unwrap();
+        assert_eq!(meta.id, Some(42));
+        assert_eq!(meta.src, vec![7, 13]);
+        assert_eq!(meta.op.as_deref(), Some("splice"));
+        assert_eq!(meta.extra, vec!["rep:4".to_string()]);
+        assert!(meta.cov);
+        assert_eq!(meta.filename(42, &meta.src), name);
+
+        let seed = AflProvenanceMetadata::parse("id:000000,time:0,execs:0,orig:a,b.txt").unwrap();
+        assert_eq!(seed.orig.as_deref(), Some("a,b.txt"));
+        assert_eq!(
+            seed.filename(0, &[]),
+            "id:000000,time:0,execs:0,orig:a,b.txt"
+        );
+
+        let crash = "id:000001,sig:11,src:000003,time:20,execs:300,op:havoc,rep:2";
+        assert_eq!(
+            AflProvenanceMetadata::parse(crash)
+                .unwrap()
+                .filename(1, &[3]),
+            crash
+        );
+
+        assert!(AflProvenanceMetadata::parse("seed.txt").is_none());
+    }
+}
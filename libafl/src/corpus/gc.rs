@@ -0,0 +1,271 @@
+//! Corpus garbage collection: pruning testcases whose coverage is subsumed by smaller or faster ones.
+//!
+//! Unlike the [`super::CorpusMinimizer`]s, the [`CorpusGc`] does not re-execute the corpus: it relies on
+//! the [`MapIndexesMetadata`] stored in the testcases, so the map feedback needs `track_indices`.
+//! Testcases without this metadata are always kept.
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use hashbrown::{HashMap, HashSet};
+use libafl_bolts::HasLen;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId, MinimizationAction},
+    feedbacks::MapIndexesMetadata,
+    schedulers::RemovableScheduler,
+    state::{HasCorpus, HasExecutions},
+    Error, HasMetadata,
+};
+
+/// When a testcase covering a superset of the map indexes of another one may replace it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SubsumptionPolicy {
+    /// The other testcase is at most as large
+    Smaller,
+    /// The other testcase is at most as slow
+    Faster,
+    /// The other testcase is at most as large and at most as slow
+    #[default]
+    SmallerAndFaster,
+}
+
+impl SubsumptionPolicy {
+    /// If `by` may replace `entry`, given that it covers a superset of its indexes
+    fn dominates(self, by: &GcEntry, entry: &GcEntry) -> bool {
+        let faster = || match (by.exec_time, entry.exec_time) {
+            (Some(by), Some(entry)) => by <= entry,
+            _ => false,
+        };
+        match self {
+            SubsumptionPolicy::Smaller => by.len <= entry.len,
+            SubsumptionPolicy::Faster => faster(),
+            SubsumptionPolicy::SmallerAndFaster => by.len <= entry.len && faster(),
+        }
+    }
+}
+
+/// A testcase considered by the [`CorpusGc`]
+#[derive(Debug, Clone)]
+struct GcEntry {
+    len: usize,
+    exec_time: Option<Duration>,
+    indexes: Vec<usize>,
+    /// If the testcase may be collected, e.g. it is old enough
+    collectable: bool,
+}
+
+/// Returns the positions of the entries subsumed by others according to the `policy`.
+///
+/// The entries are visited from the best to the worst, and an entry is only subsumed by entries that
+/// are kept, so the union of the covered indexes never shrinks.
+fn subsumed_entries(entries: &[GcEntry], policy: SubsumptionPolicy) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by_key(|&i| {
+        let entry = &entries[i];
+        let exec_time = entry.exec_time.map_or(u128::MAX, |time| time.as_nanos());
+        match policy {
+            SubsumptionPolicy::Faster => (exec_time, entry.len as u128, i),
+            SubsumptionPolicy::Smaller | SubsumptionPolicy::SmallerAndFaster => {
+                (entry.len as u128, exec_time, i)
+            }
+        }
+    });
+
+    // The kept entries covering each index
+    let mut covering: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut kept_indexes: HashMap<usize, HashSet<usize>> = HashMap::new();
+    let mut subsumed = Vec::new();
+    for i in order {
+        let entry = &entries[i];
+        let is_subsumed = entry.collectable
+            && entry.indexes.first().is_some_and(|first| {
+                covering.get(first).is_some_and(|candidates| {
+                    candidates.iter().any(|by| {
+                        policy.dominates(&entries[*by], entry)
+                            && entry
+                                .indexes
+                                .iter()
+                                .all(|idx| kept_indexes[by].contains(idx))
+                    })
+                })
+            });
+        if is_subsumed {
+            subsumed.push(i);
+        } else {
+            for idx in &entry.indexes {
+                covering.entry(*idx).or_default().push(i);
+            }
+            kept_indexes.insert(i, entry.indexes.iter().copied().collect());
+        }
+    }
+    subsumed
+}
+
+/// The corpus garbage collector disables or removes testcases whose covered map indexes are all
+/// covered by a single smaller and/or faster testcase, see [`SubsumptionPolicy`].
+///
+/// Run it periodically with the [`crate::stages::CorpusGcStage`] to keep long campaigns from slowing
+/// down scheduling and state serialization.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CorpusGc {
+    policy: SubsumptionPolicy,
+    action: MinimizationAction,
+    min_age: u64,
+    min_corpus_size: usize,
+}
+
+impl CorpusGc {
+    /// Creates a new [`CorpusGc`], disabling the testcases subsumed by smaller and faster ones
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets when a testcase may replace another one
+    #[must_use]
+    pub fn with_policy(mut self, policy: SubsumptionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Sets what to do with the subsumed testcases
+    #[must_use]
+    pub fn with_action(mut self, action: MinimizationAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// Only collects testcases added at least `min_age` executions ago, so fresh ones get fuzzed first
+    #[must_use]
+    pub fn with_min_age(mut self, min_age: u64) -> Self {
+        self.min_age = min_age;
+        self
+    }
+
+    /// Only collects if the corpus has more than `min_corpus_size` testcases
+    #[must_use]
+    pub fn with_min_corpus_size(mut self, min_corpus_size: usize) -> Self {
+        self.min_corpus_size = min_corpus_size;
+        self
+    }
+
+    /// The subsumption policy
+    #[must_use]
+    pub fn policy(&self) -> SubsumptionPolicy {
+        self.policy
+    }
+
+    /// What happens to the subsumed testcases
+    #[must_use]
+    pub fn action(&self) -> MinimizationAction {
+        self.action
+    }
+
+    /// Finds the testcases of the corpus subsumed by others, without touching the corpus
+    pub fn subsumed<S>(&self, state: &S) -> Result<Vec<CorpusId>, Error>
+    where
+        S: HasCorpus + HasExecutions,
+        S::Input: HasLen,
+    {
+        if state.corpus().count() <= self.min_corpus_size {
+            return Ok(Vec::new());
+        }
+
+        let executions = *state.executions();
+        let current = *state.corpus().current();
+        let mut ids = Vec::with_capacity(state.corpus().count());
+        let mut entries = Vec::with_capacity(state.corpus().count());
+        let mut cur_id = state.corpus().first();
+        while let Some(id) = cur_id {
+            let mut testcase = state.corpus().get(id)?.borrow_mut();
+            if let Some(meta) = testcase.metadata_map().get::<MapIndexesMetadata>() {
+                let indexes = meta.list.clone();
+                let collectable = Some(id) != current
+                    && executions.saturating_sub(*testcase.executions()) >= self.min_age;
+                entries.push(GcEntry {
+                    len: testcase.load_len(state.corpus())?,
+                    exec_time: *testcase.exec_time(),
+                    indexes,
+                    collectable,
+                });
+                ids.push(id);
+            }
+            cur_id = state.corpus().next(id);
+        }
+
+        Ok(subsumed_entries(&entries, self.policy)
+            .into_iter()
+            .map(|i| ids[i])
+            .collect())
+    }
+
+    /// Disables or removes the subsumed testcases, returning them
+    pub fn collect<CS, S>(&self, state: &mut S, scheduler: &mut CS) -> Result<Vec<CorpusId>, Error>
+    where
+        CS: RemovableScheduler<State = S>,
+        S: HasCorpus + HasExecutions,
+        S::Input: HasLen,
+    {
+        let mut subsumed = self.subsumed(state)?;
+        // reverse order; if indexes are stored in a vec, we need to remove from back to front
+        subsumed.sort_unstable_by(|id1, id2| id2.cmp(id1));
+        for id in &subsumed {
+            let removed = Some(state.corpus_mut().remove(*id)?);
+            // scheduler needs to know we've removed the input, or it will continue to try
+            // to use now-missing inputs
+            scheduler.on_remove(state, *id, &removed)?;
+            if self.action == MinimizationAction::Disable {
+                if let Some(testcase) = removed {
+                    state.corpus_mut().add_disabled(testcase)?;
+                }
+            }
+        }
+        Ok(subsumed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use core::time::Duration;
+
+    use super::{subsumed_entries, GcEntry, SubsumptionPolicy};
+
+    fn entry(len: usize, millis: u64, indexes: Vec<usize>) -> GcEntry {
+        GcEntry {
+            len,
+            exec_time: Some(Duration::from_millis(millis)),
+            indexes,
+            collectable: true,
+        }
+    }
+
+    #[test]
+    fn test_subsumed_entries() {
+        let entries = vec![
+            entry(10, 5, vec![1, 2, 3]),
+            // subsumed by the first one
+            entry(20, 5, vec![1, 2]),
+            // smaller, but slower than the first one
+            entry(5, 50, vec![2, 3]),
+            // identical to the first one, but added later
+            entry(10, 5, vec![1, 2, 3]),
+            entry(30, 1, vec![4]),
+        ];
+        let mut subsumed = subsumed_entries(&entries, SubsumptionPolicy::SmallerAndFaster);
+        subsumed.sort_unstable();
+        assert_eq!(subsumed, vec![1, 3]);
+
+        let mut subsumed = subsumed_entries(&entries, SubsumptionPolicy::Faster);
+        subsumed.sort_unstable();
+        assert_eq!(subsumed, vec![1, 2, 3]);
+
+        let mut entries = entries;
+        entries[1].collectable = false;
+        let mut subsumed = subsumed_entries(&entries, SubsumptionPolicy::SmallerAndFaster);
+        subsumed.sort_unstable();
+        assert_eq!(subsumed, vec![3]);
+    }
+}
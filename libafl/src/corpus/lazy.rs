@@ -0,0 +1,460 @@
+//! The [`LazyOnDiskCorpus`] stores [`Testcase`]s to disk, and rehydrates them on first access
+//! after a restart, instead of deserializing the whole corpus with the state.
+
+use alloc::{string::String, vec::Vec};
+use core::cell::RefCell;
+use std::path::Path;
+
+use hashbrown::HashMap;
+use libafl_bolts::serdeany::SerdeAnyMap;
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    corpus::{
+        inmemory_ondisk::InMemoryOnDiskCorpus, ondisk::OnDiskMetadataFormat, Corpus, CorpusId,
+        HasTestcase, Testcase,
+    },
+    inputs::{Input, UsesInput},
+    Error, HasMetadata,
+};
+
+/// A corpus that stores all [`Testcase`]s to disk, and restores them lazily.
+///
+/// When the state gets serialized, e.g. by a restarting event manager, the metadata of each
+/// testcase is serialized as an opaque blob, and its input is left on disk. After the restart,
+/// only the structure of the corpus is deserialized; the metadata and the input of a testcase
+/// are rehydrated the first time it is accessed through [`Corpus::get`] or
+/// [`Corpus::get_from_all`]. The metadata of the state, e.g. of the scheduler and the
+/// feedbacks, is still deserialized eagerly.
+///
+/// Otherwise, this behaves like an [`InMemoryOnDiskCorpus`].
+#[derive(Default, Clone, Debug)]
+pub struct LazyOnDiskCorpus<I>
+where
+    I: Input,
+{
+    inner: InMemoryOnDiskCorpus<I>,
+    /// The serialized metadata of the testcases not accessed since the last restart
+    pending: RefCell<HashMap<CorpusId, Vec<u8>>>,
+}
+
+/// The serialized form of a [`LazyOnDiskCorpus`]
+#[derive(Serialize)]
+#[serde(bound = "I: Serialize")]
+struct LazyCorpusRef<'a, I>
+where
+    I: Input,
+{
+    inner: &'a InMemoryOnDiskCorpus<I>,
+    pending: &'a HashMap<CorpusId, Vec<u8>>,
+}
+
+/// The deserialized form of a [`LazyOnDiskCorpus`]
+#[derive(Deserialize)]
+#[serde(bound = "I: serde::de::DeserializeOwned")]
+struct LazyCorpusOwned<I>
+where
+    I: Input,
+{
+    inner: InMemoryOnDiskCorpus<I>,
+    pending: HashMap<CorpusId, Vec<u8>>,
+}
+
+/// The input and, unless it was pending already, the metadata moved out of a testcase during
+/// serialization
+type TakenTestcase<I> = (CorpusId, Option<I>, Option<SerdeAnyMap>);
+
+impl<I> Serialize for LazyOnDiskCorpus<I>
+where
+    I: Input,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut pending = self.pending.borrow_mut();
+        let mut taken = Vec::new();
+        let ret = self
+            .take_for_serialization(&mut pending, &mut taken)
+            .and_then(|()| {
+                LazyCorpusRef {
+                    inner: &self.inner,
+                    pending: &pending,
+                }
+                .serialize(serializer)
+            });
+
+        // Put everything back, also on errors; the corpus stays usable without decoding it again
+        for (id, input, metadata) in taken {
+            let Ok(testcase) = self.inner.get_from_all(id) else {
+                continue;
+            };
+            let mut testcase = testcase.borrow_mut();
+            *testcase.input_mut() = input;
+            if let Some(metadata) = metadata {
+                pending.remove(&id);
+                *testcase.metadata_map_mut() = metadata;
+            }
+        }
+        ret
+    }
+}
+
+impl<'de, I> Deserialize<'de> for LazyOnDiskCorpus<I>
+where
+    I: Input,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let LazyCorpusOwned { inner, pending } = LazyCorpusOwned::deserialize(deserializer)?;
+        for id in pending.keys() {
+            if inner.get_from_all(*id).is_err() {
+                return Err(de::Error::custom(format!(
+                    "Serialized metadata of the unknown testcase {id}"
+                )));
+            }
+        }
+        Ok(Self {
+            inner,
+            pending: RefCell::new(pending),
+        })
+    }
+}
+
+impl<I> UsesInput for LazyOnDiskCorpus<I>
+where
+    I: Input,
+{
+    type Input = I;
+}
+
+impl<I> LazyOnDiskCorpus<I>
+where
+    I: Input,
+{
+    /// Creates the [`LazyOnDiskCorpus`].
+    ///
+    /// By default, it stores metadata for each [`Testcase`] as prettified json,
+    /// see [`InMemoryOnDiskCorpus::new`].
+    ///
+    /// Will error, if [`std::fs::create_dir_all()`] failed for `dir_path`.
+    pub fn new<P>(dir_path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::_new(InMemoryOnDiskCorpus::new(dir_path)?))
+    }
+
+    /// Creates an [`LazyOnDiskCorpus`] that does not store [`Testcase`] metadata to disk.
+    pub fn no_meta<P>(dir_path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::_new(InMemoryOnDiskCorpus::no_meta(dir_path)?))
+    }
+
+    /// Creates the [`LazyOnDiskCorpus`] specifying the metadata format and the prefix to prepend
+    /// to each testcase.
+    ///
+    /// Will error, if [`std::fs::create_dir_all()`] failed for `dir_path`.
+    pub fn with_meta_format_and_prefix<P>(
+        dir_path: P,
+        meta_format: Option<OnDiskMetadataFormat>,
+        prefix: Option<String>,
+        locking: bool,
+    ) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::_new(
+            InMemoryOnDiskCorpus::with_meta_format_and_prefix(
+                dir_path,
+                meta_format,
+                prefix,
+                locking,
+            )?,
+        ))
+    }
+
+    /// Internal constructor `fn`
+    fn _new(inner: InMemoryOnDiskCorpus<I>) -> Self {
+        Self {
+            inner,
+            pending: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Fetch the inner corpus
+    pub fn inner(&self) -> &InMemoryOnDiskCorpus<I> {
+        &self.inner
+    }
+
+    /// The number of testcases not rehydrated since the last restart
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending.borrow().len()
+    }
+
+    /// Rehydrates the testcase, if it was not accessed since the last restart
+    fn rehydrate(&self, id: CorpusId, testcase: &RefCell<Testcase<I>>) -> Result<(), Error> {
+        let Some(metadata) = self.pending.borrow_mut().remove(&id) else {
+            return Ok(());
+        };
+        let mut testcase = testcase.borrow_mut();
+        *testcase.metadata_map_mut() = postcard::from_bytes::<SerdeAnyMap>(&metadata)?;
+        self.inner.load_input_into(&mut testcase)
+    }
+
+    /// Moves the inputs out of the testcases, as they are on disk already, and the metadata of
+    /// the rehydrated testcases, which gets serialized as blobs into `pending`.
+    /// Everything moved is recorded in `taken`, to be put back after the serialization.
+    fn take_for_serialization<E>(
+        &self,
+        pending: &mut HashMap<CorpusId, Vec<u8>>,
+        taken: &mut Vec<TakenTestcase<I>>,
+    ) -> Result<(), E>
+    where
+        E: ser::Error,
+    {
+        for nth in 0..self.inner.count_all() {
+            let id = self.inner.nth_from_all(nth);
+            let mut testcase = self
+                .inner
+                .get_from_all(id)
+                .map_err(E::custom)?
+                .try_borrow_mut()
+                .map_err(|_| E::custom(format!("Testcase {id} is borrowed")))?;
+            let metadata = if pending.contains_key(&id) {
+                None
+            } else {
+                let blob = postcard::to_allocvec(testcase.metadata_map()).map_err(E::custom)?;
+                pending.insert(id, blob);
+                Some(core::mem::take(testcase.metadata_map_mut()))
+            };
+            taken.push((id, testcase.input_mut().take(), metadata));
+        }
+        Ok(())
+    }
+}
+
+impl<I> Corpus for LazyOnDiskCorpus<I>
+where
+    I: Input,
+{
+    /// Returns the number of all enabled entries
+    #[inline]
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// Returns the number of all disabled entries
+    fn count_disabled(&self) -> usize {
+        self.inner.count_disabled()
+    }
+
+    /// Returns the number of elements including disabled entries
+    #[inline]
+    fn count_all(&self) -> usize {
+        self.inner.count_all()
+    }
+
+    /// Add an enabled testcase to the corpus and return its index
+    #[inline]
+    fn add(&mut self, testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        self.inner.add(testcase)
+    }
+
+    /// Add a disabled testcase to the corpus and return its index
+    #[inline]
+    fn add_disabled(&mut self, testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        self.inner.add_disabled(testcase)
+    }
+
+    /// Replaces the testcase at the given idx
+    #[inline]
+    fn replace(&mut self, id: CorpusId, testcase: Testcase<I>) -> Result<Testcase<I>, Error> {
+        self.rehydrate(id, self.inner.get_from_all(id)?)?;
+        self.inner.replace(id, testcase)
+    }
+
+    /// Removes an entry from the corpus, returning it if it was present; considers both enabled and disabled testcases.
+    fn remove(&mut self, id: CorpusId) -> Result<Testcase<Self::Input>, Error> {
+        self.rehydrate(id, self.inner.get_from_all(id)?)?;
+        self.inner.remove(id)
+    }
+
+    /// Get by id; considers only enabled testcases
+    #[inline]
+    fn get(&self, id: CorpusId) -> Result<&RefCell<Testcase<I>>, Error> {
+        let testcase = self.inner.get(id)?;
+        self.rehydrate(id, testcase)?;
+        Ok(testcase)
+    }
+
+    /// Get by id; considers both enabled and disabled testcases
+    #[inline]
+    fn get_from_all(&self, id: CorpusId) -> Result<&RefCell<Testcase<I>>, Error> {
+        let testcase = self.inner.get_from_all(id)?;
+        self.rehydrate(id, testcase)?;
+        Ok(testcase)
+    }
+
+    /// Current testcase scheduled
+    #[inline]
+    fn current(&self) -> &Option<CorpusId> {
+        self.inner.current()
+    }
+
+    /// Current testcase scheduled (mutable)
+    #[inline]
+    fn current_mut(&mut self) -> &mut Option<CorpusId> {
+        self.inner.current_mut()
+    }
+
+    #[inline]
+    fn next(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.next(id)
+    }
+
+    /// Peek the next free corpus id
+    #[inline]
+    fn peek_free_id(&self) -> CorpusId {
+        self.inner.peek_free_id()
+    }
+
+    #[inline]
+    fn prev(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.prev(id)
+    }
+
+    #[inline]
+    fn first(&self) -> Option<CorpusId> {
+        self.inner.first()
+    }
+
+    #[inline]
+    fn last(&self) -> Option<CorpusId> {
+        self.inner.last()
+    }
+
+    /// Get the nth corpus id; considers only enabled testcases
+    #[inline]
+    fn nth(&self, nth: usize) -> CorpusId {
+        self.inner.nth(nth)
+    }
+    /// Get the nth corpus id; considers both enabled and disabled testcases
+    #[inline]
+    fn nth_from_all(&self, nth: usize) -> CorpusId {
+        self.inner.nth_from_all(nth)
+    }
+
+    #[inline]
+    fn load_input_into(&self, testcase: &mut Testcase<Self::Input>) -> Result<(), Error> {
+        self.inner.load_input_into(testcase)
+    }
+
+    #[inline]
+    fn store_input_from(&self, testcase: &Testcase<Self::Input>) -> Result<(), Error> {
+        self.inner.store_input_from(testcase)
+    }
+}
+
+impl<I> HasTestcase for LazyOnDiskCorpus<I>
+where
+    I: Input,
+{
+    fn testcase(&self, id: CorpusId) -> Result<core::cell::Ref<Testcase<Self::Input>>, Error> {
+        Ok(self.get(id)?.borrow())
+    }
+
+    fn testcase_mut(
+        &self,
+        id: CorpusId,
+    ) -> Result<core::cell::RefMut<Testcase<Self::Input>>, Error> {
+        Ok(self.get(id)?.borrow_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::LazyOnDiskCorpus;
+    use crate::{
+        corpus::{Corpus, SchedulerTestcaseMetadata, Testcase},
+        inputs::BytesInput,
+        HasMetadata,
+    };
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_lazy_corpus_restore() {
+        let dir = "target/.test/lazy_corpus";
+        drop(fs::remove_dir_all(dir));
+
+        let mut corpus = LazyOnDiskCorpus::<BytesInput>::no_meta(dir).unwrap();
+        let mut testcase = Testcase::new(BytesInput::new(b"lazy".to_vec()));
+        testcase.add_metadata(SchedulerTestcaseMetadata::new(3));
+        let id = corpus.add(testcase).unwrap();
+        let other = corpus
+            .add(Testcase::new(BytesInput::new(b"other".to_vec())))
+            .unwrap();
+        // The inputs are in memory while the testcases are in use
+        for id in [id, other] {
+            corpus
+                .load_input_into(&mut corpus.get(id).unwrap().borrow_mut())
+                .unwrap();
+        }
+
+        // A failed serialization leaves the testcases untouched
+        {
+            let _borrowed = corpus.get(other).unwrap().borrow_mut();
+            assert!(postcard::to_allocvec(&corpus).is_err());
+        }
+        assert_eq!(corpus.pending(), 0);
+        {
+            let testcase = corpus.get(id).unwrap().borrow();
+            assert!(testcase.has_metadata::<SchedulerTestcaseMetadata>());
+            assert!(testcase.input().is_some());
+        }
+
+        let serialized = postcard::to_allocvec(&corpus).unwrap();
+        // The corpus stays usable after serialization
+        assert_eq!(corpus.pending(), 0);
+        for id in [id, other] {
+            let testcase = corpus.get(id).unwrap().borrow();
+            assert!(testcase.input().is_some());
+            assert_eq!(
+                testcase.has_metadata::<SchedulerTestcaseMetadata>(),
+                id != other
+            );
+        }
+
+        let restored: LazyOnDiskCorpus<BytesInput> = postcard::from_bytes(&serialized).unwrap();
+        assert_eq!(restored.pending(), 2);
+        {
+            let testcase = restored.get(id).unwrap().borrow();
+            assert_eq!(
+                testcase
+                    .metadata::<SchedulerTestcaseMetadata>()
+                    .unwrap()
+                    .depth(),
+                3
+            );
+            assert_eq!(
+                testcase.input().as_ref(),
+                Some(&BytesInput::new(b"lazy".to_vec()))
+            );
+        }
+        assert_eq!(restored.pending(), 1);
+        assert_eq!(
+            restored.get(other).unwrap().borrow().input().as_ref(),
+            Some(&BytesInput::new(b"other".to_vec()))
+        );
+        assert_eq!(restored.pending(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
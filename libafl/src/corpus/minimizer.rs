@@ -1,20 +1,30 @@
 //! Whole corpus minimizers, for reducing the number of samples/the total size/the average runtime
 //! of your corpus.
+//!
+//! The [`GreedyCorpusMinimizer`] approximates the minimal corpus with a greedy weighted set cover and
+//! is always available; the `MapCorpusMinimizer` computes an optimal solution with `z3` and needs
+//! the `cmin` feature.
 
-use alloc::{borrow::Cow, string::ToString, vec::Vec};
-use core::{hash::Hash, marker::PhantomData};
+#[cfg(all(feature = "cmin", unix))]
+use alloc::string::ToString;
+use alloc::{borrow::Cow, collections::BinaryHeap, vec::Vec};
+use core::{cmp::Ordering, hash::Hash, marker::PhantomData};
 
-use hashbrown::{HashMap, HashSet};
+use hashbrown::HashMap;
+#[cfg(all(feature = "cmin", unix))]
+use hashbrown::HashSet;
 use libafl_bolts::{
     current_time,
     tuples::{Handle, Handled},
     AsIter, Named,
 };
 use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+#[cfg(all(feature = "cmin", unix))]
 use z3::{ast::Bool, Config, Context, Optimize};
 
 use crate::{
-    corpus::Corpus,
+    corpus::{Corpus, CorpusId},
     events::{Event, EventFirer, LogSeverity},
     executors::{Executor, HasObservers},
     monitors::{AggregatorOps, UserStats, UserStatsValue},
@@ -46,6 +56,7 @@ where
         Z: HasScheduler<Scheduler = CS, State = E::State>;
 }
 
+#[cfg(all(feature = "cmin", unix))]
 /// Minimizes a corpus according to coverage maps, weighting by the specified `TestcaseScore`.
 ///
 /// Algorithm based on WMOPT: <https://hexhive.epfl.ch/publications/files/21ISSTA2.pdf>
@@ -55,10 +66,12 @@ pub struct MapCorpusMinimizer<C, E, O, T, TS> {
     phantom: PhantomData<(E, O, T, TS)>,
 }
 
+#[cfg(all(feature = "cmin", unix))]
 /// Standard corpus minimizer, which weights inputs by length and time.
 pub type StdCorpusMinimizer<C, E, O, T> =
     MapCorpusMinimizer<C, E, O, T, LenTimeMulTestcaseScore<<E as UsesState>::State>>;
 
+#[cfg(all(feature = "cmin", unix))]
 impl<C, E, O, T, TS> MapCorpusMinimizer<C, E, O, T, TS>
 where
     E: UsesState,
@@ -76,6 +89,7 @@ where
     }
 }
 
+#[cfg(all(feature = "cmin", unix))]
 impl<C, E, O, T, TS> CorpusMinimizer<E> for MapCorpusMinimizer<C, E, O, T, TS>
 where
     E: UsesState,
@@ -239,3 +253,330 @@ where
         res
     }
 }
+
+/// What a [`GreedyCorpusMinimizer`] does with the testcases not needed for full coverage
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MinimizationAction {
+    /// Move them to the disabled testcases of the corpus, so they are kept but no longer scheduled
+    #[default]
+    Disable,
+    /// Remove them from the corpus
+    Remove,
+}
+
+/// A candidate of the greedy set cover: the (upper bound of the) number of newly covered elements
+/// and the weight of a set, ordered by the ratio of the two
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CoverCandidate {
+    gain: u64,
+    weight: u64,
+    idx: usize,
+}
+
+impl Ord for CoverCandidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // gain / weight, compared without division; ties are broken by the smaller index
+        (u128::from(self.gain) * u128::from(other.weight))
+            .cmp(&(u128::from(other.gain) * u128::from(self.weight)))
+            .then_with(|| other.idx.cmp(&self.idx))
+    }
+}
+
+impl PartialOrd for CoverCandidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Computes a greedy weighted set cover: `sets` contains the weight and the (dense) elements of each
+/// set, all elements are `< universe`. Returns the indices of the selected sets, covering every
+/// element contained in any set.
+///
+/// The sets with the best ratio of newly covered elements to weight are picked first, which is
+/// within a factor of `ln(n)` of the optimal cover. Weights of `0` are treated as `1`.
+#[must_use]
+pub fn greedy_weighted_set_cover(sets: &[(u64, Vec<usize>)], universe: usize) -> Vec<usize> {
+    let mut covered = vec![false; universe];
+    let mut selected = Vec::new();
+
+    // Gains only shrink when other sets are selected, so stale heap entries are upper bounds
+    // and we only need to recompute the gain of the top candidate (lazy greedy).
+    let mut heap: BinaryHeap<CoverCandidate> = sets
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, elements))| !elements.is_empty())
+        .map(|(idx, (weight, elements))| CoverCandidate {
+            gain: elements.len() as u64,
+            weight: (*weight).max(1),
+            idx,
+        })
+        .collect();
+
+    while let Some(mut candidate) = heap.pop() {
+        let elements = &sets[candidate.idx].1;
+        candidate.gain = elements
+            .iter()
+            .filter(|element| !covered[**element])
+            .count() as u64;
+        if candidate.gain == 0 {
+            continue;
+        }
+        if heap.peek().is_some_and(|top| *top > candidate) {
+            heap.push(candidate);
+            continue;
+        }
+        for element in elements {
+            covered[*element] = true;
+        }
+        selected.push(candidate.idx);
+    }
+    selected
+}
+
+/// Minimizes a corpus according to coverage maps, weighting by the specified `TestcaseScore`.
+///
+/// Runs every input and keeps a subset covering every (map index, value) pair observed over the whole
+/// corpus, picked with a [`greedy_weighted_set_cover`]. The other testcases are disabled or removed,
+/// according to the [`MinimizationAction`]. This is fast enough to run periodically during a campaign,
+/// see [`crate::stages::CMinStage`], and needs no coordination between clients: each client minimizes
+/// its own corpus.
+#[derive(Debug)]
+pub struct GreedyCorpusMinimizer<C, E, O, T, TS> {
+    observer_handle: Handle<C>,
+    action: MinimizationAction,
+    phantom: PhantomData<(E, O, T, TS)>,
+}
+
+/// Standard greedy corpus minimizer, which weights inputs by length and time.
+pub type StdGreedyCorpusMinimizer<C, E, O, T> =
+    GreedyCorpusMinimizer<C, E, O, T, LenTimeMulTestcaseScore<<E as UsesState>::State>>;
+
+impl<C, E, O, T, TS> GreedyCorpusMinimizer<C, E, O, T, TS>
+where
+    C: Named,
+{
+    /// Constructs a new `GreedyCorpusMinimizer` from a provided observer, disabling the testcases
+    /// that are not needed.
+    pub fn new(obs: &C) -> Self {
+        Self {
+            observer_handle: obs.handle(),
+            action: MinimizationAction::default(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets what to do with the testcases that are not needed
+    #[must_use]
+    pub fn with_action(mut self, action: MinimizationAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    /// What is done with the testcases that are not needed
+    #[must_use]
+    pub fn action(&self) -> MinimizationAction {
+        self.action
+    }
+}
+
+impl<C, E, O, T, TS> CorpusMinimizer<E> for GreedyCorpusMinimizer<C, E, O, T, TS>
+where
+    E: UsesState,
+    for<'a> O: MapObserver<Entry = T> + AsIter<'a, Item = T>,
+    C: AsRef<O>,
+    E::State: HasMetadata + HasCorpus + HasExecutions,
+    T: Copy + Hash + Eq,
+    TS: TestcaseScore<E::State>,
+{
+    fn minimize<CS, EM, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        manager: &mut EM,
+        state: &mut E::State,
+    ) -> Result<(), Error>
+    where
+        E: Executor<EM, Z> + HasObservers,
+        CS: Scheduler<State = E::State> + RemovableScheduler,
+        EM: EventFirer<State = E::State>,
+        Z: HasScheduler<Scheduler = CS, State = E::State>,
+    {
+        let mut elements: HashMap<(usize, T), usize> = HashMap::new();
+        let mut sets = Vec::with_capacity(state.corpus().count());
+        let mut ids = Vec::with_capacity(state.corpus().count());
+
+        let total = state.corpus().count() as u64;
+        let mut curr = 0;
+        let mut cur_id = state.corpus().first();
+        while let Some(id) = cur_id {
+            let (weight, input) = {
+                let mut testcase = state.corpus().get(id)?.borrow_mut();
+                let weight = TS::compute(state, &mut *testcase)?
+                    .to_u64()
+                    .expect("Weight must be computable.");
+                state.corpus().load_input_into(&mut testcase)?;
+                let input = testcase
+                    .input()
+                    .as_ref()
+                    .expect("Input must be available.")
+                    .clone();
+                (weight, input)
+            };
+
+            // Execute the input; we cannot rely on the metadata already being present.
+            executor.observers_mut().pre_exec_all(state, &input)?;
+            let kind = executor.run_target(fuzzer, state, manager, &input)?;
+            executor
+                .observers_mut()
+                .post_exec_all(state, &input, &kind)?;
+
+            curr += 1;
+            let executions = *state.executions();
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: Cow::from("minimisation exec pass"),
+                    value: UserStats::new(UserStatsValue::Ratio(curr, total), AggregatorOps::None),
+                    phantom: PhantomData,
+                },
+            )?;
+            manager.fire(
+                state,
+                Event::UpdateExecStats {
+                    time: current_time(),
+                    phantom: PhantomData,
+                    executions,
+                },
+            )?;
+
+            let observers = executor.observers();
+            let obs = observers[&self.observer_handle].as_ref();
+            let initial = obs.initial();
+            let mut covered = Vec::new();
+            for (i, e) in obs.as_iter().map(|x| *x).enumerate() {
+                if e != initial {
+                    let next = elements.len();
+                    covered.push(*elements.entry((i, e)).or_insert(next));
+                }
+            }
+            sets.push((weight, covered));
+            ids.push(id);
+
+            cur_id = state.corpus().next(id);
+        }
+
+        let mut keep = vec![false; ids.len()];
+        for idx in greedy_weighted_set_cover(&sets, elements.len()) {
+            keep[idx] = true;
+        }
+        // The testcase currently fuzzed, e.g. by the stage running the minimization, must stay
+        let current = *state.corpus().current();
+        let mut removed: Vec<CorpusId> = ids
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(id, keep)| (!keep && Some(id) != current).then_some(id))
+            .collect();
+
+        manager.log(
+            state,
+            LogSeverity::Info,
+            format!(
+                "Corpus minimization keeps {} of {total} testcases",
+                total as usize - removed.len()
+            ),
+        )?;
+
+        // reverse order; if indexes are stored in a vec, we need to remove from back to front
+        removed.sort_unstable_by(|id1, id2| id2.cmp(id1));
+        for id in removed {
+            let removed = Some(state.corpus_mut().remove(id)?);
+            // scheduler needs to know we've removed the input, or it will continue to try
+            // to use now-missing inputs
+            fuzzer.scheduler_mut().on_remove(state, id, &removed)?;
+            if self.action == MinimizationAction::Disable {
+                if let Some(testcase) = removed {
+                    state.corpus_mut().add_disabled(testcase)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use libafl_bolts::{tuples::tuple_list, AsSliceMut};
+
+    use super::{greedy_weighted_set_cover, CorpusMinimizer, StdGreedyCorpusMinimizer};
+    use crate::{
+        corpus::{Corpus, CorpusId, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasMutatorBytes},
+        observers::StdMapObserver,
+        schedulers::QueueScheduler,
+        state::{test::test_std_state, HasCorpus},
+        StdFuzzer,
+    };
+
+    #[test]
+    fn test_greedy_weighted_set_cover() {
+        let sets = vec![
+            (1, vec![0, 1]),
+            (1, vec![2, 3]),
+            (1, vec![0, 1, 2, 3]),
+            (10, vec![0, 1, 2, 3, 4]),
+            (1, vec![4]),
+            (1, vec![]),
+        ];
+        let mut selected = greedy_weighted_set_cover(&sets, 5);
+        selected.sort_unstable();
+        assert_eq!(selected, vec![2, 4]);
+
+        // A cheap set is preferred over a large, expensive one
+        let sets = vec![(100, vec![0, 1, 2]), (1, vec![0, 1]), (1, vec![2])];
+        let mut selected = greedy_weighted_set_cover(&sets, 3);
+        selected.sort_unstable();
+        assert_eq!(selected, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_greedy_minimizer_keeps_current() {
+        // Either duplicate may be dropped, unless it is the current testcase
+        for current in [CorpusId(0), CorpusId(1)] {
+            let observer = StdMapObserver::owned("map", vec![0_u8; 16]);
+            let minimizer = StdGreedyCorpusMinimizer::new(&observer);
+            let mut executor = ClosureExecutor::new(
+                |observers: &mut (StdMapObserver<'static, u8, false>, ()), input: &BytesInput| {
+                    observers.0.as_slice_mut()[usize::from(input.bytes()[0] % 16)] = 1;
+                    ExitKind::Ok
+                },
+                tuple_list!(observer),
+            );
+            let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+                QueueScheduler::new(),
+                ConstFeedback::new(false),
+                ConstFeedback::new(false),
+            );
+            let mut mgr = NopEventManager::new();
+            let mut state = test_std_state::<BytesInput>();
+            for input in [b"a", b"a", b"b"] {
+                state
+                    .corpus_mut()
+                    .add(Testcase::new(BytesInput::new(input.to_vec())))
+                    .unwrap();
+            }
+            *state.corpus_mut().current_mut() = Some(current);
+
+            minimizer
+                .minimize(&mut fuzzer, &mut executor, &mut mgr, &mut state)
+                .unwrap();
+            assert!(state.corpus().get(current).is_ok());
+            assert!(state.corpus().get(CorpusId(2)).is_ok());
+            assert!(state.corpus().count() >= 2);
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! The [`MmapOnDiskCorpus`] stores [`Testcase`]s to disk and keeps their inputs as views of the
+//! memory-mapped files, for campaigns with very large inputs.
+
+use alloc::string::String;
+use core::cell::RefCell;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{
+        inmemory_ondisk::InMemoryOnDiskCorpus, ondisk::OnDiskMetadataFormat, Corpus, CorpusId,
+        HasTestcase, Testcase,
+    },
+    inputs::{Input, UsesInput},
+    Error,
+};
+
+/// A corpus that stores all [`Testcase`]s to disk and reloads their inputs from the written files.
+///
+/// Used with an input mapping its file in [`Input::from_file`], like the
+/// [`crate::inputs::MmapBytesInput`], the inputs of all testcases stay available as memory-mapped
+/// views: their pages are backed by the page cache, not the resident memory of the fuzzer, and
+/// only get copied when a clone is mutated.
+///
+/// The inputs are not serialized with the corpus, e.g. into the state kept over restarts; they are
+/// mapped again from their files on deserialization.
+///
+/// With other inputs, this behaves like an [`InMemoryOnDiskCorpus`].
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+#[serde(
+    bound = "I: serde::de::DeserializeOwned",
+    into = "InMemoryOnDiskCorpus<I>",
+    try_from = "InMemoryOnDiskCorpus<I>"
+)]
+pub struct MmapOnDiskCorpus<I>
+where
+    I: Input,
+{
+    inner: InMemoryOnDiskCorpus<I>,
+}
+
+impl<I> UsesInput for MmapOnDiskCorpus<I>
+where
+    I: Input,
+{
+    type Input = I;
+}
+
+impl<I> MmapOnDiskCorpus<I>
+where
+    I: Input,
+{
+    /// Creates the [`MmapOnDiskCorpus`].
+    ///
+    /// By default, it stores metadata for each [`Testcase`] as prettified json,
+    /// see [`InMemoryOnDiskCorpus::new`].
+    ///
+    /// Will error, if [`std::fs::create_dir_all()`] failed for `dir_path`.
+    pub fn new<P>(dir_path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            inner: InMemoryOnDiskCorpus::new(dir_path)?,
+        })
+    }
+
+    /// Creates an [`MmapOnDiskCorpus`] that does not store [`Testcase`] metadata to disk.
+    pub fn no_meta<P>(dir_path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            inner: InMemoryOnDiskCorpus::no_meta(dir_path)?,
+        })
+    }
+
+    /// Creates the [`MmapOnDiskCorpus`] specifying the metadata format and the prefix to prepend
+    /// to each testcase.
+    ///
+    /// Will error, if [`std::fs::create_dir_all()`] failed for `dir_path`.
+    pub fn with_meta_format_and_prefix<P>(
+        dir_path: P,
+        meta_format: Option<OnDiskMetadataFormat>,
+        prefix: Option<String>,
+        locking: bool,
+    ) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self {
+            inner: InMemoryOnDiskCorpus::with_meta_format_and_prefix(
+                dir_path,
+                meta_format,
+                prefix,
+                locking,
+            )?,
+        })
+    }
+
+    /// Fetch the inner corpus
+    pub fn inner(&self) -> &InMemoryOnDiskCorpus<I> {
+        &self.inner
+    }
+
+    /// Replaces the in-memory input of the testcase with the one loaded from its file
+    fn remap(&self, id: CorpusId) -> Result<(), Error> {
+        let mut testcase = self.inner.get_from_all(id)?.borrow_mut();
+        let Some(file_path) = testcase.file_path() else {
+            return Err(Error::illegal_state(
+                "No file path set for stored testcase. Could not map its input.",
+            ));
+        };
+        let input = I::from_file(file_path)?;
+        testcase.set_input(input);
+        Ok(())
+    }
+}
+
+/// Strips the inputs, which live in the files of the testcases
+impl<I> From<MmapOnDiskCorpus<I>> for InMemoryOnDiskCorpus<I>
+where
+    I: Input,
+{
+    fn from(corpus: MmapOnDiskCorpus<I>) -> Self {
+        for nth in 0..corpus.count_all() {
+            if let Ok(testcase) = corpus.get_from_all(corpus.nth_from_all(nth)) {
+                let mut testcase = testcase.borrow_mut();
+                if testcase.file_path().is_some() {
+                    testcase.input_mut().take();
+                }
+            }
+        }
+        corpus.inner
+    }
+}
+
+/// Maps the inputs of a deserialized corpus again
+impl<I> TryFrom<InMemoryOnDiskCorpus<I>> for MmapOnDiskCorpus<I>
+where
+    I: Input,
+{
+    type Error = Error;
+
+    fn try_from(inner: InMemoryOnDiskCorpus<I>) -> Result<Self, Error> {
+        let corpus = Self { inner };
+        for nth in 0..corpus.count_all() {
+            corpus.remap(corpus.nth_from_all(nth))?;
+        }
+        Ok(corpus)
+    }
+}
+
+impl<I> Corpus for MmapOnDiskCorpus<I>
+where
+    I: Input,
+{
+    /// Returns the number of all enabled entries
+    #[inline]
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// Returns the number of all disabled entries
+    fn count_disabled(&self) -> usize {
+        self.inner.count_disabled()
+    }
+
+    /// Returns the number of elements including disabled entries
+    #[inline]
+    fn count_all(&self) -> usize {
+        self.inner.count_all()
+    }
+
+    /// Add an enabled testcase to the corpus and return its index
+    fn add(&mut self, testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        let id = self.inner.add(testcase)?;
+        self.remap(id)?;
+        Ok(id)
+    }
+
+    /// Add a disabled testcase to the corpus and return its index
+    fn add_disabled(&mut self, testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        let id = self.inner.add_disabled(testcase)?;
+        self.remap(id)?;
+        Ok(id)
+    }
+
+    /// Replaces the testcase at the given idx
+    fn replace(&mut self, id: CorpusId, testcase: Testcase<I>) -> Result<Testcase<I>, Error> {
+        let entry = self.inner.replace(id, testcase)?;
+        self.remap(id)?;
+        Ok(entry)
+    }
+
+    /// Removes an entry from the corpus, returning it if it was present; considers both enabled and disabled testcases.
+    #[inline]
+    fn remove(&mut self, id: CorpusId) -> Result<Testcase<Self::Input>, Error> {
+        self.inner.remove(id)
+    }
+
+    /// Get by id; considers only enabled testcases
+    #[inline]
+    fn get(&self, id: CorpusId) -> Result<&RefCell<Testcase<I>>, Error> {
+        self.inner.get(id)
+    }
+
+    /// Get by id; considers both enabled and disabled testcases
+    #[inline]
+    fn get_from_all(&self, id: CorpusId) -> Result<&RefCell<Testcase<Self::Input>>, Error> {
+        self.inner.get_from_all(id)
+    }
+
+    /// Current testcase scheduled
+    #[inline]
+    fn current(&self) -> &Option<CorpusId> {
+        self.inner.current()
+    }
+
+    /// Current testcase scheduled (mutable)
+    #[inline]
+    fn current_mut(&mut self) -> &mut Option<CorpusId> {
+        self.inner.current_mut()
+    }
+
+    #[inline]
+    fn next(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.next(id)
+    }
+
+    /// Peek the next free corpus id
+    #[inline]
+    fn peek_free_id(&self) -> CorpusId {
+        self.inner.peek_free_id()
+    }
+
+    #[inline]
+    fn prev(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.prev(id)
+    }
+
+    #[inline]
+    fn first(&self) -> Option<CorpusId> {
+        self.inner.first()
+    }
+
+    #[inline]
+    fn last(&self) -> Option<CorpusId> {
+        self.inner.last()
+    }
+
+    /// Get the nth corpus id; considers only enabled testcases
+    #[inline]
+    fn nth(&self, nth: usize) -> CorpusId {
+        self.inner.nth(nth)
+    }
+
+    /// Get the nth corpus id; considers both enabled and disabled testcases
+    #[inline]
+    fn nth_from_all(&self, nth: usize) -> CorpusId {
+        self.inner.nth_from_all(nth)
+    }
+
+    #[inline]
+    fn load_input_into(&self, testcase: &mut Testcase<Self::Input>) -> Result<(), Error> {
+        self.inner.load_input_into(testcase)
+    }
+
+    #[inline]
+    fn store_input_from(&self, testcase: &Testcase<Self::Input>) -> Result<(), Error> {
+        self.inner.store_input_from(testcase)
+    }
+}
+
+impl<I> HasTestcase for MmapOnDiskCorpus<I>
+where
+    I: Input,
+{
+    fn testcase(&self, id: CorpusId) -> Result<core::cell::Ref<Testcase<Self::Input>>, Error> {
+        Ok(self.get(id)?.borrow())
+    }
+
+    fn testcase_mut(
+        &self,
+        id: CorpusId,
+    ) -> Result<core::cell::RefMut<Testcase<Self::Input>>, Error> {
+        Ok(self.get(id)?.borrow_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs};
+
+    use super::MmapOnDiskCorpus;
+    use crate::{
+        corpus::{Corpus, Testcase},
+        inputs::{HasMutatorBytes, MmapBytesInput},
+    };
+
+    #[test]
+    fn test_mmap_corpus_serde() {
+        let dir = temp_dir().join(format!("libafl_mmap_corpus_{}", std::process::id()));
+        let mut corpus = MmapOnDiskCorpus::no_meta(&dir).unwrap();
+        let bytes = vec![0x41_u8; 4096];
+        let id = corpus
+            .add(Testcase::new(MmapBytesInput::new(bytes.clone())))
+            .unwrap();
+        assert!(corpus
+            .get(id)
+            .unwrap()
+            .borrow()
+            .input()
+            .as_ref()
+            .unwrap()
+            .is_mapped());
+
+        // The input is not part of the serialized corpus
+        let serialized = postcard::to_allocvec(&corpus).unwrap();
+        assert!(serialized.len() < bytes.len());
+        assert!(corpus.get(id).unwrap().borrow().input().is_some());
+
+        let restored: MmapOnDiskCorpus<MmapBytesInput> = postcard::from_bytes(&serialized).unwrap();
+        let testcase = restored.get(id).unwrap().borrow();
+        let input = testcase.input().as_ref().unwrap();
+        assert!(input.is_mapped());
+        assert_eq!(input.bytes(), bytes.as_slice());
+        drop(testcase);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,10 @@
 //! Corpuses contain the testcases, either in memory, on disk, or somewhere else.
 
 pub mod testcase;
-pub use testcase::{HasTestcase, SchedulerTestcaseMetadata, Testcase};
+pub use testcase::{
+    HasTestcase, InputOrigin, InputOriginMetadata, PendingInputOriginMetadata,
+    SchedulerTestcaseMetadata, Testcase,
+};
 
 pub mod inmemory;
 pub use inmemory::InMemoryCorpus;
@@ -21,12 +24,38 @@ pub mod cached;
 #[cfg(feature = "std")]
 pub use cached::CachedOnDiskCorpus;
 
-#[cfg(all(feature = "cmin", unix))]
+#[cfg(feature = "std")]
+pub mod lazy;
+#[cfg(feature = "std")]
+pub use lazy::LazyOnDiskCorpus;
+
+#[cfg(feature = "std")]
+pub mod afl;
+#[cfg(feature = "std")]
+pub use afl::AflProvenanceMetadata;
+
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub use snapshot::CorpusSnapshot;
+
+#[cfg(feature = "mmap_corpus")]
+pub mod mmap;
+#[cfg(feature = "mmap_corpus")]
+pub use mmap::MmapOnDiskCorpus;
+
+#[cfg(feature = "s3_corpus")]
+pub mod remote;
+#[cfg(feature = "s3_corpus")]
+pub use remote::{ObjectStore, RemoteCorpus, S3ObjectStore};
+
 pub mod minimizer;
 use core::{cell::RefCell, fmt};
 
+pub mod gc;
+pub use gc::{CorpusGc, SubsumptionPolicy};
+
 pub mod nop;
-#[cfg(all(feature = "cmin", unix))]
 pub use minimizer::*;
 pub use nop::NopCorpus;
 use serde::{Deserialize, Serialize};
@@ -175,6 +204,34 @@ pub trait Corpus: UsesInput + Serialize + for<'de> Deserialize<'de> {
         let mut testcase = self.get(id)?.borrow_mut();
         Ok(testcase.load_input(self)?.clone())
     }
+
+    /// Writes all enabled testcases to `dir`, named like an `AFL++` queue
+    /// (`id:NNNNNN,src:...,op:...,+cov`), and returns how many were written.
+    ///
+    /// The provenance is taken from the [`AflProvenanceMetadata`] of imported testcases, and from the
+    /// parent id, executions and mutation log of the others.
+    #[cfg(feature = "std")]
+    fn export_afl<P>(&self, dir: P) -> Result<usize, Error>
+    where
+        Self: Sized,
+        P: AsRef<std::path::Path>,
+    {
+        afl::export_afl(self, dir)
+    }
+
+    /// Adds all files in `dir`, e.g. an `AFL++` queue or crashes directory, to this corpus and returns
+    /// their ids. The provenance encoded in `AFL++` filenames is kept as [`AflProvenanceMetadata`].
+    ///
+    /// The testcases are added as they are, without executing them or notifying a scheduler.
+    /// To fuzz them, load them as initial inputs instead, or call the scheduler's `on_add` for each id.
+    #[cfg(feature = "std")]
+    fn import_afl<P>(&mut self, dir: P) -> Result<alloc::vec::Vec<CorpusId>, Error>
+    where
+        Self: Sized,
+        P: AsRef<std::path::Path>,
+    {
+        afl::import_afl(self, dir)
+    }
 }
 
 /// Trait for types which track the current corpus index
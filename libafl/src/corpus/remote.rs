@@ -0,0 +1,860 @@
+//! The [`RemoteCorpus`] stores [`Testcase`]s in an S3-compatible object storage bucket, so a fleet of
+//! fuzzers on different machines can share a corpus without a shared filesystem.
+//!
+//! Inputs are stored under content-addressed keys (`<prefix>/<sha256 of the serialized input>`), so
+//! concurrent writers never conflict: two fuzzers finding the same input write the same object.
+//! Only a bounded number of inputs is kept in memory, the others are fetched lazily when used.
+
+use alloc::{
+    collections::{btree_set::BTreeSet, vec_deque::VecDeque},
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cell::RefCell, fmt::Write};
+use std::{io::Read, time::SystemTime};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    corpus::{inmemory::InMemoryCorpus, Corpus, CorpusId, HasTestcase, Testcase},
+    inputs::{Input, UsesInput},
+    Error,
+};
+
+/// A minimal key-value object storage, as used by the [`RemoteCorpus`]
+pub trait ObjectStore {
+    /// Fetches the object with the given key, or `None` if it does not exist
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Stores the object with the given key, overwriting it if it exists
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error>;
+
+    /// Lists the keys of all objects starting with `prefix`
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error>;
+}
+
+/// An [`ObjectStore`] talking to an S3-compatible bucket (AWS S3, `MinIO`, GCS interoperability, ...)
+/// with blocking HTTP requests, signed with AWS Signature Version 4.
+///
+/// The secret key and the session token are not serialized together with the corpus, e.g. into
+/// the state of a restarting fuzzer. When deserialized, they are read from the
+/// `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN` environment variables instead, so these need to
+/// be set for the restarted fuzzer as well.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct S3ObjectStore {
+    endpoint: String,
+    bucket: String,
+    region: String,
+    access_key: String,
+    #[serde(skip, default = "secret_key_from_env")]
+    secret_key: String,
+    #[serde(skip, default = "session_token_from_env")]
+    session_token: Option<String>,
+    path_style: bool,
+}
+
+/// The secret key of a deserialized [`S3ObjectStore`], empty if not set
+fn secret_key_from_env() -> String {
+    std::env::var("AWS_SECRET_ACCESS_KEY").unwrap_or_default()
+}
+
+/// The session token of a deserialized [`S3ObjectStore`]
+fn session_token_from_env() -> Option<String> {
+    std::env::var("AWS_SESSION_TOKEN").ok()
+}
+
+impl S3ObjectStore {
+    /// Creates a new [`S3ObjectStore`] for the `bucket` at the `endpoint`, e.g. `https://s3.eu-west-1.amazonaws.com`.
+    ///
+    /// Requests use path-style urls (`<endpoint>/<bucket>/<key>`) by default, see [`S3ObjectStore::with_path_style`].
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self, Error> {
+        if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+            return Err(Error::illegal_argument(format!(
+                "The S3 endpoint {endpoint} must start with http:// or https://"
+            )));
+        }
+        if bucket.is_empty() {
+            return Err(Error::illegal_argument(
+                "The S3 bucket name cannot be empty",
+            ));
+        }
+        Ok(Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+            session_token: None,
+            path_style: true,
+        })
+    }
+
+    /// Creates a new [`S3ObjectStore`], reading the credentials from the `AWS_ACCESS_KEY_ID`,
+    /// `AWS_SECRET_ACCESS_KEY` and (optional) `AWS_SESSION_TOKEN` environment variables.
+    pub fn from_env(endpoint: &str, bucket: &str, region: &str) -> Result<Self, Error> {
+        let var = |name: &str| {
+            std::env::var(name)
+                .map_err(|_| Error::key_not_found(format!("Environment variable {name} not set")))
+        };
+        let mut store = Self::new(
+            endpoint,
+            bucket,
+            region,
+            &var("AWS_ACCESS_KEY_ID")?,
+            &var("AWS_SECRET_ACCESS_KEY")?,
+        )?;
+        store.session_token = session_token_from_env();
+        Ok(store)
+    }
+
+    /// Sets the session token for temporary credentials
+    #[must_use]
+    pub fn with_session_token(mut self, session_token: &str) -> Self {
+        self.session_token = Some(session_token.to_string());
+        self
+    }
+
+    /// Sets if path-style urls (`<endpoint>/<bucket>/<key>`) or virtual-hosted-style urls
+    /// (`<bucket>.<endpoint host>/<key>`) are used
+    #[must_use]
+    pub fn with_path_style(mut self, path_style: bool) -> Self {
+        self.path_style = path_style;
+        self
+    }
+
+    /// The bucket name
+    #[must_use]
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// The scheme, host and canonical (encoded) path for a key
+    fn location(&self, key: &str) -> (&str, String, String) {
+        let (scheme, host) = self.endpoint.split_once("://").unwrap();
+        let key = uri_encode(key, false);
+        if self.path_style {
+            (
+                scheme,
+                host.to_string(),
+                format!("/{}/{key}", uri_encode(&self.bucket, true)),
+            )
+        } else {
+            (scheme, format!("{}.{host}", self.bucket), format!("/{key}"))
+        }
+    }
+
+    /// Sends a signed request, returning the response or `None` on 404
+    fn request(
+        &self,
+        method: &str,
+        key: &str,
+        query: &[(&str, &str)],
+        body: &[u8],
+    ) -> Result<Option<ureq::Response>, Error> {
+        if self.secret_key.is_empty() {
+            return Err(Error::illegal_state(
+                "The S3 secret key is not set, e.g. by AWS_SECRET_ACCESS_KEY after a restart",
+            ));
+        }
+        let (scheme, host, path) = self.location(key);
+
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(k, v)| format!("{k}={v}"))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let (date, time) = amz_date(SystemTime::now());
+        let amz_date = format!("{date}T{time}Z");
+        let payload_hash = hex(&Sha256::digest(body));
+
+        let mut headers = vec![
+            ("host", host.clone()),
+            ("x-amz-content-sha256", payload_hash.clone()),
+            ("x-amz-date", amz_date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let mut canonical_request = format!("{method}\n{path}\n{query}\n");
+        for (name, value) in &headers {
+            let _ = writeln!(canonical_request, "{name}:{value}");
+        }
+        let _ = write!(canonical_request, "\n{signed_headers}\n{payload_hash}");
+
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut signing_key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date.as_str(), self.region.as_str(), "s3", "aws4_request"] {
+            signing_key = hmac_sha256(&signing_key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        let mut url = format!("{scheme}://{host}{path}");
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&query);
+        }
+        let mut request = ureq::request(method, &url).set(
+            "Authorization",
+            &format!(
+                "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+                self.access_key
+            ),
+        );
+        for (name, value) in &headers[1..] {
+            request = request.set(name, value);
+        }
+        match request.send_bytes(body) {
+            Ok(response) => Ok(Some(response)),
+            Err(ureq::Error::Status(404, _)) => Ok(None),
+            Err(ureq::Error::Status(code, response)) => Err(Error::unknown(format!(
+                "S3 {method} {url} failed with status {code}: {}",
+                response.into_string().unwrap_or_default()
+            ))),
+            Err(err) => Err(Error::unknown(format!("S3 {method} {url} failed: {err}"))),
+        }
+    }
+}
+
+impl ObjectStore for S3ObjectStore {
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let Some(response) = self.request("GET", key, &[], &[])? else {
+            return Ok(None);
+        };
+        let mut data = Vec::new();
+        response.into_reader().read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+        self.request("PUT", key, &[], data)?;
+        Ok(())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        let mut continuation_token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &continuation_token {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let Some(response) = self.request("GET", "", &query, &[])? else {
+                return Err(Error::key_not_found(format!(
+                    "S3 bucket {} does not exist",
+                    self.bucket
+                )));
+            };
+            let body = response.into_string()?;
+            keys.extend(xml_elements(&body, "Key"));
+            continuation_token = xml_elements(&body, "NextContinuationToken")
+                .into_iter()
+                .next();
+            if continuation_token.is_none() {
+                return Ok(keys);
+            }
+        }
+    }
+}
+
+/// The lowercase hex representation of `bytes`
+fn hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but unreserved characters, as required by `SigV4`.
+/// If `encode_slash` is false, `/` is kept, for object keys in paths.
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char);
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+/// The UTC date (`YYYYMMDD`) and time (`HHMMSS`) of `time`, as used in `x-amz-date`
+fn amz_date(time: SystemTime) -> (String, String) {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    (
+        format!("{year:04}{month:02}{day:02}"),
+        format!(
+            "{:02}{:02}{:02}",
+            secs_of_day / 3600,
+            secs_of_day / 60 % 60,
+            secs_of_day % 60
+        ),
+    )
+}
+
+/// The unescaped text of all `<tag>` elements in `xml`
+fn xml_elements(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    xml.split(open.as_str())
+        .skip(1)
+        .filter_map(|rest| {
+            rest.split_once(close.as_str()).map(|(text, _)| {
+                text.replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&quot;", "\"")
+                    .replace("&apos;", "'")
+                    .replace("&amp;", "&")
+            })
+        })
+        .collect()
+}
+
+/// A corpus storing the inputs of its [`Testcase`]s in an [`ObjectStore`], e.g. an [`S3ObjectStore`],
+/// keeping at most `cache_max_len` of them in memory. The eviction policy is LRU.
+///
+/// The key of each input is stored as the filename of its [`Testcase`]. The testcases themselves,
+/// including their metadata, stay local. Removed testcases are not deleted from the bucket, as other
+/// fuzzers of the fleet may still use them.
+///
+/// Use [`RemoteCorpus::new_remote_keys`] and [`RemoteCorpus::fetch_input`] to pull the inputs found by
+/// the rest of the fleet, e.g. to evaluate them with the fuzzer.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(bound = "I: serde::de::DeserializeOwned, B: Serialize + serde::de::DeserializeOwned")]
+pub struct RemoteCorpus<I, B>
+where
+    I: Input,
+{
+    inner: InMemoryCorpus<I>,
+    store: B,
+    prefix: String,
+    /// The keys in the bucket this corpus knows of, to skip redundant uploads and downloads
+    known_keys: RefCell<BTreeSet<String>>,
+    cached_indexes: RefCell<VecDeque<CorpusId>>,
+    cache_max_len: usize,
+}
+
+impl<I, B> UsesInput for RemoteCorpus<I, B>
+where
+    I: Input,
+{
+    type Input = I;
+}
+
+impl<I, B> RemoteCorpus<I, B>
+where
+    I: Input,
+    B: ObjectStore,
+{
+    /// Creates a new [`RemoteCorpus`], storing inputs in `store` under the given key prefix,
+    /// e.g. the name of the fuzzing campaign.
+    pub fn new(store: B, prefix: &str, cache_max_len: usize) -> Result<Self, Error> {
+        if cache_max_len == 0 {
+            return Err(Error::illegal_argument(
+                "The max cache len in RemoteCorpus cannot be 0",
+            ));
+        }
+        Ok(Self {
+            inner: InMemoryCorpus::new(),
+            store,
+            prefix: prefix.trim_matches('/').to_string(),
+            known_keys: RefCell::new(BTreeSet::new()),
+            cached_indexes: RefCell::new(VecDeque::new()),
+            cache_max_len,
+        })
+    }
+
+    /// The underlying object store
+    pub fn store(&self) -> &B {
+        &self.store
+    }
+
+    /// The key prefix of the inputs
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// The content-addressed key of the serialized input
+    fn key_for(&self, data: &[u8]) -> String {
+        let hash = hex(&Sha256::digest(data));
+        if self.prefix.is_empty() {
+            hash
+        } else {
+            format!("{}/{hash}", self.prefix)
+        }
+    }
+
+    /// Uploads the input, unless already known, returning its key
+    fn upload(&self, input: &I) -> Result<String, Error> {
+        let data = postcard::to_allocvec(input)?;
+        let key = self.key_for(&data);
+        if !self.known_keys.borrow().contains(&key) {
+            self.store.put(&key, &data)?;
+            self.known_keys.borrow_mut().insert(key.clone());
+        }
+        Ok(key)
+    }
+
+    /// Uploads the input of the testcase, sets its key as filename and evicts the input
+    fn store_testcase(&self, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        let Some(input) = testcase.input() else {
+            return Err(Error::illegal_argument(
+                "No input available for testcase. Could not store anything.",
+            ));
+        };
+        let key = self.upload(input)?;
+        *testcase.filename_mut() = Some(key);
+        *testcase.input_mut() = None;
+        Ok(())
+    }
+
+    /// Fetches the input of the testcase by its key, unless it is in memory
+    fn load_input(&self, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        if testcase.input().is_none() {
+            let Some(key) = testcase.filename() else {
+                return Err(Error::illegal_argument(
+                    "No object key set for testcase. Could not load inputs.",
+                ));
+            };
+            let input = self.fetch_input(key)?;
+            testcase.set_input(input);
+        }
+        Ok(())
+    }
+
+    fn cache_testcase(&self, testcase: &RefCell<Testcase<I>>, id: CorpusId) -> Result<(), Error> {
+        if testcase.borrow().input().is_some() {
+            // Cache hit, mark as most recently used
+            let mut cached = self.cached_indexes.borrow_mut();
+            if let Some(pos) = cached.iter().position(|e| *e == id) {
+                cached.remove(pos);
+                cached.push_back(id);
+            }
+            return Ok(());
+        }
+        self.load_input(&mut testcase.borrow_mut())?;
+        let mut borrowed_num = 0;
+        while self.cached_indexes.borrow().len() >= self.cache_max_len {
+            let removed = self.cached_indexes.borrow_mut().pop_front().unwrap();
+
+            if let Ok(mut borrowed) = self.inner.get_from_all(removed)?.try_borrow_mut() {
+                *borrowed.input_mut() = None;
+            } else {
+                self.cached_indexes.borrow_mut().push_back(removed);
+                borrowed_num += 1;
+                if self.cache_max_len == borrowed_num {
+                    break;
+                }
+            }
+        }
+        self.cached_indexes.borrow_mut().push_back(id);
+        Ok(())
+    }
+
+    /// Lists the keys in the bucket this corpus does not know of yet, i.e. inputs added by other fuzzers.
+    ///
+    /// The returned keys are considered known afterwards, so each key is only returned once.
+    pub fn new_remote_keys(&self) -> Result<Vec<String>, Error> {
+        let prefix = if self.prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", self.prefix)
+        };
+        let mut known_keys = self.known_keys.borrow_mut();
+        let mut keys = self.store.list(&prefix)?;
+        keys.retain(|key| known_keys.insert(key.clone()));
+        Ok(keys)
+    }
+
+    /// Fetches and deserializes the input with the given key
+    pub fn fetch_input(&self, key: &str) -> Result<I, Error> {
+        let Some(data) = self.store.get(key)? else {
+            return Err(Error::key_not_found(format!(
+                "Input {key} not found in the object store"
+            )));
+        };
+        Ok(postcard::from_bytes(&data)?)
+    }
+}
+
+impl<I, B> Corpus for RemoteCorpus<I, B>
+where
+    I: Input,
+    B: ObjectStore + Serialize + for<'de> Deserialize<'de>,
+{
+    /// Returns the number of all enabled entries
+    #[inline]
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// Returns the number of all disabled entries
+    fn count_disabled(&self) -> usize {
+        self.inner.count_disabled()
+    }
+
+    /// Returns the number of elements including disabled entries
+    #[inline]
+    fn count_all(&self) -> usize {
+        self.inner.count_all()
+    }
+
+    /// Add an enabled testcase to the corpus and return its index
+    fn add(&mut self, mut testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        self.store_testcase(&mut testcase)?;
+        self.inner.add(testcase)
+    }
+
+    /// Add a disabled testcase to the corpus and return its index
+    fn add_disabled(&mut self, mut testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        self.store_testcase(&mut testcase)?;
+        self.inner.add_disabled(testcase)
+    }
+
+    /// Replaces the testcase at the given idx
+    fn replace(&mut self, id: CorpusId, mut testcase: Testcase<I>) -> Result<Testcase<I>, Error> {
+        self.store_testcase(&mut testcase)?;
+        self.cached_indexes.borrow_mut().retain(|e| *e != id);
+        self.inner.replace(id, testcase)
+    }
+
+    /// Removes an entry from the corpus, returning it if it was present; considers both enabled and disabled testcases.
+    fn remove(&mut self, id: CorpusId) -> Result<Testcase<Self::Input>, Error> {
+        let testcase = self.inner.remove(id)?;
+        self.cached_indexes.borrow_mut().retain(|e| *e != id);
+        Ok(testcase)
+    }
+
+    /// Get by id; considers only enabled testcases
+    #[inline]
+    fn get(&self, id: CorpusId) -> Result<&RefCell<Testcase<I>>, Error> {
+        let testcase = { self.inner.get(id)? };
+        self.cache_testcase(testcase, id)?;
+        Ok(testcase)
+    }
+
+    /// Get by id; considers both enabled and disabled testcases
+    #[inline]
+    fn get_from_all(&self, id: CorpusId) -> Result<&RefCell<Testcase<Self::Input>>, Error> {
+        let testcase = { self.inner.get_from_all(id)? };
+        self.cache_testcase(testcase, id)?;
+        Ok(testcase)
+    }
+
+    /// Current testcase scheduled
+    #[inline]
+    fn current(&self) -> &Option<CorpusId> {
+        self.inner.current()
+    }
+
+    /// Current testcase scheduled (mutable)
+    #[inline]
+    fn current_mut(&mut self) -> &mut Option<CorpusId> {
+        self.inner.current_mut()
+    }
+
+    #[inline]
+    fn next(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.next(id)
+    }
+
+    /// Peek the next free corpus id
+    #[inline]
+    fn peek_free_id(&self) -> CorpusId {
+        self.inner.peek_free_id()
+    }
+
+    #[inline]
+    fn prev(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.prev(id)
+    }
+
+    #[inline]
+    fn first(&self) -> Option<CorpusId> {
+        self.inner.first()
+    }
+
+    #[inline]
+    fn last(&self) -> Option<CorpusId> {
+        self.inner.last()
+    }
+
+    /// Get the nth corpus id; considers only enabled testcases
+    #[inline]
+    fn nth(&self, nth: usize) -> CorpusId {
+        self.inner.nth(nth)
+    }
+
+    /// Get the nth corpus id; considers both enabled and disabled testcases
+    #[inline]
+    fn nth_from_all(&self, nth: usize) -> CorpusId {
+        self.inner.nth_from_all(nth)
+    }
+
+    fn load_input_into(&self, testcase: &mut Testcase<Self::Input>) -> Result<(), Error> {
+        self.load_input(testcase)
+    }
+
+    fn store_input_from(&self, testcase: &Testcase<Self::Input>) -> Result<(), Error> {
+        let Some(input) = testcase.input() else {
+            return Err(Error::illegal_argument(
+                "No input available for testcase. Could not store anything.",
+            ));
+        };
+        self.upload(input)?;
+        Ok(())
+    }
+}
+
+impl<I, B> HasTestcase for RemoteCorpus<I, B>
+where
+    I: Input,
+    B: ObjectStore + Serialize + for<'de> Deserialize<'de>,
+{
+    fn testcase(&self, id: CorpusId) -> Result<core::cell::Ref<Testcase<Self::Input>>, Error> {
+        Ok(self.get(id)?.borrow())
+    }
+
+    fn testcase_mut(
+        &self,
+        id: CorpusId,
+    ) -> Result<core::cell::RefMut<Testcase<Self::Input>>, Error> {
+        Ok(self.get(id)?.borrow_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        collections::btree_map::BTreeMap,
+        string::{String, ToString},
+        vec::Vec,
+    };
+    use core::cell::{Cell, RefCell};
+    use std::time::{Duration, SystemTime};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::{amz_date, uri_encode, ObjectStore, RemoteCorpus, S3ObjectStore};
+    use crate::{
+        corpus::{Corpus, CorpusId, Testcase},
+        inputs::BytesInput,
+        Error,
+    };
+
+    /// An in-memory [`ObjectStore`], counting the requests made to it
+    #[derive(Serialize, Deserialize, Clone, Debug, Default)]
+    struct StubObjectStore {
+        objects: RefCell<BTreeMap<String, Vec<u8>>>,
+        gets: Cell<usize>,
+        puts: Cell<usize>,
+    }
+
+    impl ObjectStore for StubObjectStore {
+        fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+            self.gets.set(self.gets.get() + 1);
+            Ok(self.objects.borrow().get(key).cloned())
+        }
+
+        fn put(&self, key: &str, data: &[u8]) -> Result<(), Error> {
+            self.puts.set(self.puts.get() + 1);
+            self.objects
+                .borrow_mut()
+                .insert(key.to_string(), data.to_vec());
+            Ok(())
+        }
+
+        fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+            Ok(self
+                .objects
+                .borrow()
+                .keys()
+                .filter(|key| key.starts_with(prefix))
+                .cloned()
+                .collect())
+        }
+    }
+
+    fn remote_corpus(inputs: &[&[u8]]) -> RemoteCorpus<BytesInput, StubObjectStore> {
+        let mut corpus = RemoteCorpus::new(StubObjectStore::default(), "/campaign/", 2).unwrap();
+        for input in inputs {
+            corpus
+                .add(Testcase::new(BytesInput::new(input.to_vec())))
+                .unwrap();
+        }
+        corpus
+    }
+
+    fn is_cached(corpus: &RemoteCorpus<BytesInput, StubObjectStore>, id: CorpusId) -> bool {
+        corpus.inner.get(id).unwrap().borrow().input().is_some()
+    }
+
+    #[test]
+    fn test_remote_corpus_cache_eviction() {
+        let corpus = remote_corpus(&[b"a", b"b", b"c", b"a"]);
+        // Duplicates are uploaded once, and inputs only stay in memory while cached
+        assert_eq!(corpus.store().puts.get(), 3);
+        assert_eq!(corpus.store().objects.borrow().len(), 3);
+        let [a, b, c] = [0_usize, 1, 2].map(CorpusId::from);
+        assert!(!is_cached(&corpus, a));
+        assert!(corpus
+            .inner
+            .get(a)
+            .unwrap()
+            .borrow()
+            .filename()
+            .as_ref()
+            .unwrap()
+            .starts_with("campaign/"));
+
+        corpus.get(a).unwrap();
+        corpus.get(b).unwrap();
+        // A cache hit does not fetch, but marks the entry as most recently used
+        corpus.get(a).unwrap();
+        assert_eq!(corpus.store().gets.get(), 2);
+
+        corpus.get(c).unwrap();
+        assert_eq!(corpus.store().gets.get(), 3);
+        assert!(is_cached(&corpus, a));
+        assert!(!is_cached(&corpus, b));
+        assert!(is_cached(&corpus, c));
+
+        // Evicted inputs are fetched again
+        assert_eq!(
+            corpus.get(b).unwrap().borrow().input(),
+            &Some(BytesInput::new(b"b".to_vec()))
+        );
+        assert_eq!(corpus.store().gets.get(), 4);
+        assert!(!is_cached(&corpus, a));
+    }
+
+    #[test]
+    fn test_remote_corpus_restore() {
+        let corpus = remote_corpus(&[b"a", b"b", b"c"]);
+        corpus.get(CorpusId::from(0_usize)).unwrap();
+
+        // A restarted fuzzer fetches the evicted inputs from the bucket again
+        let serialized = postcard::to_allocvec(&corpus).unwrap();
+        let restored: RemoteCorpus<BytesInput, StubObjectStore> =
+            postcard::from_bytes(&serialized).unwrap();
+        assert_eq!(restored.count(), 3);
+        assert_eq!(restored.prefix(), "campaign");
+        for (id, input) in [b"a", b"b", b"c"].into_iter().enumerate() {
+            assert_eq!(
+                restored.get(CorpusId::from(id)).unwrap().borrow().input(),
+                &Some(BytesInput::new(input.to_vec()))
+            );
+        }
+        // The cached input came along, and no known input is listed as new
+        assert_eq!(restored.store().gets.get(), corpus.store().gets.get() + 2);
+        assert!(restored.new_remote_keys().unwrap().is_empty());
+
+        // Another fuzzer of the fleet sees all the inputs as new, once
+        let other: RemoteCorpus<BytesInput, _> =
+            RemoteCorpus::new(corpus.store().clone(), "campaign", 2).unwrap();
+        let keys = other.new_remote_keys().unwrap();
+        assert_eq!(keys.len(), 3);
+        assert!(other.new_remote_keys().unwrap().is_empty());
+        let mut fetched: Vec<Vec<u8>> = keys
+            .iter()
+            .map(|key| other.fetch_input(key).unwrap().into())
+            .collect();
+        fetched.sort();
+        assert_eq!(fetched, [b"a", b"b", b"c"]);
+        assert!(other.fetch_input("campaign/missing").is_err());
+    }
+
+    #[test]
+    fn test_amz_date() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+        assert_eq!(
+            amz_date(time),
+            ("20240229".to_string(), "235959".to_string())
+        );
+        assert_eq!(
+            amz_date(SystemTime::UNIX_EPOCH),
+            ("19700101".to_string(), "000000".to_string())
+        );
+    }
+
+    #[test]
+    fn test_uri_encode() {
+        assert_eq!(uri_encode("a b/c~d", false), "a%20b/c~d");
+        assert_eq!(uri_encode("a b/c~d", true), "a%20b%2Fc~d");
+    }
+
+    #[test]
+    fn test_s3_credentials_not_serialized() {
+        let store = S3ObjectStore::new(
+            "https://s3.eu-west-1.amazonaws.com",
+            "corpus",
+            "eu-west-1",
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG",
+        )
+        .unwrap()
+        .with_session_token("FwoGZXIvYXdzEBYaDK");
+        let serialized = postcard::to_allocvec(&store).unwrap();
+        let contains = |needle: &[u8]| serialized.windows(needle.len()).any(|w| w == needle);
+        assert!(contains(b"AKIDEXAMPLE"));
+        assert!(!contains(b"wJalrXUtnFEMI/K7MDENG"));
+        assert!(!contains(b"FwoGZXIvYXdzEBYaDK"));
+
+        let restored: S3ObjectStore = postcard::from_bytes(&serialized).unwrap();
+        assert_eq!(restored.bucket(), "corpus");
+        assert_eq!(
+            restored.session_token,
+            std::env::var("AWS_SESSION_TOKEN").ok()
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Campaign snapshots: the [`CorpusSnapshot`] periodically captures the whole state, i.e. the
+//! corpus, the solutions, the scheduler metadata and the feedback state, to a directory, so that a
+//! campaign can be resumed from the latest snapshot after a machine failure.
+//!
+//! Each snapshot is a directory `snapshot-<sequence>` containing
+//! * `state.postcard`, the serialized state,
+//! * `queue/<id>` and `solutions/<id>`, the inputs of all testcases.
+//!
+//! Snapshots are first written to a temporary directory and then renamed, so an interrupted
+//! capture never leaves a partial snapshot behind.
+
+use alloc::{string::ToString, vec::Vec};
+use core::time::Duration;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libafl_bolts::current_time;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId},
+    inputs::Input,
+    state::{HasCorpus, HasSolutions},
+    Error,
+};
+
+/// The prefix of the snapshot directories
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+/// The file of the serialized state in a snapshot
+const SNAPSHOT_STATE_FILE: &str = "state.postcard";
+/// The directory of the corpus inputs in a snapshot
+const SNAPSHOT_QUEUE_DIR: &str = "queue";
+/// The directory of the solution inputs in a snapshot
+const SNAPSHOT_SOLUTIONS_DIR: &str = "solutions";
+
+/// The default interval between two snapshots
+pub const DEFAULT_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// The default number of snapshots kept
+pub const DEFAULT_SNAPSHOTS_KEPT: usize = 2;
+
+/// Captures the whole state of a campaign to a directory, and restores it.
+/// Use the [`crate::stages::CorpusSnapshotStage`] to capture snapshots periodically.
+///
+/// Unlike the state restorer of the restarting event managers, which only survives restarts of
+/// a fuzzer process, snapshots are written to disk and can be used to resume a campaign on a fresh
+/// machine. Use one snapshot directory per client.
+#[derive(Debug, Clone)]
+pub struct CorpusSnapshot {
+    dir: PathBuf,
+    interval: Duration,
+    keep: usize,
+    sequence: u64,
+    last_capture: Option<Duration>,
+}
+
+impl CorpusSnapshot {
+    /// Creates a [`CorpusSnapshot`] writing to the given directory, creating it if needed
+    pub fn new<P>(dir: P) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let sequence = Self::sequences(&dir)?
+            .last()
+            .map_or(0, |sequence| sequence + 1);
+        Ok(Self {
+            dir,
+            interval: DEFAULT_SNAPSHOT_INTERVAL,
+            keep: DEFAULT_SNAPSHOTS_KEPT,
+            sequence,
+            last_capture: None,
+        })
+    }
+
+    /// Sets the interval between two snapshots captured by [`CorpusSnapshot::maybe_capture`]
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the number of snapshots kept, older ones are deleted. At least one is kept.
+    #[must_use]
+    pub fn with_keep(mut self, keep: usize) -> Self {
+        self.keep = keep.max(1);
+        self
+    }
+
+    /// The snapshot directory
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The interval between two snapshots
+    #[must_use]
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// The sequence numbers of the complete snapshots in `dir`, sorted
+    fn sequences(dir: &Path) -> Result<Vec<u64>, Error> {
+        let mut sequences = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if let Some(sequence) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(SNAPSHOT_PREFIX))
+                .and_then(|sequence| sequence.parse().ok())
+            {
+                sequences.push(sequence);
+            }
+        }
+        sequences.sort_unstable();
+        Ok(sequences)
+    }
+
+    /// The path of the snapshot with the given sequence number
+    fn snapshot_path(&self, sequence: u64) -> PathBuf {
+        self.dir.join(format!("{SNAPSHOT_PREFIX}{sequence:010}"))
+    }
+
+    /// The path of the latest complete snapshot, if any
+    pub fn latest(&self) -> Result<Option<PathBuf>, Error> {
+        Ok(Self::sequences(&self.dir)?
+            .last()
+            .map(|sequence| self.snapshot_path(*sequence)))
+    }
+
+    /// Captures a snapshot if the interval passed since the last one.
+    /// The first call only starts the interval.
+    pub fn maybe_capture<S>(&mut self, state: &S) -> Result<Option<PathBuf>, Error>
+    where
+        S: HasCorpus + HasSolutions + Serialize,
+        S::Input: Input,
+    {
+        let now = current_time();
+        match self.last_capture {
+            None => {
+                self.last_capture = Some(now);
+                Ok(None)
+            }
+            Some(last_capture) if now < last_capture + self.interval => Ok(None),
+            Some(_) => self.capture(state).map(Some),
+        }
+    }
+
+    /// Captures a snapshot of the state now, and deletes the oldest snapshots exceeding the number
+    /// of snapshots kept. Returns the path of the new snapshot.
+    pub fn capture<S>(&mut self, state: &S) -> Result<PathBuf, Error>
+    where
+        S: HasCorpus + HasSolutions + Serialize,
+        S::Input: Input,
+    {
+        let tmp = self.dir.join(format!(".{SNAPSHOT_PREFIX}tmp"));
+        if tmp.exists() {
+            // Left over from an interrupted capture
+            fs::remove_dir_all(&tmp)?;
+        }
+        fs::create_dir_all(&tmp)?;
+
+        Self::write_inputs(state.corpus(), &tmp.join(SNAPSHOT_QUEUE_DIR))?;
+        Self::write_inputs(state.solutions(), &tmp.join(SNAPSHOT_SOLUTIONS_DIR))?;
+        fs::write(tmp.join(SNAPSHOT_STATE_FILE), postcard::to_allocvec(state)?)?;
+
+        let path = self.snapshot_path(self.sequence);
+        fs::rename(&tmp, &path)?;
+        self.sequence += 1;
+        self.last_capture = Some(current_time());
+
+        let sequences = Self::sequences(&self.dir)?;
+        let outdated = sequences.len().saturating_sub(self.keep);
+        for sequence in &sequences[..outdated] {
+            fs::remove_dir_all(self.snapshot_path(*sequence))?;
+        }
+
+        log::info!("Captured campaign snapshot {}", path.display());
+        Ok(path)
+    }
+
+    /// Restores the state from the latest snapshot, if any, see [`CorpusSnapshot::load`]
+    pub fn load_latest<S>(&self) -> Result<Option<S>, Error>
+    where
+        S: HasCorpus + HasSolutions + DeserializeOwned,
+        S::Input: Input,
+    {
+        self.latest()?.map(Self::load).transpose()
+    }
+
+    /// Restores the state from the given snapshot directory.
+    ///
+    /// Testcases stored on disk, e.g. by an [`crate::corpus::OnDiskCorpus`], reference their files
+    /// in the corpus directory. Files missing there, e.g. on a fresh machine, are restored from
+    /// the inputs in the snapshot.
+    pub fn load<S, P>(path: P) -> Result<S, Error>
+    where
+        S: HasCorpus + HasSolutions + DeserializeOwned,
+        S::Input: Input,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let state: S = postcard::from_bytes(&fs::read(path.join(SNAPSHOT_STATE_FILE))?)?;
+        Self::restore_files(state.corpus(), &path.join(SNAPSHOT_QUEUE_DIR))?;
+        Self::restore_files(state.solutions(), &path.join(SNAPSHOT_SOLUTIONS_DIR))?;
+        log::info!("Restored campaign snapshot {}", path.display());
+        Ok(state)
+    }
+
+    /// The ids of all testcases, enabled and disabled
+    fn all_ids<C>(corpus: &C) -> impl Iterator<Item = CorpusId> + '_
+    where
+        C: Corpus,
+    {
+        (0..corpus.count_all()).map(|nth| corpus.nth_from_all(nth))
+    }
+
+    /// Writes the inputs of all testcases to `dir`, named by their id
+    fn write_inputs<C>(corpus: &C, dir: &Path) -> Result<(), Error>
+    where
+        C: Corpus,
+        C::Input: Input,
+    {
+        fs::create_dir_all(dir)?;
+        for id in Self::all_ids(corpus) {
+            let mut testcase = corpus.get_from_all(id)?.borrow_mut();
+            testcase
+                .load_input(corpus)?
+                .to_file(dir.join(id.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Restores missing testcase files from the inputs in `dir`
+    fn restore_files<C>(corpus: &C, dir: &Path) -> Result<(), Error>
+    where
+        C: Corpus,
+        C::Input: Input,
+    {
+        for id in Self::all_ids(corpus) {
+            let testcase = corpus.get_from_all(id)?.borrow();
+            let Some(file_path) = testcase.file_path() else {
+                continue;
+            };
+            if file_path.exists() {
+                continue;
+            }
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(dir.join(id.to_string()), file_path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::CorpusSnapshot;
+    use crate::{
+        corpus::{Corpus, InMemoryCorpus, OnDiskCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        state::{HasCorpus, StdState},
+    };
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_snapshot_restore() {
+        let corpus_dir = PathBuf::from("target/.test/snapshot/corpus");
+        let snapshot_dir = PathBuf::from("target/.test/snapshot/snapshots");
+        drop(fs::remove_dir_all("target/.test/snapshot"));
+
+        let mut corpus = OnDiskCorpus::<BytesInput>::new(&corpus_dir).unwrap();
+        let id = corpus
+            .add(Testcase::new(BytesInput::new(b"snapshot".to_vec())))
+            .unwrap();
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let state = StdState::new(
+            StdRand::with_seed(0),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+
+        let mut snapshot = CorpusSnapshot::new(&snapshot_dir).unwrap().with_keep(1);
+        snapshot.capture(&state).unwrap();
+        let latest = snapshot.capture(&state).unwrap();
+        assert_eq!(snapshot.latest().unwrap(), Some(latest));
+        assert_eq!(fs::read_dir(&snapshot_dir).unwrap().count(), 1);
+
+        // Simulate a fresh machine
+        fs::remove_dir_all(&corpus_dir).unwrap();
+        let restored: StdState<_, OnDiskCorpus<BytesInput>, StdRand, InMemoryCorpus<BytesInput>> =
+            snapshot.load_latest().unwrap().unwrap();
+        let mut testcase = restored.corpus().get(id).unwrap().borrow_mut();
+        let input = testcase.load_input(restored.corpus()).unwrap();
+        assert_eq!(input, &BytesInput::new(b"snapshot".to_vec()));
+
+        drop(testcase);
+        fs::remove_dir_all("target/.test/snapshot").unwrap();
+    }
+}
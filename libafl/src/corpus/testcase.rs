@@ -562,6 +562,57 @@ impl SchedulerTestcaseMetadata {
 
 libafl_bolts::impl_serdeany!(SchedulerTestcaseMetadata);
 
+/// Where a [`Testcase`] came from
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum InputOrigin {
+    /// Found by this client, while fuzzing a testcase
+    #[default]
+    Local,
+    /// Received from another client
+    Imported,
+    /// Produced by a generator, or loaded as initial input
+    Generated,
+}
+
+impl InputOrigin {
+    /// All origins, in the order of their indices
+    pub const ALL: [InputOrigin; 3] = [Self::Local, Self::Imported, Self::Generated];
+
+    /// The index of this origin in [`InputOrigin::ALL`]
+    #[must_use]
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The [`InputOrigin`] of a testcase, added by the fuzzer when the testcase enters the corpus if
+/// enabled with [`crate::StdFuzzer::with_input_origins`]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct InputOriginMetadata {
+    /// The origin of the testcase
+    pub origin: InputOrigin,
+}
+
+libafl_bolts::impl_serdeany!(InputOriginMetadata);
+
+/// State metadata overriding the [`InputOrigin`] of the testcases added while it is present,
+/// e.g. set by the [`crate::stages::GenStage`] while evaluating generated inputs
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct PendingInputOriginMetadata {
+    /// The origin of the testcases added now
+    pub origin: InputOrigin,
+}
+
+libafl_bolts::impl_serdeany!(PendingInputOriginMetadata);
+
 #[cfg(feature = "std")]
 impl<I> Drop for Testcase<I>
 where
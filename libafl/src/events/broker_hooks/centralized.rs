@@ -2,7 +2,7 @@ use alloc::vec::Vec;
 use core::{fmt::Debug, marker::PhantomData};
 
 #[cfg(feature = "llmp_compression")]
-use libafl_bolts::{compress::GzipCompressor, llmp::LLMP_FLAG_COMPRESSED};
+use libafl_bolts::{compress::Compressor, llmp::LLMP_FLAG_COMPRESSED};
 use libafl_bolts::{
     llmp::{Flags, LlmpBrokerInner, LlmpHook, LlmpMsgHookResult, Tag},
     shmem::ShMemProvider,
@@ -19,7 +19,7 @@ use crate::{
 /// An LLMP-backed event manager for scalable multi-processed fuzzing
 pub struct CentralizedLlmpHook<I> {
     #[cfg(feature = "llmp_compression")]
-    compressor: GzipCompressor,
+    compressor: Compressor,
     phantom: PhantomData<I>,
 }
 
@@ -83,11 +83,18 @@ where
     pub fn new() -> Result<Self, Error> {
         Ok(Self {
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
             phantom: PhantomData,
         })
     }
 
+    /// The compressor used to decompress the events of the clients.
+    /// Set the same dictionary as the clients here.
+    #[cfg(feature = "llmp_compression")]
+    pub fn compressor_mut(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
     /// Handle arriving events in the broker
     #[allow(clippy::unnecessary_wraps)]
     fn handle_in_broker(
@@ -3,8 +3,9 @@ use alloc::vec::Vec;
 use core::marker::PhantomData;
 
 #[cfg(feature = "llmp_compression")]
-use libafl_bolts::{compress::GzipCompressor, llmp::LLMP_FLAG_COMPRESSED};
+use libafl_bolts::{compress::Compressor, llmp::LLMP_FLAG_COMPRESSED};
 use libafl_bolts::{
+    current_time, format_duration_hms,
     llmp::{Flags, LlmpBrokerInner, LlmpHook, LlmpMsgHookResult, Tag},
     shmem::ShMemProvider,
     ClientId,
@@ -13,9 +14,12 @@ use libafl_bolts::{
 #[cfg(feature = "llmp_compression")]
 use crate::events::llmp::COMPRESS_THRESHOLD;
 use crate::{
-    events::{llmp::LLMP_TAG_EVENT_TO_BOTH, BrokerEventResult, Event},
+    events::{
+        llmp::{batched_events, LLMP_TAG_EVENT_BATCH, LLMP_TAG_EVENT_TO_BOTH},
+        BrokerEventResult, Event,
+    },
     inputs::Input,
-    monitors::Monitor,
+    monitors::{Monitor, CLIENT_FINGERPRINT_STAT},
     Error,
 };
 
@@ -36,7 +40,7 @@ pub use centralized_multi_machine::*;
 pub struct StdLlmpEventHook<I, MT> {
     monitor: MT,
     #[cfg(feature = "llmp_compression")]
-    compressor: GzipCompressor,
+    compressor: Compressor,
     phantom: PhantomData<I>,
 }
 
@@ -48,7 +52,7 @@ where
 {
     fn on_new_message(
         &mut self,
-        _broker_inner: &mut LlmpBrokerInner<SP>,
+        broker_inner: &mut LlmpBrokerInner<SP>,
         client_id: ClientId,
         msg_tag: &mut Tag,
         #[cfg(feature = "llmp_compression")] msg_flags: &mut Flags,
@@ -73,10 +77,44 @@ where
                 &*msg
             };
             let event: Event<I> = postcard::from_bytes(event_bytes)?;
+            if matches!(event, Event::Stop { .. }) {
+                broker_inner.request_shutdown();
+            }
             match Self::handle_in_broker(monitor, client_id, &event)? {
                 BrokerEventResult::Forward => Ok(LlmpMsgHookResult::ForwardToClients),
                 BrokerEventResult::Handled => Ok(LlmpMsgHookResult::Handled),
             }
+        } else if *msg_tag == LLMP_TAG_EVENT_BATCH {
+            #[cfg(not(feature = "llmp_compression"))]
+            let batch = msg;
+            #[cfg(feature = "llmp_compression")]
+            let compressed;
+            #[cfg(feature = "llmp_compression")]
+            let batch = if *msg_flags & LLMP_FLAG_COMPRESSED == LLMP_FLAG_COMPRESSED {
+                compressed = compressor.decompress(msg)?;
+                &compressed
+            } else {
+                &*msg
+            };
+            // Forward the whole batch if any of its events is meant for the clients
+            let mut forward = false;
+            for event_bytes in batched_events(batch) {
+                let event: Event<I> = postcard::from_bytes(event_bytes?)?;
+                if matches!(event, Event::Stop { .. }) {
+                    broker_inner.request_shutdown();
+                }
+                if matches!(
+                    Self::handle_in_broker(monitor, client_id, &event)?,
+                    BrokerEventResult::Forward
+                ) {
+                    forward = true;
+                }
+            }
+            if forward {
+                Ok(LlmpMsgHookResult::ForwardToClients)
+            } else {
+                Ok(LlmpMsgHookResult::Handled)
+            }
         } else {
             Ok(LlmpMsgHookResult::ForwardToClients)
         }
@@ -86,6 +124,19 @@ where
         self.monitor.display("Broker Heartbeat", ClientId(0));
         Ok(())
     }
+
+    fn on_shutdown(&mut self) -> Result<(), Error> {
+        log::info!(
+            "Fuzzing finished after {}: {} clients, {} executions, {} corpus entries, {} objectives",
+            format_duration_hms(&current_time().saturating_sub(self.monitor.start_time())),
+            self.monitor.client_stats_count(),
+            self.monitor.total_execs(),
+            self.monitor.corpus_size(),
+            self.monitor.objective_size(),
+        );
+        self.monitor.display_final();
+        Ok(())
+    }
 }
 
 impl<I, MT> StdLlmpEventHook<I, MT>
@@ -98,11 +149,18 @@ where
         Ok(Self {
             monitor,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
             phantom: PhantomData,
         })
     }
 
+    /// The compressor used to decompress the events of the clients.
+    /// Set the same dictionary as the clients here.
+    #[cfg(feature = "llmp_compression")]
+    pub fn compressor_mut(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
     /// Handle arriving events in the broker
     #[allow(clippy::unnecessary_wraps)]
     fn handle_in_broker(
@@ -112,6 +170,7 @@ where
     ) -> Result<BrokerEventResult, Error> {
         match &event {
             Event::NewTestcase {
+                client_config,
                 corpus_size,
                 time,
                 executions,
@@ -127,6 +186,7 @@ where
                 monitor.client_stats_insert(id);
                 let client = monitor.client_stats_mut_for(id);
                 client.update_corpus_size(*corpus_size as u64);
+                client.configuration = Some(*client_config);
                 if id == client_id {
                     // do not update executions for forwarded messages, otherwise we loose the total order
                     // as a forwarded msg with a lower executions may arrive after a stats msg with an higher executions
@@ -156,6 +216,9 @@ where
                 monitor.client_stats_insert(client_id);
                 let client = monitor.client_stats_mut_for(client_id);
                 client.update_user_stats(name.clone(), value.clone());
+                if name == CLIENT_FINGERPRINT_STAT {
+                    monitor.check_fingerprint(client_id);
+                }
                 monitor.aggregate(name);
                 monitor.display(event.name(), client_id);
                 Ok(BrokerEventResult::Handled)
@@ -207,8 +270,90 @@ where
                 log::log!((*severity_level).into(), "{message}");
                 Ok(BrokerEventResult::Handled)
             }
+            Event::Error { report } => {
+                log::error!("Client {} failed: {report}", client_id.0);
+                Ok(BrokerEventResult::Handled)
+            }
+            Event::Stop { reason } => {
+                log::info!("Client {} {reason}, ending the campaign", client_id.0);
+                Ok(BrokerEventResult::Handled)
+            }
             Event::CustomBuf { .. } => Ok(BrokerEventResult::Forward),
             //_ => Ok(BrokerEventResult::Forward),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{
+        string::{String, ToString},
+        vec::Vec,
+    };
+    use core::cell::RefCell;
+
+    use libafl_bolts::ClientId;
+
+    use super::StdLlmpEventHook;
+    use crate::{
+        events::{test::RecordingEventFirer, Event, EventFirer},
+        inputs::BytesInput,
+        monitors::{
+            CampaignFingerprint, SimpleMonitor, CLIENT_FINGERPRINT_STAT, CLIENT_LABEL_STAT,
+        },
+        state::test::test_std_state,
+    };
+
+    #[test]
+    fn test_client_label_and_fingerprint() {
+        let mut state = test_std_state::<BytesInput>();
+        let lines = RefCell::new(Vec::<String>::new());
+        let mut monitor =
+            SimpleMonitor::with_user_monitor(|line| lines.borrow_mut().push(line.to_string()));
+
+        for (id, label, map_size) in [(0, "asan", 1024), (1, "cmplog", 2048)] {
+            let fingerprint = CampaignFingerprint::new().with_map_size(map_size);
+            let mut firer = RecordingEventFirer::new();
+            firer.set_client_label(&mut state, label).unwrap();
+            firer
+                .set_client_fingerprint(&mut state, &fingerprint)
+                .unwrap();
+
+            let stats: Vec<_> = firer
+                .events
+                .iter()
+                .map(|event| match event {
+                    Event::UpdateUserStats { name, value, .. } => {
+                        (name.to_string(), value.value().to_string())
+                    }
+                    event => panic!("Unexpected event {}", event.name()),
+                })
+                .collect();
+            assert_eq!(
+                stats,
+                [
+                    (CLIENT_LABEL_STAT.to_string(), label.to_string()),
+                    (CLIENT_FINGERPRINT_STAT.to_string(), fingerprint.to_string())
+                ]
+            );
+
+            for event in &firer.events {
+                StdLlmpEventHook::<BytesInput, _>::handle_in_broker(
+                    &mut monitor,
+                    ClientId(id),
+                    event,
+                )
+                .unwrap();
+            }
+        }
+
+        // The monitor names the client by its label, and shows the drift from the first client
+        let lines = lines.borrow();
+        let last = lines.last().unwrap();
+        assert!(last.starts_with("[UserStats #1 (cmplog)]"), "{last}");
+        assert!(
+            last.ends_with("config_drift: map size differ from #0 (asan)"),
+            "{last}"
+        );
+    }
+}
@@ -13,7 +13,7 @@ use std::{marker::PhantomData, process};
 
 #[cfg(feature = "llmp_compression")]
 use libafl_bolts::{
-    compress::GzipCompressor,
+    compress::Compressor,
     llmp::{LLMP_FLAG_COMPRESSED, LLMP_FLAG_INITIALIZED},
 };
 use libafl_bolts::{
@@ -58,7 +58,7 @@ where
     /// The centralized LLMP client for inter process communication
     client: LlmpClient<SP>,
     #[cfg(feature = "llmp_compression")]
-    compressor: GzipCompressor,
+    compressor: Compressor,
     time_ref: Option<Handle<TimeObserver>>,
     hooks: EMH,
     is_main: bool,
@@ -124,7 +124,7 @@ impl CentralizedEventManagerBuilder {
             hooks,
             client,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
             time_ref: time_obs,
             is_main: self.is_main,
             phantom: PhantomData,
@@ -156,7 +156,7 @@ impl CentralizedEventManagerBuilder {
             hooks,
             client,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
             time_ref: time_obs,
             is_main: self.is_main,
             phantom: PhantomData,
@@ -185,7 +185,7 @@ impl CentralizedEventManagerBuilder {
             hooks,
             client: LlmpClient::on_existing_from_env(shmem_provider, env_name)?,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
             time_ref: time_obs,
             is_main: self.is_main,
             phantom: PhantomData,
@@ -213,7 +213,7 @@ impl CentralizedEventManagerBuilder {
             hooks,
             client: LlmpClient::existing_client_from_description(shmem_provider, description)?,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
             time_ref: time_obs,
             is_main: self.is_main,
             phantom: PhantomData,
@@ -433,6 +433,9 @@ where
     S: State,
     SP: ShMemProvider,
 {
+    fn on_shutdown(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.inner.on_shutdown(state)
+    }
 }
 
 impl<EM, EMH, S, SP> HasEventManagerId for CentralizedEventManager<EM, EMH, S, SP>
@@ -466,6 +469,13 @@ where
         self.client.to_env(env_name).unwrap();
     }
 
+    /// The compressor of the events sent to the main node. Select the codec or set a trained
+    /// dictionary at runtime; the receiving side needs the same dictionary.
+    #[cfg(feature = "llmp_compression")]
+    pub fn compressor_mut(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
     /// Know if this instance is main or secondary
     pub fn is_main(&self) -> bool {
         self.is_main
@@ -0,0 +1,248 @@
+//! A/B experiments, comparing two fuzzing pipelines on one machine with one binary.
+//!
+//! The [`Experiment`] splits the cores of a [`crate::events::Launcher`] in two halves: the clients
+//! on the first half run pipeline A, the others pipeline B, each with its own [`EventConfig`].
+//! The [`ExperimentIsolationHook`] drops the testcases of the other pipeline, so both pipelines
+//! evolve their corpus independently, while a shared broker monitors all clients. Wrap the
+//! monitor in an [`ExperimentMonitor`] for comparable statistics and a summary comparison.
+//!
+//! ```rust,ignore
+//! let experiment = Experiment::new(
+//!     &cores,
+//!     EventConfig::from_name("baseline"),
+//!     EventConfig::from_name("cmplog"),
+//! )?;
+//! let monitor = experiment.monitor(MultiMonitor::new(|s| println!("{s}")), "experiment.txt");
+//! let mut launcher = Launcher::builder()
+//!     .shmem_provider(shmem_provider)
+//!     .configuration(EventConfig::from_name("experiment"))
+//!     .client_configurations(experiment.client_configurations())
+//!     .monitor(monitor)
+//!     .run_client(experiment.run_client(run_baseline, run_cmplog))
+//!     .cores(&cores)
+//!     .build();
+//! launcher.launch_with_hooks(tuple_list!(experiment.isolation_hook()))?;
+//! ```
+
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+use std::path::PathBuf;
+
+use libafl_bolts::{
+    core_affinity::{CoreId, Cores},
+    ClientId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{Event, EventConfig, EventManagerHook},
+    monitors::{ExperimentMonitor, Monitor},
+    state::State,
+    Error,
+};
+
+/// The pipeline of this client process, set by [`Experiment::run_client`]; `0` if unknown
+static CLIENT_ARM: AtomicU8 = AtomicU8::new(0);
+
+/// One of the two pipelines of an [`Experiment`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ExperimentArm {
+    /// Pipeline A, usually the baseline
+    A,
+    /// Pipeline B, usually the candidate
+    B,
+}
+
+impl ExperimentArm {
+    /// The pipeline run by this client process, if it was started by [`Experiment::run_client`]
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        match CLIENT_ARM.load(Ordering::Relaxed) {
+            1 => Some(Self::A),
+            2 => Some(Self::B),
+            _ => None,
+        }
+    }
+
+    /// Sets the pipeline run by this client process
+    fn set_current(self) {
+        let value = match self {
+            Self::A => 1,
+            Self::B => 2,
+        };
+        CLIENT_ARM.store(value, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for ExperimentArm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A => write!(f, "A"),
+            Self::B => write!(f, "B"),
+        }
+    }
+}
+
+/// Splits the cores of a launcher between two pipelines, A and B, see the [module
+/// documentation](self)
+#[derive(Debug, Clone)]
+pub struct Experiment {
+    cores_a: Vec<CoreId>,
+    cores_b: Vec<CoreId>,
+    configuration_a: EventConfig,
+    configuration_b: EventConfig,
+}
+
+impl Experiment {
+    /// Creates a new [`Experiment`] running pipeline A on the first half of the cores, and
+    /// pipeline B on the second half. The pipelines need different configurations.
+    pub fn new(
+        cores: &Cores,
+        configuration_a: EventConfig,
+        configuration_b: EventConfig,
+    ) -> Result<Self, Error> {
+        if cores.ids.len() < 2 {
+            return Err(Error::illegal_argument(
+                "An experiment needs at least two cores",
+            ));
+        }
+        if configuration_a.match_with(&configuration_b) {
+            return Err(Error::illegal_argument(
+                "The pipelines of an experiment need different configurations",
+            ));
+        }
+        if cores.ids.len() % 2 != 0 {
+            log::warn!("Odd number of cores, pipeline B runs on one more core than pipeline A");
+        }
+        let (cores_a, cores_b) = cores.ids.split_at(cores.ids.len() / 2);
+        Ok(Self {
+            cores_a: cores_a.to_vec(),
+            cores_b: cores_b.to_vec(),
+            configuration_a,
+            configuration_b,
+        })
+    }
+
+    /// The pipeline run on the core, if the core is part of the experiment
+    #[must_use]
+    pub fn arm_of(&self, core_id: CoreId) -> Option<ExperimentArm> {
+        if self.cores_a.contains(&core_id) {
+            Some(ExperimentArm::A)
+        } else if self.cores_b.contains(&core_id) {
+            Some(ExperimentArm::B)
+        } else {
+            None
+        }
+    }
+
+    /// The cores running the pipeline
+    #[must_use]
+    pub fn cores(&self, arm: ExperimentArm) -> &[CoreId] {
+        match arm {
+            ExperimentArm::A => &self.cores_a,
+            ExperimentArm::B => &self.cores_b,
+        }
+    }
+
+    /// The configuration of the pipeline
+    #[must_use]
+    pub fn configuration(&self, arm: ExperimentArm) -> EventConfig {
+        match arm {
+            ExperimentArm::A => self.configuration_a,
+            ExperimentArm::B => self.configuration_b,
+        }
+    }
+
+    /// The configuration of each core, for [`crate::events::Launcher`]'s `client_configurations`
+    #[must_use]
+    pub fn client_configurations(&self) -> Vec<(CoreId, EventConfig)> {
+        self.cores_a
+            .iter()
+            .map(|core_id| (*core_id, self.configuration_a))
+            .chain(
+                self.cores_b
+                    .iter()
+                    .map(|core_id| (*core_id, self.configuration_b)),
+            )
+            .collect()
+    }
+
+    /// The hook keeping the corpora of both pipelines apart, to pass to the launcher
+    #[must_use]
+    pub fn isolation_hook(&self) -> ExperimentIsolationHook {
+        ExperimentIsolationHook {
+            configuration_a: self.configuration_a,
+            configuration_b: self.configuration_b,
+        }
+    }
+
+    /// Wraps the monitor of the launcher in an [`ExperimentMonitor`], writing the summary
+    /// comparison to the file
+    #[must_use]
+    pub fn monitor<M, P>(&self, base: M, summary_file: P) -> ExperimentMonitor<M>
+    where
+        M: Monitor,
+        P: Into<PathBuf>,
+    {
+        ExperimentMonitor::new(self, base, summary_file)
+    }
+
+    /// Combines the `run_client` closures of both pipelines into the one of the launcher,
+    /// calling the one of the pipeline of the core
+    pub fn run_client<CA, CB, EM, S>(
+        &self,
+        run_a: CA,
+        run_b: CB,
+    ) -> impl FnOnce(Option<S>, EM, CoreId) -> Result<(), Error>
+    where
+        CA: FnOnce(Option<S>, EM, CoreId) -> Result<(), Error>,
+        CB: FnOnce(Option<S>, EM, CoreId) -> Result<(), Error>,
+    {
+        let experiment = self.clone();
+        move |state, mgr, core_id| {
+            let arm = experiment.arm_of(core_id).ok_or_else(|| {
+                Error::illegal_argument(format!("Core {} is not part of the experiment", core_id.0))
+            })?;
+            arm.set_current();
+            log::info!("Client on core {} runs pipeline {arm}", core_id.0);
+            match arm {
+                ExperimentArm::A => run_a(state, mgr, core_id),
+                ExperimentArm::B => run_b(state, mgr, core_id),
+            }
+        }
+    }
+}
+
+/// Drops the testcases of clients running the other pipeline of an [`Experiment`], so both
+/// pipelines evolve their corpus independently
+#[derive(Debug, Clone, Copy)]
+pub struct ExperimentIsolationHook {
+    configuration_a: EventConfig,
+    configuration_b: EventConfig,
+}
+
+impl<S> EventManagerHook<S> for ExperimentIsolationHook
+where
+    S: State,
+{
+    fn pre_exec(
+        &mut self,
+        _state: &mut S,
+        _client_id: ClientId,
+        event: &Event<S::Input>,
+    ) -> Result<bool, Error> {
+        let (Some(arm), Event::NewTestcase { client_config, .. }) =
+            (ExperimentArm::current(), event)
+        else {
+            return Ok(true);
+        };
+        let own = match arm {
+            ExperimentArm::A => self.configuration_a,
+            ExperimentArm::B => self.configuration_b,
+        };
+        Ok(client_config.match_with(&own))
+    }
+}
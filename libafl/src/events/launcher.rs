@@ -12,19 +12,25 @@
 //! On `Unix` systems, the [`Launcher`] will use `fork` if the `fork` feature is used for `LibAFL`.
 //! Else, it will start subsequent nodes with the same commandline, and will set special `env` variables accordingly.
 
-use alloc::string::ToString;
+use alloc::{string::ToString, vec::Vec};
 #[cfg(feature = "std")]
 use core::time::Duration;
 use core::{
     fmt::{self, Debug, Formatter},
     num::NonZeroUsize,
 };
-#[cfg(all(unix, feature = "std", feature = "fork"))]
+#[cfg(feature = "std")]
 use std::boxed::Box;
 #[cfg(feature = "std")]
 use std::net::SocketAddr;
+#[cfg(feature = "std")]
+use std::path::Path;
 #[cfg(all(feature = "std", any(windows, not(feature = "fork"))))]
 use std::process::Stdio;
+#[cfg(feature = "std")]
+use std::string::String;
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+use std::time::Instant;
 #[cfg(all(unix, feature = "std"))]
 use std::{fs::File, os::unix::io::AsRawFd};
 
@@ -45,6 +51,7 @@ use libafl_bolts::{
     core_affinity::{CoreId, Cores},
     shmem::ShMemProvider,
     tuples::{tuple_list, Handle},
+    ErrorFrame,
 };
 #[cfg(feature = "std")]
 use typed_builder::TypedBuilder;
@@ -66,11 +73,15 @@ use crate::state::UsesState;
 #[cfg(feature = "std")]
 use crate::{
     events::{
-        llmp::{LlmpRestartingEventManager, LlmpShouldSaveState, ManagerKind, RestartingMgr},
-        EventConfig,
+        llmp::{
+            LlmpRestartingEventManager, LlmpShouldSaveState, ManagerKind, RestartingMgr,
+            DEFAULT_STATE_RESTORER_SIZE,
+        },
+        set_client_error_frame, EventConfig,
     },
     monitors::Monitor,
     state::{HasExecutions, State},
+    triage::{repro_input_from_env, REPRO_INPUT_ENV},
     Error,
 };
 
@@ -81,9 +92,149 @@ const _AFL_LAUNCHER_CLIENT: &str = "AFL_LAUNCHER_CLIENT";
 #[cfg(all(feature = "fork", unix))]
 const LIBAFL_DEBUG_OUTPUT: &str = "LIBAFL_DEBUG_OUTPUT";
 
+/// The default time the clients get to shut down in an orderly fashion, once the broker exited
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+pub const DEFAULT_CLIENT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How a client saves its state on restart, see [`Launcher`] and [`CentralizedLauncher`].
+///
+/// Clients with very different state sizes can get different policies, e.g. clients only running
+/// tracers may never save their state, while the main node always does.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientStatePolicy {
+    /// Tell the manager to serialize or not the state on restart
+    pub serialize_state: LlmpShouldSaveState,
+    /// The size of the shared map the state is saved to on restart.
+    /// Larger states are written to a temporary file instead.
+    pub state_restorer_size: usize,
+}
+
+#[cfg(feature = "std")]
+impl ClientStatePolicy {
+    /// Creates a new [`ClientStatePolicy`] with the [`DEFAULT_STATE_RESTORER_SIZE`]
+    #[must_use]
+    pub fn new(serialize_state: LlmpShouldSaveState) -> Self {
+        Self {
+            serialize_state,
+            state_restorer_size: DEFAULT_STATE_RESTORER_SIZE,
+        }
+    }
+
+    /// Sets the size of the shared map the state is saved to on restart
+    #[must_use]
+    pub fn with_state_restorer_size(mut self, state_restorer_size: usize) -> Self {
+        self.state_restorer_size = state_restorer_size;
+        self
+    }
+}
+
+/// The policy of the client on `core_id`: its override, or the launcher-wide settings
+#[cfg(feature = "std")]
+fn client_state_policy(
+    overrides: &[(CoreId, ClientStatePolicy)],
+    core_id: CoreId,
+    serialize_state: LlmpShouldSaveState,
+    state_restorer_size: usize,
+) -> ClientStatePolicy {
+    overrides.iter().find(|(id, _)| *id == core_id).map_or(
+        ClientStatePolicy {
+            serialize_state,
+            state_restorer_size,
+        },
+        |(_, policy)| *policy,
+    )
+}
+
+/// Resource limits applied to each client, see [`Launcher`] and [`CentralizedLauncher`].
+///
+/// The limits are applied in the client process right after it was forked (or respawned), before
+/// the client's event manager is set up. Since resource limits are inherited, they also hold for
+/// restarted clients and targets started by the client. Unset limits are left untouched.
+#[cfg(all(unix, feature = "std"))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChildRlimits {
+    /// Enable (`true`, unlimited size) or disable (`false`) core dumps (`RLIMIT_CORE`)
+    pub core_dumps: Option<bool>,
+    /// The maximum number of open file descriptors (`RLIMIT_NOFILE`)
+    pub max_open_files: Option<u64>,
+    /// The maximum size of a file written by the process, in bytes (`RLIMIT_FSIZE`).
+    /// Stops runaway output writers from filling the disk.
+    pub max_file_size: Option<u64>,
+}
+
+#[cfg(all(unix, feature = "std"))]
+impl ChildRlimits {
+    /// Applies these limits to the current process.
+    ///
+    /// Soft limits are capped at the current hard limits, so this also works for unprivileged users.
+    #[allow(trivial_numeric_casts, clippy::unnecessary_cast)] // `rlim_t` is not `u64` everywhere
+    pub fn apply(&self) -> Result<(), Error> {
+        // The type of the `resource` argument differs between platforms, hence the macro.
+        macro_rules! set_rlimit {
+            ($resource:expr, $limit:expr) => {{
+                let mut rlim = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                // # Safety
+                // `getrlimit` only writes to, and `setrlimit` only reads from, the passed struct.
+                unsafe {
+                    if libc::getrlimit($resource, &mut rlim) != 0 {
+                        return Err(Error::last_os_error(concat!(
+                            "Failed to get ",
+                            stringify!($resource)
+                        )));
+                    }
+                    rlim.rlim_cur = if rlim.rlim_max == libc::RLIM_INFINITY {
+                        $limit
+                    } else {
+                        ($limit).min(rlim.rlim_max)
+                    };
+                    if libc::setrlimit($resource, &rlim) != 0 {
+                        return Err(Error::last_os_error(concat!(
+                            "Failed to set ",
+                            stringify!($resource)
+                        )));
+                    }
+                }
+            }};
+        }
+
+        if let Some(core_dumps) = self.core_dumps {
+            set_rlimit!(
+                libc::RLIMIT_CORE,
+                if core_dumps { libc::RLIM_INFINITY } else { 0 }
+            );
+        }
+        if let Some(max_open_files) = self.max_open_files {
+            set_rlimit!(libc::RLIMIT_NOFILE, max_open_files as libc::rlim_t);
+        }
+        if let Some(max_file_size) = self.max_file_size {
+            set_rlimit!(libc::RLIMIT_FSIZE, max_file_size as libc::rlim_t);
+            // Writing past the limit should fail with `EFBIG` instead of killing the client.
+            // # Safety
+            // Ignoring `SIGXFSZ` has no further side effects.
+            unsafe {
+                libc::signal(libc::SIGXFSZ, libc::SIG_IGN);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reproduces a single input instead of fuzzing, see [`Launcher::launch_with_hooks`]
+#[cfg(feature = "std")]
+pub type ReproClientFn<'a> = Box<dyn FnOnce(&Path, CoreId) -> Result<(), Error> + 'a>;
+
 /// Provides a [`Launcher`], which can be used to launch a fuzzing run on a specified list of cores
 ///
 /// Will hide child output, unless the settings indicate otherwise, or the `LIBAFL_DEBUG_OUTPUT` env variable is set.
+///
+/// On ctrl-c, the clients finish their current stage and send their final stats before they exit,
+/// see [`crate::events::request_shutdown`]. Once the broker exited, the clients that are still
+/// running get the `shutdown_timeout` to do so, before they are killed.
+/// A second ctrl-c exits the clients right away.
 #[cfg(feature = "std")]
 #[allow(
     clippy::type_complexity,
@@ -101,6 +252,11 @@ pub struct Launcher<'a, CF, MT, SP> {
     /// The 'main' function to run for each client forked. This probably shouldn't return
     #[builder(default, setter(strip_option))]
     run_client: Option<CF>,
+    /// Reproduces the input in [`REPRO_INPUT_ENV`], if set, instead of fuzzing, e.g. with a
+    /// [`crate::triage::ReproRunner`]. It runs in the current process, on the first core, without
+    /// a broker or any clients.
+    #[builder(default, setter(strip_option))]
+    repro_client: Option<ReproClientFn<'a>>,
     /// The broker port to use (or to attach to, in case [`Self::spawn_broker`] is `false`)
     #[builder(default = 1337_u16)]
     broker_port: u16,
@@ -142,6 +298,33 @@ pub struct Launcher<'a, CF, MT, SP> {
     /// Tell the manager to serialize or not the state on restart
     #[builder(default = LlmpShouldSaveState::OnRestart)]
     serialize_state: LlmpShouldSaveState,
+    /// The size of the shared map the state is saved to on restart.
+    /// Larger states are written to a temporary file instead.
+    #[builder(default = DEFAULT_STATE_RESTORER_SIZE)]
+    state_restorer_size: usize,
+    /// Overrides [`Self::serialize_state`] and [`Self::state_restorer_size`] for the clients on
+    /// specific cores
+    #[builder(default)]
+    client_state_policies: Vec<(CoreId, ClientStatePolicy)>,
+    /// Overrides [`Self::configuration`] for the clients on specific cores, e.g. to run different
+    /// pipelines, see [`crate::events::Experiment`]
+    #[builder(default)]
+    client_configurations: Vec<(CoreId, EventConfig)>,
+    /// Resource limits to apply in each client
+    #[cfg(all(unix, feature = "std"))]
+    #[builder(default)]
+    rlimits: ChildRlimits,
+    /// How long the clients get to shut down in an orderly fashion once the broker exited,
+    /// before they are killed
+    #[cfg(all(unix, feature = "std", feature = "fork"))]
+    #[builder(default = DEFAULT_CLIENT_SHUTDOWN_TIMEOUT)]
+    shutdown_timeout: Duration,
+    /// The directory the clients keep their corpus in, reported once fuzzing stopped
+    #[builder(default = None)]
+    corpus_dir: Option<&'a Path>,
+    /// The directory the clients store their solutions in, reported once fuzzing stopped
+    #[builder(default = None)]
+    solutions_dir: Option<&'a Path>,
 }
 
 impl<CF, MT, SP> Debug for Launcher<'_, CF, MT, SP> {
@@ -157,7 +340,8 @@ impl<CF, MT, SP> Debug for Launcher<'_, CF, MT, SP> {
         {
             dbg_struct
                 .field("stdout_file", &self.stdout_file)
-                .field("stderr_file", &self.stderr_file);
+                .field("stderr_file", &self.stderr_file)
+                .field("rlimits", &self.rlimits);
         }
 
         dbg_struct.finish_non_exhaustive()
@@ -169,6 +353,26 @@ where
     MT: Monitor + Clone,
     SP: ShMemProvider,
 {
+    /// The state policy of the client on the given core
+    #[must_use]
+    pub fn client_state_policy(&self, core_id: CoreId) -> ClientStatePolicy {
+        client_state_policy(
+            &self.client_state_policies,
+            core_id,
+            self.serialize_state,
+            self.state_restorer_size,
+        )
+    }
+
+    /// The configuration of the client on the given core: its override, or the launcher-wide one
+    #[must_use]
+    pub fn client_configuration(&self, core_id: CoreId) -> EventConfig {
+        self.client_configurations
+            .iter()
+            .find(|(id, _)| *id == core_id)
+            .map_or(self.configuration, |(_, configuration)| *configuration)
+    }
+
     /// Launch the broker and the clients and fuzz
     #[cfg(all(unix, feature = "std", feature = "fork"))]
     pub fn launch<S>(&mut self) -> Result<(), Error>
@@ -197,7 +401,22 @@ where
     MT: Monitor + Clone,
     SP: ShMemProvider,
 {
-    /// Launch the broker and the clients and fuzz with a user-supplied hook
+    /// Runs the `repro_client` on the input in [`REPRO_INPUT_ENV`], if set
+    fn repro(&mut self) -> Option<Result<(), Error>> {
+        let path = repro_input_from_env()?;
+        let Some(repro_client) = self.repro_client.take() else {
+            return Some(Err(Error::illegal_argument(format!(
+                "{REPRO_INPUT_ENV} is set, but the launcher has no repro_client"
+            ))));
+        };
+        let core_id = self.cores.ids.first().copied().unwrap_or(CoreId(0));
+        log::info!("Reproducing {} instead of fuzzing", path.display());
+        Some(repro_client(&path, core_id))
+    }
+
+    /// Launch the broker and the clients and fuzz with a user-supplied hook.
+    ///
+    /// If [`REPRO_INPUT_ENV`] is set, this only runs the `repro_client` on that input instead.
     #[cfg(all(unix, feature = "std", feature = "fork"))]
     #[allow(clippy::similar_names)]
     #[allow(clippy::too_many_lines)]
@@ -207,6 +426,10 @@ where
         EMH: EventManagerHooksTuple<S> + Clone + Copy,
         CF: FnOnce(Option<S>, LlmpRestartingEventManager<EMH, S, SP>, CoreId) -> Result<(), Error>,
     {
+        if let Some(res) = self.repro() {
+            return res;
+        }
+
         if self.cores.ids.is_empty() {
             return Err(Error::illegal_argument(
                 "No cores to spawn on given, cannot launch anything.",
@@ -271,15 +494,20 @@ where
                             }
                         }
 
+                        self.rlimits.apply()?;
+                        set_client_error_frame(ErrorFrame::new("client").with_core_id(bind_to.0));
+
                         // Fuzzer client. keeps retrying the connection to broker till the broker starts
+                        let policy = self.client_state_policy(*bind_to);
                         let builder = RestartingMgr::<EMH, MT, S, SP>::builder()
                             .shmem_provider(self.shmem_provider.clone())
                             .broker_port(self.broker_port)
                             .kind(ManagerKind::Client {
                                 cpu_core: Some(*bind_to),
                             })
-                            .configuration(self.configuration)
-                            .serialize_state(self.serialize_state)
+                            .configuration(self.client_configuration(*bind_to))
+                            .serialize_state(policy.serialize_state)
+                            .state_restorer_size(policy.state_restorer_size)
                             .hooks(hooks);
                         let builder = builder.time_ref(self.time_ref.clone());
                         let (state, mgr) = builder.build().launch()?;
@@ -308,16 +536,14 @@ where
 
             let builder = builder.time_ref(self.time_ref.clone());
 
-            builder.build().launch()?;
-
-            // Broker exited. kill all clients.
-            for handle in &handles {
-                // # Safety
-                // Normal libc call, no dereferences whatsoever
-                unsafe {
-                    libc::kill(*handle, libc::SIGINT);
-                }
+            match builder.build().launch() {
+                Ok(_) | Err(Error::ShuttingDown) => {}
+                Err(err) => return Err(err),
             }
+
+            // Broker exited. Stop all clients.
+            teardown_clients(&handles, self.shutdown_timeout);
+            report_output_dirs(self.corpus_dir, self.solutions_dir);
         } else {
             for handle in &handles {
                 let mut status = 0;
@@ -335,6 +561,8 @@ where
     }
 
     /// Launch the broker and the clients and fuzz
+    ///
+    /// If [`REPRO_INPUT_ENV`] is set, this only runs the `repro_client` on that input instead.
     #[cfg(all(feature = "std", any(windows, not(feature = "fork"))))]
     #[allow(unused_mut, clippy::match_wild_err_arm)]
     pub fn launch_with_hooks<EMH, S>(&mut self, hooks: EMH) -> Result<(), Error>
@@ -345,6 +573,10 @@ where
     {
         use libafl_bolts::core_affinity;
 
+        if let Some(res) = self.repro() {
+            return res;
+        }
+
         let is_client = std::env::var(_AFL_LAUNCHER_CLIENT);
 
         let mut handles = match is_client {
@@ -352,14 +584,20 @@ where
                 let core_id = core_conf.parse()?;
                 // the actual client. do the fuzzing
 
+                #[cfg(unix)]
+                self.rlimits.apply()?;
+                set_client_error_frame(ErrorFrame::new("client").with_core_id(core_id));
+
+                let policy = self.client_state_policy(CoreId(core_id));
                 let builder = RestartingMgr::<EMH, MT, S, SP>::builder()
                     .shmem_provider(self.shmem_provider.clone())
                     .broker_port(self.broker_port)
                     .kind(ManagerKind::Client {
                         cpu_core: Some(CoreId(core_id)),
                     })
-                    .configuration(self.configuration)
-                    .serialize_state(self.serialize_state)
+                    .configuration(self.client_configuration(CoreId(core_id)))
+                    .serialize_state(policy.serialize_state)
+                    .state_restorer_size(policy.state_restorer_size)
                     .hooks(hooks);
 
                 let builder = builder.time_ref(self.time_ref.clone());
@@ -458,12 +696,16 @@ where
 
             let builder = builder.time_ref(self.time_ref.clone());
 
-            builder.build().launch()?;
+            match builder.build().launch() {
+                Ok(_) | Err(Error::ShuttingDown) => {}
+                Err(err) => return Err(err),
+            }
 
             //broker exited. kill all clients.
             for handle in &mut handles {
                 handle.kill()?;
             }
+            report_output_dirs(self.corpus_dir, self.solutions_dir);
         } else {
             log::info!("Not spawning broker (spawn_broker is false). Waiting for fuzzer children to exit...");
             for handle in &mut handles {
@@ -478,6 +720,68 @@ where
     }
 }
 
+/// Asks the clients to shut down with `SIGTERM`, which their respawners forward to the fuzzers,
+/// and kills the ones that did not exit within the `timeout`
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+fn teardown_clients(handles: &[libc::pid_t], timeout: Duration) {
+    for handle in handles {
+        // # Safety
+        // Normal libc call, no dereferences whatsoever
+        unsafe {
+            libc::kill(*handle, libc::SIGTERM);
+        }
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut running = handles.to_vec();
+    loop {
+        running.retain(|handle| {
+            let mut status = 0;
+            // # Safety
+            // Normal libc call, `status` is a valid pointer
+            unsafe { libc::waitpid(*handle, &mut status, libc::WNOHANG) == 0 }
+        });
+        if running.is_empty() {
+            break;
+        }
+        if Instant::now() >= deadline {
+            for handle in &running {
+                log::warn!("Client with pid {handle} did not exit within {timeout:?}, killing it");
+                let mut status = 0;
+                // # Safety
+                // Normal libc calls, `status` is a valid pointer
+                unsafe {
+                    libc::kill(*handle, libc::SIGKILL);
+                    libc::waitpid(*handle, &mut status, 0);
+                }
+            }
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Tells the user where the results of the campaign live, once fuzzing stopped
+#[cfg(feature = "std")]
+fn report_output_dirs(corpus_dir: Option<&Path>, solutions_dir: Option<&Path>) {
+    for line in output_dirs_report(corpus_dir, solutions_dir) {
+        log::info!("{line}");
+    }
+}
+
+/// The lines logged by [`report_output_dirs`], one per known output directory
+#[cfg(feature = "std")]
+fn output_dirs_report(corpus_dir: Option<&Path>, solutions_dir: Option<&Path>) -> Vec<String> {
+    let mut lines = Vec::new();
+    if let Some(dir) = corpus_dir {
+        lines.push(format!("[Launcher] Corpus: {}", dir.display()));
+    }
+    if let Some(dir) = solutions_dir {
+        lines.push(format!("[Launcher] Solutions: {}", dir.display()));
+    }
+    lines
+}
+
 /// Provides a Launcher, which can be used to launch a fuzzing run on a specified list of cores with a single main and multiple secondary nodes
 /// This is for centralized, the 4th argument of the closure should mean if this is the main node.
 #[cfg(all(unix, feature = "std", feature = "fork"))]
@@ -543,6 +847,32 @@ pub struct CentralizedLauncher<'a, CF, MF, MT, SP> {
     /// Tell the manager to serialize or not the state on restart
     #[builder(default = LlmpShouldSaveState::OnRestart)]
     serialize_state: LlmpShouldSaveState,
+    /// The size of the shared map the state is saved to on restart.
+    /// Larger states are written to a temporary file instead.
+    #[builder(default = DEFAULT_STATE_RESTORER_SIZE)]
+    state_restorer_size: usize,
+    /// Overrides [`Self::serialize_state`] and [`Self::state_restorer_size`] for the clients on
+    /// specific cores
+    #[builder(default)]
+    client_state_policies: Vec<(CoreId, ClientStatePolicy)>,
+    /// Overrides the state policy of the main node, which usually keeps the largest state
+    #[builder(default = None)]
+    main_state_policy: Option<ClientStatePolicy>,
+    /// Resource limits to apply in each client
+    #[cfg(all(unix, feature = "std"))]
+    #[builder(default)]
+    rlimits: ChildRlimits,
+    /// How long the clients get to shut down in an orderly fashion once the broker exited,
+    /// before they are killed
+    #[cfg(all(unix, feature = "std", feature = "fork"))]
+    #[builder(default = DEFAULT_CLIENT_SHUTDOWN_TIMEOUT)]
+    shutdown_timeout: Duration,
+    /// The directory the clients keep their corpus in, reported once fuzzing stopped
+    #[builder(default = None)]
+    corpus_dir: Option<&'a Path>,
+    /// The directory the clients store their solutions in, reported once fuzzing stopped
+    #[builder(default = None)]
+    solutions_dir: Option<&'a Path>,
 }
 
 #[cfg(all(unix, feature = "std", feature = "fork"))]
@@ -556,6 +886,7 @@ impl<CF, MF, MT, SP> Debug for CentralizedLauncher<'_, CF, MF, MT, SP> {
             .field("remote_broker_addr", &self.remote_broker_addr)
             .field("stdout_file", &self.stdout_file)
             .field("stderr_file", &self.stderr_file)
+            .field("rlimits", &self.rlimits)
             .finish_non_exhaustive()
     }
 }
@@ -585,25 +916,37 @@ where
             CoreId,
         ) -> Result<(), Error>,
     {
-        let restarting_mgr_builder = |centralized_launcher: &Self, core_to_bind: CoreId| {
-            // Fuzzer client. keeps retrying the connection to broker till the broker starts
-            let builder = RestartingMgr::<(), MT, S, SP>::builder()
-                .always_interesting(centralized_launcher.always_interesting)
-                .shmem_provider(centralized_launcher.shmem_provider.clone())
-                .broker_port(centralized_launcher.broker_port)
-                .kind(ManagerKind::Client {
-                    cpu_core: Some(core_to_bind),
-                })
-                .configuration(centralized_launcher.configuration)
-                .serialize_state(centralized_launcher.serialize_state)
-                .hooks(tuple_list!());
-
-            let builder = builder.time_ref(centralized_launcher.time_obs.clone());
-
-            builder.build().launch()
+        let restarting_mgr_builder =
+            |centralized_launcher: &Self, core_to_bind: CoreId, policy: ClientStatePolicy| {
+                // Fuzzer client. keeps retrying the connection to broker till the broker starts
+                let builder = RestartingMgr::<(), MT, S, SP>::builder()
+                    .always_interesting(centralized_launcher.always_interesting)
+                    .shmem_provider(centralized_launcher.shmem_provider.clone())
+                    .broker_port(centralized_launcher.broker_port)
+                    .kind(ManagerKind::Client {
+                        cpu_core: Some(core_to_bind),
+                    })
+                    .configuration(centralized_launcher.configuration)
+                    .serialize_state(policy.serialize_state)
+                    .state_restorer_size(policy.state_restorer_size)
+                    .hooks(tuple_list!());
+
+                let builder = builder.time_ref(centralized_launcher.time_obs.clone());
+
+                builder.build().launch()
+            };
+        let main_mgr_builder = |centralized_launcher: &Self, core_to_bind: CoreId| {
+            let policy = centralized_launcher
+                .main_state_policy
+                .unwrap_or_else(|| centralized_launcher.client_state_policy(core_to_bind));
+            restarting_mgr_builder(centralized_launcher, core_to_bind, policy)
+        };
+        let secondary_mgr_builder = |centralized_launcher: &Self, core_to_bind: CoreId| {
+            let policy = centralized_launcher.client_state_policy(core_to_bind);
+            restarting_mgr_builder(centralized_launcher, core_to_bind, policy)
         };
 
-        self.launch_generic(restarting_mgr_builder, restarting_mgr_builder)
+        self.launch_generic(main_mgr_builder, secondary_mgr_builder)
     }
 }
 
@@ -613,14 +956,26 @@ where
     MT: Monitor + Clone + 'static,
     SP: ShMemProvider + 'static,
 {
+    /// The state policy of the secondary client on the given core.
+    /// The main node uses the `main_state_policy`, if set.
+    #[must_use]
+    pub fn client_state_policy(&self, core_id: CoreId) -> ClientStatePolicy {
+        client_state_policy(
+            &self.client_state_policies,
+            core_id,
+            self.serialize_state,
+            self.state_restorer_size,
+        )
+    }
+
     /// Launch a Centralized-based fuzzer.
     /// - `main_inner_mgr_builder` will be called to build the inner manager of the main node.
     /// - `secondary_inner_mgr_builder` will be called to build the inner manager of the secondary nodes.
     #[allow(clippy::similar_names)]
     #[allow(clippy::too_many_lines)]
-    pub fn launch_generic<EM, EMB, S>(
+    pub fn launch_generic<EM, MEMB, EMB, S>(
         &mut self,
-        main_inner_mgr_builder: EMB,
+        main_inner_mgr_builder: MEMB,
         secondary_inner_mgr_builder: EMB,
     ) -> Result<(), Error>
     where
@@ -628,6 +983,7 @@ where
         S::Input: Send + Sync + 'static,
         CF: FnOnce(Option<S>, CentralizedEventManager<EM, (), S, SP>, CoreId) -> Result<(), Error>,
         EM: UsesState<State = S>,
+        MEMB: FnOnce(&Self, CoreId) -> Result<(Option<S>, EM), Error>,
         EMB: FnOnce(&Self, CoreId) -> Result<(Option<S>, EM), Error>,
         MF: FnOnce(
             Option<S>,
@@ -696,6 +1052,8 @@ where
                             }
                         }
 
+                        self.rlimits.apply()?;
+
                         if index == 1 {
                             // Main client
                             log::debug!("Running main client on PID {}", std::process::id());
@@ -828,13 +1186,140 @@ where
         #[cfg(feature = "llmp_debug")]
         log::info!("The last client quit. Exiting.");
 
-        // Brokers exited. kill all clients.
-        for handle in &handles {
-            unsafe {
-                libc::kill(*handle, libc::SIGINT);
+        // Brokers exited. Stop all clients.
+        teardown_clients(&handles, self.shutdown_timeout);
+        report_output_dirs(self.corpus_dir, self.solutions_dir);
+
+        Err(Error::shutting_down())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+    #[cfg(all(unix, feature = "fork"))]
+    use core::time::Duration;
+    #[cfg(all(unix, feature = "fork"))]
+    use std::time::Instant;
+    use std::{
+        boxed::Box,
+        env,
+        path::{Path, PathBuf},
+    };
+
+    use libafl_bolts::{
+        core_affinity::{CoreId, Cores},
+        rands::StdRand,
+        shmem::{ShMemProvider, StdShMemProvider},
+    };
+    use serial_test::serial;
+
+    #[cfg(all(unix, feature = "fork"))]
+    use super::teardown_clients;
+    use super::{output_dirs_report, Launcher};
+    use crate::{
+        corpus::InMemoryCorpus,
+        events::{EventConfig, LlmpRestartingEventManager},
+        inputs::BytesInput,
+        monitors::NopMonitor,
+        state::StdState,
+        triage::REPRO_INPUT_ENV,
+        Error,
+    };
+
+    type TestState =
+        StdState<BytesInput, InMemoryCorpus<BytesInput>, StdRand, InMemoryCorpus<BytesInput>>;
+
+    #[test]
+    #[serial]
+    fn test_launcher_repro_bypass() {
+        let cores = Cores::from_cmdline("2").unwrap();
+        let reproduced = Cell::new(None);
+        env::set_var(REPRO_INPUT_ENV, "crash");
+        let res = Launcher::builder()
+            .shmem_provider(StdShMemProvider::new().unwrap())
+            .monitor(NopMonitor::new())
+            .configuration(EventConfig::AlwaysUnique)
+            .run_client(
+                |_: Option<TestState>,
+                 _: LlmpRestartingEventManager<(), TestState, StdShMemProvider>,
+                 _: CoreId|
+                 -> Result<(), Error> { panic!("No client may run") },
+            )
+            .repro_client(Box::new(|path: &Path, core_id| {
+                reproduced.set(Some((path.to_path_buf(), core_id)));
+                Ok(())
+            }))
+            .cores(&cores)
+            .build()
+            .launch::<TestState>();
+        env::remove_var(REPRO_INPUT_ENV);
+
+        res.unwrap();
+        assert_eq!(reproduced.take(), Some((PathBuf::from("crash"), CoreId(2))));
+    }
+    #[test]
+    fn test_output_dirs_report() {
+        assert!(output_dirs_report(None, None).is_empty());
+        assert_eq!(
+            output_dirs_report(None, Some(Path::new("./crashes"))),
+            ["[Launcher] Solutions: ./crashes"]
+        );
+        assert_eq!(
+            output_dirs_report(Some(Path::new("./corpus")), Some(Path::new("./crashes"))),
+            [
+                "[Launcher] Corpus: ./corpus",
+                "[Launcher] Solutions: ./crashes"
+            ]
+        );
+    }
+    /// Forks a child that sleeps until it is killed
+    #[cfg(all(unix, feature = "fork"))]
+    fn sleeping_child(ignore_sigterm: bool) -> libc::pid_t {
+        use nix::{
+            sys::signal::{signal, SigHandler, Signal},
+            unistd::{fork, ForkResult},
+        };
+
+        // Changed before forking, so that the child cannot get the signal before ignoring it
+        let handler = if ignore_sigterm {
+            SigHandler::SigIgn
+        } else {
+            SigHandler::SigDfl
+        };
+        // # Safety
+        // No handler functions are installed, and the child only sleeps
+        unsafe {
+            let prev = signal(Signal::SIGTERM, handler).unwrap();
+            match fork().unwrap() {
+                ForkResult::Child => loop {
+                    std::thread::sleep(Duration::from_secs(1));
+                },
+                ForkResult::Parent { child } => {
+                    signal(Signal::SIGTERM, prev).unwrap();
+                    child.as_raw()
+                }
             }
         }
+    }
 
-        Err(Error::shutting_down())
+    #[test]
+    #[cfg(all(unix, feature = "fork"))]
+    fn test_teardown_clients() {
+        let timeout = Duration::from_millis(500);
+
+        // Clients exiting on `SIGTERM` are not waited for
+        let start = Instant::now();
+        teardown_clients(&[sleeping_child(false), sleeping_child(false)], timeout);
+        assert!(start.elapsed() < timeout);
+
+        // Clients ignoring it are killed once the timeout passed
+        let start = Instant::now();
+        let stuck = sleeping_child(true);
+        teardown_clients(&[sleeping_child(false), stuck], timeout);
+        assert!(start.elapsed() >= timeout);
+        // # Safety
+        // Only checks that the child is gone, and was reaped
+        assert_eq!(unsafe { libc::kill(stuck, 0) }, -1);
     }
 }
@@ -10,12 +10,15 @@ use std::net::TcpStream;
 
 #[cfg(feature = "llmp_compression")]
 use libafl_bolts::{
-    compress::GzipCompressor,
+    compress::Compressor,
     llmp::{LLMP_FLAG_COMPRESSED, LLMP_FLAG_INITIALIZED},
 };
 use libafl_bolts::{
     current_time,
-    llmp::{LlmpClient, LlmpClientDescription},
+    llmp::{
+        Flags, LlmpClient, LlmpClientDescription, LlmpPeers, Tag, LLMP_FLAG_FROM_B2B,
+        LLMP_TAG_PEER_ANNOUNCE,
+    },
     shmem::{NopShMemProvider, ShMemProvider},
     tuples::Handle,
     ClientId,
@@ -31,7 +34,10 @@ use serde::{Deserialize, Serialize};
 use crate::events::llmp::COMPRESS_THRESHOLD;
 use crate::{
     events::{
-        llmp::{LLMP_TAG_EVENT_TO_BOTH, _LLMP_TAG_EVENT_TO_BROKER},
+        llmp::{
+            batched_events, EventBatcher, EventBatching, _LLMP_TAG_EVENT_TO_BROKER,
+            LLMP_TAG_EVENT_BATCH, LLMP_TAG_EVENT_TO_BOTH,
+        },
         AdaptiveSerializer, CustomBufEventResult, CustomBufHandlerFn, Event, EventConfig,
         EventFirer, EventManager, EventManagerHooksTuple, EventManagerId, EventProcessor,
         EventRestarter, HasCustomBufHandlers, HasEventManagerId, ProgressReporter,
@@ -60,10 +66,14 @@ where
     hooks: EMH,
     /// The LLMP client for inter process communication
     llmp: LlmpClient<SP>,
+    /// Direct channels to the other clients on this host, if enabled
+    peers: Option<LlmpPeers<SP>>,
     /// The custom buf handler
     custom_buf_handlers: Vec<Box<CustomBufHandlerFn<S>>>,
     #[cfg(feature = "llmp_compression")]
-    compressor: GzipCompressor,
+    compressor: Compressor,
+    /// The testcase events batched during discovery bursts, if enabled
+    batcher: Option<EventBatcher>,
     /// The configuration defines this specific fuzzer.
     /// A node will not re-use the observer values sent over LLMP
     /// from nodes with other configurations.
@@ -90,6 +100,7 @@ pub struct LlmpEventManagerBuilder<EMH> {
     throttle: Option<Duration>,
     hooks: EMH,
     always_interesting: bool,
+    batching: Option<EventBatching>,
 }
 
 impl Default for LlmpEventManagerBuilder<()> {
@@ -106,6 +117,7 @@ impl LlmpEventManagerBuilder<()> {
             throttle: None,
             hooks: (),
             always_interesting: false,
+            batching: None,
         }
     }
 
@@ -115,6 +127,7 @@ impl LlmpEventManagerBuilder<()> {
             throttle: self.throttle,
             hooks,
             always_interesting: self.always_interesting,
+            batching: self.batching,
         }
     }
 
@@ -125,6 +138,7 @@ impl LlmpEventManagerBuilder<()> {
             throttle: self.throttle,
             hooks: self.hooks,
             always_interesting,
+            batching: self.batching,
         }
     }
 }
@@ -137,6 +151,13 @@ impl<EMH> LlmpEventManagerBuilder<EMH> {
         self
     }
 
+    /// Batch testcase events during discovery bursts, see [`EventBatching`]
+    #[must_use]
+    pub fn batching(mut self, batching: Option<EventBatching>) -> Self {
+        self.batching = batching;
+        self
+    }
+
     /// Create a manager from a raw LLMP client
     pub fn build_from_client<S, SP>(
         self,
@@ -154,8 +175,10 @@ impl<EMH> LlmpEventManagerBuilder<EMH> {
             hooks: self.hooks,
             always_interesting: self.always_interesting,
             llmp,
+            peers: None,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
+            batcher: self.batching.map(EventBatcher::new),
             configuration,
             serialization_time: Duration::ZERO,
             deserialization_time: Duration::ZERO,
@@ -190,8 +213,10 @@ impl<EMH> LlmpEventManagerBuilder<EMH> {
             hooks: self.hooks,
             always_interesting: self.always_interesting,
             llmp,
+            peers: None,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
+            batcher: self.batching.map(EventBatcher::new),
             configuration,
             serialization_time: Duration::ZERO,
             deserialization_time: Duration::ZERO,
@@ -224,8 +249,10 @@ impl<EMH> LlmpEventManagerBuilder<EMH> {
             hooks: self.hooks,
             always_interesting: self.always_interesting,
             llmp,
+            peers: None,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
+            batcher: self.batching.map(EventBatcher::new),
             configuration,
             serialization_time: Duration::ZERO,
             deserialization_time: Duration::ZERO,
@@ -256,8 +283,10 @@ impl<EMH> LlmpEventManagerBuilder<EMH> {
             hooks: self.hooks,
             always_interesting: self.always_interesting,
             llmp,
+            peers: None,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
+            batcher: self.batching.map(EventBatcher::new),
             configuration,
             serialization_time: Duration::ZERO,
             deserialization_time: Duration::ZERO,
@@ -373,6 +402,86 @@ where
         Ok(())
     }
 
+    /// Opens a direct channel to the other clients on this host, bypassing the broker,
+    /// and announces it to them. Use it for high-volume collaboration data, such as cmplog traces,
+    /// with [`Self::send_to_peers`] and [`Self::recv_from_peers`].
+    ///
+    /// Peers only learn about each other while [`EventProcessor::process`] runs.
+    /// After a restart, the channel has to be enabled again.
+    pub fn enable_peer_channels(&mut self, shmem_provider: SP) -> Result<(), Error> {
+        let peers = LlmpPeers::new(shmem_provider, self.llmp.sender().id())?;
+        peers.announce(&mut self.llmp)?;
+        self.peers = Some(peers);
+        Ok(())
+    }
+
+    /// The direct channels to the other clients on this host, if enabled
+    #[must_use]
+    pub fn peers(&self) -> Option<&LlmpPeers<SP>> {
+        self.peers.as_ref()
+    }
+
+    /// Sends a `buf` directly to all other clients on this host that enabled peer channels.
+    pub fn send_to_peers(&mut self, tag: Tag, buf: &[u8]) -> Result<(), Error> {
+        match &mut self.peers {
+            Some(peers) => peers.send_buf(tag, buf),
+            None => Err(Error::illegal_state(
+                "Peer channels are not enabled, call enable_peer_channels first",
+            )),
+        }
+    }
+
+    /// Receives the next message sent directly by another client on this host, if any.
+    #[allow(clippy::type_complexity)]
+    pub fn recv_from_peers(&mut self) -> Result<Option<(ClientId, Tag, &[u8])>, Error> {
+        match &mut self.peers {
+            Some(peers) => peers.recv_buf(),
+            None => Ok(None),
+        }
+    }
+
+    /// Handles the announcement of a peer channel received from the broker
+    fn on_peer_announcement(&mut self, flags: Flags, buf: &[u8]) -> Result<(), Error> {
+        // Peer maps forwarded from other brokers live on another host
+        if flags & LLMP_FLAG_FROM_B2B == LLMP_FLAG_FROM_B2B {
+            return Ok(());
+        }
+        if let Some(peers) = &mut self.peers {
+            if peers.on_announcement(buf)? {
+                peers.announce(&mut self.llmp)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The compressor of outgoing events. Select the codec or set a trained dictionary at runtime;
+    /// the broker and all other clients need the same dictionary to decompress the events.
+    #[cfg(feature = "llmp_compression")]
+    pub fn compressor_mut(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
+    /// Sends serialized events, compressed if worthwhile
+    fn send_events_buf(&mut self, tag: Tag, buf: &[u8]) -> Result<(), Error> {
+        #[cfg(feature = "llmp_compression")]
+        if let Some(comp_buf) = self.compressor.maybe_compress(buf) {
+            return self.llmp.send_buf_with_flags(
+                tag,
+                LLMP_FLAG_INITIALIZED | LLMP_FLAG_COMPRESSED,
+                &comp_buf,
+            );
+        }
+        self.llmp.send_buf(tag, buf)
+    }
+
+    /// Sends the batched testcase events right away, if any, see [`EventBatching`]
+    pub fn flush_batch(&mut self) -> Result<(), Error> {
+        if let Some(batch) = self.batcher.as_mut().and_then(EventBatcher::take) {
+            self.send_events_buf(LLMP_TAG_EVENT_BATCH, &batch)?;
+        }
+        Ok(())
+    }
+
     /// Describe the client event manager's LLMP parts in a restorable fashion
     pub fn describe(&self) -> Result<LlmpClientDescription, Error> {
         self.llmp.describe()
@@ -512,40 +621,34 @@ where
         }
     }
 
-    #[cfg(feature = "llmp_compression")]
     fn fire(
         &mut self,
         _state: &mut Self::State,
         event: Event<<Self::State as UsesInput>::Input>,
     ) -> Result<(), Error> {
         let serialized = postcard::to_allocvec(&event)?;
-        let flags = LLMP_FLAG_INITIALIZED;
-
-        match self.compressor.maybe_compress(&serialized) {
-            Some(comp_buf) => {
-                self.llmp.send_buf_with_flags(
-                    LLMP_TAG_EVENT_TO_BOTH,
-                    flags | LLMP_FLAG_COMPRESSED,
-                    &comp_buf,
-                )?;
-            }
+        let now = current_time();
+        let backpressure = self.llmp.sender().pending_pages() > 1;
+        let is_testcase = matches!(event, Event::NewTestcase { .. });
+        // `Some(full)` if the event was batched
+        let batched = match &mut self.batcher {
+            Some(batcher) if is_testcase => batcher.should_batch(now, backpressure).then(|| {
+                batcher.push(now, &serialized);
+                batcher.is_full()
+            }),
+            _ => None,
+        };
+        match batched {
+            Some(true) => self.flush_batch()?,
+            Some(false) => {}
             None => {
-                self.llmp.send_buf(LLMP_TAG_EVENT_TO_BOTH, &serialized)?;
+                // Keep the order of the events
+                self.flush_batch()?;
+                self.send_events_buf(LLMP_TAG_EVENT_TO_BOTH, &serialized)?;
             }
         }
-        self.last_sent = current_time();
-
-        Ok(())
-    }
+        self.last_sent = now;
 
-    #[cfg(not(feature = "llmp_compression"))]
-    fn fire(
-        &mut self,
-        _state: &mut Self::State,
-        event: Event<<Self::State as UsesInput>::Input>,
-    ) -> Result<(), Error> {
-        let serialized = postcard::to_allocvec(&event)?;
-        self.llmp.send_buf(LLMP_TAG_EVENT_TO_BOTH, &serialized)?;
         Ok(())
     }
 
@@ -575,6 +678,9 @@ where
     /// The LLMP client needs to wait until a broker has mapped all pages before shutting down.
     /// Otherwise, the OS may already have removed the shared maps.
     fn await_restart_safe(&mut self) {
+        if let Err(err) = self.flush_batch() {
+            log::error!("Failed to send the batched events: {err}");
+        }
         // wait until we can drop the message safely.
         self.llmp.await_safe_to_unmap_blocking();
     }
@@ -599,6 +705,13 @@ where
     ) -> Result<usize, Error> {
         // TODO: Get around local event copy by moving handle_in_client
         let self_id = self.llmp.sender().id();
+        if self
+            .batcher
+            .as_ref()
+            .is_some_and(|batcher| batcher.is_due(current_time()))
+        {
+            self.flush_batch()?;
+        }
         let mut count = 0;
         while let Some((client_id, tag, _flags, msg)) = self.llmp.recv_buf_with_flags()? {
             assert!(
@@ -609,6 +722,11 @@ where
             if client_id == self_id {
                 continue;
             }
+            if tag == LLMP_TAG_PEER_ANNOUNCE {
+                let announcement = msg.to_vec();
+                self.on_peer_announcement(_flags, &announcement)?;
+                continue;
+            }
             #[cfg(not(feature = "llmp_compression"))]
             let event_bytes = msg;
             #[cfg(feature = "llmp_compression")]
@@ -620,6 +738,16 @@ where
             } else {
                 msg
             };
+            if tag == LLMP_TAG_EVENT_BATCH {
+                let batch = event_bytes.to_vec();
+                for event_bytes in batched_events(&batch) {
+                    let event: Event<S::Input> = postcard::from_bytes(event_bytes?)?;
+                    log::debug!("Received batched event {}", event.name_detailed());
+                    self.handle_in_client(fuzzer, executor, state, client_id, event)?;
+                    count += 1;
+                }
+                continue;
+            }
             let event: Event<S::Input> = postcard::from_bytes(event_bytes)?;
             log::debug!("Received event in normal llmp {}", event.name_detailed());
             self.handle_in_client(fuzzer, executor, state, client_id, event)?;
@@ -3,9 +3,11 @@
 use alloc::{boxed::Box, vec::Vec};
 use core::{marker::PhantomData, time::Duration};
 
+use hashbrown::HashSet;
+
 #[cfg(feature = "llmp_compression")]
 use libafl_bolts::{
-    compress::GzipCompressor,
+    compress::Compressor,
     llmp::{LLMP_FLAG_COMPRESSED, LLMP_FLAG_INITIALIZED},
 };
 use libafl_bolts::{
@@ -43,11 +45,134 @@ pub(crate) const _LLMP_TAG_EVENT_TO_BROKER: Tag = Tag(0x2B80438);
 pub(crate) const LLMP_TAG_EVENT_TO_BOTH: Tag = Tag(0x2B0741);
 pub(crate) const _LLMP_TAG_RESTART: Tag = Tag(0x8357A87);
 pub(crate) const _LLMP_TAG_NO_RESTART: Tag = Tag(0x57A7EE71);
+/// Several events in one message, see [`EventBatching`]
+pub(crate) const LLMP_TAG_EVENT_BATCH: Tag = Tag(0xBA7C4ED);
 
 /// The minimum buffer size at which to compress LLMP IPC messages.
 #[cfg(feature = "llmp_compression")]
 pub const COMPRESS_THRESHOLD: usize = 1024;
 
+/// Batching of testcase events during discovery bursts, e.g. after importing seeds.
+///
+/// While more than `burst_threshold` testcases are found within `window`, or while the broker
+/// lags behind reading our messages, the [`LlmpEventManager`] combines them into one message
+/// with a length header per event, instead of sending one message each.
+/// The batch is sent once it holds `max_items` events or `max_bytes` bytes, when another event is
+/// fired, or when it is older than `window`. Receivers process the events of a batch one by one.
+///
+/// Batches are only understood by [`LlmpEventManager`]s and the [`crate::events::StdLlmpEventHook`]
+/// of the broker, so don't use them with centralized or multi-machine setups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventBatching {
+    /// The number of testcases within `window` above which testcases are batched
+    pub burst_threshold: usize,
+    /// The window in which testcases are counted, also the maximum age of a batch
+    pub window: Duration,
+    /// The maximum number of events in a batch
+    pub max_items: usize,
+    /// The maximum size of a batch in bytes
+    pub max_bytes: usize,
+}
+
+impl Default for EventBatching {
+    fn default() -> Self {
+        Self {
+            burst_threshold: 16,
+            window: Duration::from_secs(1),
+            max_items: 64,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// The testcase events batched by a [`LlmpEventManager`]
+#[derive(Debug, Clone)]
+pub(crate) struct EventBatcher {
+    config: EventBatching,
+    buf: Vec<u8>,
+    items: usize,
+    started: Duration,
+    window_start: Duration,
+    window_events: usize,
+}
+
+impl EventBatcher {
+    pub(crate) fn new(config: EventBatching) -> Self {
+        Self {
+            config,
+            buf: Vec::new(),
+            items: 0,
+            started: Duration::ZERO,
+            window_start: Duration::ZERO,
+            window_events: 0,
+        }
+    }
+
+    /// Counts a testcase event and returns if it should be batched
+    pub(crate) fn should_batch(&mut self, now: Duration, backpressure: bool) -> bool {
+        if now.saturating_sub(self.window_start) > self.config.window {
+            self.window_start = now;
+            self.window_events = 0;
+        }
+        self.window_events += 1;
+        backpressure || self.window_events > self.config.burst_threshold || self.items > 0
+    }
+
+    /// Adds a serialized event to the batch
+    pub(crate) fn push(&mut self, now: Duration, event: &[u8]) {
+        if self.items == 0 {
+            self.started = now;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        self.buf
+            .extend_from_slice(&(event.len() as u32).to_le_bytes());
+        self.buf.extend_from_slice(event);
+        self.items += 1;
+    }
+
+    /// If the batch reached its maximum size
+    pub(crate) fn is_full(&self) -> bool {
+        self.items >= self.config.max_items || self.buf.len() >= self.config.max_bytes
+    }
+
+    /// If the batch is older than the window
+    pub(crate) fn is_due(&self, now: Duration) -> bool {
+        self.items > 0 && now.saturating_sub(self.started) >= self.config.window
+    }
+
+    /// Takes the batch, if not empty
+    pub(crate) fn take(&mut self) -> Option<Vec<u8>> {
+        if self.items == 0 {
+            return None;
+        }
+        self.items = 0;
+        Some(core::mem::take(&mut self.buf))
+    }
+}
+
+/// Iterates over the serialized events of a batch
+pub(crate) fn batched_events(batch: &[u8]) -> impl Iterator<Item = Result<&[u8], Error>> {
+    let mut rest = batch;
+    core::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        if rest.len() < 4 {
+            rest = &[];
+            return Some(Err(Error::illegal_state("Truncated event batch header")));
+        }
+        let (len, tail) = rest.split_at(4);
+        let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+        if tail.len() < len {
+            rest = &[];
+            return Some(Err(Error::illegal_state("Truncated event in batch")));
+        }
+        let (event, tail) = tail.split_at(len);
+        rest = tail;
+        Some(Ok(event))
+    })
+}
+
 /// Specify if the State must be persistent over restarts
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LlmpShouldSaveState {
@@ -98,9 +223,11 @@ where
     /// The custom buf handler
     custom_buf_handlers: Vec<Box<CustomBufHandlerFn<S>>>,
     #[cfg(feature = "llmp_compression")]
-    compressor: GzipCompressor,
+    compressor: Compressor,
     converter: Option<IC>,
     converter_back: Option<ICB>,
+    /// Clients whose testcases are not converted back
+    ignored_clients: HashSet<ClientId>,
     phantom: PhantomData<S>,
 }
 
@@ -160,9 +287,10 @@ impl LlmpEventConverterBuilder {
             last_sent: Duration::from_secs(0),
             llmp,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
             converter,
             converter_back,
+            ignored_clients: HashSet::new(),
             phantom: PhantomData,
             custom_buf_handlers: vec![],
         })
@@ -190,9 +318,10 @@ impl LlmpEventConverterBuilder {
             last_sent: Duration::from_secs(0),
             llmp,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
             converter,
             converter_back,
+            ignored_clients: HashSet::new(),
             phantom: PhantomData,
             custom_buf_handlers: vec![],
         })
@@ -220,9 +349,10 @@ impl LlmpEventConverterBuilder {
             last_sent: Duration::from_secs(0),
             llmp,
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::with_threshold(COMPRESS_THRESHOLD),
+            compressor: Compressor::with_threshold(COMPRESS_THRESHOLD),
             converter,
             converter_back,
+            ignored_clients: HashSet::new(),
             phantom: PhantomData,
             custom_buf_handlers: vec![],
         })
@@ -246,6 +376,7 @@ where
         debug
             .field("converter", &self.converter)
             .field("converter_back", &self.converter_back)
+            .field("ignored_clients", &self.ignored_clients)
             .field("phantom", &self.phantom)
             .finish_non_exhaustive()
     }
@@ -271,6 +402,30 @@ where
         self.converter_back.is_some()
     }
 
+    /// Sets whether testcases of the client are converted back and evaluated, e.g. to only
+    /// import from the nodes of a heterogeneous campaign whose inputs are worth parsing.
+    /// Testcases of all clients are converted by default.
+    pub fn set_convert_from(&mut self, client_id: ClientId, convert: bool) {
+        if convert {
+            self.ignored_clients.remove(&client_id);
+        } else {
+            self.ignored_clients.insert(client_id);
+        }
+    }
+
+    /// Checks if testcases of the client are converted back
+    #[must_use]
+    pub fn converts_from(&self, client_id: ClientId) -> bool {
+        !self.ignored_clients.contains(&client_id)
+    }
+
+    /// The compressor of outgoing events. Select the codec or set a trained
+    /// dictionary at runtime; the receiving side needs the same dictionary.
+    #[cfg(feature = "llmp_compression")]
+    pub fn compressor_mut(&mut self) -> &mut Compressor {
+        &mut self.compressor
+    }
+
     /// Describe the client event mgr's llmp parts in a restorable fashion
     pub fn describe(&self) -> Result<LlmpClientDescription, Error> {
         self.llmp.describe()
@@ -304,16 +459,23 @@ where
             } => {
                 log::debug!("Received new Testcase to convert from {client_id:?} (forward {forward_id:?}, forward {forward_id:?})");
 
+                if self.ignored_clients.contains(&client_id) {
+                    return Ok(());
+                }
                 let Some(converter) = self.converter_back.as_mut() else {
                     return Ok(());
                 };
+                // Inputs of other nodes may not be convertible, e.g. bytes the grammar can't parse
+                let input = match converter.convert(input) {
+                    Ok(input) => input,
+                    Err(err) => {
+                        log::debug!("Dropping Testcase from {client_id:?}: {err}");
+                        return Ok(());
+                    }
+                };
 
                 let res = fuzzer.evaluate_input_with_observers::<E, EM>(
-                    state,
-                    executor,
-                    manager,
-                    converter.convert(input)?,
-                    false,
+                    state, executor, manager, input, false,
                 )?;
 
                 if let Some(item) = res.1 {
@@ -515,3 +677,36 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::time::Duration;
+
+    use super::{batched_events, EventBatcher, EventBatching};
+
+    #[test]
+    fn test_event_batching() {
+        let mut batcher = EventBatcher::new(EventBatching {
+            burst_threshold: 2,
+            window: Duration::from_secs(1),
+            max_items: 3,
+            max_bytes: 1024,
+        });
+        let now = Duration::from_secs(10);
+        assert!(!batcher.should_batch(now, false));
+        assert!(!batcher.should_batch(now, false));
+        assert!(batcher.should_batch(now, false));
+        batcher.push(now, b"first");
+        assert!(batcher.should_batch(now, false));
+        batcher.push(now, b"");
+        assert!(!batcher.is_full());
+        assert!(batcher.is_due(now + Duration::from_secs(1)));
+
+        let batch = batcher.take().unwrap();
+        let events: Vec<_> = batched_events(&batch).map(Result::unwrap).collect();
+        assert_eq!(events, [&b"first"[..], &b""[..]]);
+        assert!(batcher.take().is_none());
+        assert!(batched_events(&batch[..batch.len() - 6]).any(|event| event.is_err()));
+    }
+}
@@ -29,21 +29,31 @@ use libafl_bolts::{
 };
 #[cfg(feature = "std")]
 use libafl_bolts::{
-    llmp::LlmpConnection, os::CTRL_C_EXIT, shmem::StdShMemProvider, staterestore::StateRestorer,
+    llmp::{LlmpConnection, Tag},
+    os::CTRL_C_EXIT,
+    shmem::StdShMemProvider,
+    staterestore::StateRestorer,
+    ClientId,
 };
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "std")]
 use typed_builder::TypedBuilder;
 
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+use crate::events::forward_sigterm_to;
+#[cfg(all(unix, feature = "std"))]
+use crate::events::note_sigint;
+#[cfg(feature = "std")]
+use crate::events::shutdown_requested;
 #[cfg(feature = "std")]
 use crate::events::AdaptiveSerializer;
 #[cfg(all(unix, feature = "std", not(miri)))]
 use crate::events::EVENTMGR_SIGHANDLER_STATE;
 use crate::{
     events::{
-        Event, EventConfig, EventFirer, EventManager, EventManagerHooksTuple, EventManagerId,
-        EventProcessor, EventRestarter, HasEventManagerId, LlmpEventManager, LlmpShouldSaveState,
-        ProgressReporter, StdLlmpEventHook,
+        Event, EventBatching, EventConfig, EventFirer, EventManager, EventManagerHooksTuple,
+        EventManagerId, EventProcessor, EventRestarter, HasEventManagerId, LlmpEventManager,
+        LlmpShouldSaveState, ProgressReporter, StdLlmpEventHook,
     },
     executors::{Executor, HasObservers},
     fuzzer::{Evaluator, EvaluatorObservers, ExecutionProcessor},
@@ -54,6 +64,11 @@ use crate::{
     Error, HasMetadata,
 };
 
+/// The default size of the shared map a restarting client saves its state to, see
+/// [`RestartingMgr`]. Larger states are written to a temporary file instead.
+#[cfg(feature = "std")]
+pub const DEFAULT_STATE_RESTORER_SIZE: usize = 256 * 1024 * 1024;
+
 /// A manager that can restart on the fly, storing states in-between (in `on_restart`)
 #[cfg(feature = "std")]
 #[derive(Debug)]
@@ -123,6 +138,10 @@ where
     S: State + HasExecutions + HasMetadata + HasLastReportTime,
     SP: ShMemProvider,
 {
+    fn on_shutdown(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.report_progress(state)?;
+        self.send_exiting()
+    }
 }
 
 #[cfg(feature = "std")]
@@ -290,6 +309,22 @@ where
         &mut self.staterestorer
     }
 
+    /// Opens a direct channel to the other clients on this host, see [`LlmpEventManager::enable_peer_channels`]
+    pub fn enable_peer_channels(&mut self, shmem_provider: SP) -> Result<(), Error> {
+        self.llmp_mgr.enable_peer_channels(shmem_provider)
+    }
+
+    /// Sends a `buf` directly to all other clients on this host, see [`LlmpEventManager::send_to_peers`]
+    pub fn send_to_peers(&mut self, tag: Tag, buf: &[u8]) -> Result<(), Error> {
+        self.llmp_mgr.send_to_peers(tag, buf)
+    }
+
+    /// Receives the next message sent directly by another client on this host, if any
+    #[allow(clippy::type_complexity)]
+    pub fn recv_from_peers(&mut self) -> Result<Option<(ClientId, Tag, &[u8])>, Error> {
+        self.llmp_mgr.recv_from_peers()
+    }
+
     /// Save LLMP state and empty state in staterestorer
     pub fn intermediate_save(&mut self) -> Result<(), Error> {
         // First, reset the page to 0 so the next iteration can read read from the beginning of this page
@@ -418,6 +453,13 @@ pub struct RestartingMgr<EMH, MT, S, SP> {
     /// Tell the manager to serialize or not the state on restart
     #[builder(default = LlmpShouldSaveState::OnRestart)]
     serialize_state: LlmpShouldSaveState,
+    /// The size of the shared map the state is saved to on restart.
+    /// Larger states are written to a temporary file instead.
+    #[builder(default = DEFAULT_STATE_RESTORER_SIZE)]
+    state_restorer_size: usize,
+    /// Batch testcase events during discovery bursts, see [`EventBatching`]
+    #[builder(default = None)]
+    event_batching: Option<EventBatching>,
     /// The hooks passed to event manager:
     hooks: EMH,
     #[builder(default = None)]
@@ -487,6 +529,7 @@ where
                             let mgr: LlmpEventManager<EMH, S, SP> = LlmpEventManager::builder()
                                 .always_interesting(self.always_interesting)
                                 .hooks(self.hooks)
+                                .batching(self.event_batching)
                                 .build_from_client(
                                     client,
                                     self.configuration,
@@ -513,6 +556,7 @@ where
                     let mgr = LlmpEventManager::builder()
                         .always_interesting(self.always_interesting)
                         .hooks(self.hooks)
+                        .batching(self.event_batching)
                         .build_on_port(
                             self.shmem_provider.clone(),
                             self.broker_port,
@@ -536,11 +580,11 @@ where
             // First, create a channel from the current fuzzer to the next to store state between restarts.
             #[cfg(unix)]
             let staterestorer: StateRestorer<SP> =
-                StateRestorer::new(self.shmem_provider.new_shmem(256 * 1024 * 1024)?);
+                StateRestorer::new(self.shmem_provider.new_shmem(self.state_restorer_size)?);
 
             #[cfg(not(unix))]
             let staterestorer: StateRestorer<SP> =
-                StateRestorer::new(self.shmem_provider.new_shmem(256 * 1024 * 1024)?);
+                StateRestorer::new(self.shmem_provider.new_shmem(self.state_restorer_size)?);
             // Store the information to a map.
             staterestorer.write_to_env(_ENV_FUZZER_SENDER)?;
 
@@ -555,9 +599,8 @@ where
                     self.shmem_provider.pre_fork()?;
                     match unsafe { fork() }? {
                         ForkResult::Parent(handle) => {
-                            unsafe {
-                                libc::signal(libc::SIGINT, libc::SIG_IGN);
-                            }
+                            note_sigint();
+                            forward_sigterm_to(handle.pid);
                             self.shmem_provider.post_fork(false)?;
                             handle.status()
                         }
@@ -573,18 +616,16 @@ where
                     }
                 };
 
-                // If this guy wants to fork, then ignore sigit
-                #[cfg(any(windows, not(feature = "fork")))]
+                // While the child runs, ctrl-c is ignored on Windows, and noted on unix
+                #[cfg(windows)]
                 unsafe {
-                    #[cfg(windows)]
                     libafl_bolts::os::windows_exceptions::signal(
                         libafl_bolts::os::windows_exceptions::SIGINT,
                         libafl_bolts::os::windows_exceptions::sig_ign(),
                     );
-
-                    #[cfg(unix)]
-                    libc::signal(libc::SIGINT, libc::SIG_IGN);
                 }
+                #[cfg(all(unix, not(feature = "fork")))]
+                note_sigint();
 
                 // On Windows (or in any case without fork), we spawn ourself again
                 #[cfg(any(windows, not(feature = "fork")))]
@@ -594,7 +635,10 @@ where
 
                 compiler_fence(Ordering::SeqCst);
 
-                if child_status == CTRL_C_EXIT || staterestorer.wants_to_exit() {
+                if child_status == CTRL_C_EXIT
+                    || staterestorer.wants_to_exit()
+                    || shutdown_requested()
+                {
                     // if ctrl-c is pressed, we end up in this branch
                     if let Err(err) = mgr.detach_from_broker(self.broker_port) {
                         log::error!("Failed to detach from broker: {err}");
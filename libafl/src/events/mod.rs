@@ -6,11 +6,17 @@ pub use events_hooks::*;
 
 pub mod simple;
 pub use simple::*;
+pub mod transport;
+pub use transport::{ByteTransport, TransportBroker, TransportEventManager};
 #[cfg(all(unix, feature = "std"))]
 pub mod centralized;
 #[cfg(all(unix, feature = "std"))]
 pub use centralized::*;
 #[cfg(feature = "std")]
+pub mod experiment;
+#[cfg(feature = "std")]
+pub use experiment::{Experiment, ExperimentArm, ExperimentIsolationHook};
+#[cfg(feature = "std")]
 #[allow(clippy::ignored_unit_patterns)]
 pub mod launcher;
 #[allow(clippy::ignored_unit_patterns)]
@@ -27,12 +33,17 @@ use alloc::{
     string::{String, ToString},
     vec::Vec,
 };
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+use core::sync::atomic::AtomicI32;
 use core::{
     fmt,
     hash::{BuildHasher, Hasher},
     marker::PhantomData,
+    sync::atomic::{AtomicBool, Ordering},
     time::Duration,
 };
+#[cfg(feature = "std")]
+use std::sync::OnceLock;
 
 use ahash::RandomState;
 pub use broker_hooks::*;
@@ -40,10 +51,12 @@ pub use broker_hooks::*;
 pub use launcher::*;
 #[cfg(all(unix, feature = "std"))]
 use libafl_bolts::os::unix_signals::{siginfo_t, ucontext_t, Handler, Signal, CTRL_C_EXIT};
+#[cfg(feature = "std")]
+use libafl_bolts::ErrorFrame;
 use libafl_bolts::{
     current_time,
     tuples::{Handle, MatchNameRef},
-    ClientId,
+    ClientId, ErrorReport,
 };
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "std")]
@@ -51,19 +64,20 @@ use uuid::Uuid;
 
 #[cfg(feature = "introspection")]
 use crate::state::HasClientPerfMonitor;
+#[cfg(feature = "scalability_introspection")]
+use crate::state::HasScalabilityMonitor;
 use crate::{
     executors::ExitKind,
+    fuzzer::StopReason,
     inputs::Input,
-    monitors::UserStats,
+    monitors::{
+        AggregatorOps, CampaignFingerprint, UserStats, UserStatsValue, CLIENT_FINGERPRINT_STAT,
+        CLIENT_LABEL_STAT,
+    },
     observers::ObserversTuple,
     state::{HasExecutions, HasLastReportTime, State},
     Error, HasMetadata,
 };
-#[cfg(feature = "scalability_introspection")]
-use crate::{
-    monitors::{AggregatorOps, UserStatsValue},
-    state::HasScalabilityMonitor,
-};
 
 /// Multi-machine mode
 #[cfg(all(unix, feature = "std", feature = "multi_machine"))]
@@ -73,8 +87,93 @@ pub mod multi_machine;
 #[cfg(all(unix, feature = "std"))]
 pub static mut EVENTMGR_SIGHANDLER_STATE: ShutdownSignalData = ShutdownSignalData {};
 
+/// Set once a shutdown was requested, see [`shutdown_requested`]
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The pid of the fuzzing child a respawner forwards `SIGTERM` to, see [`forward_sigterm_to`]
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+static SHUTDOWN_FORWARD_PID: AtomicI32 = AtomicI32::new(0);
+
+/// If a shutdown was requested, by a signal or by [`request_shutdown`].
+/// The [`crate::stages::StagesTuple`] then skips its remaining stages, and the fuzzer stops at the
+/// next progress report, after sending the final stats, see [`ProgressReporter::on_shutdown`].
+#[must_use]
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Requests an orderly shutdown of this fuzzer instance, like a first ctrl-c does.
+/// The fuzzer keeps running until its current stage is done and it reached its next progress
+/// report, so this may take a while. A ctrl-c received after this exits right away, see
+/// [`ShutdownSignalData`].
+pub fn request_shutdown() {
+    SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// The frame added to the errors reported by this client, see [`set_client_error_frame`]
+#[cfg(feature = "std")]
+static CLIENT_ERROR_FRAME: OnceLock<ErrorFrame> = OnceLock::new();
+
+/// Sets the frame added to the errors this client reports with [`EventFirer::report_error`],
+/// e.g. with the core the client is bound to. The [`Launcher`] sets it for its clients.
+/// Only the first frame set in a process is kept.
+#[cfg(feature = "std")]
+pub fn set_client_error_frame(frame: ErrorFrame) {
+    CLIENT_ERROR_FRAME.get_or_init(|| frame);
+}
+
+/// Forwards `SIGTERM`s received by a respawner to its fuzzing child, so that the child can shut
+/// down in an orderly fashion, and the respawner does not start a new one afterwards.
+/// `SIGINT` is not forwarded, see [`note_sigint`].
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+pub(crate) fn forward_sigterm_to(pid: libc::pid_t) {
+    extern "C" fn forward_sigterm(signal: libc::c_int) {
+        request_shutdown();
+        let pid = SHUTDOWN_FORWARD_PID.load(Ordering::Relaxed);
+        if pid > 0 {
+            // # Safety
+            // `kill` is async-signal-safe
+            unsafe {
+                libc::kill(pid, signal);
+            }
+        }
+    }
+
+    SHUTDOWN_FORWARD_PID.store(pid, Ordering::Relaxed);
+    // # Safety
+    // The handler only touches atomics and calls `kill`, which is async-signal-safe
+    unsafe {
+        libc::signal(
+            libc::SIGTERM,
+            forward_sigterm as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+/// Makes a respawner note a ctrl-c, which reaches its fuzzing child anyway, as it is sent to the
+/// whole process group. The respawner keeps waiting for the child, but does not start a new one
+/// once the child exited, e.g. after a crash during its orderly shutdown. Otherwise, the new child
+/// would not know about the first ctrl-c, and the second one would not exit it.
+#[cfg(all(unix, feature = "std"))]
+pub(crate) fn note_sigint() {
+    extern "C" fn note(_signal: libc::c_int) {
+        request_shutdown();
+    }
+
+    // # Safety
+    // The handler only touches an atomic
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            note as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
 /// A signal handler for catching ctrl-c.
-/// The purpose of this signal handler is solely for calling `exit()` with a specific exit code 100
+/// The first `SIGINT` or any `SIGTERM` only requests a shutdown, see [`shutdown_requested`], so
+/// that the fuzzer can send its final stats and tell its respawner not to restart it.
+/// A second ctrl-c, or `SIGQUIT`, calls `exit()` with the specific exit code 100 right away.
 /// In this way, the restarting manager can tell that we really want to exit
 #[cfg(all(unix, feature = "std"))]
 #[derive(Debug, Clone)]
@@ -84,21 +183,20 @@ pub struct ShutdownSignalData {}
 /// We can't handle SIGKILL in the signal handler, this means that you shouldn't kill your fuzzer with `kill -9` because then the shmem segments are never freed
 #[cfg(all(unix, feature = "std"))]
 impl Handler for ShutdownSignalData {
-    fn handle(
-        &mut self,
-        _signal: Signal,
-        _info: &mut siginfo_t,
-        _context: Option<&mut ucontext_t>,
-    ) {
-        // println!("in handler! {}", std::process::id());
-        unsafe {
-            // println!("Exiting from the handler....");
-
-            #[cfg(unix)]
-            libc::_exit(CTRL_C_EXIT);
-
-            #[cfg(windows)]
-            windows::Win32::System::Threading::ExitProcess(100);
+    fn handle(&mut self, signal: Signal, _info: &mut siginfo_t, _context: Option<&mut ucontext_t>) {
+        let force = match signal {
+            Signal::SigTerm => {
+                request_shutdown();
+                false
+            }
+            // A repeated ctrl-c means the user does not want to wait any longer
+            Signal::SigInterrupt => SHUTDOWN_REQUESTED.swap(true, Ordering::Relaxed),
+            _ => true,
+        };
+        if force {
+            unsafe {
+                libc::_exit(CTRL_C_EXIT);
+            }
         }
     }
 
@@ -346,6 +444,17 @@ where
         /// `PhantomData`
         phantom: PhantomData<I>,
     },
+    /// An error stopped a client, with the context frames it passed on its way up
+    Error {
+        /// The report of the error
+        report: ErrorReport,
+    },
+    /// A client met a stop condition of a budgeted fuzz loop, the broker ends the campaign,
+    /// see [`crate::fuzzer::Fuzzer::fuzz_loop_until`]
+    Stop {
+        /// Why the client stopped
+        reason: StopReason,
+    },
     /// Sends a custom buffer to other clients
     CustomBuf {
         /// The buffer
@@ -373,6 +482,8 @@ where
             Event::UpdatePerfMonitor { .. } => "PerfMonitor",
             Event::Objective { .. } => "Objective",
             Event::Log { .. } => "Log",
+            Event::Error { .. } => "Error",
+            Event::Stop { .. } => "Stop",
             Event::CustomBuf { .. } => "CustomBuf",
             /*Event::Custom {
                 sender_id: _, /*custom_event} => custom_event.name()*/
@@ -391,6 +502,8 @@ where
             Event::UpdatePerfMonitor { .. } => "PerfMonitor".to_string(),
             Event::Objective { .. } => "Objective".to_string(),
             Event::Log { .. } => "Log".to_string(),
+            Event::Error { .. } => "Error".to_string(),
+            Event::Stop { reason } => format!("Stop ({reason})"),
             Event::CustomBuf { .. } => "CustomBuf".to_string(),
             /*Event::Custom {
                 sender_id: _, /*custom_event} => custom_event.name()*/
@@ -433,6 +546,57 @@ pub trait EventFirer: UsesState {
         )
     }
 
+    /// Send off an [`Event::Error`] event to the broker, reporting the error with its context,
+    /// including the frame of this client, see [`set_client_error_frame`].
+    /// Call it before the client goes down because of the error.
+    fn report_error(&mut self, state: &mut Self::State, error: &Error) -> Result<(), Error> {
+        #[allow(unused_mut)]
+        let mut report = error.report();
+        #[cfg(feature = "std")]
+        if let Some(frame) = CLIENT_ERROR_FRAME.get() {
+            report.frames.push(frame.clone());
+        }
+        self.fire(state, Event::Error { report })
+    }
+
+    /// Registers a human-readable label for this client, e.g. `asan` or `cmplog`,
+    /// shown next to the client id by the monitors.
+    /// Call it at the start of `run_client`, so the label is sent again after each restart.
+    fn set_client_label<L>(&mut self, state: &mut Self::State, label: L) -> Result<(), Error>
+    where
+        L: Into<Cow<'static, str>>,
+    {
+        self.fire(
+            state,
+            Event::UpdateUserStats {
+                name: Cow::from(CLIENT_LABEL_STAT),
+                value: UserStats::new(UserStatsValue::String(label.into()), AggregatorOps::None),
+                phantom: PhantomData,
+            },
+        )
+    }
+
+    /// Publishes the [`CampaignFingerprint`] of this client's pipeline, so that the monitors can
+    /// warn if clients of the same campaign are configured differently.
+    /// Like [`EventFirer::set_client_label`], call it at the start of `run_client`.
+    fn set_client_fingerprint(
+        &mut self,
+        state: &mut Self::State,
+        fingerprint: &CampaignFingerprint,
+    ) -> Result<(), Error> {
+        self.fire(
+            state,
+            Event::UpdateUserStats {
+                name: Cow::from(CLIENT_FINGERPRINT_STAT),
+                value: UserStats::new(
+                    UserStatsValue::String(fingerprint.to_string().into()),
+                    AggregatorOps::None,
+                ),
+                phantom: PhantomData,
+            },
+        )
+    }
+
     /// Serialize all observers for this type and manager
     fn serialize_observers<OT>(&mut self, observers: &OT) -> Result<Option<Vec<u8>>, Error>
     where
@@ -458,11 +622,18 @@ where
     /// Given the last time, if `monitor_timeout` seconds passed, send off an info/monitor/heartbeat message to the broker.
     /// Returns the new `last` time (so the old one, unless `monitor_timeout` time has passed and monitor have been sent)
     /// Will return an [`Error`], if the stats could not be sent.
+    ///
+    /// Once a shutdown was requested, see [`shutdown_requested`], this calls [`Self::on_shutdown`]
+    /// and returns [`Error::ShuttingDown`].
     fn maybe_report_progress(
         &mut self,
         state: &mut Self::State,
         monitor_timeout: Duration,
     ) -> Result<(), Error> {
+        if shutdown_requested() {
+            self.on_shutdown(state)?;
+            return Err(Error::shutting_down());
+        }
         let Some(last_report_time) = state.last_report_time() else {
             // this is the first time we execute, no need to report progress just yet.
             *state.last_report_time_mut() = Some(current_time());
@@ -539,6 +710,12 @@ where
 
         Ok(())
     }
+
+    /// Called once a shutdown was requested, before the fuzzer stops.
+    /// Sends the final stats; restarting managers also tell their respawner not to restart us.
+    fn on_shutdown(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.report_progress(state)
+    }
 }
 
 /// Restartable trait
@@ -837,6 +1014,11 @@ where
     fn report_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
         self.inner.report_progress(state)
     }
+
+    #[inline]
+    fn on_shutdown(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.inner.on_shutdown(state)
+    }
 }
 
 impl<EM, M> HasEventManagerId for MonitorTypedEventManager<EM, M>
@@ -924,22 +1106,85 @@ pub trait AdaptiveSerializer {
 }
 
 #[cfg(test)]
-mod tests {
+pub mod test {
 
-    use core::ptr::addr_of_mut;
+    use alloc::{boxed::Box, rc::Rc, vec::Vec};
+    use core::{cell::Cell, ptr::addr_of_mut, time::Duration};
 
-    use libafl_bolts::{current_time, tuples::tuple_list, Named};
+    #[cfg(all(unix, feature = "std"))]
+    use libafl_bolts::os::unix_signals::{setup_signal_handler, CTRL_C_EXIT};
+    use libafl_bolts::{current_time, tuples::tuple_list, ErrorFrame, Named};
     use tuple_list::tuple_list_type;
 
+    #[cfg(all(unix, feature = "std"))]
+    use crate::events::{note_sigint, set_client_error_frame, EVENTMGR_SIGHANDLER_STATE};
     use crate::{
-        events::{Event, EventConfig},
-        executors::ExitKind,
-        inputs::bytes::BytesInput,
+        events::{
+            request_shutdown, shutdown_requested, Event, EventConfig, EventFirer, NopEventManager,
+            ProgressReporter,
+        },
+        executors::{test::NopExecutor, ExitKind},
+        fuzzer::test::NopFuzzer,
+        inputs::{bytes::BytesInput, UsesInput},
         observers::StdMapObserver,
+        stages::{ClosureStage, Stage, StagesTuple},
+        state::{test::test_std_state, State, UsesState},
+        Error,
     };
 
     static mut MAP: [u32; 4] = [0; 4];
 
+    /// Keeps the events fired through it
+    #[derive(Debug)]
+    pub struct RecordingEventFirer<S>
+    where
+        S: UsesInput,
+    {
+        /// The fired events
+        pub events: Vec<Event<S::Input>>,
+    }
+
+    impl<S> RecordingEventFirer<S>
+    where
+        S: UsesInput,
+    {
+        /// Creates a new [`RecordingEventFirer`] without any events
+        #[must_use]
+        pub fn new() -> Self {
+            Self { events: Vec::new() }
+        }
+    }
+
+    impl<S> Default for RecordingEventFirer<S>
+    where
+        S: UsesInput,
+    {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<S> UsesState for RecordingEventFirer<S>
+    where
+        S: State,
+    {
+        type State = S;
+    }
+
+    impl<S> EventFirer for RecordingEventFirer<S>
+    where
+        S: State,
+    {
+        fn should_send(&self) -> bool {
+            true
+        }
+
+        fn fire(&mut self, _state: &mut S, event: Event<S::Input>) -> Result<(), Error> {
+            self.events.push(event);
+            Ok(())
+        }
+    }
+
     #[test]
     fn test_event_serde() {
         let obv = unsafe {
@@ -974,4 +1219,139 @@ mod tests {
             _ => panic!("mistmatch"),
         };
     }
+
+    /// Runs `test` in a forked child, as the shutdown flag is shared by the whole process
+    #[cfg(all(unix, feature = "std"))]
+    fn in_child(test: fn()) {
+        assert_eq!(exit_code_in_child(test), 0);
+    }
+
+    /// Runs `test` in a forked child and returns the exit code of the child
+    #[cfg(all(unix, feature = "std"))]
+    fn exit_code_in_child(test: fn()) -> i32 {
+        use nix::{
+            sys::wait::{waitpid, WaitStatus},
+            unistd::{fork, ForkResult},
+        };
+
+        // # Safety
+        // The child only runs `test` and exits right away
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                let code = i32::from(std::panic::catch_unwind(test).is_err());
+                // # Safety
+                // Exits the child without running the test harness of the parent
+                unsafe { libc::_exit(code) }
+            }
+            ForkResult::Parent { child } => match waitpid(child, None).unwrap() {
+                WaitStatus::Exited(_, code) => code,
+                status => panic!("Unexpected child status {status:?}"),
+            },
+        }
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "std"))]
+    fn test_shutdown_skips_stages() {
+        in_child(|| {
+            let ran = Cell::new(false);
+            let mut stages = tuple_list!(ClosureStage::new(
+                |_: &mut NopFuzzer<_>, _: &mut NopExecutor<_>, _: &mut _, _: &mut _| {
+                    ran.set(true);
+                    Ok(())
+                }
+            ));
+            let mut state = test_std_state::<BytesInput>();
+            let mut fuzzer = NopFuzzer::new();
+            let mut executor = NopExecutor::new();
+            let mut manager = NopEventManager::new();
+
+            assert!(!shutdown_requested());
+            manager
+                .maybe_report_progress(&mut state, Duration::ZERO)
+                .unwrap();
+
+            request_shutdown();
+            stages
+                .perform_all(&mut fuzzer, &mut executor, &mut state, &mut manager)
+                .unwrap();
+            assert!(!ran.get());
+
+            let vec_ran = Rc::new(Cell::new(false));
+            let flag = vec_ran.clone();
+            let mut vec_stages: Vec<Box<dyn Stage<_, _, _, State = _, Input = _>>> =
+                vec![Box::new(ClosureStage::new(
+                    move |_: &mut NopFuzzer<_>, _: &mut NopExecutor<_>, _: &mut _, _: &mut _| {
+                        flag.set(true);
+                        Ok(())
+                    },
+                ))];
+            vec_stages
+                .perform_all(&mut fuzzer, &mut executor, &mut state, &mut manager)
+                .unwrap();
+            assert!(!vec_ran.get());
+            assert!(matches!(
+                manager.maybe_report_progress(&mut state, Duration::ZERO),
+                Err(Error::ShuttingDown)
+            ));
+        });
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "std"))]
+    fn test_second_sigint_exits() {
+        let code = exit_code_in_child(|| {
+            // # Safety
+            // The child is single-threaded
+            unsafe {
+                setup_signal_handler(addr_of_mut!(EVENTMGR_SIGHANDLER_STATE)).unwrap();
+                libc::raise(libc::SIGINT);
+            }
+            assert!(shutdown_requested());
+            // # Safety
+            // See above
+            unsafe {
+                libc::raise(libc::SIGINT);
+            }
+            unreachable!("The second ctrl-c should have exited");
+        });
+        assert_eq!(code, CTRL_C_EXIT);
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "std"))]
+    fn test_respawner_notes_sigint() {
+        in_child(|| {
+            note_sigint();
+            // # Safety
+            // The child is single-threaded
+            unsafe {
+                libc::raise(libc::SIGINT);
+            }
+            assert!(shutdown_requested());
+        });
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "std"))]
+    fn test_report_error_adds_client_frame() {
+        in_child(|| {
+            set_client_error_frame(ErrorFrame::new("client").with_core_id(3));
+            let mut state = test_std_state::<BytesInput>();
+            let mut manager = RecordingEventFirer::new();
+
+            let err = Error::illegal_state("test").context(ErrorFrame::new("stage"));
+            manager.report_error(&mut state, &err).unwrap();
+
+            let [Event::Error { report }] = manager.events.as_slice() else {
+                panic!("Expected a single error event");
+            };
+            let frames: Vec<_> = report
+                .frames
+                .iter()
+                .map(|frame| (frame.component.as_str(), frame.core_id))
+                .collect();
+            assert_eq!(frames, [("stage", None), ("client", Some(3))]);
+        });
+    }
 }
@@ -14,7 +14,7 @@ use std::{
 
 use enumflags2::{bitflags, BitFlags};
 #[cfg(feature = "llmp_compression")]
-use libafl_bolts::compress::GzipCompressor;
+use libafl_bolts::compress::Compressor;
 use libafl_bolts::{current_time, ownedref::OwnedRef, Error};
 use serde::{Deserialize, Serialize};
 use tokio::{
@@ -131,7 +131,7 @@ pub struct TcpMultiMachineState<A> {
     children: HashMap<NodeId, TcpStream>, // The children who connected during the fuzzing session.
     old_msgs: Vec<Vec<u8>>,
     #[cfg(feature = "llmp_compression")]
-    compressor: GzipCompressor,
+    compressor: Compressor,
 }
 
 /// The tree descriptor for the
@@ -240,7 +240,7 @@ where
             children: HashMap::default(),
             old_msgs: Vec::new(),
             #[cfg(feature = "llmp_compression")]
-            compressor: GzipCompressor::new(),
+            compressor: Compressor::new(),
         }));
 
         let rt =
@@ -357,7 +357,7 @@ where
 
     /// The compressor
     #[cfg(feature = "llmp_compression")]
-    pub fn compressor(&mut self) -> &GzipCompressor {
+    pub fn compressor(&mut self) -> &Compressor {
         &self.compressor
     }
 
@@ -23,6 +23,12 @@ use libafl_bolts::{os::CTRL_C_EXIT, shmem::ShMemProvider, staterestore::StateRes
 use serde::{de::DeserializeOwned, Serialize};
 
 use super::{CustomBufEventResult, CustomBufHandlerFn, HasCustomBufHandlers, ProgressReporter};
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+use crate::events::forward_sigterm_to;
+#[cfg(all(unix, feature = "std"))]
+use crate::events::note_sigint;
+#[cfg(feature = "std")]
+use crate::events::shutdown_requested;
 #[cfg(all(unix, feature = "std", not(miri)))]
 use crate::events::EVENTMGR_SIGHANDLER_STATE;
 use crate::{
@@ -280,6 +286,14 @@ where
                 log::log!((*severity_level).into(), "{message}");
                 Ok(BrokerEventResult::Handled)
             }
+            Event::Error { report } => {
+                log::error!("Client failed: {report}");
+                Ok(BrokerEventResult::Handled)
+            }
+            Event::Stop { reason } => {
+                log::info!("Client {reason}, ending the campaign");
+                Ok(BrokerEventResult::Handled)
+            }
             Event::CustomBuf { .. } => Ok(BrokerEventResult::Forward),
             //_ => Ok(BrokerEventResult::Forward),
         }
@@ -421,6 +435,10 @@ where
     S: State + HasExecutions + HasMetadata + HasLastReportTime,
     SP: ShMemProvider,
 {
+    fn on_shutdown(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.report_progress(state)?;
+        self.send_exiting()
+    }
 }
 
 #[cfg(feature = "std")]
@@ -484,9 +502,8 @@ where
                     shmem_provider.pre_fork()?;
                     match unsafe { fork() }? {
                         ForkResult::Parent(handle) => {
-                            unsafe {
-                                libc::signal(libc::SIGINT, libc::SIG_IGN);
-                            }
+                            note_sigint();
+                            forward_sigterm_to(handle.pid);
                             shmem_provider.post_fork(false)?;
                             handle.status()
                         }
@@ -497,18 +514,16 @@ where
                     }
                 };
 
-                // If this guy wants to fork, then ignore sigit
-                #[cfg(any(windows, not(feature = "fork")))]
+                // While the child runs, ctrl-c is ignored on Windows, and noted on unix
+                #[cfg(windows)]
                 unsafe {
-                    #[cfg(windows)]
                     libafl_bolts::os::windows_exceptions::signal(
                         libafl_bolts::os::windows_exceptions::SIGINT,
                         libafl_bolts::os::windows_exceptions::sig_ign(),
                     );
-
-                    #[cfg(unix)]
-                    libc::signal(libc::SIGINT, libc::SIG_IGN);
                 }
+                #[cfg(all(unix, not(feature = "fork")))]
+                note_sigint();
 
                 // On Windows (or in any case without forks), we spawn ourself again
                 #[cfg(any(windows, not(feature = "fork")))]
@@ -518,7 +533,10 @@ where
 
                 compiler_fence(Ordering::SeqCst);
 
-                if child_status == CTRL_C_EXIT || staterestorer.wants_to_exit() {
+                if child_status == CTRL_C_EXIT
+                    || staterestorer.wants_to_exit()
+                    || shutdown_requested()
+                {
                     return Err(Error::shutting_down());
                 }
 
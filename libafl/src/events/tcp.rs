@@ -41,6 +41,12 @@ use tokio::{
 use typed_builder::TypedBuilder;
 
 use super::{CustomBufEventResult, CustomBufHandlerFn};
+#[cfg(all(unix, feature = "std", feature = "fork"))]
+use crate::events::forward_sigterm_to;
+#[cfg(all(unix, feature = "std"))]
+use crate::events::note_sigint;
+#[cfg(feature = "std")]
+use crate::events::shutdown_requested;
 #[cfg(all(unix, feature = "std", not(miri)))]
 use crate::events::EVENTMGR_SIGHANDLER_STATE;
 use crate::{
@@ -52,7 +58,7 @@ use crate::{
     executors::{Executor, HasObservers},
     fuzzer::{EvaluatorObservers, ExecutionProcessor},
     inputs::{Input, UsesInput},
-    monitors::Monitor,
+    monitors::{Monitor, CLIENT_FINGERPRINT_STAT},
     state::{HasExecutions, HasLastReportTime, State, UsesState},
     Error, HasMetadata,
 };
@@ -299,6 +305,11 @@ where
                 BrokerEventResult::Handled => (),
             }
 
+            if matches!(event, Event::Stop { .. }) {
+                tokio_broker.abort();
+                break;
+            }
+
             if tokio_broker.is_finished() {
                 tokio_broker.await.unwrap();
                 break;
@@ -318,6 +329,7 @@ where
     ) -> Result<BrokerEventResult, Error> {
         match &event {
             Event::NewTestcase {
+                client_config,
                 corpus_size,
                 time,
                 executions,
@@ -332,6 +344,7 @@ where
                 monitor.client_stats_insert(id);
                 let client = monitor.client_stats_mut_for(id);
                 client.update_corpus_size(*corpus_size as u64);
+                client.configuration = Some(*client_config);
                 client.update_executions(*executions, *time);
                 monitor.display(event.name(), id);
                 Ok(BrokerEventResult::Forward)
@@ -356,6 +369,9 @@ where
                 monitor.client_stats_insert(client_id);
                 let client = monitor.client_stats_mut_for(client_id);
                 client.update_user_stats(name.clone(), value.clone());
+                if name == CLIENT_FINGERPRINT_STAT {
+                    monitor.check_fingerprint(client_id);
+                }
                 monitor.aggregate(name);
                 monitor.display(event.name(), client_id);
                 Ok(BrokerEventResult::Handled)
@@ -407,6 +423,14 @@ where
                 log::log!((*severity_level).into(), "{message}");
                 Ok(BrokerEventResult::Handled)
             }
+            Event::Error { report } => {
+                log::error!("Client {} failed: {report}", client_id.0);
+                Ok(BrokerEventResult::Handled)
+            }
+            Event::Stop { reason } => {
+                log::info!("Client {} {reason}, ending the campaign", client_id.0);
+                Ok(BrokerEventResult::Handled)
+            }
             Event::CustomBuf { .. } => Ok(BrokerEventResult::Forward),
             //_ => Ok(BrokerEventResult::Forward),
         }
@@ -887,6 +911,10 @@ where
     S: State + HasExecutions + HasMetadata + HasLastReportTime,
     SP: ShMemProvider,
 {
+    fn on_shutdown(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.report_progress(state)?;
+        self.send_exiting()
+    }
 }
 
 #[cfg(feature = "std")]
@@ -1241,9 +1269,8 @@ where
                     self.shmem_provider.pre_fork()?;
                     match unsafe { fork() }? {
                         ForkResult::Parent(handle) => {
-                            unsafe {
-                                libc::signal(libc::SIGINT, libc::SIG_IGN);
-                            }
+                            note_sigint();
+                            forward_sigterm_to(handle.pid);
                             self.shmem_provider.post_fork(false)?;
                             handle.status()
                         }
@@ -1254,18 +1281,16 @@ where
                     }
                 };
 
-                // If this guy wants to fork, then ignore sigit
-                #[cfg(any(windows, not(feature = "fork")))]
+                // While the child runs, ctrl-c is ignored on Windows, and noted on unix
+                #[cfg(windows)]
                 unsafe {
-                    #[cfg(windows)]
                     libafl_bolts::os::windows_exceptions::signal(
                         libafl_bolts::os::windows_exceptions::SIGINT,
                         libafl_bolts::os::windows_exceptions::sig_ign(),
                     );
-
-                    #[cfg(unix)]
-                    libc::signal(libc::SIGINT, libc::SIG_IGN);
                 }
+                #[cfg(all(unix, not(feature = "fork")))]
+                note_sigint();
 
                 // On Windows (or in any case without fork), we spawn ourself again
                 #[cfg(any(windows, not(feature = "fork")))]
@@ -1275,7 +1300,10 @@ where
 
                 compiler_fence(Ordering::SeqCst);
 
-                if child_status == CTRL_C_EXIT || staterestorer.wants_to_exit() {
+                if child_status == CTRL_C_EXIT
+                    || staterestorer.wants_to_exit()
+                    || shutdown_requested()
+                {
                     return Err(Error::shutting_down());
                 }
 
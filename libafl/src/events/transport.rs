@@ -0,0 +1,613 @@
+//! A `no_std` event manager over a user-provided byte pipe, e.g. a UART, RTT, or shared RAM, for
+//! fuzzers running on embedded devices.
+//!
+//! The [`TransportEventManager`] runs on the device and sends its events, i.e. stats, logs, new
+//! testcases and objectives, as frames over a [`ByteTransport`]. The [`TransportBroker`] runs on
+//! the host, reads the frames from the other end of the pipe, shows them in a [`Monitor`], and
+//! can send testcases back to the device.
+//!
+//! Each frame is the [`FRAME_MAGIC`], the length of the payload (`u32`, little-endian), the
+//! `postcard`-serialized [`Event`], and a Fletcher-16 checksum of the payload. Corrupted frames
+//! are skipped, so a lossy link only loses events.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{fmt::Debug, marker::PhantomData};
+
+use libafl_bolts::{current_time, ClientId};
+use serde::Serialize;
+
+use super::{CustomBufEventResult, CustomBufHandlerFn};
+use crate::{
+    events::{
+        Event, EventConfig, EventFirer, EventManager, EventManagerId, EventProcessor,
+        EventRestarter, HasCustomBufHandlers, HasEventManagerId, ProgressReporter,
+    },
+    executors::{Executor, ExitKind, HasObservers},
+    fuzzer::{EvaluatorObservers, StopReason},
+    inputs::{Input, UsesInput},
+    monitors::Monitor,
+    observers::ObserversTuple,
+    state::{HasExecutions, HasLastReportTime, State, UsesState},
+    Error, HasMetadata,
+};
+
+/// The bytes starting each frame
+pub const FRAME_MAGIC: [u8; 2] = [0xaf, 0x1e];
+
+/// The maximal payload length of a frame; longer frames are considered corrupted
+pub const MAX_FRAME_LEN: usize = 1 << 20;
+
+/// The length of the frame header: magic and payload length
+const HEADER_LEN: usize = FRAME_MAGIC.len() + 4;
+
+/// The length of the frame trailer: the checksum
+const TRAILER_LEN: usize = 2;
+
+/// A byte pipe between the device and the host, e.g. a UART, RTT, or shared RAM
+pub trait ByteTransport {
+    /// Writes all bytes, blocking until they are sent
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error>;
+
+    /// Reads the bytes available, without blocking. Returns the number of bytes read, or `0` if
+    /// none are available.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
+}
+
+/// The Fletcher-16 checksum of the bytes
+fn fletcher16(bytes: &[u8]) -> u16 {
+    let (mut sum1, mut sum2) = (0_u16, 0_u16);
+    for b in bytes {
+        sum1 = (sum1 + u16::from(*b)) % 255;
+        sum2 = (sum2 + sum1) % 255;
+    }
+    (sum2 << 8) | sum1
+}
+
+/// Wraps the payload in a frame
+fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_LEN + payload.len() + TRAILER_LEN);
+    frame.extend_from_slice(&FRAME_MAGIC);
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&fletcher16(payload).to_le_bytes());
+    frame
+}
+
+/// Reassembles frames from the bytes read from a [`ByteTransport`]
+#[derive(Debug, Default, Clone)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// Creates a new [`FrameDecoder`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds bytes read from the transport
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// The payload of the next complete frame, if any. Corrupted frames are skipped.
+    pub fn next_frame(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let Some(start) = self
+                .buf
+                .windows(FRAME_MAGIC.len())
+                .position(|window| window == FRAME_MAGIC)
+            else {
+                // Keep a trailing partial magic
+                let keep = usize::from(self.buf.last() == Some(&FRAME_MAGIC[0]));
+                self.buf.drain(..self.buf.len() - keep);
+                return None;
+            };
+            self.buf.drain(..start);
+            if self.buf.len() < HEADER_LEN {
+                return None;
+            }
+
+            let len =
+                u32::from_le_bytes(self.buf[FRAME_MAGIC.len()..HEADER_LEN].try_into().unwrap())
+                    as usize;
+            if len > MAX_FRAME_LEN {
+                self.buf.drain(..1);
+                continue;
+            }
+            if self.buf.len() < HEADER_LEN + len + TRAILER_LEN {
+                return None;
+            }
+
+            let payload = &self.buf[HEADER_LEN..HEADER_LEN + len];
+            let checksum = u16::from_le_bytes(
+                self.buf[HEADER_LEN + len..HEADER_LEN + len + TRAILER_LEN]
+                    .try_into()
+                    .unwrap(),
+            );
+            if fletcher16(payload) != checksum {
+                log::warn!("Dropping a corrupted frame");
+                self.buf.drain(..1);
+                continue;
+            }
+            let payload = payload.to_vec();
+            self.buf.drain(..HEADER_LEN + len + TRAILER_LEN);
+            return Some(payload);
+        }
+    }
+}
+
+/// Reads all available bytes of the transport into the decoder
+fn read_available<T>(transport: &mut T, decoder: &mut FrameDecoder) -> Result<(), Error>
+where
+    T: ByteTransport,
+{
+    let mut buf = [0_u8; 256];
+    loop {
+        let read = transport.read(&mut buf)?;
+        if read == 0 {
+            return Ok(());
+        }
+        decoder.push(&buf[..read]);
+    }
+}
+
+/// Serializes the event and sends it as a frame
+fn send_event<I, T>(transport: &mut T, event: &Event<I>) -> Result<(), Error>
+where
+    I: Input,
+    T: ByteTransport,
+{
+    let payload = postcard::to_allocvec(event)?;
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(Error::illegal_argument(
+            "Event too large for the transport, see MAX_FRAME_LEN",
+        ));
+    }
+    transport.write_all(&encode_frame(&payload))
+}
+
+/// An event manager for embedded devices, sending the events over a [`ByteTransport`] to a
+/// [`TransportBroker`] on the host.
+///
+/// Observers are not sent, to save bandwidth. Testcases sent by the host are evaluated like any
+/// other input.
+pub struct TransportEventManager<S, T>
+where
+    S: UsesInput,
+{
+    transport: T,
+    decoder: FrameDecoder,
+    custom_buf_handlers: Vec<Box<CustomBufHandlerFn<S>>>,
+    phantom: PhantomData<S>,
+}
+
+impl<S, T> Debug for TransportEventManager<S, T>
+where
+    S: UsesInput,
+    T: Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TransportEventManager")
+            .field("transport", &self.transport)
+            .field("decoder", &self.decoder)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, T> UsesState for TransportEventManager<S, T>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<S, T> EventFirer for TransportEventManager<S, T>
+where
+    S: State,
+    T: ByteTransport,
+{
+    fn should_send(&self) -> bool {
+        true
+    }
+
+    fn fire(
+        &mut self,
+        _state: &mut Self::State,
+        event: Event<<Self::State as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        send_event(&mut self.transport, &event)
+    }
+
+    fn serialize_observers<OT>(&mut self, _observers: &OT) -> Result<Option<Vec<u8>>, Error>
+    where
+        OT: ObserversTuple<Self::State> + Serialize,
+    {
+        Ok(None)
+    }
+}
+
+impl<S, T> EventRestarter for TransportEventManager<S, T>
+where
+    S: State,
+    T: ByteTransport,
+{
+}
+
+impl<E, S, T, Z> EventProcessor<E, Z> for TransportEventManager<S, T>
+where
+    E: HasObservers<State = S> + Executor<Self, Z>,
+    S: State,
+    T: ByteTransport,
+    Z: EvaluatorObservers<E::Observers, State = S>,
+{
+    fn process(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        executor: &mut E,
+    ) -> Result<usize, Error> {
+        read_available(&mut self.transport, &mut self.decoder)?;
+        let mut count = 0;
+        while let Some(frame) = self.decoder.next_frame() {
+            let event: Event<S::Input> = match postcard::from_bytes(&frame) {
+                Ok(event) => event,
+                Err(err) => {
+                    // A single corrupt or incompatible frame should not end the fuzzer
+                    log::warn!(
+                        "Dropping an undecodable frame of {} bytes: {err}",
+                        frame.len()
+                    );
+                    continue;
+                }
+            };
+            match event {
+                Event::NewTestcase { input, .. } => {
+                    let (_, id) = fuzzer.evaluate_input_with_observers::<E, Self>(
+                        state, executor, self, input, false,
+                    )?;
+                    if let Some(id) = id {
+                        log::info!("Added testcase from the host as item #{id}");
+                    }
+                }
+                Event::CustomBuf { tag, buf } => {
+                    for handler in &mut self.custom_buf_handlers {
+                        if handler(state, &tag, &buf)? == CustomBufEventResult::Handled {
+                            break;
+                        }
+                    }
+                }
+                event => {
+                    return Err(Error::unknown(format!(
+                        "Received illegal message that message should not have arrived: {:?}.",
+                        event.name()
+                    )));
+                }
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl<E, S, T, Z> EventManager<E, Z> for TransportEventManager<S, T>
+where
+    E: HasObservers<State = S> + Executor<Self, Z>,
+    S: State + HasExecutions + HasLastReportTime + HasMetadata,
+    T: ByteTransport,
+    Z: EvaluatorObservers<E::Observers, State = S>,
+{
+}
+
+impl<S, T> HasCustomBufHandlers for TransportEventManager<S, T>
+where
+    S: State,
+    T: ByteTransport,
+{
+    fn add_custom_buf_handler(&mut self, handler: Box<CustomBufHandlerFn<S>>) {
+        self.custom_buf_handlers.push(handler);
+    }
+}
+
+impl<S, T> ProgressReporter for TransportEventManager<S, T>
+where
+    S: State + HasExecutions + HasMetadata + HasLastReportTime,
+    T: ByteTransport,
+{
+}
+
+impl<S, T> HasEventManagerId for TransportEventManager<S, T>
+where
+    S: UsesInput,
+{
+    fn mgr_id(&self) -> EventManagerId {
+        EventManagerId(0)
+    }
+}
+
+impl<S, T> TransportEventManager<S, T>
+where
+    S: UsesInput,
+    T: ByteTransport,
+{
+    /// Creates a new [`TransportEventManager`] sending the events over the transport
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            decoder: FrameDecoder::new(),
+            custom_buf_handlers: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// The transport
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// The transport (mutable)
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+}
+
+/// The host side of a [`TransportEventManager`]: reads the events of a device from the
+/// transport, and shows them in the monitor
+#[derive(Debug)]
+pub struct TransportBroker<I, MT, T> {
+    monitor: MT,
+    transport: T,
+    decoder: FrameDecoder,
+    client_id: ClientId,
+    testcases: Vec<I>,
+    stop_reason: Option<StopReason>,
+}
+
+impl<I, MT, T> TransportBroker<I, MT, T>
+where
+    I: Input,
+    MT: Monitor,
+    T: ByteTransport,
+{
+    /// Creates a new [`TransportBroker`], showing the device as client `0`
+    pub fn new(monitor: MT, transport: T) -> Self {
+        Self {
+            monitor,
+            transport,
+            decoder: FrameDecoder::new(),
+            client_id: ClientId(0),
+            testcases: Vec::new(),
+            stop_reason: None,
+        }
+    }
+
+    /// Shows the device as the given client, e.g. if several brokers share a monitor
+    #[must_use]
+    pub fn with_client_id(mut self, client_id: ClientId) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    /// Reads and handles all events available on the transport.
+    /// Returns the number of events handled.
+    pub fn poll(&mut self) -> Result<usize, Error> {
+        read_available(&mut self.transport, &mut self.decoder)?;
+        let mut count = 0;
+        while let Some(frame) = self.decoder.next_frame() {
+            match postcard::from_bytes::<Event<I>>(&frame) {
+                Ok(event) => self.handle(event),
+                Err(err) => log::warn!("Dropping an undecodable event: {err}"),
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Sends a testcase to the device, to be evaluated by it
+    pub fn send_testcase(&mut self, input: I) -> Result<(), Error> {
+        send_event(
+            &mut self.transport,
+            &Event::NewTestcase {
+                input,
+                observers_buf: None,
+                exit_kind: ExitKind::Ok,
+                corpus_size: 0,
+                client_config: EventConfig::AlwaysUnique,
+                time: current_time(),
+                executions: 0,
+                forward_id: None,
+                #[cfg(all(unix, feature = "std", feature = "multi_machine"))]
+                node_id: None,
+            },
+        )
+    }
+
+    /// Takes the testcases the device reported since the last call, e.g. to store them on the host
+    pub fn take_testcases(&mut self) -> Vec<I> {
+        core::mem::take(&mut self.testcases)
+    }
+
+    /// Why the device stopped fuzzing, once it met a stop condition, e.g. to end the host loop
+    pub fn stop_reason(&self) -> Option<&StopReason> {
+        self.stop_reason.as_ref()
+    }
+
+    /// The monitor
+    pub fn monitor(&self) -> &MT {
+        &self.monitor
+    }
+
+    /// Handles an event of the device
+    fn handle(&mut self, event: Event<I>) {
+        let monitor = &mut self.monitor;
+        let client_id = self.client_id;
+        monitor.client_stats_insert(client_id);
+        let event_name = event.name_detailed();
+        match event {
+            Event::NewTestcase {
+                input,
+                corpus_size,
+                time,
+                executions,
+                ..
+            } => {
+                let client = monitor.client_stats_mut_for(client_id);
+                client.update_corpus_size(corpus_size as u64);
+                client.update_executions(executions, time);
+                self.testcases.push(input);
+            }
+            Event::UpdateExecStats {
+                time, executions, ..
+            } => {
+                monitor
+                    .client_stats_mut_for(client_id)
+                    .update_executions(executions, time);
+            }
+            Event::UpdateUserStats { name, value, .. } => {
+                monitor
+                    .client_stats_mut_for(client_id)
+                    .update_user_stats(name.clone(), value);
+                monitor.aggregate(&name);
+            }
+            #[cfg(feature = "introspection")]
+            Event::UpdatePerfMonitor {
+                time,
+                executions,
+                introspection_monitor,
+                ..
+            } => {
+                let client = monitor.client_stats_mut_for(client_id);
+                client.update_executions(executions, time);
+                client.update_introspection_monitor(*introspection_monitor);
+            }
+            Event::Objective {
+                objective_size,
+                executions,
+                time,
+            } => {
+                let client = monitor.client_stats_mut_for(client_id);
+                client.update_objective_size(objective_size as u64);
+                client.update_executions(executions, time);
+            }
+            Event::Log {
+                severity_level,
+                message,
+                ..
+            } => {
+                log::log!(severity_level.into(), "{message}");
+                return;
+            }
+            Event::Error { report } => {
+                log::error!("Client {} failed: {report}", client_id.0);
+                return;
+            }
+            Event::Stop { reason } => {
+                log::info!("Client {} {reason}", client_id.0);
+                self.stop_reason = Some(reason);
+                return;
+            }
+            Event::CustomBuf { .. } => return,
+        }
+        monitor.display(&event_name, client_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, rc::Rc, string::ToString, vec, vec::Vec};
+    use core::cell::RefCell;
+
+    use libafl_bolts::tuples::tuple_list;
+
+    use super::{encode_frame, send_event, ByteTransport, FrameDecoder, TransportEventManager};
+    use crate::{
+        events::{CustomBufEventResult, Event, EventProcessor, HasCustomBufHandlers},
+        executors::{test::ClosureExecutor, ExitKind},
+        feedbacks::ConstFeedback,
+        fuzzer::StdFuzzer,
+        inputs::BytesInput,
+        schedulers::QueueScheduler,
+        state::test::test_std_state,
+        Error,
+    };
+
+    /// Reads back the bytes written to it
+    #[derive(Debug, Default)]
+    struct Loopback {
+        bytes: Vec<u8>,
+    }
+
+    impl ByteTransport for Loopback {
+        fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error> {
+            self.bytes.extend_from_slice(bytes);
+            Ok(())
+        }
+
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            let len = buf.len().min(self.bytes.len());
+            buf[..len].copy_from_slice(&self.bytes[..len]);
+            self.bytes.drain(..len);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_process_drops_undecodable_frames() {
+        let mut transport = Loopback::default();
+        // A valid frame, whose payload is no event
+        transport.write_all(&encode_frame(&[0xff; 8])).unwrap();
+        send_event(
+            &mut transport,
+            &Event::<BytesInput>::CustomBuf {
+                tag: "tag".to_string(),
+                buf: vec![1, 2, 3],
+            },
+        )
+        .unwrap();
+
+        let mut mgr = TransportEventManager::new(transport);
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let handler_received = received.clone();
+        mgr.add_custom_buf_handler(Box::new(move |_state, tag, buf| {
+            assert_eq!(tag, "tag");
+            handler_received.borrow_mut().extend_from_slice(buf);
+            Ok(CustomBufEventResult::Handled)
+        }));
+
+        let mut state = test_std_state::<BytesInput>();
+        let mut executor = ClosureExecutor::new(
+            |_observers: &mut (), _input: &BytesInput| ExitKind::Ok,
+            tuple_list!(),
+        );
+        let mut fuzzer = StdFuzzer::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        assert_eq!(
+            mgr.process(&mut fuzzer, &mut state, &mut executor).unwrap(),
+            1
+        );
+        assert_eq!(*received.borrow(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_frame_decoder() {
+        let mut decoder = FrameDecoder::new();
+        let first = encode_frame(b"first");
+        let mut corrupted = encode_frame(b"corrupted");
+        corrupted[8] ^= 0xff;
+        let second = encode_frame(b"second");
+
+        decoder.push(b"noise");
+        decoder.push(&first[..3]);
+        assert_eq!(decoder.next_frame(), None);
+        decoder.push(&first[3..]);
+        decoder.push(&corrupted);
+        decoder.push(&second);
+        assert_eq!(decoder.next_frame(), Some(b"first".to_vec()));
+        assert_eq!(decoder.next_frame(), Some(b"second".to_vec()));
+        assert_eq!(decoder.next_frame(), None);
+    }
+}
@@ -0,0 +1,591 @@
+//! The [`AdbExecutor`] fuzzes native programs on an Android device or emulator, connected by `adb`.
+//!
+//! Each input is written to a file on the device and the target runs on it, by `adb shell`.
+//! Spawning `adb` for each run is slow, so the [`AdbMode::PersistentShell`] keeps a single shell
+//! session open, writing the inputs and starting the target through it instead.
+//!
+//! The target runs under the `timeout` of the device, and its exit status is mapped to the
+//! [`ExitKind`]: a target killed by a crash signal, or exiting with the
+//! [`AdbExecutorBuilder::crash_exitcode`], is a crash, which the
+//! [`crate::feedbacks::CrashFeedback`] turns into an objective. After a crash, the executor collects
+//! the crash log of `logcat` and, if configured, pulls the tombstone of the run from the device,
+//! see [`AdbCrash`].
+
+use alloc::{borrow::ToOwned, string::String, vec::Vec};
+use core::{marker::PhantomData, time::Duration};
+use std::{
+    env::temp_dir,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    thread,
+    time::Instant,
+};
+
+use libafl_bolts::{tuples::RefIndexable, AsSlice};
+
+use crate::{
+    executors::{
+        remote_shell::{
+            exit_kind_of, printf_to_file, shell_quote, target_command_line, RemoteShell,
+        },
+        Executor, ExitKind, HasObservers,
+    },
+    inputs::{HasTargetBytes, Input},
+    observers::{ObserversTuple, UsesObservers},
+    state::{HasExecutions, State, UsesState},
+    Error,
+};
+
+/// The default timeout of a run of the target
+pub const DEFAULT_ADB_TIMEOUT: Duration = Duration::from_secs(5);
+/// The default directory on the device for the input, writable for the `shell` user
+pub const DEFAULT_REMOTE_DIR: &str = "/data/local/tmp";
+/// The directory on the device the tombstones of crashes are written to
+pub const TOMBSTONE_DIR: &str = "/data/tombstones";
+
+/// The time `adb` gets on top of the timeout of the target, before it is considered hanging
+const ADB_GRACE_PERIOD: Duration = Duration::from_secs(2);
+/// The time between two checks if `adb` is done
+const ADB_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// A device connected by `adb`
+#[derive(Debug, Clone)]
+pub struct AdbDevice {
+    adb: PathBuf,
+    serial: Option<String>,
+}
+
+impl Default for AdbDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdbDevice {
+    /// The only connected device, using the `adb` in the `PATH`
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            adb: PathBuf::from("adb"),
+            serial: None,
+        }
+    }
+
+    /// The device with this serial, as listed by `adb devices`
+    #[must_use]
+    pub fn with_serial(mut self, serial: &str) -> Self {
+        self.serial = Some(serial.into());
+        self
+    }
+
+    /// Uses the `adb` at this path
+    #[must_use]
+    pub fn with_adb<P>(mut self, adb: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.adb = adb.as_ref().to_path_buf();
+        self
+    }
+
+    /// The serial of the device, if set
+    #[must_use]
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
+    /// An `adb` command for this device
+    #[must_use]
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.adb);
+        if let Some(serial) = &self.serial {
+            command.arg("-s").arg(serial);
+        }
+        command.stdin(Stdio::null());
+        command
+    }
+
+    /// Runs a shell command on the device, returning its output
+    pub fn shell(&self, command_line: &str) -> Result<Output, Error> {
+        Ok(self.command().arg("shell").arg(command_line).output()?)
+    }
+
+    /// Pushes a local file to the device
+    pub fn push<P>(&self, local: P, remote: &str) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let output = self
+            .command()
+            .arg("push")
+            .arg(local.as_ref())
+            .arg(remote)
+            .output()?;
+        check_output(&output, "adb push")
+    }
+
+    /// Pulls a file from the device
+    pub fn pull<P>(&self, remote: &str, local: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let output = self
+            .command()
+            .arg("pull")
+            .arg(remote)
+            .arg(local.as_ref())
+            .output()?;
+        check_output(&output, "adb pull")
+    }
+}
+
+/// Fails if an `adb` command failed
+fn check_output(output: &Output, what: &str) -> Result<(), Error> {
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::illegal_state(format!(
+            "{what} failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// How the target is started on the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AdbMode {
+    /// Pushes each input by `adb push` and runs the target by a new `adb shell`
+    #[default]
+    Spawn,
+    /// Keeps a single `adb shell` open, writing each input and running the target through it.
+    /// Much faster, as `adb` is not started for each input.
+    PersistentShell,
+}
+
+/// What was collected about the last crash
+#[derive(Debug, Clone, Default)]
+pub struct AdbCrash {
+    /// The `crash` buffer of `logcat`, with the backtrace of the crash
+    pub logcat: String,
+    /// The tombstone pulled from the device, if tombstones are collected
+    pub tombstone: Option<PathBuf>,
+}
+
+/// Runs a target on an Android device, see the [module docs](self).
+/// Use [`AdbExecutor::builder`] to create it.
+#[derive(Debug)]
+pub struct AdbExecutor<OT, S> {
+    device: AdbDevice,
+    mode: AdbMode,
+    program: String,
+    command_line: String,
+    remote_input: String,
+    local_input: PathBuf,
+    timeout: Duration,
+    crash_exitcode: Option<i8>,
+    collect_logcat: bool,
+    tombstone_dir: Option<PathBuf>,
+    shell: Option<RemoteShell>,
+    last_crash: Option<AdbCrash>,
+    observers: OT,
+    phantom: PhantomData<S>,
+}
+
+impl AdbExecutor<(), ()> {
+    /// Creates a builder for an [`AdbExecutor`]
+    #[must_use]
+    pub fn builder() -> AdbExecutorBuilder {
+        AdbExecutorBuilder::new()
+    }
+}
+
+impl<OT, S> AdbExecutor<OT, S> {
+    /// The device the target runs on
+    #[must_use]
+    pub fn device(&self) -> &AdbDevice {
+        &self.device
+    }
+
+    /// The shell command line running the target on the device
+    #[must_use]
+    pub fn command_line(&self) -> &str {
+        &self.command_line
+    }
+
+    /// What was collected about the last crash, if the last run crashed
+    #[must_use]
+    pub fn last_crash(&self) -> Option<&AdbCrash> {
+        self.last_crash.as_ref()
+    }
+
+    /// The time `adb` gets, before the run is considered hanging
+    fn adb_timeout(&self) -> Duration {
+        self.timeout + ADB_GRACE_PERIOD
+    }
+
+    /// Pushes the input and runs the target by a new `adb shell`
+    fn run_spawned(&mut self, bytes: &[u8]) -> Result<Option<i32>, Error> {
+        fs::write(&self.local_input, bytes)?;
+        self.device.push(&self.local_input, &self.remote_input)?;
+        // `adb push` keeps the time of the local file, but the tombstones of the run have to be
+        // newer than the input by the clock of the device
+        let command_line = format!(
+            "touch {} && {}",
+            shell_quote(&self.remote_input),
+            self.command_line
+        );
+        let mut child = self
+            .device
+            .command()
+            .arg("shell")
+            .arg(command_line)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let deadline = Instant::now() + self.adb_timeout();
+        loop {
+            if let Some(status) = child.try_wait()? {
+                // Since Android 7, `adb shell` exits with the exit status of the command
+                return Ok(status.code());
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Ok(None);
+            }
+            thread::sleep(ADB_POLL_INTERVAL);
+        }
+    }
+
+    /// Writes the input and runs the target through the persistent shell
+    fn run_persistent(&mut self, bytes: &[u8]) -> Result<Option<i32>, Error> {
+        let command_line = format!(
+            "{} && {}",
+            printf_to_file(bytes, &self.remote_input),
+            self.command_line
+        );
+        let timeout = self.adb_timeout();
+        let mut shell = match self.shell.take() {
+            Some(shell) => shell,
+            None => RemoteShell::spawn(self.device.command().arg("shell"))?,
+        };
+        let code = shell.run(&command_line, timeout)?.map(|(code, _)| code);
+        // Otherwise, the session is out of sync, a new one is started for the next run
+        if code.is_some() {
+            self.shell = Some(shell);
+        }
+        Ok(code)
+    }
+
+    /// Collects the crash log and the tombstone of the crash
+    fn collect_crash(&self, name: &str) -> Result<AdbCrash, Error> {
+        let mut crash = AdbCrash::default();
+        if self.collect_logcat {
+            let output = self
+                .device
+                .command()
+                .args(["logcat", "-d", "-b", "crash"])
+                .output()?;
+            crash.logcat = String::from_utf8_lossy(&output.stdout).into_owned();
+            // Clear the buffer, to only collect the log of the next crash next time
+            self.device
+                .command()
+                .args(["logcat", "-c", "-b", "crash"])
+                .output()?;
+        }
+        if let Some(tombstone_dir) = &self.tombstone_dir {
+            // Reading the tombstones needs root
+            let output = self
+                .device
+                .shell(&tombstone_search(&self.remote_input, &self.program))?;
+            let listing = String::from_utf8_lossy(&output.stdout);
+            if let Some(remote) = listing.lines().map(str::trim).find(|name| !name.is_empty()) {
+                let local = tombstone_dir.join(format!("{name}.tombstone"));
+                self.device.pull(remote, &local)?;
+                // Remove it, not to collect it again for the next crash
+                self.device
+                    .shell(&format!("rm -f {}", shell_quote(remote)))?;
+                crash.tombstone = Some(local);
+            } else {
+                log::warn!("No tombstone of the run found after a crash");
+            }
+        }
+        Ok(crash)
+    }
+}
+
+/// The shell command line listing the tombstones of a run of the `program`: the ones written
+/// after the input of the run, with the command line of the `program` in their header
+fn tombstone_search(remote_input: &str, program: &str) -> String {
+    format!(
+        "find {TOMBSTONE_DIR} -type f -newer {} -exec grep -l -F -e {} {{}} +",
+        shell_quote(remote_input),
+        shell_quote(&format!(">>> {program}"))
+    )
+}
+
+impl<OT, S> UsesState for AdbExecutor<OT, S>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<OT, S> UsesObservers for AdbExecutor<OT, S>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    type Observers = OT;
+}
+
+impl<OT, S> HasObservers for AdbExecutor<OT, S>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+impl<EM, OT, S, Z> Executor<EM, Z> for AdbExecutor<OT, S>
+where
+    EM: UsesState<State = S>,
+    OT: ObserversTuple<S>,
+    S: State + HasExecutions,
+    S::Input: HasTargetBytes,
+    Z: UsesState<State = S>,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut Self::State,
+        _mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        *state.executions_mut() += 1;
+        self.last_crash = None;
+
+        let bytes = input.target_bytes();
+        let code = match self.mode {
+            AdbMode::Spawn => self.run_spawned(bytes.as_slice())?,
+            AdbMode::PersistentShell => self.run_persistent(bytes.as_slice())?,
+        };
+        let exit_kind = match code {
+            Some(code) => exit_kind_of(code, self.crash_exitcode),
+            // adb itself hangs, the device is probably stuck in the target
+            None => ExitKind::Timeout,
+        };
+
+        if exit_kind == ExitKind::Crash {
+            self.last_crash = Some(self.collect_crash(&input.generate_name(None))?);
+        }
+        Ok(exit_kind)
+    }
+
+    fn restart_target(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        self.shell = None;
+        Ok(())
+    }
+}
+
+/// The builder for an [`AdbExecutor`]
+#[derive(Debug)]
+pub struct AdbExecutorBuilder {
+    device: AdbDevice,
+    mode: AdbMode,
+    program: Option<String>,
+    args: Vec<String>,
+    remote_dir: String,
+    timeout: Duration,
+    crash_exitcode: Option<i8>,
+    collect_logcat: bool,
+    tombstone_dir: Option<PathBuf>,
+}
+
+impl Default for AdbExecutorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdbExecutorBuilder {
+    /// Creates a new [`AdbExecutorBuilder`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            device: AdbDevice::new(),
+            mode: AdbMode::default(),
+            program: None,
+            args: Vec::new(),
+            remote_dir: DEFAULT_REMOTE_DIR.to_owned(),
+            timeout: DEFAULT_ADB_TIMEOUT,
+            crash_exitcode: None,
+            collect_logcat: true,
+            tombstone_dir: None,
+        }
+    }
+
+    /// Sets the device to run the target on, defaults to the only connected device
+    #[must_use]
+    pub fn device(mut self, device: AdbDevice) -> Self {
+        self.device = device;
+        self
+    }
+
+    /// Sets how the target is started, defaults to [`AdbMode::Spawn`]
+    #[must_use]
+    pub fn mode(mut self, mode: AdbMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the path of the target on the device
+    #[must_use]
+    pub fn program(mut self, program: &str) -> Self {
+        self.program = Some(program.into());
+        self
+    }
+
+    /// Adds an argument of the target. Each
+    /// [`crate::executors::command::AFL_INPUT_FILE_PLACEHOLDER`] (`@@`) is replaced by
+    /// the path of the input on the device. Without a placeholder, the input is passed on stdin.
+    #[must_use]
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Adds arguments of the target, see [`Self::arg`]
+    #[must_use]
+    pub fn args<IT, A>(mut self, args: IT) -> Self
+    where
+        IT: IntoIterator<Item = A>,
+        A: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Sets the directory on the device the input is written to, defaults to
+    /// [`DEFAULT_REMOTE_DIR`]
+    #[must_use]
+    pub fn remote_dir(mut self, remote_dir: &str) -> Self {
+        self.remote_dir = remote_dir.into();
+        self
+    }
+
+    /// Sets the timeout of a run of the target
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Treats a run as a crash if the target exits with this exit code, e.g. the `exitcode` of a
+    /// sanitizer. `ASan` reports abort by default, see [`AdbExecutor::command_line`].
+    #[must_use]
+    pub fn crash_exitcode(mut self, exitcode: i8) -> Self {
+        self.crash_exitcode = Some(exitcode);
+        self
+    }
+
+    /// Sets if the crash log of `logcat` is collected after a crash, defaults to `true`
+    #[must_use]
+    pub fn collect_logcat(mut self, collect_logcat: bool) -> Self {
+        self.collect_logcat = collect_logcat;
+        self
+    }
+
+    /// Pulls the tombstone of each crash into this directory, as `<input name>.tombstone`.
+    /// Reading the tombstones needs a rooted device.
+    #[must_use]
+    pub fn tombstone_dir<P>(mut self, tombstone_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.tombstone_dir = Some(tombstone_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Builds the [`AdbExecutor`]
+    pub fn build<OT, S>(self, observers: OT) -> Result<AdbExecutor<OT, S>, Error>
+    where
+        OT: ObserversTuple<S>,
+        S: State,
+    {
+        let Some(program) = self.program else {
+            return Err(Error::illegal_argument(
+                "AdbExecutor::builder: no program set!",
+            ));
+        };
+        if let Some(tombstone_dir) = &self.tombstone_dir {
+            fs::create_dir_all(tombstone_dir)?;
+        }
+        // Several fuzzers may share a device, so the input file is unique per fuzzer
+        let input_name = format!(".libafl_adb_input_{}", std::process::id());
+        let remote_input = format!("{}/{input_name}", self.remote_dir.trim_end_matches('/'));
+        let command_line = target_command_line(self.timeout, &program, &self.args, &remote_input);
+        Ok(AdbExecutor {
+            device: self.device,
+            mode: self.mode,
+            program,
+            command_line,
+            remote_input,
+            local_input: temp_dir().join(input_name),
+            timeout: self.timeout,
+            crash_exitcode: self.crash_exitcode,
+            collect_logcat: self.collect_logcat,
+            tombstone_dir: self.tombstone_dir,
+            shell: None,
+            last_crash: None,
+            observers,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{tombstone_search, AdbExecutor};
+    use crate::{inputs::BytesInput, state::NopState};
+
+    #[test]
+    fn test_adb_command_line() {
+        let executor = AdbExecutor::builder()
+            .program("/data/local/tmp/target")
+            .args(["-i", "--in=@@"])
+            .remote_dir("/data/local/tmp/")
+            .timeout(Duration::from_millis(1500))
+            .build::<(), NopState<BytesInput>>(())
+            .unwrap();
+        let input = format!("/data/local/tmp/.libafl_adb_input_{}", std::process::id());
+        assert_eq!(
+            executor.command_line(),
+            format!(
+                "ASAN_OPTIONS=\"abort_on_error=1:$ASAN_OPTIONS\" timeout -s KILL 1.500 \
+                 '/data/local/tmp/target' '-i' '--in={input}' < /dev/null > /dev/null 2>&1"
+            )
+        );
+        assert_eq!(
+            tombstone_search(&input, "/data/local/tmp/target"),
+            format!(
+                "find /data/tombstones -type f -newer '{input}' \
+                 -exec grep -l -F -e '>>> /data/local/tmp/target' {{}} +"
+            )
+        );
+        assert!(AdbExecutor::builder()
+            .build::<(), NopState<BytesInput>>(())
+            .is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! An executor post-processing each input with an [AFL++ custom mutator](crate::mutators::afl_custom)
+//! before running the target
+
+use libafl_bolts::tuples::RefIndexable;
+
+use crate::{
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::HasMutatorBytes,
+    mutators::SharedAflCustomLibrary,
+    observers::UsesObservers,
+    state::UsesState,
+    Error,
+};
+
+/// Wraps an [`Executor`], post-processing each input with `afl_custom_post_process` before
+/// running the target
+#[derive(Debug)]
+pub struct AflPostProcessExecutor<E> {
+    executor: E,
+    library: SharedAflCustomLibrary,
+}
+
+impl<E> AflPostProcessExecutor<E> {
+    /// Wraps the executor, post-processing with the loaded custom mutator
+    pub fn new(executor: E, library: SharedAflCustomLibrary) -> Self {
+        Self { executor, library }
+    }
+
+    /// The wrapped executor
+    pub fn inner(&mut self) -> &mut E {
+        &mut self.executor
+    }
+}
+
+impl<E, EM, Z> Executor<EM, Z> for AflPostProcessExecutor<E>
+where
+    E: Executor<EM, Z>,
+    EM: UsesState<State = Self::State>,
+    Z: UsesState<State = Self::State>,
+    Self::Input: HasMutatorBytes,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        let mut buf = input.bytes().to_vec();
+        let Some(out) = self.library.borrow_mut().post_process(&mut buf) else {
+            return self.executor.run_target(fuzzer, state, mgr, input);
+        };
+        let mut input = input.clone();
+        input.resize(out.len(), 0);
+        input.bytes_mut().copy_from_slice(&out);
+        self.executor.run_target(fuzzer, state, mgr, &input)
+    }
+
+    fn restart_target(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.executor.restart_target(state)
+    }
+}
+
+impl<E> UsesState for AflPostProcessExecutor<E>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E> UsesObservers for AflPostProcessExecutor<E>
+where
+    E: UsesObservers,
+{
+    type Observers = E::Observers;
+}
+
+impl<E> HasObservers for AflPostProcessExecutor<E>
+where
+    E: HasObservers,
+{
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        self.executor.observers()
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        self.executor.observers_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, vec::Vec};
+    use core::cell::RefCell;
+
+    use super::AflPostProcessExecutor;
+    use crate::{
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, Executor, ExitKind},
+        fuzzer::test::NopFuzzer,
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::afl_custom::test::{fake_library, fake_shared_library},
+        state::test::test_std_state,
+    };
+
+    #[test]
+    fn test_afl_post_process_executor() {
+        let seen = RefCell::new(Vec::new());
+        let harness = |_observers: &mut (), input: &BytesInput| {
+            seen.borrow_mut().push(input.bytes().to_vec());
+            ExitKind::Ok
+        };
+        let mut fuzzer = NopFuzzer::new();
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+        let input = BytesInput::new(b"abc".to_vec());
+
+        let mut executor =
+            AflPostProcessExecutor::new(ClosureExecutor::new(harness, ()), fake_shared_library());
+        executor
+            .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+            .unwrap();
+        assert_eq!(seen.borrow_mut().pop().unwrap(), b"pp:abc");
+        // The input itself is left alone
+        assert_eq!(input.bytes(), b"abc");
+
+        // Without afl_custom_post_process, the input is run as is
+        let library = Rc::new(RefCell::new(fake_library(&["init"]).unwrap()));
+        let mut executor = AflPostProcessExecutor::new(ClosureExecutor::new(harness, ()), library);
+        executor
+            .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+            .unwrap();
+        assert_eq!(seen.borrow_mut().pop().unwrap(), b"abc");
+    }
+}
@@ -1,12 +1,14 @@
 //! The command executor executes a sub program for each run
-use alloc::vec::Vec;
+#[cfg(any(feature = "multipart_inputs", not(unix)))]
+use alloc::string::String;
+use alloc::{string::ToString, vec::Vec};
 use core::{
     fmt::{self, Debug, Formatter},
     marker::PhantomData,
     ops::IndexMut,
 };
 #[cfg(unix)]
-use std::os::unix::ffi::OsStrExt;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 #[cfg(feature = "std")]
 use std::process::Child;
 use std::{
@@ -24,7 +26,9 @@ use libafl_bolts::{
 };
 
 #[cfg(all(feature = "std", unix))]
-use crate::executors::{Executor, ExitKind};
+use crate::executors::{core_dump::HasChildPid, Executor, ExitKind};
+#[cfg(feature = "multipart_inputs")]
+use crate::inputs::MultipartInput;
 use crate::{
     executors::HasObservers,
     inputs::{HasTargetBytes, UsesInput},
@@ -35,9 +39,19 @@ use crate::{
 #[cfg(feature = "std")]
 use crate::{inputs::Input, Error};
 
+/// The placeholder in a file name template that is replaced by the id of the fuzzer process,
+/// see [`InputLocation::file_from_template`]
+pub const INPUT_FILE_PID_PLACEHOLDER: &str = "{pid}";
+
+/// The placeholder in the commandline arguments that is replaced by the input file, like in AFL,
+/// see [`CommandExecutorBuilder::afl_args`]
+pub const AFL_INPUT_FILE_PLACEHOLDER: &str = "@@";
+
 /// How to deliver input to an external program
 /// `StdIn`: The target reads from stdin
 /// `File`: The target reads from the specified [`InputFile`]
+/// `Arg`: The input is passed as commandline argument
+/// `Env`: The input is passed as value of an environment variable
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InputLocation {
     /// Mutate a commandline argument to deliver an input
@@ -53,6 +67,34 @@ pub enum InputLocation {
         /// The file to write input to. The target should read input from this location.
         out_file: InputFile,
     },
+    /// Deliver the input as value of an environment variable, truncated at the first nul byte
+    Env {
+        /// The name of the environment variable
+        key: OsString,
+    },
+}
+
+impl InputLocation {
+    /// Creates an [`InputLocation::File`] from a file name template, replacing
+    /// [`INPUT_FILE_PID_PLACEHOLDER`] by the id of the fuzzer process.
+    /// This keeps the file names unique among parallel fuzzer instances, while keeping e.g. the
+    /// extension the target expects.
+    /// The file is removed once the last executor using it is dropped.
+    pub fn file_from_template(template: &str) -> Result<Self, Error> {
+        let path = template.replace(INPUT_FILE_PID_PLACEHOLDER, &std::process::id().to_string());
+        Ok(Self::File {
+            out_file: InputFile::create(path)?,
+        })
+    }
+
+    /// The path of the input file, for [`InputLocation::File`]
+    #[must_use]
+    pub fn file_path(&self) -> Option<&Path> {
+        match self {
+            Self::File { out_file } => Some(&out_file.path),
+            _ => None,
+        }
+    }
 }
 
 /// A simple Configurator that takes the most common parameters
@@ -145,7 +187,216 @@ where
                 out_file.write_buf(input.target_bytes().as_slice())?;
                 Ok(self.command.spawn()?)
             }
+            InputLocation::Env { key } => {
+                // Environment values can't contain nul bytes
+                let value = PartEscaping::TruncateAtNul.escape(input.target_bytes().as_slice());
+                self.command.env(key, bytes_to_os_string(value));
+                Ok(self.command.spawn()?)
+            }
+        }
+    }
+
+    fn exec_timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+/// How to deliver a part of a [`MultipartInput`] to an external program,
+/// see [`CommandExecutorBuilder::arg_input_part`] and friends
+#[cfg(feature = "multipart_inputs")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PartLocation {
+    /// Deliver the part as the commandline argument at this offset
+    Arg {
+        /// The offset of the argument
+        argnum: usize,
+    },
+    /// Deliver the part as the value of an environment variable
+    Env {
+        /// The name of the environment variable
+        key: OsString,
+    },
+    /// Deliver the part via `StdIn`
+    StdIn,
+    /// Deliver the part via the specified [`InputFile`]
+    File {
+        /// The file to write the part to. The target should read it from this location.
+        out_file: InputFile,
+    },
+}
+
+/// How the bytes of a part are escaped before they are passed as argument or environment value.
+/// Parts delivered via `StdIn` or a file are never escaped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PartEscaping {
+    /// Pass the bytes as they are. Spawning the target fails for parts containing `NUL` bytes.
+    Raw,
+    /// Cut the bytes at the first `NUL` byte, as the target would see them as C string
+    #[default]
+    TruncateAtNul,
+    /// Remove all `NUL` bytes
+    StripNul,
+    /// Remove all `NUL` bytes and quote the bytes for a POSIX shell,
+    /// for targets passing their arguments to `sh -c` or `eval`
+    ShellQuote,
+}
+
+impl PartEscaping {
+    /// Escapes the bytes of a part
+    #[must_use]
+    pub fn escape(self, bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Raw => bytes.to_vec(),
+            Self::TruncateAtNul => bytes.split(|b| *b == 0).next().unwrap_or_default().to_vec(),
+            Self::StripNul => bytes.iter().copied().filter(|b| *b != 0).collect(),
+            Self::ShellQuote => {
+                let mut quoted = Vec::with_capacity(bytes.len() + 2);
+                quoted.push(b'\'');
+                for b in bytes.iter().copied().filter(|b| *b != 0) {
+                    if b == b'\'' {
+                        quoted.extend_from_slice(b"'\\''");
+                    } else {
+                        quoted.push(b);
+                    }
+                }
+                quoted.push(b'\'');
+                quoted
+            }
+        }
+    }
+}
+
+/// Converts bytes to an [`OsString`]
+fn bytes_to_os_string(bytes: Vec<u8>) -> OsString {
+    #[cfg(unix)]
+    return OsString::from_vec(bytes);
+    // Non-unix platforms need valid unicode
+    #[cfg(not(unix))]
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+/// A Configurator delivering each part of a [`MultipartInput`] to a different location:
+/// commandline arguments, environment values, stdin, or files, all at once.
+/// Use [`CommandExecutor::builder()`] and [`CommandExecutorBuilder::build_multipart`] to use this
+/// configurator.
+///
+/// The first part with the configured name is delivered. If the input has no such part, an empty
+/// argument, no environment variable, no stdin, or an empty file is passed instead.
+#[cfg(feature = "multipart_inputs")]
+#[derive(Debug)]
+pub struct MultipartCommandConfigurator {
+    /// If set to true, the child output will remain visible
+    /// By default, the child output is hidden to increase execution speed
+    debug_child: bool,
+    stdout_observer: Option<Handle<StdOutObserver>>,
+    stderr_observer: Option<Handle<StdErrObserver>>,
+    timeout: Duration,
+    /// The names of the parts and where to deliver them
+    parts: Vec<(String, PartLocation)>,
+    escaping: PartEscaping,
+    /// The Command to execute, with placeholders for the argument parts
+    command: Command,
+}
+
+#[cfg(feature = "multipart_inputs")]
+impl MultipartCommandConfigurator {
+    /// The names of the parts and where they are delivered
+    #[must_use]
+    pub fn parts(&self) -> &[(String, PartLocation)] {
+        &self.parts
+    }
+
+    /// The escaping of argument and environment parts
+    #[must_use]
+    pub fn escaping(&self) -> PartEscaping {
+        self.escaping
+    }
+}
+
+#[cfg(feature = "multipart_inputs")]
+impl<I> CommandConfigurator<MultipartInput<I>> for MultipartCommandConfigurator
+where
+    I: HasTargetBytes,
+{
+    fn stdout_observer(&self) -> Option<Handle<StdOutObserver>> {
+        self.stdout_observer.clone()
+    }
+
+    fn stderr_observer(&self) -> Option<Handle<StdErrObserver>> {
+        self.stderr_observer.clone()
+    }
+
+    fn spawn_child(&mut self, input: &MultipartInput<I>) -> Result<Child, Error> {
+        let mut args: Vec<OsString> = self.command.get_args().map(ToOwned::to_owned).collect();
+        let mut cmd = Command::new(self.command.get_program());
+        cmd.envs(
+            self.command
+                .get_envs()
+                .filter_map(|(key, value)| value.map(|value| (key, value))),
+        );
+        if let Some(cwd) = self.command.get_current_dir() {
+            cmd.current_dir(cwd);
+        }
+
+        let mut stdin_part = None;
+        for (name, location) in &mut self.parts {
+            let part = input
+                .parts_by_name(name)
+                .next()
+                .map(|(_, part)| part.target_bytes());
+            let bytes = part.as_ref().map_or(&[][..], |part| part.as_slice());
+            match location {
+                PartLocation::Arg { argnum } => {
+                    args[*argnum] = bytes_to_os_string(self.escaping.escape(bytes));
+                }
+                PartLocation::Env { key } => {
+                    if part.is_some() {
+                        cmd.env(&*key, bytes_to_os_string(self.escaping.escape(bytes)));
+                    } else {
+                        cmd.env_remove(&*key);
+                    }
+                }
+                PartLocation::StdIn => stdin_part = Some(bytes.to_vec()),
+                PartLocation::File { out_file } => out_file.write_buf(bytes)?,
+            }
+        }
+        cmd.args(args);
+
+        if !self.debug_child {
+            cmd.stdout(Stdio::null());
+            cmd.stderr(Stdio::null());
+        }
+        if self.stdout_observer.is_some() {
+            cmd.stdout(Stdio::piped());
         }
+        if self.stderr_observer.is_some() {
+            cmd.stderr(Stdio::piped());
+        }
+
+        let has_stdin_part = self
+            .parts
+            .iter()
+            .any(|(_, location)| *location == PartLocation::StdIn);
+        if !has_stdin_part {
+            cmd.stdin(Stdio::null());
+            return Ok(cmd.spawn()?);
+        }
+
+        let mut handle = cmd.stdin(Stdio::piped()).spawn()?;
+        let mut stdin = handle.stdin.take().unwrap();
+        if let Some(stdin_part) = stdin_part {
+            if let Err(err) = stdin.write_all(&stdin_part) {
+                if err.kind() != std::io::ErrorKind::BrokenPipe {
+                    return Err(err.into());
+                }
+            } else if let Err(err) = stdin.flush() {
+                if err.kind() != std::io::ErrorKind::BrokenPipe {
+                    return Err(err.into());
+                }
+            }
+        }
+        drop(stdin);
+        Ok(handle)
     }
 
     fn exec_timeout(&self) -> Duration {
@@ -161,6 +412,8 @@ pub struct CommandExecutor<OT, S, T> {
     configurer: T,
     /// The observers used by this executor
     observers: OT,
+    /// The pid of the child of the last run
+    last_child_pid: Option<u32>,
     phantom: PhantomData<S>,
 }
 
@@ -176,6 +429,10 @@ impl CommandExecutor<(), (), ()> {
     /// * `arg_input_arg` for input delivered _as_ an command line argument
     /// * `arg_input_file` for input via a file of a specific name
     /// * `arg_input_file_std` for a file with default name (at the right location in the arguments)
+    ///
+    /// For `MultipartInput`s, each part can be delivered to a different location instead, using
+    /// `arg_input_part`, `env_input_part`, `stdin_input_part`, and `file_input_part`,
+    /// and `build_multipart`.
     #[must_use]
     pub fn builder() -> CommandExecutorBuilder {
         CommandExecutorBuilder::new()
@@ -191,6 +448,7 @@ where
         f.debug_struct("CommandExecutor")
             .field("inner", &self.configurer)
             .field("observers", &self.observers)
+            .field("last_child_pid", &self.last_child_pid)
             .finish()
     }
 }
@@ -206,6 +464,13 @@ where
     }
 }
 
+#[cfg(all(feature = "std", unix))]
+impl<OT, S, T> HasChildPid for CommandExecutor<OT, S, T> {
+    fn last_child_pid(&self) -> Option<i32> {
+        self.last_child_pid.and_then(|pid| pid.try_into().ok())
+    }
+}
+
 // this only works on unix because of the reliance on checking the process signal for detecting OOM
 #[cfg(all(feature = "std", unix))]
 impl<EM, OT, S, T, Z> Executor<EM, Z> for CommandExecutor<OT, S, T>
@@ -231,6 +496,7 @@ where
         self.observers.pre_exec_child_all(state, input)?;
 
         let mut child = self.configurer.spawn_child(input)?;
+        self.last_child_pid = Some(child.id());
 
         let res = match child
             .wait_timeout(self.configurer.exec_timeout())
@@ -324,6 +590,10 @@ pub struct CommandExecutorBuilder {
     cwd: Option<PathBuf>,
     envs: Vec<(OsString, OsString)>,
     timeout: Duration,
+    #[cfg(feature = "multipart_inputs")]
+    input_parts: Vec<(String, PartLocation)>,
+    #[cfg(feature = "multipart_inputs")]
+    part_escaping: PartEscaping,
 }
 
 impl Default for CommandExecutorBuilder {
@@ -346,6 +616,10 @@ impl CommandExecutorBuilder {
             envs: vec![],
             timeout: Duration::from_secs(5),
             debug_child: false,
+            #[cfg(feature = "multipart_inputs")]
+            input_parts: vec![],
+            #[cfg(feature = "multipart_inputs")]
+            part_escaping: PartEscaping::default(),
         }
     }
 
@@ -359,9 +633,15 @@ impl CommandExecutorBuilder {
         self
     }
 
-    /// Set the input mode and location.
-    /// This option is mandatory, if not set, the `build` method will error.
-    fn input(&mut self, input: InputLocation) -> &mut Self {
+    /// Set the input mode and location, defaulting to [`InputLocation::StdIn`].
+    /// For [`InputLocation::Arg`], the argument at `argnum` is replaced by the input, and for
+    /// [`InputLocation::File`], the file has to be passed to the target, e.g. by [`Self::arg`].
+    /// The more convenient [`Self::arg_input_arg`], [`Self::arg_input_file`], [`Self::env_input`],
+    /// or [`Self::afl_args`] take care of this.
+    ///
+    /// # Panics
+    /// Panics if the input location was set before.
+    pub fn input(&mut self, input: InputLocation) -> &mut Self {
         // This is a fatal error in the user code, no point in returning Err.
         assert_eq!(
             self.input_location,
@@ -414,6 +694,114 @@ impl CommandExecutorBuilder {
         self
     }
 
+    /// Sets the input mode to [`InputLocation::File`] with a file name template,
+    /// see [`InputLocation::file_from_template`], and adds the filename as arg at the current
+    /// position.
+    pub fn arg_input_file_template(&mut self, template: &str) -> Result<&mut Self, Error> {
+        let location = InputLocation::file_from_template(template)?;
+        if let Some(path) = location.file_path() {
+            self.args.push(path.as_os_str().to_owned());
+        }
+        Ok(self.input(location))
+    }
+
+    /// Sets the input mode to [`InputLocation::Env`], delivering the input as value of the
+    /// environment variable `key`.
+    pub fn env_input<K: AsRef<OsStr>>(&mut self, key: K) -> &mut Self {
+        self.input(InputLocation::Env {
+            key: key.as_ref().to_owned(),
+        })
+    }
+
+    /// Adds arguments to the program's commandline, like the commandline of `afl-fuzz`:
+    /// each [`AFL_INPUT_FILE_PLACEHOLDER`] (`@@`), also within an argument like `--in=@@`, is
+    /// replaced by an input file unique to the fuzzer process, and the input mode is set to
+    /// [`InputLocation::File`]. Without a placeholder, the input is delivered via stdin.
+    pub fn afl_args<IT, O>(&mut self, args: IT) -> Result<&mut Self, Error>
+    where
+        IT: IntoIterator<Item = O>,
+        O: AsRef<OsStr>,
+    {
+        for arg in args {
+            let arg = arg.as_ref();
+            let Some(template) = arg
+                .to_str()
+                .filter(|arg| arg.contains(AFL_INPUT_FILE_PLACEHOLDER))
+            else {
+                self.arg(arg);
+                continue;
+            };
+            if self.input_location.file_path().is_none() {
+                let out_file = InputFile::create(get_unique_std_input_file())?;
+                self.input(InputLocation::File { out_file });
+            }
+            let path = self.input_location.file_path().unwrap().to_string_lossy();
+            let arg = template.replace(AFL_INPUT_FILE_PLACEHOLDER, &path);
+            self.arg(arg);
+        }
+        Ok(self)
+    }
+
+    /// Delivers the part `name` of a [`MultipartInput`] as argument at the current position.
+    /// Use [`Self::build_multipart`] to build the executor.
+    #[cfg(feature = "multipart_inputs")]
+    pub fn arg_input_part<N: Into<String>>(&mut self, name: N) -> &mut Self {
+        let argnum = self.args.len();
+        self.arg("DUMMY");
+        self.input_parts
+            .push((name.into(), PartLocation::Arg { argnum }));
+        self
+    }
+
+    /// Delivers the part `name` of a [`MultipartInput`] as value of the environment variable `key`.
+    /// Use [`Self::build_multipart`] to build the executor.
+    #[cfg(feature = "multipart_inputs")]
+    pub fn env_input_part<N, K>(&mut self, name: N, key: K) -> &mut Self
+    where
+        N: Into<String>,
+        K: AsRef<OsStr>,
+    {
+        self.input_parts.push((
+            name.into(),
+            PartLocation::Env {
+                key: key.as_ref().to_owned(),
+            },
+        ));
+        self
+    }
+
+    /// Delivers the part `name` of a [`MultipartInput`] via stdin.
+    /// Use [`Self::build_multipart`] to build the executor.
+    #[cfg(feature = "multipart_inputs")]
+    pub fn stdin_input_part<N: Into<String>>(&mut self, name: N) -> &mut Self {
+        self.input_parts.push((name.into(), PartLocation::StdIn));
+        self
+    }
+
+    /// Delivers the part `name` of a [`MultipartInput`] via a file at `path`,
+    /// and adds the filename as arg at the current position.
+    /// Use [`Self::build_multipart`] to build the executor.
+    #[cfg(feature = "multipart_inputs")]
+    pub fn file_input_part<N, P>(&mut self, name: N, path: P) -> Result<&mut Self, Error>
+    where
+        N: Into<String>,
+        P: AsRef<Path>,
+    {
+        let out_file = InputFile::create(path.as_ref())?;
+        self.arg(path.as_ref());
+        self.input_parts
+            .push((name.into(), PartLocation::File { out_file }));
+        Ok(self)
+    }
+
+    /// Sets the escaping of parts delivered as argument or environment value.
+    /// Defaults to [`PartEscaping::TruncateAtNul`].
+    #[cfg(feature = "multipart_inputs")]
+    pub fn part_escaping(&mut self, escaping: PartEscaping) -> &mut Self {
+        self.part_escaping = escaping;
+        self
+    }
+
     /// Adds an argument to the program's commandline.
     pub fn arg<O: AsRef<OsStr>>(&mut self, arg: O) -> &mut CommandExecutorBuilder {
         self.args.push(arg.as_ref().to_owned());
@@ -490,13 +878,19 @@ impl CommandExecutorBuilder {
                 "CommandExecutor::builder: no program set!",
             ));
         };
+        #[cfg(feature = "multipart_inputs")]
+        if !self.input_parts.is_empty() {
+            return Err(Error::illegal_argument(
+                "CommandExecutor::builder: input parts set, use build_multipart instead!",
+            ));
+        }
 
         let mut command = Command::new(program);
         match &self.input_location {
             InputLocation::StdIn => {
                 command.stdin(Stdio::piped());
             }
-            InputLocation::File { .. } | InputLocation::Arg { .. } => {
+            InputLocation::File { .. } | InputLocation::Arg { .. } | InputLocation::Env { .. } => {
                 command.stdin(Stdio::null());
             }
         }
@@ -539,6 +933,71 @@ impl CommandExecutorBuilder {
     }
 }
 
+#[cfg(feature = "multipart_inputs")]
+impl CommandExecutorBuilder {
+    /// Builds the `CommandExecutor` for [`MultipartInput`]s, delivering each part to the location
+    /// set by `arg_input_part`, `env_input_part`, `stdin_input_part`, or `file_input_part`.
+    pub fn build_multipart<OT, S, I>(
+        &self,
+        observers: OT,
+    ) -> Result<CommandExecutor<OT, S, MultipartCommandConfigurator>, Error>
+    where
+        OT: MatchName + ObserversTuple<S>,
+        S: UsesInput<Input = MultipartInput<I>>,
+        I: Input + HasTargetBytes,
+    {
+        let Some(program) = &self.program else {
+            return Err(Error::illegal_argument(
+                "CommandExecutor::builder: no program set!",
+            ));
+        };
+        if self.input_parts.is_empty() {
+            return Err(Error::illegal_argument(
+                "CommandExecutor::builder: no input parts set!",
+            ));
+        }
+        if self.input_location != InputLocation::StdIn {
+            return Err(Error::illegal_argument(
+                "CommandExecutor::builder: the input location can't be combined with input parts!",
+            ));
+        }
+        let stdin_parts = self
+            .input_parts
+            .iter()
+            .filter(|(_, location)| *location == PartLocation::StdIn)
+            .count();
+        if stdin_parts > 1 {
+            return Err(Error::illegal_argument(
+                "CommandExecutor::builder: only one part can be delivered via stdin!",
+            ));
+        }
+
+        let mut command = Command::new(program);
+        command.args(&self.args);
+        command.envs(
+            self.envs
+                .iter()
+                .map(|(k, v)| (k.as_os_str(), v.as_os_str())),
+        );
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+
+        let configurator = MultipartCommandConfigurator {
+            debug_child: self.debug_child,
+            stdout_observer: self.stdout.clone(),
+            stderr_observer: self.stderr.clone(),
+            timeout: self.timeout,
+            parts: self.input_parts.clone(),
+            escaping: self.part_escaping,
+            command,
+        };
+        Ok(<MultipartCommandConfigurator as CommandConfigurator<
+            S::Input,
+        >>::into_executor::<OT, S>(configurator, observers))
+    }
+}
+
 /// A `CommandConfigurator` takes care of creating and spawning a [`std::process::Command`] for the [`CommandExecutor`].
 /// # Example
 #[cfg_attr(all(feature = "std", unix), doc = " ```")]
@@ -606,6 +1065,7 @@ pub trait CommandConfigurator<I>: Sized {
         CommandExecutor {
             configurer: self,
             observers,
+            last_child_pid: None,
             phantom: PhantomData,
         }
     }
@@ -613,11 +1073,13 @@ pub trait CommandConfigurator<I>: Sized {
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "multipart_inputs")]
+    use crate::inputs::MultipartInput;
     use crate::{
         events::SimpleEventManager,
         executors::{
-            command::{CommandExecutor, InputLocation},
-            Executor,
+            command::{CommandExecutor, InputLocation, PartEscaping},
+            Executor, ExitKind,
         },
         fuzzer::test::NopFuzzer,
         inputs::BytesInput,
@@ -649,4 +1111,96 @@ mod tests {
             )
             .unwrap();
     }
+
+    #[test]
+    #[cfg(unix)]
+    #[cfg_attr(miri, ignore)]
+    fn test_builder_input_locations() {
+        let mut mgr = SimpleEventManager::new(SimpleMonitor::new(|status| {
+            log::info!("{status}");
+        }));
+        let input = BytesInput::new(b"test\0ignored".to_vec());
+
+        let mut executor = CommandExecutor::builder();
+        executor
+            .program("sh")
+            .arg("-c")
+            .arg("test \"$INPUT\" = test || kill -SEGV $$")
+            .env_input("INPUT");
+        let mut executor = executor.build(()).unwrap();
+        let exit_kind = executor
+            .run_target(
+                &mut NopFuzzer::new(),
+                &mut NopState::new(),
+                &mut mgr,
+                &input,
+            )
+            .unwrap();
+        assert_eq!(exit_kind, ExitKind::Ok);
+
+        let mut executor = CommandExecutor::builder();
+        executor
+            .program("sh")
+            .afl_args([
+                "-c",
+                "test \"$(head -c 4 \"${0#--in=}\")\" = test || kill -SEGV $$",
+                "--in=@@",
+            ])
+            .unwrap();
+        let mut executor = executor.build(()).unwrap();
+        let exit_kind = executor
+            .run_target(
+                &mut NopFuzzer::new(),
+                &mut NopState::new(),
+                &mut mgr,
+                &input,
+            )
+            .unwrap();
+        assert_eq!(exit_kind, ExitKind::Ok);
+    }
+
+    #[test]
+    fn test_part_escaping() {
+        assert_eq!(PartEscaping::Raw.escape(b"a\0b"), b"a\0b");
+        assert_eq!(PartEscaping::TruncateAtNul.escape(b"a\0b"), b"a");
+        assert_eq!(PartEscaping::StripNul.escape(b"a\0b"), b"ab");
+        assert_eq!(
+            PartEscaping::ShellQuote.escape(b"it's $x"),
+            b"'it'\\''s $x'"
+        );
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "multipart_inputs"))]
+    #[cfg_attr(miri, ignore)]
+    fn test_builder_multipart() {
+        let mut mgr = SimpleEventManager::new(SimpleMonitor::new(|status| {
+            log::info!("{status}");
+        }));
+
+        let mut executor = CommandExecutor::builder();
+        executor
+            .program("sh")
+            .arg("-c")
+            .arg("test \"$0\" = arg && test \"$PART\" = env && test \"$(cat)\" = stdin || kill -SEGV $$")
+            .arg_input_part("arg")
+            .env_input_part("env", "PART")
+            .stdin_input_part("stdin");
+        let mut executor = executor.build_multipart(()).unwrap();
+
+        let input = MultipartInput::from([
+            ("arg", BytesInput::new(b"arg\0ignored".to_vec())),
+            ("env", BytesInput::new(b"env".to_vec())),
+            ("stdin", BytesInput::new(b"stdin".to_vec())),
+        ]);
+        let exit_kind = executor
+            .run_target(
+                &mut NopFuzzer::new(),
+                &mut NopState::new(),
+                &mut mgr,
+                &input,
+            )
+            .unwrap();
+        assert_eq!(exit_kind, ExitKind::Ok);
+    }
 }
@@ -0,0 +1,413 @@
+//! The [`CoreDumpExecutor`] wraps an executor running the target in a child process, such as the
+//! [`crate::executors::ForkserverExecutor`] or the [`crate::executors::CommandExecutor`], and finds
+//! the core dump of each crash. The [`crate::feedbacks::CoreDumpFeedback`] wraps the objective feedback and collects
+//! the core dump of each objective, named after its input, as `<input name>.core`. The core dumps
+//! of crashes that are no objective, e.g. duplicates, are deleted.
+//!
+//! The kernel writes the core dumps where the `core_pattern` says (`/proc/sys/kernel/core_pattern`
+//! on Linux). The core dump of a run is found by the pid of its child, so the pattern needs a `%p`,
+//! e.g. `echo '/tmp/cores/core.%p' | sudo tee /proc/sys/kernel/core_pattern`, unless
+//! `core_uses_pid` is set. Core dumps piped to a helper, such as `systemd-coredump`, can't be
+//! collected. The target also needs a core size limit, see [`enable_core_dumps`].
+//!
+//! Use the directory of the objective corpus as output directory to keep each core next to its
+//! input.
+
+use alloc::string::{String, ToString};
+use core::mem;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use libafl_bolts::{impl_serdeany, tuples::RefIndexable};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executors::{Executor, ExitKind, HasObservers},
+    observers::UsesObservers,
+    state::UsesState,
+    Error, HasMetadata,
+};
+
+/// Executors running the target in a child process, which know the pid of the last run
+pub trait HasChildPid {
+    /// The pid of the child process of the last run, also after it exited
+    fn last_child_pid(&self) -> Option<i32>;
+}
+
+/// Where the kernel writes core dumps to, when the target crashes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoreSource {
+    /// The directory the core dumps are written to
+    pub dir: PathBuf,
+    /// The file name pattern of the core dumps, with `core_pattern` specifiers, including `%p`
+    pub pattern: String,
+}
+
+impl CoreSource {
+    /// Creates a new [`CoreSource`] for core dumps named after the pattern, which contains the
+    /// pid as `%p`
+    pub fn new<P>(dir: P, pattern: &str) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        if !pattern.replace("%%", "").contains("%p") {
+            return Err(Error::illegal_argument(format!(
+                "The core pattern {pattern} has no pid (%p), so core dumps can't be told apart"
+            )));
+        }
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            pattern: pattern.into(),
+        })
+    }
+
+    /// Parses a `core_pattern`. A relative pattern is relative to the current working directory,
+    /// which the target inherits.
+    pub fn from_core_pattern(pattern: &str) -> Result<Self, Error> {
+        let pattern = pattern.trim();
+        if pattern.starts_with('|') {
+            return Err(Error::illegal_state(format!(
+                "Core dumps are piped to {pattern}, set the core_pattern to a path to collect them"
+            )));
+        }
+        let path = Path::new(pattern);
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if dir.to_string_lossy().contains('%') {
+            return Err(Error::illegal_argument(format!(
+                "Unsupported core_pattern {pattern}, only the file name may vary"
+            )));
+        }
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| Error::illegal_argument(format!("Invalid core_pattern {pattern}")))?;
+        Self::new(dir, file_name)
+    }
+
+    /// Reads the [`CoreSource`] from the `core_pattern` of the system, which appends the pid
+    /// if `core_uses_pid` is set
+    #[cfg(target_os = "linux")]
+    pub fn from_system() -> Result<Self, Error> {
+        let mut pattern = fs::read_to_string("/proc/sys/kernel/core_pattern")?
+            .trim()
+            .to_string();
+        let uses_pid = fs::read_to_string("/proc/sys/kernel/core_uses_pid")
+            .is_ok_and(|uses_pid| uses_pid.trim() == "1");
+        if uses_pid && !pattern.starts_with('|') && !pattern.replace("%%", "").contains("%p") {
+            pattern.push_str(".%p");
+        }
+        Self::from_core_pattern(&pattern)
+    }
+
+    /// The default [`CoreSource`] of the system
+    #[cfg(not(target_os = "linux"))]
+    pub fn from_system() -> Result<Self, Error> {
+        Self::new("/cores", "core.%p")
+    }
+
+    /// Finds the core dump of the process with the pid
+    pub fn find(&self, pid: i32) -> Result<Option<PathBuf>, Error> {
+        let pid = pid.to_string();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| matches_pattern(&self.pattern, name, &pid))
+                && entry.metadata()?.is_file()
+            {
+                return Ok(Some(entry.path()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Checks if the file name matches the `core_pattern` file name, with `%p` as the pid and any
+/// other specifier matching anything
+fn matches_pattern(pattern: &str, name: &str, pid: &str) -> bool {
+    let Some(specifier) = pattern.find('%') else {
+        return pattern == name;
+    };
+    let Some(name) = name.strip_prefix(&pattern[..specifier]) else {
+        return false;
+    };
+    let mut rest = pattern[specifier + 1..].chars();
+    let kind = rest.next();
+    let rest = rest.as_str();
+    match kind {
+        Some('%') => name
+            .strip_prefix('%')
+            .is_some_and(|name| matches_pattern(rest, name, pid)),
+        Some('p') => name
+            .strip_prefix(pid)
+            .is_some_and(|name| matches_pattern(rest, name, pid)),
+        _ => name
+            .char_indices()
+            .map(|(idx, _)| idx)
+            .chain([name.len()])
+            .any(|idx| matches_pattern(rest, &name[idx..], pid)),
+    }
+}
+
+/// The core dump of the last crash, waiting for the objective feedback
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PendingCoreDumpMetadata {
+    /// The path of the core dump, where the kernel wrote it
+    pub path: Option<PathBuf>,
+}
+
+impl_serdeany!(PendingCoreDumpMetadata);
+
+/// Lifts the core size limit of the current process as far as allowed, so that the targets it
+/// spawns afterwards write core dumps
+pub fn enable_core_dumps() -> Result<(), Error> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // # Safety
+    // Only reads and writes the given limit
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_CORE, &mut limit) != 0 {
+            return Err(Error::last_os_error("Could not get the core size limit"));
+        }
+        limit.rlim_cur = limit.rlim_max;
+        if libc::setrlimit(libc::RLIMIT_CORE, &limit) != 0 {
+            return Err(Error::last_os_error("Could not set the core size limit"));
+        }
+    }
+    Ok(())
+}
+
+/// Wraps an executor running the target in a child process, finding the core dump of each
+/// crash, see the [module docs](self). The [`crate::feedbacks::CoreDumpFeedback`] collects it if the crash is an
+/// objective, else it is deleted with the next crash.
+#[derive(Debug)]
+pub struct CoreDumpExecutor<E> {
+    executor: E,
+    source: CoreSource,
+}
+
+impl<E> CoreDumpExecutor<E> {
+    /// Wraps the `executor`, finding the core dumps of crashes in the `source`
+    pub fn new(executor: E, source: CoreSource) -> Self {
+        Self { executor, source }
+    }
+
+    /// The wrapped executor
+    pub fn executor(&self) -> &E {
+        &self.executor
+    }
+
+    /// The wrapped executor (mutable)
+    pub fn executor_mut(&mut self) -> &mut E {
+        &mut self.executor
+    }
+
+    /// Where the core dumps are written to
+    pub fn source(&self) -> &CoreSource {
+        &self.source
+    }
+}
+
+impl<E, EM, Z> Executor<EM, Z> for CoreDumpExecutor<E>
+where
+    E: Executor<EM, Z> + HasChildPid,
+    EM: UsesState<State = Self::State>,
+    Z: UsesState<State = Self::State>,
+    Self::State: HasMetadata,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        let exit_kind = self.executor.run_target(fuzzer, state, mgr, input)?;
+        if exit_kind == ExitKind::Crash {
+            let core = match self.executor.last_child_pid() {
+                Some(pid) => self.source.find(pid)?,
+                None => None,
+            };
+            if core.is_none() {
+                log::warn!(
+                    "No core dump found in {} after a crash",
+                    self.source.dir.display()
+                );
+            }
+            let pending = state.metadata_or_insert_with(PendingCoreDumpMetadata::default);
+            if let Some(unclaimed) = mem::replace(&mut pending.path, core) {
+                // Left over from a crash no objective feedback looked at
+                remove_core(&unclaimed)?;
+            }
+        }
+        Ok(exit_kind)
+    }
+
+    fn restart_target(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.executor.restart_target(state)
+    }
+}
+
+/// Deletes a core dump, which may be gone already
+pub(crate) fn remove_core(core: &Path) -> Result<(), Error> {
+    match fs::remove_file(core) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+impl<E> UsesState for CoreDumpExecutor<E>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E> UsesObservers for CoreDumpExecutor<E>
+where
+    E: UsesObservers,
+{
+    type Observers = E::Observers;
+}
+
+impl<E> HasObservers for CoreDumpExecutor<E>
+where
+    E: HasObservers,
+{
+    #[inline]
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        self.executor.observers()
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        self.executor.observers_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::marker::PhantomData;
+    use std::{env::temp_dir, fs};
+
+    use super::{
+        matches_pattern, CoreDumpExecutor, CoreSource, HasChildPid, PendingCoreDumpMetadata,
+    };
+    use crate::{
+        events::NopEventManager,
+        executors::{Executor, ExitKind},
+        fuzzer::test::NopFuzzer,
+        inputs::BytesInput,
+        state::{test::test_std_state, HasExecutions, State, UsesState},
+        Error, HasMetadata,
+    };
+
+    /// Pretends to run a child with the given pid, which crashes
+    struct CrashingChild<S> {
+        pid: i32,
+        phantom: PhantomData<S>,
+    }
+
+    impl<S> UsesState for CrashingChild<S>
+    where
+        S: State,
+    {
+        type State = S;
+    }
+
+    impl<EM, S, Z> Executor<EM, Z> for CrashingChild<S>
+    where
+        EM: UsesState<State = S>,
+        S: State + HasExecutions,
+        Z: UsesState<State = S>,
+    {
+        fn run_target(
+            &mut self,
+            _fuzzer: &mut Z,
+            state: &mut S,
+            _mgr: &mut EM,
+            _input: &S::Input,
+        ) -> Result<ExitKind, Error> {
+            *state.executions_mut() += 1;
+            Ok(ExitKind::Crash)
+        }
+    }
+
+    impl<S> HasChildPid for CrashingChild<S> {
+        fn last_child_pid(&self) -> Option<i32> {
+            Some(self.pid)
+        }
+    }
+
+    #[test]
+    fn test_core_pattern() {
+        assert_eq!(
+            CoreSource::from_core_pattern("/tmp/cores/core.%e.%p\n").unwrap(),
+            CoreSource::new("/tmp/cores", "core.%e.%p").unwrap()
+        );
+        assert_eq!(
+            CoreSource::from_core_pattern("core.%p").unwrap(),
+            CoreSource::new(".", "core.%p").unwrap()
+        );
+        assert!(CoreSource::from_core_pattern("core").is_err());
+        assert!(CoreSource::from_core_pattern("core.%%p").is_err());
+        assert!(CoreSource::from_core_pattern("/tmp/%e/core.%p").is_err());
+        assert!(CoreSource::from_core_pattern("|/usr/lib/systemd/systemd-coredump %P").is_err());
+
+        assert!(matches_pattern("core.%e.%p", "core.target.42", "42"));
+        assert!(matches_pattern("core.%p.%t", "core.42.1700000000", "42"));
+        assert!(matches_pattern("%%core.%p", "%core.42", "42"));
+        assert!(!matches_pattern("core.%e.%p", "core.target.420", "42"));
+        assert!(!matches_pattern("core.%p", "core.4", "42"));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_core_dump_executor() {
+        let dir = temp_dir().join(format!("libafl_core_dump_executor_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("core.42"), b"core").unwrap();
+        fs::write(dir.join("core.4242"), b"core").unwrap();
+
+        let mut executor = CoreDumpExecutor::new(
+            CrashingChild {
+                pid: 42,
+                phantom: PhantomData,
+            },
+            CoreSource::new(&dir, "core.%p").unwrap(),
+        );
+        let mut fuzzer = NopFuzzer::new();
+        let mut state = test_std_state::<BytesInput>();
+        let mut mgr = NopEventManager::new();
+        let input = BytesInput::new(vec![0]);
+
+        executor
+            .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+            .unwrap();
+        let pending = state.metadata::<PendingCoreDumpMetadata>().unwrap();
+        assert_eq!(pending.path, Some(dir.join("core.42")));
+
+        // An unclaimed core dump is deleted with the next crash
+        executor.executor_mut().pid = 4242;
+        executor
+            .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+            .unwrap();
+        let pending = state.metadata::<PendingCoreDumpMetadata>().unwrap();
+        assert_eq!(pending.path, Some(dir.join("core.4242")));
+        assert!(!dir.join("core.42").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
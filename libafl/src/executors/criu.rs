@@ -0,0 +1,422 @@
+//! The [`CriuExecutor`] skips the expensive initialization of a target, by restoring the process
+//! from a snapshot taken by [CRIU](https://criu.org) before each execution, instead of starting it
+//! from scratch.
+//!
+//! The target signals that it is initialized and ready for an input by stopping itself. The
+//! executor then dumps it to the images directory. For each execution, the input is written to
+//! the input file, the target is restored from the images, continued, and reads the input from the
+//! file whose path is in the [`CRIU_INPUT_ENV`] environment variable:
+//!
+//! ```c
+//! int main() {
+//!   expensive_init();
+//!   raise(SIGSTOP); // snapshot here
+//!   char *input = read_file(getenv("LIBAFL_CRIU_INPUT"));
+//!   return run(input);
+//! }
+//! ```
+//!
+//! CRIU needs root, or `CAP_CHECKPOINT_RESTORE`. Restoring keeps the PID of the target, so it
+//! only works while no other process took it in the meantime.
+//! Coverage maps have to be mapped from files, e.g. in `/dev/shm`, so the restored target still
+//! shares them with the fuzzer: CRIU restores shared file mappings from the file.
+
+use alloc::string::{String, ToString};
+use core::{marker::PhantomData, time::Duration};
+use std::{
+    env::temp_dir,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::Instant,
+};
+
+use libafl_bolts::{tuples::RefIndexable, AsSlice};
+use nix::{
+    sys::{
+        signal::{kill, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::Pid,
+};
+
+use crate::{
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::HasTargetBytes,
+    observers::{ObserversTuple, UsesObservers},
+    state::{HasExecutions, State, UsesState},
+    Error,
+};
+
+/// The environment variable with the path of the input file, read by the restored target
+pub const CRIU_INPUT_ENV: &str = "LIBAFL_CRIU_INPUT";
+/// The default timeout of an execution, after the target is restored
+pub const DEFAULT_CRIU_TIMEOUT: Duration = Duration::from_secs(5);
+/// The default time the target gets for its initialization, until it stops itself
+pub const DEFAULT_CRIU_STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The time between two checks if the target stopped or exited
+const WAIT_POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Waits until the child stopped itself, failing if it exits or takes longer than `timeout`
+fn wait_stopped(pid: Pid, timeout: Duration) -> Result<(), Error> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match waitpid(pid, Some(WaitPidFlag::WUNTRACED | WaitPidFlag::WNOHANG))? {
+            WaitStatus::Stopped(..) => return Ok(()),
+            WaitStatus::StillAlive => {
+                if Instant::now() >= deadline {
+                    let _ = kill(pid, Signal::SIGKILL);
+                    let _ = waitpid(pid, None);
+                    return Err(Error::illegal_state(format!(
+                        "The target did not stop itself within {timeout:?}"
+                    )));
+                }
+                thread::sleep(WAIT_POLL_INTERVAL);
+            }
+            status => {
+                return Err(Error::illegal_state(format!(
+                    "The target ended with {status:?} before it was ready for a snapshot"
+                )))
+            }
+        }
+    }
+}
+
+/// Waits until the child exits, killing it after `timeout`
+fn wait_exit(pid: Pid, timeout: Duration) -> Result<ExitKind, Error> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match waitpid(pid, Some(WaitPidFlag::WNOHANG))? {
+            WaitStatus::StillAlive => {
+                if Instant::now() >= deadline {
+                    let _ = kill(pid, Signal::SIGKILL);
+                    waitpid(pid, None)?;
+                    return Ok(ExitKind::Timeout);
+                }
+                thread::sleep(WAIT_POLL_INTERVAL);
+            }
+            WaitStatus::Signaled(..) => return Ok(ExitKind::Crash),
+            WaitStatus::Exited(..) => return Ok(ExitKind::Ok),
+            // Stopped or continued, keep waiting
+            _ => {}
+        }
+    }
+}
+
+/// Restores a target from a CRIU snapshot for each execution, see the [module docs](self).
+/// Use [`CriuExecutor::builder`] to create it.
+#[derive(Debug)]
+pub struct CriuExecutor<OT, S> {
+    criu: PathBuf,
+    images_dir: PathBuf,
+    input_file: PathBuf,
+    timeout: Duration,
+    pid: Pid,
+    observers: OT,
+    phantom: PhantomData<S>,
+}
+
+impl CriuExecutor<(), ()> {
+    /// Creates a builder for a [`CriuExecutor`]
+    #[must_use]
+    pub fn builder() -> CriuExecutorBuilder {
+        CriuExecutorBuilder::new()
+    }
+}
+
+impl<OT, S> CriuExecutor<OT, S> {
+    /// The directory the snapshot of the target is stored in
+    #[must_use]
+    pub fn images_dir(&self) -> &Path {
+        &self.images_dir
+    }
+
+    /// The PID of the target, kept across restores
+    #[must_use]
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// Restores the target from the snapshot, as child of the fuzzer
+    fn restore(&self) -> Result<(), Error> {
+        run_criu(
+            &self.criu,
+            [
+                OsStr::new("restore"),
+                OsStr::new("-D"),
+                self.images_dir.as_os_str(),
+                OsStr::new("--shell-job"),
+                OsStr::new("--restore-detached"),
+                OsStr::new("--restore-sibling"),
+            ],
+        )
+    }
+}
+
+/// Runs `criu`, failing with its log if it fails
+fn run_criu<'a, IT>(criu: &Path, args: IT) -> Result<(), Error>
+where
+    IT: IntoIterator<Item = &'a OsStr>,
+{
+    let output = Command::new(criu)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(Error::illegal_state(format!(
+            "criu failed with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+impl<OT, S> UsesState for CriuExecutor<OT, S>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<OT, S> UsesObservers for CriuExecutor<OT, S>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    type Observers = OT;
+}
+
+impl<OT, S> HasObservers for CriuExecutor<OT, S>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+impl<EM, OT, S, Z> Executor<EM, Z> for CriuExecutor<OT, S>
+where
+    EM: UsesState<State = S>,
+    OT: ObserversTuple<S>,
+    S: State + HasExecutions,
+    S::Input: HasTargetBytes,
+    Z: UsesState<State = S>,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut Self::State,
+        _mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        *state.executions_mut() += 1;
+
+        fs::write(&self.input_file, input.target_bytes().as_slice())?;
+        self.restore()?;
+        // The target was dumped stopped, and may be restored stopped
+        kill(self.pid, Signal::SIGCONT)?;
+        wait_exit(self.pid, self.timeout)
+    }
+
+    fn restart_target(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        // The next run restores a fresh target, only a leftover of a failed run has to go
+        if kill(self.pid, Signal::SIGKILL).is_ok() {
+            let _ = waitpid(self.pid, None);
+        }
+        Ok(())
+    }
+}
+
+/// The builder for a [`CriuExecutor`]
+#[derive(Debug)]
+pub struct CriuExecutorBuilder {
+    command: Option<Command>,
+    criu: PathBuf,
+    images_dir: Option<PathBuf>,
+    input_file: Option<PathBuf>,
+    timeout: Duration,
+    startup_timeout: Duration,
+}
+
+impl Default for CriuExecutorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CriuExecutorBuilder {
+    /// Creates a new [`CriuExecutorBuilder`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            command: None,
+            criu: PathBuf::from("criu"),
+            images_dir: None,
+            input_file: None,
+            timeout: DEFAULT_CRIU_TIMEOUT,
+            startup_timeout: DEFAULT_CRIU_STARTUP_TIMEOUT,
+        }
+    }
+
+    /// Sets the command starting the target
+    #[must_use]
+    pub fn command(mut self, command: Command) -> Self {
+        self.command = Some(command);
+        self
+    }
+
+    /// Uses the `criu` at this path, instead of the one in the `PATH`
+    #[must_use]
+    pub fn criu<P>(mut self, criu: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.criu = criu.as_ref().to_path_buf();
+        self
+    }
+
+    /// Sets the directory the snapshot is stored in, defaults to a new directory in the
+    /// temporary directory. A `tmpfs` makes restoring faster.
+    #[must_use]
+    pub fn images_dir<P>(mut self, images_dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.images_dir = Some(images_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the file the input is written to, defaults to a file in the temporary directory
+    #[must_use]
+    pub fn input_file<P>(mut self, input_file: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.input_file = Some(input_file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the timeout of an execution
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the time the target gets for its initialization, until it stops itself
+    #[must_use]
+    pub fn startup_timeout(mut self, startup_timeout: Duration) -> Self {
+        self.startup_timeout = startup_timeout;
+        self
+    }
+
+    /// Starts the target, waits until it is initialized and takes the snapshot
+    pub fn build<OT, S>(self, observers: OT) -> Result<CriuExecutor<OT, S>, Error>
+    where
+        OT: ObserversTuple<S>,
+        S: State,
+    {
+        let Some(mut command) = self.command else {
+            return Err(Error::illegal_argument(
+                "CriuExecutor::builder: no target command set!",
+            ));
+        };
+        let tag = format!("libafl_criu_{}", std::process::id());
+        let images_dir = self.images_dir.unwrap_or_else(|| temp_dir().join(&tag));
+        let input_file = self
+            .input_file
+            .unwrap_or_else(|| temp_dir().join(format!("{tag}_input")));
+        fs::create_dir_all(&images_dir)?;
+        // The restored target reads the input file, so it has to exist when it is dumped
+        fs::write(&input_file, b"")?;
+
+        let child = command
+            .env(CRIU_INPUT_ENV, &input_file)
+            .stdin(Stdio::null())
+            .spawn()?;
+        let pid = Pid::from_raw(child.id().try_into()?);
+        wait_stopped(pid, self.startup_timeout)?;
+
+        let pid_arg = pid.to_string();
+        let dumped = run_criu(
+            &self.criu,
+            [
+                OsStr::new("dump"),
+                OsStr::new("-t"),
+                OsStr::new(&pid_arg),
+                OsStr::new("-D"),
+                images_dir.as_os_str(),
+                OsStr::new("--shell-job"),
+            ],
+        );
+        if dumped.is_err() {
+            let _ = kill(pid, Signal::SIGKILL);
+        }
+        // CRIU kills the target after the dump, reap it to free its PID for the restore
+        waitpid(pid, None)?;
+        dumped?;
+        log::info!("Took a snapshot of the target in {}", images_dir.display());
+
+        Ok(CriuExecutor {
+            criu: self.criu,
+            images_dir,
+            input_file,
+            timeout: self.timeout,
+            pid,
+            observers,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::process::Command;
+
+    use nix::{
+        sys::signal::{kill, Signal},
+        unistd::Pid,
+    };
+
+    use super::{wait_exit, wait_stopped};
+    use crate::executors::ExitKind;
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_criu_wait() {
+        let child = Command::new("sh")
+            .args(["-c", "kill -STOP $$; kill -SEGV $$"])
+            .spawn()
+            .unwrap();
+        let pid = Pid::from_raw(child.id().try_into().unwrap());
+        wait_stopped(pid, Duration::from_secs(5)).unwrap();
+        kill(pid, Signal::SIGCONT).unwrap();
+        assert_eq!(
+            wait_exit(pid, Duration::from_secs(5)).unwrap(),
+            ExitKind::Crash
+        );
+
+        let child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from_raw(child.id().try_into().unwrap());
+        assert!(wait_stopped(pid, Duration::from_millis(10)).is_err());
+
+        let child = Command::new("sleep").arg("10").spawn().unwrap();
+        let pid = Pid::from_raw(child.id().try_into().unwrap());
+        assert_eq!(
+            wait_exit(pid, Duration::from_millis(10)).unwrap(),
+            ExitKind::Timeout
+        );
+    }
+}
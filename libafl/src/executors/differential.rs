@@ -2,28 +2,177 @@
 //! It wraps two executors that will be run after each other with the same input.
 //! In comparison to the [`crate::executors::CombinedExecutor`] it also runs the secondary executor in `run_target`.
 //!
-use core::{cell::UnsafeCell, fmt::Debug, ptr};
+//! After each run, the [`DiffExecutor`] stores a [`DiffReport`] in the state metadata, summarizing
+//! how the two runs diverged, for the [`crate::feedbacks::differential::DiffReportFeedback`].
+use alloc::{borrow::Cow, boxed::Box, vec::Vec};
+use core::{
+    cell::UnsafeCell,
+    fmt::{self, Debug, Formatter},
+    ptr,
+};
 
 use libafl_bolts::{
     ownedref::OwnedMutPtr,
-    tuples::{MatchName, RefIndexable},
+    tuples::{Handle, MatchName, MatchNameRef, RefIndexable},
 };
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "std")]
+use crate::observers::StdOutObserver;
 use crate::{
     executors::{Executor, ExitKind, HasObservers},
     inputs::UsesInput,
-    observers::{DifferentialObserversTuple, ObserversTuple, UsesObservers},
+    observers::{DifferentialObserversTuple, ObserverWithHashField, ObserversTuple, UsesObservers},
     state::UsesState,
-    Error,
+    Error, HasMetadata,
 };
 
+/// How the stdout of the two runs of a [`DiffExecutor`] differs
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StdoutDiff {
+    /// The offset of the first differing byte
+    pub offset: usize,
+    /// The length of the stdout of the primary executor
+    pub primary_len: usize,
+    /// The length of the stdout of the secondary executor
+    pub secondary_len: usize,
+}
+
+impl StdoutDiff {
+    /// Compares the stdout of two runs, returning how they differ, if they do
+    #[must_use]
+    pub fn between(primary: &[u8], secondary: &[u8]) -> Option<Self> {
+        if primary == secondary {
+            return None;
+        }
+        let offset = primary
+            .iter()
+            .zip(secondary)
+            .take_while(|(a, b)| a == b)
+            .count();
+        Some(Self {
+            offset,
+            primary_len: primary.len(),
+            secondary_len: secondary.len(),
+        })
+    }
+}
+
+/// How the two runs of the last input of a [`DiffExecutor`] diverged, stored in the state metadata
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DiffReport {
+    /// The exit kind of the primary executor
+    pub primary: ExitKind,
+    /// The exit kind of the secondary executor
+    pub secondary: ExitKind,
+    /// The names of the compared observers whose content diverged,
+    /// see [`DiffExecutor::compare_observers`]
+    pub diverging_observers: Vec<Cow<'static, str>>,
+    /// How the stdout diverged, if compared, see [`DiffExecutor::compare_stdout`]
+    pub stdout_diff: Option<StdoutDiff>,
+}
+
+libafl_bolts::impl_serdeany!(DiffReport);
+
+impl DiffReport {
+    /// Creates a new [`DiffReport`], without any divergence other than the exit kinds
+    #[must_use]
+    pub fn new(primary: ExitKind, secondary: ExitKind) -> Self {
+        Self {
+            primary,
+            secondary,
+            diverging_observers: Vec::new(),
+            stdout_diff: None,
+        }
+    }
+
+    /// If the exit kinds of the two runs differ
+    #[must_use]
+    pub fn exit_kinds_diverge(&self) -> bool {
+        self.primary != self.secondary
+    }
+
+    /// If the two runs diverged in any way
+    #[must_use]
+    pub fn is_divergent(&self) -> bool {
+        self.exit_kinds_diverge()
+            || !self.diverging_observers.is_empty()
+            || self.stdout_diff.is_some()
+    }
+}
+
+/// A comparison of an observer of the primary executor with one of the secondary executor
+struct ObserverComparison<OTA, OTB> {
+    name: Cow<'static, str>,
+    #[allow(clippy::type_complexity)]
+    differs: Box<dyn Fn(&OTA, &OTB) -> bool>,
+}
+
+/// The comparisons of the observers of the two executors, filling the [`DiffReport`]
+struct DiffComparisons<OTA, OTB> {
+    observers: Vec<ObserverComparison<OTA, OTB>>,
+    #[cfg(feature = "std")]
+    stdout: Option<(Handle<StdOutObserver>, Handle<StdOutObserver>)>,
+}
+
+impl<OTA, OTB> DiffComparisons<OTA, OTB>
+where
+    OTA: MatchName,
+    OTB: MatchName,
+{
+    /// Fills the [`DiffReport`] of the last run
+    fn report(
+        &self,
+        report: &mut DiffReport,
+        ret1: ExitKind,
+        ret2: ExitKind,
+        ota: &OTA,
+        otb: &OTB,
+    ) {
+        report.primary = ret1;
+        report.secondary = ret2;
+        report.diverging_observers.clear();
+        for comparison in &self.observers {
+            if (comparison.differs)(ota, otb) {
+                report.diverging_observers.push(comparison.name.clone());
+            }
+        }
+        #[cfg(feature = "std")]
+        {
+            report.stdout_diff = self.stdout.as_ref().and_then(|(h1, h2)| {
+                let stdout1 = ota.get(h1).and_then(|o| o.stdout.as_deref());
+                let stdout2 = otb.get(h2).and_then(|o| o.stdout.as_deref());
+                StdoutDiff::between(stdout1.unwrap_or_default(), stdout2.unwrap_or_default())
+            });
+        }
+    }
+}
+
 /// A [`DiffExecutor`] wraps a primary executor, forwarding its methods, and a secondary one
-#[derive(Debug)]
 pub struct DiffExecutor<A, B, DOT, OTA, OTB> {
     primary: A,
     secondary: B,
     observers: UnsafeCell<ProxyObserversTuple<OTA, OTB, DOT>>,
+    comparisons: DiffComparisons<OTA, OTB>,
+}
+
+impl<A, B, DOT, OTA, OTB> Debug for DiffExecutor<A, B, DOT, OTA, OTB>
+where
+    A: Debug,
+    B: Debug,
+    DOT: Debug,
+    OTA: Debug,
+    OTB: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let comparisons: Vec<_> = self.comparisons.observers.iter().map(|c| &c.name).collect();
+        f.debug_struct("DiffExecutor")
+            .field("primary", &self.primary)
+            .field("secondary", &self.secondary)
+            .field("observers", &self.observers)
+            .field("comparisons", &comparisons)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<A, B, DOT, OTA, OTB> DiffExecutor<A, B, DOT, OTA, OTB> {
@@ -44,9 +193,76 @@ impl<A, B, DOT, OTA, OTB> DiffExecutor<A, B, DOT, OTA, OTB> {
                 secondary: OwnedMutPtr::Ptr(ptr::null_mut()),
                 differential: observers,
             }),
+            comparisons: DiffComparisons {
+                observers: Vec::new(),
+                #[cfg(feature = "std")]
+                stdout: None,
+            },
         }
     }
 
+    /// Compares the observer `primary` of the primary executor with the observer `secondary` of
+    /// the secondary executor after each run. If `differs`, the name of `primary` is listed in
+    /// [`DiffReport::diverging_observers`].
+    #[must_use]
+    pub fn compare_observers<O1, O2, F>(
+        mut self,
+        primary: &Handle<O1>,
+        secondary: &Handle<O2>,
+        differs: F,
+    ) -> Self
+    where
+        OTA: MatchName,
+        OTB: MatchName,
+        O1: 'static,
+        O2: 'static,
+        F: Fn(&O1, &O2) -> bool + 'static,
+    {
+        let (primary, secondary) = (primary.clone(), secondary.clone());
+        self.comparisons.observers.push(ObserverComparison {
+            name: primary.name().clone(),
+            differs: Box::new(move |ota: &OTA, otb: &OTB| {
+                match (ota.get(&primary), otb.get(&secondary)) {
+                    (Some(o1), Some(o2)) => differs(o1, o2),
+                    _ => false,
+                }
+            }),
+        });
+        self
+    }
+
+    /// Compares the hashes of two [`ObserverWithHashField`]s after each run,
+    /// see [`Self::compare_observers`]
+    #[must_use]
+    pub fn compare_observer_hashes<O1, O2>(
+        self,
+        primary: &Handle<O1>,
+        secondary: &Handle<O2>,
+    ) -> Self
+    where
+        OTA: MatchName,
+        OTB: MatchName,
+        O1: ObserverWithHashField + 'static,
+        O2: ObserverWithHashField + 'static,
+    {
+        self.compare_observers(primary, secondary, |o1: &O1, o2: &O2| {
+            o1.hash() != o2.hash()
+        })
+    }
+
+    /// Compares the stdout captured by the two [`StdOutObserver`]s after each run,
+    /// reported as [`DiffReport::stdout_diff`]
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn compare_stdout(
+        mut self,
+        primary: &Handle<StdOutObserver>,
+        secondary: &Handle<StdOutObserver>,
+    ) -> Self {
+        self.comparisons.stdout = Some((primary.clone(), secondary.clone()));
+        self
+    }
+
     /// Retrieve the primary `Executor` that is wrapped by this `DiffExecutor`.
     pub fn primary(&mut self) -> &mut A {
         &mut self.primary
@@ -61,6 +277,7 @@ impl<A, B, DOT, OTA, OTB> DiffExecutor<A, B, DOT, OTA, OTB> {
 impl<A, B, DOT, EM, Z> Executor<EM, Z> for DiffExecutor<A, B, DOT, A::Observers, B::Observers>
 where
     A: Executor<EM, Z> + HasObservers,
+    A::State: HasMetadata,
     B: Executor<EM, Z, State = <Self as UsesState>::State> + HasObservers,
     EM: UsesState<State = <Self as UsesState>::State>,
     DOT: DifferentialObserversTuple<A::Observers, B::Observers, <Self as UsesState>::State>,
@@ -99,6 +316,12 @@ where
         observers
             .differential
             .post_observe_second_all(observers.secondary.as_mut())?;
+
+        let report = state.metadata_or_insert_with(|| DiffReport::new(ret1, ret2));
+        let observers = self.observers.get_mut();
+        let (ota, otb) = (observers.primary.as_ref(), observers.secondary.as_ref());
+        self.comparisons.report(report, ret1, ret2, ota, otb);
+
         if ret1 == ret2 {
             Ok(ret1)
         } else {
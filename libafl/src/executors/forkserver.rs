@@ -1,5 +1,7 @@
 //! Expose an `Executor` based on a `Forkserver` in order to execute AFL/AFL++ binaries
 
+#[cfg(feature = "regex")]
+use alloc::string::String;
 use alloc::{borrow::ToOwned, string::ToString, vec::Vec};
 use core::{
     fmt::{self, Debug, Formatter},
@@ -19,6 +21,7 @@ use std::{
 };
 
 use libafl_bolts::{
+    current_time,
     fs::{get_unique_std_input_file, InputFile},
     os::{dup2, pipes::Pipe},
     ownedref::OwnedSlice,
@@ -37,9 +40,15 @@ use nix::{
 };
 
 #[cfg(feature = "regex")]
-use crate::observers::{get_asan_runtime_flags_with_log_path, AsanBacktraceObserver};
 use crate::{
-    executors::{Executor, ExitKind, HasObservers},
+    executors::sanitizers::Sanitizer,
+    observers::{get_asan_runtime_flags_with_log_path, AsanBacktraceObserver, ASAN_LOG_PATH},
+};
+use crate::{
+    executors::{
+        core_dump::HasChildPid, sanitizers::SanitizerOptions, Executor, ExitKind, HasObservers,
+        TimeoutClock, CPU_TIMEOUT_WALL_FACTOR,
+    },
     inputs::{HasTargetBytes, Input, UsesInput},
     mutators::Tokens,
     observers::{MapObserver, Observer, ObserversTuple, UsesObservers},
@@ -59,6 +68,14 @@ const FS_NEW_OPT_MAPSIZE: i32 = 1_u32 as i32;
 const FS_NEW_OPT_SHDMEM_FUZZ: i32 = 2_u32 as i32;
 #[allow(clippy::cast_possible_wrap)]
 const FS_NEW_OPT_AUTODICT: i32 = 0x00000800_u32 as i32;
+/// `LibAFL` extension: the forkserver remaps the testcase shared memory on request, see
+/// [`FS_CTL_SHDMEM_RESIZE`]
+#[allow(clippy::cast_possible_wrap)]
+const FS_NEW_OPT_SHDMEM_RESIZE: i32 = 0x00001000_u32 as i32;
+/// Sent instead of the `was_killed` flag to request a new testcase shared memory, followed by
+/// its size, the length of its id and the id. The forkserver acknowledges with the same value.
+#[allow(clippy::cast_possible_wrap)]
+const FS_CTL_SHDMEM_RESIZE: i32 = 0x5253495a_u32 as i32;
 
 #[allow(clippy::cast_possible_wrap)]
 const FS_ERROR_MAP_SIZE: i32 = 1_u32 as i32;
@@ -236,6 +253,8 @@ pub struct Forkserver {
     ctl_pipe: Pipe,
     /// Pid of the current forked child (child of the forkserver) during execution
     child_pid: Option<Pid>,
+    /// Pid of the last forked child, kept after the execution
+    last_child_pid: Option<Pid>,
     /// The last status reported to us by the in-target forkserver
     status: i32,
     /// If the last run timed out (in in-target i32)
@@ -394,6 +413,7 @@ impl Forkserver {
             st_pipe,
             ctl_pipe,
             child_pid: None,
+            last_child_pid: None,
             status: 0,
             last_run_timed_out: 0,
             kill_signal,
@@ -444,6 +464,13 @@ impl Forkserver {
     /// Set the child pid
     pub fn set_child_pid(&mut self, child_pid: Pid) {
         self.child_pid = Some(child_pid);
+        self.last_child_pid = Some(child_pid);
+    }
+
+    /// The pid of the last child, also after it exited
+    #[must_use]
+    pub fn last_child_pid(&self) -> Option<Pid> {
+        self.last_child_pid
     }
 
     /// Remove the child pid.
@@ -451,6 +478,12 @@ impl Forkserver {
         self.child_pid = None;
     }
 
+    /// The read end of the status pipe, to wait for multiple forkservers at once
+    #[must_use]
+    pub fn st_read_fd(&self) -> Option<RawFd> {
+        self.st_pipe.read_end()
+    }
+
     /// Read from the st pipe
     pub fn read_st(&mut self) -> Result<(usize, i32), Error> {
         let mut buf: [u8; 4] = [0_u8; 4];
@@ -475,6 +508,26 @@ impl Forkserver {
         Ok(slen)
     }
 
+    /// Asks the forkserver to replace its testcase shared memory with the one of the given id and
+    /// size, see [`FS_NEW_OPT_SHDMEM_RESIZE`]. A stopped persistent child, which still maps the old
+    /// region, is killed by the forkserver.
+    fn request_shmem_resize(&mut self, id: &str, size: usize) -> Result<(), Error> {
+        let message = shmem_resize_message(id, size)?;
+        // The message is written at once, to not interleave with other writes
+        if self.ctl_pipe.write(&message)? != message.len() {
+            return Err(Error::unknown(
+                "Unable to request a new testcase shared memory from the fork server".to_string(),
+            ));
+        }
+        let (read_len, ack) = self.read_st()?;
+        if read_len != 4 || ack != FS_CTL_SHDMEM_RESIZE {
+            return Err(Error::unknown(format!(
+                "Fork server failed to map the new testcase shared memory ({ack:x})"
+            )));
+        }
+        Ok(())
+    }
+
     /// Read a message from the child process.
     pub fn read_st_timed(&mut self, timeout: &TimeSpec) -> Result<Option<i32>, Error> {
         let mut buf: [u8; 4] = [0_u8; 4];
@@ -515,6 +568,198 @@ impl Forkserver {
     }
 }
 
+/// The message of a [`FS_CTL_SHDMEM_RESIZE`] request
+fn shmem_resize_message(id: &str, size: usize) -> Result<Vec<u8>, Error> {
+    let size = u32::try_from(size)
+        .map_err(|_| Error::illegal_argument("The testcase shared memory exceeds 4 GiB"))?;
+    let id_len = u32::try_from(id.len())
+        .map_err(|_| Error::illegal_argument("The shared memory id is too long"))?;
+    let mut message = Vec::with_capacity(12 + id.len());
+    message.extend_from_slice(&FS_CTL_SHDMEM_RESIZE.to_ne_bytes());
+    message.extend_from_slice(&size.to_ne_bytes());
+    message.extend_from_slice(&id_len.to_ne_bytes());
+    message.extend_from_slice(id.as_bytes());
+    Ok(message)
+}
+
+/// The options a forkserver announced during the handshake
+#[derive(Debug, Default)]
+struct ForkserverOptions {
+    /// The size of the coverage map, rounded up to 64 bytes
+    map_size: Option<usize>,
+    /// If the target reads its inputs from the testcase shared memory
+    shmem_fuzz: bool,
+    /// If the forkserver remaps the testcase shared memory on request
+    shmem_resize: bool,
+    /// The raw autodictionary of the target
+    autodict: Option<Vec<u8>>,
+}
+
+/// Performs the handshake with a freshly spawned forkserver, returning the announced options
+#[allow(clippy::pedantic)]
+fn forkserver_handshake(forkserver: &mut Forkserver) -> Result<ForkserverOptions, Error> {
+    let (rlen, version_status) = forkserver.read_st()?; // Initial handshake, read 4-bytes hello message from the forkserver.
+
+    if rlen != 4 {
+        return Err(Error::unknown("Failed to start a forkserver".to_string()));
+    }
+
+    if (version_status & FS_NEW_ERROR) == FS_NEW_ERROR {
+        report_error_and_exit(version_status & 0x0000ffff)?;
+    }
+
+    let keep = version_status;
+    let version: u32 = version_status as u32 - 0x41464c00_u32;
+    if (0x41464c00..=0x41464cff).contains(&version_status) {
+        match version {
+            0 => {
+                return Err(Error::unknown("Fork server version is not assigned, this should not happen. Recompile target."));
+            }
+            FS_NEW_VERSION_MIN..=FS_NEW_VERSION_MAX => {
+                // good, do nothing
+            }
+            _ => {
+                return Err(Error::unknown(
+                    "Fork server version is not supported. Recompile the target.",
+                ));
+            }
+        }
+    }
+
+    let xored_version_status = (version_status as u32 ^ 0xffffffff) as i32;
+
+    let send_len = forkserver.write_ctl(xored_version_status)?;
+    if send_len != 4 {
+        return Err(Error::unknown("Writing to forkserver failed.".to_string()));
+    }
+
+    log::info!(
+        "All right - new fork server model version {} is up",
+        version
+    );
+
+    let (read_len, status) = forkserver.read_st()?;
+    if read_len != 4 {
+        return Err(Error::unknown(
+            "Reading from forkserver failed.".to_string(),
+        ));
+    }
+
+    let mut options = ForkserverOptions::default();
+    if status & FS_NEW_OPT_MAPSIZE == FS_NEW_OPT_MAPSIZE {
+        // When 0, we assume that map_size was filled by the user or const
+        /* TODO autofill map size from the observer
+
+        if map_size > 0 {
+            self.map_size = Some(map_size as usize);
+        }
+        */
+        let (read_len, mut map_size) = forkserver.read_st()?;
+        if read_len != 4 {
+            return Err(Error::unknown(
+                "Failed to read map size from forkserver".to_string(),
+            ));
+        }
+
+        if map_size % 64 != 0 {
+            map_size = ((map_size + 63) >> 6) << 6;
+        }
+
+        options.map_size = Some(map_size as usize);
+    }
+
+    options.shmem_fuzz = status & FS_NEW_OPT_SHDMEM_FUZZ != 0;
+    options.shmem_resize = options.shmem_fuzz && status & FS_NEW_OPT_SHDMEM_RESIZE != 0;
+
+    if status & FS_NEW_OPT_AUTODICT != 0 {
+        // Here unlike shmem input fuzzing, we are forced to read things,
+        // even if the caller does not want the dictionary
+        let (read_len, dict_size) = forkserver.read_st()?;
+        if read_len != 4 {
+            return Err(Error::unknown(
+                "Failed to read dictionary size from forkserver".to_string(),
+            ));
+        }
+
+        if !(2..=0xffffff).contains(&dict_size) {
+            return Err(Error::illegal_state(
+                "Dictionary has an illegal size".to_string(),
+            ));
+        }
+        log::info!("Autodict size {dict_size:x}");
+        let (rlen, buf) = forkserver.read_st_size(dict_size as usize)?;
+
+        if rlen != dict_size as usize {
+            return Err(Error::unknown("Failed to load autodictionary".to_string()));
+        }
+        options.autodict = Some(buf);
+    }
+
+    let (read_len, aflx) = forkserver.read_st()?;
+    if read_len != 4 {
+        return Err(Error::unknown("Reading from forkserver failed".to_string()));
+    }
+
+    if aflx != version_status {
+        return Err(Error::unknown(format!(
+            "Error in forkserver communication ({:x}=>{:x})",
+            keep, aflx
+        )));
+    }
+
+    Ok(options)
+}
+
+/// Allocates a shared memory for testcases of up to `capacity` bytes, with the size header set
+fn new_shmem_testcase<SP>(provider: &mut SP, capacity: usize) -> Result<SP::ShMem, Error>
+where
+    SP: ShMemProvider,
+{
+    let mut shmem = provider.new_shmem(capacity + SHMEM_FUZZ_HDR_SIZE)?;
+    let size_in_bytes = (capacity + SHMEM_FUZZ_HDR_SIZE).to_ne_bytes();
+    shmem.as_slice_mut()[..SHMEM_FUZZ_HDR_SIZE]
+        .clone_from_slice(&size_in_bytes[..SHMEM_FUZZ_HDR_SIZE]);
+    Ok(shmem)
+}
+
+/// Everything needed to (re)spawn the forkserver of a [`ForkserverExecutor`]
+#[derive(Debug, Clone)]
+#[allow(clippy::struct_excessive_bools)]
+struct ForkserverSpawnConfig {
+    envs: Vec<(OsString, OsString)>,
+    use_stdin: bool,
+    is_persistent: bool,
+    is_deferred_frksrv: bool,
+    debug_child: bool,
+    kill_signal: Signal,
+}
+
+impl ForkserverSpawnConfig {
+    /// Spawns a forkserver, with `extra_envs` overriding the configured environment
+    fn spawn(
+        &self,
+        target: OsString,
+        args: Vec<OsString>,
+        input_filefd: RawFd,
+        extra_envs: &[(OsString, OsString)],
+    ) -> Result<Forkserver, Error> {
+        let mut envs = self.envs.clone();
+        envs.extend_from_slice(extra_envs);
+        Forkserver::with_kill_signal(
+            target,
+            args,
+            envs,
+            input_filefd,
+            self.use_stdin,
+            0,
+            self.is_persistent,
+            self.is_deferred_frksrv,
+            self.debug_child,
+            self.kill_signal,
+        )
+    }
+}
+
 /// This [`Executor`] can run binaries compiled for AFL/AFL++ that make use of a forkserver.
 /// Shared memory feature is also available, but you have to set things up in your code.
 /// Please refer to AFL++'s docs. <https://github.com/AFLplusplus/AFLplusplus/blob/stable/instrumentation/README.persistent_mode.md>
@@ -526,16 +771,23 @@ where
     args: Vec<OsString>,
     input_file: InputFile,
     uses_shmem_testcase: bool,
+    /// If the forkserver remaps the testcase shared memory on request
+    shmem_resize: bool,
     forkserver: Forkserver,
     observers: OT,
     map: Option<SP::ShMem>,
+    shmem_provider: Option<SP>,
+    spawn_config: ForkserverSpawnConfig,
     phantom: PhantomData<S>,
     map_size: Option<usize>,
     min_input_size: usize,
     max_input_size: usize,
     #[cfg(feature = "regex")]
     asan_obs: Handle<AsanBacktraceObserver>,
+    #[cfg(feature = "regex")]
+    asan_log_path: String,
     timeout: TimeSpec,
+    timeout_clock: TimeoutClock,
     crash_exitcode: Option<i8>,
 }
 
@@ -612,19 +864,23 @@ pub struct ForkserverExecutorBuilder<'a, SP> {
     debug_child: bool,
     use_stdin: bool,
     uses_shmem_testcase: bool,
+    shmem_resize: bool,
     is_persistent: bool,
     is_deferred_frksrv: bool,
     autotokens: Option<&'a mut Tokens>,
     input_filename: Option<OsString>,
     shmem_provider: Option<&'a mut SP>,
+    shmem_input_size: Option<usize>,
     max_input_size: usize,
     min_input_size: usize,
     map_size: Option<usize>,
     kill_signal: Option<Signal>,
     timeout: Option<Duration>,
+    timeout_clock: TimeoutClock,
     #[cfg(feature = "regex")]
     asan_obs: Option<Handle<AsanBacktraceObserver>>,
     crash_exitcode: Option<i8>,
+    sanitizer_options: Vec<SanitizerOptions>,
 }
 
 impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
@@ -641,7 +897,7 @@ impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
         S::Input: Input + HasTargetBytes,
         SP: ShMemProvider,
     {
-        let (forkserver, input_file, map) = self.build_helper()?;
+        let (forkserver, input_file, map, spawn_config) = self.build_helper()?;
 
         let target = self.program.take().unwrap();
         log::info!(
@@ -676,18 +932,24 @@ impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
             args: self.arguments.clone(),
             input_file,
             uses_shmem_testcase: self.uses_shmem_testcase,
+            shmem_resize: self.shmem_resize,
             forkserver,
             observers,
             map,
+            shmem_provider: self.shmem_provider.as_deref().cloned(),
+            spawn_config,
             phantom: PhantomData,
             map_size: self.map_size,
             min_input_size: self.min_input_size,
             max_input_size: self.max_input_size,
             timeout,
+            timeout_clock: self.timeout_clock,
             asan_obs: self
                 .asan_obs
                 .clone()
                 .unwrap_or(AsanBacktraceObserver::default().handle()),
+            #[cfg(feature = "regex")]
+            asan_log_path: self.asan_log_path(),
             crash_exitcode: self.crash_exitcode,
         })
     }
@@ -707,7 +969,7 @@ impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
         S::Input: Input + HasTargetBytes,
         SP: ShMemProvider,
     {
-        let (forkserver, input_file, map) = self.build_helper()?;
+        let (forkserver, input_file, map, spawn_config) = self.build_helper()?;
 
         let target = self.program.take().unwrap();
         log::info!(
@@ -740,27 +1002,50 @@ impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
             args: self.arguments.clone(),
             input_file,
             uses_shmem_testcase: self.uses_shmem_testcase,
+            shmem_resize: self.shmem_resize,
             forkserver,
             observers,
             map,
+            shmem_provider: self.shmem_provider.as_deref().cloned(),
+            spawn_config,
             phantom: PhantomData,
             map_size: self.map_size,
             min_input_size: self.min_input_size,
             max_input_size: self.max_input_size,
             timeout,
+            timeout_clock: self.timeout_clock,
             asan_obs: self
                 .asan_obs
                 .clone()
                 .unwrap_or(AsanBacktraceObserver::default().handle()),
+            #[cfg(feature = "regex")]
+            asan_log_path: self.asan_log_path(),
             crash_exitcode: self.crash_exitcode,
         })
     }
 
-    #[allow(clippy::pedantic)]
-    fn build_helper(&mut self) -> Result<(Forkserver, InputFile, Option<SP::ShMem>), Error>
+    #[allow(clippy::pedantic, clippy::type_complexity)]
+    fn build_helper(
+        &mut self,
+    ) -> Result<
+        (
+            Forkserver,
+            InputFile,
+            Option<SP::ShMem>,
+            ForkserverSpawnConfig,
+        ),
+        Error,
+    >
     where
         SP: ShMemProvider,
     {
+        #[cfg(not(target_os = "linux"))]
+        if self.timeout_clock == TimeoutClock::CpuTime {
+            return Err(Error::unsupported(
+                "ForkserverExecutorBuilder::build: CPU-time timeouts are only supported on Linux",
+            ));
+        }
+
         let input_filename = match &self.input_filename {
             Some(name) => name.clone(),
             None => {
@@ -771,31 +1056,34 @@ impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
 
         let input_file = InputFile::create(input_filename)?;
 
-        let map = match &mut self.shmem_provider {
+        let shmem_input_size = self
+            .shmem_input_size
+            .unwrap_or(self.max_input_size)
+            .min(self.max_input_size);
+        let mut map = match self.shmem_provider.as_deref_mut() {
             None => None,
             Some(provider) => {
                 // setup shared memory
-                let mut shmem = provider.new_shmem(self.max_input_size + SHMEM_FUZZ_HDR_SIZE)?;
+                let shmem = new_shmem_testcase(provider, shmem_input_size)?;
                 shmem.write_to_env("__AFL_SHM_FUZZ_ID")?;
-
-                let size_in_bytes = (self.max_input_size + SHMEM_FUZZ_HDR_SIZE).to_ne_bytes();
-                shmem.as_slice_mut()[..4].clone_from_slice(&size_in_bytes[..4]);
                 Some(shmem)
             }
         };
 
+        let spawn_config = ForkserverSpawnConfig {
+            envs: self.child_envs()?,
+            use_stdin: self.use_stdin,
+            is_persistent: self.is_persistent,
+            is_deferred_frksrv: self.is_deferred_frksrv,
+            debug_child: self.debug_child,
+            kill_signal: self.kill_signal.unwrap_or(KILL_SIGNAL_DEFAULT),
+        };
         let mut forkserver = match &self.program {
-            Some(t) => Forkserver::with_kill_signal(
+            Some(t) => spawn_config.spawn(
                 t.clone(),
                 self.arguments.clone(),
-                self.envs.clone(),
                 input_file.as_raw_fd(),
-                self.use_stdin,
-                0,
-                self.is_persistent,
-                self.is_deferred_frksrv,
-                self.debug_child,
-                self.kill_signal.unwrap_or(KILL_SIGNAL_DEFAULT),
+                &[],
             )?,
             None => {
                 return Err(Error::illegal_argument(
@@ -804,129 +1092,36 @@ impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
             }
         };
 
-        let (rlen, version_status) = forkserver.read_st()?; // Initial handshake, read 4-bytes hello message from the forkserver.
-
-        if rlen != 4 {
-            return Err(Error::unknown("Failed to start a forkserver".to_string()));
-        }
-
-        if (version_status & FS_NEW_ERROR) == FS_NEW_ERROR {
-            report_error_and_exit(version_status & 0x0000ffff)?;
-        }
-
-        let keep = version_status;
-        let version: u32 = version_status as u32 - 0x41464c00_u32;
-        if (0x41464c00..=0x41464cff).contains(&version_status) {
-            match version {
-                0 => {
-                    return Err(Error::unknown("Fork server version is not assigned, this should not happen. Recompile target."));
-                }
-                FS_NEW_VERSION_MIN..=FS_NEW_VERSION_MAX => {
-                    // good, do nothing
-                }
-                _ => {
-                    return Err(Error::unknown(
-                        "Fork server version is not supported. Recompile the target.",
-                    ));
-                }
-            }
-        }
-
-        let xored_version_status = (version_status as u32 ^ 0xffffffff) as i32;
-
-        let send_len = forkserver.write_ctl(xored_version_status)?;
-        if send_len != 4 {
-            return Err(Error::unknown("Writing to forkserver failed.".to_string()));
-        }
-
-        log::info!(
-            "All right - new fork server model version {} is up",
-            version
-        );
-
-        let (read_len, status) = forkserver.read_st()?;
-        if read_len != 4 {
-            return Err(Error::unknown(
-                "Reading from forkserver failed.".to_string(),
-            ));
-        }
-
-        if status & FS_NEW_OPT_MAPSIZE == FS_NEW_OPT_MAPSIZE {
-            // When 0, we assume that map_size was filled by the user or const
-            /* TODO autofill map size from the observer
-
-            if map_size > 0 {
-                self.map_size = Some(map_size as usize);
-            }
-            */
-            let (read_len, mut map_size) = forkserver.read_st()?;
-            if read_len != 4 {
-                return Err(Error::unknown(
-                    "Failed to read map size from forkserver".to_string(),
-                ));
-            }
-
-            if map_size % 64 != 0 {
-                map_size = ((map_size + 63) >> 6) << 6;
-            }
+        let options = forkserver_handshake(&mut forkserver)?;
 
+        if let Some(map_size) = options.map_size {
             // TODO set AFL_MAP_SIZE
-            assert!(self.map_size.is_none() || map_size as usize <= self.map_size.unwrap());
+            assert!(self.map_size.is_none() || map_size <= self.map_size.unwrap());
 
             // we'll use this later when we truncate the observer
-            self.map_size = Some(map_size as usize);
+            self.map_size = Some(map_size);
         }
 
-        if status & FS_NEW_OPT_SHDMEM_FUZZ != 0 {
+        if options.shmem_fuzz {
             if map.is_some() {
                 log::info!("Using SHARED MEMORY FUZZING feature.");
                 self.uses_shmem_testcase = true;
+                self.shmem_resize = options.shmem_resize;
             } else {
                 return Err(Error::unknown(
                     "Target requested sharedmem fuzzing, but you didn't prepare shmem",
                 ));
             }
+        } else if map.is_some() {
+            log::info!("Target does not support sharedmem fuzzing, delivering inputs via file.");
+            map = None;
         }
 
-        if status & FS_NEW_OPT_AUTODICT != 0 {
-            // Here unlike shmem input fuzzing, we are forced to read things
-            // hence no self.autotokens.is_some() to check if we proceed
-            let (read_len, dict_size) = forkserver.read_st()?;
-            if read_len != 4 {
-                return Err(Error::unknown(
-                    "Failed to read dictionary size from forkserver".to_string(),
-                ));
-            }
-
-            if !(2..=0xffffff).contains(&dict_size) {
-                return Err(Error::illegal_state(
-                    "Dictionary has an illegal size".to_string(),
-                ));
-            }
-            log::info!("Autodict size {dict_size:x}");
-            let (rlen, buf) = forkserver.read_st_size(dict_size as usize)?;
-
-            if rlen != dict_size as usize {
-                return Err(Error::unknown("Failed to load autodictionary".to_string()));
-            }
-            if let Some(t) = &mut self.autotokens {
-                t.parse_autodict(&buf, dict_size as usize);
-            }
+        if let (Some(dict), Some(t)) = (options.autodict, &mut self.autotokens) {
+            t.parse_autodict(&dict, dict.len());
         }
 
-        let (read_len, aflx) = forkserver.read_st()?;
-        if read_len != 4 {
-            return Err(Error::unknown("Reading from forkserver failed".to_string()));
-        }
-
-        if aflx != version_status {
-            return Err(Error::unknown(format!(
-                "Error in forkserver communication ({:x}=>{:x})",
-                keep, aflx
-            )));
-        }
-
-        Ok((forkserver, input_file, map))
+        Ok((forkserver, input_file, map, spawn_config))
     }
 
     /// Use autodict?
@@ -943,6 +1138,14 @@ impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
         self
     }
 
+    #[must_use]
+    /// Set the clock the timeout is measured on, defaults to [`TimeoutClock::WallClock`].
+    /// [`TimeoutClock::CpuTime`] is only supported on Linux, and not by [`ForkserverPool::run_batch`].
+    pub fn timeout_clock(mut self, timeout_clock: TimeoutClock) -> Self {
+        self.timeout_clock = timeout_clock;
+        self
+    }
+
     #[must_use]
     /// Parse afl style command line
     ///
@@ -1035,6 +1238,16 @@ impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
         self
     }
 
+    /// Set the initial size of the shared memory for testcases, see [`Self::shmem_provider`].
+    /// Larger inputs, up to the max input size, grow the shared memory on demand by restarting
+    /// the forkserver with a larger region, instead of allocating the max input size up front.
+    /// The target has to map the whole region, as AFL++ targets do for `SysV` shared memory.
+    #[must_use]
+    pub fn shmem_input_size(mut self, size: usize) -> Self {
+        self.shmem_input_size = Some(size);
+        self
+    }
+
     /// Set the min input size
     #[must_use]
     pub fn min_input_size(mut self, size: usize) -> Self {
@@ -1138,6 +1351,57 @@ impl<'a, SP> ForkserverExecutorBuilder<'a, SP> {
         self.kill_signal = Some(kill_signal);
         self
     }
+
+    /// Sets the runtime options of a sanitizer of the target, replacing earlier options of the
+    /// same sanitizer. The options are validated when the executor is built.
+    ///
+    /// Without [`crate::executors::sanitizers::Sanitizer::Address`] options, the recommended
+    /// `ASAN_OPTIONS` to capture backtraces are used.
+    #[must_use]
+    pub fn sanitizer_options(mut self, options: SanitizerOptions) -> Self {
+        self.sanitizer_options
+            .retain(|other| other.sanitizer() != options.sanitizer());
+        self.sanitizer_options.push(options);
+        self
+    }
+
+    /// The environment of the target, with the sanitizer options
+    fn child_envs(&self) -> Result<Vec<(OsString, OsString)>, Error> {
+        let mut envs = self.envs.clone();
+        for options in &self.sanitizer_options {
+            options.validate()?;
+            #[cfg(feature = "regex")]
+            let options = &if options.sanitizer() == Sanitizer::Address
+                && options.get("log_path").is_none()
+            {
+                // The `AsanBacktraceObserver` reads the report from the log
+                options.clone().log_path(ASAN_LOG_PATH)
+            } else {
+                options.clone()
+            };
+
+            let (key, value) = options.to_env();
+            if envs.iter().any(|(k, _)| k == key) {
+                log::warn!(
+                    "Replacing {key} in the environment of the target by the sanitizer options"
+                );
+                envs.retain(|(k, _)| k != key);
+            }
+            envs.push((key.into(), value.into()));
+        }
+        Ok(envs)
+    }
+
+    /// The prefix of the ASAN log files of the target
+    #[cfg(feature = "regex")]
+    fn asan_log_path(&self) -> String {
+        self.sanitizer_options
+            .iter()
+            .find(|options| options.sanitizer() == Sanitizer::Address)
+            .and_then(|options| options.get("log_path"))
+            .unwrap_or(ASAN_LOG_PATH)
+            .to_string()
+    }
 }
 
 impl<'a> ForkserverExecutorBuilder<'a, UnixShMemProvider> {
@@ -1156,18 +1420,22 @@ impl<'a> ForkserverExecutorBuilder<'a, UnixShMemProvider> {
             debug_child: false,
             use_stdin: false,
             uses_shmem_testcase: false,
+            shmem_resize: false,
             is_persistent: false,
             is_deferred_frksrv: false,
             autotokens: None,
             input_filename: None,
             shmem_provider: None,
+            shmem_input_size: None,
             map_size: None,
             max_input_size: MAX_INPUT_SIZE_DEFAULT,
             min_input_size: MIN_INPUT_SIZE_DEFAULT,
             kill_signal: None,
             timeout: None,
+            timeout_clock: TimeoutClock::WallClock,
             asan_obs: None,
             crash_exitcode: None,
+            sanitizer_options: vec![],
         }
     }
 
@@ -1183,18 +1451,22 @@ impl<'a> ForkserverExecutorBuilder<'a, UnixShMemProvider> {
             debug_child: self.debug_child,
             use_stdin: self.use_stdin,
             uses_shmem_testcase: self.uses_shmem_testcase,
+            shmem_resize: self.shmem_resize,
             is_persistent: self.is_persistent,
             is_deferred_frksrv: self.is_deferred_frksrv,
             autotokens: self.autotokens,
             input_filename: self.input_filename,
             shmem_provider: Some(shmem_provider),
+            shmem_input_size: self.shmem_input_size,
             map_size: self.map_size,
             max_input_size: MAX_INPUT_SIZE_DEFAULT,
             min_input_size: MIN_INPUT_SIZE_DEFAULT,
             kill_signal: None,
             timeout: None,
+            timeout_clock: self.timeout_clock,
             asan_obs: None,
             crash_exitcode: None,
+            sanitizer_options: self.sanitizer_options,
         }
     }
 }
@@ -1205,29 +1477,123 @@ impl<'a> Default for ForkserverExecutorBuilder<'a, UnixShMemProvider> {
     }
 }
 
-impl<EM, OT, S, SP, Z> Executor<EM, Z> for ForkserverExecutor<OT, S, SP>
+impl<OT, S, SP> ForkserverExecutor<OT, S, SP>
+where
+    SP: ShMemProvider,
+{
+    /// The number of input bytes fitting into the shared memory for testcases
+    fn shmem_input_capacity(&self) -> usize {
+        self.map
+            .as_ref()
+            .map_or(0, |map| map.as_slice().len() - SHMEM_FUZZ_HDR_SIZE)
+    }
+
+    /// Grows the shared memory for testcases to fit `input_size` bytes. Forkservers supporting
+    /// [`FS_NEW_OPT_SHDMEM_RESIZE`] remap the new region in place. Others, e.g. of AFL++ targets,
+    /// are restarted with the new region, falling back to file delivery if the restarted target
+    /// does not use the shared memory anymore.
+    fn grow_shmem_testcase(&mut self, input_size: usize) -> Result<(), Error> {
+        let Some(provider) = &mut self.shmem_provider else {
+            return Err(Error::illegal_state(
+                "Can't grow the testcase shared memory without a shmem provider",
+            ));
+        };
+        let capacity = input_size.next_power_of_two().min(self.max_input_size);
+        let map = new_shmem_testcase(provider, capacity)?;
+        let id = map.id().to_string();
+
+        if self.shmem_resize {
+            self.forkserver
+                .request_shmem_resize(&id, map.as_slice().len())?;
+            log::info!("Grew the testcase shared memory to {capacity} bytes");
+            // The forkserver unmapped the old region, so it can be dropped now
+            self.map = Some(map);
+            return Ok(());
+        }
+
+        let options = self.respawn_forkserver(&shmem_testcase_envs(&map))?;
+        if options.shmem_fuzz {
+            log::info!(
+                "Grew the testcase shared memory to {capacity} bytes by restarting the target"
+            );
+            self.shmem_resize = options.shmem_resize;
+            self.map = Some(map);
+        } else {
+            log::warn!(
+                "Restarted target does not support sharedmem fuzzing, delivering inputs via file."
+            );
+            self.uses_shmem_testcase = false;
+            self.map = None;
+        }
+        Ok(())
+    }
+
+    /// Replaces the forkserver by a newly spawned one, with `extra_envs` added to its environment
+    fn respawn_forkserver(
+        &mut self,
+        extra_envs: &[(OsString, OsString)],
+    ) -> Result<ForkserverOptions, Error> {
+        let mut forkserver = self.spawn_config.spawn(
+            self.target.clone(),
+            self.args.clone(),
+            self.input_file.as_raw_fd(),
+            extra_envs,
+        )?;
+        let options = forkserver_handshake(&mut forkserver)?;
+        // Replacing the forkserver kills the old one, before its region is dropped.
+        // The new forkserver has no child yet, so there is no timed out child to write off.
+        self.forkserver = forkserver;
+        Ok(options)
+    }
+
+    /// Restarts the target, e.g. after the forkserver died, handing it the current testcase
+    /// shared memory
+    fn restart_forkserver(&mut self) -> Result<(), Error> {
+        let envs = self.map.as_ref().map(shmem_testcase_envs);
+        let options = self.respawn_forkserver(envs.as_ref().map_or(&[], |envs| &envs[..]))?;
+        if self.uses_shmem_testcase && !options.shmem_fuzz {
+            log::warn!(
+                "Restarted target does not support sharedmem fuzzing, delivering inputs via file."
+            );
+            self.uses_shmem_testcase = false;
+            self.map = None;
+        } else {
+            self.shmem_resize = options.shmem_resize;
+        }
+        log::info!("Restarted the forkserver");
+        Ok(())
+    }
+}
+
+/// The environment handing the testcase shared memory `map` to a forkserver
+fn shmem_testcase_envs<SHM>(map: &SHM) -> [(OsString, OsString); 2]
+where
+    SHM: ShMem,
+{
+    [
+        (
+            OsString::from("__AFL_SHM_FUZZ_ID"),
+            OsString::from(map.id().to_string()),
+        ),
+        (
+            OsString::from("__AFL_SHM_FUZZ_ID_SIZE"),
+            OsString::from(map.as_slice().len().to_string()),
+        ),
+    ]
+}
+
+impl<OT, S, SP> ForkserverExecutor<OT, S, SP>
 where
     OT: ObserversTuple<S>,
     SP: ShMemProvider,
     S: State + HasExecutions,
     S::Input: HasTargetBytes,
-    EM: UsesState<State = S>,
-    Z: UsesState<State = S>,
 {
-    #[inline]
-    fn run_target(
-        &mut self,
-        _fuzzer: &mut Z,
-        state: &mut Self::State,
-        _mgr: &mut EM,
-        input: &Self::Input,
-    ) -> Result<ExitKind, Error> {
+    /// Hands the input to the target and requests a new child from the forkserver,
+    /// without waiting for the child to finish.
+    fn start_target(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
         *state.executions_mut() += 1;
 
-        let mut exit_kind = ExitKind::Ok;
-
-        let last_run_timed_out = self.forkserver.last_run_timed_out_raw();
-
         let mut input_bytes = input.target_bytes();
         let mut input_size = input_bytes.as_slice().len();
         if input_size > self.max_input_size {
@@ -1242,6 +1608,9 @@ where
                 .copy_from_slice(input_bytes.as_slice());
             input_bytes = OwnedSlice::from(input_bytes_copy);
         }
+        if self.uses_shmem_testcase && input_size > self.shmem_input_capacity() {
+            self.grow_shmem_testcase(input_size)?;
+        }
         let input_size_in_bytes = input_size.to_ne_bytes();
         if self.uses_shmem_testcase {
             debug_assert!(
@@ -1261,32 +1630,69 @@ where
                 .write_buf(&input_bytes.as_slice()[..input_size])?;
         }
 
+        // Read after growing the shared memory, which may replace the forkserver
+        let last_run_timed_out = self.forkserver.last_run_timed_out_raw();
         let send_len = self.forkserver.write_ctl(last_run_timed_out)?;
 
         self.forkserver.set_last_run_timed_out(false);
 
         if send_len != 4 {
-            return Err(Error::unknown(
-                "Unable to request new process from fork server (OOM?)".to_string(),
+            return Err(Error::execution_failed(
+                "Unable to request new process from fork server (OOM?)",
             ));
         }
 
         let (recv_pid_len, pid) = self.forkserver.read_st()?;
         if recv_pid_len != 4 {
-            return Err(Error::unknown(
-                "Unable to request new process from fork server (OOM?)".to_string(),
+            return Err(Error::execution_failed(
+                "Unable to request new process from fork server (OOM?)",
             ));
         }
 
         if pid <= 0 {
-            return Err(Error::unknown(
-                "Fork server is misbehaving (OOM?)".to_string(),
-            ));
+            return Err(Error::execution_failed("Fork server is misbehaving (OOM?)"));
         }
 
         self.forkserver.set_child_pid(Pid::from_raw(pid));
+        Ok(())
+    }
+
+    /// Waits for the status of the child until it burned the timeout in CPU time, or
+    /// [`CPU_TIMEOUT_WALL_FACTOR`] times the timeout passed in wall-clock time.
+    /// Returns `None` if it timed out.
+    fn read_st_cpu_timed(&mut self) -> Result<Option<i32>, Error> {
+        let timeout = timespec_duration(&self.timeout);
+        let pid = self.forkserver.child_pid();
+        // A persistent child keeps its CPU time across runs
+        let cpu_start = child_cpu_time(pid);
+        let wall_start = current_time();
+        loop {
+            let wall_used = current_time().saturating_sub(wall_start);
+            let cpu_used = match (cpu_start, child_cpu_time(pid)) {
+                (Some(start), Some(now)) => now.saturating_sub(start),
+                // The CPU time can't be read, fall back to wall-clock time
+                _ => wall_used,
+            };
+            // The child can't burn more CPU than wall-clock time (unless it is multi-threaded),
+            // so wait at least for the rest of the CPU timeout before checking again
+            let wait = timeout
+                .saturating_sub(cpu_used)
+                .min((timeout * CPU_TIMEOUT_WALL_FACTOR).saturating_sub(wall_used));
+            if wait.is_zero() {
+                return Ok(None);
+            }
+            if let Some(status) = self.forkserver.read_st_timed(&wait.into())? {
+                return Ok(Some(status));
+            }
+        }
+    }
+
+    /// Evaluates the status of the child started by [`Self::start_target`],
+    /// or kills it if it timed out (`status` is `None`).
+    fn finish_target(&mut self, status: Option<i32>) -> Result<ExitKind, Error> {
+        let mut exit_kind = ExitKind::Ok;
 
-        if let Some(status) = self.forkserver.read_st_timed(&self.timeout)? {
+        if let Some(status) = status {
             self.forkserver.set_status(status);
             let exitcode_is_crash = if let Some(crash_exitcode) = self.crash_exitcode {
                 (libc::WEXITSTATUS(self.forkserver().status()) as i8) == crash_exitcode
@@ -1297,7 +1703,10 @@ where
                 exit_kind = ExitKind::Crash;
                 #[cfg(feature = "regex")]
                 if let Some(asan_observer) = self.observers.get_mut(&self.asan_obs) {
-                    asan_observer.parse_asan_output_from_asan_log_file(pid)?;
+                    asan_observer.parse_asan_output_from_log_file(
+                        &self.asan_log_path,
+                        self.forkserver.child_pid().as_raw(),
+                    )?;
                 }
             }
         } else {
@@ -1307,7 +1716,7 @@ where
             let _ = kill(self.forkserver().child_pid(), self.forkserver.kill_signal);
             let (recv_status_len, _) = self.forkserver.read_st()?;
             if recv_status_len != 4 {
-                return Err(Error::unknown("Could not kill timed-out child".to_string()));
+                return Err(Error::execution_failed("Could not kill timed-out child"));
             }
             exit_kind = ExitKind::Timeout;
         }
@@ -1320,6 +1729,45 @@ where
     }
 }
 
+impl<OT, S, SP> HasChildPid for ForkserverExecutor<OT, S, SP>
+where
+    SP: ShMemProvider,
+{
+    fn last_child_pid(&self) -> Option<i32> {
+        self.forkserver.last_child_pid().map(Pid::as_raw)
+    }
+}
+
+impl<EM, OT, S, SP, Z> Executor<EM, Z> for ForkserverExecutor<OT, S, SP>
+where
+    OT: ObserversTuple<S>,
+    SP: ShMemProvider,
+    S: State + HasExecutions,
+    S::Input: HasTargetBytes,
+    EM: UsesState<State = S>,
+    Z: UsesState<State = S>,
+{
+    #[inline]
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut Self::State,
+        _mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        self.start_target(state, input)?;
+        let status = match self.timeout_clock {
+            TimeoutClock::WallClock => self.forkserver.read_st_timed(&self.timeout)?,
+            TimeoutClock::CpuTime => self.read_st_cpu_timed()?,
+        };
+        self.finish_target(status)
+    }
+
+    fn restart_target(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        self.restart_forkserver()
+    }
+}
+
 impl<OT, S, SP> UsesState for ForkserverExecutor<OT, S, SP>
 where
     S: State,
@@ -1354,23 +1802,540 @@ where
     }
 }
 
+/// A pool of [`ForkserverExecutor`]s for the same target, managed by a single client.
+///
+/// Each instance is a separate forkserver with its own coverage map, input (file or shared memory)
+/// and observers, so each must be built with its own `__AFL_SHM_ID`, see [`ForkserverPool::with_instances`].
+/// The observers of all instances must have the same names.
+///
+/// [`Executor::run_target`] runs a single input on the next instance, in turn, so that all
+/// instances share the work. [`ForkserverPool::run_batch`] runs many inputs concurrently: every
+/// idle instance takes the next input, and whichever instance finishes first reports its result
+/// and steals the next one. This raises the throughput for targets blocking on IO, without
+/// spawning more fuzzer processes.
+///
+/// The results are always reported through the observers of the instance that ran the input,
+/// and [`HasObservers`] returns the observers of the instance that finished last.
+/// The maps are not merged into one, as an edge has the same index in the map of each instance.
+pub struct ForkserverPool<OT, S, SP>
+where
+    SP: ShMemProvider,
+{
+    instances: Vec<ForkserverExecutor<OT, S, SP>>,
+    current: usize,
+}
+
+impl<OT, S, SP> Debug for ForkserverPool<OT, S, SP>
+where
+    OT: Debug,
+    SP: ShMemProvider,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForkserverPool")
+            .field("instances", &self.instances)
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+impl<OT, S, SP> ForkserverPool<OT, S, SP>
+where
+    OT: ObserversTuple<S>,
+    S: State + HasExecutions,
+    S::Input: HasTargetBytes,
+    SP: ShMemProvider,
+{
+    /// Creates a new [`ForkserverPool`] from already running instances
+    pub fn new(instances: Vec<ForkserverExecutor<OT, S, SP>>) -> Result<Self, Error> {
+        if instances.is_empty() {
+            return Err(Error::illegal_argument(
+                "A ForkserverPool needs at least one instance",
+            ));
+        }
+        Ok(Self {
+            instances,
+            current: 0,
+        })
+    }
+
+    /// Creates a new [`ForkserverPool`] with `count` instances, built by `build` for each index.
+    ///
+    /// `build` has to give every instance its own coverage map, e.g. by writing a fresh shared map
+    /// to `__AFL_SHM_ID` and building the observers on top of it, and its own input file.
+    pub fn with_instances<F>(count: usize, mut build: F) -> Result<Self, Error>
+    where
+        F: FnMut(usize) -> Result<ForkserverExecutor<OT, S, SP>, Error>,
+    {
+        Self::new((0..count).map(&mut build).collect::<Result<_, _>>()?)
+    }
+
+    /// The instances of this pool
+    #[must_use]
+    pub fn instances(&self) -> &[ForkserverExecutor<OT, S, SP>] {
+        &self.instances
+    }
+
+    /// The instances of this pool (mutable)
+    pub fn instances_mut(&mut self) -> &mut [ForkserverExecutor<OT, S, SP>] {
+        &mut self.instances
+    }
+
+    /// The index of the instance whose observers are currently exposed
+    #[must_use]
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Runs all `inputs`, dispatching each to the next idle instance.
+    ///
+    /// Once an input finished, `on_done` is called with its index in `inputs`, the [`ExitKind`]
+    /// and the observers of the instance that ran it, after their `post_exec`.
+    /// The order of the calls depends on which instance finishes first.
+    pub fn run_batch<F>(
+        &mut self,
+        state: &mut S,
+        inputs: &[S::Input],
+        mut on_done: F,
+    ) -> Result<(), Error>
+    where
+        F: FnMut(&mut S, usize, ExitKind, &OT) -> Result<(), Error>,
+    {
+        let mut next = 0;
+        // The index of the input and the start time of each busy instance
+        let mut running: Vec<Option<(usize, Duration)>> = vec![None; self.instances.len()];
+
+        loop {
+            for (instance, slot) in self.instances.iter_mut().zip(running.iter_mut()) {
+                if slot.is_none() && next < inputs.len() {
+                    instance.observers.pre_exec_all(state, &inputs[next])?;
+                    instance.start_target(state, &inputs[next])?;
+                    *slot = Some((next, current_time()));
+                    next += 1;
+                }
+            }
+            if running.iter().all(Option::is_none) {
+                return Ok(());
+            }
+
+            // Wait until the first busy instance reports a status, or times out
+            let now = current_time();
+            let mut readfds = FdSet::new();
+            let mut wait = None;
+            for (instance, slot) in self.instances.iter().zip(running.iter()) {
+                let Some((_, started)) = slot else {
+                    continue;
+                };
+                let Some(fd) = instance.forkserver.st_read_fd() else {
+                    return Err(Error::os_error(
+                        io::Error::new(ErrorKind::BrokenPipe, "Read pipe end was already closed"),
+                        "run_batch failed",
+                    ));
+                };
+                // # Safety
+                // The FDs are valid as long as the instances live.
+                readfds.insert(unsafe { BorrowedFd::borrow_raw(fd) });
+                let remaining = timespec_duration(&instance.timeout)
+                    .saturating_sub(now.saturating_sub(*started));
+                wait = Some(wait.map_or(remaining, |wait: Duration| wait.min(remaining)));
+            }
+            let timeout = TimeSpec::from(wait.unwrap_or_default());
+            pselect(
+                Some(readfds.highest().unwrap().as_raw_fd() + 1),
+                &mut readfds,
+                None,
+                None,
+                Some(&timeout),
+                Some(&SigSet::empty()),
+            )?;
+
+            let now = current_time();
+            for (i, slot) in running.iter_mut().enumerate() {
+                let Some((input_idx, started)) = *slot else {
+                    continue;
+                };
+                let instance = &mut self.instances[i];
+                let fd = instance.forkserver.st_read_fd().unwrap();
+                // # Safety
+                // The FDs are valid as long as the instances live.
+                let status = if readfds.contains(unsafe { BorrowedFd::borrow_raw(fd) }) {
+                    let (read_len, status) = instance.forkserver.read_st()?;
+                    if read_len != 4 {
+                        return Err(Error::unknown(
+                            "Unable to communicate with fork server (OOM?)".to_string(),
+                        ));
+                    }
+                    Some(status)
+                } else if now.saturating_sub(started) >= timespec_duration(&instance.timeout) {
+                    None
+                } else {
+                    continue;
+                };
+
+                let exit_kind = instance.finish_target(status)?;
+                instance
+                    .observers
+                    .post_exec_all(state, &inputs[input_idx], &exit_kind)?;
+                *slot = None;
+                self.current = i;
+                on_done(state, input_idx, exit_kind, &instance.observers)?;
+            }
+        }
+    }
+}
+
+/// The CPU time the process `pid` burned so far, if it can be read
+#[cfg(target_os = "linux")]
+#[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+fn child_cpu_time(pid: Pid) -> Option<Duration> {
+    let mut clock_id: libc::clockid_t = 0;
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // # Safety
+    // Both calls only write to the given locals. Linux lets us read the CPU clock of any process.
+    unsafe {
+        if libc::clock_getcpuclockid(pid.as_raw(), &mut clock_id) != 0
+            || libc::clock_gettime(clock_id, &mut ts) != 0
+        {
+            return None;
+        }
+    }
+    Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+/// The CPU time the process `pid` burned so far, if it can be read
+#[cfg(not(target_os = "linux"))]
+fn child_cpu_time(_pid: Pid) -> Option<Duration> {
+    None
+}
+
+/// Converts a [`TimeSpec`] timeout to a [`Duration`]
+#[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
+fn timespec_duration(timespec: &TimeSpec) -> Duration {
+    Duration::new(timespec.tv_sec() as u64, timespec.tv_nsec() as u32)
+}
+
+impl<EM, OT, S, SP, Z> Executor<EM, Z> for ForkserverPool<OT, S, SP>
+where
+    OT: ObserversTuple<S>,
+    SP: ShMemProvider,
+    S: State + HasExecutions,
+    S::Input: HasTargetBytes,
+    EM: UsesState<State = S>,
+    Z: UsesState<State = S>,
+{
+    #[inline]
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        self.current = (self.current + 1) % self.instances.len();
+        self.instances[self.current].run_target(fuzzer, state, mgr, input)
+    }
+
+    fn restart_target(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        self.instances[self.current].restart_forkserver()
+    }
+}
+
+impl<OT, S, SP> UsesState for ForkserverPool<OT, S, SP>
+where
+    S: State,
+    SP: ShMemProvider,
+{
+    type State = S;
+}
+
+impl<OT, S, SP> UsesObservers for ForkserverPool<OT, S, SP>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+    SP: ShMemProvider,
+{
+    type Observers = OT;
+}
+
+impl<OT, S, SP> HasObservers for ForkserverPool<OT, S, SP>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+    SP: ShMemProvider,
+{
+    #[inline]
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.instances[self.current].observers)
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.instances[self.current].observers)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::ffi::OsString;
+    use core::time::Duration;
+    use std::{
+        ffi::OsString,
+        fs,
+        string::{String, ToString},
+    };
 
     use libafl_bolts::{
         shmem::{ShMem, ShMemProvider, UnixShMemProvider},
         tuples::tuple_list,
-        AsSliceMut,
+        AsSlice, AsSliceMut,
     };
     use serial_test::serial;
 
     use crate::{
-        executors::forkserver::ForkserverExecutor,
+        events::NopEventManager,
+        executors::{
+            forkserver::{
+                shmem_resize_message, ForkserverExecutor, ForkserverPool, FS_CTL_SHDMEM_RESIZE,
+                SHMEM_FUZZ_HDR_SIZE,
+            },
+            Executor, ExitKind, TimeoutClock,
+        },
+        fuzzer::test::NopFuzzer,
+        inputs::BytesInput,
         observers::{ConstMapObserver, HitcountsMapObserver},
+        state::test::test_std_state,
         Error,
     };
 
+    #[test]
+    fn test_shmem_resize_message() {
+        let message = shmem_resize_message("/libafl_1", 0x1004).unwrap();
+        assert_eq!(&message[..4], &FS_CTL_SHDMEM_RESIZE.to_ne_bytes());
+        assert_eq!(&message[4..8], &0x1004_u32.to_ne_bytes());
+        assert_eq!(&message[8..12], &9_u32.to_ne_bytes());
+        assert_eq!(&message[12..], b"/libafl_1");
+        assert!(shmem_resize_message("/libafl_1", 1 << 32).is_err());
+    }
+
+    /// A bash forkserver announcing shared memory fuzzing with resizing, which records the first
+    /// resize request to `request` and acknowledges it
+    fn fake_resizing_forkserver(request: &str) -> String {
+        [
+            r"printf '\001LFA' >&199",
+            "dd bs=4 count=1 <&198 >/dev/null 2>&1",
+            // FS_NEW_OPT_MAPSIZE | FS_NEW_OPT_SHDMEM_FUZZ | FS_NEW_OPT_SHDMEM_RESIZE, a map
+            // size of 65536 and the final hello
+            r"printf '\003\020\000\000\000\000\001\000\001LFA' >&199",
+            &format!("dd bs=12 count=1 <&198 >{request} 2>/dev/null"),
+            &format!("dd bs=256 count=1 <&198 >>{request} 2>/dev/null"),
+            r"printf 'ZISR' >&199",
+            "cat <&198 >/dev/null",
+        ]
+        .join("\n")
+    }
+
+    #[test]
+    #[serial]
+    #[cfg_attr(miri, ignore)]
+    fn test_forkserver_shmem_resize() {
+        let request =
+            std::env::temp_dir().join(format!("libafl_forkserver_resize_{}", std::process::id()));
+        let mut shmem_provider = UnixShMemProvider::new().unwrap();
+        let mut executor = ForkserverExecutor::builder()
+            .program("bash")
+            .args(["-c", &fake_resizing_forkserver(&request.to_string_lossy())])
+            .shmem_provider(&mut shmem_provider)
+            .max_input_size(1 << 16)
+            .shmem_input_size(1024)
+            .build::<_, ()>(tuple_list!())
+            .unwrap();
+        assert!(executor.uses_shmem_testcase);
+        assert!(executor.shmem_resize);
+        assert_eq!(executor.shmem_input_capacity(), 1024);
+
+        // The region is remapped by the running forkserver, instead of restarting it
+        let pid = executor.forkserver.fsrv_handle.id();
+        executor.grow_shmem_testcase(5000).unwrap();
+        assert_eq!(executor.forkserver.fsrv_handle.id(), pid);
+        assert_eq!(executor.shmem_input_capacity(), 8192);
+
+        let map = executor.map.as_ref().unwrap();
+        assert_eq!(
+            map.as_slice()[..SHMEM_FUZZ_HDR_SIZE],
+            (8192 + SHMEM_FUZZ_HDR_SIZE).to_ne_bytes()[..SHMEM_FUZZ_HDR_SIZE]
+        );
+        let recorded = fs::read(&request).unwrap();
+        fs::remove_file(&request).unwrap();
+        assert_eq!(
+            recorded,
+            shmem_resize_message(&map.id().to_string(), 8192 + SHMEM_FUZZ_HDR_SIZE).unwrap()
+        );
+    }
+
+    /// A bash forkserver without options, which exits right after the handshake unless `marker`
+    /// exists, and creates it. Otherwise, it reports each requested child as exited normally.
+    fn fake_dying_forkserver(marker: &str) -> String {
+        [
+            r"printf '\001LFA' >&199",
+            "dd bs=4 count=1 <&198 >/dev/null 2>&1",
+            r"printf '\000\000\000\000\001LFA' >&199",
+            &format!("[ -e {marker} ] || {{ touch {marker}; exit 0; }}"),
+            // Report our own pid as child, so a kill never hits an unrelated process
+            r"pid=$(printf '\\%03o' $(($$ & 255)) $(($$ >> 8 & 255)) $(($$ >> 16 & 255)) $(($$ >> 24 & 255)))",
+            r#"while [ "$(dd bs=4 count=1 <&198 2>/dev/null | wc -c)" -eq 4 ]; do"#,
+            r#"  printf "$pid\000\000\000\000" >&199"#,
+            "done",
+        ]
+        .join("\n")
+    }
+
+    /// A bash forkserver without options, which reports each requested child as exited normally,
+    /// after `delay` seconds
+    fn fake_slow_forkserver(delay: &str) -> String {
+        [
+            r"printf '\001LFA' >&199",
+            "dd bs=4 count=1 <&198 >/dev/null 2>&1",
+            r"printf '\000\000\000\000\001LFA' >&199",
+            r"pid=$(printf '\\%03o' $(($$ & 255)) $(($$ >> 8 & 255)) $(($$ >> 16 & 255)) $(($$ >> 24 & 255)))",
+            r#"while [ "$(dd bs=4 count=1 <&198 2>/dev/null | wc -c)" -eq 4 ]; do"#,
+            r#"  printf "$pid" >&199"#,
+            &format!("  sleep {delay}"),
+            r"  printf '\000\000\000\000' >&199",
+            "done",
+        ]
+        .join("\n")
+    }
+
+    /// A fake forkserver, forking a child running the shell `body` for each input
+    fn fake_forking_forkserver(body: &str) -> String {
+        [
+            r"printf '\001LFA' >&199",
+            "dd bs=4 count=1 <&198 >/dev/null 2>&1",
+            r"printf '\000\000\000\000\001LFA' >&199",
+            r#"while [ "$(dd bs=4 count=1 <&198 2>/dev/null | wc -c)" -eq 4 ]; do"#,
+            &format!("  ( {body} ) &"),
+            "  child=$!",
+            r#"  printf "$(printf '\\%03o' $((child & 255)) $((child >> 8 & 255)) $((child >> 16 & 255)) $((child >> 24 & 255)))" >&199"#,
+            "  wait $child",
+            r"  printf '\000\000\000\000' >&199",
+            "done",
+        ]
+        .join("\n")
+    }
+
+    #[test]
+    #[serial]
+    #[cfg_attr(miri, ignore)]
+    #[cfg(target_os = "linux")]
+    fn test_forkserver_cpu_timeout() {
+        let mut state = test_std_state::<BytesInput>();
+        let mut fuzzer = NopFuzzer::new();
+        let mut manager = NopEventManager::new();
+        let input = BytesInput::new(vec![0]);
+
+        let mut run = |body: &str, timeout_clock: TimeoutClock| {
+            let mut executor = ForkserverExecutor::builder()
+                .program("bash")
+                .args(["-c", &fake_forking_forkserver(body)])
+                .timeout(Duration::from_millis(200))
+                .timeout_clock(timeout_clock)
+                .build(tuple_list!())
+                .unwrap();
+            executor
+                .run_target(&mut fuzzer, &mut state, &mut manager, &input)
+                .unwrap()
+        };
+
+        // A busy-looping child burns its CPU time
+        assert_eq!(
+            run("while :; do :; done", TimeoutClock::CpuTime),
+            ExitKind::Timeout
+        );
+        // A sleeping child only times out on the wall clock
+        assert_eq!(run("sleep 0.5", TimeoutClock::CpuTime), ExitKind::Ok);
+        assert_eq!(run("sleep 0.5", TimeoutClock::WallClock), ExitKind::Timeout);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg_attr(miri, ignore)]
+    fn test_forkserver_pool() {
+        let mut pool = ForkserverPool::with_instances(2, |i| {
+            ForkserverExecutor::builder()
+                .program("bash")
+                .args(["-c", &fake_slow_forkserver(["0.5", "0"][i])])
+                .timeout(Duration::from_secs(10))
+                .build(tuple_list!())
+        })
+        .unwrap();
+
+        let mut state = test_std_state::<BytesInput>();
+        let mut fuzzer = NopFuzzer::new();
+        let mut manager = NopEventManager::new();
+        let input = BytesInput::new(vec![0]);
+
+        // Single executions take turns
+        let mut used = vec![];
+        for _ in 0..4 {
+            assert_eq!(
+                pool.run_target(&mut fuzzer, &mut state, &mut manager, &input)
+                    .unwrap(),
+                ExitKind::Ok
+            );
+            used.push(pool.current());
+        }
+        assert_eq!(used, [1, 0, 1, 0]);
+
+        // In a batch, the fast instance steals the inputs while the slow one is busy
+        let inputs = vec![input; 4];
+        let mut done = vec![];
+        pool.run_batch(&mut state, &inputs, |_, idx, exit_kind, _observers| {
+            assert_eq!(exit_kind, ExitKind::Ok);
+            done.push(idx);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(done, [1, 2, 3, 0]);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg_attr(miri, ignore)]
+    fn test_forkserver_restart_target() {
+        let marker =
+            std::env::temp_dir().join(format!("libafl_forkserver_restart_{}", std::process::id()));
+        let _ = fs::remove_file(&marker);
+        let mut executor = ForkserverExecutor::builder()
+            .program("bash")
+            .args(["-c", &fake_dying_forkserver(&marker.to_string_lossy())])
+            .build(tuple_list!())
+            .unwrap();
+
+        let mut state = test_std_state::<BytesInput>();
+        let mut fuzzer = NopFuzzer::new();
+        let mut manager = NopEventManager::new();
+        let input = BytesInput::new(vec![0]);
+
+        // The forkserver died, which the fuzzer may recover from by restarting it
+        let err = executor
+            .run_target(&mut fuzzer, &mut state, &mut manager, &input)
+            .unwrap_err();
+        assert!(err.is_recoverable(), "{err}");
+
+        Executor::<NopEventManager<_>, NopFuzzer<_>>::restart_target(&mut executor, &mut state)
+            .unwrap();
+        for _ in 0..2 {
+            assert_eq!(
+                executor
+                    .run_target(&mut fuzzer, &mut state, &mut manager, &input)
+                    .unwrap(),
+                ExitKind::Ok
+            );
+        }
+        fs::remove_file(&marker).unwrap();
+    }
+
     #[test]
     #[serial]
     #[cfg_attr(miri, ignore)]
@@ -12,8 +12,6 @@ use core::{
 #[cfg(all(target_os = "linux", feature = "std"))]
 use core::{mem::zeroed, ptr::addr_of};
 
-#[cfg(all(target_os = "linux", feature = "std"))]
-use libafl_bolts::current_time;
 #[cfg(all(unix, feature = "std", not(miri)))]
 use libafl_bolts::os::unix_signals::setup_signal_handler;
 #[cfg(all(windows, feature = "std"))]
@@ -143,7 +141,7 @@ where
                 return false;
             }
             //eprintln!("handle_timeout {:?} {}", self.avg_exec_time, self.avg_mul_k);
-            let cur_time = current_time();
+            let cur_time = self.timer().clock().now();
             if !data.is_valid() {
                 // outside the target
                 unsafe {
@@ -182,7 +180,7 @@ where
                     self.timer_mut().avg_exec_time = elapsed / self.timer().executions;
                     self.timer_mut().executions = 0; // It will be 1 when the exec finish
                 }
-                self.timer_mut().tmout_start_time = current_time();
+                self.timer_mut().tmout_start_time = self.timer().clock().now();
                 self.timer_mut().avg_mul_k += 1;
                 self.timer_mut().last_signal_time = cur_time;
                 true
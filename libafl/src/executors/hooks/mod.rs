@@ -1,5 +1,6 @@
 //! Hooks for the executors.
 //! These will be executed right before and after the executor's harness run.
+//! Executors without hooks of their own run them with [`crate::executors::Executor::with_hooks`].
 
 use crate::{executors::HasObservers, inputs::UsesInput};
 
@@ -22,6 +23,10 @@ pub mod inprocess;
 #[cfg(feature = "std")]
 pub mod timer;
 
+/// The hook limiting how often the target runs
+#[cfg(feature = "std")]
+pub mod rate_limit;
+
 /// The hook that runs before and after the executor runs the target
 pub trait ExecutorHook<S>
 where
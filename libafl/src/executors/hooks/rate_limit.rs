@@ -0,0 +1,103 @@
+//! The [`RateLimitHook`] limits how often the target runs, e.g. to not overload a remote target
+//! or a device. Layer it on any executor with [`crate::executors::Executor::with_hooks`].
+
+use core::{marker::PhantomData, time::Duration};
+use std::{thread, time::Instant};
+
+use crate::{
+    executors::{hooks::ExecutorHook, HasObservers},
+    inputs::UsesInput,
+};
+
+/// Runs the target at most a given number of times per second, sleeping before the runs that
+/// would come too early
+#[derive(Debug, Clone)]
+pub struct RateLimitHook<S> {
+    interval: Duration,
+    last_run: Option<Instant>,
+    phantom: PhantomData<S>,
+}
+
+impl<S> RateLimitHook<S> {
+    /// Creates a new [`RateLimitHook`], running the target at most `max_runs_per_sec` times per
+    /// second
+    #[must_use]
+    pub fn new(max_runs_per_sec: u32) -> Self {
+        Self::with_interval(Duration::from_secs(1) / max_runs_per_sec.max(1))
+    }
+
+    /// Creates a new [`RateLimitHook`], keeping at least `interval` between the starts of two runs
+    #[must_use]
+    pub fn with_interval(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_run: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The minimal time between the starts of two runs
+    #[must_use]
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+impl<S> ExecutorHook<S> for RateLimitHook<S>
+where
+    S: UsesInput,
+{
+    fn init<E: HasObservers>(&mut self, _state: &mut S) {}
+
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) {
+        if let Some(last_run) = self.last_run {
+            if let Some(remaining) = self.interval.checked_sub(last_run.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+        self.last_run = Some(Instant::now());
+    }
+
+    fn post_exec(&mut self, _state: &mut S, _input: &S::Input) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::time::Instant;
+
+    use libafl_bolts::tuples::tuple_list;
+
+    use super::RateLimitHook;
+    use crate::{
+        events::NopEventManager,
+        executors::{test::NopExecutor, Executor, WithHooks, WithObservers},
+        fuzzer::test::NopFuzzer,
+        inputs::BytesInput,
+        state::NopState,
+    };
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_rate_limit_hook() {
+        let mut state = NopState::new();
+        let mut executor = WithHooks::new(
+            WithObservers::new(NopExecutor::new(), ()),
+            tuple_list!(RateLimitHook::with_interval(Duration::from_millis(20))),
+            &mut state,
+        );
+        let start = Instant::now();
+        for _ in 0..3 {
+            executor
+                .run_target(
+                    &mut NopFuzzer::new(),
+                    &mut state,
+                    &mut NopEventManager::new(),
+                    &BytesInput::new(vec![1]),
+                )
+                .unwrap();
+        }
+        // The first run starts right away, the other two wait for the interval
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -19,7 +19,7 @@ use core::{
 };
 
 #[cfg(target_os = "linux")]
-use libafl_bolts::current_time;
+use libafl_bolts::clock::{Clock, SYSTEM_CLOCK};
 #[cfg(windows)]
 use windows::Win32::{
     Foundation::FILETIME,
@@ -32,6 +32,11 @@ use windows::Win32::{
 
 #[cfg(windows)]
 use crate::executors::hooks::inprocess::GLOBAL_STATE;
+#[cfg(target_os = "linux")]
+use crate::{
+    executors::{TimeoutClock, CPU_TIMEOUT_WALL_FACTOR},
+    Error,
+};
 
 #[repr(C)]
 #[cfg(all(unix, not(target_os = "linux")))]
@@ -95,6 +100,9 @@ pub struct TimerStruct {
     pub(crate) itimerspec: libc::itimerspec,
     #[cfg(target_os = "linux")]
     pub(crate) executions: u32,
+    /// The maximal number of executions sharing one armed timer in batch mode
+    #[cfg(target_os = "linux")]
+    pub(crate) max_batch_executions: u32,
     #[cfg(target_os = "linux")]
     pub(crate) avg_mul_k: u32,
     #[cfg(target_os = "linux")]
@@ -105,6 +113,35 @@ pub struct TimerStruct {
     pub(crate) start_time: Duration,
     #[cfg(target_os = "linux")]
     pub(crate) tmout_start_time: Duration,
+    /// The time source of the batch mode
+    #[cfg(target_os = "linux")]
+    pub(crate) clock: &'static dyn Clock,
+    /// The clock the timeout is measured on
+    #[cfg(target_os = "linux")]
+    pub(crate) timeout_clock: TimeoutClock,
+    /// The timer measuring CPU time, in [`TimeoutClock::CpuTime`] mode.
+    /// The wall-clock timer then only catches targets that block.
+    #[cfg(target_os = "linux")]
+    pub(crate) cpu_timerid: Option<libc::timer_t>,
+    /// The timeout of the wall-clock timer, in [`TimeoutClock::CpuTime`] mode
+    #[cfg(target_os = "linux")]
+    pub(crate) wall_itimerspec: libc::itimerspec,
+}
+
+/// Converts a timeout to a one-shot `itimerspec`
+#[cfg(target_os = "linux")]
+fn oneshot_itimerspec(timeout: Duration) -> libc::itimerspec {
+    let milli_sec = timeout.as_millis();
+    libc::itimerspec {
+        it_interval: libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        },
+        it_value: libc::timespec {
+            tv_sec: (milli_sec / 1000) as _,
+            tv_nsec: ((milli_sec % 1000) * 1000 * 1000) as _,
+        },
+    }
 }
 
 #[cfg(all(feature = "std", windows))]
@@ -210,19 +247,7 @@ impl TimerStruct {
     #[allow(unused_mut)]
     /// Create a `TimerStruct` with the specified timeout
     pub fn new(exec_tmout: Duration) -> Self {
-        let milli_sec = exec_tmout.as_millis();
-        let it_value = libc::timespec {
-            tv_sec: (milli_sec / 1000) as _,
-            tv_nsec: ((milli_sec % 1000) * 1000 * 1000) as _,
-        };
-        let it_interval = libc::timespec {
-            tv_sec: 0,
-            tv_nsec: 0,
-        };
-        let itimerspec = libc::itimerspec {
-            it_interval,
-            it_value,
-        };
+        let itimerspec = oneshot_itimerspec(exec_tmout);
         let mut timerid: libc::timer_t = null_mut();
         unsafe {
             #[cfg(not(miri))]
@@ -236,11 +261,16 @@ impl TimerStruct {
             timerid,
             exec_tmout,
             executions: 0,
+            max_batch_executions: u32::MAX,
             avg_mul_k: 1,
             last_signal_time: Duration::ZERO,
             avg_exec_time: Duration::ZERO,
             start_time: Duration::ZERO,
             tmout_start_time: Duration::ZERO,
+            clock: &SYSTEM_CLOCK,
+            timeout_clock: TimeoutClock::WallClock,
+            cpu_timerid: None,
+            wall_itimerspec: itimerspec,
         }
     }
 
@@ -253,6 +283,105 @@ impl TimerStruct {
         me
     }
 
+    /// If the batch mode is enabled
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn is_batch_mode(&self) -> bool {
+        self.batch_mode
+    }
+
+    /// Enables or disables the batch mode: instead of arming and disarming the timer for each
+    /// execution, it is armed once for many executions, and only checked precisely when it fires.
+    /// The timer is re-armed once the remaining time gets close to the average execution time,
+    /// or after [`Self::set_max_batch_executions`] executions.
+    /// The batch mode only supports [`TimeoutClock::WallClock`].
+    #[cfg(target_os = "linux")]
+    pub fn set_batch_mode(&mut self, batch_mode: bool) -> Result<(), Error> {
+        if batch_mode && self.timeout_clock == TimeoutClock::CpuTime {
+            return Err(Error::illegal_argument(
+                "Batched timeouts can only be measured in wall-clock time",
+            ));
+        }
+        self.batch_mode = batch_mode;
+        self.executions = 0;
+        Ok(())
+    }
+
+    /// The maximal number of executions sharing one armed timer in batch mode
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn max_batch_executions(&self) -> u32 {
+        self.max_batch_executions
+    }
+
+    /// Sets the maximal number of executions sharing one armed timer in batch mode, unbounded by
+    /// default. Lower values update the average execution time, used to decide when to re-arm,
+    /// more often. `1` arms the timer for each execution, like outside of batch mode.
+    #[cfg(target_os = "linux")]
+    pub fn set_max_batch_executions(&mut self, max_batch_executions: u32) {
+        self.max_batch_executions = max_batch_executions.max(1);
+    }
+
+    /// The time source used to measure executions in batch mode
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn clock(&self) -> &'static dyn Clock {
+        self.clock
+    }
+
+    /// Sets the time source used to measure executions in batch mode, e.g. a static
+    /// [`libafl_bolts::clock::MockClock`] to test the timeout logic deterministically
+    #[cfg(target_os = "linux")]
+    pub fn set_clock(&mut self, clock: &'static dyn Clock) {
+        self.clock = clock;
+    }
+
+    /// The clock the timeout is measured on
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn timeout_clock(&self) -> TimeoutClock {
+        self.timeout_clock
+    }
+
+    /// Sets the clock the timeout is measured on.
+    /// In [`TimeoutClock::CpuTime`] mode, a target blocking without burning CPU still times out
+    /// after [`CPU_TIMEOUT_WALL_FACTOR`] times the timeout of wall-clock time.
+    /// The batch mode only supports [`TimeoutClock::WallClock`].
+    #[cfg(target_os = "linux")]
+    #[allow(unused_mut)]
+    pub fn set_timeout_clock(&mut self, timeout_clock: TimeoutClock) -> Result<(), Error> {
+        if self.batch_mode && timeout_clock == TimeoutClock::CpuTime {
+            return Err(Error::illegal_argument(
+                "Batched timeouts can only be measured in wall-clock time",
+            ));
+        }
+        if let Some(cpu_timerid) = self.cpu_timerid.take() {
+            #[cfg(not(miri))]
+            unsafe {
+                libc::timer_delete(cpu_timerid);
+            }
+        }
+        self.timeout_clock = timeout_clock;
+        if timeout_clock == TimeoutClock::CpuTime {
+            let mut cpu_timerid: libc::timer_t = null_mut();
+            #[cfg(not(miri))]
+            // creates a new per-process timer, on the CPU time the process burned
+            if unsafe {
+                libc::timer_create(
+                    libc::CLOCK_PROCESS_CPUTIME_ID,
+                    null_mut(),
+                    addr_of_mut!(cpu_timerid),
+                )
+            } != 0
+            {
+                return Err(Error::last_os_error("Could not create the CPU-time timer"));
+            }
+            self.cpu_timerid = Some(cpu_timerid);
+            self.wall_itimerspec = oneshot_itimerspec(self.exec_tmout * CPU_TIMEOUT_WALL_FACTOR);
+        }
+        Ok(())
+    }
+
     #[cfg(all(unix, not(target_os = "linux")))]
     /// Set up timer
     pub fn set_timer(&mut self) {
@@ -299,9 +428,20 @@ impl TimerStruct {
             if self.batch_mode {
                 if self.executions == 0 {
                     libc::timer_settime(self.timerid, 0, addr_of_mut!(self.itimerspec), null_mut());
-                    self.tmout_start_time = current_time();
+                    self.tmout_start_time = self.clock.now();
+                }
+                self.start_time = self.clock.now();
+            } else if let Some(cpu_timerid) = self.cpu_timerid {
+                #[cfg(not(miri))]
+                {
+                    libc::timer_settime(cpu_timerid, 0, addr_of!(self.itimerspec), null_mut());
+                    libc::timer_settime(
+                        self.timerid,
+                        0,
+                        addr_of!(self.wall_itimerspec),
+                        null_mut(),
+                    );
                 }
-                self.start_time = current_time();
             } else {
                 #[cfg(not(miri))]
                 libc::timer_settime(self.timerid, 0, addr_of_mut!(self.itimerspec), null_mut());
@@ -324,11 +464,13 @@ impl TimerStruct {
     pub fn unset_timer(&mut self) {
         if self.batch_mode {
             unsafe {
-                let elapsed = current_time().saturating_sub(self.tmout_start_time);
-                let elapsed_since_signal = current_time().saturating_sub(self.tmout_start_time);
+                let now = self.clock.now();
+                let elapsed = now.saturating_sub(self.tmout_start_time);
+                let elapsed_since_signal = now.saturating_sub(self.tmout_start_time);
                 // elapsed may be > than tmout in case of received but ingored signal
                 if elapsed > self.exec_tmout
                     || self.exec_tmout.saturating_sub(elapsed) < self.avg_exec_time * self.avg_mul_k
+                    || self.executions + 1 >= self.max_batch_executions
                 {
                     let disarmed: libc::itimerspec = zeroed();
                     libc::timer_settime(self.timerid, 0, addr_of!(disarmed), null_mut());
@@ -351,6 +493,10 @@ impl TimerStruct {
                 let disarmed: libc::itimerspec = zeroed();
                 #[cfg(not(miri))]
                 libc::timer_settime(self.timerid, 0, addr_of!(disarmed), null_mut());
+                #[cfg(not(miri))]
+                if let Some(cpu_timerid) = self.cpu_timerid {
+                    libc::timer_settime(cpu_timerid, 0, addr_of!(disarmed), null_mut());
+                }
             }
         }
     }
@@ -375,3 +521,35 @@ impl TimerStruct {
         }
     }
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use core::time::Duration;
+
+    use libafl_bolts::clock::MockClock;
+
+    use super::TimerStruct;
+
+    static CLOCK: MockClock = MockClock::new(Duration::from_secs(1));
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_batch_mode_max_executions() {
+        let mut timer = TimerStruct::batch_mode(Duration::from_secs(1));
+        timer.set_clock(&CLOCK);
+        timer.set_max_batch_executions(3);
+
+        for executions in 1..=2 {
+            timer.set_timer();
+            CLOCK.advance(Duration::from_millis(1));
+            timer.unset_timer();
+            assert_eq!(timer.executions, executions);
+        }
+        // The third execution ends the batch, and the timer is re-armed for the next one
+        timer.set_timer();
+        CLOCK.advance(Duration::from_millis(1));
+        timer.unset_timer();
+        assert_eq!(timer.executions, 0);
+        assert_eq!(timer.avg_exec_time, Duration::from_micros(1500));
+    }
+}
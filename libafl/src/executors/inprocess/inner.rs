@@ -15,6 +15,8 @@ use windows::Win32::System::Threading::SetThreadStackGuarantee;
 use crate::executors::hooks::inprocess::HasTimeout;
 #[cfg(all(windows, feature = "std"))]
 use crate::executors::hooks::inprocess::HasTimeout;
+#[cfg(all(feature = "std", target_os = "linux"))]
+use crate::executors::TimeoutClock;
 use crate::{
     events::{EventFirer, EventRestarter},
     executors::{
@@ -209,7 +211,35 @@ where
         let mut me = Self::with_timeout_generic::<E, EM, OF, Z>(
             user_hooks, observers, fuzzer, state, event_mgr, exec_tmout,
         )?;
-        me.hooks_mut().0.timer_mut().batch_mode = true;
+        me.hooks_mut().0.timer_mut().set_batch_mode(true)?;
+        Ok(me)
+    }
+
+    /// Create a new in mem executor, measuring the timeout on the CPU time the target burned,
+    /// see [`TimeoutClock::CpuTime`]
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    pub fn cpu_timeout_generic<E, EM, OF, Z>(
+        user_hooks: HT,
+        observers: OT,
+        fuzzer: &mut Z,
+        state: &mut S,
+        event_mgr: &mut EM,
+        exec_tmout: Duration,
+    ) -> Result<Self, Error>
+    where
+        E: Executor<EM, Z, State = S> + HasObservers + HasInProcessHooks<S>,
+        EM: EventFirer<State = S> + EventRestarter,
+        OF: Feedback<S>,
+        S: State,
+        Z: HasObjective<Objective = OF, State = S>,
+    {
+        let mut me = Self::with_timeout_generic::<E, EM, OF, Z>(
+            user_hooks, observers, fuzzer, state, event_mgr, exec_tmout,
+        )?;
+        me.hooks_mut()
+            .0
+            .timer_mut()
+            .set_timeout_clock(TimeoutClock::CpuTime)?;
         Ok(me)
     }
 
@@ -18,6 +18,8 @@ use core::{
 
 use libafl_bolts::tuples::{tuple_list, RefIndexable};
 
+#[cfg(all(feature = "std", target_os = "linux"))]
+use crate::executors::hooks::inprocess::HasTimeout;
 #[cfg(any(unix, feature = "std"))]
 use crate::executors::hooks::inprocess::GLOBAL_STATE;
 use crate::{
@@ -226,6 +228,40 @@ where
         })
     }
 
+    /// Create a new in mem executor, measuring the timeout on the CPU time the target burned,
+    /// see [`TimeoutClock::CpuTime`](crate::executors::TimeoutClock::CpuTime)
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    pub fn with_cpu_timeout<EM, OF, Z>(
+        harness_fn: &'a mut H,
+        observers: OT,
+        fuzzer: &mut Z,
+        state: &mut S,
+        event_mgr: &mut EM,
+        exec_tmout: Duration,
+    ) -> Result<Self, Error>
+    where
+        Self: Executor<EM, Z, State = S>,
+        EM: EventFirer<State = S> + EventRestarter,
+        OF: Feedback<S>,
+        S: State,
+        Z: HasObjective<Objective = OF, State = S>,
+    {
+        let inner = GenericInProcessExecutorInner::cpu_timeout_generic::<Self, EM, OF, Z>(
+            tuple_list!(),
+            observers,
+            fuzzer,
+            state,
+            event_mgr,
+            exec_tmout,
+        )?;
+
+        Ok(Self {
+            harness_fn,
+            inner,
+            phantom: PhantomData,
+        })
+    }
+
     /// Create a new in mem executor.
     /// Caution: crash and restart in one of them will lead to odd behavior if multiple are used,
     /// depending on different corpus or state.
@@ -330,6 +366,36 @@ where
         })
     }
 
+    /// Create a new in mem executor, measuring the timeout on the CPU time the target burned,
+    /// see [`TimeoutClock::CpuTime`](crate::executors::TimeoutClock::CpuTime)
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    pub fn cpu_timeout_generic<EM, OF, Z>(
+        user_hooks: HT,
+        harness_fn: HB,
+        observers: OT,
+        fuzzer: &mut Z,
+        state: &mut S,
+        event_mgr: &mut EM,
+        exec_tmout: Duration,
+    ) -> Result<Self, Error>
+    where
+        Self: Executor<EM, Z, State = S> + HasObservers,
+        EM: EventFirer<State = S> + EventRestarter,
+        OF: Feedback<S>,
+        S: State,
+        Z: HasObjective<Objective = OF, State = S>,
+    {
+        let inner = GenericInProcessExecutorInner::cpu_timeout_generic::<Self, EM, OF, Z>(
+            user_hooks, observers, fuzzer, state, event_mgr, exec_tmout,
+        )?;
+
+        Ok(Self {
+            harness_fn,
+            inner,
+            phantom: PhantomData,
+        })
+    }
+
     /// Create a new [`InProcessExecutor`].
     /// Caution: crash and restart in one of them will lead to odd behavior if multiple are used,
     /// depending on different corpus or state.
@@ -377,6 +443,16 @@ where
         self.harness_fn.borrow_mut()
     }
 
+    /// Arms the timeout timer once for up to `max_executions` executions, instead of for each
+    /// execution, see [`crate::executors::hooks::timer::TimerStruct::set_batch_mode`]
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    pub fn set_batched_timeout(&mut self, max_executions: u32) -> Result<(), Error> {
+        let timer = self.inner.hooks_mut().0.timer_mut();
+        timer.set_batch_mode(true)?;
+        timer.set_max_batch_executions(max_executions);
+        Ok(())
+    }
+
     /// The inprocess handlers
     #[inline]
     pub fn hooks(&self) -> &(InProcessHooks<S>, HT) {
@@ -571,4 +647,80 @@ mod tests {
             .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
             .unwrap();
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[cfg(all(feature = "std", target_os = "linux"))]
+    fn test_inmem_exec_cpu_timeout() {
+        use core::time::Duration;
+        use std::os::fd::AsRawFd;
+
+        use nix::{
+            sys::wait::{waitpid, WaitStatus},
+            unistd::{fork, pipe, read, write, ForkResult},
+        };
+
+        use crate::inputs::{BytesInput, HasMutatorBytes};
+
+        let (reader, writer) = pipe().unwrap();
+        // # Safety
+        // The child only runs the executor and exits right away, the timeout handler exits with 55
+        match unsafe { fork() }.unwrap() {
+            ForkResult::Child => {
+                // Sleeps for the first input, busy-loops for any other
+                let mut harness = |input: &BytesInput| {
+                    if input.bytes()[0] == 0 {
+                        std::thread::sleep(Duration::from_millis(500));
+                    } else {
+                        loop {
+                            core::hint::spin_loop();
+                        }
+                    }
+                    ExitKind::Ok
+                };
+                let rand = libafl_bolts::rands::XkcdRand::new();
+                let corpus = InMemoryCorpus::<BytesInput>::new();
+                let solutions = InMemoryCorpus::new();
+                let mut objective = CrashFeedback::new();
+                let mut feedback = tuple_list!();
+                let sche = RandScheduler::new();
+                let mut mgr = NopEventManager::new();
+                let mut state =
+                    StdState::new(rand, corpus, solutions, &mut feedback, &mut objective).unwrap();
+                let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(sche, feedback, objective);
+
+                let mut executor = InProcessExecutor::with_cpu_timeout(
+                    &mut harness,
+                    tuple_list!(),
+                    &mut fuzzer,
+                    &mut state,
+                    &mut mgr,
+                    Duration::from_millis(200),
+                )
+                .unwrap();
+                // Sleeping does not burn CPU time
+                let exit_kind = executor
+                    .run_target(&mut fuzzer, &mut state, &mut mgr, &BytesInput::new(vec![0]))
+                    .unwrap();
+                write(&writer, &[u8::from(exit_kind == ExitKind::Ok)]).unwrap();
+                let _ = executor.run_target(
+                    &mut fuzzer,
+                    &mut state,
+                    &mut mgr,
+                    &BytesInput::new(vec![1]),
+                );
+                // # Safety
+                // Exits the child without running the test harness of the parent
+                unsafe { libc::_exit(2) }
+            }
+            ForkResult::Parent { child } => {
+                drop(writer);
+                let mut first_ok = [0];
+                assert_eq!(read(reader.as_raw_fd(), &mut first_ok).unwrap(), 1);
+                assert_eq!(first_ok, [1]);
+                // Busy-looping timed out
+                assert_eq!(waitpid(child, None).unwrap(), WaitStatus::Exited(child, 55));
+            }
+        }
+    }
 }
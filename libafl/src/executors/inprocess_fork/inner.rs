@@ -1,3 +1,4 @@
+use alloc::{boxed::Box, vec::Vec};
 use core::{
     ffi::c_void,
     fmt::{self, Debug, Formatter},
@@ -34,6 +35,72 @@ use crate::{
     Error,
 };
 
+/// A memory region restored in the parent after each run
+#[derive(Debug)]
+struct RestoreRegion {
+    ptr: *mut u8,
+    snapshot: Vec<u8>,
+}
+
+/// Resets the state the fork alone does not reset: memory shared with the child, such as
+/// `MAP_SHARED` mappings of the target, and resources the child inherits, such as the offsets of
+/// open files.
+#[derive(Default)]
+pub struct ForkReset {
+    regions: Vec<RestoreRegion>,
+    post_fork_callbacks: Vec<Box<dyn FnMut() -> Result<(), Error>>>,
+}
+
+impl Debug for ForkReset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ForkReset")
+            .field("regions", &self.regions)
+            .field("post_fork_callbacks", &self.post_fork_callbacks.len())
+            .finish()
+    }
+}
+
+/// The exit code of a forked child whose setup failed before running the target
+const CHILD_SETUP_FAILED_EXIT_CODE: i32 = 125;
+
+impl ForkReset {
+    /// Restores the `len` bytes at `ptr` to their current content, after each run.
+    ///
+    /// # Safety
+    /// The region has to stay valid and writable for the lifetime of the executor.
+    pub unsafe fn add_region(&mut self, ptr: *mut u8, len: usize) {
+        let snapshot = core::slice::from_raw_parts(ptr, len).to_vec();
+        self.regions.push(RestoreRegion { ptr, snapshot });
+    }
+
+    /// Runs the callback in the child, right after the fork, e.g. to reopen file descriptors
+    pub fn add_post_fork_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut() -> Result<(), Error> + 'static,
+    {
+        self.post_fork_callbacks.push(Box::new(callback));
+    }
+
+    fn run_post_fork_callbacks(&mut self) -> Result<(), Error> {
+        for callback in &mut self.post_fork_callbacks {
+            callback()?;
+        }
+        Ok(())
+    }
+
+    fn restore_regions(&self) {
+        for region in &self.regions {
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    region.snapshot.as_ptr(),
+                    region.ptr,
+                    region.snapshot.len(),
+                );
+            }
+        }
+    }
+}
+
 /// Inner state of GenericInProcessExecutor-like structures.
 pub struct GenericInProcessForkExecutorInner<HT, OT, S, SP, EM, Z>
 where
@@ -47,6 +114,7 @@ where
     pub(super) hooks: (InChildProcessHooks<S>, HT),
     pub(super) shmem_provider: SP,
     pub(super) observers: OT,
+    pub(super) reset: ForkReset,
     #[cfg(target_os = "linux")]
     pub(super) itimerspec: libc::itimerspec,
     #[cfg(all(unix, not(target_os = "linux")))]
@@ -68,6 +136,7 @@ where
         f.debug_struct("GenericInProcessForkExecutorInner")
             .field("observers", &self.observers)
             .field("shmem_provider", &self.shmem_provider)
+            .field("reset", &self.reset)
             .field("itimerspec", &self.itimerspec)
             .finish_non_exhaustive()
     }
@@ -79,6 +148,7 @@ where
             .debug_struct("GenericInProcessForkExecutorInner")
             .field("observers", &self.observers)
             .field("shmem_provider", &self.shmem_provider)
+            .field("reset", &self.reset)
             .field("itimerval", &self.itimerval)
             .finish_non_exhaustive();
     }
@@ -112,7 +182,15 @@ where
         mgr: &mut EM,
         input: &<GenericInProcessForkExecutorInner<HT, OT, S, SP, EM, Z> as UsesInput>::Input,
     ) -> Result<(), Error> {
-        self.shmem_provider.post_fork(true)?;
+        // The child must never return into the fuzzer loop, or two fuzzers would keep running
+        if let Err(err) = self
+            .shmem_provider
+            .post_fork(true)
+            .and_then(|()| self.reset.run_post_fork_callbacks())
+        {
+            log::error!("Failed to set up the forked child: {err}");
+            libc::_exit(CHILD_SETUP_FAILED_EXIT_CODE);
+        }
 
         self.enter_target(fuzzer, state, mgr, input);
         self.hooks.pre_exec_all(state, input);
@@ -162,6 +240,7 @@ where
         self.shmem_provider.post_fork(false)?;
 
         let res = waitpid(child, None)?;
+        self.reset.restore_regions();
         log::trace!("{res:#?}");
         match res {
             WaitStatus::Signaled(_, signal, _) => match signal {
@@ -170,6 +249,9 @@ where
                 }
                 _ => Ok(ExitKind::Crash),
             },
+            WaitStatus::Exited(_, CHILD_SETUP_FAILED_EXIT_CODE) => Err(Error::illegal_state(
+                "Failed to set up the forked child, e.g. in a post-fork callback",
+            )),
             WaitStatus::Exited(_, code) => {
                 if code > 128 && code < 160 {
                     // Signal exit codes
@@ -271,6 +353,7 @@ where
         Ok(Self {
             shmem_provider,
             observers,
+            reset: ForkReset::default(),
             hooks,
             itimerspec,
             phantom: PhantomData,
@@ -296,7 +379,7 @@ where
         let milli_sec = timeout.as_millis();
         let it_value = Timeval {
             tv_sec: (milli_sec / 1000) as i64,
-            tv_usec: (milli_sec % 1000) as i64,
+            tv_usec: ((milli_sec % 1000) * 1000) as i64,
         };
         let it_interval = Timeval {
             tv_sec: 0,
@@ -310,6 +393,7 @@ where
         Ok(Self {
             shmem_provider,
             observers,
+            reset: ForkReset::default(),
             hooks,
             itimerval,
             phantom: PhantomData,
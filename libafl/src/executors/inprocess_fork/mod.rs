@@ -229,6 +229,36 @@ where {
     }
 }
 
+impl<'a, H, HT, OT, S, SP, EM, Z> GenericInProcessForkExecutor<'a, H, HT, OT, S, SP, EM, Z>
+where
+    H: FnMut(&S::Input) -> ExitKind + ?Sized,
+    HT: ExecutorHooksTuple<S>,
+    OT: ObserversTuple<S>,
+    S: UsesInput,
+    SP: ShMemProvider,
+    EM: UsesState<State = S>,
+    Z: UsesState<State = S>,
+{
+    /// Restores the `len` bytes at `ptr` to their current content after each run, for memory
+    /// the fork does not reset, such as `MAP_SHARED` mappings of the target.
+    ///
+    /// # Safety
+    /// The region has to stay valid and writable for the lifetime of the executor.
+    pub unsafe fn restore_region(&mut self, ptr: *mut u8, len: usize) {
+        self.inner.reset.add_region(ptr, len);
+    }
+
+    /// Runs the callback in the child right after the fork, e.g. to reopen file descriptors
+    /// whose offsets would otherwise be shared with the fuzzer.
+    /// If a callback fails, the child exits without running the target, and the run fails.
+    pub fn add_post_fork_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut() -> Result<(), Error> + 'static,
+    {
+        self.inner.reset.add_post_fork_callback(callback);
+    }
+}
+
 impl<'a, H, HT, OT, S, SP, EM, Z> UsesObservers
     for GenericInProcessForkExecutor<'a, H, HT, OT, S, SP, EM, Z>
 where
@@ -366,7 +396,10 @@ mod tests {
     use serial_test::serial;
 
     use crate::{
-        executors::{inprocess_fork::GenericInProcessForkExecutorInner, Executor, ExitKind},
+        executors::{
+            inprocess_fork::{inner::ForkReset, GenericInProcessForkExecutorInner},
+            Executor, ExitKind,
+        },
         inputs::NopInput,
     };
 
@@ -426,6 +459,7 @@ mod tests {
                 hooks: tuple_list!(default),
                 shmem_provider: provider,
                 observers: tuple_list!(),
+                reset: ForkReset::default(),
                 itimerspec,
                 phantom: PhantomData,
             },
@@ -437,6 +471,7 @@ mod tests {
                 hooks: tuple_list!(default),
                 shmem_provider: provider,
                 observers: tuple_list!(),
+                reset: ForkReset::default(),
                 itimerval: itimerspec,
                 phantom: PhantomData,
             },
@@ -449,4 +484,124 @@ mod tests {
             .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
             .unwrap();
     }
+
+    #[test]
+    #[serial]
+    #[cfg_attr(miri, ignore)]
+    #[cfg(all(feature = "std", feature = "fork", unix))]
+    fn test_inprocessfork_reset() {
+        use core::{marker::PhantomData, ptr::null_mut};
+
+        use libafl_bolts::shmem::{ShMemProvider, StdShMemProvider};
+
+        #[cfg(not(target_os = "linux"))]
+        use crate::executors::hooks::timer::{Itimerval, Timeval};
+        use crate::{
+            events::SimpleEventManager,
+            executors::{
+                hooks::inprocess_fork::InChildProcessHooks,
+                inprocess_fork::GenericInProcessForkExecutor,
+            },
+            fuzzer::test::NopFuzzer,
+            state::NopState,
+            Error,
+        };
+
+        // Memory shared with the child, which the fork alone does not reset
+        let shared = unsafe {
+            libc::mmap(
+                null_mut(),
+                3,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        }
+        .cast::<u8>();
+        assert_ne!(shared.cast(), libc::MAP_FAILED);
+        let (restored, marker, ran) = (shared, unsafe { shared.add(1) }, unsafe { shared.add(2) });
+
+        let mut harness = |_buf: &NopInput| {
+            unsafe {
+                restored.write_volatile(42);
+                ran.write_volatile(1);
+            }
+            ExitKind::Ok
+        };
+        #[cfg(target_os = "linux")]
+        let timeout = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: libc::timespec {
+                tv_sec: 5,
+                tv_nsec: 0,
+            },
+        };
+        #[cfg(not(target_os = "linux"))]
+        let timeout = Itimerval {
+            it_interval: Timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            it_value: Timeval {
+                tv_sec: 5,
+                tv_usec: 0,
+            },
+        };
+        let mut executor = GenericInProcessForkExecutor {
+            harness_fn: &mut harness,
+            inner: GenericInProcessForkExecutorInner {
+                hooks: tuple_list!(InChildProcessHooks::nop()),
+                shmem_provider: StdShMemProvider::new().unwrap(),
+                observers: tuple_list!(),
+                reset: ForkReset::default(),
+                #[cfg(target_os = "linux")]
+                itimerspec: timeout,
+                #[cfg(not(target_os = "linux"))]
+                itimerval: timeout,
+                phantom: PhantomData,
+            },
+        };
+        unsafe { executor.restore_region(restored, 1) };
+        executor.add_post_fork_callback(move || {
+            unsafe { marker.write_volatile(1) };
+            Ok(())
+        });
+
+        executor
+            .run_target(
+                &mut NopFuzzer::new(),
+                &mut NopState::new(),
+                &mut SimpleEventManager::printing(),
+                &NopInput {},
+            )
+            .unwrap();
+        unsafe {
+            // The callback ran in the child, and the write of the harness was undone
+            assert_eq!(marker.read_volatile(), 1);
+            assert_eq!(restored.read_volatile(), 0);
+            assert_eq!(ran.read_volatile(), 1);
+            ran.write_volatile(0);
+        }
+
+        // A failing callback stops the child before the target, and fails the run in the parent
+        let parent = std::process::id();
+        executor.add_post_fork_callback(|| Err(Error::unknown("post-fork failure")));
+        executor
+            .run_target(
+                &mut NopFuzzer::new(),
+                &mut NopState::new(),
+                &mut SimpleEventManager::printing(),
+                &NopInput {},
+            )
+            .unwrap_err();
+        assert_eq!(std::process::id(), parent);
+        unsafe {
+            assert_eq!(ran.read_volatile(), 0);
+            libc::munmap(shared.cast(), 3);
+        }
+    }
 }
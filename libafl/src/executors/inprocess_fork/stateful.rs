@@ -222,6 +222,35 @@ where
     }
 }
 
+impl<'a, H, HT, OT, S, SP, ES, EM, Z>
+    StatefulGenericInProcessForkExecutor<'a, H, HT, OT, S, SP, ES, EM, Z>
+where
+    H: FnMut(&S::Input, &mut ES) -> ExitKind + ?Sized,
+    HT: ExecutorHooksTuple<S>,
+    OT: ObserversTuple<S>,
+    S: UsesInput,
+    SP: ShMemProvider,
+    EM: UsesState<State = S>,
+    Z: UsesState<State = S>,
+{
+    /// Restores the `len` bytes at `ptr` to their current content after each run, see
+    /// [`super::GenericInProcessForkExecutor::restore_region`]
+    ///
+    /// # Safety
+    /// The region has to stay valid and writable for the lifetime of the executor.
+    pub unsafe fn restore_region(&mut self, ptr: *mut u8, len: usize) {
+        self.inner.reset.add_region(ptr, len);
+    }
+
+    /// Runs the callback in the child right after the fork, e.g. to reopen file descriptors
+    pub fn add_post_fork_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut() -> Result<(), Error> + 'static,
+    {
+        self.inner.reset.add_post_fork_callback(callback);
+    }
+}
+
 impl<'a, H, HT, OT, S, SP, ES, EM, Z> UsesObservers
     for StatefulGenericInProcessForkExecutor<'a, H, HT, OT, S, SP, ES, EM, Z>
 where
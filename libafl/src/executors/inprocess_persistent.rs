@@ -0,0 +1,509 @@
+//! The [`InProcessPersistentExecutor`] runs the harness in-process on Windows, and keeps the
+//! process alive through crashes and timeouts.
+//!
+//! The harness runs on a worker thread, which is respawned whenever an execution kills it:
+//! - Crashes are caught by a vectored exception handler. It reports the exception and ends the
+//!   faulting worker thread, instead of the whole process.
+//! - Timeouts are caught by the executor, acting as watchdog of the worker thread: if the harness
+//!   did not finish within the timeout, the worker thread is terminated.
+//!
+//! This avoids both the process-per-exec of the [`crate::executors::CommandExecutor`], and the
+//! restart of the fuzzer after each crash of the [`crate::executors::InProcessExecutor`].
+//! However, ending a thread in the middle of the target leaks its memory and may leave locks held.
+//! The lock of the process heap is taken care of: a worker thread is never terminated while it
+//! allocates. Other locks, e.g. of the target itself, may still be left held by a timed out
+//! harness. For targets with much global state, prefer the
+//! [`crate::executors::InProcessExecutor`] with a restarting event manager.
+
+use core::{
+    cell::Cell,
+    ffi::c_void,
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    ptr,
+    time::Duration,
+};
+use std::{
+    os::windows::io::AsRawHandle,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, SyncSender},
+        Once,
+    },
+    thread::{self, JoinHandle},
+};
+
+use libafl_bolts::{
+    os::windows_exceptions::{
+        AddVectoredExceptionHandler, ExceptionCode, CRASH_EXCEPTIONS, EXCEPTION_POINTERS,
+    },
+    tuples::{tuple_list, RefIndexable},
+};
+use windows::Win32::{
+    Foundation::HANDLE,
+    System::{
+        Memory::{GetProcessHeap, HeapLock, HeapUnlock},
+        Threading::{
+            ExitThread, SetThreadStackGuarantee, TerminateThread, WaitForSingleObject, INFINITE,
+        },
+    },
+};
+
+use crate::{
+    executors::{hooks::ExecutorHooksTuple, Executor, ExitKind, HasObservers},
+    inputs::UsesInput,
+    observers::{ObserversTuple, UsesObservers},
+    state::{HasExecutions, State, UsesState},
+    Error,
+};
+
+/// The default stack size of the worker thread running the harness
+pub const DEFAULT_WORKER_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// The stack reserved for the exception handler, if the harness overflows the stack.
+/// See the `InProcessExecutor` for the reasoning behind this value.
+const WORKER_STACK_GUARANTEE: u32 = 0x20000;
+
+/// The exit code of a worker thread ended by a crash
+const WORKER_CRASH_EXIT_CODE: u32 = 1;
+/// The exit code of a worker thread terminated by a timeout
+const WORKER_TIMEOUT_EXIT_CODE: u32 = 2;
+
+/// Lets other vectored exception handlers, or the unhandled exception filter, take care of it
+const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+
+static REGISTER_EXCEPTION_HANDLER: Once = Once::new();
+
+thread_local! {
+    /// The reply channel of the execution currently running on this worker thread, if any
+    static CURRENT_REPLY: Cell<*const SyncSender<WorkerStatus>> = const { Cell::new(ptr::null()) };
+}
+
+/// How an execution on the worker thread ended
+#[derive(Debug)]
+enum WorkerStatus {
+    /// The harness returned
+    Finished(ExitKind),
+    /// The harness panicked, the worker thread is still alive
+    Panicked,
+    /// The harness raised this exception, the worker thread is gone
+    Crashed(i32),
+}
+
+/// An execution handed to the worker thread, with the types of the harness and the input erased
+struct Job {
+    run: unsafe fn(*mut c_void, *const c_void) -> ExitKind,
+    harness: *mut c_void,
+    input: *const c_void,
+    reply: SyncSender<WorkerStatus>,
+}
+
+// # Safety
+// The harness is `Send` and the input `Sync`, as required by the executor.
+// The executor waits for the job to finish, or for the worker to be gone, before the pointers dangle.
+unsafe impl Send for Job {}
+
+/// Runs the harness behind `harness` (a `&mut H`) on the input behind `input`
+unsafe fn run_harness<H, I>(harness: *mut c_void, input: *const c_void) -> ExitKind
+where
+    H: FnMut(&I) -> ExitKind + ?Sized,
+{
+    let harness = &mut *harness.cast::<&mut H>();
+    harness(&*input.cast::<I>())
+}
+
+/// Ends the worker thread on crash exceptions raised by the harness
+unsafe extern "system" fn worker_exception_handler(info: *mut EXCEPTION_POINTERS) -> i32 {
+    let reply = CURRENT_REPLY.with(Cell::get);
+    if reply.is_null() {
+        // Not in a harness on a worker thread
+        return EXCEPTION_CONTINUE_SEARCH;
+    }
+    let Some(record) = info.as_ref().and_then(|info| info.ExceptionRecord.as_ref()) else {
+        return EXCEPTION_CONTINUE_SEARCH;
+    };
+    if !CRASH_EXCEPTIONS.contains(&ExceptionCode::from(record.ExceptionCode.0)) {
+        return EXCEPTION_CONTINUE_SEARCH;
+    }
+    CURRENT_REPLY.with(|current| current.set(ptr::null()));
+    // The channel is bounded, so sending does not allocate
+    let _ = (*reply).try_send(WorkerStatus::Crashed(record.ExceptionCode.0));
+    ExitThread(WORKER_CRASH_EXIT_CODE);
+}
+
+/// Runs the jobs until the executor is dropped
+fn worker_loop(jobs: &Receiver<Job>) {
+    let mut stack_reserved = WORKER_STACK_GUARANTEE;
+    unsafe {
+        if let Err(err) = SetThreadStackGuarantee(&mut stack_reserved) {
+            log::warn!("Could not reserve stack for the exception handler: {err}");
+        }
+    }
+    while let Ok(job) = jobs.recv() {
+        CURRENT_REPLY.with(|current| current.set(&job.reply));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| unsafe {
+            (job.run)(job.harness, job.input)
+        }));
+        CURRENT_REPLY.with(|current| current.set(ptr::null()));
+        let status = match result {
+            Ok(exit_kind) => WorkerStatus::Finished(exit_kind),
+            Err(_) => WorkerStatus::Panicked,
+        };
+        let _ = job.reply.send(status);
+    }
+}
+
+/// The worker thread running the harness
+#[derive(Debug)]
+struct Worker {
+    jobs: SyncSender<Job>,
+    handle: JoinHandle<()>,
+}
+
+impl Worker {
+    fn spawn(stack_size: usize) -> Result<Self, Error> {
+        let (jobs, receiver) = mpsc::sync_channel(1);
+        let handle = thread::Builder::new()
+            .name("persistent-harness".into())
+            .stack_size(stack_size)
+            .spawn(move || worker_loop(&receiver))?;
+        Ok(Self { jobs, handle })
+    }
+
+    /// Terminates the worker thread, e.g. after a timeout, and waits until it is gone.
+    ///
+    /// The lock of the process heap is held meanwhile, so the thread is not terminated in the
+    /// middle of an allocation, which would leave the heap locked for good.
+    fn terminate(self) -> Result<(), Error> {
+        let thread = HANDLE(self.handle.as_raw_handle() as isize);
+        let terminated = unsafe {
+            let heap = GetProcessHeap()?;
+            HeapLock(heap)?;
+            // Nothing may allocate on this thread until the heap is unlocked
+            let terminated = TerminateThread(thread, WORKER_TIMEOUT_EXIT_CODE);
+            if terminated.is_ok() {
+                // The termination is asynchronous
+                WaitForSingleObject(thread, INFINITE);
+            }
+            HeapUnlock(heap)?;
+            terminated
+        };
+        terminated
+            .map_err(|err| Error::unknown(format!("Could not terminate the worker thread: {err}")))
+    }
+}
+
+/// The [`InProcessPersistentExecutor`] with no user hooks
+pub type InProcessPersistentExecutor<'a, H, OT, S> =
+    GenericInProcessPersistentExecutor<'a, H, (), OT, S>;
+
+/// Runs the harness in-process on a worker thread, surviving crashes and timeouts,
+/// see the [module docs](self)
+///
+/// The harness runs on another thread than the fuzzer, so it has to be [`Send`], and the input
+/// [`Sync`].
+pub struct GenericInProcessPersistentExecutor<'a, H, HT, OT, S>
+where
+    H: FnMut(&S::Input) -> ExitKind + Send + ?Sized,
+    S: UsesInput,
+{
+    harness_fn: &'a mut H,
+    observers: OT,
+    hooks: HT,
+    timeout: Duration,
+    stack_size: usize,
+    worker: Option<Worker>,
+    phantom: PhantomData<S>,
+}
+
+impl<'a, H, HT, OT, S> Debug for GenericInProcessPersistentExecutor<'a, H, HT, OT, S>
+where
+    H: FnMut(&S::Input) -> ExitKind + Send + ?Sized,
+    HT: Debug,
+    OT: Debug,
+    S: UsesInput,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GenericInProcessPersistentExecutor")
+            .field("observers", &self.observers)
+            .field("hooks", &self.hooks)
+            .field("timeout", &self.timeout)
+            .field("stack_size", &self.stack_size)
+            .field("worker", &self.worker)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, H, OT, S> InProcessPersistentExecutor<'a, H, OT, S>
+where
+    H: FnMut(&S::Input) -> ExitKind + Send + ?Sized,
+    OT: ObserversTuple<S>,
+    S: State,
+    S::Input: Sync,
+{
+    /// Creates a new [`InProcessPersistentExecutor`]
+    pub fn new(
+        harness_fn: &'a mut H,
+        observers: OT,
+        state: &mut S,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        Self::with_hooks(tuple_list!(), harness_fn, observers, state, timeout)
+    }
+}
+
+impl<'a, H, HT, OT, S> GenericInProcessPersistentExecutor<'a, H, HT, OT, S>
+where
+    H: FnMut(&S::Input) -> ExitKind + Send + ?Sized,
+    HT: ExecutorHooksTuple<S>,
+    OT: ObserversTuple<S>,
+    S: State,
+    S::Input: Sync,
+{
+    /// Creates a new [`GenericInProcessPersistentExecutor`] with custom hooks, run on the fuzzer
+    /// thread before and after each execution
+    pub fn with_hooks(
+        mut hooks: HT,
+        harness_fn: &'a mut H,
+        observers: OT,
+        state: &mut S,
+        timeout: Duration,
+    ) -> Result<Self, Error> {
+        REGISTER_EXCEPTION_HANDLER.call_once(|| unsafe {
+            // Called first, before any handler installed by the target
+            if AddVectoredExceptionHandler(1, Some(worker_exception_handler)).is_null() {
+                log::error!("Could not register the exception handler for the persistent executor");
+            }
+        });
+        hooks.init_all::<Self>(state);
+        Ok(Self {
+            harness_fn,
+            observers,
+            hooks,
+            timeout,
+            stack_size: DEFAULT_WORKER_STACK_SIZE,
+            worker: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Sets the stack size of the worker thread running the harness
+    #[must_use]
+    pub fn with_stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    /// The timeout of an execution
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Sets the timeout of an execution
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// The harness
+    #[must_use]
+    pub fn harness(&self) -> &H {
+        self.harness_fn
+    }
+
+    /// The harness (mutable)
+    pub fn harness_mut(&mut self) -> &mut H {
+        self.harness_fn
+    }
+
+    /// The user hooks
+    #[must_use]
+    pub fn hooks(&self) -> &HT {
+        &self.hooks
+    }
+
+    /// The user hooks (mutable)
+    pub fn hooks_mut(&mut self) -> &mut HT {
+        &mut self.hooks
+    }
+
+    /// Runs the harness on the worker thread, spawning a new one if the last execution killed it
+    fn run_on_worker(&mut self, input: &S::Input) -> Result<ExitKind, Error> {
+        if self.worker.is_none() {
+            self.worker = Some(Worker::spawn(self.stack_size)?);
+        }
+        let (reply, status) = mpsc::sync_channel(1);
+        let job = Job {
+            run: run_harness::<H, S::Input>,
+            harness: ptr::addr_of_mut!(self.harness_fn).cast(),
+            input: (input as *const S::Input).cast(),
+            reply,
+        };
+        if self.worker.as_ref().unwrap().jobs.send(job).is_err() {
+            self.worker = None;
+            return Err(Error::illegal_state("The worker thread is gone"));
+        }
+
+        Ok(match status.recv_timeout(self.timeout) {
+            Ok(WorkerStatus::Finished(exit_kind)) => exit_kind,
+            Ok(WorkerStatus::Panicked) => {
+                log::error!("Harness panicked");
+                ExitKind::Crash
+            }
+            Ok(WorkerStatus::Crashed(code)) => {
+                log::error!("Harness crashed with {}", ExceptionCode::from(code));
+                // The thread ended itself in the exception handler
+                self.worker = None;
+                ExitKind::Crash
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                log::warn!("Harness timed out, terminating the worker thread");
+                if let Some(worker) = self.worker.take() {
+                    if let Err(err) = worker.terminate() {
+                        // The harness still runs, using the harness and the input we are about to
+                        // hand back. Returning would let it use them after they are gone.
+                        log::error!("{err}, aborting");
+                        std::process::abort();
+                    }
+                }
+                ExitKind::Timeout
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                log::error!("Worker thread ended while running the harness");
+                self.worker = None;
+                ExitKind::Crash
+            }
+        })
+    }
+}
+
+impl<'a, H, HT, OT, S> UsesState for GenericInProcessPersistentExecutor<'a, H, HT, OT, S>
+where
+    H: FnMut(&S::Input) -> ExitKind + Send + ?Sized,
+    S: State,
+{
+    type State = S;
+}
+
+impl<'a, H, HT, OT, S> UsesObservers for GenericInProcessPersistentExecutor<'a, H, HT, OT, S>
+where
+    H: FnMut(&S::Input) -> ExitKind + Send + ?Sized,
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    type Observers = OT;
+}
+
+impl<'a, H, HT, OT, S> HasObservers for GenericInProcessPersistentExecutor<'a, H, HT, OT, S>
+where
+    H: FnMut(&S::Input) -> ExitKind + Send + ?Sized,
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+impl<'a, EM, H, HT, OT, S, Z> Executor<EM, Z>
+    for GenericInProcessPersistentExecutor<'a, H, HT, OT, S>
+where
+    EM: UsesState<State = S>,
+    H: FnMut(&S::Input) -> ExitKind + Send + ?Sized,
+    HT: ExecutorHooksTuple<S>,
+    OT: ObserversTuple<S>,
+    S: State + HasExecutions,
+    S::Input: Sync,
+    Z: UsesState<State = S>,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut Self::State,
+        _mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        *state.executions_mut() += 1;
+        self.hooks.pre_exec_all(state, input);
+        let exit_kind = self.run_on_worker(input)?;
+        self.hooks.post_exec_all(state, input);
+        Ok(exit_kind)
+    }
+
+    fn restart_target(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        // Dropping the worker ends its thread, the next execution spawns a fresh one
+        self.worker = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use core::{hint::black_box, ptr, time::Duration};
+
+    use libafl_bolts::tuples::tuple_list;
+
+    use super::InProcessPersistentExecutor;
+    use crate::{
+        events::NopEventManager,
+        executors::{Executor, ExitKind},
+        fuzzer::test::NopFuzzer,
+        inputs::{BytesInput, HasMutatorBytes},
+        state::test::test_std_state,
+    };
+
+    #[test]
+    fn test_persistent_executor() {
+        let mut harness = |input: &BytesInput| match input.bytes()[0] {
+            b'p' => panic!("harness panic"),
+            b'c' => {
+                // An unmapped address in the first page, faulting with an access violation
+                unsafe { ptr::write_volatile(0x10 as *mut u8, 0) };
+                ExitKind::Ok
+            }
+            // Spins in and out of the heap, until the worker thread is terminated
+            b't' => loop {
+                black_box(vec![0_u8; 64]);
+            },
+            _ => ExitKind::Ok,
+        };
+        let mut state = test_std_state::<BytesInput>();
+        let mut executor = InProcessPersistentExecutor::new(
+            &mut harness,
+            tuple_list!(),
+            &mut state,
+            Duration::from_millis(200),
+        )
+        .unwrap();
+        let mut fuzzer = NopFuzzer::new();
+        let mut mgr = NopEventManager::new();
+
+        // The worker survives panics, and is respawned after crashes and timeouts
+        for (input, exit_kind) in [
+            (b'o', ExitKind::Ok),
+            (b'p', ExitKind::Crash),
+            (b'o', ExitKind::Ok),
+            (b'c', ExitKind::Crash),
+            (b'o', ExitKind::Ok),
+            (b't', ExitKind::Timeout),
+            (b'o', ExitKind::Ok),
+        ] {
+            assert_eq!(
+                executor
+                    .run_target(
+                        &mut fuzzer,
+                        &mut state,
+                        &mut mgr,
+                        &BytesInput::new(vec![input])
+                    )
+                    .unwrap(),
+                exit_kind
+            );
+        }
+    }
+}
@@ -4,42 +4,93 @@
 use alloc::vec::Vec;
 use core::fmt::Debug;
 
+#[cfg(all(feature = "std", unix))]
+pub use adb::AdbExecutor;
+#[cfg(all(feature = "std", unix))]
+pub use afl_custom::AflPostProcessExecutor;
 pub use combined::CombinedExecutor;
 #[cfg(all(feature = "std", any(unix, doc)))]
 pub use command::CommandExecutor;
-pub use differential::DiffExecutor;
+#[cfg(all(feature = "std", unix))]
+pub use core_dump::CoreDumpExecutor;
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub use criu::CriuExecutor;
+pub use differential::{DiffExecutor, DiffReport};
 #[cfg(all(feature = "std", feature = "fork", unix))]
-pub use forkserver::{Forkserver, ForkserverExecutor};
+pub use forkserver::{Forkserver, ForkserverExecutor, ForkserverPool};
 pub use inprocess::InProcessExecutor;
 #[cfg(all(feature = "std", feature = "fork", unix))]
 pub use inprocess_fork::InProcessForkExecutor;
+#[cfg(all(feature = "std", windows))]
+pub use inprocess_persistent::{GenericInProcessPersistentExecutor, InProcessPersistentExecutor};
 #[cfg(unix)]
 use libafl_bolts::os::unix_signals::Signal;
 use libafl_bolts::tuples::RefIndexable;
+#[cfg(feature = "std")]
+pub use network::NetworkExecutor;
 use serde::{Deserialize, Serialize};
 pub use shadow::ShadowExecutor;
+#[cfg(all(feature = "std", feature = "fork", unix))]
+pub use snapshot_fork::{HasSequenceSnapshot, SnapshotForkExecutor};
+#[cfg(all(feature = "std", unix))]
+pub use ssh::SshExecutor;
+pub use with_hooks::WithHooks;
 pub use with_observers::WithObservers;
 
 use crate::{
+    executors::hooks::ExecutorHooksTuple,
     observers::{ObserversTuple, UsesObservers},
     state::UsesState,
     Error,
 };
 
+/// The module for the executor running targets on Android devices over adb
+#[cfg(all(feature = "std", unix))]
+pub mod adb;
+/// The module for the executor post-processing inputs with an AFL++ custom mutator
+#[cfg(all(feature = "std", unix))]
+pub mod afl_custom;
 pub mod combined;
 #[cfg(all(feature = "std", any(unix, doc)))]
 pub mod command;
+/// The module for the core dump collecting executor wrapper
+#[cfg(all(feature = "std", unix))]
+pub mod core_dump;
+/// The module for the executor restoring the target from CRIU snapshots
+#[cfg(all(feature = "std", target_os = "linux"))]
+pub mod criu;
 pub mod differential;
 #[cfg(all(feature = "std", feature = "fork", unix))]
 pub mod forkserver;
 pub mod inprocess;
 
+#[cfg(feature = "std")]
+pub mod sanitizers;
+
 /// The module for inproc fork executor
 #[cfg(all(feature = "std", unix))]
 pub mod inprocess_fork;
 
+/// The module for the persistent inproc executor on Windows, surviving crashes and timeouts
+#[cfg(all(feature = "std", windows))]
+pub mod inprocess_persistent;
+
+/// The module for the network executor, fuzzing servers over TCP or UDP
+#[cfg(feature = "std")]
+pub mod network;
+
+#[cfg(all(feature = "std", unix))]
+mod remote_shell;
 pub mod shadow;
+/// The module for the executor running targets on remote machines over SSH
+#[cfg(all(feature = "std", unix))]
+pub mod ssh;
+
+/// The module for the snapshot fork executor, for stateful targets
+#[cfg(all(feature = "std", feature = "fork", unix))]
+pub mod snapshot_fork;
 
+pub mod with_hooks;
 pub mod with_observers;
 
 /// The module for all the hooks
@@ -108,6 +159,24 @@ impl From<ExitKind> for DiffExitKind {
 
 libafl_bolts::impl_serdeany!(DiffExitKind);
 
+/// The clock an execution timeout is measured on
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TimeoutClock {
+    /// Wall-clock time. On a loaded machine, a target may be flagged as hang only because it
+    /// did not get scheduled.
+    #[default]
+    WallClock,
+    /// The CPU time burned by the target, so that an input is only flagged as hang if the
+    /// target actually spent the time computing.
+    /// Targets blocking without burning CPU still time out after
+    /// [`CPU_TIMEOUT_WALL_FACTOR`] times the timeout of wall-clock time.
+    CpuTime,
+}
+
+/// In [`TimeoutClock::CpuTime`] mode, the factor of the timeout after which a target times out
+/// in wall-clock time, even if it did not burn enough CPU time, e.g. because it blocks.
+pub const CPU_TIMEOUT_WALL_FACTOR: u32 = 10;
+
 /// Holds a tuple of Observers
 pub trait HasObservers: UsesObservers {
     /// Get the linked observers
@@ -132,6 +201,17 @@ where
         input: &Self::Input,
     ) -> Result<ExitKind, Error>;
 
+    /// Restarts the target after [`Executor::run_target`] failed with a recoverable error
+    /// (see [`Error::is_recoverable`]), e.g. by respawning a dead fork server.
+    ///
+    /// Used by [`crate::fuzzer::ExecutorErrorAction::RestartExecutor`].
+    /// Executors that cannot restart their target return [`Error::NotImplemented`].
+    fn restart_target(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        Err(Error::not_implemented(
+            "This executor does not support restarting its target",
+        ))
+    }
+
     /// Wraps this Executor with the given [`ObserversTuple`] to implement [`HasObservers`].
     ///
     /// If the executor already implements [`HasObservers`], then the original implementation will be overshadowed by
@@ -143,6 +223,16 @@ where
     {
         WithObservers::new(self, observers)
     }
+
+    /// Wraps this Executor to run the given [`ExecutorHooksTuple`] before and after each run,
+    /// initializing the hooks right away. See [`WithHooks`].
+    fn with_hooks<HT>(self, hooks: HT, state: &mut Self::State) -> WithHooks<Self, HT>
+    where
+        Self: Sized + HasObservers,
+        HT: ExecutorHooksTuple<Self::State>,
+    {
+        WithHooks::new(self, hooks, state)
+    }
 }
 
 /// The common signals we want to handle
@@ -168,13 +258,14 @@ pub fn common_signals() -> Vec<Signal> {
 pub mod test {
     use core::marker::PhantomData;
 
-    use libafl_bolts::{AsSlice, Error};
+    use libafl_bolts::{tuples::RefIndexable, AsSlice, Error};
 
     use crate::{
         events::NopEventManager,
-        executors::{Executor, ExitKind},
+        executors::{Executor, ExitKind, HasObservers},
         fuzzer::test::NopFuzzer,
         inputs::{BytesInput, HasTargetBytes},
+        observers::{ObserversTuple, UsesObservers},
         state::{HasExecutions, NopState, State, UsesState},
     };
 
@@ -231,6 +322,80 @@ pub mod test {
         }
     }
 
+    /// An executor calling a closure with its observers and the input, to test stages without a
+    /// target. The closure usually fills the maps of the observers, like a target would.
+    pub struct ClosureExecutor<F, OT, S> {
+        harness: F,
+        observers: OT,
+        phantom: PhantomData<S>,
+    }
+
+    impl<F, OT, S> ClosureExecutor<F, OT, S> {
+        /// Creates a new [`ClosureExecutor`]
+        #[must_use]
+        pub fn new(harness: F, observers: OT) -> Self {
+            Self {
+                harness,
+                observers,
+                phantom: PhantomData,
+            }
+        }
+    }
+
+    impl<F, OT, S> core::fmt::Debug for ClosureExecutor<F, OT, S> {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            f.debug_struct("ClosureExecutor").finish_non_exhaustive()
+        }
+    }
+
+    impl<F, OT, S> UsesState for ClosureExecutor<F, OT, S>
+    where
+        S: State,
+    {
+        type State = S;
+    }
+
+    impl<F, OT, S> UsesObservers for ClosureExecutor<F, OT, S>
+    where
+        OT: ObserversTuple<S>,
+        S: State,
+    {
+        type Observers = OT;
+    }
+
+    impl<F, OT, S> HasObservers for ClosureExecutor<F, OT, S>
+    where
+        OT: ObserversTuple<S>,
+        S: State,
+    {
+        fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+            RefIndexable::from(&self.observers)
+        }
+
+        fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+            RefIndexable::from(&mut self.observers)
+        }
+    }
+
+    impl<EM, F, OT, S, Z> Executor<EM, Z> for ClosureExecutor<F, OT, S>
+    where
+        EM: UsesState<State = S>,
+        F: FnMut(&mut OT, &S::Input) -> ExitKind,
+        S: State + HasExecutions,
+        Z: UsesState<State = S>,
+    {
+        fn run_target(
+            &mut self,
+            _fuzzer: &mut Z,
+            state: &mut Self::State,
+            _mgr: &mut EM,
+            input: &Self::Input,
+        ) -> Result<ExitKind, Error> {
+            *state.executions_mut() += 1;
+            Ok((self.harness)(&mut self.observers, input))
+        }
+    }
+
     #[test]
     fn nop_executor() {
         let empty_input = BytesInput::new(vec![]);
@@ -0,0 +1,845 @@
+//! The [`NetworkExecutor`] fuzzes servers: it delivers each input as one or more messages to a
+//! target listening on a TCP or UDP socket.
+//!
+//! The input is split into messages by the [`MessageSplit`], and sent over a connection that is
+//! either kept alive across inputs, or opened anew for each input, see [`ResetStrategy`].
+//! Optionally, the responses of the target are read after each message, and a [`HealthCheck`]
+//! probes the target after each input, to find hangs and crashes that don't close the socket.
+//!
+//! If the executor manages the target process, see [`TargetProcess`], crashes are found by the
+//! exit status of the target, which is restarted right away.
+
+use alloc::vec::Vec;
+use core::{marker::PhantomData, time::Duration};
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::Instant,
+};
+
+use libafl_bolts::{tuples::RefIndexable, AsSlice};
+
+use crate::{
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::HasTargetBytes,
+    observers::{ObserversTuple, UsesObservers},
+    state::{HasExecutions, State, UsesState},
+    Error,
+};
+
+/// The default timeout to connect to the target and send a message
+pub const DEFAULT_NETWORK_TIMEOUT: Duration = Duration::from_secs(1);
+/// The default time to wait for a response of the target
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
+/// The default maximal length of a response of the target
+pub const DEFAULT_MAX_RESPONSE_LEN: usize = 64 * 1024;
+/// The default time a managed target gets to crash after the last message of an input
+pub const DEFAULT_EXIT_GRACE: Duration = Duration::from_millis(10);
+
+/// The time to wait for the rest of a response, after its first bytes arrived
+const RESPONSE_DRAIN_TIMEOUT: Duration = Duration::from_millis(1);
+/// The time between two attempts to connect to a target that is starting up
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(10);
+/// The time between two checks if a managed target exited
+const EXIT_POLL_DELAY: Duration = Duration::from_millis(1);
+
+/// The transport protocol of the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkProtocol {
+    /// A TCP stream
+    Tcp,
+    /// UDP datagrams
+    Udp,
+}
+
+/// How an input is split into the messages sent to the target
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum MessageSplit {
+    /// The whole input is a single message
+    #[default]
+    Single,
+    /// The input is split after each occurrence of the delimiter, e.g. `\r\n` for line-based
+    /// protocols. The delimiter stays part of the message.
+    Delimiter(Vec<u8>),
+    /// The input is split into chunks of this length
+    Chunks(usize),
+}
+
+impl MessageSplit {
+    /// Splits the bytes of an input into messages, never returning empty messages
+    #[must_use]
+    pub fn split<'a>(&self, bytes: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut messages = Vec::new();
+        match self {
+            Self::Delimiter(delimiter) if !delimiter.is_empty() => {
+                let mut start = 0;
+                let mut idx = 0;
+                while idx + delimiter.len() <= bytes.len() {
+                    if bytes[idx..].starts_with(delimiter) {
+                        idx += delimiter.len();
+                        messages.push(&bytes[start..idx]);
+                        start = idx;
+                    } else {
+                        idx += 1;
+                    }
+                }
+                messages.push(&bytes[start..]);
+            }
+            Self::Single | Self::Delimiter(_) => messages.push(bytes),
+            Self::Chunks(len) => messages.extend(bytes.chunks((*len).max(1))),
+        }
+        messages.retain(|message| !message.is_empty());
+        messages
+    }
+}
+
+/// When the connection to the target, or the target itself, is reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResetStrategy {
+    /// Opens a new connection for each input
+    #[default]
+    Reconnect,
+    /// Keeps the connection across inputs, as long as the target keeps it open.
+    /// Faster, but the state of a session leaks into the next input.
+    KeepAlive,
+    /// Restarts the target for each input, for targets with global state.
+    /// Needs a [`TargetProcess`].
+    RestartTarget,
+}
+
+/// A probe of the target after each input: a fresh connection, on which `probe` is sent.
+/// The target is healthy if it answers, with a response starting with `expected`, if set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthCheck {
+    probe: Vec<u8>,
+    expected: Option<Vec<u8>>,
+}
+
+impl HealthCheck {
+    /// Creates a new [`HealthCheck`], sending `probe` and expecting any response
+    #[must_use]
+    pub fn new(probe: Vec<u8>) -> Self {
+        Self {
+            probe,
+            expected: None,
+        }
+    }
+
+    /// Expects a response starting with `expected`
+    #[must_use]
+    pub fn with_expected(mut self, expected: Vec<u8>) -> Self {
+        self.expected = Some(expected);
+        self
+    }
+}
+
+/// The target process of a [`NetworkExecutor`], started, checked and restarted by the executor
+#[derive(Debug)]
+pub struct TargetProcess {
+    command: Command,
+    child: Option<Child>,
+    startup_delay: Duration,
+}
+
+impl TargetProcess {
+    /// Creates a new [`TargetProcess`], not started yet.
+    /// The stdio of the `command` is set to null, discarding the output of the target; use
+    /// [`TargetProcess::command_mut`] to change it afterwards.
+    #[must_use]
+    pub fn new(mut command: Command) -> Self {
+        command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        Self {
+            command,
+            child: None,
+            startup_delay: Duration::ZERO,
+        }
+    }
+
+    /// The command starting the target, e.g. to change its stdio
+    pub fn command_mut(&mut self) -> &mut Command {
+        &mut self.command
+    }
+
+    /// Sets the time the target needs to start up, before it accepts connections.
+    /// Connections are retried until the network timeout either way.
+    #[must_use]
+    pub fn with_startup_delay(mut self, startup_delay: Duration) -> Self {
+        self.startup_delay = startup_delay;
+        self
+    }
+
+    /// Starts the target, unless it is running
+    pub fn start(&mut self) -> Result<(), Error> {
+        if self.child.is_none() {
+            self.child = Some(self.command.spawn()?);
+            thread::sleep(self.startup_delay);
+        }
+        Ok(())
+    }
+
+    /// Kills the target, if it is running
+    pub fn kill(&mut self) -> Result<(), Error> {
+        if let Some(mut child) = self.child.take() {
+            // It may have exited already
+            let _ = child.kill();
+            child.wait()?;
+        }
+        Ok(())
+    }
+
+    /// Kills and starts the target
+    pub fn restart(&mut self) -> Result<(), Error> {
+        self.kill()?;
+        self.start()
+    }
+
+    /// Returns the exit status if the target exited since it was started
+    pub fn exit_status(&mut self) -> Result<Option<ExitStatus>, Error> {
+        let Some(child) = &mut self.child else {
+            return Ok(None);
+        };
+        let status = child.try_wait()?;
+        if status.is_some() {
+            self.child = None;
+        }
+        Ok(status)
+    }
+
+    /// The process id of the running target
+    #[must_use]
+    pub fn pid(&self) -> Option<u32> {
+        self.child.as_ref().map(Child::id)
+    }
+}
+
+impl Drop for TargetProcess {
+    fn drop(&mut self) {
+        let _ = self.kill();
+    }
+}
+
+/// Checks if the target exited because of a crash, rather than on its own
+fn is_crash_status(status: ExitStatus) -> bool {
+    #[cfg(unix)]
+    return status.signal().is_some();
+    #[cfg(not(unix))]
+    !status.success()
+}
+
+/// Checks if an io error means that the peer did not answer in time
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// A connection to the target
+#[derive(Debug)]
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+}
+
+impl Connection {
+    /// Connects to the target, retrying until `timeout` while it is starting up
+    fn connect(protocol: NetworkProtocol, addr: SocketAddr, timeout: Duration) -> io::Result<Self> {
+        let start = Instant::now();
+        loop {
+            let connection = match protocol {
+                NetworkProtocol::Tcp => {
+                    TcpStream::connect_timeout(&addr, timeout).and_then(|stream| {
+                        stream.set_nodelay(true)?;
+                        stream.set_write_timeout(Some(timeout))?;
+                        Ok(Self::Tcp(stream))
+                    })
+                }
+                NetworkProtocol::Udp => {
+                    let local: SocketAddr = if addr.is_ipv4() {
+                        (Ipv4Addr::UNSPECIFIED, 0).into()
+                    } else {
+                        (Ipv6Addr::UNSPECIFIED, 0).into()
+                    };
+                    UdpSocket::bind(local).and_then(|socket| {
+                        socket.connect(addr)?;
+                        socket.set_write_timeout(Some(timeout))?;
+                        Ok(Self::Udp(socket))
+                    })
+                }
+            };
+            match connection {
+                Err(err)
+                    if err.kind() == ErrorKind::ConnectionRefused && start.elapsed() < timeout =>
+                {
+                    thread::sleep(CONNECT_RETRY_DELAY);
+                }
+                connection => return connection,
+            }
+        }
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.write_all(message),
+            Self::Udp(socket) => socket.send(message).map(|_| ()),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(Some(timeout)),
+            Self::Udp(socket) => socket.set_read_timeout(Some(timeout)),
+        }
+    }
+
+    fn recv_some(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => stream.read(buf),
+            Self::Udp(socket) => socket.recv(buf),
+        }
+    }
+
+    /// Reads a response of up to `max_len` bytes into `buf`, waiting up to `timeout` for its
+    /// first bytes. A missing response is empty, rather than an error.
+    fn recv_response<'a>(
+        &mut self,
+        buf: &'a mut Vec<u8>,
+        timeout: Duration,
+        max_len: usize,
+    ) -> io::Result<&'a [u8]> {
+        buf.resize(max_len, 0);
+        let mut len = 0;
+        self.set_read_timeout(timeout)?;
+        while len < max_len {
+            match self.recv_some(&mut buf[len..]) {
+                Ok(0) => break,
+                Ok(read) => {
+                    len += read;
+                    self.set_read_timeout(RESPONSE_DRAIN_TIMEOUT)?;
+                }
+                Err(err) if is_timeout(&err) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(&buf[..len])
+    }
+}
+
+/// Delivers inputs as messages to a target listening on a socket, see the [module docs](self).
+/// Use [`NetworkExecutor::builder`] to create it.
+#[derive(Debug)]
+pub struct NetworkExecutor<OT, S> {
+    addr: SocketAddr,
+    protocol: NetworkProtocol,
+    split: MessageSplit,
+    reset: ResetStrategy,
+    timeout: Duration,
+    response_timeout: Option<Duration>,
+    max_response_len: usize,
+    health_check: Option<HealthCheck>,
+    target: Option<TargetProcess>,
+    exit_grace: Duration,
+    connection: Option<Connection>,
+    /// The buffer responses are read into, reused across reads
+    recv_buf: Vec<u8>,
+    responses: Vec<Vec<u8>>,
+    observers: OT,
+    phantom: PhantomData<S>,
+}
+
+impl NetworkExecutor<(), ()> {
+    /// Creates a builder for a [`NetworkExecutor`]
+    #[must_use]
+    pub fn builder() -> NetworkExecutorBuilder {
+        NetworkExecutorBuilder::new()
+    }
+}
+
+impl<OT, S> NetworkExecutor<OT, S> {
+    /// The responses of the target to the messages of the last input, if responses are read
+    #[must_use]
+    pub fn responses(&self) -> &[Vec<u8>] {
+        &self.responses
+    }
+
+    /// The target process, if managed by the executor
+    #[must_use]
+    pub fn target(&self) -> Option<&TargetProcess> {
+        self.target.as_ref()
+    }
+
+    /// The target process (mutable), if managed by the executor
+    pub fn target_mut(&mut self) -> Option<&mut TargetProcess> {
+        self.target.as_mut()
+    }
+
+    /// Connects to the target, starting it first if needed
+    fn connect(&mut self) -> Result<Connection, Error> {
+        if let Some(target) = &mut self.target {
+            target.start()?;
+        }
+        Connection::connect(self.protocol, self.addr, self.timeout).map_err(|err| {
+            Error::illegal_state(format!(
+                "Could not connect to the target at {}: {err}",
+                self.addr
+            ))
+        })
+    }
+
+    /// Sends the messages of an input, reading the responses if configured
+    fn deliver(&mut self, connection: &mut Connection, bytes: &[u8]) -> ExitKind {
+        for message in self.split.split(bytes) {
+            if let Err(err) = connection.send(message) {
+                if is_timeout(&err) {
+                    return ExitKind::Timeout;
+                }
+                // The target closed the connection, maybe crashed, see the exit checks
+                log::debug!("Sending to the target failed: {err}");
+                break;
+            }
+            if let Some(response_timeout) = self.response_timeout {
+                match connection.recv_response(
+                    &mut self.recv_buf,
+                    response_timeout,
+                    self.max_response_len,
+                ) {
+                    Ok(response) => self.responses.push(response.to_vec()),
+                    Err(err) => {
+                        log::debug!("Reading from the target failed: {err}");
+                        break;
+                    }
+                }
+            }
+        }
+        ExitKind::Ok
+    }
+
+    /// Probes the target with the [`HealthCheck`], returning how it failed, if it did
+    fn probe(&mut self) -> Option<ExitKind> {
+        let health_check = self.health_check.as_ref()?;
+        let Ok(mut connection) = Connection::connect(self.protocol, self.addr, self.timeout) else {
+            return Some(ExitKind::Crash);
+        };
+        if connection.send(&health_check.probe).is_err() {
+            return Some(ExitKind::Crash);
+        }
+        let response_timeout = self.response_timeout.unwrap_or(self.timeout);
+        match connection.recv_response(&mut self.recv_buf, response_timeout, self.max_response_len)
+        {
+            // No response at all means the target hangs
+            Ok([]) => Some(ExitKind::Timeout),
+            Ok(response) => health_check
+                .expected
+                .as_ref()
+                .is_some_and(|expected| !response.starts_with(expected))
+                .then_some(ExitKind::Crash),
+            Err(_) => Some(ExitKind::Crash),
+        }
+    }
+
+    /// Waits up to the exit grace for the managed target to exit, returning its exit status
+    fn wait_for_exit(&mut self) -> Result<Option<ExitStatus>, Error> {
+        let Some(target) = &mut self.target else {
+            return Ok(None);
+        };
+        let start = Instant::now();
+        loop {
+            let status = target.exit_status()?;
+            if status.is_some() || start.elapsed() >= self.exit_grace {
+                return Ok(status);
+            }
+            thread::sleep(EXIT_POLL_DELAY);
+        }
+    }
+}
+
+impl<OT, S> UsesState for NetworkExecutor<OT, S>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<OT, S> UsesObservers for NetworkExecutor<OT, S>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    type Observers = OT;
+}
+
+impl<OT, S> HasObservers for NetworkExecutor<OT, S>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+impl<EM, OT, S, Z> Executor<EM, Z> for NetworkExecutor<OT, S>
+where
+    EM: UsesState<State = S>,
+    OT: ObserversTuple<S>,
+    S: State + HasExecutions,
+    S::Input: HasTargetBytes,
+    Z: UsesState<State = S>,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut Self::State,
+        _mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        *state.executions_mut() += 1;
+        self.responses.clear();
+
+        if let Some(target) = &mut self.target {
+            // The target may have died after the last check, it is restarted on connect
+            if let Some(status) = target.exit_status()? {
+                self.connection = None;
+                if is_crash_status(status) {
+                    log::warn!("Target crashed with {status} after the previous input");
+                    return Ok(ExitKind::Crash);
+                }
+            }
+        }
+
+        if self.reset == ResetStrategy::RestartTarget {
+            self.connection = None;
+            if let Some(target) = &mut self.target {
+                target.restart()?;
+            }
+        }
+        let mut connection = match self.connection.take() {
+            Some(connection) => connection,
+            None => self.connect()?,
+        };
+
+        let mut exit_kind = self.deliver(&mut connection, input.target_bytes().as_slice());
+        if exit_kind == ExitKind::Ok {
+            if let Some(failure) = self.probe() {
+                exit_kind = failure;
+            }
+        }
+
+        // Without a health check, give the target time to crash on the last message, so the crash
+        // is not blamed on the next input
+        let status = if exit_kind == ExitKind::Ok && self.health_check.is_none() {
+            self.wait_for_exit()?
+        } else if let Some(target) = &mut self.target {
+            target.exit_status()?
+        } else {
+            None
+        };
+        if let Some(status) = status {
+            log::info!("Target exited with {status}");
+            if is_crash_status(status) {
+                exit_kind = ExitKind::Crash;
+            }
+        } else if exit_kind != ExitKind::Ok {
+            if let Some(target) = &mut self.target {
+                // The target is still around, but hangs or is broken
+                target.kill()?;
+            }
+        }
+
+        if self.reset == ResetStrategy::KeepAlive && exit_kind == ExitKind::Ok {
+            self.connection = Some(connection);
+        }
+        Ok(exit_kind)
+    }
+
+    fn restart_target(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        self.connection = None;
+        match &mut self.target {
+            Some(target) => target.restart(),
+            None => Err(Error::not_implemented(
+                "The NetworkExecutor can only restart a target it manages",
+            )),
+        }
+    }
+}
+
+/// The builder for a [`NetworkExecutor`]
+#[derive(Debug)]
+pub struct NetworkExecutorBuilder {
+    addr: Option<(NetworkProtocol, SocketAddr)>,
+    split: MessageSplit,
+    reset: ResetStrategy,
+    timeout: Duration,
+    response_timeout: Option<Duration>,
+    max_response_len: usize,
+    health_check: Option<HealthCheck>,
+    target: Option<TargetProcess>,
+    exit_grace: Duration,
+}
+
+impl Default for NetworkExecutorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkExecutorBuilder {
+    /// Creates a new [`NetworkExecutorBuilder`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            addr: None,
+            split: MessageSplit::default(),
+            reset: ResetStrategy::default(),
+            timeout: DEFAULT_NETWORK_TIMEOUT,
+            response_timeout: None,
+            max_response_len: DEFAULT_MAX_RESPONSE_LEN,
+            health_check: None,
+            target: None,
+            exit_grace: DEFAULT_EXIT_GRACE,
+        }
+    }
+
+    /// Sends the inputs to a TCP server at `addr`
+    #[must_use]
+    pub fn tcp(mut self, addr: SocketAddr) -> Self {
+        self.addr = Some((NetworkProtocol::Tcp, addr));
+        self
+    }
+
+    /// Sends the inputs to a UDP server at `addr`
+    #[must_use]
+    pub fn udp(mut self, addr: SocketAddr) -> Self {
+        self.addr = Some((NetworkProtocol::Udp, addr));
+        self
+    }
+
+    /// Sets how an input is split into messages, defaults to [`MessageSplit::Single`]
+    #[must_use]
+    pub fn message_split(mut self, split: MessageSplit) -> Self {
+        self.split = split;
+        self
+    }
+
+    /// Sets when the connection or the target is reset, defaults to
+    /// [`ResetStrategy::Reconnect`]
+    #[must_use]
+    pub fn reset(mut self, reset: ResetStrategy) -> Self {
+        self.reset = reset;
+        self
+    }
+
+    /// Sets the timeout to connect to the target and send a message
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Reads the response of the target after each message, waiting up to `timeout` for it.
+    /// The responses are available by [`NetworkExecutor::responses`].
+    #[must_use]
+    pub fn read_responses(mut self, timeout: Duration) -> Self {
+        self.response_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximal length of a response, defaults to [`DEFAULT_MAX_RESPONSE_LEN`]
+    #[must_use]
+    pub fn max_response_len(mut self, max_response_len: usize) -> Self {
+        self.max_response_len = max_response_len;
+        self
+    }
+
+    /// Probes the target after each input, see [`HealthCheck`]
+    #[must_use]
+    pub fn health_check(mut self, health_check: HealthCheck) -> Self {
+        self.health_check = Some(health_check);
+        self
+    }
+
+    /// Lets the executor start, check and restart the target process
+    #[must_use]
+    pub fn target(mut self, target: TargetProcess) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Sets how long a managed target gets to crash after the last message of an input, before
+    /// the input is considered fine. Without it, a late crash is blamed on the next input.
+    /// Not used with a [`HealthCheck`], which waits for the target anyway.
+    /// Defaults to [`DEFAULT_EXIT_GRACE`].
+    #[must_use]
+    pub fn exit_grace(mut self, exit_grace: Duration) -> Self {
+        self.exit_grace = exit_grace;
+        self
+    }
+
+    /// Builds the [`NetworkExecutor`]
+    pub fn build<OT, S>(self, observers: OT) -> Result<NetworkExecutor<OT, S>, Error>
+    where
+        OT: ObserversTuple<S>,
+        S: State,
+    {
+        let Some((protocol, addr)) = self.addr else {
+            return Err(Error::illegal_argument(
+                "NetworkExecutor::builder: no target address set!",
+            ));
+        };
+        if self.reset == ResetStrategy::RestartTarget && self.target.is_none() {
+            return Err(Error::illegal_argument(
+                "NetworkExecutor::builder: restarting the target needs a target process!",
+            ));
+        }
+        Ok(NetworkExecutor {
+            addr,
+            protocol,
+            split: self.split,
+            reset: self.reset,
+            timeout: self.timeout,
+            response_timeout: self.response_timeout,
+            max_response_len: self.max_response_len,
+            health_check: self.health_check,
+            target: self.target,
+            exit_grace: self.exit_grace,
+            connection: None,
+            recv_buf: Vec::new(),
+            responses: Vec::new(),
+            observers,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use std::process::Command;
+    use std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpListener},
+        thread,
+        time::Duration,
+    };
+
+    #[cfg(unix)]
+    use super::TargetProcess;
+    use super::{MessageSplit, NetworkExecutor};
+    use crate::{
+        events::NopEventManager,
+        executors::{Executor, ExitKind},
+        fuzzer::test::NopFuzzer,
+        inputs::BytesInput,
+        state::NopState,
+    };
+
+    #[test]
+    fn test_message_split() {
+        let bytes = b"USER a\r\nPASS b\r\nQUIT";
+        assert_eq!(MessageSplit::Single.split(bytes), vec![&bytes[..]]);
+        assert_eq!(
+            MessageSplit::Delimiter(b"\r\n".to_vec()).split(bytes),
+            vec![&b"USER a\r\n"[..], b"PASS b\r\n", b"QUIT"]
+        );
+        assert_eq!(
+            MessageSplit::Chunks(8).split(bytes),
+            vec![&b"USER a\r\n"[..], b"PASS b\r\n", b"QUIT"]
+        );
+        assert!(MessageSplit::Chunks(4).split(b"").is_empty());
+    }
+
+    /// Starts a server echoing each message, uppercased
+    fn spawn_echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for mut stream in listener.incoming().map_while(Result::ok) {
+                let mut buf = [0; 64];
+                while let Ok(len) = stream.read(&mut buf) {
+                    if len == 0 {
+                        break;
+                    }
+                    let _ = stream.write_all(&buf[..len].to_ascii_uppercase());
+                }
+            }
+        });
+        addr
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_network_executor_tcp() {
+        let addr = spawn_echo_server();
+
+        let mut executor = NetworkExecutor::builder()
+            .tcp(addr)
+            .message_split(MessageSplit::Delimiter(b"\n".to_vec()))
+            .read_responses(Duration::from_secs(1))
+            .build(())
+            .unwrap();
+        let exit_kind = executor
+            .run_target(
+                &mut NopFuzzer::new(),
+                &mut NopState::new(),
+                &mut NopEventManager::new(),
+                &BytesInput::new(b"hello\nworld\n".to_vec()),
+            )
+            .unwrap();
+        assert_eq!(exit_kind, ExitKind::Ok);
+        assert_eq!(
+            executor.responses(),
+            &[b"HELLO\n".to_vec(), b"WORLD\n".to_vec()]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    #[cfg_attr(miri, ignore)]
+    fn test_network_executor_late_crash() {
+        // A managed target crashing a while after it was started
+        let crashing_target = || {
+            let mut command = Command::new("sh");
+            command.args(["-c", "sleep 0.2; kill -SEGV $$"]);
+            TargetProcess::new(command)
+        };
+        let run = |executor: &mut NetworkExecutor<(), NopState<BytesInput>>| {
+            executor
+                .run_target(
+                    &mut NopFuzzer::new(),
+                    &mut NopState::new(),
+                    &mut NopEventManager::new(),
+                    &BytesInput::new(b"hello".to_vec()),
+                )
+                .unwrap()
+        };
+
+        // Waiting long enough after the input, the crash is found right away
+        let mut executor = NetworkExecutor::builder()
+            .tcp(spawn_echo_server())
+            .target(crashing_target())
+            .exit_grace(Duration::from_secs(5))
+            .build(())
+            .unwrap();
+        assert_eq!(run(&mut executor), ExitKind::Crash);
+
+        // Otherwise, it is found before the next input, instead of failing to connect
+        let mut executor = NetworkExecutor::builder()
+            .tcp(spawn_echo_server())
+            .target(crashing_target())
+            .exit_grace(Duration::ZERO)
+            .build(())
+            .unwrap();
+        assert_eq!(run(&mut executor), ExitKind::Ok);
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(run(&mut executor), ExitKind::Crash);
+        assert_eq!(run(&mut executor), ExitKind::Ok);
+    }
+}
@@ -0,0 +1,244 @@
+//! A shell session on a remote machine or device, kept open across runs of the target, shared by
+//! the [`crate::executors::AdbExecutor`] and the [`crate::executors::SshExecutor`].
+
+use alloc::{string::String, vec::Vec};
+use core::{fmt::Write as _, time::Duration};
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    executors::{command::AFL_INPUT_FILE_PLACEHOLDER, ExitKind},
+    Error,
+};
+
+/// Printed by the shell after each command line, followed by its exit status
+const EXIT_MARKER: &str = "__LIBAFL_EXIT__";
+/// The exit status of `timeout`, when the target timed out, and the one of a killed target
+const TIMEOUT_EXIT_CODES: [i32; 2] = [124, 128 + 9];
+/// The signals of crashes: `SIGILL`, `SIGTRAP`, `SIGABRT`, `SIGBUS`, `SIGFPE` and `SIGSEGV`
+const CRASH_SIGNALS: [i32; 6] = [4, 5, 6, 7, 8, 11];
+
+/// Quotes an argument for a POSIX shell
+pub(crate) fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// A `printf` command writing the bytes to a file, escaping all bytes that are not safe in a
+/// shell word or a format string
+pub(crate) fn printf_to_file(bytes: &[u8], path: &str) -> String {
+    let mut command = String::with_capacity(bytes.len() * 4 + path.len() + 16);
+    command.push_str("printf '");
+    for &byte in bytes {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b' ' | b'.' | b',' | b'-' | b'_') {
+            command.push(byte as char);
+        } else {
+            write!(command, "\\{byte:03o}").unwrap();
+        }
+    }
+    write!(command, "' > {}", shell_quote(path)).unwrap();
+    command
+}
+
+/// The shell command line running the target under `timeout`, with its output discarded.
+/// Each [`AFL_INPUT_FILE_PLACEHOLDER`] in the args is replaced by the path of the input,
+/// without one, the input is passed on stdin.
+///
+/// `ASan` reports call `abort()`, unless the `ASAN_OPTIONS` of the remote say otherwise, as its
+/// default `exitcode=1` cannot be told apart from a normal exit.
+pub(crate) fn target_command_line(
+    timeout: Duration,
+    program: &str,
+    args: &[String],
+    input_path: &str,
+) -> String {
+    let mut command_line = format!(
+        "ASAN_OPTIONS=\"abort_on_error=1:$ASAN_OPTIONS\" timeout -s KILL {:.3} {}",
+        timeout.as_secs_f64(),
+        shell_quote(program)
+    );
+    let mut has_placeholder = false;
+    for arg in args {
+        has_placeholder |= arg.contains(AFL_INPUT_FILE_PLACEHOLDER);
+        let arg = arg.replace(AFL_INPUT_FILE_PLACEHOLDER, input_path);
+        command_line.push(' ');
+        command_line.push_str(&shell_quote(&arg));
+    }
+    if has_placeholder {
+        // Do not read from the stdin of the shell, which may be the session itself
+        command_line.push_str(" < /dev/null");
+    } else {
+        write!(command_line, " < {}", shell_quote(input_path)).unwrap();
+    }
+    command_line.push_str(" > /dev/null 2>&1");
+    command_line
+}
+
+/// Maps the exit status of a target run by [`target_command_line`] to an [`ExitKind`].
+/// Besides the crash signals, the `crash_exitcode`, if any, is a crash.
+pub(crate) fn exit_kind_of(code: i32, crash_exitcode: Option<i8>) -> ExitKind {
+    if TIMEOUT_EXIT_CODES.contains(&code) {
+        ExitKind::Timeout
+    } else if (code > 128 && CRASH_SIGNALS.contains(&(code - 128)))
+        || crash_exitcode.is_some_and(|crash_exitcode| i32::from(crash_exitcode) & 0xff == code)
+    {
+        ExitKind::Crash
+    } else {
+        ExitKind::Ok
+    }
+}
+
+/// A shell session, reading command lines on stdin
+#[derive(Debug)]
+pub(crate) struct RemoteShell {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl RemoteShell {
+    /// Opens a shell session by a command like `adb shell`
+    pub(crate) fn spawn(command: &mut Command) -> Result<Self, Error> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(Error::illegal_state("The remote shell has no stdio"));
+        };
+        // Reads the output of the shell in the background, to wait for it with a timeout
+        let (sender, lines) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+        })
+    }
+
+    /// Runs a command line, returning its exit status and the lines it printed, or `None` if
+    /// the shell did not answer in time
+    pub(crate) fn run(
+        &mut self,
+        command_line: &str,
+        timeout: Duration,
+    ) -> Result<Option<(i32, Vec<String>)>, Error> {
+        writeln!(self.stdin, "{command_line}; echo {EXIT_MARKER} $?")?;
+        self.stdin.flush()?;
+        let deadline = Instant::now() + timeout;
+        let mut output = Vec::new();
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(remaining) {
+                Ok(line) => {
+                    // The command may print without a trailing newline, so look for the marker
+                    let Some(pos) = line.find(EXIT_MARKER) else {
+                        output.push(line);
+                        continue;
+                    };
+                    if pos > 0 {
+                        output.push(line[..pos].into());
+                    }
+                    let code = line[pos + EXIT_MARKER.len()..].trim();
+                    return match code.parse() {
+                        Ok(code) => Ok(Some((code, output))),
+                        Err(_) => Err(Error::illegal_state(format!("Invalid exit status {code}"))),
+                    };
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Error::illegal_state("The remote shell closed"))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for RemoteShell {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::process::Command;
+
+    use super::{exit_kind_of, printf_to_file, shell_quote, target_command_line, RemoteShell};
+    use crate::executors::ExitKind;
+
+    #[test]
+    fn test_remote_exit_kind() {
+        assert_eq!(exit_kind_of(0, None), ExitKind::Ok);
+        assert_eq!(exit_kind_of(1, None), ExitKind::Ok);
+        assert_eq!(exit_kind_of(124, None), ExitKind::Timeout);
+        assert_eq!(exit_kind_of(128 + 9, None), ExitKind::Timeout);
+        assert_eq!(exit_kind_of(128 + 11, None), ExitKind::Crash);
+        assert_eq!(exit_kind_of(128 + 6, None), ExitKind::Crash);
+        assert_eq!(exit_kind_of(128 + 15, None), ExitKind::Ok);
+        assert_eq!(exit_kind_of(1, Some(1)), ExitKind::Crash);
+        assert_eq!(exit_kind_of(255, Some(-1)), ExitKind::Crash);
+        assert_eq!(exit_kind_of(2, Some(1)), ExitKind::Ok);
+    }
+
+    #[test]
+    fn test_remote_shell_escaping() {
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(
+            printf_to_file(b"a b'%\n\0", "/tmp/in"),
+            r"printf 'a b\047\045\012\000' > '/tmp/in'"
+        );
+        assert_eq!(
+            target_command_line(Duration::from_secs(1), "./target", &[], "/tmp/in"),
+            "ASAN_OPTIONS=\"abort_on_error=1:$ASAN_OPTIONS\" timeout -s KILL 1.000 './target' \
+             < '/tmp/in' > /dev/null 2>&1"
+        );
+        assert_eq!(
+            target_command_line(
+                Duration::from_secs(1),
+                "./target",
+                &["@@".into()],
+                "/tmp/in"
+            ),
+            "ASAN_OPTIONS=\"abort_on_error=1:$ASAN_OPTIONS\" timeout -s KILL 1.000 './target' \
+             '/tmp/in' < /dev/null > /dev/null 2>&1"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_remote_shell_session() {
+        let mut shell = RemoteShell::spawn(&mut Command::new("sh")).unwrap();
+        let (code, output) = shell
+            .run("echo hello; printf partial; false", Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(code, 1);
+        assert_eq!(output, ["hello", "partial"]);
+
+        let path = std::env::temp_dir().join(format!("libafl_remote_shell_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        let bytes = b"\x00\xffbinary 'input'\n%s\\";
+        let (code, _) = shell
+            .run(&printf_to_file(bytes, path), Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(code, 0);
+        assert_eq!(std::fs::read(path).unwrap(), bytes);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+//! Typed runtime options for the sanitizers of a target, like `ASAN_OPTIONS`.
+//!
+//! Instead of concatenating option strings by hand, build [`SanitizerOptions`] and pass them to
+//! an executor, e.g. [`crate::executors::forkserver::ForkserverExecutorBuilder::sanitizer_options`].
+//! The options are validated before the target starts, and quoted as the sanitizer runtimes
+//! expect.
+//!
+//! ```rust,ignore
+//! let asan = SanitizerOptions::recommended(Sanitizer::Address)
+//!     .malloc_context_size(0)
+//!     .client_log_path("./logs", mgr.mgr_id());
+//! let executor = ForkserverExecutor::builder()
+//!     .program("./target")
+//!     .sanitizer_options(asan)
+//!     .build(observers)?;
+//! ```
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+use std::path::Path;
+
+use libafl_bolts::ClientId;
+
+use crate::Error;
+
+/// A sanitizer runtime, configured by its own environment variable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sanitizer {
+    /// `AddressSanitizer`, configured by `ASAN_OPTIONS`
+    Address,
+    /// `MemorySanitizer`, configured by `MSAN_OPTIONS`
+    Memory,
+    /// `UndefinedBehaviorSanitizer`, configured by `UBSAN_OPTIONS`
+    UndefinedBehavior,
+    /// `LeakSanitizer`, configured by `LSAN_OPTIONS`
+    Leak,
+    /// `ThreadSanitizer`, configured by `TSAN_OPTIONS`
+    Thread,
+}
+
+impl Sanitizer {
+    /// The environment variable holding the options of the sanitizer
+    #[must_use]
+    pub fn env_var(self) -> &'static str {
+        match self {
+            Self::Address => "ASAN_OPTIONS",
+            Self::Memory => "MSAN_OPTIONS",
+            Self::UndefinedBehavior => "UBSAN_OPTIONS",
+            Self::Leak => "LSAN_OPTIONS",
+            Self::Thread => "TSAN_OPTIONS",
+        }
+    }
+
+    /// The short name of the sanitizer, e.g. `asan`
+    #[must_use]
+    pub fn short_name(self) -> &'static str {
+        match self {
+            Self::Address => "asan",
+            Self::Memory => "msan",
+            Self::UndefinedBehavior => "ubsan",
+            Self::Leak => "lsan",
+            Self::Thread => "tsan",
+        }
+    }
+}
+
+impl fmt::Display for Sanitizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.short_name())
+    }
+}
+
+/// The sanitizers understanding an option set by a typed setter of [`SanitizerOptions`], or
+/// `None` for other options, which are not checked
+fn supported_by(key: &str) -> Option<&'static [Sanitizer]> {
+    use Sanitizer::{Address, Leak, Memory, Thread, UndefinedBehavior};
+    const ALL: &[Sanitizer] = &[Address, Memory, UndefinedBehavior, Leak, Thread];
+    match key {
+        "abort_on_error"
+        | "symbolize"
+        | "allocator_may_return_null"
+        | "log_path"
+        | "handle_abort"
+        | "handle_segv"
+        | "handle_sigbus"
+        | "handle_sigill"
+        | "handle_sigfpe" => Some(ALL),
+        "halt_on_error" => Some(&[Address, Memory, UndefinedBehavior, Thread]),
+        "malloc_context_size" => Some(&[Address, Memory, Leak]),
+        "detect_leaks" => Some(&[Address, Leak]),
+        "print_stacktrace" => Some(&[UndefinedBehavior]),
+        _ => None,
+    }
+}
+
+/// Typed runtime options of a [`Sanitizer`], see the [module documentation](self)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanitizerOptions {
+    sanitizer: Sanitizer,
+    /// In the order they were first set; setting an option again replaces its value
+    options: Vec<(String, String)>,
+}
+
+impl SanitizerOptions {
+    /// Creates empty [`SanitizerOptions`], leaving all options at the defaults of the runtime
+    #[must_use]
+    pub fn new(sanitizer: Sanitizer) -> Self {
+        Self {
+            sanitizer,
+            options: vec![],
+        }
+    }
+
+    /// Creates [`SanitizerOptions`] suited for fuzzing: crashes abort, so the fuzzer notices
+    /// them, and reports are not symbolized, which is slow
+    #[must_use]
+    pub fn recommended(sanitizer: Sanitizer) -> Self {
+        let options = Self::new(sanitizer)
+            .abort_on_error(true)
+            .symbolize(false)
+            .allocator_may_return_null(true);
+        match sanitizer {
+            Sanitizer::Address => options.detect_leaks(false).handle_signals(true),
+            Sanitizer::UndefinedBehavior => options.halt_on_error(true).print_stacktrace(true),
+            Sanitizer::Memory | Sanitizer::Leak | Sanitizer::Thread => options,
+        }
+    }
+
+    /// Parses options in the format of the environment variable, e.g. `a=1:b=2`
+    pub fn parse(sanitizer: Sanitizer, options: &str) -> Result<Self, Error> {
+        let mut parsed = Self::new(sanitizer);
+        let mut option = String::new();
+        let mut quote = None;
+        for c in options.chars().chain([':']) {
+            match (quote, c) {
+                (Some(q), c) if q == c => quote = None,
+                (None, '"' | '\'') => quote = Some(c),
+                (None, c) if c == ':' || c.is_whitespace() => {
+                    if !option.is_empty() {
+                        let (key, value) = option.split_once('=').ok_or_else(|| {
+                            Error::illegal_argument(format!(
+                                "Invalid {} option {option}, expected key=value",
+                                sanitizer.env_var()
+                            ))
+                        })?;
+                        parsed = parsed.option(key, value);
+                        option.clear();
+                    }
+                }
+                (_, c) => option.push(c),
+            }
+        }
+        parsed.validate()?;
+        Ok(parsed)
+    }
+
+    /// Sets a raw option, for options without a typed setter
+    #[must_use]
+    pub fn option<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let key = key.into();
+        let value = value.into();
+        if let Some(option) = self.options.iter_mut().find(|(k, _)| *k == key) {
+            option.1 = value;
+        } else {
+            self.options.push((key, value));
+        }
+        self
+    }
+
+    /// Sets a boolean option
+    fn flag(self, key: &str, value: bool) -> Self {
+        self.option(key, if value { "1" } else { "0" })
+    }
+
+    /// Whether errors call `abort()` instead of `_exit()`, so the fuzzer sees a crash
+    #[must_use]
+    pub fn abort_on_error(self, abort_on_error: bool) -> Self {
+        self.flag("abort_on_error", abort_on_error)
+    }
+
+    /// Whether reports are symbolized, which is slow
+    #[must_use]
+    pub fn symbolize(self, symbolize: bool) -> Self {
+        self.flag("symbolize", symbolize)
+    }
+
+    /// Whether the sanitizer keeps running after the first error
+    #[must_use]
+    pub fn halt_on_error(self, halt_on_error: bool) -> Self {
+        self.flag("halt_on_error", halt_on_error)
+    }
+
+    /// The number of frames recorded for each allocation, `0` is fastest
+    #[must_use]
+    pub fn malloc_context_size(self, malloc_context_size: usize) -> Self {
+        self.option("malloc_context_size", malloc_context_size.to_string())
+    }
+
+    /// Whether leaks are reported at exit
+    #[must_use]
+    pub fn detect_leaks(self, detect_leaks: bool) -> Self {
+        self.flag("detect_leaks", detect_leaks)
+    }
+
+    /// Whether failing allocations return `NULL` instead of crashing
+    #[must_use]
+    pub fn allocator_may_return_null(self, allocator_may_return_null: bool) -> Self {
+        self.flag("allocator_may_return_null", allocator_may_return_null)
+    }
+
+    /// Whether `UBSan` prints a stack trace with each report
+    #[must_use]
+    pub fn print_stacktrace(self, print_stacktrace: bool) -> Self {
+        self.flag("print_stacktrace", print_stacktrace)
+    }
+
+    /// Whether the sanitizer reports crashing signals, like `SIGSEGV` and `SIGILL`
+    #[must_use]
+    pub fn handle_signals(self, handle_signals: bool) -> Self {
+        [
+            "handle_abort",
+            "handle_segv",
+            "handle_sigbus",
+            "handle_sigill",
+            "handle_sigfpe",
+        ]
+        .into_iter()
+        .fold(self, |options, key| options.flag(key, handle_signals))
+    }
+
+    /// Writes reports to `<log_path>.<pid>` instead of `stderr`
+    #[must_use]
+    pub fn log_path<P>(self, log_path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.option("log_path", log_path.as_ref().display().to_string())
+    }
+
+    /// Writes reports to a file per client in the directory, so clients don't mix up their
+    /// reports, e.g. `<dir>/asan.client-3.<pid>`
+    #[must_use]
+    pub fn client_log_path<P>(self, dir: P, client_id: ClientId) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let file = format!("{}.client-{}", self.sanitizer, client_id.0);
+        self.log_path(dir.as_ref().join(file))
+    }
+
+    /// The sanitizer configured by these options
+    #[must_use]
+    pub fn sanitizer(&self) -> Sanitizer {
+        self.sanitizer
+    }
+
+    /// The value of the option, if set
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Checks the options are well-formed and understood by the sanitizer
+    pub fn validate(&self) -> Result<(), Error> {
+        let env_var = self.sanitizer.env_var();
+        for (key, value) in &self.options {
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(Error::illegal_argument(format!(
+                    "Invalid {env_var} option name {key:?}"
+                )));
+            }
+            if value.contains('"') && value.contains('\'') {
+                return Err(Error::illegal_argument(format!(
+                    "The {env_var} option {key} can't contain both kinds of quotes"
+                )));
+            }
+            if supported_by(key).is_some_and(|supported| !supported.contains(&self.sanitizer)) {
+                return Err(Error::illegal_argument(format!(
+                    "The option {key} is not supported by {env_var}"
+                )));
+            }
+        }
+        if let Some(log_path) = self.get("log_path") {
+            if let Some(dir) = Path::new(log_path).parent() {
+                if !dir.as_os_str().is_empty() && !dir.is_dir() {
+                    return Err(Error::illegal_argument(format!(
+                        "The directory of the {env_var} log_path {log_path} does not exist"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The environment variable and its value, to pass to the target
+    #[must_use]
+    pub fn to_env(&self) -> (&'static str, String) {
+        (self.sanitizer.env_var(), self.to_string())
+    }
+}
+
+impl fmt::Display for SanitizerOptions {
+    /// The options in the format of the environment variable, quoting values as needed
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, (key, value)) in self.options.iter().enumerate() {
+            if idx > 0 {
+                write!(f, ":")?;
+            }
+            if value.contains(|c: char| c == ':' || c == ',' || c.is_whitespace()) {
+                let quote = if value.contains('"') { '\'' } else { '"' };
+                write!(f, "{key}={quote}{value}{quote}")?;
+            } else {
+                write!(f, "{key}={value}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use libafl_bolts::ClientId;
+
+    use crate::executors::sanitizers::{Sanitizer, SanitizerOptions};
+
+    #[test]
+    fn test_sanitizer_options() {
+        let options = SanitizerOptions::new(Sanitizer::Address)
+            .abort_on_error(true)
+            .malloc_context_size(0)
+            .abort_on_error(false)
+            .client_log_path("/tmp", ClientId(3));
+        assert_eq!(
+            options.to_string(),
+            "abort_on_error=0:malloc_context_size=0:log_path=/tmp/asan.client-3"
+        );
+        options.validate().unwrap();
+
+        let parsed = SanitizerOptions::parse(Sanitizer::Address, &options.to_string()).unwrap();
+        assert_eq!(parsed, options);
+
+        let spaced = SanitizerOptions::new(Sanitizer::Memory).option("suppressions", "a b:c");
+        assert_eq!(spaced.to_string(), "suppressions=\"a b:c\"");
+        assert_eq!(
+            SanitizerOptions::parse(Sanitizer::Memory, &spaced.to_string()).unwrap(),
+            spaced
+        );
+
+        assert!(SanitizerOptions::new(Sanitizer::UndefinedBehavior)
+            .malloc_context_size(0)
+            .validate()
+            .is_err());
+        assert!(SanitizerOptions::parse(Sanitizer::Address, "abort_on_error").is_err());
+    }
+
+    #[test]
+    fn test_sanitizer_options_support() {
+        for sanitizer in [
+            Sanitizer::Address,
+            Sanitizer::Memory,
+            Sanitizer::UndefinedBehavior,
+            Sanitizer::Leak,
+            Sanitizer::Thread,
+        ] {
+            SanitizerOptions::recommended(sanitizer).validate().unwrap();
+        }
+        // ASan reports must keep failing the target, whatever detects them
+        let asan = SanitizerOptions::recommended(Sanitizer::Address);
+        assert_eq!(asan.get("abort_on_error"), Some("1"));
+        assert_eq!(asan.get("exitcode"), None);
+
+        assert!(SanitizerOptions::new(Sanitizer::Leak)
+            .halt_on_error(true)
+            .validate()
+            .is_err());
+        assert!(SanitizerOptions::new(Sanitizer::Thread)
+            .detect_leaks(false)
+            .validate()
+            .is_err());
+        // Options without a typed setter are passed on unchecked
+        SanitizerOptions::parse(Sanitizer::Thread, "history_size=3:exitcode=66").unwrap();
+    }
+}
@@ -74,6 +74,10 @@ where
     ) -> Result<ExitKind, Error> {
         self.executor.run_target(fuzzer, state, mgr, input)
     }
+
+    fn restart_target(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.executor.restart_target(state)
+    }
 }
 
 impl<E, SOT> UsesState for ShadowExecutor<E, SOT>
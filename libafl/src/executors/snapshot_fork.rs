@@ -0,0 +1,607 @@
+//! The [`SnapshotForkExecutor`] fuzzes stateful targets, such as network protocol implementations,
+//! from a snapshot of the target process, taken after it reached a given state.
+//!
+//! The target runs in-process and receives the operations of a [`SequenceInput`] one at a time.
+//! To take a snapshot, a snapshot process is forked off, which replays a prefix of operations, e.g.
+//! a login, and then waits. Each execution of an input starting with this prefix forks the snapshot
+//! process again and only replays the remaining operations in the fork. Inputs with another prefix
+//! are replayed completely, in a fork of the fuzzer process.
+//!
+//! As with the [`crate::executors::InProcessForkExecutor`], the observers need to live in shared
+//! memory, so the fuzzer sees the coverage of the forked children. The snapshot process keeps the
+//! coverage map of the prefix and restores it in each of its children, so an execution from the
+//! snapshot reports the same coverage as a complete replay.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    ptr,
+    time::Duration,
+};
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+};
+
+use libafl_bolts::{
+    shmem::ShMemProvider,
+    tuples::{Handle, MatchNameRef, RefIndexable},
+};
+use nix::{
+    sys::{
+        signal::{kill, Signal},
+        wait::{waitpid, WaitStatus},
+    },
+    unistd::{fork, ForkResult, Pid},
+};
+
+use crate::{
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::{same_ops, SequenceInput, SequenceOp, UsesInput},
+    observers::{MapObserver, ObserversTuple, UsesObservers},
+    state::{HasExecutions, State, UsesState},
+    Error,
+};
+
+/// The exit code of a child whose harness reported a crash
+const CRASH_EXIT_CODE: i32 = 66;
+/// The exit code of a child whose harness reported a timeout
+const TIMEOUT_EXIT_CODE: i32 = 67;
+/// The exit code of a child that could not set up its shared memory
+const SETUP_FAILED_EXIT_CODE: i32 = 68;
+
+/// The status bytes the snapshot process sends back to the fuzzer
+const STATUS_OK: u8 = 0;
+const STATUS_CRASH: u8 = 1;
+const STATUS_TIMEOUT: u8 = 2;
+
+/// Maps the wait status of a forked child to the [`ExitKind`] of the execution
+fn exit_kind_from_status(status: WaitStatus) -> ExitKind {
+    match status {
+        WaitStatus::Signaled(_, Signal::SIGALRM, _) | WaitStatus::Exited(_, TIMEOUT_EXIT_CODE) => {
+            ExitKind::Timeout
+        }
+        WaitStatus::Signaled(..) | WaitStatus::Exited(_, 1..) => ExitKind::Crash,
+        _ => ExitKind::Ok,
+    }
+}
+
+/// Encodes an [`ExitKind`] as status byte of the snapshot protocol
+fn exit_kind_to_status(exit_kind: ExitKind) -> u8 {
+    match exit_kind {
+        ExitKind::Ok => STATUS_OK,
+        ExitKind::Timeout => STATUS_TIMEOUT,
+        _ => STATUS_CRASH,
+    }
+}
+
+/// Decodes a status byte of the snapshot protocol
+fn exit_kind_from_status_byte(status: u8) -> ExitKind {
+    match status {
+        STATUS_OK => ExitKind::Ok,
+        STATUS_TIMEOUT => ExitKind::Timeout,
+        _ => ExitKind::Crash,
+    }
+}
+
+/// Arms the real-time timer of the current (child) process, terminating it with `SIGALRM` after
+/// `timeout`, even if the parent installed a handler for it
+#[allow(clippy::unnecessary_fallible_conversions)] // `suseconds_t` is `i32` on some platforms
+fn arm_timeout(timeout: Duration) {
+    let timeout = timeout.max(Duration::from_micros(1));
+    let timer = libc::itimerval {
+        it_interval: libc::timeval {
+            tv_sec: 0,
+            tv_usec: 0,
+        },
+        it_value: libc::timeval {
+            tv_sec: timeout.as_secs().try_into().unwrap_or(libc::time_t::MAX),
+            tv_usec: timeout.subsec_micros().try_into().unwrap(),
+        },
+    };
+    unsafe {
+        libc::signal(libc::SIGALRM, libc::SIG_DFL);
+        libc::setitimer(libc::ITIMER_REAL, &timer, ptr::null_mut());
+    }
+}
+
+/// Replays the operations in the current (child) process, stopping at the first one that does not
+/// return [`ExitKind::Ok`]
+fn replay<H, T>(harness: &mut H, ops: &[T]) -> ExitKind
+where
+    H: FnMut(&T) -> ExitKind,
+{
+    for op in ops {
+        let exit_kind = harness(op);
+        if exit_kind != ExitKind::Ok {
+            return exit_kind;
+        }
+    }
+    ExitKind::Ok
+}
+
+/// Exits the current (child) process with the exit code for the [`ExitKind`]
+fn exit_child(exit_kind: ExitKind) -> ! {
+    let code = match exit_kind {
+        ExitKind::Ok => 0,
+        ExitKind::Timeout => TIMEOUT_EXIT_CODE,
+        _ => CRASH_EXIT_CODE,
+    };
+    unsafe { libc::_exit(code) }
+}
+
+/// What the [`PrefixCoverage`] closure does with the coverage map
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PrefixCoverageOp {
+    /// Clears the map before replaying the prefix
+    Reset,
+    /// Keeps the map after replaying the prefix
+    Save,
+    /// Writes the kept map back, before replaying the rest of an input
+    Restore,
+}
+
+/// Resets, saves and restores the coverage map of the snapshot prefix
+type PrefixCoverage<OT> = Box<dyn FnMut(&mut OT, PrefixCoverageOp)>;
+
+/// The [`PrefixCoverage`] for the map observer with the handle
+fn prefix_coverage<C, O, OT>(map_observer_handle: Handle<C>) -> PrefixCoverage<OT>
+where
+    C: AsMut<O> + 'static,
+    O: MapObserver + 'static,
+    OT: MatchNameRef,
+{
+    let mut saved = Vec::new();
+    Box::new(move |observers: &mut OT, op| {
+        let Some(observer) = observers.get_mut(&map_observer_handle) else {
+            return;
+        };
+        let map = observer.as_mut();
+        match op {
+            PrefixCoverageOp::Reset => {
+                // Only fails for maps that cannot be reset at all, keep what is there then
+                let _ = map.reset_map();
+            }
+            PrefixCoverageOp::Save => saved = map.to_vec(),
+            PrefixCoverageOp::Restore => {
+                let initial = map.initial();
+                for (idx, entry) in saved.iter().enumerate() {
+                    if *entry != initial {
+                        map.set(idx, *entry);
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Executors that can snapshot a prefix of a [`SequenceInput`], see the [module docs](self)
+pub trait HasSequenceSnapshot<T> {
+    /// The operations replayed by the current snapshot, if any
+    fn snapshot_prefix(&self) -> Option<&[T]>;
+
+    /// Takes a new snapshot after replaying the operations of the `prefix`,
+    /// replacing the current snapshot
+    fn take_snapshot(&mut self, prefix: &[T]) -> Result<(), Error>;
+
+    /// Discards the current snapshot
+    fn discard_snapshot(&mut self);
+}
+
+/// A forked process waiting at the snapshot, see the [module docs](self)
+#[derive(Debug)]
+struct SnapshotProcess<T> {
+    pid: Pid,
+    prefix: Vec<T>,
+    stream: UnixStream,
+}
+
+impl<T> Drop for SnapshotProcess<T> {
+    fn drop(&mut self) {
+        let _ = kill(self.pid, Signal::SIGKILL);
+        let _ = waitpid(self.pid, None);
+    }
+}
+
+/// Executes [`SequenceInput`]s from a snapshot of the target process, see the [module docs](self).
+///
+/// The harness is called once per operation, with the state of the target kept between calls.
+pub struct SnapshotForkExecutor<H, OT, S, SP, T, EM, Z> {
+    harness: H,
+    observers: OT,
+    prefix_coverage: PrefixCoverage<OT>,
+    shmem_provider: SP,
+    timeout: Duration,
+    snapshot: Option<SnapshotProcess<T>>,
+    phantom: PhantomData<(S, EM, Z)>,
+}
+
+impl<H, OT, S, SP, T, EM, Z> Debug for SnapshotForkExecutor<H, OT, S, SP, T, EM, Z>
+where
+    OT: Debug,
+    SP: Debug,
+    T: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SnapshotForkExecutor")
+            .field("observers", &self.observers)
+            .field("shmem_provider", &self.shmem_provider)
+            .field("timeout", &self.timeout)
+            .field("snapshot", &self.snapshot)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<H, OT, S, SP, T, EM, Z> SnapshotForkExecutor<H, OT, S, SP, T, EM, Z>
+where
+    H: FnMut(&T) -> ExitKind,
+    OT: MatchNameRef,
+    SP: ShMemProvider,
+    T: SequenceOp,
+{
+    /// Creates a new [`SnapshotForkExecutor`], without a snapshot yet.
+    /// The coverage of the snapshot prefix is restored in the map of the map observer for each
+    /// execution from the snapshot. The `timeout` applies to each execution.
+    pub fn new<C, O>(
+        harness: H,
+        observers: OT,
+        map_observer_handle: Handle<C>,
+        timeout: Duration,
+        shmem_provider: SP,
+    ) -> Self
+    where
+        C: AsMut<O> + 'static,
+        O: MapObserver + 'static,
+    {
+        Self {
+            harness,
+            observers,
+            prefix_coverage: prefix_coverage(map_observer_handle),
+            shmem_provider,
+            timeout,
+            snapshot: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The harness, called once per operation
+    #[must_use]
+    pub fn harness(&self) -> &H {
+        &self.harness
+    }
+
+    /// The harness, called once per operation, mut
+    pub fn harness_mut(&mut self) -> &mut H {
+        &mut self.harness
+    }
+
+    /// The timeout of each execution
+    #[must_use]
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Replays all operations in a fork of the fuzzer process
+    fn run_forked(&mut self, ops: &[T]) -> Result<ExitKind, Error> {
+        self.shmem_provider.pre_fork()?;
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                // Never return into the fuzzer loop from the child
+                if self.shmem_provider.post_fork(true).is_err() {
+                    unsafe { libc::_exit(SETUP_FAILED_EXIT_CODE) }
+                }
+                arm_timeout(self.timeout);
+                exit_child(replay(&mut self.harness, ops))
+            }
+            Ok(ForkResult::Parent { child }) => {
+                self.shmem_provider.post_fork(false)?;
+                match waitpid(child, None)? {
+                    WaitStatus::Exited(_, SETUP_FAILED_EXIT_CODE) => Err(Error::illegal_state(
+                        "The forked child could not set up its shared memory",
+                    )),
+                    status => Ok(exit_kind_from_status(status)),
+                }
+            }
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    /// Replays the remaining operations in a fork of the snapshot process.
+    /// Returns `None` if the snapshot process is gone.
+    fn run_from_snapshot(&mut self, ops: &[T]) -> Result<Option<ExitKind>, Error> {
+        let Some(snapshot) = &mut self.snapshot else {
+            return Ok(None);
+        };
+        let payload = postcard::to_allocvec(ops)?;
+        let len = u32::try_from(payload.len())
+            .map_err(|_| Error::illegal_argument("Sequence too long for a snapshot"))?;
+        let mut status = [0_u8];
+        let sent = snapshot
+            .stream
+            .write_all(&len.to_le_bytes())
+            .and_then(|()| snapshot.stream.write_all(&payload))
+            .and_then(|()| snapshot.stream.read_exact(&mut status));
+        if let Err(err) = sent {
+            log::warn!("Snapshot process is gone ({err}), replaying from the start");
+            self.snapshot = None;
+            return Ok(None);
+        }
+        Ok(Some(exit_kind_from_status_byte(status[0])))
+    }
+
+    /// The main loop of the snapshot process: forks a child for each received sequence of
+    /// operations, and reports its [`ExitKind`]. Exits once the fuzzer closes the stream.
+    fn serve_snapshot(&mut self, mut stream: UnixStream) -> ! {
+        loop {
+            let mut len = [0_u8; 4];
+            if stream.read_exact(&mut len).is_err() {
+                unsafe { libc::_exit(0) }
+            }
+            let mut payload = vec![0_u8; u32::from_le_bytes(len) as usize];
+            if stream.read_exact(&mut payload).is_err() {
+                unsafe { libc::_exit(0) }
+            }
+            let Ok(ops) = postcard::from_bytes::<Vec<T>>(&payload) else {
+                unsafe { libc::_exit(1) }
+            };
+
+            let exit_kind = match unsafe { fork() } {
+                Ok(ForkResult::Child) => {
+                    (self.prefix_coverage)(&mut self.observers, PrefixCoverageOp::Restore);
+                    arm_timeout(self.timeout);
+                    exit_child(replay(&mut self.harness, &ops))
+                }
+                Ok(ForkResult::Parent { child }) => {
+                    waitpid(child, None).map_or(ExitKind::Crash, exit_kind_from_status)
+                }
+                Err(_) => unsafe { libc::_exit(1) },
+            };
+            if stream.write_all(&[exit_kind_to_status(exit_kind)]).is_err() {
+                unsafe { libc::_exit(0) }
+            }
+        }
+    }
+}
+
+impl<H, OT, S, SP, T, EM, Z> HasSequenceSnapshot<T> for SnapshotForkExecutor<H, OT, S, SP, T, EM, Z>
+where
+    H: FnMut(&T) -> ExitKind,
+    OT: MatchNameRef,
+    SP: ShMemProvider,
+    T: SequenceOp,
+{
+    fn snapshot_prefix(&self) -> Option<&[T]> {
+        self.snapshot
+            .as_ref()
+            .map(|snapshot| snapshot.prefix.as_slice())
+    }
+
+    fn take_snapshot(&mut self, prefix: &[T]) -> Result<(), Error> {
+        self.snapshot = None;
+        let (mut parent_stream, child_stream) = UnixStream::pair()?;
+
+        self.shmem_provider.pre_fork()?;
+        match unsafe { fork() } {
+            Ok(ForkResult::Child) => {
+                // Never return into the fuzzer loop from the child; the fuzzer sees the closed
+                // stream instead of the status
+                if self.shmem_provider.post_fork(true).is_err() {
+                    unsafe { libc::_exit(SETUP_FAILED_EXIT_CODE) }
+                }
+                drop(parent_stream);
+                let mut child_stream = child_stream;
+                (self.prefix_coverage)(&mut self.observers, PrefixCoverageOp::Reset);
+                let exit_kind = replay(&mut self.harness, prefix);
+                // Before the fuzzer resets the map for the next execution
+                (self.prefix_coverage)(&mut self.observers, PrefixCoverageOp::Save);
+                let ready = child_stream.write_all(&[exit_kind_to_status(exit_kind)]);
+                if ready.is_err() || exit_kind != ExitKind::Ok {
+                    unsafe { libc::_exit(0) }
+                }
+                self.serve_snapshot(child_stream)
+            }
+            Ok(ForkResult::Parent { child }) => {
+                self.shmem_provider.post_fork(false)?;
+                drop(child_stream);
+                let snapshot = SnapshotProcess {
+                    pid: child,
+                    prefix: prefix.to_vec(),
+                    stream: parent_stream.try_clone()?,
+                };
+                // The prefix itself has to finish in time, too
+                parent_stream.set_read_timeout(Some(self.timeout.max(Duration::from_millis(1))))?;
+                let mut status = [0_u8];
+                parent_stream.read_exact(&mut status)?;
+                parent_stream.set_read_timeout(None)?;
+                if status[0] != STATUS_OK {
+                    return Err(Error::illegal_state(format!(
+                        "The snapshot prefix did not run cleanly: {:?}",
+                        exit_kind_from_status_byte(status[0])
+                    )));
+                }
+                self.snapshot = Some(snapshot);
+                Ok(())
+            }
+            Err(e) => Err(Error::from(e)),
+        }
+    }
+
+    fn discard_snapshot(&mut self) {
+        self.snapshot = None;
+    }
+}
+
+impl<H, OT, S, SP, T, EM, Z> UsesState for SnapshotForkExecutor<H, OT, S, SP, T, EM, Z>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<H, OT, S, SP, T, EM, Z> UsesObservers for SnapshotForkExecutor<H, OT, S, SP, T, EM, Z>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    type Observers = OT;
+}
+
+impl<H, OT, S, SP, T, EM, Z> HasObservers for SnapshotForkExecutor<H, OT, S, SP, T, EM, Z>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+impl<H, OT, S, SP, T, EM, Z> Executor<EM, Z> for SnapshotForkExecutor<H, OT, S, SP, T, EM, Z>
+where
+    H: FnMut(&T) -> ExitKind,
+    OT: ObserversTuple<S> + Debug,
+    S: State + HasExecutions + UsesInput<Input = SequenceInput<T>>,
+    SP: ShMemProvider,
+    T: SequenceOp,
+    EM: UsesState<State = S>,
+    Z: UsesState<State = S>,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut Self::State,
+        _mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        *state.executions_mut() += 1;
+
+        let prefix_len = self
+            .snapshot_prefix()
+            .filter(|prefix| {
+                input.ops().len() >= prefix.len() && same_ops(&input.ops()[..prefix.len()], prefix)
+            })
+            .map(<[T]>::len);
+        if let Some(prefix_len) = prefix_len {
+            if let Some(exit_kind) = self.run_from_snapshot(&input.ops()[prefix_len..])? {
+                return Ok(exit_kind);
+            }
+        }
+        self.run_forked(input.ops())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use core::time::Duration;
+    use std::time::Instant;
+
+    use libafl_bolts::{
+        shmem::{ShMemProvider, StdShMemProvider},
+        tuples::{tuple_list, Handled},
+        AsSlice, AsSliceMut,
+    };
+    use serial_test::serial;
+
+    use super::{HasSequenceSnapshot, SnapshotForkExecutor};
+    use crate::{
+        events::NopEventManager,
+        executors::{Executor, ExitKind, HasObservers},
+        fuzzer::test::NopFuzzer,
+        inputs::{Operation, SequenceInput},
+        observers::{ObserversTuple, StdMapObserver},
+        state::{test::test_std_state, UsesState},
+    };
+
+    /// Runs the input with fresh observers
+    fn run_input<E, EM, Z>(
+        executor: &mut E,
+        fuzzer: &mut Z,
+        state: &mut E::State,
+        mgr: &mut EM,
+        input: &E::Input,
+    ) -> ExitKind
+    where
+        E: Executor<EM, Z> + HasObservers,
+        E::Observers: ObserversTuple<E::State>,
+        EM: UsesState<State = E::State>,
+        Z: UsesState<State = E::State>,
+    {
+        executor.observers_mut().pre_exec_all(state, input).unwrap();
+        executor.run_target(fuzzer, state, mgr, input).unwrap()
+    }
+
+    #[test]
+    #[serial]
+    #[cfg_attr(miri, ignore)]
+    fn test_snapshot_fork_executor() {
+        let mut provider = StdShMemProvider::new().unwrap();
+        let mut map = provider.new_shmem(16).unwrap();
+        let mut logins = provider.new_shmem(1).unwrap();
+        let map_ptr = map.as_slice_mut().as_mut_ptr();
+        let logins_ptr = logins.as_slice_mut().as_mut_ptr();
+        let observer = unsafe { StdMapObserver::from_mut_ptr("map", map_ptr, 16) };
+        let handle = observer.handle();
+
+        // 0 logs in, 1 crashes once logged in, 2 hangs, any other op covers its index
+        let mut logged_in = false;
+        let harness = move |op: &Operation| {
+            let map = unsafe { core::slice::from_raw_parts_mut(map_ptr, 16) };
+            map[op.opcode as usize] = 1;
+            match op.opcode {
+                0 => {
+                    logged_in = true;
+                    unsafe { *logins_ptr += 1 };
+                    ExitKind::Ok
+                }
+                1 if logged_in => ExitKind::Crash,
+                2 => loop {
+                    core::hint::spin_loop();
+                },
+                _ => ExitKind::Ok,
+            }
+        };
+        let mut executor = SnapshotForkExecutor::new(
+            harness,
+            tuple_list!(observer),
+            handle,
+            Duration::from_millis(100),
+            provider,
+        );
+        let mut fuzzer = NopFuzzer::new();
+        let mut state = test_std_state::<SequenceInput<Operation>>();
+        let mut mgr = NopEventManager::new();
+        let op = |opcode| Operation::new(opcode, vec![]);
+        let mut run = |executor: &mut _, opcodes: &[u32]| {
+            let input = SequenceInput::new(opcodes.iter().copied().map(op).collect());
+            run_input(executor, &mut fuzzer, &mut state, &mut mgr, &input)
+        };
+
+        assert_eq!(run(&mut executor, &[1]), ExitKind::Ok);
+        assert_eq!(run(&mut executor, &[0, 1]), ExitKind::Crash);
+        assert_eq!(logins.as_slice()[0], 1);
+
+        // Inputs starting with the prefix run from the snapshot, which logged in once
+        executor.take_snapshot(&[op(0)]).unwrap();
+        assert_eq!(logins.as_slice()[0], 2);
+        assert_eq!(run(&mut executor, &[0, 1]), ExitKind::Crash);
+        assert_eq!(run(&mut executor, &[0, 3]), ExitKind::Ok);
+        assert_eq!(logins.as_slice()[0], 2);
+        // The coverage of the prefix is restored
+        assert_eq!(map.as_slice()[..4], [1, 0, 0, 1]);
+
+        // Other inputs replay all operations
+        assert_eq!(run(&mut executor, &[3, 0]), ExitKind::Ok);
+        assert_eq!(logins.as_slice()[0], 3);
+
+        // Timeouts are not rounded up to seconds
+        let start = Instant::now();
+        assert_eq!(run(&mut executor, &[0, 2]), ExitKind::Timeout);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,637 @@
+//! The [`SshExecutor`] fuzzes a target on a remote machine, such as an embedded board in a lab,
+//! over a persistent SSH session.
+//!
+//! A single `ssh` connection is kept open across runs: each input is written to a file on the
+//! remote machine, and the target runs on it, under the `timeout` of the remote machine.
+//! Crashes are found by the exit status of the target and, optionally, by scraping the kernel
+//! log for oopses, sanitizer reports and segfaults of the target, see [`SshExecutorBuilder::dmesg`].
+//! The connection breaking down during a run, e.g. because the target brought the machine down,
+//! counts as a crash as well. New sessions are retried until the machine accepts them again,
+//! e.g. after a reboot.
+//!
+//! The session needs to log in without interaction, e.g. with a key, as `ssh` runs in batch mode.
+
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{marker::PhantomData, time::Duration};
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Instant,
+};
+
+use libafl_bolts::{tuples::RefIndexable, AsSlice};
+
+use crate::{
+    executors::{
+        remote_shell::{exit_kind_of, printf_to_file, target_command_line, RemoteShell},
+        Executor, ExitKind, HasObservers,
+    },
+    inputs::HasTargetBytes,
+    observers::{ObserversTuple, UsesObservers},
+    state::{HasExecutions, State, UsesState},
+    Error,
+};
+
+/// The default timeout of a run of the target
+pub const DEFAULT_SSH_TIMEOUT: Duration = Duration::from_secs(5);
+/// The default time to wait for the remote machine to accept a new session
+pub const DEFAULT_SSH_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// The default directory on the remote machine for the input
+pub const DEFAULT_SSH_REMOTE_DIR: &str = "/tmp";
+/// The default kernel log patterns of crashes, see [`SshExecutorBuilder::dmesg`]
+pub const DEFAULT_DMESG_PATTERNS: &[&str] = &[
+    "BUG:",
+    "Oops",
+    "Kernel panic",
+    "general protection fault",
+    "KASAN:",
+    "UBSAN:",
+];
+
+/// The time the session gets on top of the timeout of the target, before it is considered hanging
+const SSH_GRACE_PERIOD: Duration = Duration::from_secs(2);
+/// The time between two attempts to open a session
+const SSH_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// The command printing the boot id of the remote machine, followed by its kernel log
+const DMESG_COMMAND: &str = "cat /proc/sys/kernel/random/boot_id && dmesg";
+/// The kernel truncates the command names of processes to this length
+const TASK_COMM_LEN: usize = 15;
+
+/// The position in the kernel log up to which it was scraped
+#[derive(Debug, Clone, PartialEq)]
+struct DmesgCursor {
+    /// The kernel log starts over after a reboot
+    boot_id: String,
+    /// The timestamp of the last line
+    time: f64,
+}
+
+/// The timestamp of a kernel log line, such as `[   12.345678] message`
+fn dmesg_time(line: &str) -> Option<f64> {
+    let line = line.strip_prefix('[')?;
+    line[..line.find(']')?].trim().parse().ok()
+}
+
+/// The lines of the output of [`DMESG_COMMAND`] logged after the `cursor`, moving it to the
+/// end of the log. Without a cursor, the log is only skipped up to now.
+fn dmesg_since(cursor: &mut Option<DmesgCursor>, output: Vec<String>) -> Vec<String> {
+    let mut lines = output.into_iter();
+    let boot_id = lines.next().unwrap_or_default();
+    let since = match cursor {
+        Some(cursor) if cursor.boot_id == boot_id => Some(cursor.time),
+        // The machine rebooted, the whole log is new
+        Some(_) => Some(f64::MIN),
+        None => None,
+    };
+    let mut time = since.unwrap_or(f64::MIN);
+    let mut new_lines = Vec::new();
+    for line in lines {
+        // Continuation lines have no timestamp of their own
+        let line_time = dmesg_time(&line).unwrap_or(time);
+        if since.is_some_and(|since| line_time > since) {
+            new_lines.push(line);
+        }
+        time = time.max(line_time);
+    }
+    *cursor = Some(DmesgCursor { boot_id, time });
+    new_lines
+}
+
+/// Whether the kernel log line reports a crash: it matches one of the `patterns`, or reports a
+/// segfault of a process with the command name `comm`
+fn is_dmesg_crash(line: &str, patterns: &[String], comm: &str) -> bool {
+    patterns.iter().any(|pattern| line.contains(pattern))
+        || (line.contains("segfault at") && line.contains(&format!(" {comm}[")))
+}
+
+/// A remote machine, reachable by `ssh`
+#[derive(Debug, Clone)]
+pub struct SshTarget {
+    ssh: PathBuf,
+    host: String,
+    user: Option<String>,
+    port: Option<u16>,
+    identity: Option<PathBuf>,
+    options: Vec<String>,
+}
+
+impl SshTarget {
+    /// The machine at `host`, using the `ssh` in the `PATH`
+    #[must_use]
+    pub fn new(host: &str) -> Self {
+        Self {
+            ssh: PathBuf::from("ssh"),
+            host: host.into(),
+            user: None,
+            port: None,
+            identity: None,
+            options: Vec::new(),
+        }
+    }
+
+    /// Logs in as this user
+    #[must_use]
+    pub fn with_user(mut self, user: &str) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Connects to this port
+    #[must_use]
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Logs in with the private key in this file
+    #[must_use]
+    pub fn with_identity<P>(mut self, identity: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.identity = Some(identity.as_ref().to_path_buf());
+        self
+    }
+
+    /// Adds an `ssh` option, e.g. `StrictHostKeyChecking=no`
+    #[must_use]
+    pub fn with_option(mut self, option: &str) -> Self {
+        self.options.push(option.into());
+        self
+    }
+
+    /// Uses the `ssh` at this path
+    #[must_use]
+    pub fn with_ssh<P>(mut self, ssh: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.ssh = ssh.as_ref().to_path_buf();
+        self
+    }
+
+    /// The destination passed to `ssh`, `[user@]host`
+    #[must_use]
+    pub fn destination(&self) -> String {
+        match &self.user {
+            Some(user) => format!("{user}@{}", self.host),
+            None => self.host.clone(),
+        }
+    }
+
+    /// The `ssh` command opening a shell session on the remote machine, reading command lines
+    /// on stdin
+    #[must_use]
+    pub fn command(&self) -> Command {
+        let mut command = Command::new(&self.ssh);
+        command.args(["-T", "-o", "BatchMode=yes"]);
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        if let Some(identity) = &self.identity {
+            command.arg("-i").arg(identity);
+        }
+        for option in &self.options {
+            command.arg("-o").arg(option);
+        }
+        command.arg(self.destination()).arg("sh");
+        command
+    }
+}
+
+/// Runs a target on a remote machine over SSH, see the [module docs](self).
+/// Use [`SshExecutor::builder`] to create it.
+#[derive(Debug)]
+pub struct SshExecutor<OT, S> {
+    target: SshTarget,
+    command_line: String,
+    remote_input: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    dmesg_patterns: Option<Vec<String>>,
+    dmesg_cursor: Option<DmesgCursor>,
+    comm: String,
+    shell: Option<RemoteShell>,
+    dmesg: Vec<String>,
+    observers: OT,
+    phantom: PhantomData<S>,
+}
+
+impl SshExecutor<(), ()> {
+    /// Creates a builder for an [`SshExecutor`]
+    #[must_use]
+    pub fn builder() -> SshExecutorBuilder {
+        SshExecutorBuilder::new()
+    }
+}
+
+impl<OT, S> SshExecutor<OT, S> {
+    /// The remote machine the target runs on
+    #[must_use]
+    pub fn target(&self) -> &SshTarget {
+        &self.target
+    }
+
+    /// The shell command line running the target on the remote machine
+    #[must_use]
+    pub fn command_line(&self) -> &str {
+        &self.command_line
+    }
+
+    /// The kernel log lines of crashes, logged during the last run, if the kernel log is scraped
+    #[must_use]
+    pub fn dmesg(&self) -> &[String] {
+        &self.dmesg
+    }
+
+    /// Opens a new session, retrying until the remote machine accepts it, e.g. while it reboots.
+    /// Before the first run, this skips the kernel log up to now.
+    fn connect(&mut self) -> Result<RemoteShell, Error> {
+        let deadline = Instant::now() + self.connect_timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let attempt = RemoteShell::spawn(&mut self.target.command())
+                .and_then(|mut shell| Ok(shell.run("true", remaining)?.map(|_| shell)));
+            match attempt {
+                Ok(Some(mut shell)) => {
+                    if self.dmesg_cursor.is_none() {
+                        self.scrape_dmesg(&mut shell)?;
+                    }
+                    return Ok(shell);
+                }
+                Ok(None) => {}
+                Err(err) => log::debug!("Could not open an SSH session: {err}"),
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::illegal_state(format!(
+                    "Could not open an SSH session to {} within {:?}",
+                    self.target.destination(),
+                    self.connect_timeout
+                )));
+            }
+            thread::sleep(
+                SSH_RECONNECT_DELAY.min(deadline.saturating_duration_since(Instant::now())),
+            );
+        }
+    }
+
+    /// Reads the kernel log since the last call, keeping the lines reporting crashes
+    fn scrape_dmesg(&mut self, shell: &mut RemoteShell) -> Result<(), Error> {
+        let Some(patterns) = &self.dmesg_patterns else {
+            return Ok(());
+        };
+        let Some((code, output)) = shell.run(DMESG_COMMAND, self.connect_timeout)? else {
+            return Err(Error::illegal_state("Reading the kernel log timed out"));
+        };
+        if code != 0 {
+            return Err(Error::illegal_state(format!(
+                "dmesg failed with {code}, reading the kernel log may need root"
+            )));
+        }
+        self.dmesg = dmesg_since(&mut self.dmesg_cursor, output)
+            .into_iter()
+            .filter(|line| is_dmesg_crash(line, patterns, &self.comm))
+            .collect();
+        Ok(())
+    }
+}
+
+impl<OT, S> UsesState for SshExecutor<OT, S>
+where
+    S: State,
+{
+    type State = S;
+}
+
+impl<OT, S> UsesObservers for SshExecutor<OT, S>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    type Observers = OT;
+}
+
+impl<OT, S> HasObservers for SshExecutor<OT, S>
+where
+    OT: ObserversTuple<S>,
+    S: State,
+{
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+impl<EM, OT, S, Z> Executor<EM, Z> for SshExecutor<OT, S>
+where
+    EM: UsesState<State = S>,
+    OT: ObserversTuple<S>,
+    S: State + HasExecutions,
+    S::Input: HasTargetBytes,
+    Z: UsesState<State = S>,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut Self::State,
+        _mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        *state.executions_mut() += 1;
+        self.dmesg.clear();
+
+        let mut shell = match self.shell.take() {
+            Some(shell) => shell,
+            None => self.connect()?,
+        };
+        let command_line = format!(
+            "{} && {}",
+            printf_to_file(input.target_bytes().as_slice(), &self.remote_input),
+            self.command_line
+        );
+        let mut exit_kind = match shell.run(&command_line, self.timeout + SSH_GRACE_PERIOD) {
+            Ok(Some((code, _))) => exit_kind_of(code, None),
+            Ok(None) => {
+                // The session is out of sync, e.g. because the kernel hangs after an oops:
+                // look at the kernel log in a new session
+                drop(shell);
+                shell = self.connect()?;
+                ExitKind::Timeout
+            }
+            Err(err) => {
+                log::info!("The SSH session broke down during the run: {err}");
+                return Ok(ExitKind::Crash);
+            }
+        };
+
+        self.scrape_dmesg(&mut shell)?;
+        if !self.dmesg.is_empty() {
+            log::info!("Crash in the kernel log: {}", self.dmesg[0]);
+            exit_kind = ExitKind::Crash;
+        }
+        self.shell = Some(shell);
+        Ok(exit_kind)
+    }
+
+    fn restart_target(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        self.shell = None;
+        Ok(())
+    }
+}
+
+/// The builder for an [`SshExecutor`]
+#[derive(Debug)]
+pub struct SshExecutorBuilder {
+    target: Option<SshTarget>,
+    program: Option<String>,
+    args: Vec<String>,
+    remote_dir: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    dmesg_patterns: Option<Vec<String>>,
+}
+
+impl Default for SshExecutorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SshExecutorBuilder {
+    /// Creates a new [`SshExecutorBuilder`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            target: None,
+            program: None,
+            args: Vec::new(),
+            remote_dir: DEFAULT_SSH_REMOTE_DIR.to_owned(),
+            timeout: DEFAULT_SSH_TIMEOUT,
+            connect_timeout: DEFAULT_SSH_CONNECT_TIMEOUT,
+            dmesg_patterns: None,
+        }
+    }
+
+    /// Sets the remote machine to run the target on
+    #[must_use]
+    pub fn target(mut self, target: SshTarget) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    /// Sets the path of the target on the remote machine
+    #[must_use]
+    pub fn program(mut self, program: &str) -> Self {
+        self.program = Some(program.into());
+        self
+    }
+
+    /// Adds an argument of the target. Each
+    /// [`crate::executors::command::AFL_INPUT_FILE_PLACEHOLDER`] (`@@`) is replaced by
+    /// the path of the input on the remote machine. Without a placeholder, the input is passed
+    /// on stdin.
+    #[must_use]
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// Adds arguments of the target, see [`Self::arg`]
+    #[must_use]
+    pub fn args<IT, A>(mut self, args: IT) -> Self
+    where
+        IT: IntoIterator<Item = A>,
+        A: AsRef<str>,
+    {
+        self.args
+            .extend(args.into_iter().map(|arg| arg.as_ref().to_owned()));
+        self
+    }
+
+    /// Sets the directory on the remote machine the input is written to, defaults to
+    /// [`DEFAULT_SSH_REMOTE_DIR`]
+    #[must_use]
+    pub fn remote_dir(mut self, remote_dir: &str) -> Self {
+        self.remote_dir = remote_dir.into();
+        self
+    }
+
+    /// Sets the timeout of a run of the target
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the time to wait for the remote machine to accept a new session, e.g. while it
+    /// reboots
+    #[must_use]
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// Scrapes the kernel log after each run for the [`DEFAULT_DMESG_PATTERNS`] and segfaults of
+    /// the target, reporting a crash if one matches. The kernel log is not cleared, only the
+    /// lines logged during the run are scraped, by their timestamps. Reading the kernel log needs
+    /// root if `kernel.dmesg_restrict` is set on the remote machine.
+    #[must_use]
+    pub fn dmesg(self) -> Self {
+        self.dmesg_patterns(DEFAULT_DMESG_PATTERNS.iter().copied())
+    }
+
+    /// Scrapes the kernel log after each run for these patterns, see [`Self::dmesg`]
+    #[must_use]
+    pub fn dmesg_patterns<IT, P>(mut self, patterns: IT) -> Self
+    where
+        IT: IntoIterator<Item = P>,
+        P: AsRef<str>,
+    {
+        self.dmesg_patterns = Some(
+            patterns
+                .into_iter()
+                .map(|pattern| pattern.as_ref().to_owned())
+                .collect(),
+        );
+        self
+    }
+
+    /// Builds the [`SshExecutor`]
+    pub fn build<OT, S>(self, observers: OT) -> Result<SshExecutor<OT, S>, Error>
+    where
+        OT: ObserversTuple<S>,
+        S: State,
+    {
+        let Some(target) = self.target else {
+            return Err(Error::illegal_argument(
+                "SshExecutor::builder: no target machine set!",
+            ));
+        };
+        let Some(program) = &self.program else {
+            return Err(Error::illegal_argument(
+                "SshExecutor::builder: no program set!",
+            ));
+        };
+        // Several fuzzers may share a machine, so the input file is unique per fuzzer
+        let remote_input = format!(
+            "{}/.libafl_ssh_input_{}",
+            self.remote_dir.trim_end_matches('/'),
+            std::process::id()
+        );
+        let command_line = target_command_line(self.timeout, program, &self.args, &remote_input);
+        // Segfaults in the kernel log name the process by its (truncated) command name
+        let comm = program
+            .rsplit('/')
+            .next()
+            .unwrap_or(program)
+            .chars()
+            .take(TASK_COMM_LEN)
+            .collect();
+        Ok(SshExecutor {
+            target,
+            command_line,
+            remote_input,
+            timeout: self.timeout,
+            connect_timeout: self.connect_timeout,
+            dmesg_patterns: self.dmesg_patterns,
+            dmesg_cursor: None,
+            comm,
+            shell: None,
+            dmesg: Vec::new(),
+            observers,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs, os::unix::fs::PermissionsExt};
+
+    use super::{dmesg_since, is_dmesg_crash, SshExecutor, SshTarget};
+    use crate::{
+        events::NopEventManager,
+        executors::{Executor, ExitKind},
+        fuzzer::test::NopFuzzer,
+        inputs::BytesInput,
+        state::NopState,
+    };
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_ssh_executor() {
+        // Stands in for `ssh`, opening a local shell instead
+        let fake_ssh = temp_dir().join(format!("libafl_fake_ssh_{}", std::process::id()));
+        fs::write(&fake_ssh, "#!/bin/sh\nexec sh\n").unwrap();
+        fs::set_permissions(&fake_ssh, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut executor = SshExecutor::builder()
+            .target(SshTarget::new("board").with_ssh(&fake_ssh))
+            .program("sh")
+            .args(["-c", r#"read x; [ "$x" = crash ] && kill -SEGV $$; exit 0"#])
+            .remote_dir(temp_dir().to_str().unwrap())
+            .build(())
+            .unwrap();
+        let mut run = |bytes: &[u8]| {
+            executor
+                .run_target(
+                    &mut NopFuzzer::new(),
+                    &mut NopState::new(),
+                    &mut NopEventManager::new(),
+                    &BytesInput::new(bytes.to_vec()),
+                )
+                .unwrap()
+        };
+        assert_eq!(run(b"fine\n"), ExitKind::Ok);
+        assert_eq!(run(b"crash\n"), ExitKind::Crash);
+        assert_eq!(run(b"fine again\n"), ExitKind::Ok);
+
+        fs::remove_file(fake_ssh).unwrap();
+    }
+
+    #[test]
+    fn test_ssh_dmesg_cursor() {
+        let log = |lines: &[&str]| lines.iter().map(|&line| line.into()).collect();
+        let mut cursor = None;
+        // The log before the first run is skipped
+        assert!(dmesg_since(&mut cursor, log(&["boot", "[    1.000000] BUG: old"])).is_empty());
+        assert_eq!(
+            dmesg_since(
+                &mut cursor,
+                log(&["boot", "[    1.000000] BUG: old", "[    2.500000] BUG: new"])
+            ),
+            ["[    2.500000] BUG: new"]
+        );
+        assert!(dmesg_since(&mut cursor, log(&["boot", "[    2.500000] BUG: new"])).is_empty());
+        // After a reboot, the whole log is new
+        assert_eq!(
+            dmesg_since(&mut cursor, log(&["reboot", "[    0.100000] Oops"])),
+            ["[    0.100000] Oops"]
+        );
+
+        let patterns = ["BUG:".into()];
+        assert!(is_dmesg_crash(
+            "[1.0] BUG: kernel NULL",
+            &patterns,
+            "target"
+        ));
+        assert!(is_dmesg_crash(
+            "[1.0] target[42]: segfault at 0 ip 0",
+            &patterns,
+            "target"
+        ));
+        assert!(!is_dmesg_crash(
+            "[1.0] other[42]: segfault at 0 ip 0",
+            &patterns,
+            "target"
+        ));
+    }
+}
@@ -0,0 +1,111 @@
+//! A wrapper for any [`Executor`] to run an [`ExecutorHooksTuple`] before and after each run of
+//! the target, e.g. for executors without hooks of their own, such as the
+//! [`crate::executors::ForkserverExecutor`] or the [`crate::executors::CommandExecutor`].
+
+use libafl_bolts::tuples::RefIndexable;
+
+use crate::{
+    executors::{hooks::ExecutorHooksTuple, Executor, ExitKind, HasObservers},
+    observers::UsesObservers,
+    state::UsesState,
+    Error,
+};
+
+/// A wrapper for any [`Executor`] to run an [`ExecutorHooksTuple`] before and after each run.
+///
+/// The hooks run in the fuzzer process, around the whole [`Executor::run_target`] of the wrapped
+/// executor. The post exec hooks run even if the wrapped executor fails.
+#[derive(Debug)]
+pub struct WithHooks<E, HT> {
+    executor: E,
+    hooks: HT,
+}
+
+impl<E, HT> WithHooks<E, HT>
+where
+    E: HasObservers,
+    HT: ExecutorHooksTuple<E::State>,
+{
+    /// Wraps the given [`Executor`], running the hooks around each run of the target.
+    /// The hooks are initialized right away.
+    pub fn new(executor: E, mut hooks: HT, state: &mut E::State) -> Self {
+        hooks.init_all::<E>(state);
+        Self { executor, hooks }
+    }
+}
+
+impl<E, HT> WithHooks<E, HT> {
+    /// The wrapped executor
+    pub fn executor(&self) -> &E {
+        &self.executor
+    }
+
+    /// The wrapped executor (mutable)
+    pub fn executor_mut(&mut self) -> &mut E {
+        &mut self.executor
+    }
+
+    /// The hooks
+    pub fn hooks(&self) -> &HT {
+        &self.hooks
+    }
+
+    /// The hooks (mutable)
+    pub fn hooks_mut(&mut self) -> &mut HT {
+        &mut self.hooks
+    }
+}
+
+impl<E, EM, HT, Z> Executor<EM, Z> for WithHooks<E, HT>
+where
+    E: Executor<EM, Z>,
+    EM: UsesState<State = Self::State>,
+    HT: ExecutorHooksTuple<Self::State>,
+    Z: UsesState<State = Self::State>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        self.hooks.pre_exec_all(state, input);
+        let ret = self.executor.run_target(fuzzer, state, mgr, input);
+        self.hooks.post_exec_all(state, input);
+        ret
+    }
+
+    fn restart_target(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.executor.restart_target(state)
+    }
+}
+
+impl<E, HT> UsesState for WithHooks<E, HT>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, HT> UsesObservers for WithHooks<E, HT>
+where
+    E: UsesObservers,
+{
+    type Observers = E::Observers;
+}
+
+impl<E, HT> HasObservers for WithHooks<E, HT>
+where
+    E: HasObservers,
+{
+    #[inline]
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        self.executor.observers()
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        self.executor.observers_mut()
+    }
+}
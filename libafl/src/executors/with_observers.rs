@@ -33,6 +33,10 @@ where
     ) -> Result<ExitKind, Error> {
         self.executor.run_target(fuzzer, state, mgr, input)
     }
+
+    fn restart_target(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.executor.restart_target(state)
+    }
 }
 
 impl<E, OT> UsesState for WithObservers<E, OT>
@@ -0,0 +1,357 @@
+//! The [`CoreDumpFeedback`] collects the core dumps of objectives, found by the
+//! [`crate::executors::CoreDumpExecutor`], see [`crate::executors::core_dump`].
+
+use alloc::{borrow::Cow, collections::VecDeque};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libafl_bolts::{impl_serdeany, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::Testcase,
+    events::EventFirer,
+    executors::{
+        core_dump::{remove_core, PendingCoreDumpMetadata},
+        ExitKind,
+    },
+    feedbacks::Feedback,
+    inputs::Input,
+    observers::ObserversTuple,
+    state::State,
+    Error, HasMetadata,
+};
+
+/// The default maximal size of a collected core dump
+pub const DEFAULT_MAX_CORE_SIZE: u64 = 1 << 30;
+/// The default maximal number of collected core dumps kept on disk
+pub const DEFAULT_MAX_CORES: usize = 100;
+
+/// The path of the collected core dump of an objective
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CoreDumpMetadata {
+    /// The path of the collected core dump
+    pub path: PathBuf,
+}
+
+impl_serdeany!(CoreDumpMetadata);
+
+/// Moves core dumps to an output directory, keeping at most [`Self::max_cores`] of them
+#[derive(Debug)]
+pub struct CoreDumpCollector {
+    output_dir: PathBuf,
+    max_core_size: u64,
+    max_cores: usize,
+    collected: VecDeque<PathBuf>,
+}
+
+impl CoreDumpCollector {
+    /// Creates a new [`CoreDumpCollector`], moving the core dumps to `output_dir`
+    pub fn new<P>(output_dir: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        fs::create_dir_all(&output_dir)?;
+        Ok(Self {
+            output_dir: output_dir.as_ref().to_path_buf(),
+            max_core_size: DEFAULT_MAX_CORE_SIZE,
+            max_cores: DEFAULT_MAX_CORES,
+            collected: VecDeque::new(),
+        })
+    }
+
+    /// Sets the maximal size of a core dump, larger ones are deleted instead of collected
+    #[must_use]
+    pub fn max_core_size(mut self, max_core_size: u64) -> Self {
+        self.max_core_size = max_core_size;
+        self
+    }
+
+    /// Sets the maximal number of collected core dumps, the oldest ones are deleted first
+    #[must_use]
+    pub fn max_cores(mut self, max_cores: usize) -> Self {
+        self.max_cores = max_cores;
+        self
+    }
+
+    /// The core dumps collected so far, still on disk, oldest first
+    pub fn collected(&self) -> impl Iterator<Item = &Path> {
+        self.collected.iter().map(PathBuf::as_path)
+    }
+
+    /// Collects the core dump as `<name>.core`.
+    /// Returns its path, if it was within the size limit.
+    pub fn collect(&mut self, core: &Path, name: &str) -> Result<Option<PathBuf>, Error> {
+        let size = fs::metadata(core)?.len();
+        if size > self.max_core_size {
+            log::info!(
+                "Deleting core dump {} of {size} bytes, above the limit",
+                core.display()
+            );
+            remove_core(core)?;
+            return Ok(None);
+        }
+
+        let dest = self.output_dir.join(format!("{name}.core"));
+        move_file(core, &dest)?;
+        if let Some(pos) = self.collected.iter().position(|path| *path == dest) {
+            // Overwritten by a crash of the same input
+            self.collected.remove(pos);
+        }
+        self.collected.push_back(dest.clone());
+        while self.collected.len() > self.max_cores {
+            if let Some(oldest) = self.collected.pop_front() {
+                remove_core(&oldest)?;
+            }
+        }
+        Ok(Some(dest))
+    }
+}
+
+/// Moves a file, copying it if it is on another file system
+fn move_file(from: &Path, to: &Path) -> Result<(), Error> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Wraps the objective feedback, collecting the core dump of each objective with a
+/// [`CoreDumpCollector`], and deleting the core dumps of other crashes.
+///
+/// The core dump is named after the file name of the objective, or [`Input::generate_name`]
+/// without a corpus id if it has none yet.
+#[derive(Debug)]
+pub struct CoreDumpFeedback<A> {
+    inner: A,
+    name: Cow<'static, str>,
+    collector: CoreDumpCollector,
+}
+
+impl<A> CoreDumpFeedback<A>
+where
+    A: Named,
+{
+    /// Creates a new [`CoreDumpFeedback`], collecting the core dumps of the objectives of the
+    /// `inner` feedback with the `collector`
+    pub fn new(inner: A, collector: CoreDumpCollector) -> Self {
+        Self {
+            name: Cow::from(format!("CoreDump({})", inner.name())),
+            inner,
+            collector,
+        }
+    }
+
+    /// The [`CoreDumpCollector`]
+    pub fn collector(&self) -> &CoreDumpCollector {
+        &self.collector
+    }
+}
+
+impl<A> Named for CoreDumpFeedback<A> {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+/// Takes the core dump of the last crash, if there is one
+fn take_pending_core<S>(state: &mut S) -> Option<PathBuf>
+where
+    S: HasMetadata,
+{
+    state
+        .metadata_map_mut()
+        .get_mut::<PendingCoreDumpMetadata>()
+        .and_then(|pending| pending.path.take())
+}
+
+impl<A, S> Feedback<S> for CoreDumpFeedback<A>
+where
+    A: Feedback<S>,
+    S: State + HasMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.init_state(state)
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &S::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.inner
+            .is_interesting(state, manager, input, observers, exit_kind)
+    }
+
+    fn append_metadata<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        self.inner
+            .append_metadata(state, manager, observers, testcase)?;
+
+        let Some(core) = take_pending_core(state) else {
+            return Ok(());
+        };
+        let name = match (testcase.filename(), testcase.input()) {
+            (Some(filename), _) => filename.clone(),
+            (None, Some(input)) => input.generate_name(None),
+            (None, None) => return remove_core(&core),
+        };
+        match self.collector.collect(&core, &name) {
+            Ok(Some(path)) => {
+                log::info!("Collected core dump {}", path.display());
+                testcase.add_metadata(CoreDumpMetadata { path });
+            }
+            Ok(None) => {}
+            // Losing the core dump is no reason to lose the objective
+            Err(err) => log::error!("Failed to collect the core dump {}: {err}", core.display()),
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        self.inner.discard_metadata(state, input)?;
+        match take_pending_core(state) {
+            Some(core) => remove_core(&core),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.inner.last_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs, path::PathBuf, vec, vec::Vec};
+
+    use super::{CoreDumpCollector, CoreDumpFeedback, CoreDumpMetadata};
+    use crate::{
+        corpus::Testcase,
+        events::NopEventManager,
+        executors::{core_dump::PendingCoreDumpMetadata, ExitKind},
+        feedbacks::{CrashFeedback, Feedback},
+        inputs::BytesInput,
+        state::test::test_std_state,
+        HasMetadata,
+    };
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_core_dump_collector() {
+        let base = temp_dir().join(format!("libafl_core_dump_{}", std::process::id()));
+        let (source_dir, output_dir) = (base.join("source"), base.join("output"));
+        fs::create_dir_all(&source_dir).unwrap();
+        let mut collector = CoreDumpCollector::new(&output_dir)
+            .unwrap()
+            .max_core_size(4)
+            .max_cores(1);
+
+        fs::write(source_dir.join("core.1"), b"core").unwrap();
+        let first = collector
+            .collect(&source_dir.join("core.1"), "first")
+            .unwrap()
+            .unwrap();
+        assert_eq!(first, output_dir.join("first.core"));
+        assert!(!source_dir.join("core.1").exists());
+
+        // Too large cores are deleted, not collected
+        fs::write(source_dir.join("core.2"), b"too large").unwrap();
+        assert!(collector
+            .collect(&source_dir.join("core.2"), "large")
+            .unwrap()
+            .is_none());
+        assert!(!source_dir.join("core.2").exists());
+
+        // Only the newest core is kept
+        fs::write(source_dir.join("core.3"), b"core").unwrap();
+        collector
+            .collect(&source_dir.join("core.3"), "second")
+            .unwrap()
+            .unwrap();
+        assert!(!first.exists());
+        assert_eq!(
+            collector.collected().map(PathBuf::from).collect::<Vec<_>>(),
+            vec![output_dir.join("second.core")]
+        );
+
+        fs::remove_dir_all(base).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_core_dump_feedback() {
+        let base = temp_dir().join(format!("libafl_core_dump_feedback_{}", std::process::id()));
+        let (source_dir, output_dir) = (base.join("source"), base.join("output"));
+        fs::create_dir_all(&source_dir).unwrap();
+        let mut feedback = CoreDumpFeedback::new(
+            CrashFeedback::new(),
+            CoreDumpCollector::new(&output_dir).unwrap(),
+        );
+        let mut state = test_std_state::<BytesInput>();
+        let mut mgr = NopEventManager::new();
+        let input = BytesInput::new(vec![1]);
+        let crash = |state: &mut _, name: &str| {
+            let core = source_dir.join(name);
+            fs::write(&core, b"core").unwrap();
+            HasMetadata::add_metadata(
+                state,
+                PendingCoreDumpMetadata {
+                    path: Some(core.clone()),
+                },
+            );
+            core
+        };
+
+        // The core dump of a crash that is no objective is deleted
+        let core = crash(&mut state, "core.1");
+        feedback.discard_metadata(&mut state, &input).unwrap();
+        assert!(!core.exists());
+
+        // The core dump of an objective is collected next to it
+        let core = crash(&mut state, "core.2");
+        assert!(feedback
+            .is_interesting(&mut state, &mut mgr, &input, &(), &ExitKind::Crash)
+            .unwrap());
+        let mut testcase = Testcase::new(input.clone());
+        *testcase.filename_mut() = Some("objective".into());
+        feedback
+            .append_metadata(&mut state, &mut mgr, &(), &mut testcase)
+            .unwrap();
+        assert!(!core.exists());
+        let collected = output_dir.join("objective.core");
+        assert!(collected.exists());
+        assert_eq!(
+            testcase.metadata::<CoreDumpMetadata>().unwrap().path,
+            collected
+        );
+
+        fs::remove_dir_all(base).unwrap();
+    }
+}
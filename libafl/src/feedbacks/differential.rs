@@ -1,13 +1,18 @@
 //! Diff Feedback, comparing the content of two observers of the same type.
 //!
+//! The [`DiffReportFeedback`] instead judges the [`DiffReport`] of a
+//! [`DiffExecutor`](crate::executors::DiffExecutor).
 
-use alloc::borrow::Cow;
+use alloc::{borrow::Cow, vec::Vec};
 use core::{
     fmt::{self, Debug, Formatter},
     marker::PhantomData,
 };
 
+use hashbrown::HashSet;
 use libafl_bolts::{
+    hash_std,
+    serdeany::VersionedSerdeAny,
     tuples::{Handle, Handled, MatchName, MatchNameRef},
     Named,
 };
@@ -17,12 +22,12 @@ use serde::{Deserialize, Serialize};
 use crate::feedbacks::premature_last_result_err;
 use crate::{
     events::EventFirer,
-    executors::ExitKind,
+    executors::{differential::DiffReport, ExitKind},
     feedbacks::{Feedback, FeedbackFactory},
-    inputs::Input,
+    inputs::{Input, UsesInput},
     observers::{Observer, ObserversTuple},
     state::State,
-    Error, HasMetadata,
+    Error, HasMetadata, HasNamedMetadata,
 };
 
 /// The result of a differential test between two observers.
@@ -192,6 +197,192 @@ where
     }
 }
 
+/// The divergences already reported by a [`DiffReportFeedback`], if it deduplicates them
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[allow(clippy::unsafe_derive_deserialize)]
+pub struct DiffReportFeedbackMetadata {
+    /// The hashes of the reported divergences
+    pub signatures: HashSet<u64>,
+}
+
+libafl_bolts::impl_serdeany_versioned!(DiffReportFeedbackMetadata);
+
+impl VersionedSerdeAny for DiffReportFeedbackMetadata {
+    const SCHEMA_VERSION: u32 = 1;
+}
+
+/// A [`DiffReportFeedback`] considers the runs of a [`DiffExecutor`](crate::executors::DiffExecutor)
+/// interesting if they diverged, as described by its [`DiffReport`], within the configured
+/// tolerances.
+///
+/// By default, any divergence is interesting: of the exit kinds, of the compared observers,
+/// or of the stdout.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct DiffReportFeedback {
+    name: Cow<'static, str>,
+    ignore_stdout: bool,
+    ignore_timeouts: bool,
+    require_exit_kind_mismatch: bool,
+    ignored_observers: Vec<Cow<'static, str>>,
+    dedup: bool,
+    #[cfg(feature = "track_hit_feedbacks")]
+    // The previous run's result of `Self::is_interesting`
+    last_result: Option<bool>,
+}
+
+impl DiffReportFeedback {
+    /// Creates a new [`DiffReportFeedback`], reporting any divergence
+    #[must_use]
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name: Cow::from(name),
+            ignore_stdout: false,
+            ignore_timeouts: false,
+            require_exit_kind_mismatch: false,
+            ignored_observers: Vec::new(),
+            dedup: false,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+
+    /// Ignores divergences of the stdout
+    #[must_use]
+    pub fn ignore_stdout(mut self) -> Self {
+        self.ignore_stdout = true;
+        self
+    }
+
+    /// Ignores runs in which either executor timed out, since their outcome is incomplete
+    #[must_use]
+    pub fn ignore_timeouts(mut self) -> Self {
+        self.ignore_timeouts = true;
+        self
+    }
+
+    /// Only reports runs in which the exit kinds diverged, whatever the observers and stdout say
+    #[must_use]
+    pub fn require_exit_kind_mismatch(mut self) -> Self {
+        self.require_exit_kind_mismatch = true;
+        self
+    }
+
+    /// Ignores divergences of the compared observer of the primary executor named `name`
+    #[must_use]
+    pub fn ignore_observer(mut self, name: &'static str) -> Self {
+        self.ignored_observers.push(Cow::from(name));
+        self
+    }
+
+    /// Only reports each kind of divergence once: runs diverging the same way as an already
+    /// reported one, with the same exit kinds and diverging observers, are not interesting
+    #[must_use]
+    pub fn dedup(mut self) -> Self {
+        self.dedup = true;
+        self
+    }
+
+    /// Checks if the `report` is a divergence within the tolerances
+    #[must_use]
+    pub fn is_divergence(&self, report: &DiffReport) -> bool {
+        if self.ignore_timeouts
+            && (report.primary == ExitKind::Timeout || report.secondary == ExitKind::Timeout)
+        {
+            return false;
+        }
+        if report.exit_kinds_diverge() {
+            return true;
+        }
+        !self.require_exit_kind_mismatch
+            && (self.diverging_observers(report).next().is_some()
+                || (!self.ignore_stdout && report.stdout_diff.is_some()))
+    }
+
+    /// The diverging observers of the `report`, not ignored
+    fn diverging_observers<'a>(
+        &'a self,
+        report: &'a DiffReport,
+    ) -> impl Iterator<Item = &'a Cow<'static, str>> + 'a {
+        report
+            .diverging_observers
+            .iter()
+            .filter(|name| !self.ignored_observers.contains(name))
+    }
+
+    /// The hash identifying the kind of divergence of the `report`, for [`Self::dedup`]
+    fn signature(&self, report: &DiffReport) -> Result<u64, Error> {
+        let observers: Vec<_> = self.diverging_observers(report).collect();
+        let stdout = !self.ignore_stdout && report.stdout_diff.is_some();
+        Ok(hash_std(&postcard::to_allocvec(&(
+            report.primary,
+            report.secondary,
+            observers,
+            stdout,
+        ))?))
+    }
+}
+
+impl Named for DiffReportFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Feedback<S> for DiffReportFeedback
+where
+    S: State + HasMetadata + HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        if self.dedup {
+            state.add_named_metadata(&self.name, DiffReportFeedbackMetadata::default());
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &<S as UsesInput>::Input,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let report = state.metadata::<DiffReport>().map_err(|_| {
+            Error::illegal_state(
+                "DiffReportFeedback: no DiffReport, is the executor a DiffExecutor?",
+            )
+        })?;
+        let mut res = self.is_divergence(report);
+        let signature = if res && self.dedup {
+            Some(self.signature(report)?)
+        } else {
+            None
+        };
+        if let Some(signature) = signature {
+            res = state
+                .named_metadata_or_insert_with(&self.name, DiffReportFeedbackMetadata::default)
+                .signatures
+                .insert(signature);
+        }
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alloc::borrow::Cow;
@@ -201,8 +392,11 @@ mod tests {
 
     use crate::{
         events::EventFirer,
-        executors::ExitKind,
-        feedbacks::{differential::DiffResult, DiffFeedback, Feedback},
+        executors::{
+            differential::{DiffReport, StdoutDiff},
+            ExitKind,
+        },
+        feedbacks::{differential::DiffResult, DiffFeedback, DiffReportFeedback, Feedback},
         inputs::{BytesInput, UsesInput},
         observers::Observer,
         state::{NopState, State, UsesState},
@@ -299,4 +493,32 @@ mod tests {
     fn test_diff_neq() {
         test_diff(false);
     }
+
+    #[test]
+    fn test_diff_report_tolerances() {
+        let mut report = DiffReport::new(ExitKind::Ok, ExitKind::Ok);
+        let feedback = DiffReportFeedback::new("diff_report");
+        assert!(!feedback.is_divergence(&report));
+
+        report.diverging_observers.push(Cow::from("edges"));
+        assert!(feedback.is_divergence(&report));
+        assert!(!feedback
+            .clone()
+            .ignore_observer("edges")
+            .is_divergence(&report));
+        assert!(!feedback
+            .clone()
+            .require_exit_kind_mismatch()
+            .is_divergence(&report));
+
+        report.diverging_observers.clear();
+        report.stdout_diff = StdoutDiff::between(b"ok\n", b"ok\r\n");
+        assert_eq!(report.stdout_diff.unwrap().offset, 2);
+        assert!(feedback.is_divergence(&report));
+        assert!(!feedback.clone().ignore_stdout().is_divergence(&report));
+
+        report.secondary = ExitKind::Timeout;
+        assert!(feedback.is_divergence(&report));
+        assert!(!feedback.clone().ignore_timeouts().is_divergence(&report));
+    }
 }
@@ -0,0 +1,457 @@
+//! Time-to-coverage statistics: the [`EdgeDiscoveryFeedback`] records when, by whom and from which
+//! parent each map entry was first discovered, into the [`EdgeDiscoveryMetadata`] of the state.
+//!
+//! The feedback broadcasts the discoveries of its client; add the [`EdgeDiscoveryHook`] to the
+//! event manager of each client to merge them into the campaign-wide table.
+
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::{fmt::Write, marker::PhantomData, time::Duration};
+
+use hashbrown::HashMap;
+use libafl_bolts::{current_time, impl_serdeany, ClientId, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId, Testcase},
+    events::{Event, EventFirer, EventManagerHook},
+    executors::ExitKind,
+    feedbacks::{Feedback, MapNoveltiesMetadata},
+    inputs::Input,
+    monitors::{AggregatorOps, UserStats, UserStatsValue},
+    mutators::LogMutationMetadata,
+    observers::ObserversTuple,
+    state::{HasCorpus, HasExecutions, HasStartTime, State},
+    Error, HasMetadata,
+};
+
+/// The tag of the [`Event::CustomBuf`] carrying edge discoveries
+pub const EDGE_DISCOVERY_TAG: &str = "libafl_edge_discovery";
+
+/// The default interval in which the [`EdgeDiscoveryFeedback`] reports to the monitor
+pub const DEFAULT_DISCOVERY_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The first discovery of a map entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EdgeDiscovery {
+    /// The time since the start of the campaign
+    pub time: Duration,
+    /// The executions of the discovering client at discovery
+    pub executions: u64,
+    /// The discovering client, see [`EdgeDiscoveryFeedback::with_client_id`]
+    pub client: Option<u32>,
+    /// The testcase mutated to discover the entry
+    pub parent: Option<CorpusId>,
+    /// The id of the discovering testcase in the corpus
+    pub corpus_id: Option<CorpusId>,
+}
+
+/// The first discoveries of all map entries, stored in the state by the [`EdgeDiscoveryFeedback`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct EdgeDiscoveryMetadata {
+    discoveries: HashMap<usize, EdgeDiscovery>,
+}
+
+impl_serdeany!(EdgeDiscoveryMetadata);
+
+impl EdgeDiscoveryMetadata {
+    /// The first discovery of the map entry, if discovered
+    #[must_use]
+    pub fn get(&self, edge: usize) -> Option<&EdgeDiscovery> {
+        self.discoveries.get(&edge)
+    }
+
+    /// All discovered map entries and their first discoveries, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &EdgeDiscovery)> {
+        self.discoveries
+            .iter()
+            .map(|(edge, discovery)| (*edge, discovery))
+    }
+
+    /// The number of discovered map entries
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.discoveries.len()
+    }
+
+    /// If no map entry was discovered yet
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.discoveries.is_empty()
+    }
+
+    /// Records the discovery of the map entry, keeping the earlier one if already discovered.
+    /// Returns if the entry was not discovered before.
+    pub fn record(&mut self, edge: usize, discovery: EdgeDiscovery) -> bool {
+        if let Some(existing) = self.discoveries.get_mut(&edge) {
+            if discovery.time < existing.time {
+                *existing = discovery;
+            }
+            false
+        } else {
+            self.discoveries.insert(edge, discovery);
+            true
+        }
+    }
+
+    /// Merges the discoveries of another client, keeping the earliest discovery of each entry.
+    ///
+    /// The times must be relative to the same campaign start, e.g. for clients of the same launcher.
+    pub fn merge(&mut self, other: &Self) {
+        for (edge, discovery) in &other.discoveries {
+            self.record(*edge, *discovery);
+        }
+    }
+
+    /// The number of map entries discovered at or after `since`, relative to the campaign start
+    #[must_use]
+    pub fn found_since(&self, since: Duration) -> usize {
+        self.discoveries
+            .values()
+            .filter(|discovery| discovery.time >= since)
+            .count()
+    }
+
+    /// The number of map entries discovered in the `window` before `now`, relative to the campaign
+    /// start, e.g. the edges found in the last hour
+    #[must_use]
+    pub fn found_in_last(&self, now: Duration, window: Duration) -> usize {
+        self.found_since(now.saturating_sub(window))
+    }
+
+    /// The coverage velocity in map entries per hour, over the `window` before `now`
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn velocity(&self, now: Duration, window: Duration) -> f64 {
+        let window = window.min(now);
+        if window.is_zero() {
+            return 0.0;
+        }
+        self.found_in_last(now, window) as f64 * 3600.0 / window.as_secs_f64()
+    }
+
+    /// The mutations that discovered the map entry, if the discovering testcase is still in the
+    /// `corpus` and a `LoggerScheduledMutator` logged them
+    pub fn mutations<C>(&self, corpus: &C, edge: usize) -> Option<Vec<Cow<'static, str>>>
+    where
+        C: Corpus,
+    {
+        let corpus_id = self.get(edge)?.corpus_id?;
+        let testcase = corpus.get_from_all(corpus_id).ok()?.borrow();
+        testcase
+            .metadata_map()
+            .get::<LogMutationMetadata>()
+            .map(|log| log.list.clone())
+    }
+
+    /// Exports the discoveries as CSV, sorted by time
+    #[must_use]
+    pub fn to_csv(&self) -> String {
+        let mut discoveries: Vec<_> = self.iter().collect();
+        discoveries.sort_by_key(|(edge, discovery)| (discovery.time, *edge));
+
+        let mut csv = String::from("edge,time_ms,executions,client,parent,corpus_id\n");
+        let opt = |value: Option<usize>| value.map(|value| format!("{value}")).unwrap_or_default();
+        for (edge, discovery) in discoveries {
+            let _ = writeln!(
+                csv,
+                "{edge},{},{},{},{},{}",
+                discovery.time.as_millis(),
+                discovery.executions,
+                opt(discovery.client.map(|client| client as usize)),
+                opt(discovery.parent.map(usize::from)),
+                opt(discovery.corpus_id.map(usize::from)),
+            );
+        }
+        csv
+    }
+
+    /// Writes the discoveries to a CSV file, see [`EdgeDiscoveryMetadata::to_csv`]
+    #[cfg(feature = "std")]
+    pub fn write_csv<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        std::fs::write(path, self.to_csv())?;
+        Ok(())
+    }
+}
+
+/// Nop feedback recording the first discovery of each map entry into the [`EdgeDiscoveryMetadata`]
+/// of the state. For this Feedback, the testcase is never interesting (use with an OR).
+///
+/// It relies on the [`MapNoveltiesMetadata`] of new testcases, so it must come after a map feedback
+/// tracking novelties. The entries new to the table are broadcast to the other clients, whose
+/// [`EdgeDiscoveryHook`] merges them, so that each client knows the campaign-wide first
+/// discoveries. Periodically, it reports the edges found in the last hour and the coverage
+/// velocity of the whole campaign to the monitor as user stats; the monitor shows the maximum
+/// over all clients, the one with the most recent table.
+#[derive(Debug, Clone)]
+pub struct EdgeDiscoveryFeedback<S> {
+    name: Cow<'static, str>,
+    client: Option<u32>,
+    report_interval: Duration,
+    last_report: Option<Duration>,
+    phantom: PhantomData<S>,
+}
+
+impl<S> EdgeDiscoveryFeedback<S> {
+    /// Creates a new [`EdgeDiscoveryFeedback`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed("EdgeDiscoveryFeedback"),
+            client: None,
+            report_interval: DEFAULT_DISCOVERY_REPORT_INTERVAL,
+            last_report: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the id of this client recorded with each discovery, e.g. its core id
+    #[must_use]
+    pub fn with_client_id(mut self, client: u32) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Sets the interval in which the statistics are reported to the monitor
+    #[must_use]
+    pub fn with_report_interval(mut self, report_interval: Duration) -> Self {
+        self.report_interval = report_interval;
+        self
+    }
+}
+
+impl<S> Default for EdgeDiscoveryFeedback<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Named for EdgeDiscoveryFeedback<S> {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> Feedback<S> for EdgeDiscoveryFeedback<S>
+where
+    S: State + HasMetadata + HasStartTime + HasExecutions + HasCorpus,
+{
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &S::Input,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let now = current_time();
+        if self
+            .last_report
+            .is_some_and(|last_report| now < last_report + self.report_interval)
+        {
+            return Ok(false);
+        }
+        self.last_report = Some(now);
+
+        let Ok(meta) = state.metadata::<EdgeDiscoveryMetadata>() else {
+            return Ok(false);
+        };
+        let elapsed = now.saturating_sub(*state.start_time());
+        let hour = Duration::from_secs(3600);
+        let last_hour = meta.found_in_last(elapsed, hour) as u64;
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let velocity = meta.velocity(elapsed, hour) as u64;
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: Cow::from("edges_last_hour"),
+                value: UserStats::new(UserStatsValue::Number(last_hour), AggregatorOps::Max),
+                phantom: PhantomData,
+            },
+        )?;
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: Cow::from("edges_per_hour"),
+                value: UserStats::new(UserStatsValue::Number(velocity), AggregatorOps::Max),
+                phantom: PhantomData,
+            },
+        )?;
+        Ok(false)
+    }
+
+    /// Records the novelties of the new testcase, and broadcasts the ones new to the table
+    fn append_metadata<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        let Some(novelties) = testcase.metadata_map().get::<MapNoveltiesMetadata>() else {
+            return Ok(());
+        };
+        let discovery = EdgeDiscovery {
+            time: current_time().saturating_sub(*state.start_time()),
+            executions: *state.executions(),
+            client: self.client,
+            parent: *state.corpus().current(),
+            corpus_id: Some(state.corpus().peek_free_id()),
+        };
+        let meta = state.metadata_or_insert_with(EdgeDiscoveryMetadata::default);
+        let discoveries: Vec<_> = novelties
+            .iter()
+            .filter(|edge| meta.record(**edge, discovery))
+            .map(|edge| (*edge, discovery))
+            .collect();
+        if !discoveries.is_empty() {
+            manager.fire(state, EdgeDiscoveryHook::event(&discoveries)?)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn discard_metadata(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        Ok(())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
+
+/// Merges the edge discoveries broadcast by other clients into the [`EdgeDiscoveryMetadata`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EdgeDiscoveryHook;
+
+impl EdgeDiscoveryHook {
+    /// Creates a new [`EdgeDiscoveryHook`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Serializes edge discoveries into the buffer of an [`Event::CustomBuf`]
+    pub fn event<I>(discoveries: &[(usize, EdgeDiscovery)]) -> Result<Event<I>, Error>
+    where
+        I: Input,
+    {
+        Ok(Event::CustomBuf {
+            buf: postcard::to_allocvec(discoveries)?,
+            tag: String::from(EDGE_DISCOVERY_TAG),
+        })
+    }
+}
+
+impl<S> EventManagerHook<S> for EdgeDiscoveryHook
+where
+    S: State + HasMetadata,
+{
+    fn pre_exec(
+        &mut self,
+        state: &mut S,
+        _client_id: ClientId,
+        event: &Event<S::Input>,
+    ) -> Result<bool, Error> {
+        let Event::CustomBuf { buf, tag } = event else {
+            return Ok(true);
+        };
+        if tag != EDGE_DISCOVERY_TAG {
+            return Ok(true);
+        }
+        let discoveries: Vec<(usize, EdgeDiscovery)> = postcard::from_bytes(buf)?;
+        let meta = state.metadata_or_insert_with(EdgeDiscoveryMetadata::default);
+        for (edge, discovery) in discoveries {
+            meta.record(edge, discovery);
+        }
+        // Handled, no need to pass it on to the custom buf handlers
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec};
+    use core::time::Duration;
+
+    use libafl_bolts::ClientId;
+
+    use super::{EdgeDiscovery, EdgeDiscoveryHook, EdgeDiscoveryMetadata};
+    use crate::{
+        events::{Event, EventManagerHook},
+        inputs::BytesInput,
+        state::test::test_std_state,
+        HasMetadata,
+    };
+
+    fn discovery(secs: u64, client: u32) -> EdgeDiscovery {
+        EdgeDiscovery {
+            time: Duration::from_secs(secs),
+            executions: secs * 100,
+            client: Some(client),
+            parent: None,
+            corpus_id: None,
+        }
+    }
+
+    #[test]
+    fn test_edge_discovery_merge() {
+        let mut first = EdgeDiscoveryMetadata::default();
+        assert!(first.record(1, discovery(10, 0)));
+        assert!(first.record(2, discovery(4000, 0)));
+        assert!(!first.record(1, discovery(20, 0)));
+
+        let mut second = EdgeDiscoveryMetadata::default();
+        second.record(2, discovery(3000, 1));
+        second.record(3, discovery(7000, 1));
+        first.merge(&second);
+
+        assert_eq!(first.len(), 3);
+        assert_eq!(first.get(1).unwrap().time, Duration::from_secs(10));
+        assert_eq!(first.get(2).unwrap().client, Some(1));
+
+        let now = Duration::from_secs(7200);
+        assert_eq!(first.found_in_last(now, Duration::from_secs(3600)), 1);
+        assert_eq!(first.found_since(Duration::from_secs(3000)), 2);
+        assert!((first.velocity(now, Duration::from_secs(7200)) - 1.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_edge_discovery_hook() {
+        let mut state = test_std_state::<BytesInput>();
+        let mut hook = EdgeDiscoveryHook::new();
+        state
+            .metadata_or_insert_with(EdgeDiscoveryMetadata::default)
+            .record(1, discovery(20, 0));
+
+        let event =
+            EdgeDiscoveryHook::event(&[(1, discovery(10, 1)), (2, discovery(30, 1))]).unwrap();
+        assert!(!hook.pre_exec(&mut state, ClientId(1), &event).unwrap());
+        let meta = state.metadata::<EdgeDiscoveryMetadata>().unwrap();
+        assert_eq!(meta.len(), 2);
+        assert_eq!(meta.get(1).unwrap().client, Some(1));
+
+        let other = Event::CustomBuf {
+            buf: vec![],
+            tag: String::from("other"),
+        };
+        assert!(hook.pre_exec(&mut state, ClientId(1), &other).unwrap());
+    }
+}
@@ -0,0 +1,402 @@
+//! Feedback replacing corpus entries with shorter (or faster) inputs reaching the same coverage
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{marker::PhantomData, time::Duration};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    tuples::{Handle, Handled, MatchNameRef},
+    HasLen, Named,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+use crate::{
+    corpus::{Corpus, CorpusId, Testcase},
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle},
+    inputs::UsesInput,
+    observers::{MapObserver, ObserversTuple, TimeObserver},
+    state::{HasCorpus, State},
+    Error, HasMetadata,
+};
+
+/// The default minimal gain (in percent) for an input to replace an existing corpus entry
+pub const DEFAULT_MIN_GAIN_PERCENT: u64 = 10;
+
+/// The coverage signature of a testcase, added by the [`LengthPreferenceFeedback`]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoverageSignatureMetadata {
+    /// The hash of the coverage map
+    pub signature: u64,
+    /// The length of the input
+    pub len: usize,
+    /// The execution time of the input, if known
+    pub exec_time: Option<Duration>,
+}
+
+libafl_bolts::impl_serdeany!(CoverageSignatureMetadata);
+
+/// A better input found for a corpus entry, waiting for the
+/// [`crate::stages::LengthPreferenceStage`] to replace the entry
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct PendingReplacement {
+    /// The serialized input
+    pub(crate) input: Vec<u8>,
+    /// The coverage signature of the input
+    pub(crate) signature: CoverageSignatureMetadata,
+}
+
+/// The state of the [`LengthPreferenceFeedback`]: the corpus entry for each known coverage signature
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+#[allow(clippy::unsafe_derive_deserialize)]
+pub struct LengthPreferenceMetadata {
+    pub(crate) signatures: HashMap<u64, CorpusId>,
+    last_indexed: Option<CorpusId>,
+    pub(crate) pending: HashMap<CorpusId, PendingReplacement>,
+    pub(crate) replaced: u64,
+}
+
+libafl_bolts::impl_serdeany!(LengthPreferenceMetadata);
+
+impl LengthPreferenceMetadata {
+    /// The corpus entry with the given coverage signature, if any
+    #[must_use]
+    pub fn get(&self, signature: u64) -> Option<CorpusId> {
+        self.signatures.get(&signature).copied()
+    }
+
+    /// The number of corpus entries replaced so far
+    #[must_use]
+    pub fn replaced(&self) -> u64 {
+        self.replaced
+    }
+
+    /// The number of corpus entries waiting to be replaced
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// A [`LengthPreferenceFeedback`] keeps the corpus lean: if an input reaches the exact same coverage
+/// (the same [`MapObserver::hash_simple`]) as an existing corpus entry, but is meaningfully shorter,
+/// or (if a [`TimeObserver`] is given) not longer but meaningfully faster, it replaces the existing
+/// entry in place, once the input was discarded by the other feedbacks. The metadata of the
+/// replaced entry is kept.
+///
+/// The feedback only records the better inputs; the replacement itself is done by the
+/// [`crate::stages::LengthPreferenceStage`], which also informs the scheduler.
+///
+/// This feedback never reports inputs as interesting; combine it with the coverage feedback, e.g.
+/// `feedback_or!(MaxMapFeedback::new(&edges_observer), LengthPreferenceFeedback::new(&edges_observer))`.
+/// Only corpus entries added while this feedback is in use carry a coverage signature and can be replaced.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LengthPreferenceFeedback<O> {
+    name: Cow<'static, str>,
+    map_ref: Handle<O>,
+    time_ref: Option<Handle<TimeObserver>>,
+    min_gain_percent: u64,
+    /// The signature of the last execution
+    last_signature: Option<CoverageSignatureMetadata>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    // The previous run's result of `Self::is_interesting`
+    last_result: Option<bool>,
+    phantom: PhantomData<O>,
+}
+
+impl<O> LengthPreferenceFeedback<O>
+where
+    O: Named,
+{
+    /// Creates a new [`LengthPreferenceFeedback`] comparing the coverage of the given map observer
+    #[must_use]
+    pub fn new(map_observer: &O) -> Self {
+        Self {
+            name: Cow::from("LengthPreferenceFeedback"),
+            map_ref: map_observer.handle(),
+            time_ref: None,
+            min_gain_percent: DEFAULT_MIN_GAIN_PERCENT,
+            last_signature: None,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Also replace entries with inputs that are faster, as measured by the given [`TimeObserver`]
+    #[must_use]
+    pub fn with_time_observer(mut self, time_observer: &TimeObserver) -> Self {
+        self.time_ref = Some(time_observer.handle());
+        self
+    }
+
+    /// Sets how much shorter (or faster), in percent, an input has to be to replace an entry
+    #[must_use]
+    pub fn with_min_gain_percent(mut self, min_gain_percent: u64) -> Self {
+        self.min_gain_percent = min_gain_percent.min(100);
+        self
+    }
+
+    /// Returns `true` if `new` is meaningfully better than `old`
+    fn is_better(&self, new: &CoverageSignatureMetadata, old: &CoverageSignatureMetadata) -> bool {
+        let keep = 100 - self.min_gain_percent;
+        let shorter = (new.len as u64) * 100 <= (old.len as u64) * keep && new.len < old.len;
+        let faster = match (new.exec_time, old.exec_time) {
+            (Some(new_time), Some(old_time)) => {
+                new.len <= old.len
+                    && new_time.as_nanos() * 100 <= old_time.as_nanos() * u128::from(keep)
+            }
+            _ => false,
+        };
+        shorter || faster
+    }
+
+    /// Indexes the signatures of all corpus entries added since the last call
+    fn index_corpus<S>(state: &mut S) -> Result<(), Error>
+    where
+        S: HasCorpus + HasMetadata,
+    {
+        let last_indexed = state
+            .metadata_or_insert_with(LengthPreferenceMetadata::default)
+            .last_indexed;
+        let mut next = match last_indexed {
+            Some(id) => state.corpus().next(id),
+            None => state.corpus().first(),
+        };
+        let mut indexed = Vec::new();
+        let mut last = last_indexed;
+        while let Some(id) = next {
+            let testcase = state.corpus().get(id)?.borrow();
+            if let Ok(meta) = testcase.metadata::<CoverageSignatureMetadata>() {
+                indexed.push((meta.signature, id));
+            }
+            last = Some(id);
+            next = state.corpus().next(id);
+        }
+
+        let meta = state.metadata_mut::<LengthPreferenceMetadata>()?;
+        meta.last_indexed = last;
+        for (signature, id) in indexed {
+            meta.signatures.entry(signature).or_insert(id);
+        }
+        Ok(())
+    }
+}
+
+impl<O, S> Feedback<S> for LengthPreferenceFeedback<O>
+where
+    O: MapObserver,
+    S: State + HasCorpus + HasMetadata,
+    S::Input: HasLen,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.metadata_or_insert_with(LengthPreferenceMetadata::default);
+        Ok(())
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &<S as UsesInput>::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(false);
+        }
+        self.last_signature = None;
+        if *exit_kind != ExitKind::Ok {
+            return Ok(false);
+        }
+
+        let observer = observers.get(&self.map_ref).ok_or_else(|| {
+            Error::key_not_found(format!("Map observer {} not found", self.map_ref.name()))
+        })?;
+        let exec_time = self
+            .time_ref
+            .as_ref()
+            .and_then(|time_ref| observers.get(time_ref))
+            .and_then(|time_observer| *time_observer.last_runtime());
+        self.last_signature = Some(CoverageSignatureMetadata {
+            signature: observer.hash_simple(),
+            len: input.len(),
+            exec_time,
+        });
+        Ok(false)
+    }
+
+    fn append_metadata<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        if let Some(signature) = self.last_signature.take() {
+            testcase.add_metadata(signature);
+        }
+        Ok(())
+    }
+
+    /// Records the discarded input to replace the corpus entry with the same coverage, if better
+    fn discard_metadata(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        let Some(signature) = self.last_signature.take() else {
+            return Ok(());
+        };
+
+        Self::index_corpus(state)?;
+        let Some(id) = state
+            .metadata::<LengthPreferenceMetadata>()?
+            .get(signature.signature)
+        else {
+            return Ok(());
+        };
+
+        let old = if let Ok(testcase) = state.corpus().get(id) {
+            *testcase.borrow().metadata::<CoverageSignatureMetadata>()?
+        } else {
+            // The entry was removed in the meantime
+            state
+                .metadata_mut::<LengthPreferenceMetadata>()?
+                .signatures
+                .remove(&signature.signature);
+            return Ok(());
+        };
+        if !self.is_better(&signature, &old) {
+            return Ok(());
+        }
+        let meta = state.metadata_mut::<LengthPreferenceMetadata>()?;
+        if let Some(pending) = meta.pending.get(&id) {
+            if !self.is_better(&signature, &pending.signature) {
+                return Ok(());
+            }
+        }
+
+        log::debug!(
+            "Found an input of {} bytes to replace corpus entry {id} ({} bytes) with the same coverage",
+            signature.len,
+            old.len
+        );
+        meta.pending.insert(
+            id,
+            PendingReplacement {
+                input: postcard::to_allocvec(input)?,
+                signature,
+            },
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+}
+
+impl<O> Named for LengthPreferenceFeedback<O> {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<O> HasObserverHandle for LengthPreferenceFeedback<O> {
+    type Observer = O;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<O> {
+        &self.map_ref
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use libafl_bolts::{tuples::tuple_list, HasLen};
+
+    use super::{CoverageSignatureMetadata, LengthPreferenceFeedback, LengthPreferenceMetadata};
+    use crate::{
+        corpus::{Corpus, Testcase},
+        events::NopEventManager,
+        executors::ExitKind,
+        feedbacks::Feedback,
+        inputs::BytesInput,
+        observers::{MapObserver, StdMapObserver},
+        state::{test::test_std_state, HasCorpus},
+        HasMetadata,
+    };
+
+    #[test]
+    fn test_length_preference_is_better() {
+        let mut map = [0_u8; 4];
+        let observer = unsafe { StdMapObserver::new("map", &mut map) };
+        let feedback = LengthPreferenceFeedback::new(&observer);
+        let old = CoverageSignatureMetadata {
+            signature: 0,
+            len: 100,
+            exec_time: Some(Duration::from_millis(10)),
+        };
+        let mut new = CoverageSignatureMetadata {
+            signature: 0,
+            len: 95,
+            exec_time: None,
+        };
+        assert!(!feedback.is_better(&new, &old));
+        new.len = 90;
+        assert!(feedback.is_better(&new, &old));
+        new.len = 100;
+        new.exec_time = Some(Duration::from_millis(5));
+        assert!(feedback.is_better(&new, &old));
+        new.len = 101;
+        assert!(!feedback.is_better(&new, &old));
+    }
+
+    #[test]
+    fn test_length_preference_records_discarded() {
+        let observer = StdMapObserver::owned("map", vec![1_u8, 0, 0, 0]);
+        let mut feedback = LengthPreferenceFeedback::new(&observer);
+        let mut state = test_std_state::<BytesInput>();
+        let mut testcase = Testcase::new(BytesInput::new(vec![0; 100]));
+        testcase.add_metadata(CoverageSignatureMetadata {
+            signature: observer.hash_simple(),
+            len: 100,
+            exec_time: None,
+        });
+        let id = state.corpus_mut().add(testcase).unwrap();
+        feedback.init_state(&mut state).unwrap();
+        let mut manager = NopEventManager::new();
+        let observers = tuple_list!(observer);
+        let input = BytesInput::new(vec![1; 10]);
+
+        assert!(!feedback
+            .is_interesting(&mut state, &mut manager, &input, &observers, &ExitKind::Ok)
+            .unwrap());
+        assert_eq!(state.corpus().cloned_input_for_id(id).unwrap().len(), 100);
+
+        // The replacement is left to the stage
+        feedback.discard_metadata(&mut state, &input).unwrap();
+        assert_eq!(state.corpus().cloned_input_for_id(id).unwrap().len(), 100);
+        assert_eq!(
+            state
+                .metadata::<LengthPreferenceMetadata>()
+                .unwrap()
+                .pending(),
+            1
+        );
+    }
+}
@@ -14,7 +14,11 @@ use core::{
 
 #[cfg(feature = "std")]
 pub use concolic::ConcolicFeedback;
-pub use differential::DiffFeedback;
+#[cfg(all(feature = "std", unix))]
+pub use core_dump::CoreDumpFeedback;
+pub use differential::{DiffFeedback, DiffReportFeedback};
+pub use discovery::*;
+pub use length_preference::LengthPreferenceFeedback;
 use libafl_bolts::{
     tuples::{Handle, Handled, MatchNameRef},
     Named,
@@ -23,10 +27,16 @@ pub use list::*;
 pub use map::*;
 #[cfg(feature = "nautilus")]
 pub use nautilus::*;
+pub use network::{NetworkTrafficFeedback, NetworkTrafficMetadata};
 #[cfg(feature = "std")]
 pub use new_hash_feedback::NewHashFeedback;
 #[cfg(feature = "std")]
 pub use new_hash_feedback::NewHashFeedbackMetadata;
+pub use replay::MutationReplayFeedback;
+#[cfg(feature = "std")]
+pub use reproducer::ReproducerFeedback;
+#[cfg(feature = "regex")]
+pub use sanitizer_report::SanitizerReportFeedback;
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -39,17 +49,27 @@ use crate::{
 };
 #[cfg(feature = "std")]
 pub mod concolic;
+#[cfg(all(feature = "std", unix))]
+pub mod core_dump;
 #[cfg(feature = "std")]
 /// The module for `CustomFilenameToTestcaseFeedback`
 pub mod custom_filename;
 pub mod differential;
+pub mod discovery;
+pub mod length_preference;
 /// The module for list feedback
 pub mod list;
 pub mod map;
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
+pub mod network;
 #[cfg(feature = "std")]
 pub mod new_hash_feedback;
+pub mod replay;
+#[cfg(feature = "std")]
+pub mod reproducer;
+#[cfg(feature = "regex")]
+pub mod sanitizer_report;
 #[cfg(feature = "std")]
 pub mod stdio;
 pub mod transferred;
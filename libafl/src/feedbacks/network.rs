@@ -0,0 +1,110 @@
+//! Feedback and metadata storing the traffic captured by a [`NetworkObserver`] in the testcase.
+
+use alloc::{borrow::Cow, vec::Vec};
+
+use libafl_bolts::{
+    impl_serdeany,
+    tuples::{Handle, Handled, MatchNameRef},
+    Named,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    observers::{NetworkObserver, ObserversTuple, TrafficChunk},
+    state::State,
+    Error, HasMetadata,
+};
+
+/// Metadata for [`NetworkTrafficFeedback`]: the traffic of the execution adding the testcase
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkTrafficMetadata {
+    /// The captured chunks, in order
+    pub chunks: Vec<TrafficChunk>,
+    /// If traffic was dropped, exceeding the maximal size of the observer
+    pub truncated: bool,
+}
+
+impl_serdeany!(NetworkTrafficMetadata);
+
+/// Nop feedback that annotates the captured network traffic in the new testcase. The testcase
+/// is never interesting (use with an OR); combine it with a `NewHashFeedback` on the
+/// [`NetworkObserver`] to find new responses.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NetworkTrafficFeedback {
+    o_ref: Handle<NetworkObserver>,
+}
+
+impl<S> Feedback<S> for NetworkTrafficFeedback
+where
+    S: State,
+{
+    #[allow(clippy::wrong_self_convention)]
+    #[inline]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &S::Input,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    /// Append to the testcase the captured traffic in case of a new corpus item.
+    #[inline]
+    fn append_metadata<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        let observer = observers
+            .get(&self.o_ref)
+            .ok_or(Error::illegal_state("NetworkObserver is missing"))?;
+        testcase.metadata_map_mut().insert(NetworkTrafficMetadata {
+            chunks: observer.chunks().to_vec(),
+            truncated: observer.is_truncated(),
+        });
+        Ok(())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
+
+impl Named for NetworkTrafficFeedback {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        self.o_ref.name()
+    }
+}
+
+impl NetworkTrafficFeedback {
+    /// Creates a new [`NetworkTrafficFeedback`].
+    #[must_use]
+    pub fn new(observer: &NetworkObserver) -> Self {
+        Self {
+            o_ref: observer.handle(),
+        }
+    }
+}
@@ -5,6 +5,7 @@ use std::{fmt::Debug, marker::PhantomData};
 
 use hashbrown::HashSet;
 use libafl_bolts::{
+    serdeany::VersionedSerdeAny,
     tuples::{Handle, Handled, MatchNameRef},
     Named,
 };
@@ -41,8 +42,11 @@ pub struct NewHashFeedbackMetadata {
     pub hash_set: HashSet<u64>,
 }
 
-#[rustfmt::skip]
-libafl_bolts::impl_serdeany!(NewHashFeedbackMetadata);
+libafl_bolts::impl_serdeany_versioned!(NewHashFeedbackMetadata);
+
+impl VersionedSerdeAny for NewHashFeedbackMetadata {
+    const SCHEMA_VERSION: u32 = 1;
+}
 
 impl NewHashFeedbackMetadata {
     /// Create a new [`NewHashFeedbackMetadata`]
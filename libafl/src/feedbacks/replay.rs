@@ -0,0 +1,77 @@
+//! The [`MutationReplayFeedback`] adds the record of the mutation that produced a testcase to it,
+//! e.g. to solutions, see [`crate::mutators::ReplayLogMutator`].
+
+use alloc::borrow::Cow;
+
+use libafl_bolts::Named;
+
+use crate::{
+    corpus::Testcase, events::EventFirer, executors::ExitKind, feedbacks::Feedback,
+    mutators::MutationReplayMetadata, observers::ObserversTuple, state::State, Error, HasMetadata,
+};
+
+/// Adds the [`MutationReplayMetadata`] of the mutated input being evaluated, recorded in the
+/// state by the [`crate::mutators::ReplayLogMutator`], to new testcases.
+///
+/// This feedback never finds a run interesting by itself, combine it with an `or`, e.g.
+/// `feedback_or!(CrashFeedback::new(), MutationReplayFeedback::new())` as objective.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MutationReplayFeedback;
+
+impl MutationReplayFeedback {
+    /// Creates a new [`MutationReplayFeedback`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for MutationReplayFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("MutationReplayFeedback");
+        &NAME
+    }
+}
+
+impl<S> Feedback<S> for MutationReplayFeedback
+where
+    S: State + HasMetadata,
+{
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &S::Input,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        if let Ok(record) = state.metadata::<MutationReplayMetadata>() {
+            testcase.add_metadata(record.clone());
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
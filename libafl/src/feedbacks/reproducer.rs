@@ -0,0 +1,328 @@
+//! The [`ReproducerFeedback`] writes a self-contained reproduction bundle for each objective.
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write as _;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use libafl_bolts::Named;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::Testcase, events::EventFirer, executors::ExitKind, feedbacks::Feedback, inputs::Input,
+    observers::ObserversTuple, state::State, Error, HasMetadata,
+};
+
+/// The placeholder for the input file in the reproducer command line, as in `AFL`
+pub const INPUT_PLACEHOLDER: &str = "@@";
+
+/// The environment variables copied into the reproducer by default
+pub const DEFAULT_REPRODUCER_ENV_VARS: &[&str] = &[
+    "ASAN_OPTIONS",
+    "UBSAN_OPTIONS",
+    "MSAN_OPTIONS",
+    "LSAN_OPTIONS",
+    "TSAN_OPTIONS",
+    "LD_PRELOAD",
+    "LD_LIBRARY_PATH",
+];
+
+/// The name of the input file in a reproducer bundle
+const BUNDLE_INPUT_FILE: &str = "input";
+/// The name of the replay script in a reproducer bundle
+const BUNDLE_SCRIPT_FILE: &str = "reproduce.sh";
+/// The name of the harness configuration in a reproducer bundle
+const BUNDLE_CONFIG_FILE: &str = "reproducer.json";
+
+/// The harness configuration stored in a reproducer bundle
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReproducerConfig {
+    /// The command line, with [`INPUT_PLACEHOLDER`] replaced by the input file.
+    /// Without a placeholder, the input is passed on stdin.
+    pub command: Vec<String>,
+    /// The environment of the command
+    pub env: Vec<(String, String)>,
+    /// The working directory of the fuzzer, if known
+    pub cwd: Option<PathBuf>,
+    /// How the execution finished during fuzzing
+    pub exit_kind: Option<ExitKind>,
+}
+
+/// The location of the reproduction bundle of an objective, added by the [`ReproducerFeedback`]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReproducerMetadata {
+    /// The directory of the bundle
+    pub path: PathBuf,
+}
+
+libafl_bolts::impl_serdeany!(ReproducerMetadata);
+
+/// A [`ReproducerFeedback`] wraps an objective feedback. For each objective, it writes a
+/// self-contained reproduction bundle to `<dir>/<testcase name>.repro/`, containing the input,
+/// the harness configuration with the relevant environment variables (`reproducer.json`),
+/// and a shell script replaying it (`reproduce.sh`).
+///
+/// Use the solutions directory of the `OnDiskCorpus` as `dir`, to keep the bundles next to the objectives.
+#[derive(Debug, Clone)]
+pub struct ReproducerFeedback<A> {
+    inner: A,
+    name: Cow<'static, str>,
+    dir: PathBuf,
+    command: Vec<String>,
+    env: Vec<(String, String)>,
+    last_exit_kind: Option<ExitKind>,
+}
+
+impl<A> ReproducerFeedback<A>
+where
+    A: Named,
+{
+    /// Creates a new [`ReproducerFeedback`] writing bundles to `dir`.
+    ///
+    /// The command defaults to the current executable with the input file as the only argument,
+    /// as accepted by `libFuzzer`-style harnesses; the values of [`DEFAULT_REPRODUCER_ENV_VARS`]
+    /// are taken from the current environment.
+    pub fn new<P>(inner: A, dir: P) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let command = vec![
+            env::current_exe()?.to_string_lossy().into_owned(),
+            INPUT_PLACEHOLDER.to_string(),
+        ];
+        let env = DEFAULT_REPRODUCER_ENV_VARS
+            .iter()
+            .filter_map(|key| env::var(key).ok().map(|value| ((*key).to_string(), value)))
+            .collect();
+        Ok(Self {
+            name: Cow::from(format!("Reproducer({})", inner.name())),
+            inner,
+            dir,
+            command,
+            env,
+            last_exit_kind: None,
+        })
+    }
+
+    /// Sets the command line replaying an input. [`INPUT_PLACEHOLDER`] is replaced by the input file;
+    /// without a placeholder, the input is passed on stdin.
+    #[must_use]
+    pub fn with_command<I, T>(mut self, command: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        self.command = command.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Adds an environment variable to the reproducer, overwriting a previous value
+    #[must_use]
+    pub fn with_env<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let key = key.into();
+        self.env.retain(|(k, _)| *k != key);
+        self.env.push((key, value.into()));
+        self
+    }
+
+    /// The wrapped feedback
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// The harness configuration of the next bundle
+    #[must_use]
+    pub fn config(&self) -> ReproducerConfig {
+        ReproducerConfig {
+            command: self.command.clone(),
+            env: self.env.clone(),
+            cwd: env::current_dir().ok(),
+            exit_kind: self.last_exit_kind,
+        }
+    }
+
+    /// Writes a reproduction bundle for the given input to `bundle_dir`
+    fn write_bundle<I>(&self, bundle_dir: &Path, input: &I) -> Result<(), Error>
+    where
+        I: Input,
+    {
+        fs::create_dir_all(bundle_dir)?;
+        input.to_file(bundle_dir.join(BUNDLE_INPUT_FILE))?;
+
+        let config = self.config();
+        fs::write(
+            bundle_dir.join(BUNDLE_CONFIG_FILE),
+            serde_json::to_string_pretty(&config)?,
+        )?;
+
+        let script_path = bundle_dir.join(BUNDLE_SCRIPT_FILE);
+        fs::write(&script_path, replay_script(&config))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&script_path, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+}
+
+/// Quotes a string for a POSIX shell
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+/// Generates the shell script replaying the bundled input with the given configuration
+#[must_use]
+pub fn replay_script(config: &ReproducerConfig) -> String {
+    let mut script = String::from("#!/bin/sh\n# Reproducer generated by LibAFL\n");
+    if let Some(exit_kind) = config.exit_kind {
+        let _ = writeln!(script, "# Exit kind during fuzzing: {exit_kind:?}");
+    }
+    script.push_str("INPUT=\"$(cd \"$(dirname \"$0\")\" && pwd)/input\"\n");
+    if let Some(cwd) = &config.cwd {
+        let _ = writeln!(script, "cd {}", shell_quote(&cwd.to_string_lossy()));
+    }
+    for (key, value) in &config.env {
+        let _ = writeln!(script, "export {key}={}", shell_quote(value));
+    }
+
+    script.push_str("exec");
+    let mut has_placeholder = false;
+    for arg in &config.command {
+        script.push(' ');
+        if arg.contains(INPUT_PLACEHOLDER) {
+            has_placeholder = true;
+            let _ = write!(
+                script,
+                "\"{}\"",
+                arg.replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('$', "\\$")
+                    .replace('`', "\\`")
+                    .replace(INPUT_PLACEHOLDER, "$INPUT")
+            );
+        } else {
+            script.push_str(&shell_quote(arg));
+        }
+    }
+    if !has_placeholder {
+        script.push_str(" < \"$INPUT\"");
+    }
+    script.push_str(" \"$@\"\n");
+    script
+}
+
+impl<A> Named for ReproducerFeedback<A> {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<A, S> Feedback<S> for ReproducerFeedback<A>
+where
+    A: Feedback<S>,
+    S: State,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.init_state(state)
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &S::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.last_exit_kind = Some(*exit_kind);
+        self.inner
+            .is_interesting(state, manager, input, observers, exit_kind)
+    }
+
+    fn append_metadata<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        self.inner
+            .append_metadata(state, manager, observers, testcase)?;
+
+        let Some(input) = testcase.input().as_ref() else {
+            return Ok(());
+        };
+        let name = testcase
+            .filename()
+            .clone()
+            .unwrap_or_else(|| input.generate_name(None));
+        let bundle_dir = self.dir.join(format!("{name}.repro"));
+        if let Err(err) = self.write_bundle(&bundle_dir, input) {
+            // Losing the bundle is no reason to lose the objective
+            log::error!("Failed to write the reproducer bundle {bundle_dir:?}: {err}");
+            return Ok(());
+        }
+        log::info!("Wrote reproducer bundle to {bundle_dir:?}");
+        testcase.add_metadata(ReproducerMetadata { path: bundle_dir });
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        self.inner.discard_metadata(state, input)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.inner.last_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use super::{replay_script, ReproducerConfig};
+    use crate::executors::ExitKind;
+
+    #[test]
+    fn test_replay_script() {
+        let config = ReproducerConfig {
+            command: vec!["./harness".to_string(), "--file=@@".to_string()],
+            env: vec![("ASAN_OPTIONS".to_string(), "abort_on_error=1".to_string())],
+            cwd: None,
+            exit_kind: Some(ExitKind::Crash),
+        };
+        let script = replay_script(&config);
+        assert!(script.contains("export ASAN_OPTIONS='abort_on_error=1'\n"));
+        assert!(script.ends_with("exec './harness' \"--file=$INPUT\" \"$@\"\n"));
+
+        let config = ReproducerConfig {
+            command: vec!["./it's".to_string()],
+            ..config
+        };
+        assert!(replay_script(&config).ends_with("exec './it'\\''s' < \"$INPUT\" \"$@\"\n"));
+    }
+}
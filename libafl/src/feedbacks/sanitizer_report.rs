@@ -0,0 +1,129 @@
+//! Feedback attaching parsed sanitizer reports to testcases
+
+use alloc::{borrow::Cow, string::ToString};
+use core::marker::PhantomData;
+
+use libafl_bolts::{
+    tuples::{Handle, Handled, MatchNameRef},
+    Named,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+use crate::{
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle},
+    observers::{HasSanitizerReport, ObserversTuple},
+    state::State,
+    Error, HasMetadata,
+};
+
+/// The prefix of the names of [`SanitizerReportFeedback`]s
+pub const SANITIZER_REPORT_FEEDBACK_PREFIX: &str = "sanitizer_report_";
+
+/// A [`SanitizerReportFeedback`] is interesting if the observer captured a sanitizer report,
+/// and attaches the parsed [`crate::observers::SanitizerReport`] to the testcase.
+///
+/// Use it as objective (or together with a `CrashFeedback` in a `feedback_or!`), so that objectives
+/// carry metadata like `heap-buffer-overflow READ 4` instead of just a signal.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SanitizerReportFeedback<O> {
+    name: Cow<'static, str>,
+    o_ref: Handle<O>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    // The previous run's result of `Self::is_interesting`
+    last_result: Option<bool>,
+    phantom: PhantomData<O>,
+}
+
+impl<O, S> Feedback<S> for SanitizerReportFeedback<O>
+where
+    O: HasSanitizerReport + Named,
+    S: State,
+{
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers
+            .get(&self.o_ref)
+            .ok_or_else(|| Error::illegal_state("SanitizerReportFeedback observer is missing"))?;
+        let res = observer.sanitizer_report().is_some();
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    fn append_metadata<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        let observer = observers
+            .get(&self.o_ref)
+            .ok_or_else(|| Error::illegal_state("SanitizerReportFeedback observer is missing"))?;
+        if let Some(report) = observer.sanitizer_report() {
+            log::info!("Sanitizer report: {report}");
+            testcase.add_metadata(report);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+}
+
+impl<O> Named for SanitizerReportFeedback<O> {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<O> HasObserverHandle for SanitizerReportFeedback<O> {
+    type Observer = O;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<O> {
+        &self.o_ref
+    }
+}
+
+impl<O> SanitizerReportFeedback<O>
+where
+    O: HasSanitizerReport + Named,
+{
+    /// Creates a new [`SanitizerReportFeedback`] for the given observer.
+    #[must_use]
+    pub fn new(observer: &O) -> Self {
+        Self {
+            name: Cow::from(SANITIZER_REPORT_FEEDBACK_PREFIX.to_string() + observer.name()),
+            o_ref: observer.handle(),
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+            phantom: PhantomData,
+        }
+    }
+}
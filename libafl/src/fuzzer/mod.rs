@@ -1,31 +1,39 @@
 //! The `Fuzzer` is the main struct for a fuzz campaign.
 
-use alloc::string::ToString;
-use core::{fmt::Debug, marker::PhantomData, time::Duration};
+use alloc::{borrow::Cow, string::ToString};
+use core::{fmt::Debug, marker::PhantomData, mem, time::Duration};
 
-use libafl_bolts::current_time;
-use serde::{de::DeserializeOwned, Serialize};
+use hashbrown::HashMap;
+use libafl_bolts::{current_time, hash_std, ErrorFrame};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
 use crate::{
-    corpus::{Corpus, CorpusId, HasCurrentCorpusId, HasTestcase, Testcase},
+    corpus::{
+        Corpus, CorpusId, HasCurrentCorpusId, HasTestcase, InputOrigin, InputOriginMetadata,
+        PendingInputOriginMetadata, Testcase,
+    },
     events::{Event, EventConfig, EventFirer, EventProcessor, ProgressReporter},
     executors::{Executor, ExitKind, HasObservers},
     feedbacks::Feedback,
     inputs::UsesInput,
     mark_feature_time,
+    monitors::{AggregatorOps, UserStats, UserStatsValue},
     observers::ObserversTuple,
     schedulers::Scheduler,
     stages::{HasCurrentStage, StagesTuple},
     start_timer,
     state::{
         HasCorpus, HasCurrentTestcase, HasExecutions, HasImported, HasLastReportTime, HasSolutions,
-        UsesState,
+        HasStartTime, UsesState,
     },
-    Error, HasMetadata,
+    Error, HasMetadata, HasNamedMetadata,
 };
 #[cfg(feature = "introspection")]
 use crate::{monitors::PerfFeature, state::HasClientPerfMonitor};
 
+pub mod stop;
+pub use stop::{StopConditions, StopReason};
+
 /// Send a monitor update all 15 (or more) seconds
 const STATS_TIMEOUT_DEFAULT: Duration = Duration::from_secs(15);
 
@@ -212,11 +220,55 @@ where
         state: &mut Self::State,
         manager: &mut EM,
     ) -> Result<(), Error> {
+        stages.validate_registers()?;
         let monitor_timeout = STATS_TIMEOUT_DEFAULT;
         loop {
             // log::info!("Starting another fuzz_loop");
             manager.maybe_report_progress(state, monitor_timeout)?;
-            self.fuzz_one(stages, executor, state, manager)?;
+            if let Err(err) = self.fuzz_one(stages, executor, state, manager) {
+                return Err(report_fuzz_error(state, manager, err));
+            }
+        }
+    }
+
+    /// Fuzz until one of the [`StopConditions`] is met, and return the reason.
+    ///
+    /// The final stats and an [`Event::Stop`] with the [`StopReason`] are sent to the broker,
+    /// which then ends the campaign, and [`ProgressReporter::on_shutdown`] tells restarting
+    /// managers not to restart the client. If the start time of the state is unset, it is set to
+    /// now, for the wall time budget.
+    fn fuzz_loop_until(
+        &mut self,
+        stages: &mut ST,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+        conditions: &StopConditions,
+    ) -> Result<StopReason, Error>
+    where
+        Self::State: HasCorpus + HasSolutions + HasNamedMetadata + HasStartTime,
+    {
+        stages.validate_registers()?;
+        if state.start_time().is_zero() {
+            *state.start_time_mut() = current_time();
+        }
+        let monitor_timeout = STATS_TIMEOUT_DEFAULT;
+        loop {
+            manager.maybe_report_progress(state, monitor_timeout)?;
+            if let Some(reason) = conditions.check(state) {
+                log::info!("Stopping the fuzz loop: {reason}");
+                manager.fire(
+                    state,
+                    Event::Stop {
+                        reason: reason.clone(),
+                    },
+                )?;
+                manager.on_shutdown(state)?;
+                return Ok(reason);
+            }
+            if let Err(err) = self.fuzz_one(stages, executor, state, manager) {
+                return Err(report_fuzz_error(state, manager, err));
+            }
         }
     }
 
@@ -243,12 +295,16 @@ where
             ));
         }
 
+        stages.validate_registers()?;
         let mut ret = None;
         let monitor_timeout = STATS_TIMEOUT_DEFAULT;
 
         for _ in 0..iters {
             manager.maybe_report_progress(state, monitor_timeout)?;
-            ret = Some(self.fuzz_one(stages, executor, state, manager)?);
+            match self.fuzz_one(stages, executor, state, manager) {
+                Ok(id) => ret = Some(id),
+                Err(err) => return Err(report_fuzz_error(state, manager, err)),
+            }
         }
 
         manager.report_progress(state)?;
@@ -262,6 +318,20 @@ where
     }
 }
 
+/// Sends the error stopping the fuzz loop to the broker, with its context, and returns it.
+/// Failing to send it only gets logged, the original error is what matters.
+fn report_fuzz_error<EM>(state: &mut EM::State, manager: &mut EM, err: Error) -> Error
+where
+    EM: EventFirer,
+{
+    if !matches!(err, Error::ShuttingDown) {
+        if let Err(fire_err) = manager.report_error(state, &err) {
+            log::error!("Could not report error to the broker: {fire_err}");
+        }
+    }
+    err
+}
+
 /// The corpus this input should be added to
 #[derive(Debug, PartialEq, Eq)]
 pub enum ExecuteInputResult {
@@ -273,12 +343,134 @@ pub enum ExecuteInputResult {
     Solution,
 }
 
+/// What the fuzzer does once the retries of an [`ExecutorErrorPolicy`] are exhausted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecutorErrorAction {
+    /// Return the error, usually stopping the client
+    Abort,
+    /// Skip the current input: evaluations of the input are discarded, and if a stage fails
+    /// because of it, the remaining stages for the current testcase are skipped.
+    SkipInput,
+    /// Restart the target using [`Executor::restart_target`] and run the input once more.
+    /// If this fails again, the error is returned.
+    RestartExecutor,
+}
+
+/// How the fuzzer reacts to recoverable errors returned by [`Executor::run_target`]
+/// (see [`Error::is_recoverable`]), such as [`Error::ExecutionFailed`], a broken pipe to a fork
+/// server or a temporary exhaustion of file descriptors.
+///
+/// Non-recoverable errors, and errors raised outside of the executor, e.g. by observers or
+/// stages, are always returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutorErrorPolicy {
+    retries: usize,
+    action: ExecutorErrorAction,
+}
+
+impl Default for ExecutorErrorPolicy {
+    /// Abort on the first error
+    fn default() -> Self {
+        Self::abort()
+    }
+}
+
+impl ExecutorErrorPolicy {
+    /// Return errors immediately, without retrying
+    #[must_use]
+    pub fn abort() -> Self {
+        Self {
+            retries: 0,
+            action: ExecutorErrorAction::Abort,
+        }
+    }
+
+    /// Retry the execution up to `retries` times, then return the error
+    #[must_use]
+    pub fn retry(retries: usize) -> Self {
+        Self {
+            retries,
+            action: ExecutorErrorAction::Abort,
+        }
+    }
+
+    /// Skip inputs for which the execution failed
+    #[must_use]
+    pub fn skip_input() -> Self {
+        Self {
+            retries: 0,
+            action: ExecutorErrorAction::SkipInput,
+        }
+    }
+
+    /// Restart the executor and run the input once more if the execution failed
+    #[must_use]
+    pub fn restart_executor() -> Self {
+        Self {
+            retries: 0,
+            action: ExecutorErrorAction::RestartExecutor,
+        }
+    }
+
+    /// Retry the execution up to `retries` times before applying the [`ExecutorErrorAction`]
+    #[must_use]
+    pub fn with_retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// The number of retries before the [`ExecutorErrorAction`] is applied
+    #[must_use]
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    /// The [`ExecutorErrorAction`] applied once all retries failed
+    #[must_use]
+    pub fn action(&self) -> ExecutorErrorAction {
+        self.action
+    }
+}
+
+/// The hashes of the inputs in the corpus, stored in the state by a [`StdFuzzer`] deduplicating
+/// inputs, see [`StdFuzzer::with_input_dedup`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct InputDedupMetadata {
+    hashes: HashMap<u64, CorpusId>,
+    dropped: u64,
+}
+
+libafl_bolts::impl_serdeany!(InputDedupMetadata);
+
+impl InputDedupMetadata {
+    /// The corpus entry with the given input hash, if any
+    #[must_use]
+    pub fn get(&self, hash: u64) -> Option<CorpusId> {
+        self.hashes.get(&hash).copied()
+    }
+
+    /// The number of duplicate inputs that were not added to the corpus
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
 /// Your default fuzzer instance, for everyday use.
 #[derive(Debug)]
 pub struct StdFuzzer<CS, F, OF, OT> {
     scheduler: CS,
     feedback: F,
     objective: OF,
+    error_policy: ExecutorErrorPolicy,
+    /// Set once the executor failed on the current input and the [`ExecutorErrorPolicy`] skips it
+    skipping_input: bool,
+    input_dedup: bool,
+    input_origins: bool,
     phantom: PhantomData<OT>,
 }
 
@@ -353,6 +545,7 @@ where
         + HasExecutions
         + HasCorpus
         + HasImported
+        + HasMetadata
         + HasCurrentTestcase<<Self::State as UsesInput>::Input>
         + HasCurrentCorpusId,
 {
@@ -448,8 +641,22 @@ where
                 // Not a solution
                 self.objective_mut().discard_metadata(state, &input)?;
 
+                // Testcases from the other fuzzers may already be in the corpus
+                if !send_events {
+                    if let Some(id) = self.known_duplicate(state, &input)? {
+                        self.feedback_mut().discard_metadata(state, &input)?;
+                        Self::drop_duplicate(state, manager)?;
+                        return Ok(Some(id));
+                    }
+                }
+
                 // Add the input to the main corpus
                 let mut testcase = Testcase::with_executions(input.clone(), *state.executions());
+                if self.input_origins {
+                    testcase.add_metadata(InputOriginMetadata {
+                        origin: Self::input_origin(state, send_events),
+                    });
+                }
                 #[cfg(feature = "track_hit_feedbacks")]
                 self.feedback_mut()
                     .append_hit_feedbacks(testcase.hit_feedbacks_mut())?;
@@ -457,6 +664,7 @@ where
                     .append_metadata(state, manager, observers, &mut testcase)?;
                 let id = state.corpus_mut().add(testcase)?;
                 self.scheduler_mut().on_add(state, id)?;
+                self.record_input_hash(state, &input, id)?;
 
                 if send_events && manager.should_send() {
                     // TODO set None for fast targets
@@ -527,7 +735,7 @@ where
     OT: ObserversTuple<Self::State> + Serialize + DeserializeOwned,
     F: Feedback<Self::State>,
     OF: Feedback<Self::State>,
-    CS::State: HasCorpus + HasSolutions + HasExecutions + HasImported,
+    CS::State: HasCorpus + HasSolutions + HasExecutions + HasImported + HasMetadata,
 {
     /// Process one input, adding to the respective corpora if needed and firing the right events
     #[inline]
@@ -543,7 +751,22 @@ where
         E: Executor<EM, Self> + HasObservers<Observers = OT, State = Self::State>,
         EM: EventFirer<State = Self::State>,
     {
-        let exit_kind = self.execute_input(state, executor, manager, &input)?;
+        // Don't run testcases from the other fuzzers that are already in the corpus
+        if !send_events {
+            if let Some(id) = self.known_duplicate(state, &input)? {
+                Self::drop_duplicate(state, manager)?;
+                return Ok((ExecuteInputResult::None, Some(id)));
+            }
+        }
+
+        let exit_kind = match self.execute_input(state, executor, manager, &input) {
+            Ok(exit_kind) => exit_kind,
+            Err(err) if mem::take(&mut self.skipping_input) => {
+                log::warn!("Skipping input after recoverable executor error: {err}");
+                return Ok((ExecuteInputResult::None, None));
+            }
+            Err(err) => return Err(err),
+        };
         let observers = executor.observers();
 
         self.scheduler.on_evaluation(state, &input, &*observers)?;
@@ -560,7 +783,7 @@ where
     F: Feedback<Self::State>,
     OF: Feedback<Self::State>,
     OT: ObserversTuple<Self::State> + Serialize + DeserializeOwned,
-    CS::State: HasCorpus + HasSolutions + HasExecutions + HasImported,
+    CS::State: HasCorpus + HasSolutions + HasExecutions + HasImported + HasMetadata,
 {
     /// Process one input, adding to the respective corpora if needed and firing the right events
     #[inline]
@@ -586,6 +809,9 @@ where
         Ok(id)
     }
     /// Adds an input, even if it's not considered `interesting` by any of the executors
+    ///
+    /// With [`StdFuzzer::with_input_dedup`], an input already in the corpus is not added again,
+    /// and the id of the existing entry is returned.
     fn add_input(
         &mut self,
         state: &mut Self::State,
@@ -593,10 +819,20 @@ where
         manager: &mut EM,
         input: <Self::State as UsesInput>::Input,
     ) -> Result<CorpusId, Error> {
+        if let Some(id) = self.known_duplicate(state, &input)? {
+            Self::drop_duplicate(state, manager)?;
+            return Ok(id);
+        }
+
         let exit_kind = self.execute_input(state, executor, manager, &input)?;
         let observers = executor.observers();
         // Always consider this to be "interesting"
         let mut testcase = Testcase::with_executions(input.clone(), *state.executions());
+        if self.input_origins {
+            testcase.add_metadata(InputOriginMetadata {
+                origin: Self::input_origin(state, true),
+            });
+        }
 
         // Maybe a solution
         #[cfg(not(feature = "introspection"))]
@@ -660,6 +896,7 @@ where
             .append_metadata(state, manager, &*observers, &mut testcase)?;
         let id = state.corpus_mut().add(testcase)?;
         self.scheduler_mut().on_add(state, id)?;
+        self.record_input_hash(state, &input, id)?;
 
         let observers_buf = if manager.configuration() == EventConfig::AlwaysUnique {
             None
@@ -731,7 +968,14 @@ where
         state.introspection_monitor_mut().reset_stage_index();
 
         // Execute all stages
-        stages.perform_all(self, executor, state, manager)?;
+        self.skipping_input = false;
+        if let Err(err) = stages.perform_all(self, executor, state, manager) {
+            if !mem::take(&mut self.skipping_input) {
+                return Err(err.context(ErrorFrame::new("fuzz_one").with_input_id(id.0)));
+            }
+            log::warn!("Skipping the remaining stages after recoverable executor error: {err}");
+            state.clear_stage()?;
+        }
 
         // Init timer for manager
         #[cfg(feature = "introspection")]
@@ -771,10 +1015,119 @@ where
             scheduler,
             feedback,
             objective,
+            error_policy: ExecutorErrorPolicy::default(),
+            skipping_input: false,
+            input_dedup: false,
+            input_origins: false,
             phantom: PhantomData,
         }
     }
 
+    /// Sets the [`ExecutorErrorPolicy`] applied when the executor returns a recoverable error
+    #[must_use]
+    pub fn with_error_policy(mut self, error_policy: ExecutorErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// The [`ExecutorErrorPolicy`] of this fuzzer
+    #[must_use]
+    pub fn error_policy(&self) -> &ExecutorErrorPolicy {
+        &self.error_policy
+    }
+
+    /// Deduplicates the corpus by content: the hashes of all added inputs are kept in the
+    /// [`InputDedupMetadata`], and inputs received from other fuzzers that are already in the corpus
+    /// are neither run nor added again. The number of dropped duplicates is reported as user stat.
+    ///
+    /// Inputs are hashed in their serialized form, using `xxh3` if the `xxh3` feature is enabled.
+    #[must_use]
+    pub fn with_input_dedup(mut self, input_dedup: bool) -> Self {
+        self.input_dedup = input_dedup;
+        self
+    }
+
+    /// If this fuzzer deduplicates the corpus by content, see [`StdFuzzer::with_input_dedup`]
+    #[must_use]
+    pub fn input_dedup(&self) -> bool {
+        self.input_dedup
+    }
+
+    /// Records the [`InputOrigin`] of each new testcase in its [`InputOriginMetadata`], as needed
+    /// by the [`crate::schedulers::OriginQuotaScheduler`]. Off by default, as the metadata is
+    /// stored with every testcase.
+    #[must_use]
+    pub fn with_input_origins(mut self, input_origins: bool) -> Self {
+        self.input_origins = input_origins;
+        self
+    }
+
+    /// If this fuzzer records the origins of testcases, see [`StdFuzzer::with_input_origins`]
+    #[must_use]
+    pub fn input_origins(&self) -> bool {
+        self.input_origins
+    }
+
+    /// Runs the input and triggers observers, applying the [`ExecutorErrorPolicy`] on errors
+    /// of the executor
+    fn execute_input_with_policy<E, EM>(
+        &mut self,
+        state: &mut <Self as UsesState>::State,
+        executor: &mut E,
+        event_mgr: &mut EM,
+        input: &<<Self as UsesState>::State as UsesInput>::Input,
+    ) -> Result<ExitKind, Error>
+    where
+        E: Executor<EM, Self> + HasObservers<State = <Self as UsesState>::State>,
+        EM: UsesState<State = <Self as UsesState>::State>,
+    {
+        let mut retries = 0;
+        let mut restarted = false;
+        loop {
+            start_timer!(state);
+            executor.observers_mut().pre_exec_all(state, input)?;
+            mark_feature_time!(state, PerfFeature::PreExecObservers);
+
+            start_timer!(state);
+            let res = executor.run_target(self, state, event_mgr, input);
+            mark_feature_time!(state, PerfFeature::TargetExecution);
+
+            let exit_kind = match res {
+                Ok(exit_kind) => exit_kind,
+                Err(err) if err.is_recoverable() => {
+                    if retries < self.error_policy.retries {
+                        retries += 1;
+                        log::warn!(
+                            "Recoverable executor error, retrying ({retries}/{}): {err}",
+                            self.error_policy.retries
+                        );
+                        continue;
+                    }
+                    match self.error_policy.action {
+                        ExecutorErrorAction::RestartExecutor if !restarted => {
+                            restarted = true;
+                            log::warn!("Recoverable executor error, restarting the target: {err}");
+                            executor.restart_target(state)?;
+                            continue;
+                        }
+                        ExecutorErrorAction::SkipInput => self.skipping_input = true,
+                        _ => {}
+                    }
+                    return Err(err);
+                }
+                Err(err) => return Err(err),
+            };
+
+            start_timer!(state);
+            executor
+                .observers_mut()
+                .post_exec_all(state, input, &exit_kind)?;
+            mark_feature_time!(state, PerfFeature::PostExecObservers);
+
+            return Ok(exit_kind);
+        }
+    }
+
     /// Runs the input and triggers observers
     pub fn execute_input<E, EM>(
         &mut self,
@@ -788,21 +1141,99 @@ where
         EM: UsesState<State = <Self as UsesState>::State>,
         OT: ObserversTuple<<Self as UsesState>::State>,
     {
-        start_timer!(state);
-        executor.observers_mut().pre_exec_all(state, input)?;
-        mark_feature_time!(state, PerfFeature::PreExecObservers);
+        self.execute_input_with_policy(state, executor, event_mgr, input)
+    }
+}
 
-        start_timer!(state);
-        let exit_kind = executor.run_target(self, state, event_mgr, input)?;
-        mark_feature_time!(state, PerfFeature::TargetExecution);
+impl<CS, F, OF, OT> StdFuzzer<CS, F, OF, OT>
+where
+    CS: Scheduler,
+    CS::State: HasCorpus + HasMetadata,
+{
+    /// The [`InputOrigin`] of a testcase added now. Inputs received from other fuzzers are
+    /// imported; inputs added while no testcase is fuzzed, e.g. initial inputs, are generated.
+    fn input_origin(state: &<Self as UsesState>::State, send_events: bool) -> InputOrigin {
+        if !send_events {
+            InputOrigin::Imported
+        } else if let Some(pending) = state.metadata_map().get::<PendingInputOriginMetadata>() {
+            pending.origin
+        } else if state.corpus().current().is_none() {
+            InputOrigin::Generated
+        } else {
+            InputOrigin::Local
+        }
+    }
 
-        start_timer!(state);
-        executor
-            .observers_mut()
-            .post_exec_all(state, input, &exit_kind)?;
-        mark_feature_time!(state, PerfFeature::PostExecObservers);
+    /// The hash of the serialized input
+    fn input_hash(input: &<<Self as UsesState>::State as UsesInput>::Input) -> Result<u64, Error> {
+        Ok(hash_std(&postcard::to_allocvec(input)?))
+    }
+
+    /// The id of the corpus entry with the same input, if deduplicating
+    fn known_duplicate(
+        &self,
+        state: &mut <Self as UsesState>::State,
+        input: &<<Self as UsesState>::State as UsesInput>::Input,
+    ) -> Result<Option<CorpusId>, Error> {
+        if !self.input_dedup {
+            return Ok(None);
+        }
+        let hash = Self::input_hash(input)?;
+        let Some(id) = state
+            .metadata_map()
+            .get::<InputDedupMetadata>()
+            .and_then(|meta| meta.get(hash))
+        else {
+            return Ok(None);
+        };
+        if state.corpus().get_from_all(id).is_ok() {
+            Ok(Some(id))
+        } else {
+            // The entry was removed from the corpus in the meantime
+            state
+                .metadata_mut::<InputDedupMetadata>()?
+                .hashes
+                .remove(&hash);
+            Ok(None)
+        }
+    }
+
+    /// Remembers the hash of the input added to the corpus as `id`, if deduplicating
+    fn record_input_hash(
+        &self,
+        state: &mut <Self as UsesState>::State,
+        input: &<<Self as UsesState>::State as UsesInput>::Input,
+        id: CorpusId,
+    ) -> Result<(), Error> {
+        if self.input_dedup {
+            let hash = Self::input_hash(input)?;
+            state
+                .metadata_or_insert_with(InputDedupMetadata::default)
+                .hashes
+                .insert(hash, id);
+        }
+        Ok(())
+    }
 
-        Ok(exit_kind)
+    /// Counts a dropped duplicate and reports the count to the monitor
+    fn drop_duplicate<EM>(
+        state: &mut <Self as UsesState>::State,
+        manager: &mut EM,
+    ) -> Result<(), Error>
+    where
+        EM: EventFirer<State = <Self as UsesState>::State>,
+    {
+        let meta = state.metadata_or_insert_with(InputDedupMetadata::default);
+        meta.dropped += 1;
+        let dropped = meta.dropped;
+        manager.fire(
+            state,
+            Event::UpdateUserStats {
+                name: Cow::from("dropped_duplicates"),
+                value: UserStats::new(UserStatsValue::Number(dropped), AggregatorOps::Sum),
+                phantom: PhantomData,
+            },
+        )
     }
 }
 
@@ -839,36 +1270,36 @@ where
         event_mgr: &mut EM,
         input: &<<Self as UsesState>::State as UsesInput>::Input,
     ) -> Result<ExitKind, Error> {
-        start_timer!(state);
-        executor.observers_mut().pre_exec_all(state, input)?;
-        mark_feature_time!(state, PerfFeature::PreExecObservers);
-
-        start_timer!(state);
-        let exit_kind = executor.run_target(self, state, event_mgr, input)?;
-        mark_feature_time!(state, PerfFeature::TargetExecution);
-
-        start_timer!(state);
-        executor
-            .observers_mut()
-            .post_exec_all(state, input, &exit_kind)?;
-        mark_feature_time!(state, PerfFeature::PostExecObservers);
-
-        Ok(exit_kind)
+        self.execute_input_with_policy(state, executor, event_mgr, input)
     }
 }
 
 #[cfg(test)]
 pub mod test {
+    use alloc::{borrow::Cow, vec::Vec};
     use core::marker::PhantomData;
+    use std::io;
 
-    use libafl_bolts::Error;
+    use libafl_bolts::{
+        tuples::{tuple_list, RefIndexable},
+        Error, Named,
+    };
 
+    use super::{ExecutesInput, ExecutionProcessor, ExecutorErrorPolicy, StdFuzzer};
     use crate::{
-        corpus::CorpusId,
-        events::ProgressReporter,
-        stages::{HasCurrentStage, StagesTuple},
-        state::{HasExecutions, HasLastReportTime, State, UsesState},
-        Fuzzer, HasMetadata,
+        corpus::{Corpus, CorpusId, InputOrigin, InputOriginMetadata, Testcase},
+        events::{test::RecordingEventFirer, Event, EventFirer, NopEventManager, ProgressReporter},
+        executors::{test::ClosureExecutor, Executor, ExitKind, HasObservers},
+        feedbacks::{ConstFeedback, Feedback},
+        inputs::BytesInput,
+        monitors::UserStatsValue,
+        observers::{ObserversTuple, UsesObservers},
+        schedulers::QueueScheduler,
+        stages::{HasCurrentStage, Stage, StagesTuple},
+        state::{
+            test::test_std_state, HasCorpus, HasExecutions, HasLastReportTime, State, UsesState,
+        },
+        Evaluator, EvaluatorObservers, Fuzzer, HasFeedback, HasMetadata,
     };
 
     #[derive(Clone, Debug)]
@@ -915,4 +1346,333 @@ pub mod test {
             unimplemented!()
         }
     }
+
+    /// An executor failing with a recoverable error, until its target is restarted if `recovers`
+    #[derive(Debug)]
+    struct FailingExecutor<S> {
+        recovers: bool,
+        failing: bool,
+        restarts: usize,
+        observers: (),
+        phantom: PhantomData<S>,
+    }
+
+    impl<S> FailingExecutor<S> {
+        fn new(recovers: bool) -> Self {
+            Self {
+                recovers,
+                failing: true,
+                restarts: 0,
+                observers: (),
+                phantom: PhantomData,
+            }
+        }
+    }
+
+    impl<S> UsesState for FailingExecutor<S>
+    where
+        S: State,
+    {
+        type State = S;
+    }
+
+    impl<S> UsesObservers for FailingExecutor<S>
+    where
+        S: State,
+    {
+        type Observers = ();
+    }
+
+    impl<S> HasObservers for FailingExecutor<S>
+    where
+        S: State,
+    {
+        fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+            RefIndexable::from(&self.observers)
+        }
+
+        fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+            RefIndexable::from(&mut self.observers)
+        }
+    }
+
+    impl<EM, S, Z> Executor<EM, Z> for FailingExecutor<S>
+    where
+        EM: UsesState<State = S>,
+        S: State + HasExecutions,
+        Z: UsesState<State = S>,
+    {
+        fn run_target(
+            &mut self,
+            _fuzzer: &mut Z,
+            _state: &mut S,
+            _mgr: &mut EM,
+            _input: &S::Input,
+        ) -> Result<ExitKind, Error> {
+            if self.failing {
+                Err(Error::execution_failed("the target died"))
+            } else {
+                Ok(ExitKind::Ok)
+            }
+        }
+
+        fn restart_target(&mut self, _state: &mut S) -> Result<(), Error> {
+            self.restarts += 1;
+            self.failing = !self.recovers;
+            Ok(())
+        }
+    }
+
+    /// A stage failing either in the executor or on its own, with a recoverable error
+    #[derive(Debug)]
+    struct FailingStage<S> {
+        in_executor: bool,
+        phantom: PhantomData<S>,
+    }
+
+    impl<S> UsesState for FailingStage<S>
+    where
+        S: State,
+    {
+        type State = S;
+    }
+
+    impl<E, EM, S, Z> Stage<E, EM, Z> for FailingStage<S>
+    where
+        E: UsesState<State = S>,
+        EM: UsesState<State = S>,
+        S: State,
+        S::Input: Default,
+        Z: ExecutesInput<E, EM, State = S>,
+    {
+        fn perform(
+            &mut self,
+            fuzzer: &mut Z,
+            executor: &mut E,
+            state: &mut S,
+            manager: &mut EM,
+        ) -> Result<(), Error> {
+            if self.in_executor {
+                fuzzer.execute_input(state, executor, manager, &S::Input::default())?;
+                Ok(())
+            } else {
+                Err(io::Error::from(io::ErrorKind::BrokenPipe).into())
+            }
+        }
+
+        fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+            Ok(true)
+        }
+
+        fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_error_policy_restart_executor() {
+        let mut state = test_std_state::<BytesInput>();
+        let mut manager = NopEventManager::new();
+        let input = BytesInput::new(vec![0]);
+
+        let mut fuzzer = StdFuzzer::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        )
+        .with_error_policy(ExecutorErrorPolicy::restart_executor());
+        let mut executor = FailingExecutor::new(true);
+        assert_eq!(
+            fuzzer
+                .execute_input(&mut state, &mut executor, &mut manager, &input)
+                .unwrap(),
+            ExitKind::Ok
+        );
+        assert_eq!(executor.restarts, 1);
+
+        // the target is only restarted once per input
+        let mut executor = FailingExecutor::new(false);
+        let err = fuzzer
+            .execute_input(&mut state, &mut executor, &mut manager, &input)
+            .unwrap_err();
+        assert!(matches!(err, Error::ExecutionFailed(..)));
+        assert_eq!(executor.restarts, 1);
+    }
+
+    #[test]
+    fn test_error_policy_skip_input() {
+        let mut state = test_std_state::<BytesInput>();
+        state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![0])))
+            .unwrap();
+        let mut manager = NopEventManager::new();
+        let mut executor = FailingExecutor::new(false);
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        )
+        .with_error_policy(ExecutorErrorPolicy::skip_input());
+
+        // the input the executor failed on is skipped
+        let mut stages = tuple_list!(FailingStage {
+            in_executor: true,
+            phantom: PhantomData,
+        });
+        fuzzer
+            .fuzz_one(&mut stages, &mut executor, &mut state, &mut manager)
+            .unwrap();
+
+        // recoverable errors raised by stages on their own are returned
+        let mut stages = tuple_list!(FailingStage {
+            in_executor: false,
+            phantom: PhantomData,
+        });
+        assert!(fuzzer
+            .fuzz_one(&mut stages, &mut executor, &mut state, &mut manager)
+            .is_err());
+    }
+
+    /// The origin recorded for the corpus entry
+    fn origin<S: HasCorpus>(state: &S, id: CorpusId) -> Option<InputOrigin> {
+        state
+            .corpus()
+            .get(id)
+            .unwrap()
+            .borrow()
+            .metadata_map()
+            .get::<InputOriginMetadata>()
+            .map(|meta| meta.origin)
+    }
+
+    #[test]
+    fn test_input_origins() {
+        let mut state = test_std_state::<BytesInput>();
+        let mut manager = NopEventManager::new();
+        let mut executor =
+            ClosureExecutor::new(|_observers: &mut (), _input: &BytesInput| ExitKind::Ok, ());
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        // The origins are not recorded by default
+        let id = fuzzer
+            .add_input(
+                &mut state,
+                &mut executor,
+                &mut manager,
+                BytesInput::new(vec![0]),
+            )
+            .unwrap();
+        assert_eq!(origin(&state, id), None);
+
+        let mut fuzzer = fuzzer.with_input_origins(true);
+        let id = fuzzer
+            .add_input(
+                &mut state,
+                &mut executor,
+                &mut manager,
+                BytesInput::new(vec![1]),
+            )
+            .unwrap();
+        assert_eq!(origin(&state, id), Some(InputOrigin::Generated));
+    }
+
+    /// Always interesting, counts the discarded inputs
+    #[derive(Debug, Default)]
+    struct DiscardCountingFeedback {
+        discarded: usize,
+    }
+
+    impl Named for DiscardCountingFeedback {
+        fn name(&self) -> &Cow<'static, str> {
+            static NAME: Cow<'static, str> = Cow::Borrowed("DiscardCountingFeedback");
+            &NAME
+        }
+    }
+
+    impl<S> Feedback<S> for DiscardCountingFeedback
+    where
+        S: State,
+    {
+        fn is_interesting<EM, OT>(
+            &mut self,
+            _state: &mut S,
+            _manager: &mut EM,
+            _input: &S::Input,
+            _observers: &OT,
+            _exit_kind: &ExitKind,
+        ) -> Result<bool, Error>
+        where
+            EM: EventFirer<State = S>,
+            OT: ObserversTuple<S>,
+        {
+            Ok(true)
+        }
+
+        fn discard_metadata(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+            self.discarded += 1;
+            Ok(())
+        }
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        fn last_result(&self) -> Result<bool, Error> {
+            Ok(true)
+        }
+    }
+
+    #[test]
+    fn test_input_dedup() {
+        let mut state = test_std_state::<BytesInput>();
+        let mut manager = RecordingEventFirer::new();
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            DiscardCountingFeedback::default(),
+            ConstFeedback::new(false),
+        )
+        .with_input_dedup(true);
+
+        let input = BytesInput::new(vec![0]);
+        for _ in 0..2 {
+            // Inputs from other fuzzers are deduplicated
+            fuzzer
+                .execute_and_process(
+                    &mut state,
+                    &mut manager,
+                    input.clone(),
+                    &(),
+                    &ExitKind::Ok,
+                    false,
+                )
+                .unwrap();
+        }
+
+        // Known inputs from other fuzzers are not even executed
+        let mut executor = ClosureExecutor::new(
+            |_observers: &mut (), _input: &BytesInput| panic!("The duplicate was executed"),
+            (),
+        );
+        fuzzer
+            .evaluate_input_with_observers(&mut state, &mut executor, &mut manager, input, false)
+            .unwrap();
+
+        assert_eq!(state.corpus().count(), 1);
+        assert_eq!(fuzzer.feedback().discarded, 1);
+        let dropped: Vec<_> = manager
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                Event::UpdateUserStats { name, value, .. } if name == "dropped_duplicates" => {
+                    match value.value() {
+                        UserStatsValue::Number(dropped) => Some(*dropped),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(dropped, [1, 2]);
+    }
 }
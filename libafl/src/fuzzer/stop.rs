@@ -0,0 +1,239 @@
+//! Stop conditions for a budgeted fuzz loop, see [`crate::fuzzer::Fuzzer::fuzz_loop_until`].
+
+use alloc::borrow::Cow;
+use core::{
+    fmt::{self, Debug, Display, Formatter},
+    time::Duration,
+};
+
+use libafl_bolts::{
+    current_time,
+    serdeany::{NamedSerdeAnyMap, SerdeAny},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    corpus::Corpus,
+    feedbacks::MapFeedbackMetadata,
+    state::{HasCorpus, HasExecutions, HasSolutions, HasStartTime},
+    HasNamedMetadata,
+};
+
+/// Why a budgeted fuzz loop stopped, sent to the broker with an [`crate::events::Event::Stop`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StopReason {
+    /// The state reached the maximum number of executions
+    Executions(u64),
+    /// The corpus reached the maximum size
+    CorpusSize(usize),
+    /// The given number of objectives was found
+    Objectives(usize),
+    /// The map feedback with the given name reached the coverage, in percent of the map
+    Coverage {
+        /// The name of the map feedback
+        name: Cow<'static, str>,
+        /// The coverage reached, in percent
+        percent: f64,
+    },
+    /// The wall time since the start of the campaign exceeded the budget
+    WallTime(Duration),
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Executions(executions) => write!(f, "reached {executions} executions"),
+            Self::CorpusSize(size) => write!(f, "reached a corpus size of {size}"),
+            Self::Objectives(objectives) => write!(f, "found {objectives} objectives"),
+            Self::Coverage { name, percent } => {
+                write!(f, "reached {percent:.2}% coverage of {name}")
+            }
+            Self::WallTime(time) => write!(f, "ran for {time:?}"),
+        }
+    }
+}
+
+/// Reads the coverage of a map feedback, in percent, from the named metadata of the state
+type CoverageFn = fn(&NamedSerdeAnyMap, &str) -> Option<f64>;
+
+/// The coverage of the [`MapFeedbackMetadata`] with the given name, in percent of the map
+#[allow(clippy::cast_precision_loss)]
+fn map_coverage<T>(metadata: &NamedSerdeAnyMap, name: &str) -> Option<f64>
+where
+    T: Debug + Default + Copy + 'static + Serialize + DeserializeOwned,
+    MapFeedbackMetadata<T>: SerdeAny,
+{
+    let meta = metadata.get::<MapFeedbackMetadata<T>>(name)?;
+    if meta.history_map.is_empty() {
+        return None;
+    }
+    Some(meta.num_covered_map_indexes as f64 * 100.0 / meta.history_map.len() as f64)
+}
+
+/// A coverage stop condition, see [`StopConditions::with_coverage`]
+#[derive(Debug, Clone)]
+struct CoverageCondition {
+    name: Cow<'static, str>,
+    percent: f64,
+    coverage: CoverageFn,
+}
+
+/// The conditions to end a budgeted fuzz loop, see [`crate::fuzzer::Fuzzer::fuzz_loop_until`].
+/// The loop stops as soon as any of the conditions is met.
+#[derive(Debug, Clone, Default)]
+pub struct StopConditions {
+    max_executions: Option<u64>,
+    max_corpus_size: Option<usize>,
+    max_objectives: Option<usize>,
+    coverage: Option<CoverageCondition>,
+    max_time: Option<Duration>,
+}
+
+impl StopConditions {
+    /// Creates [`StopConditions`] without any condition, add them with the `with_*` functions
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops once the state reached the given number of executions
+    #[must_use]
+    pub fn with_max_executions(mut self, executions: u64) -> Self {
+        self.max_executions = Some(executions);
+        self
+    }
+
+    /// Stops once the corpus reached the given size, disabled testcases excluded
+    #[must_use]
+    pub fn with_max_corpus_size(mut self, size: usize) -> Self {
+        self.max_corpus_size = Some(size);
+        self
+    }
+
+    /// Stops once the given number of objectives was found, e.g. `1` to stop on the first crash
+    #[must_use]
+    pub fn with_max_objectives(mut self, objectives: usize) -> Self {
+        self.max_objectives = Some(objectives);
+        self
+    }
+
+    /// Stops once the [`crate::feedbacks::MapFeedback`] with the given name covered the given
+    /// percentage of its map. `T` is the type of the map entries, e.g. `u8` for an edges map.
+    #[must_use]
+    pub fn with_coverage<T>(mut self, name: impl Into<Cow<'static, str>>, percent: f64) -> Self
+    where
+        T: Debug + Default + Copy + 'static + Serialize + DeserializeOwned,
+        MapFeedbackMetadata<T>: SerdeAny,
+    {
+        self.coverage = Some(CoverageCondition {
+            name: name.into(),
+            percent,
+            coverage: map_coverage::<T>,
+        });
+        self
+    }
+
+    /// Stops once the given wall time passed since the start time of the state.
+    /// The time spans restarts, as the start time is part of the state.
+    #[must_use]
+    pub fn with_max_time(mut self, time: Duration) -> Self {
+        self.max_time = Some(time);
+        self
+    }
+
+    /// The first condition the state meets, if any
+    pub fn check<S>(&self, state: &S) -> Option<StopReason>
+    where
+        S: HasCorpus + HasSolutions + HasExecutions + HasNamedMetadata + HasStartTime,
+    {
+        if let Some(max) = self.max_executions {
+            if *state.executions() >= max {
+                return Some(StopReason::Executions(*state.executions()));
+            }
+        }
+        if let Some(max) = self.max_corpus_size {
+            if state.corpus().count() >= max {
+                return Some(StopReason::CorpusSize(state.corpus().count()));
+            }
+        }
+        if let Some(max) = self.max_objectives {
+            if state.solutions().count() >= max {
+                return Some(StopReason::Objectives(state.solutions().count()));
+            }
+        }
+        if let Some(condition) = &self.coverage {
+            if let Some(percent) = (condition.coverage)(state.named_metadata_map(), &condition.name)
+            {
+                if percent >= condition.percent {
+                    return Some(StopReason::Coverage {
+                        name: condition.name.clone(),
+                        percent,
+                    });
+                }
+            }
+        }
+        if let Some(max) = self.max_time {
+            let elapsed = current_time().saturating_sub(*state.start_time());
+            if elapsed >= max {
+                return Some(StopReason::WallTime(elapsed));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use libafl_bolts::current_time;
+
+    use super::{StopConditions, StopReason};
+    use crate::{
+        corpus::{Corpus, Testcase},
+        feedbacks::MapFeedbackMetadata,
+        inputs::BytesInput,
+        state::{test::test_std_state, HasCorpus, HasExecutions, HasStartTime},
+        HasNamedMetadata,
+    };
+
+    #[test]
+    fn test_stop_conditions() {
+        #[cfg(miri)]
+        unsafe {
+            libafl_bolts::serdeany::RegistryBuilder::register::<MapFeedbackMetadata<u8>>();
+        }
+
+        let mut state = test_std_state::<BytesInput>();
+        *state.start_time_mut() = current_time();
+        assert_eq!(StopConditions::new().check(&state), None);
+
+        let conditions = StopConditions::new()
+            .with_max_executions(10)
+            .with_max_corpus_size(2)
+            .with_coverage::<u8>("edges", 50.0)
+            .with_max_time(Duration::from_secs(3600));
+        assert_eq!(conditions.check(&state), None);
+
+        let mut meta = MapFeedbackMetadata::<u8>::new(4);
+        meta.num_covered_map_indexes = 2;
+        state.add_named_metadata("edges", meta);
+        assert_eq!(
+            conditions.check(&state),
+            Some(StopReason::Coverage {
+                name: "edges".into(),
+                percent: 50.0
+            })
+        );
+
+        *state.executions_mut() = 10;
+        assert_eq!(conditions.check(&state), Some(StopReason::Executions(10)));
+
+        let conditions = StopConditions::new().with_max_corpus_size(1);
+        state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![0])))
+            .unwrap();
+        assert_eq!(conditions.check(&state), Some(StopReason::CorpusSize(1)));
+    }
+}
@@ -0,0 +1,1348 @@
+//! Load grammars from [`ANTLR4`](https://github.com/antlr/antlr4) `.g4` files, for the
+//! [`Nautilus`](crate::generators::nautilus) and [`Gramatron`](crate::generators::gramatron)
+//! grammar fuzzers.
+//!
+//! The [`AntlrGrammar`] understands the context-free subset of ANTLR4: parser and lexer rules,
+//! fragments, alternatives, blocks, `?`, `*` and `+`, string literals, ranges, character sets,
+//! `~` negation and `.`. Actions, predicates, labels, options and lexer commands are ignored.
+//! Negated sets and `.` generate printable ASCII, large character sets are truncated.
+//!
+//! Since lexer rules marked `-> skip` (e.g. whitespace) never show up in generated inputs, the
+//! tokens of parser rules are followed by a separator, a space by default.
+//!
+//! Compiling big grammars, in particular to an [`Automaton`], takes a while. The [`AntlrCache`]
+//! stores the compiled form on disk and reuses it for as long as the grammar files are unchanged.
+//!
+//! ```rust,ignore
+//! let cache = AntlrCache::new("./grammar_cache")?;
+//! let grammar = cache.grammar(&["JSON.g4"])?;
+//! let automaton = cache.automaton(&grammar, 10)?;
+//! let mut generator = GramatronGenerator::new(&automaton);
+//! let context = grammar.to_nautilus(15)?;
+//! ```
+
+use alloc::{
+    collections::VecDeque,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::hash::{BuildHasher, Hasher};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ahash::RandomState;
+use hashbrown::{HashMap, HashSet};
+use libafl_bolts::fs::write_file_atomic;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[cfg(feature = "nautilus")]
+use crate::{common::nautilus::grammartec::context::Context, generators::NautilusContext};
+use crate::{
+    generators::gramatron::{Automaton, Trigger},
+    Error,
+};
+
+/// The most characters a character set expands to
+const MAX_SET_CHARS: usize = 256;
+
+/// The most alternatives of the grammar during the conversion to Greibach normal form
+const MAX_GNF_ALTERNATIVES: usize = 1 << 20;
+
+/// The most states of an [`Automaton`]
+const MAX_AUTOMATON_STATES: usize = 1 << 20;
+
+/// Bumped whenever the compiled form changes, to invalidate old caches
+const CACHE_VERSION: u32 = 2;
+
+/// A symbol of an alternative of an [`AntlrRule`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AntlrSymbol {
+    /// A literal string
+    Terminal(String),
+    /// The index of a rule of the [`AntlrGrammar`]
+    NonTerminal(usize),
+    /// The separator following the tokens of parser rules
+    Separator,
+}
+
+/// A rule of an [`AntlrGrammar`], with the EBNF of ANTLR already lowered to plain alternatives
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AntlrRule {
+    /// The name of the rule; rules generated for blocks and sets contain a `$`
+    pub name: String,
+    /// The alternatives of the rule
+    pub alternatives: Vec<Vec<AntlrSymbol>>,
+}
+
+/// A context-free grammar loaded from ANTLR4 `.g4` files, see the [module documentation](self)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AntlrGrammar {
+    rules: Vec<AntlrRule>,
+    start: usize,
+    token_separator: String,
+}
+
+impl AntlrGrammar {
+    /// Parses a grammar from the source of a `.g4` file
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        Self::parse_all(&[source])
+    }
+
+    /// Parses a grammar split over several `.g4` files, e.g. a parser and a lexer grammar
+    pub fn parse_all(sources: &[&str]) -> Result<Self, Error> {
+        let mut builder = GrammarBuilder::default();
+        for source in sources {
+            let tokens = scan(source)?;
+            GrammarParser {
+                tokens,
+                pos: 0,
+                builder: &mut builder,
+            }
+            .parse_grammar()?;
+        }
+        builder.finish()
+    }
+
+    /// Loads a grammar from a `.g4` file
+    pub fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_files(&[path])
+    }
+
+    /// Loads a grammar split over several `.g4` files, e.g. a parser and a lexer grammar
+    pub fn from_files<P>(paths: &[P]) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let sources = read_sources(paths)?;
+        let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+        Self::parse_all(&sources)
+    }
+
+    /// Generates inputs starting from the rule instead of the first parser rule
+    pub fn with_start(mut self, start: &str) -> Result<Self, Error> {
+        self.start = self
+            .rules
+            .iter()
+            .position(|rule| rule.name == start)
+            .ok_or_else(|| Error::key_not_found(format!("No rule {start} in the grammar")))?;
+        Ok(self)
+    }
+
+    /// Sets the separator following the tokens of parser rules, a space by default
+    #[must_use]
+    pub fn with_token_separator(mut self, token_separator: &str) -> Self {
+        self.token_separator = token_separator.to_string();
+        self
+    }
+
+    /// The rules of the grammar
+    #[must_use]
+    pub fn rules(&self) -> &[AntlrRule] {
+        &self.rules
+    }
+
+    /// The rule inputs are generated from
+    #[must_use]
+    pub fn start(&self) -> &AntlrRule {
+        &self.rules[self.start]
+    }
+
+    /// The rules reachable from the start rule, in breadth-first order
+    fn reachable(&self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        let mut order = vec![self.start];
+        seen.insert(self.start);
+        let mut idx = 0;
+        while idx < order.len() {
+            for alternative in &self.rules[order[idx]].alternatives {
+                for symbol in alternative {
+                    if let AntlrSymbol::NonTerminal(nt) = symbol {
+                        if seen.insert(*nt) {
+                            order.push(*nt);
+                        }
+                    }
+                }
+            }
+            idx += 1;
+        }
+        order
+    }
+
+    /// Fails if a reachable rule can never derive a finite string
+    fn check_productive(&self, reachable: &[usize]) -> Result<(), Error> {
+        let mut productive = vec![false; self.rules.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &nt in reachable {
+                if !productive[nt]
+                    && self.rules[nt].alternatives.iter().any(|alternative| {
+                        alternative.iter().all(|symbol| match symbol {
+                            AntlrSymbol::NonTerminal(other) => productive[*other],
+                            _ => true,
+                        })
+                    })
+                {
+                    productive[nt] = true;
+                    changed = true;
+                }
+            }
+        }
+        match reachable.iter().find(|nt| !productive[**nt]) {
+            Some(nt) => Err(Error::illegal_argument(format!(
+                "Rule {} never derives a finite string",
+                self.rules[*nt].name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// The alternative with separators replaced, and adjacent terminals merged
+    fn resolve(&self, alternative: &[AntlrSymbol]) -> Vec<GnfSymbol> {
+        let mut resolved = vec![];
+        for symbol in alternative {
+            match symbol {
+                AntlrSymbol::Terminal(terminal) => push_terminal(&mut resolved, terminal),
+                AntlrSymbol::Separator => push_terminal(&mut resolved, &self.token_separator),
+                AntlrSymbol::NonTerminal(nt) => resolved.push(GnfSymbol::N(*nt)),
+            }
+        }
+        resolved
+    }
+
+    /// Compiles the grammar to a [`NautilusContext`]
+    #[cfg(feature = "nautilus")]
+    pub fn to_nautilus(&self, tree_depth: usize) -> Result<NautilusContext, Error> {
+        let reachable = self.reachable();
+        self.check_productive(&reachable)?;
+
+        let mut ctx = Context::new();
+        // Nautilus can't escape a backslash in front of a nonterminal, so such terminals get
+        // their own rule
+        let mut term_rules: HashMap<String, String> = HashMap::new();
+        for &nt in &reachable {
+            for alternative in &self.rules[nt].alternatives {
+                let mut format = String::new();
+                for symbol in self.resolve(alternative) {
+                    match symbol {
+                        GnfSymbol::N(other) => format.push_str(&format!("{{R{other}}}")),
+                        GnfSymbol::T(terminal) if terminal.contains('\\') => {
+                            let len = term_rules.len();
+                            let name = term_rules.entry(terminal).or_insert_with_key(|terminal| {
+                                let name = format!("T{len}");
+                                ctx.add_term_rule(&name, terminal.as_bytes());
+                                name
+                            });
+                            format.push_str(&format!("{{{name}}}"));
+                        }
+                        GnfSymbol::T(terminal) => {
+                            format.push_str(&terminal.replace('{', "\\{").replace('}', "\\}"));
+                        }
+                    }
+                }
+                ctx.add_rule(&format!("R{nt}"), format.as_bytes());
+            }
+        }
+        ctx.add_rule("START", format!("{{R{}}}", self.start).as_bytes());
+        ctx.initialize(tree_depth);
+        Ok(NautilusContext { ctx })
+    }
+
+    /// Compiles the grammar to a Gramatron [`Automaton`], like `construct_automata` does for
+    /// JSON grammars. States with a stack of more than `stack_limit` nonterminals are abandoned,
+    /// which keeps the automaton finite for recursive grammars.
+    pub fn to_automaton(&self, stack_limit: usize) -> Result<Automaton, Error> {
+        if stack_limit == 0 {
+            return Err(Error::illegal_argument(
+                "The stack limit of an automaton needs to be positive",
+            ));
+        }
+        let gnf = self.to_gnf()?;
+
+        let init_state = 0;
+        let mut final_state = None;
+        let mut pda: Vec<Vec<Trigger>> = vec![vec![]];
+        // States are told apart by their exact stack of nonterminals. A sorted stack, as in
+        // Gramatron, merges states that expand the same nonterminals in a different order,
+        // e.g. an open parenthesis and the rest of a binary expression.
+        let mut states = HashMap::new();
+        states.insert(vec![self.start], init_state);
+        let mut worklist = VecDeque::new();
+        worklist.push_back((init_state, VecDeque::from([self.start])));
+
+        while let Some((state, stack)) = worklist.pop_front() {
+            let Some(&nt) = stack.front() else {
+                continue;
+            };
+            for (terminal, rest) in &gnf[nt] {
+                let mut next = stack.clone();
+                next.pop_front();
+                for symbol in rest.iter().rev() {
+                    next.push_front(*symbol);
+                }
+                let key: Vec<usize> = next.iter().copied().collect();
+
+                let dest = if let Some(dest) = states.get(&key) {
+                    *dest
+                } else {
+                    if next.len() > stack_limit {
+                        continue;
+                    }
+                    if pda.len() >= MAX_AUTOMATON_STATES {
+                        return Err(Error::illegal_argument(format!(
+                            "The automaton exceeds {MAX_AUTOMATON_STATES} states, try a lower stack limit"
+                        )));
+                    }
+                    let dest = pda.len();
+                    pda.push(vec![]);
+                    if next.is_empty() {
+                        final_state = Some(dest);
+                    }
+                    states.insert(key, dest);
+                    worklist.push_back((dest, next));
+                    dest
+                };
+                pda[state].push(Trigger {
+                    dest,
+                    term: terminal.clone(),
+                });
+            }
+        }
+
+        let final_state = final_state.ok_or_else(|| {
+            Error::illegal_argument(format!(
+                "The grammar derives no input within a stack limit of {stack_limit}"
+            ))
+        })?;
+
+        // Drop the transitions into abandoned states, which never reach the final state
+        let mut incoming = vec![vec![]; pda.len()];
+        for (state, triggers) in pda.iter().enumerate() {
+            for trigger in triggers {
+                incoming[trigger.dest].push(state);
+            }
+        }
+        let mut live = vec![false; pda.len()];
+        live[final_state] = true;
+        let mut worklist = vec![final_state];
+        while let Some(state) = worklist.pop() {
+            for &source in &incoming[state] {
+                if !live[source] {
+                    live[source] = true;
+                    worklist.push(source);
+                }
+            }
+        }
+        if !live[init_state] {
+            return Err(Error::illegal_argument(format!(
+                "The grammar derives no input within a stack limit of {stack_limit}"
+            )));
+        }
+        for (state, triggers) in pda.iter_mut().enumerate() {
+            if live[state] {
+                triggers.retain(|trigger| live[trigger.dest]);
+            } else {
+                triggers.clear();
+            }
+        }
+
+        Ok(Automaton {
+            final_state,
+            init_state,
+            pda,
+        })
+    }
+
+    /// Converts the reachable rules to Greibach normal form: every alternative is a terminal
+    /// followed by nonterminals. The empty string is a terminal, too.
+    fn to_gnf(&self) -> Result<GnfGrammar, Error> {
+        let order = self.reachable();
+        self.check_productive(&order)?;
+
+        let mut grammar: Vec<Vec<Vec<GnfSymbol>>> = vec![vec![]; self.rules.len()];
+        for &nt in &order {
+            grammar[nt] = self.rules[nt]
+                .alternatives
+                .iter()
+                .map(|alternative| {
+                    let mut resolved = self.resolve(alternative);
+                    if resolved.is_empty() {
+                        resolved.push(GnfSymbol::T(String::new()));
+                    }
+                    resolved
+                })
+                .collect();
+            dedup_alternatives(&mut grammar[nt]);
+        }
+
+        // Remove left recursion: afterwards, the alternatives of a rule only start with the
+        // rules following it in `order`, or with terminals
+        for (idx, &nt) in order.iter().enumerate() {
+            for &earlier in &order[..idx] {
+                if grammar[nt]
+                    .iter()
+                    .any(|alt| alt[0] == GnfSymbol::N(earlier))
+                {
+                    let replacements = grammar[earlier].clone();
+                    let alternatives = core::mem::take(&mut grammar[nt]);
+                    grammar[nt] = substitute_leading(alternatives, earlier, &replacements);
+                }
+            }
+
+            let (recursive, others): (Vec<_>, Vec<_>) = core::mem::take(&mut grammar[nt])
+                .into_iter()
+                .partition(|alt| alt[0] == GnfSymbol::N(nt));
+            // A rule deriving itself adds nothing
+            let tails: Vec<Vec<GnfSymbol>> = recursive
+                .into_iter()
+                .filter(|alt| alt.len() > 1)
+                .map(|alt| alt[1..].to_vec())
+                .collect();
+            if tails.is_empty() {
+                grammar[nt] = others;
+            } else {
+                let tail = grammar.len();
+                grammar[nt] = with_tail(others, tail);
+                grammar.push(with_tail(tails, tail));
+            }
+
+            let total: usize = grammar.iter().map(Vec::len).sum();
+            if total > MAX_GNF_ALTERNATIVES {
+                return Err(Error::illegal_argument(format!(
+                    "The grammar exceeds {MAX_GNF_ALTERNATIVES} alternatives in Greibach normal form"
+                )));
+            }
+        }
+
+        // Substitute the leading nonterminals, in topological order
+        let mut done = vec![false; grammar.len()];
+        let mut in_progress = vec![false; grammar.len()];
+        for nt in 0..grammar.len() {
+            substitute_all_leading(&mut grammar, nt, &mut done, &mut in_progress)?;
+        }
+
+        // Replace the terminals after the first one by rules deriving them
+        let mut terminal_rules: HashMap<String, usize> = HashMap::new();
+        let mut gnf: GnfGrammar = vec![vec![]; grammar.len()];
+        for (nt, alternatives) in grammar.into_iter().enumerate() {
+            for alternative in alternatives {
+                let mut symbols = alternative.into_iter();
+                let Some(GnfSymbol::T(terminal)) = symbols.next() else {
+                    return Err(Error::illegal_state(
+                        "Alternative not in Greibach normal form",
+                    ));
+                };
+                let rest = symbols
+                    .map(|symbol| match symbol {
+                        GnfSymbol::N(other) => other,
+                        GnfSymbol::T(terminal) => *terminal_rules
+                            .entry(terminal)
+                            .or_insert_with_key(|terminal| {
+                                gnf.push(vec![(terminal.clone(), vec![])]);
+                                gnf.len() - 1
+                            }),
+                    })
+                    .collect();
+                gnf[nt].push((terminal, rest));
+            }
+        }
+        Ok(gnf)
+    }
+}
+
+/// The alternatives of each rule in Greibach normal form, a terminal and the following rules
+type GnfGrammar = Vec<Vec<(String, Vec<usize>)>>;
+
+/// A symbol during the conversion to Greibach normal form
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum GnfSymbol {
+    T(String),
+    N(usize),
+}
+
+/// Appends the terminal, merging it with a terminal at the end
+fn push_terminal(symbols: &mut Vec<GnfSymbol>, terminal: &str) {
+    if let Some(GnfSymbol::T(last)) = symbols.last_mut() {
+        last.push_str(terminal);
+    } else {
+        symbols.push(GnfSymbol::T(terminal.to_string()));
+    }
+}
+
+/// Concatenates the alternatives, merging adjacent terminals
+fn concat(head: &[GnfSymbol], tail: &[GnfSymbol]) -> Vec<GnfSymbol> {
+    let mut symbols = head.to_vec();
+    for symbol in tail {
+        match symbol {
+            GnfSymbol::T(terminal) => push_terminal(&mut symbols, terminal),
+            GnfSymbol::N(_) => symbols.push(symbol.clone()),
+        }
+    }
+    symbols
+}
+
+/// Removes duplicate alternatives, keeping the order
+fn dedup_alternatives(alternatives: &mut Vec<Vec<GnfSymbol>>) {
+    let mut seen = HashSet::new();
+    alternatives.retain(|alt| seen.insert(alt.clone()));
+}
+
+/// The alternatives, each once on its own and once followed by the `tail` nonterminal
+fn with_tail(alternatives: Vec<Vec<GnfSymbol>>, tail: usize) -> Vec<Vec<GnfSymbol>> {
+    let mut result: Vec<_> = alternatives
+        .iter()
+        .map(|alt| concat(alt, &[GnfSymbol::N(tail)]))
+        .collect();
+    result.extend(alternatives);
+    dedup_alternatives(&mut result);
+    result
+}
+
+/// Replaces the alternatives starting with `nt` by the `replacements` of `nt`
+fn substitute_leading(
+    alternatives: Vec<Vec<GnfSymbol>>,
+    nt: usize,
+    replacements: &[Vec<GnfSymbol>],
+) -> Vec<Vec<GnfSymbol>> {
+    let mut result = vec![];
+    for alt in alternatives {
+        if alt[0] == GnfSymbol::N(nt) {
+            for replacement in replacements {
+                result.push(concat(replacement, &alt[1..]));
+            }
+        } else {
+            result.push(alt);
+        }
+    }
+    dedup_alternatives(&mut result);
+    result
+}
+
+/// Substitutes the leading nonterminals of the alternatives of `nt` until all start with a
+/// terminal, doing so for the substituted nonterminals first
+fn substitute_all_leading(
+    grammar: &mut [Vec<Vec<GnfSymbol>>],
+    nt: usize,
+    done: &mut [bool],
+    in_progress: &mut [bool],
+) -> Result<(), Error> {
+    if done[nt] {
+        return Ok(());
+    }
+    if in_progress[nt] {
+        return Err(Error::illegal_argument(
+            "Could not remove the left recursion of the grammar",
+        ));
+    }
+    in_progress[nt] = true;
+    while let Some(leading) = grammar[nt].iter().find_map(|alt| match alt[0] {
+        GnfSymbol::N(leading) => Some(leading),
+        GnfSymbol::T(_) => None,
+    }) {
+        substitute_all_leading(grammar, leading, done, in_progress)?;
+        let replacements = grammar[leading].clone();
+        let alternatives = core::mem::take(&mut grammar[nt]);
+        grammar[nt] = substitute_leading(alternatives, leading, &replacements);
+    }
+    in_progress[nt] = false;
+    done[nt] = true;
+    Ok(())
+}
+
+/// Reads the sources of the grammar files
+fn read_sources<P>(paths: &[P]) -> Result<Vec<String>, Error>
+where
+    P: AsRef<Path>,
+{
+    paths
+        .iter()
+        .map(|path| {
+            fs::read_to_string(path.as_ref()).map_err(|err| {
+                Error::os_error(
+                    err,
+                    format!("Could not read grammar file {}", path.as_ref().display()),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Caches compiled [`AntlrGrammar`]s and [`Automaton`]s on disk, keyed by a hash of their
+/// source, see the [module documentation](self)
+#[derive(Debug, Clone)]
+pub struct AntlrCache {
+    dir: PathBuf,
+}
+
+impl AntlrCache {
+    /// Creates a new [`AntlrCache`] in the directory, creating it if needed
+    pub fn new<P>(dir: P) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Loads a grammar split over one or more `.g4` files, parsing them only if they changed
+    pub fn grammar<P>(&self, paths: &[P]) -> Result<AntlrGrammar, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let sources = read_sources(paths)?;
+        let mut hasher = cache_hasher();
+        for source in &sources {
+            hasher.write_usize(source.len());
+            hasher.write(source.as_bytes());
+        }
+        let file = self.dir.join(format!("{:016x}.grammar", hasher.finish()));
+        if let Some(grammar) = load_cached(&file) {
+            return Ok(grammar);
+        }
+        let sources: Vec<&str> = sources.iter().map(String::as_str).collect();
+        let grammar = AntlrGrammar::parse_all(&sources)?;
+        store_cached(&file, &grammar);
+        Ok(grammar)
+    }
+
+    /// Compiles the grammar to an [`Automaton`], see [`AntlrGrammar::to_automaton`], reusing an
+    /// earlier compilation of the same grammar
+    pub fn automaton(
+        &self,
+        grammar: &AntlrGrammar,
+        stack_limit: usize,
+    ) -> Result<Automaton, Error> {
+        let mut hasher = cache_hasher();
+        hasher.write(&postcard::to_allocvec(grammar)?);
+        hasher.write_usize(stack_limit);
+        let file = self.dir.join(format!("{:016x}.automaton", hasher.finish()));
+        if let Some(automaton) = load_cached(&file) {
+            return Ok(automaton);
+        }
+        let automaton = grammar.to_automaton(stack_limit)?;
+        store_cached(&file, &automaton);
+        Ok(automaton)
+    }
+}
+
+/// A hasher stable across runs, for the keys of the cache
+fn cache_hasher() -> impl Hasher {
+    let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+    hasher.write_u32(CACHE_VERSION);
+    hasher
+}
+
+/// Loads a cached value, or `None` if it is missing or unreadable
+fn load_cached<T>(file: &Path) -> Option<T>
+where
+    T: DeserializeOwned,
+{
+    let bytes = fs::read(file).ok()?;
+    match postcard::from_bytes(&bytes) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            log::warn!("Ignoring corrupt grammar cache {}: {err}", file.display());
+            None
+        }
+    }
+}
+
+/// Stores a value in the cache; failures only cost the next run a recompilation
+fn store_cached<T>(file: &Path, value: &T)
+where
+    T: Serialize,
+{
+    let result = postcard::to_allocvec(value)
+        .map_err(Error::from)
+        .and_then(|bytes| write_file_atomic(file, &bytes));
+    if let Err(err) = result {
+        log::warn!("Could not write grammar cache {}: {err}", file.display());
+    }
+}
+
+/// A token of a `.g4` file
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Literal(String),
+    /// The raw content of `[...]`, a character set or rule arguments
+    Bracket(String),
+    /// The raw content of `{...}`, an action or a block of options
+    Action(String),
+    Punct(&'static str),
+}
+
+/// The punctuation of `.g4` files, longest first
+const PUNCTS: [&str; 19] = [
+    "..", "+=", "->", "::", ":", ";", "|", "(", ")", "?", "*", "+", ".", "~", "=", "#", ",", "@",
+    "<",
+];
+
+/// Splits the source of a `.g4` file into tokens
+fn scan(source: &str) -> Result<Vec<Token>, Error> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        let rest: String = chars[pos..chars.len().min(pos + 2)].iter().collect();
+        if c.is_whitespace() {
+            pos += 1;
+        } else if rest == "//" {
+            while pos < chars.len() && chars[pos] != '\n' {
+                pos += 1;
+            }
+        } else if rest == "/*" {
+            pos += 2;
+            while pos < chars.len() && !(chars[pos] == '*' && chars.get(pos + 1) == Some(&'/')) {
+                pos += 1;
+            }
+            pos += 2;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = pos;
+            while pos < chars.len() && (chars[pos].is_ascii_alphanumeric() || chars[pos] == '_') {
+                pos += 1;
+            }
+            tokens.push(Token::Ident(chars[start..pos].iter().collect()));
+        } else if c == '\'' {
+            pos += 1;
+            let mut literal = String::new();
+            loop {
+                match chars.get(pos) {
+                    None => return Err(Error::illegal_argument("Unterminated string literal")),
+                    Some('\'') => break,
+                    Some('\\') => {
+                        let (escaped, len) = unescape(&chars[pos..])?;
+                        literal.push(escaped);
+                        pos += len;
+                    }
+                    Some(c) => {
+                        literal.push(*c);
+                        pos += 1;
+                    }
+                }
+            }
+            pos += 1;
+            tokens.push(Token::Literal(literal));
+        } else if c == '[' {
+            pos += 1;
+            let start = pos;
+            while pos < chars.len() && chars[pos] != ']' {
+                pos += if chars[pos] == '\\' { 2 } else { 1 };
+            }
+            if pos >= chars.len() {
+                return Err(Error::illegal_argument("Unterminated character set"));
+            }
+            tokens.push(Token::Bracket(chars[start..pos].iter().collect()));
+            pos += 1;
+        } else if c == '{' {
+            let start = pos + 1;
+            pos = skip_action(&chars, pos)?;
+            tokens.push(Token::Action(chars[start..pos - 1].iter().collect()));
+        } else if let Some(punct) = PUNCTS.iter().copied().find(|punct| rest.starts_with(punct)) {
+            pos += punct.len();
+            tokens.push(Token::Punct(punct));
+        } else if c == '>' {
+            pos += 1;
+            tokens.push(Token::Punct(">"));
+        } else {
+            return Err(Error::illegal_argument(format!(
+                "Unexpected character {c:?} in grammar"
+            )));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Skips the action starting at `pos` with a `{`, returning the position after its `}`
+fn skip_action(chars: &[char], mut pos: usize) -> Result<usize, Error> {
+    let mut depth = 0;
+    let mut quote = None;
+    while let Some(c) = chars.get(pos) {
+        match (quote, c) {
+            (Some(_), '\\') => pos += 1,
+            (Some(q), c) if q == *c => quote = None,
+            (None, '\'' | '"') => quote = Some(*c),
+            (None, '{') => depth += 1,
+            (None, '}') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(pos + 1);
+                }
+            }
+            _ => {}
+        }
+        pos += 1;
+    }
+    Err(Error::illegal_argument("Unterminated action"))
+}
+
+/// Decodes the escape sequence starting with the `\`, returning the char and its length
+fn unescape(chars: &[char]) -> Result<(char, usize), Error> {
+    let Some(c) = chars.get(1) else {
+        return Err(Error::illegal_argument("Unterminated escape sequence"));
+    };
+    let escaped = match c {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        'b' => '\u{8}',
+        'f' => '\u{c}',
+        'u' if chars.get(2) == Some(&'{') => {
+            let end = chars
+                .iter()
+                .position(|c| *c == '}')
+                .ok_or_else(|| Error::illegal_argument("Unterminated unicode escape"))?;
+            let hex: String = chars[3..end].iter().collect();
+            return Ok((parse_code_point(&hex)?, end + 1));
+        }
+        'u' => {
+            let hex: String = chars.iter().skip(2).take(4).collect();
+            return Ok((parse_code_point(&hex)?, 6));
+        }
+        c => *c,
+    };
+    Ok((escaped, 2))
+}
+
+/// Parses a hexadecimal unicode code point
+fn parse_code_point(hex: &str) -> Result<char, Error> {
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| Error::illegal_argument(format!("Invalid unicode escape {hex}")))
+}
+
+/// Parses the content of a `[...]` character set to ranges
+fn parse_char_set(raw: &str) -> Result<Vec<(char, char)>, Error> {
+    let chars: Vec<char> = raw.chars().collect();
+    let mut set = vec![];
+    let mut pos = 0;
+    let next_char = |pos: &mut usize| -> Result<char, Error> {
+        if chars[*pos] == '\\' {
+            if chars.get(*pos + 1) == Some(&'p') {
+                // Unicode properties, like \p{L}, are approximated by ASCII letters
+                while *pos < chars.len() && chars[*pos] != '}' {
+                    *pos += 1;
+                }
+                *pos += 1;
+                return Ok('\u{0}');
+            }
+            let (c, len) = unescape(&chars[*pos..])?;
+            *pos += len;
+            Ok(c)
+        } else {
+            *pos += 1;
+            Ok(chars[*pos - 1])
+        }
+    };
+    while pos < chars.len() {
+        let property = chars[pos] == '\\' && chars.get(pos + 1) == Some(&'p');
+        let first = next_char(&mut pos)?;
+        if property {
+            set.extend([('a', 'z'), ('A', 'Z')]);
+        } else if chars.get(pos) == Some(&'-') && pos + 1 < chars.len() {
+            pos += 1;
+            let last = next_char(&mut pos)?;
+            set.push((first, last));
+        } else {
+            set.push((first, first));
+        }
+    }
+    Ok(set)
+}
+
+/// The chars of the ranges, at most [`MAX_SET_CHARS`] of them
+fn expand_char_set(ranges: &[(char, char)]) -> Vec<char> {
+    let mut chars: Vec<char> = ranges
+        .iter()
+        .flat_map(|(first, last)| *first..=*last)
+        .take(MAX_SET_CHARS)
+        .collect();
+    chars.sort_unstable();
+    chars.dedup();
+    chars
+}
+
+/// The chars `.` and negated sets choose from
+fn printable_chars() -> impl Iterator<Item = char> {
+    ['\t', '\n', '\r'].into_iter().chain(' '..='~')
+}
+
+/// Collects the rules of one or more `.g4` files
+#[derive(Default)]
+struct GrammarBuilder {
+    rules: Vec<AntlrRule>,
+    /// Rules are referenced before they are defined
+    defined: Vec<bool>,
+    ids: HashMap<String, usize>,
+    /// Tokens declared in `tokens { ... }` without a lexer rule
+    declared_tokens: HashSet<String>,
+    char_sets: HashMap<Vec<char>, usize>,
+    first_parser_rule: Option<usize>,
+    first_lexer_rule: Option<usize>,
+}
+
+impl GrammarBuilder {
+    /// The index of the named rule, added if it's not known yet
+    fn rule_id(&mut self, name: &str) -> usize {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = self.add_rule(name.to_string(), vec![]);
+        self.defined[id] = false;
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Adds a rule, e.g. for a block
+    fn add_rule(&mut self, name: String, alternatives: Vec<Vec<AntlrSymbol>>) -> usize {
+        self.rules.push(AntlrRule { name, alternatives });
+        self.defined.push(true);
+        self.rules.len() - 1
+    }
+
+    /// Adds a rule deriving a block, named after the rule containing it
+    fn add_block(&mut self, rule: &str, alternatives: Vec<Vec<AntlrSymbol>>) -> usize {
+        let name = format!("{rule}${}", self.rules.len());
+        self.add_rule(name, alternatives)
+    }
+
+    /// The rule deriving each char of the set
+    fn char_set(&mut self, rule: &str, chars: Vec<char>) -> Vec<AntlrSymbol> {
+        if let Some(id) = self.char_sets.get(&chars) {
+            return vec![AntlrSymbol::NonTerminal(*id)];
+        }
+        let alternatives = chars
+            .iter()
+            .map(|c| vec![AntlrSymbol::Terminal(c.to_string())])
+            .collect();
+        let id = self.add_block(rule, alternatives);
+        self.char_sets.insert(chars, id);
+        vec![AntlrSymbol::NonTerminal(id)]
+    }
+
+    /// Checks all referenced rules are defined, and picks the start rule
+    fn finish(mut self) -> Result<AntlrGrammar, Error> {
+        for id in 0..self.rules.len() {
+            if self.defined[id] {
+                continue;
+            }
+            let name = self.rules[id].name.clone();
+            if self.declared_tokens.contains(&name) {
+                // Imaginary tokens, e.g. set by actions, are generated by their name
+                self.rules[id].alternatives = vec![vec![AntlrSymbol::Terminal(name)]];
+            } else {
+                return Err(Error::illegal_argument(format!(
+                    "Rule {name} is referenced but not defined"
+                )));
+            }
+        }
+        let start = self
+            .first_parser_rule
+            .or(self.first_lexer_rule)
+            .ok_or_else(|| Error::illegal_argument("The grammar has no rules"))?;
+        Ok(AntlrGrammar {
+            rules: self.rules,
+            start,
+            token_separator: " ".to_string(),
+        })
+    }
+}
+
+/// Parses the tokens of a `.g4` file into a [`GrammarBuilder`]
+struct GrammarParser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    builder: &'a mut GrammarBuilder,
+}
+
+impl GrammarParser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(p)) if *p == punct)
+    }
+
+    fn next(&mut self) -> Result<Token, Error> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| Error::illegal_argument("Unexpected end of grammar"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), Error> {
+        match self.next()? {
+            Token::Punct(p) if p == punct => Ok(()),
+            token => Err(Error::illegal_argument(format!(
+                "Expected {punct} in grammar, found {token:?}"
+            ))),
+        }
+    }
+
+    /// Skips tokens up to and including the punctuation
+    fn skip_past(&mut self, punct: &str) -> Result<(), Error> {
+        while !self.peek_punct(punct) {
+            self.next()?;
+        }
+        self.next()?;
+        Ok(())
+    }
+
+    fn parse_grammar(&mut self) -> Result<(), Error> {
+        let mut fragment = false;
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::Ident(ident) => {
+                    self.pos += 1;
+                    match ident.as_str() {
+                        "lexer" | "parser" => {}
+                        "grammar" | "mode" => self.skip_past(";")?,
+                        "import" => {
+                            log::warn!("Ignoring the imports of the grammar, pass all grammar files instead");
+                            self.skip_past(";")?;
+                        }
+                        "options" | "channels" => {
+                            self.next()?;
+                        }
+                        "tokens" => {
+                            if let Token::Action(tokens) = self.next()? {
+                                self.builder.declared_tokens.extend(
+                                    tokens
+                                        .split(|c: char| c == ',' || c.is_whitespace())
+                                        .filter(|token| !token.is_empty())
+                                        .map(ToString::to_string),
+                                );
+                            }
+                        }
+                        "fragment" => fragment = true,
+                        _ => {
+                            self.parse_rule(&ident, fragment)?;
+                            fragment = false;
+                        }
+                    }
+                }
+                Token::Punct("@") => {
+                    // Named actions, like @header { ... }
+                    while !matches!(self.next()?, Token::Action(_)) {}
+                }
+                token => {
+                    return Err(Error::illegal_argument(format!(
+                        "Unexpected {token:?} in grammar"
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_rule(&mut self, name: &str, fragment: bool) -> Result<(), Error> {
+        // Skip arguments, return values, options and actions up to the colon
+        self.skip_past(":")?;
+        let lexer = name.starts_with(|c: char| c.is_ascii_uppercase());
+        let alternatives = self.parse_alternatives(name, lexer)?;
+        self.expect_punct(";")?;
+        while matches!(self.peek(), Some(Token::Ident(ident)) if ident == "catch" || ident == "finally")
+        {
+            while !matches!(self.next()?, Token::Action(_)) {}
+        }
+
+        let id = self.builder.rule_id(name);
+        if self.builder.defined[id] {
+            return Err(Error::illegal_argument(format!(
+                "Rule {name} is defined twice"
+            )));
+        }
+        self.builder.defined[id] = true;
+        self.builder.rules[id].alternatives = alternatives;
+        if lexer && !fragment {
+            self.builder.first_lexer_rule.get_or_insert(id);
+        } else if !lexer {
+            self.builder.first_parser_rule.get_or_insert(id);
+        }
+        Ok(())
+    }
+
+    /// Parses alternatives up to a `)` or `;`
+    fn parse_alternatives(
+        &mut self,
+        rule: &str,
+        lexer: bool,
+    ) -> Result<Vec<Vec<AntlrSymbol>>, Error> {
+        let mut alternatives = vec![self.parse_alternative(rule, lexer)?];
+        while self.peek_punct("|") {
+            self.pos += 1;
+            alternatives.push(self.parse_alternative(rule, lexer)?);
+        }
+        Ok(alternatives)
+    }
+
+    fn parse_alternative(&mut self, rule: &str, lexer: bool) -> Result<Vec<AntlrSymbol>, Error> {
+        let mut symbols = vec![];
+        loop {
+            match self.peek() {
+                None => return Err(Error::illegal_argument("Unexpected end of grammar")),
+                Some(Token::Punct("|" | ")" | ";")) => return Ok(symbols),
+                Some(Token::Punct("#")) => {
+                    // Alternative labels
+                    self.pos += 2;
+                }
+                Some(Token::Punct("->")) => {
+                    // Lexer commands, like -> channel(HIDDEN)
+                    let mut depth = 0;
+                    while depth > 0 || !matches!(self.peek(), Some(Token::Punct("|" | ")" | ";"))) {
+                        match self.next()? {
+                            Token::Punct("(") => depth += 1,
+                            Token::Punct(")") => depth -= 1,
+                            _ => {}
+                        }
+                    }
+                }
+                Some(_) => symbols.extend(self.parse_element(rule, lexer)?),
+            }
+        }
+    }
+
+    fn parse_element(&mut self, rule: &str, lexer: bool) -> Result<Vec<AntlrSymbol>, Error> {
+        if matches!(self.peek(), Some(Token::Ident(_)))
+            && matches!(
+                self.tokens.get(self.pos + 1),
+                Some(Token::Punct("=" | "+="))
+            )
+        {
+            // Labels, like name=ID
+            self.pos += 2;
+        }
+        let atom = self.parse_atom(rule, lexer)?;
+
+        let suffix = match self.peek() {
+            Some(Token::Punct(suffix @ ("?" | "*" | "+"))) => *suffix,
+            _ => return Ok(atom),
+        };
+        self.pos += 1;
+        if self.peek_punct("?") {
+            // Non-greedy
+            self.pos += 1;
+        }
+        let id = self.builder.add_block(rule, vec![]);
+        let repeat = concat_symbols(&atom, &[AntlrSymbol::NonTerminal(id)]);
+        self.builder.rules[id].alternatives = match suffix {
+            "?" => vec![vec![], atom],
+            "*" => vec![vec![], repeat],
+            _ => vec![atom, repeat],
+        };
+        Ok(vec![AntlrSymbol::NonTerminal(id)])
+    }
+
+    fn parse_atom(&mut self, rule: &str, lexer: bool) -> Result<Vec<AntlrSymbol>, Error> {
+        let separator = if lexer {
+            vec![]
+        } else {
+            vec![AntlrSymbol::Separator]
+        };
+        match self.next()? {
+            Token::Literal(literal) if self.peek_punct("..") => {
+                self.pos += 1;
+                let Token::Literal(last) = self.next()? else {
+                    return Err(Error::illegal_argument("Expected a literal after .."));
+                };
+                let range = range_of(&literal, &last)?;
+                let chars = expand_char_set(&[range]);
+                Ok(concat_symbols(
+                    &self.builder.char_set(rule, chars),
+                    &separator,
+                ))
+            }
+            Token::Literal(literal) => Ok(concat_symbols(
+                &[AntlrSymbol::Terminal(literal)],
+                &separator,
+            )),
+            Token::Ident(ident) if ident == "EOF" => Ok(vec![]),
+            Token::Ident(ident) => {
+                let id = self.builder.rule_id(&ident);
+                let token = ident.starts_with(|c: char| c.is_ascii_uppercase());
+                let separator = if token { separator } else { vec![] };
+                Ok(concat_symbols(&[AntlrSymbol::NonTerminal(id)], &separator))
+            }
+            Token::Bracket(raw) => {
+                let chars = expand_char_set(&parse_char_set(&raw)?);
+                Ok(concat_symbols(
+                    &self.builder.char_set(rule, chars),
+                    &separator,
+                ))
+            }
+            Token::Punct(".") => {
+                let chars = printable_chars().collect();
+                Ok(concat_symbols(
+                    &self.builder.char_set(rule, chars),
+                    &separator,
+                ))
+            }
+            Token::Punct("~") => {
+                let excluded = self.parse_set()?;
+                let chars = printable_chars()
+                    .filter(|c| {
+                        !excluded
+                            .iter()
+                            .any(|(first, last)| (*first..=*last).contains(c))
+                    })
+                    .collect();
+                Ok(concat_symbols(
+                    &self.builder.char_set(rule, chars),
+                    &separator,
+                ))
+            }
+            Token::Punct("(") => {
+                let mut alternatives = self.parse_alternatives(rule, lexer)?;
+                self.expect_punct(")")?;
+                if alternatives.len() == 1 {
+                    return Ok(alternatives.remove(0));
+                }
+                let id = self.builder.add_block(rule, alternatives);
+                Ok(vec![AntlrSymbol::NonTerminal(id)])
+            }
+            Token::Action(_) => {
+                if self.peek_punct("?") {
+                    // Semantic predicates
+                    self.pos += 1;
+                }
+                Ok(vec![])
+            }
+            Token::Punct("<") => {
+                self.skip_past(">")?;
+                Ok(vec![])
+            }
+            token @ Token::Punct(_) => Err(Error::illegal_argument(format!(
+                "Unexpected {token:?} in rule {rule}"
+            ))),
+        }
+    }
+
+    /// Parses the set after a `~`
+    fn parse_set(&mut self) -> Result<Vec<(char, char)>, Error> {
+        match self.next()? {
+            Token::Bracket(raw) => parse_char_set(&raw),
+            Token::Literal(literal) if self.peek_punct("..") => {
+                self.pos += 1;
+                let Token::Literal(last) = self.next()? else {
+                    return Err(Error::illegal_argument("Expected a literal after .."));
+                };
+                Ok(vec![range_of(&literal, &last)?])
+            }
+            Token::Literal(literal) => Ok(literal.chars().map(|c| (c, c)).collect()),
+            Token::Punct("(") => {
+                let mut set = self.parse_set()?;
+                while self.peek_punct("|") {
+                    self.pos += 1;
+                    set.extend(self.parse_set()?);
+                }
+                self.expect_punct(")")?;
+                Ok(set)
+            }
+            token => Err(Error::illegal_argument(format!(
+                "Unexpected {token:?} after ~"
+            ))),
+        }
+    }
+}
+
+/// The range between two single-char literals
+fn range_of(first: &str, last: &str) -> Result<(char, char), Error> {
+    let mut first_chars = first.chars();
+    let mut last_chars = last.chars();
+    match (
+        first_chars.next(),
+        first_chars.next(),
+        last_chars.next(),
+        last_chars.next(),
+    ) {
+        (Some(first), None, Some(last), None) => Ok((first, last)),
+        _ => Err(Error::illegal_argument(format!(
+            "Invalid range '{first}'..'{last}'"
+        ))),
+    }
+}
+
+/// Concatenates the symbols
+fn concat_symbols(head: &[AntlrSymbol], tail: &[AntlrSymbol]) -> Vec<AntlrSymbol> {
+    head.iter().chain(tail).cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use libafl_bolts::rands::{Rand, StdRand};
+
+    use crate::generators::antlr::AntlrGrammar;
+
+    const GRAMMAR: &str = r"
+        grammar Expr;
+        // Left recursion and EBNF
+        expr : expr ('+' | '*') expr   # binary
+             | '(' expr ')'
+             | NUMBER
+             ;
+        NUMBER : '-'? DIGIT+ ;
+        fragment DIGIT : [0-9] ;
+        WS : [ \t\r\n]+ -> skip ;
+    ";
+
+    #[test]
+    fn test_antlr_automaton() {
+        let grammar = AntlrGrammar::parse(GRAMMAR)
+            .unwrap()
+            .with_token_separator("");
+        assert_eq!(grammar.start().name, "expr");
+
+        let automaton = grammar.to_automaton(8).unwrap();
+        let mut rand = StdRand::with_seed(0);
+        for _ in 0..100 {
+            let mut state = automaton.init_state;
+            let mut generated = String::new();
+            while state != automaton.final_state {
+                let triggers = &automaton.pda[state];
+                assert!(!triggers.is_empty());
+                let trigger = &triggers[rand.below(triggers.len())];
+                generated.push_str(&trigger.term);
+                state = trigger.dest;
+            }
+            assert!(!generated.is_empty());
+            assert!(generated
+                .chars()
+                .all(|c| c.is_ascii_digit() || "+*()-".contains(c)));
+
+            // Parentheses are balanced
+            let mut depth = 0_usize;
+            for c in generated.chars() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = depth.checked_sub(1).unwrap(),
+                    _ => {}
+                }
+            }
+            assert_eq!(depth, 0, "unbalanced: {generated}");
+        }
+    }
+
+    #[test]
+    fn test_antlr_errors() {
+        assert!(AntlrGrammar::parse("grammar A; a : b ;").is_err());
+        assert!(AntlrGrammar::parse("grammar A; a : a 'x' ;")
+            .unwrap()
+            .to_automaton(4)
+            .is_err());
+        assert!(AntlrGrammar::parse("grammar A; a : 'x' ; a : 'y' ;").is_err());
+    }
+}
@@ -0,0 +1,371 @@
+//! A context-free [`Grammar`] and the [`GrammarGenerator`] producing [`GrammarInput`]s from it
+use alloc::{string::String, vec::Vec};
+use core::{
+    hash::{BuildHasher, Hash, Hasher},
+    marker::PhantomData,
+};
+
+use ahash::RandomState;
+use hashbrown::HashMap;
+use libafl_bolts::rands::Rand;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    generators::Generator,
+    inputs::{GrammarInput, GrammarNode},
+    state::HasRand,
+    Error,
+};
+
+/// The maximal length of the bytes [`Grammar::parse`] tries to parse
+pub const MAX_PARSE_LEN: usize = 1 << 12;
+
+/// The parses of a non-terminal at a position: the end of each parse, with its tree
+type Parses = Vec<(usize, GrammarNode)>;
+
+/// A symbol in the expansion of a [`GrammarRule`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GrammarSymbol {
+    /// Literal bytes
+    Terminal(Vec<u8>),
+    /// A non-terminal, by name
+    NonTerminal(String),
+}
+
+/// A rule of a [`Grammar`], expanding a non-terminal into a sequence of symbols
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GrammarRule {
+    /// The index of the expanded non-terminal
+    pub nonterminal: usize,
+    /// The expansion
+    pub symbols: Vec<GrammarSymbol>,
+}
+
+/// A context-free grammar
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Grammar {
+    id: u64,
+    start: usize,
+    nonterminals: Vec<String>,
+    rules: Vec<GrammarRule>,
+    /// The rules of each non-terminal
+    rules_by_nonterminal: Vec<Vec<usize>>,
+    /// The minimal depth of a tree expanding each rule
+    min_depths: Vec<usize>,
+}
+
+impl Grammar {
+    /// Creates a [`Grammar`] from rules `(non-terminal, expansion)` and the start non-terminal.
+    ///
+    /// Returns an error if a non-terminal has no rule, or can't expand to terminals only.
+    pub fn new<S, R>(start: &str, rules: R) -> Result<Self, Error>
+    where
+        S: Into<String>,
+        R: IntoIterator<Item = (S, Vec<GrammarSymbol>)>,
+    {
+        let rules: Vec<(String, Vec<GrammarSymbol>)> = rules
+            .into_iter()
+            .map(|(nonterminal, symbols)| (nonterminal.into(), symbols))
+            .collect();
+
+        let mut nonterminals: Vec<String> = Vec::new();
+        for (nonterminal, _) in &rules {
+            if !nonterminals.contains(nonterminal) {
+                nonterminals.push(nonterminal.clone());
+            }
+        }
+        let index = |name: &str| {
+            nonterminals
+                .iter()
+                .position(|nonterminal| nonterminal == name)
+                .ok_or_else(|| Error::illegal_argument(format!("No rule for non-terminal {name}")))
+        };
+
+        let start = index(start)?;
+        let mut rules_by_nonterminal = vec![Vec::new(); nonterminals.len()];
+        let mut grammar_rules = Vec::with_capacity(rules.len());
+        for (idx, (nonterminal, symbols)) in rules.into_iter().enumerate() {
+            for symbol in &symbols {
+                if let GrammarSymbol::NonTerminal(name) = symbol {
+                    index(name)?;
+                }
+            }
+            let nonterminal = index(&nonterminal)?;
+            rules_by_nonterminal[nonterminal].push(idx);
+            grammar_rules.push(GrammarRule {
+                nonterminal,
+                symbols,
+            });
+        }
+
+        let mut grammar = Self {
+            id: 0,
+            start,
+            nonterminals,
+            rules: grammar_rules,
+            rules_by_nonterminal,
+            min_depths: Vec::new(),
+        };
+        grammar.compute_min_depths()?;
+
+        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+        grammar.start.hash(&mut hasher);
+        grammar.nonterminals.hash(&mut hasher);
+        grammar.rules.hash(&mut hasher);
+        grammar.id = hasher.finish();
+        Ok(grammar)
+    }
+
+    /// Computes the minimal depth of each rule as fixpoint
+    fn compute_min_depths(&mut self) -> Result<(), Error> {
+        let mut nonterminal_depths = vec![usize::MAX; self.nonterminals.len()];
+        self.min_depths = vec![usize::MAX; self.rules.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (idx, rule) in self.rules.iter().enumerate() {
+                let depth = rule
+                    .symbols
+                    .iter()
+                    .map(|symbol| match symbol {
+                        GrammarSymbol::Terminal(_) => 0,
+                        GrammarSymbol::NonTerminal(name) => {
+                            nonterminal_depths[self.nonterminal_index(name).unwrap()]
+                        }
+                    })
+                    .max()
+                    .unwrap_or(0)
+                    .saturating_add(1);
+                if depth < self.min_depths[idx] {
+                    self.min_depths[idx] = depth;
+                    changed = true;
+                }
+                if depth < nonterminal_depths[rule.nonterminal] {
+                    nonterminal_depths[rule.nonterminal] = depth;
+                }
+            }
+        }
+        if let Some(idx) = nonterminal_depths
+            .iter()
+            .position(|depth| *depth == usize::MAX)
+        {
+            return Err(Error::illegal_argument(format!(
+                "Non-terminal {} never expands to terminals only",
+                self.nonterminals[idx]
+            )));
+        }
+        Ok(())
+    }
+
+    /// The id of this grammar, a hash of its rules, stored in each [`GrammarInput`]
+    #[must_use]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The index of the start non-terminal
+    #[must_use]
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// The names of the non-terminals
+    #[must_use]
+    pub fn nonterminals(&self) -> &[String] {
+        &self.nonterminals
+    }
+
+    /// The index of the non-terminal with the given name
+    #[must_use]
+    pub fn nonterminal_index(&self, name: &str) -> Option<usize> {
+        self.nonterminals
+            .iter()
+            .position(|nonterminal| nonterminal == name)
+    }
+
+    /// The rules of this grammar
+    #[must_use]
+    pub fn rules(&self) -> &[GrammarRule] {
+        &self.rules
+    }
+
+    /// The non-terminal expanded by the rule of a node, if it is a non-terminal of this grammar
+    #[must_use]
+    pub fn nonterminal_of(&self, node: &GrammarNode) -> Option<usize> {
+        node.rule()
+            .and_then(|rule| self.rules.get(rule))
+            .map(|rule| rule.nonterminal)
+    }
+
+    /// Generates a random derivation tree for the non-terminal. Beyond `max_depth`, only the
+    /// rules leading to the shallowest trees are chosen, so the tree stays finite.
+    pub fn generate_node<R>(
+        &self,
+        rand: &mut R,
+        nonterminal: usize,
+        max_depth: usize,
+    ) -> GrammarNode
+    where
+        R: Rand,
+    {
+        let rules = &self.rules_by_nonterminal[nonterminal];
+        let rule = if max_depth == 0 {
+            *rules
+                .iter()
+                .min_by_key(|rule| self.min_depths[**rule])
+                .unwrap()
+        } else {
+            rules[rand.below(rules.len())]
+        };
+        let children = self.rules[rule]
+            .symbols
+            .iter()
+            .map(|symbol| match symbol {
+                GrammarSymbol::Terminal(bytes) => GrammarNode::Terminal(bytes.clone()),
+                GrammarSymbol::NonTerminal(name) => self.generate_node(
+                    rand,
+                    self.nonterminal_index(name).unwrap(),
+                    max_depth.saturating_sub(1),
+                ),
+            })
+            .collect();
+        GrammarNode::NonTerminal { rule, children }
+    }
+
+    /// Parses the bytes into a derivation tree of the start non-terminal, if the grammar
+    /// produces them. Returns `None` for bytes longer than [`MAX_PARSE_LEN`].
+    ///
+    /// The parser tries all rules, keeping one tree per non-terminal, start and end position, so
+    /// it stays polynomial in the length of the bytes. Left recursion is not followed: bytes
+    /// only produced by expanding a non-terminal into itself at the same position don't parse.
+    #[must_use]
+    pub fn parse(&self, bytes: &[u8]) -> Option<GrammarInput> {
+        if bytes.len() > MAX_PARSE_LEN {
+            return None;
+        }
+        let mut memo = HashMap::new();
+        self.parse_nonterminal(bytes, self.start, 0, &mut memo);
+        memo.remove(&(self.start, 0))
+            .unwrap_or_default()
+            .into_iter()
+            .find(|(end, _)| *end == bytes.len())
+            .map(|(_, root)| GrammarInput::new(self.id, root))
+    }
+
+    /// Memoizes all parses of the non-terminal starting at `pos`, at most one per end position
+    fn parse_nonterminal(
+        &self,
+        bytes: &[u8],
+        nonterminal: usize,
+        pos: usize,
+        memo: &mut HashMap<(usize, usize), Parses>,
+    ) {
+        if memo.contains_key(&(nonterminal, pos)) {
+            return;
+        }
+        // Guards against left recursion: while in progress, the non-terminal doesn't parse here
+        memo.insert((nonterminal, pos), Vec::new());
+
+        let mut parses: Parses = Vec::new();
+        for rule in &self.rules_by_nonterminal[nonterminal] {
+            let mut partial = vec![(pos, Vec::new())];
+            for symbol in &self.rules[*rule].symbols {
+                let mut next: Vec<(usize, Vec<GrammarNode>)> = Vec::new();
+                for (start, children) in partial {
+                    match symbol {
+                        GrammarSymbol::Terminal(terminal) => {
+                            if bytes[start..].starts_with(terminal) {
+                                Self::extend_partial(
+                                    &mut next,
+                                    &children,
+                                    start + terminal.len(),
+                                    &GrammarNode::Terminal(terminal.clone()),
+                                );
+                            }
+                        }
+                        GrammarSymbol::NonTerminal(name) => {
+                            let nonterminal = self.nonterminal_index(name).unwrap();
+                            self.parse_nonterminal(bytes, nonterminal, start, memo);
+                            for (end, node) in &memo[&(nonterminal, start)] {
+                                Self::extend_partial(&mut next, &children, *end, node);
+                            }
+                        }
+                    }
+                }
+                partial = next;
+                if partial.is_empty() {
+                    break;
+                }
+            }
+            for (end, children) in partial {
+                if parses.iter().all(|(other, _)| *other != end) {
+                    parses.push((
+                        end,
+                        GrammarNode::NonTerminal {
+                            rule: *rule,
+                            children,
+                        },
+                    ));
+                }
+            }
+        }
+        memo.insert((nonterminal, pos), parses);
+    }
+
+    /// Adds the partial parse extended by the node, unless one ends at the same position already
+    fn extend_partial(
+        next: &mut Vec<(usize, Vec<GrammarNode>)>,
+        children: &[GrammarNode],
+        end: usize,
+        node: &GrammarNode,
+    ) {
+        if next.iter().all(|(other, _)| *other != end) {
+            let mut children = children.to_vec();
+            children.push(node.clone());
+            next.push((end, children));
+        }
+    }
+
+    /// Generates a random [`GrammarInput`] from the start non-terminal
+    pub fn generate<R>(&self, rand: &mut R, max_depth: usize) -> GrammarInput
+    where
+        R: Rand,
+    {
+        GrammarInput::new(self.id, self.generate_node(rand, self.start, max_depth))
+    }
+}
+
+/// Generates random [`GrammarInput`]s from a [`Grammar`]
+#[derive(Clone, Debug)]
+pub struct GrammarGenerator<'a, S> {
+    grammar: &'a Grammar,
+    max_depth: usize,
+    phantom: PhantomData<S>,
+}
+
+impl<'a, S> Generator<GrammarInput, S> for GrammarGenerator<'a, S>
+where
+    S: HasRand,
+{
+    fn generate(&mut self, state: &mut S) -> Result<GrammarInput, Error> {
+        Ok(self.grammar.generate(state.rand_mut(), self.max_depth))
+    }
+}
+
+impl<'a, S> GrammarGenerator<'a, S> {
+    /// Returns a new [`GrammarGenerator`], generating trees of about `max_depth` levels
+    #[must_use]
+    pub fn new(grammar: &'a Grammar, max_depth: usize) -> Self {
+        Self {
+            grammar,
+            max_depth,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The grammar of this generator
+    #[must_use]
+    pub fn grammar(&self) -> &'a Grammar {
+        self.grammar
+    }
+}
@@ -14,6 +14,14 @@ use crate::{
 pub mod gramatron;
 pub use gramatron::*;
 
+#[cfg(feature = "std")]
+pub mod antlr;
+#[cfg(feature = "std")]
+pub use antlr::*;
+
+pub mod grammar;
+pub use grammar::*;
+
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
 #[cfg(feature = "nautilus")]
@@ -0,0 +1,238 @@
+//! An input storing the derivation tree of a context-free [`Grammar`], so that tree-level mutators
+//! work on the tree directly instead of re-parsing bytes.
+//!
+//! Use the [`GrammarToBytesInputConverter`] and [`BytesToGrammarInputConverter`] to share
+//! testcases with nodes fuzzing the same target with [`BytesInput`]s.
+
+use alloc::{string::String, vec::Vec};
+use core::hash::{BuildHasher, Hasher};
+
+use ahash::RandomState;
+use libafl_bolts::{ownedref::OwnedSlice, HasLen};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::CorpusId,
+    generators::Grammar,
+    inputs::{BytesInput, HasMutatorBytes, HasTargetBytes, Input, InputConverter},
+    Error,
+};
+
+/// A node of a derivation tree
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum GrammarNode {
+    /// A terminal, producing its bytes
+    Terminal(Vec<u8>),
+    /// A non-terminal, expanded by a rule of the grammar
+    NonTerminal {
+        /// The index of the rule in the grammar
+        rule: usize,
+        /// The expansion of the rule, one node per symbol
+        children: Vec<GrammarNode>,
+    },
+}
+
+impl GrammarNode {
+    /// Appends the bytes produced by this subtree to `bytes`
+    pub fn unparse(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Self::Terminal(terminal) => bytes.extend_from_slice(terminal),
+            Self::NonTerminal { children, .. } => {
+                for child in children {
+                    child.unparse(bytes);
+                }
+            }
+        }
+    }
+
+    /// The rule expanding this node, for non-terminals
+    #[must_use]
+    pub fn rule(&self) -> Option<usize> {
+        match self {
+            Self::Terminal(_) => None,
+            Self::NonTerminal { rule, .. } => Some(*rule),
+        }
+    }
+
+    /// The number of non-terminal nodes in this subtree, including this node
+    #[must_use]
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Terminal(_) => 0,
+            Self::NonTerminal { children, .. } => {
+                1 + children.iter().map(GrammarNode::size).sum::<usize>()
+            }
+        }
+    }
+
+    /// The non-terminal node at the given index, counting non-terminals in pre-order
+    #[must_use]
+    pub fn nth(&self, mut nth: usize) -> Option<&GrammarNode> {
+        match self {
+            Self::Terminal(_) => None,
+            Self::NonTerminal { children, .. } => {
+                if nth == 0 {
+                    return Some(self);
+                }
+                nth -= 1;
+                for child in children {
+                    let size = child.size();
+                    if nth < size {
+                        return child.nth(nth);
+                    }
+                    nth -= size;
+                }
+                None
+            }
+        }
+    }
+
+    /// The non-terminal node at the given index (mutable), counting non-terminals in pre-order
+    pub fn nth_mut(&mut self, mut nth: usize) -> Option<&mut GrammarNode> {
+        if nth == 0 {
+            return matches!(self, Self::NonTerminal { .. }).then_some(self);
+        }
+        match self {
+            Self::Terminal(_) => None,
+            Self::NonTerminal { children, .. } => {
+                nth -= 1;
+                for child in children {
+                    let size = child.size();
+                    if nth < size {
+                        return child.nth_mut(nth);
+                    }
+                    nth -= size;
+                }
+                None
+            }
+        }
+    }
+
+    /// Appends the rules and subtree sizes of all non-terminal nodes in pre-order
+    pub fn flatten(&self, nodes: &mut Vec<(usize, usize)>) {
+        if let Self::NonTerminal { rule, children } = self {
+            let idx = nodes.len();
+            nodes.push((*rule, 0));
+            for child in children {
+                child.flatten(nodes);
+            }
+            nodes[idx].1 = nodes.len() - idx;
+        }
+    }
+}
+
+/// An input for grammar fuzzing, storing the derivation tree and the id of its grammar.
+///
+/// The bytes sent to the target are produced by [`GrammarInput::generate_bytes`], without the
+/// grammar, as the terminals are part of the tree.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GrammarInput {
+    grammar_id: u64,
+    root: GrammarNode,
+}
+
+impl Input for GrammarInput {
+    /// Generate a name for this input
+    #[must_use]
+    fn generate_name(&self, _id: Option<CorpusId>) -> String {
+        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+        hasher.write(&self.generate_bytes());
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+impl HasLen for GrammarInput {
+    /// The number of non-terminal nodes in the tree
+    #[inline]
+    fn len(&self) -> usize {
+        self.root.size()
+    }
+}
+
+impl HasTargetBytes for GrammarInput {
+    #[inline]
+    fn target_bytes(&self) -> OwnedSlice<u8> {
+        OwnedSlice::from(self.generate_bytes())
+    }
+}
+
+impl GrammarInput {
+    /// Creates a new [`GrammarInput`] from the derivation tree of the grammar with the given id,
+    /// see [`crate::generators::Grammar::id`]
+    #[must_use]
+    pub fn new(grammar_id: u64, root: GrammarNode) -> Self {
+        Self { grammar_id, root }
+    }
+
+    /// The id of the grammar of the derivation tree
+    #[must_use]
+    pub fn grammar_id(&self) -> u64 {
+        self.grammar_id
+    }
+
+    /// The root of the derivation tree
+    #[must_use]
+    pub fn root(&self) -> &GrammarNode {
+        &self.root
+    }
+
+    /// The root of the derivation tree (mutable)
+    pub fn root_mut(&mut self) -> &mut GrammarNode {
+        &mut self.root
+    }
+
+    /// Produces the bytes of this input, to be sent to the target
+    #[must_use]
+    pub fn generate_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.root.unparse(&mut bytes);
+        bytes
+    }
+}
+
+/// `InputConverter` to convert from `GrammarInput` to `BytesInput`, which always succeeds
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GrammarToBytesInputConverter;
+
+impl GrammarToBytesInputConverter {
+    /// Creates a new `GrammarToBytesInputConverter`
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl InputConverter for GrammarToBytesInputConverter {
+    type From = GrammarInput;
+    type To = BytesInput;
+
+    fn convert(&mut self, input: Self::From) -> Result<Self::To, Error> {
+        Ok(BytesInput::new(input.generate_bytes()))
+    }
+}
+
+/// `InputConverter` to convert from `BytesInput` to `GrammarInput`, by parsing the bytes with
+/// [`Grammar::parse`]. Fails for bytes the grammar doesn't produce.
+#[derive(Debug, Clone, Copy)]
+pub struct BytesToGrammarInputConverter<'a> {
+    grammar: &'a Grammar,
+}
+
+impl<'a> BytesToGrammarInputConverter<'a> {
+    /// Creates a new `BytesToGrammarInputConverter`, parsing with the grammar
+    #[must_use]
+    pub fn new(grammar: &'a Grammar) -> Self {
+        Self { grammar }
+    }
+}
+
+impl<'a> InputConverter for BytesToGrammarInputConverter<'a> {
+    type From = BytesInput;
+    type To = GrammarInput;
+
+    fn convert(&mut self, input: Self::From) -> Result<Self::To, Error> {
+        self.grammar
+            .parse(input.bytes())
+            .ok_or_else(|| Error::illegal_argument("The bytes are not produced by the grammar"))
+    }
+}
@@ -0,0 +1,373 @@
+//! The [`HttpInput`] models an HTTP/1.x request as separate parts, so that mutations hit the
+//! method, the path, the headers or the body instead of breaking the protocol framing.
+//! Use the mutators in [`crate::mutators::http`] to mutate it.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::hash::{BuildHasher, Hasher};
+
+use ahash::RandomState;
+use libafl_bolts::{ownedref::OwnedSlice, HasLen};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::CorpusId,
+    inputs::{BytesInput, HasMutatorBytes, HasTargetBytes, Input},
+    Error,
+};
+
+/// A header of an [`HttpInput`]
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HttpHeader {
+    /// The name of the header
+    pub name: BytesInput,
+    /// The value of the header
+    pub value: BytesInput,
+}
+
+impl HttpHeader {
+    /// Creates a new [`HttpHeader`]
+    #[must_use]
+    pub fn new(name: &[u8], value: &[u8]) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+
+    /// Checks if this header has the given name, ignoring the case
+    #[must_use]
+    pub fn is(&self, name: &[u8]) -> bool {
+        self.name.bytes().eq_ignore_ascii_case(name)
+    }
+}
+
+/// A chunk of a body sent with `Transfer-Encoding: chunked`
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct HttpChunk {
+    /// The number of body bytes sent in this chunk
+    pub len: usize,
+    /// The size declared in the chunk header, if it differs from the actual size
+    pub declared_len: Option<usize>,
+    /// The chunk extension, appended to the size, e.g. `;name=value`
+    pub extension: Vec<u8>,
+}
+
+/// How the body of an [`HttpInput`] is framed
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HttpBodyEncoding {
+    /// The body is sent as is
+    #[default]
+    Identity,
+    /// The body is split into chunks. Body bytes not covered by the chunks are sent as a last
+    /// chunk, before the terminating empty chunk.
+    Chunked(Vec<HttpChunk>),
+}
+
+/// An HTTP/1.x request, with method, path, version, headers and body as separately mutable parts.
+///
+/// The headers are sent as they are, including `Content-Length` and `Transfer-Encoding`; nothing
+/// is fixed up during serialization, so framing inconsistencies produced by the mutators reach the
+/// target.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct HttpInput {
+    method: BytesInput,
+    path: BytesInput,
+    version: BytesInput,
+    headers: Vec<HttpHeader>,
+    body: BytesInput,
+    encoding: HttpBodyEncoding,
+}
+
+impl Input for HttpInput {
+    /// Generate a name for this input
+    fn generate_name(&self, _id: Option<CorpusId>) -> String {
+        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+        hasher.write(&self.to_bytes());
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+impl HasLen for HttpInput {
+    /// The length of the serialized request
+    #[inline]
+    fn len(&self) -> usize {
+        self.to_bytes().len()
+    }
+}
+
+impl HasTargetBytes for HttpInput {
+    #[inline]
+    fn target_bytes(&self) -> OwnedSlice<u8> {
+        OwnedSlice::from(self.to_bytes())
+    }
+}
+
+impl Default for HttpInput {
+    fn default() -> Self {
+        Self::new(b"GET", b"/")
+    }
+}
+
+impl HttpInput {
+    /// Creates a new `HTTP/1.1` [`HttpInput`] without headers and body
+    #[must_use]
+    pub fn new(method: &[u8], path: &[u8]) -> Self {
+        Self {
+            method: method.into(),
+            path: path.into(),
+            version: BytesInput::from(&b"HTTP/1.1"[..]),
+            headers: Vec::new(),
+            body: BytesInput::default(),
+            encoding: HttpBodyEncoding::Identity,
+        }
+    }
+
+    /// Adds a header
+    #[must_use]
+    pub fn with_header(mut self, name: &[u8], value: &[u8]) -> Self {
+        self.headers.push(HttpHeader::new(name, value));
+        self
+    }
+
+    /// Sets the body, and a matching `Content-Length` header
+    #[must_use]
+    pub fn with_body(mut self, body: &[u8]) -> Self {
+        self.headers.retain(|header| !header.is(b"content-length"));
+        self.headers.push(HttpHeader::new(
+            b"Content-Length",
+            body.len().to_string().as_bytes(),
+        ));
+        self.body = body.into();
+        self
+    }
+
+    /// Parses a raw request. The body is everything after the headers and is kept in its
+    /// received form, so chunked bodies are parsed as [`HttpBodyEncoding::Identity`].
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let head_len = bytes
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .ok_or_else(|| Error::illegal_argument("No end of the HTTP headers found"))?;
+        let (head, body) = (&bytes[..head_len], &bytes[head_len + 4..]);
+
+        let mut lines = head
+            .split(|b| *b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line));
+        let mut request_line = lines.next().unwrap_or_default().splitn(3, |b| *b == b' ');
+        let (Some(method), Some(path), Some(version)) = (
+            request_line.next(),
+            request_line.next(),
+            request_line.next(),
+        ) else {
+            return Err(Error::illegal_argument("Malformed HTTP request line"));
+        };
+
+        let mut headers = Vec::new();
+        for line in lines {
+            let colon = line
+                .iter()
+                .position(|b| *b == b':')
+                .ok_or_else(|| Error::illegal_argument("Malformed HTTP header"))?;
+            let value = &line[colon + 1..];
+            let value = &value[value.iter().take_while(|b| **b == b' ').count()..];
+            headers.push(HttpHeader::new(&line[..colon], value));
+        }
+
+        Ok(Self {
+            method: method.into(),
+            path: path.into(),
+            version: version.into(),
+            headers,
+            body: body.into(),
+            encoding: HttpBodyEncoding::Identity,
+        })
+    }
+
+    /// Serializes the request to the raw bytes sent to the target
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.body.len() + 128);
+        bytes.extend_from_slice(self.method.bytes());
+        bytes.push(b' ');
+        bytes.extend_from_slice(self.path.bytes());
+        bytes.push(b' ');
+        bytes.extend_from_slice(self.version.bytes());
+        bytes.extend_from_slice(b"\r\n");
+        for header in &self.headers {
+            bytes.extend_from_slice(header.name.bytes());
+            bytes.extend_from_slice(b": ");
+            bytes.extend_from_slice(header.value.bytes());
+            bytes.extend_from_slice(b"\r\n");
+        }
+        bytes.extend_from_slice(b"\r\n");
+
+        let body = self.body.bytes();
+        match &self.encoding {
+            HttpBodyEncoding::Identity => bytes.extend_from_slice(body),
+            HttpBodyEncoding::Chunked(chunks) => {
+                let mut offset = 0;
+                let mut write_chunk = |declared: usize, extension: &[u8], data: &[u8]| {
+                    bytes.extend_from_slice(format!("{declared:x}").as_bytes());
+                    bytes.extend_from_slice(extension);
+                    bytes.extend_from_slice(b"\r\n");
+                    bytes.extend_from_slice(data);
+                    bytes.extend_from_slice(b"\r\n");
+                };
+                for chunk in chunks {
+                    let end = (offset + chunk.len).min(body.len());
+                    let data = &body[offset..end];
+                    write_chunk(
+                        chunk.declared_len.unwrap_or(data.len()),
+                        &chunk.extension,
+                        data,
+                    );
+                    offset = end;
+                }
+                if offset < body.len() {
+                    write_chunk(body.len() - offset, &[], &body[offset..]);
+                }
+                bytes.extend_from_slice(b"0\r\n\r\n");
+            }
+        }
+        bytes
+    }
+
+    /// The method
+    #[must_use]
+    pub fn method(&self) -> &BytesInput {
+        &self.method
+    }
+
+    /// The method (mutable)
+    pub fn method_mut(&mut self) -> &mut BytesInput {
+        &mut self.method
+    }
+
+    /// The path
+    #[must_use]
+    pub fn path(&self) -> &BytesInput {
+        &self.path
+    }
+
+    /// The path (mutable)
+    pub fn path_mut(&mut self) -> &mut BytesInput {
+        &mut self.path
+    }
+
+    /// The version
+    #[must_use]
+    pub fn version(&self) -> &BytesInput {
+        &self.version
+    }
+
+    /// The version (mutable)
+    pub fn version_mut(&mut self) -> &mut BytesInput {
+        &mut self.version
+    }
+
+    /// The headers, in the order they are sent
+    #[must_use]
+    pub fn headers(&self) -> &[HttpHeader] {
+        &self.headers
+    }
+
+    /// The headers (mutable)
+    pub fn headers_mut(&mut self) -> &mut Vec<HttpHeader> {
+        &mut self.headers
+    }
+
+    /// The first header with the given name, ignoring the case
+    #[must_use]
+    pub fn header(&self, name: &[u8]) -> Option<&HttpHeader> {
+        self.headers.iter().find(|header| header.is(name))
+    }
+
+    /// The body
+    #[must_use]
+    pub fn body(&self) -> &BytesInput {
+        &self.body
+    }
+
+    /// The body (mutable)
+    pub fn body_mut(&mut self) -> &mut BytesInput {
+        &mut self.body
+    }
+
+    /// The framing of the body
+    #[must_use]
+    pub fn encoding(&self) -> &HttpBodyEncoding {
+        &self.encoding
+    }
+
+    /// The framing of the body (mutable)
+    pub fn encoding_mut(&mut self) -> &mut HttpBodyEncoding {
+        &mut self.encoding
+    }
+
+    /// The number of byte parts: method, path, version, body, and name and value of each header
+    #[must_use]
+    pub fn part_count(&self) -> usize {
+        4 + 2 * self.headers.len()
+    }
+
+    /// The byte part with the given index, see [`HttpInput::part_count`]
+    pub fn part_mut(&mut self, idx: usize) -> Option<&mut BytesInput> {
+        match idx {
+            0 => Some(&mut self.method),
+            1 => Some(&mut self.path),
+            2 => Some(&mut self.version),
+            3 => Some(&mut self.body),
+            _ => {
+                let header = self.headers.get_mut((idx - 4) / 2)?;
+                Some(if idx % 2 == 0 {
+                    &mut header.name
+                } else {
+                    &mut header.value
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{HttpBodyEncoding, HttpChunk, HttpInput};
+    use crate::inputs::HasMutatorBytes;
+
+    #[test]
+    fn test_http_roundtrip() {
+        let raw = b"POST /api HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nbody";
+        let input = HttpInput::parse(raw).unwrap();
+        assert_eq!(input.header(b"content-length").unwrap().value.bytes(), b"4");
+        assert_eq!(input.to_bytes(), raw);
+        assert_eq!(
+            HttpInput::new(b"POST", b"/api")
+                .with_header(b"Host", b"localhost")
+                .with_body(b"body"),
+            input
+        );
+    }
+
+    #[test]
+    fn test_http_chunked() {
+        let mut input = HttpInput::new(b"POST", b"/")
+            .with_header(b"Transfer-Encoding", b"chunked")
+            .with_body(b"hello world");
+        input
+            .headers_mut()
+            .retain(|header| !header.is(b"content-length"));
+        *input.encoding_mut() = HttpBodyEncoding::Chunked(vec![HttpChunk {
+            len: 5,
+            declared_len: Some(6),
+            extension: b";x".to_vec(),
+        }]);
+        assert!(input
+            .to_bytes()
+            .ends_with(b"\r\n\r\n6;x\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"));
+    }
+}
@@ -0,0 +1,272 @@
+//! The [`MmapBytesInput`] is a [`crate::inputs::BytesInput`] that can be backed by a memory-mapped file,
+//! for very large inputs like disk images or videos. Only inputs that get mutated are copied to memory.
+
+use alloc::{borrow::ToOwned, string::String, sync::Arc, vec::Vec};
+use core::{
+    fmt::{self, Debug, Formatter},
+    hash::{BuildHasher, Hash, Hasher},
+};
+use std::{fs::File, path::Path};
+
+use ahash::RandomState;
+use libafl_bolts::{fs::write_file_atomic, ownedref::OwnedSlice, Error, HasLen};
+use memmap2::Mmap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    corpus::CorpusId,
+    inputs::{HasMutatorBytes, HasTargetBytes, Input},
+};
+
+/// Where the bytes of a [`MmapBytesInput`] live
+#[derive(Clone)]
+enum MmapBytesStorage {
+    /// A read-only view of a memory-mapped file, shared between clones
+    Mapped(Arc<Mmap>),
+    /// Bytes owned by this input, e.g. after a mutation
+    Owned(Vec<u8>),
+}
+
+/// A bytes input that is a read-only view of a memory-mapped file when loaded with
+/// [`Input::from_file`], and gets copied to memory on the first mutation (copy-on-write).
+///
+/// Clones of a mapped input share the mapping, so cloning corpus entries for mutation is cheap
+/// until the clone gets mutated. Use it with the [`crate::corpus::MmapOnDiskCorpus`] to keep the
+/// resident memory of campaigns with very large inputs low.
+///
+/// The file must not be truncated or modified in place while it is mapped.
+/// Files written by [`Input::to_file`] are replaced atomically, so this holds for corpus entries.
+#[derive(Clone)]
+pub struct MmapBytesInput {
+    storage: MmapBytesStorage,
+}
+
+impl MmapBytesInput {
+    /// Creates a new [`MmapBytesInput`] owning the given bytes
+    #[must_use]
+    pub const fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            storage: MmapBytesStorage::Owned(bytes),
+        }
+    }
+
+    /// Maps the given file read-only
+    pub fn map_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            // Empty mappings are not portable
+            return Ok(Self::new(Vec::new()));
+        }
+        // # Safety
+        // The file may not be modified while mapped, see the type docs.
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(Self {
+            storage: MmapBytesStorage::Mapped(Arc::new(map)),
+        })
+    }
+
+    /// If the bytes are still a view of a memory-mapped file
+    #[must_use]
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.storage, MmapBytesStorage::Mapped(_))
+    }
+
+    /// Copies the bytes to memory, if still mapped, and returns them
+    fn owned_mut(&mut self) -> &mut Vec<u8> {
+        if let MmapBytesStorage::Mapped(map) = &self.storage {
+            let bytes = map.to_vec();
+            self.storage = MmapBytesStorage::Owned(bytes);
+        }
+        match &mut self.storage {
+            MmapBytesStorage::Owned(bytes) => bytes,
+            MmapBytesStorage::Mapped(_) => unreachable!("The bytes were copied to memory above"),
+        }
+    }
+}
+
+impl Input for MmapBytesInput {
+    /// Write this input to the file
+    fn to_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        write_file_atomic(path, self.bytes())
+    }
+
+    /// Map the content of this input from a file
+    fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Self::map_file(path)
+    }
+
+    /// Generate a name for this input
+    fn generate_name(&self, _id: Option<CorpusId>) -> String {
+        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+        hasher.write(self.bytes());
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+impl Debug for MmapBytesInput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MmapBytesInput")
+            .field("len", &self.len())
+            .field("mapped", &self.is_mapped())
+            .finish()
+    }
+}
+
+impl PartialEq for MmapBytesInput {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes() == other.bytes()
+    }
+}
+
+impl Eq for MmapBytesInput {}
+
+impl Hash for MmapBytesInput {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.bytes().hash(state);
+    }
+}
+
+impl Default for MmapBytesInput {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+/// Serialized like a [`crate::inputs::BytesInput`]; mapped inputs are sent and stored as bytes
+impl Serialize for MmapBytesInput {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        #[derive(Serialize)]
+        struct Repr<'a> {
+            bytes: &'a [u8],
+        }
+        Repr {
+            bytes: self.bytes(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MmapBytesInput {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Repr {
+            bytes: Vec<u8>,
+        }
+        Repr::deserialize(deserializer).map(|repr| Self::new(repr.bytes))
+    }
+}
+
+impl HasMutatorBytes for MmapBytesInput {
+    #[inline]
+    fn bytes(&self) -> &[u8] {
+        match &self.storage {
+            MmapBytesStorage::Mapped(map) => map,
+            MmapBytesStorage::Owned(bytes) => bytes,
+        }
+    }
+
+    #[inline]
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        self.owned_mut()
+    }
+
+    fn resize(&mut self, new_len: usize, value: u8) {
+        self.owned_mut().resize(new_len, value);
+    }
+
+    fn extend<'a, I: IntoIterator<Item = &'a u8>>(&mut self, iter: I) {
+        Extend::extend(self.owned_mut(), iter);
+    }
+
+    fn splice<R, I>(&mut self, range: R, replace_with: I) -> alloc::vec::Splice<'_, I::IntoIter>
+    where
+        R: core::ops::RangeBounds<usize>,
+        I: IntoIterator<Item = u8>,
+    {
+        self.owned_mut().splice(range, replace_with)
+    }
+
+    fn drain<R>(&mut self, range: R) -> alloc::vec::Drain<'_, u8>
+    where
+        R: core::ops::RangeBounds<usize>,
+    {
+        self.owned_mut().drain(range)
+    }
+}
+
+impl HasTargetBytes for MmapBytesInput {
+    #[inline]
+    fn target_bytes(&self) -> OwnedSlice<u8> {
+        OwnedSlice::from(self.bytes())
+    }
+}
+
+impl HasLen for MmapBytesInput {
+    #[inline]
+    fn len(&self) -> usize {
+        self.bytes().len()
+    }
+}
+
+impl From<Vec<u8>> for MmapBytesInput {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<&[u8]> for MmapBytesInput {
+    fn from(bytes: &[u8]) -> Self {
+        Self::new(bytes.to_owned())
+    }
+}
+
+impl From<MmapBytesInput> for Vec<u8> {
+    fn from(mut value: MmapBytesInput) -> Vec<u8> {
+        core::mem::take(value.owned_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs};
+
+    use libafl_bolts::HasLen;
+
+    use super::MmapBytesInput;
+    use crate::inputs::{HasMutatorBytes, Input};
+
+    #[test]
+    fn test_mmap_bytes_input_cow() {
+        let path = temp_dir().join(format!("libafl_mmap_input_{}", std::process::id()));
+        fs::write(&path, b"hello mmap").unwrap();
+
+        let input = MmapBytesInput::from_file(&path).unwrap();
+        assert!(input.is_mapped());
+        let mut mutated = input.clone();
+        mutated.bytes_mut()[0] = b'H';
+        mutated.extend(b"!");
+
+        assert!(input.is_mapped());
+        assert!(!mutated.is_mapped());
+        assert_eq!(input.bytes(), b"hello mmap");
+        assert_eq!(mutated.bytes(), b"Hello mmap!");
+        assert_eq!(mutated.len(), 11);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
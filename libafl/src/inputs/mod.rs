@@ -9,6 +9,15 @@ pub use encoded::*;
 pub mod gramatron;
 pub use gramatron::*;
 
+pub mod grammar;
+pub use grammar::*;
+
+pub mod http;
+pub use http::*;
+
+pub mod sequence;
+pub use sequence::{same_ops, Operation, SequenceInput, SequenceOp};
+
 pub mod generalized;
 pub use generalized::*;
 
@@ -23,6 +32,16 @@ pub use multi::*;
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
 
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+#[cfg(feature = "protobuf")]
+pub use protobuf::ProtobufInput;
+
+#[cfg(feature = "mmap_corpus")]
+pub mod mmap;
+#[cfg(feature = "mmap_corpus")]
+pub use mmap::MmapBytesInput;
+
 use alloc::{
     boxed::Box,
     string::{String, ToString},
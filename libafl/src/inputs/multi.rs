@@ -2,7 +2,9 @@
 //!
 //! Unfortunately, since both [`serde::de::Deserialize`] and [`Clone`] require [`Sized`], it is not
 //! possible to dynamically define a single input with dynamic typing. As such, [`MultipartInput`]
-//! requires that each subcomponent be the same subtype.
+//! requires that each subcomponent be the same subtype. For parts of different types, use
+//! [`TypedPart`], an enum over the supported part types, and bind mutators to the parts of a type
+//! with [`crate::mutators::PartMutator`].
 
 use alloc::{
     string::{String, ToString},
@@ -10,9 +12,13 @@ use alloc::{
 };
 
 use arrayvec::ArrayVec;
+use libafl_bolts::ownedref::OwnedSlice;
 use serde::{Deserialize, Serialize};
 
-use crate::{corpus::CorpusId, inputs::Input};
+use crate::{
+    corpus::CorpusId,
+    inputs::{BytesInput, GrammarInput, HasTargetBytes, Input},
+};
 
 /// An input composed of multiple parts. Use in situations where subcomponents are not necessarily
 /// related, or represent distinct parts of the input.
@@ -163,3 +169,102 @@ where
             .join(",")
     }
 }
+
+/// A part of a [`MultipartInput`] of one of several input types, e.g. for APIs taking a
+/// configuration blob, a data blob and flags
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TypedPart {
+    /// A part of raw bytes
+    Bytes(BytesInput),
+    /// An integer part, sent as 8 little-endian bytes
+    Integer(u64),
+    /// A part generated from a grammar
+    Grammar(GrammarInput),
+}
+
+impl Input for TypedPart {
+    fn generate_name(&self, id: Option<CorpusId>) -> String {
+        match self {
+            Self::Bytes(bytes) => bytes.generate_name(id),
+            Self::Integer(value) => format!("{value:016x}"),
+            Self::Grammar(grammar) => grammar.generate_name(id),
+        }
+    }
+}
+
+impl HasTargetBytes for TypedPart {
+    fn target_bytes(&self) -> OwnedSlice<u8> {
+        match self {
+            Self::Bytes(bytes) => bytes.target_bytes(),
+            Self::Integer(value) => OwnedSlice::from(value.to_le_bytes().to_vec()),
+            Self::Grammar(grammar) => grammar.target_bytes(),
+        }
+    }
+}
+
+impl From<BytesInput> for TypedPart {
+    fn from(bytes: BytesInput) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl From<u64> for TypedPart {
+    fn from(value: u64) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<GrammarInput> for TypedPart {
+    fn from(grammar: GrammarInput) -> Self {
+        Self::Grammar(grammar)
+    }
+}
+
+/// A [`MultipartInput`] whose parts may have different types
+pub type TypedMultipartInput = MultipartInput<TypedPart>;
+
+/// Access to a part of a [`MultipartInput`] as a part of type `P`, if it is one.
+///
+/// Every type is a part of its own type, so mutators bound to parts work for homogeneous
+/// [`MultipartInput`]s as well.
+pub trait HasPart<P> {
+    /// This part as `P`, if it is one
+    fn as_part(&self) -> Option<&P>;
+
+    /// This part as `P` (mutable), if it is one
+    fn as_part_mut(&mut self) -> Option<&mut P>;
+}
+
+impl<I> HasPart<I> for I {
+    fn as_part(&self) -> Option<&I> {
+        Some(self)
+    }
+
+    fn as_part_mut(&mut self) -> Option<&mut I> {
+        Some(self)
+    }
+}
+
+macro_rules! impl_typed_part {
+    ($part: ty, $variant: ident) => {
+        impl HasPart<$part> for TypedPart {
+            fn as_part(&self) -> Option<&$part> {
+                match self {
+                    Self::$variant(part) => Some(part),
+                    _ => None,
+                }
+            }
+
+            fn as_part_mut(&mut self) -> Option<&mut $part> {
+                match self {
+                    Self::$variant(part) => Some(part),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_typed_part!(BytesInput, Bytes);
+impl_typed_part!(u64, Integer);
+impl_typed_part!(GrammarInput, Grammar);
@@ -0,0 +1,242 @@
+//! The [`ProtobufInput`] holds a [`prost`] message, for structure-aware fuzzing of targets
+//! consuming protobufs, like gRPC services or config parsers, in the style of libprotobuf-mutator.
+//!
+//! The harness gets the message in protobuf wire format, see [`HasTargetBytes`], and corpus files
+//! are stored in wire format, too. The mutators in [`crate::mutators::protobuf`] mutate the fields
+//! of the message using the reflection of [`prost_reflect`].
+
+use alloc::{string::String, vec::Vec};
+use core::hash::{BuildHasher, Hash, Hasher};
+use std::{fs::File, io::Read, path::Path};
+
+use ahash::RandomState;
+use libafl_bolts::{fs::write_file_atomic, ownedref::OwnedSlice, Error, HasLen};
+use prost::Message;
+use prost_reflect::{DynamicMessage, ReflectMessage};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    corpus::CorpusId,
+    inputs::{HasTargetBytes, Input},
+};
+
+/// An input holding a protobuf message
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProtobufInput<M> {
+    message: M,
+}
+
+impl<M> ProtobufInput<M>
+where
+    M: Message + Default,
+{
+    /// Creates a new [`ProtobufInput`] holding the message
+    #[must_use]
+    pub fn new(message: M) -> Self {
+        Self { message }
+    }
+
+    /// Decodes a [`ProtobufInput`] from protobuf wire format
+    pub fn from_wire_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        M::decode(bytes)
+            .map(Self::new)
+            .map_err(|err| Error::serialize(format!("Could not decode protobuf message: {err}")))
+    }
+
+    /// The message in protobuf wire format, as passed to the harness
+    #[must_use]
+    pub fn to_wire_bytes(&self) -> Vec<u8> {
+        self.message.encode_to_vec()
+    }
+
+    /// The message
+    #[must_use]
+    pub fn message(&self) -> &M {
+        &self.message
+    }
+
+    /// The message (mutable)
+    pub fn message_mut(&mut self) -> &mut M {
+        &mut self.message
+    }
+
+    /// Consumes the input, returning the message
+    #[must_use]
+    pub fn into_message(self) -> M {
+        self.message
+    }
+}
+
+impl<M> ProtobufInput<M>
+where
+    M: ReflectMessage + Default,
+{
+    /// The message as [`DynamicMessage`], to inspect or change its fields by reflection
+    #[must_use]
+    pub fn to_dynamic(&self) -> DynamicMessage {
+        self.message.transcode_to_dynamic()
+    }
+
+    /// Replaces the message by the [`DynamicMessage`], which must be of the same message type
+    pub fn set_dynamic(&mut self, dynamic: &DynamicMessage) -> Result<(), Error> {
+        self.message = dynamic.transcode_to::<M>().map_err(|err| {
+            Error::illegal_argument(format!(
+                "Could not convert {} to the message of the input: {err}",
+                dynamic.descriptor().full_name()
+            ))
+        })?;
+        Ok(())
+    }
+}
+
+impl<M> From<M> for ProtobufInput<M>
+where
+    M: Message + Default,
+{
+    fn from(message: M) -> Self {
+        Self::new(message)
+    }
+}
+
+impl<M> Serialize for ProtobufInput<M>
+where
+    M: Message + Default,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_wire_bytes().serialize(serializer)
+    }
+}
+
+impl<'de, M> Deserialize<'de> for ProtobufInput<M>
+where
+    M: Message + Default,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        Self::from_wire_bytes(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+impl<M> Hash for ProtobufInput<M>
+where
+    M: Message + Default,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.to_wire_bytes().hash(state);
+    }
+}
+
+impl<M> Input for ProtobufInput<M>
+where
+    M: Message + Default + Clone,
+{
+    /// Write this input to the file, in protobuf wire format
+    fn to_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        write_file_atomic(path, &self.to_wire_bytes())
+    }
+
+    /// Load the content of this input from a file in protobuf wire format
+    fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = File::open(path)?;
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        Self::from_wire_bytes(&bytes)
+    }
+
+    /// Generate a name for this input
+    fn generate_name(&self, _id: Option<CorpusId>) -> String {
+        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+        hasher.write(&self.to_wire_bytes());
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+impl<M> HasTargetBytes for ProtobufInput<M>
+where
+    M: Message + Default,
+{
+    #[inline]
+    fn target_bytes(&self) -> OwnedSlice<u8> {
+        OwnedSlice::from(self.to_wire_bytes())
+    }
+}
+
+impl<M> HasLen for ProtobufInput<M>
+where
+    M: Message + Default,
+{
+    /// The length of the message in wire format
+    #[inline]
+    fn len(&self) -> usize {
+        self.message.encoded_len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::HasLen;
+    use prost::Message;
+    use prost_reflect::{prost_types::Timestamp, ReflectMessage, Value};
+
+    use crate::inputs::{HasTargetBytes, Input, ProtobufInput};
+
+    #[test]
+    fn test_protobuf_input_wire_format() {
+        let input = ProtobufInput::new(Timestamp {
+            seconds: 42,
+            nanos: 7,
+        });
+        let wire = input.to_wire_bytes();
+        assert_eq!(wire, input.message().encode_to_vec());
+        assert_eq!(&*input.target_bytes(), wire.as_slice());
+        assert_eq!(input.len(), wire.len());
+        assert_eq!(
+            ProtobufInput::<Timestamp>::from_wire_bytes(&wire).unwrap(),
+            input
+        );
+        // A truncated varint is no valid message
+        assert!(ProtobufInput::<Timestamp>::from_wire_bytes(&[0xff]).is_err());
+
+        let serialized = postcard::to_allocvec(&input).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<ProtobufInput<Timestamp>>(&serialized).unwrap(),
+            input
+        );
+        assert_eq!(input.generate_name(None), input.clone().generate_name(None));
+    }
+
+    #[test]
+    fn test_protobuf_input_dynamic() {
+        let mut input = ProtobufInput::new(Timestamp {
+            seconds: 42,
+            nanos: 7,
+        });
+        let mut dynamic = input.to_dynamic();
+        assert_eq!(
+            dynamic.descriptor(),
+            input.message().descriptor(),
+            "the dynamic message has the type of the input"
+        );
+        dynamic.set_field_by_name("nanos", Value::I32(8));
+        input.set_dynamic(&dynamic).unwrap();
+        assert_eq!(
+            input.into_message(),
+            Timestamp {
+                seconds: 42,
+                nanos: 8
+            }
+        );
+    }
+}
@@ -0,0 +1,214 @@
+//! The [`SequenceInput`] is an ordered list of typed operations, e.g. syscalls or API calls, each
+//! with an op code and integer arguments. Use it to fuzz stateful targets, such as kernels, storage
+//! engines or libraries, where a single blob is the wrong abstraction.
+//! The mutators in [`crate::mutators::sequence`] insert, delete, swap and duplicate operations,
+//! and mutate their arguments.
+//!
+//! The target receives the sequence in a flat encoding, see [`SequenceInput::encode`], and decodes
+//! it with `libafl_targets::sequence::SequenceDecoder`.
+
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::Debug,
+    hash::{BuildHasher, Hasher},
+};
+
+use ahash::RandomState;
+use libafl_bolts::{ownedref::OwnedSlice, HasLen};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    corpus::CorpusId,
+    inputs::{HasTargetBytes, Input},
+    Error,
+};
+
+/// An operation of a [`SequenceInput`]: an op code with integer arguments
+pub trait SequenceOp: Clone + Debug + Serialize + DeserializeOwned {
+    /// The op code, identifying the operation, e.g. the syscall number
+    fn opcode(&self) -> u32;
+
+    /// The arguments
+    fn args(&self) -> &[u64];
+
+    /// The arguments (mutable)
+    fn args_mut(&mut self) -> &mut [u64];
+
+    /// Checks if the operation does the same as `other`, by default comparing op codes and
+    /// arguments
+    fn same_op(&self, other: &Self) -> bool {
+        self.opcode() == other.opcode() && self.args() == other.args()
+    }
+}
+
+/// Checks if two lists of operations do the same, see [`SequenceOp::same_op`]
+pub fn same_ops<T>(ops: &[T], other: &[T]) -> bool
+where
+    T: SequenceOp,
+{
+    ops.len() == other.len() && ops.iter().zip(other).all(|(op, other)| op.same_op(other))
+}
+
+/// The default [`SequenceOp`]: an op code with any number of arguments
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Operation {
+    /// The op code
+    pub opcode: u32,
+    /// The arguments
+    pub args: Vec<u64>,
+}
+
+impl Operation {
+    /// Creates a new [`Operation`]
+    #[must_use]
+    pub fn new(opcode: u32, args: Vec<u64>) -> Self {
+        Self { opcode, args }
+    }
+}
+
+impl SequenceOp for Operation {
+    fn opcode(&self) -> u32 {
+        self.opcode
+    }
+
+    fn args(&self) -> &[u64] {
+        &self.args
+    }
+
+    fn args_mut(&mut self) -> &mut [u64] {
+        &mut self.args
+    }
+}
+
+/// An ordered list of operations, see [`SequenceOp`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(bound = "T: SequenceOp")]
+pub struct SequenceInput<T = Operation> {
+    ops: Vec<T>,
+}
+
+impl<T> Default for SequenceInput<T> {
+    fn default() -> Self {
+        Self { ops: Vec::new() }
+    }
+}
+
+impl<T> Input for SequenceInput<T>
+where
+    T: SequenceOp,
+{
+    /// Generate a name for this input
+    fn generate_name(&self, _id: Option<CorpusId>) -> String {
+        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+        hasher.write(&self.encode());
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+impl<T> HasLen for SequenceInput<T> {
+    /// The number of operations
+    #[inline]
+    fn len(&self) -> usize {
+        self.ops.len()
+    }
+}
+
+impl<T> HasTargetBytes for SequenceInput<T>
+where
+    T: SequenceOp,
+{
+    #[inline]
+    fn target_bytes(&self) -> OwnedSlice<u8> {
+        OwnedSlice::from(self.encode())
+    }
+}
+
+impl<T> From<Vec<T>> for SequenceInput<T> {
+    fn from(ops: Vec<T>) -> Self {
+        Self { ops }
+    }
+}
+
+impl<T> SequenceInput<T> {
+    /// Creates a new [`SequenceInput`] from the operations
+    #[must_use]
+    pub fn new(ops: Vec<T>) -> Self {
+        Self { ops }
+    }
+
+    /// The operations, in order
+    #[must_use]
+    pub fn ops(&self) -> &[T] {
+        &self.ops
+    }
+
+    /// The operations (mutable)
+    pub fn ops_mut(&mut self) -> &mut Vec<T> {
+        &mut self.ops
+    }
+}
+
+impl<T> SequenceInput<T>
+where
+    T: SequenceOp,
+{
+    /// Encodes the sequence for the target. Each operation is written as its op code (`u32`), its
+    /// number of arguments (`u32`), and the arguments (`u64` each), all little-endian.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.ops.len() * 24);
+        for op in &self.ops {
+            bytes.extend_from_slice(&op.opcode().to_le_bytes());
+            bytes.extend_from_slice(&(op.args().len() as u32).to_le_bytes());
+            for arg in op.args() {
+                bytes.extend_from_slice(&arg.to_le_bytes());
+            }
+        }
+        bytes
+    }
+}
+
+impl SequenceInput<Operation> {
+    /// Decodes a sequence of [`Operation`]s, in the format written by [`SequenceInput::encode`]
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, Error> {
+        let mut ops = Vec::new();
+        while !bytes.is_empty() {
+            if bytes.len() < 8 {
+                return Err(Error::illegal_argument("Truncated operation header"));
+            }
+            let opcode = u32::from_le_bytes(bytes[..4].try_into().unwrap());
+            let argc = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+            bytes = &bytes[8..];
+            if bytes.len() / 8 < argc {
+                return Err(Error::illegal_argument("Truncated operation arguments"));
+            }
+            let args = bytes[..argc * 8]
+                .chunks_exact(8)
+                .map(|arg| u64::from_le_bytes(arg.try_into().unwrap()))
+                .collect();
+            bytes = &bytes[argc * 8..];
+            ops.push(Operation::new(opcode, args));
+        }
+        Ok(Self { ops })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{Operation, SequenceInput};
+
+    #[test]
+    fn test_sequence_roundtrip() {
+        let input = SequenceInput::new(vec![
+            Operation::new(2, vec![0x1000, 7]),
+            Operation::new(0, vec![]),
+            Operation::new(1, vec![u64::MAX]),
+        ]);
+        let encoded = input.encode();
+        assert_eq!(encoded.len(), 3 * 8 + 3 * 8);
+        assert_eq!(SequenceInput::decode(&encoded).unwrap(), input);
+        assert!(SequenceInput::decode(&encoded[..encoded.len() - 1]).is_err());
+    }
+}
@@ -108,6 +108,7 @@ pub mod observers;
 pub mod schedulers;
 pub mod stages;
 pub mod state;
+pub mod triage;
 
 pub use fuzzer::*;
 pub use libafl_bolts::Error;
@@ -117,7 +118,7 @@ pub use libafl_bolts::Error;
 pub mod prelude {
     pub use super::{
         corpus::*, events::*, executors::*, feedbacks::*, fuzzer::*, generators::*, inputs::*,
-        monitors::*, mutators::*, observers::*, schedulers::*, stages::*, state::*, *,
+        monitors::*, mutators::*, observers::*, schedulers::*, stages::*, state::*, triage::*, *,
     };
 }
 
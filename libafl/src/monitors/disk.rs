@@ -97,6 +97,17 @@ exec_sec = {}
                 )
                 .expect("Failed to write to the TOML file");
 
+                if let Some(label) = &client.label {
+                    // Labels are user input, a JSON string is a valid TOML basic string
+                    writeln!(&mut file, "label = {}", json!(label))
+                        .expect("Failed to write to the TOML file");
+                }
+
+                if let Some(fingerprint) = &client.fingerprint {
+                    writeln!(&mut file, "fingerprint = \"{fingerprint}\"")
+                        .expect("Failed to write to the TOML file");
+                }
+
                 for (key, val) in &client.user_monitor {
                     let k: String = key
                         .chars()
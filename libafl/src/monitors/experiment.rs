@@ -0,0 +1,333 @@
+//! The [`ExperimentMonitor`] wraps a base monitor and compares the two pipelines of an
+//! [`Experiment`], writing a summary comparison to a file.
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, time::Duration};
+use std::{fs, path::PathBuf};
+
+use hashbrown::HashMap;
+use libafl_bolts::{current_time, format_duration_hms, ClientId};
+
+use crate::{
+    events::{EventConfig, Experiment, ExperimentArm},
+    monitors::{ClientStats, Monitor, UserStatsValue},
+};
+
+/// The statistics of one pipeline of an [`Experiment`]
+#[derive(Debug, Clone, Default)]
+pub struct ArmStats {
+    /// The number of clients running the pipeline
+    pub clients: usize,
+    /// The executions of all clients
+    pub executions: u64,
+    /// The executions per second of all clients
+    pub execs_per_sec: f64,
+    /// The largest corpus of a client
+    pub corpus_size: u64,
+    /// The objectives found by all clients
+    pub objective_size: u64,
+    /// The best coverage of a client, per user stat reporting a ratio, e.g. the edges of a map
+    pub coverage: HashMap<Cow<'static, str>, (u64, u64)>,
+}
+
+impl ArmStats {
+    /// Adds the statistics of a client of the pipeline
+    fn add_client(&mut self, client: &mut ClientStats, cur_time: Duration) {
+        self.clients += 1;
+        self.executions += client.executions;
+        self.execs_per_sec += client.execs_per_sec(cur_time);
+        self.corpus_size = self.corpus_size.max(client.corpus_size);
+        self.objective_size += client.objective_size;
+        for (name, stat) in &client.user_monitor {
+            if let UserStatsValue::Ratio(covered, total) = stat.value() {
+                let best = self.coverage.entry(name.clone()).or_insert((0, *total));
+                if *covered > best.0 {
+                    *best = (*covered, *total);
+                }
+            }
+        }
+    }
+}
+
+/// A summary comparing the two pipelines of an [`Experiment`]
+#[derive(Debug, Clone)]
+pub struct ExperimentSummary {
+    /// The time since the start of the experiment
+    pub run_time: Duration,
+    /// The statistics of pipeline A
+    pub a: ArmStats,
+    /// The statistics of pipeline B
+    pub b: ArmStats,
+}
+
+/// Formats the ratio of the values of B and A
+fn ratio(a: f64, b: f64) -> String {
+    if a > 0.0 {
+        format!("{:.2}x", b / a)
+    } else {
+        String::from("-")
+    }
+}
+
+impl fmt::Display for ExperimentSummary {
+    #[allow(clippy::cast_precision_loss)]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "A/B experiment after {}",
+            format_duration_hms(&self.run_time)
+        )?;
+        writeln!(f, "{:<20} {:>16} {:>16} {:>8}", "", "A", "B", "B/A")?;
+        let rows = [
+            ("clients", self.a.clients as f64, self.b.clients as f64),
+            (
+                "executions",
+                self.a.executions as f64,
+                self.b.executions as f64,
+            ),
+            ("exec/sec", self.a.execs_per_sec, self.b.execs_per_sec),
+            (
+                "corpus",
+                self.a.corpus_size as f64,
+                self.b.corpus_size as f64,
+            ),
+            (
+                "objectives",
+                self.a.objective_size as f64,
+                self.b.objective_size as f64,
+            ),
+        ];
+        for (name, a, b) in rows {
+            writeln!(f, "{name:<20} {a:>16.0} {b:>16.0} {:>8}", ratio(a, b))?;
+        }
+
+        let mut names: Vec<_> = self
+            .a
+            .coverage
+            .keys()
+            .chain(self.b.coverage.keys())
+            .collect();
+        names.sort();
+        names.dedup();
+        for name in names {
+            let a = self.a.coverage.get(name).copied().unwrap_or_default();
+            let b = self.b.coverage.get(name).copied().unwrap_or_default();
+            writeln!(
+                f,
+                "{name:<20} {:>16} {:>16} {:>8}",
+                format!("{}/{}", a.0, a.1),
+                format!("{}/{}", b.0, b.1),
+                ratio(a.0 as f64, b.0 as f64)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a monitor and compares the clients of the two pipelines of an [`Experiment`], telling
+/// them apart by their [`EventConfig`]. The summary is written to a file periodically, and a
+/// last time when the broker shuts down.
+#[derive(Debug, Clone)]
+pub struct ExperimentMonitor<M>
+where
+    M: Monitor,
+{
+    base: M,
+    configuration_a: EventConfig,
+    configuration_b: EventConfig,
+    summary_file: PathBuf,
+    last_update: Duration,
+    update_interval: Duration,
+}
+
+impl<M> Monitor for ExperimentMonitor<M>
+where
+    M: Monitor,
+{
+    /// The client monitor, mutable
+    fn client_stats_mut(&mut self) -> &mut Vec<ClientStats> {
+        self.base.client_stats_mut()
+    }
+
+    /// The client monitor
+    fn client_stats(&self) -> &[ClientStats] {
+        self.base.client_stats()
+    }
+
+    /// Time this fuzzing run stated
+    fn start_time(&self) -> Duration {
+        self.base.start_time()
+    }
+
+    /// Set creation time
+    fn set_start_time(&mut self, time: Duration) {
+        self.base.set_start_time(time);
+    }
+
+    fn aggregate(&mut self, name: &str) {
+        self.base.aggregate(name);
+    }
+
+    fn display(&mut self, event_msg: &str, sender_id: ClientId) {
+        let cur_time = current_time();
+        if cur_time.saturating_sub(self.last_update) >= self.update_interval {
+            self.last_update = cur_time;
+            self.write_summary();
+        }
+
+        self.base.display(event_msg, sender_id);
+    }
+}
+
+impl<M> ExperimentMonitor<M>
+where
+    M: Monitor,
+{
+    /// Creates a new [`ExperimentMonitor`] for the experiment, writing the summary to the file
+    /// every minute
+    #[must_use]
+    pub fn new<P>(experiment: &Experiment, base: M, summary_file: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            base,
+            configuration_a: experiment.configuration(ExperimentArm::A),
+            configuration_b: experiment.configuration(ExperimentArm::B),
+            summary_file: summary_file.into(),
+            last_update: current_time(),
+            update_interval: Duration::from_secs(60),
+        }
+    }
+
+    /// Sets the interval the summary is written in
+    #[must_use]
+    pub fn with_update_interval(mut self, update_interval: Duration) -> Self {
+        self.update_interval = update_interval;
+        self
+    }
+
+    /// The pipeline the client runs, if it reported a testcase already
+    #[must_use]
+    pub fn arm_of(&self, client: &ClientStats) -> Option<ExperimentArm> {
+        let configuration = client.configuration?;
+        if configuration.match_with(&self.configuration_a) {
+            Some(ExperimentArm::A)
+        } else if configuration.match_with(&self.configuration_b) {
+            Some(ExperimentArm::B)
+        } else {
+            None
+        }
+    }
+
+    /// Compares the statistics of both pipelines
+    pub fn summary(&mut self) -> ExperimentSummary {
+        let cur_time = current_time();
+        let mut a = ArmStats::default();
+        let mut b = ArmStats::default();
+        for idx in 0..self.client_stats().len() {
+            let arm = self.arm_of(&self.client_stats()[idx]);
+            let client = &mut self.client_stats_mut()[idx];
+            match arm {
+                Some(ExperimentArm::A) => a.add_client(client, cur_time),
+                Some(ExperimentArm::B) => b.add_client(client, cur_time),
+                None => {}
+            }
+        }
+        ExperimentSummary {
+            run_time: cur_time.saturating_sub(self.start_time()),
+            a,
+            b,
+        }
+    }
+
+    /// Writes the summary to the file
+    fn write_summary(&mut self) {
+        let summary = self.summary();
+        if let Err(err) = fs::write(&self.summary_file, summary.to_string()) {
+            log::error!(
+                "Failed to write the experiment summary to {}: {err}",
+                self.summary_file.display()
+            );
+        }
+    }
+}
+
+impl<M> Drop for ExperimentMonitor<M>
+where
+    M: Monitor,
+{
+    fn drop(&mut self) {
+        // Copies of the monitor outside of the broker never saw a client
+        if self.client_stats_count() == 0 {
+            return;
+        }
+        self.write_summary();
+        log::info!("{}", self.summary());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec};
+    use core::time::Duration;
+    use std::{env::temp_dir, fs};
+
+    use libafl_bolts::{core_affinity::Cores, current_time, ClientId};
+
+    use super::ExperimentMonitor;
+    use crate::{
+        events::{EventConfig, Experiment},
+        monitors::{AggregatorOps, Monitor, NopMonitor, UserStats, UserStatsValue},
+    };
+
+    #[test]
+    fn test_experiment_summary() {
+        let experiment = Experiment::new(
+            &Cores::from(vec![0, 1]),
+            EventConfig::from_name("a"),
+            EventConfig::from_name("b"),
+        )
+        .unwrap();
+        let summary_file =
+            temp_dir().join(format!("libafl_experiment_summary_{}", std::process::id()));
+        let mut monitor = ExperimentMonitor::new(&experiment, NopMonitor::new(), &summary_file);
+
+        for (id, name, executions, covered) in [
+            (0, Some("a"), 10, 5),
+            (1, Some("b"), 20, 8),
+            (2, None, 40, 10),
+        ] {
+            monitor.client_stats_insert(ClientId(id));
+            let client = monitor.client_stats_mut_for(ClientId(id));
+            client.configuration = name.map(EventConfig::from_name);
+            client.executions = executions;
+            client.update_user_stats(
+                "edges".into(),
+                UserStats::new(UserStatsValue::Ratio(covered, 10), AggregatorOps::Avg),
+            );
+        }
+
+        let summary = monitor.summary();
+        assert_eq!((summary.a.clients, summary.b.clients), (1, 1));
+        assert_eq!((summary.a.executions, summary.b.executions), (10, 20));
+        assert_eq!(summary.b.coverage["edges"], (8, 10));
+        let text = summary.to_string();
+        assert!(text.contains("2.00x"));
+        assert!(text.contains("1.60x"));
+
+        // A start time in the future, e.g. after a clock adjustment, does not panic
+        monitor.set_start_time(current_time() + Duration::from_secs(3600));
+        assert_eq!(monitor.summary().run_time, Duration::ZERO);
+
+        drop(monitor);
+        assert!(fs::read_to_string(&summary_file)
+            .unwrap()
+            .starts_with("A/B experiment"));
+        fs::remove_file(summary_file).unwrap();
+    }
+}
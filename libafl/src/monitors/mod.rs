@@ -21,10 +21,16 @@ use core::{fmt, fmt::Write, time::Duration};
 
 #[cfg(feature = "std")]
 pub use disk::{OnDiskJSONMonitor, OnDiskTOMLMonitor};
+#[cfg(feature = "std")]
+pub mod experiment;
+#[cfg(feature = "std")]
+pub use experiment::{ArmStats, ExperimentMonitor, ExperimentSummary};
 use hashbrown::HashMap;
 use libafl_bolts::{current_time, format_duration_hms, ClientId};
 use serde::{Deserialize, Serialize};
 
+use crate::events::EventConfig;
+
 #[cfg(feature = "afl_exec_sec")]
 const CLIENT_STATS_TIME_WINDOW_SECS: u64 = 5; // 5 seconds
 
@@ -335,6 +341,179 @@ fn prettify_float(value: f64) -> String {
     }
 }
 
+/// The name of the user stat carrying the human-readable label of a client, see [`ClientStats::label`]
+pub const CLIENT_LABEL_STAT: &str = "client_label";
+
+/// The name of the user stat carrying the [`CampaignFingerprint`] of a client, see [`ClientStats::fingerprint`]
+pub const CLIENT_FINGERPRINT_STAT: &str = "client_fingerprint";
+
+/// The name of the user stat the monitors show for a client whose [`CampaignFingerprint`] diverges
+/// from the one of the first client, see [`Monitor::check_fingerprint`]
+pub const CONFIG_DRIFT_STAT: &str = "config_drift";
+
+/// A fingerprint of the fuzzing pipeline of a client: its feedbacks, scheduler, mutators and map size.
+///
+/// Clients publish it with [`crate::events::EventFirer::set_client_fingerprint`], and the monitors
+/// warn when clients of the same campaign report different fingerprints, e.g. because a
+/// heterogeneous binary was deployed by accident.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CampaignFingerprint {
+    /// The names of the feedbacks
+    pub feedbacks: Vec<String>,
+    /// The type name of the scheduler
+    pub scheduler: String,
+    /// A hash of the names of the mutators, in order
+    pub mutators_hash: u64,
+    /// The size of the coverage map
+    pub map_size: usize,
+}
+
+impl CampaignFingerprint {
+    /// Creates an empty [`CampaignFingerprint`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the name of a feedback
+    #[must_use]
+    pub fn with_feedback<N>(mut self, name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.feedbacks.push(name.into());
+        self
+    }
+
+    /// Sets the scheduler to the given type
+    #[must_use]
+    pub fn with_scheduler<CS>(mut self) -> Self {
+        self.scheduler = core::any::type_name::<CS>().to_string();
+        self
+    }
+
+    /// Sets the mutators, e.g. from [`crate::mutators::MutatorsTuple::names`]
+    #[must_use]
+    pub fn with_mutators(mut self, names: &[&str]) -> Self {
+        self.mutators_hash = libafl_bolts::hash_std(names.join("\n").as_bytes());
+        self
+    }
+
+    /// Sets the size of the coverage map
+    #[must_use]
+    pub fn with_map_size(mut self, map_size: usize) -> Self {
+        self.map_size = map_size;
+        self
+    }
+
+    /// Parses a fingerprint in the format written by its [`fmt::Display`] implementation
+    #[must_use]
+    pub fn parse(fingerprint: &str) -> Option<Self> {
+        let fields = split_escaped(fingerprint, ';');
+        let [map_size, mutators_hash, feedbacks, scheduler] = fields.as_slice() else {
+            return None;
+        };
+        let feedbacks = feedbacks.strip_prefix("feedbacks=")?;
+        Some(Self {
+            feedbacks: if feedbacks.is_empty() {
+                Vec::new()
+            } else {
+                split_escaped(feedbacks, '|')
+                    .into_iter()
+                    .map(unescape)
+                    .collect()
+            },
+            scheduler: unescape(scheduler.strip_prefix("scheduler=")?),
+            mutators_hash: u64::from_str_radix(mutators_hash.strip_prefix("mutators=")?, 16)
+                .ok()?,
+            map_size: map_size.strip_prefix("map=")?.parse().ok()?,
+        })
+    }
+
+    /// The names of the parts that differ from the other fingerprint
+    #[must_use]
+    pub fn diff(&self, other: &Self) -> Vec<&'static str> {
+        let mut diff = Vec::new();
+        if self.feedbacks != other.feedbacks {
+            diff.push("feedbacks");
+        }
+        if self.scheduler != other.scheduler {
+            diff.push("scheduler");
+        }
+        if self.mutators_hash != other.mutators_hash {
+            diff.push("mutators");
+        }
+        if self.map_size != other.map_size {
+            diff.push("map size");
+        }
+        diff
+    }
+}
+
+/// Splits at each unescaped `separator`, keeping the escapes, see [`CampaignFingerprint::parse`]
+fn split_escaped(value: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            parts.push(&value[start..i]);
+            start = i + 1;
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+/// Removes the escapes written by [`write_escaped`]
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(if c == '\\' {
+            chars.next().unwrap_or(c)
+        } else {
+            c
+        });
+    }
+    unescaped
+}
+
+/// Writes the value with the separators of a [`CampaignFingerprint`] escaped by a backslash
+fn write_escaped(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    for c in value.chars() {
+        if matches!(c, '\\' | ';' | '|') {
+            f.write_char('\\')?;
+        }
+        f.write_char(c)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for CampaignFingerprint {
+    /// Writes `map=<size>;mutators=<hash>;feedbacks=<name>|<name>;scheduler=<type>`, with
+    /// backslashes, `;` and `|` in the names escaped by a backslash
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "map={};mutators={:016x};feedbacks=",
+            self.map_size, self.mutators_hash
+        )?;
+        for (i, feedback) in self.feedbacks.iter().enumerate() {
+            if i > 0 {
+                f.write_char('|')?;
+            }
+            write_escaped(f, feedback)?;
+        }
+        f.write_str(";scheduler=")?;
+        write_escaped(f, &self.scheduler)
+    }
+}
+
 /// A simple struct to keep track of client monitor
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ClientStats {
@@ -363,6 +542,12 @@ pub struct ClientStats {
     pub last_window_time: Duration,
     /// the start time of the client
     pub start_time: Duration,
+    /// The human-readable label of this client, e.g. `asan` or `cmplog`, if it registered one
+    pub label: Option<Cow<'static, str>>,
+    /// The [`CampaignFingerprint`] of this client, if it published one
+    pub fingerprint: Option<CampaignFingerprint>,
+    /// The [`EventConfig`] of this client, known once it reported a testcase
+    pub configuration: Option<EventConfig>,
     /// User-defined monitor
     pub user_monitor: HashMap<Cow<'static, str>, UserStats>,
     /// Client performance statistics
@@ -464,15 +649,38 @@ impl ClientStats {
         prettify_float(self.execs_per_sec(cur_time))
     }
 
-    /// Update the user-defined stat with name and value
+    /// Update the user-defined stat with name and value.
+    /// The [`CLIENT_LABEL_STAT`] sets the [`ClientStats::label`] instead,
+    /// and the [`CLIENT_FINGERPRINT_STAT`] the [`ClientStats::fingerprint`].
     pub fn update_user_stats(
         &mut self,
         name: Cow<'static, str>,
         value: UserStats,
     ) -> Option<UserStats> {
+        if name == CLIENT_LABEL_STAT {
+            if let UserStatsValue::String(label) = value.value() {
+                self.label = Some(label.clone());
+                return None;
+            }
+        }
+        if name == CLIENT_FINGERPRINT_STAT {
+            if let UserStatsValue::String(fingerprint) = value.value() {
+                self.fingerprint = CampaignFingerprint::parse(fingerprint);
+                return None;
+            }
+        }
         self.user_monitor.insert(name, value)
     }
 
+    /// The name of this client in monitors: `#<id>`, followed by the label, if any
+    #[must_use]
+    pub fn display_name(&self, client_id: ClientId) -> String {
+        match &self.label {
+            Some(label) => format!("#{} ({label})", client_id.0),
+            None => format!("#{}", client_id.0),
+        }
+    }
+
     #[must_use]
     /// Get a user-defined stat using the name
     pub fn get_user_stats(&self, name: &str) -> Option<&UserStats> {
@@ -503,6 +711,12 @@ pub trait Monitor {
     /// Show the monitor to the user
     fn display(&mut self, event_msg: &str, sender_id: ClientId);
 
+    /// Show the totals to the user one last time, when the campaign ends, e.g. after ctrl-c.
+    /// By default, the monitor is displayed with a `Final report` event.
+    fn display_final(&mut self) {
+        self.display("Final report", ClientId(0));
+    }
+
     /// Amount of elements in the corpus (combined for all children)
     fn corpus_size(&self) -> u64 {
         self.client_stats()
@@ -510,6 +724,14 @@ pub trait Monitor {
             .fold(0_u64, |acc, x| acc + x.corpus_size)
     }
 
+    /// The name of a client in monitors: `#<id>`, followed by its label, if any
+    fn client_name(&self, client_id: ClientId) -> String {
+        self.client_stats().get(client_id.0 as usize).map_or_else(
+            || format!("#{}", client_id.0),
+            |client| client.display_name(client_id),
+        )
+    }
+
     /// Count the number of enabled client stats
     fn client_stats_count(&self) -> usize {
         self.client_stats()
@@ -581,6 +803,59 @@ pub trait Monitor {
 
     /// Aggregate the results in case there're multiple clients
     fn aggregate(&mut self, _name: &str) {}
+
+    /// Compares the [`CampaignFingerprint`] of the client to the one of the first client that
+    /// published one, and warns about the parts that differ.
+    /// The differing parts are also shown by the monitors, as the [`CONFIG_DRIFT_STAT`] user stat
+    /// of the client. Returns the differing parts.
+    fn check_fingerprint(&mut self, client_id: ClientId) -> Vec<&'static str> {
+        let Some(fingerprint) = self
+            .client_stats()
+            .get(client_id.0 as usize)
+            .and_then(|client| client.fingerprint.as_ref())
+        else {
+            return Vec::new();
+        };
+        let Some((reference_id, reference)) = self
+            .client_stats()
+            .iter()
+            .enumerate()
+            .find_map(|(id, client)| client.fingerprint.as_ref().map(|f| (id, f)))
+        else {
+            return Vec::new();
+        };
+        if reference_id == client_id.0 as usize {
+            log::info!(
+                "Campaign fingerprint of client {}: {fingerprint}",
+                self.client_name(client_id)
+            );
+            return Vec::new();
+        }
+
+        let diff = fingerprint.diff(reference);
+        if diff.is_empty() {
+            self.client_stats_mut_for(client_id)
+                .user_monitor
+                .remove(CONFIG_DRIFT_STAT);
+        } else {
+            log::warn!(
+                "Client {} diverges from client {} in the campaign configuration ({}): {fingerprint} vs. {reference}",
+                self.client_name(client_id),
+                self.client_name(ClientId(reference_id as u32)),
+                diff.join(", ")
+            );
+            let drift = format!(
+                "{} differ from {}",
+                diff.join(", "),
+                self.client_name(ClientId(reference_id as u32))
+            );
+            self.client_stats_mut_for(client_id).update_user_stats(
+                Cow::from(CONFIG_DRIFT_STAT),
+                UserStats::new(UserStatsValue::String(drift.into()), AggregatorOps::None),
+            );
+        }
+        diff
+    }
 }
 
 /// Monitor that print exactly nothing.
@@ -690,9 +965,9 @@ impl Monitor for SimplePrintingMonitor {
             .collect::<Vec<_>>();
         userstats.sort();
         println!(
-            "[{} #{}] run time: {}, clients: {}, corpus: {}, objectives: {}, executions: {}, exec/sec: {}, {}",
+            "[{} {}] run time: {}, clients: {}, corpus: {}, objectives: {}, executions: {}, exec/sec: {}, {}",
             event_msg,
-            sender_id.0,
+            self.client_name(sender_id),
             format_duration_hms(&(current_time() - self.start_time)),
             self.client_stats_count(),
             self.corpus_size(),
@@ -766,9 +1041,9 @@ where
 
     fn display(&mut self, event_msg: &str, sender_id: ClientId) {
         let mut fmt = format!(
-            "[{} #{}] run time: {}, clients: {}, corpus: {}, objectives: {}, executions: {}, exec/sec: {}",
+            "[{} {}] run time: {}, clients: {}, corpus: {}, objectives: {}, executions: {}, exec/sec: {}",
             event_msg,
-            sender_id.0,
+            self.client_name(sender_id),
             format_duration_hms(&(current_time() - self.start_time)),
             self.client_stats_count(),
             self.corpus_size(),
@@ -1310,3 +1585,66 @@ impl Default for ClientPerfMonitor {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use libafl_bolts::ClientId;
+
+    use super::{
+        CampaignFingerprint, Monitor, NopMonitor, UserStatsValue, CLIENT_FINGERPRINT_STAT,
+        CONFIG_DRIFT_STAT,
+    };
+    use crate::monitors::{AggregatorOps, UserStats};
+
+    #[test]
+    fn test_fingerprint_roundtrip() {
+        let fingerprint = CampaignFingerprint::new()
+            .with_feedback("map;edges")
+            .with_feedback("time|out\\")
+            .with_scheduler::<[u8; 4]>()
+            .with_mutators(&["BitFlipMutator"])
+            .with_map_size(65536);
+        let parsed = CampaignFingerprint::parse(&fingerprint.to_string()).unwrap();
+        assert_eq!(parsed, fingerprint);
+        assert_eq!(
+            CampaignFingerprint::parse(&CampaignFingerprint::new().to_string()).unwrap(),
+            CampaignFingerprint::new()
+        );
+        assert!(CampaignFingerprint::parse("map=1;mutators=0").is_none());
+    }
+
+    #[test]
+    fn test_fingerprint_drift() {
+        let mut monitor = NopMonitor::new();
+        let publish = |monitor: &mut NopMonitor, id: u32, map_size: usize| {
+            let fingerprint = CampaignFingerprint::new().with_map_size(map_size);
+            monitor.client_stats_insert(ClientId(id));
+            monitor
+                .client_stats_mut_for(ClientId(id))
+                .update_user_stats(
+                    CLIENT_FINGERPRINT_STAT.into(),
+                    UserStats::new(
+                        UserStatsValue::String(fingerprint.to_string().into()),
+                        AggregatorOps::None,
+                    ),
+                );
+            monitor.check_fingerprint(ClientId(id))
+        };
+
+        assert!(publish(&mut monitor, 0, 1024).is_empty());
+        assert_eq!(publish(&mut monitor, 1, 2048), ["map size"]);
+        assert!(monitor
+            .client_stats_for(ClientId(1))
+            .get_user_stats(CONFIG_DRIFT_STAT)
+            .is_some());
+
+        // The drift is cleared once the client is redeployed
+        assert!(publish(&mut monitor, 1, 1024).is_empty());
+        assert!(monitor
+            .client_stats_for(ClientId(1))
+            .get_user_stats(CONFIG_DRIFT_STAT)
+            .is_none());
+    }
+}
@@ -201,6 +201,8 @@ impl ItemGeometry {
 
 #[derive(Debug, Default, Clone)]
 pub struct ClientTuiContext {
+    /// The label of the client, if it registered one
+    pub label: Option<Cow<'static, str>>,
     pub corpus: u64,
     pub objectives: u64,
     pub executions: u64,
@@ -216,6 +218,7 @@ pub struct ClientTuiContext {
 
 impl ClientTuiContext {
     pub fn grab_data(&mut self, client: &ClientStats, exec_sec: String) {
+        self.label.clone_from(&client.label);
         self.corpus = client.corpus_size;
         self.objectives = client.objective_size;
         self.executions = client.executions;
@@ -388,10 +391,10 @@ impl Monitor for TuiMonitor {
         }
 
         self.client_stats_insert(sender_id);
+        let sender = self.client_name(sender_id);
         let client = self.client_stats_mut_for(sender_id);
         let exec_sec = client.execs_per_sec_pretty(cur_time);
 
-        let sender = format!("#{}", sender_id.0);
         let pad = if event_msg.len() + sender.len() < 13 {
             " ".repeat(13 - event_msg.len() - sender.len())
         } else {
@@ -246,9 +246,16 @@ impl TuiUI {
     }
 
     fn draw_client_ui(&mut self, f: &mut Frame, app: &Arc<RwLock<TuiContext>>, area: Rect) {
+        let label = app
+            .read()
+            .unwrap()
+            .clients
+            .get(&self.clients_idx)
+            .and_then(|client| client.label.clone())
+            .map_or_else(String::new, |label| format!(" ({label})"));
         let client_block = Block::default()
             .title(Span::styled(
-                format!("client #{} (l/r arrows to switch)", self.clients_idx),
+                format!("client #{}{label} (l/r arrows to switch)", self.clients_idx),
                 Style::default()
                     .fg(Color::LightCyan)
                     .add_modifier(Modifier::BOLD),
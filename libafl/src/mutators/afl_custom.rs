@@ -0,0 +1,560 @@
+//! Compatibility layer for [AFL++ custom mutators](https://aflplus.plus/docs/custom_mutators/),
+//! loading an existing custom mutator shared object unchanged.
+//!
+//! An [`AflCustomLibrary`] loads the shared object and calls its `afl_custom_*` functions. Share
+//! it between the pieces using the callbacks it implements:
+//! - the [`AflCustomMutator`] calls `afl_custom_fuzz`,
+//! - the [`AflPostProcessExecutor`](crate::executors::AflPostProcessExecutor) calls `afl_custom_post_process` on each input before running
+//!   the target,
+//! - the [`AflCustomTrimStage`](crate::stages::AflCustomTrimStage) trims each corpus entry once with `afl_custom_init_trim`,
+//!   `afl_custom_trim` and `afl_custom_post_trim`.
+//!
+//! The `afl` state passed to `afl_custom_init` is a null pointer, so mutators dereferencing it are
+//! not supported.
+
+use alloc::{borrow::Cow, rc::Rc, vec::Vec};
+use core::{
+    cell::RefCell,
+    ffi::{c_char, c_uint, c_void, CStr},
+    fmt, ptr, slice,
+};
+use std::{ffi::CString, os::unix::ffi::OsStrExt, path::Path};
+
+use libafl_bolts::{rands::Rand, Named};
+
+use crate::{
+    corpus::Corpus,
+    inputs::HasMutatorBytes,
+    mutators::{MutationResult, Mutator},
+    random_corpus_id,
+    state::{HasCorpus, HasMaxSize, HasRand},
+    Error,
+};
+
+type InitFn = unsafe extern "C" fn(afl: *mut c_void, seed: c_uint) -> *mut c_void;
+type DeinitFn = unsafe extern "C" fn(data: *mut c_void);
+type FuzzFn = unsafe extern "C" fn(
+    data: *mut c_void,
+    buf: *mut u8,
+    buf_size: usize,
+    out_buf: *mut *mut u8,
+    add_buf: *mut u8,
+    add_buf_size: usize,
+    max_size: usize,
+) -> usize;
+type PostProcessFn = unsafe extern "C" fn(
+    data: *mut c_void,
+    buf: *mut u8,
+    buf_size: usize,
+    out_buf: *mut *mut u8,
+) -> usize;
+type InitTrimFn = unsafe extern "C" fn(data: *mut c_void, buf: *mut u8, buf_size: usize) -> i32;
+type TrimFn = unsafe extern "C" fn(data: *mut c_void, out_buf: *mut *mut u8) -> usize;
+type PostTrimFn = unsafe extern "C" fn(data: *mut c_void, success: u8) -> i32;
+
+/// The trim callbacks of a custom mutator, only used if all three are present
+#[derive(Clone, Copy)]
+struct TrimFns {
+    init_trim: InitTrimFn,
+    trim: TrimFn,
+    post_trim: PostTrimFn,
+}
+
+/// A loaded AFL++ custom mutator shared object
+pub struct AflCustomLibrary {
+    handle: *mut c_void,
+    data: *mut c_void,
+    deinit: Option<DeinitFn>,
+    fuzz: Option<FuzzFn>,
+    post_process: Option<PostProcessFn>,
+    trim: Option<TrimFns>,
+}
+
+impl fmt::Debug for AflCustomLibrary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AflCustomLibrary")
+            .field("handle", &self.handle)
+            .field("fuzz", &self.fuzz.is_some())
+            .field("post_process", &self.post_process.is_some())
+            .field("trim", &self.trim.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// An [`AflCustomLibrary`], shared between the mutator, the executor and the trim stage
+pub type SharedAflCustomLibrary = Rc<RefCell<AflCustomLibrary>>;
+
+/// The last `dlerror`, as error
+unsafe fn dl_error(msg: &str) -> Error {
+    let err = libc::dlerror();
+    if err.is_null() {
+        Error::unknown(msg)
+    } else {
+        Error::unknown(format!(
+            "{msg}: {}",
+            CStr::from_ptr(err as *const c_char).to_string_lossy()
+        ))
+    }
+}
+
+impl AflCustomLibrary {
+    /// Loads the custom mutator shared object and calls its `afl_custom_init` with the seed.
+    ///
+    /// # Safety
+    /// The shared object runs arbitrary code when loaded, and its functions must have the
+    /// signatures of the AFL++ custom mutator API.
+    pub unsafe fn load<P>(path: P, seed: u32) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|_| Error::illegal_argument("Custom mutator path contains a NUL byte"))?;
+        let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW);
+        if handle.is_null() {
+            return Err(dl_error("Failed to load the custom mutator"));
+        }
+        let symbol = |name: &[u8]| {
+            let symbol = libc::dlsym(handle, name.as_ptr().cast());
+            (!symbol.is_null()).then_some(symbol)
+        };
+        Self::with_symbols(handle, symbol, seed)
+    }
+
+    /// Resolves the `afl_custom_*` functions with `symbol` and calls `afl_custom_init`.
+    /// The handle, if not null, is closed on drop, including on errors.
+    unsafe fn with_symbols<F>(handle: *mut c_void, symbol: F, seed: u32) -> Result<Self, Error>
+    where
+        F: Fn(&[u8]) -> Option<*mut c_void>,
+    {
+        let trim = match (
+            symbol(b"afl_custom_init_trim\0"),
+            symbol(b"afl_custom_trim\0"),
+            symbol(b"afl_custom_post_trim\0"),
+        ) {
+            (Some(init_trim), Some(trim), Some(post_trim)) => Some(TrimFns {
+                init_trim: core::mem::transmute::<*mut c_void, InitTrimFn>(init_trim),
+                trim: core::mem::transmute::<*mut c_void, TrimFn>(trim),
+                post_trim: core::mem::transmute::<*mut c_void, PostTrimFn>(post_trim),
+            }),
+            _ => None,
+        };
+        let mut library = Self {
+            handle,
+            data: ptr::null_mut(),
+            deinit: symbol(b"afl_custom_deinit\0")
+                .map(|f| core::mem::transmute::<*mut c_void, DeinitFn>(f)),
+            fuzz: symbol(b"afl_custom_fuzz\0")
+                .map(|f| core::mem::transmute::<*mut c_void, FuzzFn>(f)),
+            post_process: symbol(b"afl_custom_post_process\0")
+                .map(|f| core::mem::transmute::<*mut c_void, PostProcessFn>(f)),
+            trim,
+        };
+
+        let Some(init) = symbol(b"afl_custom_init\0") else {
+            return Err(Error::illegal_argument(
+                "The custom mutator has no afl_custom_init",
+            ));
+        };
+        let init: InitFn = core::mem::transmute(init);
+        let data = init(ptr::null_mut(), seed);
+        if data.is_null() {
+            return Err(Error::unknown("afl_custom_init failed"));
+        }
+        library.data = data;
+        Ok(library)
+    }
+
+    /// Loads the custom mutator, see [`AflCustomLibrary::load`], ready to be shared
+    ///
+    /// # Safety
+    /// See [`AflCustomLibrary::load`].
+    pub unsafe fn load_shared<P>(path: P, seed: u32) -> Result<SharedAflCustomLibrary, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Rc::new(RefCell::new(Self::load(path, seed)?)))
+    }
+
+    /// Checks if the custom mutator implements `afl_custom_fuzz`
+    #[must_use]
+    pub fn has_fuzz(&self) -> bool {
+        self.fuzz.is_some()
+    }
+
+    /// Checks if the custom mutator implements `afl_custom_post_process`
+    #[must_use]
+    pub fn has_post_process(&self) -> bool {
+        self.post_process.is_some()
+    }
+
+    /// Checks if the custom mutator implements the trim callbacks
+    #[must_use]
+    pub fn has_trim(&self) -> bool {
+        self.trim.is_some()
+    }
+
+    /// Copies a buffer returned by the custom mutator, which keeps ownership of it
+    unsafe fn returned(out_buf: *const u8, len: usize) -> Vec<u8> {
+        if out_buf.is_null() || len == 0 {
+            Vec::new()
+        } else {
+            slice::from_raw_parts(out_buf, len).to_vec()
+        }
+    }
+
+    /// Mutates the buffer with `afl_custom_fuzz`, splicing with `add_buf` if the mutator wants to.
+    /// Returns `None` if the mutator doesn't implement it or produced nothing.
+    pub fn fuzz(
+        &mut self,
+        buf: &mut [u8],
+        add_buf: Option<&mut [u8]>,
+        max_size: usize,
+    ) -> Option<Vec<u8>> {
+        let fuzz = self.fuzz?;
+        let (add_buf, add_buf_size) = add_buf.map_or((ptr::null_mut(), 0), |add_buf| {
+            (add_buf.as_mut_ptr(), add_buf.len())
+        });
+        let mut out_buf = ptr::null_mut();
+        let out = unsafe {
+            let len = fuzz(
+                self.data,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut out_buf,
+                add_buf,
+                add_buf_size,
+                max_size,
+            );
+            Self::returned(out_buf, len.min(max_size))
+        };
+        (!out.is_empty()).then_some(out)
+    }
+
+    /// Post-processes the buffer with `afl_custom_post_process`.
+    /// Returns `None` if the mutator doesn't implement it.
+    pub fn post_process(&mut self, buf: &mut [u8]) -> Option<Vec<u8>> {
+        let post_process = self.post_process?;
+        let mut out_buf = ptr::null_mut();
+        unsafe {
+            let len = post_process(self.data, buf.as_mut_ptr(), buf.len(), &mut out_buf);
+            Some(Self::returned(out_buf, len))
+        }
+    }
+
+    /// Starts trimming the buffer with `afl_custom_init_trim`, returning the number of steps.
+    /// Returns `None` if the mutator doesn't implement trimming or failed.
+    pub fn init_trim(&mut self, buf: &mut [u8]) -> Option<i32> {
+        let trim = self.trim?;
+        let steps = unsafe { (trim.init_trim)(self.data, buf.as_mut_ptr(), buf.len()) };
+        (steps >= 0).then_some(steps)
+    }
+
+    /// The next trimmed candidate, from `afl_custom_trim`
+    pub fn trim(&mut self) -> Option<Vec<u8>> {
+        let trim = self.trim?;
+        let mut out_buf = ptr::null_mut();
+        unsafe {
+            let len = (trim.trim)(self.data, &mut out_buf);
+            Some(Self::returned(out_buf, len))
+        }
+    }
+
+    /// Reports whether the last candidate kept the behavior, with `afl_custom_post_trim`.
+    /// Returns the next step, negative on errors.
+    pub fn post_trim(&mut self, success: bool) -> i32 {
+        self.trim.map_or(-1, |trim| unsafe {
+            (trim.post_trim)(self.data, u8::from(success))
+        })
+    }
+}
+
+impl Drop for AflCustomLibrary {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(deinit) = self.deinit {
+                if !self.data.is_null() {
+                    deinit(self.data);
+                }
+            }
+            if !self.handle.is_null() {
+                libc::dlclose(self.handle);
+            }
+        }
+    }
+}
+
+/// A [`Mutator`] calling `afl_custom_fuzz` of an AFL++ custom mutator, passing a random corpus
+/// entry for splicing
+#[derive(Debug)]
+pub struct AflCustomMutator {
+    library: SharedAflCustomLibrary,
+}
+
+impl AflCustomMutator {
+    /// Creates a new [`AflCustomMutator`] for the loaded custom mutator
+    #[must_use]
+    pub fn new(library: SharedAflCustomLibrary) -> Self {
+        Self { library }
+    }
+}
+
+impl<I, S> Mutator<I, S> for AflCustomMutator
+where
+    I: HasMutatorBytes,
+    S: HasCorpus + HasRand + HasMaxSize,
+    S::Input: HasMutatorBytes,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let max_size = state.max_size();
+        let mut add_buf = if state.corpus().count() == 0 {
+            None
+        } else {
+            let id = random_corpus_id!(state.corpus(), state.rand_mut());
+            Some(state.corpus().cloned_input_for_id(id)?.bytes().to_vec())
+        };
+
+        let mut buf = input.bytes().to_vec();
+        let Some(out) = self
+            .library
+            .borrow_mut()
+            .fuzz(&mut buf, add_buf.as_deref_mut(), max_size)
+        else {
+            return Ok(MutationResult::Skipped);
+        };
+        if out == buf {
+            return Ok(MutationResult::Skipped);
+        }
+        input.resize(out.len(), 0);
+        input.bytes_mut().copy_from_slice(&out);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for AflCustomMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("AflCustomMutator");
+        &NAME
+    }
+}
+
+/// An in-process stand-in for a custom mutator shared object, to test the callers of the
+/// [`AflCustomLibrary`] without compiling and loading a library
+#[cfg(test)]
+pub(crate) mod test {
+    use alloc::{boxed::Box, rc::Rc, vec::Vec};
+    use core::{cell::RefCell, ffi::c_void, ptr, slice};
+
+    use super::{AflCustomLibrary, SharedAflCustomLibrary};
+    use crate::Error;
+
+    /// The state of the fake mutator, returned by `afl_custom_init`
+    #[derive(Default)]
+    struct FakeMutator {
+        out: Vec<u8>,
+        trimmed: Vec<u8>,
+        candidate: Vec<u8>,
+        pos: usize,
+        attempts: i32,
+    }
+
+    unsafe fn fake<'a>(data: *mut c_void) -> &'a mut FakeMutator {
+        &mut *data.cast::<FakeMutator>()
+    }
+
+    unsafe fn bytes<'a>(buf: *mut u8, len: usize) -> &'a [u8] {
+        if buf.is_null() {
+            &[]
+        } else {
+            slice::from_raw_parts(buf, len)
+        }
+    }
+
+    unsafe extern "C" fn fake_init(_afl: *mut c_void, _seed: u32) -> *mut c_void {
+        Box::into_raw(Box::<FakeMutator>::default()).cast()
+    }
+
+    unsafe extern "C" fn fake_deinit(data: *mut c_void) {
+        drop(Box::from_raw(data.cast::<FakeMutator>()));
+    }
+
+    /// Reverses the buffer and appends the splice buffer
+    unsafe extern "C" fn fake_fuzz(
+        data: *mut c_void,
+        buf: *mut u8,
+        buf_size: usize,
+        out_buf: *mut *mut u8,
+        add_buf: *mut u8,
+        add_buf_size: usize,
+        _max_size: usize,
+    ) -> usize {
+        let fake = fake(data);
+        fake.out = bytes(buf, buf_size).iter().rev().copied().collect();
+        fake.out.extend_from_slice(bytes(add_buf, add_buf_size));
+        *out_buf = fake.out.as_mut_ptr();
+        fake.out.len()
+    }
+
+    /// Prefixes the buffer with `pp:`
+    unsafe extern "C" fn fake_post_process(
+        data: *mut c_void,
+        buf: *mut u8,
+        buf_size: usize,
+        out_buf: *mut *mut u8,
+    ) -> usize {
+        let fake = fake(data);
+        fake.out = b"pp:".to_vec();
+        fake.out.extend_from_slice(bytes(buf, buf_size));
+        *out_buf = fake.out.as_mut_ptr();
+        fake.out.len()
+    }
+
+    /// Trims one byte per step, moving on to the next byte if the removal failed
+    unsafe extern "C" fn fake_init_trim(data: *mut c_void, buf: *mut u8, buf_size: usize) -> i32 {
+        let fake = fake(data);
+        fake.trimmed = bytes(buf, buf_size).to_vec();
+        fake.pos = 0;
+        fake.attempts = 0;
+        i32::try_from(buf_size).unwrap()
+    }
+
+    unsafe extern "C" fn fake_trim(data: *mut c_void, out_buf: *mut *mut u8) -> usize {
+        let fake = fake(data);
+        fake.candidate.clone_from(&fake.trimmed);
+        if fake.pos < fake.candidate.len() {
+            fake.candidate.remove(fake.pos);
+        }
+        *out_buf = fake.candidate.as_mut_ptr();
+        fake.candidate.len()
+    }
+
+    unsafe extern "C" fn fake_post_trim(data: *mut c_void, success: u8) -> i32 {
+        let fake = fake(data);
+        if success == 0 {
+            fake.pos += 1;
+        } else {
+            fake.trimmed.clone_from(&fake.candidate);
+        }
+        fake.attempts += 1;
+        fake.attempts
+    }
+
+    /// A fake custom mutator, exporting only the `afl_custom_*` functions named in `exported`
+    pub(crate) fn fake_library(exported: &[&str]) -> Result<AflCustomLibrary, Error> {
+        let symbol = |name: &[u8]| {
+            let name = core::str::from_utf8(&name[..name.len() - 1]).unwrap();
+            let name = name.strip_prefix("afl_custom_")?;
+            let function = match name {
+                "init" => fake_init as *mut c_void,
+                "deinit" => fake_deinit as *mut c_void,
+                "fuzz" => fake_fuzz as *mut c_void,
+                "post_process" => fake_post_process as *mut c_void,
+                "init_trim" => fake_init_trim as *mut c_void,
+                "trim" => fake_trim as *mut c_void,
+                "post_trim" => fake_post_trim as *mut c_void,
+                _ => return None,
+            };
+            exported.contains(&name).then_some(function)
+        };
+        unsafe { AflCustomLibrary::with_symbols(ptr::null_mut(), symbol, 0) }
+    }
+
+    /// A fake custom mutator implementing every callback, ready to be shared
+    pub(crate) fn fake_shared_library() -> SharedAflCustomLibrary {
+        Rc::new(RefCell::new(
+            fake_library(&[
+                "init",
+                "deinit",
+                "fuzz",
+                "post_process",
+                "init_trim",
+                "trim",
+                "post_trim",
+            ])
+            .unwrap(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, vec::Vec};
+    use core::cell::RefCell;
+
+    use super::{
+        test::{fake_library, fake_shared_library},
+        AflCustomLibrary, AflCustomMutator,
+    };
+    use crate::{
+        corpus::{Corpus, Testcase},
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::{MutationResult, Mutator},
+        state::{test::test_std_state, HasCorpus},
+    };
+
+    #[test]
+    fn test_afl_custom_library() {
+        assert!(unsafe { AflCustomLibrary::load("/nonexistent/custom_mutator.so", 0) }.is_err());
+        assert!(fake_library(&["fuzz"]).is_err());
+
+        let mut library = fake_library(&["init", "deinit", "fuzz", "trim", "post_trim"]).unwrap();
+        assert!(library.has_fuzz());
+        assert!(!library.has_post_process());
+        // Trimming needs all three callbacks
+        assert!(!library.has_trim());
+        assert_eq!(library.post_process(&mut [1, 2]), None);
+        assert_eq!(library.init_trim(&mut [1, 2]), None);
+        assert_eq!(library.post_trim(true), -1);
+
+        assert_eq!(
+            library.fuzz(&mut [1, 2, 3], Some(&mut [4, 5]), 16),
+            Some(vec![3, 2, 1, 4, 5])
+        );
+        // The output is cut to the maximum size
+        assert_eq!(
+            library.fuzz(&mut [1, 2, 3], Some(&mut [4, 5]), 4),
+            Some(vec![3, 2, 1, 4])
+        );
+        assert_eq!(library.fuzz(&mut [], None, 16), None);
+    }
+
+    #[test]
+    fn test_afl_custom_mutator() {
+        let mut state = test_std_state::<BytesInput>();
+        let mut mutator = AflCustomMutator::new(fake_shared_library());
+
+        let mut input = BytesInput::new(b"abc".to_vec());
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Mutated
+        );
+        assert_eq!(input.bytes(), b"cba");
+
+        // A palindrome without a corpus entry to splice with doesn't change
+        let mut input = BytesInput::new(b"aba".to_vec());
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Skipped
+        );
+
+        // The only corpus entry is passed for splicing
+        state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"Z".to_vec())))
+            .unwrap();
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Mutated
+        );
+        assert_eq!(input.bytes(), b"abaZ");
+
+        // Without afl_custom_fuzz, the mutator always skips
+        let library = Rc::new(RefCell::new(fake_library(&["init"]).unwrap()));
+        let mut mutator = AflCustomMutator::new(library);
+        let before: Vec<u8> = input.bytes().to_vec();
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Skipped
+        );
+        assert_eq!(input.bytes(), before);
+    }
+}
@@ -0,0 +1,343 @@
+//! Extracts dictionary [`Tokens`] from the target binary itself, similar to the `autodict-ql`
+//! of AFL++, but without building a database of the source.
+//!
+//! Two kinds of tokens are collected:
+//! - printable strings from the read-only data sections, like keywords and format magics, and
+//! - the immediates of comparison instructions, e.g. `cmp eax, 0x46464952` for `RIFF`. These are
+//!   found by a linear sweep over the code sections, which is only supported for `x86` and `x86_64`.
+//!
+//! With the `binary_tokens` feature, the binary is parsed with the `object` crate, so ELF, Mach-O
+//! and PE binaries are supported, and the `BinaryTokensStage` populates the [`Tokens`] at
+//! campaign start. Without it, [`BinaryTokenExtractor::extract_sections`] takes the raw sections.
+
+use alloc::vec::Vec;
+#[cfg(feature = "binary_tokens")]
+use std::{fs, path::Path};
+
+#[cfg(feature = "binary_tokens")]
+use object::{Architecture, Object, ObjectSection, SectionKind};
+
+use crate::mutators::Tokens;
+#[cfg(feature = "binary_tokens")]
+use crate::Error;
+
+/// The default maximal number of tokens extracted from a binary
+pub const DEFAULT_MAX_BINARY_TOKENS: usize = 4096;
+/// The default minimal length of an extracted string
+pub const DEFAULT_MIN_STRING_LEN: usize = 4;
+/// The default maximal length of an extracted string, longer ones are mostly messages
+pub const DEFAULT_MAX_STRING_LEN: usize = 32;
+
+/// Extracts dictionary tokens from a binary, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct BinaryTokenExtractor {
+    min_len: usize,
+    max_len: usize,
+    max_tokens: usize,
+    strings: bool,
+    cmp_immediates: bool,
+    all_immediates: bool,
+}
+
+impl Default for BinaryTokenExtractor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BinaryTokenExtractor {
+    /// Creates a new [`BinaryTokenExtractor`], extracting strings and printable comparison
+    /// immediates
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            min_len: DEFAULT_MIN_STRING_LEN,
+            max_len: DEFAULT_MAX_STRING_LEN,
+            max_tokens: DEFAULT_MAX_BINARY_TOKENS,
+            strings: true,
+            cmp_immediates: true,
+            all_immediates: false,
+        }
+    }
+
+    /// Sets the minimal and maximal length of extracted strings
+    #[must_use]
+    pub fn with_len_range(mut self, min_len: usize, max_len: usize) -> Self {
+        self.min_len = min_len.max(1);
+        self.max_len = max_len.max(self.min_len);
+        self
+    }
+
+    /// Sets the maximal number of extracted tokens.
+    /// Strings and comparison immediates get half of them each, unless one kind leaves some over.
+    #[must_use]
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Enables or disables the extraction of strings from the read-only data
+    #[must_use]
+    pub fn with_strings(mut self, strings: bool) -> Self {
+        self.strings = strings;
+        self
+    }
+
+    /// Enables or disables the extraction of comparison immediates from the code
+    #[must_use]
+    pub fn with_cmp_immediates(mut self, cmp_immediates: bool) -> Self {
+        self.cmp_immediates = cmp_immediates;
+        self
+    }
+
+    /// Also keeps comparison immediates which are not printable, as long as they are not small
+    /// integers. This finds binary magics, at the cost of a noisier dictionary.
+    #[must_use]
+    pub fn with_all_immediates(mut self, all_immediates: bool) -> Self {
+        self.all_immediates = all_immediates;
+        self
+    }
+
+    /// Extracts the tokens of the binary at the given path
+    #[cfg(feature = "binary_tokens")]
+    pub fn extract_file<P>(&self, path: P) -> Result<Tokens, Error>
+    where
+        P: AsRef<Path>,
+    {
+        self.extract(&fs::read(path)?)
+    }
+
+    /// Extracts the tokens of the currently running executable, i.e. of the target of an
+    /// in-process fuzzer
+    #[cfg(feature = "binary_tokens")]
+    pub fn extract_current_exe(&self) -> Result<Tokens, Error> {
+        self.extract_file(std::env::current_exe()?)
+    }
+
+    /// Extracts the tokens of a binary in memory.
+    /// Comparison immediates come first, as they are the most likely to matter.
+    #[cfg(feature = "binary_tokens")]
+    pub fn extract(&self, binary: &[u8]) -> Result<Tokens, Error> {
+        let file = object::File::parse(binary)
+            .map_err(|err| Error::illegal_argument(format!("Could not parse the binary: {err}")))?;
+        let x86 = matches!(
+            file.architecture(),
+            Architecture::X86_64 | Architecture::X86_64_X32 | Architecture::I386
+        );
+        if self.cmp_immediates && !x86 {
+            log::info!(
+                "Comparison immediates are not supported for {:?}, only extracting strings",
+                file.architecture()
+            );
+        }
+
+        let code = file
+            .sections()
+            .filter(|section| x86 && section.kind() == SectionKind::Text)
+            .filter_map(|section| section.data().ok());
+        let data = file
+            .sections()
+            .filter(|section| {
+                matches!(
+                    section.kind(),
+                    SectionKind::ReadOnlyData
+                        | SectionKind::ReadOnlyDataWithRel
+                        | SectionKind::ReadOnlyString
+                )
+            })
+            .filter_map(|section| section.data().ok());
+        Ok(self.extract_sections(code, data))
+    }
+
+    /// Extracts the tokens of raw sections: comparison immediates from the `x86_code` and strings
+    /// from the read-only `data`. Comparison immediates come first, as they are the most likely
+    /// to matter.
+    pub fn extract_sections<'a, C, D>(&self, x86_code: C, data: D) -> Tokens
+    where
+        C: IntoIterator<Item = &'a [u8]>,
+        D: IntoIterator<Item = &'a [u8]>,
+    {
+        // Each kind is collected on its own, so the bogus immediates of the linear sweep cannot
+        // starve the strings, and the other way around
+        let mut immediates = Tokens::new();
+        if self.cmp_immediates {
+            'sections: for code in x86_code {
+                for token in x86_cmp_immediates(code) {
+                    if immediates.len() >= self.max_tokens {
+                        break 'sections;
+                    }
+                    if self.is_plausible_immediate(&token) {
+                        immediates.add_token(&token);
+                    }
+                }
+            }
+        }
+        let mut strings = Tokens::new();
+        if self.strings {
+            'sections: for data in data {
+                for token in printable_strings(data, self.min_len, self.max_len) {
+                    if strings.len() >= self.max_tokens {
+                        break 'sections;
+                    }
+                    strings.add_token(&token);
+                }
+            }
+        }
+
+        // Half of the budget for each kind, the rest of one kind goes to the other
+        let strings_share = self.max_tokens - self.max_tokens / 2;
+        let immediates_len = immediates
+            .len()
+            .min(self.max_tokens - strings.len().min(strings_share));
+        let strings_len = strings.len().min(self.max_tokens - immediates_len);
+        let mut tokens = Tokens::new();
+        tokens.add_tokens(&immediates.tokens()[..immediates_len]);
+        tokens.add_tokens(&strings.tokens()[..strings_len]);
+        tokens
+    }
+
+    /// Checks if an immediate is worth a token: printable, or a large value if
+    /// [`Self::with_all_immediates`] is set. Small integers and repeated bytes are never taken.
+    fn is_plausible_immediate(&self, token: &[u8]) -> bool {
+        if token.iter().all(|b| *b == token[0]) {
+            return false;
+        }
+        if token.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+            return true;
+        }
+        // Little endian, so small (negative) values have their (sign) bytes at the end
+        self.all_immediates
+            && token.len() == 4
+            && token[2..].iter().any(|b| *b != 0)
+            && token[2..].iter().any(|b| *b != 0xff)
+    }
+}
+
+/// Finds the runs of printable characters of at least `min_len` and at most `max_len` bytes
+fn printable_strings(data: &[u8], min_len: usize, max_len: usize) -> Vec<Vec<u8>> {
+    data.split(|b| !(b.is_ascii_graphic() || *b == b' '))
+        .filter(|run| (min_len..=max_len).contains(&run.len()))
+        .filter(|run| run.iter().any(|b| *b != run[0]))
+        .map(<[u8]>::to_vec)
+        .collect()
+}
+
+/// The number of bytes of the `SIB` byte and displacement following a `ModR/M` byte
+fn x86_modrm_extra_len(modrm: u8, sib: Option<u8>) -> usize {
+    let mode = modrm >> 6;
+    let rm = modrm & 7;
+    let sib_len = usize::from(mode != 3 && rm == 4);
+    let disp_len = match mode {
+        0 if rm == 5 => 4,
+        // A `SIB` without base register has a 32 bit displacement
+        0 if rm == 4 && sib.is_some_and(|sib| sib & 7 == 5) => 4,
+        1 => 1,
+        2 => 4,
+        _ => 0,
+    };
+    sib_len + disp_len
+}
+
+/// Finds the immediates of `cmp` instructions by a linear sweep over x86 code.
+///
+/// Matches `cmp eax, imm32` (`3d`) and `cmp r/m32, imm32` (`81 /7`), also with a 64 bit `REX.W`
+/// prefix, and their 16 bit forms with the `66` prefix. As the sweep ignores instruction
+/// boundaries, some immediates are bogus, which the plausibility checks mostly filter out.
+fn x86_cmp_immediates(code: &[u8]) -> Vec<Vec<u8>> {
+    let mut immediates = Vec::new();
+    for idx in 0..code.len() {
+        let imm_len = if idx > 0 && code[idx - 1] == 0x66 {
+            2
+        } else {
+            4
+        };
+        let imm_start = match code[idx] {
+            0x3d => idx + 1,
+            0x81 => {
+                let Some(&modrm) = code.get(idx + 1) else {
+                    continue;
+                };
+                if (modrm >> 3) & 7 != 7 {
+                    continue;
+                }
+                idx + 2 + x86_modrm_extra_len(modrm, code.get(idx + 2).copied())
+            }
+            _ => continue,
+        };
+        if let Some(imm) = code.get(imm_start..imm_start + imm_len) {
+            immediates.push(imm.to_vec());
+        }
+    }
+    immediates
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::{printable_strings, x86_cmp_immediates, BinaryTokenExtractor};
+
+    #[test]
+    fn test_binary_tokens_strings() {
+        let data = b"\0GIF89a\0ab\0%s: this message is way too long for a token\0zzzz\x01PNG \x7f";
+        assert_eq!(
+            printable_strings(data, 4, 32),
+            vec![b"GIF89a".to_vec(), b"PNG ".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_binary_tokens_cmp_immediates() {
+        // cmp eax, "RIFF"; cmp qword [rbp-8], "WAVE"; cmp word [rdi], "MZ"; cmp ecx, 0x10
+        let code = [
+            0x3d, b'R', b'I', b'F', b'F', //
+            0x48, 0x81, 0x7d, 0xf8, b'W', b'A', b'V', b'E', //
+            0x66, 0x81, 0x3f, b'M', b'Z', //
+            0x81, 0xf9, 0x10, 0x00, 0x00, 0x00,
+        ];
+        let immediates = x86_cmp_immediates(&code);
+        for expected in [&b"RIFF"[..], b"WAVE", b"MZ", &[0x10, 0, 0, 0]] {
+            assert!(immediates.iter().any(|imm| imm == expected));
+        }
+
+        let extractor = BinaryTokenExtractor::new();
+        assert!(extractor.is_plausible_immediate(b"RIFF"));
+        assert!(!extractor.is_plausible_immediate(&[0x10, 0, 0, 0]));
+        assert!(!extractor.is_plausible_immediate(&[0xfe, 0xff, 0xff, 0xff]));
+        let extractor = extractor.with_all_immediates(true);
+        assert!(extractor.is_plausible_immediate(&[0x7f, b'E', b'L', b'F'][..]));
+        assert!(extractor.is_plausible_immediate(&[0xef, 0xbe, 0xad, 0xde]));
+        assert!(!extractor.is_plausible_immediate(&[0x10, 0, 0, 0]));
+    }
+
+    #[test]
+    fn test_binary_tokens_budget() {
+        // A sweep over many `3d` bytes finds lots of printable, but bogus, immediates
+        let code: Vec<u8> = (0..64_u8)
+            .flat_map(|idx| [0x3d, b'A' + idx % 26, b'a' + idx / 26, b'!', b'?'])
+            .collect();
+        let data = b"\0GIF89a\0RIFF\0%PDF-\0".to_vec();
+
+        // The immediates cannot starve the strings
+        let tokens = BinaryTokenExtractor::new()
+            .with_max_tokens(8)
+            .extract_sections([code.as_slice()], [data.as_slice()]);
+        assert_eq!(tokens.len(), 8);
+        for string in [&b"GIF89a"[..], b"RIFF", b"%PDF-"] {
+            assert!(tokens.iter().any(|token| token == string));
+        }
+        assert_eq!(tokens.tokens()[0], b"Aa!?".to_vec());
+
+        // The immediates use the budget the strings leave over
+        let tokens = BinaryTokenExtractor::new()
+            .with_max_tokens(8)
+            .extract_sections([code.as_slice()], [&b"\0RIFF\0"[..]]);
+        assert_eq!(tokens.len(), 8);
+        assert_eq!(tokens.tokens()[7], b"RIFF".to_vec());
+
+        let tokens = BinaryTokenExtractor::new()
+            .with_cmp_immediates(false)
+            .extract_sections([code.as_slice()], [data.as_slice()]);
+        assert_eq!(tokens.len(), 3);
+    }
+}
@@ -0,0 +1,461 @@
+//! Post-processing of mutated inputs, fixing up length fields, checksums and magic bytes of binary
+//! formats, so that mutated inputs are not rejected early by the target and it does not need to be
+//! patched to ignore checksums.
+//!
+//! The [`PostProcessMutator`] wraps another mutator and runs an [`InputFixup`] on every input it
+//! mutated, before it gets executed. Either use a [`FixupSpec`] describing the fields, or any
+//! closure taking the input.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::ops::Range;
+
+use libafl_bolts::Named;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::CorpusId,
+    inputs::HasMutatorBytes,
+    mutators::{MutationResult, Mutator},
+    Error,
+};
+
+/// The byte order of a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Endianness {
+    /// Least significant byte first
+    Little,
+    /// Most significant byte first
+    Big,
+}
+
+/// The part of the input a [`Fixup`] covers, resolved against the length of the mutated input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixupRange {
+    start: usize,
+    /// The exclusive end, if set
+    end: Option<usize>,
+    /// The number of bytes left out at the end of the input, if `end` is not set
+    trailing: usize,
+}
+
+impl FixupRange {
+    /// Covers a fixed range of the input
+    #[must_use]
+    pub fn new(range: Range<usize>) -> Self {
+        Self {
+            start: range.start,
+            end: Some(range.end),
+            trailing: 0,
+        }
+    }
+
+    /// Covers the input from `start` up to its end
+    #[must_use]
+    pub fn starting_at(start: usize) -> Self {
+        Self::between(start, 0)
+    }
+
+    /// Covers the input from `start`, leaving out the last `trailing` bytes,
+    /// e.g. a trailing checksum
+    #[must_use]
+    pub fn between(start: usize, trailing: usize) -> Self {
+        Self {
+            start,
+            end: None,
+            trailing,
+        }
+    }
+
+    /// The covered range for an input of the given length, if it fits
+    #[must_use]
+    pub fn resolve(&self, len: usize) -> Option<Range<usize>> {
+        let end = match self.end {
+            Some(end) => end,
+            None => len.checked_sub(self.trailing)?,
+        };
+        (self.start <= end && end <= len).then_some(self.start..end)
+    }
+}
+
+/// The checksum algorithms supported by [`Fixup::Checksum`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChecksumKind {
+    /// The CRC-32 used by zlib, PNG, Ethernet, ...
+    Crc32,
+    /// The Adler-32 checksum used by zlib streams
+    Adler32,
+    /// The sum of all bytes, truncated to the field width
+    Sum,
+}
+
+impl ChecksumKind {
+    /// Computes the checksum over the given bytes
+    #[must_use]
+    pub fn compute(self, bytes: &[u8]) -> u64 {
+        match self {
+            ChecksumKind::Crc32 => u64::from(crc32(bytes)),
+            ChecksumKind::Adler32 => u64::from(adler32(bytes)),
+            ChecksumKind::Sum => bytes
+                .iter()
+                .fold(0_u64, |sum, b| sum.wrapping_add(u64::from(*b))),
+        }
+    }
+}
+
+/// Computes the CRC-32 (IEEE 802.3) of the given bytes
+#[must_use]
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+    for b in bytes {
+        crc ^= u32::from(*b);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Computes the Adler-32 checksum of the given bytes
+#[must_use]
+pub fn adler32(bytes: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for chunk in bytes.chunks(5552) {
+        for byte in chunk {
+            a += u32::from(*byte);
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    (b << 16) | a
+}
+
+/// A single field of the input to recompute after mutation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Fixup {
+    /// Writes the length of the covered range as an integer of `width` bytes at `offset`
+    Length {
+        /// The offset of the field
+        offset: usize,
+        /// The width of the field in bytes, at most 8
+        width: usize,
+        /// The byte order of the field
+        endianness: Endianness,
+        /// The range whose length is written
+        covers: FixupRange,
+    },
+    /// Writes a checksum of the covered range as an integer of `width` bytes at `offset`
+    Checksum {
+        /// The offset of the field
+        offset: usize,
+        /// The width of the field in bytes, at most 8
+        width: usize,
+        /// The byte order of the field
+        endianness: Endianness,
+        /// The checksum algorithm
+        kind: ChecksumKind,
+        /// The range the checksum is computed over
+        covers: FixupRange,
+    },
+    /// Restores magic bytes at `offset`
+    Magic {
+        /// The offset of the magic bytes
+        offset: usize,
+        /// The magic bytes
+        bytes: Vec<u8>,
+    },
+}
+
+impl Fixup {
+    /// A length field of `width` bytes at `offset`
+    #[must_use]
+    pub fn length(offset: usize, width: usize, endianness: Endianness, covers: FixupRange) -> Self {
+        Self::Length {
+            offset,
+            width,
+            endianness,
+            covers,
+        }
+    }
+
+    /// A big endian CRC-32 at `offset`, as found in PNG chunks
+    #[must_use]
+    pub fn crc32(offset: usize, endianness: Endianness, covers: FixupRange) -> Self {
+        Self::checksum(offset, 4, endianness, ChecksumKind::Crc32, covers)
+    }
+
+    /// A checksum field of `width` bytes at `offset`
+    #[must_use]
+    pub fn checksum(
+        offset: usize,
+        width: usize,
+        endianness: Endianness,
+        kind: ChecksumKind,
+        covers: FixupRange,
+    ) -> Self {
+        Self::Checksum {
+            offset,
+            width,
+            endianness,
+            kind,
+            covers,
+        }
+    }
+
+    /// Magic bytes at `offset`
+    #[must_use]
+    pub fn magic(offset: usize, bytes: Vec<u8>) -> Self {
+        Self::Magic { offset, bytes }
+    }
+
+    /// Applies this fixup to the given bytes.
+    /// Returns `false` if the field or the covered range does not fit into the input.
+    pub fn apply(&self, bytes: &mut [u8]) -> bool {
+        match self {
+            Fixup::Length {
+                offset,
+                width,
+                endianness,
+                covers,
+            } => {
+                let Some(range) = covers.resolve(bytes.len()) else {
+                    return false;
+                };
+                write_int(bytes, *offset, *width, *endianness, range.len() as u64)
+            }
+            Fixup::Checksum {
+                offset,
+                width,
+                endianness,
+                kind,
+                covers,
+            } => {
+                let Some(range) = covers.resolve(bytes.len()) else {
+                    return false;
+                };
+                let checksum = kind.compute(&bytes[range]);
+                write_int(bytes, *offset, *width, *endianness, checksum)
+            }
+            Fixup::Magic {
+                offset,
+                bytes: magic,
+            } => {
+                let Some(field) = offset
+                    .checked_add(magic.len())
+                    .and_then(|end| bytes.get_mut(*offset..end))
+                else {
+                    return false;
+                };
+                field.copy_from_slice(magic);
+                true
+            }
+        }
+    }
+}
+
+/// Writes the lowest `width` bytes of `value` at `offset`
+fn write_int(
+    bytes: &mut [u8],
+    offset: usize,
+    width: usize,
+    endianness: Endianness,
+    value: u64,
+) -> bool {
+    if width == 0 || width > 8 {
+        return false;
+    }
+    let Some(field) = offset
+        .checked_add(width)
+        .and_then(|end| bytes.get_mut(offset..end))
+    else {
+        return false;
+    };
+    match endianness {
+        Endianness::Little => field.copy_from_slice(&value.to_le_bytes()[..width]),
+        Endianness::Big => field.copy_from_slice(&value.to_be_bytes()[8 - width..]),
+    }
+    true
+}
+
+/// A list of [`Fixup`]s, applied in order.
+///
+/// Order them so that fields covered by a checksum are fixed before the checksum is, e.g. the
+/// length field of a PNG chunk before its CRC.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixupSpec {
+    fixups: Vec<Fixup>,
+}
+
+impl FixupSpec {
+    /// Creates an empty [`FixupSpec`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a [`Fixup`], applied after all previously added ones
+    #[must_use]
+    pub fn with(mut self, fixup: Fixup) -> Self {
+        self.fixups.push(fixup);
+        self
+    }
+
+    /// The fixups of this spec
+    #[must_use]
+    pub fn fixups(&self) -> &[Fixup] {
+        &self.fixups
+    }
+
+    /// Applies all fixups to the given bytes, skipping those that do not fit
+    pub fn apply(&self, bytes: &mut [u8]) {
+        for fixup in &self.fixups {
+            if !fixup.apply(bytes) {
+                log::trace!("Skipping fixup {fixup:?}, it does not fit the input");
+            }
+        }
+    }
+}
+
+/// Repairs an input after it was mutated, before it gets executed
+pub trait InputFixup<I> {
+    /// Fixes up the given input
+    fn fixup(&mut self, input: &mut I) -> Result<(), Error>;
+}
+
+impl<I> InputFixup<I> for FixupSpec
+where
+    I: HasMutatorBytes,
+{
+    fn fixup(&mut self, input: &mut I) -> Result<(), Error> {
+        self.apply(input.bytes_mut());
+        Ok(())
+    }
+}
+
+impl<F, I> InputFixup<I> for F
+where
+    F: FnMut(&mut I) -> Result<(), Error>,
+{
+    fn fixup(&mut self, input: &mut I) -> Result<(), Error> {
+        self(input)
+    }
+}
+
+/// Applies an [`InputFixup`] to every input mutated by the wrapped mutator
+#[derive(Debug)]
+pub struct PostProcessMutator<M, F> {
+    name: Cow<'static, str>,
+    mutator: M,
+    fixup: F,
+}
+
+impl<I, M, F, S> Mutator<I, S> for PostProcessMutator<M, F>
+where
+    M: Mutator<I, S>,
+    F: InputFixup<I>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let result = self.mutator.mutate(state, input)?;
+        if result == MutationResult::Mutated {
+            self.fixup.fixup(input)?;
+        }
+        Ok(result)
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        self.mutator.post_exec(state, new_corpus_id)
+    }
+}
+
+impl<M, F> Named for PostProcessMutator<M, F> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<M, F> PostProcessMutator<M, F>
+where
+    M: Named,
+{
+    /// Creates a new [`PostProcessMutator`], fixing up the inputs mutated by `mutator`
+    #[must_use]
+    pub fn new(mutator: M, fixup: F) -> Self {
+        Self {
+            name: Cow::Owned(format!("PostProcessMutator<{}>", mutator.name())),
+            mutator,
+            fixup,
+        }
+    }
+
+    /// The fixup applied after each mutation
+    pub fn fixup_mut(&mut self) -> &mut F {
+        &mut self.fixup
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, crc32, Endianness, Fixup, FixupRange, FixupSpec, PostProcessMutator};
+    use crate::{
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::{BitFlipMutator, Mutator},
+        state::NopState,
+    };
+
+    /// A PNG `IEND` chunk: length, type, no data, CRC over type and data
+    fn png_chunk_spec() -> FixupSpec {
+        FixupSpec::new()
+            .with(Fixup::length(
+                0,
+                4,
+                Endianness::Big,
+                FixupRange::between(8, 4),
+            ))
+            .with(Fixup::magic(4, b"IEND".to_vec()))
+            .with(Fixup::crc32(8, Endianness::Big, FixupRange::new(4..8)))
+    }
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn test_fixup_spec() {
+        let spec = png_chunk_spec();
+        let mut chunk = vec![0xff; 12];
+        spec.apply(&mut chunk);
+        assert_eq!(
+            chunk,
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]
+        );
+
+        // Fields that do not fit are skipped
+        let mut short = vec![0xff; 2];
+        png_chunk_spec().apply(&mut short);
+        assert_eq!(short, [0xff; 2]);
+    }
+
+    #[test]
+    fn test_post_process_mutator() {
+        let spec = FixupSpec::new()
+            .with(Fixup::length(
+                0,
+                2,
+                Endianness::Little,
+                FixupRange::starting_at(2),
+            ))
+            .with(Fixup::magic(2, b"MZ".to_vec()));
+        let mut mutator = PostProcessMutator::new(BitFlipMutator::new(), spec);
+        let mut state = NopState::<BytesInput>::new();
+
+        for _ in 0..100 {
+            let mut input = BytesInput::new(vec![0, 0, b'M', b'Z', 1, 2, 3, 4]);
+            mutator.mutate(&mut state, &mut input).unwrap();
+            assert_eq!(&input.bytes()[..4], &[6, 0, b'M', b'Z']);
+        }
+    }
+}
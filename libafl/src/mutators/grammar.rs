@@ -0,0 +1,245 @@
+//! Mutators for the derivation trees of [`GrammarInput`]s, working on the tree directly
+use alloc::{borrow::Cow, vec::Vec};
+
+use libafl_bolts::{rands::Rand, Named};
+
+use crate::{
+    generators::Grammar,
+    inputs::GrammarInput,
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+
+/// The number of tries to find a recursive subtree
+const RECURSION_TRIES: usize = 16;
+
+/// The default maximal number of times a recursion is repeated
+const DEFAULT_MAX_RECURSIONS: usize = 4;
+
+/// Replaces a random subtree with a freshly generated one for the same non-terminal
+#[derive(Debug)]
+pub struct GrammarSubtreeMutator<'a> {
+    grammar: &'a Grammar,
+    max_depth: usize,
+}
+
+impl<'a, S> Mutator<GrammarInput, S> for GrammarSubtreeMutator<'a>
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut GrammarInput) -> Result<MutationResult, Error> {
+        let size = input.root().size();
+        if size == 0 || input.grammar_id() != self.grammar.id() {
+            return Ok(MutationResult::Skipped);
+        }
+        let nth = state.rand_mut().below(size);
+        let Some(nonterminal) = input
+            .root()
+            .nth(nth)
+            .and_then(|node| self.grammar.nonterminal_of(node))
+        else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let generated = self
+            .grammar
+            .generate_node(state.rand_mut(), nonterminal, self.max_depth);
+        let node = input.root_mut().nth_mut(nth).unwrap();
+        if *node == generated {
+            return Ok(MutationResult::Skipped);
+        }
+        *node = generated;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<'a> Named for GrammarSubtreeMutator<'a> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("GrammarSubtreeMutator");
+        &NAME
+    }
+}
+
+impl<'a> GrammarSubtreeMutator<'a> {
+    /// Creates a new [`GrammarSubtreeMutator`], generating subtrees of about `max_depth` levels
+    #[must_use]
+    pub fn new(grammar: &'a Grammar, max_depth: usize) -> Self {
+        Self { grammar, max_depth }
+    }
+}
+
+/// Finds a subtree containing a descendant for the same non-terminal, and repeats this recursion
+/// a random number of times, e.g. turning `(a)` into `(((a)))`
+#[derive(Debug)]
+pub struct GrammarRecursionMutator<'a> {
+    grammar: &'a Grammar,
+    max_recursions: usize,
+}
+
+impl<'a, S> Mutator<GrammarInput, S> for GrammarRecursionMutator<'a>
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut GrammarInput) -> Result<MutationResult, Error> {
+        if input.grammar_id() != self.grammar.id() {
+            return Ok(MutationResult::Skipped);
+        }
+        let mut nodes = Vec::new();
+        input.root().flatten(&mut nodes);
+        if nodes.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let nonterminal = |rule: usize| self.grammar.rules().get(rule).map(|r| r.nonterminal);
+
+        for _ in 0..RECURSION_TRIES {
+            let outer = state.rand_mut().below(nodes.len());
+            let (outer_rule, outer_size) = nodes[outer];
+            let Some(outer_nonterminal) = nonterminal(outer_rule) else {
+                continue;
+            };
+            let inner: Vec<usize> = (outer + 1..outer + outer_size)
+                .filter(|idx| nonterminal(nodes[*idx].0) == Some(outer_nonterminal))
+                .collect();
+            if inner.is_empty() {
+                continue;
+            }
+            let inner = inner[state.rand_mut().below(inner.len())];
+            let recursions = state.rand_mut().between(1, self.max_recursions.max(1));
+
+            // The descendant of each inserted copy is at the same offset as in the original,
+            // and non-terminals before it in pre-order keep their index.
+            let subtree = input.root().nth(outer).unwrap().clone();
+            let offset = inner - outer;
+            let mut target = inner;
+            for _ in 0..recursions {
+                *input.root_mut().nth_mut(target).unwrap() = subtree.clone();
+                target += offset;
+            }
+            return Ok(MutationResult::Mutated);
+        }
+        Ok(MutationResult::Skipped)
+    }
+}
+
+impl<'a> Named for GrammarRecursionMutator<'a> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("GrammarRecursionMutator");
+        &NAME
+    }
+}
+
+impl<'a> GrammarRecursionMutator<'a> {
+    /// Creates a new [`GrammarRecursionMutator`]
+    #[must_use]
+    pub fn new(grammar: &'a Grammar) -> Self {
+        Self {
+            grammar,
+            max_recursions: DEFAULT_MAX_RECURSIONS,
+        }
+    }
+
+    /// Sets the maximal number of times a recursion is repeated
+    #[must_use]
+    pub fn with_max_recursions(mut self, max_recursions: usize) -> Self {
+        self.max_recursions = max_recursions;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{GrammarRecursionMutator, GrammarSubtreeMutator};
+    use crate::{
+        corpus::InMemoryCorpus,
+        feedbacks::ConstFeedback,
+        generators::{Grammar, GrammarSymbol},
+        inputs::{
+            BytesInput, BytesToGrammarInputConverter, GrammarInput, GrammarToBytesInputConverter,
+            InputConverter,
+        },
+        mutators::{MutationResult, Mutator},
+        state::{HasRand, StdState},
+    };
+
+    fn grammar() -> Grammar {
+        Grammar::new(
+            "EXPR",
+            [
+                (
+                    "EXPR",
+                    vec![
+                        GrammarSymbol::Terminal(b"(".to_vec()),
+                        GrammarSymbol::NonTerminal("EXPR".into()),
+                        GrammarSymbol::Terminal(b")".to_vec()),
+                    ],
+                ),
+                ("EXPR", vec![GrammarSymbol::NonTerminal("NUM".into())]),
+                ("NUM", vec![GrammarSymbol::Terminal(b"1".to_vec())]),
+                ("NUM", vec![GrammarSymbol::Terminal(b"2".to_vec())]),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_grammar_mutators() {
+        let grammar = grammar();
+        assert!(Grammar::new("A", [("A", vec![GrammarSymbol::NonTerminal("A".into())])]).is_err());
+
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<GrammarInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+
+        // At depth 0, the shallowest rules are chosen
+        let mut input = grammar.generate(state.rand_mut(), 0);
+        assert_eq!(input.grammar_id(), grammar.id());
+        assert_eq!(input.root().size(), 2);
+
+        let mut subtree = GrammarSubtreeMutator::new(&grammar, 4);
+        let mut recursion = GrammarRecursionMutator::new(&grammar);
+        for _ in 0..64 {
+            subtree.mutate(&mut state, &mut input).unwrap();
+            let before = input.root().size();
+            if recursion.mutate(&mut state, &mut input).unwrap() == MutationResult::Mutated {
+                assert!(input.root().size() > before);
+            }
+            let bytes = input.generate_bytes();
+            #[allow(clippy::naive_bytecount)]
+            let open = bytes.iter().filter(|b| **b == b'(').count();
+            #[allow(clippy::naive_bytecount)]
+            let close = bytes.iter().filter(|b| **b == b')').count();
+            assert_eq!(open, close);
+            assert!(matches!(bytes[open], b'1' | b'2'));
+            input = grammar.generate(state.rand_mut(), 3);
+        }
+    }
+
+    #[test]
+    fn test_grammar_parse() {
+        let grammar = grammar();
+        let mut rand = StdRand::with_seed(1337);
+        let mut to_bytes = GrammarToBytesInputConverter::new();
+        let mut to_grammar = BytesToGrammarInputConverter::new(&grammar);
+        for _ in 0..16 {
+            let input = grammar.generate(&mut rand, 4);
+            let bytes = to_bytes.convert(input.clone()).unwrap();
+            assert_eq!(to_grammar.convert(bytes).unwrap(), input);
+        }
+        assert!(to_grammar
+            .convert(BytesInput::new(b"((1)".to_vec()))
+            .is_err());
+        assert!(grammar.parse(b"").is_none());
+    }
+}
@@ -0,0 +1,331 @@
+//! Mutators for [`HttpInput`]s, mutating single parts of the request or tampering with its
+//! framing, i.e. duplicated headers, chunked encoding and `Content-Length`.
+
+use alloc::{borrow::Cow, string::ToString, vec::Vec};
+
+use libafl_bolts::{rands::Rand, Named};
+
+use crate::{
+    corpus::CorpusId,
+    inputs::{BytesInput, HasMutatorBytes, HttpBodyEncoding, HttpChunk, HttpHeader, HttpInput},
+    mutators::{MutationResult, Mutator},
+    state::HasRand,
+    Error,
+};
+
+/// Values of `Transfer-Encoding` that parsers disagree on
+const TRANSFER_ENCODINGS: &[&[u8]] = &[
+    b"chunked",
+    b"Chunked",
+    b" chunked",
+    b"chunked ",
+    b"chunked\t",
+    b"xchunked",
+    b"chunked, identity",
+    b"identity, chunked",
+    b"chunked;q=1",
+];
+
+/// The maximal number of chunks a body is split into
+const MAX_CHUNKS: usize = 8;
+
+/// Applies a [`BytesInput`] mutator to a random part of the [`HttpInput`], i.e. the method, the
+/// path, the version, the body, or the name or value of a header
+#[derive(Debug)]
+pub struct HttpPartMutator<M> {
+    name: Cow<'static, str>,
+    mutator: M,
+}
+
+impl<M, S> Mutator<HttpInput, S> for HttpPartMutator<M>
+where
+    M: Mutator<BytesInput, S>,
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut HttpInput) -> Result<MutationResult, Error> {
+        let part = state.rand_mut().below(input.part_count());
+        self.mutator.mutate(state, input.part_mut(part).unwrap())
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        self.mutator.post_exec(state, new_corpus_id)
+    }
+}
+
+impl<M> Named for HttpPartMutator<M> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<M> HttpPartMutator<M>
+where
+    M: Named,
+{
+    /// Creates a new [`HttpPartMutator`], applying the given mutator to the parts
+    #[must_use]
+    pub fn new(mutator: M) -> Self {
+        Self {
+            name: Cow::Owned(format!("HttpPartMutator<{}>", mutator.name())),
+            mutator,
+        }
+    }
+}
+
+/// Duplicates a random header, sometimes changing the case of the duplicate's name,
+/// so that parsers picking the first or the last occurrence disagree
+#[derive(Debug, Default)]
+pub struct HttpHeaderDuplicateMutator;
+
+impl<S> Mutator<HttpInput, S> for HttpHeaderDuplicateMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut HttpInput) -> Result<MutationResult, Error> {
+        if input.headers().is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let rand = state.rand_mut();
+        let header = rand.below(input.headers().len());
+        let mut duplicate = input.headers()[header].clone();
+        if rand.coinflip(0.5) {
+            for b in duplicate.name.bytes_mut() {
+                if b.is_ascii_alphabetic() && rand.coinflip(0.5) {
+                    *b ^= 0x20;
+                }
+            }
+        }
+        let position = rand.below(input.headers().len() + 1);
+        input.headers_mut().insert(position, duplicate);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for HttpHeaderDuplicateMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("HttpHeaderDuplicateMutator");
+        &NAME
+    }
+}
+
+impl HttpHeaderDuplicateMutator {
+    /// Creates a new [`HttpHeaderDuplicateMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Tampers with the chunked encoding of the body: switches between identity and chunked framing,
+/// declares wrong chunk sizes, adds chunk extensions, or obfuscates the `Transfer-Encoding` header
+#[derive(Debug, Default)]
+pub struct HttpChunkedMutator;
+
+impl<S> Mutator<HttpInput, S> for HttpChunkedMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut HttpInput) -> Result<MutationResult, Error> {
+        let rand = state.rand_mut();
+        let body_len = input.body().bytes().len();
+
+        if *input.encoding() == HttpBodyEncoding::Identity {
+            let count = rand.between(1, MAX_CHUNKS);
+            let mut chunks = Vec::with_capacity(count);
+            let mut remaining = body_len;
+            for _ in 0..count {
+                let len = rand.below(remaining + 1);
+                chunks.push(HttpChunk {
+                    len,
+                    declared_len: None,
+                    extension: Vec::new(),
+                });
+                remaining -= len;
+            }
+            *input.encoding_mut() = HttpBodyEncoding::Chunked(chunks);
+            if input.header(b"transfer-encoding").is_none() {
+                input
+                    .headers_mut()
+                    .push(HttpHeader::new(b"Transfer-Encoding", b"chunked"));
+            }
+            return Ok(MutationResult::Mutated);
+        }
+        let HttpBodyEncoding::Chunked(chunks) = input.encoding_mut() else {
+            unreachable!("Checked above");
+        };
+
+        match rand.below(4) {
+            0 if !chunks.is_empty() => {
+                let chunk = rand.below(chunks.len());
+                let len = chunks[chunk].len;
+                chunks[chunk].declared_len = Some(match rand.below(3) {
+                    0 => len + rand.between(1, 16),
+                    1 => len.saturating_sub(rand.between(1, 16)),
+                    _ => rand.below(usize::MAX),
+                });
+            }
+            1 if !chunks.is_empty() => {
+                let chunk = rand.below(chunks.len());
+                let extension = match rand.below(3) {
+                    0 => &b";"[..],
+                    1 => &b";a=b"[..],
+                    _ => &b"\n"[..],
+                };
+                chunks[chunk].extension.extend_from_slice(extension);
+            }
+            2 => {
+                *input.encoding_mut() = HttpBodyEncoding::Identity;
+            }
+            _ => {
+                let encoding = *rand.choose(TRANSFER_ENCODINGS).unwrap();
+                let header = HttpHeader::new(b"Transfer-Encoding", encoding);
+                match input
+                    .headers()
+                    .iter()
+                    .position(|header| header.is(b"transfer-encoding"))
+                {
+                    Some(idx) => input.headers_mut()[idx] = header,
+                    None => input.headers_mut().push(header),
+                }
+            }
+        }
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for HttpChunkedMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("HttpChunkedMutator");
+        &NAME
+    }
+}
+
+impl HttpChunkedMutator {
+    /// Creates a new [`HttpChunkedMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Desynchronizes the `Content-Length` from the body: declares a wrong length, adds a conflicting
+/// second `Content-Length`, or writes the length in a form parsers disagree on
+#[derive(Debug, Default)]
+pub struct HttpContentLengthMutator;
+
+impl<S> Mutator<HttpInput, S> for HttpContentLengthMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut HttpInput) -> Result<MutationResult, Error> {
+        let rand = state.rand_mut();
+        let body_len = input.body().bytes().len();
+
+        let value = match rand.below(3) {
+            0 => body_len + rand.between(1, 16),
+            1 => body_len.saturating_sub(rand.between(1, 16)),
+            _ => rand.below(usize::MAX),
+        }
+        .to_string();
+        let value = match rand.below(6) {
+            0 => format!("+{value}"),
+            1 => format!("0{value}"),
+            2 => format!(" {value}"),
+            3 => format!("{value}, {body_len}"),
+            _ => value,
+        };
+
+        let existing = input
+            .headers()
+            .iter()
+            .position(|header| header.is(b"content-length"));
+        match existing {
+            // Replace the declared length
+            Some(idx) if rand.coinflip(0.5) => {
+                input.headers_mut()[idx].value = value.as_bytes().into();
+            }
+            // Add a conflicting one, or one besides `Transfer-Encoding`
+            _ => {
+                let position = rand.below(input.headers().len() + 1);
+                input.headers_mut().insert(
+                    position,
+                    HttpHeader::new(b"Content-Length", value.as_bytes()),
+                );
+            }
+        }
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for HttpContentLengthMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("HttpContentLengthMutator");
+        &NAME
+    }
+}
+
+impl HttpContentLengthMutator {
+    /// Creates a new [`HttpContentLengthMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::rands::StdRand;
+
+    use super::{HttpChunkedMutator, HttpContentLengthMutator, HttpHeaderDuplicateMutator};
+    use crate::{
+        corpus::InMemoryCorpus,
+        feedbacks::ConstFeedback,
+        inputs::{HasMutatorBytes, HttpBodyEncoding, HttpInput},
+        mutators::{MutationResult, Mutator},
+        state::StdState,
+    };
+
+    #[test]
+    fn test_http_framing_mutators() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<HttpInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let base = HttpInput::new(b"POST", b"/")
+            .with_header(b"Host", b"localhost")
+            .with_body(b"hello world");
+
+        let mut input = base.clone();
+        assert_eq!(
+            HttpHeaderDuplicateMutator::new()
+                .mutate(&mut state, &mut input)
+                .unwrap(),
+            MutationResult::Mutated
+        );
+        assert_eq!(input.headers().len(), base.headers().len() + 1);
+
+        let mut input = base.clone();
+        HttpChunkedMutator::new()
+            .mutate(&mut state, &mut input)
+            .unwrap();
+        assert!(matches!(input.encoding(), HttpBodyEncoding::Chunked(_)));
+        assert!(input.header(b"transfer-encoding").is_some());
+        assert!(input.to_bytes().ends_with(b"0\r\n\r\n"));
+
+        let mut input = base.clone();
+        HttpContentLengthMutator::new()
+            .mutate(&mut state, &mut input)
+            .unwrap();
+        assert!(input
+            .headers()
+            .iter()
+            .filter(|header| header.is(b"content-length"))
+            .any(|header| header.value.bytes() != b"11"));
+    }
+}
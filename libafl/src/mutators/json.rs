@@ -0,0 +1,419 @@
+//! Structure-aware mutations of inputs that happen to be valid JSON.
+//! The [`JsonMutator`] parses the input into a DOM, mutates a single node and re-serializes it,
+//! so the document stays well-formed. Inputs that are no valid JSON are handed to a fallback
+//! byte-level mutator, usually havoc.
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::mem;
+
+use libafl_bolts::{rands::Rand, Named};
+use serde_json::{Map, Value};
+
+use crate::{
+    corpus::CorpusId,
+    inputs::HasMutatorBytes,
+    mutators::{MutationResult, Mutator},
+    state::{HasMaxSize, HasRand},
+    Error,
+};
+
+/// The default maximal depth of the nesting bombs
+pub const DEFAULT_MAX_NESTING: usize = 64;
+
+/// The maximal number of copies added when duplicating an array element
+const MAX_DUPLICATES: usize = 16;
+
+/// Keys that targets often treat specially
+const INTERESTING_KEYS: &[&str] = &[
+    "",
+    "__proto__",
+    "constructor",
+    "prototype",
+    "$ref",
+    "@type",
+    "null",
+    "\u{0}",
+    "\u{feff}",
+];
+
+/// Characters appended to keys, mostly separators of path-like keys
+const KEY_CHARS: &[u8] = b" _-.$/\\\"0aZ";
+
+/// A segment of the path from the root of the document to a node
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum JsonPathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Collects the paths to all nodes of the document, the root first
+fn collect_paths(
+    value: &Value,
+    path: &mut Vec<JsonPathSegment>,
+    paths: &mut Vec<Vec<JsonPathSegment>>,
+) {
+    paths.push(path.clone());
+    match value {
+        Value::Object(map) => {
+            for (key, nested) in map {
+                path.push(JsonPathSegment::Key(key.clone()));
+                collect_paths(nested, path, paths);
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            for (idx, nested) in items.iter().enumerate() {
+                path.push(JsonPathSegment::Index(idx));
+                collect_paths(nested, path, paths);
+                path.pop();
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The node at the path
+fn node_at<'a>(mut value: &'a Value, path: &[JsonPathSegment]) -> Option<&'a Value> {
+    for segment in path {
+        value = match (value, segment) {
+            (Value::Object(map), JsonPathSegment::Key(key)) => map.get(key)?,
+            (Value::Array(items), JsonPathSegment::Index(idx)) => items.get(*idx)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// The node at the path, mutable
+fn node_at_mut<'a>(mut value: &'a mut Value, path: &[JsonPathSegment]) -> Option<&'a mut Value> {
+    for segment in path {
+        value = match (value, segment) {
+            (Value::Object(map), JsonPathSegment::Key(key)) => map.get_mut(key)?,
+            (Value::Array(items), JsonPathSegment::Index(idx)) => items.get_mut(*idx)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// The index of the JSON type of the value, see [`confused_value`]
+fn type_index(value: &Value) -> usize {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+/// A value of a different JSON type than the given one, derived from it where possible
+fn confused_value<R>(rand: &mut R, value: &Value) -> Value
+where
+    R: Rand,
+{
+    let kind = (type_index(value) + 1 + rand.below(5)) % 6;
+    match kind {
+        0 => Value::Null,
+        1 => Value::Bool(rand.below(2) == 0),
+        2 => match value {
+            Value::Bool(b) => Value::from(u8::from(*b)),
+            Value::String(s) if s.parse::<f64>().is_ok() => {
+                serde_json::from_str(s).unwrap_or_else(|_| Value::from(0))
+            }
+            _ => match rand.below(8) {
+                0 => Value::from(0),
+                1 => Value::from(-1),
+                2 => Value::from(i64::MAX),
+                3 => Value::from(i64::MIN),
+                4 => Value::from(u64::MAX),
+                5 => Value::from(1e308),
+                6 => Value::from(-0.5),
+                _ => Value::from(rand.next()),
+            },
+        },
+        3 => match value {
+            Value::Null if rand.below(2) == 0 => Value::String(String::new()),
+            _ => Value::String(value.to_string()),
+        },
+        4 => Value::Array(if matches!(value, Value::Null) {
+            Vec::new()
+        } else {
+            alloc::vec![value.clone()]
+        }),
+        _ => {
+            let mut map = Map::new();
+            if !matches!(value, Value::Null) {
+                map.insert(String::new(), value.clone());
+            }
+            Value::Object(map)
+        }
+    }
+}
+
+/// Renames a random key of a random object
+fn rename_key<R>(rand: &mut R, doc: &mut Value, paths: &[Vec<JsonPathSegment>]) -> bool
+where
+    R: Rand,
+{
+    let objects: Vec<_> = paths
+        .iter()
+        .filter(|path| matches!(node_at(doc, path), Some(Value::Object(map)) if !map.is_empty()))
+        .collect();
+    if objects.is_empty() {
+        return false;
+    }
+    let path = objects[rand.below(objects.len())];
+    let Some(Value::Object(map)) = node_at_mut(doc, path) else {
+        return false;
+    };
+    let key = map.keys().nth(rand.below(map.len())).unwrap().clone();
+    let new_key = match rand.below(5) {
+        0 => INTERESTING_KEYS[rand.below(INTERESTING_KEYS.len())].to_string(),
+        1 => {
+            let mut new_key = key.clone();
+            new_key.push(char::from(KEY_CHARS[rand.below(KEY_CHARS.len())]));
+            new_key
+        }
+        2 => key.chars().take(key.chars().count() / 2).collect(),
+        3 => {
+            if key.chars().any(char::is_lowercase) {
+                key.to_uppercase()
+            } else {
+                key.to_lowercase()
+            }
+        }
+        _ => map.keys().nth(rand.below(map.len())).unwrap().clone(),
+    };
+    if new_key == key {
+        return false;
+    }
+    let value = map.remove(&key).unwrap();
+    // Renaming onto an existing key merges the two entries, which is an interesting mutation too
+    map.insert(new_key, value);
+    true
+}
+
+/// Replaces a random node with a value of a different type
+fn confuse_type<R>(rand: &mut R, doc: &mut Value, paths: &[Vec<JsonPathSegment>]) -> bool
+where
+    R: Rand,
+{
+    let path = &paths[rand.below(paths.len())];
+    let Some(node) = node_at_mut(doc, path) else {
+        return false;
+    };
+    *node = confused_value(rand, node);
+    true
+}
+
+/// Duplicates a random element of a random array, possibly multiple times
+fn duplicate_element<R>(rand: &mut R, doc: &mut Value, paths: &[Vec<JsonPathSegment>]) -> bool
+where
+    R: Rand,
+{
+    let arrays: Vec<_> = paths
+        .iter()
+        .filter(|path| matches!(node_at(doc, path), Some(Value::Array(items)) if !items.is_empty()))
+        .collect();
+    if arrays.is_empty() {
+        return false;
+    }
+    let path = arrays[rand.below(arrays.len())];
+    let Some(Value::Array(items)) = node_at_mut(doc, path) else {
+        return false;
+    };
+    let element = items[rand.below(items.len())].clone();
+    for _ in 0..=rand.below(MAX_DUPLICATES) {
+        let idx = rand.below(items.len() + 1);
+        items.insert(idx, element.clone());
+    }
+    true
+}
+
+/// Wraps a random node in deeply nested arrays and objects
+fn nesting_bomb<R>(
+    rand: &mut R,
+    doc: &mut Value,
+    paths: &[Vec<JsonPathSegment>],
+    max_nesting: usize,
+) -> bool
+where
+    R: Rand,
+{
+    let path = &paths[rand.below(paths.len())];
+    let Some(node) = node_at_mut(doc, path) else {
+        return false;
+    };
+    let mut value = mem::take(node);
+    for _ in 0..=rand.below(max_nesting) {
+        value = if rand.below(2) == 0 {
+            Value::Array(alloc::vec![value])
+        } else {
+            let mut map = Map::new();
+            map.insert("a".to_string(), value);
+            Value::Object(map)
+        };
+    }
+    *node = value;
+    true
+}
+
+/// Removes a random node, other than the root
+fn delete_node<R>(rand: &mut R, doc: &mut Value, paths: &[Vec<JsonPathSegment>]) -> bool
+where
+    R: Rand,
+{
+    if paths.len() < 2 {
+        return false;
+    }
+    // The root is always the first path
+    let path = &paths[1 + rand.below(paths.len() - 1)];
+    let (last, parent) = path.split_last().unwrap();
+    match (node_at_mut(doc, parent), last) {
+        (Some(Value::Object(map)), JsonPathSegment::Key(key)) => map.remove(key).is_some(),
+        (Some(Value::Array(items)), JsonPathSegment::Index(idx)) if *idx < items.len() => {
+            items.remove(*idx);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Mutates inputs that are valid JSON on the level of the document nodes: it renames keys,
+/// confuses the types of values, duplicates array elements, deletes nodes and wraps them in
+/// nesting bombs. The result is always well-formed JSON, apart from nesting bombs deeper than
+/// the parsers' recursion limits.
+///
+/// Inputs that can't be parsed as JSON are mutated by the fallback mutator instead.
+#[derive(Debug)]
+pub struct JsonMutator<M> {
+    name: Cow<'static, str>,
+    fallback: M,
+    max_nesting: usize,
+}
+
+impl<I, M, S> Mutator<I, S> for JsonMutator<M>
+where
+    I: HasMutatorBytes,
+    M: Mutator<I, S>,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let Ok(mut doc) = serde_json::from_slice::<Value>(input.bytes()) else {
+            return self.fallback.mutate(state, input);
+        };
+        let mut paths = Vec::new();
+        collect_paths(&doc, &mut Vec::new(), &mut paths);
+
+        let rand = state.rand_mut();
+        let mutated = match rand.below(5) {
+            0 => rename_key(rand, &mut doc, &paths),
+            1 => confuse_type(rand, &mut doc, &paths),
+            2 => duplicate_element(rand, &mut doc, &paths),
+            3 => nesting_bomb(rand, &mut doc, &paths, self.max_nesting),
+            _ => delete_node(rand, &mut doc, &paths),
+        };
+        if !mutated {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let bytes = serde_json::to_vec(&doc)?;
+        if bytes.len() > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+        input.resize(bytes.len(), 0);
+        input.bytes_mut().copy_from_slice(&bytes);
+        Ok(MutationResult::Mutated)
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        self.fallback.post_exec(state, new_corpus_id)
+    }
+}
+
+impl<M> Named for JsonMutator<M> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<M> JsonMutator<M>
+where
+    M: Named,
+{
+    /// Creates a new [`JsonMutator`], mutating inputs that are no valid JSON with the fallback
+    #[must_use]
+    pub fn new(fallback: M) -> Self {
+        Self {
+            name: Cow::Owned(format!("JsonMutator<{}>", fallback.name())),
+            fallback,
+            max_nesting: DEFAULT_MAX_NESTING,
+        }
+    }
+}
+
+impl<M> JsonMutator<M> {
+    /// Sets the maximal depth of the nesting bombs
+    #[must_use]
+    pub fn with_max_nesting(mut self, max_nesting: usize) -> Self {
+        self.max_nesting = max_nesting.max(1);
+        self
+    }
+
+    /// The fallback mutator
+    #[must_use]
+    pub fn fallback(&self) -> &M {
+        &self.fallback
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::rands::StdRand;
+
+    use super::JsonMutator;
+    use crate::{
+        corpus::NopCorpus,
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::{BitFlipMutator, MutationResult, Mutator},
+        state::StdState,
+    };
+
+    #[test]
+    fn test_json_mutator() {
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            NopCorpus::<BytesInput>::new(),
+            NopCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap();
+        let mut mutator = JsonMutator::new(BitFlipMutator::new()).with_max_nesting(16);
+
+        let original = br#"{"name": "x", "items": [1, 2.5, null, {"nested": true}]}"#;
+        let mut mutated = 0;
+        for _ in 0..256 {
+            let mut input = BytesInput::new(original.to_vec());
+            if mutator.mutate(&mut state, &mut input).unwrap() == MutationResult::Mutated {
+                mutated += 1;
+                serde_json::from_slice::<serde_json::Value>(input.bytes()).unwrap();
+            }
+        }
+        assert!(mutated > 128);
+
+        // No valid JSON, so the bit flips of the fallback are applied
+        let mut input = BytesInput::new(b"{\"broken\": ".to_vec());
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Mutated
+        );
+        assert_ne!(input.bytes(), b"{\"broken\": ");
+    }
+}
@@ -9,16 +9,30 @@ pub use mutations::*;
 pub mod token_mutations;
 use serde::{Deserialize, Serialize};
 pub use token_mutations::*;
+pub mod token_crossover;
+pub use token_crossover::*;
 pub mod encoded_mutations;
 pub use encoded_mutations::*;
 pub mod mopt_mutator;
 pub use mopt_mutator::*;
 pub mod gramatron;
 pub use gramatron::*;
+pub mod grammar;
+pub use grammar::*;
 pub mod grimoire;
 pub use grimoire::*;
+pub mod http;
+pub use http::*;
+pub mod sequence;
+pub use sequence::*;
 pub mod tuneable;
 pub use tuneable::*;
+pub mod weighted;
+pub use weighted::*;
+pub mod fixup;
+pub use fixup::*;
+pub mod replay;
+pub use replay::*;
 
 #[cfg(feature = "unicode")]
 pub mod unicode;
@@ -33,8 +47,25 @@ pub use multi::*;
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
 
+pub mod binary_tokens;
+pub use binary_tokens::*;
+
+#[cfg(feature = "std")]
+pub mod json;
+#[cfg(feature = "std")]
+pub use json::*;
+
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+#[cfg(feature = "protobuf")]
+pub use protobuf::*;
+
+#[cfg(all(feature = "std", unix))]
+pub mod afl_custom;
 use alloc::{borrow::Cow, boxed::Box, vec::Vec};
 
+#[cfg(all(feature = "std", unix))]
+pub use afl_custom::*;
 use libafl_bolts::{tuples::IntoVec, HasLen, Named};
 #[cfg(feature = "nautilus")]
 pub use nautilus::*;
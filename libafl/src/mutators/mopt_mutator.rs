@@ -24,6 +24,10 @@ use crate::{
 /// In short, in the pilot fuzzing mode, the fuzzer employs several `swarms` to compute the probability to choose the mutation operator.
 /// On the other hand, in the core fuzzing mode, the fuzzer chooses the best `swarms`, which was determined during the pilot fuzzing mode, to compute the probability to choose the operation operator.
 /// With the current implementation we are always in the pacemaker fuzzing mode.
+///
+/// All of the learned state, including the current [`MOptMode`], lives in this metadata,
+/// so it is saved and restored together with the rest of the fuzzer state across restarts.
+/// States saved by older versions of `LibAFL`, without the mode, can't be restored.
 #[derive(Serialize, Deserialize, Clone)]
 #[cfg_attr(
     any(not(feature = "serdeany_autoreg"), miri),
@@ -60,6 +64,8 @@ pub struct MOpt {
     pub core_time: usize,
     /// The swarm identifier that we are currently using in the pilot fuzzing mode
     pub swarm_now: usize,
+    /// The mode we are currently fuzzing in
+    pub mode: MOptMode,
     /// A parameter for the PSO algorithm
     x_now: Vec<Vec<f64>>,
     /// A parameter for the PSO algorithm
@@ -111,6 +117,7 @@ impl Debug for MOpt {
             .field("\ng_now", &self.g_max)
             .field("\npilot_time", &self.pilot_time)
             .field("\ncore_time", &self.core_time)
+            .field("\nmode", &self.mode)
             .field("\n\nx_now", &self.x_now)
             .field("\n\nl_best", &self.l_best)
             .field("\n\neff_best", &self.eff_best)
@@ -163,6 +170,7 @@ impl MOpt {
             pilot_time: 0,
             core_time: 0,
             swarm_now: 0,
+            mode: MOptMode::Pilotfuzzing,
             x_now: vec![vec![0.0; operator_num]; swarm_num],
             l_best: vec![vec![0.0; operator_num]; swarm_num],
             eff_best: vec![vec![0.0; operator_num]; swarm_num],
@@ -349,7 +357,7 @@ const V_MAX: f64 = 1.0;
 const V_MIN: f64 = 0.05;
 
 /// The `MOpt` mode to use
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MOptMode {
     /// Pilot fuzzing mode
     Pilotfuzzing,
@@ -365,7 +373,6 @@ where
     S: HasRand + HasMetadata + HasCorpus + HasSolutions,
 {
     name: Cow<'static, str>,
-    mode: MOptMode,
     finds_before: usize,
     mutations: MT,
     max_stack_pow: usize,
@@ -404,7 +411,7 @@ where
         let after = state.corpus().count() + state.solutions().count();
 
         let mopt = state.metadata_map_mut().get_mut::<MOpt>().unwrap();
-        let key_module = self.mode;
+        let key_module = mopt.mode;
         match key_module {
             MOptMode::Corefuzzing => {
                 mopt.core_time += 1;
@@ -428,7 +435,7 @@ where
                         mopt.core_operator_cycles[i] = mopt.core_operator_cycles_v2[i];
                     }
                     mopt.pso_update()?;
-                    self.mode = MOptMode::Pilotfuzzing;
+                    mopt.mode = MOptMode::Pilotfuzzing;
                 }
             }
             MOptMode::Pilotfuzzing => {
@@ -486,7 +493,7 @@ where
                         // If there's only 1 swarm, then no core_fuzzing mode.
                         mopt.pso_update()?;
                     } else if mopt.swarm_now == mopt.swarm_num {
-                        self.mode = MOptMode::Corefuzzing;
+                        mopt.mode = MOptMode::Corefuzzing;
 
                         for i in 0..mopt.operator_num {
                             mopt.core_operator_cycles_v2[i] = mopt.core_operator_cycles[i];
@@ -518,19 +525,27 @@ where
     S: HasRand + HasMetadata + HasCorpus + HasSolutions,
 {
     /// Create a new [`StdMOptMutator`].
+    ///
+    /// If the state already holds [`struct@MOpt`] metadata for the same number of mutations and swarms,
+    /// e.g. after a restart, the learned state is reused instead of starting over.
     pub fn new(
         state: &mut S,
         mutations: MT,
         max_stack_pow: usize,
         swarm_num: usize,
     ) -> Result<Self, Error> {
-        if !state.has_metadata::<MOpt>() {
+        let reusable = state
+            .metadata::<MOpt>()
+            .is_ok_and(|mopt| mopt.operator_num == mutations.len() && mopt.swarm_num == swarm_num);
+        if !reusable {
+            if state.has_metadata::<MOpt>() {
+                log::warn!("Discarding MOpt state that does not match the configured mutations");
+            }
             let rand_seed = state.rand_mut().next();
             state.add_metadata::<MOpt>(MOpt::new(mutations.len(), swarm_num, rand_seed)?);
         }
         Ok(Self {
             name: Cow::from(format!("StdMOptMutator[{}]", mutations.names().join(","))),
-            mode: MOptMode::Pilotfuzzing,
             finds_before: 0,
             mutations,
             max_stack_pow,
@@ -641,10 +656,84 @@ where
     }
 
     fn scheduled_mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
-        let mode = self.mode;
+        let mode = state.metadata::<MOpt>()?.mode;
         match mode {
             MOptMode::Corefuzzing => self.core_mutate(state, input),
             MOptMode::Pilotfuzzing => self.pilot_mutate(state, input),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::{rands::StdRand, tuples::tuple_list, HasLen};
+
+    use super::{MOpt, MOptMode, StdMOptMutator};
+    use crate::{
+        corpus::InMemoryCorpus,
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        mutators::{havoc_mutations, BitFlipMutator, ByteDecMutator},
+        state::StdState,
+        HasMetadata,
+    };
+
+    #[test]
+    fn test_mopt_persistence() {
+        // # Safety
+        // No concurrency per testcase
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            MOpt::register();
+        }
+
+        let mut mopt = MOpt::new(4, 5, 1337).unwrap();
+        mopt.mode = MOptMode::Corefuzzing;
+        mopt.swarm_now = 3;
+        mopt.total_finds = 42;
+        let bytes = postcard::to_allocvec(&mopt).unwrap();
+        let restored: MOpt = postcard::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.mode, MOptMode::Corefuzzing);
+        assert_eq!(restored.swarm_now, 3);
+        assert_eq!(restored.total_finds, 42);
+        assert_eq!(restored.probability_now, mopt.probability_now);
+
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+
+        // A matching restored state is reused as-is.
+        state.add_metadata(restored);
+        let _mutator = StdMOptMutator::<BytesInput, _, _>::new(
+            &mut state,
+            tuple_list!(
+                BitFlipMutator::new(),
+                ByteDecMutator::new(),
+                BitFlipMutator::new(),
+                ByteDecMutator::new()
+            ),
+            7,
+            5,
+        )
+        .unwrap();
+        let mopt = state.metadata::<MOpt>().unwrap();
+        assert_eq!(mopt.mode, MOptMode::Corefuzzing);
+        assert_eq!(mopt.total_finds, 42);
+
+        // A state for different mutations is discarded.
+        let mutations = havoc_mutations::<BytesInput>();
+        let len = mutations.len();
+        let _mutator = StdMOptMutator::new(&mut state, mutations, 7, 5).unwrap();
+        let mopt = state.metadata::<MOpt>().unwrap();
+        assert_eq!(mopt.operator_num, len);
+        assert_eq!(mopt.mode, MOptMode::Pilotfuzzing);
+        assert_eq!(mopt.total_finds, 0);
+    }
+}
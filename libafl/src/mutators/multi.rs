@@ -1,13 +1,20 @@
 //! Mutator definitions for [`MultipartInput`]s. See [`crate::inputs::multi`] for details.
 
-use core::cmp::{min, Ordering};
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::{
+    cmp::{min, Ordering},
+    marker::PhantomData,
+};
 
-use libafl_bolts::{rands::Rand, Error};
+use libafl_bolts::{rands::Rand, Error, Named};
 
 use crate::{
     corpus::{Corpus, CorpusId},
     impl_default_multipart,
-    inputs::{multi::MultipartInput, HasMutatorBytes, Input},
+    inputs::{
+        multi::{HasPart, MultipartInput},
+        HasMutatorBytes, Input,
+    },
     mutators::{
         mutations::{
             rand_range, BitFlipMutator, ByteAddMutator, ByteDecMutator, ByteFlipMutator,
@@ -16,6 +23,7 @@ use crate::{
             BytesInsertMutator, BytesRandInsertMutator, BytesRandSetMutator, BytesSetMutator,
             BytesSwapMutator, CrossoverInsertMutator, CrossoverReplaceMutator, DwordAddMutator,
             DwordInterestingMutator, QwordAddMutator, WordAddMutator, WordInterestingMutator,
+            ARITH_MAX, INTERESTING_32,
         },
         token_mutations::{I2SRandReplace, TokenInsert, TokenReplace},
         MutationResult, Mutator,
@@ -320,3 +328,272 @@ where
         }
     }
 }
+
+/// Selects the parts of a [`MultipartInput`] a [`PartMutator`] may mutate
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PartSelector {
+    /// All parts
+    #[default]
+    Any,
+    /// The part with the given index
+    Index(usize),
+    /// All parts with the given name
+    Name(Cow<'static, str>),
+}
+
+impl PartSelector {
+    /// Checks if the part with the given index and name is selected
+    #[must_use]
+    pub fn matches(&self, idx: usize, name: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Index(selected) => *selected == idx,
+            Self::Name(selected) => selected == name,
+        }
+    }
+}
+
+/// How a [`PartMutator`] picks one of the selected parts for each mutation
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PartSchedule {
+    /// Pick a part uniformly at random
+    #[default]
+    Uniform,
+    /// Pick the parts in turn
+    RoundRobin,
+    /// Pick a part at random, weighted by its name. Parts not listed have a weight of 1.
+    Weighted(Vec<(String, u64)>),
+}
+
+/// Binds a mutator for parts of type `P` to the parts of a [`MultipartInput`] chosen by a
+/// [`PartSelector`], e.g. an integer mutator to the `flags` part of a
+/// [`crate::inputs::TypedMultipartInput`].
+/// Parts of other types are never passed to the mutator.
+#[derive(Debug)]
+pub struct PartMutator<M, P> {
+    name: Cow<'static, str>,
+    mutator: M,
+    selector: PartSelector,
+    schedule: PartSchedule,
+    next: usize,
+    phantom: PhantomData<fn() -> P>,
+}
+
+impl<I, M, P, S> Mutator<MultipartInput<I>, S> for PartMutator<M, P>
+where
+    I: HasPart<P>,
+    M: Mutator<P, S>,
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut MultipartInput<I>,
+    ) -> Result<MutationResult, Error> {
+        let candidates = input
+            .names()
+            .iter()
+            .zip(input.parts())
+            .enumerate()
+            .filter(|(idx, (name, part))| {
+                // Call it on `I`, every reference is a part of its own type as well
+                self.selector.matches(*idx, name) && HasPart::<P>::as_part(*part).is_some()
+            })
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let selected = match &self.schedule {
+            PartSchedule::Uniform => candidates[state.rand_mut().below(candidates.len())],
+            PartSchedule::RoundRobin => {
+                self.next = self.next.wrapping_add(1);
+                candidates[(self.next - 1) % candidates.len()]
+            }
+            PartSchedule::Weighted(weights) => {
+                let weight = |idx: usize| {
+                    let name = &input.names()[idx];
+                    weights
+                        .iter()
+                        .find(|(weighted, _)| weighted == name)
+                        .map_or(1, |(_, weight)| *weight)
+                };
+                let total: u64 = candidates.iter().map(|idx| weight(*idx)).sum();
+                if total == 0 {
+                    return Ok(MutationResult::Skipped);
+                }
+                let mut pick = state.rand_mut().below(total as usize) as u64;
+                *candidates
+                    .iter()
+                    .find(|idx| {
+                        let weight = weight(**idx);
+                        if pick < weight {
+                            true
+                        } else {
+                            pick -= weight;
+                            false
+                        }
+                    })
+                    .unwrap()
+            }
+        };
+
+        let part = input.part_mut(selected).unwrap().as_part_mut().unwrap();
+        self.mutator.mutate(state, part)
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        self.mutator.post_exec(state, new_corpus_id)
+    }
+}
+
+impl<M, P> Named for PartMutator<M, P> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<M, P> PartMutator<M, P>
+where
+    M: Named,
+{
+    /// Creates a new [`PartMutator`], applying the mutator to any part of type `P`
+    #[must_use]
+    pub fn new(mutator: M) -> Self {
+        Self {
+            name: Cow::Owned(format!("PartMutator<{}>", mutator.name())),
+            mutator,
+            selector: PartSelector::Any,
+            schedule: PartSchedule::Uniform,
+            next: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Only mutates the parts chosen by the selector
+    #[must_use]
+    pub fn on_parts(mut self, selector: PartSelector) -> Self {
+        self.name = Cow::Owned(match &selector {
+            PartSelector::Any => format!("PartMutator<{}>", self.mutator.name()),
+            PartSelector::Index(idx) => format!("PartMutator<{}>[{idx}]", self.mutator.name()),
+            PartSelector::Name(name) => format!("PartMutator<{}>[{name}]", self.mutator.name()),
+        });
+        self.selector = selector;
+        self
+    }
+
+    /// Picks the part to mutate according to the schedule
+    #[must_use]
+    pub fn with_schedule(mut self, schedule: PartSchedule) -> Self {
+        self.schedule = schedule;
+        self
+    }
+
+    /// The part selector
+    #[must_use]
+    pub fn selector(&self) -> &PartSelector {
+        &self.selector
+    }
+}
+
+/// Mutates an integer part, see [`crate::inputs::TypedPart::Integer`]: adds or subtracts a small
+/// value, flips a bit, or replaces it with an interesting or a random value
+#[derive(Debug, Default)]
+pub struct IntegerPartMutator;
+
+impl<S> Mutator<u64, S> for IntegerPartMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut u64) -> Result<MutationResult, Error> {
+        let rand = state.rand_mut();
+        let mutated = match rand.below(5) {
+            0 => input.wrapping_add(rand.between(1, ARITH_MAX) as u64),
+            1 => input.wrapping_sub(rand.between(1, ARITH_MAX) as u64),
+            2 => *input ^ (1 << rand.below(64)),
+            #[allow(clippy::cast_sign_loss)]
+            3 => i64::from(*rand.choose(&INTERESTING_32).unwrap()) as u64,
+            _ => rand.next(),
+        };
+        if mutated == *input {
+            return Ok(MutationResult::Skipped);
+        }
+        *input = mutated;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for IntegerPartMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("IntegerPartMutator");
+        &NAME
+    }
+}
+
+impl IntegerPartMutator {
+    /// Creates a new [`IntegerPartMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, vec};
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{IntegerPartMutator, PartMutator, PartSchedule, PartSelector};
+    use crate::{
+        corpus::InMemoryCorpus,
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasMutatorBytes, TypedMultipartInput, TypedPart},
+        mutators::{ByteRandMutator, MutationResult, Mutator},
+        state::StdState,
+    };
+
+    #[test]
+    fn test_typed_part_mutators() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<TypedMultipartInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let base = TypedMultipartInput::from([
+            (
+                "config",
+                TypedPart::from(BytesInput::new(b"config".to_vec())),
+            ),
+            ("data", TypedPart::from(BytesInput::new(b"data".to_vec()))),
+            ("flags", TypedPart::from(0_u64)),
+        ]);
+
+        let mut input = base.clone();
+        let mut flags = PartMutator::new(IntegerPartMutator::new())
+            .on_parts(PartSelector::Name(Cow::Borrowed("flags")));
+        while flags.mutate(&mut state, &mut input).unwrap() == MutationResult::Skipped {}
+        assert_eq!(input.parts()[..2], base.parts()[..2]);
+        assert_ne!(input.parts()[2], base.parts()[2]);
+
+        let mut input = base.clone();
+        let mut bytes: PartMutator<_, BytesInput> = PartMutator::new(ByteRandMutator::new())
+            .on_parts(PartSelector::Any)
+            .with_schedule(PartSchedule::Weighted(vec![("config".into(), 0)]));
+        for _ in 0..16 {
+            bytes.mutate(&mut state, &mut input).unwrap();
+        }
+        assert_eq!(input.parts()[0], base.parts()[0]);
+        assert_eq!(input.parts()[2], base.parts()[2]);
+        let TypedPart::Bytes(data) = &input.parts()[1] else {
+            unreachable!()
+        };
+        assert_ne!(data.bytes(), b"data");
+    }
+}
@@ -0,0 +1,611 @@
+//! Structure-aware mutators for [`ProtobufInput`]s, in the style of libprotobuf-mutator.
+//! They mutate the fields of the message, and of the messages nested in it, using the reflection
+//! of [`prost_reflect`], so the mutated message always stays valid.
+
+use alloc::{borrow::Cow, string::String, vec::Vec};
+
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+    Named,
+};
+use prost::bytes::Bytes;
+use prost_reflect::{DynamicMessage, FieldDescriptor, Kind, ReflectMessage, Value};
+
+use crate::{
+    corpus::Corpus,
+    inputs::ProtobufInput,
+    mutators::{MutationResult, Mutator, ARITH_MAX, INTERESTING_32},
+    random_corpus_id,
+    state::{HasCorpus, HasRand},
+    Error,
+};
+
+/// Interesting values for float and double fields
+const INTERESTING_FLOATS: [f64; 10] = [
+    0.0,
+    -0.0,
+    1.0,
+    -1.0,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    f64::NAN,
+    f64::MIN_POSITIVE,
+    f64::MAX,
+    f64::MIN,
+];
+
+/// The path from the root message to a nested message: the field numbers, with the index for
+/// repeated fields
+type MessagePath = Vec<(u32, Option<usize>)>;
+
+/// Collects the fields accepted by the filter, of the message and of all messages nested in its
+/// set fields, with the path to the message declaring them
+fn collect_fields<F>(
+    message: &DynamicMessage,
+    filter: &F,
+    path: &mut MessagePath,
+    fields: &mut Vec<(MessagePath, FieldDescriptor)>,
+) where
+    F: Fn(&DynamicMessage, &FieldDescriptor) -> bool,
+{
+    for field in message.descriptor().fields() {
+        if filter(message, &field) {
+            fields.push((path.clone(), field));
+        }
+    }
+    for (field, value) in message.fields() {
+        match value {
+            Value::Message(nested) => {
+                path.push((field.number(), None));
+                collect_fields(nested, filter, path, fields);
+                path.pop();
+            }
+            Value::List(items) => {
+                for (idx, item) in items.iter().enumerate() {
+                    if let Value::Message(nested) = item {
+                        path.push((field.number(), Some(idx)));
+                        collect_fields(nested, filter, path, fields);
+                        path.pop();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The message at the path
+fn message_at<'a>(
+    mut message: &'a mut DynamicMessage,
+    path: &[(u32, Option<usize>)],
+) -> Option<&'a mut DynamicMessage> {
+    for &(number, idx) in path {
+        let field = message.descriptor().get_field(number)?;
+        message = match (message.get_field_mut(&field), idx) {
+            (Value::Message(nested), None) => nested,
+            (Value::List(items), Some(idx)) => match items.get_mut(idx)? {
+                Value::Message(nested) => nested,
+                _ => return None,
+            },
+            _ => return None,
+        };
+    }
+    Some(message)
+}
+
+/// Picks a random field accepted by the filter, of the root message or of a message nested in
+/// it, returning the message declaring the field along with it
+fn random_field<'a, R, F>(
+    rand: &mut R,
+    root: &'a mut DynamicMessage,
+    filter: F,
+) -> Option<(&'a mut DynamicMessage, FieldDescriptor)>
+where
+    R: Rand,
+    F: Fn(&DynamicMessage, &FieldDescriptor) -> bool,
+{
+    let mut fields = Vec::new();
+    collect_fields(root, &filter, &mut Vec::new(), &mut fields);
+    let (path, field) = rand.choose(fields)?;
+    Some((message_at(root, &path)?, field))
+}
+
+/// Checks if the field holds a scalar, i.e. no message, in a singular or repeated field
+fn is_scalar(field: &FieldDescriptor) -> bool {
+    !field.is_map() && !matches!(field.kind(), Kind::Message(_))
+}
+
+/// Mutates an integer: small additions and subtractions, interesting values, or random bits
+#[allow(clippy::cast_possible_wrap)]
+fn mutate_int<R>(rand: &mut R, value: i64) -> i64
+where
+    R: Rand,
+{
+    let delta = 1 + rand.below(ARITH_MAX) as i64;
+    match rand.below(4) {
+        0 => value.wrapping_add(delta),
+        1 => value.wrapping_sub(delta),
+        2 => i64::from(*rand.choose(&INTERESTING_32).unwrap()),
+        _ => rand.next() as i64,
+    }
+}
+
+/// Mutates a float: interesting values, small changes, or scaling
+fn mutate_float<R>(rand: &mut R, value: f64) -> f64
+where
+    R: Rand,
+{
+    match rand.below(3) {
+        0 => *rand.choose(&INTERESTING_FLOATS).unwrap(),
+        1 => value + rand.next_float() * 2.0 - 1.0,
+        _ => value * (rand.next_float() * 4.0 - 2.0),
+    }
+}
+
+/// A random char, mostly printable ascii
+#[allow(clippy::cast_possible_truncation)]
+fn random_char<R>(rand: &mut R) -> char
+where
+    R: Rand,
+{
+    if rand.coinflip(0.9) {
+        char::from(b' ' + rand.below(95) as u8)
+    } else {
+        char::from_u32(rand.below(0x11_0000) as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+    }
+}
+
+/// Inserts, replaces or deletes a char, keeping the string valid utf-8
+fn mutate_string<R>(rand: &mut R, string: &mut String)
+where
+    R: Rand,
+{
+    let mut chars: Vec<char> = string.chars().collect();
+    let len = chars.len();
+    match rand.below(3) {
+        0 if len > 0 => {
+            chars.remove(rand.below(len));
+        }
+        1 if len > 0 => {
+            let idx = rand.below(len);
+            chars[idx] = random_char(rand);
+        }
+        _ => {
+            let idx = rand.below(len + 1);
+            chars.insert(idx, random_char(rand));
+        }
+    }
+    *string = chars.into_iter().collect();
+}
+
+/// Flips a bit, or inserts, replaces or deletes a byte
+#[allow(clippy::cast_possible_truncation)]
+fn mutate_bytes<R>(rand: &mut R, bytes: &Bytes) -> Bytes
+where
+    R: Rand,
+{
+    let mut bytes = bytes.to_vec();
+    let len = bytes.len();
+    match rand.below(4) {
+        0 if len > 0 => {
+            let idx = rand.below(len);
+            bytes[idx] ^= 1 << rand.below(8);
+        }
+        1 if len > 0 => {
+            bytes.remove(rand.below(len));
+        }
+        2 if len > 0 => {
+            let idx = rand.below(len);
+            bytes[idx] = rand.next() as u8;
+        }
+        _ => {
+            let idx = rand.below(len + 1);
+            bytes.insert(idx, rand.next() as u8);
+        }
+    }
+    Bytes::from(bytes)
+}
+
+/// Mutates a scalar value of the given kind within the bounds of its type.
+/// Returns `false` if the value is no scalar, or an enum without values.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_possible_wrap,
+    clippy::cast_sign_loss
+)]
+fn mutate_scalar<R>(rand: &mut R, kind: &Kind, value: &mut Value) -> bool
+where
+    R: Rand,
+{
+    match value {
+        Value::Bool(v) => *v = !*v,
+        Value::I32(v) => *v = mutate_int(rand, i64::from(*v)) as i32,
+        Value::I64(v) => *v = mutate_int(rand, *v),
+        Value::U32(v) => *v = mutate_int(rand, i64::from(*v)) as u32,
+        Value::U64(v) => *v = mutate_int(rand, *v as i64) as u64,
+        Value::F32(v) => *v = mutate_float(rand, f64::from(*v)) as f32,
+        Value::F64(v) => *v = mutate_float(rand, *v),
+        Value::String(v) => mutate_string(rand, v),
+        Value::Bytes(v) => *v = mutate_bytes(rand, v),
+        Value::EnumNumber(v) => {
+            let Kind::Enum(descriptor) = kind else {
+                return false;
+            };
+            let Some(enum_value) = rand.choose(descriptor.values()) else {
+                return false;
+            };
+            *v = enum_value.number();
+        }
+        Value::Message(_) | Value::List(_) | Value::Map(_) => return false,
+    }
+    true
+}
+
+/// Sets an unset singular field to a random value, or clears a set one
+#[derive(Debug, Default)]
+pub struct ProtobufPresenceMutator;
+
+impl<M, S> Mutator<ProtobufInput<M>, S> for ProtobufPresenceMutator
+where
+    M: ReflectMessage + Default,
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput<M>,
+    ) -> Result<MutationResult, Error> {
+        let mut root = input.to_dynamic();
+        let rand = state.rand_mut();
+        let Some((message, field)) = random_field(rand, &mut root, |_, field| {
+            !field.is_list() && !field.is_map()
+        }) else {
+            return Ok(MutationResult::Skipped);
+        };
+        if message.has_field(&field) {
+            message.clear_field(&field);
+        } else {
+            let mut value = Value::default_value_for_field(&field);
+            mutate_scalar(rand, &field.kind(), &mut value);
+            message.set_field(&field, value);
+        }
+        input.set_dynamic(&root)?;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for ProtobufPresenceMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufPresenceMutator");
+        &NAME
+    }
+}
+
+impl ProtobufPresenceMutator {
+    /// Creates a new [`ProtobufPresenceMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Mutates a scalar field, or an item of a repeated scalar field, within the bounds of its type.
+/// Enum fields only get the values declared for the enum.
+#[derive(Debug, Default)]
+pub struct ProtobufScalarMutator;
+
+impl<M, S> Mutator<ProtobufInput<M>, S> for ProtobufScalarMutator
+where
+    M: ReflectMessage + Default,
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput<M>,
+    ) -> Result<MutationResult, Error> {
+        let mut root = input.to_dynamic();
+        let rand = state.rand_mut();
+        let Some((message, field)) = random_field(rand, &mut root, |_, field| is_scalar(field))
+        else {
+            return Ok(MutationResult::Skipped);
+        };
+        let kind = field.kind();
+        let mutated = match message.get_field_mut(&field) {
+            Value::List(items) => {
+                if items.is_empty() {
+                    items.push(Value::default_value(&kind));
+                }
+                let idx = rand.below(items.len());
+                mutate_scalar(rand, &kind, &mut items[idx])
+            }
+            value => mutate_scalar(rand, &kind, value),
+        };
+        if !mutated {
+            return Ok(MutationResult::Skipped);
+        }
+        input.set_dynamic(&root)?;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for ProtobufScalarMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufScalarMutator");
+        &NAME
+    }
+}
+
+impl ProtobufScalarMutator {
+    /// Creates a new [`ProtobufScalarMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Shuffles the items of a repeated field
+#[derive(Debug, Default)]
+pub struct ProtobufShuffleMutator;
+
+impl<M, S> Mutator<ProtobufInput<M>, S> for ProtobufShuffleMutator
+where
+    M: ReflectMessage + Default,
+    S: HasRand,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput<M>,
+    ) -> Result<MutationResult, Error> {
+        let mut root = input.to_dynamic();
+        let rand = state.rand_mut();
+        let Some((message, field)) = random_field(rand, &mut root, |message, field| {
+            field.is_list()
+                && matches!(&*message.get_field(field), Value::List(items) if items.len() > 1)
+        }) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Value::List(items) = message.get_field_mut(&field) else {
+            return Ok(MutationResult::Skipped);
+        };
+        for idx in (1..items.len()).rev() {
+            let other = rand.below(idx + 1);
+            items.swap(idx, other);
+        }
+        input.set_dynamic(&root)?;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for ProtobufShuffleMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufShuffleMutator");
+        &NAME
+    }
+}
+
+impl ProtobufShuffleMutator {
+    /// Creates a new [`ProtobufShuffleMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Copies a set field of another corpus entry into a message of the same type in the input,
+/// which may be nested at a different position
+#[derive(Debug, Default)]
+pub struct ProtobufCrossoverMutator;
+
+impl<M, S> Mutator<ProtobufInput<M>, S> for ProtobufCrossoverMutator
+where
+    M: ReflectMessage + Default + Clone,
+    S: HasRand + HasCorpus<Input = ProtobufInput<M>>,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput<M>,
+    ) -> Result<MutationResult, Error> {
+        if state.corpus().is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let id = random_corpus_id!(state.corpus(), state.rand_mut());
+        // We don't want to use the testcase we're already using for crossover
+        if *state.corpus().current() == Some(id) {
+            return Ok(MutationResult::Skipped);
+        }
+        let mut donor = {
+            let mut other_testcase = state.corpus().get(id)?.borrow_mut();
+            other_testcase.load_input(state.corpus())?.to_dynamic()
+        };
+
+        let rand = state.rand_mut();
+        let Some((donor_message, field)) =
+            random_field(rand, &mut donor, |message, field| message.has_field(field))
+        else {
+            return Ok(MutationResult::Skipped);
+        };
+        let value = donor_message.get_field(&field).into_owned();
+
+        let mut root = input.to_dynamic();
+        let Some((message, field)) = random_field(rand, &mut root, |_, other| *other == field)
+        else {
+            return Ok(MutationResult::Skipped);
+        };
+        message.set_field(&field, value);
+        input.set_dynamic(&root)?;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for ProtobufCrossoverMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufCrossoverMutator");
+        &NAME
+    }
+}
+
+impl ProtobufCrossoverMutator {
+    /// Creates a new [`ProtobufCrossoverMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// The type of the mutations for [`ProtobufInput`]s
+pub type ProtobufMutationsType = tuple_list_type!(
+    ProtobufPresenceMutator,
+    ProtobufScalarMutator,
+    ProtobufShuffleMutator,
+    ProtobufCrossoverMutator,
+);
+
+/// Get the mutations for [`ProtobufInput`]s
+#[must_use]
+pub fn protobuf_mutations() -> ProtobufMutationsType {
+    tuple_list!(
+        ProtobufPresenceMutator::new(),
+        ProtobufScalarMutator::new(),
+        ProtobufShuffleMutator::new(),
+        ProtobufCrossoverMutator::new(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use prost_reflect::prost_types::{value::Kind, ListValue, Timestamp, Value};
+
+    use super::{
+        ProtobufCrossoverMutator, ProtobufPresenceMutator, ProtobufScalarMutator,
+        ProtobufShuffleMutator,
+    };
+    use crate::{
+        corpus::{Corpus, Testcase},
+        inputs::ProtobufInput,
+        mutators::{MutationResult, Mutator},
+        state::{test::test_std_state, HasCorpus},
+    };
+
+    /// A list of the numbers in `0..len`
+    #[allow(clippy::cast_precision_loss)]
+    fn number_list(len: usize) -> ListValue {
+        ListValue {
+            values: (0..len)
+                .map(|number| Value {
+                    kind: Some(Kind::NumberValue(number as f64)),
+                })
+                .collect(),
+        }
+    }
+
+    /// The numbers in the list
+    fn numbers(list: &ListValue) -> Vec<f64> {
+        list.values
+            .iter()
+            .map(|value| match value.kind {
+                Some(Kind::NumberValue(number)) => number,
+                _ => panic!("not a number: {value:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_protobuf_scalar_mutator() {
+        let mut state = test_std_state::<ProtobufInput<Timestamp>>();
+        let mut mutator = ProtobufScalarMutator::new();
+        let original = ProtobufInput::new(Timestamp {
+            seconds: 42,
+            nanos: 7,
+        });
+        let mut changed = false;
+        for _ in 0..32 {
+            let mut input = original.clone();
+            assert_eq!(
+                mutator.mutate(&mut state, &mut input).unwrap(),
+                MutationResult::Mutated
+            );
+            changed |= input != original;
+        }
+        assert!(changed);
+    }
+
+    #[test]
+    fn test_protobuf_presence_mutator() {
+        let mut state = test_std_state::<ProtobufInput<Timestamp>>();
+        let mut mutator = ProtobufPresenceMutator::new();
+        // Both fields are set, so one of them is cleared
+        let mut input = ProtobufInput::new(Timestamp {
+            seconds: 1,
+            nanos: 1,
+        });
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Mutated
+        );
+        let message = input.message();
+        assert!(
+            (message.seconds, message.nanos) == (0, 1)
+                || (message.seconds, message.nanos) == (1, 0),
+            "{message:?}"
+        );
+    }
+
+    #[test]
+    fn test_protobuf_shuffle_mutator() {
+        let mut state = test_std_state::<ProtobufInput<ListValue>>();
+        let mut mutator = ProtobufShuffleMutator::new();
+
+        let mut input = ProtobufInput::new(number_list(1));
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Skipped
+        );
+
+        let original = number_list(16);
+        let mut input = ProtobufInput::new(original.clone());
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Mutated
+        );
+        let mut shuffled = numbers(input.message());
+        assert_ne!(shuffled, numbers(&original));
+        shuffled.sort_by(f64::total_cmp);
+        assert_eq!(shuffled, numbers(&original));
+    }
+
+    #[test]
+    fn test_protobuf_crossover_mutator() {
+        let mut state = test_std_state::<ProtobufInput<Timestamp>>();
+        let mut mutator = ProtobufCrossoverMutator::new();
+        let mut input = ProtobufInput::new(Timestamp::default());
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Skipped
+        );
+
+        state
+            .corpus_mut()
+            .add(Testcase::new(ProtobufInput::new(Timestamp {
+                seconds: 5,
+                nanos: 6,
+            })))
+            .unwrap();
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input).unwrap(),
+            MutationResult::Mutated
+        );
+        // One field of the donor was copied
+        let message = input.message();
+        assert!(
+            (message.seconds, message.nanos) == (5, 0)
+                || (message.seconds, message.nanos) == (0, 6),
+            "{message:?}"
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Deterministic replay of the mutations that produced a testcase.
+//!
+//! The [`ReplayLogMutator`] wraps the mutator of a campaign and records the state of the random
+//! number generator before each mutation in a [`MutationReplayMetadata`]. New corpus entries get
+//! the record of the mutation that produced them; for solutions, add a
+//! [`crate::feedbacks::MutationReplayFeedback`] to the objective. With [`replay_mutation`], the
+//! exact input is produced again from the parent, e.g. to run it with a
+//! [`crate::triage::ReproRunner`] and debug the behavior of the fuzzer.
+//!
+//! For a fully deterministic campaign, seed the rand of each client with
+//! [`libafl_bolts::rands::derive_seed`] from a root seed, and log the scheduling decisions with a
+//! [`crate::schedulers::LoggingScheduler`].
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::hash::Hasher;
+
+use libafl_bolts::{hasher_std, rands::Rand, Named};
+use postcard::ser_flavors::Flavor;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId},
+    mutators::{MutationResult, Mutator},
+    state::{HasCorpus, HasRand},
+    Error, HasMetadata,
+};
+
+/// The record of a mutation, to replay it with [`replay_mutation`].
+///
+/// The [`ReplayLogMutator`] keeps the record in the state until the mutated input was evaluated,
+/// and adds it to the corpus entry the mutation produced.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct MutationReplayMetadata {
+    /// The testcase the mutated input was cloned from
+    pub parent_id: Option<CorpusId>,
+    /// The serialized state of the rand before the mutation
+    pub rand: Vec<u8>,
+    /// The number of times the mutator ran on the input, e.g. the stack of a mutational stage
+    pub mutations: usize,
+}
+
+libafl_bolts::impl_serdeany!(MutationReplayMetadata);
+
+/// Wraps a mutator and records the state of the rand before each mutation, see the
+/// [module docs](self).
+#[derive(Debug, Clone)]
+pub struct ReplayLogMutator<M> {
+    name: Cow<'static, str>,
+    mutator: M,
+    /// The hash of the state of the rand after the last mutation
+    rand_after: Option<u64>,
+}
+
+/// A postcard flavor hashing the serialized bytes instead of storing them
+struct HashFlavor<H>(H);
+
+impl<H> Flavor for HashFlavor<H>
+where
+    H: Hasher,
+{
+    type Output = u64;
+
+    fn try_push(&mut self, data: u8) -> postcard::Result<()> {
+        self.0.write_u8(data);
+        Ok(())
+    }
+
+    fn try_extend(&mut self, data: &[u8]) -> postcard::Result<()> {
+        self.0.write(data);
+        Ok(())
+    }
+
+    fn finalize(self) -> postcard::Result<u64> {
+        Ok(self.0.finish())
+    }
+}
+
+/// Hashes the state of the rand, without allocating in the hot loop
+fn hash_rand<R>(rand: &R) -> Result<u64, Error>
+where
+    R: Rand,
+{
+    Ok(postcard::serialize_with_flavor(
+        rand,
+        HashFlavor(hasher_std()),
+    )?)
+}
+
+impl<M> ReplayLogMutator<M>
+where
+    M: Named,
+{
+    /// Creates a new [`ReplayLogMutator`] wrapping the mutator
+    pub fn new(mutator: M) -> Self {
+        Self {
+            name: Cow::from(format!("ReplayLogMutator<{}>", mutator.name())),
+            mutator,
+            rand_after: None,
+        }
+    }
+}
+
+impl<M> ReplayLogMutator<M> {
+    /// The wrapped mutator
+    pub fn mutator(&self) -> &M {
+        &self.mutator
+    }
+
+    /// The wrapped mutator (mutable)
+    pub fn mutator_mut(&mut self) -> &mut M {
+        &mut self.mutator
+    }
+}
+
+impl<M> Named for ReplayLogMutator<M> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, M, S> Mutator<I, S> for ReplayLogMutator<M>
+where
+    M: Mutator<I, S>,
+    S: HasRand + HasCorpus + HasMetadata,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let parent_id = *state.corpus().current();
+        // Stacked mutations of the same input continue the pending record
+        let stacked = self.rand_after == Some(hash_rand(state.rand())?)
+            && state
+                .metadata::<MutationReplayMetadata>()
+                .is_ok_and(|record| record.parent_id == parent_id);
+        if stacked {
+            state.metadata_mut::<MutationReplayMetadata>()?.mutations += 1;
+        } else {
+            state.add_metadata(MutationReplayMetadata {
+                parent_id,
+                rand: postcard::to_allocvec(state.rand())?,
+                mutations: 1,
+            });
+        }
+
+        let res = self.mutator.mutate(state, input);
+        self.rand_after = Some(hash_rand(state.rand())?);
+        res
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        self.mutator.post_exec(state, new_corpus_id)?;
+        // The record only belongs to the input mutated last
+        let record = state.remove_metadata::<MutationReplayMetadata>();
+        if let (Some(id), Some(record)) = (new_corpus_id, record) {
+            state.corpus().get(id)?.borrow_mut().add_metadata(*record);
+        }
+        Ok(())
+    }
+}
+
+/// Produces the input of a recorded mutation again, by mutating a clone of the parent with the
+/// recorded state of the rand, as often as recorded.
+///
+/// The mutator has to be composed like the one that ran the mutation, and only draw from the rand
+/// of the state. Mutations depending on the rest of the state, e.g. crossovers picking another
+/// corpus entry, only replay exactly with the same state. The rand of the state is restored
+/// afterwards.
+pub fn replay_mutation<M, S>(
+    mutator: &mut M,
+    state: &mut S,
+    record: &MutationReplayMetadata,
+) -> Result<S::Input, Error>
+where
+    M: Mutator<S::Input, S>,
+    S: HasRand + HasCorpus,
+{
+    let parent_id = record
+        .parent_id
+        .ok_or_else(|| Error::illegal_argument("The recorded mutation has no parent testcase"))?;
+    let mut input = state.corpus().cloned_input_for_id(parent_id)?;
+
+    let rand = core::mem::replace(state.rand_mut(), postcard::from_bytes(&record.rand)?);
+    let res = (0..record.mutations).try_for_each(|_| mutator.mutate(state, &mut input).map(drop));
+    *state.rand_mut() = rand;
+    res?;
+    Ok(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::rands::StdRand;
+
+    use super::{replay_mutation, MutationReplayMetadata, ReplayLogMutator};
+    use crate::{
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        mutators::{havoc_mutations_no_crossover, Mutator, StdScheduledMutator},
+        state::{HasCorpus, StdState},
+        HasMetadata,
+    };
+
+    #[test]
+    fn test_replay_mutation() {
+        let mut corpus = InMemoryCorpus::new();
+        let parent = corpus
+            .add(Testcase::new(BytesInput::new(b"replay me".to_vec())))
+            .unwrap();
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        *state.corpus_mut().current_mut() = Some(parent);
+
+        let mut mutator =
+            ReplayLogMutator::new(StdScheduledMutator::new(havoc_mutations_no_crossover()));
+        // Stacked mutations, as in a mutational stage
+        let mut mutated = state.corpus().cloned_input_for_id(parent).unwrap();
+        mutator.mutate(&mut state, &mut mutated).unwrap();
+        mutator.mutate(&mut state, &mut mutated).unwrap();
+        let record = state.metadata::<MutationReplayMetadata>().unwrap().clone();
+        assert_eq!(record.parent_id, Some(parent));
+        assert_eq!(record.mutations, 2);
+
+        let new = state
+            .corpus_mut()
+            .add(Testcase::new(mutated.clone()))
+            .unwrap();
+        mutator.post_exec(&mut state, Some(new)).unwrap();
+        assert!(!state.has_metadata::<MutationReplayMetadata>());
+        assert!(state
+            .corpus()
+            .get(new)
+            .unwrap()
+            .borrow()
+            .has_metadata::<MutationReplayMetadata>());
+
+        // Other mutations in between don't matter
+        let mut other = mutated.clone();
+        mutator.mutate(&mut state, &mut other).unwrap();
+
+        let replayed = replay_mutation(mutator.mutator_mut(), &mut state, &record).unwrap();
+        assert_eq!(replayed, mutated);
+    }
+}
@@ -207,7 +207,8 @@ where
         }
     }
 
-    /// Create a new [`StdScheduledMutator`] instance specifying mutations and the maximun number of iterations
+    /// Create a new [`StdScheduledMutator`] instance specifying mutations and the maximun number of iterations.
+    /// The maximum power of two of stacked mutations is at least `1`.
     pub fn with_max_stack_pow(mutations: MT, max_stack_pow: usize) -> Self {
         StdScheduledMutator {
             name: Cow::from(format!(
@@ -215,7 +216,7 @@ where
                 mutations.names().join(", ")
             )),
             mutations,
-            max_stack_pow,
+            max_stack_pow: max_stack_pow.max(1),
             phantom: PhantomData,
         }
     }
@@ -475,18 +476,21 @@ where
 
 #[cfg(test)]
 mod tests {
-    use libafl_bolts::rands::{StdRand, XkcdRand};
+    use libafl_bolts::{
+        rands::{StdRand, XkcdRand},
+        tuples::tuple_list,
+    };
 
     use crate::{
         corpus::{Corpus, InMemoryCorpus, Testcase},
         feedbacks::ConstFeedback,
         inputs::{BytesInput, HasMutatorBytes},
         mutators::{
-            mutations::SpliceMutator,
-            scheduled::{havoc_mutations, StdScheduledMutator},
+            mutations::{BitFlipMutator, SpliceMutator},
+            scheduled::{havoc_mutations, ScheduledMutator, StdScheduledMutator},
             Mutator,
         },
-        state::StdState,
+        state::{NopState, StdState},
     };
 
     #[test]
@@ -567,4 +571,14 @@ mod tests {
             assert_ne!(equal_in_a_row, 5);
         }
     }
+
+    #[test]
+    fn test_zero_max_stack_pow() {
+        let mut state: NopState<BytesInput> = NopState::new();
+        let input = BytesInput::new(vec![42]);
+        let havoc = StdScheduledMutator::with_max_stack_pow(tuple_list!(BitFlipMutator::new()), 0);
+        for _ in 0..16 {
+            assert_eq!(havoc.iterations(&mut state, &input), 2);
+        }
+    }
 }
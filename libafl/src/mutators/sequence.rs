@@ -0,0 +1,346 @@
+//! Mutators for [`SequenceInput`]s, changing the order and number of operations, or their
+//! arguments.
+
+use alloc::{borrow::Cow, vec::Vec};
+
+use libafl_bolts::{rands::Rand, HasLen, Named};
+
+use crate::{
+    inputs::{SequenceInput, SequenceOp},
+    mutators::{MutationResult, Mutator, ARITH_MAX, INTERESTING_32},
+    state::HasRand,
+    Error,
+};
+
+/// The default maximal number of operations the mutators grow a sequence to
+pub const DEFAULT_MAX_SEQUENCE_LEN: usize = 64;
+
+/// Inserts an operation from a set of templates, e.g. one per supported syscall, at a random
+/// position. Combine it with the [`SequenceArgMutator`] to vary the arguments of inserted
+/// operations.
+#[derive(Debug)]
+pub struct SequenceInsertMutator<T> {
+    templates: Vec<T>,
+    max_len: usize,
+}
+
+impl<S, T> Mutator<SequenceInput<T>, S> for SequenceInsertMutator<T>
+where
+    S: HasRand,
+    T: SequenceOp,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut SequenceInput<T>,
+    ) -> Result<MutationResult, Error> {
+        if self.templates.is_empty() || input.len() >= self.max_len {
+            return Ok(MutationResult::Skipped);
+        }
+        let rand = state.rand_mut();
+        let op = rand.choose(&self.templates).unwrap().clone();
+        let position = rand.below(input.len() + 1);
+        input.ops_mut().insert(position, op);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<T> Named for SequenceInsertMutator<T> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SequenceInsertMutator");
+        &NAME
+    }
+}
+
+impl<T> SequenceInsertMutator<T> {
+    /// Creates a new [`SequenceInsertMutator`], inserting copies of the templates
+    #[must_use]
+    pub fn new(templates: Vec<T>) -> Self {
+        Self {
+            templates,
+            max_len: DEFAULT_MAX_SEQUENCE_LEN,
+        }
+    }
+
+    /// Sets the maximal number of operations of a sequence
+    #[must_use]
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+/// Deletes a random operation
+#[derive(Debug, Default)]
+pub struct SequenceDeleteMutator;
+
+impl<S, T> Mutator<SequenceInput<T>, S> for SequenceDeleteMutator
+where
+    S: HasRand,
+    T: SequenceOp,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut SequenceInput<T>,
+    ) -> Result<MutationResult, Error> {
+        if input.len() <= 1 {
+            return Ok(MutationResult::Skipped);
+        }
+        let position = state.rand_mut().below(input.len());
+        input.ops_mut().remove(position);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for SequenceDeleteMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SequenceDeleteMutator");
+        &NAME
+    }
+}
+
+impl SequenceDeleteMutator {
+    /// Creates a new [`SequenceDeleteMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Swaps two random operations
+#[derive(Debug, Default)]
+pub struct SequenceSwapMutator;
+
+impl<S, T> Mutator<SequenceInput<T>, S> for SequenceSwapMutator
+where
+    S: HasRand,
+    T: SequenceOp,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut SequenceInput<T>,
+    ) -> Result<MutationResult, Error> {
+        if input.len() < 2 {
+            return Ok(MutationResult::Skipped);
+        }
+        let rand = state.rand_mut();
+        let first = rand.below(input.len());
+        let second = (first + rand.between(1, input.len() - 1)) % input.len();
+        input.ops_mut().swap(first, second);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for SequenceSwapMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SequenceSwapMutator");
+        &NAME
+    }
+}
+
+impl SequenceSwapMutator {
+    /// Creates a new [`SequenceSwapMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Duplicates a random operation, inserting the copy at a random position
+#[derive(Debug)]
+pub struct SequenceDuplicateMutator {
+    max_len: usize,
+}
+
+impl<S, T> Mutator<SequenceInput<T>, S> for SequenceDuplicateMutator
+where
+    S: HasRand,
+    T: SequenceOp,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut SequenceInput<T>,
+    ) -> Result<MutationResult, Error> {
+        if input.is_empty() || input.len() >= self.max_len {
+            return Ok(MutationResult::Skipped);
+        }
+        let rand = state.rand_mut();
+        let op = input.ops()[rand.below(input.len())].clone();
+        let position = rand.below(input.len() + 1);
+        input.ops_mut().insert(position, op);
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for SequenceDuplicateMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SequenceDuplicateMutator");
+        &NAME
+    }
+}
+
+impl Default for SequenceDuplicateMutator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SequenceDuplicateMutator {
+    /// Creates a new [`SequenceDuplicateMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            max_len: DEFAULT_MAX_SEQUENCE_LEN,
+        }
+    }
+
+    /// Sets the maximal number of operations of a sequence
+    #[must_use]
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+/// Mutates an argument of a random operation: adds or subtracts a small value, flips a bit,
+/// replaces it with an interesting or a random value, or copies an argument of another operation,
+/// e.g. to reuse a handle returned earlier
+#[derive(Debug, Default)]
+pub struct SequenceArgMutator;
+
+impl<S, T> Mutator<SequenceInput<T>, S> for SequenceArgMutator
+where
+    S: HasRand,
+    T: SequenceOp,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut SequenceInput<T>,
+    ) -> Result<MutationResult, Error> {
+        let with_args = input
+            .ops()
+            .iter()
+            .enumerate()
+            .filter(|(_, op)| !op.args().is_empty())
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        if with_args.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let rand = state.rand_mut();
+        let op = *rand.choose(&with_args).unwrap();
+        let arg = rand.below(input.ops()[op].args().len());
+        let value = input.ops()[op].args()[arg];
+
+        let mutated = match rand.below(6) {
+            0 => value.wrapping_add(rand.between(1, ARITH_MAX) as u64),
+            1 => value.wrapping_sub(rand.between(1, ARITH_MAX) as u64),
+            2 => value ^ (1 << rand.below(64)),
+            #[allow(clippy::cast_sign_loss)]
+            3 => i64::from(*rand.choose(&INTERESTING_32).unwrap()) as u64,
+            4 => {
+                let other = input.ops()[*rand.choose(&with_args).unwrap()].args();
+                other[rand.below(other.len())]
+            }
+            _ => rand.next(),
+        };
+        if mutated == value {
+            return Ok(MutationResult::Skipped);
+        }
+        input.ops_mut()[op].args_mut()[arg] = mutated;
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl Named for SequenceArgMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("SequenceArgMutator");
+        &NAME
+    }
+}
+
+impl SequenceArgMutator {
+    /// Creates a new [`SequenceArgMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use libafl_bolts::{rands::StdRand, HasLen};
+
+    use super::{
+        SequenceArgMutator, SequenceDeleteMutator, SequenceDuplicateMutator, SequenceInsertMutator,
+        SequenceSwapMutator,
+    };
+    use crate::{
+        corpus::InMemoryCorpus,
+        feedbacks::ConstFeedback,
+        inputs::{Operation, SequenceInput},
+        mutators::{MutationResult, Mutator},
+        state::StdState,
+    };
+
+    #[test]
+    fn test_sequence_mutators() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<SequenceInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let base = SequenceInput::new(vec![
+            Operation::new(0, vec![1]),
+            Operation::new(1, vec![2, 3]),
+        ]);
+
+        let mut input = base.clone();
+        SequenceInsertMutator::new(vec![Operation::new(7, vec![])])
+            .mutate(&mut state, &mut input)
+            .unwrap();
+        assert_eq!(input.len(), 3);
+        assert!(input.ops().iter().any(|op| op.opcode == 7));
+
+        let mut input = base.clone();
+        SequenceDeleteMutator::new()
+            .mutate(&mut state, &mut input)
+            .unwrap();
+        assert_eq!(input.len(), 1);
+
+        let mut input = base.clone();
+        SequenceSwapMutator::new()
+            .mutate(&mut state, &mut input)
+            .unwrap();
+        assert_eq!(input.ops()[0], base.ops()[1]);
+
+        let mut input = base.clone();
+        assert_eq!(
+            SequenceDuplicateMutator::new()
+                .with_max_len(2)
+                .mutate(&mut state, &mut input)
+                .unwrap(),
+            MutationResult::Skipped
+        );
+
+        let mut input = base.clone();
+        while SequenceArgMutator::new()
+            .mutate(&mut state, &mut input)
+            .unwrap()
+            == MutationResult::Skipped
+        {}
+        assert_ne!(input, base);
+        assert_eq!(input.len(), base.len());
+    }
+}
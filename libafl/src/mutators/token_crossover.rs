@@ -0,0 +1,486 @@
+//! Crossover mutations that respect token boundaries.
+//! Instead of splitting inputs at arbitrary byte offsets, they split them where a [`TokenLexer`]
+//! says a token starts or ends, so keywords, identifiers and numbers of text formats stay intact.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::cmp::min;
+
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type, Merge},
+    Named,
+};
+
+use crate::{
+    corpus::Corpus,
+    inputs::HasMutatorBytes,
+    mutators::{
+        havoc_mutations_no_crossover, HavocMutationsNoCrossoverType, MutationResult, Mutator,
+        Tokens,
+    },
+    random_corpus_id_with_disabled,
+    state::{HasCorpus, HasMaxSize, HasRand},
+    Error, HasMetadata,
+};
+
+/// The maximal number of tokens copied from the other input in one crossover
+const MAX_CROSSOVER_TOKENS: usize = 16;
+
+/// Finds the token boundaries of an input, for token-aware crossover.
+///
+/// It is implemented for closures taking the bytes and pushing the boundary offsets.
+pub trait TokenLexer<S> {
+    /// Pushes the offsets at which tokens start or end in the bytes.
+    /// The start and the end of the input are always boundaries, they don't need to be pushed.
+    fn boundaries(&mut self, state: &S, bytes: &[u8], boundaries: &mut Vec<usize>);
+}
+
+impl<F, S> TokenLexer<S> for F
+where
+    F: FnMut(&[u8], &mut Vec<usize>),
+{
+    fn boundaries(&mut self, _state: &S, bytes: &[u8], boundaries: &mut Vec<usize>) {
+        self(bytes, boundaries);
+    }
+}
+
+/// The class of a byte for [`char_class_boundaries`]
+fn char_class(byte: u8) -> u8 {
+    if byte.is_ascii_alphanumeric() || byte == b'_' || byte >= 0x80 {
+        0
+    } else if byte.is_ascii_whitespace() {
+        1
+    } else {
+        2
+    }
+}
+
+/// Pushes the boundaries between words, whitespace and punctuation, where every punctuation
+/// character is a token of its own
+pub fn char_class_boundaries(bytes: &[u8], boundaries: &mut Vec<usize>) {
+    for idx in 1..bytes.len() {
+        let (prev, cur) = (char_class(bytes[idx - 1]), char_class(bytes[idx]));
+        if prev != cur || cur == 2 {
+            boundaries.push(idx);
+        }
+    }
+}
+
+/// A [`TokenLexer`] splitting inputs at the occurrences of the [`Tokens`] in the state metadata,
+/// as well as between words, whitespace and punctuation, see [`char_class_boundaries`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokensLexer;
+
+impl<S> TokenLexer<S> for TokensLexer
+where
+    S: HasMetadata,
+{
+    fn boundaries(&mut self, state: &S, bytes: &[u8], boundaries: &mut Vec<usize>) {
+        char_class_boundaries(bytes, boundaries);
+        let Ok(tokens) = state.metadata::<Tokens>() else {
+            return;
+        };
+        for token in tokens.tokens() {
+            if token.is_empty() || token.len() > bytes.len() {
+                continue;
+            }
+            for (idx, window) in bytes.windows(token.len()).enumerate() {
+                if window == token.as_slice() {
+                    boundaries.push(idx);
+                    boundaries.push(idx + token.len());
+                }
+            }
+        }
+    }
+}
+
+impl TokensLexer {
+    /// Creates a new [`TokensLexer`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// The sorted, deduplicated token boundaries of the bytes, including their start and end
+fn token_boundaries<L, S>(lexer: &mut L, state: &S, bytes: &[u8]) -> Vec<usize>
+where
+    L: TokenLexer<S>,
+{
+    let mut boundaries = Vec::new();
+    lexer.boundaries(state, bytes, &mut boundaries);
+    boundaries.push(0);
+    boundaries.push(bytes.len());
+    boundaries.retain(|offset| *offset <= bytes.len());
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    boundaries
+}
+
+/// Picks a random range of up to [`MAX_CROSSOVER_TOKENS`] tokens, between two boundaries
+fn token_range<R>(rand: &mut R, boundaries: &[usize]) -> (usize, usize)
+where
+    R: Rand,
+{
+    // `boundaries` always contains the start and the end, so there are at least two
+    let start = rand.below(boundaries.len() - 1);
+    let count = 1 + rand.below(min(MAX_CROSSOVER_TOKENS, boundaries.len() - 1 - start));
+    (boundaries[start], boundaries[start + count])
+}
+
+/// Loads a random other corpus entry, returning its bytes, or `None` if it's the current one
+fn other_input_bytes<S>(state: &mut S) -> Result<Option<Vec<u8>>, Error>
+where
+    S: HasCorpus + HasRand,
+    S::Input: HasMutatorBytes,
+{
+    let id = random_corpus_id_with_disabled!(state.corpus(), state.rand_mut());
+    // We don't want to use the testcase we're already using for splicing
+    if let Some(cur) = state.corpus().current() {
+        if id == *cur {
+            return Ok(None);
+        }
+    }
+    let mut other_testcase = state.corpus().get_from_all(id)?.borrow_mut();
+    let other = other_testcase.load_input(state.corpus())?;
+    Ok(Some(other.bytes().to_vec()))
+}
+
+/// Splices the input with another corpus entry at token boundaries: the input up to one of its
+/// boundaries is followed by the other input from one of its boundaries on
+#[derive(Debug, Default)]
+pub struct TokenSpliceMutator<L> {
+    lexer: L,
+}
+
+impl<I, L, S> Mutator<I, S> for TokenSpliceMutator<L>
+where
+    I: HasMutatorBytes,
+    L: TokenLexer<S>,
+    S: HasCorpus + HasRand + HasMaxSize,
+    S::Input: HasMutatorBytes,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let Some(other) = other_input_bytes(state)? else {
+            return Ok(MutationResult::Skipped);
+        };
+        if other.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let ours = token_boundaries(&mut self.lexer, state, input.bytes());
+        let theirs = token_boundaries(&mut self.lexer, state, &other);
+
+        let split_at = ours[state.rand_mut().below(ours.len())];
+        // Never take the end of the other input, so at least one token is copied
+        let other_from = theirs[state.rand_mut().below(theirs.len() - 1)];
+        if split_at + other.len() - other_from > state.max_size()
+            || input.bytes()[split_at..] == other[other_from..]
+        {
+            return Ok(MutationResult::Skipped);
+        }
+
+        input.splice(split_at.., other[other_from..].iter().copied());
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<L> Named for TokenSpliceMutator<L> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("TokenSpliceMutator");
+        &NAME
+    }
+}
+
+impl TokenSpliceMutator<TokensLexer> {
+    /// Creates a new [`TokenSpliceMutator`], splitting at the [`Tokens`] and character classes
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_lexer(TokensLexer)
+    }
+}
+
+impl<L> TokenSpliceMutator<L> {
+    /// Creates a new [`TokenSpliceMutator`], splitting where the lexer says
+    #[must_use]
+    pub fn with_lexer(lexer: L) -> Self {
+        Self { lexer }
+    }
+}
+
+/// Inserts a run of tokens of another corpus entry at a token boundary of the input
+#[derive(Debug, Default)]
+pub struct TokenCrossoverInsertMutator<L> {
+    lexer: L,
+}
+
+impl<I, L, S> Mutator<I, S> for TokenCrossoverInsertMutator<L>
+where
+    I: HasMutatorBytes,
+    L: TokenLexer<S>,
+    S: HasCorpus + HasRand + HasMaxSize,
+    S::Input: HasMutatorBytes,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let Some(other) = other_input_bytes(state)? else {
+            return Ok(MutationResult::Skipped);
+        };
+        if other.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let ours = token_boundaries(&mut self.lexer, state, input.bytes());
+        let theirs = token_boundaries(&mut self.lexer, state, &other);
+
+        let target = ours[state.rand_mut().below(ours.len())];
+        let (from, to) = token_range(state.rand_mut(), &theirs);
+        if input.bytes().len() + to - from > state.max_size() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        input.splice(target..target, other[from..to].iter().copied());
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<L> Named for TokenCrossoverInsertMutator<L> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("TokenCrossoverInsertMutator");
+        &NAME
+    }
+}
+
+impl TokenCrossoverInsertMutator<TokensLexer> {
+    /// Creates a new [`TokenCrossoverInsertMutator`], splitting at the [`Tokens`] and character
+    /// classes
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_lexer(TokensLexer)
+    }
+}
+
+impl<L> TokenCrossoverInsertMutator<L> {
+    /// Creates a new [`TokenCrossoverInsertMutator`], splitting where the lexer says
+    #[must_use]
+    pub fn with_lexer(lexer: L) -> Self {
+        Self { lexer }
+    }
+}
+
+/// Replaces a run of tokens of the input with a run of tokens of another corpus entry
+#[derive(Debug, Default)]
+pub struct TokenCrossoverReplaceMutator<L> {
+    lexer: L,
+}
+
+impl<I, L, S> Mutator<I, S> for TokenCrossoverReplaceMutator<L>
+where
+    I: HasMutatorBytes,
+    L: TokenLexer<S>,
+    S: HasCorpus + HasRand + HasMaxSize,
+    S::Input: HasMutatorBytes,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        if input.bytes().is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let Some(other) = other_input_bytes(state)? else {
+            return Ok(MutationResult::Skipped);
+        };
+        if other.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+        let ours = token_boundaries(&mut self.lexer, state, input.bytes());
+        let theirs = token_boundaries(&mut self.lexer, state, &other);
+
+        let (start, end) = token_range(state.rand_mut(), &ours);
+        let (from, to) = token_range(state.rand_mut(), &theirs);
+        if input.bytes().len() - (end - start) + (to - from) > state.max_size()
+            || input.bytes()[start..end] == other[from..to]
+        {
+            return Ok(MutationResult::Skipped);
+        }
+
+        input.splice(start..end, other[from..to].iter().copied());
+        Ok(MutationResult::Mutated)
+    }
+}
+
+impl<L> Named for TokenCrossoverReplaceMutator<L> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("TokenCrossoverReplaceMutator");
+        &NAME
+    }
+}
+
+impl TokenCrossoverReplaceMutator<TokensLexer> {
+    /// Creates a new [`TokenCrossoverReplaceMutator`], splitting at the [`Tokens`] and character
+    /// classes
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_lexer(TokensLexer)
+    }
+}
+
+impl<L> TokenCrossoverReplaceMutator<L> {
+    /// Creates a new [`TokenCrossoverReplaceMutator`], splitting where the lexer says
+    #[must_use]
+    pub fn with_lexer(lexer: L) -> Self {
+        Self { lexer }
+    }
+}
+
+/// Tuple type of the token-aware counterparts of the Havoc mutator's crossover mutations
+pub type TokenHavocCrossoverType<L> = tuple_list_type!(
+    TokenCrossoverInsertMutator<L>,
+    TokenCrossoverReplaceMutator<L>
+);
+
+/// Tuple type of the Havoc mutations, with token-aware crossover
+pub type TokenHavocMutationsType<L> =
+    <HavocMutationsNoCrossoverType as Merge<TokenHavocCrossoverType<L>>>::MergeResult;
+
+/// Get the token-aware counterparts of the Havoc mutator's crossover mutations, see
+/// [`crate::mutators::havoc_crossover`]
+#[must_use]
+pub fn token_havoc_crossover() -> TokenHavocCrossoverType<TokensLexer> {
+    token_havoc_crossover_with_lexer(TokensLexer)
+}
+
+/// Get the token-aware counterparts of the Havoc mutator's crossover mutations, splitting where
+/// the lexer says
+#[must_use]
+pub fn token_havoc_crossover_with_lexer<L>(lexer: L) -> TokenHavocCrossoverType<L>
+where
+    L: Clone,
+{
+    tuple_list!(
+        TokenCrossoverInsertMutator::with_lexer(lexer.clone()),
+        TokenCrossoverReplaceMutator::with_lexer(lexer),
+    )
+}
+
+/// Get the mutations that compose the Havoc mutator, with crossover at token boundaries instead
+/// of arbitrary offsets, see [`crate::mutators::havoc_mutations`]
+#[must_use]
+pub fn token_havoc_mutations() -> TokenHavocMutationsType<TokensLexer> {
+    havoc_mutations_no_crossover().merge(token_havoc_crossover())
+}
+
+/// Get the mutations that compose the Havoc mutator, with crossover at the token boundaries
+/// found by the lexer
+#[must_use]
+pub fn token_havoc_mutations_with_lexer<L>(lexer: L) -> TokenHavocMutationsType<L>
+where
+    L: Clone,
+{
+    havoc_mutations_no_crossover().merge(token_havoc_crossover_with_lexer(lexer))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{
+        char_class_boundaries, token_boundaries, TokenCrossoverInsertMutator,
+        TokenCrossoverReplaceMutator, TokenSpliceMutator, TokensLexer,
+    };
+    use crate::{
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::{MutationResult, Mutator, Tokens},
+        state::{HasCorpus, StdState},
+        HasMetadata,
+    };
+
+    /// If every word of the bytes consists of whole words of the originals
+    fn whole_words(bytes: &[u8], words: &[&[u8]]) -> bool {
+        bytes
+            .split(|b| !b.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .all(|word| {
+                // `ends[i]`: the first `i` bytes are a concatenation of original words
+                let mut ends = alloc::vec![false; word.len() + 1];
+                ends[0] = true;
+                for end in 1..=word.len() {
+                    ends[end] = words.iter().any(|w| {
+                        w.len() <= end && ends[end - w.len()] && word[end - w.len()..end] == **w
+                    });
+                }
+                ends[word.len()]
+            })
+    }
+
+    #[test]
+    fn test_token_boundaries() {
+        let mut boundaries = Vec::new();
+        char_class_boundaries(b"foo(bar, 42)", &mut boundaries);
+        assert_eq!(boundaries, [3, 4, 7, 8, 9, 11]);
+
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        state.add_metadata(Tokens::from([b"oba".to_vec()]));
+        assert_eq!(
+            token_boundaries(&mut TokensLexer, &state, b"foobar"),
+            [0, 2, 5, 6]
+        );
+    }
+
+    #[test]
+    fn test_token_crossover() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let originals: [&[u8]; 2] = [b"alpha beta gamma", b"delta, epsilon(zeta)"];
+        let words: Vec<&[u8]> = originals
+            .iter()
+            .flat_map(|o| o.split(|b| !b.is_ascii_alphanumeric()))
+            .filter(|w| !w.is_empty())
+            .collect();
+        for original in originals {
+            state
+                .corpus_mut()
+                .add(Testcase::new(BytesInput::new(original.to_vec())))
+                .unwrap();
+        }
+
+        let mut splice = TokenSpliceMutator::new();
+        let mut insert = TokenCrossoverInsertMutator::new();
+        let mut replace = TokenCrossoverReplaceMutator::new();
+        let mut mutated = 0;
+        for i in 0..300 {
+            let mut input = BytesInput::new(originals[i % 2].to_vec());
+            let result = match i % 3 {
+                0 => splice.mutate(&mut state, &mut input),
+                1 => insert.mutate(&mut state, &mut input),
+                _ => replace.mutate(&mut state, &mut input),
+            }
+            .unwrap();
+            if result == MutationResult::Mutated {
+                mutated += 1;
+            }
+            assert!(
+                whole_words(input.bytes(), &words),
+                "split a word: {:?}",
+                core::str::from_utf8(input.bytes())
+            );
+        }
+        assert!(mutated > 100);
+    }
+}
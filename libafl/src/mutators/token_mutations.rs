@@ -1,6 +1,6 @@
 //! Tokens are what AFL calls extras or dictionaries.
 //! They may be inserted as part of mutations during fuzzing.
-use alloc::{borrow::Cow, vec::Vec};
+use alloc::{borrow::Cow, string::String, vec::Vec};
 #[cfg(any(target_os = "linux", target_vendor = "apple"))]
 use core::slice::from_raw_parts;
 use core::{
@@ -29,9 +29,9 @@ use crate::{
         buffer_self_copy, mutations::buffer_copy, MultiMutator, MutationResult, Mutator, Named,
     },
     observers::cmp::{AFLppCmpValuesMetadata, CmpValues, CmpValuesMetadata},
-    stages::TaintMetadata,
-    state::{HasCorpus, HasMaxSize, HasRand},
-    Error, HasMetadata,
+    stages::TAINT_REGISTER,
+    state::{HasCorpus, HasMaxSize, HasRand, HasStageRegisters},
+    Error, HasMetadata, HasNamedMetadata,
 };
 
 /// A state metadata holding a list of tokens
@@ -153,6 +153,16 @@ impl Tokens {
         true
     }
 
+    /// Removes a token from the dictionary.
+    /// Returns `false` if the token was not present.
+    pub fn remove_token(&mut self, token: &[u8]) -> bool {
+        if !self.tokens_set.remove(token) {
+            return false;
+        }
+        self.tokens_vec.retain(|other| other != token);
+        true
+    }
+
     /// Reads a tokens file, returning the count of new entries read
     #[cfg(feature = "std")]
     pub fn add_from_file<P>(&mut self, file: P) -> Result<&mut Self, Error>
@@ -421,6 +431,9 @@ impl TokenReplace {
 
 /// A `I2SRandReplace` [`Mutator`] replaces a random matching input-2-state comparison operand with the other.
 /// It needs a valid [`CmpValuesMetadata`] in the state.
+///
+/// Integer operands are searched for in both endiannesses. If they are not found in their binary
+/// form, their ASCII encodings (decimal and hex) are tried as well, e.g. for text formats.
 #[derive(Debug, Default)]
 pub struct I2SRandReplace;
 
@@ -454,6 +467,7 @@ where
 
         let meta = state.metadata_map().get::<CmpValuesMetadata>().unwrap();
         let cmp_values = &meta.list[idx];
+        let numeric = cmp_values.to_u128_tuple();
 
         let mut result = MutationResult::Skipped;
         match cmp_values {
@@ -557,6 +571,36 @@ where
                     }
                 }
             }
+            CmpValues::U128(v) => {
+                if len >= size_of::<u128>() {
+                    for i in off..len - (size_of::<u128>() - 1) {
+                        let val = u128::from_ne_bytes(
+                            bytes[i..i + size_of::<u128>()].try_into().unwrap(),
+                        );
+                        if val == v.0 {
+                            let new_bytes = v.1.to_ne_bytes();
+                            bytes[i..i + size_of::<u128>()].copy_from_slice(&new_bytes);
+                            result = MutationResult::Mutated;
+                            break;
+                        } else if val.swap_bytes() == v.0 {
+                            let new_bytes = v.1.swap_bytes().to_ne_bytes();
+                            bytes[i..i + size_of::<u128>()].copy_from_slice(&new_bytes);
+                            result = MutationResult::Mutated;
+                            break;
+                        } else if val == v.1 {
+                            let new_bytes = v.0.to_ne_bytes();
+                            bytes[i..i + size_of::<u128>()].copy_from_slice(&new_bytes);
+                            result = MutationResult::Mutated;
+                            break;
+                        } else if val.swap_bytes() == v.1 {
+                            let new_bytes = v.0.swap_bytes().to_ne_bytes();
+                            bytes[i..i + size_of::<u128>()].copy_from_slice(&new_bytes);
+                            result = MutationResult::Mutated;
+                            break;
+                        }
+                    }
+                }
+            }
             CmpValues::Bytes(v) => {
                 'outer: for i in off..len {
                     let mut size = core::cmp::min(v.0.len(), len - i);
@@ -585,10 +629,78 @@ where
             }
         }
 
+        if result == MutationResult::Skipped {
+            if let Some(values) = numeric {
+                result = replace_encoded_integer(input, off, values, state.max_size());
+            }
+        }
+
         Ok(result)
     }
 }
 
+/// Finds `pattern` in `bytes` starting at `off`, not directly surrounded by bytes for which
+/// `is_digit` holds, so that we do not match parts of longer numbers.
+fn find_encoded(
+    bytes: &[u8],
+    off: usize,
+    pattern: &[u8],
+    is_digit: fn(&u8) -> bool,
+) -> Option<usize> {
+    if pattern.is_empty() || bytes.len() < pattern.len() {
+        return None;
+    }
+    (off..=bytes.len() - pattern.len()).find(|&i| {
+        bytes[i..i + pattern.len()] == *pattern
+            && (i == 0 || !is_digit(&bytes[i - 1]))
+            && !bytes.get(i + pattern.len()).is_some_and(is_digit)
+    })
+}
+
+/// Replaces an ASCII-encoded (decimal or hex) occurrence of one integer comparison operand
+/// with the same encoding of the other operand.
+fn replace_encoded_integer<I>(
+    input: &mut I,
+    off: usize,
+    values: (u128, u128),
+    max_size: usize,
+) -> MutationResult
+where
+    I: HasMutatorBytes,
+{
+    for (pattern, repl) in [(values.0, values.1), (values.1, values.0)] {
+        // Single digits would match pretty much anywhere
+        if pattern == repl || pattern < 10 {
+            continue;
+        }
+        #[allow(clippy::type_complexity)]
+        let encodings: [(String, String, fn(&u8) -> bool); 3] = [
+            (format!("{pattern}"), format!("{repl}"), u8::is_ascii_digit),
+            (
+                format!("{pattern:x}"),
+                format!("{repl:x}"),
+                u8::is_ascii_hexdigit,
+            ),
+            (
+                format!("{pattern:X}"),
+                format!("{repl:X}"),
+                u8::is_ascii_hexdigit,
+            ),
+        ];
+        for (encoded, replacement, is_digit) in encodings {
+            let Some(pos) = find_encoded(input.bytes(), off, encoded.as_bytes(), is_digit) else {
+                continue;
+            };
+            if input.bytes().len() - encoded.len() + replacement.len() > max_size {
+                continue;
+            }
+            input.splice(pos..pos + encoded.len(), replacement.bytes());
+            return MutationResult::Mutated;
+        }
+    }
+    MutationResult::Skipped
+}
+
 impl Named for I2SRandReplace {
     fn name(&self) -> &Cow<'static, str> {
         static NAME: Cow<'static, str> = Cow::Borrowed("I2SRandReplace");
@@ -612,7 +724,10 @@ const CMP_ATTRIBUTE_IS_FP_MOD: u8 = 16;
 const CMP_ATTRIBUTE_IS_INT_MOD: u8 = 32;
 const CMP_ATTRIBUTE_IS_TRANSFORM: u8 = 64;
 
-/// AFL++ redqueen mutation
+/// AFL++ redqueen mutation.
+///
+/// It needs the [`TAINT_REGISTER`] written by a [`crate::stages::ColorizationStage`] for the
+/// current testcase; declare it with [`crate::stages::RegisterUsageStage::consumes`].
 #[derive(Debug, Default)]
 pub struct AFLppRedQueen {
     enable_transform: bool,
@@ -1087,7 +1202,13 @@ impl AFLppRedQueen {
 
 impl<I, S> MultiMutator<I, S> for AFLppRedQueen
 where
-    S: UsesInput + HasMetadata + HasRand + HasMaxSize + HasCorpus + HasCurrentCorpusId,
+    S: UsesInput
+        + HasMetadata
+        + HasNamedMetadata
+        + HasRand
+        + HasMaxSize
+        + HasCorpus
+        + HasCurrentCorpusId,
     I: HasMutatorBytes + From<Vec<u8>>,
 {
     #[allow(clippy::needless_range_loop)]
@@ -1108,7 +1229,7 @@ where
         let (cmp_len, cmp_meta, taint_meta) = {
             let (Some(cmp_meta), Some(taint_meta)) = (
                 state.metadata_map().get::<AFLppCmpValuesMetadata>(),
-                state.metadata_map().get::<TaintMetadata>(),
+                state.current_register(&TAINT_REGISTER),
             ) else {
                 return Ok(vec![]);
             };
@@ -1865,8 +1986,16 @@ mod tests {
     #[cfg(feature = "std")]
     use std::fs;
 
+    use super::I2SRandReplace;
     #[cfg(feature = "std")]
     use super::{AFLppRedQueen, Tokens};
+    use crate::{
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::{MutationResult, Mutator},
+        observers::cmp::{CmpValues, CmpValuesMetadata},
+        state::NopState,
+        HasMetadata,
+    };
 
     #[cfg(feature = "std")]
     #[test]
@@ -1886,6 +2015,52 @@ token2="B"
         let _res = fs::remove_file("test.tkns");
     }
 
+    #[test]
+    fn test_i2s_replace() {
+        // # Safety
+        // No concurrency per testcase
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            CmpValuesMetadata::register();
+        }
+
+        let cases = [
+            // 128 bit operands, in little endian
+            (
+                CmpValues::U128((0x1122_3344_5566_7788_99aa_bbcc_ddee_ff00, 42)),
+                0x1122_3344_5566_7788_99aa_bbcc_ddee_ff00_u128
+                    .to_le_bytes()
+                    .to_vec(),
+                42_u128.to_le_bytes().to_vec(),
+            ),
+            // decimal ascii encoded
+            (
+                CmpValues::U32((1337, 65_535)),
+                b"len=1337;".to_vec(),
+                b"len=65535;".to_vec(),
+            ),
+            // hex ascii encoded
+            (
+                CmpValues::U64((0xdead_beef, 0x1234)),
+                b"id:deadbeef".to_vec(),
+                b"id:1234".to_vec(),
+            ),
+        ];
+
+        for (cmp, input, expected) in cases {
+            let mut state: NopState<BytesInput> = NopState::new();
+            state.add_metadata(CmpValuesMetadata { list: vec![cmp] });
+            let mut mutator = I2SRandReplace::new();
+            // the mutator starts searching at a random offset
+            let mutated = (0..1000).find_map(|_| {
+                let mut candidate = BytesInput::new(input.clone());
+                (mutator.mutate(&mut state, &mut candidate).unwrap() == MutationResult::Mutated)
+                    .then_some(candidate)
+            });
+            assert_eq!(mutated.unwrap().bytes(), expected.as_slice());
+        }
+    }
+
     #[cfg(feature = "std")]
     #[test]
     fn test_token_mutations() {
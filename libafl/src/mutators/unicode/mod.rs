@@ -27,6 +27,9 @@ use crate::{
 #[allow(clippy::redundant_static_lifetimes)]
 pub mod unicode_categories;
 
+pub mod text;
+pub use text::*;
+
 /// Input which contains the context necessary to perform unicode mutations
 pub type UnicodeInput = (BytesInput, UnicodeIdentificationMetadata);
 
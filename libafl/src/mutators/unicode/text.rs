@@ -0,0 +1,520 @@
+//! Mutators for text inputs, which keep the input valid UTF-8.
+//!
+//! Unlike the category-preserving mutators, these need no [`crate::stages::UnicodeIdentificationStage`]:
+//! they work on any [`HasMutatorBytes`] input and skip inputs that are not valid UTF-8. They target
+//! the unicode handling of text formats, like JSON or XML parsers and compiler frontends, where
+//! raw byte mutations mostly produce invalid inputs.
+
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::ops::Range;
+
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+    Error, Named,
+};
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    inputs::HasMutatorBytes,
+    mutators::{MutationResult, Mutator},
+    state::{HasMaxSize, HasRand},
+};
+
+/// Pairs of characters which look alike, the ASCII one first
+const HOMOGLYPHS: &[(char, char)] = &[
+    ('A', '\u{0410}'),
+    ('B', '\u{0412}'),
+    ('C', '\u{0421}'),
+    ('E', '\u{0415}'),
+    ('H', '\u{041D}'),
+    ('I', '\u{0406}'),
+    ('K', '\u{041A}'),
+    ('M', '\u{041C}'),
+    ('O', '\u{041E}'),
+    ('P', '\u{0420}'),
+    ('T', '\u{0422}'),
+    ('X', '\u{0425}'),
+    ('a', '\u{0430}'),
+    ('c', '\u{0441}'),
+    ('e', '\u{0435}'),
+    ('i', '\u{0456}'),
+    ('j', '\u{0458}'),
+    ('o', '\u{043E}'),
+    ('p', '\u{0440}'),
+    ('s', '\u{0455}'),
+    ('x', '\u{0445}'),
+    ('y', '\u{0443}'),
+    ('o', '\u{03BF}'),
+    ('0', '\u{041E}'),
+    ('1', '\u{0406}'),
+    (' ', '\u{00A0}'),
+    (' ', '\u{2009}'),
+    ('-', '\u{2010}'),
+    ('-', '\u{2212}'),
+    ('.', '\u{2024}'),
+    ('/', '\u{2215}'),
+    (';', '\u{037E}'),
+    ('"', '\u{201C}'),
+    ('"', '\u{201D}'),
+    ('\'', '\u{2019}'),
+    ('<', '\u{2039}'),
+    ('>', '\u{203A}'),
+];
+
+/// The offset of the fullwidth forms of printable ASCII, `!` to `~`
+const FULLWIDTH_OFFSET: u32 = 0xFEE0;
+
+/// Ranges of combining and zero-width characters, which attach to the preceding character
+const COMBINING: &[(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200B, 0x200D),
+    (0x20D0, 0x20FF),
+    (0xFE00, 0xFE0F),
+    (0xFE20, 0xFE2F),
+];
+
+/// The most combining characters inserted at once
+const MAX_COMBINING: usize = 4;
+
+/// The input as text, if it is valid UTF-8 and not empty
+fn as_text(bytes: &[u8]) -> Option<&str> {
+    core::str::from_utf8(bytes)
+        .ok()
+        .filter(|text| !text.is_empty())
+}
+
+/// Picks a random character of the text matching the predicate, returning its byte range
+fn choose_char<R, F>(rand: &mut R, text: &str, predicate: F) -> Option<(Range<usize>, char)>
+where
+    R: Rand,
+    F: Fn(char) -> bool,
+{
+    let candidates: Vec<(usize, char)> =
+        text.char_indices().filter(|(_, c)| predicate(*c)).collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    let (idx, c) = candidates[rand.below(candidates.len())];
+    Some((idx..idx + c.len_utf8(), c))
+}
+
+/// Replaces the range of the input, unless the input would exceed the max size
+fn replace<I, S>(state: &S, input: &mut I, range: Range<usize>, replacement: &str) -> MutationResult
+where
+    I: HasMutatorBytes,
+    S: HasMaxSize,
+{
+    if input.bytes()[range.clone()] == *replacement.as_bytes()
+        || input.bytes().len() - range.len() + replacement.len() > state.max_size()
+    {
+        return MutationResult::Skipped;
+    }
+    input.splice(range, replacement.bytes());
+    MutationResult::Mutated
+}
+
+/// Flips the case of a character, or of a whole word
+#[derive(Debug, Default)]
+pub struct Utf8CaseFlipMutator;
+
+impl Utf8CaseFlipMutator {
+    /// Creates a new [`Utf8CaseFlipMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for Utf8CaseFlipMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("utf8-case-flip");
+        &NAME
+    }
+}
+
+impl<I, S> Mutator<I, S> for Utf8CaseFlipMutator
+where
+    I: HasMutatorBytes,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let Some(text) = as_text(input.bytes()) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some((mut range, c)) = choose_char(state.rand_mut(), text, |c| {
+            c.is_lowercase() || c.is_uppercase()
+        }) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        if state.rand_mut().below(4) == 0 {
+            // Flip the whole word, e.g. turning a keyword into uppercase
+            let start = text[..range.start]
+                .char_indices()
+                .rev()
+                .take_while(|(_, c)| c.is_alphabetic())
+                .last()
+                .map_or(range.start, |(idx, _)| idx);
+            let end = text[range.start..]
+                .char_indices()
+                .find(|(_, c)| !c.is_alphabetic())
+                .map_or(text.len(), |(idx, _)| range.start + idx);
+            range = start..end;
+        }
+
+        let replacement: String = if c.is_lowercase() {
+            text[range.clone()]
+                .chars()
+                .flat_map(char::to_uppercase)
+                .collect()
+        } else {
+            text[range.clone()]
+                .chars()
+                .flat_map(char::to_lowercase)
+                .collect()
+        };
+        Ok(replace(state, input, range, &replacement))
+    }
+}
+
+/// Replaces a character by one looking alike, e.g. a latin `a` by a cyrillic `а`, or an ASCII
+/// character by its fullwidth form, and back
+#[derive(Debug, Default)]
+pub struct Utf8HomoglyphMutator;
+
+impl Utf8HomoglyphMutator {
+    /// Creates a new [`Utf8HomoglyphMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// The characters looking like `c`
+    fn homoglyphs(c: char) -> Vec<char> {
+        let mut homoglyphs: Vec<char> = HOMOGLYPHS
+            .iter()
+            .filter_map(|&(ascii, other)| {
+                if ascii == c {
+                    Some(other)
+                } else if other == c {
+                    Some(ascii)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let fullwidth = if c.is_ascii_graphic() {
+            char::from_u32(c as u32 + FULLWIDTH_OFFSET)
+        } else {
+            (c as u32)
+                .checked_sub(FULLWIDTH_OFFSET)
+                .and_then(char::from_u32)
+                .filter(char::is_ascii_graphic)
+        };
+        homoglyphs.extend(fullwidth);
+        homoglyphs
+    }
+}
+
+impl Named for Utf8HomoglyphMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("utf8-homoglyph");
+        &NAME
+    }
+}
+
+impl<I, S> Mutator<I, S> for Utf8HomoglyphMutator
+where
+    I: HasMutatorBytes,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let Some(text) = as_text(input.bytes()) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some((range, c)) =
+            choose_char(state.rand_mut(), text, |c| !Self::homoglyphs(c).is_empty())
+        else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let homoglyphs = Self::homoglyphs(c);
+        let homoglyph = homoglyphs[state.rand_mut().below(homoglyphs.len())];
+        let mut buf = [0; 4];
+        Ok(replace(
+            state,
+            input,
+            range,
+            homoglyph.encode_utf8(&mut buf),
+        ))
+    }
+}
+
+/// Inserts combining or zero-width characters after a character, e.g. accents or joiners
+#[derive(Debug, Default)]
+pub struct Utf8CombiningCharMutator;
+
+impl Utf8CombiningCharMutator {
+    /// Creates a new [`Utf8CombiningCharMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for Utf8CombiningCharMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("utf8-combining-char");
+        &NAME
+    }
+}
+
+impl<I, S> Mutator<I, S> for Utf8CombiningCharMutator
+where
+    I: HasMutatorBytes,
+    S: HasRand + HasMaxSize,
+{
+    #[allow(clippy::cast_possible_truncation)]
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let Some(text) = as_text(input.bytes()) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some((range, _)) = choose_char(state.rand_mut(), text, |_| true) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let mut combining = String::new();
+        for _ in 0..state.rand_mut().between(1, MAX_COMBINING) {
+            let (min, max) = COMBINING[state.rand_mut().below(COMBINING.len())];
+            let offset = state.rand_mut().below((max - min + 1) as usize) as u32;
+            combining.extend(char::from_u32(min + offset));
+        }
+        Ok(replace(state, input, range.end..range.end, &combining))
+    }
+}
+
+/// The unicode normalization forms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NormalizationForm {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+impl NormalizationForm {
+    const ALL: [Self; 4] = [Self::Nfc, Self::Nfd, Self::Nfkc, Self::Nfkd];
+
+    fn normalize(self, text: &str) -> String {
+        match self {
+            Self::Nfc => text.nfc().collect(),
+            Self::Nfd => text.nfd().collect(),
+            Self::Nfkc => text.nfkc().collect(),
+            Self::Nfkd => text.nfkd().collect(),
+        }
+    }
+}
+
+/// Switches the normalization form (NFC, NFD, NFKC or NFKD) of a word or the whole input, e.g.
+/// decomposing `é` into `e` and a combining accent
+#[derive(Debug, Default)]
+pub struct Utf8NormalizationMutator;
+
+impl Utf8NormalizationMutator {
+    /// Creates a new [`Utf8NormalizationMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for Utf8NormalizationMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("utf8-normalization");
+        &NAME
+    }
+}
+
+impl<I, S> Mutator<I, S> for Utf8NormalizationMutator
+where
+    I: HasMutatorBytes,
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let Some(text) = as_text(input.bytes()) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let range = if state.rand_mut().below(4) == 0 {
+            0..text.len()
+        } else {
+            let Some((range, _)) = choose_char(state.rand_mut(), text, |c| !c.is_whitespace())
+            else {
+                return Ok(MutationResult::Skipped);
+            };
+            let start = text[..range.start]
+                .rfind(char::is_whitespace)
+                .map_or(0, |idx| {
+                    idx + text[idx..].chars().next().map_or(1, char::len_utf8)
+                });
+            let end = text[range.start..]
+                .find(char::is_whitespace)
+                .map_or(text.len(), |idx| range.start + idx);
+            start..end
+        };
+
+        // Try the forms in a random order, until one changes the text
+        let first = state.rand_mut().below(NormalizationForm::ALL.len());
+        for idx in 0..NormalizationForm::ALL.len() {
+            let form = NormalizationForm::ALL[(first + idx) % NormalizationForm::ALL.len()];
+            let normalized = form.normalize(&text[range.clone()]);
+            if normalized != text[range.clone()] {
+                return Ok(replace(state, input, range, &normalized));
+            }
+        }
+        Ok(MutationResult::Skipped)
+    }
+}
+
+/// Replaces a character by a random one of the same UTF-8 length, often a nearby codepoint, so
+/// the input keeps its length and stays valid UTF-8
+#[derive(Debug, Default)]
+pub struct Utf8CodepointMutator;
+
+impl Utf8CodepointMutator {
+    /// Creates a new [`Utf8CodepointMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Named for Utf8CodepointMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("utf8-codepoint");
+        &NAME
+    }
+}
+
+impl<I, S> Mutator<I, S> for Utf8CodepointMutator
+where
+    I: HasMutatorBytes,
+    S: HasRand + HasMaxSize,
+{
+    #[allow(clippy::cast_possible_truncation)]
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let Some(text) = as_text(input.bytes()) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some((range, c)) = choose_char(state.rand_mut(), text, |_| true) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let (min, max) = match c.len_utf8() {
+            1 => (0, 0x7F),
+            2 => (0x80, 0x7FF),
+            3 => (0x800, 0xFFFF),
+            _ => (0x1_0000, 0x10_FFFF),
+        };
+        let rand = state.rand_mut();
+        // Surrogates are no chars, so retry a few times
+        let replacement = (0..8).find_map(|_| {
+            let codepoint = if rand.below(2) == 0 {
+                // A nearby codepoint, likely of the same script
+                let delta = rand.between(1, 32) as u32;
+                if rand.below(2) == 0 {
+                    (c as u32).saturating_sub(delta).max(min)
+                } else {
+                    (c as u32 + delta).min(max)
+                }
+            } else {
+                min + rand.below((max - min + 1) as usize) as u32
+            };
+            char::from_u32(codepoint).filter(|new_c| *new_c != c)
+        });
+        let Some(replacement) = replacement else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let mut buf = [0; 4];
+        Ok(replace(
+            state,
+            input,
+            range,
+            replacement.encode_utf8(&mut buf),
+        ))
+    }
+}
+
+/// Tuple type of the mutations for UTF-8 text
+pub type Utf8MutationsType = tuple_list_type!(
+    Utf8CaseFlipMutator,
+    Utf8HomoglyphMutator,
+    Utf8CombiningCharMutator,
+    Utf8NormalizationMutator,
+    Utf8CodepointMutator,
+);
+
+/// Get the mutations for UTF-8 text
+#[must_use]
+pub fn utf8_mutations() -> Utf8MutationsType {
+    tuple_list!(
+        Utf8CaseFlipMutator::new(),
+        Utf8HomoglyphMutator::new(),
+        Utf8CombiningCharMutator::new(),
+        Utf8NormalizationMutator::new(),
+        Utf8CodepointMutator::new(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::{rands::StdRand, HasLen};
+
+    use crate::{
+        corpus::NopCorpus,
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::{utf8_mutations, MutationResult, MutatorsTuple},
+        state::StdState,
+    };
+
+    #[test]
+    fn test_utf8_mutations_stay_valid() {
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            NopCorpus::<BytesInput>::new(),
+            NopCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap();
+        let mut mutations = utf8_mutations();
+
+        for idx in 0..(1 << 10) {
+            let mut input =
+                BytesInput::from("{\"Name\": \"Crème brûlée\", \"ok\": true}".as_bytes());
+            let result = mutations
+                .get_and_mutate((idx % mutations.len()).into(), &mut state, &mut input)
+                .unwrap();
+            assert!(core::str::from_utf8(input.bytes()).is_ok());
+            if result == MutationResult::Skipped {
+                assert_eq!(
+                    input.bytes(),
+                    "{\"Name\": \"Crème brûlée\", \"ok\": true}".as_bytes()
+                );
+            }
+        }
+
+        // Invalid UTF-8 is left alone
+        let mut input = BytesInput::from(&[0xff, 0xfe, 0x41][..]);
+        for idx in 0..mutations.len() {
+            let result = mutations
+                .get_and_mutate(idx.into(), &mut state, &mut input)
+                .unwrap();
+            assert_eq!(result, MutationResult::Skipped);
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! A `ScheduledMutator` that picks mutations according to per-mutation weights,
+//! optionally adapting them online based on which mutations produced new corpus entries.
+//!
+//! The adaptation is a simple bandit: every mutation keeps an exponentially smoothed reward,
+//! updated after each execution. Unlike [`crate::mutators::StdMOptMutator`], all state lives in
+//! a single serializable metadata, so the learned weights survive restarts.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{
+    fmt::{self, Debug},
+    marker::PhantomData,
+};
+
+use libafl_bolts::{impl_serdeany, rands::Rand, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::CorpusId,
+    mutators::{
+        ComposedByMutations, MutationId, MutationResult, Mutator, MutatorsTuple, ScheduledMutator,
+    },
+    state::HasRand,
+    Error, HasMetadata,
+};
+
+/// A sensible default learning rate for [`WeightedHavocMutator::with_adaptation`]
+pub const DEFAULT_ADAPTATION_RATE: f64 = 0.05;
+
+/// The minimum share of its base weight an operator keeps, so that no operator starves
+pub const DEFAULT_ADAPTATION_FLOOR: f64 = 0.1;
+
+/// Metadata in the state, holding the weights and statistics of a [`WeightedHavocMutator`]
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct WeightedHavocMetadata {
+    /// The configured base weight of each mutation
+    pub weights: Vec<f64>,
+    /// The smoothed reward of each mutation, in `[0, 1]`
+    pub rewards: Vec<f64>,
+    /// How often each mutation has been applied
+    pub uses: Vec<u64>,
+    /// How often each mutation contributed to a new corpus entry
+    pub finds: Vec<u64>,
+}
+
+impl_serdeany!(WeightedHavocMetadata);
+
+impl WeightedHavocMetadata {
+    /// Creates new [`WeightedHavocMetadata`] for the given base weights
+    #[must_use]
+    pub fn new(weights: Vec<f64>) -> Self {
+        let len = weights.len();
+        Self {
+            weights,
+            rewards: vec![0.0; len],
+            uses: vec![0; len],
+            finds: vec![0; len],
+        }
+    }
+
+    /// The number of mutations tracked by this metadata
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.weights.len()
+    }
+
+    /// Returns `true` if no mutation is tracked
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.weights.is_empty()
+    }
+
+    /// The weight used for sampling mutation `idx`, taking the learned reward into account
+    #[must_use]
+    pub fn effective_weight(&self, idx: usize, floor: Option<f64>) -> f64 {
+        match floor {
+            Some(floor) => self.weights[idx] * (floor + (1.0 - floor) * self.rewards[idx]),
+            None => self.weights[idx],
+        }
+    }
+
+    /// Gets the stored metadata, used by the [`WeightedHavocMutator`]
+    pub fn get<S: HasMetadata>(state: &S) -> Result<&Self, Error> {
+        state
+            .metadata_map()
+            .get::<Self>()
+            .ok_or_else(|| Error::illegal_state("WeightedHavocMutator not in use"))
+    }
+
+    /// Gets the stored metadata, used by the [`WeightedHavocMutator`], mut
+    pub fn get_mut<S: HasMetadata>(state: &mut S) -> Result<&mut Self, Error> {
+        state
+            .metadata_map_mut()
+            .get_mut::<Self>()
+            .ok_or_else(|| Error::illegal_state("WeightedHavocMutator not in use"))
+    }
+}
+
+/// A [`Mutator`] that schedules the embedded mutations according to per-mutation weights.
+///
+/// With [`WeightedHavocMutator::with_adaptation`], mutations applied in an execution that
+/// produced a new corpus entry are credited, and their weight grows accordingly.
+pub struct WeightedHavocMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    name: Cow<'static, str>,
+    mutations: MT,
+    max_stack_pow: usize,
+    /// The learning rate and floor of the adaptation, if enabled
+    adaptation: Option<(f64, f64)>,
+    /// The mutations applied during the last call to `mutate`
+    applied: Vec<MutationId>,
+    phantom: PhantomData<(I, S)>,
+}
+
+impl<I, MT, S> Debug for WeightedHavocMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "WeightedHavocMutator with {} mutations for Input type {}",
+            self.mutations.len(),
+            core::any::type_name::<I>()
+        )
+    }
+}
+
+impl<I, MT, S> Named for WeightedHavocMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, MT, S> Mutator<I, S> for WeightedHavocMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        self.applied.clear();
+        let mut r = MutationResult::Skipped;
+        let num = self.iterations(state, input);
+        for _ in 0..num {
+            let idx = self.schedule(state, input);
+            let outcome = self.mutations.get_and_mutate(idx, state, input)?;
+            if outcome == MutationResult::Mutated {
+                r = MutationResult::Mutated;
+                self.applied.push(idx);
+            }
+        }
+        Ok(r)
+    }
+
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        for idx in &self.applied {
+            self.mutations
+                .get_and_post_exec(idx.0, state, new_corpus_id)?;
+        }
+
+        let metadata = WeightedHavocMetadata::get_mut(state)?;
+        let found = new_corpus_id.is_some();
+        for idx in &self.applied {
+            metadata.uses[idx.0] += 1;
+            if found {
+                metadata.finds[idx.0] += 1;
+            }
+        }
+
+        if let Some((rate, _)) = self.adaptation {
+            let reward = if found { 1.0 } else { 0.0 };
+            for idx in &self.applied {
+                let current = &mut metadata.rewards[idx.0];
+                *current += rate * (reward - *current);
+            }
+        }
+
+        self.applied.clear();
+        Ok(())
+    }
+}
+
+impl<I, MT, S> ComposedByMutations<I, MT, S> for WeightedHavocMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    /// Get the mutations
+    #[inline]
+    fn mutations(&self) -> &MT {
+        &self.mutations
+    }
+
+    // Get the mutations (mutable)
+    #[inline]
+    fn mutations_mut(&mut self) -> &mut MT {
+        &mut self.mutations
+    }
+}
+
+impl<I, MT, S> ScheduledMutator<I, MT, S> for WeightedHavocMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    /// Compute the number of iterations used to apply stacked mutations
+    fn iterations(&self, state: &mut S, _: &I) -> u64 {
+        1 << (1 + state.rand_mut().below(self.max_stack_pow))
+    }
+
+    /// Get the next mutation to apply, sampled proportionally to its weight
+    fn schedule(&self, state: &mut S, _: &I) -> MutationId {
+        debug_assert!(self.mutations.len() != 0);
+        let coin = state.rand_mut().next_float();
+        let floor = self.adaptation.map(|(_, floor)| floor);
+
+        // Assumption: we can not reach this code path without previously adding this metadatum.
+        let metadata = WeightedHavocMetadata::get(state).unwrap();
+        debug_assert_eq!(self.mutations.len(), metadata.len());
+
+        let total: f64 = (0..metadata.len())
+            .map(|idx| metadata.effective_weight(idx, floor))
+            .sum();
+        let mut target = coin * total;
+        for idx in 0..metadata.len() {
+            let weight = metadata.effective_weight(idx, floor);
+            if target < weight {
+                return idx.into();
+            }
+            target -= weight;
+        }
+
+        // Floating point slack, pick the last mutation with a non-zero weight.
+        (0..metadata.len())
+            .rev()
+            .find(|idx| metadata.effective_weight(*idx, floor) > 0.0)
+            .unwrap_or(0)
+            .into()
+    }
+}
+
+impl<I, MT, S> WeightedHavocMutator<I, MT, S>
+where
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasMetadata,
+{
+    /// Create a new [`WeightedHavocMutator`] instance, weighting all mutations equally
+    pub fn new(state: &mut S, mutations: MT) -> Self {
+        let weights = vec![1.0; mutations.len()];
+        Self::with_weights(state, mutations, weights).unwrap()
+    }
+
+    /// Create a new [`WeightedHavocMutator`] instance with a weight for each mutation.
+    ///
+    /// If the state already holds matching [`WeightedHavocMetadata`], e.g. after a restart,
+    /// the learned rewards are kept and only the base weights are updated.
+    pub fn with_weights(state: &mut S, mutations: MT, weights: Vec<f64>) -> Result<Self, Error> {
+        if weights.len() != mutations.len() {
+            return Err(Error::illegal_argument(format!(
+                "expected {} weights, got {}",
+                mutations.len(),
+                weights.len()
+            )));
+        }
+        if !weights.iter().all(|w| w.is_finite() && *w >= 0.0) || weights.iter().all(|w| *w == 0.0)
+        {
+            return Err(Error::illegal_argument(format!(
+                "invalid mutation weights: {weights:?}"
+            )));
+        }
+
+        match state.metadata_map_mut().get_mut::<WeightedHavocMetadata>() {
+            Some(metadata) if metadata.len() == weights.len() => metadata.weights = weights,
+            _ => state.add_metadata(WeightedHavocMetadata::new(weights)),
+        }
+
+        Ok(Self {
+            name: Cow::from(format!(
+                "WeightedHavocMutator[{}]",
+                mutations.names().join(", ")
+            )),
+            mutations,
+            max_stack_pow: 7,
+            adaptation: None,
+            applied: Vec::new(),
+            phantom: PhantomData,
+        })
+    }
+
+    /// Enable online adaptation of the weights, with the given learning rate in `(0, 1]`.
+    ///
+    /// See [`DEFAULT_ADAPTATION_RATE`] for a sensible default.
+    #[must_use]
+    pub fn with_adaptation(mut self, rate: f64) -> Self {
+        let floor = self
+            .adaptation
+            .map_or(DEFAULT_ADAPTATION_FLOOR, |(_, floor)| floor);
+        self.adaptation = Some((rate.clamp(f64::EPSILON, 1.0), floor));
+        self
+    }
+
+    /// Set the share of its base weight an operator keeps when it never finds anything.
+    /// Only meaningful together with [`WeightedHavocMutator::with_adaptation`].
+    #[must_use]
+    pub fn with_adaptation_floor(mut self, floor: f64) -> Self {
+        let rate = self
+            .adaptation
+            .map_or(DEFAULT_ADAPTATION_RATE, |(rate, _)| rate);
+        self.adaptation = Some((rate, floor.clamp(f64::EPSILON, 1.0)));
+        self
+    }
+
+    /// Set the maximum power of two of stacked mutations, at least `1`
+    #[must_use]
+    pub fn with_max_stack_pow(mut self, max_stack_pow: usize) -> Self {
+        self.max_stack_pow = max_stack_pow.max(1);
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use libafl_bolts::tuples::tuple_list;
+
+    use super::{WeightedHavocMetadata, WeightedHavocMutator};
+    use crate::{
+        corpus::CorpusId,
+        inputs::BytesInput,
+        mutators::{BitFlipMutator, ByteDecMutator, ByteRandMutator, Mutator, ScheduledMutator},
+        state::NopState,
+    };
+
+    #[test]
+    fn test_weighted_schedule() {
+        // # Safety
+        // No concurrency per testcase
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            WeightedHavocMetadata::register();
+        }
+
+        let mut state: NopState<BytesInput> = NopState::new();
+        let mutators = tuple_list!(
+            BitFlipMutator::new(),
+            ByteDecMutator::new(),
+            ByteRandMutator::new()
+        );
+        assert!(WeightedHavocMutator::<BytesInput, _, _>::with_weights(
+            &mut state,
+            mutators,
+            vec![1.0]
+        )
+        .is_err());
+
+        let mutators = tuple_list!(
+            BitFlipMutator::new(),
+            ByteDecMutator::new(),
+            ByteRandMutator::new()
+        );
+        let weighted =
+            WeightedHavocMutator::with_weights(&mut state, mutators, vec![0.0, 0.0, 1.0])
+                .unwrap()
+                .with_max_stack_pow(0);
+        let input = BytesInput::new(vec![42]);
+        for _ in 0..100 {
+            assert_eq!(weighted.schedule(&mut state, &input), 2.into());
+            assert_eq!(weighted.iterations(&mut state, &input), 2);
+        }
+    }
+
+    #[test]
+    fn test_weighted_adaptation() {
+        // # Safety
+        // No concurrency per testcase
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            WeightedHavocMetadata::register();
+        }
+
+        let mut state: NopState<BytesInput> = NopState::new();
+        let mutators = tuple_list!(BitFlipMutator::new(), ByteDecMutator::new());
+        let mut weighted = WeightedHavocMutator::with_weights(&mut state, mutators, vec![1.0, 0.0])
+            .unwrap()
+            .with_adaptation(0.5);
+        let mut input = BytesInput::new(vec![42; 16]);
+
+        weighted.mutate(&mut state, &mut input).unwrap();
+        weighted.post_exec(&mut state, Some(CorpusId(0))).unwrap();
+
+        let metadata = WeightedHavocMetadata::get(&state).unwrap();
+        assert!(metadata.uses[0] > 0);
+        assert_eq!(metadata.uses[0], metadata.finds[0]);
+        assert_eq!(metadata.uses[1], 0);
+        assert!(metadata.rewards[0] > 0.0);
+        assert!(metadata.effective_weight(0, Some(0.1)) > 0.1);
+
+        // The learned state survives re-creating the mutator, e.g. after a restart.
+        let mutators = tuple_list!(BitFlipMutator::new(), ByteDecMutator::new());
+        let _weighted = WeightedHavocMutator::<BytesInput, _, _>::with_weights(
+            &mut state,
+            mutators,
+            vec![1.0, 1.0],
+        );
+        let metadata = WeightedHavocMetadata::get(&state).unwrap();
+        assert!(metadata.rewards[0] > 0.0);
+        assert_eq!(metadata.weights, vec![1.0, 1.0]);
+    }
+}
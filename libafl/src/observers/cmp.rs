@@ -35,8 +35,10 @@ where
     fn add_from(&mut self, usable_count: usize, cmp_map: &mut CM, cmp_observer_data: Self::Data);
 }
 
-/// Compare values collected during a run
+/// Compare values collected during a run.
+/// More kinds of values may be added, so matches need a wildcard arm.
 #[derive(Eq, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[non_exhaustive]
 pub enum CmpValues {
     /// Two u8 values
     U8((u8, u8)),
@@ -46,6 +48,8 @@ pub enum CmpValues {
     U32((u32, u32)),
     /// Two u64 values
     U64((u64, u64)),
+    /// Two u128 values
+    U128((u128, u128)),
     /// Two vecs of u8 values/byte
     Bytes((Vec<u8>, Vec<u8>)),
 }
@@ -56,11 +60,16 @@ impl CmpValues {
     pub fn is_numeric(&self) -> bool {
         matches!(
             self,
-            CmpValues::U8(_) | CmpValues::U16(_) | CmpValues::U32(_) | CmpValues::U64(_)
+            CmpValues::U8(_)
+                | CmpValues::U16(_)
+                | CmpValues::U32(_)
+                | CmpValues::U64(_)
+                | CmpValues::U128(_)
         )
     }
 
-    /// Converts the value to a u64 tuple
+    /// Converts the value to a u64 tuple.
+    /// Returns `None` for bytes and for 128 bit values, see [`CmpValues::to_u128_tuple`]
     #[must_use]
     pub fn to_u64_tuple(&self) -> Option<(u64, u64)> {
         match self {
@@ -68,6 +77,30 @@ impl CmpValues {
             CmpValues::U16(t) => Some((u64::from(t.0), u64::from(t.1))),
             CmpValues::U32(t) => Some((u64::from(t.0), u64::from(t.1))),
             CmpValues::U64(t) => Some(*t),
+            CmpValues::U128(_) | CmpValues::Bytes(_) => None,
+        }
+    }
+
+    /// Converts the value to a u128 tuple
+    #[must_use]
+    pub fn to_u128_tuple(&self) -> Option<(u128, u128)> {
+        match self {
+            CmpValues::U128(t) => Some(*t),
+            _ => self
+                .to_u64_tuple()
+                .map(|(v0, v1)| (u128::from(v0), u128::from(v1))),
+        }
+    }
+
+    /// The size of a numeric operand in bytes, `None` for bytes
+    #[must_use]
+    pub fn numeric_size(&self) -> Option<usize> {
+        match self {
+            CmpValues::U8(_) => Some(1),
+            CmpValues::U16(_) => Some(2),
+            CmpValues::U32(_) => Some(4),
+            CmpValues::U64(_) => Some(8),
+            CmpValues::U128(_) => Some(16),
             CmpValues::Bytes(_) => None,
         }
     }
@@ -0,0 +1,305 @@
+//! Map observer with configurable counter semantics, for any counter width
+use alloc::{borrow::Cow, vec::Vec};
+use core::{fmt::Debug, hash::Hash};
+
+use libafl_bolts::{HasLen, Named, Truncate};
+use num_traits::PrimInt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executors::ExitKind,
+    inputs::UsesInput,
+    observers::{map::MapObserver, DifferentialObserver, Observer, ObserversTuple},
+    Error,
+};
+
+/// How the raw counters written by the target are interpreted before they reach the feedback
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum CounterSemantics {
+    /// AFL-style hitcount buckets (`1`, `2`, `3`, `4-7`, `8-15`, `16-31`, `32-127`, `128+`).
+    /// Counters wider than `u8` use the same buckets, so all counts `>= 128` are equivalent.
+    Buckets,
+    /// Pure edge coverage: any non-zero counter becomes `1`, hit counts are ignored.
+    EdgeBits,
+    /// Counters are kept as they are. Use with `u16`/`u32` maps (and saturating
+    /// instrumentation) for the highest precision.
+    Saturating,
+}
+
+impl CounterSemantics {
+    /// Maps a raw counter value according to these semantics
+    #[inline]
+    #[must_use]
+    pub fn apply<T>(self, value: T) -> T
+    where
+        T: PrimInt,
+    {
+        match self {
+            CounterSemantics::Saturating => value,
+            CounterSemantics::EdgeBits => {
+                if value.is_zero() {
+                    value
+                } else {
+                    T::one()
+                }
+            }
+            CounterSemantics::Buckets => {
+                let bucket: u8 = match value.to_u64().unwrap_or(u64::MAX) {
+                    0 => 0,
+                    1 => 1,
+                    2 => 2,
+                    3 => 4,
+                    4..=7 => 8,
+                    8..=15 => 16,
+                    16..=31 => 32,
+                    32..=127 => 64,
+                    _ => 128,
+                };
+                T::from(bucket).unwrap_or_else(T::max_value)
+            }
+        }
+    }
+}
+
+/// Map observer that post-processes the counters of the wrapped map according to the
+/// [`CounterSemantics`] chosen at construction.
+///
+/// Unlike [`crate::observers::HitcountsMapObserver`], this works for any integer map entry type,
+/// so the same instrumentation can be used with different sensitivity/precision tradeoffs.
+/// The resulting map is meant to be used with a [`crate::feedbacks::MaxMapFeedback`].
+#[derive(Serialize, Deserialize, Clone, Debug, Hash)]
+#[serde(bound = "M: serde::de::DeserializeOwned")]
+pub struct CountersMapObserver<M>
+where
+    M: Serialize,
+{
+    base: M,
+    semantics: CounterSemantics,
+}
+
+impl<M> CountersMapObserver<M>
+where
+    M: MapObserver,
+{
+    /// Creates a new [`CountersMapObserver`] interpreting the counters of `base` with the given
+    /// [`CounterSemantics`].
+    pub fn new(base: M, semantics: CounterSemantics) -> Self {
+        Self { base, semantics }
+    }
+
+    /// The [`CounterSemantics`] of this observer
+    #[must_use]
+    pub fn semantics(&self) -> CounterSemantics {
+        self.semantics
+    }
+
+    /// The wrapped map observer
+    pub fn base(&self) -> &M {
+        &self.base
+    }
+
+    /// Apply the [`CounterSemantics`] to each entry of the map
+    fn classify(&mut self)
+    where
+        M::Entry: PrimInt,
+    {
+        if self.semantics == CounterSemantics::Saturating {
+            return;
+        }
+        let initial = self.base.initial();
+        for i in 0..self.base.usable_count() {
+            let value = self.base.get(i);
+            if value != initial {
+                self.base.set(i, self.semantics.apply(value));
+            }
+        }
+    }
+}
+
+impl<S, M> Observer<S> for CountersMapObserver<M>
+where
+    M: MapObserver + Observer<S>,
+    M::Entry: PrimInt,
+    S: UsesInput,
+{
+    #[inline]
+    fn pre_exec(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        self.base.pre_exec(state, input)
+    }
+
+    #[inline]
+    fn post_exec(
+        &mut self,
+        state: &mut S,
+        input: &S::Input,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.classify();
+        self.base.post_exec(state, input, exit_kind)
+    }
+}
+
+impl<M> Named for CountersMapObserver<M>
+where
+    M: Named + Serialize + serde::de::DeserializeOwned,
+{
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        self.base.name()
+    }
+}
+
+impl<M> HasLen for CountersMapObserver<M>
+where
+    M: MapObserver,
+{
+    #[inline]
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+}
+
+impl<M> AsRef<Self> for CountersMapObserver<M>
+where
+    M: MapObserver,
+{
+    fn as_ref(&self) -> &Self {
+        self
+    }
+}
+
+impl<M> AsMut<Self> for CountersMapObserver<M>
+where
+    M: MapObserver,
+{
+    fn as_mut(&mut self) -> &mut Self {
+        self
+    }
+}
+
+impl<M> MapObserver for CountersMapObserver<M>
+where
+    M: MapObserver,
+{
+    type Entry = M::Entry;
+
+    #[inline]
+    fn initial(&self) -> M::Entry {
+        self.base.initial()
+    }
+
+    #[inline]
+    fn usable_count(&self) -> usize {
+        self.base.usable_count()
+    }
+
+    #[inline]
+    fn get(&self, idx: usize) -> M::Entry {
+        self.base.get(idx)
+    }
+
+    #[inline]
+    fn set(&mut self, idx: usize, val: M::Entry) {
+        self.base.set(idx, val);
+    }
+
+    /// Count the set bytes in the map
+    fn count_bytes(&self) -> u64 {
+        self.base.count_bytes()
+    }
+
+    /// Reset the map
+    #[inline]
+    fn reset_map(&mut self) -> Result<(), Error> {
+        self.base.reset_map()
+    }
+
+    #[inline]
+    fn hash_simple(&self) -> u64 {
+        self.base.hash_simple()
+    }
+
+    fn to_vec(&self) -> Vec<M::Entry> {
+        self.base.to_vec()
+    }
+
+    fn how_many_set(&self, indexes: &[usize]) -> usize {
+        self.base.how_many_set(indexes)
+    }
+}
+
+impl<M> Truncate for CountersMapObserver<M>
+where
+    M: Named + Serialize + serde::de::DeserializeOwned + Truncate,
+{
+    fn truncate(&mut self, new_len: usize) {
+        self.base.truncate(new_len);
+    }
+}
+
+impl<'it, M> IntoIterator for &'it CountersMapObserver<M>
+where
+    M: Serialize + serde::de::DeserializeOwned,
+    &'it M: IntoIterator,
+{
+    type Item = <&'it M as IntoIterator>::Item;
+    type IntoIter = <&'it M as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.base.into_iter()
+    }
+}
+
+impl<'it, M> IntoIterator for &'it mut CountersMapObserver<M>
+where
+    M: Serialize + serde::de::DeserializeOwned,
+    &'it mut M: IntoIterator,
+{
+    type Item = <&'it mut M as IntoIterator>::Item;
+    type IntoIter = <&'it mut M as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.base.into_iter()
+    }
+}
+
+impl<M, OTA, OTB, S> DifferentialObserver<OTA, OTB, S> for CountersMapObserver<M>
+where
+    M: DifferentialObserver<OTA, OTB, S> + MapObserver + Serialize,
+    M::Entry: PrimInt,
+    OTA: ObserversTuple<S>,
+    OTB: ObserversTuple<S>,
+    S: UsesInput,
+{
+    fn pre_observe_first(&mut self, observers: &mut OTA) -> Result<(), Error> {
+        self.base.pre_observe_first(observers)
+    }
+
+    fn post_observe_first(&mut self, observers: &mut OTA) -> Result<(), Error> {
+        self.base.post_observe_first(observers)
+    }
+
+    fn pre_observe_second(&mut self, observers: &mut OTB) -> Result<(), Error> {
+        self.base.pre_observe_second(observers)
+    }
+
+    fn post_observe_second(&mut self, observers: &mut OTB) -> Result<(), Error> {
+        self.base.post_observe_second(observers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CounterSemantics;
+
+    #[test]
+    fn test_counter_semantics() {
+        assert_eq!(CounterSemantics::Buckets.apply(0u8), 0);
+        assert_eq!(CounterSemantics::Buckets.apply(3u8), 4);
+        assert_eq!(CounterSemantics::Buckets.apply(200u8), 128);
+        assert_eq!(CounterSemantics::Buckets.apply(5000u32), 128);
+        assert_eq!(CounterSemantics::EdgeBits.apply(42u16), 1);
+        assert_eq!(CounterSemantics::EdgeBits.apply(0u16), 0);
+        assert_eq!(CounterSemantics::Saturating.apply(5000u32), 5000);
+    }
+}
@@ -0,0 +1,352 @@
+//! A [`MapObserver`] exposing the divergence of two map observers during differential execution
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    ops::{Deref, DerefMut},
+    slice::{Iter, IterMut},
+};
+
+use ahash::RandomState;
+use libafl_bolts::{
+    tuples::{Handle, Handled, MatchNameRef},
+    HasLen, Named,
+};
+use num_traits::{PrimInt, WrappingSub};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    inputs::UsesInput,
+    observers::{map::MapObserver, DifferentialObserver, Observer, ObserversTuple},
+    Error,
+};
+
+/// How the entries of the two observed maps are combined into the divergence map
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffMapMode {
+    /// `first ^ second` for each entry
+    Xor,
+    /// `|first - second|` for each entry. For signed entries, a distance larger than the maximum
+    /// of the type wraps around, i.e. it is the unsigned distance in the bits of the entry
+    Distance,
+    /// `1` if the entries differ, `0` otherwise
+    Mismatch,
+}
+
+impl DiffMapMode {
+    /// Combine two map entries according to this mode
+    #[inline]
+    #[must_use]
+    pub fn combine<T>(self, first: T, second: T) -> T
+    where
+        T: PrimInt + WrappingSub,
+    {
+        match self {
+            DiffMapMode::Xor => first ^ second,
+            DiffMapMode::Distance => {
+                if first > second {
+                    first.wrapping_sub(&second)
+                } else {
+                    second.wrapping_sub(&first)
+                }
+            }
+            DiffMapMode::Mismatch => {
+                if first == second {
+                    T::zero()
+                } else {
+                    T::one()
+                }
+            }
+        }
+    }
+}
+
+/// A [`DiffMapObserver`] captures a snapshot of a map observer of the first executor of a
+/// [`crate::executors::DiffExecutor`] and compares it with a map observer of the second executor.
+///
+/// The resulting map contains the per-entry divergence of the two observers (see [`DiffMapMode`]),
+/// so that any map feedback (e.g. [`crate::feedbacks::MaxMapFeedback`]) on this observer selects
+/// inputs for which the two implementations behave differently.
+///
+/// This observer must be placed in the differential observers tuple of the
+/// [`crate::executors::DiffExecutor`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(bound = "T: serde::de::DeserializeOwned")]
+pub struct DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    map: Vec<T>,
+    first_snapshot: Vec<T>,
+    first_ref: Handle<A>,
+    second_ref: Handle<B>,
+    mode: DiffMapMode,
+    name: Cow<'static, str>,
+}
+
+impl<A, B, T> DiffMapObserver<A, B, T>
+where
+    A: Named,
+    B: Named,
+    T: 'static + Default + Copy + Serialize,
+{
+    /// Creates a new [`DiffMapObserver`] comparing `first` (observed by the first executor) and
+    /// `second` (observed by the second executor).
+    #[must_use]
+    pub fn new<N>(name: N, first: &A, second: &B, mode: DiffMapMode) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        Self {
+            map: Vec::new(),
+            first_snapshot: Vec::new(),
+            first_ref: first.handle(),
+            second_ref: second.handle(),
+            mode,
+            name: name.into(),
+        }
+    }
+
+    /// The [`DiffMapMode`] of this observer
+    #[must_use]
+    pub fn mode(&self) -> DiffMapMode {
+        self.mode
+    }
+}
+
+impl<A, B, T> DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    /// The number of entries that diverged in the last differential execution
+    #[must_use]
+    pub fn divergences(&self) -> usize
+    where
+        T: PartialEq,
+    {
+        let initial = T::default();
+        self.map.iter().filter(|x| **x != initial).count()
+    }
+}
+
+impl<A, B, S, T> Observer<S> for DiffMapObserver<A, B, T>
+where
+    S: UsesInput,
+    T: 'static + Default + Copy + Serialize,
+    Self: MapObserver,
+{
+    #[inline]
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.first_snapshot.clear();
+        self.reset_map()
+    }
+}
+
+impl<A, B, OTA, OTB, S, T> DifferentialObserver<OTA, OTB, S> for DiffMapObserver<A, B, T>
+where
+    A: MapObserver<Entry = T>,
+    B: MapObserver<Entry = T>,
+    OTA: ObserversTuple<S>,
+    OTB: ObserversTuple<S>,
+    S: UsesInput,
+    T: 'static + PrimInt + WrappingSub + Default + Copy + Serialize,
+    Self: MapObserver,
+{
+    fn post_observe_first(&mut self, observers: &mut OTA) -> Result<(), Error> {
+        let first = observers.get(&self.first_ref).ok_or_else(|| {
+            Error::key_not_found(format!(
+                "First map observer {} not found",
+                self.first_ref.name()
+            ))
+        })?;
+        self.first_snapshot = first.to_vec();
+        Ok(())
+    }
+
+    fn post_observe_second(&mut self, observers: &mut OTB) -> Result<(), Error> {
+        let second = observers.get(&self.second_ref).ok_or_else(|| {
+            Error::key_not_found(format!(
+                "Second map observer {} not found",
+                self.second_ref.name()
+            ))
+        })?;
+        let len = self.first_snapshot.len().max(second.usable_count());
+        self.map.resize(len, T::default());
+        for (i, entry) in self.map.iter_mut().enumerate() {
+            let first = self.first_snapshot.get(i).copied().unwrap_or_default();
+            let second = if i < second.usable_count() {
+                second.get(i)
+            } else {
+                T::default()
+            };
+            *entry = self.mode.combine(first, second);
+        }
+        Ok(())
+    }
+}
+
+impl<A, B, T> Named for DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<A, B, T> HasLen for DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    #[inline]
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+}
+
+impl<'it, A, B, T> IntoIterator for &'it DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    type Item = <Iter<'it, T> as Iterator>::Item;
+    type IntoIter = Iter<'it, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter()
+    }
+}
+
+impl<'it, A, B, T> IntoIterator for &'it mut DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    type Item = <IterMut<'it, T> as Iterator>::Item;
+    type IntoIter = IterMut<'it, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter_mut()
+    }
+}
+
+impl<A, B, T> Hash for DiffMapObserver<A, B, T>
+where
+    T: 'static + Hash + Default + Copy + Serialize,
+{
+    #[inline]
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.map.hash(hasher);
+    }
+}
+
+impl<A, B, T> AsRef<Self> for DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    fn as_ref(&self) -> &Self {
+        self
+    }
+}
+
+impl<A, B, T> AsMut<Self> for DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    fn as_mut(&mut self) -> &mut Self {
+        self
+    }
+}
+
+impl<A, B, T> MapObserver for DiffMapObserver<A, B, T>
+where
+    T: 'static + PrimInt + Default + Copy + Hash + Serialize + serde::de::DeserializeOwned + Debug,
+{
+    type Entry = T;
+
+    #[inline]
+    fn get(&self, pos: usize) -> T {
+        self.map[pos]
+    }
+
+    #[inline]
+    fn set(&mut self, pos: usize, val: Self::Entry) {
+        self.map[pos] = val;
+    }
+
+    fn count_bytes(&self) -> u64 {
+        self.divergences() as u64
+    }
+
+    #[inline]
+    fn usable_count(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    fn hash_simple(&self) -> u64 {
+        RandomState::with_seeds(0, 0, 0, 0).hash_one(self)
+    }
+
+    #[inline]
+    fn initial(&self) -> T {
+        T::default()
+    }
+
+    fn reset_map(&mut self) -> Result<(), Error> {
+        let initial = self.initial();
+        for x in &mut self.map {
+            *x = initial;
+        }
+        Ok(())
+    }
+
+    fn to_vec(&self) -> Vec<T> {
+        self.map.clone()
+    }
+
+    fn how_many_set(&self, indexes: &[usize]) -> usize {
+        let initial = self.initial();
+        indexes
+            .iter()
+            .filter(|&&i| i < self.map.len() && self.map[i] != initial)
+            .count()
+    }
+}
+
+impl<A, B, T> Deref for DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.map
+    }
+}
+
+impl<A, B, T> DerefMut for DiffMapObserver<A, B, T>
+where
+    T: 'static + Default + Copy + Serialize,
+{
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiffMapMode;
+
+    #[test]
+    #[allow(clippy::cast_sign_loss)]
+    fn test_diff_map_mode_combine() {
+        assert_eq!(DiffMapMode::Xor.combine(0b1100u8, 0b1010u8), 0b0110);
+        assert_eq!(DiffMapMode::Distance.combine(3u8, 10u8), 7);
+        assert_eq!(DiffMapMode::Distance.combine(10u8, 3u8), 7);
+        assert_eq!(DiffMapMode::Distance.combine(-128i8, 127i8) as u8, 255);
+        assert_eq!(DiffMapMode::Distance.combine(-3i32, 4i32), 7);
+        assert_eq!(DiffMapMode::Mismatch.combine(5u16, 5u16), 0);
+        assert_eq!(DiffMapMode::Mismatch.combine(5u16, 6u16), 1);
+    }
+}
@@ -0,0 +1,385 @@
+//! A multi map observer whose set of maps can grow between executions
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{
+    fmt::{self, Debug, Formatter},
+    hash::{Hash, Hasher},
+    iter::Flatten,
+    mem::size_of_val,
+    slice::{self, Iter, IterMut},
+};
+
+use ahash::RandomState;
+use libafl_bolts::{
+    ownedref::OwnedMutSlice, AsIter, AsIterMut, AsSlice, AsSliceMut, HasLen, Named,
+};
+use meminterval::IntervalTree;
+use num_traits::Bounded;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    inputs::UsesInput,
+    observers::{map::MapObserver, Observer},
+    Error,
+};
+
+/// A function returning all maps that currently exist, e.g. the coverage regions of all loaded modules
+pub type MapDiscovery<'a, T> = fn() -> Vec<OwnedMutSlice<'a, T>>;
+
+/// The [`DynamicMultiMapObserver`] concatenates multiple maps into one observer, like the
+/// [`crate::observers::MultiMapObserver`], but new maps can be added between executions.
+///
+/// This is useful for targets that load several instrumented shared objects at runtime,
+/// each with its own coverage region.
+/// New maps are always appended to the end, so the indices of already known entries are stable
+/// and the history of [`crate::feedbacks::MapFeedback`] stays valid (it grows along with the observer).
+///
+/// If a [`MapDiscovery`] function is set, it is queried before each execution and all maps starting
+/// at an address which is not observed yet are appended.
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "T: serde::de::DeserializeOwned")]
+#[allow(clippy::unsafe_derive_deserialize)]
+pub struct DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + Debug,
+{
+    maps: Vec<OwnedMutSlice<'a, T>>,
+    intervals: IntervalTree<usize, usize>,
+    len: usize,
+    initial: T,
+    name: Cow<'static, str>,
+    #[serde(skip)]
+    discovery: Option<MapDiscovery<'a, T>>,
+}
+
+impl<'a, T> Debug for DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DynamicMultiMapObserver")
+            .field("maps", &self.maps)
+            .field("len", &self.len)
+            .field("initial", &self.initial)
+            .field("name", &self.name)
+            .field("discovery", &self.discovery.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a, T> DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + serde::de::DeserializeOwned + Debug,
+{
+    /// Creates a new [`DynamicMultiMapObserver`] observing the given maps
+    #[must_use]
+    pub fn new<N>(name: N, maps: Vec<OwnedMutSlice<'a, T>>) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        let mut observer = Self {
+            maps: Vec::new(),
+            intervals: IntervalTree::new(),
+            len: 0,
+            initial: T::default(),
+            name: name.into(),
+            discovery: None,
+        };
+        for map in maps {
+            observer.push_map(map);
+        }
+        observer
+    }
+
+    /// Creates a new [`DynamicMultiMapObserver`] observing all maps returned by `discovery`,
+    /// now and before each execution.
+    #[must_use]
+    pub fn with_discovery<N>(name: N, discovery: MapDiscovery<'a, T>) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        let mut observer = Self::new(name, Vec::new());
+        observer.discovery = Some(discovery);
+        observer.discover_maps();
+        observer
+    }
+
+    /// Appends a map to the observed maps.
+    pub fn push_map(&mut self, map: OwnedMutSlice<'a, T>) {
+        let l = map.as_slice().len();
+        if l == 0 {
+            return;
+        }
+        self.intervals
+            .insert(self.len..(self.len + l), self.maps.len());
+        self.len += l;
+        self.maps.push(map);
+    }
+
+    /// Queries the [`MapDiscovery`] function, if any, and appends all new maps.
+    /// Returns the number of added maps.
+    pub fn discover_maps(&mut self) -> usize {
+        let Some(discovery) = self.discovery else {
+            return 0;
+        };
+        let mut added = 0;
+        for map in discovery() {
+            let start = map.as_slice().as_ptr();
+            if !self.maps.iter().any(|m| m.as_slice().as_ptr() == start) {
+                self.push_map(map);
+                added += 1;
+            }
+        }
+        if added > 0 {
+            log::info!(
+                "{}: observing {added} new map(s), {} entries in total",
+                self.name,
+                self.len
+            );
+        }
+        added
+    }
+
+    /// The number of observed maps
+    #[must_use]
+    pub fn maps_count(&self) -> usize {
+        self.maps.len()
+    }
+
+    /// Returns an iterator over the map.
+    pub fn iter(&self) -> <&Self as IntoIterator>::IntoIter {
+        <&Self as IntoIterator>::into_iter(self)
+    }
+
+    /// Returns a mutable iterator over the map.
+    pub fn iter_mut(&mut self) -> <&mut Self as IntoIterator>::IntoIter {
+        <&mut Self as IntoIterator>::into_iter(self)
+    }
+}
+
+impl<'a, S, T> Observer<S> for DynamicMultiMapObserver<'a, T>
+where
+    S: UsesInput,
+    T: 'static + Default + Copy + Serialize + serde::de::DeserializeOwned + Debug,
+    Self: MapObserver,
+{
+    #[inline]
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.discover_maps();
+        self.reset_map()
+    }
+}
+
+impl<'a, T> Named for DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + Debug,
+{
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<'a, T> HasLen for DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + Debug,
+{
+    #[inline]
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<'a, T> Hash for DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + Debug,
+{
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        for map in &self.maps {
+            let slice = map.as_slice();
+            let ptr = slice.as_ptr() as *const u8;
+            let map_size = size_of_val(slice);
+            unsafe {
+                hasher.write(slice::from_raw_parts(ptr, map_size));
+            }
+        }
+    }
+}
+
+impl<'a, T> AsRef<Self> for DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + Debug,
+{
+    fn as_ref(&self) -> &Self {
+        self
+    }
+}
+
+impl<'a, T> AsMut<Self> for DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + Debug,
+{
+    fn as_mut(&mut self) -> &mut Self {
+        self
+    }
+}
+
+impl<'a, T> MapObserver for DynamicMultiMapObserver<'a, T>
+where
+    T: 'static
+        + Bounded
+        + PartialEq
+        + Default
+        + Copy
+        + Hash
+        + Serialize
+        + serde::de::DeserializeOwned
+        + Debug,
+{
+    type Entry = T;
+
+    #[inline]
+    fn get(&self, idx: usize) -> T {
+        let elem = self.intervals.query(idx..=idx).next().unwrap();
+        let i = *elem.value;
+        let j = idx - elem.interval.start;
+        self.maps[i].as_slice()[j]
+    }
+
+    #[inline]
+    fn set(&mut self, idx: usize, val: Self::Entry) {
+        let elem = self.intervals.query(idx..=idx).next().unwrap();
+        let i = *elem.value;
+        let j = idx - elem.interval.start;
+        self.maps[i].as_slice_mut()[j] = val;
+    }
+
+    #[inline]
+    fn initial(&self) -> T {
+        self.initial
+    }
+
+    fn count_bytes(&self) -> u64 {
+        let initial = self.initial();
+        self.maps
+            .iter()
+            .flat_map(|map| map.as_slice().iter())
+            .filter(|x| **x != initial)
+            .count() as u64
+    }
+
+    #[inline]
+    fn hash_simple(&self) -> u64 {
+        RandomState::with_seeds(0, 0, 0, 0).hash_one(self)
+    }
+
+    fn reset_map(&mut self) -> Result<(), Error> {
+        let initial = self.initial();
+        for map in &mut self.maps {
+            for x in map.as_slice_mut() {
+                *x = initial;
+            }
+        }
+        Ok(())
+    }
+
+    fn usable_count(&self) -> usize {
+        self.len()
+    }
+
+    fn to_vec(&self) -> Vec<Self::Entry> {
+        self.maps
+            .iter()
+            .flat_map(|map| map.as_slice().iter().copied())
+            .collect()
+    }
+
+    /// Get the number of set entries with the specified indexes
+    fn how_many_set(&self, indexes: &[usize]) -> usize {
+        let initial = self.initial();
+        let cnt = self.usable_count();
+        indexes
+            .iter()
+            .filter(|i| **i < cnt && self.get(**i) != initial)
+            .count()
+    }
+}
+
+impl<'a, 'it, T> AsIter<'it> for DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + serde::de::DeserializeOwned + Debug,
+    'a: 'it,
+{
+    type Item = T;
+    type Ref = &'it T;
+    type IntoIter = Flatten<Iter<'it, OwnedMutSlice<'a, T>>>;
+
+    fn as_iter(&'it self) -> Self::IntoIter {
+        self.maps.iter().flatten()
+    }
+}
+
+impl<'a, 'it, T> AsIterMut<'it> for DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + serde::de::DeserializeOwned + Debug,
+    'a: 'it,
+{
+    type RefMut = &'it mut T;
+    type IntoIterMut = Flatten<IterMut<'it, OwnedMutSlice<'a, T>>>;
+
+    fn as_iter_mut(&'it mut self) -> Self::IntoIterMut {
+        self.maps.iter_mut().flatten()
+    }
+}
+
+impl<'a, 'it, T> IntoIterator for &'it DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + serde::de::DeserializeOwned + Debug,
+{
+    type Item = <Iter<'it, T> as Iterator>::Item;
+    type IntoIter = Flatten<Iter<'it, OwnedMutSlice<'a, T>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.maps.iter().flatten()
+    }
+}
+
+impl<'a, 'it, T> IntoIterator for &'it mut DynamicMultiMapObserver<'a, T>
+where
+    T: 'static + Default + Copy + Serialize + serde::de::DeserializeOwned + Debug,
+{
+    type Item = <IterMut<'it, T> as Iterator>::Item;
+    type IntoIter = Flatten<IterMut<'it, OwnedMutSlice<'a, T>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.maps.iter_mut().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use libafl_bolts::ownedref::OwnedMutSlice;
+
+    use super::DynamicMultiMapObserver;
+    use crate::observers::MapObserver;
+
+    #[test]
+    fn test_dynamic_multi_map_grows() {
+        let mut observer =
+            DynamicMultiMapObserver::new("dyn", vec![OwnedMutSlice::from(vec![0_u8, 1, 0])]);
+        assert_eq!(observer.usable_count(), 3);
+        assert_eq!(observer.count_bytes(), 1);
+
+        observer.push_map(OwnedMutSlice::from(vec![2_u8, 0]));
+        assert_eq!(observer.usable_count(), 5);
+        assert_eq!(observer.maps_count(), 2);
+        assert_eq!(observer.get(1), 1);
+        assert_eq!(observer.get(3), 2);
+        assert_eq!(observer.to_vec(), vec![0, 1, 0, 2, 0]);
+
+        observer.reset_map().unwrap();
+        assert_eq!(observer.count_bytes(), 0);
+    }
+}
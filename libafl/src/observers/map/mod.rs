@@ -29,9 +29,23 @@ pub use variable_map::*;
 pub mod hitcount_map;
 pub use hitcount_map::*;
 
+pub mod counters_map;
+pub use counters_map::*;
+
+pub mod diff_map;
+pub use diff_map::*;
+
+#[cfg(all(unix, feature = "std"))]
+pub mod paged_map;
+#[cfg(all(unix, feature = "std"))]
+pub use paged_map::*;
+
 pub mod multi_map;
 pub use multi_map::*;
 
+pub mod dynamic_multi_map;
+pub use dynamic_multi_map::*;
+
 pub mod owned_map;
 pub use owned_map::*;
 
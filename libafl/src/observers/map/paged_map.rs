@@ -0,0 +1,374 @@
+//! Map observer skipping untouched memory pages of oversized maps
+use alloc::{borrow::Cow, vec::Vec};
+use core::{fmt::Debug, hash::Hash, mem::size_of};
+
+use libafl_bolts::{AsSlice, AsSliceMut, HasLen, Named, Truncate};
+use num_traits::Bounded;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executors::ExitKind,
+    inputs::UsesInput,
+    observers::{map::MapObserver, DifferentialObserver, Observer, ObserversTuple},
+    Error,
+};
+
+/// Returns the system page size
+fn page_size() -> usize {
+    // # Safety
+    // `sysconf` has no preconditions.
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    usize::try_from(size).unwrap_or(4096)
+}
+
+/// Map observer for maps that are (much) larger than what the target uses, e.g. a single
+/// oversized map allocation that is reused for many differently sized targets.
+///
+/// After each execution, this observer asks the kernel (using `mincore`) which pages of the map
+/// have ever been touched. Pages that were never touched are still backed by the zero page, so
+/// their entries are known to be `0` and never need to be scanned:
+/// - [`MapObserver::usable_count`] (and [`AsSlice`], which feedbacks use for novelty search) ends
+///   at the last touched page,
+/// - [`MapObserver::count_bytes`] and [`MapObserver::reset_map`] skip untouched pages.
+///
+/// This requires the initial value of the map to be `0`, and the map to be backed by anonymous or
+/// shared memory, or by a `static` in `.bss`. If `mincore` fails, all pages are assumed touched.
+#[derive(Serialize, Deserialize, Clone, Debug, Hash)]
+#[serde(bound = "M: serde::de::DeserializeOwned")]
+pub struct PagedMapObserver<M>
+where
+    M: Serialize,
+{
+    base: M,
+    /// For each page of the map, whether it was touched
+    touched_pages: Vec<bool>,
+    /// The number of entries up to the end of the last touched page
+    touched_len: usize,
+    /// The number of map entries per page, and the offset of the map in its first page
+    #[serde(skip)]
+    page_layout: Option<(usize, usize)>,
+}
+
+impl<M, T> PagedMapObserver<M>
+where
+    M: MapObserver<Entry = T> + for<'a> AsSlice<'a, Entry = T, SliceRef = &'a [T]>,
+    T: Copy,
+{
+    /// Creates a new [`PagedMapObserver`].
+    pub fn new(base: M) -> Self {
+        let touched_len = base.usable_count();
+        Self {
+            base,
+            touched_pages: Vec::new(),
+            touched_len,
+            page_layout: None,
+        }
+    }
+
+    /// The number of pages of the map that have been touched so far
+    #[must_use]
+    pub fn touched_pages(&self) -> usize {
+        self.touched_pages
+            .iter()
+            .filter(|touched| **touched)
+            .count()
+    }
+
+    /// Returns the ranges of entries of touched pages
+    fn touched_ranges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let usable = self.base.usable_count();
+        let (entries_per_page, offset) = self.page_layout.unwrap_or((usable.max(1), 0));
+        self.touched_pages
+            .iter()
+            .enumerate()
+            .filter(|(_, touched)| **touched)
+            .map(move |(page, _)| {
+                let start = (page * entries_per_page).saturating_sub(offset);
+                let end = ((page + 1) * entries_per_page)
+                    .saturating_sub(offset)
+                    .min(usable);
+                (start, end)
+            })
+    }
+
+    /// Updates the touched pages using `mincore`
+    fn update_touched_pages(&mut self) {
+        let map = self.base.as_slice();
+        let usable = self.base.usable_count().min(map.len());
+        if usable == 0 {
+            self.touched_pages.clear();
+            self.touched_len = 0;
+            return;
+        }
+
+        let page_size = page_size();
+        let entry_size = size_of::<T>().max(1);
+        let start = map.as_ptr() as usize;
+        let page_start = start & !(page_size - 1);
+        let end = start + usable * entry_size;
+        let page_count = (end - page_start).div_ceil(page_size);
+
+        let mut residency = vec![0_u8; page_count];
+        // # Safety
+        // `mincore` only writes `page_count` entries to `residency`; the range is page-aligned.
+        let ret = unsafe {
+            libc::mincore(
+                page_start as *mut libc::c_void,
+                end - page_start,
+                residency.as_mut_ptr().cast(),
+            )
+        };
+
+        self.page_layout = Some((page_size / entry_size, (start - page_start) / entry_size));
+        if ret == 0 {
+            self.touched_pages = residency.iter().map(|r| r & 1 != 0).collect();
+        } else {
+            self.touched_pages = vec![true; page_count];
+        }
+        self.touched_len = self.touched_ranges().map(|(_, end)| end).max().unwrap_or(0);
+    }
+}
+
+impl<S, M, T> Observer<S> for PagedMapObserver<M>
+where
+    M: MapObserver<Entry = T>
+        + Observer<S>
+        + for<'a> AsSlice<'a, Entry = T, SliceRef = &'a [T]>
+        + for<'a> AsSliceMut<'a, SliceRefMut = &'a mut [T]>,
+    T: Bounded + PartialEq + Default + Copy + Debug + Hash + 'static,
+    S: UsesInput,
+{
+    /// Resets the touched pages of the map.
+    ///
+    /// The `pre_exec` of the base observer is *not* called, as it would usually reset (and
+    /// thereby touch) the whole map.
+    #[inline]
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        MapObserver::reset_map(self)
+    }
+
+    #[inline]
+    fn post_exec(
+        &mut self,
+        state: &mut S,
+        input: &S::Input,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.update_touched_pages();
+        self.base.post_exec(state, input, exit_kind)
+    }
+}
+
+impl<M> Named for PagedMapObserver<M>
+where
+    M: Named + Serialize + serde::de::DeserializeOwned,
+{
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        self.base.name()
+    }
+}
+
+impl<M> HasLen for PagedMapObserver<M>
+where
+    M: MapObserver,
+{
+    #[inline]
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+}
+
+impl<M> AsRef<Self> for PagedMapObserver<M>
+where
+    M: MapObserver,
+{
+    fn as_ref(&self) -> &Self {
+        self
+    }
+}
+
+impl<M> AsMut<Self> for PagedMapObserver<M>
+where
+    M: MapObserver,
+{
+    fn as_mut(&mut self) -> &mut Self {
+        self
+    }
+}
+
+impl<M, T> MapObserver for PagedMapObserver<M>
+where
+    M: MapObserver<Entry = T>
+        + for<'a> AsSlice<'a, Entry = T, SliceRef = &'a [T]>
+        + for<'a> AsSliceMut<'a, SliceRefMut = &'a mut [T]>,
+    T: Bounded + PartialEq + Default + Copy + Debug + Hash + 'static,
+{
+    type Entry = T;
+
+    #[inline]
+    fn initial(&self) -> T {
+        self.base.initial()
+    }
+
+    /// The number of entries up to the end of the last touched page
+    #[inline]
+    fn usable_count(&self) -> usize {
+        self.touched_len.min(self.base.usable_count())
+    }
+
+    #[inline]
+    fn get(&self, idx: usize) -> T {
+        self.base.get(idx)
+    }
+
+    #[inline]
+    fn set(&mut self, idx: usize, val: T) {
+        self.base.set(idx, val);
+    }
+
+    /// Count the set entries in the touched pages
+    fn count_bytes(&self) -> u64 {
+        let initial = self.initial();
+        let map = self.base.as_slice();
+        let mut res = 0;
+        for (start, end) in self.touched_ranges() {
+            res += map[start..end].iter().filter(|x| **x != initial).count() as u64;
+        }
+        res
+    }
+
+    /// Reset the touched pages of the map
+    fn reset_map(&mut self) -> Result<(), Error> {
+        if self.page_layout.is_none() {
+            // Before the first reset, e.g. after construction or a restart, look up the pages
+            // touched so far, as resetting the whole map would touch every page.
+            self.update_touched_pages();
+        }
+        let initial = self.initial();
+        let ranges: Vec<(usize, usize)> = self.touched_ranges().collect();
+        let map = self.base.as_slice_mut();
+        for (start, end) in ranges {
+            for x in &mut map[start..end] {
+                *x = initial;
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn hash_simple(&self) -> u64 {
+        self.base.hash_simple()
+    }
+
+    fn to_vec(&self) -> Vec<T> {
+        self.base.to_vec()
+    }
+
+    fn how_many_set(&self, indexes: &[usize]) -> usize {
+        self.base.how_many_set(indexes)
+    }
+}
+
+impl<'a, M, T> AsSlice<'a> for PagedMapObserver<M>
+where
+    M: MapObserver<Entry = T> + AsSlice<'a, Entry = T, SliceRef = &'a [T]>,
+    T: 'a,
+{
+    type Entry = T;
+    type SliceRef = &'a [T];
+
+    /// The map, up to the end of the last touched page
+    #[inline]
+    fn as_slice(&'a self) -> Self::SliceRef {
+        let map = self.base.as_slice();
+        &map[..self.touched_len.min(map.len())]
+    }
+}
+
+impl<M> Truncate for PagedMapObserver<M>
+where
+    M: Named + Serialize + serde::de::DeserializeOwned + Truncate,
+{
+    fn truncate(&mut self, new_len: usize) {
+        self.base.truncate(new_len);
+        self.touched_len = self.touched_len.min(new_len);
+    }
+}
+
+impl<M, OTA, OTB, S> DifferentialObserver<OTA, OTB, S> for PagedMapObserver<M>
+where
+    M: DifferentialObserver<OTA, OTB, S> + Serialize,
+    Self: Observer<S>,
+    OTA: ObserversTuple<S>,
+    OTB: ObserversTuple<S>,
+    S: UsesInput,
+{
+    fn pre_observe_first(&mut self, observers: &mut OTA) -> Result<(), Error> {
+        self.base.pre_observe_first(observers)
+    }
+
+    fn post_observe_first(&mut self, observers: &mut OTA) -> Result<(), Error> {
+        self.base.post_observe_first(observers)
+    }
+
+    fn pre_observe_second(&mut self, observers: &mut OTB) -> Result<(), Error> {
+        self.base.pre_observe_second(observers)
+    }
+
+    fn post_observe_second(&mut self, observers: &mut OTB) -> Result<(), Error> {
+        self.base.post_observe_second(observers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::AsSliceMut;
+
+    use super::{page_size, PagedMapObserver};
+    use crate::observers::{map::MapObserver, StdMapObserver};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_paged_map_skips_untouched_pages() {
+        let page_size = page_size();
+        let len = 16 * page_size;
+        // # Safety
+        // A fresh anonymous mapping, unmapped at the end of the test.
+        let map = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert_ne!(map, libc::MAP_FAILED);
+
+        let base = unsafe { StdMapObserver::from_mut_ptr("paged", map.cast::<u8>(), len) };
+        let mut observer = PagedMapObserver::new(base);
+
+        // The first reset must not touch the whole map
+        observer.reset_map().unwrap();
+        assert_eq!(observer.touched_pages(), 0);
+        assert_eq!(observer.usable_count(), 0);
+
+        observer.base.as_slice_mut()[3 * page_size + 7] = 1;
+        observer.update_touched_pages();
+        assert_eq!(observer.touched_pages(), 1);
+        assert_eq!(observer.usable_count(), 4 * page_size);
+        assert_eq!(observer.count_bytes(), 1);
+
+        observer.reset_map().unwrap();
+        assert_eq!(observer.count_bytes(), 0);
+        assert_eq!(observer.touched_pages(), 1);
+
+        // # Safety
+        // The mapping is not used after this point.
+        unsafe {
+            libc::munmap(map, len);
+        }
+    }
+}
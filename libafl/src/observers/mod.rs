@@ -14,6 +14,11 @@ pub mod stacktrace;
 #[cfg(feature = "regex")]
 pub use stacktrace::*;
 
+#[cfg(feature = "regex")]
+pub mod sanitizer_report;
+#[cfg(feature = "regex")]
+pub use sanitizer_report::*;
+
 /// Profiler observer
 #[cfg(feature = "std")]
 pub mod profiling;
@@ -24,17 +29,20 @@ pub mod concolic;
 pub mod map;
 pub use map::*;
 
+pub mod network;
+pub use network::*;
+
 pub mod value;
 
 /// List observer
 pub mod list;
 use core::{fmt::Debug, time::Duration};
-#[cfg(feature = "std")]
-use std::time::Instant;
 
-#[cfg(not(feature = "std"))]
-use libafl_bolts::current_time;
-use libafl_bolts::{tuples::MatchName, Named};
+use libafl_bolts::{
+    clock::{Clock, SYSTEM_CLOCK},
+    tuples::MatchName,
+    Named,
+};
 pub use list::*;
 use serde::{Deserialize, Serialize};
 pub use value::*;
@@ -323,60 +331,42 @@ where
 }
 
 /// A simple observer, just overlooking the runtime of the target.
+///
+/// The time is taken from a [`Clock`], the [`SYSTEM_CLOCK`] by default.
+/// Use [`TimeObserver::with_clock`] and a static [`libafl_bolts::clock::MockClock`] for deterministic tests.
+/// The clock is not serialized; a deserialized observer uses the [`SYSTEM_CLOCK`].
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TimeObserver {
     name: Cow<'static, str>,
 
-    #[cfg(feature = "std")]
-    #[serde(with = "instant_serializer")]
-    start_time: Instant,
-
-    #[cfg(not(feature = "std"))]
     start_time: Duration,
 
     last_runtime: Option<Duration>,
-}
-
-#[cfg(feature = "std")]
-mod instant_serializer {
-    use core::time::Duration;
-    use std::time::Instant;
-
-    use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-    #[allow(clippy::trivially_copy_pass_by_ref)]
-    pub fn serialize<S>(instant: &Instant, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        let duration = instant.elapsed();
-        duration.serialize(serializer)
-    }
+    #[serde(skip, default = "system_clock")]
+    clock: &'static dyn Clock,
+}
 
-    pub fn deserialize<'de, D>(deserializer: D) -> Result<Instant, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        let duration = Duration::deserialize(deserializer)?;
-        let instant = Instant::now().checked_sub(duration).unwrap();
-        Ok(instant)
-    }
+/// The default clock of a deserialized [`TimeObserver`]
+fn system_clock() -> &'static dyn Clock {
+    &SYSTEM_CLOCK
 }
 
 impl TimeObserver {
     /// Creates a new [`TimeObserver`] with the given name.
     #[must_use]
     pub fn new(name: &'static str) -> Self {
+        Self::with_clock(name, &SYSTEM_CLOCK)
+    }
+
+    /// Creates a new [`TimeObserver`] with the given name, taking the time from `clock`.
+    #[must_use]
+    pub fn with_clock(name: &'static str, clock: &'static dyn Clock) -> Self {
         Self {
             name: Cow::from(name),
-
-            #[cfg(feature = "std")]
-            start_time: Instant::now(),
-
-            #[cfg(not(feature = "std"))]
-            start_time: Duration::from_secs(0),
-
+            start_time: Duration::ZERO,
             last_runtime: None,
+            clock,
         }
     }
 
@@ -385,45 +375,36 @@ impl TimeObserver {
     pub fn last_runtime(&self) -> &Option<Duration> {
         &self.last_runtime
     }
+
+    /// The clock of this observer
+    #[must_use]
+    pub fn clock(&self) -> &'static dyn Clock {
+        self.clock
+    }
+
+    /// Sets the clock of this observer
+    pub fn set_clock(&mut self, clock: &'static dyn Clock) {
+        self.clock = clock;
+    }
 }
 
 impl<S> Observer<S> for TimeObserver
 where
     S: UsesInput,
 {
-    #[cfg(feature = "std")]
     fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
         self.last_runtime = None;
-        self.start_time = Instant::now();
+        self.start_time = self.clock.now();
         Ok(())
     }
 
-    #[cfg(not(feature = "std"))]
-    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
-        self.last_runtime = None;
-        self.start_time = current_time();
-        Ok(())
-    }
-
-    #[cfg(feature = "std")]
     fn post_exec(
         &mut self,
         _state: &mut S,
         _input: &S::Input,
         _exit_kind: &ExitKind,
     ) -> Result<(), Error> {
-        self.last_runtime = Some(self.start_time.elapsed());
-        Ok(())
-    }
-
-    #[cfg(not(feature = "std"))]
-    fn post_exec(
-        &mut self,
-        _state: &mut S,
-        _input: &S::Input,
-        _exit_kind: &ExitKind,
-    ) -> Result<(), Error> {
-        self.last_runtime = current_time().checked_sub(self.start_time);
+        self.last_runtime = self.clock.now().checked_sub(self.start_time);
         Ok(())
     }
 }
@@ -446,15 +427,21 @@ where
 #[cfg(test)]
 mod tests {
 
-    use core::ptr::addr_of_mut;
+    use core::{ptr::addr_of_mut, time::Duration};
 
     use libafl_bolts::{
+        clock::MockClock,
         ownedref::OwnedMutSlice,
         tuples::{tuple_list, tuple_list_type},
         Named,
     };
 
-    use crate::observers::{StdMapObserver, TimeObserver};
+    use crate::{
+        executors::ExitKind,
+        inputs::BytesInput,
+        observers::{Observer, StdMapObserver, TimeObserver},
+        state::NopState,
+    };
 
     static mut MAP: [u32; 4] = [0; 4];
 
@@ -472,4 +459,19 @@ mod tests {
             postcard::from_bytes(&vec).unwrap();
         assert_eq!(obv.0.name(), obv2.0.name());
     }
+
+    #[test]
+    fn test_time_observer_mock_clock() {
+        static CLOCK: MockClock = MockClock::new(Duration::from_secs(10));
+        let mut observer = TimeObserver::with_clock("time", &CLOCK);
+        let mut state = NopState::<BytesInput>::new();
+        let input = BytesInput::new(vec![]);
+
+        observer.pre_exec(&mut state, &input).unwrap();
+        CLOCK.advance(Duration::from_millis(42));
+        observer
+            .post_exec(&mut state, &input, &ExitKind::Ok)
+            .unwrap();
+        assert_eq!(*observer.last_runtime(), Some(Duration::from_millis(42)));
+    }
 }
@@ -0,0 +1,186 @@
+//! The [`NetworkObserver`] captures the bytes a socket-based target sends and receives during an
+//! execution. The executor, or the harness talking to the target, must feed it, see
+//! [`NetworkObserver::record_sent`] and [`NetworkObserver::record_received`].
+//!
+//! Its hash covers the responses of the target, so a `NewHashFeedback` on it finds inputs
+//! triggering new responses, guiding stateful protocol fuzzing. The
+//! `NetworkTrafficFeedback` stores the captured traffic in the testcase, for reproduction.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::hash::{BuildHasher, Hasher};
+
+use ahash::RandomState;
+use libafl_bolts::Named;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    inputs::UsesInput,
+    observers::{Observer, ObserverWithHashField},
+    Error,
+};
+
+/// The default maximal number of bytes captured per execution
+pub const DEFAULT_MAX_TRAFFIC_BYTES: usize = 1 << 16;
+
+/// The direction of captured traffic, seen from the fuzzer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TrafficDirection {
+    /// Sent to the target
+    Sent,
+    /// Received from the target
+    Received,
+}
+
+/// A chunk of traffic, e.g. one `send` or `recv`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TrafficChunk {
+    /// The direction of the chunk
+    pub direction: TrafficDirection,
+    /// The bytes of the chunk
+    pub data: Vec<u8>,
+}
+
+/// An observer capturing the network traffic of an execution, bounded in size.
+/// Only works with executors or harnesses feeding it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NetworkObserver {
+    name: Cow<'static, str>,
+    max_bytes: usize,
+    captured: usize,
+    truncated: bool,
+    chunks: Vec<TrafficChunk>,
+}
+
+impl NetworkObserver {
+    /// Creates a new [`NetworkObserver`], capturing up to [`DEFAULT_MAX_TRAFFIC_BYTES`] per
+    /// execution
+    #[must_use]
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name: Cow::from(name),
+            max_bytes: DEFAULT_MAX_TRAFFIC_BYTES,
+            captured: 0,
+            truncated: false,
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Sets the maximal number of bytes captured per execution, in both directions
+    #[must_use]
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Records bytes sent to the target
+    pub fn record_sent(&mut self, data: &[u8]) {
+        self.record(TrafficDirection::Sent, data);
+    }
+
+    /// Records bytes received from the target
+    pub fn record_received(&mut self, data: &[u8]) {
+        self.record(TrafficDirection::Received, data);
+    }
+
+    /// Records a chunk, truncated to the remaining capacity
+    pub fn record(&mut self, direction: TrafficDirection, data: &[u8]) {
+        let len = data.len().min(self.max_bytes - self.captured);
+        if len < data.len() {
+            self.truncated = true;
+        }
+        if len == 0 {
+            return;
+        }
+        self.captured += len;
+        self.chunks.push(TrafficChunk {
+            direction,
+            data: data[..len].to_vec(),
+        });
+    }
+
+    /// The chunks captured during the last execution, in order
+    #[must_use]
+    pub fn chunks(&self) -> &[TrafficChunk] {
+        &self.chunks
+    }
+
+    /// Checks if traffic of the last execution was dropped, exceeding the maximal size
+    #[must_use]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The bytes captured in one direction, concatenated
+    #[must_use]
+    pub fn stream(&self, direction: TrafficDirection) -> Vec<u8> {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.direction == direction)
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect()
+    }
+}
+
+impl Named for NetworkObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl ObserverWithHashField for NetworkObserver {
+    /// The hash of the received bytes, if anything was received.
+    /// How the bytes were split into chunks does not matter, as it depends on the timing.
+    fn hash(&self) -> Option<u64> {
+        let received = self.stream(TrafficDirection::Received);
+        if received.is_empty() {
+            return None;
+        }
+        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+        hasher.write(&received);
+        Some(hasher.finish())
+    }
+}
+
+impl<S> Observer<S> for NetworkObserver
+where
+    S: UsesInput,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.chunks.clear();
+        self.captured = 0;
+        self.truncated = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NetworkObserver, TrafficDirection};
+    use crate::observers::ObserverWithHashField;
+
+    #[test]
+    fn test_network_observer() {
+        let mut observer = NetworkObserver::new("network").with_max_bytes(8);
+        assert_eq!(observer.hash(), None);
+        observer.record_sent(b"HELO");
+        observer.record_received(b"250");
+        let hash = observer.hash();
+        assert!(hash.is_some());
+
+        // The same responses, split differently, are the same behavior
+        let mut other = NetworkObserver::new("network").with_max_bytes(8);
+        other.record_sent(b"HELO");
+        other.record_received(b"25");
+        other.record_received(b"0");
+        assert_eq!(other.hash(), hash);
+        assert_eq!(other.stream(TrafficDirection::Received), b"250");
+
+        let mut other = NetworkObserver::new("network").with_max_bytes(8);
+        other.record_received(b"550");
+        assert_ne!(other.hash(), hash);
+
+        observer.record_sent(b"QUIT");
+        assert!(observer.is_truncated());
+        assert_eq!(observer.stream(TrafficDirection::Sent), b"HELOQ");
+    }
+}
@@ -0,0 +1,373 @@
+//! The [`SanitizerReportObserver`] parses `AddressSanitizer` and `UndefinedBehaviorSanitizer`
+//! reports into a structured [`SanitizerReport`].
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::{self, Display, Formatter};
+use std::{fs, path::PathBuf, sync::OnceLock};
+
+use libafl_bolts::Named;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executors::ExitKind,
+    inputs::UsesInput,
+    observers::{AsanBacktraceObserver, Observer, StdErrObserver},
+    Error,
+};
+
+/// The default number of stack frames kept in a [`SanitizerReport`]
+pub const DEFAULT_SANITIZER_REPORT_FRAMES: usize = 5;
+
+/// The regexes matching the parts of a sanitizer report, compiled on first use
+static ERROR: OnceLock<Regex> = OnceLock::new();
+static RUNTIME_ERROR: OnceLock<Regex> = OnceLock::new();
+static ACCESS: OnceLock<Regex> = OnceLock::new();
+static PC: OnceLock<Regex> = OnceLock::new();
+static FRAME: OnceLock<Regex> = OnceLock::new();
+
+/// The kind of memory access reported by a sanitizer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SanitizerAccessKind {
+    /// A read access
+    Read,
+    /// A write access
+    Write,
+}
+
+impl Display for SanitizerAccessKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SanitizerAccessKind::Read => write!(f, "READ"),
+            SanitizerAccessKind::Write => write!(f, "WRITE"),
+        }
+    }
+}
+
+/// A structured sanitizer report, as parsed from the text output of the sanitizer runtime
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SanitizerReport {
+    /// The bug type, e.g. `heap-buffer-overflow` or `SEGV`
+    pub bug_type: String,
+    /// The faulting address, if reported
+    pub address: Option<u64>,
+    /// The program counter of the faulting instruction, if reported
+    pub pc: Option<u64>,
+    /// The kind of the faulting access, if reported
+    pub access_kind: Option<SanitizerAccessKind>,
+    /// The size of the faulting access, if reported
+    pub access_size: Option<usize>,
+    /// The top frames of the stack trace, as `function` or `module+offset`
+    pub frames: Vec<String>,
+}
+
+libafl_bolts::impl_serdeany!(SanitizerReport);
+
+impl Display for SanitizerReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.bug_type)?;
+        if let Some(access_kind) = self.access_kind {
+            write!(f, " {access_kind}")?;
+        }
+        if let Some(access_size) = self.access_size {
+            write!(f, " {access_size}")?;
+        }
+        if let Some(frame) = self.frames.first() {
+            write!(f, " in {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+impl SanitizerReport {
+    /// Parses the first sanitizer report in `output`, keeping at most `max_frames` stack frames.
+    ///
+    /// Supports `AddressSanitizer` reports
+    /// (`ERROR: AddressSanitizer: heap-buffer-overflow on address 0x... ` followed by
+    /// `READ of size 4 at 0x...`) and `UndefinedBehaviorSanitizer` reports
+    /// (`file.c:1:2: runtime error: ...`, or `ERROR: UndefinedBehaviorSanitizer: SEGV ...`).
+    #[must_use]
+    pub fn parse(output: &str, max_frames: usize) -> Option<Self> {
+        let error = ERROR.get_or_init(|| {
+            Regex::new(
+                r"ERROR: (?:Address|Memory|Thread|Leak|UndefinedBehavior)Sanitizer: ([\w-]+)(?: on (?:unknown )?address (0x[0-9a-fA-F]+))?",
+            )
+            .unwrap()
+        });
+        let runtime_error =
+            RUNTIME_ERROR.get_or_init(|| Regex::new(r"runtime error: (.*)").unwrap());
+        let access = ACCESS
+            .get_or_init(|| Regex::new(r"(READ|WRITE) (?:of size (\d+)|memory access)").unwrap());
+        let pc = PC.get_or_init(|| Regex::new(r"(?:at pc|\(pc) (0x[0-9a-fA-F]+)").unwrap());
+        let frame = FRAME.get_or_init(|| {
+            Regex::new(r"^\s*#\d+\s+(0x[0-9a-fA-F]+)\s+(?:in\s+(\S+)|\((.*)\))").unwrap()
+        });
+
+        let (bug_type, address, rest) = if let Some(m) = error.captures(output) {
+            let address = m.get(2).and_then(|a| parse_hex(a.as_str()));
+            (
+                m[1].to_string(),
+                address,
+                &output[m.get(0).unwrap().end()..],
+            )
+        } else if let Some(m) = runtime_error.captures(output) {
+            (
+                ubsan_bug_type(&m[1]).to_string(),
+                None,
+                &output[m.get(0).unwrap().end()..],
+            )
+        } else {
+            return None;
+        };
+
+        let (access_kind, access_size) = match access.captures(rest) {
+            Some(m) => (
+                Some(if &m[1] == "READ" {
+                    SanitizerAccessKind::Read
+                } else {
+                    SanitizerAccessKind::Write
+                }),
+                m.get(2).and_then(|size| size.as_str().parse().ok()),
+            ),
+            None => (None, None),
+        };
+
+        let frame_captures: Vec<_> = rest
+            .lines()
+            .filter_map(|line| frame.captures(line))
+            .take(max_frames.max(1))
+            .collect();
+        // Prefer the pc on the error line, else use the address of the top frame
+        let pc = rest
+            .lines()
+            .next()
+            .and_then(|line| pc.captures(line))
+            .and_then(|m| parse_hex(&m[1]))
+            .or_else(|| frame_captures.first().and_then(|m| parse_hex(&m[1])));
+        let frames = frame_captures
+            .iter()
+            .filter_map(|m| m.get(2).or_else(|| m.get(3)))
+            .map(|m| m.as_str().to_string())
+            .take(max_frames)
+            .collect();
+
+        Some(Self {
+            bug_type,
+            address,
+            pc,
+            access_kind,
+            access_size,
+            frames,
+        })
+    }
+}
+
+/// Parses a `0x`-prefixed hex number
+fn parse_hex(hex: &str) -> Option<u64> {
+    u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok()
+}
+
+/// Maps the message of an `UndefinedBehaviorSanitizer` `runtime error` to a short bug type.
+fn ubsan_bug_type(message: &str) -> &'static str {
+    if message.contains("overflow") {
+        "integer-overflow"
+    } else if message.contains("shift") {
+        "invalid-shift"
+    } else if message.contains("division by zero") {
+        "division-by-zero"
+    } else if message.contains("null pointer") {
+        "null-deref"
+    } else if message.contains("misaligned") {
+        "misaligned-access"
+    } else if message.contains("out of bounds") {
+        "index-out-of-bounds"
+    } else if message.contains("not a valid value") {
+        "invalid-value"
+    } else {
+        "undefined-behavior"
+    }
+}
+
+/// Observers that can provide a parsed [`SanitizerReport`] for the last execution
+pub trait HasSanitizerReport {
+    /// The sanitizer report of the last execution, if any
+    fn sanitizer_report(&self) -> Option<SanitizerReport>;
+}
+
+impl HasSanitizerReport for StdErrObserver {
+    fn sanitizer_report(&self) -> Option<SanitizerReport> {
+        self.stderr.as_ref().and_then(|stderr| {
+            SanitizerReport::parse(
+                &String::from_utf8_lossy(stderr),
+                DEFAULT_SANITIZER_REPORT_FRAMES,
+            )
+        })
+    }
+}
+
+impl HasSanitizerReport for AsanBacktraceObserver {
+    fn sanitizer_report(&self) -> Option<SanitizerReport> {
+        self.report().cloned()
+    }
+}
+
+/// An observer parsing the sanitizer report of a crashing execution.
+///
+/// The report can be passed in directly using [`SanitizerReportObserver::parse_output`].
+/// If a log path is set with [`SanitizerReportObserver::with_log_path`] (matching the
+/// `log_path` sanitizer option), the observer reads the report from `<log path>.<pid>` of the
+/// current process after a crash, which works for in-process and fork executors.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SanitizerReportObserver {
+    name: Cow<'static, str>,
+    log_path: Option<PathBuf>,
+    max_frames: usize,
+    report: Option<SanitizerReport>,
+}
+
+impl SanitizerReportObserver {
+    /// Creates a new [`SanitizerReportObserver`] with the given name.
+    #[must_use]
+    pub fn new<S>(name: S) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        Self {
+            name: name.into(),
+            log_path: None,
+            max_frames: DEFAULT_SANITIZER_REPORT_FRAMES,
+            report: None,
+        }
+    }
+
+    /// Reads reports from the sanitizer log file `<log_path>.<pid>` after crashes.
+    #[must_use]
+    pub fn with_log_path<P>(mut self, log_path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.log_path = Some(log_path.into());
+        self
+    }
+
+    /// Sets the maximum number of stack frames kept in the report.
+    #[must_use]
+    pub fn with_max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    /// Parses the given sanitizer output, replacing the current report.
+    pub fn parse_output(&mut self, output: &str) {
+        self.report = SanitizerReport::parse(output, self.max_frames);
+    }
+
+    /// The report of the last execution, if any
+    #[must_use]
+    pub fn report(&self) -> Option<&SanitizerReport> {
+        self.report.as_ref()
+    }
+
+    fn parse_log_file(&mut self, exit_kind: ExitKind) -> Result<(), Error> {
+        if exit_kind != ExitKind::Crash {
+            return Ok(());
+        }
+        if let Some(log_path) = &self.log_path {
+            let mut log_file = log_path.clone().into_os_string();
+            log_file.push(format!(".{}", std::process::id()));
+            if let Ok(output) = fs::read_to_string(&log_file) {
+                fs::remove_file(&log_file)?;
+                self.parse_output(&output);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HasSanitizerReport for SanitizerReportObserver {
+    fn sanitizer_report(&self) -> Option<SanitizerReport> {
+        self.report.clone()
+    }
+}
+
+impl<S> Observer<S> for SanitizerReportObserver
+where
+    S: UsesInput,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.report = None;
+        Ok(())
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.parse_log_file(*exit_kind)
+    }
+
+    fn post_exec_child(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.parse_log_file(*exit_kind)
+    }
+}
+
+impl Named for SanitizerReportObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::{SanitizerAccessKind, SanitizerReport};
+
+    #[test]
+    fn test_parse_asan_report() {
+        let output = "==1234==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000014 at pc 0x4f3b2a bp 0x7ffd sp 0x7ffc
+READ of size 4 at 0x602000000014 thread T0
+    #0 0x4f3b2a in parse_header /src/parser.c:12:3
+    #1 0x4f3c10 in LLVMFuzzerTestOneInput /src/harness.c:8:5
+    #2 0x7f0000001000  (/lib/libc.so.6+0x29d90)
+";
+        let report = SanitizerReport::parse(output, 5).unwrap();
+        assert_eq!(report.bug_type, "heap-buffer-overflow");
+        assert_eq!(report.address, Some(0x6020_0000_0014));
+        assert_eq!(report.pc, Some(0x004f_3b2a));
+        assert_eq!(report.access_kind, Some(SanitizerAccessKind::Read));
+        assert_eq!(report.access_size, Some(4));
+        assert_eq!(
+            report.frames,
+            [
+                "parse_header",
+                "LLVMFuzzerTestOneInput",
+                "/lib/libc.so.6+0x29d90"
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "heap-buffer-overflow READ 4 in parse_header"
+        );
+    }
+
+    #[test]
+    fn test_parse_ubsan_report() {
+        let output = "src/parser.c:20:7: runtime error: signed integer overflow: 2147483647 + 1 cannot be represented in type 'int'\n";
+        let report = SanitizerReport::parse(output, 5).unwrap();
+        assert_eq!(report.bug_type, "integer-overflow");
+        assert_eq!(report.access_kind, None);
+        assert!(SanitizerReport::parse("all good", 5).is_none());
+    }
+}
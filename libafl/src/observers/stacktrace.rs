@@ -1,11 +1,14 @@
 //! the ``StacktraceObserver`` looks up the stacktrace on the execution thread and computes a hash for it for dedupe
 
-use alloc::{borrow::Cow, string::String, vec::Vec};
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
 #[cfg(feature = "casr")]
 use std::{
     collections::hash_map::DefaultHasher,
     hash::{Hash, Hasher},
-    string::ToString,
 };
 use std::{
     fmt::Debug,
@@ -16,7 +19,7 @@ use std::{
 };
 
 use backtrace::Backtrace;
-use libafl_bolts::{ownedref::OwnedRefMut, Named};
+use libafl_bolts::{hash_std, ownedref::OwnedRefMut, Named};
 #[allow(unused_imports)]
 #[cfg(feature = "casr")]
 use libcasr::{
@@ -39,30 +42,198 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use super::ObserverWithHashField;
-use crate::{executors::ExitKind, inputs::UsesInput, observers::Observer, Error};
+use crate::{
+    executors::ExitKind,
+    inputs::UsesInput,
+    observers::{Observer, SanitizerReport, DEFAULT_SANITIZER_REPORT_FRAMES},
+    Error,
+};
+
+/// Frames whose normalized symbol name matches one of these patterns are dropped before hashing.
+/// A pattern ending in `*` matches any name starting with the rest, other patterns match the whole
+/// name, so user functions like `free_list_push` are kept.
+///
+/// These are frames of the unwinder, the fuzzer itself, allocators, and sanitizer runtimes, which
+/// differ between otherwise identical crashes.
+pub const DEFAULT_IGNORED_FRAMES: &[&str] = &[
+    "backtrace::*",
+    "libafl::*",
+    "libafl_bolts::*",
+    "libafl_targets::*",
+    "std::panicking::*",
+    "std::panic::*",
+    "core::panicking::*",
+    "core::panic::*",
+    "rust_begin_unwind",
+    "rust_panic",
+    "__rust_*",
+    "__asan*",
+    "__sanitizer*",
+    "__interceptor_*",
+    "__ubsan*",
+    "__msan*",
+    "__tsan*",
+    "__lsan*",
+    "__GI_*",
+    "abort",
+    "raise",
+    "malloc",
+    "calloc",
+    "realloc",
+    "free",
+    "operator new",
+    "operator new[]",
+    "operator delete",
+    "operator delete[]",
+];
+
+/// Configures how backtraces are turned into hashes for deduplication.
+///
+/// The [`Default`] hashes raw addresses, like before symbolization was available, so existing
+/// hashes stay the same. Use [`BacktraceConfig::symbolized`] for hashes that survive ASLR and
+/// recompiles.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BacktraceConfig {
+    /// Resolve symbols and hash normalized function names instead of raw addresses.
+    /// Raw addresses are not stable across ASLR runs and recompiles.
+    pub symbolize: bool,
+    /// The maximum number of (non-ignored) frames to hash, starting from the innermost frame.
+    pub max_frames: usize,
+    /// Frames whose normalized name matches any of these patterns are ignored,
+    /// see [`DEFAULT_IGNORED_FRAMES`] for the syntax.
+    pub ignored_frames: Vec<String>,
+}
+
+impl Default for BacktraceConfig {
+    fn default() -> Self {
+        Self::unsymbolized()
+    }
+}
+
+impl BacktraceConfig {
+    /// A config hashing raw addresses of all frames, without symbolization.
+    #[must_use]
+    pub fn unsymbolized() -> Self {
+        Self {
+            symbolize: false,
+            max_frames: usize::MAX,
+            ignored_frames: vec![],
+        }
+    }
+
+    /// A config hashing the normalized names of the innermost 8 frames, ignoring the
+    /// [`DEFAULT_IGNORED_FRAMES`].
+    #[must_use]
+    pub fn symbolized() -> Self {
+        Self {
+            symbolize: true,
+            max_frames: 8,
+            ignored_frames: DEFAULT_IGNORED_FRAMES
+                .iter()
+                .map(|pattern| (*pattern).to_string())
+                .collect(),
+        }
+    }
+
+    /// Returns `true` if a frame with the given normalized name should not be hashed.
+    #[must_use]
+    pub fn is_ignored(&self, name: &str) -> bool {
+        self.ignored_frames
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    /// Hashes a list of symbol names, normalizing and filtering them according to this config.
+    #[must_use]
+    pub fn hash_symbols<'b, I>(&self, symbols: I) -> u64
+    where
+        I: IntoIterator<Item = &'b str>,
+    {
+        let mut normalized = String::new();
+        for name in symbols
+            .into_iter()
+            .map(normalize_symbol)
+            .filter(|name| !name.is_empty() && !self.is_ignored(name))
+            .take(self.max_frames)
+        {
+            normalized.push_str(&name);
+            normalized.push('\n');
+        }
+        hash_std(normalized.as_bytes())
+    }
+}
+
+/// Normalizes a (demangled) symbol name, so that it is stable across recompiles:
+/// the Rust symbol hash (`::h0123456789abcdef`), offsets (`+0x42`), and argument lists are removed.
+#[must_use]
+pub fn normalize_symbol(name: &str) -> String {
+    let mut name = name.trim();
+    // strip `+0x...` offsets
+    if let Some(idx) = name.find("+0x") {
+        name = &name[..idx];
+    }
+    // strip the rust symbol hash
+    if let Some(idx) = name.rfind("::h") {
+        let hash = &name[idx + 3..];
+        if hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            name = &name[..idx];
+        }
+    }
+    // strip C++ argument lists
+    if let Some(idx) = name.find('(') {
+        name = &name[..idx];
+    }
+    name.trim().to_string()
+}
 
 #[cfg(not(feature = "casr"))]
-/// Collects the backtrace via [`Backtrace`] and [`Debug`]
-/// ([`Debug`] is currently used for dev purposes, symbols hash will be used eventually)
+/// Collects the backtrace via [`Backtrace`], hashing raw addresses like the default [`BacktraceConfig`]
 #[must_use]
 pub fn collect_backtrace() -> u64 {
-    let b = Backtrace::new_unresolved();
+    collect_backtrace_with_config(&BacktraceConfig::default())
+}
+
+#[cfg(not(feature = "casr"))]
+/// Collects the backtrace via [`Backtrace`] and hashes it according to the given [`BacktraceConfig`]
+#[must_use]
+pub fn collect_backtrace_with_config(config: &BacktraceConfig) -> u64 {
+    let mut b = Backtrace::new_unresolved();
     if b.frames().is_empty() {
         return 0;
     }
-    let mut hash = 0;
-    for frame in &b.frames()[1..] {
-        hash ^= frame.ip() as u64;
-    }
-    // will use symbols later
-    // let trace = format!("{:?}", b);
-    // log::trace!("{}", trace);
-    // log::info!(
-    //     "backtrace collected with hash={} at pid={}",
-    //     hash,
-    //     std::process::id()
-    // );
-    hash
+    if !config.symbolize {
+        let mut hash = 0;
+        for frame in b.frames()[1..].iter().take(config.max_frames) {
+            hash ^= frame.ip() as u64;
+        }
+        return hash;
+    }
+    b.resolve();
+    let names: Vec<String> = b.frames()[1..]
+        .iter()
+        .map(|frame| {
+            frame
+                .symbols()
+                .first()
+                .and_then(backtrace::BacktraceSymbol::name)
+                .map_or_else(
+                    || {
+                        // Unresolved frame: fall back to the module-relative offset, which is
+                        // stable across ASLR runs (but not across recompiles).
+                        frame
+                            .module_base_address()
+                            .map_or_else(String::new, |base| {
+                                format!("{:#x}", frame.ip() as usize - base as usize)
+                            })
+                    },
+                    |name| name.to_string(),
+                )
+        })
+        .collect();
+    config.hash_symbols(names.iter().map(String::as_str))
 }
 
 #[cfg(feature = "casr")]
@@ -117,6 +288,8 @@ pub struct BacktraceObserver<'a> {
     observer_name: Cow<'static, str>,
     hash: OwnedRefMut<'a, Option<u64>>,
     harness_type: HarnessType,
+    #[serde(default)]
+    config: BacktraceConfig,
 }
 
 impl<'a> BacktraceObserver<'a> {
@@ -135,6 +308,7 @@ impl<'a> BacktraceObserver<'a> {
             observer_name: observer_name.into(),
             hash: backtrace_hash,
             harness_type,
+            config: BacktraceConfig::default(),
         }
     }
 
@@ -154,6 +328,7 @@ impl<'a> BacktraceObserver<'a> {
             observer_name: observer_name.into(),
             hash: backtrace_hash,
             harness_type,
+            config: BacktraceConfig::default(),
         }
     }
 
@@ -166,6 +341,33 @@ impl<'a> BacktraceObserver<'a> {
         Self::new(observer_name, OwnedRefMut::owned(None), harness_type)
     }
 
+    /// Sets the [`BacktraceConfig`] used to symbolize and normalize backtraces before hashing.
+    ///
+    /// This has no effect with the `casr` feature, which uses its own frame filters.
+    #[must_use]
+    pub fn with_config(mut self, config: BacktraceConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The [`BacktraceConfig`] of this observer
+    #[must_use]
+    pub fn config(&self) -> &BacktraceConfig {
+        &self.config
+    }
+
+    /// Collects and hashes the current backtrace
+    fn collect(&self) -> u64 {
+        #[cfg(not(feature = "casr"))]
+        {
+            collect_backtrace_with_config(&self.config)
+        }
+        #[cfg(feature = "casr")]
+        {
+            collect_backtrace()
+        }
+    }
+
     /// Updates the hash value of this observer.
     fn update_hash(&mut self, hash: u64) {
         *self.hash.as_mut() = Some(hash);
@@ -208,7 +410,7 @@ where
     ) -> Result<(), Error> {
         if self.harness_type == HarnessType::InProcess {
             if *exit_kind == ExitKind::Crash {
-                self.update_hash(collect_backtrace());
+                self.update_hash(self.collect());
             } else {
                 self.clear_hash();
             }
@@ -224,7 +426,7 @@ where
     ) -> Result<(), Error> {
         if self.harness_type == HarnessType::Child {
             if *exit_kind == ExitKind::Crash {
-                self.update_hash(collect_backtrace());
+                self.update_hash(self.collect());
             } else {
                 self.clear_hash();
             }
@@ -272,6 +474,10 @@ pub fn get_asan_runtime_flags() -> String {
 pub struct AsanBacktraceObserver {
     observer_name: Cow<'static, str>,
     hash: Option<u64>,
+    #[serde(default)]
+    config: BacktraceConfig,
+    #[serde(default)]
+    report: Option<SanitizerReport>,
 }
 
 impl AsanBacktraceObserver {
@@ -285,6 +491,8 @@ impl AsanBacktraceObserver {
         Self {
             observer_name: observer_name.into(),
             hash: None,
+            config: BacktraceConfig::default(),
+            report: None,
         }
     }
 
@@ -299,9 +507,26 @@ impl AsanBacktraceObserver {
         Self {
             observer_name: observer_name.into(),
             hash: None,
+            config: BacktraceConfig::default(),
+            report: None,
         }
     }
 
+    /// Sets the [`BacktraceConfig`] used to normalize ASAN stack frames before hashing.
+    ///
+    /// This has no effect with the `casr` feature, which uses its own frame filters.
+    #[must_use]
+    pub fn with_config(mut self, config: BacktraceConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// The structured ASAN report of the last parsed output, if any
+    #[must_use]
+    pub fn report(&self) -> Option<&SanitizerReport> {
+        self.report.as_ref()
+    }
+
     /// read ASAN output from the child stderr and parse it.
     pub fn parse_asan_output_from_childstderr(
         &mut self,
@@ -315,7 +540,16 @@ impl AsanBacktraceObserver {
 
     /// read ASAN output from the log file and parse it.
     pub fn parse_asan_output_from_asan_log_file(&mut self, pid: i32) -> Result<(), Error> {
-        let log_path = format!("{ASAN_LOG_PATH}.{pid}");
+        self.parse_asan_output_from_log_file(ASAN_LOG_PATH, pid)
+    }
+
+    /// read ASAN output from the log file of the child, written to `log_path.<pid>`, and parse it.
+    pub fn parse_asan_output_from_log_file(
+        &mut self,
+        log_path: &str,
+        pid: i32,
+    ) -> Result<(), Error> {
+        let log_path = format!("{log_path}.{pid}");
         let mut asan_output = File::open(Path::new(&log_path))?;
 
         let mut buf = String::new();
@@ -329,18 +563,38 @@ impl AsanBacktraceObserver {
     #[cfg(not(feature = "casr"))]
     /// parse ASAN error output emited by the target command and compute the hash
     pub fn parse_asan_output(&mut self, output: &str) {
-        let mut hash = 0;
-        let matcher = Regex::new("\\s*#[0-9]*\\s0x([0-9a-f]*)\\s.*").unwrap();
-        matcher.captures_iter(output).for_each(|m| {
-            let g = m.get(1).unwrap();
-            hash ^= u64::from_str_radix(g.as_str(), 16).unwrap();
-        });
+        self.report = SanitizerReport::parse(output, DEFAULT_SANITIZER_REPORT_FRAMES);
+        if !self.config.symbolize {
+            let mut hash = 0;
+            let matcher = Regex::new("\\s*#[0-9]*\\s0x([0-9a-f]*)\\s.*").unwrap();
+            matcher
+                .captures_iter(output)
+                .take(self.config.max_frames)
+                .for_each(|m| {
+                    let g = m.get(1).unwrap();
+                    hash ^= u64::from_str_radix(g.as_str(), 16).unwrap();
+                });
+            self.update_hash(hash);
+            return;
+        }
+        // ASAN already symbolizes the frames: `#0 0x4f3b2a in func file.c:12:3`
+        // or, if no symbol is available, `#0 0x4f3b2a (/path/to/module+0x1b2a)`.
+        let matcher =
+            Regex::new("\\s*#[0-9]+\\s0x[0-9a-f]+\\s(?:in\\s(.*?)\\s\\S+$|\\((.*)\\))").unwrap();
+        let names: Vec<&str> = output
+            .lines()
+            .filter_map(|line| matcher.captures(line))
+            .filter_map(|m| m.get(1).or_else(|| m.get(2)))
+            .map(|m| m.as_str())
+            .collect();
+        let hash = self.config.hash_symbols(names);
         self.update_hash(hash);
     }
 
     #[cfg(feature = "casr")]
     /// parse ASAN error output emited by the target command and compute the hash
     pub fn parse_asan_output(&mut self, output: &str) {
+        self.report = SanitizerReport::parse(output, DEFAULT_SANITIZER_REPORT_FRAMES);
         let mut hash = 0;
         if let Ok(st_vec) = AsanStacktrace::extract_stacktrace(output) {
             if let Ok(mut stacktrace) = AsanStacktrace::parse_stacktrace(&st_vec) {
@@ -396,3 +650,80 @@ impl Named for AsanBacktraceObserver {
         &self.observer_name
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_symbol, AsanBacktraceObserver, BacktraceConfig, ObserverWithHashField};
+
+    #[test]
+    fn test_normalize_symbol() {
+        assert_eq!(
+            normalize_symbol("my_crate::parse::h0123456789abcdef"),
+            "my_crate::parse"
+        );
+        assert_eq!(
+            normalize_symbol("parse_header(char const*, int)"),
+            "parse_header"
+        );
+        assert_eq!(
+            normalize_symbol("/usr/lib/libfoo.so+0x1b2a"),
+            "/usr/lib/libfoo.so"
+        );
+    }
+
+    #[test]
+    fn test_hash_symbols_ignores_runtime_frames() {
+        let config = BacktraceConfig::symbolized();
+        let with_runtime = config.hash_symbols([
+            "__asan_report_load4",
+            "malloc",
+            "parse_header(char const*)",
+            "main",
+        ]);
+        let without_runtime = config.hash_symbols(["parse_header", "main"]);
+        assert_eq!(with_runtime, without_runtime);
+        assert_ne!(with_runtime, config.hash_symbols(["parse_body", "main"]));
+    }
+
+    #[test]
+    fn test_ignored_frames_match_whole_names() {
+        let config = BacktraceConfig::symbolized();
+        assert!(config.is_ignored("free"));
+        assert!(config.is_ignored("operator delete[]"));
+        assert!(config.is_ignored("__asan_report_load4"));
+        assert!(config.is_ignored("std::panicking::begin_panic"));
+        // User functions sharing a prefix with runtime functions are kept
+        assert!(!config.is_ignored("free_list_push"));
+        assert!(!config.is_ignored("raise_error"));
+        assert!(!config.is_ignored("std_panicking_helper"));
+        assert_ne!(
+            config.hash_symbols(["free_list_push", "main"]),
+            config.hash_symbols(["main"])
+        );
+    }
+
+    #[test]
+    fn test_default_config_is_unsymbolized() {
+        // Hashes of existing campaigns must not change
+        assert_eq!(BacktraceConfig::default(), BacktraceConfig::unsymbolized());
+        assert!(!BacktraceConfig::default().symbolize);
+    }
+
+    #[test]
+    #[cfg(not(feature = "casr"))]
+    fn test_asan_backtrace_config() {
+        let output =
+            "    #0 0x4f3b2a in free_list_push list.c:12:3\n    #1 0x4f00f0 in main main.c:3:1\n";
+        let mut observer = AsanBacktraceObserver::new("asan");
+        observer.parse_asan_output(output);
+        assert_eq!(observer.hash(), Some(0x4f_3b2a ^ 0x4f_00f0));
+
+        let mut observer =
+            AsanBacktraceObserver::new("asan").with_config(BacktraceConfig::symbolized());
+        observer.parse_asan_output(output);
+        assert_eq!(
+            observer.hash(),
+            Some(BacktraceConfig::symbolized().hash_symbols(["free_list_push", "main"]))
+        );
+    }
+}
@@ -5,10 +5,15 @@
 use alloc::borrow::Cow;
 use std::vec::Vec;
 
-use libafl_bolts::Named;
+use libafl_bolts::{hash_std, Named};
 use serde::{Deserialize, Serialize};
 
-use crate::{inputs::UsesInput, observers::Observer, state::State, Error};
+use crate::{
+    inputs::UsesInput,
+    observers::{Observer, ObserverWithHashField},
+    state::State,
+    Error,
+};
 
 /// An observer that captures stdout of a target.
 /// Only works for supported executors.
@@ -196,6 +201,13 @@ impl Named for StdOutObserver {
     }
 }
 
+impl ObserverWithHashField for StdOutObserver {
+    /// The hash of the captured `stdout`, if any
+    fn hash(&self) -> Option<u64> {
+        self.stdout.as_deref().map(hash_std)
+    }
+}
+
 impl<S> Observer<S> for StdOutObserver
 where
     S: State,
@@ -250,6 +262,13 @@ impl Named for StdErrObserver {
     }
 }
 
+impl ObserverWithHashField for StdErrObserver {
+    /// The hash of the captured `stderr`, if any
+    fn hash(&self) -> Option<u64> {
+        self.stderr.as_deref().map(hash_std)
+    }
+}
+
 impl<S> Observer<S> for StdErrObserver
 where
     S: State,
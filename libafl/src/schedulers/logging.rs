@@ -0,0 +1,207 @@
+//! The [`LoggingScheduler`] logs each scheduling decision of another scheduler, e.g. to check that
+//! a campaign seeded with [`libafl_bolts::rands::derive_seed`] replays deterministically.
+
+use alloc::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{CorpusId, Testcase},
+    inputs::UsesInput,
+    observers::ObserversTuple,
+    schedulers::{RemovableScheduler, Scheduler},
+    state::{HasCorpus, HasExecutions, UsesState},
+    Error, HasMetadata,
+};
+
+/// The default number of scheduling decisions kept in the [`SchedulingLogMetadata`]
+pub const DEFAULT_SCHEDULING_LOG_LEN: usize = 1024;
+
+/// A scheduling decision, see [`SchedulingLogMetadata`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchedulingDecision {
+    /// The executions of the state when the testcase was scheduled
+    pub executions: u64,
+    /// The scheduled testcase
+    pub id: CorpusId,
+}
+
+/// The latest scheduling decisions of a [`LoggingScheduler`], oldest first
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct SchedulingLogMetadata {
+    /// The latest decisions
+    pub decisions: VecDeque<SchedulingDecision>,
+    /// The number of decisions in total
+    pub total: u64,
+}
+
+libafl_bolts::impl_serdeany!(SchedulingLogMetadata);
+
+/// Wraps a scheduler and logs each testcase it schedules, with the `log` crate at debug level and
+/// in the [`SchedulingLogMetadata`] of the state.
+#[derive(Debug, Clone)]
+pub struct LoggingScheduler<CS> {
+    base: CS,
+    max_len: usize,
+}
+
+impl<CS> LoggingScheduler<CS> {
+    /// Creates a new [`LoggingScheduler`] wrapping the base scheduler, keeping the latest
+    /// [`DEFAULT_SCHEDULING_LOG_LEN`] decisions
+    #[must_use]
+    pub fn new(base: CS) -> Self {
+        Self {
+            base,
+            max_len: DEFAULT_SCHEDULING_LOG_LEN,
+        }
+    }
+
+    /// Sets the number of decisions kept in the [`SchedulingLogMetadata`].
+    /// With `0`, decisions are only logged.
+    #[must_use]
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// The base scheduler
+    #[must_use]
+    pub fn base(&self) -> &CS {
+        &self.base
+    }
+}
+
+impl<CS> UsesState for LoggingScheduler<CS>
+where
+    CS: UsesState,
+{
+    type State = CS::State;
+}
+
+impl<CS> RemovableScheduler for LoggingScheduler<CS>
+where
+    CS: RemovableScheduler,
+    <Self as UsesState>::State: HasCorpus + HasMetadata + HasExecutions,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut <Self as UsesState>::State,
+        id: CorpusId,
+        testcase: &Option<Testcase<<<Self as UsesState>::State as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        self.base.on_remove(state, id, testcase)
+    }
+
+    fn on_replace(
+        &mut self,
+        state: &mut <Self as UsesState>::State,
+        id: CorpusId,
+        prev: &Testcase<<<Self as UsesState>::State as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        self.base.on_replace(state, id, prev)
+    }
+}
+
+impl<CS> Scheduler for LoggingScheduler<CS>
+where
+    CS: Scheduler,
+    Self::State: HasCorpus + HasMetadata + HasExecutions,
+{
+    fn on_add(&mut self, state: &mut Self::State, id: CorpusId) -> Result<(), Error> {
+        self.base.on_add(state, id)
+    }
+
+    fn on_evaluation<OT>(
+        &mut self,
+        state: &mut Self::State,
+        input: &<Self::State as UsesInput>::Input,
+        observers: &OT,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<Self::State>,
+    {
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut Self::State) -> Result<CorpusId, Error> {
+        let id = self.base.next(state)?;
+        let decision = SchedulingDecision {
+            executions: *state.executions(),
+            id,
+        };
+        let meta = state.metadata_or_insert_with(SchedulingLogMetadata::default);
+        meta.total += 1;
+        log::debug!(
+            "Scheduling decision {}: testcase {id} after {} executions",
+            meta.total,
+            decision.executions
+        );
+        if self.max_len > 0 {
+            if meta.decisions.len() >= self.max_len {
+                meta.decisions.pop_front();
+            }
+            meta.decisions.push_back(decision);
+        }
+        Ok(id)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut Self::State,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.base.set_current_scheduled(state, next_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{LoggingScheduler, SchedulingLogMetadata};
+    use crate::{
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        schedulers::{QueueScheduler, Scheduler},
+        state::{HasCorpus, StdState},
+        HasMetadata,
+    };
+
+    #[test]
+    fn test_logging_scheduler() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut scheduler = LoggingScheduler::new(QueueScheduler::new()).with_max_len(2);
+        for byte in 0..2 {
+            let id = state
+                .corpus_mut()
+                .add(Testcase::new(BytesInput::new(vec![byte])))
+                .unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+        }
+
+        let ids = (0..3)
+            .map(|_| scheduler.next(&mut state).unwrap())
+            .collect::<Vec<_>>();
+        let meta = state.metadata::<SchedulingLogMetadata>().unwrap();
+        assert_eq!(meta.total, 3);
+        assert_eq!(meta.decisions.len(), 2);
+        assert_eq!(meta.decisions[0].id, ids[1]);
+        assert_eq!(meta.decisions[1].id, ids[2]);
+    }
+}
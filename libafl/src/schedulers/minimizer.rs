@@ -5,7 +5,11 @@ use alloc::vec::Vec;
 use core::{any::type_name, cmp::Ordering, marker::PhantomData};
 
 use hashbrown::{HashMap, HashSet};
-use libafl_bolts::{rands::Rand, serdeany::SerdeAny, AsIter, HasRefCnt};
+use libafl_bolts::{
+    rands::Rand,
+    serdeany::{SerdeAny, VersionedSerdeAny},
+    AsIter, HasRefCnt,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -43,7 +47,11 @@ pub struct TopRatedsMetadata {
     pub map: HashMap<usize, CorpusId>,
 }
 
-libafl_bolts::impl_serdeany!(TopRatedsMetadata);
+libafl_bolts::impl_serdeany_versioned!(TopRatedsMetadata);
+
+impl VersionedSerdeAny for TopRatedsMetadata {
+    const SCHEMA_VERSION: u32 = 1;
+}
 
 impl TopRatedsMetadata {
     /// Creates a new [`struct@TopRatedsMetadata`]
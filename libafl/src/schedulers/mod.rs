@@ -17,6 +17,9 @@ pub use minimizer::{
 pub mod powersched;
 pub use powersched::{PowerQueueScheduler, SchedulerMetadata};
 
+pub mod pareto;
+pub use pareto::{ParetoMetadata, ParetoScheduler};
+
 pub mod probabilistic_sampling;
 pub use probabilistic_sampling::ProbabilitySamplingScheduler;
 
@@ -26,6 +29,23 @@ pub use accounting::CoverageAccountingScheduler;
 pub mod weighted;
 pub use weighted::{StdWeightedScheduler, WeightedScheduler};
 
+pub mod rarity;
+pub use rarity::{
+    EdgeFrequencyHook, EdgeFrequencyMetadata, RareEdgeScheduler, RareEdgeTestcaseScore,
+};
+
+pub mod origin;
+pub use origin::{InputOriginStatsMetadata, OriginQuotaScheduler};
+
+pub mod logging;
+pub use logging::{LoggingScheduler, SchedulingDecision, SchedulingLogMetadata};
+
+pub mod namespace;
+pub use namespace::{
+    CorpusNamespace, CorpusNamespaceMetadata, MagicBytesNamespaceExtractor, NamespaceExtractor,
+    NamespaceScheduler, NamespaceStats, NamespaceStatsMetadata,
+};
+
 pub mod tuneable;
 use libafl_bolts::{
     rands::Rand,
@@ -101,6 +121,11 @@ where
 
         // Attach a `SchedulerTestcaseMetadata` to the queue entry.
         depth += 1;
+        if let Some(psmeta) = state.metadata_map_mut().get_mut::<SchedulerMetadata>() {
+            if depth > psmeta.max_depth() {
+                psmeta.set_max_depth(depth);
+            }
+        }
         let mut testcase = state.testcase_mut(id)?;
         testcase.add_metadata(SchedulerTestcaseMetadata::with_n_fuzz_entry(
             depth,
@@ -131,7 +156,7 @@ where
 
         hash %= psmeta.n_fuzz().len();
         // Update the path frequency
-        psmeta.n_fuzz_mut()[hash] = psmeta.n_fuzz()[hash].saturating_add(1);
+        psmeta.record_n_fuzz(hash);
 
         self.set_last_hash(hash);
 
@@ -0,0 +1,582 @@
+//! The [`NamespaceScheduler`] splits the corpus into hierarchical namespaces, such as the file
+//! format of an input or the API entry point it targets, and balances the executions between
+//! them, so that a multi-format target does not collapse onto its easiest format.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use hashbrown::HashMap;
+use libafl_bolts::{rands::Rand, AsSlice};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId, Testcase},
+    inputs::{HasTargetBytes, Input, UsesInput},
+    observers::ObserversTuple,
+    schedulers::{RemovableScheduler, Scheduler},
+    state::{HasCorpus, HasExecutions, HasRand, UsesState},
+    Error, HasMetadata,
+};
+
+/// A hierarchical corpus namespace, with `/` separated segments, like `image/png`.
+/// The empty namespace is the root, containing all others.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CorpusNamespace(String);
+
+impl CorpusNamespace {
+    /// Creates a new namespace, ignoring empty segments
+    #[must_use]
+    pub fn new(path: &str) -> Self {
+        Self(
+            path.split('/')
+                .filter(|segment| !segment.is_empty())
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+
+    /// The root namespace
+    #[must_use]
+    pub fn root() -> Self {
+        Self(String::new())
+    }
+
+    /// The segments of this namespace, from the outermost
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split('/').filter(|segment| !segment.is_empty())
+    }
+
+    /// The number of segments, 0 for the root
+    #[must_use]
+    pub fn depth(&self) -> usize {
+        self.segments().count()
+    }
+
+    /// The enclosing namespace, or `None` for the root
+    #[must_use]
+    pub fn parent(&self) -> Option<Self> {
+        if self.0.is_empty() {
+            None
+        } else {
+            Some(Self(
+                self.0
+                    .rsplit_once('/')
+                    .map_or_else(String::new, |(parent, _)| parent.to_string()),
+            ))
+        }
+    }
+
+    /// The enclosing namespace with the given depth, or this one if it is not deeper
+    #[must_use]
+    pub fn ancestor(&self, depth: usize) -> Self {
+        Self(self.segments().take(depth).collect::<Vec<_>>().join("/"))
+    }
+
+    /// If this namespace is `other` or nested inside of it
+    #[must_use]
+    pub fn is_within(&self, other: &Self) -> bool {
+        other.0.is_empty()
+            || self.0 == other.0
+            || (self.0.starts_with(&other.0) && self.0.as_bytes()[other.0.len()] == b'/')
+    }
+
+    /// The namespace as string
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for CorpusNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            f.write_str("/")
+        } else {
+            f.write_str(&self.0)
+        }
+    }
+}
+
+impl From<&str> for CorpusNamespace {
+    fn from(path: &str) -> Self {
+        Self::new(path)
+    }
+}
+
+/// The namespace of a testcase, added by the [`NamespaceScheduler`] when it is added to the corpus.
+/// If it is already present, for example set by a feedback, it is kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct CorpusNamespaceMetadata {
+    /// The namespace of the testcase
+    pub namespace: CorpusNamespace,
+}
+
+libafl_bolts::impl_serdeany!(CorpusNamespaceMetadata);
+
+/// Statistics of a single namespace
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NamespaceStats {
+    /// The number of corpus entries in the namespace
+    pub entries: u64,
+    /// The number of times a testcase of the namespace was scheduled
+    pub scheduled: u64,
+    /// The executions spent fuzzing testcases of the namespace
+    pub executions: u64,
+    /// The corpus entries in the namespace
+    ids: Vec<CorpusId>,
+}
+
+/// Per-namespace statistics of the [`NamespaceScheduler`]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct NamespaceStatsMetadata {
+    /// The statistics of each namespace with testcases directly in it
+    pub namespaces: HashMap<CorpusNamespace, NamespaceStats>,
+    /// The namespace of the testcase scheduled last, and the executions at that time
+    last: Option<(CorpusNamespace, u64)>,
+}
+
+libafl_bolts::impl_serdeany!(NamespaceStatsMetadata);
+
+impl NamespaceStatsMetadata {
+    /// The number of corpus entries in the namespace, including nested namespaces
+    #[must_use]
+    pub fn entries_within(&self, namespace: &CorpusNamespace) -> u64 {
+        self.namespaces
+            .iter()
+            .filter(|(ns, _)| ns.is_within(namespace))
+            .map(|(_, stats)| stats.entries)
+            .sum()
+    }
+
+    /// The executions spent on the namespace, including nested namespaces
+    #[must_use]
+    pub fn executions_within(&self, namespace: &CorpusNamespace) -> u64 {
+        self.namespaces
+            .iter()
+            .filter(|(ns, _)| ns.is_within(namespace))
+            .map(|(_, stats)| stats.executions)
+            .sum()
+    }
+
+    fn add(&mut self, namespace: CorpusNamespace, id: CorpusId) {
+        let stats = self.namespaces.entry(namespace).or_default();
+        stats.entries += 1;
+        stats.ids.push(id);
+    }
+
+    fn remove(&mut self, namespace: &CorpusNamespace, id: CorpusId) {
+        if let Some(stats) = self.namespaces.get_mut(namespace) {
+            stats.entries = stats.entries.saturating_sub(1);
+            stats.ids.retain(|other| *other != id);
+        }
+    }
+}
+
+/// Extracts the [`CorpusNamespace`] of a new testcase, for example by sniffing the file format of
+/// the input or reading coverage metadata of the testcase.
+///
+/// It is implemented for closures taking the testcase and its loaded input.
+pub trait NamespaceExtractor<I>
+where
+    I: Input,
+{
+    /// The namespace of the testcase
+    fn namespace(&mut self, testcase: &Testcase<I>, input: &I) -> CorpusNamespace;
+}
+
+impl<F, I> NamespaceExtractor<I> for F
+where
+    F: FnMut(&Testcase<I>, &I) -> CorpusNamespace,
+    I: Input,
+{
+    fn namespace(&mut self, testcase: &Testcase<I>, input: &I) -> CorpusNamespace {
+        self(testcase, input)
+    }
+}
+
+/// A [`NamespaceExtractor`] sniffing the file format from magic bytes at the start of the input
+#[derive(Debug, Clone)]
+pub struct MagicBytesNamespaceExtractor {
+    magics: Vec<(Vec<u8>, CorpusNamespace)>,
+    fallback: CorpusNamespace,
+}
+
+impl MagicBytesNamespaceExtractor {
+    /// Creates a new [`MagicBytesNamespaceExtractor`], putting inputs without known magic bytes
+    /// into the `fallback` namespace
+    #[must_use]
+    pub fn new(fallback: CorpusNamespace) -> Self {
+        Self {
+            magics: Vec::new(),
+            fallback,
+        }
+    }
+
+    /// Puts inputs starting with `magic` into the namespace. The first matching magic wins.
+    #[must_use]
+    pub fn with_magic(mut self, magic: &[u8], namespace: CorpusNamespace) -> Self {
+        self.magics.push((magic.to_vec(), namespace));
+        self
+    }
+}
+
+impl<I> NamespaceExtractor<I> for MagicBytesNamespaceExtractor
+where
+    I: Input + HasTargetBytes,
+{
+    fn namespace(&mut self, _testcase: &Testcase<I>, input: &I) -> CorpusNamespace {
+        let bytes = input.target_bytes();
+        let bytes = bytes.as_slice();
+        self.magics
+            .iter()
+            .find(|(magic, _)| bytes.starts_with(magic))
+            .map_or_else(|| self.fallback.clone(), |(_, ns)| ns.clone())
+    }
+}
+
+/// Wraps a scheduler and balances the executions between the [`CorpusNamespace`]s of the corpus,
+/// according to configurable weights.
+///
+/// For each pick, the scheduler descends the namespace hierarchy from the root, choosing the
+/// child furthest below its weighted share of the executions spent in the parent. The base
+/// scheduler is then asked for a testcase once; if it is not in that namespace, a random testcase
+/// of the namespace is taken instead. Namespaces without corpus entries are skipped.
+/// The statistics are kept in the [`NamespaceStatsMetadata`] of the state.
+#[derive(Debug, Clone)]
+pub struct NamespaceScheduler<CS, E> {
+    base: CS,
+    extractor: E,
+    weights: HashMap<CorpusNamespace, f64>,
+}
+
+impl<CS, E> UsesState for NamespaceScheduler<CS, E>
+where
+    CS: UsesState,
+{
+    type State = CS::State;
+}
+
+impl<CS, E> RemovableScheduler for NamespaceScheduler<CS, E>
+where
+    CS: RemovableScheduler,
+    E: NamespaceExtractor<<<Self as UsesState>::State as UsesInput>::Input>,
+    <Self as UsesState>::State: HasCorpus + HasMetadata + HasExecutions + HasRand,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut <Self as UsesState>::State,
+        id: CorpusId,
+        testcase: &Option<Testcase<<<Self as UsesState>::State as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        self.base.on_remove(state, id, testcase)?;
+        if let Some(namespace) = testcase.as_ref().and_then(Self::testcase_namespace) {
+            state
+                .metadata_or_insert_with(NamespaceStatsMetadata::default)
+                .remove(&namespace, id);
+        }
+        Ok(())
+    }
+
+    fn on_replace(
+        &mut self,
+        state: &mut <Self as UsesState>::State,
+        id: CorpusId,
+        prev: &Testcase<<<Self as UsesState>::State as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        self.base.on_replace(state, id, prev)?;
+        let namespace = self.assign_namespace(state, id)?;
+        let meta = state.metadata_or_insert_with(NamespaceStatsMetadata::default);
+        if let Some(prev_namespace) = Self::testcase_namespace(prev) {
+            meta.remove(&prev_namespace, id);
+        }
+        meta.add(namespace, id);
+        Ok(())
+    }
+}
+
+impl<CS, E> Scheduler for NamespaceScheduler<CS, E>
+where
+    CS: Scheduler,
+    E: NamespaceExtractor<<Self::State as UsesInput>::Input>,
+    Self::State: HasCorpus + HasMetadata + HasExecutions + HasRand,
+{
+    fn on_add(&mut self, state: &mut Self::State, id: CorpusId) -> Result<(), Error> {
+        self.base.on_add(state, id)?;
+        let namespace = self.assign_namespace(state, id)?;
+        state
+            .metadata_or_insert_with(NamespaceStatsMetadata::default)
+            .add(namespace, id);
+        Ok(())
+    }
+
+    fn on_evaluation<OT>(
+        &mut self,
+        state: &mut Self::State,
+        input: &<Self::State as UsesInput>::Input,
+        observers: &OT,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<Self::State>,
+    {
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut Self::State) -> Result<CorpusId, Error> {
+        let executions = *state.executions();
+        let meta = state.metadata_or_insert_with(NamespaceStatsMetadata::default);
+        if let Some((namespace, last_executions)) = meta.last.take() {
+            meta.namespaces.entry(namespace).or_default().executions +=
+                executions.saturating_sub(last_executions);
+        }
+        let wanted = self.wanted_namespace(meta);
+
+        // Ask the base scheduler only once, as each pick updates its state
+        let mut id = self.base.next(state)?;
+        let mut namespace = Self::namespace(state, id)?;
+        if let Some(wanted) = wanted {
+            if namespace != wanted {
+                let candidates = state
+                    .metadata::<NamespaceStatsMetadata>()?
+                    .namespaces
+                    .get(&wanted)
+                    .map(|stats| stats.ids.clone())
+                    .unwrap_or_default();
+                if !candidates.is_empty() {
+                    id = candidates[state.rand_mut().below(candidates.len())];
+                    namespace = wanted;
+                    self.set_current_scheduled(state, Some(id))?;
+                }
+            }
+        }
+
+        let meta = state.metadata_or_insert_with(NamespaceStatsMetadata::default);
+        meta.namespaces
+            .entry(namespace.clone())
+            .or_default()
+            .scheduled += 1;
+        meta.last = Some((namespace, executions));
+        Ok(id)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut Self::State,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.base.set_current_scheduled(state, next_id)
+    }
+}
+
+impl<CS, E> NamespaceScheduler<CS, E> {
+    /// Creates a new [`NamespaceScheduler`] wrapping the base scheduler, assigning namespaces to
+    /// new testcases with the extractor. All namespaces have the weight 1 by default.
+    #[must_use]
+    pub fn new(base: CS, extractor: E) -> Self {
+        Self {
+            base,
+            extractor,
+            weights: HashMap::new(),
+        }
+    }
+
+    /// Sets the weight of a namespace, relative to its siblings in the hierarchy.
+    /// A weight of 0 stops the namespace, and everything nested inside, from being picked.
+    #[must_use]
+    pub fn with_weight(mut self, namespace: CorpusNamespace, weight: f64) -> Self {
+        self.weights.insert(namespace, weight.max(0.0));
+        self
+    }
+
+    /// The weight of a namespace relative to its siblings
+    #[must_use]
+    pub fn weight(&self, namespace: &CorpusNamespace) -> f64 {
+        self.weights.get(namespace).copied().unwrap_or(1.0)
+    }
+
+    /// The base scheduler
+    #[must_use]
+    pub fn base(&self) -> &CS {
+        &self.base
+    }
+
+    /// Descends from the root to the namespace furthest below its weighted share
+    fn wanted_namespace(&self, meta: &NamespaceStatsMetadata) -> Option<CorpusNamespace> {
+        let mut current = CorpusNamespace::root();
+        loop {
+            let depth = current.depth();
+            // Children of the current namespace with entries, and the namespace itself if it
+            // directly holds testcases
+            let mut candidates: Vec<CorpusNamespace> = meta
+                .namespaces
+                .iter()
+                .filter(|(ns, stats)| stats.entries > 0 && ns.is_within(&current))
+                .map(|(ns, _)| ns.ancestor(depth + 1))
+                .filter(|ns| self.weight(ns) > 0.0)
+                .collect();
+            candidates.sort();
+            candidates.dedup();
+
+            let total_weight: f64 = candidates.iter().map(|ns| self.weight(ns)).sum();
+            let total_executions = meta.executions_within(&current);
+            let executions = |ns: &CorpusNamespace| {
+                if *ns == current {
+                    meta.namespaces.get(ns).map_or(0, |stats| stats.executions)
+                } else {
+                    meta.executions_within(ns)
+                }
+            };
+            let next = candidates
+                .into_iter()
+                .map(|ns| {
+                    #[allow(clippy::cast_precision_loss)]
+                    let share = if total_executions == 0 {
+                        0.0
+                    } else {
+                        executions(&ns) as f64 / total_executions as f64
+                    };
+                    let deficit = self.weight(&ns) / total_weight - share;
+                    (ns, deficit)
+                })
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(ns, _)| ns)?;
+            if next == current {
+                return Some(next);
+            }
+            current = next;
+        }
+    }
+
+    /// The namespace stored in the testcase metadata, if any
+    fn testcase_namespace<I>(testcase: &Testcase<I>) -> Option<CorpusNamespace>
+    where
+        I: Input,
+    {
+        testcase
+            .metadata_map()
+            .get::<CorpusNamespaceMetadata>()
+            .map(|meta| meta.namespace.clone())
+    }
+
+    /// The namespace of the corpus entry, the root if unknown
+    fn namespace<S>(state: &S, id: CorpusId) -> Result<CorpusNamespace, Error>
+    where
+        S: HasCorpus,
+    {
+        Ok(Self::testcase_namespace(&state.corpus().get(id)?.borrow()).unwrap_or_default())
+    }
+
+    /// Reads the namespace of the corpus entry, extracting and storing it if not yet present
+    fn assign_namespace<S>(&mut self, state: &S, id: CorpusId) -> Result<CorpusNamespace, Error>
+    where
+        S: HasCorpus,
+        E: NamespaceExtractor<<S as UsesInput>::Input>,
+    {
+        let mut testcase = state.corpus().get(id)?.borrow_mut();
+        if let Some(namespace) = Self::testcase_namespace(&testcase) {
+            return Ok(namespace);
+        }
+        let input = testcase.load_input(state.corpus())?.clone();
+        let namespace = self.extractor.namespace(&testcase, &input);
+        testcase.add_metadata(CorpusNamespaceMetadata {
+            namespace: namespace.clone(),
+        });
+        Ok(namespace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::rands::StdRand;
+
+    use super::{
+        CorpusNamespace, MagicBytesNamespaceExtractor, NamespaceScheduler, NamespaceStatsMetadata,
+    };
+    use crate::{
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        schedulers::{QueueScheduler, Scheduler},
+        state::{HasCorpus, HasExecutions, StdState},
+        HasMetadata,
+    };
+
+    #[test]
+    fn test_namespace_hierarchy() {
+        let ns = CorpusNamespace::new("/image//png/");
+        assert_eq!(ns.as_str(), "image/png");
+        assert_eq!(ns.depth(), 2);
+        assert_eq!(ns.parent(), Some(CorpusNamespace::new("image")));
+        assert_eq!(ns.ancestor(1), CorpusNamespace::new("image"));
+        assert!(ns.is_within(&CorpusNamespace::new("image")));
+        assert!(ns.is_within(&CorpusNamespace::root()));
+        assert!(!CorpusNamespace::new("imagery").is_within(&CorpusNamespace::new("image")));
+        assert_eq!(CorpusNamespace::root().parent(), None);
+    }
+
+    #[test]
+    #[allow(clippy::cast_precision_loss)]
+    fn test_namespace_weights() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let extractor = MagicBytesNamespaceExtractor::new("other".into())
+            .with_magic(b"\x89PNG", "image/png".into())
+            .with_magic(b"\xff\xd8", "image/jpeg".into())
+            .with_magic(b"GIF8", "image/gif".into());
+        let mut scheduler = NamespaceScheduler::new(QueueScheduler::new(), extractor)
+            .with_weight("image".into(), 3.0);
+
+        // Most testcases are PNGs, which would get most of the executions otherwise
+        for input in [
+            &b"\x89PNG1"[..],
+            b"\x89PNG2",
+            b"\x89PNG3",
+            b"\x89PNG4",
+            b"\x89PNG5",
+            b"\xff\xd8",
+            b"GIF89a",
+            b"text",
+        ] {
+            let id = state
+                .corpus_mut()
+                .add(Testcase::new(BytesInput::new(input.to_vec())))
+                .unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+        }
+
+        for _ in 0..400 {
+            scheduler.next(&mut state).unwrap();
+            *state.executions_mut() += 10;
+        }
+        let meta = state.metadata::<NamespaceStatsMetadata>().unwrap();
+        assert_eq!(
+            meta.namespaces[&CorpusNamespace::new("image/png")].entries,
+            5
+        );
+        let total = meta.executions_within(&CorpusNamespace::root()) as f64;
+        let image = meta.executions_within(&"image".into()) as f64 / total;
+        assert!((0.7..=0.8).contains(&image), "image share {image}");
+        for format in ["image/png", "image/jpeg", "image/gif"] {
+            let share = meta.executions_within(&format.into()) as f64 / total;
+            assert!((0.2..=0.3).contains(&share), "{format} share {share}");
+        }
+    }
+}
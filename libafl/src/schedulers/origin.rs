@@ -0,0 +1,350 @@
+//! The [`OriginQuotaScheduler`] splits the executions between locally found, imported and
+//! generated testcases, so that heavy import traffic does not drown out a client's own
+//! exploration.
+
+use alloc::vec::Vec;
+
+use libafl_bolts::rands::Rand;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId, InputOrigin, InputOriginMetadata, Testcase},
+    inputs::{Input, UsesInput},
+    observers::ObserversTuple,
+    schedulers::{RemovableScheduler, Scheduler},
+    state::{HasCorpus, HasExecutions, HasRand, UsesState},
+    Error, HasMetadata,
+};
+
+/// The default share of executions spent on locally found testcases
+pub const DEFAULT_LOCAL_QUOTA: f64 = 0.5;
+/// The default share of executions spent on imported testcases
+pub const DEFAULT_IMPORTED_QUOTA: f64 = 0.3;
+/// The default share of executions spent on generated testcases
+pub const DEFAULT_GENERATED_QUOTA: f64 = 0.2;
+
+/// Per-origin statistics of the [`OriginQuotaScheduler`], indexed by [`InputOrigin::index`]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct InputOriginStatsMetadata {
+    /// The number of corpus entries of each origin
+    pub entries: [u64; 3],
+    /// The number of times a testcase of each origin was scheduled
+    pub scheduled: [u64; 3],
+    /// The executions spent fuzzing testcases of each origin
+    pub executions: [u64; 3],
+    /// The origin of the testcase scheduled last, and the executions at that time
+    last: Option<(InputOrigin, u64)>,
+    /// The corpus entries of each origin
+    ids: [Vec<CorpusId>; 3],
+}
+
+libafl_bolts::impl_serdeany!(InputOriginStatsMetadata);
+
+impl InputOriginStatsMetadata {
+    /// The share of executions spent on testcases of the origin, between 0 and 1
+    #[must_use]
+    pub fn execution_share(&self, origin: InputOrigin) -> f64 {
+        let total: u64 = self.executions.iter().sum();
+        if total == 0 {
+            0.0
+        } else {
+            #[allow(clippy::cast_precision_loss)]
+            let share = self.executions[origin.index()] as f64 / total as f64;
+            share
+        }
+    }
+}
+
+/// Wraps a scheduler and spends configurable shares of the executions on locally found, imported
+/// and generated testcases, see [`InputOrigin`].
+///
+/// For each pick, the origin furthest below its quota is chosen. The base scheduler is then asked
+/// for a testcase once; if it is not of that origin, a random testcase of the origin is taken
+/// instead. Origins without corpus entries are skipped.
+/// The statistics are kept in the [`InputOriginStatsMetadata`] of the state.
+///
+/// The origins are read from the [`InputOriginMetadata`] of the testcases, which the fuzzer only
+/// records if enabled with [`crate::StdFuzzer::with_input_origins`]. Testcases without it count as
+/// locally found.
+#[derive(Debug, Clone)]
+pub struct OriginQuotaScheduler<CS> {
+    base: CS,
+    quotas: [f64; 3],
+}
+
+impl<CS> UsesState for OriginQuotaScheduler<CS>
+where
+    CS: UsesState,
+{
+    type State = CS::State;
+}
+
+impl<CS> RemovableScheduler for OriginQuotaScheduler<CS>
+where
+    CS: RemovableScheduler,
+    <Self as UsesState>::State: HasCorpus + HasMetadata + HasExecutions + HasRand,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut <Self as UsesState>::State,
+        id: CorpusId,
+        testcase: &Option<Testcase<<<Self as UsesState>::State as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        self.base.on_remove(state, id, testcase)?;
+        if let Some(testcase) = testcase {
+            let origin = Self::testcase_origin(testcase);
+            let meta = state.metadata_or_insert_with(InputOriginStatsMetadata::default);
+            meta.entries[origin.index()] = meta.entries[origin.index()].saturating_sub(1);
+            meta.ids[origin.index()].retain(|entry| *entry != id);
+        }
+        Ok(())
+    }
+
+    fn on_replace(
+        &mut self,
+        state: &mut <Self as UsesState>::State,
+        id: CorpusId,
+        prev: &Testcase<<<Self as UsesState>::State as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        self.base.on_replace(state, id, prev)?;
+        let prev_origin = Self::testcase_origin(prev);
+        let origin = Self::origin(state, id)?;
+        let meta = state.metadata_or_insert_with(InputOriginStatsMetadata::default);
+        meta.entries[prev_origin.index()] = meta.entries[prev_origin.index()].saturating_sub(1);
+        meta.ids[prev_origin.index()].retain(|entry| *entry != id);
+        meta.entries[origin.index()] += 1;
+        meta.ids[origin.index()].push(id);
+        Ok(())
+    }
+}
+
+impl<CS> Scheduler for OriginQuotaScheduler<CS>
+where
+    CS: Scheduler,
+    Self::State: HasCorpus + HasMetadata + HasExecutions + HasRand,
+{
+    fn on_add(&mut self, state: &mut Self::State, id: CorpusId) -> Result<(), Error> {
+        self.base.on_add(state, id)?;
+        let origin = Self::origin(state, id)?;
+        let meta = state.metadata_or_insert_with(InputOriginStatsMetadata::default);
+        meta.entries[origin.index()] += 1;
+        meta.ids[origin.index()].push(id);
+        Ok(())
+    }
+
+    fn on_evaluation<OT>(
+        &mut self,
+        state: &mut Self::State,
+        input: &<Self::State as UsesInput>::Input,
+        observers: &OT,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<Self::State>,
+    {
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut Self::State) -> Result<CorpusId, Error> {
+        let executions = *state.executions();
+        let meta = state.metadata_or_insert_with(InputOriginStatsMetadata::default);
+        if let Some((origin, last_executions)) = meta.last {
+            meta.executions[origin.index()] += executions.saturating_sub(last_executions);
+        }
+        let wanted = self.wanted_origin(meta);
+
+        // Ask the base scheduler only once, as each pick updates its state
+        let mut id = self.base.next(state)?;
+        let mut origin = Self::origin(state, id)?;
+        if let Some(wanted) = wanted {
+            if origin != wanted {
+                let candidates =
+                    state.metadata::<InputOriginStatsMetadata>()?.ids[wanted.index()].clone();
+                if !candidates.is_empty() {
+                    id = candidates[state.rand_mut().below(candidates.len())];
+                    origin = wanted;
+                    self.set_current_scheduled(state, Some(id))?;
+                }
+            }
+        }
+
+        let meta = state.metadata_or_insert_with(InputOriginStatsMetadata::default);
+        meta.scheduled[origin.index()] += 1;
+        meta.last = Some((origin, executions));
+        Ok(id)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut Self::State,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.base.set_current_scheduled(state, next_id)
+    }
+}
+
+impl<CS> OriginQuotaScheduler<CS> {
+    /// Creates a new [`OriginQuotaScheduler`] wrapping the base scheduler, with the default quotas
+    #[must_use]
+    pub fn new(base: CS) -> Self {
+        let mut quotas = [0.0; 3];
+        quotas[InputOrigin::Local.index()] = DEFAULT_LOCAL_QUOTA;
+        quotas[InputOrigin::Imported.index()] = DEFAULT_IMPORTED_QUOTA;
+        quotas[InputOrigin::Generated.index()] = DEFAULT_GENERATED_QUOTA;
+        Self { base, quotas }
+    }
+
+    /// Sets the relative share of executions spent on testcases of the origin.
+    /// The quotas don't need to sum up to one, they are normalized.
+    #[must_use]
+    pub fn with_quota(mut self, origin: InputOrigin, quota: f64) -> Self {
+        self.quotas[origin.index()] = quota.max(0.0);
+        self
+    }
+
+    /// The normalized share of executions for the origin
+    #[must_use]
+    pub fn quota(&self, origin: InputOrigin) -> f64 {
+        let total: f64 = self.quotas.iter().sum();
+        if total <= 0.0 {
+            0.0
+        } else {
+            self.quotas[origin.index()] / total
+        }
+    }
+
+    /// The base scheduler
+    #[must_use]
+    pub fn base(&self) -> &CS {
+        &self.base
+    }
+
+    /// The origin with corpus entries that is furthest below its quota
+    fn wanted_origin(&self, meta: &InputOriginStatsMetadata) -> Option<InputOrigin> {
+        InputOrigin::ALL
+            .into_iter()
+            .filter(|origin| meta.entries[origin.index()] > 0 && self.quota(*origin) > 0.0)
+            .map(|origin| (origin, self.quota(origin) - meta.execution_share(origin)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(origin, _)| origin)
+    }
+
+    /// The origin of a testcase, local if unknown
+    fn testcase_origin<I>(testcase: &Testcase<I>) -> InputOrigin
+    where
+        I: Input,
+    {
+        testcase
+            .metadata_map()
+            .get::<InputOriginMetadata>()
+            .map_or(InputOrigin::Local, |meta| meta.origin)
+    }
+
+    /// The origin of the corpus entry
+    fn origin<S>(state: &S, id: CorpusId) -> Result<InputOrigin, Error>
+    where
+        S: HasCorpus,
+    {
+        Ok(Self::testcase_origin(&state.corpus().get(id)?.borrow()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use libafl_bolts::{rands::StdRand, Error};
+
+    use super::{InputOriginStatsMetadata, OriginQuotaScheduler};
+    use crate::{
+        corpus::{
+            Corpus, CorpusId, HasTestcase, InMemoryCorpus, InputOrigin, InputOriginMetadata,
+            Testcase,
+        },
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        schedulers::{QueueScheduler, Scheduler},
+        state::{HasCorpus, HasExecutions, State, StdState, UsesState},
+        HasMetadata,
+    };
+
+    /// Counts how often it is asked for a testcase
+    #[derive(Debug)]
+    struct CountingScheduler<S> {
+        base: QueueScheduler<S>,
+        picks: usize,
+    }
+
+    impl<S> UsesState for CountingScheduler<S>
+    where
+        S: State,
+    {
+        type State = S;
+    }
+
+    impl<S> Scheduler for CountingScheduler<S>
+    where
+        S: State + HasCorpus + HasTestcase,
+    {
+        fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+            self.base.on_add(state, id)
+        }
+
+        fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+            self.picks += 1;
+            self.base.next(state)
+        }
+    }
+
+    #[test]
+    fn test_origin_quotas() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut scheduler = OriginQuotaScheduler::new(CountingScheduler {
+            base: QueueScheduler::new(),
+            picks: 0,
+        })
+        .with_quota(InputOrigin::Local, 1.0)
+        .with_quota(InputOrigin::Imported, 1.0)
+        .with_quota(InputOrigin::Generated, 0.0);
+
+        for (i, origin) in [
+            InputOrigin::Imported,
+            InputOrigin::Imported,
+            InputOrigin::Imported,
+            InputOrigin::Local,
+            InputOrigin::Generated,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut testcase = Testcase::new(BytesInput::new(vec![i as u8]));
+            testcase.add_metadata(InputOriginMetadata { origin });
+            let id = state.corpus_mut().add(testcase).unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+        }
+
+        for _ in 0..100 {
+            scheduler.next(&mut state).unwrap();
+            *state.executions_mut() += 10;
+        }
+        // The base scheduler is asked once per pick
+        assert_eq!(scheduler.base().picks, 100);
+        let meta = state.metadata::<InputOriginStatsMetadata>().unwrap();
+        assert_eq!(meta.entries, [1, 3, 1]);
+        assert_eq!(meta.scheduled[InputOrigin::Generated.index()], 0);
+        let local = meta.execution_share(InputOrigin::Local);
+        assert!((0.4..=0.6).contains(&local), "local share {local}");
+    }
+}
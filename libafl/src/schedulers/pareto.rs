@@ -0,0 +1,421 @@
+//! The [`ParetoScheduler`] keeps the Pareto frontier of the corpus over new-coverage potential,
+//! execution time and input size, and only samples testcases on the frontier, instead of
+//! collapsing all objectives into a single score.
+
+use alloc::{string::String, vec::Vec};
+use core::marker::PhantomData;
+
+use hashbrown::HashMap;
+use libafl_bolts::{rands::Rand, HasLen};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId, HasTestcase, Testcase},
+    feedbacks::MapIndexesMetadata,
+    inputs::UsesInput,
+    random_corpus_id,
+    schedulers::{RemovableScheduler, Scheduler},
+    state::{HasCorpus, HasRand, State, UsesState},
+    Error, HasMetadata,
+};
+
+/// The objectives of a testcase for the [`ParetoScheduler`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ParetoObjectives {
+    /// The new-coverage potential, the number of covered map entries divided by the number of
+    /// times the testcase was scheduled. Higher is better.
+    pub potential: f64,
+    /// The execution time in nanoseconds, 0 if not yet measured. Lower is better.
+    pub exec_time: f64,
+    /// The size of the input. Lower is better.
+    pub size: f64,
+}
+
+impl ParetoObjectives {
+    /// The execution time in nanoseconds, `None` if not yet measured
+    #[must_use]
+    pub fn known_exec_time(&self) -> Option<f64> {
+        (self.exec_time > 0.0).then_some(self.exec_time)
+    }
+
+    /// If these objectives are at least as good as `other` in all, and better in one objective.
+    /// The execution time is only compared if both are known.
+    #[must_use]
+    pub fn dominates(&self, other: &Self) -> bool {
+        let (faster_or_equal, faster) = match (self.known_exec_time(), other.known_exec_time()) {
+            (Some(time), Some(other_time)) => (time <= other_time, time < other_time),
+            _ => (true, false),
+        };
+        self.potential >= other.potential
+            && faster_or_equal
+            && self.size <= other.size
+            && (self.potential > other.potential || faster || self.size < other.size)
+    }
+}
+
+/// The objectives of all corpus entries and the current Pareto frontier
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct ParetoMetadata {
+    objectives: HashMap<CorpusId, ParetoObjectives>,
+    frontier: Vec<CorpusId>,
+}
+
+libafl_bolts::impl_serdeany!(ParetoMetadata);
+
+impl ParetoMetadata {
+    /// Creates a new, empty [`ParetoMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The corpus entries not dominated by any other entry
+    #[must_use]
+    pub fn frontier(&self) -> &[CorpusId] {
+        &self.frontier
+    }
+
+    /// The objectives of the corpus entry
+    #[must_use]
+    pub fn objectives(&self, id: CorpusId) -> Option<&ParetoObjectives> {
+        self.objectives.get(&id)
+    }
+
+    /// Sets the objectives of the corpus entry and updates the frontier
+    pub fn insert(&mut self, id: CorpusId, objectives: ParetoObjectives) {
+        if let Some(prev) = self.objectives.insert(id, objectives) {
+            if prev == objectives {
+                return;
+            }
+            if self.frontier.contains(&id) {
+                // The entry may have gotten worse, uncovering entries it dominated
+                self.recompute_frontier();
+                return;
+            }
+        }
+        if self
+            .frontier
+            .iter()
+            .any(|other| self.objectives[other].dominates(&objectives))
+        {
+            return;
+        }
+        let objectives_of = &self.objectives;
+        self.frontier
+            .retain(|other| !objectives.dominates(&objectives_of[other]));
+        self.frontier.push(id);
+    }
+
+    /// Removes the corpus entry and updates the frontier
+    pub fn remove(&mut self, id: CorpusId) {
+        if self.objectives.remove(&id).is_some() && self.frontier.contains(&id) {
+            self.recompute_frontier();
+        }
+    }
+
+    /// Recomputes the frontier from scratch
+    pub fn recompute_frontier(&mut self) {
+        let mut ids: Vec<CorpusId> = self.objectives.keys().copied().collect();
+        // Entries with a higher potential can't be dominated by later ones with a lower potential
+        ids.sort_unstable_by(|a, b| {
+            self.objectives[b]
+                .potential
+                .total_cmp(&self.objectives[a].potential)
+                .then(a.cmp(b))
+        });
+        self.frontier.clear();
+        for id in ids {
+            let objectives = &self.objectives[&id];
+            if !self
+                .frontier
+                .iter()
+                .any(|other| self.objectives[other].dominates(objectives))
+            {
+                let objectives_of = &self.objectives;
+                self.frontier
+                    .retain(|other| !objectives.dominates(&objectives_of[other]));
+                self.frontier.push(id);
+            }
+        }
+    }
+}
+
+/// The default weight of each objective when sampling from the frontier
+pub const DEFAULT_PARETO_WEIGHT: f64 = 1.0;
+
+/// Samples testcases from the Pareto frontier over new-coverage potential, execution time and
+/// input size, see [`ParetoObjectives`].
+///
+/// Among the frontier, testcases are picked proportionally to a weighted sum of their normalized
+/// objectives, so the weights bias the choice toward testcases with much potential, fast ones or
+/// small ones. The coverage potential relies on the [`MapIndexesMetadata`] of the testcases, so
+/// the map feedback needs to track indexes.
+#[derive(Debug, Clone)]
+pub struct ParetoScheduler<S> {
+    coverage_weight: f64,
+    speed_weight: f64,
+    size_weight: f64,
+    phantom: PhantomData<S>,
+}
+
+impl<S> UsesState for ParetoScheduler<S>
+where
+    S: State + HasTestcase,
+{
+    type State = S;
+}
+
+impl<S> RemovableScheduler for ParetoScheduler<S>
+where
+    S: HasCorpus + HasMetadata + HasRand + HasTestcase + State,
+    S::Input: HasLen,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut Self::State,
+        id: CorpusId,
+        _testcase: &Option<Testcase<<Self::State as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        state
+            .metadata_or_insert_with(ParetoMetadata::new)
+            .remove(id);
+        Ok(())
+    }
+
+    fn on_replace(
+        &mut self,
+        state: &mut Self::State,
+        id: CorpusId,
+        _prev: &Testcase<<Self::State as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        Self::update_objectives(state, id)
+    }
+}
+
+impl<S> Scheduler for ParetoScheduler<S>
+where
+    S: HasCorpus + HasMetadata + HasRand + HasTestcase + State,
+    S::Input: HasLen,
+{
+    fn on_add(&mut self, state: &mut Self::State, id: CorpusId) -> Result<(), Error> {
+        let current_id = *state.corpus().current();
+        state
+            .corpus()
+            .get(id)?
+            .borrow_mut()
+            .set_parent_id_optional(current_id);
+
+        Self::update_objectives(state, id)
+    }
+
+    fn next(&mut self, state: &mut Self::State) -> Result<CorpusId, Error> {
+        if state.corpus().count() == 0 {
+            return Err(Error::empty(String::from(
+                "No entries in corpus. This often implies the target is not properly instrumented.",
+            )));
+        }
+
+        // The last scheduled testcase lost some of its potential
+        if let Some(current_id) = *state.corpus().current() {
+            if state.corpus().get(current_id).is_ok() {
+                Self::update_objectives(state, current_id)?;
+            }
+        }
+
+        let meta = state.metadata_or_insert_with(ParetoMetadata::new);
+        let weights = self.frontier_weights(meta);
+        let id = if weights.is_empty() {
+            random_corpus_id!(state.corpus(), state.rand_mut())
+        } else {
+            let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+            let threshold = state.rand_mut().next_float() * total;
+            let mut sum = 0.0;
+            let mut id = weights[weights.len() - 1].0;
+            for (candidate, weight) in &weights {
+                sum += weight;
+                if sum >= threshold {
+                    id = *candidate;
+                    break;
+                }
+            }
+            id
+        };
+
+        self.set_current_scheduled(state, Some(id))?;
+        Ok(id)
+    }
+}
+
+impl<S> ParetoScheduler<S> {
+    /// Creates a new [`ParetoScheduler`], weighting all objectives equally
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            coverage_weight: DEFAULT_PARETO_WEIGHT,
+            speed_weight: DEFAULT_PARETO_WEIGHT,
+            size_weight: DEFAULT_PARETO_WEIGHT,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the weight of the new-coverage potential
+    #[must_use]
+    pub fn with_coverage_weight(mut self, weight: f64) -> Self {
+        self.coverage_weight = weight.max(0.0);
+        self
+    }
+
+    /// Sets the weight of fast execution
+    #[must_use]
+    pub fn with_speed_weight(mut self, weight: f64) -> Self {
+        self.speed_weight = weight.max(0.0);
+        self
+    }
+
+    /// Sets the weight of small input size
+    #[must_use]
+    pub fn with_size_weight(mut self, weight: f64) -> Self {
+        self.size_weight = weight.max(0.0);
+        self
+    }
+
+    /// The sampling weight of each testcase on the frontier
+    fn frontier_weights(&self, meta: &ParetoMetadata) -> Vec<(CorpusId, f64)> {
+        let objectives: Vec<(CorpusId, ParetoObjectives)> = meta
+            .frontier()
+            .iter()
+            .filter_map(|id| meta.objectives(*id).map(|objectives| (*id, *objectives)))
+            .collect();
+
+        let range = |f: fn(&ParetoObjectives) -> Option<f64>| {
+            objectives
+                .iter()
+                .filter_map(|(_, objectives)| f(objectives))
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                    (min.min(v), max.max(v))
+                })
+        };
+        let normalize = |v: f64, (min, max): (f64, f64)| {
+            if max > min {
+                (v - min) / (max - min)
+            } else {
+                1.0
+            }
+        };
+        let potential = range(|objectives| Some(objectives.potential));
+        let exec_time = range(ParetoObjectives::known_exec_time);
+        let size = range(|objectives| Some(objectives.size));
+
+        objectives
+            .iter()
+            .map(|(id, objectives)| {
+                // Keep a small base weight, so every testcase on the frontier gets picked eventually
+                let weight = 0.01
+                    + self.coverage_weight * normalize(objectives.potential, potential)
+                    // An unknown execution time is neither fast nor slow
+                    + self.speed_weight
+                        * objectives
+                            .known_exec_time()
+                            .map_or(0.5, |time| 1.0 - normalize(time, exec_time))
+                    + self.size_weight * (1.0 - normalize(objectives.size, size));
+                (*id, weight)
+            })
+            .collect()
+    }
+
+    /// Computes the objectives of the corpus entry and updates the frontier
+    #[allow(clippy::cast_precision_loss)]
+    fn update_objectives(state: &mut S, id: CorpusId) -> Result<(), Error>
+    where
+        S: HasCorpus + HasMetadata,
+        S::Input: HasLen,
+    {
+        let objectives = {
+            let mut testcase = state.corpus().get(id)?.borrow_mut();
+            let covered = testcase
+                .metadata_map()
+                .get::<MapIndexesMetadata>()
+                .map_or(0, |meta| meta.list.len());
+            let exec_time = testcase.exec_time().map_or(0, |time| time.as_nanos());
+            let size = testcase.load_len(state.corpus())?;
+            ParetoObjectives {
+                potential: covered as f64 / (1 + testcase.scheduled_count()) as f64,
+                exec_time: exec_time as f64,
+                size: size as f64,
+            }
+        };
+        state
+            .metadata_or_insert_with(ParetoMetadata::new)
+            .insert(id, objectives);
+        Ok(())
+    }
+}
+
+impl<S> Default for ParetoScheduler<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParetoMetadata, ParetoObjectives};
+    use crate::corpus::CorpusId;
+
+    fn objectives(potential: f64, exec_time: f64, size: f64) -> ParetoObjectives {
+        ParetoObjectives {
+            potential,
+            exec_time,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_pareto_frontier() {
+        let mut meta = ParetoMetadata::new();
+        meta.insert(CorpusId(0), objectives(10.0, 100.0, 10.0));
+        // Faster, but less potential: both on the frontier
+        meta.insert(CorpusId(1), objectives(5.0, 50.0, 10.0));
+        // Dominated by 0
+        meta.insert(CorpusId(2), objectives(8.0, 200.0, 20.0));
+        let mut frontier = meta.frontier().to_vec();
+        frontier.sort_unstable();
+        assert_eq!(frontier, [CorpusId(0), CorpusId(1)]);
+
+        // 0 got scheduled a lot, now 2 is no longer dominated by it
+        meta.insert(CorpusId(0), objectives(1.0, 100.0, 10.0));
+        let mut frontier = meta.frontier().to_vec();
+        frontier.sort_unstable();
+        assert_eq!(frontier, [CorpusId(1), CorpusId(2)]);
+
+        // A new entry dominating everything
+        meta.insert(CorpusId(3), objectives(20.0, 10.0, 1.0));
+        assert_eq!(meta.frontier(), [CorpusId(3)]);
+
+        meta.remove(CorpusId(3));
+        let mut frontier = meta.frontier().to_vec();
+        frontier.sort_unstable();
+        assert_eq!(frontier, [CorpusId(1), CorpusId(2)]);
+    }
+
+    #[test]
+    fn test_pareto_unknown_exec_time() {
+        // A testcase that was never measured is not the fastest
+        assert!(!objectives(1.0, 0.0, 10.0).dominates(&objectives(1.0, 50.0, 10.0)));
+        assert!(!objectives(1.0, 50.0, 10.0).dominates(&objectives(1.0, 0.0, 10.0)));
+        // The other objectives still count
+        assert!(objectives(2.0, 0.0, 10.0).dominates(&objectives(1.0, 50.0, 10.0)));
+        assert!(objectives(2.0, 50.0, 10.0).dominates(&objectives(1.0, 0.0, 10.0)));
+
+        let mut meta = ParetoMetadata::new();
+        meta.insert(CorpusId(0), objectives(1.0, 50.0, 10.0));
+        meta.insert(CorpusId(1), objectives(1.0, 0.0, 10.0));
+        let mut frontier = meta.frontier().to_vec();
+        frontier.sort_unstable();
+        assert_eq!(frontier, [CorpusId(0), CorpusId(1)]);
+    }
+}
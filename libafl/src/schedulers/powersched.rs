@@ -1,13 +1,14 @@
 //! The queue corpus scheduler for power schedules.
 
 use alloc::vec::Vec;
-use core::{marker::PhantomData, time::Duration};
+use core::{fmt, marker::PhantomData, str::FromStr, time::Duration};
 
 use libafl_bolts::{
+    serdeany::VersionedSerdeAny,
     tuples::{Handle, Handled},
     Named,
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
     corpus::{Corpus, CorpusId, HasTestcase, Testcase},
@@ -21,7 +22,82 @@ use crate::{
 /// The n fuzz size
 pub const N_FUZZ_SIZE: usize = 1 << 21;
 
-libafl_bolts::impl_serdeany!(SchedulerMetadata);
+/// Default divisor applied to the schedule factor, `POWER_BETA` in AFL++
+pub const DEFAULT_POWER_BETA: f64 = 1.0;
+/// Default upper bound for the schedule factor, `MAX_FACTOR` in AFL++
+pub const DEFAULT_MAX_FACTOR: f64 = DEFAULT_POWER_BETA * 32.0;
+/// Default upper bound for the perf score, in multiples of 100, `HAVOC_MAX_MULT` in AFL++
+pub const DEFAULT_HAVOC_MAX_MULT: f64 = 64.0;
+/// Default number of deepest queue levels boosted by [`PowerSchedule::MMOPT`]
+pub const DEFAULT_MMOPT_RECENT_DEPTH: u64 = 5;
+/// Default perf score bonus of [`PowerSchedule::RARE`] per map entry a testcase is top rated for
+pub const DEFAULT_RARE_TOP_RATED_BONUS: f64 = 10.0;
+
+/// The tunable parameters of the power schedules
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct PowerScheduleParams {
+    /// The divisor applied to the schedule factor
+    pub beta: f64,
+    /// The upper bound for the schedule factor
+    pub max_factor: f64,
+    /// The upper bound for the perf score, in multiples of 100
+    pub havoc_max_mult: f64,
+    /// The number of deepest queue levels boosted by [`PowerSchedule::MMOPT`]
+    pub mmopt_recent_depth: u64,
+    /// The perf score bonus of [`PowerSchedule::RARE`] per map entry a testcase is top rated for
+    pub rare_top_rated_bonus: f64,
+}
+
+impl Default for PowerScheduleParams {
+    fn default() -> Self {
+        Self {
+            beta: DEFAULT_POWER_BETA,
+            max_factor: DEFAULT_MAX_FACTOR,
+            havoc_max_mult: DEFAULT_HAVOC_MAX_MULT,
+            mmopt_recent_depth: DEFAULT_MMOPT_RECENT_DEPTH,
+            rare_top_rated_bonus: DEFAULT_RARE_TOP_RATED_BONUS,
+        }
+    }
+}
+
+libafl_bolts::impl_serdeany_versioned!(SchedulerMetadata);
+
+impl VersionedSerdeAny for SchedulerMetadata {
+    const SCHEMA_VERSION: u32 = 1;
+
+    fn deserialize_legacy<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let legacy = LegacySchedulerMetadata::deserialize(deserializer)?;
+        Ok(Self {
+            strat: legacy.strat,
+            exec_time: legacy.exec_time,
+            cycles: legacy.cycles,
+            bitmap_size: legacy.bitmap_size,
+            bitmap_size_log: legacy.bitmap_size_log,
+            bitmap_entries: legacy.bitmap_entries,
+            queue_cycles: legacy.queue_cycles,
+            n_fuzz_total: legacy.n_fuzz.iter().map(|x| u64::from(*x)).sum(),
+            n_fuzz: legacy.n_fuzz,
+            max_depth: 0,
+            params: PowerScheduleParams::default(),
+        })
+    }
+}
+
+/// The layout of [`struct@SchedulerMetadata`] before it was versioned
+#[derive(Deserialize)]
+struct LegacySchedulerMetadata {
+    strat: Option<PowerSchedule>,
+    exec_time: Duration,
+    cycles: u64,
+    bitmap_size: u64,
+    bitmap_size_log: f64,
+    bitmap_entries: u64,
+    queue_cycles: u64,
+    n_fuzz: Vec<u32>,
+}
 
 /// The metadata used for power schedules
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -46,6 +122,12 @@ pub struct SchedulerMetadata {
     queue_cycles: u64,
     /// The vector to contain the frequency of each execution path.
     n_fuzz: Vec<u32>,
+    /// The total number of recorded executions in `n_fuzz`
+    n_fuzz_total: u64,
+    /// The deepest depth of any corpus entry
+    max_depth: u64,
+    /// The parameters of the power schedules
+    params: PowerScheduleParams,
 }
 
 /// The metadata for runs in the calibration stage.
@@ -53,6 +135,12 @@ impl SchedulerMetadata {
     /// Creates a new [`struct@SchedulerMetadata`]
     #[must_use]
     pub fn new(strat: Option<PowerSchedule>) -> Self {
+        Self::with_params(strat, PowerScheduleParams::default())
+    }
+
+    /// Creates a new [`struct@SchedulerMetadata`] with the given [`PowerScheduleParams`]
+    #[must_use]
+    pub fn with_params(strat: Option<PowerSchedule>, params: PowerScheduleParams) -> Self {
         Self {
             strat,
             exec_time: Duration::from_millis(0),
@@ -62,6 +150,9 @@ impl SchedulerMetadata {
             bitmap_entries: 0,
             queue_cycles: 0,
             n_fuzz: vec![0; N_FUZZ_SIZE],
+            n_fuzz_total: 0,
+            max_depth: 0,
+            params,
         }
     }
 
@@ -153,6 +244,40 @@ impl SchedulerMetadata {
     pub fn n_fuzz_mut(&mut self) -> &mut [u32] {
         &mut self.n_fuzz
     }
+
+    /// The total number of executions recorded in `n_fuzz`
+    #[must_use]
+    pub fn n_fuzz_total(&self) -> u64 {
+        self.n_fuzz_total
+    }
+
+    /// Records an execution of the path with the given `n_fuzz` entry
+    pub fn record_n_fuzz(&mut self, entry: usize) {
+        self.n_fuzz[entry] = self.n_fuzz[entry].saturating_add(1);
+        self.n_fuzz_total = self.n_fuzz_total.saturating_add(1);
+    }
+
+    /// The deepest depth of any corpus entry
+    #[must_use]
+    pub fn max_depth(&self) -> u64 {
+        self.max_depth
+    }
+
+    /// Sets the deepest depth of any corpus entry
+    pub fn set_max_depth(&mut self, val: u64) {
+        self.max_depth = val;
+    }
+
+    /// The parameters of the power schedules
+    #[must_use]
+    pub fn params(&self) -> &PowerScheduleParams {
+        &self.params
+    }
+
+    /// Sets the parameters of the power schedules
+    pub fn set_params(&mut self, params: PowerScheduleParams) {
+        self.params = params;
+    }
 }
 
 /// The power schedule to use
@@ -170,6 +295,73 @@ pub enum PowerSchedule {
     LIN,
     /// The `quad` power schedule
     QUAD,
+    /// The `mmopt` power schedule, boosting the most recent (deepest) queue entries
+    MMOPT,
+    /// The `rare` power schedule, favoring testcases hitting rarely exercised edges
+    RARE,
+    /// The `seek` power schedule, like `explore` but ignoring the execution speed
+    SEEK,
+}
+
+impl PowerSchedule {
+    /// All available power schedules
+    pub const ALL: [PowerSchedule; 9] = [
+        PowerSchedule::EXPLORE,
+        PowerSchedule::EXPLOIT,
+        PowerSchedule::FAST,
+        PowerSchedule::COE,
+        PowerSchedule::LIN,
+        PowerSchedule::QUAD,
+        PowerSchedule::MMOPT,
+        PowerSchedule::RARE,
+        PowerSchedule::SEEK,
+    ];
+
+    /// The name of this schedule, as used by AFL++'s `-p` option
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            PowerSchedule::EXPLORE => "explore",
+            PowerSchedule::EXPLOIT => "exploit",
+            PowerSchedule::FAST => "fast",
+            PowerSchedule::COE => "coe",
+            PowerSchedule::LIN => "lin",
+            PowerSchedule::QUAD => "quad",
+            PowerSchedule::MMOPT => "mmopt",
+            PowerSchedule::RARE => "rare",
+            PowerSchedule::SEEK => "seek",
+        }
+    }
+
+    /// If the schedule factor (see [`PowerScheduleParams::max_factor`]) applies to this schedule
+    #[must_use]
+    pub fn uses_factor(self) -> bool {
+        matches!(
+            self,
+            PowerSchedule::EXPLOIT
+                | PowerSchedule::FAST
+                | PowerSchedule::COE
+                | PowerSchedule::LIN
+                | PowerSchedule::QUAD
+        )
+    }
+}
+
+impl fmt::Display for PowerSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for PowerSchedule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PowerSchedule::ALL
+            .into_iter()
+            .find(|strat| strat.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::illegal_argument(format!("Unknown power schedule {s}")))
+    }
 }
 
 /// A corpus scheduler using power schedules
@@ -0,0 +1,424 @@
+//! Rarity-based seed scheduling: the [`EdgeFrequencyMetadata`] counts, for each map entry, how
+//! many corpus entries of the whole campaign cover it, and the [`RareEdgeTestcaseScore`] boosts
+//! testcases exercising globally rare edges.
+//!
+//! The [`RareEdgeScheduler`] wraps a scheduler and keeps the counts up to date for the local
+//! corpus. It relies on the [`MapIndexesMetadata`] of the testcases, so the map feedback needs to
+//! track indexes. To aggregate the counts of all clients, add a
+//! [`crate::stages::EdgeFrequencySyncStage`], broadcasting the local counts, and the
+//! [`EdgeFrequencyHook`] to the event manager, merging the counts received from other clients.
+
+use alloc::{string::String, vec::Vec};
+use core::marker::PhantomData;
+
+use hashbrown::HashMap;
+use libafl_bolts::ClientId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId, Testcase},
+    events::{Event, EventManagerHook},
+    feedbacks::MapIndexesMetadata,
+    inputs::{Input, UsesInput},
+    observers::ObserversTuple,
+    schedulers::{
+        probabilistic_sampling::ProbabilityMetadata, RemovableScheduler, Scheduler, TestcaseScore,
+    },
+    state::{HasCorpus, State, UsesState},
+    Error, HasMetadata,
+};
+
+/// The tag of the [`Event::CustomBuf`] carrying edge frequencies
+pub const EDGE_FREQUENCY_TAG: &str = "libafl_edge_frequency";
+
+/// The number of corpus entries covering each map entry, over the whole campaign
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct EdgeFrequencyMetadata {
+    /// The number of corpus entries of all clients covering each map entry
+    hits: HashMap<usize, u64>,
+    /// The local counts not yet broadcast to other clients
+    unsent: HashMap<usize, u64>,
+    /// If the counts changed since the scores of the corpus were last recomputed
+    changed: bool,
+}
+
+libafl_bolts::impl_serdeany!(EdgeFrequencyMetadata);
+
+impl EdgeFrequencyMetadata {
+    /// Creates a new, empty [`EdgeFrequencyMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a local corpus entry covering the given map entries
+    pub fn record(&mut self, indexes: &[usize]) {
+        for idx in indexes {
+            *self.hits.entry(*idx).or_default() += 1;
+            *self.unsent.entry(*idx).or_default() += 1;
+        }
+        self.changed |= !indexes.is_empty();
+    }
+
+    /// Forgets a removed local corpus entry covering the given map entries
+    pub fn forget(&mut self, indexes: &[usize]) {
+        for idx in indexes {
+            if let Some(hits) = self.hits.get_mut(idx) {
+                *hits = hits.saturating_sub(1);
+            }
+        }
+        self.changed |= !indexes.is_empty();
+    }
+
+    /// Merges counts received from another client
+    pub fn merge(&mut self, counts: &[(usize, u64)]) {
+        for (idx, count) in counts {
+            let hits = self.hits.entry(*idx).or_default();
+            *hits = hits.saturating_add(*count);
+        }
+        self.changed |= !counts.is_empty();
+    }
+
+    /// If the counts changed since the last call, resetting the flag
+    pub fn take_changed(&mut self) -> bool {
+        core::mem::take(&mut self.changed)
+    }
+
+    /// Takes the local counts not yet broadcast to other clients
+    pub fn take_unsent(&mut self) -> Vec<(usize, u64)> {
+        self.unsent.drain().collect()
+    }
+
+    /// The number of corpus entries covering the map entry
+    #[must_use]
+    pub fn frequency(&self, idx: usize) -> u64 {
+        self.hits.get(&idx).copied().unwrap_or_default()
+    }
+
+    /// The number of map entries covered by any corpus entry
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.hits.len()
+    }
+
+    /// If no map entry was recorded yet
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.hits.is_empty()
+    }
+
+    /// The rarity of the given map entries, the sum of their inverse frequencies.
+    /// Entries covered by a single corpus entry count fully, common ones barely.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn rarity(&self, indexes: &[usize]) -> f64 {
+        indexes
+            .iter()
+            .map(|idx| 1.0 / self.frequency(*idx).max(1) as f64)
+            .sum()
+    }
+}
+
+/// Scores testcases by the rarity of the edges they cover, see [`EdgeFrequencyMetadata::rarity`].
+/// Testcases without [`MapIndexesMetadata`] get the base score of 1.
+#[derive(Debug, Clone)]
+pub struct RareEdgeTestcaseScore<S> {
+    phantom: PhantomData<S>,
+}
+
+impl<S> TestcaseScore<S> for RareEdgeTestcaseScore<S>
+where
+    S: HasCorpus + HasMetadata,
+{
+    fn compute(state: &S, entry: &mut Testcase<S::Input>) -> Result<f64, Error> {
+        let (Some(freqs), Some(indexes)) = (
+            state.metadata_map().get::<EdgeFrequencyMetadata>(),
+            entry.metadata_map().get::<MapIndexesMetadata>(),
+        ) else {
+            return Ok(1.0);
+        };
+        Ok(1.0 + freqs.rarity(indexes))
+    }
+}
+
+/// Wraps a scheduler and keeps the [`EdgeFrequencyMetadata`] up to date with the local corpus.
+///
+/// Combine it with a base scheduler using the [`RareEdgeTestcaseScore`], e.g. a
+/// [`crate::schedulers::ProbabilitySamplingScheduler`], to boost testcases covering rare edges.
+/// As the frequencies change with every new corpus entry, here or on other clients, the scores
+/// stored in the [`ProbabilityMetadata`] are recomputed on the next evaluation.
+#[derive(Debug, Clone)]
+pub struct RareEdgeScheduler<CS> {
+    base: CS,
+}
+
+impl<CS> UsesState for RareEdgeScheduler<CS>
+where
+    CS: UsesState,
+{
+    type State = CS::State;
+}
+
+impl<CS> RemovableScheduler for RareEdgeScheduler<CS>
+where
+    CS: RemovableScheduler,
+    <Self as UsesState>::State: HasCorpus + HasMetadata,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut <Self as UsesState>::State,
+        id: CorpusId,
+        testcase: &Option<Testcase<<<Self as UsesState>::State as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        if let Some(indexes) = testcase
+            .as_ref()
+            .and_then(|testcase| testcase.metadata_map().get::<MapIndexesMetadata>())
+        {
+            state
+                .metadata_or_insert_with(EdgeFrequencyMetadata::new)
+                .forget(indexes);
+        }
+        self.base.on_remove(state, id, testcase)
+    }
+
+    fn on_replace(
+        &mut self,
+        state: &mut <Self as UsesState>::State,
+        id: CorpusId,
+        prev: &Testcase<<<Self as UsesState>::State as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        if let Some(indexes) = prev.metadata_map().get::<MapIndexesMetadata>() {
+            state
+                .metadata_or_insert_with(EdgeFrequencyMetadata::new)
+                .forget(indexes);
+        }
+        Self::record(state, id)?;
+        self.base.on_replace(state, id, prev)
+    }
+}
+
+impl<CS> Scheduler for RareEdgeScheduler<CS>
+where
+    CS: Scheduler,
+    Self::State: HasCorpus + HasMetadata,
+{
+    fn on_add(&mut self, state: &mut Self::State, id: CorpusId) -> Result<(), Error> {
+        // Record first, so the base scheduler scores the new entry with up to date frequencies
+        Self::record(state, id)?;
+        self.base.on_add(state, id)
+    }
+
+    fn on_evaluation<OT>(
+        &mut self,
+        state: &mut Self::State,
+        input: &<Self::State as UsesInput>::Input,
+        observers: &OT,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<Self::State>,
+    {
+        Self::rescore(state)?;
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut Self::State) -> Result<CorpusId, Error> {
+        self.base.next(state)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut Self::State,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.base.set_current_scheduled(state, next_id)
+    }
+}
+
+impl<CS> RareEdgeScheduler<CS> {
+    /// Creates a new [`RareEdgeScheduler`] wrapping the base scheduler
+    #[must_use]
+    pub fn new(base: CS) -> Self {
+        Self { base }
+    }
+
+    /// The base scheduler
+    #[must_use]
+    pub fn base(&self) -> &CS {
+        &self.base
+    }
+
+    /// Recomputes the [`RareEdgeTestcaseScore`] of all corpus entries in the
+    /// [`ProbabilityMetadata`], if the edge frequencies changed since the last time
+    #[allow(clippy::cast_precision_loss)]
+    fn rescore<S>(state: &mut S) -> Result<(), Error>
+    where
+        S: HasCorpus + HasMetadata,
+    {
+        let changed = state
+            .metadata_map_mut()
+            .get_mut::<EdgeFrequencyMetadata>()
+            .is_some_and(EdgeFrequencyMetadata::take_changed);
+        if !changed || !state.has_metadata::<ProbabilityMetadata>() {
+            return Ok(());
+        }
+        let mut scores = HashMap::new();
+        let mut total = 0.0;
+        for id in state.corpus().ids() {
+            let score =
+                RareEdgeTestcaseScore::compute(state, &mut *state.corpus().get(id)?.borrow_mut())?;
+            scores.insert(id, score);
+            total += score;
+        }
+        let meta = state.metadata_mut::<ProbabilityMetadata>()?;
+        meta.map = scores;
+        meta.total_probability = total;
+        Ok(())
+    }
+
+    /// Records the map entries covered by the corpus entry
+    fn record<S>(state: &mut S, id: CorpusId) -> Result<(), Error>
+    where
+        S: HasCorpus + HasMetadata,
+    {
+        let indexes = state
+            .corpus()
+            .get(id)?
+            .borrow()
+            .metadata_map()
+            .get::<MapIndexesMetadata>()
+            .map(|meta| meta.list.clone());
+        if let Some(indexes) = indexes {
+            state
+                .metadata_or_insert_with(EdgeFrequencyMetadata::new)
+                .record(&indexes);
+        }
+        Ok(())
+    }
+}
+
+/// Merges the edge frequencies broadcast by other clients into the [`EdgeFrequencyMetadata`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EdgeFrequencyHook;
+
+impl EdgeFrequencyHook {
+    /// Creates a new [`EdgeFrequencyHook`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Serializes edge frequencies into the buffer of an [`Event::CustomBuf`]
+    pub fn event<I>(counts: &[(usize, u64)]) -> Result<Event<I>, Error>
+    where
+        I: Input,
+    {
+        Ok(Event::CustomBuf {
+            buf: postcard::to_allocvec(counts)?,
+            tag: String::from(EDGE_FREQUENCY_TAG),
+        })
+    }
+}
+
+impl<S> EventManagerHook<S> for EdgeFrequencyHook
+where
+    S: State + HasMetadata,
+{
+    fn pre_exec(
+        &mut self,
+        state: &mut S,
+        _client_id: ClientId,
+        event: &Event<S::Input>,
+    ) -> Result<bool, Error> {
+        let Event::CustomBuf { buf, tag } = event else {
+            return Ok(true);
+        };
+        if tag != EDGE_FREQUENCY_TAG {
+            return Ok(true);
+        }
+        let counts: Vec<(usize, u64)> = postcard::from_bytes(buf)?;
+        state
+            .metadata_or_insert_with(EdgeFrequencyMetadata::new)
+            .merge(&counts);
+        // Handled, no need to pass it on to the custom buf handlers
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{EdgeFrequencyMetadata, RareEdgeScheduler, RareEdgeTestcaseScore};
+    use crate::{
+        corpus::{Corpus, Testcase},
+        feedbacks::MapIndexesMetadata,
+        inputs::BytesInput,
+        schedulers::{
+            probabilistic_sampling::ProbabilityMetadata, ProbabilitySamplingScheduler, Scheduler,
+        },
+        state::{test::test_std_state, HasCorpus},
+        HasMetadata,
+    };
+
+    #[test]
+    fn test_edge_frequency() {
+        let mut meta = EdgeFrequencyMetadata::new();
+        meta.record(&[1, 2, 3]);
+        meta.record(&[1, 2]);
+        meta.merge(&[(1, 2), (4, 1)]);
+
+        assert_eq!(meta.frequency(1), 4);
+        assert_eq!(meta.frequency(3), 1);
+        assert_eq!(meta.frequency(5), 0);
+        // 3 is only covered once, so it is rarer than 1
+        assert!(meta.rarity(&[3]) > meta.rarity(&[1]));
+
+        // Only the local counts are broadcast
+        let mut unsent = meta.take_unsent();
+        unsent.sort_unstable();
+        assert_eq!(unsent, [(1, 2), (2, 2), (3, 1)]);
+        assert!(meta.take_unsent().is_empty());
+
+        meta.forget(&[3]);
+        assert_eq!(meta.frequency(3), 0);
+    }
+
+    #[test]
+    fn test_rare_edge_rescore() {
+        let mut state = test_std_state::<BytesInput>();
+        let mut scheduler = RareEdgeScheduler::new(ProbabilitySamplingScheduler::<
+            RareEdgeTestcaseScore<_>,
+            _,
+        >::new());
+        let mut ids = vec![];
+        for edge in [1, 2] {
+            let mut testcase = Testcase::new(BytesInput::new(vec![0]));
+            testcase.add_metadata(MapIndexesMetadata::new(vec![edge]));
+            let id = state.corpus_mut().add(testcase).unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+            ids.push(id);
+        }
+        // Both edges are covered once
+        scheduler
+            .on_evaluation(&mut state, &BytesInput::new(vec![0]), &())
+            .unwrap();
+        let meta = state.metadata::<ProbabilityMetadata>().unwrap();
+        assert!((meta.map[&ids[0]] - meta.map[&ids[1]]).abs() < f64::EPSILON);
+
+        // Edge 1 turns out to be common on other clients
+        state
+            .metadata_mut::<EdgeFrequencyMetadata>()
+            .unwrap()
+            .merge(&[(1, 9)]);
+        scheduler
+            .on_evaluation(&mut state, &BytesInput::new(vec![0]), &())
+            .unwrap();
+        let meta = state.metadata::<ProbabilityMetadata>().unwrap();
+        assert!(meta.map[&ids[0]] < meta.map[&ids[1]]);
+        assert!((meta.total_probability - meta.map.values().sum::<f64>()).abs() < f64::EPSILON);
+    }
+}
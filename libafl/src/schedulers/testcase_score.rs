@@ -44,11 +44,6 @@ where
     }
 }
 
-/// Constants for powerschedules
-const POWER_BETA: f64 = 1.0;
-const MAX_FACTOR: f64 = POWER_BETA * 32.0;
-const HAVOC_MAX_MULT: f64 = 64.0;
-
 /// The power assigned to each corpus entry
 /// This result is used for power scheduling
 #[derive(Debug, Clone)]
@@ -121,20 +116,26 @@ where
         let favored = entry.has_metadata::<IsFavoredMetadata>();
         let tcmeta = entry.metadata::<SchedulerTestcaseMetadata>()?;
 
-        if q_exec_us * 0.1 > avg_exec_us {
-            perf_score = 10.0;
-        } else if q_exec_us * 0.2 > avg_exec_us {
-            perf_score = 25.0;
-        } else if q_exec_us * 0.5 > avg_exec_us {
-            perf_score = 50.0;
-        } else if q_exec_us * 0.75 > avg_exec_us {
-            perf_score = 75.0;
-        } else if q_exec_us * 4.0 < avg_exec_us {
-            perf_score = 300.0;
-        } else if q_exec_us * 3.0 < avg_exec_us {
-            perf_score = 200.0;
-        } else if q_exec_us * 2.0 < avg_exec_us {
-            perf_score = 150.0;
+        // RARE and SEEK ignore the execution speed
+        if !matches!(
+            psmeta.strat(),
+            Some(PowerSchedule::RARE | PowerSchedule::SEEK)
+        ) {
+            if q_exec_us * 0.1 > avg_exec_us {
+                perf_score = 10.0;
+            } else if q_exec_us * 0.2 > avg_exec_us {
+                perf_score = 25.0;
+            } else if q_exec_us * 0.5 > avg_exec_us {
+                perf_score = 50.0;
+            } else if q_exec_us * 0.75 > avg_exec_us {
+                perf_score = 75.0;
+            } else if q_exec_us * 4.0 < avg_exec_us {
+                perf_score = 300.0;
+            } else if q_exec_us * 3.0 < avg_exec_us {
+                perf_score = 200.0;
+            } else if q_exec_us * 2.0 < avg_exec_us {
+                perf_score = 150.0;
+            }
         }
 
         let q_bitmap_size = tcmeta.bitmap_size() as f64;
@@ -170,17 +171,39 @@ where
             perf_score *= 5.0;
         }
 
+        let params = psmeta.params();
         let mut factor: f64 = 1.0;
 
         // COE and Fast schedule are fairly different from what are described in the original thesis,
         // This implementation follows the changes made in this pull request https://github.com/AFLplusplus/AFLplusplus/pull/568
         if let Some(strat) = psmeta.strat() {
             match strat {
-                PowerSchedule::EXPLORE => {
-                    // Nothing happens in EXPLORE
+                PowerSchedule::EXPLORE | PowerSchedule::SEEK => {
+                    // Nothing happens in EXPLORE and SEEK
                 }
                 PowerSchedule::EXPLOIT => {
-                    factor = MAX_FACTOR;
+                    factor = params.max_factor;
+                }
+                PowerSchedule::MMOPT => {
+                    // Put focus on the most recent, deepest entries
+                    if psmeta.max_depth().saturating_sub(tcmeta.depth()) < params.mmopt_recent_depth
+                    {
+                        perf_score *= 2.0;
+                    }
+                }
+                PowerSchedule::RARE => {
+                    // Increase the score for every map entry this testcase is the top contender for
+                    let tc_ref = entry
+                        .metadata_map()
+                        .get::<MapIndexesMetadata>()
+                        .map_or(0, HasRefCnt::refcnt);
+                    perf_score += tc_ref as f64 * params.rare_top_rated_bonus;
+                    // The more often the fuzzer hits the path of this testcase, the less it is worth
+                    if psmeta.n_fuzz_total() > 0 {
+                        perf_score *= 1.0
+                            - f64::from(psmeta.n_fuzz()[tcmeta.n_fuzz_entry()])
+                                / psmeta.n_fuzz_total() as f64;
+                    }
                 }
                 PowerSchedule::COE => {
                     if libm::log2(f64::from(psmeta.n_fuzz()[tcmeta.n_fuzz_entry()])) > fuzz_mu
@@ -241,12 +264,12 @@ where
         }
 
         if let Some(strat) = psmeta.strat() {
-            if strat != PowerSchedule::EXPLORE {
-                if factor > MAX_FACTOR {
-                    factor = MAX_FACTOR;
+            if strat.uses_factor() {
+                if factor > params.max_factor {
+                    factor = params.max_factor;
                 }
 
-                perf_score *= factor / POWER_BETA;
+                perf_score *= factor / params.beta;
             }
         }
 
@@ -258,8 +281,8 @@ where
         }
 
         // Upper bound
-        if perf_score > HAVOC_MAX_MULT * 100.0 {
-            perf_score = HAVOC_MAX_MULT * 100.0;
+        if perf_score > params.havoc_max_mult * 100.0 {
+            perf_score = params.havoc_max_mult * 100.0;
         }
 
         Ok(perf_score)
@@ -304,7 +327,11 @@ where
         let q_bitmap_size = tcmeta.bitmap_size() as f64;
 
         if let Some(
-            PowerSchedule::FAST | PowerSchedule::COE | PowerSchedule::LIN | PowerSchedule::QUAD,
+            PowerSchedule::FAST
+            | PowerSchedule::COE
+            | PowerSchedule::LIN
+            | PowerSchedule::QUAD
+            | PowerSchedule::RARE,
         ) = psmeta.strat()
         {
             let hits = psmeta.n_fuzz()[tcmeta.n_fuzz_entry()];
@@ -313,7 +340,12 @@ where
             }
         }
 
-        weight *= avg_exec_us / q_exec_us;
+        if !matches!(
+            psmeta.strat(),
+            Some(PowerSchedule::RARE | PowerSchedule::SEEK)
+        ) {
+            weight *= avg_exec_us / q_exec_us;
+        }
         weight *= libm::log2(q_bitmap_size).max(1.0) / avg_bitmap_size;
 
         let tc_ref = match entry.metadata_map().get::<MapIndexesMetadata>() {
@@ -338,3 +370,163 @@ where
         Ok(weight)
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::float_cmp)] // The expected scores are exact
+mod tests {
+    use core::time::Duration;
+
+    use crate::{
+        corpus::{SchedulerTestcaseMetadata, Testcase},
+        feedbacks::MapIndexesMetadata,
+        inputs::BytesInput,
+        schedulers::{
+            powersched::{PowerSchedule, PowerScheduleParams, SchedulerMetadata},
+            testcase_score::{CorpusPowerTestcaseScore, TestcaseScore},
+        },
+        state::test::test_std_state,
+        HasMetadata,
+    };
+
+    /// The average execution time of the corpus
+    const AVG_EXEC_TIME: Duration = Duration::from_millis(10);
+    /// Slow enough for the fastest possible perf score of 10
+    const SLOW_EXEC_TIME: Duration = Duration::from_millis(200);
+
+    /// The perf score of a testcase of average speed and bitmap size, at the given `depth` of a
+    /// corpus 10 levels deep, after `tweak` adjusted the metadata
+    fn perf_score<T>(
+        strat: PowerSchedule,
+        params: PowerScheduleParams,
+        exec_time: Duration,
+        depth: u64,
+        tweak: T,
+    ) -> f64
+    where
+        T: FnOnce(&mut SchedulerMetadata, &mut Testcase<BytesInput>),
+    {
+        let mut state = test_std_state::<BytesInput>();
+        let mut psmeta = SchedulerMetadata::with_params(Some(strat), params);
+        psmeta.set_exec_time(AVG_EXEC_TIME);
+        psmeta.set_cycles(1);
+        psmeta.set_bitmap_size(10);
+        psmeta.set_bitmap_entries(1);
+        psmeta.set_max_depth(10);
+
+        let mut testcase = Testcase::new(BytesInput::new(vec![0]));
+        testcase.set_exec_time(exec_time);
+        let mut tcmeta = SchedulerTestcaseMetadata::with_n_fuzz_entry(depth, 0);
+        tcmeta.set_bitmap_size(10);
+        testcase.add_metadata(tcmeta);
+
+        tweak(&mut psmeta, &mut testcase);
+        state.add_metadata(psmeta);
+        CorpusPowerTestcaseScore::compute(&state, &mut testcase).unwrap()
+    }
+
+    #[test]
+    fn test_mmopt_score() {
+        let params = PowerScheduleParams::default();
+        // Depth 6 doubles the score, and is among the 5 deepest levels
+        assert_eq!(
+            perf_score(PowerSchedule::MMOPT, params, AVG_EXEC_TIME, 6, |_, _| {}),
+            400.0
+        );
+        assert_eq!(
+            perf_score(PowerSchedule::EXPLORE, params, AVG_EXEC_TIME, 6, |_, _| {}),
+            200.0
+        );
+        // Depth 4 is not
+        assert_eq!(
+            perf_score(PowerSchedule::MMOPT, params, AVG_EXEC_TIME, 4, |_, _| {}),
+            200.0
+        );
+
+        let params = PowerScheduleParams {
+            mmopt_recent_depth: 7,
+            ..params
+        };
+        assert_eq!(
+            perf_score(PowerSchedule::MMOPT, params, AVG_EXEC_TIME, 4, |_, _| {}),
+            400.0
+        );
+    }
+
+    #[test]
+    fn test_rare_score() {
+        // Top rated for 3 map entries, and a quarter of the executions hit its path
+        let rare = |psmeta: &mut SchedulerMetadata, testcase: &mut Testcase<BytesInput>| {
+            let mut indexes = MapIndexesMetadata::new(vec![0, 1, 2]);
+            indexes.tcref = 3;
+            testcase.add_metadata(indexes);
+            psmeta.record_n_fuzz(0);
+            for _ in 0..3 {
+                psmeta.record_n_fuzz(1);
+            }
+        };
+
+        let params = PowerScheduleParams::default();
+        // (100 + 3 * 10) * (1 - 1/4), regardless of the speed
+        assert_eq!(
+            perf_score(PowerSchedule::RARE, params, AVG_EXEC_TIME, 1, rare),
+            97.5
+        );
+        assert_eq!(
+            perf_score(PowerSchedule::RARE, params, SLOW_EXEC_TIME, 1, rare),
+            97.5
+        );
+
+        let params = PowerScheduleParams {
+            rare_top_rated_bonus: 20.0,
+            ..params
+        };
+        assert_eq!(
+            perf_score(PowerSchedule::RARE, params, AVG_EXEC_TIME, 1, rare),
+            120.0
+        );
+    }
+
+    #[test]
+    fn test_seek_score() {
+        let params = PowerScheduleParams::default();
+        assert_eq!(
+            perf_score(PowerSchedule::SEEK, params, SLOW_EXEC_TIME, 1, |_, _| {}),
+            100.0
+        );
+        assert_eq!(
+            perf_score(PowerSchedule::EXPLORE, params, SLOW_EXEC_TIME, 1, |_, _| {}),
+            10.0
+        );
+    }
+
+    #[test]
+    fn test_power_schedule_params() {
+        let exploit =
+            |params| perf_score(PowerSchedule::EXPLOIT, params, AVG_EXEC_TIME, 1, |_, _| {});
+        let params = PowerScheduleParams::default();
+        // The score is multiplied by `max_factor / beta`
+        assert_eq!(exploit(params), 3200.0);
+        assert_eq!(
+            exploit(PowerScheduleParams {
+                beta: 2.0,
+                ..params
+            }),
+            1600.0
+        );
+        assert_eq!(
+            exploit(PowerScheduleParams {
+                max_factor: 8.0,
+                ..params
+            }),
+            800.0
+        );
+        // and capped at `havoc_max_mult * 100`
+        assert_eq!(
+            exploit(PowerScheduleParams {
+                havoc_max_mult: 10.0,
+                ..params
+            }),
+            1000.0
+        );
+    }
+}
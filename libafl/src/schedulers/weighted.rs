@@ -6,6 +6,7 @@ use core::marker::PhantomData;
 use hashbrown::HashMap;
 use libafl_bolts::{
     rands::Rand,
+    serdeany::VersionedSerdeAny,
     tuples::{Handle, Handled},
     Named,
 };
@@ -17,7 +18,7 @@ use crate::{
     observers::{MapObserver, ObserversTuple},
     random_corpus_id,
     schedulers::{
-        powersched::{PowerSchedule, SchedulerMetadata},
+        powersched::{PowerSchedule, PowerScheduleParams, SchedulerMetadata},
         testcase_score::{CorpusWeightTestcaseScore, TestcaseScore},
         AflScheduler, RemovableScheduler, Scheduler,
     },
@@ -91,13 +92,22 @@ impl WeightedScheduleMetadata {
     }
 }
 
-libafl_bolts::impl_serdeany!(WeightedScheduleMetadata);
+libafl_bolts::impl_serdeany_versioned!(WeightedScheduleMetadata);
+
+impl VersionedSerdeAny for WeightedScheduleMetadata {
+    const SCHEMA_VERSION: u32 = 1;
+}
 
 /// A corpus scheduler using power schedules with weighted queue item selection algo.
+///
+/// The power schedule and its parameters are read from the [`SchedulerMetadata`] of the state,
+/// so they may be switched at runtime using [`SchedulerMetadata::set_strat`] and
+/// [`SchedulerMetadata::set_params`].
 #[derive(Clone, Debug)]
 pub struct WeightedScheduler<C, F, O, S> {
     table_invalidated: bool,
     strat: Option<PowerSchedule>,
+    params: PowerScheduleParams,
     map_observer_handle: Handle<C>,
     last_hash: usize,
     phantom: PhantomData<(F, O, S)>,
@@ -121,11 +131,14 @@ where
     /// Create a new [`WeightedScheduler`]
     #[must_use]
     pub fn with_schedule(state: &mut S, map_observer: &C, strat: Option<PowerSchedule>) -> Self {
-        let _ = state.metadata_or_insert_with(|| SchedulerMetadata::new(strat));
+        let params = *state
+            .metadata_or_insert_with(|| SchedulerMetadata::new(strat))
+            .params();
         let _ = state.metadata_or_insert_with(WeightedScheduleMetadata::new);
 
         Self {
             strat,
+            params,
             map_observer_handle: map_observer.handle(),
             last_hash: 0,
             table_invalidated: true,
@@ -134,6 +147,23 @@ where
         }
     }
 
+    /// Create a new [`WeightedScheduler`] with the given [`PowerScheduleParams`].
+    /// Replaces the schedule and parameters of existing [`SchedulerMetadata`].
+    #[must_use]
+    pub fn with_schedule_and_params(
+        state: &mut S,
+        map_observer: &C,
+        strat: Option<PowerSchedule>,
+        params: PowerScheduleParams,
+    ) -> Self {
+        let psmeta =
+            state.metadata_or_insert_with(|| SchedulerMetadata::with_params(strat, params));
+        psmeta.set_strat(strat);
+        psmeta.set_params(params);
+
+        Self::with_schedule(state, map_observer, strat)
+    }
+
     /// Cycle the `PowerSchedule` on completion of a queue cycle
     #[must_use]
     pub fn cycling_scheduler(mut self) -> Self {
@@ -147,6 +177,12 @@ where
         &self.strat
     }
 
+    /// The [`PowerScheduleParams`] the current alias table was computed with
+    #[must_use]
+    pub fn params(&self) -> &PowerScheduleParams {
+        &self.params
+    }
+
     /// Create a new alias table when the fuzzer finds a new corpus entry
     #[allow(
         clippy::unused_self,
@@ -242,8 +278,12 @@ where
             PowerSchedule::FAST => PowerSchedule::COE,
             PowerSchedule::QUAD => PowerSchedule::FAST,
             PowerSchedule::EXPLOIT => PowerSchedule::EXPLORE,
+            PowerSchedule::MMOPT => PowerSchedule::RARE,
+            PowerSchedule::RARE => PowerSchedule::SEEK,
+            PowerSchedule::SEEK => PowerSchedule::MMOPT,
         };
         metadata.set_strat(Some(next_strat));
+        self.strat = Some(next_strat);
         // We need to recalculate the scores of testcases.
         self.table_invalidated = true;
         Ok(next_strat)
@@ -335,6 +375,13 @@ where
 
     #[allow(clippy::similar_names, clippy::cast_precision_loss)]
     fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        // The schedule may have been switched at runtime
+        let psmeta = state.metadata::<SchedulerMetadata>()?;
+        if psmeta.strat() != self.strat || *psmeta.params() != self.params {
+            self.strat = psmeta.strat();
+            self.params = *psmeta.params();
+            self.table_invalidated = true;
+        }
         if self.table_invalidated {
             self.create_alias_table(state)?;
             self.table_invalidated = false;
@@ -396,3 +443,53 @@ where
 
 /// The standard corpus weight, same as in `AFL++`
 pub type StdWeightedScheduler<C, O, S> = WeightedScheduler<C, CorpusWeightTestcaseScore<S>, O, S>;
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        corpus::{Corpus, Testcase},
+        inputs::BytesInput,
+        observers::StdMapObserver,
+        schedulers::{
+            powersched::{PowerSchedule, PowerScheduleParams, SchedulerMetadata},
+            Scheduler, StdWeightedScheduler,
+        },
+        state::{test::test_std_state, HasCorpus},
+        HasMetadata,
+    };
+
+    #[test]
+    fn test_switch_schedule_at_runtime() {
+        let mut state = test_std_state::<BytesInput>();
+        let observer = StdMapObserver::owned("map", vec![0_u8; 4]);
+        let mut scheduler: StdWeightedScheduler<_, StdMapObserver<u8, false>, _> =
+            StdWeightedScheduler::with_schedule(
+                &mut state,
+                &observer,
+                Some(PowerSchedule::EXPLORE),
+            );
+        for i in 0..2 {
+            let id = state
+                .corpus_mut()
+                .add(Testcase::new(BytesInput::new(vec![i])))
+                .unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+        }
+        scheduler.next(&mut state).unwrap();
+        assert_eq!(scheduler.strat(), &Some(PowerSchedule::EXPLORE));
+
+        let params = PowerScheduleParams {
+            rare_top_rated_bonus: 20.0,
+            ..PowerScheduleParams::default()
+        };
+        let psmeta = state.metadata_mut::<SchedulerMetadata>().unwrap();
+        psmeta.set_strat(Some(PowerSchedule::RARE));
+        psmeta.set_params(params);
+
+        // Picked up on the next scheduling
+        assert_eq!(scheduler.strat(), &Some(PowerSchedule::EXPLORE));
+        scheduler.next(&mut state).unwrap();
+        assert_eq!(scheduler.strat(), &Some(PowerSchedule::RARE));
+        assert_eq!(scheduler.params(), &params);
+    }
+}
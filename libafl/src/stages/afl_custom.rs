@@ -0,0 +1,227 @@
+//! A stage trimming corpus entries with an [AFL++ custom mutator](crate::mutators::afl_custom)
+
+use alloc::borrow::Cow;
+use core::marker::PhantomData;
+
+use libafl_bolts::{impl_serdeany, tuples::Handle, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, HasCurrentCorpusId},
+    executors::{ExitKind, HasObservers},
+    fuzzer::ExecutesInput,
+    inputs::{HasMutatorBytes, UsesInput},
+    mutators::SharedAflCustomLibrary,
+    observers::MapObserver,
+    stages::Stage,
+    state::{HasCorpus, UsesState},
+    Error, HasMetadata,
+};
+
+/// Marks a testcase already trimmed by the [`AflCustomTrimStage`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct AflCustomTrimmedMetadata;
+
+impl_serdeany!(AflCustomTrimmedMetadata);
+
+/// Trims each corpus entry once with the trim callbacks of an AFL++ custom mutator. A candidate
+/// is kept if it runs without crashing and with the same coverage map hash as the entry.
+#[derive(Debug)]
+pub struct AflCustomTrimStage<C, E, EM, O, Z> {
+    library: SharedAflCustomLibrary,
+    map_observer_handle: Handle<C>,
+    phantom: PhantomData<(E, EM, O, Z)>,
+}
+
+impl<C, E, EM, O, Z> AflCustomTrimStage<C, E, EM, O, Z> {
+    /// Creates a new [`AflCustomTrimStage`], comparing the coverage with the map observer
+    #[must_use]
+    pub fn new(library: SharedAflCustomLibrary, map_observer_handle: Handle<C>) -> Self {
+        Self {
+            library,
+            map_observer_handle,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The hash of the coverage map after the last execution
+    fn map_hash(&self, executor: &E) -> u64
+    where
+        E: HasObservers,
+        O: MapObserver,
+        C: AsRef<O>,
+    {
+        executor.observers()[&self.map_observer_handle]
+            .as_ref()
+            .hash_simple()
+    }
+}
+
+impl<C, E, EM, O, Z> UsesState for AflCustomTrimStage<C, E, EM, O, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<C, E, EM, O, Z> Stage<E, EM, Z> for AflCustomTrimStage<C, E, EM, O, Z>
+where
+    E: HasObservers + UsesState,
+    EM: UsesState<State = Self::State>,
+    Z: ExecutesInput<E, EM, State = Self::State>,
+    O: MapObserver,
+    C: AsRef<O>,
+    Self::State: HasCorpus + HasCurrentCorpusId,
+    <Self::State as UsesInput>::Input: HasMutatorBytes,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let Some(corpus_id) = state.current_corpus_id()? else {
+            return Err(Error::illegal_state(
+                "state is not currently processing a corpus index",
+            ));
+        };
+        {
+            let mut testcase = state.corpus().get(corpus_id)?.borrow_mut();
+            if testcase.has_metadata::<AflCustomTrimmedMetadata>() {
+                return Ok(());
+            }
+            testcase.add_metadata(AflCustomTrimmedMetadata);
+        }
+        let mut base = state.corpus().cloned_input_for_id(corpus_id)?;
+        let orig_len = base.bytes().len();
+        let mut buf = base.bytes().to_vec();
+        let Some(steps) = self.library.borrow_mut().init_trim(&mut buf) else {
+            return Ok(());
+        };
+        fuzzer.execute_input(state, executor, manager, &base)?;
+        let orig_hash = self.map_hash(executor);
+
+        let mut step = 0;
+        while step < steps {
+            let Some(candidate) = self.library.borrow_mut().trim() else {
+                break;
+            };
+            let mut input = base.clone();
+            input.resize(candidate.len(), 0);
+            input.bytes_mut().copy_from_slice(&candidate);
+            let exit_kind = fuzzer.execute_input(state, executor, manager, &input)?;
+            let success = exit_kind == ExitKind::Ok && self.map_hash(executor) == orig_hash;
+            if success {
+                base = input;
+            }
+            step = self.library.borrow_mut().post_trim(success);
+            if step < 0 {
+                log::warn!("afl_custom_post_trim failed, stopping to trim");
+                break;
+            }
+        }
+
+        if base.bytes().len() < orig_len {
+            let mut testcase = state.corpus().get(corpus_id)?.borrow_mut();
+            *testcase.input_mut() = Some(base);
+            state.corpus().store_input_from(&testcase)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // Entries are marked as trimmed before trimming, so a crash doesn't trim them again
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<C, E, EM, O, Z> Named for AflCustomTrimStage<C, E, EM, O, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("AflCustomTrimStage");
+        &NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use libafl_bolts::{
+        tuples::{tuple_list, Handled},
+        AsSliceMut,
+    };
+
+    use super::{AflCustomTrimStage, AflCustomTrimmedMetadata};
+    use crate::{
+        corpus::{Corpus, HasCurrentCorpusId, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::afl_custom::test::fake_shared_library,
+        observers::StdMapObserver,
+        schedulers::QueueScheduler,
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus, HasCurrentTestcase},
+        HasMetadata, StdFuzzer,
+    };
+
+    #[test]
+    fn test_afl_custom_trim_stage() {
+        let observer = StdMapObserver::owned("map", vec![0_u8; 1]);
+        let handle = observer.handle();
+        let execs = Cell::new(0_usize);
+        // The coverage only depends on the `X`, so everything else can be trimmed
+        let mut executor = ClosureExecutor::new(
+            |observers: &mut (StdMapObserver<'static, u8, false>, ()), input: &BytesInput| {
+                execs.set(execs.get() + 1);
+                observers.0.as_slice_mut()[0] = u8::from(input.bytes().contains(&b'X'));
+                ExitKind::Ok
+            },
+            tuple_list!(observer),
+        );
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+        let corpus_id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"aaXbb".to_vec())))
+            .unwrap();
+        state.set_corpus_id(corpus_id).unwrap();
+
+        let mut stage = AflCustomTrimStage::<_, _, _, StdMapObserver<'static, u8, false>, _>::new(
+            fake_shared_library(),
+            handle,
+        );
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        // The original run, then one per step
+        assert_eq!(execs.replace(0), 6);
+        let testcase = state.current_testcase().unwrap();
+        assert_eq!(testcase.input().as_ref().unwrap().bytes(), b"X");
+        assert!(testcase.has_metadata::<AflCustomTrimmedMetadata>());
+        drop(testcase);
+
+        // Each entry is only trimmed once
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(execs.get(), 0);
+    }
+}
@@ -0,0 +1,409 @@
+//! The [`AutoDictStage`] learns a dictionary at runtime, instead of relying on a static one.
+//!
+//! Candidate tokens are extracted from the [`CmpValuesMetadata`] of the last execution: the
+//! operands of string and memory comparisons, e.g. intercepted by the sanitizer hooks of
+//! `libafl_targets`, and printable magic values of integer comparisons. Each run, the stage tries
+//! a few candidates by inserting them into the current testcase, and scores them by how often
+//! that yields new coverage. The best candidates are kept in the [`Tokens`] metadata, bounded by
+//! [`AutoDictStage::with_max_tokens`]; tokens of the static dictionary are never removed.
+//!
+//! The learned state lives in the [`AutoDictMetadata`], so it survives restarts along with the
+//! state. With [`AutoDictStage::with_dict_file`], the learned tokens are also written to a
+//! dictionary file, to reuse them in later campaigns.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::marker::PhantomData;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use hashbrown::HashMap;
+use libafl_bolts::{impl_serdeany, rands::Rand, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, HasCurrentCorpusId},
+    fuzzer::{Evaluator, ExecuteInputResult},
+    inputs::{HasMutatorBytes, UsesInput},
+    mutators::Tokens,
+    observers::cmp::{CmpValues, CmpValuesMetadata},
+    stages::Stage,
+    state::{HasCorpus, HasMaxSize, HasRand, UsesState},
+    Error, HasMetadata,
+};
+
+/// The default maximal number of learned tokens in the dictionary
+pub const DEFAULT_MAX_LEARNED_TOKENS: usize = 256;
+/// The default number of candidates tried per run
+pub const DEFAULT_TRIALS_PER_RUN: usize = 4;
+/// The maximal number of candidates remembered
+pub const MAX_TOKEN_CANDIDATES: usize = 4096;
+/// The number of trials after which a candidate that never yielded new coverage is given up
+pub const MAX_FRUITLESS_TRIALS: u64 = 16;
+/// The minimal and maximal length of a candidate token
+const TOKEN_LEN: core::ops::RangeInclusive<usize> = 2..=32;
+
+/// The trials of a candidate token
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenStats {
+    /// The number of times the token was inserted
+    pub trials: u64,
+    /// The number of insertions yielding new coverage
+    pub hits: u64,
+}
+
+impl TokenStats {
+    /// The score of the token: its hit rate, biased towards tokens with few trials
+    #[must_use]
+    pub fn score(&self) -> u64 {
+        (self.hits << 10) / (self.trials + 1)
+    }
+
+    /// Checks if the token is given up, having never yielded new coverage
+    #[must_use]
+    pub fn is_fruitless(&self) -> bool {
+        self.hits == 0 && self.trials >= MAX_FRUITLESS_TRIALS
+    }
+}
+
+/// The candidates and learned tokens of the [`AutoDictStage`], stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AutoDictMetadata {
+    /// The candidate tokens with their trials
+    pub candidates: HashMap<Vec<u8>, TokenStats>,
+    /// The tokens added to the [`Tokens`] by the stage
+    pub learned: Vec<Vec<u8>>,
+}
+
+impl_serdeany!(AutoDictMetadata);
+
+impl AutoDictMetadata {
+    /// Adds a candidate token, unless it is known already.
+    /// If there are too many candidates, the one given up first is replaced.
+    pub fn add_candidate(&mut self, token: Vec<u8>) {
+        if self.candidates.contains_key(&token) {
+            return;
+        }
+        if self.candidates.len() >= MAX_TOKEN_CANDIDATES {
+            let Some(evicted) = self
+                .candidates
+                .iter()
+                .filter(|(_, stats)| stats.is_fruitless())
+                .map(|(token, _)| token.clone())
+                .next()
+            else {
+                return;
+            };
+            self.candidates.remove(&evicted);
+        }
+        self.candidates.insert(token, TokenStats::default());
+    }
+}
+
+/// Extracts the candidate tokens of a comparison
+fn candidates(cmp: &CmpValues) -> Vec<Vec<u8>> {
+    let mut tokens = Vec::new();
+    match cmp {
+        CmpValues::Bytes((left, right)) => {
+            for operand in [left, right] {
+                // C strings are logged with their terminator and whatever follows it
+                let len = operand
+                    .iter()
+                    .position(|b| *b == 0)
+                    .unwrap_or(operand.len());
+                tokens.push(operand[..len].to_vec());
+            }
+        }
+        CmpValues::U32((left, right)) => {
+            for operand in [left, right] {
+                tokens.push(operand.to_le_bytes().to_vec());
+                tokens.push(operand.to_be_bytes().to_vec());
+            }
+        }
+        CmpValues::U64((left, right)) => {
+            for operand in [left, right] {
+                tokens.push(operand.to_le_bytes().to_vec());
+                tokens.push(operand.to_be_bytes().to_vec());
+            }
+        }
+        CmpValues::U128((left, right)) => {
+            for operand in [left, right] {
+                tokens.push(operand.to_le_bytes().to_vec());
+                tokens.push(operand.to_be_bytes().to_vec());
+            }
+        }
+        CmpValues::U8(_) | CmpValues::U16(_) => {}
+    }
+    tokens.retain(|token| is_plausible(token, cmp.is_numeric()));
+    tokens
+}
+
+/// Checks if a token is worth a trial: not too short or long, not a single repeated byte, and
+/// printable for integer operands, so only magic values like `RIFF` are taken
+fn is_plausible(token: &[u8], numeric: bool) -> bool {
+    TOKEN_LEN.contains(&token.len())
+        && token.iter().any(|b| *b != token[0])
+        && (!numeric || token.iter().all(|b| b.is_ascii_graphic() || *b == b' '))
+}
+
+/// Learns a dictionary from comparisons at runtime, see the [module docs](self)
+#[derive(Debug)]
+pub struct AutoDictStage<E, EM, Z> {
+    name: Cow<'static, str>,
+    max_tokens: usize,
+    trials_per_run: usize,
+    #[cfg(feature = "std")]
+    dict_file: Option<PathBuf>,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, Z> UsesState for AutoDictStage<E, EM, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, EM, Z> Default for AutoDictStage<E, EM, Z> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, EM, Z> AutoDictStage<E, EM, Z> {
+    /// Creates a new [`AutoDictStage`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed("AutoDictStage"),
+            max_tokens: DEFAULT_MAX_LEARNED_TOKENS,
+            trials_per_run: DEFAULT_TRIALS_PER_RUN,
+            #[cfg(feature = "std")]
+            dict_file: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the maximal number of learned tokens in the dictionary
+    #[must_use]
+    pub fn with_max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    /// Sets the number of candidates tried per run, each costing an execution
+    #[must_use]
+    pub fn with_trials_per_run(mut self, trials_per_run: usize) -> Self {
+        self.trials_per_run = trials_per_run;
+        self
+    }
+
+    /// Writes the learned tokens to a dictionary file whenever they change, in the format read by
+    /// [`Tokens::from_file`]
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn with_dict_file<P>(mut self, path: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.dict_file = Some(path.into());
+        self
+    }
+
+    /// Replaces the learned tokens in the dictionary by the best candidates.
+    /// Returns `true` if the learned tokens changed.
+    fn update_tokens<S>(&self, state: &mut S) -> Result<bool, Error>
+    where
+        S: HasMetadata,
+    {
+        let meta = state.metadata_mut::<AutoDictMetadata>()?;
+        let mut best = meta
+            .candidates
+            .iter()
+            .filter(|(_, stats)| stats.hits > 0)
+            .map(|(token, stats)| (stats.score(), token.clone()))
+            .collect::<Vec<_>>();
+        best.sort_unstable_by(|a, b| b.cmp(a));
+        best.truncate(self.max_tokens);
+        if best.len() == meta.learned.len()
+            && best.iter().all(|(_, token)| meta.learned.contains(token))
+        {
+            return Ok(false);
+        }
+        let previous = core::mem::take(&mut meta.learned);
+
+        let tokens = state.metadata_or_insert_with(Tokens::new);
+        for token in &previous {
+            tokens.remove_token(token);
+        }
+        let mut learned = Vec::with_capacity(best.len());
+        for (_, token) in best {
+            // Tokens of the static dictionary are left alone
+            if tokens.add_token(&token) {
+                learned.push(token);
+            }
+        }
+        state.metadata_mut::<AutoDictMetadata>()?.learned = learned;
+        Ok(true)
+    }
+
+    /// Writes the learned tokens to the dictionary file, if any
+    #[cfg(feature = "std")]
+    fn write_dict_file<S>(&self, state: &S) -> Result<(), Error>
+    where
+        S: HasMetadata,
+    {
+        use core::fmt::Write;
+
+        let Some(path) = &self.dict_file else {
+            return Ok(());
+        };
+        let mut contents = alloc::string::String::new();
+        for (idx, token) in state
+            .metadata::<AutoDictMetadata>()?
+            .learned
+            .iter()
+            .enumerate()
+        {
+            write!(contents, "auto_{idx}=\"").unwrap();
+            for b in token {
+                match b {
+                    b'"' | b'\\' => write!(contents, "\\{}", char::from(*b)).unwrap(),
+                    b' '..=b'~' => contents.push(char::from(*b)),
+                    _ => write!(contents, "\\x{b:02x}").unwrap(),
+                }
+            }
+            contents.push_str("\"\n");
+        }
+        libafl_bolts::fs::write_file_atomic(path, contents.as_bytes())
+    }
+}
+
+impl<E, EM, Z> Stage<E, EM, Z> for AutoDictStage<E, EM, Z>
+where
+    E: UsesState,
+    EM: UsesState<State = Self::State>,
+    Z: Evaluator<E, EM, State = Self::State>,
+    Self::State: HasCorpus + HasCurrentCorpusId + HasMaxSize + HasMetadata + HasRand,
+    <Self::State as UsesInput>::Input: HasMutatorBytes,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let harvested = state
+            .metadata_map()
+            .get::<CmpValuesMetadata>()
+            .map(|cmps| cmps.list.iter().flat_map(candidates).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let meta = state.metadata_or_insert_with(AutoDictMetadata::default);
+        for token in harvested {
+            meta.add_candidate(token);
+        }
+
+        // Try the candidates with the fewest trials
+        let mut trials = meta
+            .candidates
+            .iter()
+            .filter(|(_, stats)| !stats.is_fruitless())
+            .map(|(token, stats)| (stats.trials, token.clone()))
+            .collect::<Vec<_>>();
+        trials.sort_unstable();
+        trials.truncate(self.trials_per_run);
+        if trials.is_empty() {
+            return Ok(());
+        }
+        let Some(corpus_id) = state.current_corpus_id()? else {
+            return Err(Error::illegal_state(
+                "state is not currently processing a corpus index",
+            ));
+        };
+        let base = state.corpus().cloned_input_for_id(corpus_id)?;
+
+        for (_, token) in trials {
+            let size = base.bytes().len();
+            if size + token.len() > state.max_size() {
+                continue;
+            }
+            let offset = state.rand_mut().below(size + 1);
+            let mut input = base.clone();
+            input.resize(size + token.len(), 0);
+            input
+                .bytes_mut()
+                .copy_within(offset..size, offset + token.len());
+            input.bytes_mut()[offset..offset + token.len()].copy_from_slice(&token);
+
+            let (result, _) = fuzzer.evaluate_input(state, executor, manager, input)?;
+            let stats = state
+                .metadata_mut::<AutoDictMetadata>()?
+                .candidates
+                .entry(token)
+                .or_default();
+            stats.trials += 1;
+            if result == ExecuteInputResult::Corpus {
+                stats.hits += 1;
+            }
+        }
+
+        if self.update_tokens(state)? {
+            #[cfg(feature = "std")]
+            self.write_dict_file(state)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // Each trial is a single execution, the stage can run again after a crash
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, Z> Named for AutoDictStage<E, EM, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{candidates, AutoDictMetadata, TokenStats};
+    use crate::observers::cmp::CmpValues;
+
+    #[test]
+    fn test_autodict_candidates() {
+        let cmp = CmpValues::Bytes((b"GET\0garbage".to_vec(), b"POST".to_vec()));
+        assert_eq!(candidates(&cmp), vec![b"GET".to_vec(), b"POST".to_vec()]);
+        // Only printable integers are magic values
+        let cmp = CmpValues::U32((u32::from_le_bytes(*b"RIFF"), 7));
+        assert_eq!(candidates(&cmp), vec![b"RIFF".to_vec(), b"FFIR".to_vec()]);
+        assert!(candidates(&CmpValues::Bytes((b"aaaa".to_vec(), b"x".to_vec()))).is_empty());
+
+        let mut meta = AutoDictMetadata::default();
+        meta.add_candidate(b"GET".to_vec());
+        meta.candidates.get_mut(&b"GET".to_vec()).unwrap().trials = 3;
+        meta.add_candidate(b"GET".to_vec());
+        assert_eq!(meta.candidates[&b"GET".to_vec()].trials, 3);
+
+        let hit = TokenStats { trials: 2, hits: 1 };
+        let miss = TokenStats {
+            trials: 16,
+            hits: 0,
+        };
+        assert!(hit.score() > miss.score());
+        assert!(miss.is_fruitless() && !hit.is_fruitless());
+    }
+}
@@ -0,0 +1,138 @@
+//! The [`BinaryTokensStage`] populates the [`Tokens`] from the target binaries at campaign start,
+//! using the [`BinaryTokenExtractor`].
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::marker::PhantomData;
+use std::path::PathBuf;
+
+use libafl_bolts::{impl_serdeany, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    mutators::{BinaryTokenExtractor, Tokens},
+    stages::Stage,
+    state::UsesState,
+    Error, HasMetadata,
+};
+
+/// The binaries the [`BinaryTokensStage`] already extracted tokens from, stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct BinaryTokensMetadata {
+    /// The binaries whose tokens were added to the [`Tokens`]
+    pub extracted: Vec<PathBuf>,
+}
+
+impl_serdeany!(BinaryTokensMetadata);
+
+/// Adds the tokens of the target binaries to the [`Tokens`] on its first run.
+/// Each binary is only processed once per campaign, even across restarts.
+#[derive(Debug)]
+pub struct BinaryTokensStage<E, EM, Z> {
+    name: Cow<'static, str>,
+    binaries: Vec<PathBuf>,
+    extractor: BinaryTokenExtractor,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, Z> UsesState for BinaryTokensStage<E, EM, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, EM, Z> BinaryTokensStage<E, EM, Z> {
+    /// Creates a new [`BinaryTokensStage`] for the given target binaries
+    pub fn new<IT, P>(binaries: IT) -> Self
+    where
+        IT: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        Self {
+            name: Cow::Borrowed("BinaryTokensStage"),
+            binaries: binaries.into_iter().map(Into::into).collect(),
+            extractor: BinaryTokenExtractor::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Creates a new [`BinaryTokensStage`] for the currently running executable, i.e. the target
+    /// of an in-process fuzzer
+    pub fn for_current_exe() -> Result<Self, Error> {
+        Ok(Self::new([std::env::current_exe()?]))
+    }
+
+    /// Sets the [`BinaryTokenExtractor`] used for the binaries
+    #[must_use]
+    pub fn with_extractor(mut self, extractor: BinaryTokenExtractor) -> Self {
+        self.extractor = extractor;
+        self
+    }
+
+    /// The target binaries
+    #[must_use]
+    pub fn binaries(&self) -> &[PathBuf] {
+        &self.binaries
+    }
+}
+
+impl<E, EM, Z> Stage<E, EM, Z> for BinaryTokensStage<E, EM, Z>
+where
+    E: UsesState,
+    EM: UsesState<State = Self::State>,
+    Z: UsesState<State = Self::State>,
+    Self::State: HasMetadata,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut Self::State,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        for binary in &self.binaries {
+            if state
+                .metadata_or_insert_with(BinaryTokensMetadata::default)
+                .extracted
+                .contains(binary)
+            {
+                continue;
+            }
+            let extracted = self.extractor.extract_file(binary)?;
+            let tokens = state.metadata_or_insert_with(Tokens::new);
+            let before = tokens.len();
+            tokens.add_tokens(extracted.iter());
+            log::info!(
+                "Added {} tokens extracted from {}",
+                tokens.len() - before,
+                binary.display()
+            );
+            state
+                .metadata_mut::<BinaryTokensMetadata>()?
+                .extracted
+                .push(binary.clone());
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // Extracting the tokens does not run the target
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, Z> Named for BinaryTokensStage<E, EM, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
@@ -2,26 +2,28 @@
 
 use alloc::{
     borrow::{Cow, ToOwned},
+    collections::VecDeque,
     vec::Vec,
 };
 use core::{fmt::Debug, marker::PhantomData, time::Duration};
 
-use hashbrown::HashSet;
-use libafl_bolts::{current_time, impl_serdeany, tuples::Handle, AsIter, Named};
+use hashbrown::{HashMap, HashSet};
+use libafl_bolts::{current_time, impl_serdeany, rands::Rand, tuples::Handle, AsIter, Named};
 use num_traits::Bounded;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    corpus::{Corpus, SchedulerTestcaseMetadata},
+    corpus::{Corpus, CorpusId, HasCurrentCorpusId, SchedulerTestcaseMetadata},
     events::{Event, EventFirer, LogSeverity},
     executors::{Executor, ExitKind, HasObservers},
     feedbacks::{map::MapFeedbackMetadata, HasObserverHandle},
     fuzzer::Evaluator,
     monitors::{AggregatorOps, UserStats, UserStatsValue},
     observers::{MapObserver, ObserversTuple},
+    random_corpus_id,
     schedulers::powersched::SchedulerMetadata,
     stages::{Stage, StdRestartHelper},
-    state::{HasCorpus, HasCurrentTestcase, HasExecutions, UsesState},
+    state::{HasCorpus, HasExecutions, HasRand, UsesState},
     Error, HasMetadata, HasNamedMetadata,
 };
 
@@ -67,6 +69,35 @@ impl Default for UnstableEntriesMetadata {
     }
 }
 
+/// The entries a lazy [`CalibrationStage`] has only calibrated provisionally,
+/// waiting for their full calibration, see [`CalibrationStage::with_lazy_calibration`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct LazyCalibrationMetadata {
+    pending: VecDeque<CorpusId>,
+}
+impl_serdeany!(LazyCalibrationMetadata);
+
+impl LazyCalibrationMetadata {
+    /// The entries waiting for their full calibration, oldest first
+    #[must_use]
+    pub fn pending(&self) -> &VecDeque<CorpusId> {
+        &self.pending
+    }
+}
+
+/// Marks a testcase whose scheduling metadata is provisional, i.e. measured with a single execution
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct ProvisionalCalibrationMetadata;
+impl_serdeany!(ProvisionalCalibrationMetadata);
+
 /// Default name for `CalibrationStage`; derived from AFL++
 pub const CALIBRATION_STAGE_NAME: &str = "calibration";
 /// The calibration stage will measure the average exec time and the target's stability for this input.
@@ -78,6 +109,8 @@ pub struct CalibrationStage<C, E, O, OT> {
     stage_max: usize,
     /// If we should track stability
     track_stability: bool,
+    /// The number of deferred calibrations per run, if calibrating lazily
+    lazy_batch: Option<usize>,
     phantom: PhantomData<(E, O, OT)>,
 }
 
@@ -99,15 +132,10 @@ where
     C: AsRef<O>,
     for<'de> <O as MapObserver>::Entry: Serialize + Deserialize<'de> + 'static,
     OT: ObserversTuple<Self::State>,
-    Self::State: HasCorpus + HasMetadata + HasNamedMetadata + HasExecutions,
+    Self::State: HasCorpus + HasCurrentCorpusId + HasMetadata + HasNamedMetadata + HasExecutions,
     Z: Evaluator<E, EM, State = Self::State>,
 {
     #[inline]
-    #[allow(
-        clippy::let_and_return,
-        clippy::too_many_lines,
-        clippy::cast_precision_loss
-    )]
     fn perform(
         &mut self,
         fuzzer: &mut Z,
@@ -115,19 +143,102 @@ where
         state: &mut Self::State,
         mgr: &mut EM,
     ) -> Result<(), Error> {
+        let Some(corpus_id) = state.current_corpus_id()? else {
+            return Err(Error::illegal_state(
+                "state is not currently processing a corpus index",
+            ));
+        };
+
         // Run this stage only once for each corpus entry and only if we haven't already inspected it
-        {
-            let testcase = state.current_testcase()?;
-            // println!("calibration; corpus.scheduled_count() : {}", corpus.scheduled_count());
+        let calibrated = state.corpus().get(corpus_id)?.borrow().scheduled_count() > 0;
 
-            if testcase.scheduled_count() > 0 {
+        let Some(batch) = self.lazy_batch else {
+            if calibrated {
                 return Ok(());
             }
+            return self.calibrate(fuzzer, executor, state, mgr, corpus_id, false);
+        };
+
+        if !calibrated {
+            // Fuzz fresh entries right away, with scheduling metadata from a single execution
+            self.calibrate(fuzzer, executor, state, mgr, corpus_id, true)?;
+            state
+                .metadata_or_insert_with(LazyCalibrationMetadata::default)
+                .pending
+                .push_back(corpus_id);
+            return Ok(());
         }
 
-        let mut iter = self.stage_max;
-        // If we restarted after a timeout or crash, do less iterations.
-        let input = state.current_input_cloned()?;
+        // Catch up on the deferred calibrations while fuzzing entries we already know
+        for _ in 0..batch {
+            let Some(id) = state
+                .metadata_or_insert_with(LazyCalibrationMetadata::default)
+                .pending
+                .pop_front()
+            else {
+                break;
+            };
+            // The entry may have been removed in the meantime
+            if state.corpus().get(id).is_ok() {
+                self.calibrate(fuzzer, executor, state, mgr, id, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        // Calibration stage disallow restarts
+        // If a testcase that causes crash/timeout in the queue, we need to remove it from the queue immediately.
+        StdRestartHelper::no_retry(state, &self.name)
+
+        // todo
+        // remove this guy from corpus queue
+    }
+
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        // TODO: Make sure this is the correct way / there may be a better way?
+        StdRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+impl<C, E, O, OT> CalibrationStage<C, E, O, OT>
+where
+    E: UsesState,
+{
+    /// Calibrates the given corpus entry. A provisional calibration runs the entry only once,
+    /// without checking stability, and marks it with [`ProvisionalCalibrationMetadata`].
+    #[allow(
+        clippy::let_and_return,
+        clippy::too_many_lines,
+        clippy::cast_precision_loss
+    )]
+    fn calibrate<EM, Z>(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut E::State,
+        mgr: &mut EM,
+        corpus_id: CorpusId,
+        provisional: bool,
+    ) -> Result<(), Error>
+    where
+        E: Executor<EM, Z> + HasObservers<Observers = OT>,
+        EM: EventFirer<State = E::State>,
+        O: MapObserver,
+        C: AsRef<O>,
+        for<'de> <O as MapObserver>::Entry: Serialize + Deserialize<'de> + 'static,
+        OT: ObserversTuple<E::State>,
+        Z: UsesState<State = E::State>,
+        E::State: HasCorpus + HasMetadata + HasNamedMetadata + HasExecutions,
+    {
+        let mut iter = if provisional { 1 } else { self.stage_max };
+        let input = state.corpus().cloned_input_for_id(corpus_id)?;
+        // An entry calibrated provisionally before is already accounted for in the bitmap averages
+        let was_provisional = state
+            .corpus()
+            .get(corpus_id)?
+            .borrow()
+            .has_metadata::<ProvisionalCalibrationMetadata>();
 
         // Run once to get the initial calibration map
         executor.observers_mut().pre_exec_all(state, &input)?;
@@ -178,8 +289,6 @@ where
         let mut has_errors = false;
 
         while i < iter {
-            let input = state.current_input_cloned()?;
-
             executor.observers_mut().pre_exec_all(state, &input)?;
             start = current_time();
 
@@ -270,11 +379,14 @@ where
 
             psmeta.set_exec_time(psmeta.exec_time() + total_time);
             psmeta.set_cycles(psmeta.cycles() + (iter as u64));
-            psmeta.set_bitmap_size(psmeta.bitmap_size() + bitmap_size);
-            psmeta.set_bitmap_size_log(psmeta.bitmap_size_log() + libm::log2(bitmap_size as f64));
-            psmeta.set_bitmap_entries(psmeta.bitmap_entries() + 1);
+            if !was_provisional {
+                psmeta.set_bitmap_size(psmeta.bitmap_size() + bitmap_size);
+                psmeta
+                    .set_bitmap_size_log(psmeta.bitmap_size_log() + libm::log2(bitmap_size as f64));
+                psmeta.set_bitmap_entries(psmeta.bitmap_entries() + 1);
+            }
 
-            let mut testcase = state.current_testcase_mut()?;
+            let mut testcase = state.corpus().get(corpus_id)?.borrow_mut();
 
             testcase.set_exec_time(total_time / (iter as u32));
             // log::trace!("time: {:#?}", testcase.exec_time());
@@ -304,7 +416,20 @@ where
 
             data.set_cycle_and_time((total_time, iter));
             data.set_bitmap_size(bitmap_size);
-            data.set_handicap(handicap);
+            if !was_provisional {
+                data.set_handicap(handicap);
+            }
+        }
+
+        {
+            let mut testcase = state.corpus().get(corpus_id)?.borrow_mut();
+            if provisional {
+                testcase.add_metadata(ProvisionalCalibrationMetadata);
+            } else if was_provisional {
+                let _ = testcase
+                    .metadata_map_mut()
+                    .remove::<ProvisionalCalibrationMetadata>();
+            }
         }
 
         // Send the stability event to the broker
@@ -349,20 +474,6 @@ where
 
         Ok(())
     }
-
-    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
-        // Calibration stage disallow restarts
-        // If a testcase that causes crash/timeout in the queue, we need to remove it from the queue immediately.
-        StdRestartHelper::no_retry(state, &self.name)
-
-        // todo
-        // remove this guy from corpus queue
-    }
-
-    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
-        // TODO: Make sure this is the correct way / there may be a better way?
-        StdRestartHelper::clear_progress(state, &self.name)
-    }
 }
 
 impl<C, E, O, OT> CalibrationStage<C, E, O, OT>
@@ -385,6 +496,7 @@ where
             map_name: map_name.clone(),
             stage_max: CAL_STAGE_START,
             track_stability: true,
+            lazy_batch: None,
             phantom: PhantomData,
             name: Cow::Owned(
                 CALIBRATION_STAGE_NAME.to_owned() + ":" + map_name.into_owned().as_str(),
@@ -402,6 +514,15 @@ where
         ret.track_stability = false;
         ret
     }
+
+    /// Calibrate lazily: new corpus entries are executed only once to get provisional scheduling
+    /// metadata, so they can be fuzzed right away. Their full calibration is deferred to later runs
+    /// of this stage on already calibrated entries, at most `batch` per run.
+    #[must_use]
+    pub fn with_lazy_calibration(mut self, batch: usize) -> Self {
+        self.lazy_batch = Some(batch);
+        self
+    }
 }
 
 impl<C, E, O, OT> Named for CalibrationStage<C, E, O, OT> {
@@ -409,3 +530,528 @@ impl<C, E, O, OT> Named for CalibrationStage<C, E, O, OT> {
         &self.name
     }
 }
+
+/// The stability of a corpus entry, measured by a [`RecalibrationStage`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TestcaseStabilityMetadata {
+    /// The fraction of filled map entries that stayed the same over all runs
+    pub stability: f64,
+    /// The map entries that changed between runs
+    pub unstable_entries: Vec<usize>,
+    /// The time of the last recalibration, since the epoch
+    pub last_checked: Duration,
+}
+impl_serdeany!(TestcaseStabilityMetadata);
+
+/// The time of the last run of a [`RecalibrationStage`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct RecalibrationMetadata {
+    last_run: Duration,
+}
+impl_serdeany!(RecalibrationMetadata);
+
+impl RecalibrationMetadata {
+    /// The time of the last recalibration, since the epoch
+    #[must_use]
+    pub fn last_run(&self) -> Duration {
+        self.last_run
+    }
+}
+
+/// Default name for `RecalibrationStage`
+pub const RECALIBRATION_STAGE_NAME: &str = "recalibration";
+/// The default interval between two recalibrations
+pub const DEFAULT_RECALIBRATION_INTERVAL: Duration = Duration::from_secs(15 * 60);
+/// The default number of corpus entries to recalibrate at once
+pub const DEFAULT_RECALIBRATION_SAMPLE_SIZE: usize = 16;
+
+/// Periodically re-runs the calibration on a random sample of the corpus, to notice targets
+/// becoming less stable over time, e.g. because of time-dependent behavior.
+///
+/// Each sampled entry gets its [`TestcaseStabilityMetadata`] updated. If an ignore threshold is
+/// set, map entries that changed between runs for at least this fraction of the sampled entries
+/// covering them are marked as unstable, like the [`CalibrationStage`] does, so the map feedback
+/// ignores them from now on.
+#[derive(Clone, Debug)]
+pub struct RecalibrationStage<C, E, O, OT> {
+    map_observer_handle: Handle<C>,
+    map_name: Cow<'static, str>,
+    name: Cow<'static, str>,
+    interval: Duration,
+    sample_size: usize,
+    runs: usize,
+    ignore_threshold: Option<f64>,
+    phantom: PhantomData<(E, O, OT)>,
+}
+
+impl<C, E, O, OT> UsesState for RecalibrationStage<C, E, O, OT>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<C, E, EM, O, OT, Z> Stage<E, EM, Z> for RecalibrationStage<C, E, O, OT>
+where
+    E: Executor<EM, Z> + HasObservers<Observers = OT>,
+    EM: EventFirer<State = Self::State>,
+    O: MapObserver,
+    C: AsRef<O>,
+    for<'de> <O as MapObserver>::Entry: Serialize + Deserialize<'de> + 'static,
+    OT: ObserversTuple<Self::State>,
+    Self::State: HasCorpus + HasMetadata + HasNamedMetadata + HasRand,
+    Z: Evaluator<E, EM, State = Self::State>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        mgr: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        let Some(meta) = state
+            .named_metadata_map_mut()
+            .get_mut::<RecalibrationMetadata>(&self.name)
+        else {
+            // Start counting with the first run, the entries were just calibrated
+            state.add_named_metadata(&self.name, RecalibrationMetadata { last_run: now });
+            return Ok(());
+        };
+        if now.saturating_sub(meta.last_run) < self.interval {
+            return Ok(());
+        }
+        meta.last_run = now;
+
+        let count = state.corpus().count();
+        if count == 0 {
+            return Ok(());
+        }
+        let mut sample = Vec::with_capacity(self.sample_size.min(count));
+        while sample.len() < self.sample_size.min(count) {
+            let id = random_corpus_id!(state.corpus(), state.rand_mut());
+            if !sample.contains(&id) {
+                sample.push(id);
+            }
+        }
+
+        // For each map entry, how many sampled entries cover it, and how many of them were unstable
+        let mut covered: HashMap<usize, usize> = HashMap::new();
+        let mut flickered: HashMap<usize, usize> = HashMap::new();
+        for id in sample {
+            let unstable = self.recalibrate(fuzzer, executor, state, mgr, id, &mut covered)?;
+            for idx in unstable {
+                *flickered.entry(idx).or_default() += 1;
+            }
+        }
+
+        if let Some(threshold) = self.ignore_threshold {
+            #[allow(clippy::cast_precision_loss)]
+            let ignored: Vec<usize> = flickered
+                .into_iter()
+                .filter(|(idx, n)| *n as f64 / covered[idx].max(1) as f64 >= threshold)
+                .map(|(idx, _)| idx)
+                .collect();
+            self.ignore_entries(state, mgr, &ignored)?;
+        }
+        Ok(())
+    }
+
+    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        // Like the calibration, don't retry entries crashing the target
+        StdRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        StdRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+impl<C, E, O, OT> RecalibrationStage<C, E, O, OT>
+where
+    E: UsesState,
+{
+    /// Runs the corpus entry `self.runs` times, updating its [`TestcaseStabilityMetadata`].
+    /// Returns the map entries that changed between runs, and counts the covered ones in `covered`.
+    /// Runs that crashed or timed out take a different path and are not compared.
+    fn recalibrate<EM, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut E::State,
+        mgr: &mut EM,
+        corpus_id: CorpusId,
+        covered: &mut HashMap<usize, usize>,
+    ) -> Result<Vec<usize>, Error>
+    where
+        E: Executor<EM, Z> + HasObservers<Observers = OT>,
+        EM: EventFirer<State = E::State>,
+        O: MapObserver,
+        C: AsRef<O>,
+        OT: ObserversTuple<E::State>,
+        Z: UsesState<State = E::State>,
+        E::State: HasCorpus,
+    {
+        let input = state.corpus().cloned_input_for_id(corpus_id)?;
+        let mut first: Option<Vec<O::Entry>> = None;
+        let mut unstable = HashSet::new();
+        let mut initial = O::Entry::default();
+
+        for _ in 0..self.runs {
+            executor.observers_mut().pre_exec_all(state, &input)?;
+            let exit_kind = executor.run_target(fuzzer, state, mgr, &input)?;
+            executor
+                .observers_mut()
+                .post_exec_all(state, &input, &exit_kind)?;
+            if exit_kind != ExitKind::Ok {
+                continue;
+            }
+
+            let observers = executor.observers();
+            let observer = observers[&self.map_observer_handle].as_ref();
+            initial = observer.initial();
+            let map = observer.to_vec();
+            match &first {
+                None => first = Some(map),
+                Some(first) => unstable.extend(
+                    first
+                        .iter()
+                        .zip(map.iter())
+                        .enumerate()
+                        .filter(|(_, (first, cur))| first != cur)
+                        .map(|(idx, _)| idx),
+                ),
+            }
+        }
+
+        let Some(first) = first else {
+            return Ok(Vec::new());
+        };
+        let mut filled = 0;
+        for (idx, entry) in first.iter().enumerate() {
+            if *entry != initial || unstable.contains(&idx) {
+                filled += 1;
+                *covered.entry(idx).or_default() += 1;
+            }
+        }
+
+        let mut unstable_entries: Vec<usize> = unstable.into_iter().collect();
+        unstable_entries.sort_unstable();
+        #[allow(clippy::cast_precision_loss)]
+        let stability = if filled == 0 {
+            1.0
+        } else {
+            (filled - unstable_entries.len()) as f64 / filled as f64
+        };
+        state
+            .corpus()
+            .get(corpus_id)?
+            .borrow_mut()
+            .add_metadata(TestcaseStabilityMetadata {
+                stability,
+                unstable_entries: unstable_entries.clone(),
+                last_checked: current_time(),
+            });
+        Ok(unstable_entries)
+    }
+
+    /// Marks the map entries as unstable, so the map feedback ignores them, and reports the new
+    /// stability
+    fn ignore_entries<EM>(
+        &self,
+        state: &mut E::State,
+        mgr: &mut EM,
+        ignored: &[usize],
+    ) -> Result<(), Error>
+    where
+        EM: EventFirer<State = E::State>,
+        O: MapObserver,
+        for<'de> <O as MapObserver>::Entry: Serialize + Deserialize<'de> + 'static,
+        E::State: HasMetadata + HasNamedMetadata,
+    {
+        let Some(map_state) = state
+            .named_metadata_map_mut()
+            .get_mut::<MapFeedbackMetadata<O::Entry>>(&self.map_name)
+        else {
+            return Ok(());
+        };
+        let mut newly_ignored = false;
+        for idx in ignored {
+            if *idx >= map_state.history_map.len() {
+                map_state.history_map.resize(*idx + 1, O::Entry::default());
+            }
+            // Entries at the maximum value can never be novel, see the calibration
+            if map_state.history_map[*idx] != O::Entry::max_value() {
+                map_state.history_map[*idx] = O::Entry::max_value();
+                newly_ignored = true;
+            }
+        }
+        let filled_entries_count = map_state.num_covered_map_indexes;
+        if !newly_ignored {
+            return Ok(());
+        }
+
+        let metadata = state.metadata_or_insert_with(UnstableEntriesMetadata::new);
+        metadata.unstable_entries.extend(ignored.iter().copied());
+        metadata.filled_entries_count = filled_entries_count;
+        let unstable_entries = metadata.unstable_entries.len();
+        if filled_entries_count == 0 {
+            return Ok(());
+        }
+        mgr.fire(
+            state,
+            Event::UpdateUserStats {
+                name: Cow::from("stability"),
+                value: UserStats::new(
+                    UserStatsValue::Ratio(
+                        filled_entries_count.saturating_sub(unstable_entries) as u64,
+                        filled_entries_count as u64,
+                    ),
+                    AggregatorOps::Avg,
+                ),
+                phantom: PhantomData,
+            },
+        )
+    }
+}
+
+impl<C, E, O, OT> RecalibrationStage<C, E, O, OT>
+where
+    O: MapObserver,
+    C: AsRef<O>,
+    E: UsesState,
+{
+    /// Create a new [`RecalibrationStage`], recalibrating [`DEFAULT_RECALIBRATION_SAMPLE_SIZE`]
+    /// entries every [`DEFAULT_RECALIBRATION_INTERVAL`], without ignoring unstable map entries.
+    #[must_use]
+    pub fn new<F>(map_feedback: &F) -> Self
+    where
+        F: HasObserverHandle<Observer = C> + Named,
+    {
+        let map_name = map_feedback.name().clone();
+        Self {
+            map_observer_handle: map_feedback.observer_handle().clone(),
+            map_name: map_name.clone(),
+            name: Cow::Owned(
+                RECALIBRATION_STAGE_NAME.to_owned() + ":" + map_name.into_owned().as_str(),
+            ),
+            interval: DEFAULT_RECALIBRATION_INTERVAL,
+            sample_size: DEFAULT_RECALIBRATION_SAMPLE_SIZE,
+            runs: CAL_STAGE_MAX,
+            ignore_threshold: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the interval between two recalibrations
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the number of corpus entries to recalibrate at once
+    #[must_use]
+    pub fn with_sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size;
+        self
+    }
+
+    /// Sets the number of runs per recalibrated corpus entry, at least 2
+    #[must_use]
+    pub fn with_runs(mut self, runs: usize) -> Self {
+        self.runs = runs.max(2);
+        self
+    }
+
+    /// Ignores map entries that changed between runs for at least `threshold` (between 0 and 1)
+    /// of the sampled corpus entries covering them
+    #[must_use]
+    pub fn with_ignore_threshold(mut self, threshold: f64) -> Self {
+        self.ignore_threshold = Some(threshold);
+        self
+    }
+}
+
+impl<C, E, O, OT> Named for RecalibrationStage<C, E, O, OT> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use core::time::Duration;
+
+    use libafl_bolts::{tuples::tuple_list, AsSliceMut, HasLen};
+
+    use super::{
+        CalibrationStage, LazyCalibrationMetadata, ProvisionalCalibrationMetadata,
+        RecalibrationStage, TestcaseStabilityMetadata,
+    };
+    use crate::{
+        corpus::{Corpus, HasCurrentCorpusId, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        feedbacks::{ConstFeedback, MapFeedbackMetadata, MaxMapFeedback},
+        inputs::BytesInput,
+        observers::StdMapObserver,
+        schedulers::{powersched::SchedulerMetadata, QueueScheduler},
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus, HasExecutions},
+        HasMetadata, HasNamedMetadata, StdFuzzer,
+    };
+
+    #[test]
+    fn test_lazy_calibration() {
+        let observer = StdMapObserver::owned("map", vec![0_u8; 16]);
+        let feedback = MaxMapFeedback::new(&observer);
+        let mut stage = CalibrationStage::new(&feedback).with_lazy_calibration(1);
+        let mut fuzzer = StdFuzzer::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut executor = ClosureExecutor::new(
+            |observers: &mut (StdMapObserver<'static, u8, false>, ()), input: &BytesInput| {
+                observers.0.as_slice_mut()[input.len()] = 1;
+                ExitKind::Ok
+            },
+            tuple_list!(observer),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+        state.add_named_metadata("map", MapFeedbackMetadata::<u8>::new(16));
+        state.add_metadata(SchedulerMetadata::new(None));
+
+        let first = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![0; 2])))
+            .unwrap();
+        state.set_corpus_id(first).unwrap();
+
+        // A fresh entry runs once and waits for its full calibration
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*state.executions(), 1);
+        assert_eq!(
+            state
+                .metadata::<LazyCalibrationMetadata>()
+                .unwrap()
+                .pending()
+                .iter()
+                .copied()
+                .collect::<Vec<_>>(),
+            vec![first]
+        );
+        assert!(state
+            .corpus()
+            .get(first)
+            .unwrap()
+            .borrow()
+            .has_metadata::<ProvisionalCalibrationMetadata>());
+
+        // Fuzzing an already calibrated entry catches up on the deferred calibration
+        state
+            .corpus()
+            .get(first)
+            .unwrap()
+            .borrow_mut()
+            .set_scheduled_count(1);
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert!(*state.executions() > 1);
+        assert!(state
+            .metadata::<LazyCalibrationMetadata>()
+            .unwrap()
+            .pending()
+            .is_empty());
+        assert!(!state
+            .corpus()
+            .get(first)
+            .unwrap()
+            .borrow()
+            .has_metadata::<ProvisionalCalibrationMetadata>());
+        // The provisional run already accounted for the entry in the bitmap averages
+        assert_eq!(
+            state
+                .metadata::<SchedulerMetadata>()
+                .unwrap()
+                .bitmap_entries(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_recalibration_ignores_crashes() {
+        let observer = StdMapObserver::owned("map", vec![0_u8; 16]);
+        let feedback = MaxMapFeedback::new(&observer);
+        let mut stage = RecalibrationStage::new(&feedback)
+            .with_interval(Duration::ZERO)
+            .with_sample_size(1)
+            .with_runs(4)
+            .with_ignore_threshold(0.5);
+        let mut fuzzer = StdFuzzer::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut run = 0;
+        let mut executor = ClosureExecutor::new(
+            move |observers: &mut (StdMapObserver<'static, u8, false>, ()), _: &BytesInput| {
+                run += 1;
+                let map = observers.0.as_slice_mut();
+                map[0] = 1;
+                // Entry 1 flickers, the crashing run covers entry 2
+                map[1] = run % 2;
+                if run == 3 {
+                    map[2] = 1;
+                    return ExitKind::Crash;
+                }
+                ExitKind::Ok
+            },
+            tuple_list!(observer),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+        state.add_named_metadata("map", MapFeedbackMetadata::<u8>::new(16));
+        let id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![0; 2])))
+            .unwrap();
+
+        // The first run only starts the interval
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*state.executions(), 0);
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*state.executions(), 4);
+
+        let testcase = state.corpus().get(id).unwrap().borrow();
+        let stability = testcase.metadata::<TestcaseStabilityMetadata>().unwrap();
+        assert_eq!(stability.unstable_entries, vec![1]);
+        assert!((stability.stability - 0.5).abs() < f64::EPSILON);
+        drop(testcase);
+
+        let history = &state
+            .named_metadata::<MapFeedbackMetadata<u8>>("map")
+            .unwrap()
+            .history_map;
+        assert_eq!(history[1], u8::MAX);
+        assert_ne!(history[2], u8::MAX);
+    }
+}
@@ -0,0 +1,153 @@
+//! The [`CMinStage`] periodically minimizes the corpus with a [`CorpusMinimizer`].
+
+use alloc::borrow::Cow;
+use core::{marker::PhantomData, time::Duration};
+
+use libafl_bolts::{current_time, impl_serdeany, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusMinimizer, StdGreedyCorpusMinimizer},
+    events::EventFirer,
+    executors::{Executor, HasObservers},
+    schedulers::RemovableScheduler,
+    stages::Stage,
+    state::{HasCorpus, UsesState},
+    Error, HasMetadata, HasScheduler,
+};
+
+/// The default minimum time between two minimizations of the [`CMinStage`]
+pub const DEFAULT_CMIN_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The default minimum number of testcases added to the corpus before the [`CMinStage`] runs again
+pub const DEFAULT_CMIN_MIN_GROWTH: usize = 256;
+
+/// The progress of the [`CMinStage`], stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+pub struct CMinMetadata {
+    /// The time of the last minimization
+    pub last_run: Duration,
+    /// The corpus size after the last minimization
+    pub last_count: usize,
+    /// The number of minimizations so far
+    pub runs: u64,
+}
+
+impl_serdeany!(CMinMetadata);
+
+/// The [`CMinStage`] minimizes the corpus with a [`CorpusMinimizer`] once enough time passed and
+/// enough testcases were added since the last minimization, see [`CMinStage::with_interval`] and
+/// [`CMinStage::with_min_growth`].
+///
+/// The minimization re-executes the whole corpus, so keep the interval long.
+#[derive(Debug)]
+pub struct CMinStage<CM, E, EM, Z> {
+    name: Cow<'static, str>,
+    minimizer: CM,
+    interval: Duration,
+    min_growth: usize,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+/// The standard [`CMinStage`], using a [`StdGreedyCorpusMinimizer`] on the given map observer
+pub type StdCMinStage<C, E, EM, O, T, Z> =
+    CMinStage<StdGreedyCorpusMinimizer<C, E, O, T>, E, EM, Z>;
+
+impl<CM, E, EM, Z> UsesState for CMinStage<CM, E, EM, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<CM, E, EM, Z> CMinStage<CM, E, EM, Z> {
+    /// Creates a new [`CMinStage`] with the given minimizer, running at most once per
+    /// [`DEFAULT_CMIN_INTERVAL`] and after [`DEFAULT_CMIN_MIN_GROWTH`] new testcases
+    pub fn new(minimizer: CM) -> Self {
+        Self {
+            name: Cow::Borrowed("CMinStage"),
+            minimizer,
+            interval: DEFAULT_CMIN_INTERVAL,
+            min_growth: DEFAULT_CMIN_MIN_GROWTH,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the minimum time between two minimizations
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets the minimum number of testcases added to the corpus between two minimizations
+    #[must_use]
+    pub fn with_min_growth(mut self, min_growth: usize) -> Self {
+        self.min_growth = min_growth;
+        self
+    }
+
+    /// The underlying minimizer
+    pub fn minimizer(&self) -> &CM {
+        &self.minimizer
+    }
+}
+
+impl<CM, E, EM, Z> Stage<E, EM, Z> for CMinStage<CM, E, EM, Z>
+where
+    CM: CorpusMinimizer<E>,
+    E: Executor<EM, Z> + HasObservers,
+    EM: EventFirer<State = Self::State>,
+    Z: HasScheduler<State = Self::State>,
+    Z::Scheduler: RemovableScheduler,
+    Self::State: HasCorpus + HasMetadata,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        let count = state.corpus().count();
+        let meta = state.metadata_or_insert_with(CMinMetadata::default);
+        if (meta.runs > 0 && now < meta.last_run + self.interval)
+            || count < meta.last_count + self.min_growth
+        {
+            return Ok(());
+        }
+        // Store the progress first, so a crash during the minimization does not cause a loop
+        meta.last_run = now;
+        meta.last_count = count;
+        meta.runs += 1;
+
+        self.minimizer
+            .minimize::<Z::Scheduler, EM, Z>(fuzzer, executor, manager, state)?;
+
+        let count = state.corpus().count();
+        state.metadata_mut::<CMinMetadata>()?.last_count = count;
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // The progress is stored in the metadata before minimizing
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<CM, E, EM, Z> Named for CMinStage<CM, E, EM, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
@@ -14,17 +14,21 @@ use libafl_bolts::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    corpus::{CorpusId, HasCurrentCorpusId},
     events::EventFirer,
     executors::{Executor, HasObservers},
     inputs::HasMutatorBytes,
     mutators::mutations::buffer_copy,
     observers::{MapObserver, ObserversTuple},
-    stages::{Stage, StdRestartHelper},
-    state::{HasCorpus, HasCurrentTestcase, HasRand, UsesState},
+    stages::{Stage, StageCheckpoint, StdRestartHelper},
+    state::{
+        HasCorpus, HasCurrentTestcase, HasRand, HasStageRegisters, StageRegister,
+        StageRegisterDeclarations, UsesState,
+    },
     Error, HasMetadata, HasNamedMetadata,
 };
 
-// Bigger range is better
+// Bigger range is better, then the earlier one, so the ranges are always tried in the same order
 #[derive(Debug, PartialEq, Eq)]
 struct Bigger(Range<usize>);
 
@@ -36,7 +40,10 @@ impl PartialOrd for Bigger {
 
 impl Ord for Bigger {
     fn cmp(&self, other: &Bigger) -> Ordering {
-        self.0.len().cmp(&other.0.len())
+        self.0
+            .len()
+            .cmp(&other.0.len())
+            .then_with(|| other.0.start.cmp(&self.0.start))
     }
 }
 
@@ -58,6 +65,41 @@ impl Ord for Earlier {
 
 /// Default name for `ColorizationStage`; derived from ALF++
 pub const COLORIZATION_STAGE_NAME: &str = "colorization";
+
+/// The number of executions between two [`StageCheckpoint`]s of a [`ColorizationStage`]
+pub const COLORIZATION_CHECKPOINT_INTERVAL: usize = 256;
+
+/// The [`StageCheckpoint`] key of the [`ColorizationSnapshot`]
+const COLORIZATION_SNAPSHOT_KEY: &str = "snapshot";
+
+/// The state of a [`ColorizationStage`] at the start of an iteration,
+/// saved every [`COLORIZATION_CHECKPOINT_INTERVAL`] executions
+#[derive(Debug, Serialize, Deserialize)]
+struct ColorizationSnapshot {
+    iteration: usize,
+    orig_hash: usize,
+    input: Vec<u8>,
+    changed: Vec<u8>,
+    ranges: Vec<Range<usize>>,
+    ok_ranges: Vec<Range<usize>>,
+}
+
+/// The iteration of a [`ColorizationStage`] in flight, kept as named metadata and updated in place
+/// before each execution. After a restart, the stage resumes from its [`ColorizationSnapshot`] and
+/// skips the iterations that made the client restart.
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Serialize, Deserialize)]
+struct ColorizationProgress {
+    corpus_id: CorpusId,
+    iteration: usize,
+    skipped: Vec<usize>,
+}
+
+libafl_bolts::impl_serdeany!(ColorizationProgress);
+
 /// The mutational stage using power schedules
 #[derive(Clone, Debug)]
 pub struct ColorizationStage<C, E, EM, O, Z> {
@@ -87,7 +129,7 @@ impl<C, E, EM, O, Z> Stage<E, EM, Z> for ColorizationStage<C, E, EM, O, Z>
 where
     EM: UsesState<State = Self::State> + EventFirer,
     E: HasObservers + Executor<EM, Z>,
-    Self::State: HasCorpus + HasMetadata + HasRand + HasNamedMetadata,
+    Self::State: HasCorpus + HasMetadata + HasRand + HasNamedMetadata + HasCurrentCorpusId,
     E::Input: HasMutatorBytes,
     O: MapObserver,
     C: AsRef<O> + Named,
@@ -103,12 +145,32 @@ where
         manager: &mut EM,
     ) -> Result<(), Error> {
         // Run with the mutated input
-        Self::colorize(fuzzer, executor, state, manager, &self.map_observer_handle)?;
+        Self::colorize(
+            fuzzer,
+            executor,
+            state,
+            manager,
+            &self.map_observer_handle,
+            &self.name,
+        )?;
 
         Ok(())
     }
 
     fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        let corpus_id = state.current_corpus_id()?;
+        if let Some(progress) = state
+            .named_metadata_map_mut()
+            .get_mut::<ColorizationProgress>(&self.name)
+        {
+            if Some(progress.corpus_id) == corpus_id {
+                // Resume from the checkpoint, skipping the iteration that made the client restart
+                if !progress.skipped.contains(&progress.iteration) {
+                    progress.skipped.push(progress.iteration);
+                }
+                return Ok(true);
+            }
+        }
         // This is a deterministic stage
         // Once it failed, then don't retry,
         // It will just fail again
@@ -116,10 +178,18 @@ where
     }
 
     fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        let _ = state.remove_named_metadata::<ColorizationProgress>(&self.name);
         StdRestartHelper::clear_progress(state, &self.name)
     }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        registers.produces(&TAINT_REGISTER);
+    }
 }
 
+/// The register the [`ColorizationStage`] writes the [`TaintMetadata`] of the current testcase to
+pub const TAINT_REGISTER: StageRegister<TaintMetadata> = StageRegister::new("taint", 1);
+
 /// Store the taint and the input
 #[derive(Debug, Serialize, Deserialize)]
 #[cfg_attr(
@@ -165,60 +235,110 @@ where
     O: MapObserver,
     C: AsRef<O> + Named,
     E: HasObservers + Executor<EM, Z>,
-    <Self as UsesState>::State: HasCorpus + HasMetadata + HasRand,
+    <Self as UsesState>::State:
+        HasCorpus + HasMetadata + HasNamedMetadata + HasRand + HasCurrentCorpusId,
     E::Input: HasMutatorBytes,
     Z: UsesState<State = <Self as UsesState>::State>,
 {
     #[inline]
-    #[allow(clippy::let_and_return)]
+    #[allow(clippy::let_and_return, clippy::too_many_lines)]
     fn colorize(
         fuzzer: &mut Z,
         executor: &mut E,
         state: &mut <Self as UsesState>::State,
         manager: &mut EM,
         observer_handle: &Handle<C>,
+        name: &str,
     ) -> Result<E::Input, Error> {
         let mut input = state.current_input_cloned()?;
         // The backup of the input
         let backup = input.clone();
         // This is the buffer we'll randomly mutate during type_replace
         let mut changed = input.clone();
-
-        // input will be consumed so clone it
-        let consumed_input = input.clone();
-
-        // First, run orig_input once and get the original hash
-
-        // Idea: No need to do this every time
-        let orig_hash = Self::get_raw_map_hash_run(
-            fuzzer,
-            executor,
-            state,
-            manager,
-            consumed_input,
-            observer_handle,
-        )?;
-        let changed_bytes = changed.bytes_mut();
-        let input_len = changed_bytes.len();
+        let input_len = changed.bytes().len();
 
         // Binary heap, pop is logN, insert is logN
         // We will separate this range into smaller ranges.
         // Keep it sorted, we want biggest ones to come first
         let mut ranges = BinaryHeap::new();
-        ranges.push(Bigger(0..input_len));
 
         // This heap contains the smaller ranges. Changes inside them does not affect the coverage.
         // Keep it sorted, we want the earliest ones to come first so that it's easier to sort them
         let mut ok_ranges = BinaryHeap::new();
 
-        // println!("Replaced bytes: {:#?}", changed_bytes);
-        // Now replace with random values (This is type_replace)
-        Self::type_replace(changed_bytes, state);
+        let snapshot = if state.has_named_metadata::<ColorizationProgress>(name) {
+            StageCheckpoint::load::<_, ColorizationSnapshot>(
+                state,
+                name,
+                COLORIZATION_SNAPSHOT_KEY,
+            )?
+            .filter(|snapshot| snapshot.input.len() == input_len)
+        } else {
+            None
+        };
+        let (orig_hash, first_iteration) = if let Some(snapshot) = snapshot {
+            // Resume the interrupted colorization
+            input.bytes_mut().copy_from_slice(&snapshot.input);
+            changed.bytes_mut().copy_from_slice(&snapshot.changed);
+            ranges.extend(snapshot.ranges.into_iter().map(Bigger));
+            ok_ranges.extend(snapshot.ok_ranges.into_iter().map(Earlier));
+            (snapshot.orig_hash, snapshot.iteration)
+        } else {
+            // input will be consumed so clone it
+            let consumed_input = input.clone();
+
+            // First, run orig_input once and get the original hash
+
+            // Idea: No need to do this every time
+            let orig_hash = Self::get_raw_map_hash_run(
+                fuzzer,
+                executor,
+                state,
+                manager,
+                consumed_input,
+                observer_handle,
+            )?;
+            ranges.push(Bigger(0..input_len));
+
+            // println!("Replaced bytes: {:#?}", changed_bytes);
+            // Now replace with random values (This is type_replace)
+            Self::type_replace(changed.bytes_mut(), state);
+
+            let corpus_id = state.current_corpus_id()?.ok_or_else(|| {
+                Error::illegal_state("state is not currently processing a corpus index")
+            })?;
+            state.add_named_metadata(
+                name,
+                ColorizationProgress {
+                    corpus_id,
+                    iteration: 0,
+                    skipped: Vec::new(),
+                },
+            );
+            (orig_hash, 0)
+        };
 
         // println!("Replaced bytes: {:#?}", changed_bytes);
         // What we do is now to separate the input into smaller regions
         // And in each small regions make sure changing those bytes in the regions does not affect the coverage
-        for _ in 0..input_len * 2 {
+        for iteration in first_iteration..input_len * 2 {
+            if iteration % COLORIZATION_CHECKPOINT_INTERVAL == 0 {
+                let snapshot = ColorizationSnapshot {
+                    iteration,
+                    orig_hash,
+                    input: input.bytes().to_vec(),
+                    changed: changed.bytes().to_vec(),
+                    ranges: ranges.iter().map(|b: &Bigger| b.0.clone()).collect(),
+                    ok_ranges: ok_ranges.iter().map(|e: &Earlier| e.0.clone()).collect(),
+                };
+                StageCheckpoint::save(state, name, COLORIZATION_SNAPSHOT_KEY, &snapshot)?;
+                // Replaying from the new snapshot starts with this iteration
+                state
+                    .named_metadata_mut::<ColorizationProgress>(name)?
+                    .skipped
+                    .retain(|skipped| *skipped >= iteration);
+            }
+
             if let Some(b) = ranges.pop() {
                 // Let's try the largest one (ranges is sorted)
                 let r = b.0;
@@ -235,17 +355,25 @@ where
                     );
                 }
 
-                let consumed_input = input.clone();
-                let changed_hash = Self::get_raw_map_hash_run(
-                    fuzzer,
-                    executor,
-                    state,
-                    manager,
-                    consumed_input,
-                    observer_handle,
-                )?;
-
-                if orig_hash == changed_hash {
+                let progress = state.named_metadata_mut::<ColorizationProgress>(name)?;
+                // The change made the client restart before, so it is not safe
+                let safe = if progress.skipped.contains(&iteration) {
+                    false
+                } else {
+                    progress.iteration = iteration;
+                    let consumed_input = input.clone();
+                    let changed_hash = Self::get_raw_map_hash_run(
+                        fuzzer,
+                        executor,
+                        state,
+                        manager,
+                        consumed_input,
+                        observer_handle,
+                    )?;
+                    orig_hash == changed_hash
+                };
+
+                if safe {
                     // The change in this range is safe!
                     // println!("this range safe to change: {:#?}", range_start..range_end);
 
@@ -299,14 +427,10 @@ where
             }
         }
 
-        if let Some(meta) = state.metadata_map_mut().get_mut::<TaintMetadata>() {
-            meta.update(input.bytes().to_vec(), res);
-
-            // println!("meta: {:#?}", meta);
-        } else {
-            let meta = TaintMetadata::new(input.bytes().to_vec(), res);
-            state.add_metadata::<TaintMetadata>(meta);
-        }
+        state.write_register(
+            &TAINT_REGISTER,
+            TaintMetadata::new(input.bytes().to_vec(), res),
+        );
 
         Ok(input)
     }
@@ -443,3 +567,73 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use core::cell::Cell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use libafl_bolts::{tuples::tuple_list, AsSliceMut, Named};
+
+    use super::{ColorizationProgress, ColorizationStage, TAINT_REGISTER};
+    use crate::{
+        corpus::{Corpus, HasCurrentCorpusId, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        fuzzer::test::NopFuzzer,
+        inputs::{BytesInput, HasMutatorBytes},
+        observers::StdMapObserver,
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus, HasStageRegisters},
+        HasNamedMetadata,
+    };
+
+    #[test]
+    fn test_colorization_resumes() {
+        let observer = StdMapObserver::owned("map", vec![0_u8; 256]);
+        let mut stage = ColorizationStage::new(&observer);
+        let crashes = Cell::new(0_usize);
+        let mut executor = ClosureExecutor::new(
+            |observers: &mut (StdMapObserver<'static, u8, false>, ()), input: &BytesInput| {
+                let bytes = input.bytes();
+                // Only the first byte changes the coverage
+                observers.0.as_slice_mut().fill(0);
+                observers.0.as_slice_mut()[bytes[0] as usize] = 1;
+                if bytes[..4] == *b"aaaa" && bytes[4..].iter().all(|byte| *byte != b'a') {
+                    crashes.set(crashes.get() + 1);
+                    panic!("simulated crash");
+                }
+                ExitKind::Ok
+            },
+            tuple_list!(observer),
+        );
+        let mut fuzzer = NopFuzzer::new();
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+
+        let corpus_id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"aaaaaaaa".to_vec())))
+            .unwrap();
+        state.set_corpus_id(corpus_id).unwrap();
+
+        // The client "restarts" while colorizing the second half
+        assert!(catch_unwind(AssertUnwindSafe(|| {
+            stage.perform_restartable(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+        }))
+        .is_err());
+        assert_eq!(crashes.get(), 1);
+
+        // The stage resumes, instead of giving up, and skips the crashing change
+        stage
+            .perform_restartable(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(crashes.get(), 1);
+        assert_eq!(
+            *state.register(&TAINT_REGISTER).unwrap().ranges(),
+            vec![1..8]
+        );
+        assert!(!state.has_named_metadata::<ColorizationProgress>(stage.name()));
+    }
+}
@@ -116,7 +116,11 @@ impl<'a, EM, TE, Z> ConcolicTracingStage<'a, EM, TE, Z> {
 
 #[cfg(feature = "concolic_mutation")]
 #[allow(clippy::too_many_lines)]
-fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<Vec<(usize, u8)>> {
+fn generate_mutations(
+    iter: impl Iterator<Item = (SymExprRef, SymExpr)>,
+    options: &ConcolicSolverOptions,
+    mut flipped: Option<&mut FlippedBranchesMetadata>,
+) -> Vec<Vec<(usize, u8)>> {
     use hashbrown::HashMap;
     use z3::{
         ast::{Ast, Bool, Dynamic, BV},
@@ -156,7 +160,7 @@ fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<
     let mut res = Vec::new();
 
     let mut cfg = Config::new();
-    cfg.set_timeout_msec(10_000);
+    cfg.set_timeout_msec(options.timeout_ms);
     let ctx = Context::new(&cfg);
     let solver = Solver::new(&ctx);
 
@@ -298,13 +302,27 @@ fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<
         if let Some(expr) = z3_expr {
             translation.insert(id, expr);
         } else if let SymExpr::PathConstraint {
-            constraint, taken, ..
+            constraint,
+            taken,
+            location,
         } = msg
         {
             let op = translation[&constraint].as_bool().unwrap();
             let op = if taken { op } else { op.not() }.simplify();
+            let branch = (usize::from(location), !taken);
             if op.as_bool().is_some() {
                 // this constraint is useless, as it is always sat or unsat
+            } else if flipped
+                .as_ref()
+                .is_some_and(|flipped| flipped.contains(branch))
+            {
+                // this branch direction was already solved for, only follow the path
+                solver.assert(&op);
+            } else if options
+                .max_solutions
+                .is_some_and(|max_solutions| res.len() >= max_solutions)
+            {
+                return res;
             } else {
                 let negated_constraint = op.not().simplify();
                 solver.push();
@@ -312,6 +330,8 @@ fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<
                 match solver.check() {
                     z3::SatResult::Unsat => {
                         // negation is unsat => no mutation
+                        // The branch direction may still be reachable on other paths, so it is
+                        // not marked as flipped
                         solver.pop(1);
                         // check that out path is ever still sat, otherwise, we can stop trying
                         if matches!(
@@ -346,6 +366,9 @@ fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<
                         }
                         res.push(replacements);
                         solver.pop(1);
+                        if let Some(flipped) = flipped.as_deref_mut() {
+                            flipped.insert(branch);
+                        }
                     }
                 };
                 // assert the path constraint
@@ -357,11 +380,86 @@ fn generate_mutations(iter: impl Iterator<Item = (SymExprRef, SymExpr)>) -> Vec<
     res
 }
 
+/// The default timeout of a single solver query, in milliseconds
+#[cfg(feature = "concolic_mutation")]
+pub const DEFAULT_SOLVER_TIMEOUT_MS: u32 = 10_000;
+
+/// Options for solving the path constraints of a concolic trace
+#[cfg(feature = "concolic_mutation")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConcolicSolverOptions {
+    /// The timeout of a single solver query, in milliseconds
+    pub timeout_ms: u32,
+    /// The maximum number of new inputs to solve for per trace, `None` for all branches
+    pub max_solutions: Option<usize>,
+    /// If branches already flipped for another testcase should be skipped,
+    /// see [`FlippedBranchesMetadata`]
+    pub dedup_branches: bool,
+}
+
+#[cfg(feature = "concolic_mutation")]
+impl Default for ConcolicSolverOptions {
+    fn default() -> Self {
+        Self {
+            timeout_ms: DEFAULT_SOLVER_TIMEOUT_MS,
+            max_solutions: None,
+            dedup_branches: false,
+        }
+    }
+}
+
+/// The branch directions, as location and taken flag, the concolic mutational stage already
+/// solved for. With branch deduplication, each is only solved for once
+/// per campaign, like `SymCC` does.
+#[cfg(feature = "concolic_mutation")]
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct FlippedBranchesMetadata {
+    flipped: hashbrown::HashSet<(usize, bool)>,
+}
+
+#[cfg(feature = "concolic_mutation")]
+libafl_bolts::impl_serdeany!(FlippedBranchesMetadata);
+
+#[cfg(feature = "concolic_mutation")]
+impl FlippedBranchesMetadata {
+    /// If the branch direction was solved for already
+    #[must_use]
+    pub fn contains(&self, branch: (usize, bool)) -> bool {
+        self.flipped.contains(&branch)
+    }
+
+    /// Marks the branch direction as solved for
+    pub fn insert(&mut self, branch: (usize, bool)) {
+        self.flipped.insert(branch);
+    }
+
+    /// The number of branch directions solved for
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.flipped.len()
+    }
+
+    /// If no branch direction was solved for yet
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.flipped.is_empty()
+    }
+}
+
 /// A mutational stage that uses Z3 to solve concolic constraints attached to the [`crate::corpus::Testcase`] by the [`ConcolicTracingStage`].
+///
+/// For each path constraint of the trace, it negates the branch condition, solves for an input
+/// taking the other direction, and evaluates it, adding it to the corpus if interesting.
+/// Together with a [`ConcolicTracingStage`] this forms a complete hybrid fuzzing loop.
 #[cfg(feature = "concolic_mutation")]
 #[derive(Clone, Debug, Default)]
 pub struct SimpleConcolicMutationalStage<Z> {
     name: Cow<'static, str>,
+    options: ConcolicSolverOptions,
     phantom: PhantomData<Z>,
 }
 
@@ -411,18 +509,33 @@ where
         }
         let testcase = state.current_testcase()?.clone();
 
+        // Taken out of the state while solving, and put back afterwards
+        let mut flipped = self.options.dedup_branches.then(|| {
+            state
+                .metadata_map_mut()
+                .remove::<FlippedBranchesMetadata>()
+                .map(|flipped| *flipped)
+                .unwrap_or_default()
+        });
         let mutations = testcase.metadata::<ConcolicMetadata>().ok().map(|meta| {
             start_timer!(state);
-            let mutations = { generate_mutations(meta.iter_messages()) };
+            let mutations =
+                { generate_mutations(meta.iter_messages(), &self.options, flipped.as_mut()) };
             mark_feature_time!(state, PerfFeature::Mutate);
             mutations
         });
+        if let Some(flipped) = flipped {
+            state.add_metadata(flipped);
+        }
 
         if let Some(mutations) = mutations {
             for mutation in mutations {
                 let mut input_copy = state.current_input_cloned()?;
                 for (index, new_byte) in mutation {
-                    input_copy.bytes_mut()[index] = new_byte;
+                    // The solver may assign input bytes the target read out of bounds
+                    if let Some(byte) = input_copy.bytes_mut().get_mut(index) {
+                        *byte = new_byte;
+                    }
                 }
                 // Time is measured directly the `evaluate_input` function
                 fuzzer.evaluate_input(state, executor, manager, input_copy)?;
@@ -460,7 +573,121 @@ impl<Z> SimpleConcolicMutationalStage<Z> {
             name: Cow::Owned(
                 SIMPLE_CONCOLIC_MUTATIONAL_NAME.to_owned() + ":" + stage_id.to_string().as_str(),
             ),
+            options: ConcolicSolverOptions::default(),
             phantom: PhantomData,
         }
     }
+
+    /// Construct this stage with the given [`ConcolicSolverOptions`]
+    #[must_use]
+    pub fn with_options(options: ConcolicSolverOptions) -> Self {
+        let mut stage = Self::new();
+        stage.options = options;
+        stage
+    }
+
+    /// Sets the timeout of a single solver query, in milliseconds
+    #[must_use]
+    pub fn with_timeout_ms(mut self, timeout_ms: u32) -> Self {
+        self.options.timeout_ms = timeout_ms;
+        self
+    }
+
+    /// Solves for at most `max_solutions` new inputs per testcase
+    #[must_use]
+    pub fn with_max_solutions(mut self, max_solutions: usize) -> Self {
+        self.options.max_solutions = Some(max_solutions);
+        self
+    }
+
+    /// Solves for each branch direction only once per campaign, see [`FlippedBranchesMetadata`]
+    #[must_use]
+    pub fn with_branch_dedup(mut self) -> Self {
+        self.options.dedup_branches = true;
+        self
+    }
+
+    /// The [`ConcolicSolverOptions`] of this stage
+    #[must_use]
+    pub fn options(&self) -> &ConcolicSolverOptions {
+        &self.options
+    }
+}
+
+#[cfg(all(test, feature = "concolic_mutation"))]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use core::num::NonZeroUsize;
+
+    use super::{generate_mutations, ConcolicSolverOptions, FlippedBranchesMetadata};
+    use crate::observers::concolic::{SymExpr, SymExprRef};
+
+    fn expr_ref(id: usize) -> SymExprRef {
+        NonZeroUsize::new(id).unwrap()
+    }
+
+    /// A trace of `if input[0] == 'A' {}` not taken, followed by `if input[0] != 'A' {}` taken
+    fn trace() -> Vec<(SymExprRef, SymExpr)> {
+        vec![
+            (
+                expr_ref(1),
+                SymExpr::InputByte {
+                    offset: 0,
+                    value: b'B',
+                },
+            ),
+            (
+                expr_ref(2),
+                SymExpr::Integer {
+                    value: u64::from(b'A'),
+                    bits: 8,
+                },
+            ),
+            (
+                expr_ref(3),
+                SymExpr::Equal {
+                    a: expr_ref(1),
+                    b: expr_ref(2),
+                },
+            ),
+            (
+                expr_ref(4),
+                SymExpr::PathConstraint {
+                    constraint: expr_ref(3),
+                    taken: false,
+                    location: 1.into(),
+                },
+            ),
+            (expr_ref(5), SymExpr::Not { op: expr_ref(3) }),
+            (
+                expr_ref(6),
+                SymExpr::PathConstraint {
+                    constraint: expr_ref(5),
+                    taken: true,
+                    location: 2.into(),
+                },
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_branch_dedup() {
+        let options = ConcolicSolverOptions {
+            dedup_branches: true,
+            ..ConcolicSolverOptions::default()
+        };
+        let mut flipped = FlippedBranchesMetadata::default();
+
+        let mutations = generate_mutations(trace().into_iter(), &options, Some(&mut flipped));
+        assert_eq!(mutations, vec![vec![(0, b'A')]]);
+        assert!(flipped.contains((1, true)));
+        // the second branch can't be flipped on this path, but may be reachable on others
+        assert!(!flipped.contains((2, false)));
+        assert_eq!(flipped.len(), 1);
+
+        // the first branch is not solved for again
+        let mutations = generate_mutations(trace().into_iter(), &options, Some(&mut flipped));
+        assert!(mutations.is_empty());
+        assert_eq!(flipped.len(), 1);
+    }
 }
@@ -0,0 +1,142 @@
+//! The [`CorpusGcStage`] periodically prunes the corpus with a [`CorpusGc`].
+
+use alloc::borrow::Cow;
+use core::{marker::PhantomData, time::Duration};
+
+use libafl_bolts::{current_time, impl_serdeany, HasLen, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusGc},
+    events::{EventFirer, LogSeverity},
+    schedulers::RemovableScheduler,
+    stages::Stage,
+    state::{HasCorpus, HasExecutions, UsesState},
+    Error, HasMetadata, HasScheduler,
+};
+
+/// The default minimum time between two collections of the [`CorpusGcStage`]
+pub const DEFAULT_CORPUS_GC_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// The progress of the [`CorpusGcStage`], stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+pub struct CorpusGcMetadata {
+    /// The time of the last collection
+    pub last_run: Duration,
+    /// The number of collections so far
+    pub runs: u64,
+    /// The number of testcases collected so far
+    pub collected: u64,
+}
+
+impl_serdeany!(CorpusGcMetadata);
+
+/// The [`CorpusGcStage`] runs a [`CorpusGc`] on the corpus once per interval,
+/// see [`CorpusGcStage::with_interval`].
+///
+/// It does not execute the target, so it is cheap compared to the `CMinStage`.
+#[derive(Debug)]
+pub struct CorpusGcStage<E, EM, Z> {
+    name: Cow<'static, str>,
+    gc: CorpusGc,
+    interval: Duration,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, Z> UsesState for CorpusGcStage<E, EM, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, EM, Z> CorpusGcStage<E, EM, Z> {
+    /// Creates a new [`CorpusGcStage`] running the given [`CorpusGc`] at most once per
+    /// [`DEFAULT_CORPUS_GC_INTERVAL`]
+    #[must_use]
+    pub fn new(gc: CorpusGc) -> Self {
+        Self {
+            name: Cow::Borrowed("CorpusGcStage"),
+            gc,
+            interval: DEFAULT_CORPUS_GC_INTERVAL,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the minimum time between two collections
+    #[must_use]
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The underlying garbage collector
+    #[must_use]
+    pub fn gc(&self) -> &CorpusGc {
+        &self.gc
+    }
+}
+
+impl<E, EM, Z> Stage<E, EM, Z> for CorpusGcStage<E, EM, Z>
+where
+    E: UsesState,
+    EM: EventFirer<State = Self::State>,
+    Z: HasScheduler<State = Self::State>,
+    Z::Scheduler: RemovableScheduler,
+    Self::State: HasCorpus + HasExecutions + HasMetadata,
+    Self::Input: HasLen,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        let meta = state.metadata_or_insert_with(CorpusGcMetadata::default);
+        if meta.runs > 0 && now < meta.last_run + self.interval {
+            return Ok(());
+        }
+        meta.last_run = now;
+        meta.runs += 1;
+
+        let collected = self.gc.collect(state, fuzzer.scheduler_mut())?;
+        if collected.is_empty() {
+            return Ok(());
+        }
+        state.metadata_mut::<CorpusGcMetadata>()?.collected += collected.len() as u64;
+        let count = state.corpus().count();
+        manager.log(
+            state,
+            LogSeverity::Info,
+            format!(
+                "Corpus GC collected {} testcases, {count} remain",
+                collected.len()
+            ),
+        )?;
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(())
+    }
+}
+
+impl<E, EM, Z> Named for CorpusGcStage<E, EM, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
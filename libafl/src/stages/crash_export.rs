@@ -0,0 +1,142 @@
+//! The [`CrashExportStage`] exports the metadata of new objectives for external triage pipelines.
+
+use alloc::{borrow::Cow, string::String};
+use core::marker::PhantomData;
+use std::{fs, path::PathBuf};
+
+use libafl_bolts::{impl_serdeany, AsSlice, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId},
+    inputs::{HasTargetBytes, UsesInput},
+    stages::Stage,
+    state::{HasSolutions, UsesState},
+    triage::{export_testcase, CrashExportFormat},
+    Error, HasMetadata,
+};
+
+/// The progress of the [`CrashExportStage`], stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+pub struct CrashExportMetadata {
+    last_solution: Option<CorpusId>,
+}
+
+impl_serdeany!(CrashExportMetadata);
+
+/// The [`CrashExportStage`] writes the metadata of each new objective to
+/// `<dir>/<testcase name>.<extension>` in a [`CrashExportFormat`] understood by existing triage
+/// pipelines, such as `ClusterFuzz` or `CASR`.
+///
+/// Put it after the stages adding metadata to objectives, e.g. the `ExploitabilityStage`.
+#[derive(Debug)]
+pub struct CrashExportStage<EM, Z> {
+    name: Cow<'static, str>,
+    dir: PathBuf,
+    format: CrashExportFormat,
+    phantom: PhantomData<(EM, Z)>,
+}
+
+impl<EM, Z> UsesState for CrashExportStage<EM, Z>
+where
+    EM: UsesState,
+{
+    type State = EM::State;
+}
+
+impl<EM, Z> CrashExportStage<EM, Z> {
+    /// Creates a new [`CrashExportStage`] writing to `dir` in the given format
+    pub fn new<P>(dir: P, format: CrashExportFormat) -> Result<Self, Error>
+    where
+        P: Into<PathBuf>,
+    {
+        let dir = dir.into();
+        if let Err(e) = fs::create_dir_all(&dir) {
+            return Err(Error::os_error(
+                e,
+                format!("Error creating directory {dir:?}"),
+            ));
+        }
+        Ok(Self {
+            name: Cow::Borrowed("CrashExportStage"),
+            dir,
+            format,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The format of the exported objectives
+    #[must_use]
+    pub fn format(&self) -> CrashExportFormat {
+        self.format
+    }
+}
+
+impl<E, EM, Z> Stage<E, EM, Z> for CrashExportStage<EM, Z>
+where
+    EM: UsesState,
+    E: UsesState<State = Self::State>,
+    Z: UsesState<State = Self::State>,
+    Self::State: HasSolutions + HasMetadata,
+    <Self::State as UsesInput>::Input: HasTargetBytes,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut Self::State,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        let mut next = match state
+            .metadata_or_insert_with(CrashExportMetadata::default)
+            .last_solution
+        {
+            Some(id) => state.solutions().next(id),
+            None => state.solutions().first(),
+        };
+
+        while let Some(id) = next {
+            let (name, json) = {
+                let mut testcase = state.solutions().get(id)?.borrow_mut();
+                state.solutions().load_input_into(&mut testcase)?;
+                let bytes = testcase.input().as_ref().unwrap().target_bytes();
+                let json = export_testcase(self.format, &testcase, bytes.as_slice())?;
+                let name = testcase
+                    .filename()
+                    .as_ref()
+                    .map_or_else(|| format!("id_{id}"), String::clone);
+                (name, json)
+            };
+            fs::write(
+                self.dir.join(format!("{name}.{}", self.format.extension())),
+                json,
+            )?;
+
+            state.metadata_mut::<CrashExportMetadata>()?.last_solution = Some(id);
+            next = state.solutions().next(id);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(())
+    }
+}
+
+impl<EM, Z> Named for CrashExportStage<EM, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
@@ -0,0 +1,211 @@
+//! The [`ExploitabilityStage`] re-runs new objectives and classifies their exploitability.
+
+use alloc::{borrow::Cow, format};
+use core::marker::PhantomData;
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    impl_serdeany,
+    tuples::{Handle, Handled, MatchNameRef},
+    Named,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId},
+    events::{Event, EventFirer, LogSeverity},
+    executors::{Executor, HasObservers},
+    monitors::{AggregatorOps, UserStats, UserStatsValue},
+    observers::{HasSanitizerReport, ObserversTuple},
+    stages::Stage,
+    state::{HasExecutions, HasSolutions, State, UsesState},
+    triage::{Exploitability, ExploitabilityMetadata},
+    Error, HasMetadata,
+};
+
+/// The progress of the [`ExploitabilityStage`], stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+pub struct ExploitabilityStageMetadata {
+    /// The last objective that was classified
+    last_solution: Option<CorpusId>,
+    /// The number of objectives per class
+    pub counts: HashMap<Exploitability, u64>,
+}
+
+impl_serdeany!(ExploitabilityStageMetadata);
+
+/// The [`ExploitabilityStage`] re-runs each new objective with its own executor, classifies the crash
+/// based on the [`crate::observers::SanitizerReport`] of the given observer, and adds an
+/// [`ExploitabilityMetadata`] to the objective.
+/// Each classification is logged, and the number of objectives per class is reported as user stats.
+///
+/// Since the objectives crash the target again, the executor should run them in a separate process,
+/// e.g. an `InProcessForkExecutor` or a `ForkserverExecutor`. The progress is stored before each
+/// execution, so an objective that takes down the fuzzer is not retried.
+#[derive(Debug)]
+pub struct ExploitabilityStage<EM, O, TE, Z> {
+    name: Cow<'static, str>,
+    triage_executor: TE,
+    o_ref: Handle<O>,
+    phantom: PhantomData<(EM, Z)>,
+}
+
+impl<EM, O, TE, Z> UsesState for ExploitabilityStage<EM, O, TE, Z>
+where
+    TE: UsesState,
+{
+    type State = TE::State;
+}
+
+impl<EM, O, TE, Z> ExploitabilityStage<EM, O, TE, Z>
+where
+    O: Named,
+{
+    /// Creates a new [`ExploitabilityStage`], re-running the objectives with `triage_executor`,
+    /// which has to contain the given `observer`
+    pub fn new(triage_executor: TE, observer: &O) -> Self {
+        Self {
+            name: Cow::Borrowed("ExploitabilityStage"),
+            triage_executor,
+            o_ref: observer.handle(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Gets the underlying triage executor
+    pub fn executor(&self) -> &TE {
+        &self.triage_executor
+    }
+
+    /// Gets the underlying triage executor (mut)
+    pub fn executor_mut(&mut self) -> &mut TE {
+        &mut self.triage_executor
+    }
+}
+
+impl<EM, O, TE, Z> ExploitabilityStage<EM, O, TE, Z>
+where
+    O: HasSanitizerReport,
+    TE: Executor<EM, Z> + HasObservers,
+    EM: EventFirer<State = <Self as UsesState>::State>,
+    Z: UsesState<State = <Self as UsesState>::State>,
+    <Self as UsesState>::State: State + HasSolutions + HasExecutions + HasMetadata,
+{
+    /// Re-runs the objective with the given id and classifies it
+    pub fn classify(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut <Self as UsesState>::State,
+        manager: &mut EM,
+        id: CorpusId,
+    ) -> Result<ExploitabilityMetadata, Error> {
+        let input = state.solutions().cloned_input_for_id(id)?;
+
+        self.triage_executor
+            .observers_mut()
+            .pre_exec_all(state, &input)?;
+        let exit_kind = self
+            .triage_executor
+            .run_target(fuzzer, state, manager, &input)?;
+        self.triage_executor
+            .observers_mut()
+            .post_exec_all(state, &input, &exit_kind)?;
+
+        let observers = self.triage_executor.observers();
+        let report = observers
+            .get(&self.o_ref)
+            .ok_or_else(|| {
+                Error::key_not_found(format!("Observer {} not found", self.o_ref.name()))
+            })?
+            .sanitizer_report();
+        Ok(ExploitabilityMetadata::new(exit_kind, report))
+    }
+}
+
+impl<E, EM, O, TE, Z> Stage<E, EM, Z> for ExploitabilityStage<EM, O, TE, Z>
+where
+    E: UsesState<State = <Self as UsesState>::State>,
+    O: HasSanitizerReport,
+    TE: Executor<EM, Z> + HasObservers,
+    EM: EventFirer<State = <Self as UsesState>::State>,
+    Z: UsesState<State = <Self as UsesState>::State>,
+    <Self as UsesState>::State: State + HasSolutions + HasExecutions + HasMetadata,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut <Self as UsesState>::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let last_solution = state
+            .metadata_or_insert_with(ExploitabilityStageMetadata::default)
+            .last_solution;
+        let mut next = match last_solution {
+            Some(id) => state.solutions().next(id),
+            None => state.solutions().first(),
+        };
+
+        while let Some(id) = next {
+            // Store the progress first, in case the objective takes us down
+            state
+                .metadata_mut::<ExploitabilityStageMetadata>()?
+                .last_solution = Some(id);
+
+            let metadata = self.classify(fuzzer, state, manager, id)?;
+            let exploitability = metadata.exploitability;
+            let message = match &metadata.report {
+                Some(report) => format!(
+                    "Objective {id} is {exploitability}: {} ({report})",
+                    metadata.reason
+                ),
+                None => format!("Objective {id} is {exploitability}: {}", metadata.reason),
+            };
+            state
+                .solutions()
+                .get(id)?
+                .borrow_mut()
+                .add_metadata(metadata);
+
+            let count = {
+                let counts = &mut state.metadata_mut::<ExploitabilityStageMetadata>()?.counts;
+                let count = counts.entry(exploitability).or_insert(0);
+                *count += 1;
+                *count
+            };
+            manager.log(state, LogSeverity::Info, message)?;
+            manager.fire(
+                state,
+                Event::UpdateUserStats {
+                    name: Cow::from(format!("objectives_{exploitability}")),
+                    value: UserStats::new(UserStatsValue::Number(count), AggregatorOps::Sum),
+                    phantom: PhantomData,
+                },
+            )?;
+
+            next = state.solutions().next(id);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // The progress is stored per objective in the metadata
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<EM, O, TE, Z> Named for ExploitabilityStage<EM, O, TE, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
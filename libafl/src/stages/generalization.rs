@@ -4,7 +4,7 @@ use alloc::{
     borrow::{Cow, ToOwned},
     vec::Vec,
 };
-use core::{fmt::Debug, marker::PhantomData};
+use core::{fmt::Debug, marker::PhantomData, ops::Range};
 
 use libafl_bolts::{
     tuples::{Handle, Handled},
@@ -12,14 +12,14 @@ use libafl_bolts::{
 };
 
 use crate::{
-    corpus::{Corpus, HasCurrentCorpusId},
+    corpus::{Corpus, CorpusId, HasCurrentCorpusId},
     executors::{Executor, HasObservers},
     feedbacks::map::MapNoveltiesMetadata,
     inputs::{BytesInput, GeneralizedInputMetadata, GeneralizedItem, HasMutatorBytes, UsesInput},
     mark_feature_time,
-    observers::{CanTrack, MapObserver, ObserversTuple},
+    observers::{CanTrack, CmpValues, CmpValuesMetadata, MapObserver, ObserversTuple},
     require_novelties_tracking,
-    stages::{Stage, StdRestartHelper},
+    stages::{CmpTaintMetadata, Stage, StdRestartHelper},
     start_timer,
     state::{HasCorpus, HasExecutions, UsesState},
     Error, HasMetadata, HasNamedMetadata,
@@ -29,6 +29,9 @@ use crate::{monitors::PerfFeature, state::HasClientPerfMonitor};
 
 const MAX_GENERALIZED_LEN: usize = 8192;
 
+/// The maximum number of fields the binary mode tries to remove, bounding its executions
+const MAX_BINARY_FIELDS: usize = 256;
+
 const fn increment_by_offset(_list: &[Option<u8>], idx: usize, off: u8) -> usize {
     idx + 1 + off as usize
 }
@@ -43,6 +46,56 @@ fn find_next_char(list: &[Option<u8>], mut idx: usize, ch: u8) -> usize {
     idx
 }
 
+/// The input byte ranges compared against by the target, so likely fields of a binary format:
+/// each occurrence of a logged comparison operand (of at least 2 bytes) in the input
+fn cmp_operand_fields(values: &[CmpValues], input: &[u8]) -> Vec<Range<usize>> {
+    let mut patterns: Vec<Vec<u8>> = vec![];
+    for value in values {
+        if let CmpValues::Bytes((v0, v1)) = value {
+            patterns.extend([v0, v1].into_iter().filter(|v| v.len() >= 2).cloned());
+            continue;
+        }
+        let (Some(size), Some((v0, v1))) = (value.numeric_size(), value.to_u128_tuple()) else {
+            continue;
+        };
+        if size < 2 {
+            continue;
+        }
+        for v in [v0, v1] {
+            patterns.push(v.to_le_bytes()[..size].to_vec());
+            patterns.push(v.to_be_bytes()[16 - size..].to_vec());
+        }
+    }
+    patterns.sort_unstable();
+    patterns.dedup();
+
+    let mut fields = vec![];
+    for pattern in patterns {
+        if pattern.len() > input.len() {
+            continue;
+        }
+        fields.extend(
+            input
+                .windows(pattern.len())
+                .enumerate()
+                .filter(|(_, window)| *window == pattern.as_slice())
+                .map(|(start, _)| start..start + pattern.len()),
+        );
+    }
+    fields
+}
+
+/// How the [`GeneralizationStage`] looks for removable parts of an input
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GeneralizationMode {
+    /// Split at delimiters and brackets of text formats, like grimoire does
+    #[default]
+    Text,
+    /// Split at the fields of binary formats, derived from the [`CmpTaintMetadata`] of the testcase
+    /// and the [`CmpValuesMetadata`] logged while executing it, instead of delimiters
+    Binary,
+}
+
 /// The name for generalization stage
 pub static GENERALIZATION_STAGE_NAME: &str = "generalization";
 
@@ -51,6 +104,7 @@ pub static GENERALIZATION_STAGE_NAME: &str = "generalization";
 pub struct GeneralizationStage<C, EM, O, OT, Z> {
     name: Cow<'static, str>,
     map_observer_handle: Handle<C>,
+    mode: GeneralizationMode,
     #[allow(clippy::type_complexity)]
     phantom: PhantomData<(EM, O, OT, Z)>,
 }
@@ -93,7 +147,7 @@ where
             ));
         };
 
-        let (mut payload, original, novelties) = {
+        let (mut payload, original, novelties, mut fields) = {
             start_timer!(state);
             {
                 let corpus = state.corpus();
@@ -118,14 +172,55 @@ where
             if meta.as_slice().is_empty() {
                 return Ok(()); // don't generalise inputs which don't have novelties
             }
-            (payload, original, meta.as_slice().to_vec())
+            let novelties = meta.as_slice().to_vec();
+
+            let mut fields = vec![];
+            if self.mode == GeneralizationMode::Binary {
+                if let Some(taint) = entry.metadata_map().get::<CmpTaintMetadata>() {
+                    for site in taint.sites() {
+                        fields.extend(site.ranges.iter().cloned());
+                    }
+                }
+            }
+            (payload, original, novelties, fields)
+        };
+
+        // The comparisons logged for another input would yield bogus fields,
+        // so only use the ones logged while verifying the original below
+        let previous_cmps = if self.mode == GeneralizationMode::Binary {
+            state.metadata_map_mut().remove::<CmpValuesMetadata>()
+        } else {
+            None
         };
 
         // Do not generalized unstable inputs
-        if !self.verify_input(fuzzer, executor, state, manager, &novelties, &original)? {
+        let stable = self.verify_input(fuzzer, executor, state, manager, &novelties, &original);
+
+        if self.mode == GeneralizationMode::Binary {
+            if let Some(cmps) = state.metadata_map().get::<CmpValuesMetadata>() {
+                fields.extend(cmp_operand_fields(&cmps.list, original.bytes()));
+            } else if let Some(previous_cmps) = previous_cmps {
+                // Keep the values for other stages
+                state.metadata_map_mut().insert_boxed(previous_cmps);
+            }
+            fields.retain(|field| field.start < field.end && field.end <= payload.len());
+        }
+        if !stable? {
             return Ok(());
         }
 
+        if self.mode == GeneralizationMode::Binary {
+            self.find_gaps_in_fields(
+                fuzzer,
+                executor,
+                state,
+                manager,
+                &mut payload,
+                &novelties,
+                fields,
+            )?;
+        }
+
         self.find_gaps(
             fuzzer,
             executor,
@@ -177,6 +272,10 @@ where
             0,
         )?;
 
+        if self.mode == GeneralizationMode::Binary {
+            return Self::save_generalized(state, corpus_id, &payload);
+        }
+
         self.find_gaps(
             fuzzer,
             executor,
@@ -309,20 +408,7 @@ where
             b'"',
         )?;
 
-        if payload.len() <= MAX_GENERALIZED_LEN {
-            // Save the modified input in the corpus
-            {
-                let meta = GeneralizedInputMetadata::generalized_from_options(&payload);
-
-                assert!(meta.generalized().first() == Some(&GeneralizedItem::Gap));
-                assert!(meta.generalized().last() == Some(&GeneralizedItem::Gap));
-
-                let mut entry = state.corpus().get(corpus_id)?.borrow_mut();
-                entry.metadata_map_mut().insert(meta);
-            }
-        }
-
-        Ok(())
+        Self::save_generalized(state, corpus_id, &payload)
     }
 
     #[inline]
@@ -357,10 +443,53 @@ where
                 GENERALIZATION_STAGE_NAME.to_owned() + ":" + name.into_owned().as_str(),
             ),
             map_observer_handle: map_observer.handle(),
+            mode: GeneralizationMode::Text,
             phantom: PhantomData,
         }
     }
 
+    /// Create a new [`GeneralizationStage`] for binary formats, see [`GeneralizationMode::Binary`].
+    ///
+    /// Place it after a [`crate::stages::CmpTaintStage`], or give the executor a
+    /// [`crate::observers::CmpObserver`] filling the [`CmpValuesMetadata`], to learn the fields of
+    /// the input. The [`CmpValuesMetadata`] is only used if it was logged for the input itself.
+    #[must_use]
+    pub fn new_binary(map_observer: &C) -> Self {
+        Self::new(map_observer).with_mode(GeneralizationMode::Binary)
+    }
+
+    /// Sets the [`GeneralizationMode`]
+    #[must_use]
+    pub fn with_mode(mut self, mode: GeneralizationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// The [`GeneralizationMode`]
+    #[must_use]
+    pub fn mode(&self) -> GeneralizationMode {
+        self.mode
+    }
+
+    /// Saves the generalized payload to the testcase, unless it is too long
+    fn save_generalized(
+        state: &mut <Self as UsesState>::State,
+        corpus_id: CorpusId,
+        payload: &[Option<u8>],
+    ) -> Result<(), Error> {
+        if payload.len() <= MAX_GENERALIZED_LEN {
+            // Save the modified input in the corpus
+            let meta = GeneralizedInputMetadata::generalized_from_options(payload);
+
+            assert!(meta.generalized().first() == Some(&GeneralizedItem::Gap));
+            assert!(meta.generalized().last() == Some(&GeneralizedItem::Gap));
+
+            let mut entry = state.corpus().get(corpus_id)?.borrow_mut();
+            entry.metadata_map_mut().insert(meta);
+        }
+        Ok(())
+    }
+
     fn verify_input<E>(
         &self,
         fuzzer: &mut Z,
@@ -400,6 +529,58 @@ where
         payload.retain(|&x| !(x.is_none() & core::mem::replace(&mut previous, x.is_none())));
     }
 
+    /// Tries to remove each field of a binary input, the largest first, then each chunk between
+    /// two field boundaries. The fields index into the untrimmed payload.
+    #[allow(clippy::too_many_arguments)]
+    fn find_gaps_in_fields<E>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut <Self as UsesState>::State,
+        manager: &mut EM,
+        payload: &mut Vec<Option<u8>>,
+        novelties: &[usize],
+        mut fields: Vec<Range<usize>>,
+    ) -> Result<(), Error>
+    where
+        E: Executor<EM, Z> + HasObservers<Observers = OT, State = <Self as UsesState>::State>,
+        Z: UsesState<State = <Self as UsesState>::State>,
+    {
+        let mut boundaries: Vec<usize> = fields
+            .iter()
+            .flat_map(|field| [field.start, field.end])
+            .chain([0, payload.len()])
+            .collect();
+        boundaries.sort_unstable();
+        boundaries.dedup();
+
+        fields.sort_unstable_by_key(|field| (core::cmp::Reverse(field.len()), field.start));
+        fields.dedup();
+        fields.truncate(MAX_BINARY_FIELDS);
+        let chunks = boundaries
+            .windows(2)
+            .map(|bounds| bounds[0]..bounds[1])
+            .take(MAX_BINARY_FIELDS);
+
+        for range in fields.into_iter().chain(chunks) {
+            if payload[range.clone()].iter().all(Option::is_none) {
+                continue;
+            }
+            let mut candidate = BytesInput::new(vec![]);
+            candidate.extend(payload[..range.start].iter().flatten());
+            candidate.extend(payload[range.end..].iter().flatten());
+
+            if self.verify_input(fuzzer, executor, state, manager, novelties, &candidate)? {
+                for item in &mut payload[range] {
+                    *item = None;
+                }
+            }
+        }
+
+        Self::trim_payload(payload);
+        Ok(())
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn find_gaps<E>(
         &self,
@@ -495,3 +676,158 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, vec::Vec};
+    use core::cell::RefCell;
+
+    use libafl_bolts::{tuples::tuple_list, Named};
+
+    use super::{cmp_operand_fields, GeneralizationStage};
+    use crate::{
+        corpus::{Corpus, CorpusId, HasCurrentCorpusId, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        feedbacks::{map::MapNoveltiesMetadata, ConstFeedback},
+        inputs::{BytesInput, HasMutatorBytes, UsesInput},
+        observers::{
+            CanTrack, CmpValues, CmpValuesMetadata, ExplicitTracking, MapObserver, Observer,
+            StdMapObserver,
+        },
+        schedulers::QueueScheduler,
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus},
+        Error, HasMetadata, StdFuzzer,
+    };
+
+    /// Logs the comparison of the magic on each execution, like a `CmpObserver`
+    #[derive(Debug)]
+    struct MagicCmpObserver;
+
+    impl Named for MagicCmpObserver {
+        fn name(&self) -> &Cow<'static, str> {
+            static NAME: Cow<'static, str> = Cow::Borrowed("magic_cmp");
+            &NAME
+        }
+    }
+
+    impl<S> Observer<S> for MagicCmpObserver
+    where
+        S: UsesInput + HasMetadata,
+    {
+        fn post_exec(
+            &mut self,
+            state: &mut S,
+            _input: &S::Input,
+            _exit_kind: &ExitKind,
+        ) -> Result<(), Error> {
+            state.add_metadata(CmpValuesMetadata {
+                list: vec![CmpValues::Bytes((b"MAGC".to_vec(), b"MAGC".to_vec()))],
+            });
+            Ok(())
+        }
+    }
+
+    type TrackedMap = ExplicitTracking<StdMapObserver<'static, u8, false>, false, true>;
+
+    /// Covers the first map entry if the input starts with the magic, recording each execution
+    fn magic_harness<OT>(
+        executed: &RefCell<Vec<Vec<u8>>>,
+    ) -> impl FnMut(&mut (TrackedMap, OT), &BytesInput) -> ExitKind + '_ {
+        move |observers, input| {
+            executed.borrow_mut().push(input.bytes().to_vec());
+            if input.bytes().starts_with(b"MAGC") {
+                observers.0.as_mut().set(0, 1);
+            }
+            ExitKind::Ok
+        }
+    }
+
+    #[test]
+    fn test_cmp_operand_fields() {
+        let input = b"\x37\x13ab\x13\x37abc";
+        let values = [
+            CmpValues::U8((b'a', b'b')),
+            CmpValues::U16((0x1337, 0)),
+            CmpValues::Bytes((b"abc".to_vec(), b"x".to_vec())),
+        ];
+        let mut fields = cmp_operand_fields(&values, input);
+        fields.sort_unstable_by_key(|field| (field.start, field.end));
+        // Both byte orders of the numbers, no single bytes
+        assert_eq!(fields, [0..2, 4..6, 6..9]);
+    }
+
+    #[test]
+    fn test_binary_generalization_cmp_values() {
+        let new_state = || {
+            let mut state = test_std_state::<BytesInput>();
+            let mut testcase = Testcase::new(BytesInput::new(b"MAGCjunkjunkXY".to_vec()));
+            testcase.add_metadata(MapNoveltiesMetadata::new(vec![0]));
+            let corpus_id = state.corpus_mut().add(testcase).unwrap();
+            state.set_corpus_id(corpus_id).unwrap();
+            // Logged for another input by an earlier execution
+            state.add_metadata(CmpValuesMetadata {
+                list: vec![CmpValues::Bytes((
+                    b"junkjunk".to_vec(),
+                    b"junkjunk".to_vec(),
+                ))],
+            });
+            state
+        };
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+        let executed = RefCell::new(Vec::<Vec<u8>>::new());
+
+        // Without comparisons logged for the input, the stale ones are neither used nor dropped
+        let mut state = new_state();
+        let map_observer = StdMapObserver::owned("map", vec![0_u8; 4]).track_novelties();
+        let mut stage = GeneralizationStage::new_binary(&map_observer);
+        let mut executor =
+            ClosureExecutor::new(magic_harness(&executed), tuple_list!(map_observer));
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        // The first candidate removes the whole input, not the longer stale field
+        assert_eq!(executed.borrow()[1], b"");
+        let stale = &state.metadata::<CmpValuesMetadata>().unwrap().list;
+        assert_eq!(
+            stale[..],
+            [CmpValues::Bytes((
+                b"junkjunk".to_vec(),
+                b"junkjunk".to_vec()
+            ))]
+        );
+
+        // The comparisons logged while verifying the input yield its fields
+        executed.borrow_mut().clear();
+        let mut state = new_state();
+        let map_observer = StdMapObserver::owned("map", vec![0_u8; 4]).track_novelties();
+        let mut stage = GeneralizationStage::new_binary(&map_observer);
+        let mut executor = ClosureExecutor::new(
+            magic_harness(&executed),
+            tuple_list!(map_observer, MagicCmpObserver),
+        );
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(executed.borrow()[0], b"MAGCjunkjunkXY");
+        // Instead of the longer stale field, the logged magic is removed first
+        assert_eq!(executed.borrow()[1], b"junkjunkXY");
+        let fresh = &state.metadata::<CmpValuesMetadata>().unwrap().list;
+        assert_eq!(
+            fresh[..],
+            [CmpValues::Bytes((b"MAGC".to_vec(), b"MAGC".to_vec()))]
+        );
+        assert!(state
+            .corpus()
+            .get(CorpusId(0))
+            .unwrap()
+            .borrow()
+            .has_metadata::<crate::inputs::GeneralizedInputMetadata>());
+    }
+}
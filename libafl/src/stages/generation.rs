@@ -5,11 +5,12 @@
 use core::marker::PhantomData;
 
 use crate::{
+    corpus::{InputOrigin, PendingInputOriginMetadata},
     generators::Generator,
     inputs::UsesInput,
     stages::Stage,
     state::{HasCorpus, HasRand, UsesState},
-    Error, Evaluator,
+    Error, Evaluator, HasMetadata,
 };
 
 /// A [`Stage`] that generates a single input via a [`Generator`] and evaluates
@@ -38,7 +39,7 @@ where
     E: UsesState<State = Self::State>,
     EM: UsesState<State = Self::State>,
     Z: Evaluator<E, EM>,
-    Self::State: HasCorpus + HasRand,
+    Self::State: HasCorpus + HasRand + HasMetadata,
     G: Generator<<<Self as UsesState>::State as UsesInput>::Input, Self::State>,
 {
     #[inline]
@@ -50,7 +51,12 @@ where
         manager: &mut EM,
     ) -> Result<(), Error> {
         let input = self.0.generate(state)?;
-        fuzzer.evaluate_input(state, executor, manager, input)?;
+        state.add_metadata(PendingInputOriginMetadata {
+            origin: InputOrigin::Generated,
+        });
+        let res = fuzzer.evaluate_input(state, executor, manager, input);
+        state.remove_metadata::<PendingInputOriginMetadata>();
+        res?;
         Ok(())
     }
 
@@ -0,0 +1,254 @@
+//! The [`LengthPreferenceStage`] replaces corpus entries with the shorter (or faster) inputs
+//! found by the [`crate::feedbacks::LengthPreferenceFeedback`].
+
+use alloc::borrow::Cow;
+use core::marker::PhantomData;
+
+use libafl_bolts::Named;
+
+use crate::{
+    corpus::{Corpus, Testcase},
+    feedbacks::length_preference::LengthPreferenceMetadata,
+    inputs::UsesInput,
+    schedulers::RemovableScheduler,
+    stages::Stage,
+    state::{HasCorpus, UsesState},
+    Error, HasMetadata, HasScheduler,
+};
+
+/// Replaces the corpus entries for which the [`crate::feedbacks::LengthPreferenceFeedback`]
+/// found a better input with the same coverage. The metadata of the entries is kept, and the
+/// scheduler is informed through [`RemovableScheduler::on_replace`].
+#[derive(Debug)]
+pub struct LengthPreferenceStage<E, EM, Z> {
+    name: Cow<'static, str>,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, Z> UsesState for LengthPreferenceStage<E, EM, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, EM, Z> LengthPreferenceStage<E, EM, Z> {
+    /// Creates a new [`LengthPreferenceStage`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed("LengthPreferenceStage"),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, Z> Default for LengthPreferenceStage<E, EM, Z> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E, EM, Z> Stage<E, EM, Z> for LengthPreferenceStage<E, EM, Z>
+where
+    E: UsesState,
+    EM: UsesState<State = Self::State>,
+    Z: HasScheduler<State = Self::State>,
+    Z::Scheduler: RemovableScheduler,
+    Self::State: HasCorpus + HasMetadata,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut Self::State,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        let Some(meta) = state
+            .metadata_map_mut()
+            .get_mut::<LengthPreferenceMetadata>()
+        else {
+            return Ok(());
+        };
+        let pending = core::mem::take(&mut meta.pending);
+
+        for (id, replacement) in pending {
+            let Ok(entry) = state.corpus().get(id) else {
+                // The entry was removed in the meantime
+                state
+                    .metadata_mut::<LengthPreferenceMetadata>()?
+                    .signatures
+                    .remove(&replacement.signature.signature);
+                continue;
+            };
+            let mut metadata = entry.borrow().metadata_map().clone();
+            metadata.insert(replacement.signature);
+
+            let input: <Self::State as UsesInput>::Input =
+                postcard::from_bytes(&replacement.input)?;
+            let mut testcase = Testcase::new(input);
+            if let Some(exec_time) = replacement.signature.exec_time {
+                testcase.set_exec_time(exec_time);
+            }
+            *testcase.metadata_map_mut() = metadata;
+
+            let prev = state.corpus_mut().replace(id, testcase)?;
+            fuzzer.scheduler_mut().on_replace(state, id, &prev)?;
+            state.metadata_mut::<LengthPreferenceMetadata>()?.replaced += 1;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(())
+    }
+}
+
+impl<E, EM, Z> Named for LengthPreferenceStage<E, EM, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use libafl_bolts::{tuples::tuple_list, Error, HasLen};
+
+    use super::LengthPreferenceStage;
+    use crate::{
+        corpus::{Corpus, CorpusId, HasTestcase, Testcase},
+        events::NopEventManager,
+        executors::{test::NopExecutor, ExitKind},
+        feedbacks::{
+            length_preference::{CoverageSignatureMetadata, LengthPreferenceMetadata},
+            ConstFeedback, Feedback, LengthPreferenceFeedback,
+        },
+        inputs::{BytesInput, UsesInput},
+        observers::{MapObserver, StdMapObserver},
+        schedulers::{QueueScheduler, RemovableScheduler, Scheduler},
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus, State, UsesState},
+        HasMetadata, HasScheduler, StdFuzzer,
+    };
+
+    /// Counts the replaced testcases it is told about
+    #[derive(Debug)]
+    struct ReplaceCountingScheduler<S> {
+        base: QueueScheduler<S>,
+        replaced: usize,
+    }
+
+    impl<S> UsesState for ReplaceCountingScheduler<S>
+    where
+        S: State,
+    {
+        type State = S;
+    }
+
+    impl<S> Scheduler for ReplaceCountingScheduler<S>
+    where
+        S: State + HasCorpus + HasTestcase,
+    {
+        fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+            self.base.on_add(state, id)
+        }
+
+        fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+            self.base.next(state)
+        }
+    }
+
+    impl<S> RemovableScheduler for ReplaceCountingScheduler<S>
+    where
+        S: State + HasCorpus + HasTestcase,
+    {
+        fn on_replace(
+            &mut self,
+            _state: &mut S,
+            _id: CorpusId,
+            _prev: &Testcase<<S as UsesInput>::Input>,
+        ) -> Result<(), Error> {
+            self.replaced += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_length_preference_stage() {
+        let observer = StdMapObserver::owned("map", vec![1_u8, 0, 0, 0]);
+        let mut feedback = LengthPreferenceFeedback::new(&observer);
+        let mut state = test_std_state::<BytesInput>();
+        let mut testcase = Testcase::new(BytesInput::new(vec![0; 100]));
+        testcase.add_metadata(CoverageSignatureMetadata {
+            signature: observer.hash_simple(),
+            len: 100,
+            exec_time: None,
+        });
+        let id = state.corpus_mut().add(testcase).unwrap();
+        feedback.init_state(&mut state).unwrap();
+        let mut manager = NopEventManager::new();
+        let observers = tuple_list!(observer);
+
+        // Only the best input is kept for the entry
+        for len in [50, 10, 20] {
+            let input = BytesInput::new(vec![1; len]);
+            feedback
+                .is_interesting(&mut state, &mut manager, &input, &observers, &ExitKind::Ok)
+                .unwrap();
+            feedback.discard_metadata(&mut state, &input).unwrap();
+        }
+        assert_eq!(
+            state
+                .metadata::<LengthPreferenceMetadata>()
+                .unwrap()
+                .pending(),
+            1
+        );
+        assert_eq!(state.corpus().cloned_input_for_id(id).unwrap().len(), 100);
+
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            ReplaceCountingScheduler {
+                base: QueueScheduler::new(),
+                replaced: 0,
+            },
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut stage = LengthPreferenceStage::new();
+        stage
+            .perform(
+                &mut fuzzer,
+                &mut NopExecutor::new(),
+                &mut state,
+                &mut manager,
+            )
+            .unwrap();
+
+        assert_eq!(
+            state.corpus().cloned_input_for_id(id).unwrap(),
+            BytesInput::new(vec![1; 10])
+        );
+        let testcase = state.corpus().get(id).unwrap().borrow();
+        assert_eq!(
+            testcase
+                .metadata::<CoverageSignatureMetadata>()
+                .unwrap()
+                .len,
+            10
+        );
+        drop(testcase);
+        assert_eq!(fuzzer.scheduler().replaced, 1);
+        let meta = state.metadata::<LengthPreferenceMetadata>().unwrap();
+        assert_eq!((meta.pending(), meta.replaced()), (0, 1));
+    }
+}
@@ -1,11 +1,15 @@
 //! Stage wrappers that add logics to stage list
 
-use core::marker::PhantomData;
+use alloc::borrow::Cow;
+use core::{marker::PhantomData, time::Duration};
+
+use libafl_bolts::{current_time, impl_serdeany, Named};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     stages::{HasCurrentStage, HasNestedStageStatus, Stage, StageId, StagesTuple},
-    state::UsesState,
-    Error,
+    state::{HasExecutions, StageRegisterDeclarations, UsesState},
+    Error, HasNamedMetadata,
 };
 
 /// Progress for nested stages. This merely enters/exits the inner stage's scope.
@@ -13,7 +17,7 @@ use crate::{
 pub struct NestedStageStdRestartHelper;
 
 impl NestedStageStdRestartHelper {
-    fn should_restart<S, ST>(state: &mut S, _stage: &ST) -> Result<bool, Error>
+    pub(crate) fn should_restart<S, ST>(state: &mut S, _stage: &ST) -> Result<bool, Error>
     where
         S: HasNestedStageStatus,
     {
@@ -21,7 +25,7 @@ impl NestedStageStdRestartHelper {
         Ok(true)
     }
 
-    fn clear_progress<S, ST>(state: &mut S, _stage: &ST) -> Result<(), Error>
+    pub(crate) fn clear_progress<S, ST>(state: &mut S, _stage: &ST) -> Result<(), Error>
     where
         S: HasNestedStageStatus,
     {
@@ -77,6 +81,10 @@ where
     fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
         NestedStageStdRestartHelper::clear_progress(state, self)
     }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        self.stages.declare_registers_all(registers);
+    }
 }
 
 impl<CB, E, EM, ST, Z> WhileStage<CB, E, EM, ST, Z>
@@ -141,6 +149,10 @@ where
     fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
         NestedStageStdRestartHelper::clear_progress(state, self)
     }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        self.if_stages.declare_registers_all(registers);
+    }
 }
 
 impl<CB, E, EM, ST, Z> IfStage<CB, E, EM, ST, Z>
@@ -226,6 +238,11 @@ where
     fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
         NestedStageStdRestartHelper::clear_progress(state, self)
     }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        self.if_stages.declare_registers_all(registers);
+        self.else_stages.declare_registers_all(registers);
+    }
 }
 
 impl<CB, E, EM, ST1, ST2, Z> IfElseStage<CB, E, EM, ST1, ST2, Z>
@@ -287,6 +304,12 @@ where
     fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
         NestedStageStdRestartHelper::clear_progress(state, self)
     }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        if let Some(stages) = &self.stages {
+            stages.declare_registers_all(registers);
+        }
+    }
 }
 
 impl<E, EM, ST, Z> OptionalStage<E, EM, ST, Z> {
@@ -317,3 +340,373 @@ impl<E, EM, ST, Z> OptionalStage<E, EM, ST, Z> {
         }
     }
 }
+
+/// The time a [`TimeBudgetStage`] spent running its stages, stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TimeBudgetMetadata {
+    /// The time spent in the wrapped stages so far
+    pub spent: Duration,
+    /// When `spent` was last updated, while the wrapped stages are running.
+    /// After a restart in between, the time until they resume counts as spent, too.
+    pub running_since: Option<Duration>,
+}
+
+impl_serdeany!(TimeBudgetMetadata);
+
+impl TimeBudgetMetadata {
+    /// Adds the time since the last update to `spent` and starts counting again from now
+    fn update<S>(state: &mut S, name: &str) -> Duration
+    where
+        S: HasNamedMetadata,
+    {
+        let meta = state.named_metadata_or_insert_with(name, Self::default);
+        let now = current_time();
+        if let Some(since) = meta.running_since {
+            meta.spent += now.saturating_sub(since);
+        }
+        meta.running_since = Some(now);
+        meta.spent
+    }
+}
+
+/// Runs the wrapped stages until they used up a time budget in total, then skips them, e.g. for a
+/// campaign phase like an initial deterministic pass.
+/// The time spent is kept as [`TimeBudgetMetadata`] in the state and updated before each wrapped
+/// stage, so it survives restarts. Once the budget is used up, the remaining stages are skipped.
+#[derive(Debug)]
+pub struct TimeBudgetStage<E, EM, ST, Z> {
+    name: Cow<'static, str>,
+    budget: Duration,
+    stages: ST,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, ST, Z> UsesState for TimeBudgetStage<E, EM, ST, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, EM, ST, Z> Named for TimeBudgetStage<E, EM, ST, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<E, EM, ST, Z> Stage<E, EM, Z> for TimeBudgetStage<E, EM, ST, Z>
+where
+    E: UsesState,
+    EM: UsesState<State = Self::State>,
+    ST: StagesTuple<E, EM, Self::State, Z>,
+    Z: UsesState<State = Self::State>,
+    Self::State: HasNestedStageStatus + HasNamedMetadata,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let name = &self.name;
+        let budget = self.budget;
+        let res = self
+            .stages
+            .perform_while(fuzzer, executor, state, manager, &mut |state| {
+                Ok(TimeBudgetMetadata::update(state, name) < budget)
+            });
+
+        TimeBudgetMetadata::update(state, name);
+        state
+            .named_metadata_mut::<TimeBudgetMetadata>(name)?
+            .running_since = None;
+        res
+    }
+
+    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        NestedStageStdRestartHelper::should_restart(state, self)
+    }
+
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        NestedStageStdRestartHelper::clear_progress(state, self)
+    }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        self.stages.declare_registers_all(registers);
+    }
+}
+
+impl<E, EM, ST, Z> TimeBudgetStage<E, EM, ST, Z> {
+    /// Creates a new [`TimeBudgetStage`], running the `stages` for `budget` in total.
+    /// The `name` identifies its [`TimeBudgetMetadata`], so it has to be unique.
+    pub fn new(name: &'static str, budget: Duration, stages: ST) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            budget,
+            stages,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The total time budget of the wrapped stages
+    #[must_use]
+    pub fn budget(&self) -> Duration {
+        self.budget
+    }
+
+    /// The time the wrapped stages spent so far
+    pub fn spent<S>(&self, state: &S) -> Duration
+    where
+        S: HasNamedMetadata,
+    {
+        state
+            .named_metadata::<TimeBudgetMetadata>(&self.name)
+            .map_or(Duration::ZERO, |meta| meta.spent)
+    }
+
+    /// Resets the time spent, so the wrapped stages get their full budget again
+    pub fn reset<S>(&self, state: &mut S)
+    where
+        S: HasNamedMetadata,
+    {
+        let _ = state
+            .named_metadata_map_mut()
+            .remove::<TimeBudgetMetadata>(&self.name);
+    }
+}
+
+/// The executions at the last run of an [`EveryNExecsStage`], stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct EveryNExecsMetadata {
+    /// The number of executions when the wrapped stages last ran
+    pub last_run_execs: u64,
+}
+
+impl_serdeany!(EveryNExecsMetadata);
+
+/// Runs the wrapped stages at most once every `n` executions of the fuzzer, e.g. for expensive
+/// stages like a corpus sync or a minimization.
+/// The executions of the last run are kept as [`EveryNExecsMetadata`] in the state.
+#[derive(Debug)]
+pub struct EveryNExecsStage<E, EM, ST, Z> {
+    name: Cow<'static, str>,
+    n: u64,
+    stages: ST,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, ST, Z> UsesState for EveryNExecsStage<E, EM, ST, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, EM, ST, Z> Named for EveryNExecsStage<E, EM, ST, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<E, EM, ST, Z> Stage<E, EM, Z> for EveryNExecsStage<E, EM, ST, Z>
+where
+    E: UsesState,
+    EM: UsesState<State = Self::State>,
+    ST: StagesTuple<E, EM, Self::State, Z>,
+    Z: UsesState<State = Self::State>,
+    Self::State: HasNestedStageStatus + HasNamedMetadata + HasExecutions,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        if state.current_stage_idx()?.is_none() {
+            let executions = *state.executions();
+            let meta =
+                state.named_metadata_or_insert_with(&self.name, EveryNExecsMetadata::default);
+            if executions.saturating_sub(meta.last_run_execs) < self.n {
+                return Ok(());
+            }
+            meta.last_run_execs = executions;
+        }
+        self.stages.perform_all(fuzzer, executor, state, manager)
+    }
+
+    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        NestedStageStdRestartHelper::should_restart(state, self)
+    }
+
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        NestedStageStdRestartHelper::clear_progress(state, self)
+    }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        self.stages.declare_registers_all(registers);
+    }
+}
+
+impl<E, EM, ST, Z> EveryNExecsStage<E, EM, ST, Z> {
+    /// Creates a new [`EveryNExecsStage`], running the `stages` once every `n` executions.
+    /// The `name` identifies its [`EveryNExecsMetadata`], so it has to be unique.
+    pub fn new(name: &'static str, n: u64, stages: ST) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            n: n.max(1),
+            stages,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The number of executions between two runs of the wrapped stages
+    #[must_use]
+    pub fn n(&self) -> u64 {
+        self.n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use core::{cell::Cell, time::Duration};
+
+    use libafl_bolts::{current_time, rands::StdRand, tuples::tuple_list, Error};
+
+    use super::{TimeBudgetMetadata, TimeBudgetStage};
+    use crate::{
+        corpus::{Corpus, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        executors::test::NopExecutor,
+        fuzzer::test::NopFuzzer,
+        inputs::BytesInput,
+        stages::{ClosureStage, StagesTuple},
+        state::{test::test_std_state, HasCorpus, StdState},
+        HasNamedMetadata,
+    };
+
+    type TestState =
+        StdState<BytesInput, InMemoryCorpus<BytesInput>, StdRand, InMemoryCorpus<BytesInput>>;
+    type TestExecutor = NopExecutor<TestState>;
+    type TestManager = NopEventManager<TestState>;
+    type TestFuzzer = NopFuzzer<TestState>;
+
+    /// A stage counting its runs, which takes at least `duration`
+    #[allow(clippy::type_complexity)]
+    fn counting_stage(
+        runs: &Cell<usize>,
+        duration: Duration,
+    ) -> ClosureStage<
+        impl FnMut(
+                &mut TestFuzzer,
+                &mut TestExecutor,
+                &mut TestState,
+                &mut TestManager,
+            ) -> Result<(), Error>
+            + '_,
+        TestExecutor,
+        TestManager,
+        TestFuzzer,
+    > {
+        ClosureStage::new(
+            move |_: &mut TestFuzzer,
+                  _: &mut TestExecutor,
+                  _: &mut TestState,
+                  _: &mut TestManager| {
+                runs.set(runs.get() + 1);
+                let start = current_time();
+                while current_time().saturating_sub(start) < duration {}
+                Ok(())
+            },
+        )
+    }
+
+    #[test]
+    fn test_time_budget_stage_checks_between_stages() {
+        let runs = [Cell::new(0), Cell::new(0)];
+        let mut stages = tuple_list!(TimeBudgetStage::new(
+            "budget",
+            Duration::from_millis(10),
+            tuple_list!(
+                counting_stage(&runs[0], Duration::from_millis(20)),
+                counting_stage(&runs[1], Duration::ZERO)
+            ),
+        ));
+
+        let mut state = test_std_state::<BytesInput>();
+        let id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![0])))
+            .unwrap();
+        state.set_corpus_id(id).unwrap();
+        let mut fuzzer = NopFuzzer::new();
+        let mut executor = NopExecutor::new();
+        let mut manager = NopEventManager::new();
+
+        for _ in 0..2 {
+            stages
+                .perform_all(&mut fuzzer, &mut executor, &mut state, &mut manager)
+                .unwrap();
+        }
+
+        // the budget ran out during the first stage, so the second never ran
+        assert_eq!(runs[0].get(), 1);
+        assert_eq!(runs[1].get(), 0);
+        assert!(stages.0.spent(&state) >= Duration::from_millis(20));
+        assert!(state
+            .named_metadata::<TimeBudgetMetadata>("budget")
+            .unwrap()
+            .running_since
+            .is_none());
+
+        stages.0.reset(&mut state);
+        assert_eq!(stages.0.spent(&state), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_time_budget_stage_credits_interrupted_run() {
+        let runs = Cell::new(0);
+        let budget = Duration::from_secs(60);
+        let mut stages = tuple_list!(TimeBudgetStage::new(
+            "budget",
+            budget,
+            tuple_list!(counting_stage(&runs, Duration::ZERO)),
+        ));
+
+        let mut state = test_std_state::<BytesInput>();
+        let id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![0])))
+            .unwrap();
+        state.set_corpus_id(id).unwrap();
+        let mut fuzzer = NopFuzzer::new();
+        let mut executor = NopExecutor::new();
+        let mut manager = NopEventManager::new();
+
+        // the fuzzer restarted after the stages ran for the whole budget
+        state.add_named_metadata(
+            "budget",
+            TimeBudgetMetadata {
+                spent: Duration::ZERO,
+                running_since: Some(current_time().saturating_sub(budget)),
+            },
+        );
+
+        stages
+            .perform_all(&mut fuzzer, &mut executor, &mut state, &mut manager)
+            .unwrap();
+
+        assert_eq!(runs.get(), 0);
+        assert!(stages.0.spent(&state) >= budget);
+    }
+}
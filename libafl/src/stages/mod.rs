@@ -7,35 +7,64 @@ Other stages may enrich [`crate::corpus::Testcase`]s with metadata.
 use alloc::{
     borrow::{Cow, ToOwned},
     boxed::Box,
-    string::ToString,
+    string::{String, ToString},
     vec::Vec,
 };
-use core::{fmt, marker::PhantomData};
+use core::{any::type_name, fmt, marker::PhantomData};
 
-pub use calibrate::CalibrationStage;
+#[cfg(all(feature = "std", unix))]
+pub use afl_custom::{AflCustomTrimStage, AflCustomTrimmedMetadata};
+pub use autodict::{AutoDictMetadata, AutoDictStage};
+#[cfg(feature = "binary_tokens")]
+pub use binary_tokens::{BinaryTokensMetadata, BinaryTokensStage};
+pub use calibrate::{
+    CalibrationStage, LazyCalibrationMetadata, ProvisionalCalibrationMetadata,
+    RecalibrationMetadata, RecalibrationStage, TestcaseStabilityMetadata,
+};
+pub use cmin::*;
 pub use colorization::*;
 #[cfg(all(feature = "std", unix))]
 pub use concolic::ConcolicTracingStage;
 #[cfg(all(feature = "std", feature = "concolic_mutation", unix))]
-pub use concolic::SimpleConcolicMutationalStage;
+pub use concolic::{ConcolicSolverOptions, FlippedBranchesMetadata, SimpleConcolicMutationalStage};
+pub use corpus_gc::*;
+#[cfg(feature = "crash_export")]
+pub use crash_export::*;
 #[cfg(feature = "std")]
 pub use dump::*;
-pub use generalization::GeneralizationStage;
-use hashbrown::HashSet;
+#[cfg(feature = "regex")]
+pub use exploitability::*;
+pub use generalization::{GeneralizationMode, GeneralizationStage};
+use hashbrown::{HashMap, HashSet};
+pub use length_preference::LengthPreferenceStage;
 use libafl_bolts::{
     impl_serdeany,
     tuples::{HasConstLen, IntoVec},
-    Named,
+    ErrorContext, ErrorFrame, Named,
 };
 pub use logics::*;
 pub use mutational::{MutationalStage, StdMutationalStage};
+pub use plateau::{
+    ClosureEscalation, EscalatedProbabilitiesMetadata, EscalationPolicy,
+    MutationProbabilityEscalation, PlateauMetadata, PlateauStage,
+};
 pub use power::{PowerMutationalStage, StdPowerMutationalStage};
-use serde::{Deserialize, Serialize};
+pub use rarity::EdgeFrequencySyncStage;
+pub use registers::RegisterUsageStage;
+pub use regression::{RegressionMetadata, RegressionReplay, RegressionStage};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+#[cfg(feature = "std")]
+pub use snapshot::CorpusSnapshotStage;
+#[cfg(all(feature = "std", feature = "fork", unix))]
+pub use stateful::SnapshotSequenceStage;
 pub use stats::AflStatsStage;
 #[cfg(feature = "std")]
 pub use sync::*;
+pub use taint::*;
 pub use tmin::{
-    MapEqualityFactory, MapEqualityFeedback, StdTMinMutationalStage, TMinMutationalStage,
+    HashEqualityFactory, HashEqualityFeedback, MapEqualityFactory, MapEqualityFeedback,
+    MinimizedMetadata, PredicateTMinMetadata, PredicateTMinStage, StdTMinMutationalStage,
+    TMinMutationalStage, TMinPass,
 };
 pub use tracing::{ShadowTracingStage, TracingStage};
 pub use tuneable::*;
@@ -45,13 +74,16 @@ pub use unicode::*;
 
 use crate::{
     corpus::{CorpusId, HasCurrentCorpusId},
-    events::{EventFirer, EventRestarter, HasEventManagerId, ProgressReporter},
+    events::{shutdown_requested, EventFirer, EventRestarter, HasEventManagerId, ProgressReporter},
     executors::{Executor, HasObservers},
     inputs::UsesInput,
     observers::ObserversTuple,
     schedulers::Scheduler,
     stages::push::PushStage,
-    state::{HasCorpus, HasExecutions, HasLastReportTime, HasRand, State, UsesState},
+    state::{
+        HasCorpus, HasExecutions, HasLastReportTime, HasRand, StageRegisterDeclarations, State,
+        UsesState,
+    },
     Error, EvaluatorObservers, ExecutesInput, ExecutionProcessor, HasMetadata, HasNamedMetadata,
     HasScheduler,
 };
@@ -61,20 +93,41 @@ pub mod mutational;
 pub mod push;
 pub mod tmin;
 
+#[cfg(all(feature = "std", unix))]
+pub mod afl_custom;
+pub mod autodict;
+#[cfg(feature = "binary_tokens")]
+pub mod binary_tokens;
 pub mod calibrate;
+pub mod cmin;
 pub mod colorization;
 #[cfg(all(feature = "std", unix))]
 pub mod concolic;
+pub mod corpus_gc;
+#[cfg(feature = "crash_export")]
+pub mod crash_export;
 #[cfg(feature = "std")]
 pub mod dump;
+#[cfg(feature = "regex")]
+pub mod exploitability;
 pub mod generalization;
 /// The [`generation::GenStage`] generates a single input and evaluates it.
 pub mod generation;
+pub mod length_preference;
 pub mod logics;
+pub mod plateau;
 pub mod power;
+pub mod rarity;
+pub mod registers;
+pub mod regression;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(all(feature = "std", feature = "fork", unix))]
+pub mod stateful;
 pub mod stats;
 #[cfg(feature = "std")]
 pub mod sync;
+pub mod taint;
 pub mod tracing;
 pub mod tuneable;
 #[cfg(feature = "unicode")]
@@ -125,6 +178,10 @@ where
         }
         self.clear_progress(state)
     }
+
+    /// Declares the [`crate::state::StageRegister`]s this stage produces and consumes,
+    /// checked by [`StagesTuple::validate_registers`]
+    fn declare_registers(&self, _registers: &mut StageRegisterDeclarations) {}
 }
 
 /// A tuple holding all `Stages` used for fuzzing.
@@ -143,6 +200,31 @@ where
         state: &mut S,
         manager: &mut EM,
     ) -> Result<(), Error>;
+
+    /// Performs the `Stages` in this tuple like [`StagesTuple::perform_all`], but calls `proceed`
+    /// before starting each stage and stops once it returns `false`.
+    /// A stage resumed after a restart always runs to its end.
+    fn perform_while<CB>(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        proceed: &mut CB,
+    ) -> Result<(), Error>
+    where
+        CB: FnMut(&mut S) -> Result<bool, Error>;
+
+    /// Declares the registers of all `Stages` in this tuple, in execution order
+    fn declare_registers_all(&self, _registers: &mut StageRegisterDeclarations) {}
+
+    /// Checks that each register consumed by a stage is produced by an earlier stage,
+    /// with the same type and version. [`crate::Fuzzer::fuzz_loop`] calls this before fuzzing.
+    fn validate_registers(&self) -> Result<(), Error> {
+        let mut registers = StageRegisterDeclarations::new();
+        self.declare_registers_all(&mut registers);
+        registers.validate()
+    }
 }
 
 impl<E, EM, S, Z> StagesTuple<E, EM, S, Z> for ()
@@ -167,6 +249,20 @@ where
             Ok(())
         }
     }
+
+    fn perform_while<CB>(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        _proceed: &mut CB,
+    ) -> Result<(), Error>
+    where
+        CB: FnMut(&mut S) -> Result<bool, Error>,
+    {
+        self.perform_all(fuzzer, executor, state, manager)
+    }
 }
 
 impl<Head, Tail, E, EM, Z> StagesTuple<E, EM, Head::State, Z> for (Head, Tail)
@@ -185,6 +281,20 @@ where
         state: &mut Head::State,
         manager: &mut EM,
     ) -> Result<(), Error> {
+        self.perform_while(fuzzer, executor, state, manager, &mut |_| Ok(true))
+    }
+
+    fn perform_while<CB>(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Head::State,
+        manager: &mut EM,
+        proceed: &mut CB,
+    ) -> Result<(), Error>
+    where
+        CB: FnMut(&mut Head::State) -> Result<bool, Error>,
+    {
         match state.current_stage_idx()? {
             Some(idx) if idx < StageId(Self::LEN) => {
                 // do nothing; we are resuming
@@ -193,7 +303,9 @@ where
                 // perform the stage, but don't set it
                 let stage = &mut self.0;
 
-                stage.perform_restartable(fuzzer, executor, state, manager)?;
+                stage
+                    .perform_restartable(fuzzer, executor, state, manager)
+                    .with_context(|| ErrorFrame::new(stage_name::<Head>()))?;
 
                 state.clear_stage()?;
             }
@@ -202,20 +314,41 @@ where
             }
             // this is None, but the match can't deduce that
             _ => {
+                // skip the remaining stages, the fuzz loop shuts down at its next progress report
+                if shutdown_requested() || !proceed(state)? {
+                    return Ok(());
+                }
+
                 state.set_current_stage_idx(StageId(Self::LEN))?;
 
                 let stage = &mut self.0;
-                stage.perform_restartable(fuzzer, executor, state, manager)?;
+                stage
+                    .perform_restartable(fuzzer, executor, state, manager)
+                    .with_context(|| ErrorFrame::new(stage_name::<Head>()))?;
 
                 state.clear_stage()?;
             }
         }
 
         // Execute the remaining stages
-        self.1.perform_all(fuzzer, executor, state, manager)
+        self.1
+            .perform_while(fuzzer, executor, state, manager, proceed)
+    }
+
+    fn declare_registers_all(&self, registers: &mut StageRegisterDeclarations) {
+        self.0.declare_registers(registers);
+        registers.next_stage();
+        self.1.declare_registers_all(registers);
     }
 }
 
+/// The name of a stage type, without its path and generics, for error context
+fn stage_name<S>() -> &'static str {
+    let name = type_name::<S>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
 impl<Head, Tail, E, EM, Z>
     IntoVec<Box<dyn Stage<E, EM, Z, State = Head::State, Input = Head::Input>>> for (Head, Tail)
 where
@@ -284,8 +417,35 @@ where
         state: &mut S,
         manager: &mut EM,
     ) -> Result<(), Error> {
-        self.iter_mut()
-            .try_for_each(|x| x.perform_restartable(fuzzer, executor, state, manager))
+        self.perform_while(fuzzer, executor, state, manager, &mut |_| Ok(true))
+    }
+
+    fn perform_while<CB>(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        proceed: &mut CB,
+    ) -> Result<(), Error>
+    where
+        CB: FnMut(&mut S) -> Result<bool, Error>,
+    {
+        for stage in self {
+            // skip the remaining stages, the fuzz loop shuts down at its next progress report
+            if shutdown_requested() || !proceed(state)? {
+                break;
+            }
+            stage.perform_restartable(fuzzer, executor, state, manager)?;
+        }
+        Ok(())
+    }
+
+    fn declare_registers_all(&self, registers: &mut StageRegisterDeclarations) {
+        for stage in self {
+            stage.declare_registers(registers);
+            registers.next_stage();
+        }
     }
 }
 
@@ -550,14 +710,71 @@ impl StdRestartHelper {
         })
     }
 
-    /// Clears the progress
+    /// Clears the progress, including the [`StageCheckpoint`] of the stage
     pub fn clear_progress<S>(state: &mut S, name: &str) -> Result<(), Error>
     where
         S: HasNamedMetadata,
     {
         state.named_metadata_mut::<Self>(name)?.tries_remaining = None;
+        StageCheckpoint::clear(state, name);
+        Ok(())
+    }
+}
+
+/// The progress tokens of a stage, see [`StageCheckpoint`]
+#[derive(Clone, Deserialize, Serialize, Debug, Default)]
+pub struct StageCheckpointMetadata {
+    tokens: HashMap<String, Vec<u8>>,
+}
+
+impl_serdeany!(StageCheckpointMetadata);
+
+/// Fine-grained progress tokens of long-running stages, e.g. a pass index and a byte offset,
+/// persisted in the state so the stage resumes where it left off after the client restarted.
+///
+/// A stage saves its tokens while it runs, and loads them when it runs again. As the state is
+/// stored when the client restarts, e.g. after a crash, the tokens then point to the work that
+/// caused the restart, which the stage should usually skip. [`StdRestartHelper::clear_progress`]
+/// clears the tokens once the stage finished normally.
+#[derive(Debug, Clone, Copy)]
+pub struct StageCheckpoint;
+
+impl StageCheckpoint {
+    /// Saves the progress token `key` of the stage `name`
+    pub fn save<S, T>(state: &mut S, name: &str, key: &str, token: &T) -> Result<(), Error>
+    where
+        S: HasNamedMetadata,
+        T: Serialize,
+    {
+        let bytes = postcard::to_allocvec(token)?;
+        state
+            .named_metadata_or_insert_with(name, StageCheckpointMetadata::default)
+            .tokens
+            .insert(key.to_string(), bytes);
         Ok(())
     }
+
+    /// Loads the progress token `key` of the stage `name`, if it was saved
+    pub fn load<S, T>(state: &S, name: &str, key: &str) -> Result<Option<T>, Error>
+    where
+        S: HasNamedMetadata,
+        T: DeserializeOwned,
+    {
+        state
+            .named_metadata_map()
+            .get::<StageCheckpointMetadata>(name)
+            .and_then(|checkpoint| checkpoint.tokens.get(key))
+            .map(|bytes| postcard::from_bytes(bytes).map_err(Error::from))
+            .transpose()
+    }
+
+    /// Clears all progress tokens of the stage `name`
+    pub fn clear<S>(state: &mut S, name: &str)
+    where
+        S: HasNamedMetadata,
+    {
+        let _ = state.remove_named_metadata::<StageCheckpointMetadata>(name);
+    }
 }
 
 /// The index of a stage
@@ -15,6 +15,9 @@ use crate::{
     inputs::Input,
     mark_feature_time,
     mutators::{MultiMutator, MutationResult, Mutator},
+    schedulers::{
+        powersched::SchedulerMetadata, testcase_score::CorpusPowerTestcaseScore, TestcaseScore,
+    },
     stages::{Stage, StdRestartHelper},
     start_timer,
     state::{HasCorpus, HasCurrentTestcase, HasExecutions, HasRand, UsesState},
@@ -97,6 +100,12 @@ where
     /// Gets the number of iterations this mutator should run for.
     fn iterations(&self, state: &mut Self::State) -> Result<usize, Error>;
 
+    /// Gets the number of times the mutator is stacked on the input before each execution.
+    #[inline]
+    fn stacked_mutations(&self, _state: &mut Self::State) -> Result<usize, Error> {
+        Ok(1)
+    }
+
     /// Runs this (mutational) stage for the given testcase
     #[allow(clippy::cast_possible_wrap)] // more than i32 stages on 32 bit system - highly unlikely...
     fn perform_mutational(
@@ -115,6 +124,7 @@ where
             .saturating_sub(self.execs_since_progress_start(state)?);
         */
         let num = self.iterations(state)?;
+        let stack = self.stacked_mutations(state)?.max(1);
         let mut testcase = state.current_testcase_mut()?;
 
         let Ok(input) = I::try_transform_from(&mut testcase, state) else {
@@ -127,7 +137,12 @@ where
             let mut input = input.clone();
 
             start_timer!(state);
-            let mutated = self.mutator_mut().mutate(state, &mut input)?;
+            let mut mutated = MutationResult::Skipped;
+            for _ in 0..stack {
+                if self.mutator_mut().mutate(state, &mut input)? == MutationResult::Mutated {
+                    mutated = MutationResult::Mutated;
+                }
+            }
             mark_feature_time!(state, PerfFeature::Mutate);
 
             if mutated == MutationResult::Skipped {
@@ -152,6 +167,84 @@ where
 /// It may randomly continue earlier.
 pub static DEFAULT_MUTATIONAL_MAX_ITERATIONS: usize = 128;
 
+/// The energy of a testcase with an average power score, see [`CorpusPowerTestcaseScore`].
+pub const HAVOC_BASELINE_ENERGY: f64 = 100.0;
+
+/// Default upper bound for the number of stacked mutator invocations of [`HavocScaling`]
+pub const DEFAULT_HAVOC_MAX_STACK: usize = 16;
+
+/// How the energy of a testcase is mapped to the number of stacked mutations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HavocScalingCurve {
+    /// Stack proportionally to the energy
+    Linear,
+    /// Stack proportionally to the square root of the energy
+    Sqrt,
+    /// Stack one more time for each doubling of the energy
+    Logarithmic,
+}
+
+/// Scales the number of stacked mutations per execution with the energy the power schedule
+/// assigned to the current testcase, so that high energy entries get heavier mutation batches.
+///
+/// Testcases at or below [`HAVOC_BASELINE_ENERGY`] are mutated once per execution, as without scaling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HavocScaling {
+    curve: HavocScalingCurve,
+    max_stack: usize,
+}
+
+impl HavocScaling {
+    /// Creates a new [`HavocScaling`] with the given curve, stacking at most [`DEFAULT_HAVOC_MAX_STACK`] times
+    #[must_use]
+    pub fn new(curve: HavocScalingCurve) -> Self {
+        Self {
+            curve,
+            max_stack: DEFAULT_HAVOC_MAX_STACK,
+        }
+    }
+
+    /// Sets the maximum number of stacked mutator invocations
+    #[must_use]
+    pub fn with_max_stack(mut self, max_stack: usize) -> Self {
+        self.max_stack = max_stack.max(1);
+        self
+    }
+
+    /// The curve mapping energy to stacked mutations
+    #[must_use]
+    pub fn curve(&self) -> HavocScalingCurve {
+        self.curve
+    }
+
+    /// The maximum number of stacked mutator invocations
+    #[must_use]
+    pub fn max_stack(&self) -> usize {
+        self.max_stack
+    }
+
+    /// The number of stacked mutator invocations for the given energy
+    #[must_use]
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss
+    )]
+    pub fn stack_for_energy(&self, energy: f64) -> usize {
+        let ratio = energy / HAVOC_BASELINE_ENERGY;
+        if ratio.is_nan() || ratio <= 1.0 {
+            return 1;
+        }
+        let stack = match self.curve {
+            HavocScalingCurve::Linear => ratio,
+            HavocScalingCurve::Sqrt => libm::sqrt(ratio),
+            HavocScalingCurve::Logarithmic => 1.0 + libm::log2(ratio),
+        };
+        let max_stack = self.max_stack as f64;
+        (libm::ceil(stack).min(max_stack) as usize).max(1)
+    }
+}
+
 /// The default mutational stage
 #[derive(Clone, Debug)]
 pub struct StdMutationalStage<E, EM, I, M, Z> {
@@ -161,6 +254,8 @@ pub struct StdMutationalStage<E, EM, I, M, Z> {
     mutator: M,
     /// The maximum amount of iterations we should do each round
     max_iterations: usize,
+    /// Scales the stacked mutations with the energy of the testcase, if set
+    havoc_scaling: Option<HavocScaling>,
     #[allow(clippy::type_complexity)]
     phantom: PhantomData<(E, EM, I, Z)>,
 }
@@ -190,6 +285,20 @@ where
     fn iterations(&self, state: &mut Self::State) -> Result<usize, Error> {
         Ok(1 + state.rand_mut().below(self.max_iterations))
     }
+
+    /// Gets the number of stacked mutations from the energy of the current testcase,
+    /// if [`HavocScaling`] is enabled and a power schedule is in use
+    fn stacked_mutations(&self, state: &mut Self::State) -> Result<usize, Error> {
+        let Some(scaling) = self.havoc_scaling else {
+            return Ok(1);
+        };
+        if !state.has_metadata::<SchedulerMetadata>() {
+            return Ok(1);
+        }
+        let mut testcase = state.current_testcase_mut()?;
+        let energy = CorpusPowerTestcaseScore::<Self::State>::compute(state, &mut testcase)?;
+        Ok(scaling.stack_for_energy(energy))
+    }
 }
 
 /// The unique id for mutational stage
@@ -291,9 +400,29 @@ where
             ),
             mutator,
             max_iterations,
+            havoc_scaling: None,
             phantom: PhantomData,
         }
     }
+
+    /// Scales the number of stacked mutations with the energy of the current testcase,
+    /// as assigned by the power schedule in [`SchedulerMetadata`]
+    #[must_use]
+    pub fn with_havoc_scaling(mut self, scaling: HavocScaling) -> Self {
+        self.havoc_scaling = Some(scaling);
+        self
+    }
+
+    /// The [`HavocScaling`] of this stage, if any
+    #[must_use]
+    pub fn havoc_scaling(&self) -> Option<HavocScaling> {
+        self.havoc_scaling
+    }
+
+    /// Sets or disables the [`HavocScaling`] of this stage
+    pub fn set_havoc_scaling(&mut self, scaling: Option<HavocScaling>) {
+        self.havoc_scaling = scaling;
+    }
 }
 
 /// A mutational stage that operates on multiple inputs, as returned by [`MultiMutator::multi_mutate`].
@@ -402,3 +531,124 @@ impl<E, EM, I, M, Z> MultiMutationalStage<E, EM, I, M, Z> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, vec};
+    use core::time::Duration;
+
+    use libafl_bolts::Named;
+
+    use super::{
+        HavocScaling, HavocScalingCurve, MutationalStage, StdMutationalStage,
+        DEFAULT_HAVOC_MAX_STACK,
+    };
+    use crate::{
+        corpus::{Corpus, HasCurrentCorpusId, SchedulerTestcaseMetadata, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        mutators::{MutationResult, Mutator},
+        schedulers::{
+            powersched::{PowerSchedule, SchedulerMetadata},
+            QueueScheduler,
+        },
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus},
+        Error, HasMetadata, StdFuzzer,
+    };
+
+    /// Counts how often it mutated
+    #[derive(Debug, Default)]
+    struct CountingMutator(usize);
+
+    impl Named for CountingMutator {
+        fn name(&self) -> &Cow<'static, str> {
+            static NAME: Cow<'static, str> = Cow::Borrowed("CountingMutator");
+            &NAME
+        }
+    }
+
+    impl<I, S> Mutator<I, S> for CountingMutator {
+        fn mutate(&mut self, _state: &mut S, _input: &mut I) -> Result<MutationResult, Error> {
+            self.0 += 1;
+            Ok(MutationResult::Mutated)
+        }
+    }
+
+    #[test]
+    fn test_havoc_scaling_curves() {
+        let linear = HavocScaling::new(HavocScalingCurve::Linear);
+        let sqrt = HavocScaling::new(HavocScalingCurve::Sqrt);
+        let logarithmic = HavocScaling::new(HavocScalingCurve::Logarithmic);
+
+        // At or below the baseline, the input is mutated once
+        for scaling in [linear, sqrt, logarithmic] {
+            assert_eq!(scaling.stack_for_energy(0.0), 1);
+            assert_eq!(scaling.stack_for_energy(100.0), 1);
+            assert_eq!(scaling.stack_for_energy(f64::NAN), 1);
+            assert_eq!(
+                scaling.stack_for_energy(f64::INFINITY),
+                DEFAULT_HAVOC_MAX_STACK
+            );
+        }
+
+        assert_eq!(linear.stack_for_energy(400.0), 4);
+        assert_eq!(sqrt.stack_for_energy(400.0), 2);
+        assert_eq!(logarithmic.stack_for_energy(400.0), 3);
+        assert_eq!(linear.stack_for_energy(250.0), 3);
+        assert_eq!(linear.stack_for_energy(1e6), DEFAULT_HAVOC_MAX_STACK);
+
+        assert_eq!(linear.with_max_stack(2).stack_for_energy(400.0), 2);
+        // The input is always mutated at least once
+        assert_eq!(linear.with_max_stack(0).max_stack(), 1);
+    }
+
+    #[test]
+    fn test_havoc_scaling_stage() {
+        let mut executor =
+            ClosureExecutor::new(|_observers: &mut (), _input: &BytesInput| ExitKind::Ok, ());
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+
+        // A testcase running 10 times faster than the average gets an energy of 300
+        let mut testcase = Testcase::new(BytesInput::new(vec![0]));
+        testcase.set_exec_time(Duration::from_millis(10));
+        let mut tcmeta = SchedulerTestcaseMetadata::new(0);
+        tcmeta.set_bitmap_size(1);
+        testcase.add_metadata(tcmeta);
+        let id = state.corpus_mut().add(testcase).unwrap();
+        state.set_corpus_id(id).unwrap();
+
+        let mut stage = StdMutationalStage::with_max_iterations(CountingMutator::default(), 1)
+            .with_havoc_scaling(HavocScaling::new(HavocScalingCurve::Linear));
+
+        // Without a power schedule, the input is mutated once per execution
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(stage.mutator().0, 1);
+
+        let mut psmeta = SchedulerMetadata::new(Some(PowerSchedule::EXPLORE));
+        psmeta.set_exec_time(Duration::from_millis(100));
+        psmeta.set_cycles(1);
+        state.add_metadata(psmeta);
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(stage.mutator().0, 1 + 3);
+
+        // Without scaling, the energy is ignored
+        stage.set_havoc_scaling(None);
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(stage.mutator().0, 1 + 3 + 1);
+    }
+}
@@ -0,0 +1,466 @@
+//! A meta-stage reacting to coverage plateaus, see [`PlateauStage`].
+//!
+//! When the corpus stops growing for a while, the fuzzer escalates: the [`EscalationPolicy`]
+//! switches the mutation strategy, and the escalation stages run, e.g. a grimoire stage, a
+//! cmplog-heavy pass or a corpus sync. When new coverage is found again, the policy switches back.
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    vec::Vec,
+};
+use core::{fmt::Debug, marker::PhantomData, time::Duration};
+
+use libafl_bolts::{
+    current_time, impl_serdeany, math::calculate_cumulative_distribution_in_place, Named,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::Corpus,
+    mutators::TuneableScheduledMutatorMetadata,
+    stages::{
+        logics::NestedStageStdRestartHelper, HasCurrentStage, HasNestedStageStatus, Stage,
+        StagesTuple,
+    },
+    state::{HasCorpus, StageRegisterDeclarations, UsesState},
+    Error, HasMetadata, HasNamedMetadata,
+};
+
+/// Default name for `PlateauStage`
+pub const PLATEAU_STAGE_NAME: &str = "plateau";
+/// The default time without new coverage after which the [`PlateauStage`] escalates
+pub const DEFAULT_PLATEAU_TIME: Duration = Duration::from_secs(30 * 60);
+/// The default highest escalation level of the [`PlateauStage`]
+pub const DEFAULT_MAX_ESCALATION_LEVEL: usize = 3;
+
+/// The progress tracked by a [`PlateauStage`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct PlateauMetadata {
+    last_progress: Duration,
+    corpus_count: usize,
+    level: usize,
+}
+impl_serdeany!(PlateauMetadata);
+
+impl PlateauMetadata {
+    /// The time new coverage was found last, since the epoch
+    #[must_use]
+    pub fn last_progress(&self) -> Duration {
+        self.last_progress
+    }
+
+    /// The current escalation level, 0 if the fuzzer makes progress
+    #[must_use]
+    pub fn level(&self) -> usize {
+        self.level
+    }
+}
+
+/// Decides what to change when the fuzzer hits a coverage plateau.
+///
+/// Policies can be combined as tuple list, e.g. `tuple_list!(a, b)`, notifying all of them in order.
+pub trait EscalationPolicy<S> {
+    /// The fuzzer did not find new coverage for `level` plateau times, starting at 1
+    fn escalate(&mut self, state: &mut S, level: usize) -> Result<(), Error>;
+
+    /// The fuzzer found new coverage again, after escalating to `level`
+    fn recover(&mut self, state: &mut S, level: usize) -> Result<(), Error>;
+}
+
+impl<S> EscalationPolicy<S> for () {
+    fn escalate(&mut self, _state: &mut S, _level: usize) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn recover(&mut self, _state: &mut S, _level: usize) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<Head, Tail, S> EscalationPolicy<S> for (Head, Tail)
+where
+    Head: EscalationPolicy<S>,
+    Tail: EscalationPolicy<S>,
+{
+    fn escalate(&mut self, state: &mut S, level: usize) -> Result<(), Error> {
+        self.0.escalate(state, level)?;
+        self.1.escalate(state, level)
+    }
+
+    fn recover(&mut self, state: &mut S, level: usize) -> Result<(), Error> {
+        self.0.recover(state, level)?;
+        self.1.recover(state, level)
+    }
+}
+
+/// An [`EscalationPolicy`] calling a closure with the new escalation level, 0 when recovering
+#[derive(Debug, Clone)]
+pub struct ClosureEscalation<F> {
+    closure: F,
+}
+
+impl<F> ClosureEscalation<F> {
+    /// Creates a new [`ClosureEscalation`]
+    pub fn new(closure: F) -> Self {
+        Self { closure }
+    }
+}
+
+impl<F, S> EscalationPolicy<S> for ClosureEscalation<F>
+where
+    F: FnMut(&mut S, usize) -> Result<(), Error>,
+{
+    fn escalate(&mut self, state: &mut S, level: usize) -> Result<(), Error> {
+        (self.closure)(state, level)
+    }
+
+    fn recover(&mut self, state: &mut S, _level: usize) -> Result<(), Error> {
+        (self.closure)(state, 0)
+    }
+}
+
+/// The cumulative mutation probabilities a [`MutationProbabilityEscalation`] replaced, kept in the
+/// state to restore them after a restart, too
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EscalatedProbabilitiesMetadata {
+    previous: Vec<f32>,
+}
+impl_serdeany!(EscalatedProbabilitiesMetadata);
+
+/// An [`EscalationPolicy`] switching the mutation probabilities of a
+/// [`crate::mutators::TuneableScheduledMutator`] on each escalation level, e.g. to splice more.
+/// Levels beyond the configured ones keep the last probabilities.
+/// When recovering, the mutator falls back to its previous probabilities, kept in the
+/// [`EscalatedProbabilitiesMetadata`].
+#[derive(Debug, Clone)]
+pub struct MutationProbabilityEscalation {
+    /// The mutation probabilities for each escalation level, starting at 1
+    levels: Vec<Vec<f32>>,
+}
+
+impl MutationProbabilityEscalation {
+    /// Creates a new [`MutationProbabilityEscalation`] with the probabilities per
+    /// [`crate::mutators::MutationId`] for each escalation level, starting at 1.
+    pub fn new(levels: Vec<Vec<f32>>) -> Result<Self, Error> {
+        if levels.is_empty() {
+            return Err(Error::illegal_argument(
+                "At least one escalation level is needed",
+            ));
+        }
+        let levels = levels
+            .into_iter()
+            .map(|mut probabilities| {
+                calculate_cumulative_distribution_in_place(&mut probabilities)?;
+                Ok(probabilities)
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(Self { levels })
+    }
+}
+
+impl<S> EscalationPolicy<S> for MutationProbabilityEscalation
+where
+    S: HasMetadata,
+{
+    fn escalate(&mut self, state: &mut S, level: usize) -> Result<(), Error> {
+        if !state.has_metadata::<EscalatedProbabilitiesMetadata>() {
+            let previous = TuneableScheduledMutatorMetadata::get(state)?
+                .mutation_probabilities_cumulative
+                .clone();
+            state.add_metadata(EscalatedProbabilitiesMetadata { previous });
+        }
+        let metadata = TuneableScheduledMutatorMetadata::get_mut(state)?;
+        let idx = level.saturating_sub(1).min(self.levels.len() - 1);
+        metadata.mutation_ids.clear();
+        metadata.next_id = 0.into();
+        metadata
+            .mutation_probabilities_cumulative
+            .clone_from(&self.levels[idx]);
+        Ok(())
+    }
+
+    fn recover(&mut self, state: &mut S, _level: usize) -> Result<(), Error> {
+        if let Some(escalated) = state.remove_metadata::<EscalatedProbabilitiesMetadata>() {
+            TuneableScheduledMutatorMetadata::get_mut(state)?.mutation_probabilities_cumulative =
+                escalated.previous;
+        }
+        Ok(())
+    }
+}
+
+/// Monitors the time since the corpus last grew. After `plateau_time` without new coverage, it
+/// escalates one level through its [`EscalationPolicy`], up to `max_level`, once per
+/// `plateau_time`, and runs its escalation stages on each run until the fuzzer makes progress
+/// again. Then the policy recovers.
+#[derive(Debug)]
+pub struct PlateauStage<E, EM, P, ST, Z> {
+    name: Cow<'static, str>,
+    plateau_time: Duration,
+    max_level: usize,
+    policy: P,
+    stages: ST,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, P, ST, Z> UsesState for PlateauStage<E, EM, P, ST, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, EM, P, ST, Z> Named for PlateauStage<E, EM, P, ST, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<E, EM, P, ST, Z> Stage<E, EM, Z> for PlateauStage<E, EM, P, ST, Z>
+where
+    E: UsesState,
+    EM: UsesState<State = Self::State>,
+    P: EscalationPolicy<Self::State>,
+    ST: StagesTuple<E, EM, Self::State, Z>,
+    Z: UsesState<State = Self::State>,
+    Self::State: HasCorpus + HasMetadata + HasNamedMetadata + HasNestedStageStatus,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        // Resume the escalation stages after a restart
+        if state.current_stage_idx()?.is_some() {
+            return self.stages.perform_all(fuzzer, executor, state, manager);
+        }
+
+        let now = current_time();
+        let corpus_count = state.corpus().count();
+        let Some(meta) = state
+            .named_metadata_map_mut()
+            .get_mut::<PlateauMetadata>(&self.name)
+        else {
+            state.add_named_metadata(
+                &self.name,
+                PlateauMetadata {
+                    last_progress: now,
+                    corpus_count,
+                    level: 0,
+                },
+            );
+            return Ok(());
+        };
+
+        let level = meta.level;
+        if corpus_count > meta.corpus_count {
+            meta.corpus_count = corpus_count;
+            meta.last_progress = now;
+            meta.level = 0;
+            if level > 0 {
+                log::info!(
+                    "{}: progress resumed, recovering from level {level}",
+                    self.name
+                );
+                self.policy.recover(state, level)?;
+            }
+            return Ok(());
+        }
+        meta.corpus_count = corpus_count;
+
+        let plateau_time = self.plateau_time.as_secs_f64();
+        #[allow(
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss,
+            clippy::cast_precision_loss
+        )]
+        let target = ((now.saturating_sub(meta.last_progress).as_secs_f64() / plateau_time)
+            as usize)
+            .min(self.max_level);
+        if target > level {
+            meta.level = target;
+            for next in (level + 1)..=target {
+                log::info!("{}: no progress, escalating to level {next}", self.name);
+                self.policy.escalate(state, next)?;
+            }
+        }
+
+        if target > 0 {
+            self.stages.perform_all(fuzzer, executor, state, manager)?;
+        }
+        Ok(())
+    }
+
+    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        NestedStageStdRestartHelper::should_restart(state, self)
+    }
+
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        NestedStageStdRestartHelper::clear_progress(state, self)
+    }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        self.stages.declare_registers_all(registers);
+    }
+}
+
+impl<E, EM, P, ST, Z> PlateauStage<E, EM, P, ST, Z> {
+    /// Creates a new [`PlateauStage`], escalating through the `policy` and running the `stages`
+    /// after [`DEFAULT_PLATEAU_TIME`] without new coverage.
+    /// Use `()` as `stages` to only switch strategies through the policy.
+    pub fn new(policy: P, stages: ST) -> Self {
+        Self::with_name(policy, stages, PLATEAU_STAGE_NAME)
+    }
+
+    /// Creates a new [`PlateauStage`] with a custom name, to use more than one per fuzzer
+    pub fn with_name(policy: P, stages: ST, name: &str) -> Self {
+        Self {
+            name: Cow::Owned(name.to_owned()),
+            plateau_time: DEFAULT_PLATEAU_TIME,
+            max_level: DEFAULT_MAX_ESCALATION_LEVEL,
+            policy,
+            stages,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the time without new coverage after which to escalate one level
+    #[must_use]
+    pub fn with_plateau_time(mut self, plateau_time: Duration) -> Self {
+        self.plateau_time = plateau_time.max(Duration::from_secs(1));
+        self
+    }
+
+    /// Sets the highest escalation level, at least 1
+    #[must_use]
+    pub fn with_max_level(mut self, max_level: usize) -> Self {
+        self.max_level = max_level.max(1);
+        self
+    }
+
+    /// The escalation policy
+    #[must_use]
+    pub fn policy(&self) -> &P {
+        &self.policy
+    }
+
+    /// The escalation policy, mut
+    pub fn policy_mut(&mut self) -> &mut P {
+        &mut self.policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{rc::Rc, vec, vec::Vec};
+    use core::{cell::RefCell, time::Duration};
+
+    use libafl_bolts::current_time;
+
+    use super::{
+        ClosureEscalation, EscalatedProbabilitiesMetadata, EscalationPolicy,
+        MutationProbabilityEscalation, PlateauMetadata, PlateauStage, PLATEAU_STAGE_NAME,
+    };
+    use crate::{
+        corpus::{Corpus, Testcase},
+        events::NopEventManager,
+        executors::test::NopExecutor,
+        fuzzer::test::NopFuzzer,
+        inputs::BytesInput,
+        mutators::TuneableScheduledMutatorMetadata,
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus},
+        HasMetadata, HasNamedMetadata,
+    };
+
+    #[test]
+    fn test_plateau_stage() {
+        let levels = Rc::new(RefCell::new(Vec::new()));
+        let logged = levels.clone();
+        let mut stage = PlateauStage::new(
+            ClosureEscalation::new(move |_state: &mut _, level| {
+                logged.borrow_mut().push(level);
+                Ok(())
+            }),
+            (),
+        )
+        .with_plateau_time(Duration::from_secs(60));
+        let mut state = test_std_state::<BytesInput>();
+        let mut fuzzer = NopFuzzer::new();
+        let mut executor = NopExecutor::new();
+        let mut mgr = NopEventManager::new();
+
+        // The first run starts the clock
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert!(levels.borrow().is_empty());
+
+        // Two and a half plateau times without progress escalate two levels at once
+        state
+            .named_metadata_mut::<PlateauMetadata>(PLATEAU_STAGE_NAME)
+            .unwrap()
+            .last_progress = current_time().saturating_sub(Duration::from_secs(150));
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*levels.borrow(), [1, 2]);
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*levels.borrow(), [1, 2]);
+
+        // New coverage recovers
+        state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![0])))
+            .unwrap();
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*levels.borrow(), [1, 2, 0]);
+        let meta = state
+            .named_metadata::<PlateauMetadata>(PLATEAU_STAGE_NAME)
+            .unwrap();
+        assert_eq!(meta.level(), 0);
+    }
+
+    #[test]
+    fn test_mutation_probability_escalation() {
+        let mut state = test_std_state::<BytesInput>();
+        state.add_metadata(TuneableScheduledMutatorMetadata {
+            mutation_probabilities_cumulative: vec![0.5, 1.0],
+            ..TuneableScheduledMutatorMetadata::default()
+        });
+
+        let mut policy = MutationProbabilityEscalation::new(vec![vec![0.0, 1.0]]).unwrap();
+        policy.escalate(&mut state, 1).unwrap();
+        policy.escalate(&mut state, 2).unwrap();
+        assert_eq!(
+            TuneableScheduledMutatorMetadata::get(&state)
+                .unwrap()
+                .mutation_probabilities_cumulative,
+            [0.0, f32::INFINITY]
+        );
+
+        // The previous probabilities survive a restart, which re-creates the policy
+        let mut policy = MutationProbabilityEscalation::new(vec![vec![0.0, 1.0]]).unwrap();
+        policy.recover(&mut state, 2).unwrap();
+        assert_eq!(
+            TuneableScheduledMutatorMetadata::get(&state)
+                .unwrap()
+                .mutation_probabilities_cumulative,
+            [0.5, 1.0]
+        );
+        assert!(!state.has_metadata::<EscalatedProbabilitiesMetadata>());
+    }
+}
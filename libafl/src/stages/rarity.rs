@@ -0,0 +1,93 @@
+//! The [`EdgeFrequencySyncStage`] broadcasts the local edge frequencies of the
+//! [`EdgeFrequencyMetadata`] to other clients, see [`crate::schedulers::rarity`].
+
+use core::{marker::PhantomData, time::Duration};
+
+use libafl_bolts::current_time;
+
+use crate::{
+    events::EventFirer,
+    schedulers::rarity::{EdgeFrequencyHook, EdgeFrequencyMetadata},
+    stages::Stage,
+    state::UsesState,
+    Error, HasMetadata,
+};
+
+/// The default interval between two broadcasts of the edge frequencies
+pub const DEFAULT_EDGE_FREQUENCY_SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Periodically fires the local counts of the [`EdgeFrequencyMetadata`] not yet sent, to be merged
+/// by the [`EdgeFrequencyHook`] of the other clients
+#[derive(Debug, Clone)]
+pub struct EdgeFrequencySyncStage<E, EM, Z> {
+    interval: Duration,
+    last_sync: Duration,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, Z> UsesState for EdgeFrequencySyncStage<E, EM, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E, EM, Z> Stage<E, EM, Z> for EdgeFrequencySyncStage<E, EM, Z>
+where
+    E: UsesState,
+    EM: EventFirer<State = Self::State>,
+    Z: UsesState<State = Self::State>,
+    Self::State: HasMetadata,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let cur = current_time();
+        if cur.checked_sub(self.last_sync).unwrap_or_default() < self.interval {
+            return Ok(());
+        }
+        self.last_sync = cur;
+
+        let Some(meta) = state.metadata_map_mut().get_mut::<EdgeFrequencyMetadata>() else {
+            return Ok(());
+        };
+        let counts = meta.take_unsent();
+        if !counts.is_empty() {
+            manager.fire(state, EdgeFrequencyHook::event(&counts)?)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // Not running the target, nothing to restore
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, Z> EdgeFrequencySyncStage<E, EM, Z> {
+    /// Creates a new [`EdgeFrequencySyncStage`], broadcasting at most once per `interval`
+    #[must_use]
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last_sync: Duration::ZERO,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<E, EM, Z> Default for EdgeFrequencySyncStage<E, EM, Z> {
+    fn default() -> Self {
+        Self::new(DEFAULT_EDGE_FREQUENCY_SYNC_INTERVAL)
+    }
+}
@@ -0,0 +1,112 @@
+//! The [`RegisterUsageStage`] declares the [`StageRegister`]s used by a stage that can't declare
+//! them itself, e.g. a mutational stage whose mutators read a register.
+
+use alloc::{borrow::Cow, vec::Vec};
+
+use libafl_bolts::Named;
+
+use crate::{
+    stages::Stage,
+    state::{RegisterDeclaration, StageRegister, StageRegisterDeclarations, UsesState},
+    Error,
+};
+
+/// Wraps a [`Stage`], adding declarations of produced and consumed registers
+#[derive(Debug)]
+pub struct RegisterUsageStage<ST> {
+    stage: ST,
+    produced: Vec<RegisterDeclaration>,
+    consumed: Vec<RegisterDeclaration>,
+}
+
+impl<ST> UsesState for RegisterUsageStage<ST>
+where
+    ST: UsesState,
+{
+    type State = ST::State;
+}
+
+impl<ST> Named for RegisterUsageStage<ST>
+where
+    ST: Named,
+{
+    fn name(&self) -> &Cow<'static, str> {
+        self.stage.name()
+    }
+}
+
+impl<E, EM, ST, Z> Stage<E, EM, Z> for RegisterUsageStage<ST>
+where
+    ST: Stage<E, EM, Z>,
+    E: UsesState<State = Self::State>,
+    EM: UsesState<State = Self::State>,
+    Z: UsesState<State = Self::State>,
+{
+    #[inline]
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        self.stage.perform(fuzzer, executor, state, manager)
+    }
+
+    #[inline]
+    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        self.stage.should_restart(state)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.stage.clear_progress(state)
+    }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        self.stage.declare_registers(registers);
+        for declaration in &self.produced {
+            registers.produces_declaration(*declaration);
+        }
+        for declaration in &self.consumed {
+            registers.consumes_declaration(*declaration);
+        }
+    }
+}
+
+impl<ST> RegisterUsageStage<ST> {
+    /// Wraps the stage, without declarations yet
+    #[must_use]
+    pub fn new(stage: ST) -> Self {
+        Self {
+            stage,
+            produced: Vec::new(),
+            consumed: Vec::new(),
+        }
+    }
+
+    /// Declares that the wrapped stage produces the register
+    #[must_use]
+    pub fn produces<T>(mut self, register: &StageRegister<T>) -> Self {
+        self.produced.push(register.declaration());
+        self
+    }
+
+    /// Declares that the wrapped stage consumes the register
+    #[must_use]
+    pub fn consumes<T>(mut self, register: &StageRegister<T>) -> Self {
+        self.consumed.push(register.declaration());
+        self
+    }
+
+    /// The wrapped stage
+    #[must_use]
+    pub fn inner(&self) -> &ST {
+        &self.stage
+    }
+
+    /// The wrapped stage (mutable)
+    pub fn inner_mut(&mut self) -> &mut ST {
+        &mut self.stage
+    }
+}
@@ -0,0 +1,455 @@
+//! The [`RegressionStage`] replays the whole corpus and all solutions once the target binary was
+//! rebuilt, e.g. after a patch.
+//!
+//! The first run stores the build-id of the target in the state. Once a later run sees another
+//! build-id, the stage replays the existing entries in batches, one batch per run, each twice as
+//! large as the previous one, up to [`MAX_REGRESSION_BATCH`]. The first batches are quick, and the
+//! replay soon takes most of the time of each fuzzing iteration, while the other stages keep
+//! running in between. Entries that crash the client are recorded after the restart. At the end,
+//! it reports the coverage of the replayed entries, compared to the last replay if there was one,
+//! the corpus entries that now crash, and the solutions that are fixed or still crash.
+//!
+//! Add it as the first stage, so each batch runs before the other stages of the iteration.
+
+use alloc::{borrow::Cow, string::String, vec::Vec};
+use core::{fmt::Write, marker::PhantomData};
+
+use libafl_bolts::{impl_serdeany, tuples::Handle, Named};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId},
+    events::{EventFirer, LogSeverity},
+    executors::{Executor, ExitKind, HasObservers},
+    observers::{MapObserver, ObserversTuple},
+    stages::Stage,
+    state::{HasCorpus, HasSolutions, UsesState},
+    Error, HasMetadata,
+};
+
+/// The maximal number of entries the [`RegressionStage`] replays per run
+pub const MAX_REGRESSION_BATCH: usize = 1 << 12;
+
+/// The progress and results of a replay of the [`RegressionStage`]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RegressionReplay {
+    /// The corpus entries to replay
+    pub corpus: Vec<CorpusId>,
+    /// The solutions to replay, after the corpus
+    pub solutions: Vec<CorpusId>,
+    /// The number of entries replayed so far
+    pub position: usize,
+    /// The number of entries to replay in the next run
+    pub batch: usize,
+    /// Set while an entry runs; still set after a restart if the entry crashed the client
+    pub in_flight: bool,
+    /// The map indices covered by the replayed entries, as bitset
+    pub covered: Vec<u64>,
+    /// The corpus entries crashing or timing out on the new build
+    pub crashing_entries: Vec<CorpusId>,
+    /// The solutions no longer crashing or timing out
+    pub fixed: Vec<CorpusId>,
+    /// The solutions still crashing or timing out
+    pub still_crashing: Vec<CorpusId>,
+}
+
+impl RegressionReplay {
+    /// Checks if all entries were replayed
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.position >= self.corpus.len() + self.solutions.len()
+    }
+
+    /// The number of map indices covered by the replayed entries
+    #[must_use]
+    pub fn covered_edges(&self) -> usize {
+        self.covered
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Records the result of the entry at `position`
+    fn record(&mut self, position: usize, exit_kind: ExitKind) {
+        let crashed = exit_kind != ExitKind::Ok;
+        if let Some(id) = self.corpus.get(position) {
+            if crashed {
+                self.crashing_entries.push(*id);
+            }
+        } else if let Some(id) = self.solutions.get(position - self.corpus.len()) {
+            if crashed {
+                self.still_crashing.push(*id);
+            } else {
+                self.fixed.push(*id);
+            }
+        }
+    }
+}
+
+/// The build-id of the target and the regression replay, stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct RegressionMetadata {
+    /// The build-id of the target the state was fuzzed with
+    pub build_id: Vec<u8>,
+    /// The map indices covered by the corpus on the last replayed build, if any
+    pub covered_edges: Option<usize>,
+    /// The running or last finished replay
+    pub replay: Option<RegressionReplay>,
+}
+
+impl_serdeany!(RegressionMetadata);
+
+/// Replays the corpus and the solutions when the build-id of the target changes, reporting
+/// coverage differences, newly crashing corpus entries, and fixed or still crashing solutions.
+#[derive(Debug)]
+pub struct RegressionStage<C, E, EM, O, Z> {
+    name: Cow<'static, str>,
+    build_id: Vec<u8>,
+    map_observer_handle: Handle<C>,
+    phantom: PhantomData<(E, EM, O, Z)>,
+}
+
+impl<C, E, EM, O, Z> UsesState for RegressionStage<C, E, EM, O, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<C, E, EM, O, Z> RegressionStage<C, E, EM, O, Z> {
+    /// Creates a new [`RegressionStage`] for the target with the given build-id, measuring the
+    /// coverage of the replay with the map observer
+    #[must_use]
+    pub fn new(build_id: Vec<u8>, map_observer_handle: Handle<C>) -> Self {
+        Self {
+            name: Cow::Borrowed("RegressionStage"),
+            build_id,
+            map_observer_handle,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Creates a new [`RegressionStage`] for the target binary at the path, identified by its
+    /// GNU build-id, or by a hash of the file if it has none
+    #[cfg(feature = "std")]
+    pub fn from_binary<P>(path: P, map_observer_handle: Handle<C>) -> Result<Self, Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        Ok(Self::new(build_id_of(path)?, map_observer_handle))
+    }
+
+    /// The build-id of the target
+    #[must_use]
+    pub fn build_id(&self) -> &[u8] {
+        &self.build_id
+    }
+}
+
+impl<C, E, EM, O, Z> Stage<E, EM, Z> for RegressionStage<C, E, EM, O, Z>
+where
+    E: Executor<EM, Z> + HasObservers,
+    E::Observers: ObserversTuple<Self::State>,
+    EM: EventFirer<State = Self::State>,
+    O: MapObserver,
+    C: AsRef<O>,
+    Z: UsesState<State = Self::State>,
+    Self::State: HasCorpus + HasSolutions + HasMetadata,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        if !state.has_metadata::<RegressionMetadata>() {
+            state.add_metadata(RegressionMetadata {
+                build_id: self.build_id.clone(),
+                ..RegressionMetadata::default()
+            });
+            return Ok(());
+        }
+
+        let meta = state.metadata_mut::<RegressionMetadata>()?;
+        if meta.build_id != self.build_id {
+            meta.build_id.clone_from(&self.build_id);
+            let replay = RegressionReplay {
+                corpus: state.corpus().ids().collect(),
+                solutions: state.solutions().ids().collect(),
+                batch: 1,
+                ..RegressionReplay::default()
+            };
+            let message = format!(
+                "Target build changed to {}, replaying {} corpus entries and {} solutions",
+                hex(&self.build_id),
+                replay.corpus.len(),
+                replay.solutions.len()
+            );
+            state.metadata_mut::<RegressionMetadata>()?.replay = Some(replay);
+            manager.log(state, LogSeverity::Info, message)?;
+        }
+
+        let meta = state.metadata_mut::<RegressionMetadata>()?;
+        let Some(replay) = meta.replay.as_mut().filter(|replay| !replay.is_done()) else {
+            return Ok(());
+        };
+        if replay.in_flight {
+            // The client restarted while running the entry, it crashed
+            replay.in_flight = false;
+            replay.record(replay.position, ExitKind::Crash);
+            replay.position += 1;
+        }
+        let start = replay.position;
+        let end = (start + replay.batch).min(replay.corpus.len() + replay.solutions.len());
+        replay.batch = (replay.batch * 2).min(MAX_REGRESSION_BATCH);
+
+        for position in start..end {
+            let replay = replay_mut(state)?;
+            replay.in_flight = true;
+            let corpus_len = replay.corpus.len();
+            let input = if position < corpus_len {
+                let id = replay.corpus[position];
+                state.corpus().cloned_input_for_id(id)
+            } else {
+                let id = replay.solutions[position - corpus_len];
+                state.solutions().cloned_input_for_id(id)
+            };
+            // The entry may have been removed meanwhile
+            let exit_kind = match input {
+                Ok(input) => {
+                    executor.observers_mut().pre_exec_all(state, &input)?;
+                    let exit_kind = executor.run_target(fuzzer, state, manager, &input)?;
+                    executor
+                        .observers_mut()
+                        .post_exec_all(state, &input, &exit_kind)?;
+                    Some(exit_kind)
+                }
+                Err(_) => None,
+            };
+
+            let observers = executor.observers();
+            let map = observers[&self.map_observer_handle].as_ref();
+            let replay = replay_mut(state)?;
+            if let Some(exit_kind) = exit_kind {
+                let len = map.usable_count();
+                replay
+                    .covered
+                    .resize(replay.covered.len().max(len.div_ceil(64)), 0);
+                let initial = map.initial();
+                for idx in (0..len).filter(|idx| map.get(*idx) != initial) {
+                    replay.covered[idx / 64] |= 1 << (idx % 64);
+                }
+                replay.record(position, exit_kind);
+            }
+            replay.in_flight = false;
+            replay.position = position + 1;
+        }
+
+        let meta = state.metadata_mut::<RegressionMetadata>()?;
+        let replay = meta.replay.as_ref().unwrap();
+        if !replay.is_done() {
+            return Ok(());
+        }
+        let edges = replay.covered_edges();
+        let mut message = format!(
+            "Regression replay done: {} corpus entries ({} now crash), {} solutions ({} fixed, {} still crash), {edges} edges covered",
+            replay.corpus.len(),
+            replay.crashing_entries.len(),
+            replay.solutions.len(),
+            replay.fixed.len(),
+            replay.still_crashing.len(),
+        );
+        if let Some(previous) = meta.covered_edges {
+            if edges >= previous {
+                write!(message, " (+{} since the previous build)", edges - previous).unwrap();
+            } else {
+                write!(message, " (-{} since the previous build)", previous - edges).unwrap();
+            }
+        }
+        meta.covered_edges = Some(edges);
+        manager.log(state, LogSeverity::Info, message)?;
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // The replay tracks its own progress, including entries that crashed the client
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<C, E, EM, O, Z> Named for RegressionStage<C, E, EM, O, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+/// The running replay
+fn replay_mut<S>(state: &mut S) -> Result<&mut RegressionReplay, Error>
+where
+    S: HasMetadata,
+{
+    state
+        .metadata_mut::<RegressionMetadata>()?
+        .replay
+        .as_mut()
+        .ok_or_else(|| Error::illegal_state("No regression replay running"))
+}
+
+/// Formats a build-id as hex
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        write!(hex, "{b:02x}").unwrap();
+        hex
+    })
+}
+
+/// The build-id of a binary: its GNU build-id if it has one, else a hash of the file
+#[cfg(feature = "std")]
+pub fn build_id_of<P>(path: P) -> Result<Vec<u8>, Error>
+where
+    P: AsRef<std::path::Path>,
+{
+    use core::hash::{BuildHasher, Hasher};
+
+    let bytes = std::fs::read(path)?;
+    if let Some(build_id) = libafl_bolts::build_id::gnu_build_id(&bytes) {
+        return Ok(build_id.to_vec());
+    }
+    let mut hasher = ahash::RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+    hasher.write(&bytes);
+    Ok(hasher.finish().to_le_bytes().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use libafl_bolts::{
+        tuples::{tuple_list, Handled},
+        AsSliceMut,
+    };
+
+    use super::{RegressionMetadata, RegressionStage};
+    use crate::{
+        corpus::{Corpus, CorpusId, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        fuzzer::test::NopFuzzer,
+        inputs::{BytesInput, HasMutatorBytes},
+        observers::StdMapObserver,
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus, HasExecutions, HasSolutions},
+        HasMetadata,
+    };
+
+    #[test]
+    fn test_regression_replay() {
+        let observer = StdMapObserver::owned("map", vec![0_u8; 16]);
+        let handle = observer.handle();
+        let mut fuzzer = NopFuzzer::new();
+        // Inputs starting with 0xff crash, the others cover the index of their first byte
+        let mut executor = ClosureExecutor::new(
+            |observers: &mut (StdMapObserver<'static, u8, false>, ()), input: &BytesInput| {
+                match input.bytes()[0] {
+                    0xff => ExitKind::Crash,
+                    idx => {
+                        observers.0.as_slice_mut()[idx as usize] = 1;
+                        ExitKind::Ok
+                    }
+                }
+            },
+            tuple_list!(observer),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+        let corpus: Vec<CorpusId> = [1, 2, 0xff]
+            .into_iter()
+            .map(|b| {
+                state
+                    .corpus_mut()
+                    .add(Testcase::new(BytesInput::new(vec![b])))
+                    .unwrap()
+            })
+            .collect();
+        let solutions: Vec<CorpusId> = [0xff, 3]
+            .into_iter()
+            .map(|b| {
+                state
+                    .solutions_mut()
+                    .add(Testcase::new(BytesInput::new(vec![b])))
+                    .unwrap()
+            })
+            .collect();
+
+        // The first run only records the build
+        let mut stage = RegressionStage::<_, _, _, StdMapObserver<'static, u8, false>, _>::new(
+            vec![1],
+            handle.clone(),
+        );
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*state.executions(), 0);
+        assert!(state
+            .metadata::<RegressionMetadata>()
+            .unwrap()
+            .replay
+            .is_none());
+
+        // After a rebuild, the batches double: 1, 2, then the last 2 of the 5 entries
+        let mut stage =
+            RegressionStage::<_, _, _, StdMapObserver<'static, u8, false>, _>::new(vec![2], handle);
+        for executions in [1, 3, 5, 5] {
+            stage
+                .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+                .unwrap();
+            assert_eq!(*state.executions(), executions);
+        }
+        let meta = state.metadata::<RegressionMetadata>().unwrap();
+        assert_eq!(meta.build_id, [2]);
+        assert_eq!(meta.covered_edges, Some(3));
+        let replay = meta.replay.as_ref().unwrap();
+        assert!(replay.is_done());
+        assert_eq!(replay.crashing_entries, [corpus[2]]);
+        assert_eq!(replay.still_crashing, [solutions[0]]);
+        assert_eq!(replay.fixed, [solutions[1]]);
+
+        // An entry still running after a restart crashed the client and is skipped
+        stage.build_id = vec![3];
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*state.executions(), 6);
+        let replay = state
+            .metadata_mut::<RegressionMetadata>()
+            .unwrap()
+            .replay
+            .as_mut()
+            .unwrap();
+        assert_eq!(replay.position, 1);
+        replay.in_flight = true;
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*state.executions(), 8);
+        let replay = state
+            .metadata::<RegressionMetadata>()
+            .unwrap()
+            .replay
+            .as_ref()
+            .unwrap();
+        assert_eq!(replay.position, 4);
+        assert_eq!(replay.crashing_entries, [corpus[1], corpus[2]]);
+    }
+}
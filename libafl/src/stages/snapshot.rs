@@ -0,0 +1,83 @@
+//! The [`CorpusSnapshotStage`] periodically captures a [`CorpusSnapshot`] of the campaign
+
+use core::marker::PhantomData;
+
+use serde::Serialize;
+
+use crate::{
+    corpus::CorpusSnapshot,
+    inputs::{Input, UsesInput},
+    stages::Stage,
+    state::{HasCorpus, HasSolutions, UsesState},
+    Error,
+};
+
+/// A stage capturing a [`CorpusSnapshot`] of the whole state whenever its interval passed
+#[derive(Debug)]
+pub struct CorpusSnapshotStage<EM, Z> {
+    snapshot: CorpusSnapshot,
+    phantom: PhantomData<(EM, Z)>,
+}
+
+impl<EM, Z> UsesState for CorpusSnapshotStage<EM, Z>
+where
+    EM: UsesState,
+{
+    type State = EM::State;
+}
+
+impl<E, EM, Z> Stage<E, EM, Z> for CorpusSnapshotStage<EM, Z>
+where
+    EM: UsesState,
+    E: UsesState<State = Self::State>,
+    Z: UsesState<State = Self::State>,
+    Self::State: HasCorpus + HasSolutions + Serialize,
+    <Self::State as UsesInput>::Input: Input,
+{
+    #[inline]
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut Self::State,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        self.snapshot.maybe_capture(state)?;
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut Self::State) -> Result<(), Error> {
+        // Not executing the target, so restart safety is not needed
+        Ok(())
+    }
+}
+
+impl<EM, Z> CorpusSnapshotStage<EM, Z> {
+    /// Create a new [`CorpusSnapshotStage`] capturing to the given [`CorpusSnapshot`].
+    /// Use [`CorpusSnapshot::load_latest`] to restore the state before the campaign starts.
+    #[must_use]
+    pub fn new(snapshot: CorpusSnapshot) -> Self {
+        Self {
+            snapshot,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The [`CorpusSnapshot`] of this stage
+    #[must_use]
+    pub fn snapshot(&self) -> &CorpusSnapshot {
+        &self.snapshot
+    }
+
+    /// The [`CorpusSnapshot`] of this stage (mutable), e.g. to capture a final snapshot
+    pub fn snapshot_mut(&mut self) -> &mut CorpusSnapshot {
+        &mut self.snapshot
+    }
+}
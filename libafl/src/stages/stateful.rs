@@ -0,0 +1,147 @@
+//! The [`SnapshotSequenceStage`] fuzzes [`SequenceInput`]s of stateful targets, e.g. protocol
+//! message sequences, from a snapshot of the target, see [`crate::executors::snapshot_fork`].
+
+use alloc::borrow::{Cow, ToOwned};
+use core::marker::PhantomData;
+
+use libafl_bolts::{rands::Rand, Named};
+
+use crate::{
+    executors::HasSequenceSnapshot,
+    inputs::{same_ops, SequenceInput, SequenceOp, UsesInput},
+    mutators::{MutationResult, Mutator},
+    stages::{mutational::DEFAULT_MUTATIONAL_MAX_ITERATIONS, Stage, StdRestartHelper},
+    state::{HasCurrentTestcase, HasRand, UsesState},
+    Error, Evaluator, HasNamedMetadata,
+};
+
+/// The default name of the [`SnapshotSequenceStage`]
+pub const SNAPSHOT_SEQUENCE_STAGE_NAME: &str = "snapshotsequence";
+
+/// The default snapshot prefix: all operations but the last one
+fn all_but_last<T>(ops: &[T]) -> usize {
+    ops.len().saturating_sub(1)
+}
+
+/// Fuzzes the operations after a prefix of the current testcase, from a snapshot of the target
+/// after replaying this prefix.
+///
+/// The prefix, e.g. a protocol handshake, is chosen by the `prefix_len` function, by default all
+/// operations but the last one. The executor takes a new snapshot whenever the prefix changes, and
+/// the mutator only sees the remaining operations, so every execution starts from the snapshot.
+#[derive(Debug, Clone)]
+pub struct SnapshotSequenceStage<E, EM, M, T, Z> {
+    name: Cow<'static, str>,
+    mutator: M,
+    max_iterations: usize,
+    prefix_len: fn(&[T]) -> usize,
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, M, T, Z> UsesState for SnapshotSequenceStage<E, EM, M, T, Z>
+where
+    Z: UsesState,
+{
+    type State = Z::State;
+}
+
+impl<E, EM, M, T, Z> Named for SnapshotSequenceStage<E, EM, M, T, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<E, EM, M, T, Z> Stage<E, EM, Z> for SnapshotSequenceStage<E, EM, M, T, Z>
+where
+    E: HasSequenceSnapshot<T> + UsesState<State = Self::State>,
+    EM: UsesState<State = Self::State>,
+    M: Mutator<SequenceInput<T>, Self::State>,
+    T: SequenceOp,
+    Z: Evaluator<E, EM>,
+    Self::State: HasCurrentTestcase<SequenceInput<T>> + HasRand + HasNamedMetadata,
+    Self::State: UsesInput<Input = SequenceInput<T>>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let input = state.current_input_cloned()?;
+        let prefix_len = (self.prefix_len)(input.ops()).min(input.ops().len());
+        let (prefix, rest) = input.ops().split_at(prefix_len);
+
+        let has_snapshot = executor
+            .snapshot_prefix()
+            .is_some_and(|current| same_ops(current, prefix));
+        if !has_snapshot {
+            if let Err(err) = executor.take_snapshot(prefix) {
+                // Without a snapshot, the executor replays every input from the start
+                log::warn!("Could not snapshot the prefix of the current testcase: {err}");
+                executor.discard_snapshot();
+            }
+        }
+
+        let iterations = 1 + state.rand_mut().below(self.max_iterations);
+        for _ in 0..iterations {
+            let mut suffix = SequenceInput::new(rest.to_vec());
+            if self.mutator.mutate(state, &mut suffix)? == MutationResult::Skipped {
+                continue;
+            }
+            let mut ops = prefix.to_vec();
+            ops.extend_from_slice(suffix.ops());
+
+            let (_, corpus_id) =
+                fuzzer.evaluate_input(state, executor, manager, SequenceInput::new(ops))?;
+            self.mutator.post_exec(state, corpus_id)?;
+        }
+        Ok(())
+    }
+
+    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        StdRestartHelper::should_restart(state, &self.name, 3)
+    }
+
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        StdRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+impl<E, EM, M, T, Z> SnapshotSequenceStage<E, EM, M, T, Z> {
+    /// Creates a new [`SnapshotSequenceStage`], snapshotting all operations but the last one
+    pub fn new(mutator: M) -> Self {
+        Self {
+            name: Cow::Owned(SNAPSHOT_SEQUENCE_STAGE_NAME.to_owned()),
+            mutator,
+            max_iterations: DEFAULT_MUTATIONAL_MAX_ITERATIONS,
+            prefix_len: all_but_last,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the function choosing the number of operations to snapshot for a testcase
+    #[must_use]
+    pub fn with_prefix_len(mut self, prefix_len: fn(&[T]) -> usize) -> Self {
+        self.prefix_len = prefix_len;
+        self
+    }
+
+    /// Sets the maximum number of executions per testcase, at least 1
+    #[must_use]
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+
+    /// The mutator, applied to the operations after the prefix
+    #[must_use]
+    pub fn mutator(&self) -> &M {
+        &self.mutator
+    }
+
+    /// The mutator, applied to the operations after the prefix, mut
+    pub fn mutator_mut(&mut self) -> &mut M {
+        &mut self.mutator
+    }
+}
@@ -1,6 +1,39 @@
 //! The [`SyncFromDiskStage`] is a stage that imports inputs from disk for e.g. sync with AFL
+//!
+//! Next to plain directories (synced by modification time), the stage supports a multi-writer
+//! sync directory format, see [`SyncDirWriter`]:
+//!
+//! ```text
+//! sync_dir/
+//!   <writer id>/
+//!     00000000000000000000      <- entries, named by a per-writer sequence number
+//!     00000000000000000001
+//!     .tmp-00000000000000000002 <- entry currently being written, ignored by readers
+//! ```
+//!
+//! Each writer only ever creates files in its own subdirectory, and entries are published by an
+//! atomic rename, so any number of clients (or external tools) can add entries concurrently.
+//! Readers remember the last consumed sequence number per writer and import each entry once.
+//!
+//! Finally, the stage can take part in an AFL++ `-M/-S` campaign, see [`AflQueueWriter`]:
+//!
+//! ```text
+//! sync_dir/
+//!   <fuzzer name>/
+//!     queue/
+//!       id:000000,...           <- entries, exported by each fuzzer into its own queue
+//!     .synced/
+//!       <other fuzzer name>     <- sync cursor for the queue of the other fuzzer
+//! ```
+//!
+//! The stage exports the inputs found by this client into its own queue, and imports the queue
+//! entries of all other fuzzers. The cursor per foreign fuzzer is kept on disk like AFL++ does, so
+//! entries are not imported again after a restart.
 
-use alloc::borrow::{Cow, ToOwned};
+use alloc::{
+    borrow::{Cow, ToOwned},
+    string::{String, ToString},
+};
 use core::marker::PhantomData;
 use std::{
     fs,
@@ -9,13 +42,16 @@ use std::{
     vec::Vec,
 };
 
+use hashbrown::HashMap;
 use libafl_bolts::{current_time, shmem::ShMemProvider, Named};
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "introspection")]
 use crate::state::HasClientPerfMonitor;
 use crate::{
-    corpus::{Corpus, CorpusId, HasTestcase},
+    corpus::{
+        Corpus, CorpusId, HasTestcase, InputOrigin, InputOriginMetadata, PendingInputOriginMetadata,
+    },
     events::{llmp::LlmpEventConverter, Event, EventConfig, EventFirer},
     executors::{Executor, ExitKind, HasObservers},
     fuzzer::{Evaluator, EvaluatorObservers, ExecutionProcessor},
@@ -36,6 +72,13 @@ pub struct SyncFromDiskMetadata {
     pub last_time: SystemTime,
     /// The paths that are left to sync
     pub left_to_sync: Vec<PathBuf>,
+    /// The last imported sequence number for each writer of a multi-writer sync directory, or the
+    /// last imported queue entry id of each fuzzer of an AFL++ sync directory
+    #[serde(default)]
+    pub last_seqs: HashMap<String, u64>,
+    /// The last corpus entry exported into the queue of an AFL++ sync directory
+    #[serde(default)]
+    pub last_exported: Option<CorpusId>,
 }
 
 libafl_bolts::impl_serdeany!(SyncFromDiskMetadata);
@@ -47,18 +90,350 @@ impl SyncFromDiskMetadata {
         Self {
             last_time,
             left_to_sync,
+            last_seqs: HashMap::new(),
+            last_exported: None,
+        }
+    }
+}
+
+/// The prefix of entries that are still being written in a multi-writer sync directory
+const SYNC_DIR_TMP_PREFIX: &str = ".tmp-";
+
+/// Formats a sequence number as entry name in a multi-writer sync directory.
+/// The fixed width keeps the lexicographic order equal to the numeric order.
+fn sync_dir_entry_name(seq: u64) -> String {
+    format!("{seq:020}")
+}
+
+/// Writes entries to a multi-writer sync directory (see the [module documentation](self)).
+///
+/// Every writer owns a subdirectory named after its unique id. Entries are written to a temporary
+/// file first and then atomically renamed to their sequence number, so readers never observe
+/// partially written entries and never import the same entry twice.
+#[derive(Debug)]
+pub struct SyncDirWriter {
+    dir: PathBuf,
+    next_seq: u64,
+}
+
+impl SyncDirWriter {
+    /// Creates a new [`SyncDirWriter`] for the writer `writer_id` in `sync_dir`.
+    ///
+    /// Existing entries of this writer are kept, and new entries continue their sequence.
+    pub fn new<P>(sync_dir: P, writer_id: &str) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        if writer_id.is_empty() || writer_id.starts_with('.') || writer_id.contains('/') {
+            return Err(Error::illegal_argument(format!(
+                "Invalid sync dir writer id: {writer_id:?}"
+            )));
+        }
+        let dir = sync_dir.as_ref().join(writer_id);
+        fs::create_dir_all(&dir)?;
+        let next_seq = read_sync_dir_entries(&dir, None)?
+            .last()
+            .map_or(0, |(seq, _)| seq + 1);
+        Ok(Self { dir, next_seq })
+    }
+
+    /// The directory of this writer
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Publishes a new entry with the given contents, returning its path.
+    pub fn write(&mut self, bytes: &[u8]) -> Result<PathBuf, Error> {
+        self.publish(|tmp_path| Ok(fs::write(tmp_path, bytes)?))
+    }
+
+    /// Publishes a new entry for the given input, returning its path.
+    pub fn write_input<I>(&mut self, input: &I) -> Result<PathBuf, Error>
+    where
+        I: Input,
+    {
+        self.publish(|tmp_path| input.to_file(tmp_path))
+    }
+
+    fn publish<F>(&mut self, write: F) -> Result<PathBuf, Error>
+    where
+        F: FnOnce(&Path) -> Result<(), Error>,
+    {
+        let name = sync_dir_entry_name(self.next_seq);
+        let tmp_path = self.dir.join(format!("{SYNC_DIR_TMP_PREFIX}{name}"));
+        let path = self.dir.join(name);
+        write(&tmp_path)?;
+        fs::rename(&tmp_path, &path)?;
+        self.next_seq += 1;
+        Ok(path)
+    }
+}
+
+/// Lists the published entries in a writer directory of a multi-writer sync directory, ordered by
+/// sequence number, skipping all entries up to and including `after`.
+fn read_sync_dir_entries(dir: &Path, after: Option<u64>) -> Result<Vec<(u64, PathBuf)>, Error> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(ToString::to_string) else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let Ok(seq) = name.parse::<u64>() else {
+            continue;
+        };
+        if after.is_some_and(|after| seq <= after) {
+            continue;
+        }
+        entries.push((seq, entry.path()));
+    }
+    entries.sort_unstable_by_key(|(seq, _)| *seq);
+    Ok(entries)
+}
+
+/// Lists all entries of a multi-writer sync directory that were not imported yet, according to
+/// `last_seqs`, and updates `last_seqs` accordingly. Entries of the writer `own_id` are skipped.
+pub fn collect_new_sync_dir_entries(
+    sync_dir: &Path,
+    own_id: Option<&str>,
+    last_seqs: &mut HashMap<String, u64>,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut new_entries = Vec::new();
+    for writer in fs::read_dir(sync_dir)? {
+        let writer = writer?;
+        let Some(writer_id) = writer.file_name().to_str().map(ToString::to_string) else {
+            continue;
+        };
+        if writer_id.starts_with('.') || own_id == Some(writer_id.as_str()) {
+            continue;
+        }
+        if !writer.file_type()?.is_dir() {
+            continue;
+        }
+        let entries = read_sync_dir_entries(&writer.path(), last_seqs.get(&writer_id).copied())?;
+        if let Some((last, _)) = entries.last() {
+            last_seqs.insert(writer_id, *last);
+        }
+        new_entries.extend(entries.into_iter().map(|(_, path)| path));
+    }
+    Ok(new_entries)
+}
+
+/// The queue directory of each fuzzer in an AFL++ sync directory
+const AFL_QUEUE_DIR: &str = "queue";
+
+/// The directory holding the sync cursors of each fuzzer in an AFL++ sync directory
+const AFL_SYNCED_DIR: &str = ".synced";
+
+/// Parses the id of an AFL++ queue entry named `id:000042,...`
+fn afl_queue_entry_id(name: &str) -> Option<u64> {
+    name.strip_prefix("id:")?.split(',').next()?.parse().ok()
+}
+
+/// Checks if an AFL++ queue entry named `id:000042,sync:<fuzzer name>,...` was synced from the
+/// fuzzer `fuzzer_name`
+fn afl_queue_entry_synced_from(name: &str, fuzzer_name: &str) -> bool {
+    name.split(',')
+        .any(|part| part.strip_prefix("sync:") == Some(fuzzer_name))
+}
+
+/// Exports inputs into the queue of a fuzzer in an AFL++ sync directory
+/// (see the [module documentation](self)), as `<sync_dir>/<fuzzer name>/queue/id:NNNNNN,...`.
+///
+/// Entries are written to a hidden temporary file first and then renamed, so other fuzzers never
+/// observe partially written entries.
+#[derive(Debug)]
+pub struct AflQueueWriter {
+    sync_dir: PathBuf,
+    fuzzer_name: String,
+    next_id: u64,
+}
+
+impl AflQueueWriter {
+    /// Creates a new [`AflQueueWriter`] for the fuzzer `fuzzer_name` in `sync_dir`.
+    ///
+    /// Existing queue entries are kept, and new entries continue their ids.
+    pub fn new<P>(sync_dir: P, fuzzer_name: &str) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        if fuzzer_name.is_empty() || fuzzer_name.starts_with('.') || fuzzer_name.contains('/') {
+            return Err(Error::illegal_argument(format!(
+                "Invalid AFL++ fuzzer name: {fuzzer_name:?}"
+            )));
+        }
+        let sync_dir = sync_dir.as_ref().to_path_buf();
+        let queue_dir = sync_dir.join(fuzzer_name).join(AFL_QUEUE_DIR);
+        fs::create_dir_all(&queue_dir)?;
+        fs::create_dir_all(sync_dir.join(fuzzer_name).join(AFL_SYNCED_DIR))?;
+        let mut next_id = 0;
+        for entry in fs::read_dir(&queue_dir)? {
+            if let Some(id) = entry?.file_name().to_str().and_then(afl_queue_entry_id) {
+                next_id = next_id.max(id + 1);
+            }
+        }
+        Ok(Self {
+            sync_dir,
+            fuzzer_name: fuzzer_name.to_string(),
+            next_id,
+        })
+    }
+
+    /// The name of this fuzzer
+    #[must_use]
+    pub fn fuzzer_name(&self) -> &str {
+        &self.fuzzer_name
+    }
+
+    /// The sync directory shared by all fuzzers of the campaign
+    #[must_use]
+    pub fn sync_dir(&self) -> &Path {
+        &self.sync_dir
+    }
+
+    /// The queue directory of this fuzzer
+    #[must_use]
+    pub fn queue_dir(&self) -> PathBuf {
+        self.sync_dir.join(&self.fuzzer_name).join(AFL_QUEUE_DIR)
+    }
+
+    /// Exports the input as next queue entry, with the given suffix after its id, returning its path
+    pub fn write_input<I>(&mut self, input: &I, suffix: &str) -> Result<PathBuf, Error>
+    where
+        I: Input,
+    {
+        let name = format!("id:{:06},{suffix}", self.next_id);
+        let queue_dir = self.queue_dir();
+        let tmp_path = queue_dir.join(format!(".{name}"));
+        let path = queue_dir.join(name);
+        input.to_file(&tmp_path)?;
+        fs::rename(&tmp_path, &path)?;
+        self.next_id += 1;
+        Ok(path)
+    }
+
+    /// The path of the sync cursor for the queue of the fuzzer `other`
+    fn cursor_path(&self, other: &str) -> PathBuf {
+        self.sync_dir
+            .join(&self.fuzzer_name)
+            .join(AFL_SYNCED_DIR)
+            .join(other)
+    }
+
+    /// Reads the sync cursor for the fuzzer `other`, the last imported id, if any.
+    /// Like AFL++, the file holds the next id to accept as native endian `u32`.
+    fn read_cursor(&self, other: &str) -> Option<u64> {
+        let bytes = fs::read(self.cursor_path(other)).ok()?;
+        let next_min_accept = u32::from_ne_bytes(bytes.get(..4)?.try_into().ok()?);
+        u64::from(next_min_accept).checked_sub(1)
+    }
+
+    /// Writes the sync cursor for the fuzzer `other`
+    fn write_cursor(&self, other: &str, last: u64) -> Result<(), Error> {
+        let next_min_accept = u32::try_from(last + 1).unwrap_or(u32::MAX);
+        Ok(fs::write(
+            self.cursor_path(other),
+            next_min_accept.to_ne_bytes(),
+        )?)
+    }
+
+    /// Writes the sync cursors on disk for all fuzzers in `last_seqs`.
+    /// Only call this once the entries listed by [`AflQueueWriter::collect_new_entries`] are
+    /// imported, so a crash in between does not skip them.
+    pub fn write_cursors(&self, last_seqs: &HashMap<String, u64>) -> Result<(), Error> {
+        for (other, last) in last_seqs {
+            if self.read_cursor(other) != Some(*last) {
+                self.write_cursor(other, *last)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the queue entries of the other fuzzers that were not imported yet, according to
+    /// `last_seqs` or the sync cursors on disk, and updates `last_seqs`.
+    /// Entries synced from this fuzzer (named `...,sync:<fuzzer name>,...`) are skipped.
+    pub fn collect_new_entries(
+        &self,
+        last_seqs: &mut HashMap<String, u64>,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let mut new_entries = Vec::new();
+        for fuzzer in fs::read_dir(&self.sync_dir)? {
+            let fuzzer = fuzzer?;
+            let Some(other) = fuzzer.file_name().to_str().map(ToString::to_string) else {
+                continue;
+            };
+            if other.starts_with('.') || other == self.fuzzer_name {
+                continue;
+            }
+            let queue_dir = fuzzer.path().join(AFL_QUEUE_DIR);
+            if !queue_dir.is_dir() {
+                continue;
+            }
+            let last = last_seqs
+                .get(&other)
+                .copied()
+                .or_else(|| self.read_cursor(&other));
+
+            let mut entries = Vec::new();
+            for entry in fs::read_dir(&queue_dir)? {
+                let entry = entry?;
+                let Some(name) = entry.file_name().to_str().map(ToString::to_string) else {
+                    continue;
+                };
+                let Some(id) = afl_queue_entry_id(&name) else {
+                    continue;
+                };
+                if last.is_some_and(|last| id <= last) {
+                    continue;
+                }
+                entries.push((
+                    id,
+                    afl_queue_entry_synced_from(&name, &self.fuzzer_name),
+                    entry.path(),
+                ));
+            }
+            entries.sort_unstable_by_key(|(id, _, _)| *id);
+
+            if let Some((last, _, _)) = entries.last() {
+                last_seqs.insert(other, *last);
+            }
+            new_entries.extend(
+                entries
+                    .into_iter()
+                    .filter(|(_, from_us, _)| !from_us)
+                    .map(|(_, _, path)| path),
+            );
         }
+        Ok(new_entries)
     }
 }
 
 /// Default name for `SyncFromDiskStage`; derived from AFL++
 pub const SYNC_FROM_DISK_STAGE_NAME: &str = "sync";
 
+/// The layout of the directory a [`SyncFromDiskStage`] syncs with
+#[derive(Debug)]
+enum SyncDirFormat {
+    /// Any files, imported if they were modified since the last sync
+    Plain,
+    /// The multi-writer format of [`SyncDirWriter`], skipping the entries of our own writer, if any
+    MultiWriter {
+        /// The writer id of this client
+        own_id: Option<String>,
+    },
+    /// An AFL++ sync directory, exporting into our own queue
+    Afl(AflQueueWriter),
+}
+
 /// A stage that loads testcases from disk to sync with other fuzzers such as AFL++
 #[derive(Debug)]
 pub struct SyncFromDiskStage<CB, E, EM, Z> {
     name: Cow<'static, str>,
     sync_dir: PathBuf,
+    format: SyncDirFormat,
     load_callback: CB,
     phantom: PhantomData<(E, EM, Z)>,
 }
@@ -93,28 +468,62 @@ where
         manager: &mut EM,
     ) -> Result<(), Error> {
         log::debug!("Syncing from disk: {:?}", self.sync_dir);
-        let last = state
-            .metadata_map()
-            .get::<SyncFromDiskMetadata>()
-            .map(|m| m.last_time);
-
-        if let (Some(max_time), mut new_files) = self.load_from_directory(None, &last)? {
-            if last.is_none() {
-                state
-                    .metadata_map_mut()
-                    .insert(SyncFromDiskMetadata::new(max_time, new_files));
-            } else {
-                state
+        match &mut self.format {
+            SyncDirFormat::Afl(afl_writer) => {
+                if !state.has_metadata::<SyncFromDiskMetadata>() {
+                    state.add_metadata(SyncFromDiskMetadata::new(SystemTime::UNIX_EPOCH, vec![]));
+                }
+                Self::export_afl_queue(afl_writer, state)?;
+                let sync_from_disk_metadata = state
                     .metadata_map_mut()
                     .get_mut::<SyncFromDiskMetadata>()
-                    .unwrap()
-                    .last_time = max_time;
-                state
+                    .unwrap();
+                let mut new_files =
+                    afl_writer.collect_new_entries(&mut sync_from_disk_metadata.last_seqs)?;
+                sync_from_disk_metadata.last_time = SystemTime::now();
+                sync_from_disk_metadata.left_to_sync.append(&mut new_files);
+            }
+            SyncDirFormat::MultiWriter { own_id } => {
+                if !state.has_metadata::<SyncFromDiskMetadata>() {
+                    state.add_metadata(SyncFromDiskMetadata::new(SystemTime::UNIX_EPOCH, vec![]));
+                }
+                let sync_from_disk_metadata = state
                     .metadata_map_mut()
                     .get_mut::<SyncFromDiskMetadata>()
-                    .unwrap()
-                    .left_to_sync
-                    .append(&mut new_files);
+                    .unwrap();
+                let mut new_files = collect_new_sync_dir_entries(
+                    &self.sync_dir,
+                    own_id.as_deref(),
+                    &mut sync_from_disk_metadata.last_seqs,
+                )?;
+                sync_from_disk_metadata.last_time = SystemTime::now();
+                sync_from_disk_metadata.left_to_sync.append(&mut new_files);
+            }
+            SyncDirFormat::Plain => {
+                let last = state
+                    .metadata_map()
+                    .get::<SyncFromDiskMetadata>()
+                    .map(|m| m.last_time);
+
+                if let (Some(max_time), mut new_files) = self.load_from_directory(None, &last)? {
+                    if last.is_none() {
+                        state
+                            .metadata_map_mut()
+                            .insert(SyncFromDiskMetadata::new(max_time, new_files));
+                    } else {
+                        state
+                            .metadata_map_mut()
+                            .get_mut::<SyncFromDiskMetadata>()
+                            .unwrap()
+                            .last_time = max_time;
+                        state
+                            .metadata_map_mut()
+                            .get_mut::<SyncFromDiskMetadata>()
+                            .unwrap()
+                            .left_to_sync
+                            .append(&mut new_files);
+                    }
+                }
             }
         }
 
@@ -138,7 +547,18 @@ where
                     .left_to_sync
                     .retain(|p| p != &path);
                 log::debug!("Evaluating: {:?}", path);
-                fuzzer.evaluate_input(state, executor, manager, input)?;
+                // Mark the inputs as imported, so they are not exported again
+                state.add_metadata(PendingInputOriginMetadata {
+                    origin: InputOrigin::Imported,
+                });
+                let res = fuzzer.evaluate_input(state, executor, manager, input);
+                state.remove_metadata::<PendingInputOriginMetadata>();
+                res?;
+            }
+
+            // All collected entries are imported now, so the cursors on disk may move past them
+            if let SyncDirFormat::Afl(afl_writer) = &self.format {
+                afl_writer.write_cursors(&state.metadata::<SyncFromDiskMetadata>()?.last_seqs)?;
             }
         }
 
@@ -169,10 +589,82 @@ impl<CB, E, EM, Z> SyncFromDiskStage<CB, E, EM, Z> {
             name: Cow::Owned(SYNC_FROM_DISK_STAGE_NAME.to_owned() + ":" + name),
             phantom: PhantomData,
             sync_dir,
+            format: SyncDirFormat::Plain,
             load_callback,
         }
     }
 
+    /// Creates a new [`SyncFromDiskStage`] for a multi-writer sync directory, see [`SyncDirWriter`].
+    ///
+    /// Entries written by `own_id` (usually the id this client uses for its own [`SyncDirWriter`])
+    /// are not imported.
+    #[must_use]
+    pub fn new_multi_writer(
+        sync_dir: PathBuf,
+        load_callback: CB,
+        name: &str,
+        own_id: Option<String>,
+    ) -> Self {
+        Self {
+            name: Cow::Owned(SYNC_FROM_DISK_STAGE_NAME.to_owned() + ":" + name),
+            phantom: PhantomData,
+            sync_dir,
+            format: SyncDirFormat::MultiWriter { own_id },
+            load_callback,
+        }
+    }
+
+    /// Creates a new [`SyncFromDiskStage`] taking part in an AFL++ `-M/-S` campaign in `sync_dir`
+    /// as the fuzzer `fuzzer_name`, see [`AflQueueWriter`].
+    ///
+    /// The inputs found by this client are exported into `<sync_dir>/<fuzzer_name>/queue`, and the
+    /// queue entries of all other fuzzers are imported. Enable
+    /// [`crate::StdFuzzer::with_input_origins`] to not export the imported entries again.
+    pub fn new_afl(sync_dir: PathBuf, load_callback: CB, fuzzer_name: &str) -> Result<Self, Error> {
+        let afl_writer = AflQueueWriter::new(&sync_dir, fuzzer_name)?;
+        Ok(Self {
+            name: Cow::Owned(SYNC_FROM_DISK_STAGE_NAME.to_owned() + ":" + fuzzer_name),
+            phantom: PhantomData,
+            sync_dir,
+            format: SyncDirFormat::Afl(afl_writer),
+            load_callback,
+        })
+    }
+
+    /// Exports the corpus entries added since the last export into our own AFL++ queue,
+    /// except for the imported ones. These are only known if the fuzzer records the origins,
+    /// see [`crate::StdFuzzer::with_input_origins`].
+    fn export_afl_queue<S>(afl_writer: &mut AflQueueWriter, state: &mut S) -> Result<(), Error>
+    where
+        S: HasCorpus + HasMetadata,
+    {
+        let last_exported = state.metadata::<SyncFromDiskMetadata>()?.last_exported;
+        let mut cur_id = match last_exported {
+            None => state.corpus().first(),
+            Some(last) if state.corpus().get(last).is_ok() => state.corpus().next(last),
+            // The last exported entry was removed, continue after its id
+            Some(last) => state.corpus().ids().find(|id| *id > last),
+        };
+        let mut last = last_exported;
+        while let Some(id) = cur_id {
+            let imported = state
+                .corpus()
+                .get(id)?
+                .borrow()
+                .metadata_map()
+                .get::<InputOriginMetadata>()
+                .is_some_and(|meta| meta.origin == InputOrigin::Imported);
+            if !imported {
+                let input = state.corpus().cloned_input_for_id(id)?;
+                afl_writer.write_input(&input, &format!("src:libafl{id}"))?;
+            }
+            last = Some(id);
+            cur_id = state.corpus().next(id);
+        }
+        state.metadata_mut::<SyncFromDiskMetadata>()?.last_exported = last;
+        Ok(())
+    }
+
     fn load_from_directory(
         &self,
         path: Option<PathBuf>,
@@ -244,6 +736,7 @@ where
         Self {
             name: Cow::Borrowed(SYNC_FROM_DISK_STAGE_NAME),
             sync_dir,
+            format: SyncDirFormat::Plain,
             load_callback: load_callback::<_, _>,
             phantom: PhantomData,
         }
@@ -393,3 +886,214 @@ where
         Self { client }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs, path::Path, vec::Vec};
+
+    use hashbrown::HashMap;
+
+    use super::{
+        collect_new_sync_dir_entries, AflQueueWriter, SyncDirWriter, SyncFromDiskMetadata,
+        SyncFromDiskStage,
+    };
+    use crate::{
+        corpus::{Corpus, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasMutatorBytes, Input},
+        schedulers::QueueScheduler,
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus},
+        HasMetadata, StdFuzzer,
+    };
+
+    #[test]
+    fn test_sync_dir_writer() {
+        let sync_dir = temp_dir().join(format!("libafl_sync_dir_writer_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&sync_dir);
+        assert!(SyncDirWriter::new(&sync_dir, ".hidden").is_err());
+        assert!(SyncDirWriter::new(&sync_dir, "a/b").is_err());
+
+        let mut own = SyncDirWriter::new(&sync_dir, "own").unwrap();
+        let mut other = SyncDirWriter::new(&sync_dir, "other").unwrap();
+        own.write(b"own").unwrap();
+        let first = other.write(b"1").unwrap();
+        // Entries still being written are not imported
+        fs::write(other.dir().join(".tmp-00000000000000000001"), b"partial").unwrap();
+
+        let mut last_seqs = HashMap::new();
+        let entries = collect_new_sync_dir_entries(&sync_dir, Some("own"), &mut last_seqs).unwrap();
+        assert_eq!(entries, [first]);
+        assert_eq!(last_seqs.get("other"), Some(&0));
+        assert!(
+            collect_new_sync_dir_entries(&sync_dir, Some("own"), &mut last_seqs)
+                .unwrap()
+                .is_empty()
+        );
+
+        // A restarted writer continues its sequence
+        drop(other);
+        let mut other = SyncDirWriter::new(&sync_dir, "other").unwrap();
+        let second = other.write(b"2").unwrap();
+        assert_eq!(second.file_name().unwrap(), "00000000000000000001");
+        let entries = collect_new_sync_dir_entries(&sync_dir, Some("own"), &mut last_seqs).unwrap();
+        assert_eq!(entries, [second]);
+
+        // Without an own id, all writers are imported
+        let entries = collect_new_sync_dir_entries(&sync_dir, None, &mut HashMap::new()).unwrap();
+        assert_eq!(entries.len(), 3);
+
+        fs::remove_dir_all(&sync_dir).unwrap();
+    }
+
+    #[test]
+    fn test_multi_writer_sync_stage() {
+        fn imported<S>(state: &S) -> Vec<Vec<u8>>
+        where
+            S: HasCorpus,
+            S::Input: HasMutatorBytes,
+        {
+            let corpus = state.corpus();
+            corpus
+                .ids()
+                .map(|id| corpus.cloned_input_for_id(id).unwrap().bytes().to_vec())
+                .collect()
+        }
+
+        let sync_dir = temp_dir().join(format!("libafl_sync_multi_writer_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&sync_dir);
+        let mut own = SyncDirWriter::new(&sync_dir, "own").unwrap();
+        let mut other = SyncDirWriter::new(&sync_dir, "other").unwrap();
+        own.write(b"own").unwrap();
+        other.write(b"other1").unwrap();
+
+        let mut stage = SyncFromDiskStage::new_multi_writer(
+            sync_dir.clone(),
+            |_: &mut _, _: &mut _, path: &Path| BytesInput::from_file(path),
+            "multi",
+            Some("own".into()),
+        );
+        let mut executor =
+            ClosureExecutor::new(|_observers: &mut (), _input: &BytesInput| ExitKind::Ok, ());
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(true),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(imported(&state), [b"other1".to_vec()]);
+
+        // Only new entries are imported on the next sync
+        other.write(b"other2").unwrap();
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(imported(&state), [b"other1".to_vec(), b"other2".to_vec()]);
+
+        fs::remove_dir_all(&sync_dir).unwrap();
+    }
+
+    #[test]
+    fn test_afl_queue_import() {
+        let sync_dir = temp_dir().join(format!("libafl_afl_sync_import_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&sync_dir);
+        let writer = AflQueueWriter::new(&sync_dir, "main").unwrap();
+
+        let other_queue = sync_dir.join("other").join("queue");
+        fs::create_dir_all(&other_queue).unwrap();
+        for name in [
+            "id:000000,sync:main2,src:000001",
+            "id:000001,sync:main,src:000002",
+            "id:000002,src:000000",
+        ] {
+            fs::write(other_queue.join(name), b"x").unwrap();
+        }
+
+        let mut last_seqs = HashMap::new();
+        let mut entries = writer.collect_new_entries(&mut last_seqs).unwrap();
+        entries.sort();
+        let names: Vec<_> = entries
+            .iter()
+            .map(|path| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        // only the entry synced from us is skipped, not the one from a fuzzer sharing our prefix
+        assert_eq!(
+            names,
+            ["id:000000,sync:main2,src:000001", "id:000002,src:000000"]
+        );
+        assert_eq!(last_seqs.get("other"), Some(&2));
+
+        // the cursor on disk only moves once the entries are imported
+        assert_eq!(
+            writer
+                .collect_new_entries(&mut HashMap::new())
+                .unwrap()
+                .len(),
+            2
+        );
+        writer.write_cursors(&last_seqs).unwrap();
+        assert!(writer
+            .collect_new_entries(&mut HashMap::new())
+            .unwrap()
+            .is_empty());
+
+        fs::remove_dir_all(&sync_dir).unwrap();
+    }
+
+    #[test]
+    fn test_afl_queue_export_after_remove() {
+        let sync_dir = temp_dir().join(format!("libafl_afl_sync_export_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&sync_dir);
+        let mut writer = AflQueueWriter::new(&sync_dir, "main").unwrap();
+
+        let mut state = test_std_state::<BytesInput>();
+        state.add_metadata(SyncFromDiskMetadata::new(
+            std::time::SystemTime::UNIX_EPOCH,
+            Vec::new(),
+        ));
+        let export = |writer: &mut AflQueueWriter, state: &mut _| {
+            SyncFromDiskStage::<(), (), (), ()>::export_afl_queue(writer, state).unwrap();
+        };
+
+        state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"a".to_vec())))
+            .unwrap();
+        let last = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"b".to_vec())))
+            .unwrap();
+        export(&mut writer, &mut state);
+        assert_eq!(
+            state
+                .metadata::<SyncFromDiskMetadata>()
+                .unwrap()
+                .last_exported,
+            Some(last)
+        );
+
+        // removing the last exported entry must not stop the export
+        state.corpus_mut().remove(last).unwrap();
+        let new = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"c".to_vec())))
+            .unwrap();
+        export(&mut writer, &mut state);
+        assert_eq!(
+            state
+                .metadata::<SyncFromDiskMetadata>()
+                .unwrap()
+                .last_exported,
+            Some(new)
+        );
+        assert_eq!(fs::read_dir(writer.queue_dir()).unwrap().count(), 3);
+
+        fs::remove_dir_all(&sync_dir).unwrap();
+    }
+}
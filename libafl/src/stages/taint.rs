@@ -0,0 +1,742 @@
+//! Taint-guided mutations, built on the byte-level results of the [`ColorizationStage`].
+//!
+//! The [`CmpTaintStage`] re-runs the current testcase once per colorized range from the
+//! [`TAINT_REGISTER`], with only that range changed, and diffs the logged comparisons to learn
+//! which input bytes flow into which comparison site. The result is stored in the testcase as
+//! [`CmpTaintMetadata`].
+//!
+//! The [`TaintMutationalStage`] then picks a comparison whose operands did not match yet, i.e. one
+//! that guards a branch which is likely still uncovered, and focuses its mutations on exactly the
+//! bytes that influence it.
+//!
+//! [`ColorizationStage`]: crate::stages::ColorizationStage
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    string::ToString,
+    vec::Vec,
+};
+use core::{marker::PhantomData, ops::Range};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type, Handle, Handled},
+    Named,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executors::{Executor, HasObservers},
+    fuzzer::Evaluator,
+    inputs::{BytesInput, HasMutatorBytes},
+    mutators::{
+        BitFlipMutator, ByteAddMutator, ByteFlipMutator, ByteInterestingMutator, ByteRandMutator,
+        BytesRandSetMutator, DwordAddMutator, DwordInterestingMutator, MutationResult, Mutator,
+        QwordAddMutator, WordAddMutator, WordInterestingMutator,
+    },
+    observers::{
+        cmp::{CmpMap, CmpObserver, CmpObserverMetadata, CmpValues},
+        ObserversTuple,
+    },
+    stages::{
+        mutational::DEFAULT_MUTATIONAL_MAX_ITERATIONS, Stage, StdRestartHelper, TAINT_REGISTER,
+    },
+    state::{
+        HasCorpus, HasCurrentTestcase, HasRand, HasStageRegisters, StageRegisterDeclarations,
+        UsesState,
+    },
+    Error, HasMetadata, HasNamedMetadata,
+};
+
+/// The default maximum number of ranges the [`CmpTaintStage`] checks per testcase
+pub const DEFAULT_MAX_TAINT_RANGES: usize = 64;
+
+/// Colorized ranges are split into chunks of at most this many bytes before they are checked
+pub const DEFAULT_TAINT_CHUNK_LEN: usize = 16;
+
+/// The input ranges influencing a single comparison site
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CmpTaint {
+    /// The index of the comparison site in the cmp map
+    pub site: usize,
+    /// The input byte ranges influencing the operands of this comparison
+    pub ranges: Vec<Range<usize>>,
+    /// If the operands of this comparison were equal when running the testcase
+    pub solved: bool,
+}
+
+/// Testcase metadata, mapping comparison sites to the input byte ranges influencing them
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct CmpTaintMetadata {
+    sites: Vec<CmpTaint>,
+}
+
+libafl_bolts::impl_serdeany!(CmpTaintMetadata);
+
+impl CmpTaintMetadata {
+    /// Creates a new [`CmpTaintMetadata`]
+    #[must_use]
+    pub fn new(mut sites: Vec<CmpTaint>) -> Self {
+        sites.sort_by_key(|taint| taint.site);
+        Self { sites }
+    }
+
+    /// All tainted comparison sites, ordered by site
+    #[must_use]
+    pub fn sites(&self) -> &[CmpTaint] {
+        &self.sites
+    }
+
+    /// The taint of the given comparison site, if any input byte influences it
+    #[must_use]
+    pub fn get(&self, site: usize) -> Option<&CmpTaint> {
+        self.sites
+            .binary_search_by_key(&site, |taint| taint.site)
+            .ok()
+            .map(|idx| &self.sites[idx])
+    }
+
+    /// The tainted comparisons whose operands did not match yet
+    pub fn unsolved(&self) -> impl Iterator<Item = &CmpTaint> {
+        self.sites.iter().filter(|taint| !taint.solved)
+    }
+}
+
+/// Checks if both operands of a logged comparison are equal
+#[must_use]
+pub fn cmp_is_solved(values: &CmpValues) -> bool {
+    match values {
+        CmpValues::Bytes((left, right)) => left == right,
+        _ => values
+            .to_u128_tuple()
+            .is_some_and(|(left, right)| left == right),
+    }
+}
+
+/// Splits `ranges` into chunks of at most `chunk_len` bytes, returning at most `max` of them
+#[must_use]
+pub fn split_taint_ranges(
+    ranges: &[Range<usize>],
+    chunk_len: usize,
+    max: usize,
+) -> Vec<Range<usize>> {
+    let chunk_len = chunk_len.max(1);
+    ranges
+        .iter()
+        .flat_map(|range| {
+            range
+                .clone()
+                .step_by(chunk_len)
+                .map(move |start| start..(start + chunk_len).min(range.end))
+        })
+        .take(max)
+        .collect()
+}
+
+/// The name of the [`CmpTaintStage`]
+pub static CMP_TAINT_STAGE_NAME: &str = "cmptaint";
+
+/// Learns which input byte ranges influence which comparison site, see the [module docs](self).
+///
+/// Needs to run after a [`crate::stages::ColorizationStage`], with an executor that has a
+/// [`CmpObserver`], e.g. the tracing executor of a cmplog setup.
+#[derive(Debug)]
+pub struct CmpTaintStage<'a, C, CM, E, EM, M, Z> {
+    cmp_observer_handle: Handle<C>,
+    name: Cow<'static, str>,
+    max_ranges: usize,
+    chunk_len: usize,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(&'a (), CM, E, EM, M, Z)>,
+}
+
+impl<'a, C, CM, E, EM, M, Z> UsesState for CmpTaintStage<'a, C, CM, E, EM, M, Z>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<'a, C, CM, E, EM, M, Z> Named for CmpTaintStage<'a, C, CM, E, EM, M, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<'a, C, CM, E, EM, M, Z> Stage<E, EM, Z> for CmpTaintStage<'a, C, CM, E, EM, M, Z>
+where
+    EM: UsesState<State = Self::State>,
+    E: HasObservers + Executor<EM, Z>,
+    Self::State: HasCorpus + HasMetadata + HasNamedMetadata + HasStageRegisters,
+    E::Input: HasMutatorBytes + Clone,
+    C: CmpObserver<'a, CM, Self::State, M> + Named,
+    CM: CmpMap,
+    M: CmpObserverMetadata<'a, CM>,
+    Z: UsesState<State = Self::State>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let Some(taint) = state.current_register(&TAINT_REGISTER) else {
+            // The colorization did not run for this testcase, e.g. because it is empty.
+            return Ok(());
+        };
+        let colorized = taint.input_vec().clone();
+        let ranges = split_taint_ranges(taint.ranges(), self.chunk_len, self.max_ranges);
+
+        let original = state.current_input_cloned()?;
+        if colorized.len() != original.bytes().len() {
+            return Ok(());
+        }
+
+        let baseline = self.trace(fuzzer, executor, state, manager, &original)?;
+
+        let mut influenced: HashMap<usize, Vec<Range<usize>>> = HashMap::new();
+        for range in ranges {
+            let mut input = original.clone();
+            input.bytes_mut()[range.clone()].copy_from_slice(&colorized[range.clone()]);
+            let changed = self.trace(fuzzer, executor, state, manager, &input)?;
+            for (site, values) in &baseline {
+                if changed.get(site) != Some(values) {
+                    influenced.entry(*site).or_default().push(range.clone());
+                }
+            }
+        }
+
+        let sites = influenced
+            .into_iter()
+            .map(|(site, ranges)| CmpTaint {
+                site,
+                ranges,
+                solved: cmp_is_solved(&baseline[&site]),
+            })
+            .collect();
+        state
+            .current_testcase_mut()?
+            .add_metadata(CmpTaintMetadata::new(sites));
+
+        Ok(())
+    }
+
+    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        // This is a deterministic stage, it will just fail again
+        StdRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        StdRestartHelper::clear_progress(state, &self.name)
+    }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        registers.consumes(&TAINT_REGISTER);
+    }
+}
+
+impl<'a, C, CM, E, EM, M, Z> CmpTaintStage<'a, C, CM, E, EM, M, Z>
+where
+    EM: UsesState<State = <Self as UsesState>::State>,
+    E: HasObservers + Executor<EM, Z>,
+    C: CmpObserver<'a, CM, <Self as UsesState>::State, M> + Named,
+    CM: CmpMap,
+    M: CmpObserverMetadata<'a, CM>,
+    Z: UsesState<State = <Self as UsesState>::State>,
+{
+    /// Creates a new [`CmpTaintStage`] for the given cmp observer
+    #[must_use]
+    pub fn new(cmp_observer: &C) -> Self {
+        Self {
+            cmp_observer_handle: cmp_observer.handle(),
+            name: Cow::Owned(CMP_TAINT_STAGE_NAME.to_owned() + ":" + cmp_observer.name().as_ref()),
+            max_ranges: DEFAULT_MAX_TAINT_RANGES,
+            chunk_len: DEFAULT_TAINT_CHUNK_LEN,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the maximum number of ranges checked, and thus executions, per testcase
+    #[must_use]
+    pub fn with_max_ranges(mut self, max_ranges: usize) -> Self {
+        self.max_ranges = max_ranges;
+        self
+    }
+
+    /// Sets the maximum length of a checked range. Smaller chunks give more precise results,
+    /// at the cost of more executions.
+    #[must_use]
+    pub fn with_chunk_len(mut self, chunk_len: usize) -> Self {
+        self.chunk_len = chunk_len;
+        self
+    }
+
+    /// Runs the input, returning the first logged values of each comparison site
+    fn trace(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut <Self as UsesState>::State,
+        manager: &mut EM,
+        input: &E::Input,
+    ) -> Result<HashMap<usize, CmpValues>, Error> {
+        executor.observers_mut().pre_exec_all(state, input)?;
+
+        let exit_kind = executor.run_target(fuzzer, state, manager, input)?;
+
+        let observers = executor.observers();
+        let cmp_map = observers[&self.cmp_observer_handle].cmp_map();
+        let mut values = HashMap::new();
+        for site in 0..cmp_map.len() {
+            if cmp_map.usable_executions_for(site) == 0 {
+                continue;
+            }
+            if let Some(cmp) = cmp_map.values_of(site, 0) {
+                values.insert(site, cmp);
+            }
+        }
+
+        executor
+            .observers_mut()
+            .post_exec_all(state, input, &exit_kind)?;
+
+        Ok(values)
+    }
+}
+
+/// Tuple type of the mutations used by default in the [`TaintMutationalStage`]
+pub type TaintMutationsType = tuple_list_type!(
+    BitFlipMutator,
+    ByteFlipMutator,
+    ByteRandMutator,
+    ByteAddMutator,
+    WordAddMutator,
+    DwordAddMutator,
+    QwordAddMutator,
+    ByteInterestingMutator,
+    WordInterestingMutator,
+    DwordInterestingMutator,
+    BytesRandSetMutator,
+);
+
+/// Havoc and interesting value mutations, that keep the size of the tainted range intact
+#[must_use]
+pub fn taint_mutations() -> TaintMutationsType {
+    tuple_list!(
+        BitFlipMutator::new(),
+        ByteFlipMutator::new(),
+        ByteRandMutator::new(),
+        ByteAddMutator::new(),
+        WordAddMutator::new(),
+        DwordAddMutator::new(),
+        QwordAddMutator::new(),
+        ByteInterestingMutator::new(),
+        WordInterestingMutator::new(),
+        DwordInterestingMutator::new(),
+        BytesRandSetMutator::new(),
+    )
+}
+
+/// The unique id for the taint mutational stage
+static mut TAINT_MUTATIONAL_STAGE_ID: usize = 0;
+/// The name for the taint mutational stage
+pub static TAINT_MUTATIONAL_STAGE_NAME: &str = "taintmutational";
+
+/// A mutational stage that mutates only the input bytes influencing a not yet solved comparison,
+/// according to the [`CmpTaintMetadata`] of the current testcase.
+///
+/// The mutator works on a [`BytesInput`] holding the bytes of a single tainted range, which is
+/// written back into the testcase afterwards; e.g. use a
+/// [`crate::mutators::StdScheduledMutator`] with [`taint_mutations`].
+#[derive(Debug)]
+pub struct TaintMutationalStage<E, EM, M, Z> {
+    name: Cow<'static, str>,
+    mutator: M,
+    max_iterations: usize,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(E, EM, Z)>,
+}
+
+impl<E, EM, M, Z> UsesState for TaintMutationalStage<E, EM, M, Z>
+where
+    Z: UsesState,
+{
+    type State = Z::State;
+}
+
+impl<E, EM, M, Z> Named for TaintMutationalStage<E, EM, M, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<E, EM, M, Z> Stage<E, EM, Z> for TaintMutationalStage<E, EM, M, Z>
+where
+    E: UsesState<State = Self::State>,
+    EM: UsesState<State = Self::State>,
+    M: Mutator<BytesInput, Self::State>,
+    Z: Evaluator<E, EM>,
+    Self::State: HasCorpus + HasRand + HasMetadata + HasNamedMetadata,
+    Self::Input: HasMutatorBytes + Clone,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let targets: Vec<Vec<Range<usize>>> = {
+            let testcase = state.current_testcase()?;
+            let Some(meta) = testcase.metadata_map().get::<CmpTaintMetadata>() else {
+                return Ok(());
+            };
+            meta.unsolved().map(|taint| taint.ranges.clone()).collect()
+        };
+        if targets.is_empty() {
+            return Ok(());
+        }
+
+        let original = state.current_input_cloned()?;
+        let num = 1 + state.rand_mut().below(self.max_iterations);
+        for _ in 0..num {
+            let ranges = state.rand_mut().choose(&targets).unwrap();
+            let Some(range) = state.rand_mut().choose(ranges).cloned() else {
+                continue;
+            };
+            if range.end > original.bytes().len() {
+                continue;
+            }
+
+            let mut part = BytesInput::new(original.bytes()[range.clone()].to_vec());
+            if self.mutator.mutate(state, &mut part)? == MutationResult::Skipped {
+                continue;
+            }
+
+            let mut input = original.clone();
+            input.splice(range, part.bytes().iter().copied());
+
+            let (_, corpus_id) = fuzzer.evaluate_input(state, executor, manager, input)?;
+            self.mutator.post_exec(state, corpus_id)?;
+        }
+
+        Ok(())
+    }
+
+    fn should_restart(&mut self, state: &mut Self::State) -> Result<bool, Error> {
+        StdRestartHelper::should_restart(state, &self.name, 3)
+    }
+
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        StdRestartHelper::clear_progress(state, &self.name)
+    }
+}
+
+impl<E, EM, M, Z> TaintMutationalStage<E, EM, M, Z> {
+    /// Creates a new [`TaintMutationalStage`]
+    pub fn new(mutator: M) -> Self {
+        Self::with_max_iterations(mutator, DEFAULT_MUTATIONAL_MAX_ITERATIONS)
+    }
+
+    /// Creates a new [`TaintMutationalStage`] with the given max iterations
+    pub fn with_max_iterations(mutator: M, max_iterations: usize) -> Self {
+        // unsafe but impossible that you create two threads both instantiating this instance
+        let stage_id = unsafe {
+            let ret = TAINT_MUTATIONAL_STAGE_ID;
+            TAINT_MUTATIONAL_STAGE_ID += 1;
+            ret
+        };
+        Self {
+            name: Cow::Owned(
+                TAINT_MUTATIONAL_STAGE_NAME.to_owned() + ":" + stage_id.to_string().as_str(),
+            ),
+            mutator,
+            max_iterations,
+            phantom: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::single_range_in_vec_init)] // the taint ranges of a site
+mod tests {
+    use alloc::{boxed::Box, vec, vec::Vec};
+    use core::{fmt::Debug, marker::PhantomData};
+
+    use libafl_bolts::{ownedref::OwnedRefMut, tuples::tuple_list, Error};
+    use serde::{Deserialize, Serialize};
+
+    use super::{
+        cmp_is_solved, split_taint_ranges, CmpTaint, CmpTaintMetadata, CmpTaintStage,
+        TaintMutationalStage,
+    };
+    use crate::{
+        corpus::{Corpus, CorpusId, HasCurrentCorpusId, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        fuzzer::{test::NopFuzzer, Evaluator, ExecuteInputResult},
+        inputs::{BytesInput, HasMutatorBytes, UsesInput},
+        mutators::ByteRandMutator,
+        observers::cmp::{CmpMap, CmpObserver, CmpValues, CmpValuesMetadata, StdCmpObserver},
+        stages::{colorization::TaintMetadata, Stage, TAINT_REGISTER},
+        state::{
+            test::test_std_state, HasCorpus, HasCurrentTestcase, HasStageRegisters, UsesState,
+        },
+        HasMetadata,
+    };
+
+    /// Logs a single execution of each comparison site
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct TestCmpMap {
+        values: Vec<Option<CmpValues>>,
+    }
+
+    impl CmpMap for TestCmpMap {
+        fn len(&self) -> usize {
+            self.values.len()
+        }
+
+        fn executions_for(&self, idx: usize) -> usize {
+            usize::from(self.values[idx].is_some())
+        }
+
+        fn usable_executions_for(&self, idx: usize) -> usize {
+            self.executions_for(idx)
+        }
+
+        fn values_of(&self, idx: usize, execution: usize) -> Option<CmpValues> {
+            if execution == 0 {
+                self.values[idx].clone()
+            } else {
+                None
+            }
+        }
+
+        fn reset(&mut self) -> Result<(), Error> {
+            self.values.iter_mut().for_each(|value| *value = None);
+            Ok(())
+        }
+    }
+
+    type TestCmpObserver<S> = StdCmpObserver<'static, TestCmpMap, S, CmpValuesMetadata>;
+
+    /// Site 0 compares the first two bytes against a magic value, site 1 compares byte 6 with itself
+    fn compare_magic<S>(observers: &mut (TestCmpObserver<S>, ()), input: &BytesInput) -> ExitKind
+    where
+        S: UsesInput + Debug + HasMetadata,
+    {
+        let bytes = input.bytes();
+        let values = &mut observers.0.cmp_map_mut().values;
+        values[0] = Some(CmpValues::U16((
+            u16::from_be_bytes([bytes[0], bytes[1]]),
+            0x4142,
+        )));
+        values[1] = Some(CmpValues::U8((bytes[6], bytes[6])));
+        ExitKind::Ok
+    }
+
+    /// Records the evaluated inputs, without running them
+    #[derive(Debug)]
+    struct RecordingFuzzer<S> {
+        evaluated: Vec<BytesInput>,
+        phantom: PhantomData<S>,
+    }
+
+    impl<S> UsesState for RecordingFuzzer<S>
+    where
+        S: crate::state::State,
+    {
+        type State = S;
+    }
+
+    impl<E, EM, S> Evaluator<E, EM> for RecordingFuzzer<S>
+    where
+        S: crate::state::State<Input = BytesInput>,
+    {
+        fn evaluate_input_events(
+            &mut self,
+            _state: &mut S,
+            _executor: &mut E,
+            _manager: &mut EM,
+            input: BytesInput,
+            _send_events: bool,
+        ) -> Result<(ExecuteInputResult, Option<CorpusId>), Error> {
+            self.evaluated.push(input);
+            Ok((ExecuteInputResult::None, None))
+        }
+
+        fn add_input(
+            &mut self,
+            _state: &mut S,
+            _executor: &mut E,
+            _manager: &mut EM,
+            _input: BytesInput,
+        ) -> Result<CorpusId, Error> {
+            unimplemented!()
+        }
+
+        fn add_disabled_input(
+            &mut self,
+            _state: &mut S,
+            _input: BytesInput,
+        ) -> Result<CorpusId, Error> {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn test_split_taint_ranges() {
+        assert_eq!(
+            split_taint_ranges(&[0..4, 10..30], 8, 64),
+            vec![0..4, 10..18, 18..26, 26..30]
+        );
+        assert_eq!(
+            split_taint_ranges(&[0..100], 10, 3),
+            vec![0..10, 10..20, 20..30]
+        );
+    }
+
+    #[test]
+    fn test_cmp_taint_metadata() {
+        assert!(cmp_is_solved(&CmpValues::U32((7, 7))));
+        assert!(!cmp_is_solved(&CmpValues::U128((7, 8))));
+        assert!(!cmp_is_solved(&CmpValues::Bytes((
+            b"GET".to_vec(),
+            b"PUT".to_vec()
+        ))));
+
+        let meta = CmpTaintMetadata::new(vec![
+            CmpTaint {
+                site: 42,
+                ranges: vec![4..8],
+                solved: false,
+            },
+            CmpTaint {
+                site: 7,
+                ranges: vec![0..2],
+                solved: true,
+            },
+        ]);
+        assert_eq!(meta.sites()[0].site, 7);
+        assert_eq!(meta.get(42).unwrap().ranges, vec![4..8]);
+        assert!(meta.get(1).is_none());
+        assert_eq!(
+            meta.unsolved().map(|taint| taint.site).collect::<Vec<_>>(),
+            vec![42]
+        );
+    }
+
+    #[test]
+    fn test_cmp_taint_stage() {
+        let observer = TestCmpObserver::new(
+            "cmps",
+            OwnedRefMut::Owned(Box::new(TestCmpMap {
+                values: vec![None; 2],
+            })),
+            false,
+        );
+        let mut stage = CmpTaintStage::new(&observer).with_chunk_len(2);
+        let mut executor = ClosureExecutor::new(compare_magic, tuple_list!(observer));
+        let mut fuzzer = NopFuzzer::new();
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+
+        let corpus_id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(vec![0; 8])))
+            .unwrap();
+        state.set_corpus_id(corpus_id).unwrap();
+
+        // Without a colorization, there is nothing to learn
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert!(state
+            .current_testcase()
+            .unwrap()
+            .metadata::<CmpTaintMetadata>()
+            .is_err());
+
+        state.write_register(
+            &TAINT_REGISTER,
+            TaintMetadata::new(vec![0xff; 8], vec![0..8]),
+        );
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+
+        let testcase = state.current_testcase().unwrap();
+        let meta = testcase.metadata::<CmpTaintMetadata>().unwrap();
+        let magic = meta.get(0).unwrap();
+        assert_eq!(magic.ranges, vec![0..2]);
+        assert!(!magic.solved);
+        let same = meta.get(1).unwrap();
+        assert_eq!(same.ranges, vec![6..8]);
+        assert!(same.solved);
+        assert_eq!(
+            meta.unsolved().map(|taint| taint.site).collect::<Vec<_>>(),
+            vec![0]
+        );
+    }
+
+    #[test]
+    fn test_taint_mutational_stage() {
+        let mut stage = TaintMutationalStage::with_max_iterations(ByteRandMutator::new(), 32);
+        let mut executor =
+            ClosureExecutor::new(|_observers: &mut (), _input: &BytesInput| ExitKind::Ok, ());
+        let mut fuzzer = RecordingFuzzer {
+            evaluated: Vec::new(),
+            phantom: PhantomData,
+        };
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+
+        let original = vec![0_u8; 16];
+        let corpus_id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(original.clone())))
+            .unwrap();
+        state.set_corpus_id(corpus_id).unwrap();
+
+        // Without taint information, the stage does nothing
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert!(fuzzer.evaluated.is_empty());
+
+        state
+            .current_testcase_mut()
+            .unwrap()
+            .add_metadata(CmpTaintMetadata::new(vec![
+                CmpTaint {
+                    site: 1,
+                    ranges: vec![4..8],
+                    solved: false,
+                },
+                CmpTaint {
+                    site: 2,
+                    ranges: vec![10..12],
+                    solved: true,
+                },
+            ]));
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+
+        // Only the bytes of the unsolved comparison are mutated
+        assert!(!fuzzer.evaluated.is_empty());
+        for input in &fuzzer.evaluated {
+            let bytes = input.bytes();
+            assert_eq!(bytes.len(), original.len());
+            assert_eq!(bytes[..4], original[..4]);
+            assert_eq!(bytes[8..], original[8..]);
+            assert_ne!(bytes[4..8], original[4..8]);
+        }
+    }
+}
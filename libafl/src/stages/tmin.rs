@@ -3,30 +3,33 @@
 use alloc::{
     borrow::{Cow, ToOwned},
     string::ToString,
+    vec::Vec,
 };
 use core::{borrow::BorrowMut, fmt::Debug, hash::Hash, marker::PhantomData};
 
 use ahash::RandomState;
 use libafl_bolts::{
+    impl_serdeany,
     tuples::{Handle, Handled, MatchNameRef},
     HasLen, Named,
 };
+use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "track_hit_feedbacks")]
 use crate::feedbacks::premature_last_result_err;
 use crate::{
-    corpus::{Corpus, HasCurrentCorpusId, Testcase},
+    corpus::{Corpus, CorpusId, HasCurrentCorpusId, Testcase},
     events::EventFirer,
     executors::{ExitKind, HasObservers},
     feedbacks::{Feedback, FeedbackFactory, HasObserverHandle},
-    inputs::UsesInput,
+    inputs::{HasMutatorBytes, UsesInput},
     mark_feature_time,
-    mutators::{MutationResult, Mutator},
-    observers::{MapObserver, ObserversTuple},
+    mutators::{MutationResult, Mutator, Tokens},
+    observers::{MapObserver, ObserverWithHashField, ObserversTuple},
     schedulers::RemovableScheduler,
     stages::{
         mutational::{MutatedTransform, MutatedTransformPost},
-        ExecutionCountRestartHelper, Stage,
+        ExecutionCountRestartHelper, Stage, StageCheckpoint,
     },
     start_timer,
     state::{
@@ -463,3 +466,840 @@ where
         }
     }
 }
+
+/// A feedback which checks if the hash of an observer is equal to the original hash provided,
+/// e.g. the stack hash of a `BacktraceObserver` or the hash of the output of a `StdOutObserver`
+#[derive(Clone, Debug)]
+pub struct HashEqualityFeedback<O, S> {
+    name: Cow<'static, str>,
+    observer_ref: Handle<O>,
+    orig_hash: Option<u64>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    // The previous run's result of `Self::is_interesting`
+    last_result: Option<bool>,
+    phantom: PhantomData<S>,
+}
+
+impl<O, S> Named for HashEqualityFeedback<O, S> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<O, S> HasObserverHandle for HashEqualityFeedback<O, S> {
+    type Observer = O;
+
+    fn observer_handle(&self) -> &Handle<Self::Observer> {
+        &self.observer_ref
+    }
+}
+
+impl<O, S> Feedback<S> for HashEqualityFeedback<O, S>
+where
+    O: ObserverWithHashField,
+    S: State,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let obs = observers
+            .get(self.observer_handle())
+            .expect("Should have been provided valid observer name.");
+        // Without an original hash, there is nothing to preserve
+        let res = self.orig_hash.is_some() && obs.hash() == self.orig_hash;
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+}
+
+/// A feedback factory for ensuring that the hash of an observer stays the same for minimized inputs
+#[derive(Debug, Clone)]
+pub struct HashEqualityFactory<O, S> {
+    observer_ref: Handle<O>,
+    phantom: PhantomData<S>,
+}
+
+impl<O, S> HashEqualityFactory<O, S>
+where
+    O: ObserverWithHashField + Handled,
+{
+    /// Creates a new hash equality feedback for the given observer
+    pub fn new(obs: &O) -> Self {
+        Self {
+            observer_ref: obs.handle(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<O, S> HasObserverHandle for HashEqualityFactory<O, S> {
+    type Observer = O;
+
+    fn observer_handle(&self) -> &Handle<O> {
+        &self.observer_ref
+    }
+}
+
+impl<O, OT, S> FeedbackFactory<HashEqualityFeedback<O, S>, OT> for HashEqualityFactory<O, S>
+where
+    O: ObserverWithHashField + Handled,
+    OT: ObserversTuple<S>,
+    S: UsesInput,
+{
+    fn create_feedback(&self, observers: &OT) -> HashEqualityFeedback<O, S> {
+        let obs = observers
+            .get(self.observer_handle())
+            .expect("Should have been provided valid observer name.");
+        HashEqualityFeedback {
+            name: Cow::from("HashEq"),
+            observer_ref: obs.handle(),
+            orig_hash: obs.hash(),
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// A reduction pass of the [`PredicateTMinStage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TMinPass {
+    /// Removes chunks of halving size, down to single bytes, as `afl-tmin` does
+    ChunkRemoval,
+    /// Sets single bytes to zero
+    ByteZeroing,
+    /// Removes whole occurrences of the [`Tokens`] in the state at once
+    TokenCollapsing,
+}
+
+/// The default reduction passes of the [`PredicateTMinStage`]
+pub const DEFAULT_TMIN_PASSES: &[TMinPass] = &[
+    TMinPass::ChunkRemoval,
+    TMinPass::TokenCollapsing,
+    TMinPass::ByteZeroing,
+];
+
+/// The default number of executions the [`PredicateTMinStage`] spends on a single testcase
+pub const DEFAULT_TMIN_BUDGET: u64 = 4096;
+
+/// Added to testcases handled by a [`PredicateTMinStage`], so they are minimized only once
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct MinimizedMetadata {
+    /// The length of the input before the minimization
+    pub original_len: usize,
+    /// The number of executions spent on the minimization
+    pub execs: u64,
+}
+
+impl_serdeany!(MinimizedMetadata);
+
+/// The progress of a [`PredicateTMinStage`] minimizing solutions, stored in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+pub struct PredicateTMinMetadata {
+    last_solution: Option<CorpusId>,
+}
+
+impl_serdeany!(PredicateTMinMetadata);
+
+/// The testcase a [`PredicateTMinStage`] is minimizing
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+enum TMinTarget {
+    Corpus(CorpusId),
+    Solution(CorpusId),
+}
+
+/// The position of a [`PredicateTMinStage`] in its passes, kept as named metadata and updated in
+/// place before each execution, so it resumes after the candidate that made the client restart
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct TMinProgress {
+    target: TMinTarget,
+    /// The index of the current pass
+    pass: usize,
+    /// The chunk size, or the token index, of the current pass
+    step: usize,
+    /// The byte offset in the current pass
+    pos: usize,
+    /// If the current round of passes shrank the input
+    round_progress: bool,
+    /// If any round shrank the input
+    minimized: bool,
+    execs: u64,
+}
+
+impl_serdeany!(TMinProgress);
+
+/// The [`StageCheckpoint`] key of the smallest input found so far, saved whenever it changes
+const TMIN_BASE_KEY: &str = "base";
+
+/// A stage minimizing testcases while a predicate holds, e.g. "still crashes with the same stack
+/// hash" ([`HashEqualityFactory`]) or "still covers the same map" ([`MapEqualityFactory`]).
+///
+/// The predicate is a [`Feedback`], created by a [`FeedbackFactory`] from the observers of an
+/// execution of the original testcase. Unlike the [`StdTMinMutationalStage`], the reductions are
+/// deterministic: the [`TMinPass`]es run in order until none of them shrinks the input anymore,
+/// or until the execution budget for the testcase is spent.
+///
+/// By default, the current corpus entry is minimized once; see [`PredicateTMinStage::on_solutions`]
+/// to minimize new solutions instead.
+#[derive(Clone, Debug)]
+pub struct PredicateTMinStage<E, EM, F, FF, Z> {
+    name: Cow<'static, str>,
+    factory: FF,
+    passes: Vec<TMinPass>,
+    budget: u64,
+    solutions: bool,
+    #[allow(clippy::type_complexity)]
+    phantom: PhantomData<(E, EM, F, Z)>,
+}
+
+impl<E, EM, F, FF, Z> UsesState for PredicateTMinStage<E, EM, F, FF, Z>
+where
+    Z: UsesState,
+{
+    type State = Z::State;
+}
+
+impl<E, EM, F, FF, Z> PredicateTMinStage<E, EM, F, FF, Z> {
+    /// Creates a new [`PredicateTMinStage`] with the predicate created by `factory`,
+    /// the [`DEFAULT_TMIN_PASSES`] and the [`DEFAULT_TMIN_BUDGET`]
+    pub fn new(factory: FF) -> Self {
+        Self {
+            name: Cow::Borrowed("PredicateTMinStage"),
+            factory,
+            passes: DEFAULT_TMIN_PASSES.to_vec(),
+            budget: DEFAULT_TMIN_BUDGET,
+            solutions: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the reduction passes, run in the given order
+    #[must_use]
+    pub fn with_passes<P>(mut self, passes: P) -> Self
+    where
+        P: IntoIterator<Item = TMinPass>,
+    {
+        self.passes = passes.into_iter().collect();
+        self
+    }
+
+    /// Sets the maximum number of executions spent on a single testcase
+    #[must_use]
+    pub fn with_budget(mut self, budget: u64) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Minimizes each new solution instead of the current corpus entry
+    #[must_use]
+    pub fn on_solutions(mut self) -> Self {
+        self.solutions = true;
+        self
+    }
+
+    /// The reduction passes
+    #[must_use]
+    pub fn passes(&self) -> &[TMinPass] {
+        &self.passes
+    }
+}
+
+impl<E, EM, F, FF, Z> PredicateTMinStage<E, EM, F, FF, Z>
+where
+    Z: ExecutesInput<E, EM>,
+    E: HasObservers<State = Z::State>,
+    EM: EventFirer<State = Z::State>,
+    FF: FeedbackFactory<F, E::Observers>,
+    F: Feedback<Z::State>,
+    <Z::State as UsesInput>::Input: HasMutatorBytes + Clone,
+    Z::State: HasMetadata + HasNamedMetadata,
+{
+    /// The [`TMinProgress`] of an interrupted minimization, if any
+    fn resumed_progress(&self, state: &Z::State) -> Option<TMinProgress> {
+        state
+            .named_metadata_map()
+            .get::<TMinProgress>(&self.name)
+            .copied()
+    }
+
+    /// Clears the [`TMinProgress`] and the [`StageCheckpoint`] of the stage
+    fn clear_checkpoint(&self, state: &mut Z::State) {
+        let _ = state.remove_named_metadata::<TMinProgress>(&self.name);
+        StageCheckpoint::clear(state, &self.name);
+    }
+
+    /// Minimizes `original` while the predicate holds, returning the minimized input, if smaller,
+    /// and the number of executions spent.
+    /// Resumes from the [`TMinProgress`] if it belongs to the same `target`.
+    #[allow(clippy::type_complexity, clippy::too_many_lines)]
+    fn minimize(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Z::State,
+        manager: &mut EM,
+        original: &<Z::State as UsesInput>::Input,
+        target: TMinTarget,
+    ) -> Result<(Option<<Z::State as UsesInput>::Input>, u64), Error> {
+        let tokens = state
+            .metadata_map()
+            .get::<Tokens>()
+            .map_or_else(Vec::new, |tokens| tokens.tokens().to_vec());
+
+        let resume = self
+            .resumed_progress(state)
+            .filter(|progress| progress.target == target);
+        let mut base = original.clone();
+        if resume.is_some() {
+            if let Some(bytes) =
+                StageCheckpoint::load::<_, Vec<u8>>(state, &self.name, TMIN_BASE_KEY)?
+            {
+                base.drain(..);
+                base.extend(&bytes);
+            }
+        }
+
+        let exit_kind = fuzzer.execute_input(state, executor, manager, original)?;
+        let mut feedback = self.factory.create_feedback(&*executor.observers());
+        if !feedback.is_interesting(state, manager, original, &*executor.observers(), &exit_kind)? {
+            // The original does not fulfill the predicate, there is nothing to preserve
+            self.clear_checkpoint(state);
+            return Ok((None, 1));
+        }
+
+        let mut progress = resume.unwrap_or(TMinProgress {
+            target,
+            pass: 0,
+            step: 0,
+            pos: 0,
+            round_progress: false,
+            minimized: false,
+            execs: 1,
+        });
+        if resume.is_none() {
+            // A checkpoint of another target is stale
+            StageCheckpoint::clear(state, &self.name);
+        }
+        state.add_named_metadata(&self.name, progress);
+        // The candidate at the checkpoint made the client restart, skip it
+        let mut skip = resume.is_some();
+        let budget = self.budget;
+        let name = &self.name;
+        let mut holds = |candidate: &<Z::State as UsesInput>::Input,
+                         progress: &mut TMinProgress|
+         -> Result<bool, Error> {
+            if core::mem::take(&mut skip) || progress.execs >= budget {
+                return Ok(false);
+            }
+            *state.named_metadata_mut::<TMinProgress>(name)? = *progress;
+            progress.execs += 1;
+            let exit_kind = fuzzer.execute_input(state, executor, manager, candidate)?;
+            let observers = executor.observers();
+            let holds =
+                feedback.is_interesting(state, manager, candidate, &*observers, &exit_kind)?;
+            if holds {
+                StageCheckpoint::save(state, name, TMIN_BASE_KEY, &candidate.bytes())?;
+            }
+            Ok(holds)
+        };
+
+        loop {
+            while progress.pass < self.passes.len() {
+                match self.passes[progress.pass] {
+                    TMinPass::ChunkRemoval => {
+                        if progress.step == 0 {
+                            progress.step = (base.bytes().len().next_power_of_two() / 2).max(1);
+                        }
+                        while progress.step > 0 {
+                            while progress.pos < base.bytes().len() {
+                                let end = (progress.pos + progress.step).min(base.bytes().len());
+                                let mut candidate = base.clone();
+                                candidate.drain(progress.pos..end);
+                                if holds(&candidate, &mut progress)? {
+                                    base = candidate;
+                                    progress.round_progress = true;
+                                } else {
+                                    progress.pos += progress.step;
+                                }
+                            }
+                            progress.step /= 2;
+                            progress.pos = 0;
+                        }
+                    }
+                    TMinPass::ByteZeroing => {
+                        while progress.pos < base.bytes().len() {
+                            if base.bytes()[progress.pos] != 0 {
+                                let mut candidate = base.clone();
+                                candidate.bytes_mut()[progress.pos] = 0;
+                                if holds(&candidate, &mut progress)? {
+                                    base = candidate;
+                                    progress.round_progress = true;
+                                }
+                            }
+                            progress.pos += 1;
+                        }
+                    }
+                    TMinPass::TokenCollapsing => {
+                        while progress.step < tokens.len() {
+                            let token = &tokens[progress.step];
+                            while let Some(offset) = base.bytes()[progress.pos..]
+                                .windows(token.len().max(1))
+                                .position(|window| !token.is_empty() && window == token.as_slice())
+                            {
+                                let idx = progress.pos + offset;
+                                let mut candidate = base.clone();
+                                candidate.drain(idx..idx + token.len());
+                                if holds(&candidate, &mut progress)? {
+                                    base = candidate;
+                                    progress.round_progress = true;
+                                    progress.pos = idx;
+                                } else {
+                                    progress.pos = idx + 1;
+                                }
+                            }
+                            progress.step += 1;
+                            progress.pos = 0;
+                        }
+                    }
+                }
+                progress.pass += 1;
+                progress.step = 0;
+                progress.pos = 0;
+            }
+            progress.minimized |= progress.round_progress;
+            if !progress.round_progress {
+                break;
+            }
+            progress.round_progress = false;
+            progress.pass = 0;
+        }
+
+        self.clear_checkpoint(state);
+        Ok((progress.minimized.then_some(base), progress.execs))
+    }
+}
+
+impl<E, EM, F, FF, Z> Stage<E, EM, Z> for PredicateTMinStage<E, EM, F, FF, Z>
+where
+    Z: HasScheduler + ExecutionProcessor<E::Observers> + ExecutesInput<E, EM> + HasFeedback,
+    Z::Scheduler: RemovableScheduler,
+    E: HasObservers<State = Self::State>,
+    EM: EventFirer<State = Self::State>,
+    FF: FeedbackFactory<F, E::Observers>,
+    F: Feedback<Self::State>,
+    Self::Input: HasMutatorBytes + Clone,
+    Self::State: HasMetadata + HasNamedMetadata + HasExecutions + HasSolutions + HasCorpus,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut Self::State,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let resume = self.resumed_progress(state).map(|progress| progress.target);
+
+        if self.solutions {
+            let last_solution = state
+                .metadata_or_insert_with(PredicateTMinMetadata::default)
+                .last_solution;
+            let mut next = match (resume, last_solution) {
+                // Resume the minimization interrupted by a restart
+                (Some(TMinTarget::Solution(id)), _) => Some(id),
+                (_, Some(id)) => state.solutions().next(id),
+                (_, None) => state.solutions().first(),
+            };
+            while let Some(id) = next {
+                // Store the progress first, so a crash during the minimization does not cause a loop
+                state.metadata_mut::<PredicateTMinMetadata>()?.last_solution = Some(id);
+
+                let input = state.solutions().cloned_input_for_id(id)?;
+                let original_len = input.bytes().len();
+                if let (Some(minimized), execs) = self.minimize(
+                    fuzzer,
+                    executor,
+                    state,
+                    manager,
+                    &input,
+                    TMinTarget::Solution(id),
+                )? {
+                    let mut testcase = Testcase::with_executions(minimized, *state.executions());
+                    *testcase.metadata_map_mut() =
+                        state.solutions().get(id)?.borrow().metadata_map().clone();
+                    testcase.add_metadata(MinimizedMetadata {
+                        original_len,
+                        execs,
+                    });
+                    state.solutions_mut().replace(id, testcase)?;
+                }
+                next = state.solutions().next(id);
+            }
+            return Ok(());
+        }
+
+        let Some(base_corpus_id) = state.current_corpus_id()? else {
+            return Err(Error::illegal_state(
+                "state is not currently processing a corpus index",
+            ));
+        };
+        let target = TMinTarget::Corpus(base_corpus_id);
+        if resume != Some(target)
+            && state
+                .current_testcase()?
+                .has_metadata::<MinimizedMetadata>()
+        {
+            return Ok(());
+        }
+
+        let input = state.current_input_cloned()?;
+        let original_len = input.bytes().len();
+        // Mark the testcase first, so it is not minimized again from the start after a restart
+        state
+            .current_testcase_mut()?
+            .add_metadata(MinimizedMetadata {
+                original_len,
+                execs: 0,
+            });
+
+        let (minimized, execs) = self.minimize(fuzzer, executor, state, manager, &input, target)?;
+        let Some(base) = minimized else {
+            state
+                .current_testcase_mut()?
+                .metadata_mut::<MinimizedMetadata>()?
+                .execs = execs;
+            return Ok(());
+        };
+
+        let exit_kind = fuzzer.execute_input(state, executor, manager, &base)?;
+        let observers = executor.observers();
+        fuzzer
+            .feedback_mut()
+            .is_interesting(state, manager, &base, &*observers, &exit_kind)?;
+        let mut testcase = Testcase::with_executions(base, *state.executions());
+        fuzzer
+            .feedback_mut()
+            .append_metadata(state, manager, &*observers, &mut testcase)?;
+        testcase.add_metadata(MinimizedMetadata {
+            original_len,
+            execs,
+        });
+        let prev = state.corpus_mut().replace(base_corpus_id, testcase)?;
+        fuzzer
+            .scheduler_mut()
+            .on_replace(state, base_corpus_id, &prev)?;
+        Ok(())
+    }
+
+    #[inline]
+    fn should_restart(&mut self, _state: &mut Self::State) -> Result<bool, Error> {
+        // The progress is stored as checkpoint while minimizing
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, state: &mut Self::State) -> Result<(), Error> {
+        self.clear_checkpoint(state);
+        Ok(())
+    }
+}
+
+impl<E, EM, F, FF, Z> Named for PredicateTMinStage<E, EM, F, FF, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use core::cell::Cell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use libafl_bolts::{tuples::tuple_list, Named};
+
+    use super::{
+        HashEqualityFactory, MinimizedMetadata, PredicateTMinMetadata, PredicateTMinStage,
+        StageCheckpoint, TMinPass, TMinProgress, TMinTarget, TMIN_BASE_KEY,
+    };
+    use crate::{
+        corpus::{Corpus, CorpusId, HasCurrentCorpusId, Testcase},
+        events::NopEventManager,
+        executors::{test::ClosureExecutor, ExitKind},
+        feedbacks::{ConstFeedback, CrashFeedback},
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::Tokens,
+        observers::StdOutObserver,
+        schedulers::QueueScheduler,
+        stages::Stage,
+        state::{test::test_std_state, HasCorpus, HasExecutions, HasSolutions},
+        HasMetadata, HasNamedMetadata, StdFuzzer,
+    };
+
+    /// Crashes if the input contains an `X`
+    fn crash_on_x(_observers: &mut (), input: &BytesInput) -> ExitKind {
+        if input.bytes().contains(&b'X') {
+            ExitKind::Crash
+        } else {
+            ExitKind::Ok
+        }
+    }
+
+    #[test]
+    fn test_predicate_tmin_passes() {
+        let mut executor = ClosureExecutor::new(crash_on_x, ());
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+        state.add_metadata(Tokens::from([b"ab".to_vec(), b"cd".to_vec()]));
+        let original = BytesInput::new(b"abXcdab".to_vec());
+        let target = TMinTarget::Corpus(CorpusId(0));
+
+        let mut minimize = |stage: PredicateTMinStage<_, _, CrashFeedback, _, _>,
+                            input: &BytesInput| {
+            let (minimized, execs) = stage
+                .minimize(
+                    &mut fuzzer,
+                    &mut executor,
+                    &mut state,
+                    &mut mgr,
+                    input,
+                    target,
+                )
+                .unwrap();
+            (minimized.map(|input| input.bytes().to_vec()), execs)
+        };
+        let stage = || PredicateTMinStage::new(CrashFeedback::new());
+
+        let (zeroed, _) = minimize(stage().with_passes([TMinPass::ByteZeroing]), &original);
+        assert_eq!(zeroed.unwrap(), b"\0\0X\0\0\0\0");
+        let (collapsed, _) = minimize(stage().with_passes([TMinPass::TokenCollapsing]), &original);
+        assert_eq!(collapsed.unwrap(), b"X");
+        let (removed, _) = minimize(stage().with_passes([TMinPass::ChunkRemoval]), &original);
+        assert_eq!(removed.unwrap(), b"X");
+
+        // Without budget, only the original is executed
+        assert_eq!(minimize(stage().with_budget(1), &original), (None, 1));
+        // An input not fulfilling the predicate is not minimized
+        assert_eq!(
+            minimize(stage(), &BytesInput::new(b"abcd".to_vec())),
+            (None, 1)
+        );
+    }
+
+    #[test]
+    fn test_predicate_tmin_hash_equality() {
+        let stdout = StdOutObserver::new("stdout");
+        let factory = HashEqualityFactory::new(&stdout);
+        let stage = PredicateTMinStage::new(factory);
+        // The target prints an error message only for inputs containing an `X`
+        let mut executor = ClosureExecutor::new(
+            |observers: &mut (StdOutObserver, ()), input: &BytesInput| {
+                if input.bytes().contains(&b'X') {
+                    observers.0.observe_stdout(b"error: X");
+                } else {
+                    observers.0.observe_stdout(b"ok");
+                }
+                ExitKind::Ok
+            },
+            tuple_list!(stdout),
+        );
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+
+        let (minimized, _) = stage
+            .minimize(
+                &mut fuzzer,
+                &mut executor,
+                &mut state,
+                &mut mgr,
+                &BytesInput::new(b"aaXbb".to_vec()),
+                TMinTarget::Solution(CorpusId(0)),
+            )
+            .unwrap();
+        assert_eq!(minimized.unwrap().bytes(), b"X");
+    }
+
+    #[test]
+    fn test_predicate_tmin_stage() {
+        let mut executor = ClosureExecutor::new(crash_on_x, ());
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+
+        // Minimizes the current corpus entry once
+        let mut stage = PredicateTMinStage::new(CrashFeedback::new());
+        let id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"aaXbb".to_vec())))
+            .unwrap();
+        state.set_corpus_id(id).unwrap();
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        let testcase = state.corpus().get(id).unwrap().borrow();
+        assert_eq!(testcase.input().as_ref().unwrap().bytes(), b"X");
+        assert_eq!(
+            testcase
+                .metadata::<MinimizedMetadata>()
+                .unwrap()
+                .original_len,
+            5
+        );
+        drop(testcase);
+        let executions = *state.executions();
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_eq!(*state.executions(), executions);
+
+        // Minimizes each new solution
+        let mut stage = PredicateTMinStage::new(CrashFeedback::new()).on_solutions();
+        let ids = [b"Xcc".to_vec(), b"ddX".to_vec()].map(|bytes| {
+            state
+                .solutions_mut()
+                .add(Testcase::new(BytesInput::new(bytes)))
+                .unwrap()
+        });
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        for id in ids {
+            let solution = state.solutions().get(id).unwrap().borrow();
+            assert_eq!(solution.input().as_ref().unwrap().bytes(), b"X");
+            assert!(solution.has_metadata::<MinimizedMetadata>());
+        }
+        assert_eq!(
+            state
+                .metadata::<PredicateTMinMetadata>()
+                .unwrap()
+                .last_solution,
+            Some(ids[1])
+        );
+    }
+
+    #[test]
+    fn test_predicate_tmin_resumes() {
+        let original = BytesInput::new(b"aaaaXbbbb".to_vec());
+        let target = TMinTarget::Corpus(CorpusId(0));
+        let stage = PredicateTMinStage::<_, _, CrashFeedback, _, _>::new(CrashFeedback::new());
+        let calls = Cell::new(0_usize);
+        let interrupt_at = Cell::new(None);
+        let mut executor = ClosureExecutor::new(
+            |_observers: &mut (), input: &BytesInput| {
+                calls.set(calls.get() + 1);
+                assert_ne!(Some(calls.get()), interrupt_at.get(), "simulated crash");
+                if input.bytes().contains(&b'X') {
+                    ExitKind::Crash
+                } else {
+                    ExitKind::Ok
+                }
+            },
+            (),
+        );
+        let mut fuzzer = StdFuzzer::<_, _, _, ()>::new(
+            QueueScheduler::new(),
+            ConstFeedback::new(false),
+            ConstFeedback::new(false),
+        );
+        let mut mgr = NopEventManager::new();
+        let mut state = test_std_state::<BytesInput>();
+
+        let (minimized, execs) = stage
+            .minimize(
+                &mut fuzzer,
+                &mut executor,
+                &mut state,
+                &mut mgr,
+                &original,
+                target,
+            )
+            .unwrap();
+        assert_eq!(minimized.unwrap().bytes(), b"X");
+        let uninterrupted = calls.replace(0);
+        assert_eq!(execs, uninterrupted as u64);
+        assert!(!state.has_named_metadata::<TMinProgress>(stage.name()));
+
+        // The client "restarts" in the middle of the minimization
+        interrupt_at.set(Some(uninterrupted / 2));
+        assert!(catch_unwind(AssertUnwindSafe(|| stage.minimize(
+            &mut fuzzer,
+            &mut executor,
+            &mut state,
+            &mut mgr,
+            &original,
+            target,
+        )))
+        .is_err());
+        let progress = stage.resumed_progress(&state).unwrap();
+        assert_eq!(progress.target, target);
+        assert_eq!(progress.execs as usize, uninterrupted / 2 - 1);
+        let base = StageCheckpoint::load::<_, Vec<u8>>(&state, stage.name(), TMIN_BASE_KEY)
+            .unwrap()
+            .unwrap();
+        assert!(base.len() < original.bytes().len());
+
+        // Resuming skips the candidate of the restart and the work before it
+        calls.set(0);
+        interrupt_at.set(None);
+        let (minimized, execs) = stage
+            .minimize(
+                &mut fuzzer,
+                &mut executor,
+                &mut state,
+                &mut mgr,
+                &original,
+                target,
+            )
+            .unwrap();
+        assert_eq!(minimized.unwrap().bytes(), b"X");
+        assert!(calls.get() < uninterrupted);
+        assert_eq!(execs, progress.execs + calls.get() as u64 - 1);
+        assert!(!state.has_named_metadata::<TMinProgress>(stage.name()));
+        assert!(
+            StageCheckpoint::load::<_, Vec<u8>>(&state, stage.name(), TMIN_BASE_KEY)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,462 @@
+//! Export and import of a [`StdState`] in a stable, versioned file format, independent of its
+//! in-memory layout, so that a campaign can move to another machine, Rust version or a moderately
+//! different build of the fuzzer.
+//!
+//! An export is a single file, containing
+//! * the magic bytes [`STATE_EXPORT_MAGIC`],
+//! * the format version [`STATE_EXPORT_VERSION`], as little endian `u32`,
+//! * the [`postcard`]-serialized [`StateExportManifest`] and sections, each section serialized on
+//!   its own, so readers skip sections they don't know.
+//!
+//! The sections are
+//! * `counters`: the executions, the imported testcases, the start time and the max size,
+//! * `rand`: the random number generator,
+//! * `metadata` and `named_metadata`: the metadata of the state,
+//! * `corpus` and `solutions`: the testcases, with their inputs and metadata.
+//!
+//! Metadata is stored under the stable name of its type, see [`PortableSerdeAny`]. Entries of types
+//! without a stable name are not exported. Entries of types unknown to the importing build, or with
+//! an incompatible layout, are skipped with a warning; use
+//! [`libafl_bolts::serdeany::VersionedSerdeAny`] to migrate them instead. The stage progress and
+//! the current testcase are not exported, an imported state starts with a new fuzzing loop.
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::time::Duration;
+use std::{fs, path::Path};
+
+use libafl_bolts::{
+    current_time,
+    fs::write_file_atomic,
+    rands::Rand,
+    serdeany::{stable_name_of, PortableSerdeAny, SerdeAny},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId, Testcase},
+    inputs::Input,
+    schedulers::Scheduler,
+    state::StdState,
+    Error, HasMetadata,
+};
+
+/// The magic bytes at the start of a state export
+pub const STATE_EXPORT_MAGIC: &[u8; 8] = b"LIBAFLST";
+
+/// The version of the state export format
+pub const STATE_EXPORT_VERSION: u32 = 1;
+
+const SECTION_COUNTERS: &str = "counters";
+const SECTION_RAND: &str = "rand";
+const SECTION_METADATA: &str = "metadata";
+const SECTION_NAMED_METADATA: &str = "named_metadata";
+const SECTION_CORPUS: &str = "corpus";
+const SECTION_SOLUTIONS: &str = "solutions";
+
+/// Describes a state export, see [`read_export_manifest`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateExportManifest {
+    /// The version of `LibAFL` that wrote the export
+    pub libafl_version: String,
+    /// The stable name of the type of the inputs, if one is registered, see
+    /// [`libafl_bolts::serdeany::RegistryBuilder::register_stable_name`]
+    pub input_type: Option<String>,
+    /// When the export was written, since the epoch
+    pub created: Duration,
+    /// The names of the sections
+    pub sections: Vec<String>,
+}
+
+/// A named section of a state export
+#[derive(Debug, Serialize, Deserialize)]
+struct StateExportSection {
+    name: String,
+    data: Vec<u8>,
+}
+
+/// The content of a state export after the header
+#[derive(Debug, Serialize, Deserialize)]
+struct StateExport {
+    manifest: StateExportManifest,
+    sections: Vec<StateExportSection>,
+}
+
+/// The `counters` section
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedCounters {
+    executions: u64,
+    imported: usize,
+    start_time: Duration,
+    max_size: usize,
+}
+
+/// A testcase in the `corpus` and `solutions` sections
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedTestcase {
+    id: CorpusId,
+    input: Vec<u8>,
+    disabled: bool,
+    parent_id: Option<CorpusId>,
+    executions: u64,
+    scheduled_count: usize,
+    exec_time: Option<Duration>,
+    metadata: Vec<PortableSerdeAny>,
+}
+
+/// Reads the manifest of a state export, e.g. to check where it comes from before importing it
+pub fn read_export_manifest<P>(path: P) -> Result<StateExportManifest, Error>
+where
+    P: AsRef<Path>,
+{
+    Ok(read_export(path.as_ref())?.manifest)
+}
+
+fn read_export(path: &Path) -> Result<StateExport, Error> {
+    let bytes = fs::read(path)?;
+    let header_len = STATE_EXPORT_MAGIC.len() + 4;
+    if bytes.len() < header_len || &bytes[..STATE_EXPORT_MAGIC.len()] != STATE_EXPORT_MAGIC {
+        return Err(Error::illegal_argument(format!(
+            "{} is not a state export",
+            path.display()
+        )));
+    }
+    let version = u32::from_le_bytes(
+        bytes[STATE_EXPORT_MAGIC.len()..header_len]
+            .try_into()
+            .unwrap(),
+    );
+    if version > STATE_EXPORT_VERSION {
+        return Err(Error::unsupported(format!(
+            "State export format version {version} is newer than the supported version {STATE_EXPORT_VERSION}"
+        )));
+    }
+    Ok(postcard::from_bytes(&bytes[header_len..])?)
+}
+
+fn encode_section<T>(name: &str, value: &T) -> Result<StateExportSection, Error>
+where
+    T: Serialize + ?Sized,
+{
+    Ok(StateExportSection {
+        name: name.into(),
+        data: postcard::to_allocvec(value)?,
+    })
+}
+
+/// Stores a metadata entry, or skips it with a warning if its type has no stable name
+fn export_value(value: &dyn SerdeAny) -> Result<Option<PortableSerdeAny>, Error> {
+    let exported = PortableSerdeAny::encode(value)?;
+    if exported.is_none() {
+        log::warn!(
+            "Not exporting metadata of type {} without a stable name",
+            value.type_name()
+        );
+    }
+    Ok(exported)
+}
+
+fn export_metadata<'a>(
+    values: impl Iterator<Item = &'a dyn SerdeAny>,
+) -> Result<Vec<PortableSerdeAny>, Error> {
+    values
+        .filter_map(|value| export_value(value).transpose())
+        .collect()
+}
+
+/// Loads a metadata entry, or skips it with a warning
+fn import_metadata(value: &PortableSerdeAny) -> Option<Box<dyn SerdeAny>> {
+    match value.decode() {
+        Ok(Some(value)) => Some(value),
+        Ok(None) => {
+            log::warn!("Skipping metadata of unknown type {}", value.stable_name);
+            None
+        }
+        Err(err) => {
+            log::warn!("Skipping metadata: {err}");
+            None
+        }
+    }
+}
+
+fn export_testcases<C>(corpus: &C) -> Result<Vec<ExportedTestcase>, Error>
+where
+    C: Corpus,
+{
+    let mut testcases = (0..corpus.count_all())
+        .map(|nth| {
+            let id = corpus.nth_from_all(nth);
+            let mut testcase = corpus.get_from_all(id)?.borrow_mut();
+            let input = postcard::to_allocvec(testcase.load_input(corpus)?)?;
+            Ok(ExportedTestcase {
+                id,
+                input,
+                disabled: testcase.disabled(),
+                parent_id: testcase.parent_id(),
+                executions: *testcase.executions(),
+                scheduled_count: testcase.scheduled_count(),
+                exec_time: *testcase.exec_time(),
+                metadata: export_metadata(testcase.metadata_map().values())?,
+            })
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    // Adding them in order keeps the ids on import
+    testcases.sort_unstable_by_key(|testcase| testcase.id);
+    Ok(testcases)
+}
+
+fn import_testcases<C>(corpus: &mut C, data: &[u8]) -> Result<(), Error>
+where
+    C: Corpus,
+{
+    let testcases: Vec<ExportedTestcase> = postcard::from_bytes(data)?;
+    for exported in testcases {
+        let mut testcase = Testcase::new(postcard::from_bytes(&exported.input)?);
+        testcase.set_parent_id_optional(exported.parent_id);
+        *testcase.executions_mut() = exported.executions;
+        testcase.set_scheduled_count(exported.scheduled_count);
+        *testcase.exec_time_mut() = exported.exec_time;
+        for value in exported.metadata.iter().filter_map(import_metadata) {
+            testcase.metadata_map_mut().insert_dyn(value);
+        }
+        let id = if exported.disabled {
+            corpus.add_disabled(testcase)?
+        } else {
+            corpus.add(testcase)?
+        };
+        if id != exported.id {
+            log::warn!(
+                "Imported testcase {} as {id}, metadata referring to it is stale",
+                exported.id
+            );
+        }
+    }
+    Ok(())
+}
+
+impl<C, I, R, SC> StdState<I, C, R, SC>
+where
+    I: Input + 'static,
+    C: Corpus<Input = I>,
+    R: Rand,
+    SC: Corpus<Input = I>,
+{
+    /// Exports the state to a file, see the [module docs](self) for the format
+    pub fn export<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let named_metadata = self
+            .named_metadata
+            .entries()
+            .filter_map(|(name, value)| {
+                export_value(value)
+                    .map(|exported| exported.map(|exported| (name, exported)))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let sections = vec![
+            encode_section(
+                SECTION_COUNTERS,
+                &ExportedCounters {
+                    executions: self.executions,
+                    imported: self.imported,
+                    start_time: self.start_time,
+                    max_size: self.max_size,
+                },
+            )?,
+            encode_section(SECTION_RAND, &self.rand)?,
+            encode_section(SECTION_METADATA, &export_metadata(self.metadata.values())?)?,
+            encode_section(SECTION_NAMED_METADATA, &named_metadata)?,
+            encode_section(SECTION_CORPUS, &export_testcases(&self.corpus)?)?,
+            encode_section(SECTION_SOLUTIONS, &export_testcases(&self.solutions)?)?,
+        ];
+        let export = StateExport {
+            manifest: StateExportManifest {
+                libafl_version: env!("CARGO_PKG_VERSION").into(),
+                input_type: stable_name_of::<I>().map(Into::into),
+                created: current_time(),
+                sections: sections
+                    .iter()
+                    .map(|section| section.name.clone())
+                    .collect(),
+            },
+            sections,
+        };
+
+        let mut bytes = STATE_EXPORT_MAGIC.to_vec();
+        bytes.extend_from_slice(&STATE_EXPORT_VERSION.to_le_bytes());
+        bytes.extend(postcard::to_allocvec(&export)?);
+        write_file_atomic(path, &bytes)
+    }
+
+    /// Imports a state exported with [`Self::export`] into this state, which needs empty corpora.
+    ///
+    /// The corpora and the rand of this state are used with the imported content, e.g. to move
+    /// from an [`crate::corpus::InMemoryCorpus`] to an [`crate::corpus::OnDiskCorpus`]. A rand
+    /// of another type is not imported.
+    ///
+    /// The `scheduler` is told about each imported (enabled) testcase of the corpus, as if it
+    /// was just added, so that it can set up its metadata for them.
+    pub fn import<P, CS>(&mut self, path: P, scheduler: &mut CS) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        CS: Scheduler<State = Self>,
+    {
+        if self.corpus.count_all() > 0 || self.solutions.count_all() > 0 {
+            return Err(Error::illegal_state(
+                "States can only be imported into a state with empty corpora",
+            ));
+        }
+        let path = path.as_ref();
+        let export = read_export(path)?;
+        log::info!(
+            "Importing state {} exported by LibAFL {}",
+            path.display(),
+            export.manifest.libafl_version
+        );
+        if let (Some(exported), Some(current)) =
+            (&export.manifest.input_type, stable_name_of::<I>())
+        {
+            if exported != current {
+                log::warn!(
+                    "The state export has inputs of type {exported}, importing them as {current}"
+                );
+            }
+        }
+
+        for section in export.sections {
+            match section.name.as_str() {
+                SECTION_COUNTERS => {
+                    let counters: ExportedCounters = postcard::from_bytes(&section.data)?;
+                    self.executions = counters.executions;
+                    self.imported = counters.imported;
+                    self.start_time = counters.start_time;
+                    self.max_size = counters.max_size;
+                }
+                SECTION_RAND => match postcard::from_bytes(&section.data) {
+                    Ok(rand) => self.rand = rand,
+                    Err(err) => log::warn!("Keeping the current rand, cannot import it: {err}"),
+                },
+                SECTION_METADATA => {
+                    let metadata: Vec<PortableSerdeAny> = postcard::from_bytes(&section.data)?;
+                    for value in metadata.iter().filter_map(import_metadata) {
+                        self.metadata.insert_dyn(value);
+                    }
+                }
+                SECTION_NAMED_METADATA => {
+                    let metadata: Vec<(String, PortableSerdeAny)> =
+                        postcard::from_bytes(&section.data)?;
+                    for (name, value) in &metadata {
+                        if let Some(value) = import_metadata(value) {
+                            self.named_metadata.insert_dyn(name, value);
+                        }
+                    }
+                }
+                SECTION_CORPUS => import_testcases(&mut self.corpus, &section.data)?,
+                SECTION_SOLUTIONS => import_testcases(&mut self.solutions, &section.data)?,
+                name => log::warn!("Skipping unknown section {name} of the state export"),
+            }
+        }
+
+        let ids = self.corpus.ids().collect::<Vec<_>>();
+        for id in ids {
+            scheduler.on_add(self, id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use libafl_bolts::{
+        rands::{Rand, StdRand},
+        serdeany::RegistryBuilder,
+    };
+
+    use super::read_export_manifest;
+    use crate::{
+        corpus::{Corpus, CorpusId, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        mutators::MutationReplayMetadata,
+        schedulers::{QueueScheduler, SchedulingLogMetadata},
+        state::{HasCorpus, HasExecutions, HasRand, HasSolutions, StdState},
+        HasMetadata, HasNamedMetadata,
+    };
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_state_export_import() {
+        unsafe {
+            RegistryBuilder::register_stable_name::<BytesInput>("BytesInput");
+        }
+        let path = "target/.test/state_export/state.export";
+        drop(fs::remove_dir_all("target/.test/state_export"));
+        fs::create_dir_all("target/.test/state_export").unwrap();
+
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut testcase = Testcase::new(BytesInput::new(b"export me".to_vec()));
+        testcase.add_metadata(MutationReplayMetadata {
+            parent_id: None,
+            rand: vec![1, 2],
+            mutations: 3,
+        });
+        state.corpus_mut().add(testcase).unwrap();
+        state
+            .solutions_mut()
+            .add(Testcase::new(BytesInput::new(b"crash".to_vec())))
+            .unwrap();
+        *state.executions_mut() = 42;
+        state.add_metadata(SchedulingLogMetadata::default());
+        state.add_named_metadata("log", SchedulingLogMetadata::default());
+        state.export(path).unwrap();
+
+        let manifest = read_export_manifest(path).unwrap();
+        assert_eq!(manifest.sections.len(), 6);
+        assert_eq!(manifest.input_type.as_deref(), Some("BytesInput"));
+
+        let mut imported = StdState::new(
+            StdRand::with_seed(2),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut scheduler = QueueScheduler::new();
+        imported.import(path, &mut scheduler).unwrap();
+        assert_eq!(*imported.executions(), 42);
+        assert_eq!(imported.rand_mut().next(), state.rand_mut().next());
+        assert!(imported.has_metadata::<SchedulingLogMetadata>());
+        assert!(imported.has_named_metadata::<SchedulingLogMetadata>("log"));
+        assert_eq!(imported.corpus().count(), 1);
+        assert_eq!(imported.solutions().count(), 1);
+        let testcase = imported.corpus().get(CorpusId(0)).unwrap().borrow();
+        assert_eq!(
+            testcase.input().as_ref().unwrap(),
+            &BytesInput::new(b"export me".to_vec())
+        );
+        assert_eq!(
+            testcase
+                .metadata::<MutationReplayMetadata>()
+                .unwrap()
+                .mutations,
+            3
+        );
+        drop(testcase);
+
+        // Only into empty corpora
+        assert!(imported.import(path, &mut scheduler).is_err());
+    }
+}
@@ -23,6 +23,17 @@ use libafl_bolts::{
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
+pub use export::{
+    read_export_manifest, StateExportManifest, STATE_EXPORT_MAGIC, STATE_EXPORT_VERSION,
+};
+pub mod registers;
+pub use registers::{
+    HasStageRegisters, RegisterDeclaration, StageRegister, StageRegisterDeclarations,
+    StageRegistersMetadata,
+};
 mod stack;
 pub use stack::StageStack;
 
@@ -841,7 +852,11 @@ where
         Z: Evaluator<E, EM, State = Self>,
     {
         self.canonicalize_input_dirs(in_dirs)?;
-        log::debug!("Loading with in_dirs {:?}, canonicalized as {:?} ", in_dirs, self.remaining_initial_files);
+        log::debug!(
+            "Loading with in_dirs {:?}, canonicalized as {:?} ",
+            in_dirs,
+            self.remaining_initial_files
+        );
         self.continue_loading_initial_inputs_custom(
             fuzzer,
             executor,
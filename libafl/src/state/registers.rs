@@ -0,0 +1,347 @@
+//! Stage registers: typed, versioned named values passed from one stage to the next,
+//! e.g. the taint ranges found by the [`crate::stages::ColorizationStage`] and used by the
+//! redqueen mutators.
+//!
+//! Stages declare the registers they produce and consume in [`crate::stages::Stage::declare_registers`].
+//! [`crate::stages::StagesTuple::validate_registers`] checks, before fuzzing starts, that each
+//! consumed register is produced by an earlier stage with the same type and version, so a
+//! miswired pipeline fails at startup instead of silently doing nothing.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{any::type_name, fmt, marker::PhantomData};
+
+use hashbrown::HashMap;
+use libafl_bolts::serdeany::SerdeAny;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{CorpusId, HasCurrentCorpusId},
+    Error, HasMetadata, HasNamedMetadata,
+};
+
+/// A typed, versioned, named register, usually declared as a `const` shared by the producing and
+/// the consuming stages.
+///
+/// Bump the version whenever the meaning of the value changes, so that stages built against
+/// different versions are caught by the validation.
+pub struct StageRegister<T> {
+    name: &'static str,
+    version: u32,
+    phantom: PhantomData<fn() -> T>,
+}
+
+impl<T> StageRegister<T> {
+    /// Creates a new [`StageRegister`]
+    #[must_use]
+    pub const fn new(name: &'static str, version: u32) -> Self {
+        Self {
+            name,
+            version,
+            phantom: PhantomData,
+        }
+    }
+
+    /// The name of this register
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The version of this register
+    #[must_use]
+    pub const fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The [`RegisterDeclaration`] of this register
+    #[must_use]
+    pub fn declaration(&self) -> RegisterDeclaration {
+        RegisterDeclaration {
+            name: self.name,
+            version: self.version,
+            type_name: type_name::<T>(),
+        }
+    }
+}
+
+impl<T> Clone for StageRegister<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for StageRegister<T> {}
+
+impl<T> fmt::Debug for StageRegister<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StageRegister")
+            .field("name", &self.name)
+            .field("version", &self.version)
+            .field("type", &type_name::<T>())
+            .finish()
+    }
+}
+
+/// The name, version and type of a [`StageRegister`], as declared by stages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterDeclaration {
+    /// The name of the register
+    pub name: &'static str,
+    /// The version of the register
+    pub version: u32,
+    /// The type of the value of the register
+    pub type_name: &'static str,
+}
+
+impl fmt::Display for RegisterDeclaration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (v{}, {})", self.name, self.version, self.type_name)
+    }
+}
+
+/// The registers produced and consumed by the stages of a pipeline, in execution order
+#[derive(Debug, Default, Clone)]
+pub struct StageRegisterDeclarations {
+    stage: usize,
+    produced: Vec<(usize, RegisterDeclaration)>,
+    consumed: Vec<(usize, RegisterDeclaration)>,
+}
+
+impl StageRegisterDeclarations {
+    /// Creates empty [`StageRegisterDeclarations`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares that the current stage produces the register
+    pub fn produces<T>(&mut self, register: &StageRegister<T>) {
+        self.produces_declaration(register.declaration());
+    }
+
+    /// Declares that the current stage consumes the register
+    pub fn consumes<T>(&mut self, register: &StageRegister<T>) {
+        self.consumes_declaration(register.declaration());
+    }
+
+    /// Declares that the current stage produces the register, see [`StageRegister::declaration`]
+    pub fn produces_declaration(&mut self, declaration: RegisterDeclaration) {
+        self.produced.push((self.stage, declaration));
+    }
+
+    /// Declares that the current stage consumes the register, see [`StageRegister::declaration`]
+    pub fn consumes_declaration(&mut self, declaration: RegisterDeclaration) {
+        self.consumed.push((self.stage, declaration));
+    }
+
+    /// Moves on to the next stage of the pipeline
+    pub fn next_stage(&mut self) {
+        self.stage += 1;
+    }
+
+    /// The declared producers, with the index of their stage
+    #[must_use]
+    pub fn produced(&self) -> &[(usize, RegisterDeclaration)] {
+        &self.produced
+    }
+
+    /// The declared consumers, with the index of their stage
+    #[must_use]
+    pub fn consumed(&self) -> &[(usize, RegisterDeclaration)] {
+        &self.consumed
+    }
+
+    /// Checks that all producers of a register agree on its type and version, and that each
+    /// consumed register is produced by an earlier stage with the same type and version.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (idx, (stage, produced)) in self.produced.iter().enumerate() {
+            if let Some((other_stage, other)) = self.produced[..idx]
+                .iter()
+                .find(|(_, other)| other.name == produced.name && other != produced)
+            {
+                return Err(Error::illegal_argument(format!(
+                    "Stage {stage} produces register {produced}, but stage {other_stage} produces {other}"
+                )));
+            }
+        }
+
+        for (stage, consumed) in &self.consumed {
+            let producers: Vec<&(usize, RegisterDeclaration)> = self
+                .produced
+                .iter()
+                .filter(|(_, produced)| produced.name == consumed.name)
+                .collect();
+            match producers.iter().find(|(producer, _)| producer < stage) {
+                Some((_, produced)) if produced != consumed => {
+                    return Err(Error::illegal_argument(format!(
+                        "Stage {stage} consumes register {consumed}, but it is produced as {produced}"
+                    )));
+                }
+                Some(_) => {}
+                None if producers.is_empty() => {
+                    return Err(Error::illegal_argument(format!(
+                        "Stage {stage} consumes register {consumed}, but no stage produces it"
+                    )));
+                }
+                None => {
+                    return Err(Error::illegal_argument(format!(
+                        "Stage {stage} consumes register {consumed}, but it is only produced by later stages"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The bookkeeping of a written register
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct RegisterEntry {
+    version: u32,
+    corpus_id: Option<CorpusId>,
+}
+
+/// The versions and the testcases the registers were last written for
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    allow(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+pub struct StageRegistersMetadata {
+    entries: HashMap<String, RegisterEntry>,
+}
+
+libafl_bolts::impl_serdeany!(StageRegistersMetadata);
+
+impl StageRegistersMetadata {
+    /// The testcase the register was last written for, if it was written with the same version
+    #[must_use]
+    pub fn written_for<T>(&self, register: &StageRegister<T>) -> Option<Option<CorpusId>> {
+        self.entries
+            .get(register.name)
+            .filter(|entry| entry.version == register.version)
+            .map(|entry| entry.corpus_id)
+    }
+}
+
+/// Read and write [`StageRegister`]s, stored in the named metadata of the state
+pub trait HasStageRegisters {
+    /// Writes the register, for the current testcase
+    fn write_register<T>(&mut self, register: &StageRegister<T>, value: T)
+    where
+        T: SerdeAny;
+
+    /// The value of the register, if it was written with the same version
+    fn register<T>(&self, register: &StageRegister<T>) -> Option<&T>
+    where
+        T: SerdeAny;
+
+    /// The value of the register (mutable), if it was written with the same version
+    fn register_mut<T>(&mut self, register: &StageRegister<T>) -> Option<&mut T>
+    where
+        T: SerdeAny;
+
+    /// The value of the register, if it was written with the same version for the current testcase
+    fn current_register<T>(&self, register: &StageRegister<T>) -> Option<&T>
+    where
+        T: SerdeAny;
+}
+
+impl<S> HasStageRegisters for S
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    fn write_register<T>(&mut self, register: &StageRegister<T>, value: T)
+    where
+        T: SerdeAny,
+    {
+        let corpus_id = self.current_corpus_id().ok().flatten();
+        self.metadata_or_insert_with(StageRegistersMetadata::default)
+            .entries
+            .insert(
+                register.name.to_string(),
+                RegisterEntry {
+                    version: register.version,
+                    corpus_id,
+                },
+            );
+        self.named_metadata_map_mut().insert(register.name, value);
+    }
+
+    fn register<T>(&self, register: &StageRegister<T>) -> Option<&T>
+    where
+        T: SerdeAny,
+    {
+        self.metadata::<StageRegistersMetadata>()
+            .ok()?
+            .written_for(register)?;
+        self.named_metadata_map().get::<T>(register.name)
+    }
+
+    fn register_mut<T>(&mut self, register: &StageRegister<T>) -> Option<&mut T>
+    where
+        T: SerdeAny,
+    {
+        self.metadata::<StageRegistersMetadata>()
+            .ok()?
+            .written_for(register)?;
+        self.named_metadata_map_mut().get_mut::<T>(register.name)
+    }
+
+    fn current_register<T>(&self, register: &StageRegister<T>) -> Option<&T>
+    where
+        T: SerdeAny,
+    {
+        let written_for = self
+            .metadata::<StageRegistersMetadata>()
+            .ok()?
+            .written_for(register)?;
+        if written_for != self.current_corpus_id().ok().flatten() {
+            return None;
+        }
+        self.named_metadata_map().get::<T>(register.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StageRegister, StageRegisterDeclarations};
+
+    const A: StageRegister<u64> = StageRegister::new("a", 1);
+    const A_V2: StageRegister<u64> = StageRegister::new("a", 2);
+    const A_BYTES: StageRegister<u8> = StageRegister::new("a", 1);
+
+    #[test]
+    fn test_register_validation() {
+        let mut ok = StageRegisterDeclarations::new();
+        ok.produces(&A);
+        ok.next_stage();
+        ok.consumes(&A);
+        assert!(ok.validate().is_ok());
+
+        let mut missing = StageRegisterDeclarations::new();
+        missing.consumes(&A);
+        assert!(missing.validate().is_err());
+
+        let mut late = StageRegisterDeclarations::new();
+        late.consumes(&A);
+        late.next_stage();
+        late.produces(&A);
+        assert!(late.validate().is_err());
+
+        let mut version = StageRegisterDeclarations::new();
+        version.produces(&A);
+        version.next_stage();
+        version.consumes(&A_V2);
+        assert!(version.validate().is_err());
+
+        let mut conflicting = StageRegisterDeclarations::new();
+        conflicting.produces(&A);
+        conflicting.next_stage();
+        conflicting.produces(&A_BYTES);
+        assert!(conflicting.validate().is_err());
+    }
+}
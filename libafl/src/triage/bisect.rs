@@ -0,0 +1,241 @@
+//! Bisection of objectives against a series of target binaries, e.g. nightly builds.
+//!
+//! The [`BinaryBisector`] finds the first binary of the series in which an objective reproduces,
+//! running it with any [`Executor`] built for each binary, e.g. a `ForkserverExecutor` or a
+//! `CommandExecutor`. The result is attached to the objective as [`BisectionMetadata`].
+
+use alloc::vec::Vec;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    corpus::{Corpus, CorpusId},
+    executors::{Executor, ExitKind, HasObservers},
+    observers::ObserversTuple,
+    state::{HasSolutions, UsesState},
+    Error, HasMetadata,
+};
+
+/// The result of a bisection, added to the objective by [`BinaryBisector::bisect_objective`]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BisectionMetadata {
+    /// The bisected binaries, oldest first
+    pub binaries: Vec<PathBuf>,
+    /// The index of the first binary reproducing the objective, or `None` if even the newest does not
+    pub first_bad: Option<usize>,
+    /// The index of the binary before `first_bad`, which does not reproduce the objective
+    pub last_good: Option<usize>,
+    /// The tested binaries and whether they reproduced the objective, in test order
+    pub tested: Vec<(usize, bool)>,
+}
+
+libafl_bolts::impl_serdeany!(BisectionMetadata);
+
+impl BisectionMetadata {
+    /// The first binary reproducing the objective
+    #[must_use]
+    pub fn first_bad_binary(&self) -> Option<&Path> {
+        self.first_bad.map(|idx| self.binaries[idx].as_path())
+    }
+
+    /// The last binary not reproducing the objective
+    #[must_use]
+    pub fn last_good_binary(&self) -> Option<&Path> {
+        self.last_good.map(|idx| self.binaries[idx].as_path())
+    }
+}
+
+/// Finds the first of `count` revisions for which `reproduces` holds, assuming that it holds for all
+/// later revisions once it held. Returns the first bad and the last good revision, if any.
+pub fn bisect_first<F>(
+    count: usize,
+    mut reproduces: F,
+) -> Result<(Option<usize>, Option<usize>), Error>
+where
+    F: FnMut(usize) -> Result<bool, Error>,
+{
+    if count == 0 || !reproduces(count - 1)? {
+        return Ok((None, None));
+    }
+    // `bad` always reproduces, `good` (if any) never does
+    let mut good: Option<usize> = None;
+    let mut bad = count - 1;
+    loop {
+        let low = good.map_or(0, |good| good + 1);
+        if low >= bad {
+            return Ok((Some(bad), good));
+        }
+        let mid = low + (bad - low) / 2;
+        if reproduces(mid)? {
+            bad = mid;
+        } else {
+            good = Some(mid);
+        }
+    }
+}
+
+/// Bisects objectives against a series of target binaries, oldest first.
+///
+/// For each tested binary, the executor is created by the `build` closure; an objective reproduces if
+/// any of the runs ends with one of the configured [`ExitKind`]s ([`ExitKind::Crash`] by default).
+#[derive(Debug)]
+pub struct BinaryBisector<F> {
+    binaries: Vec<PathBuf>,
+    build: F,
+    exit_kinds: Vec<ExitKind>,
+    runs: usize,
+}
+
+impl<F> BinaryBisector<F> {
+    /// Creates a new [`BinaryBisector`] for the given binaries, oldest first
+    pub fn new<P>(binaries: impl IntoIterator<Item = P>, build: F) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self {
+            binaries: binaries.into_iter().map(Into::into).collect(),
+            build,
+            exit_kinds: vec![ExitKind::Crash],
+            runs: 1,
+        }
+    }
+
+    /// Sets the [`ExitKind`]s counting as a reproduction, e.g. [`ExitKind::Timeout`] for hangs
+    #[must_use]
+    pub fn with_exit_kinds(mut self, exit_kinds: Vec<ExitKind>) -> Self {
+        self.exit_kinds = exit_kinds;
+        self
+    }
+
+    /// Sets how often an input is run on each binary, for flaky objectives
+    #[must_use]
+    pub fn with_runs(mut self, runs: usize) -> Self {
+        self.runs = runs.max(1);
+        self
+    }
+
+    /// The bisected binaries, oldest first
+    #[must_use]
+    pub fn binaries(&self) -> &[PathBuf] {
+        &self.binaries
+    }
+
+    /// Runs `input` on the binary at `idx`, returning if it reproduces
+    pub fn reproduces<E, EM, Z>(
+        &mut self,
+        idx: usize,
+        fuzzer: &mut Z,
+        state: &mut E::State,
+        mgr: &mut EM,
+        input: &E::Input,
+    ) -> Result<bool, Error>
+    where
+        F: FnMut(&Path) -> Result<E, Error>,
+        E: Executor<EM, Z> + HasObservers,
+        E::Observers: ObserversTuple<E::State>,
+        EM: UsesState<State = E::State>,
+        Z: UsesState<State = E::State>,
+    {
+        let mut executor = (self.build)(&self.binaries[idx])?;
+        for _ in 0..self.runs {
+            executor.observers_mut().pre_exec_all(state, input)?;
+            let exit_kind = executor.run_target(fuzzer, state, mgr, input)?;
+            executor
+                .observers_mut()
+                .post_exec_all(state, input, &exit_kind)?;
+            if self.exit_kinds.contains(&exit_kind) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Finds the first binary in which `input` reproduces
+    pub fn bisect<E, EM, Z>(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut E::State,
+        mgr: &mut EM,
+        input: &E::Input,
+    ) -> Result<BisectionMetadata, Error>
+    where
+        F: FnMut(&Path) -> Result<E, Error>,
+        E: Executor<EM, Z> + HasObservers,
+        E::Observers: ObserversTuple<E::State>,
+        EM: UsesState<State = E::State>,
+        Z: UsesState<State = E::State>,
+    {
+        let mut tested = Vec::new();
+        let (first_bad, last_good) = bisect_first(self.binaries.len(), |idx| {
+            let reproduces = self.reproduces(idx, fuzzer, state, mgr, input)?;
+            log::info!(
+                "Bisection: {:?} {}",
+                self.binaries[idx],
+                if reproduces {
+                    "reproduces"
+                } else {
+                    "does not reproduce"
+                }
+            );
+            tested.push((idx, reproduces));
+            Ok(reproduces)
+        })?;
+        Ok(BisectionMetadata {
+            binaries: self.binaries.clone(),
+            first_bad,
+            last_good,
+            tested,
+        })
+    }
+
+    /// Bisects the objective with the given id and attaches the [`BisectionMetadata`] to it
+    pub fn bisect_objective<E, EM, Z>(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut E::State,
+        mgr: &mut EM,
+        id: CorpusId,
+    ) -> Result<BisectionMetadata, Error>
+    where
+        F: FnMut(&Path) -> Result<E, Error>,
+        E: Executor<EM, Z> + HasObservers,
+        E::Observers: ObserversTuple<E::State>,
+        E::State: HasSolutions,
+        EM: UsesState<State = E::State>,
+        Z: UsesState<State = E::State>,
+    {
+        let input = state.solutions().cloned_input_for_id(id)?;
+        let metadata = self.bisect(fuzzer, state, mgr, &input)?;
+
+        // Replace the objective, so on-disk corpora store the new metadata as well
+        let mut testcase = state.solutions().get(id)?.borrow().clone();
+        testcase.set_input(input);
+        testcase.add_metadata(metadata.clone());
+        state.solutions_mut().replace(id, testcase)?;
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::bisect_first;
+
+    #[test]
+    fn test_bisect_first() {
+        for first_bad in 0..10 {
+            let mut tested = Vec::new();
+            let result = bisect_first(10, |idx| {
+                tested.push(idx);
+                Ok(idx >= first_bad)
+            })
+            .unwrap();
+            assert_eq!(result, (Some(first_bad), first_bad.checked_sub(1)));
+            assert!(tested.len() <= 5);
+        }
+        assert_eq!(bisect_first(10, |_| Ok(false)).unwrap(), (None, None));
+        assert_eq!(bisect_first(0, |_| Ok(true)).unwrap(), (None, None));
+    }
+}
@@ -0,0 +1,234 @@
+//! Exploitability classification of objectives, in the spirit of `!exploitable` and CERT triage.
+//!
+//! The classification is based on the crash signal, the faulting address, the kind of the faulting
+//! access, and whether the program counter is controlled, as reported by the sanitizer runtime.
+
+use alloc::string::String;
+use core::fmt::{self, Display, Formatter};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    executors::ExitKind,
+    observers::{SanitizerAccessKind, SanitizerReport},
+};
+
+/// Faults below this address are considered `NULL` pointer dereferences
+pub const NULL_PAGE_LIMIT: u64 = 0x1_0000;
+
+/// The exploitability of a crash
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Exploitability {
+    /// The crash is very likely exploitable, e.g. a controlled program counter or a corrupted heap
+    Exploitable,
+    /// The crash may be exploitable, e.g. an out-of-bounds read or a wild write
+    ProbablyExploitable,
+    /// Not enough information to classify the crash
+    Unknown,
+    /// The crash is very likely not exploitable, e.g. a `NULL` dereference, an assertion or a timeout
+    Benign,
+}
+
+impl Exploitability {
+    /// All classes, from most to least severe
+    pub const ALL: [Exploitability; 4] = [
+        Exploitability::Exploitable,
+        Exploitability::ProbablyExploitable,
+        Exploitability::Unknown,
+        Exploitability::Benign,
+    ];
+
+    /// A short, stable name of this class, used in stats
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Exploitability::Exploitable => "exploitable",
+            Exploitability::ProbablyExploitable => "probably_exploitable",
+            Exploitability::Unknown => "unknown",
+            Exploitability::Benign => "benign",
+        }
+    }
+}
+
+impl Display for Exploitability {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The exploitability of an objective, added to the testcase by the
+/// [`crate::stages::ExploitabilityStage`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExploitabilityMetadata {
+    /// The classification
+    pub exploitability: Exploitability,
+    /// A human-readable reason for the classification
+    pub reason: String,
+    /// The sanitizer report the classification is based on, if any
+    pub report: Option<SanitizerReport>,
+}
+
+libafl_bolts::impl_serdeany!(ExploitabilityMetadata);
+
+impl ExploitabilityMetadata {
+    /// Classifies a crash, see [`classify`]
+    #[must_use]
+    pub fn new(exit_kind: ExitKind, report: Option<SanitizerReport>) -> Self {
+        let (exploitability, reason) = classify(exit_kind, report.as_ref());
+        Self {
+            exploitability,
+            reason: reason.into(),
+            report,
+        }
+    }
+}
+
+/// Classifies a crash with the given [`ExitKind`] and (optional) [`SanitizerReport`].
+/// Returns the class and a short reason.
+#[must_use]
+pub fn classify(
+    exit_kind: ExitKind,
+    report: Option<&SanitizerReport>,
+) -> (Exploitability, &'static str) {
+    let Some(report) = report else {
+        return match exit_kind {
+            ExitKind::Timeout => (Exploitability::Benign, "timeout"),
+            ExitKind::Oom => (Exploitability::Benign, "out of memory"),
+            ExitKind::Ok => (Exploitability::Benign, "did not crash on re-execution"),
+            _ => (Exploitability::Unknown, "no sanitizer report"),
+        };
+    };
+
+    let is_write = report.access_kind == Some(SanitizerAccessKind::Write);
+    let near_null = report.address.is_some_and(|addr| addr < NULL_PAGE_LIMIT);
+    let pc_controlled = report.pc.is_some() && report.pc == report.address;
+
+    match report.bug_type.as_str() {
+        "double-free" | "bad-free" | "attempting-double-free" | "attempting-free" => {
+            (Exploitability::Exploitable, "heap corruption on free")
+        }
+        "heap-use-after-free"
+        | "use-after-poison"
+        | "stack-use-after-return"
+        | "stack-use-after-scope" => {
+            if is_write {
+                (Exploitability::Exploitable, "write after free")
+            } else {
+                (Exploitability::ProbablyExploitable, "use after free")
+            }
+        }
+        "heap-buffer-overflow"
+        | "stack-buffer-overflow"
+        | "stack-buffer-underflow"
+        | "dynamic-stack-buffer-overflow"
+        | "global-buffer-overflow"
+        | "container-overflow"
+        | "intra-object-overflow"
+        | "wild-addr-write"
+        | "wild-addr-read"
+        | "unknown-crash" => {
+            if is_write {
+                (Exploitability::Exploitable, "out-of-bounds write")
+            } else {
+                (Exploitability::ProbablyExploitable, "out-of-bounds read")
+            }
+        }
+        "SEGV" | "BUS" => {
+            if pc_controlled {
+                (Exploitability::Exploitable, "program counter is controlled")
+            } else if near_null {
+                (Exploitability::Benign, "NULL pointer dereference")
+            } else if is_write {
+                (Exploitability::ProbablyExploitable, "wild write")
+            } else {
+                (Exploitability::Unknown, "wild read")
+            }
+        }
+        "ILL" => {
+            if pc_controlled {
+                (Exploitability::Exploitable, "program counter is controlled")
+            } else {
+                (Exploitability::Unknown, "illegal instruction")
+            }
+        }
+        "stack-overflow" => (Exploitability::Benign, "stack exhaustion"),
+        "ABRT" => (Exploitability::Benign, "abort"),
+        "FPE" | "division-by-zero" => (Exploitability::Benign, "arithmetic error"),
+        "out-of-memory"
+        | "allocation-size-too-big"
+        | "requested-allocation-size-exceeds-maximum-supported-size"
+        | "calloc-overflow"
+        | "detected-memory-leaks" => (Exploitability::Benign, "resource exhaustion"),
+        "null-deref" => (Exploitability::Benign, "NULL pointer dereference"),
+        "integer-overflow" | "invalid-shift" | "misaligned-access" | "invalid-value"
+        | "undefined-behavior" => (Exploitability::Benign, "undefined behavior"),
+        "index-out-of-bounds" => (
+            Exploitability::ProbablyExploitable,
+            "out-of-bounds array index",
+        ),
+        _ => (Exploitability::Unknown, "unknown bug type"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec::Vec};
+
+    use super::{classify, Exploitability};
+    use crate::{
+        executors::ExitKind,
+        observers::{SanitizerAccessKind, SanitizerReport},
+    };
+
+    fn report(
+        bug_type: &str,
+        address: u64,
+        pc: u64,
+        access_kind: SanitizerAccessKind,
+    ) -> SanitizerReport {
+        SanitizerReport {
+            bug_type: bug_type.to_string(),
+            address: Some(address),
+            pc: Some(pc),
+            access_kind: Some(access_kind),
+            access_size: None,
+            frames: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        let write_overflow = report(
+            "heap-buffer-overflow",
+            0x6020_0000_0010,
+            0x4000,
+            SanitizerAccessKind::Write,
+        );
+        assert_eq!(
+            classify(ExitKind::Crash, Some(&write_overflow)).0,
+            Exploitability::Exploitable
+        );
+        let read_overflow = report(
+            "heap-buffer-overflow",
+            0x6020_0000_0010,
+            0x4000,
+            SanitizerAccessKind::Read,
+        );
+        assert_eq!(
+            classify(ExitKind::Crash, Some(&read_overflow)).0,
+            Exploitability::ProbablyExploitable
+        );
+        let null_deref = report("SEGV", 0x8, 0x4000, SanitizerAccessKind::Read);
+        assert_eq!(
+            classify(ExitKind::Crash, Some(&null_deref)).0,
+            Exploitability::Benign
+        );
+        let controlled_pc = report("SEGV", 0x4141_4141, 0x4141_4141, SanitizerAccessKind::Read);
+        assert_eq!(
+            classify(ExitKind::Crash, Some(&controlled_pc)).0,
+            Exploitability::Exploitable
+        );
+        assert_eq!(classify(ExitKind::Crash, None).0, Exploitability::Unknown);
+        assert_eq!(classify(ExitKind::Timeout, None).0, Exploitability::Benign);
+    }
+}
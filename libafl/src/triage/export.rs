@@ -0,0 +1,295 @@
+//! Export of objective metadata in the formats of existing crash triage pipelines.
+//!
+//! [`ClusterFuzzCrashInfo`] follows the crash fields of `ClusterFuzz`/`OSS-Fuzz` testcases,
+//! [`casr_report`] the report format of `CASR`. Both are built from the metadata other components
+//! attached to the objective: the [`SanitizerReport`], the [`CrashDedupKeyMetadata`],
+//! the [`ExploitabilityMetadata`] and the [`StdErrMetadata`].
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write as _;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::stdio::StdErrMetadata,
+    observers::SanitizerReport,
+    triage::{classify, CrashDedupKeyMetadata, Exploitability, ExploitabilityMetadata},
+    Error, HasMetadata,
+};
+
+/// The number of frames forming the crash state, as in `ClusterFuzz`
+pub const CRASH_STATE_FRAMES: usize = 3;
+
+/// The format of an exported objective
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CrashExportFormat {
+    /// `ClusterFuzz`/`OSS-Fuzz` crash info, see [`ClusterFuzzCrashInfo`]
+    #[default]
+    ClusterFuzz,
+    /// A `CASR` report, see [`casr_report`]
+    Casr,
+}
+
+impl CrashExportFormat {
+    /// The file extension of exported objectives
+    #[must_use]
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CrashExportFormat::ClusterFuzz => "crashinfo.json",
+            CrashExportFormat::Casr => "casrep",
+        }
+    }
+}
+
+/// The crash fields of a `ClusterFuzz`/`OSS-Fuzz` testcase
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ClusterFuzzCrashInfo {
+    /// The crash type, e.g. `Heap-buffer-overflow\nREAD 4`
+    pub crash_type: String,
+    /// The faulting address as hex, or empty if unknown
+    pub crash_address: String,
+    /// The top frames of the stack trace, separated by newlines
+    pub crash_state: String,
+    /// The full sanitizer output, or the parsed frames if the output was not captured
+    pub crash_stacktrace: String,
+    /// If the crash is a security issue
+    pub security_flag: bool,
+    /// The estimated severity of a security issue (`High`, `Medium` or `Low`)
+    pub security_severity: Option<String>,
+    /// The deduplication key of the objective as hex, if deduplicated
+    pub stack_hash: Option<String>,
+    /// The signal terminating the target, e.g. `SIGSEGV`, if known
+    pub signal: Option<String>,
+    /// The SHA-256 of the input, as hex
+    pub input_sha256: String,
+    /// The size of the input
+    pub input_size: usize,
+    /// The name of the testcase file, if any
+    pub testcase_name: Option<String>,
+}
+
+impl ClusterFuzzCrashInfo {
+    /// Collects the crash info of an objective with the given input bytes
+    #[must_use]
+    pub fn from_testcase<I>(testcase: &Testcase<I>, input_bytes: &[u8]) -> Self {
+        let report = testcase.metadata_map().get::<SanitizerReport>();
+        let exploitability = exploitability(testcase, report);
+
+        let (crash_type, crash_address, frames) = match report {
+            Some(report) => (
+                crash_type(report),
+                report
+                    .address
+                    .map_or_else(String::new, |address| format!("{address:#x}")),
+                report.frames.clone(),
+            ),
+            None => ("Crash".to_string(), String::new(), Vec::new()),
+        };
+        let crash_state = frames
+            .iter()
+            .take(CRASH_STATE_FRAMES)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let crash_stacktrace = match testcase.metadata_map().get::<StdErrMetadata>() {
+            Some(stderr) => stderr.stderr.clone(),
+            None => numbered_frames(&frames),
+        };
+
+        let security_severity = match exploitability {
+            Exploitability::Exploitable => Some("High"),
+            Exploitability::ProbablyExploitable => Some("Medium"),
+            Exploitability::Unknown | Exploitability::Benign => None,
+        };
+
+        Self {
+            crash_type,
+            crash_address,
+            crash_state,
+            crash_stacktrace,
+            security_flag: security_severity.is_some(),
+            security_severity: security_severity.map(ToString::to_string),
+            stack_hash: testcase
+                .metadata_map()
+                .get::<CrashDedupKeyMetadata>()
+                .map(|meta| format!("{:016x}", meta.key)),
+            signal: report.and_then(|report| signal(&report.bug_type).map(ToString::to_string)),
+            input_sha256: sha256_hex(input_bytes),
+            input_size: input_bytes.len(),
+            testcase_name: testcase.filename().clone(),
+        }
+    }
+}
+
+/// Builds a `CASR` report (`.casrep`) of an objective with the given input bytes
+#[must_use]
+pub fn casr_report<I>(testcase: &Testcase<I>, input_bytes: &[u8]) -> serde_json::Value {
+    let report = testcase.metadata_map().get::<SanitizerReport>();
+    let exploitability = exploitability(testcase, report);
+    let severity = match exploitability {
+        Exploitability::Exploitable => "EXPLOITABLE",
+        Exploitability::ProbablyExploitable => "PROBABLY_EXPLOITABLE",
+        Exploitability::Unknown | Exploitability::Benign => "NOT_EXPLOITABLE",
+    };
+    let reason = testcase
+        .metadata_map()
+        .get::<ExploitabilityMetadata>()
+        .map_or_else(
+            || classify(ExitKind::Crash, report).1.to_string(),
+            |meta| meta.reason.clone(),
+        );
+    let frames = report.map_or_else(Vec::new, |report| report.frames.clone());
+    let asan_report: Vec<&str> = testcase
+        .metadata_map()
+        .get::<StdErrMetadata>()
+        .map_or_else(Vec::new, |stderr| stderr.stderr.lines().collect());
+
+    json!({
+        "CrashSeverity": {
+            "Type": severity,
+            "ShortDescription": report.map_or("crash", |report| report.bug_type.as_str()),
+            "Description": report.map_or_else(String::new, ToString::to_string),
+            "Explanation": reason,
+        },
+        "Stacktrace": numbered_frames(&frames).lines().collect::<Vec<_>>(),
+        "CrashLine": frames.first().cloned().unwrap_or_default(),
+        "AsanReport": asan_report,
+        "InputSha256": sha256_hex(input_bytes),
+    })
+}
+
+/// Serializes the metadata of an objective with the given input bytes in the given format
+pub fn export_testcase<I>(
+    format: CrashExportFormat,
+    testcase: &Testcase<I>,
+    input_bytes: &[u8],
+) -> Result<String, Error> {
+    let json = match format {
+        CrashExportFormat::ClusterFuzz => serde_json::to_string_pretty(
+            &ClusterFuzzCrashInfo::from_testcase(testcase, input_bytes),
+        )?,
+        CrashExportFormat::Casr => {
+            serde_json::to_string_pretty(&casr_report(testcase, input_bytes))?
+        }
+    };
+    Ok(json)
+}
+
+/// The exploitability of an objective, as classified before or from its sanitizer report
+fn exploitability<I>(testcase: &Testcase<I>, report: Option<&SanitizerReport>) -> Exploitability {
+    testcase
+        .metadata_map()
+        .get::<ExploitabilityMetadata>()
+        .map_or_else(
+            || classify(ExitKind::Crash, report).0,
+            |meta| meta.exploitability,
+        )
+}
+
+/// The `ClusterFuzz` crash type of a sanitizer report, e.g. `Heap-buffer-overflow\nREAD 4`
+fn crash_type(report: &SanitizerReport) -> String {
+    let mut crash_type = match report.bug_type.as_str() {
+        "SEGV" | "BUS" => format!("{}-on-unknown-address", report.bug_type),
+        "ABRT" => "Abrt".to_string(),
+        "FPE" => "Floating-point-exception".to_string(),
+        "ILL" => "Ill".to_string(),
+        bug_type => {
+            let mut chars = bug_type.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_uppercase().chain(chars).collect::<String>()
+            })
+        }
+    };
+    if let Some(access_kind) = report.access_kind {
+        let _ = write!(crash_type, "\n{access_kind}");
+        if let Some(size) = report.access_size {
+            let _ = write!(crash_type, " {size}");
+        }
+    }
+    crash_type
+}
+
+/// The signal a sanitizer bug type corresponds to
+fn signal(bug_type: &str) -> Option<&'static str> {
+    match bug_type {
+        "SEGV" => Some("SIGSEGV"),
+        "BUS" => Some("SIGBUS"),
+        "ABRT" => Some("SIGABRT"),
+        "FPE" => Some("SIGFPE"),
+        "ILL" => Some("SIGILL"),
+        _ => None,
+    }
+}
+
+/// Formats frames as a symbolized sanitizer stack trace
+fn numbered_frames(frames: &[String]) -> String {
+    let mut out = String::new();
+    for (i, frame) in frames.iter().enumerate() {
+        let _ = writeln!(out, "    #{i} {frame}");
+    }
+    out
+}
+
+/// The SHA-256 of `bytes` as lowercase hex
+fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(64);
+    for byte in Sha256::digest(bytes) {
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use super::ClusterFuzzCrashInfo;
+    use crate::{
+        corpus::Testcase,
+        inputs::BytesInput,
+        observers::{SanitizerAccessKind, SanitizerReport},
+        triage::CrashDedupKeyMetadata,
+        HasMetadata,
+    };
+
+    #[test]
+    fn test_cluster_fuzz_crash_info() {
+        let mut testcase = Testcase::new(BytesInput::new(vec![]));
+        testcase.add_metadata(SanitizerReport {
+            bug_type: "heap-buffer-overflow".to_string(),
+            address: Some(0x6020_0000_0011),
+            pc: Some(0x4000),
+            access_kind: Some(SanitizerAccessKind::Read),
+            access_size: Some(4),
+            frames: vec![
+                "parse".to_string(),
+                "decode".to_string(),
+                "LLVMFuzzerTestOneInput".to_string(),
+                "main".to_string(),
+            ],
+        });
+        testcase.add_metadata(CrashDedupKeyMetadata {
+            strategy: "backtrace_hash".into(),
+            key: 0xabcd,
+        });
+
+        let info = ClusterFuzzCrashInfo::from_testcase(&testcase, b"abc");
+        assert_eq!(info.crash_type, "Heap-buffer-overflow\nREAD 4");
+        assert_eq!(info.crash_address, "0x602000000011");
+        assert_eq!(info.crash_state, "parse\ndecode\nLLVMFuzzerTestOneInput");
+        assert!(info.security_flag);
+        assert_eq!(info.stack_hash.as_deref(), Some("000000000000abcd"));
+        assert_eq!(
+            info.input_sha256,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+}
@@ -0,0 +1,452 @@
+//! Crash triage: deduplication of objectives with pluggable strategies.
+//!
+//! A [`CrashDeduper`] is a [`Feedback`] meant to be combined with an objective feedback, e.g.
+//! `feedback_and_fast!(CrashFeedback::new(), CrashDeduper::new(BacktraceHashStrategy::new(&bt_observer)))`.
+//! It computes a key for each crashing execution using a [`DedupStrategy`] and is only interesting
+//! for keys it has not seen before. The index of known keys is kept in the state,
+//! so restarted clients don't report known crashes again.
+//!
+//! A [`ReproRunner`] reproduces a single input with the executor and feedbacks of the fuzzer.
+
+#[cfg(feature = "std")]
+pub mod bisect;
+#[cfg(feature = "std")]
+pub use bisect::*;
+#[cfg(feature = "regex")]
+pub mod exploitability;
+#[cfg(feature = "regex")]
+pub use exploitability::*;
+#[cfg(feature = "crash_export")]
+pub mod export;
+#[cfg(feature = "crash_export")]
+pub use export::*;
+#[cfg(feature = "std")]
+pub mod repro;
+use alloc::borrow::Cow;
+use core::{fmt::Debug, marker::PhantomData};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    tuples::{Handle, Handled, MatchNameRef},
+    Named,
+};
+#[cfg(feature = "std")]
+pub use repro::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+#[cfg(feature = "regex")]
+use crate::observers::HasSanitizerReport;
+use crate::{
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    observers::{MapObserver, ObserverWithHashField, ObserversTuple},
+    state::State,
+    Error, HasMetadata, HasNamedMetadata,
+};
+
+/// A strategy computing the deduplication key of a crashing execution
+pub trait DedupStrategy<S>: Named
+where
+    S: UsesInput,
+{
+    /// The deduplication key of the last execution, or `None` if no key could be computed
+    fn dedup_key<OT>(
+        &mut self,
+        state: &mut S,
+        input: &S::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<Option<u64>, Error>
+    where
+        OT: ObserversTuple<S>;
+}
+
+/// Deduplicates by the hash of the backtrace, e.g. of a [`crate::observers::BacktraceObserver`]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BacktraceHashStrategy<O> {
+    o_ref: Handle<O>,
+}
+
+impl<O> BacktraceHashStrategy<O>
+where
+    O: Named,
+{
+    /// Creates a new [`BacktraceHashStrategy`]
+    #[must_use]
+    pub fn new(observer: &O) -> Self {
+        Self {
+            o_ref: observer.handle(),
+        }
+    }
+}
+
+impl<O> Named for BacktraceHashStrategy<O> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("backtrace_hash");
+        &NAME
+    }
+}
+
+impl<O, S> DedupStrategy<S> for BacktraceHashStrategy<O>
+where
+    O: ObserverWithHashField,
+    S: UsesInput,
+{
+    fn dedup_key<OT>(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<Option<u64>, Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers.get(&self.o_ref).ok_or_else(|| {
+            Error::key_not_found(format!("Observer {} not found", self.o_ref.name()))
+        })?;
+        Ok(observer.hash())
+    }
+}
+
+/// Deduplicates by the program counter of the faulting instruction, as reported by the sanitizer
+#[cfg(feature = "regex")]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FaultingPcStrategy<O> {
+    o_ref: Handle<O>,
+}
+
+#[cfg(feature = "regex")]
+impl<O> FaultingPcStrategy<O>
+where
+    O: Named,
+{
+    /// Creates a new [`FaultingPcStrategy`]
+    #[must_use]
+    pub fn new(observer: &O) -> Self {
+        Self {
+            o_ref: observer.handle(),
+        }
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<O> Named for FaultingPcStrategy<O> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("faulting_pc");
+        &NAME
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<O, S> DedupStrategy<S> for FaultingPcStrategy<O>
+where
+    O: HasSanitizerReport,
+    S: UsesInput,
+{
+    fn dedup_key<OT>(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<Option<u64>, Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers.get(&self.o_ref).ok_or_else(|| {
+            Error::key_not_found(format!("Observer {} not found", self.o_ref.name()))
+        })?;
+        Ok(observer.sanitizer_report().and_then(|report| report.pc))
+    }
+}
+
+/// Deduplicates by the sanitizer bug type and the top stack frame,
+/// e.g. `heap-buffer-overflow` in `parse_header`
+#[cfg(feature = "regex")]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BugTypeFrameStrategy<O> {
+    o_ref: Handle<O>,
+}
+
+#[cfg(feature = "regex")]
+impl<O> BugTypeFrameStrategy<O>
+where
+    O: Named,
+{
+    /// Creates a new [`BugTypeFrameStrategy`]
+    #[must_use]
+    pub fn new(observer: &O) -> Self {
+        Self {
+            o_ref: observer.handle(),
+        }
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<O> Named for BugTypeFrameStrategy<O> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("bug_type_frame");
+        &NAME
+    }
+}
+
+#[cfg(feature = "regex")]
+impl<O, S> DedupStrategy<S> for BugTypeFrameStrategy<O>
+where
+    O: HasSanitizerReport,
+    S: UsesInput,
+{
+    fn dedup_key<OT>(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<Option<u64>, Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers.get(&self.o_ref).ok_or_else(|| {
+            Error::key_not_found(format!("Observer {} not found", self.o_ref.name()))
+        })?;
+        Ok(observer.sanitizer_report().map(|report| {
+            let frame = report
+                .frames
+                .first()
+                .map_or("", alloc::string::String::as_str);
+            libafl_bolts::hash_std(format!("{}|{frame}", report.bug_type).as_bytes())
+        }))
+    }
+}
+
+/// Deduplicates by the coverage map of the crashing run
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CoverageStrategy<O> {
+    o_ref: Handle<O>,
+}
+
+impl<O> CoverageStrategy<O>
+where
+    O: Named,
+{
+    /// Creates a new [`CoverageStrategy`]
+    #[must_use]
+    pub fn new(observer: &O) -> Self {
+        Self {
+            o_ref: observer.handle(),
+        }
+    }
+}
+
+impl<O> Named for CoverageStrategy<O> {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("coverage");
+        &NAME
+    }
+}
+
+impl<O, S> DedupStrategy<S> for CoverageStrategy<O>
+where
+    O: MapObserver,
+    S: UsesInput,
+{
+    fn dedup_key<OT>(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<Option<u64>, Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers.get(&self.o_ref).ok_or_else(|| {
+            Error::key_not_found(format!("Observer {} not found", self.o_ref.name()))
+        })?;
+        Ok(Some(observer.hash_simple()))
+    }
+}
+
+/// The index of known crash keys of a [`CrashDeduper`], stored in the state
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[allow(clippy::unsafe_derive_deserialize)]
+pub struct CrashDedupMetadata {
+    /// The number of hits for each known key
+    pub keys: HashMap<u64, u64>,
+    /// The number of crashes discarded as duplicates
+    pub duplicates: u64,
+}
+
+libafl_bolts::impl_serdeany!(CrashDedupMetadata);
+
+impl CrashDedupMetadata {
+    /// Records a hit of the given key, returning `true` if the key is new
+    pub fn record(&mut self, key: u64) -> bool {
+        let hits = self.keys.entry(key).or_insert(0);
+        *hits += 1;
+        if *hits == 1 {
+            true
+        } else {
+            self.duplicates += 1;
+            false
+        }
+    }
+
+    /// The number of unique keys
+    #[must_use]
+    pub fn unique(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+/// The deduplication key of an objective, added by the [`CrashDeduper`]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CrashDedupKeyMetadata {
+    /// The name of the [`DedupStrategy`]
+    pub strategy: Cow<'static, str>,
+    /// The deduplication key
+    pub key: u64,
+}
+
+libafl_bolts::impl_serdeany!(CrashDedupKeyMetadata);
+
+/// A [`Feedback`] that is only interesting for crashes with a new deduplication key,
+/// as computed by its [`DedupStrategy`]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CrashDeduper<D, S> {
+    name: Cow<'static, str>,
+    strategy: D,
+    unknown_is_interesting: bool,
+    last_key: Option<u64>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    // The previous run's result of `Self::is_interesting`
+    last_result: Option<bool>,
+    phantom: PhantomData<S>,
+}
+
+impl<D, S> CrashDeduper<D, S>
+where
+    D: Named,
+{
+    /// Creates a new [`CrashDeduper`] with the given [`DedupStrategy`]
+    #[must_use]
+    pub fn new(strategy: D) -> Self {
+        Self {
+            name: Cow::from(format!("CrashDeduper_{}", strategy.name())),
+            strategy,
+            unknown_is_interesting: true,
+            last_key: None,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets whether crashes for which the strategy cannot compute a key are interesting.
+    /// Defaults to `true`.
+    #[must_use]
+    pub fn with_unknown_is_interesting(mut self, unknown_is_interesting: bool) -> Self {
+        self.unknown_is_interesting = unknown_is_interesting;
+        self
+    }
+
+    /// The [`DedupStrategy`] of this deduper
+    pub fn strategy(&self) -> &D {
+        &self.strategy
+    }
+}
+
+impl<D, S> Named for CrashDeduper<D, S> {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<D, S> Feedback<S> for CrashDeduper<D, S>
+where
+    D: DedupStrategy<S>,
+    S: State + HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.named_metadata_or_insert_with(&self.name, CrashDedupMetadata::default);
+        Ok(())
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        input: &S::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.last_key = self
+            .strategy
+            .dedup_key(state, input, observers, exit_kind)?;
+        let res = match self.last_key {
+            Some(key) => state
+                .named_metadata_or_insert_with(&self.name, CrashDedupMetadata::default)
+                .record(key),
+            None => self.unknown_is_interesting,
+        };
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    fn append_metadata<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        if let Some(key) = self.last_key.take() {
+            testcase.add_metadata(CrashDedupKeyMetadata {
+                strategy: self.strategy.name().clone(),
+                key,
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CrashDedupMetadata;
+
+    #[test]
+    fn test_crash_dedup_metadata() {
+        let mut meta = CrashDedupMetadata::default();
+        assert!(meta.record(1));
+        assert!(!meta.record(1));
+        assert!(meta.record(2));
+        assert!(!meta.record(1));
+        assert_eq!(meta.unique(), 2);
+        assert_eq!(meta.duplicates, 2);
+        assert_eq!(meta.keys[&1], 3);
+    }
+}
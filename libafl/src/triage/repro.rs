@@ -0,0 +1,304 @@
+//! Reproduction of a single input, e.g. a crash file, with the composition of the fuzzer.
+//!
+//! The [`ReproRunner`] runs the input once with the executor, observers and feedbacks the fuzzer
+//! uses, and reports how the run ended and which feedbacks found it interesting, see
+//! [`ReproReport`]. If [`REPRO_INPUT_ENV`] is set, the `Launcher` bypasses the broker and the
+//! clients, and calls its `repro_client` with the input in the current process instead:
+//!
+//! ```rust,ignore
+//! Launcher::builder()
+//!     // ...
+//!     .repro_client(Box::new(|path: &Path, _core_id| {
+//!         enable_sanitizer_reports();
+//!         // build the state, fuzzer, executor and feedbacks like `run_client` does
+//!         let mut runner = ReproRunner::new(executor, feedback, objective);
+//!         println!("{}", runner.run_file(&mut fuzzer, &mut state, &mut mgr, path)?);
+//!         Ok(())
+//!     }))
+//!     .build()
+//!     .launch()?;
+//! ```
+
+#[cfg(feature = "regex")]
+use alloc::boxed::Box;
+#[cfg(feature = "track_hit_feedbacks")]
+use alloc::{borrow::Cow, vec::Vec};
+use core::{
+    fmt::{self, Debug, Display, Formatter},
+    time::Duration,
+};
+use std::{env, path::PathBuf, time::Instant};
+
+#[cfg(feature = "regex")]
+use libafl_bolts::tuples::{Handle, MatchNameRef};
+
+#[cfg(feature = "regex")]
+use crate::observers::{HasSanitizerReport, SanitizerReport};
+use crate::{
+    events::EventFirer,
+    executors::{Executor, ExitKind, HasObservers},
+    feedbacks::Feedback,
+    inputs::Input,
+    observers::ObserversTuple,
+    state::{State, UsesState},
+    Error,
+};
+
+/// The environment variable with the path of an input to reproduce, see [`repro_input_from_env`]
+pub const REPRO_INPUT_ENV: &str = "LIBAFL_REPRO_INPUT";
+
+/// The sanitizer options for a reproduction run, printing full reports
+const REPRO_SANITIZER_OPTIONS: &str = "symbolize=1:print_stacktrace=1:abort_on_error=1";
+
+/// The input to reproduce, if [`REPRO_INPUT_ENV`] is set
+#[must_use]
+pub fn repro_input_from_env() -> Option<PathBuf> {
+    env::var_os(REPRO_INPUT_ENV).map(PathBuf::from)
+}
+
+/// Enables full reports of the `AddressSanitizer`, `UndefinedBehaviorSanitizer` and
+/// `MemorySanitizer`, unless their options are set already.
+///
+/// Call it before the executor starts the target, the sanitizer runtimes read their options at
+/// startup.
+pub fn enable_sanitizer_reports() {
+    for var in ["ASAN_OPTIONS", "UBSAN_OPTIONS", "MSAN_OPTIONS"] {
+        if env::var_os(var).is_none() {
+            env::set_var(var, REPRO_SANITIZER_OPTIONS);
+        }
+    }
+}
+
+/// The outcome of a reproduction run, see [`ReproRunner::run`]
+#[derive(Debug, Clone)]
+pub struct ReproReport {
+    /// How the run ended
+    pub exit_kind: ExitKind,
+    /// How long the run took
+    pub duration: Duration,
+    /// If the feedback found the run interesting
+    pub interesting: bool,
+    /// If the objective found the run interesting
+    pub objective: bool,
+    /// The names of the feedbacks that found the run interesting
+    #[cfg(feature = "track_hit_feedbacks")]
+    pub hit_feedbacks: Vec<Cow<'static, str>>,
+    /// The names of the objective feedbacks that found the run interesting
+    #[cfg(feature = "track_hit_feedbacks")]
+    pub hit_objectives: Vec<Cow<'static, str>>,
+    /// The sanitizer report of the run, if any, see [`ReproRunner::with_sanitizer_report`]
+    #[cfg(feature = "regex")]
+    pub sanitizer_report: Option<SanitizerReport>,
+}
+
+impl Display for ReproReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "exit kind: {:?} after {:?}",
+            self.exit_kind, self.duration
+        )?;
+        writeln!(f, "interesting: {}", self.interesting)?;
+        #[cfg(feature = "track_hit_feedbacks")]
+        writeln!(f, "  hit feedbacks: {:?}", self.hit_feedbacks)?;
+        writeln!(f, "objective: {}", self.objective)?;
+        #[cfg(feature = "track_hit_feedbacks")]
+        writeln!(f, "  hit objectives: {:?}", self.hit_objectives)?;
+        #[cfg(feature = "regex")]
+        if let Some(report) = &self.sanitizer_report {
+            writeln!(f, "sanitizer report: {report}")?;
+            for frame in &report.frames {
+                writeln!(f, "  {frame}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extracts the sanitizer report from the observers of the executor
+#[cfg(feature = "regex")]
+type SanitizerReportFn<OT> = Box<dyn Fn(&OT) -> Option<SanitizerReport>>;
+
+/// Runs a single input with the executor, feedback and objective of a fuzzer, see the
+/// [module docs](self)
+pub struct ReproRunner<E, F, OF>
+where
+    E: HasObservers,
+{
+    executor: E,
+    feedback: F,
+    objective: OF,
+    #[cfg(feature = "regex")]
+    sanitizer_report: Option<SanitizerReportFn<E::Observers>>,
+}
+
+impl<E, F, OF> Debug for ReproRunner<E, F, OF>
+where
+    E: HasObservers + Debug,
+    F: Debug,
+    OF: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReproRunner")
+            .field("executor", &self.executor)
+            .field("feedback", &self.feedback)
+            .field("objective", &self.objective)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, F, OF> ReproRunner<E, F, OF>
+where
+    E: HasObservers,
+    E::Observers: ObserversTuple<E::State>,
+    E::State: State,
+    F: Feedback<E::State>,
+    OF: Feedback<E::State>,
+{
+    /// Creates a new [`ReproRunner`] with the executor, feedback and objective of a fuzzer
+    pub fn new(executor: E, feedback: F, objective: OF) -> Self {
+        Self {
+            executor,
+            feedback,
+            objective,
+            #[cfg(feature = "regex")]
+            sanitizer_report: None,
+        }
+    }
+
+    /// Adds the sanitizer report of this observer, e.g. a
+    /// [`crate::observers::SanitizerReportObserver`], to the [`ReproReport`]
+    #[cfg(feature = "regex")]
+    #[must_use]
+    pub fn with_sanitizer_report<O>(mut self, observer: &Handle<O>) -> Self
+    where
+        O: HasSanitizerReport + 'static,
+    {
+        let observer = observer.clone();
+        self.sanitizer_report = Some(Box::new(move |observers: &E::Observers| {
+            observers
+                .get(&observer)
+                .and_then(HasSanitizerReport::sanitizer_report)
+        }));
+        self
+    }
+
+    /// The executor
+    pub fn executor(&self) -> &E {
+        &self.executor
+    }
+
+    /// The executor (mutable)
+    pub fn executor_mut(&mut self) -> &mut E {
+        &mut self.executor
+    }
+
+    /// Runs the input once, and evaluates the feedback and the objective on the run
+    pub fn run<EM, Z>(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut E::State,
+        mgr: &mut EM,
+        input: &E::Input,
+    ) -> Result<ReproReport, Error>
+    where
+        E: Executor<EM, Z>,
+        EM: EventFirer<State = E::State>,
+        Z: UsesState<State = E::State>,
+    {
+        self.executor.observers_mut().pre_exec_all(state, input)?;
+        let start = Instant::now();
+        let exit_kind = self.executor.run_target(fuzzer, state, mgr, input)?;
+        let duration = start.elapsed();
+        self.executor
+            .observers_mut()
+            .post_exec_all(state, input, &exit_kind)?;
+
+        let observers = self.executor.observers();
+        let interesting =
+            self.feedback
+                .is_interesting(state, mgr, input, &*observers, &exit_kind)?;
+        let objective =
+            self.objective
+                .is_interesting(state, mgr, input, &*observers, &exit_kind)?;
+        // Nothing is added to a corpus
+        self.feedback.discard_metadata(state, input)?;
+        self.objective.discard_metadata(state, input)?;
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        let (hit_feedbacks, hit_objectives) = {
+            let (mut hit_feedbacks, mut hit_objectives) = (Vec::new(), Vec::new());
+            self.feedback.append_hit_feedbacks(&mut hit_feedbacks)?;
+            self.objective.append_hit_feedbacks(&mut hit_objectives)?;
+            (hit_feedbacks, hit_objectives)
+        };
+
+        Ok(ReproReport {
+            exit_kind,
+            duration,
+            interesting,
+            objective,
+            #[cfg(feature = "track_hit_feedbacks")]
+            hit_feedbacks,
+            #[cfg(feature = "track_hit_feedbacks")]
+            hit_objectives,
+            #[cfg(feature = "regex")]
+            sanitizer_report: self
+                .sanitizer_report
+                .as_ref()
+                .and_then(|report| report(&*observers)),
+        })
+    }
+
+    /// Loads the input from the file and runs it, see [`Self::run`]
+    pub fn run_file<EM, P, Z>(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut E::State,
+        mgr: &mut EM,
+        path: P,
+    ) -> Result<ReproReport, Error>
+    where
+        E: Executor<EM, Z>,
+        EM: EventFirer<State = E::State>,
+        P: AsRef<std::path::Path>,
+        Z: UsesState<State = E::State>,
+    {
+        let input = E::Input::from_file(path)?;
+        self.run(fuzzer, state, mgr, &input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReproRunner;
+    use crate::{
+        events::NopEventManager,
+        executors::{test::NopExecutor, ExitKind, WithObservers},
+        feedbacks::{ConstFeedback, CrashFeedback},
+        fuzzer::test::NopFuzzer,
+        inputs::BytesInput,
+        state::NopState,
+    };
+
+    #[test]
+    fn test_repro_runner() {
+        let mut runner = ReproRunner::new(
+            WithObservers::new(NopExecutor::new(), ()),
+            ConstFeedback::new(true),
+            CrashFeedback::new(),
+        );
+        let report = runner
+            .run(
+                &mut NopFuzzer::new(),
+                &mut NopState::new(),
+                &mut NopEventManager::new(),
+                &BytesInput::new(vec![1]),
+            )
+            .unwrap();
+        assert_eq!(report.exit_kind, ExitKind::Ok);
+        assert!(report.interesting);
+        assert!(!report.objective);
+        assert!(format!("{report}").contains("interesting: true"));
+    }
+}
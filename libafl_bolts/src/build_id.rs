@@ -52,6 +52,37 @@ pub fn get() -> Uuid {
     *BUILD_ID.get_or_init(calculate)
 }
 
+/// Finds the `NT_GNU_BUILD_ID` note in the bytes of a little-endian ELF file, e.g. a shared
+/// library, and returns the build-id.
+///
+/// Instead of parsing the section headers, this scans for the note header, which is good enough
+/// for build-ids of 8 to 64 bytes.
+#[must_use]
+pub fn gnu_build_id(bytes: &[u8]) -> Option<&[u8]> {
+    // namesz == 4, descsz, type == NT_GNU_BUILD_ID (3), name == "GNU\0"
+    const NOTE_TAIL: &[u8] = b"\x03\x00\x00\x00GNU\x00";
+    if !bytes.starts_with(b"\x7fELF") {
+        return None;
+    }
+    let mut start = 0;
+    while let Some(pos) = bytes[start..]
+        .windows(NOTE_TAIL.len())
+        .position(|window| window == NOTE_TAIL)
+    {
+        let tail = start + pos;
+        start = tail + 1;
+        if tail < 8 || bytes[tail - 8..tail - 4] != [4, 0, 0, 0] {
+            continue;
+        }
+        let desc_len = u32::from_le_bytes(bytes[tail - 4..tail].try_into().unwrap()) as usize;
+        let desc = tail + NOTE_TAIL.len();
+        if (8..=64).contains(&desc_len) && desc + desc_len <= bytes.len() {
+            return Some(&bytes[desc..desc + desc_len]);
+        }
+    }
+    None
+}
+
 fn from_exe<H: Hasher>(mut hasher: H) -> Result<H, ()> {
     #[cfg(not(target_arch = "wasm32"))]
     {
@@ -112,3 +143,34 @@ impl<T: Hasher> io::Write for HashWriter<T> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::gnu_build_id;
+
+    /// A minimal little-endian ELF blob with a GNU build-id note
+    fn elf_with_build_id(build_id: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x7fELF".to_vec();
+        bytes.resize(64, 0);
+        bytes.extend_from_slice(&4_u32.to_le_bytes());
+        bytes.extend_from_slice(&u32::try_from(build_id.len()).unwrap().to_le_bytes());
+        bytes.extend_from_slice(b"\x03\x00\x00\x00GNU\x00");
+        bytes.extend_from_slice(build_id);
+        bytes
+    }
+
+    #[test]
+    fn test_gnu_build_id() {
+        let build_id = [0xab; 20];
+        assert_eq!(
+            gnu_build_id(&elf_with_build_id(&build_id)),
+            Some(&build_id[..])
+        );
+        // Too short for a build-id
+        assert_eq!(gnu_build_id(&elf_with_build_id(&[1, 2])), None);
+        // Not an ELF file
+        assert_eq!(gnu_build_id(&elf_with_build_id(&build_id)[4..]), None);
+    }
+}
@@ -0,0 +1,140 @@
+//! Injectable time sources.
+//!
+//! Components measuring time, such as the `TimeObserver` or the batched timeouts of the in-process
+//! executors, take a [`Clock`]. By default, this is the monotonic [`SystemClock`];
+//! tests and simulated campaigns can use a [`MockClock`] instead, which only moves when told to.
+//! This makes time-dependent logic deterministic and lets simulations run faster than real time.
+
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+#[cfg(target_has_atomic = "64")]
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::{fmt::Debug, time::Duration};
+#[cfg(feature = "std")]
+use std::{sync::OnceLock, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "std"))]
+use crate::current_time;
+
+/// A source of the current time.
+///
+/// Clocks are read from signal handlers (e.g. for timeouts), so `now` must not block or allocate.
+pub trait Clock: Debug + Sync {
+    /// The current time, as the duration since an arbitrary, fixed epoch
+    fn now(&self) -> Duration;
+}
+
+impl<C> Clock for &C
+where
+    C: Clock + ?Sized,
+{
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<C> Clock for Arc<C>
+where
+    C: Clock + Send + ?Sized,
+{
+    fn now(&self) -> Duration {
+        (**self).now()
+    }
+}
+
+/// The epoch of the [`SystemClock`], set on its first use
+#[cfg(feature = "std")]
+static SYSTEM_CLOCK_EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// The real time, measured on the monotonic [`Instant`] since the first use of the clock, so
+/// intervals don't jump with the wall clock. Without `std`, this falls back to
+/// [`crate::current_time`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> Duration {
+        #[cfg(feature = "std")]
+        {
+            SYSTEM_CLOCK_EPOCH.get_or_init(Instant::now).elapsed()
+        }
+        #[cfg(not(feature = "std"))]
+        {
+            current_time()
+        }
+    }
+}
+
+/// A [`SystemClock`] usable where a `&'static dyn Clock` is needed
+pub static SYSTEM_CLOCK: SystemClock = SystemClock;
+
+/// A clock that only moves when told to, for deterministic tests and simulations.
+///
+/// The clock is thread- and signal-safe. Share it by reference (e.g. as a `static`) or in an `Arc`.
+#[cfg(target_has_atomic = "64")]
+#[derive(Debug, Default)]
+pub struct MockClock {
+    nanos: AtomicU64,
+}
+
+#[cfg(target_has_atomic = "64")]
+impl MockClock {
+    /// Creates a new [`MockClock`], starting at `start`
+    #[must_use]
+    pub const fn new(start: Duration) -> Self {
+        Self {
+            nanos: AtomicU64::new(start.as_secs() * 1_000_000_000 + start.subsec_nanos() as u64),
+        }
+    }
+
+    /// Sets the current time
+    pub fn set(&self, now: Duration) {
+        self.nanos.store(now.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    /// Moves the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        self.nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::SeqCst);
+    }
+}
+
+#[cfg(target_has_atomic = "64")]
+impl Clock for MockClock {
+    #[inline]
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::SeqCst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{Clock, MockClock, SystemClock};
+
+    static CLOCK: MockClock = MockClock::new(Duration::from_secs(1));
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = &CLOCK;
+        assert_eq!(clock.now(), Duration::from_secs(1));
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(clock.now(), Duration::from_millis(2500));
+        clock.set(Duration::ZERO);
+        assert_eq!(CLOCK.now(), Duration::ZERO);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_system_clock_monotonic() {
+        let clock = SystemClock;
+        let start = clock.now();
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(clock.now() >= start + Duration::from_millis(1));
+    }
+}
@@ -110,6 +110,7 @@ pub mod build_id;
     feature = "std"
 ))]
 pub mod cli;
+pub mod clock;
 #[cfg(feature = "gzip")]
 pub mod compress;
 #[cfg(feature = "std")]
@@ -160,10 +161,10 @@ pub mod bolts_prelude {
     pub use super::{cpu::*, os::*};
 }
 
-#[cfg(all(unix, feature = "std"))]
+#[cfg(feature = "alloc")]
 use alloc::boxed::Box;
 #[cfg(feature = "alloc")]
-use alloc::{borrow::Cow, vec::Vec};
+use alloc::{borrow::Cow, string::ToString, vec::Vec};
 #[cfg(all(not(feature = "xxh3"), feature = "alloc"))]
 use core::hash::BuildHasher;
 #[cfg(any(feature = "xxh3", feature = "alloc"))]
@@ -316,6 +317,132 @@ pub enum Error {
     Unknown(String, ErrorBacktrace),
     /// Error with the corpora
     InvalidCorpus(String, ErrorBacktrace),
+    /// The executor failed to run the target, but may succeed if retried or after restarting
+    /// the target, e.g. because a fork server died
+    ExecutionFailed(String, ErrorBacktrace),
+    /// An error with a frame of context, identifying where it happened, see [`Error::context`]
+    #[cfg(feature = "alloc")]
+    Context(Box<Error>, ErrorFrame),
+}
+
+/// A frame of context attached to an [`Error`] on its way up, e.g. by a stage or a client.
+/// Fields not known at the place the frame is added stay `None`.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorFrame {
+    /// The component the error passed, e.g. the name of a stage
+    pub component: String,
+    /// The client the error happened in
+    pub client_id: Option<ClientId>,
+    /// The core the client is bound to
+    pub core_id: Option<usize>,
+    /// The id of the testcase processed when the error happened
+    pub input_id: Option<usize>,
+}
+
+#[cfg(feature = "alloc")]
+impl ErrorFrame {
+    /// Creates a new [`ErrorFrame`] for the component
+    #[must_use]
+    pub fn new<S>(component: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            component: component.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Sets the client the error happened in
+    #[must_use]
+    pub fn with_client_id(mut self, client_id: ClientId) -> Self {
+        self.client_id = Some(client_id);
+        self
+    }
+
+    /// Sets the core the client is bound to
+    #[must_use]
+    pub fn with_core_id(mut self, core_id: usize) -> Self {
+        self.core_id = Some(core_id);
+        self
+    }
+
+    /// Sets the id of the testcase processed when the error happened
+    #[must_use]
+    pub fn with_input_id(mut self, input_id: usize) -> Self {
+        self.input_id = Some(input_id);
+        self
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Display for ErrorFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "in {}", self.component)?;
+        let mut details = Vec::new();
+        if let Some(client_id) = self.client_id {
+            details.push(format!("client {}", client_id.0));
+        }
+        if let Some(core_id) = self.core_id {
+            details.push(format!("core {core_id}"));
+        }
+        if let Some(input_id) = self.input_id {
+            details.push(format!("testcase {input_id}"));
+        }
+        if !details.is_empty() {
+            write!(f, " ({})", details.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A serializable report of an [`Error`] and its context frames, innermost first,
+/// e.g. to send an error to the broker
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorReport {
+    /// The message of the root error
+    pub message: String,
+    /// The context frames, innermost first
+    pub frames: Vec<ErrorFrame>,
+}
+
+#[cfg(feature = "alloc")]
+impl Display for ErrorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.frames {
+            write!(f, "\n  {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds [`ErrorFrame`]s to the error of a [`Result`]
+#[cfg(feature = "alloc")]
+pub trait ErrorContext<T> {
+    /// Adds the frame to the error, if any, see [`Error::context`]
+    fn context(self, frame: ErrorFrame) -> Result<T, Error>;
+
+    /// Adds the frame returned by the closure to the error, if any, see [`Error::context`]
+    fn with_context<F>(self, frame: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> ErrorFrame;
+}
+
+#[cfg(feature = "alloc")]
+impl<T> ErrorContext<T> for Result<T, Error> {
+    fn context(self, frame: ErrorFrame) -> Result<T, Error> {
+        self.map_err(|err| err.context(frame))
+    }
+
+    fn with_context<F>(self, frame: F) -> Result<T, Error>
+    where
+        F: FnOnce() -> ErrorFrame,
+    {
+        self.map_err(|err| err.context(frame()))
+    }
 }
 
 impl Error {
@@ -440,6 +567,90 @@ impl Error {
     {
         Error::InvalidCorpus(arg.into(), ErrorBacktrace::new())
     }
+    /// The executor failed to run the target, see [`Error::is_recoverable`]
+    #[must_use]
+    pub fn execution_failed<S>(arg: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Error::ExecutionFailed(arg.into(), ErrorBacktrace::new())
+    }
+
+    /// Adds a frame of context to this error.
+    /// [`Error::ShuttingDown`] is returned as-is, as it is not really an error.
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn context(self, frame: ErrorFrame) -> Self {
+        match self {
+            Error::ShuttingDown => self,
+            err => Error::Context(Box::new(err), frame),
+        }
+    }
+
+    /// The error without its context frames
+    #[must_use]
+    pub fn root(&self) -> &Self {
+        match self {
+            #[cfg(feature = "alloc")]
+            Error::Context(err, _) => err.root(),
+            err => err,
+        }
+    }
+
+    /// The context frames of this error, innermost first
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn frames(&self) -> Vec<&ErrorFrame> {
+        let mut frames = Vec::new();
+        let mut err = self;
+        while let Error::Context(inner, frame) = err {
+            frames.push(frame);
+            err = inner;
+        }
+        frames.reverse();
+        frames
+    }
+
+    /// A serializable report of this error and its context frames
+    #[cfg(feature = "alloc")]
+    #[must_use]
+    pub fn report(&self) -> ErrorReport {
+        ErrorReport {
+            message: self.root().to_string(),
+            frames: self.frames().into_iter().cloned().collect(),
+        }
+    }
+
+    /// Returns `true` if this error is transient and the failed operation may succeed if retried,
+    /// such as an [`Error::ExecutionFailed`], a broken pipe to a fork server or a temporary
+    /// exhaustion of file descriptors.
+    #[must_use]
+    pub fn is_recoverable(&self) -> bool {
+        match self.root() {
+            Error::ExecutionFailed(_, _) => true,
+            #[cfg(feature = "std")]
+            Error::OsError(err, _, _) => {
+                #[cfg(unix)]
+                if matches!(
+                    err.raw_os_error(),
+                    Some(libc::EMFILE | libc::ENFILE | libc::EAGAIN | libc::EINTR | libc::ENOMEM)
+                ) {
+                    return true;
+                }
+                matches!(
+                    err.kind(),
+                    io::ErrorKind::BrokenPipe
+                        | io::ErrorKind::ConnectionReset
+                        | io::ErrorKind::ConnectionAborted
+                        | io::ErrorKind::UnexpectedEof
+                        | io::ErrorKind::Interrupted
+                        | io::ErrorKind::WouldBlock
+                        | io::ErrorKind::TimedOut
+                )
+            }
+            _ => false,
+        }
+    }
 }
 
 impl Display for Error {
@@ -504,6 +715,12 @@ impl Display for Error {
                 write!(f, "Invalid corpus: {0}", &s)?;
                 display_error_backtrace(f, b)
             }
+            Self::ExecutionFailed(s, b) => {
+                write!(f, "Execution failed: {0}", &s)?;
+                display_error_backtrace(f, b)
+            }
+            #[cfg(feature = "alloc")]
+            Self::Context(err, frame) => write!(f, "{err}\n  {frame}"),
         }
     }
 }
@@ -1215,4 +1432,36 @@ mod tests {
         log::set_max_level(log::LevelFilter::Debug);
         log::info!("Test");
     }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn test_error_context() {
+        use alloc::string::ToString;
+
+        use crate::{ClientId, Error, ErrorContext, ErrorFrame};
+
+        let res: Result<(), Error> = Err(Error::illegal_state("broken"));
+        let err = res
+            .context(ErrorFrame::new("CalibrationStage").with_input_id(7))
+            .context(
+                ErrorFrame::new("client")
+                    .with_client_id(ClientId(2))
+                    .with_core_id(3),
+            )
+            .unwrap_err();
+        assert!(matches!(err.root(), Error::IllegalState(..)));
+
+        let report = err.report();
+        assert_eq!(report.frames.len(), 2);
+        assert_eq!(report.frames[0].component, "CalibrationStage");
+        assert_eq!(report.frames[1].client_id, Some(ClientId(2)));
+        assert!(report
+            .to_string()
+            .ends_with("in CalibrationStage (testcase 7)\n  in client (client 2, core 3)"));
+
+        assert!(matches!(
+            Error::ShuttingDown.context(ErrorFrame::new("client")),
+            Error::ShuttingDown
+        ));
+    }
 }
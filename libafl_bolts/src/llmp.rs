@@ -107,6 +107,11 @@ use crate::{
     ClientId, Error,
 };
 
+/// How long the broker keeps handling messages after a shutdown signal, waiting for the clients
+/// to send their last reports and detach
+#[cfg(feature = "std")]
+pub const LLMP_SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// The max number of pages a [`client`] may have mapped that were not yet read by the [`broker`]
 /// Usually, this value should not exceed `1`, else the broker cannot keep up with the amount of incoming messages.
 /// Instead of increasing this value, you may consider sending new messages at a lower rate, else your Sender will eventually `OOM`.
@@ -136,6 +141,8 @@ const LLMP_TAG_CLIENT_EXIT: Tag = Tag(0xC11E472);
 const LLMP_TAG_EXITING: Tag = Tag(0x13C5171);
 /// Client gave up as the receiver/broker was too slow
 const LLMP_SLOW_RECEIVER_PANIC: Tag = Tag(0x70051041);
+/// A client announces its direct peer channel, see [`LlmpPeers`]
+pub const LLMP_TAG_PEER_ANNOUNCE: Tag = Tag(0x9EE7A770);
 
 /// Unused...
 pub const LLMP_FLAG_INITIALIZED: Flags = Flags(0x0);
@@ -955,6 +962,13 @@ where
         self.id
     }
 
+    /// The number of pages of this sender that are still in use, including the current one.
+    /// More than one page means the receivers did not yet catch up with the sent messages.
+    #[must_use]
+    pub fn pending_pages(&self) -> usize {
+        self.out_shmems.len()
+    }
+
     /// Completely reset the current sender map.
     /// Afterwards, no receiver should read from it at a different location.
     /// This is only useful if all connected llmp parties start over, for example after a crash.
@@ -2061,6 +2075,8 @@ where
     pub exit_cleanly_after: Option<NonZeroUsize>,
     /// Clients that should be removed soon
     clients_to_remove: Vec<ClientId>,
+    /// If a shutdown was requested, see [`LlmpBrokerInner::request_shutdown`]
+    shutdown_requested: bool,
     /// The `ShMemProvider` to use
     shmem_provider: SP,
 }
@@ -2085,6 +2101,9 @@ pub trait Broker {
     /// The hooks run for `on_timeout`
     fn on_timeout(&mut self) -> Result<(), Error>;
 
+    /// The hooks run for `on_shutdown`
+    fn on_shutdown(&mut self) -> Result<(), Error>;
+
     /// The main thing the `broker` does
     fn broker_once(&mut self) -> Result<bool, Error>;
 
@@ -2117,6 +2136,10 @@ where
         self.hooks.on_timeout_all()
     }
 
+    fn on_shutdown(&mut self) -> Result<(), Error> {
+        self.hooks.on_shutdown_all()
+    }
+
     fn broker_once(&mut self) -> Result<bool, Error> {
         self.broker_once()
     }
@@ -2219,6 +2242,12 @@ where
     fn on_timeout(&mut self) -> Result<(), Error> {
         Ok(())
     }
+
+    /// Hook called once when the broker stops, after the last messages of the clients were
+    /// handled, e.g. to print a final report.
+    fn on_shutdown(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 /// A tuple of Llmp hooks. They are evaluated sequentially, and returns if one decides to filter out the evaluated message.
@@ -2239,6 +2268,9 @@ where
 
     /// Call all hook callbacks on timeout.
     fn on_timeout_all(&mut self) -> Result<(), Error>;
+
+    /// Call all hook callbacks on shutdown.
+    fn on_shutdown_all(&mut self) -> Result<(), Error>;
 }
 
 impl<SP> LlmpHookTuple<SP> for ()
@@ -2260,6 +2292,10 @@ where
     fn on_timeout_all(&mut self) -> Result<(), Error> {
         Ok(())
     }
+
+    fn on_shutdown_all(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 impl<Head, Tail, SP> LlmpHookTuple<SP> for (Head, Tail)
@@ -2297,6 +2333,11 @@ where
         self.0.on_timeout()?;
         self.1.on_timeout_all()
     }
+
+    fn on_shutdown_all(&mut self) -> Result<(), Error> {
+        self.0.on_shutdown()?;
+        self.1.on_shutdown_all()
+    }
 }
 
 impl<SP> LlmpBroker<(), SP>
@@ -2374,6 +2415,9 @@ impl Brokers {
                     broker.send_buf(LLMP_TAG_EXITING, &[]).expect(
                         "Error when shutting down broker: Could not send LLMP_TAG_EXITING msg.",
                     );
+                    if let Err(err) = broker.on_shutdown() {
+                        log::error!("An error occurred in broker shutdown hooks: {err}");
+                    }
 
                     return false;
                 }
@@ -2406,6 +2450,9 @@ impl Brokers {
                     {
                         // No more clients connected, and the amount of clients we were waiting for was previously connected.
                         // exit cleanly.
+                        if let Err(err) = broker.on_shutdown() {
+                            log::error!("An error occurred in broker shutdown hooks: {err}");
+                        }
                         return false;
                     }
                 }
@@ -2522,10 +2569,17 @@ where
                 panic!("Cannot sleep on no_std platform (requested {time:?})");
             }
         }
+        #[cfg(feature = "std")]
+        if self.inner.is_shutting_down() {
+            self.drain_clients(sleep_time);
+        }
         self.inner
             .llmp_out
             .send_buf(LLMP_TAG_EXITING, &[])
             .expect("Error when shutting down broker: Could not send LLMP_TAG_EXITING msg.");
+        if let Err(err) = self.hooks.on_shutdown_all() {
+            log::error!("An error occurred in broker shutdown hooks: {err}");
+        }
     }
 
     /// Loops until the last client quits,
@@ -2586,10 +2640,33 @@ where
                 panic!("Cannot sleep on no_std platform (requested {time:?})");
             }
         }
+        #[cfg(feature = "std")]
+        if self.inner.is_shutting_down() {
+            self.drain_clients(sleep_time);
+        }
         self.inner
             .llmp_out
             .send_buf(LLMP_TAG_EXITING, &[])
             .expect("Error when shutting down broker: Could not send LLMP_TAG_EXITING msg.");
+        if let Err(err) = self.hooks.on_shutdown_all() {
+            log::error!("An error occurred in broker shutdown hooks: {err}");
+        }
+    }
+
+    /// After a shutdown signal, keeps brokering until all clients detached, for at most
+    /// [`LLMP_SHUTDOWN_DRAIN_TIMEOUT`], so their final messages still reach the hooks.
+    #[cfg(feature = "std")]
+    fn drain_clients(&mut self, sleep_time: Option<Duration>) {
+        let deadline = current_time() + LLMP_SHUTDOWN_DRAIN_TIMEOUT;
+        while self.inner.has_clients() && current_time() < deadline {
+            if let Err(err) = self.broker_once() {
+                log::warn!("Error while waiting for clients to exit: {err}");
+                break;
+            }
+            if let Some(time) = sleep_time {
+                thread::sleep(time);
+            }
+        }
     }
 
     /// The broker walks all pages and looks for changes, then broadcasts them on
@@ -2840,6 +2917,7 @@ where
             listeners: vec![],
             exit_cleanly_after: None,
             num_clients_seen: 0,
+            shutdown_requested: false,
             shmem_provider,
         })
     }
@@ -3017,20 +3095,28 @@ where
         Ok(())
     }
 
+    /// Requests the broker loop to shut down, like a `SIGINT` does, e.g. from a hook once a client
+    /// reported that the campaign is over. The broker then waits for the clients to detach.
+    #[inline]
+    pub fn request_shutdown(&mut self) {
+        self.shutdown_requested = true;
+    }
+
     /// Internal function, returns true when shuttdown is requested by a `SIGINT` signal
+    /// or [`Self::request_shutdown`]
     #[inline]
     #[cfg(any(unix, all(windows, feature = "std")))]
-    #[allow(clippy::unused_self)]
     fn is_shutting_down(&self) -> bool {
-        unsafe { ptr::read_volatile(ptr::addr_of!(LLMP_SIGHANDLER_STATE.shutting_down)) }
+        self.shutdown_requested
+            || unsafe { ptr::read_volatile(ptr::addr_of!(LLMP_SIGHANDLER_STATE.shutting_down)) }
     }
 
-    /// Always returns true on platforms, where no shutdown signal handlers are supported
+    /// Only returns true after [`Self::request_shutdown`] on platforms, where no shutdown
+    /// signal handlers are supported
     #[inline]
     #[cfg(not(any(unix, all(windows, feature = "std"))))]
-    #[allow(clippy::unused_self)]
     fn is_shutting_down(&self) -> bool {
-        false
+        self.shutdown_requested
     }
 
     /// Returns if any clients are currently connected.
@@ -3754,10 +3840,223 @@ where
     }
 }
 
+/// Announcement of the direct channel of a client, sent through the broker with [`LLMP_TAG_PEER_ANNOUNCE`]
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct LlmpPeerAnnouncement {
+    /// The id of the announcing client
+    pub client_id: ClientId,
+    /// The description of the outgoing peer map of this client
+    pub description: LlmpDescription,
+}
+
+/// A peer we receive direct messages from
+#[derive(Debug)]
+struct LlmpPeer<SP>
+where
+    SP: ShMemProvider,
+{
+    id: ClientId,
+    shmem_id: ShMemId,
+    receiver: LlmpReceiver<SP>,
+}
+
+/// Direct (peer-to-peer) channels between clients on the same host, bypassing the broker.
+///
+/// Each client owns one outgoing map, read by all of its peers.
+/// The maps are announced through the broker once (see [`LlmpPeers::announce`]);
+/// afterwards, messages flow directly from client to client.
+/// This is meant for high-volume, best-effort collaboration data, such as cmplog traces or hint tables,
+/// while the broker keeps handling the global corpus and stats traffic.
+/// Announcements forwarded from other brokers (flagged [`LLMP_FLAG_FROM_B2B`]) must be ignored,
+/// since their maps live on a different host.
+///
+/// The outgoing map is read by several peers, each at its own pace, so no single reader can tell
+/// when a page is safe to unmap: the map keeps all of its pages until the client exits.
+#[derive(Debug)]
+pub struct LlmpPeers<SP>
+where
+    SP: ShMemProvider,
+{
+    /// Our outgoing map, read by all peers
+    sender: LlmpSender<SP>,
+    /// The peers we receive from
+    peers: Vec<LlmpPeer<SP>>,
+    /// The next peer to poll, to receive from all peers fairly
+    next_peer: usize,
+    shmem_provider: SP,
+}
+
+impl<SP> LlmpPeers<SP>
+where
+    SP: ShMemProvider,
+{
+    /// Creates the outgoing peer map for the client with the given `id`
+    pub fn new(shmem_provider: SP, id: ClientId) -> Result<Self, Error> {
+        Ok(Self {
+            // The first peer to move on would mark a page as safe to unmap for all of them
+            sender: LlmpSender::new(shmem_provider.clone(), id, true)?,
+            peers: vec![],
+            next_peer: 0,
+            shmem_provider,
+        })
+    }
+
+    /// The id of our client
+    #[must_use]
+    pub fn id(&self) -> ClientId {
+        self.sender.id()
+    }
+
+    /// The serialized [`LlmpPeerAnnouncement`] for our outgoing map
+    pub fn announcement(&self) -> Result<Vec<u8>, Error> {
+        Ok(postcard::to_allocvec(&LlmpPeerAnnouncement {
+            client_id: self.sender.id(),
+            description: self.sender.describe()?,
+        })?)
+    }
+
+    /// Announces our outgoing map to all other clients of the broker
+    pub fn announce(&self, client: &mut LlmpClient<SP>) -> Result<(), Error> {
+        client.send_buf(LLMP_TAG_PEER_ANNOUNCE, &self.announcement()?)
+    }
+
+    /// Handles the announcement of another client, attaching to its outgoing map.
+    /// A peer that announces a new map (for example after a restart) replaces the old one.
+    /// Returns `true` if we attached to a new map, in which case we should announce ourselves again,
+    /// so that a (re)started peer learns about us, too.
+    pub fn on_announcement(&mut self, buf: &[u8]) -> Result<bool, Error> {
+        let announcement: LlmpPeerAnnouncement = postcard::from_bytes(buf)?;
+        if announcement.client_id == self.id() {
+            return Ok(false);
+        }
+        let shmem_id = announcement.description.shmem.id;
+        let existing = self
+            .peers
+            .iter()
+            .position(|peer| peer.id == announcement.client_id);
+        if let Some(idx) = existing {
+            if self.peers[idx].shmem_id == shmem_id {
+                return Ok(false);
+            }
+        }
+
+        let receiver = match LlmpReceiver::on_existing_from_description(
+            self.shmem_provider.clone(),
+            &announcement.description,
+        ) {
+            Ok(receiver) => receiver,
+            Err(err) => {
+                // The peer is probably gone already
+                log::info!(
+                    "Could not attach to the peer map of client {:?}: {err}",
+                    announcement.client_id
+                );
+                return Ok(false);
+            }
+        };
+        let peer = LlmpPeer {
+            id: announcement.client_id,
+            shmem_id,
+            receiver,
+        };
+        match existing {
+            Some(idx) => self.peers[idx] = peer,
+            None => self.peers.push(peer),
+        }
+        log::debug!(
+            "Client {:?} attached to the peer map of client {:?}",
+            self.id(),
+            announcement.client_id
+        );
+        Ok(true)
+    }
+
+    /// The ids of all known peers
+    pub fn peers(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.peers.iter().map(|peer| peer.id)
+    }
+
+    /// The number of known peers
+    #[must_use]
+    pub fn peers_count(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Stops receiving from the given peer
+    pub fn remove_peer(&mut self, id: ClientId) {
+        self.peers.retain(|peer| peer.id != id);
+    }
+
+    /// Sends a `buf` to all peers.
+    /// Messages sent while no peer is known are dropped.
+    pub fn send_buf(&mut self, tag: Tag, buf: &[u8]) -> Result<(), Error> {
+        self.send_buf_with_flags(tag, LLMP_FLAG_INITIALIZED, buf)
+    }
+
+    /// Sends a `buf` with the given `flags` to all peers.
+    /// Messages sent while no peer is known are dropped.
+    pub fn send_buf_with_flags(&mut self, tag: Tag, flags: Flags, buf: &[u8]) -> Result<(), Error> {
+        if self.peers.is_empty() {
+            return Ok(());
+        }
+        self.sender.send_buf_with_flags(tag, flags, buf)
+    }
+
+    /// Returns the next message of any peer, if available, else None
+    #[allow(clippy::type_complexity)]
+    #[inline]
+    pub fn recv_buf(&mut self) -> Result<Option<(ClientId, Tag, &[u8])>, Error> {
+        if let Some((sender, tag, _flags, buf)) = self.recv_buf_with_flags()? {
+            Ok(Some((sender, tag, buf)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the next message of any peer, including the `flags`, if available, else None.
+    /// Peers are polled round-robin; peers that exited are removed.
+    #[allow(clippy::type_complexity)]
+    pub fn recv_buf_with_flags(&mut self) -> Result<Option<(ClientId, Tag, Flags, &[u8])>, Error> {
+        for _ in 0..self.peers.len() {
+            if self.peers.is_empty() {
+                break;
+            }
+            let idx = self.next_peer % self.peers.len();
+            self.next_peer = idx + 1;
+            match unsafe { self.peers[idx].receiver.recv() } {
+                Ok(Some(msg)) => unsafe {
+                    let receiver = &mut self.peers[idx].receiver;
+                    return Ok(Some((
+                        (*msg).sender,
+                        (*msg).tag,
+                        (*msg).flags,
+                        (*msg).try_as_slice(&mut receiver.current_recv_shmem)?,
+                    )));
+                },
+                Ok(None) => {}
+                Err(Error::ShuttingDown) => {
+                    log::debug!("Peer {:?} exited", self.peers[idx].id);
+                    self.peers.remove(idx);
+                    self.next_peer = idx;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
+
+    /// Tells all peers that we are exiting.
+    /// We are no longer allowed to send anything afterwards.
+    pub fn send_exiting(&mut self) -> Result<(), Error> {
+        self.sender.send_exiting()
+    }
+}
+
 #[cfg(test)]
 #[cfg(all(unix, feature = "std", not(target_os = "haiku")))]
 mod tests {
 
+    use alloc::vec::Vec;
     use std::{thread::sleep, time::Duration};
 
     use serial_test::serial;
@@ -3765,9 +4064,12 @@ mod tests {
     use super::{
         LlmpClient,
         LlmpConnection::{self, IsBroker, IsClient},
-        Tag,
+        LlmpPeers, Tag,
+    };
+    use crate::{
+        shmem::{ShMemProvider, StdShMemProvider},
+        ClientId,
     };
-    use crate::shmem::{ShMemProvider, StdShMemProvider};
 
     #[test]
     #[serial]
@@ -3817,4 +4119,42 @@ mod tests {
         // We want at least the tcp and sender clients.
         assert_eq!(broker.inner.llmp_clients.len(), 2);
     }
+
+    #[test]
+    #[serial]
+    #[cfg_attr(miri, ignore)]
+    pub fn test_llmp_peers() {
+        let shmem_provider = StdShMemProvider::new().unwrap();
+        let mut peers_a = LlmpPeers::new(shmem_provider.clone(), ClientId(1)).unwrap();
+        let mut peers_b = LlmpPeers::new(shmem_provider, ClientId(2)).unwrap();
+
+        // Nobody is listening yet, this message is dropped
+        peers_a.send_buf(Tag(0x1337), &[0]).unwrap();
+
+        assert!(peers_b
+            .on_announcement(&peers_a.announcement().unwrap())
+            .unwrap());
+        assert!(peers_a
+            .on_announcement(&peers_b.announcement().unwrap())
+            .unwrap());
+        // Known peers and our own announcement are ignored
+        assert!(!peers_b
+            .on_announcement(&peers_a.announcement().unwrap())
+            .unwrap());
+        assert!(!peers_a
+            .on_announcement(&peers_a.announcement().unwrap())
+            .unwrap());
+        assert_eq!(peers_a.peers().collect::<Vec<_>>(), vec![ClientId(2)]);
+
+        peers_a.send_buf(Tag(0x1337), &[1, 2]).unwrap();
+        let (sender, tag, buf) = peers_b.recv_buf().unwrap().unwrap();
+        assert_eq!(sender, ClientId(1));
+        assert_eq!(tag, Tag(0x1337));
+        assert_eq!(buf, &[1, 2]);
+        assert!(peers_b.recv_buf().unwrap().is_none());
+
+        peers_a.send_exiting().unwrap();
+        assert!(peers_b.recv_buf().unwrap().is_none());
+        assert_eq!(peers_b.peers_count(), 0);
+    }
 }
@@ -42,6 +42,21 @@ fn random_seed_from_random_state() -> u64 {
     RandomState::new().build_hasher().finish()
 }
 
+/// Derives the seed of a component from the root seed of a campaign and the path of the
+/// component, e.g. `"client/3/scheduler"`.
+///
+/// Every component gets an independent sequence, and the same root seed reproduces all of them,
+/// e.g. for a deterministic replay of a campaign. The derivation is stable across platforms.
+#[must_use]
+pub fn derive_seed(root_seed: u64, path: &str) -> u64 {
+    // FNV-1a over the path, starting from the root seed
+    let mut seed = root_seed ^ 0xcbf29ce484222325;
+    for &byte in path.as_bytes() {
+        seed = (seed ^ u64::from(byte)).wrapping_mul(0x100000001b3);
+    }
+    splitmix64(&mut seed)
+}
+
 // https://prng.di.unimi.it/splitmix64.c
 fn splitmix64(x: &mut u64) -> u64 {
     *x = x.wrapping_add(0x9e3779b97f4a7c15);
@@ -529,7 +544,7 @@ impl XkcdRand {
 #[cfg(test)]
 mod tests {
     use crate::rands::{
-        Rand, RomuDuoJrRand, RomuTrioRand, Sfc64Rand, StdRand, XorShift64Rand,
+        derive_seed, Rand, RomuDuoJrRand, RomuTrioRand, Sfc64Rand, StdRand, XorShift64Rand,
         Xoshiro256PlusPlusRand,
     };
 
@@ -552,6 +567,15 @@ mod tests {
         test_single_rand(&mut Sfc64Rand::with_seed(0));
     }
 
+    #[test]
+    fn test_derive_seed() {
+        let seed = derive_seed(1337, "client/0/scheduler");
+        assert_eq!(seed, derive_seed(1337, "client/0/scheduler"));
+        assert_ne!(seed, derive_seed(1337, "client/1/scheduler"));
+        assert_ne!(seed, derive_seed(1338, "client/0/scheduler"));
+        assert_ne!(derive_seed(0, ""), derive_seed(0, "a"));
+    }
+
     #[test]
     fn test_romutrio_golden() {
         // https://github.com/ziglang/zig/blob/130fb5cb0fb9039e79450c9db58d6590c5bee3b3/lib/std/Random/RomuTrio.zig#L75-L95
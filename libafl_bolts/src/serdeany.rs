@@ -1,17 +1,22 @@
 //! Poor-rust-man's downcasts for stuff we send over the wire (or shared maps)
 
-use alloc::boxed::Box;
 #[cfg(feature = "unsafe_stable_anymap")]
-use alloc::string::{String, ToString};
+use alloc::string::ToString;
+use alloc::{boxed::Box, string::String, vec::Vec};
 #[cfg(feature = "unsafe_stable_anymap")]
 use core::any::type_name;
 #[cfg(not(feature = "unsafe_stable_anymap"))]
 use core::any::TypeId;
 use core::{any::Any, fmt::Debug};
 
-use serde::{de::DeserializeSeed, Deserialize, Deserializer, Serialize, Serializer};
+use serde::{
+    de::{DeserializeOwned, DeserializeSeed},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
 pub use serdeany_registry::*;
 
+use crate::Error;
+
 #[cfg(not(feature = "unsafe_stable_anymap"))]
 use crate::anymap::unpack_type_id;
 
@@ -49,6 +54,30 @@ fn type_repr<T>() -> &'static str {
     type_name::<T>()
 }
 
+#[cfg(not(feature = "unsafe_stable_anymap"))]
+fn type_repr_dyn(value: &dyn SerdeAny) -> TypeRepr {
+    unpack_type_id(value.as_any().type_id())
+}
+
+#[cfg(feature = "unsafe_stable_anymap")]
+fn type_repr_dyn(value: &dyn SerdeAny) -> TypeRepr {
+    value.type_name().to_string()
+}
+
+/// Marks the id of a [`VersionedSerdeAny`] serialized in a [`VersionedData`] envelope, to tell it
+/// apart from a value serialized before its type was versioned
+const VERSIONED_ID_MARKER: u128 = 1 << 127;
+
+#[cfg(not(feature = "unsafe_stable_anymap"))]
+fn versioned_repr(repr: &TypeRepr) -> TypeRepr {
+    *repr ^ VERSIONED_ID_MARKER
+}
+
+#[cfg(feature = "unsafe_stable_anymap")]
+fn versioned_repr(repr: &TypeRepr) -> TypeRepr {
+    format!("{repr}@versioned")
+}
+
 /// A (de)serializable Any trait
 pub trait SerdeAny: Any + erased_serde::Serialize + Debug {
     /// returns this as Any trait
@@ -57,6 +86,127 @@ pub trait SerdeAny: Any + erased_serde::Serialize + Debug {
     fn as_any_mut(&mut self) -> &mut dyn Any;
     /// returns this as boxed Any trait
     fn as_any_boxed(self: Box<Self>) -> Box<dyn Any>;
+    /// The schema version this type is serialized with, if it is a [`VersionedSerdeAny`]
+    fn schema_version(&self) -> Option<u32> {
+        None
+    }
+
+    /// The name of the type, e.g. to list the elements of a map for debugging
+    fn type_name(&self) -> &'static str {
+        core::any::type_name::<Self>()
+    }
+}
+
+/// A [`SerdeAny`] with a schema version, implemented using [`crate::impl_serdeany_versioned`].
+///
+/// Versioned types are serialized in a [`VersionedData`] envelope, holding the schema version and
+/// the [`postcard`]-serialized value. When loading a value serialized by an older schema version,
+/// e.g. after upgrading the fuzzer binary mid-campaign, [`VersionedSerdeAny::migrate`] converts it
+/// to the current one, instead of failing to deserialize.
+///
+/// Values serialized before their type was versioned are decoded with
+/// [`VersionedSerdeAny::deserialize_legacy`].
+///
+/// Note that values are only found again across different binaries with stable type ids, see the
+/// `unsafe_stable_anymap` feature.
+pub trait VersionedSerdeAny: Sized {
+    /// The current schema version. Bump it whenever the serialized layout changes.
+    const SCHEMA_VERSION: u32;
+
+    /// Converts `data`, serialized with the older schema `version`, to the current schema
+    fn migrate(version: u32, _data: &[u8]) -> Result<Self, Error> {
+        Err(Error::serialize(format!(
+            "No migration from schema version {version} to {} of {}",
+            Self::SCHEMA_VERSION,
+            core::any::type_name::<Self>()
+        )))
+    }
+
+    /// Deserializes a value serialized before this type was versioned, i.e. without a
+    /// [`VersionedData`] envelope. Override it if the layout changed since then.
+    fn deserialize_legacy<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+        Self: Deserialize<'de>,
+    {
+        Self::deserialize(deserializer)
+    }
+}
+
+/// The serialized form of a [`VersionedSerdeAny`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionedData {
+    /// The schema version the data was serialized with
+    pub version: u32,
+    /// The [`postcard`]-serialized value
+    pub data: Vec<u8>,
+}
+
+impl VersionedData {
+    /// Serializes the value with the current schema version
+    pub fn encode<T>(value: &T) -> Result<Self, Error>
+    where
+        T: VersionedSerdeAny + Serialize,
+    {
+        Ok(Self {
+            version: T::SCHEMA_VERSION,
+            data: postcard::to_allocvec(value)?,
+        })
+    }
+
+    /// Deserializes the value, migrating it if it was serialized with an older schema version
+    pub fn decode<T>(&self) -> Result<T, Error>
+    where
+        T: VersionedSerdeAny + DeserializeOwned,
+    {
+        match self.version.cmp(&T::SCHEMA_VERSION) {
+            core::cmp::Ordering::Equal => Ok(postcard::from_bytes(&self.data)?),
+            core::cmp::Ordering::Less => T::migrate(self.version, &self.data),
+            core::cmp::Ordering::Greater => Err(Error::serialize(format!(
+                "{} was serialized with schema version {}, newer than the supported version {}",
+                core::any::type_name::<T>(),
+                self.version,
+                T::SCHEMA_VERSION
+            ))),
+        }
+    }
+}
+
+/// A [`SerdeAny`] value serialized with the stable name of its type instead of its type id.
+///
+/// Type ids and [`core::any::type_name`] differ between builds and compiler versions, so only
+/// types with a name registered with [`RegistryBuilder::register_stable_name`] can be stored in
+/// this form. The `impl_serdeany` macros register `module::path::Type` for each registered type.
+/// Values in this form can be loaded by another build of the fuzzer, e.g. for a portable export of
+/// the state. Values of types unknown to the loading build are recognized and can be skipped.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortableSerdeAny {
+    /// The stable name of the type, see [`RegistryBuilder::register_stable_name`]
+    pub stable_name: String,
+    /// The [`postcard`]-serialized value, in a [`VersionedData`] envelope for versioned types
+    pub data: Vec<u8>,
+}
+
+impl PortableSerdeAny {
+    /// Serializes the value, or returns `None` if its type has no stable name
+    pub fn encode(value: &dyn SerdeAny) -> Result<Option<Self>, Error> {
+        let Some(stable_name) = stable_name_by_repr(&type_repr_dyn(value)) else {
+            return Ok(None);
+        };
+        let mut data = postcard::to_allocvec(&Wrap(value))?;
+        if let Some(version) = value.schema_version() {
+            data = postcard::to_allocvec(&VersionedData { version, data })?;
+        }
+        Ok(Some(Self {
+            stable_name: stable_name.into(),
+            data,
+        }))
+    }
+
+    /// Deserializes the value, or returns `None` if no type of this stable name is registered
+    pub fn decode(&self) -> Result<Option<Box<dyn SerdeAny>>, Error> {
+        deserialize_by_stable_name(&self.stable_name, &self.data)
+    }
 }
 
 /// Wrap a type for serialization
@@ -123,13 +273,13 @@ pub mod serdeany_registry {
 
     use crate::{
         serdeany::{
-            type_repr, type_repr_owned, DeserializeCallback, DeserializeCallbackSeed, SerdeAny,
-            TypeRepr,
+            type_repr, type_repr_dyn, type_repr_owned, versioned_repr, DeserializeCallback,
+            DeserializeCallbackSeed, SerdeAny, TypeRepr, VersionedData, VersionedSerdeAny,
         },
         Error,
     };
 
-    /// A [`HashMap`] that maps from [`TypeRepr`] to a deserializer and its [`TypeId`].
+    /// A [`HashMap`] that maps from [`TypeRepr`] to a deserializer, its [`TypeId`] and its type name.
     type DeserializeCallbackMap = HashMap<TypeRepr, (DeserializeCallback<dyn SerdeAny>, TypeId)>;
 
     /// Visitor object used internally for the [`crate::serdeany::SerdeAny`] registry.
@@ -166,6 +316,7 @@ pub mod serdeany_registry {
     #[allow(unused_qualifications)]
     struct Registry {
         deserializers: Option<DeserializeCallbackMap>,
+        stable_names: Option<HashMap<TypeRepr, &'static str>>,
         finalized: bool,
     }
 
@@ -174,23 +325,64 @@ pub mod serdeany_registry {
         pub fn register<T>(&mut self)
         where
             T: crate::serdeany::SerdeAny + Serialize + serde::de::DeserializeOwned,
+        {
+            self.register_with::<T>(type_repr_owned::<T>(), |de| {
+                Ok(Box::new(erased_serde::deserialize::<T>(de)?))
+            });
+        }
+
+        pub fn register_versioned<T>(&mut self)
+        where
+            T: crate::serdeany::SerdeAny
+                + VersionedSerdeAny
+                + Serialize
+                + serde::de::DeserializeOwned,
+        {
+            // Values serialized before the type was versioned keep the plain type id
+            self.register_with::<T>(type_repr_owned::<T>(), |de| {
+                Ok(Box::new(T::deserialize_legacy(de)?))
+            });
+            self.register_with::<T>(versioned_repr(&type_repr_owned::<T>()), |de| {
+                let versioned: VersionedData = erased_serde::deserialize(de)?;
+                let value = versioned
+                    .decode::<T>()
+                    .map_err(<erased_serde::Error as serde::de::Error>::custom)?;
+                Ok(Box::new(value))
+            });
+        }
+
+        fn register_with<T>(&mut self, repr: TypeRepr, cb: DeserializeCallback<dyn SerdeAny>)
+        where
+            T: crate::serdeany::SerdeAny,
         {
             assert!(!self.finalized, "Registry is already finalized!");
 
             let deserializers = self.deserializers.get_or_insert_with(HashMap::default);
             let _entry = deserializers
-                .entry(type_repr_owned::<T>())
-                .or_insert_with(|| {
-                    (
-                        |de| Ok(Box::new(erased_serde::deserialize::<T>(de)?)),
-                        TypeId::of::<T>(),
-                    )
-                });
+                .entry(repr)
+                .or_insert_with(|| (cb, TypeId::of::<T>()));
 
             #[cfg(feature = "unsafe_stable_anymap")]
             assert_eq!(_entry.1, TypeId::of::<T>(), "Fatal safety error: TypeId of type {} is not equals to the deserializer's TypeId for this type! Two registered types have the same type_name!", type_repr::<T>());
         }
 
+        fn register_stable_name<T>(&mut self, name: &'static str)
+        where
+            T: 'static,
+        {
+            assert!(!self.finalized, "Registry is already finalized!");
+
+            let stable_names = self.stable_names.get_or_insert_with(HashMap::default);
+            let repr = type_repr_owned::<T>();
+            assert!(
+                stable_names
+                    .iter()
+                    .all(|(other, other_name)| *other_name != name || *other == repr),
+                "Two registered types have the same stable name {name}!"
+            );
+            stable_names.insert(repr, name);
+        }
+
         pub fn finalize(&mut self) {
             self.finalized = true;
         }
@@ -198,9 +390,56 @@ pub mod serdeany_registry {
 
     static mut REGISTRY: Registry = Registry {
         deserializers: None,
+        stable_names: None,
         finalized: false,
     };
 
+    /// The stable name registered for the type with the given repr
+    pub(crate) fn stable_name_by_repr(repr: &TypeRepr) -> Option<&'static str> {
+        unsafe { REGISTRY.stable_names.as_ref() }?
+            .get(repr)
+            .copied()
+    }
+
+    /// The stable name registered for the type `T`, see [`RegistryBuilder::register_stable_name`]
+    #[must_use]
+    pub fn stable_name_of<T>() -> Option<&'static str>
+    where
+        T: 'static,
+    {
+        stable_name_by_repr(&type_repr_owned::<T>())
+    }
+
+    /// Deserializes a [`postcard`]-serialized value of the registered type with the given stable
+    /// name, see [`crate::serdeany::PortableSerdeAny`]
+    pub(crate) fn deserialize_by_stable_name(
+        stable_name: &str,
+        data: &[u8],
+    ) -> Result<Option<Box<dyn SerdeAny>>, Error> {
+        let repr = unsafe { REGISTRY.stable_names.as_ref() }.and_then(|stable_names| {
+            stable_names
+                .iter()
+                .find(|(_, name)| **name == stable_name)
+                .map(|(repr, _)| repr)
+        });
+        // Versioned types are stored in a `VersionedData` envelope
+        let cb = repr.and_then(|repr| {
+            let deserializers = unsafe { REGISTRY.deserializers.as_ref() }?;
+            deserializers
+                .get(&versioned_repr(repr))
+                .or_else(|| deserializers.get(repr))
+                .map(|(cb, _)| *cb)
+        });
+        let Some(cb) = cb else {
+            return Ok(None);
+        };
+        let mut deserializer = postcard::Deserializer::from_bytes(data);
+        let mut erased = <dyn erased_serde::Deserializer>::erase(&mut deserializer);
+        cb(&mut erased)
+            .map(Some)
+            .map_err(|err| Error::serialize(format!("Cannot deserialize {stable_name}: {err}")))
+    }
+
     /// This sugar must be used to register all the structs which
     /// have trait objects that can be serialized and deserialized in the program
     #[derive(Debug)]
@@ -222,6 +461,39 @@ pub mod serdeany_registry {
             }
         }
 
+        /// Register a given [`VersionedSerdeAny`] struct type for trait object (de)serialization
+        ///
+        /// # Safety
+        /// This may never be called concurrently or at the same time as `finalize`.
+        /// It dereferences the `REGISTRY` hashmap and adds the given type to it.
+        pub unsafe fn register_versioned<T>()
+        where
+            T: crate::serdeany::SerdeAny
+                + VersionedSerdeAny
+                + Serialize
+                + serde::de::DeserializeOwned,
+        {
+            unsafe {
+                REGISTRY.register_versioned::<T>();
+            }
+        }
+
+        /// Register a name for the type `T` that stays the same across builds and compiler
+        /// versions, e.g. `my_fuzzer::MyMetadata`, see [`crate::serdeany::PortableSerdeAny`].
+        /// The `impl_serdeany` macros register their types with their module path.
+        ///
+        /// # Safety
+        /// This may never be called concurrently or at the same time as `finalize`.
+        /// It dereferences the `REGISTRY` hashmap and adds the given name to it.
+        pub unsafe fn register_stable_name<T>(name: &'static str)
+        where
+            T: 'static,
+        {
+            unsafe {
+                REGISTRY.register_stable_name::<T>(name);
+            }
+        }
+
         /// Finalize the registry, no more registrations are allowed after this call
         ///
         /// # Safety
@@ -336,6 +608,13 @@ pub mod serdeany_registry {
                 .insert(type_repr_owned::<T>(), value);
         }
 
+        /// Insert a boxed element of any type into the map, e.g. one loaded with
+        /// [`crate::serdeany::PortableSerdeAny::decode`].
+        #[inline]
+        pub fn insert_dyn(&mut self, value: Box<dyn SerdeAny>) {
+            self.map.insert(type_repr_dyn(&*value), value);
+        }
+
         /// Get an entry to an element in this map.
         #[inline]
         #[allow(unused_qualifications)]
@@ -416,6 +695,11 @@ pub mod serdeany_registry {
             self.map.contains_key(type_repr)
         }
 
+        /// Iterates over all elements in this map, e.g. to inspect them for debugging
+        pub fn values(&self) -> impl Iterator<Item = &dyn crate::serdeany::SerdeAny> {
+            self.map.values().map(|x| &**x)
+        }
+
         /// Create a new [`SerdeAnyMap`].
         #[must_use]
         pub fn new() -> Self {
@@ -604,6 +888,16 @@ pub mod serdeany_registry {
             self.entry::<T>(name.into()).insert(Box::new(val));
         }
 
+        /// Insert a boxed element of any type into this map, e.g. one loaded with
+        /// [`crate::serdeany::PortableSerdeAny::decode`].
+        #[inline]
+        pub fn insert_dyn(&mut self, name: &str, value: Box<dyn SerdeAny>) {
+            self.map
+                .entry(type_repr_dyn(&*value))
+                .or_default()
+                .insert(name.into(), value);
+        }
+
         /// Get a reference to the type map.
         #[inline]
         #[allow(unused_qualifications)]
@@ -744,6 +1038,27 @@ pub mod serdeany_registry {
             }
         }
 
+        /// Iterates over all elements in this map with their names, e.g. to inspect them for
+        /// debugging
+        pub fn entries(&self) -> impl Iterator<Item = (&str, &dyn crate::serdeany::SerdeAny)> {
+            self.map
+                .values()
+                .flat_map(|h| h.iter().map(|(name, x)| (name.as_str(), &**x)))
+        }
+
+        /// Remove all elements with the given `name`, of any type.
+        /// Returns the number of removed elements.
+        #[inline]
+        pub fn remove_all(&mut self, name: &str) -> usize {
+            let mut removed = 0;
+            for h in self.map.values_mut() {
+                if h.remove(name).is_some() {
+                    removed += 1;
+                }
+            }
+            removed
+        }
+
         /// Create a new `SerdeAny` map.
         #[must_use]
         pub fn new() -> Self {
@@ -770,8 +1085,15 @@ impl Serialize for dyn crate::serdeany::SerdeAny {
 
         let id = crate::anymap::unpack_type_id(self.type_id());
         let mut seq = se.serialize_seq(Some(2))?;
-        seq.serialize_element(&id)?;
-        seq.serialize_element(&crate::serdeany::Wrap(self))?;
+        if let Some(version) = self.schema_version() {
+            seq.serialize_element(&(id ^ crate::serdeany::VERSIONED_ID_MARKER))?;
+            let data = postcard::to_allocvec(&crate::serdeany::Wrap(self))
+                .map_err(serde::ser::Error::custom)?;
+            seq.serialize_element(&VersionedData { version, data })?;
+        } else {
+            seq.serialize_element(&id)?;
+            seq.serialize_element(&crate::serdeany::Wrap(self))?;
+        }
         seq.end()
     }
 }
@@ -786,7 +1108,7 @@ impl<'de> Deserialize<'de> for Box<dyn crate::serdeany::SerdeAny> {
     }
 }
 
-/// Register a `SerdeAny` type in the [`RegistryBuilder`]
+/// Register a `SerdeAny` type in the [`RegistryBuilder`], with its module path as stable name
 ///
 /// Do nothing for without the `serdeany_autoreg` feature, you'll have to register it manually
 /// in `main()` with [`RegistryBuilder::register`] or using `<T>::register()`.
@@ -794,6 +1116,12 @@ impl<'de> Deserialize<'de> for Box<dyn crate::serdeany::SerdeAny> {
 #[macro_export]
 macro_rules! create_register {
     ($struct_type:ty) => {
+        $crate::create_register!(
+            $struct_type,
+            concat!(module_path!(), "::", stringify!($struct_type))
+        );
+    };
+    ($struct_type:ty, $stable_name:expr) => {
         const _: () = {
             /// Automatically register this type
             #[$crate::ctor]
@@ -802,13 +1130,16 @@ macro_rules! create_register {
                 // This `register` call will always run at startup and never in parallel.
                 unsafe {
                     $crate::serdeany::RegistryBuilder::register::<$struct_type>();
+                    $crate::serdeany::RegistryBuilder::register_stable_name::<$struct_type>(
+                        $stable_name,
+                    );
                 }
             }
         };
     };
 }
 
-/// Register a `SerdeAny` type in the [`RegistryBuilder`]
+/// Register a `SerdeAny` type in the [`RegistryBuilder`], with its module path as stable name
 ///
 /// Do nothing for without the `serdeany_autoreg` feature, you'll have to register it manually
 /// in `main()` with [`RegistryBuilder::register`] or using `<T>::register()`.
@@ -816,6 +1147,90 @@ macro_rules! create_register {
 #[macro_export]
 macro_rules! create_register {
     ($struct_type:ty) => {};
+    ($struct_type:ty, $stable_name:expr) => {};
+}
+
+/// Register a [`VersionedSerdeAny`] type in the [`RegistryBuilder`]
+///
+/// Do nothing for without the `serdeany_autoreg` feature, you'll have to register it manually
+/// in `main()` with [`RegistryBuilder::register_versioned`] or using `<T>::register()`.
+#[cfg(all(feature = "serdeany_autoreg", not(miri)))]
+#[macro_export]
+macro_rules! create_register_versioned {
+    ($struct_type:ty) => {
+        const _: () = {
+            /// Automatically register this type
+            #[$crate::ctor]
+            fn register() {
+                // # Safety
+                // This `register` call will always run at startup and never in parallel.
+                unsafe {
+                    $crate::serdeany::RegistryBuilder::register_versioned::<$struct_type>();
+                    $crate::serdeany::RegistryBuilder::register_stable_name::<$struct_type>(
+                        concat!(module_path!(), "::", stringify!($struct_type)),
+                    );
+                }
+            }
+        };
+    };
+}
+
+/// Register a [`VersionedSerdeAny`] type in the [`RegistryBuilder`]
+///
+/// Do nothing for without the `serdeany_autoreg` feature, you'll have to register it manually
+/// in `main()` with [`RegistryBuilder::register_versioned`] or using `<T>::register()`.
+#[cfg(not(all(feature = "serdeany_autoreg", not(miri))))]
+#[macro_export]
+macro_rules! create_register_versioned {
+    ($struct_type:ty) => {};
+}
+
+/// Implement a [`SerdeAny`] for a type implementing [`VersionedSerdeAny`], so it gets serialized
+/// with its schema version, registering it in the [`RegistryBuilder`] when on std
+#[macro_export]
+macro_rules! impl_serdeany_versioned {
+    ($struct_name:ident) => {
+        impl $crate::serdeany::SerdeAny for $struct_name {
+            fn as_any(&self) -> &dyn ::core::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn ::core::any::Any {
+                self
+            }
+
+            fn as_any_boxed(
+                self: $crate::alloc::boxed::Box<$struct_name>,
+            ) -> $crate::alloc::boxed::Box<dyn ::core::any::Any> {
+                self
+            }
+
+            fn schema_version(&self) -> ::core::option::Option<u32> {
+                ::core::option::Option::Some(
+                    <$struct_name as $crate::serdeany::VersionedSerdeAny>::SCHEMA_VERSION,
+                )
+            }
+        }
+
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        impl $struct_name {
+            /// Manually register this type at a later point in time
+            ///
+            /// # Safety
+            /// This may never be called concurrently as it dereferences the `RegistryBuilder` without acquiring a lock.
+            #[allow(unused)]
+            pub unsafe fn register() {
+                $crate::serdeany::RegistryBuilder::register_versioned::<$struct_name>();
+                $crate::serdeany::RegistryBuilder::register_stable_name::<$struct_name>(concat!(
+                    module_path!(),
+                    "::",
+                    stringify!($struct_name)
+                ));
+            }
+        }
+
+        $crate::create_register_versioned!($struct_name);
+    };
 }
 
 /// Implement a [`SerdeAny`], registering it in the [`RegistryBuilder`] when on std
@@ -855,7 +1270,18 @@ macro_rules! impl_serdeany {
         }
 
         $(
-            $crate::create_register!($struct_name < $( $opt ),+ >);
+            $crate::create_register!(
+                $struct_name < $( $opt ),+ >,
+                $crate::alloc::boxed::Box::leak(
+                    $crate::alloc::format!(
+                        "{}::{}<{}>",
+                        module_path!(),
+                        stringify!($struct_name),
+                        [$( stringify!($opt) ),+].join(", ")
+                    )
+                    .into_boxed_str()
+                )
+            );
         )*
     };
     ($struct_name:ident) =>
@@ -888,6 +1314,9 @@ macro_rules! impl_serdeany {
             #[allow(unused)]
             pub unsafe fn register() {
                 $crate::serdeany::RegistryBuilder::register::<$struct_name>();
+                $crate::serdeany::RegistryBuilder::register_stable_name::<$struct_name>(
+                    concat!(module_path!(), "::", stringify!($struct_name)),
+                );
             }
         }
 
@@ -897,9 +1326,19 @@ macro_rules! impl_serdeany {
 
 #[cfg(test)]
 mod tests {
-    use serde::{Deserialize, Serialize};
+    use alloc::{boxed::Box, vec::Vec};
+    use core::any::{Any, TypeId};
 
-    use crate::serdeany::RegistryBuilder;
+    use serde::{ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::{
+        anymap::unpack_type_id,
+        serdeany::{
+            stable_name_of, NamedSerdeAnyMap, PortableSerdeAny, RegistryBuilder, SerdeAny,
+            SerdeAnyMap, VersionedData, VersionedSerdeAny,
+        },
+        Error,
+    };
 
     #[derive(Debug, Serialize, Deserialize)]
     struct MyType(u32);
@@ -909,7 +1348,7 @@ mod tests {
         use serde::{Deserialize, Serialize};
 
         #[derive(Debug, Serialize, Deserialize)]
-        pub(super) struct MyType(f32);
+        pub(super) struct MyType(pub(super) f32);
         impl_serdeany!(MyType);
     }
 
@@ -929,4 +1368,175 @@ mod tests {
         );
         assert!(postcard::from_bytes::<inner::MyType>(&serialized).is_err());
     }
+
+    /// The old layout of [`MyVersionedType`]
+    #[derive(Debug, Serialize, Deserialize)]
+    struct MyVersionedTypeV1 {
+        a: u32,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct MyVersionedType {
+        a: u32,
+        b: u64,
+    }
+
+    impl VersionedSerdeAny for MyVersionedType {
+        const SCHEMA_VERSION: u32 = 2;
+
+        fn migrate(version: u32, data: &[u8]) -> Result<Self, Error> {
+            match version {
+                1 => {
+                    let old: MyVersionedTypeV1 = postcard::from_bytes(data)?;
+                    Ok(Self { a: old.a, b: 0 })
+                }
+                _ => Err(Error::serialize("unknown version")),
+            }
+        }
+
+        fn deserialize_legacy<'de, D>(deserializer: D) -> Result<Self, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let old = MyVersionedTypeV1::deserialize(deserializer)?;
+            Ok(Self { a: old.a, b: 0 })
+        }
+    }
+    impl_serdeany_versioned!(MyVersionedType);
+
+    /// A [`MyVersionedType`] serialized as a [`SerdeAny`] before the type was versioned
+    struct Legacy(MyVersionedTypeV1);
+
+    impl Serialize for Legacy {
+        fn serialize<S>(&self, se: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            let mut seq = se.serialize_seq(Some(2))?;
+            seq.serialize_element(&unpack_type_id(TypeId::of::<MyVersionedType>()))?;
+            seq.serialize_element(&self.0)?;
+            seq.end()
+        }
+    }
+
+    /// A type without a stable name
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Unnamed;
+
+    impl SerdeAny for Unnamed {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+
+        fn as_any_boxed(self: Box<Self>) -> Box<dyn Any> {
+            self
+        }
+    }
+
+    #[test]
+    fn test_versioned_serdeany() {
+        unsafe {
+            RegistryBuilder::register_versioned::<MyVersionedType>();
+        }
+
+        let mut map = SerdeAnyMap::new();
+        map.insert(MyVersionedType { a: 1, b: 2 });
+        let serialized = postcard::to_allocvec(&map).unwrap();
+        let map: SerdeAnyMap = postcard::from_bytes(&serialized).unwrap();
+        let value = map.get::<MyVersionedType>().unwrap();
+        assert_eq!((value.a, value.b), (1, 2));
+
+        // Data of an older schema is migrated
+        let old = VersionedData {
+            version: 1,
+            data: postcard::to_allocvec(&MyVersionedTypeV1 { a: 3 }).unwrap(),
+        };
+        let value: MyVersionedType = old.decode().unwrap();
+        assert_eq!((value.a, value.b), (3, 0));
+
+        // Data of a newer schema is rejected
+        let new = VersionedData {
+            version: 3,
+            data: vec![],
+        };
+        assert!(new.decode::<MyVersionedType>().is_err());
+
+        // Data serialized before the type was versioned is still loaded
+        let legacy = postcard::to_allocvec(&Legacy(MyVersionedTypeV1 { a: 4 })).unwrap();
+        let value: Box<dyn SerdeAny> = postcard::from_bytes(&legacy).unwrap();
+        let value = value.as_any().downcast_ref::<MyVersionedType>().unwrap();
+        assert_eq!((value.a, value.b), (4, 0));
+    }
+
+    #[test]
+    fn test_named_serdeany_map() {
+        unsafe {
+            RegistryBuilder::register::<MyType>();
+            RegistryBuilder::register::<inner::MyType>();
+        }
+
+        let mut map = NamedSerdeAnyMap::new();
+        map.insert("a", MyType(1));
+        map.insert("b", MyType(2));
+        map.insert("a", inner::MyType(3.0));
+
+        let mut names = map
+            .entries()
+            .map(|(name, x)| (name, x.type_name()))
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[0], ("a", core::any::type_name::<MyType>()));
+        assert!(names[1].1.ends_with("inner::MyType"));
+        assert_eq!(names[2], ("b", core::any::type_name::<MyType>()));
+
+        // Removing a name keeps the elements of other names
+        assert_eq!(map.remove_all("a"), 2);
+        assert_eq!(map.entries().count(), 1);
+        assert_eq!(map.get::<MyType>("b").unwrap().0, 2);
+    }
+
+    #[test]
+    fn test_portable_serdeany() {
+        unsafe {
+            RegistryBuilder::register::<MyType>();
+            RegistryBuilder::register_stable_name::<MyType>("tests::MyType");
+            RegistryBuilder::register_versioned::<MyVersionedType>();
+            RegistryBuilder::register_stable_name::<MyVersionedType>("tests::MyVersionedType");
+        }
+        assert_eq!(stable_name_of::<MyType>(), Some("tests::MyType"));
+
+        let mut map = SerdeAnyMap::new();
+        map.insert(MyType(7));
+        map.insert(MyVersionedType { a: 1, b: 2 });
+        let portable = map
+            .values()
+            .map(|x| PortableSerdeAny::encode(x).unwrap().unwrap())
+            .collect::<Vec<_>>();
+        assert!(portable
+            .iter()
+            .any(|value| value.stable_name == "tests::MyType"));
+
+        let mut loaded = SerdeAnyMap::new();
+        for value in &portable {
+            loaded.insert_dyn(value.decode().unwrap().unwrap());
+        }
+        assert_eq!(loaded.get::<MyType>().unwrap().0, 7);
+        let value = loaded.get::<MyVersionedType>().unwrap();
+        assert_eq!((value.a, value.b), (1, 2));
+
+        // Types without a stable name are not encoded
+        assert!(PortableSerdeAny::encode(&Unnamed).unwrap().is_none());
+
+        // Unknown types are recognized
+        let unknown = PortableSerdeAny {
+            stable_name: "unknown::Type".into(),
+            data: vec![],
+        };
+        assert!(unknown.decode().unwrap().is_none());
+    }
 }
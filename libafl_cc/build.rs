@@ -429,6 +429,7 @@ pub const LIBAFL_CC_LLVM_VERSION: Option<usize> = None;
 
     for pass in &[
         "function-logging.cc",
+        "function-coverage-pass.cc",
         "cmplog-routines-pass.cc",
         "autotokens-pass.cc",
         "coverage-accounting-pass.cc",
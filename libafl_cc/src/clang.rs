@@ -43,6 +43,8 @@ pub enum LLVMPasses {
     Ctx,
     /// Function logging
     FunctionLogging,
+    /// Function coverage, with a symbol table written to `FUNCTION_SYMBOLS_OUTPUT_PATH`
+    FunctionCoverage,
     /// Profiling
     Profiling,
     /// Data dependency instrumentation
@@ -73,6 +75,8 @@ impl LLVMPasses {
             LLVMPasses::FunctionLogging => {
                 PathBuf::from(env!("OUT_DIR")).join(format!("function-logging.{}", dll_extension()))
             }
+            LLVMPasses::FunctionCoverage => PathBuf::from(env!("OUT_DIR"))
+                .join(format!("function-coverage-pass.{}", dll_extension())),
             LLVMPasses::Profiling => {
                 PathBuf::from(env!("OUT_DIR")).join(format!("profiling.{}", dll_extension()))
             }
@@ -0,0 +1,187 @@
+/*
+   LibAFL - Function Coverage LLVM pass
+   --------------------------------------------------
+
+   Copyright 2024 AFLplusplus Project. All rights reserved.
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at:
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+*/
+
+#include <stdio.h>
+#include <stdlib.h>
+#include "common-llvm.h"
+#ifndef _WIN32
+  #include <unistd.h>
+  #include <sys/time.h>
+#else
+  #include <io.h>
+#endif
+#include <string.h>
+#include <sys/types.h>
+#include <sys/stat.h>
+#include <fcntl.h>
+#include <ctype.h>
+
+#include <list>
+#include <string>
+#include <fstream>
+#include <set>
+#include <algorithm>
+
+#include "llvm/Config/llvm-config.h"
+#include "llvm/ADT/Statistic.h"
+#include "llvm/IR/IRBuilder.h"
+
+#if USE_NEW_PM
+  #include "llvm/Passes/PassPlugin.h"
+  #include "llvm/Passes/PassBuilder.h"
+  #include "llvm/IR/PassManager.h"
+#else
+  #include "llvm/IR/LegacyPassManager.h"
+  #include "llvm/Transforms/IPO/PassManagerBuilder.h"
+#endif
+
+#include "llvm/IR/BasicBlock.h"
+#include "llvm/IR/Module.h"
+#include "llvm/IR/DebugInfo.h"
+#include "llvm/IR/CFG.h"
+#include "llvm/IR/Verifier.h"
+#include "llvm/Support/Debug.h"
+#include "llvm/Support/raw_ostream.h"
+#include "llvm/Transforms/Utils/BasicBlockUtils.h"
+#include "llvm/Analysis/LoopInfo.h"
+#include "llvm/Analysis/ValueTracking.h"
+#include "llvm/Pass.h"
+#include "llvm/IR/Constants.h"
+
+#include <iostream>
+
+using namespace llvm;
+
+namespace {
+
+/* The function ids have to be stable across compilers and runs, as they are
+   matched against the symbol table at runtime, so we can't use std::hash. */
+uint64_t fnv1a_64(StringRef s) {
+  uint64_t hash = 0xcbf29ce484222325ULL;
+  for (unsigned char c : s) {
+    hash ^= c;
+    hash *= 0x100000001b3ULL;
+  }
+  return hash;
+}
+
+#if USE_NEW_PM
+class FunctionCoverage : public PassInfoMixin<FunctionCoverage> {
+ public:
+  FunctionCoverage() {
+#else
+class FunctionCoverage : public ModulePass {
+ public:
+  static char ID;
+
+  FunctionCoverage() : ModulePass(ID) {
+#endif
+  }
+
+#if USE_NEW_PM
+  PreservedAnalyses run(Module &M, ModuleAnalysisManager &MAM);
+#else
+  bool runOnModule(Module &M) override;
+#endif
+};
+
+}  // namespace
+
+#if USE_NEW_PM
+extern "C" ::llvm::PassPluginLibraryInfo LLVM_ATTRIBUTE_WEAK
+llvmGetPassPluginInfo() {
+  return {LLVM_PLUGIN_API_VERSION, "FunctionCoveragePass", "v0.1",
+          /* lambda to insert our pass into the pass pipeline. */
+          [](PassBuilder &PB) {
+
+  #if LLVM_VERSION_MAJOR <= 13
+            using OptimizationLevel = typename PassBuilder::OptimizationLevel;
+  #endif
+            PB.registerOptimizerLastEPCallback(
+                [](ModulePassManager &MPM, OptimizationLevel OL) {
+                  MPM.addPass(FunctionCoverage());
+                });
+          }};
+}
+#else
+char FunctionCoverage::ID = 0;
+#endif
+
+#if USE_NEW_PM
+PreservedAnalyses FunctionCoverage::run(Module &M, ModuleAnalysisManager &MAM) {
+#else
+bool FunctionCoverage::runOnModule(Module &M) {
+
+#endif
+  LLVMContext   &C = M.getContext();
+  Type          *VoidTy = Type::getVoidTy(C);
+  IntegerType   *Int64Ty = IntegerType::getInt64Ty(C);
+  FunctionCallee coverageHook = M.getOrInsertFunction(
+      "__libafl_target_function_coverage_hook", VoidTy, Int64Ty);
+
+  /* The symbol table: one `<id in hex> <function name>` line per function */
+  std::string symbols;
+
+  for (auto &F : M) {
+    if (isIgnoreFunction(&F)) { continue; }
+    if (F.size() < 1) { continue; }
+
+    StringRef name = F.getName();
+    uint64_t  function_id = fnv1a_64(name);
+
+    char id_str[17];
+    snprintf(id_str, sizeof(id_str), "%016llx",
+             (unsigned long long)function_id);
+    symbols += std::string(id_str) + " " + name.str() + "\n";
+
+    // instrument the first basic block of this fn
+    BasicBlock &entry = F.front();
+    IRBuilder<> IRB(&entry);
+    IRB.SetInsertPoint(&*entry.getFirstInsertionPt());
+    IRB.CreateCall(coverageHook, {ConstantInt::get(Int64Ty, function_id)});
+  }
+
+  if (getenv("FUNCTION_SYMBOLS_OUTPUT_PATH")) {
+    std::string module_name = M.getName().str();
+    std::replace(module_name.begin(), module_name.end(), '/', '_');
+    std::ofstream symbols_out(getenv("FUNCTION_SYMBOLS_OUTPUT_PATH") +
+                              std::string("/") + module_name + ".funcs");
+    symbols_out << symbols;
+  }
+
+#if USE_NEW_PM
+  auto PA = PreservedAnalyses::none();
+  return PA;
+#else
+  return true;
+#endif
+}
+
+#if USE_NEW_PM
+
+#else
+static void registerFunctionCoveragePass(const PassManagerBuilder &,
+                                         legacy::PassManagerBase &PM) {
+  PM.add(new FunctionCoverage());
+}
+
+static RegisterPass<FunctionCoverage> X("function-coverage",
+                                        "function coverage pass", false, false);
+
+static RegisterStandardPasses RegisterFunctionCoverage(
+    PassManagerBuilder::EP_OptimizerLast, registerFunctionCoveragePass);
+
+static RegisterStandardPasses RegisterFunctionCoverage0(
+    PassManagerBuilder::EP_EnabledOnOptLevel0, registerFunctionCoveragePass);
+#endif
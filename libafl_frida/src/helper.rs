@@ -29,7 +29,12 @@ use yaxpeax_x86::amd64::InstDecoder;
 
 #[cfg(feature = "cmplog")]
 use crate::cmplog_rt::CmpLogRuntime;
-use crate::{asan::asan_rt::AsanRuntime, coverage_rt::CoverageRuntime, drcov_rt::DrCovRuntime};
+use crate::{
+    asan::asan_rt::AsanRuntime,
+    coverage_rt::CoverageRuntime,
+    drcov_rt::DrCovRuntime,
+    instrumentation_cache::{CachedAnalysis, InstrumentationCache},
+};
 
 /// The Runtime trait
 pub trait FridaRuntime: 'static + Debug {
@@ -143,6 +148,7 @@ pub struct FridaInstrumentationHelperBuilder {
     instrument_module_predicate: Option<Box<dyn FnMut(&ModuleDetails) -> bool>>,
     skip_module_predicate: Box<dyn FnMut(&ModuleDetails) -> bool>,
     skip_ranges: Vec<SkipRange>,
+    instrumentation_cache_dir: Option<PathBuf>,
 }
 
 impl FridaInstrumentationHelperBuilder {
@@ -243,6 +249,17 @@ impl FridaInstrumentationHelperBuilder {
         self
     }
 
+    /// Persist the instrumentation decisions in the given directory, to speed up the
+    /// instrumentation after a restart, see [`InstrumentationCache`].
+    /// Use the same directory for all restarts of a client.
+    #[must_use]
+    pub fn instrumentation_cache_dir<P: Into<PathBuf>>(self, dir: P) -> Self {
+        Self {
+            instrumentation_cache_dir: Some(dir.into()),
+            ..self
+        }
+    }
+
     /// Build a [`FridaInstrumentationHelper`]
     pub fn build<RT: FridaRuntimeTuple>(
         self,
@@ -255,6 +272,7 @@ impl FridaInstrumentationHelperBuilder {
             mut instrument_module_predicate,
             mut skip_module_predicate,
             skip_ranges,
+            instrumentation_cache_dir,
         } = self;
 
         let mut module_filter = Box::new(move |module| {
@@ -276,6 +294,7 @@ impl FridaInstrumentationHelperBuilder {
         // These moves MUST occur before the runtimes are init-ed
         let ranges = Rc::new(RefCell::new(ranges));
         let runtimes = Rc::new(RefCell::new(runtimes));
+        let instrumentation_cache = Rc::new(RefCell::new(None));
 
         if stalker_enabled {
             for (i, module) in module_map.values().iter().enumerate() {
@@ -305,9 +324,30 @@ impl FridaInstrumentationHelperBuilder {
             runtimes
                 .borrow_mut()
                 .init_all(gum, &ranges.borrow(), &module_map);
+
+            if let Some(dir) = instrumentation_cache_dir {
+                match InstrumentationCache::new(&dir) {
+                    Ok(mut cache) => {
+                        for module in module_map.values() {
+                            let range = module.range();
+                            let start = range.base_address().0 as usize;
+                            cache.add_module(&module.path(), start..(start + range.size()));
+                        }
+                        *instrumentation_cache.borrow_mut() = Some(cache);
+                    }
+                    Err(err) => {
+                        log::warn!("Failed to open the instrumentation cache at {dir:?}: {err}");
+                    }
+                }
+            }
         }
 
-        let transformer = FridaInstrumentationHelper::build_transformer(gum, &ranges, &runtimes);
+        let transformer = FridaInstrumentationHelper::build_transformer(
+            gum,
+            &ranges,
+            &runtimes,
+            &instrumentation_cache,
+        );
 
         #[cfg(unix)]
         FridaInstrumentationHelper::<'_, RT>::workaround_gum_allocate_near();
@@ -316,6 +356,7 @@ impl FridaInstrumentationHelperBuilder {
             transformer,
             ranges,
             runtimes,
+            instrumentation_cache,
             stalker_enabled,
             disable_excludes,
         }
@@ -330,6 +371,7 @@ impl Debug for FridaInstrumentationHelperBuilder {
             .field("instrument_module_predicate", &"<closure>")
             .field("skip_module_predicate", &"<closure>")
             .field("skip_ranges", &self.skip_ranges)
+            .field("instrumentation_cache_dir", &self.instrumentation_cache_dir)
             .field("disable_excludes", &self.disable_excludes);
         dbg_me.finish()
     }
@@ -349,6 +391,7 @@ impl Default for FridaInstrumentationHelperBuilder {
                 range.contains(&(Self::new as usize))
             }),
             skip_ranges: Vec::new(),
+            instrumentation_cache_dir: None,
         }
     }
 }
@@ -358,6 +401,7 @@ pub struct FridaInstrumentationHelper<'a, RT: 'a> {
     transformer: Transformer<'a>,
     ranges: Rc<RefCell<RangeMap<usize, (u16, String)>>>,
     runtimes: Rc<RefCell<RT>>,
+    instrumentation_cache: Rc<RefCell<Option<InstrumentationCache>>>,
     stalker_enabled: bool,
     pub(crate) disable_excludes: bool,
 }
@@ -368,6 +412,7 @@ impl<RT> Debug for FridaInstrumentationHelper<'_, RT> {
         dbg_me
             .field("ranges", &self.ranges)
             .field("module_map", &"<ModuleMap>")
+            .field("instrumentation_cache", &self.instrumentation_cache)
             .field("stalker_enabled", &self.stalker_enabled);
         dbg_me.finish()
     }
@@ -386,6 +431,30 @@ pub fn get_module_size(module_name: &str) -> usize {
     code_size
 }
 
+/// Runs the analysis of the instruction at the address, unless the cache knows it finds nothing,
+/// and remembers if it finds nothing
+fn cached_analysis<T, F>(
+    cache: &mut Option<InstrumentationCache>,
+    analysis: CachedAnalysis,
+    address: usize,
+    analyze: F,
+) -> Option<T>
+where
+    F: FnOnce() -> Option<T>,
+{
+    let Some(cache) = cache else {
+        return analyze();
+    };
+    if cache.is_uninteresting(analysis, address) {
+        return None;
+    }
+    let res = analyze();
+    if res.is_none() {
+        cache.mark_uninteresting(analysis, address);
+    }
+    res
+}
+
 fn pathlist_contains_module<I, P>(list: I, module: &ModuleDetails) -> bool
 where
     I: IntoIterator<Item = P>,
@@ -449,9 +518,11 @@ where
         gum: &'a Gum,
         ranges: &Rc<RefCell<RangeMap<usize, (u16, String)>>>,
         runtimes: &Rc<RefCell<RT>>,
+        instrumentation_cache: &Rc<RefCell<Option<InstrumentationCache>>>,
     ) -> Transformer<'a> {
         let ranges = Rc::clone(ranges);
         let runtimes = Rc::clone(runtimes);
+        let instrumentation_cache = Rc::clone(instrumentation_cache);
 
         #[cfg(target_arch = "x86_64")]
         let decoder = InstDecoder::default();
@@ -460,7 +531,14 @@ where
         let decoder = <ARMv8 as Arch>::Decoder::default();
 
         Transformer::from_callback(gum, move |basic_block, output| {
-            Self::transform(basic_block, &output, &ranges, &runtimes, decoder);
+            Self::transform(
+                basic_block,
+                &output,
+                &ranges,
+                &runtimes,
+                &instrumentation_cache,
+                decoder,
+            );
         })
     }
 
@@ -470,8 +548,10 @@ where
         output: &StalkerOutput,
         ranges: &Rc<RefCell<RangeMap<usize, (u16, String)>>>,
         runtimes_unborrowed: &Rc<RefCell<RT>>,
+        instrumentation_cache: &Rc<RefCell<Option<InstrumentationCache>>>,
         decoder: InstDecoder,
     ) {
+        let mut instrumentation_cache = instrumentation_cache.borrow_mut();
         let mut first = true;
         let mut basic_block_start = 0;
         let mut basic_block_size = 0;
@@ -506,7 +586,12 @@ where
                 }
 
                 let res = if let Some(_rt) = runtimes.match_first_type_mut::<AsanRuntime>() {
-                    AsanRuntime::asan_is_interesting_instruction(decoder, address, instr)
+                    cached_analysis(
+                        &mut instrumentation_cache,
+                        CachedAnalysis::Asan,
+                        address as usize,
+                        || AsanRuntime::asan_is_interesting_instruction(decoder, address, instr),
+                    )
                 } else {
                     None
                 };
@@ -554,8 +639,16 @@ where
                     any(target_arch = "aarch64", target_arch = "x86_64")
                 ))]
                 if let Some(rt) = runtimes.match_first_type_mut::<CmpLogRuntime>() {
-                    if let Some((op1, op2, shift, special_case)) =
-                        CmpLogRuntime::cmplog_is_interesting_instruction(decoder, address, instr)
+                    if let Some((op1, op2, shift, special_case)) = cached_analysis(
+                        &mut instrumentation_cache,
+                        CachedAnalysis::CmpLog,
+                        address as usize,
+                        || {
+                            CmpLogRuntime::cmplog_is_interesting_instruction(
+                                decoder, address, instr,
+                            )
+                        },
+                    )
                     //change this as well
                     {
                         //emit code that saves the relevant data in runtime(passes it to x0, x1)
@@ -600,6 +693,11 @@ where
     /// Clean up all runtimes
     pub fn deinit(&mut self, gum: &Gum) {
         (*self.runtimes).borrow_mut().deinit_all(gum);
+        if let Some(cache) = self.instrumentation_cache.borrow_mut().as_mut() {
+            if let Err(err) = cache.persist() {
+                log::warn!("Failed to persist the instrumentation cache: {err}");
+            }
+        }
     }
 
     /*
@@ -667,6 +765,9 @@ where
 
     /// Method called after execution
     pub fn post_exec<I: Input + HasTargetBytes>(&mut self, input: &I) -> Result<(), Error> {
+        if let Some(cache) = self.instrumentation_cache.borrow_mut().as_mut() {
+            cache.persist_if_due()?;
+        }
         (*self.runtimes).borrow_mut().post_exec_all(input)
     }
 
@@ -0,0 +1,291 @@
+//! A cache of instrumentation decisions, persisted across client restarts.
+//!
+//! The code translated by the Stalker can't be reused after a restart, as it lives in the memory of
+//! the old process. What can be reused are the decisions taken while translating: most
+//! instructions need neither an `ASan` check nor `CmpLog` handling, and finding that out means
+//! decoding each of them again. The [`InstrumentationCache`] remembers, per module, the offsets of
+//! instructions that turned out to be uninteresting, and skips decoding them after a restart.
+//!
+//! Entries are keyed by the build-id of the module, or by a hash of the module file if it has
+//! none, so a rebuilt binary never reuses stale decisions. Only negative decisions are cached: an
+//! instruction missing from the cache is simply analyzed again.
+
+use core::{fmt::Write, ops::Range, time::Duration};
+use std::{
+    fs,
+    hash::{BuildHasher, Hasher},
+    path::{Path, PathBuf},
+};
+
+use ahash::RandomState;
+use hashbrown::HashSet;
+use libafl::Error;
+use libafl_bolts::{build_id::gnu_build_id, current_time, fs::write_file_atomic};
+use rangemap::RangeMap;
+
+/// The version of the cache format and of the analyses; bump it when either changes
+const CACHE_VERSION: u32 = 1;
+
+/// How often the cache is written while fuzzing, at most
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The analyses whose results are cached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachedAnalysis {
+    /// Whether an instruction needs an `ASan` shadow check
+    Asan,
+    /// Whether an instruction is a comparison for `CmpLog`
+    CmpLog,
+}
+
+impl CachedAnalysis {
+    fn tag(self) -> char {
+        match self {
+            Self::Asan => 'a',
+            Self::CmpLog => 'c',
+        }
+    }
+}
+
+/// The cached decisions for a single module
+#[derive(Debug)]
+struct ModuleCache {
+    base: usize,
+    key: String,
+    asan: HashSet<usize>,
+    cmplog: HashSet<usize>,
+    dirty: bool,
+}
+
+impl ModuleCache {
+    fn set(&self, analysis: CachedAnalysis) -> &HashSet<usize> {
+        match analysis {
+            CachedAnalysis::Asan => &self.asan,
+            CachedAnalysis::CmpLog => &self.cmplog,
+        }
+    }
+
+    fn set_mut(&mut self, analysis: CachedAnalysis) -> &mut HashSet<usize> {
+        match analysis {
+            CachedAnalysis::Asan => &mut self.asan,
+            CachedAnalysis::CmpLog => &mut self.cmplog,
+        }
+    }
+
+    fn parse(&mut self, contents: &str) {
+        let mut lines = contents.lines();
+        if lines.next() != Some(format!("v{CACHE_VERSION}").as_str()) {
+            return;
+        }
+        for line in lines {
+            let Some((tag, offset)) = line.split_once(' ') else {
+                continue;
+            };
+            let Ok(offset) = usize::from_str_radix(offset, 16) else {
+                continue;
+            };
+            match tag {
+                "a" => self.asan.insert(offset),
+                "c" => self.cmplog.insert(offset),
+                _ => continue,
+            };
+        }
+    }
+
+    fn serialize(&self) -> String {
+        let mut contents = format!("v{CACHE_VERSION}\n");
+        for analysis in [CachedAnalysis::Asan, CachedAnalysis::CmpLog] {
+            for offset in self.set(analysis) {
+                writeln!(contents, "{} {offset:x}", analysis.tag()).unwrap();
+            }
+        }
+        contents
+    }
+}
+
+/// Caches, per module, the instructions that need no instrumentation, and persists them in a
+/// directory shared by the restarts of a client
+#[derive(Debug)]
+pub struct InstrumentationCache {
+    dir: PathBuf,
+    ranges: RangeMap<usize, usize>,
+    modules: Vec<ModuleCache>,
+    last_persisted: Duration,
+}
+
+impl InstrumentationCache {
+    /// Creates a new [`InstrumentationCache`], persisted in the given directory
+    pub fn new<P>(dir: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            ranges: RangeMap::new(),
+            modules: Vec::new(),
+            last_persisted: current_time(),
+        })
+    }
+
+    /// Adds an instrumented module, loading its cached decisions.
+    /// Modules whose file can't be read are not cached.
+    pub fn add_module(&mut self, path: &str, range: Range<usize>) {
+        let Some(key) = module_key(Path::new(path)) else {
+            log::info!("Not caching the instrumentation of {path}: failed to read the module");
+            return;
+        };
+        let mut module = ModuleCache {
+            base: range.start,
+            key,
+            asan: HashSet::new(),
+            cmplog: HashSet::new(),
+            dirty: false,
+        };
+        if let Ok(contents) = fs::read_to_string(cache_file(&self.dir, &module.key)) {
+            module.parse(&contents);
+            log::info!(
+                "Loaded {} cached instrumentation decisions for {path}",
+                module.asan.len() + module.cmplog.len()
+            );
+        }
+        self.ranges.insert(range, self.modules.len());
+        self.modules.push(module);
+    }
+
+    /// Checks if the instruction at the address is known to need no instrumentation
+    #[must_use]
+    pub fn is_uninteresting(&self, analysis: CachedAnalysis, address: usize) -> bool {
+        self.ranges.get(&address).is_some_and(|idx| {
+            let module = &self.modules[*idx];
+            module.set(analysis).contains(&(address - module.base))
+        })
+    }
+
+    /// Remembers that the instruction at the address needs no instrumentation
+    pub fn mark_uninteresting(&mut self, analysis: CachedAnalysis, address: usize) {
+        if let Some(idx) = self.ranges.get(&address) {
+            let module = &mut self.modules[*idx];
+            let offset = address - module.base;
+            module.dirty |= module.set_mut(analysis).insert(offset);
+        }
+    }
+
+    /// Writes the decisions of all modules with new entries
+    pub fn persist(&mut self) -> Result<(), Error> {
+        for module in self.modules.iter_mut().filter(|module| module.dirty) {
+            write_file_atomic(
+                cache_file(&self.dir, &module.key),
+                module.serialize().as_bytes(),
+            )?;
+            module.dirty = false;
+        }
+        self.last_persisted = current_time();
+        Ok(())
+    }
+
+    /// Writes the decisions, if the last write was long enough ago.
+    /// Called after each execution, as clients usually restart by crashing.
+    pub fn persist_if_due(&mut self) -> Result<(), Error> {
+        // The wall clock may be set back in the meantime
+        if current_time().saturating_sub(self.last_persisted) >= PERSIST_INTERVAL {
+            self.persist()?;
+        }
+        Ok(())
+    }
+}
+
+/// The file the decisions of the module with the given key are persisted in
+fn cache_file(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{key}.cache"))
+}
+
+/// The key of a module: its GNU build-id if it has one, else a hash of the module file
+fn module_key(path: &Path) -> Option<String> {
+    let bytes = fs::read(path).ok()?;
+    if let Some(build_id) = gnu_build_id(&bytes) {
+        return Some(build_id.iter().fold(String::new(), |mut key, b| {
+            write!(key, "{b:02x}").unwrap();
+            key
+        }));
+    }
+    let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+    hasher.write(&bytes);
+    Some(format!("hash-{:016x}", hasher.finish()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env::temp_dir, fs};
+
+    use super::{module_key, CachedAnalysis, InstrumentationCache, ModuleCache};
+
+    /// A minimal little-endian ELF blob with a GNU build-id note
+    fn elf_with_build_id(build_id: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x7fELF".to_vec();
+        bytes.resize(64, 0);
+        bytes.extend_from_slice(&4_u32.to_le_bytes());
+        bytes.extend_from_slice(&u32::try_from(build_id.len()).unwrap().to_le_bytes());
+        bytes.extend_from_slice(b"\x03\x00\x00\x00GNU\x00");
+        bytes.extend_from_slice(build_id);
+        bytes
+    }
+
+    #[test]
+    fn test_module_cache_roundtrip() {
+        let mut module = ModuleCache {
+            base: 0x1000,
+            key: "key".into(),
+            asan: [0x10, 0x20].into_iter().collect(),
+            cmplog: [0x30].into_iter().collect(),
+            dirty: false,
+        };
+        let contents = module.serialize();
+        module.asan.clear();
+        module.cmplog.clear();
+        module.parse(&contents);
+        assert_eq!(module.asan, [0x10, 0x20].into_iter().collect());
+        assert_eq!(module.cmplog, [0x30].into_iter().collect());
+
+        // Caches of other versions are ignored
+        module.asan.clear();
+        module.cmplog.clear();
+        module.parse(&contents.replacen('v', "v0", 1));
+        assert!(module.asan.is_empty() && module.cmplog.is_empty());
+    }
+
+    #[test]
+    fn test_instrumentation_cache_persists() {
+        let dir = temp_dir().join(format!(
+            "libafl_frida_instrumentation_cache_{}",
+            std::process::id()
+        ));
+        let cache_dir = dir.join("cache");
+        fs::create_dir_all(&dir).unwrap();
+        let module_path = dir.join("module.so");
+        fs::write(&module_path, elf_with_build_id(&[7; 20])).unwrap();
+        let module_path = module_path.to_str().unwrap();
+        assert_eq!(module_key(module_path.as_ref()).unwrap(), "07".repeat(20));
+
+        let mut cache = InstrumentationCache::new(&cache_dir).unwrap();
+        cache.add_module(module_path, 0x1000..0x2000);
+        cache.add_module("/nonexistent/module.so", 0x3000..0x4000);
+        cache.mark_uninteresting(CachedAnalysis::Asan, 0x1010);
+        cache.mark_uninteresting(CachedAnalysis::Asan, 0x3010);
+        assert!(cache.is_uninteresting(CachedAnalysis::Asan, 0x1010));
+        assert!(!cache.is_uninteresting(CachedAnalysis::CmpLog, 0x1010));
+        assert!(!cache.is_uninteresting(CachedAnalysis::Asan, 0x3010));
+        // Not due yet, right after creating the cache
+        cache.persist_if_due().unwrap();
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 0);
+        cache.persist().unwrap();
+
+        // After a restart, the module may be loaded at another address
+        let mut cache = InstrumentationCache::new(&cache_dir).unwrap();
+        cache.add_module(module_path, 0x5000..0x6000);
+        assert!(cache.is_uninteresting(CachedAnalysis::Asan, 0x5010));
+        assert!(!cache.is_uninteresting(CachedAnalysis::Asan, 0x5020));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -87,6 +87,8 @@ pub mod cmplog_rt;
 /// The `LibAFL` firda helper
 pub mod helper;
 
+pub mod instrumentation_cache;
+
 pub mod drcov_rt;
 
 /// The frida executor
@@ -0,0 +1,194 @@
+//! Guest determinism helpers for reproducible full-system runs.
+//!
+//! [`GuestDeterminism`] generates the QEMU arguments that remove the usual sources of
+//! nondeterminism of a guest: the clocks (the guest TSC and the virtual clock are driven by
+//! the instruction counter instead of the host time), the RTC, the entropy sources, and
+//! generated MAC addresses.
+//!
+//! ```rust,ignore
+//! let mut args: Vec<String> = env::args().collect();
+//! args.extend(GuestDeterminism::new().with_seed(1337).with_virtio_rng(true).args());
+//! let qemu = Qemu::init(&args, &env)?;
+//! ```
+
+use std::fmt::Write;
+
+/// The default `shift` of the instruction counter: each instruction takes `2^shift` ns of virtual time
+pub const DEFAULT_ICOUNT_SHIFT: u32 = 5;
+
+/// The default (fixed) start date of the guest RTC
+pub const DEFAULT_RTC_BASE: &str = "2020-01-01T00:00:00";
+
+/// The default seed of the guest entropy sources
+pub const DEFAULT_GUEST_SEED: u64 = 0x00C0_FFEE;
+
+/// Configuration of the deterministic guest devices, turned into QEMU arguments with [`GuestDeterminism::args`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuestDeterminism {
+    icount_shift: Option<u32>,
+    rtc_base: Option<String>,
+    seed: Option<u64>,
+    virtio_rng: bool,
+    mac_address: Option<[u8; 6]>,
+    nic_model: String,
+}
+
+impl Default for GuestDeterminism {
+    /// Deterministic clocks, RTC and entropy; no additional devices
+    fn default() -> Self {
+        Self {
+            icount_shift: Some(DEFAULT_ICOUNT_SHIFT),
+            rtc_base: Some(DEFAULT_RTC_BASE.to_string()),
+            seed: Some(DEFAULT_GUEST_SEED),
+            virtio_rng: false,
+            mac_address: None,
+            nic_model: "virtio-net-pci".to_string(),
+        }
+    }
+}
+
+impl GuestDeterminism {
+    /// Creates a new [`GuestDeterminism`] with deterministic clocks, RTC and entropy
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drive the virtual clock (and the guest TSC) by the instruction counter with the given `shift`,
+    /// instead of the host time. `None` keeps the host-driven clocks.
+    #[must_use]
+    pub fn with_icount_shift(mut self, icount_shift: Option<u32>) -> Self {
+        self.icount_shift = icount_shift;
+        self
+    }
+
+    /// Start the guest RTC at the given date (e.g. `2020-01-01T00:00:00`), stepped by the virtual clock.
+    /// `None` keeps the host RTC.
+    #[must_use]
+    pub fn with_rtc_base<S>(mut self, rtc_base: Option<S>) -> Self
+    where
+        S: Into<String>,
+    {
+        self.rtc_base = rtc_base.map(Into::into);
+        self
+    }
+
+    /// Seed all guest entropy sources (including `virtio-rng` and `rdrand`) with `seed`
+    #[must_use]
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Use the host entropy for the guest
+    #[must_use]
+    pub fn without_seed(mut self) -> Self {
+        self.seed = None;
+        self
+    }
+
+    /// Add a `virtio-rng` device, fed by the (seeded) QEMU entropy source
+    #[must_use]
+    pub fn with_virtio_rng(mut self, virtio_rng: bool) -> Self {
+        self.virtio_rng = virtio_rng;
+        self
+    }
+
+    /// Add a user-mode network card with a stable MAC address
+    #[must_use]
+    pub fn with_mac_address(mut self, mac_address: [u8; 6]) -> Self {
+        self.mac_address = Some(mac_address);
+        self
+    }
+
+    /// The device model of the network card added by [`GuestDeterminism::with_mac_address`].
+    /// Defaults to `virtio-net-pci`.
+    #[must_use]
+    pub fn with_nic_model<S>(mut self, nic_model: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.nic_model = nic_model.into();
+        self
+    }
+
+    /// The QEMU arguments implementing this configuration
+    #[must_use]
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+
+        if let Some(shift) = self.icount_shift {
+            args.push("-icount".to_string());
+            args.push(format!("shift={shift},align=off,sleep=off"));
+        }
+
+        if let Some(rtc_base) = &self.rtc_base {
+            args.push("-rtc".to_string());
+            args.push(format!("base={rtc_base},clock=vm,driftfix=none"));
+        }
+
+        if let Some(seed) = self.seed {
+            args.push("-seed".to_string());
+            args.push(seed.to_string());
+        }
+
+        if self.virtio_rng {
+            args.push("-object".to_string());
+            args.push("rng-builtin,id=libafl_rng".to_string());
+            args.push("-device".to_string());
+            args.push("virtio-rng-pci,rng=libafl_rng".to_string());
+        }
+
+        if let Some(mac) = self.mac_address {
+            let mut mac_str = String::new();
+            for (i, byte) in mac.iter().enumerate() {
+                if i > 0 {
+                    mac_str.push(':');
+                }
+                write!(mac_str, "{byte:02x}").unwrap();
+            }
+            args.push("-nic".to_string());
+            args.push(format!("user,model={},mac={mac_str}", self.nic_model));
+        }
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GuestDeterminism;
+
+    #[test]
+    fn test_guest_determinism_args() {
+        let args = GuestDeterminism::new()
+            .with_seed(42)
+            .with_virtio_rng(true)
+            .with_mac_address([0x52, 0x54, 0, 0x12, 0x34, 0x56])
+            .args();
+        assert_eq!(
+            args,
+            [
+                "-icount",
+                "shift=5,align=off,sleep=off",
+                "-rtc",
+                "base=2020-01-01T00:00:00,clock=vm,driftfix=none",
+                "-seed",
+                "42",
+                "-object",
+                "rng-builtin,id=libafl_rng",
+                "-device",
+                "virtio-rng-pci,rng=libafl_rng",
+                "-nic",
+                "user,model=virtio-net-pci,mac=52:54:00:12:34:56",
+            ]
+        );
+
+        let args = GuestDeterminism::new()
+            .with_icount_shift(None)
+            .with_rtc_base(None::<String>)
+            .without_seed()
+            .args();
+        assert!(args.is_empty());
+    }
+}
@@ -47,6 +47,11 @@ mod systemmode;
 #[allow(unused_imports)]
 pub use systemmode::*;
 
+#[cfg(emulation_mode = "systemmode")]
+mod determinism;
+#[cfg(emulation_mode = "systemmode")]
+pub use determinism::*;
+
 pub const SKIP_EXEC_HOOK: u64 = u64::MAX;
 static mut QEMU_IS_INITIALIZED: bool = false;
 
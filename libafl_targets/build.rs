@@ -107,6 +107,11 @@ fn main() {
             println!("cargo:rerun-if-changed=src/value_profile.h");
         }
 
+        #[cfg(feature = "sancov_value_profile_bytes")]
+        {
+            sancov_cmp.define("SANCOV_VALUE_PROFILE_BYTES", "1");
+        }
+
         #[cfg(feature = "sancov_cmplog")]
         {
             sancov_cmp.define("SANCOV_CMPLOG", "1");
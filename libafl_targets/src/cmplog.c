@@ -102,6 +102,13 @@ void __libafl_targets_cmplog_instructions(uintptr_t k, uint8_t shape,
   cmplog_instructions_checked(k, shape, arg1, arg2);
 }
 
+// Generic cmplog callback for 128 bit instructions, split in 64 bit halves
+void __libafl_targets_cmplog_instructions_128(uintptr_t k, uint64_t lo1,
+                                              uint64_t hi1, uint64_t lo2,
+                                              uint64_t hi2) {
+  cmplog_instructions_128_checked(k, lo1, hi1, lo2, hi2);
+}
+
 // Very generic cmplog routines callback
 void __libafl_targets_cmplog_routines(uintptr_t k, const uint8_t *ptr1,
                                       const uint8_t *ptr2) {
@@ -118,16 +125,20 @@ void __libafl_targets_cmplog_routines(uintptr_t k, const uint8_t *ptr1,
 }
 
 // cmplog routines but with len specified
+// Comparisons longer than CMPLOG_RTN_LEN are logged in chunks.
 void __libafl_targets_cmplog_routines_len(uintptr_t k, const uint8_t *ptr1,
                                           const uint8_t *ptr2, size_t len) {
   if (!libafl_cmplog_enabled) { return; }
 
-  if (area_is_valid(ptr1, CMPLOG_RTN_LEN) <= 0 ||
-      area_is_valid(ptr2, CMPLOG_RTN_LEN) <= 0) {
+  len = MIN(len, CMPLOG_RTN_MAX_LEN);
+  int l1, l2;
+  if ((l1 = area_is_valid(ptr1, len)) <= 0 ||
+      (l2 = area_is_valid(ptr2, len)) <= 0) {
     return;
   }
+  len = MIN(len, (size_t)MIN(l1, l2));
 
-  cmplog_routines_checked(k, ptr1, ptr2, len);
+  cmplog_routines_chunked(k, ptr1, ptr2, len);
 }
 /*
   CMPLOG Callback for instructions
@@ -200,14 +211,17 @@ void __cmplog_ins_hook16_extended(uint128_t arg1, uint128_t arg2,
   k = (k >> 4) ^ (k << 8);
   k &= CMPLOG_MAP_W - 1;
 
-  cmplog_instructions_extended_checked(k, 15, arg1, arg2, attr);
+  cmplog_instructions_128_extended_checked(
+      k, 15, (uint64_t)arg1, (uint64_t)(arg1 >> 64), (uint64_t)arg2,
+      (uint64_t)(arg2 >> 64), attr);
 }
 void __cmplog_ins_hook16(uint128_t arg1, uint128_t arg2) {
   uintptr_t k = RETADDR;
   k = (k >> 4) ^ (k << 8);
   k &= CMPLOG_MAP_W - 1;
 
-  cmplog_instructions_checked(k, 16, arg1, arg2);
+  cmplog_instructions_128_checked(k, (uint64_t)arg1, (uint64_t)(arg1 >> 64),
+                                  (uint64_t)arg2, (uint64_t)(arg2 >> 64));
 }
 
 void __cmplog_ins_hookN_extended(uint128_t arg1, uint128_t arg2, uint8_t attr,
@@ -216,14 +230,25 @@ void __cmplog_ins_hookN_extended(uint128_t arg1, uint128_t arg2, uint8_t attr,
   k = (k >> 4) ^ (k << 8);
   k &= CMPLOG_MAP_W - 1;
 
-  cmplog_instructions_extended_checked(k, size - 1, arg1, arg2, attr);
+  if (size > 8) {
+    cmplog_instructions_128_extended_checked(
+        k, size - 1, (uint64_t)arg1, (uint64_t)(arg1 >> 64), (uint64_t)arg2,
+        (uint64_t)(arg2 >> 64), attr);
+  } else {
+    cmplog_instructions_extended_checked(k, size - 1, arg1, arg2, attr);
+  }
 }
 void __cmplog_ins_hookN(uint128_t arg1, uint128_t arg2, uint8_t size) {
   uintptr_t k = RETADDR;
   k = (k >> 4) ^ (k << 8);
   k &= CMPLOG_MAP_W - 1;
 
-  cmplog_instructions_checked(k, size, arg1, arg2);
+  if (size > 8) {
+    cmplog_instructions_128_checked(k, (uint64_t)arg1, (uint64_t)(arg1 >> 64),
+                                    (uint64_t)arg2, (uint64_t)(arg2 >> 64));
+  } else {
+    cmplog_instructions_checked(k, size, arg1, arg2);
+  }
 }
 #endif
 /*
@@ -264,17 +289,45 @@ void __cmplog_rtn_hook_extended(const uint8_t *ptr1, const uint8_t *ptr2) {
   cmplog_routines_checked_extended(k, ptr1, ptr2, len);
 }
 
+/* hook for memcmp-like functions, longer comparisons (e.g. of structs or
+   stack buffers copied from the input) are logged in chunks. */
 void __cmplog_rtn_hook_n(const uint8_t *ptr1, const uint8_t *ptr2,
                          uint64_t len) {
-  // this code is from AFL++
-  // but i don't know why afl++ just ignores the len argument
-  (void)(len);
-  __cmplog_rtn_hook(ptr1, ptr2);
+  if (!libafl_cmplog_enabled) { return; }
+  if (len <= CMPLOG_RTN_LEN) {
+    __cmplog_rtn_hook(ptr1, ptr2);
+    return;
+  }
+
+  int l = MIN(len, CMPLOG_RTN_MAX_LEN);
+  l = MIN(l, area_is_valid(ptr1, l));
+  l = MIN(l, area_is_valid(ptr2, l));
+  if (l <= 0) return;
+
+  uintptr_t k = RETADDR;
+  k = (k >> 4) ^ (k << 8);
+  k &= CMPLOG_MAP_W - 1;
+
+  cmplog_routines_chunked(k, ptr1, ptr2, l);
 }
 void __cmplog_rtn_hook_n_extended(const uint8_t *ptr1, const uint8_t *ptr2,
                                   uint64_t len) {
-  (void)(len);
-  __cmplog_rtn_hook_extended(ptr1, ptr2);
+  if (!libafl_cmplog_enabled) { return; }
+  if (len <= CMPLOG_RTN_LEN) {
+    __cmplog_rtn_hook_extended(ptr1, ptr2);
+    return;
+  }
+
+  int l = MIN(len, CMPLOG_RTN_MAX_LEN);
+  l = MIN(l, area_is_valid(ptr1, l));
+  l = MIN(l, area_is_valid(ptr2, l));
+  if (l <= 0) return;
+
+  uintptr_t k = RETADDR;
+  k = (k >> 4) ^ (k << 8);
+  k &= CMPLOG_MAP_W - 1;
+
+  cmplog_routines_chunked_extended(k, ptr1, ptr2, l);
 }
 /* hook for string functions, eg. strcmp, strcasecmp etc. */
 void __cmplog_rtn_hook_str(const uint8_t *ptr1, uint8_t *ptr2) {
@@ -327,7 +380,7 @@ void __cmplog_rtn_hook_strn(uint8_t *ptr1, uint8_t *ptr2, uint64_t len) {
   if (!libafl_cmplog_enabled) { return; }
   if (unlikely(!ptr1 || !ptr2)) return;
 
-  int len0 = MIN(len, 31);  // cap by 31
+  int len0 = MIN(len, CMPLOG_RTN_MAX_LEN);
   // these strnlen could indeed fail. but if it fails here it will sigsegv in
   // the following hooked function call anyways
   int len1 = strnlen(ptr1, len0);
@@ -343,7 +396,7 @@ void __cmplog_rtn_hook_strn(uint8_t *ptr1, uint8_t *ptr2, uint64_t len) {
   k = (k >> 4) ^ (k << 8);
   k &= CMPLOG_MAP_W - 1;
 
-  cmplog_routines_checked(k, ptr1, ptr2, l);
+  cmplog_routines_chunked(k, ptr1, ptr2, l);
 }
 /* hook for string with length functions, eg. strncmp, strncasecmp etc.
    Note that we ignore the len parameter and take longer strings if present. */
@@ -352,7 +405,7 @@ void __cmplog_rtn_hook_strn_extended(uint8_t *ptr1, uint8_t *ptr2,
   if (!libafl_cmplog_enabled) { return; }
   if (unlikely(!ptr1 || !ptr2)) return;
 
-  int len0 = MIN(len, 31);  // cap by 31
+  int len0 = MIN(len, CMPLOG_RTN_MAX_LEN);
   // these strnlen could indeed fail. but if it fails here it will sigsegv in
   // the following hooked function call anyways
   int len1 = strnlen(ptr1, len0);
@@ -368,7 +421,7 @@ void __cmplog_rtn_hook_strn_extended(uint8_t *ptr1, uint8_t *ptr2,
   k = (k >> 4) ^ (k << 8);
   k &= CMPLOG_MAP_W - 1;
 
-  cmplog_routines_checked_extended(k, ptr1, ptr2, l);
+  cmplog_routines_chunked_extended(k, ptr1, ptr2, l);
 }
 
 // gcc libstdc++
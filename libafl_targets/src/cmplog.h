@@ -18,6 +18,19 @@
   #define CMPLOG_RTN_LEN 32
 #endif
 
+// comparisons longer than CMPLOG_RTN_LEN are logged in chunks, up to this many.
+// A single long comparison must not take all CMPLOG_MAP_RTN_H slots of its key.
+#ifndef CMPLOG_RTN_MAX_CHUNKS
+  #define CMPLOG_RTN_MAX_CHUNKS (CMPLOG_MAP_RTN_H / 2)
+#endif
+#define CMPLOG_RTN_MAX_LEN (CMPLOG_RTN_LEN * CMPLOG_RTN_MAX_CHUNKS)
+
+// the shape of a 128 bit comparison in the (non extended) libafl map.
+// Such a comparison takes two consecutive operand slots: low halves, then high
+// halves. As the slots of a key are read by its shape, a key logging 128 bit
+// comparisons logs nothing else, and the other way around.
+#define CMPLOG_SHAPE_128 16
+
 #define CMPLOG_MAP_RTN_H \
   ((CMPLOG_MAP_H * sizeof(CmpLogInstruction)) / sizeof(CmpLogRoutine))
 
@@ -98,11 +111,15 @@ extern CmpLogMapExtended *libafl_cmplog_map_extended_ptr;
 
 extern uint8_t libafl_cmplog_enabled;
 
-// 5 of CMPLOG inner APIs, we static inline everything
+// CMPLOG inner APIs, we static inline everything
 // area_is_valid, cmplog_instructions_checked,
 // cmplog_instructions_extended_checked,
+// cmplog_instructions_128_checked,
+// cmplog_instructions_128_extended_checked,
 // cmplog_routines_checked,
-// cmplog_routines_checked_extended
+// cmplog_routines_checked_extended,
+// cmplog_routines_chunked,
+// cmplog_routines_chunked_extended
 
 static inline void cmplog_instructions_checked(uintptr_t k, uint8_t shape,
                                                uint64_t arg1, uint64_t arg2) {
@@ -115,6 +132,10 @@ static inline void cmplog_instructions_checked(uintptr_t k, uint8_t shape,
     libafl_cmplog_map_ptr->headers[k].hits = 1;
     libafl_cmplog_map_ptr->headers[k].shape = shape;
     hits = 0;
+  } else if (libafl_cmplog_map_ptr->headers[k].shape == CMPLOG_SHAPE_128) {
+    // the key is taken by 128 bit comparisons
+    libafl_cmplog_enabled = true;
+    return;
   } else {
     hits = libafl_cmplog_map_ptr->headers[k].hits++;
     if (libafl_cmplog_map_ptr->headers[k].shape < shape) {
@@ -163,9 +184,79 @@ static inline void cmplog_instructions_extended_checked(
 #endif
 }
 
+// 128 bit comparisons use two operand slots in the libafl map
+static inline void cmplog_instructions_128_checked(uintptr_t k, uint64_t lo1,
+                                                   uint64_t hi1, uint64_t lo2,
+                                                   uint64_t hi2) {
+  if (!libafl_cmplog_enabled) { return; }
+  libafl_cmplog_enabled = false;
+
+  uint16_t hits;
+  if (libafl_cmplog_map_ptr->headers[k].kind != CMPLOG_KIND_INS) {
+    libafl_cmplog_map_ptr->headers[k].kind = CMPLOG_KIND_INS;
+    libafl_cmplog_map_ptr->headers[k].hits = 1;
+    libafl_cmplog_map_ptr->headers[k].shape = CMPLOG_SHAPE_128;
+    hits = 0;
+  } else if (libafl_cmplog_map_ptr->headers[k].shape != CMPLOG_SHAPE_128) {
+    // the key is taken by smaller comparisons, keep their shape
+    libafl_cmplog_enabled = true;
+    return;
+  } else {
+    hits = libafl_cmplog_map_ptr->headers[k].hits++;
+  }
+
+  hits &= (CMPLOG_MAP_H / 2) - 1;
+  libafl_cmplog_map_ptr->vals.operands[k][2 * hits].v0 = lo1;
+  libafl_cmplog_map_ptr->vals.operands[k][2 * hits].v1 = lo2;
+  libafl_cmplog_map_ptr->vals.operands[k][2 * hits + 1].v0 = hi1;
+  libafl_cmplog_map_ptr->vals.operands[k][2 * hits + 1].v1 = hi2;
+  libafl_cmplog_enabled = true;
+}
+
+// the AFL++ map has dedicated fields for the upper halves
+static inline void cmplog_instructions_128_extended_checked(
+    uintptr_t k, uint8_t shape, uint64_t lo1, uint64_t hi1, uint64_t lo2,
+    uint64_t hi2, uint8_t attr) {
+#ifdef CMPLOG_EXTENDED
+  if (!libafl_cmplog_enabled) { return; }
+  libafl_cmplog_enabled = false;
+
+  uint16_t hits;
+  if (libafl_cmplog_map_extended_ptr->headers[k].type != CMPLOG_KIND_INS) {
+    libafl_cmplog_map_extended_ptr->headers[k].type = CMPLOG_KIND_INS;
+    libafl_cmplog_map_extended_ptr->headers[k].hits = 1;
+    libafl_cmplog_map_extended_ptr->headers[k].shape = shape;
+    hits = 0;
+  } else {
+    hits = libafl_cmplog_map_extended_ptr->headers[k].hits++;
+    if (libafl_cmplog_map_extended_ptr->headers[k].shape < shape) {
+      libafl_cmplog_map_extended_ptr->headers[k].shape = shape;
+    }
+  }
+
+  hits &= CMPLOG_MAP_H - 1;
+  libafl_cmplog_map_extended_ptr->vals.operands[k][hits].v0 = lo1;
+  libafl_cmplog_map_extended_ptr->vals.operands[k][hits].v1 = lo2;
+  libafl_cmplog_map_extended_ptr->vals.operands[k][hits].v0_128 = hi1;
+  libafl_cmplog_map_extended_ptr->vals.operands[k][hits].v1_128 = hi2;
+  libafl_cmplog_map_extended_ptr->headers[k].attribute = attr;
+  libafl_cmplog_enabled = true;
+#else
+  // just do nothing
+  (void)k;
+  (void)shape;
+  (void)lo1;
+  (void)hi1;
+  (void)lo2;
+  (void)hi2;
+  (void)attr;
+#endif
+}
+
 // cmplog routines after area check
 static inline void cmplog_routines_checked(uintptr_t k, const uint8_t *ptr1,
                                            const uint8_t *ptr2, size_t len) {
+  if (len > CMPLOG_RTN_LEN) { len = CMPLOG_RTN_LEN; }
   libafl_cmplog_enabled = false;
   uint32_t hits;
 
@@ -194,6 +285,7 @@ static inline void cmplog_routines_checked_extended(uintptr_t      k,
                                                     const uint8_t *ptr2,
                                                     size_t         len) {
 #ifdef CMPLOG_EXTENDED
+  if (len > CMPLOG_RTN_LEN) { len = CMPLOG_RTN_LEN; }
   libafl_cmplog_enabled = false;
   uint32_t hits;
   // printf("RTN: %ld %ld %ld %ld\n", k, *ptr1, *ptr2, len);
@@ -225,12 +317,38 @@ static inline void cmplog_routines_checked_extended(uintptr_t      k,
 #endif
 }
 
-// Expose these three APIs so that you can still call into them from outside
+// cmplog routines after area check, longer comparisons (e.g. memcmp of
+// structs) are logged as consecutive chunks of CMPLOG_RTN_LEN bytes each
+static inline void cmplog_routines_chunked(uintptr_t k, const uint8_t *ptr1,
+                                           const uint8_t *ptr2, size_t len) {
+  if (len > CMPLOG_RTN_MAX_LEN) { len = CMPLOG_RTN_MAX_LEN; }
+  for (size_t off = 0; off < len; off += CMPLOG_RTN_LEN) {
+    size_t chunk = MIN(len - off, (size_t)CMPLOG_RTN_LEN);
+    cmplog_routines_checked(k, ptr1 + off, ptr2 + off, chunk);
+  }
+}
+
+static inline void cmplog_routines_chunked_extended(uintptr_t      k,
+                                                    const uint8_t *ptr1,
+                                                    const uint8_t *ptr2,
+                                                    size_t         len) {
+  if (len > CMPLOG_RTN_MAX_LEN) { len = CMPLOG_RTN_MAX_LEN; }
+  for (size_t off = 0; off < len; off += CMPLOG_RTN_LEN) {
+    size_t chunk = MIN(len - off, (size_t)CMPLOG_RTN_LEN);
+    cmplog_routines_checked_extended(k, ptr1 + off, ptr2 + off, chunk);
+  }
+}
+
+// Expose these APIs so that you can still call into them from outside
 // libafl_targets
 
 void __libafl_targets_cmplog_instructions(uintptr_t k, uint8_t shape,
                                           uint64_t arg1, uint64_t arg2);
 
+void __libafl_targets_cmplog_instructions_128(uintptr_t k, uint64_t lo1,
+                                              uint64_t hi1, uint64_t lo2,
+                                              uint64_t hi2);
+
 void __libafl_targets_cmplog_routines(uintptr_t k, const uint8_t *ptr1,
                                       const uint8_t *ptr2);
 
@@ -29,6 +29,18 @@ pub const CMPLOG_MAP_SIZE: usize = CMPLOG_MAP_W * CMPLOG_MAP_H;
 /// The size of a logged routine argument in bytes
 pub const CMPLOG_RTN_LEN: usize = 32;
 
+/// The maximum number of [`CMPLOG_RTN_LEN`] sized chunks a long routine comparison is logged as,
+/// half of the [`CMPLOG_MAP_RTN_H`] slots of its key
+pub const CMPLOG_RTN_MAX_CHUNKS: usize = CMPLOG_MAP_RTN_H / 2;
+
+/// The maximum length of a logged routine comparison, longer comparisons are truncated
+pub const CMPLOG_RTN_MAX_LEN: usize = CMPLOG_RTN_LEN * CMPLOG_RTN_MAX_CHUNKS;
+
+/// The shape of a 128 bit comparison in the [`CmpLogMap`].
+/// It takes two consecutive operand slots, the low halves followed by the high halves.
+/// A key logging 128 bit comparisons logs no other instructions.
+pub const CMPLOG_SHAPE_128: u8 = 16;
+
 /// The hight of a cmplog routine map
 pub const CMPLOG_MAP_RTN_H: usize =
     (CMPLOG_MAP_H * size_of::<CmpLogInstruction>()) / size_of::<CmpLogRoutine>();
@@ -50,6 +62,15 @@ extern "C" {
     /// Logs an instruction for feedback during fuzzing
     pub fn __libafl_targets_cmplog_instructions(k: usize, shape: u8, arg1: u64, arg2: u64);
 
+    /// Logs a 128 bit instruction, split into 64 bit halves, for feedback during fuzzing
+    pub fn __libafl_targets_cmplog_instructions_128(
+        k: usize,
+        lo1: u64,
+        hi1: u64,
+        lo2: u64,
+        hi2: u64,
+    );
+
     /// Logs a routine for feedback during fuzzing
     pub fn __libafl_targets_cmplog_routines(k: usize, ptr1: *const u8, ptr2: *const u8);
 
@@ -334,7 +355,10 @@ impl CmpMap for CmpLogMap {
 
     fn usable_executions_for(&self, idx: usize) -> usize {
         if self.headers[idx].kind == CMPLOG_KIND_INS {
-            if self.executions_for(idx) < CMPLOG_MAP_H {
+            if self.headers[idx].shape == CMPLOG_SHAPE_128 {
+                // Each 128 bit comparison takes two slots
+                core::cmp::min(self.executions_for(idx), CMPLOG_MAP_H / 2)
+            } else if self.executions_for(idx) < CMPLOG_MAP_H {
                 self.executions_for(idx)
             } else {
                 CMPLOG_MAP_H
@@ -366,6 +390,14 @@ impl CmpMap for CmpLogMap {
                         self.vals.operands[idx][execution].0,
                         self.vals.operands[idx][execution].1,
                     ))),
+                    CMPLOG_SHAPE_128 => {
+                        let lo = self.vals.operands[idx][2 * execution];
+                        let hi = self.vals.operands[idx][2 * execution + 1];
+                        Some(CmpValues::U128((
+                            (u128::from(hi.0) << 64) | u128::from(lo.0),
+                            (u128::from(hi.1) << 64) | u128::from(lo.1),
+                        )))
+                    }
                     // other => panic!("Invalid CmpLog shape {}", other),
                     _ => None,
                 }
@@ -535,7 +567,13 @@ impl CmpMap for AFLppCmpLogMap {
                         self.vals.operands[idx][execution].v0,
                         self.vals.operands[idx][execution].v1,
                     ))),
-                    // TODO handle 128 bits cmps
+                    15 => {
+                        let operands = self.vals.operands[idx][execution];
+                        Some(CmpValues::U128((
+                            (u128::from(operands.v0_128) << 64) | u128::from(operands.v0),
+                            (u128::from(operands.v1_128) << 64) | u128::from(operands.v1),
+                        )))
+                    }
                     // other => panic!("Invalid CmpLog shape {}", other),
                     _ => None,
                 }
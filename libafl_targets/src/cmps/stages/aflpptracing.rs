@@ -5,8 +5,11 @@ use libafl::{
     executors::{Executor, HasObservers},
     inputs::{BytesInput, UsesInput},
     observers::ObserversTuple,
-    stages::{colorization::TaintMetadata, Stage, StdRestartHelper},
-    state::{HasCorpus, HasCurrentTestcase, HasExecutions, UsesState},
+    stages::{colorization::TAINT_REGISTER, Stage, StdRestartHelper},
+    state::{
+        HasCorpus, HasCurrentTestcase, HasExecutions, HasStageRegisters, StageRegisterDeclarations,
+        UsesState,
+    },
     Error, HasMetadata, HasNamedMetadata,
 };
 use libafl_bolts::{
@@ -92,9 +95,11 @@ where
             .post_exec_all(state, &unmutated_input, &exit_kind)?;
 
         // Second run with the mutated input
-        let mutated_input = match state.metadata_map().get::<TaintMetadata>() {
+        let mutated_input = match state.current_register(&TAINT_REGISTER) {
             Some(meta) => BytesInput::from(meta.input_vec().as_ref()),
-            None => return Err(Error::unknown("No metadata found")),
+            None => return Err(Error::unknown(
+                "No taint for the current testcase found, is a ColorizationStage running before?",
+            )),
         };
 
         if let Some(ob) = self
@@ -134,6 +139,10 @@ where
         // TODO: this may need better resumption? (Or is it always used with a forkserver?)
         StdRestartHelper::clear_progress(state, &self.name)
     }
+
+    fn declare_registers(&self, registers: &mut StageRegisterDeclarations) {
+        registers.consumes(&TAINT_REGISTER);
+    }
 }
 
 impl<'a, EM, TE, Z> AFLppCmplogTracingStage<'a, EM, TE, Z>
@@ -44,6 +44,10 @@
 #define FS_NEW_OPT_MAPSIZE 0x1
 #define FS_NEW_OPT_SHDMEM_FUZZ 0x2
 #define FS_NEW_OPT_AUTODICT 0x800
+// LibAFL extension: the testcase shared memory is remapped on request
+#define FS_NEW_OPT_SHDMEM_RESIZE 0x1000
+// Sent instead of was_killed, followed by the size, the id length and the id
+#define FS_CTL_SHDMEM_RESIZE 0x5253495a
 
 /* Reporting options */
 #define FS_OPT_ENABLED 0x80000001
@@ -73,6 +77,9 @@ uint8_t        *__afl_fuzz_ptr;
 static uint32_t __afl_fuzz_len_local;
 uint32_t       *__afl_fuzz_len = &__afl_fuzz_len_local;
 
+static uint8_t *__afl_fuzz_map;
+static size_t   __afl_fuzz_map_size;
+
 int already_initialized_shm;
 int already_initialized_forkserver;
 
@@ -169,43 +176,67 @@ void __afl_map_shm(void) {
   }
 }
 
-static void map_input_shared_memory() {
-  char *id_str = getenv(SHM_FUZZ_ENV_VAR);
-
-  if (id_str) {
-    uint8_t *map = NULL;
+/* Maps the testcase shared memory of the given id, NULL on failure */
+static uint8_t *attach_input_shared_memory(const char *id_str, size_t size) {
+  uint8_t *map = NULL;
 
 #ifdef USEMMAP
-    const char *shm_file_path = id_str;
-    int         shm_fd = -1;
-
-    /* create the shared memory segment as if it was a file */
-    shm_fd = shm_open(shm_file_path, O_RDWR, DEFAULT_PERMISSION);
-    if (shm_fd == -1) {
-      fprintf(stderr, "shm_open() failed for fuzz\n");
-      send_forkserver_error(FS_ERROR_SHM_OPEN);
-      exit(1);
-    }
+  const char *shm_file_path = id_str;
+  int         shm_fd = -1;
+
+  /* create the shared memory segment as if it was a file */
+  shm_fd = shm_open(shm_file_path, O_RDWR, DEFAULT_PERMISSION);
+  if (shm_fd == -1) {
+    fprintf(stderr, "shm_open() failed for fuzz\n");
+    return NULL;
+  }
 
-    map = (uint8_t *)mmap(0, MAX_FILE + sizeof(uint32_t), PROT_READ, MAP_SHARED,
-                          shm_fd, 0);
+  map = (uint8_t *)mmap(0, size, PROT_READ, MAP_SHARED, shm_fd, 0);
+  close(shm_fd);
+  if (map == MAP_FAILED) { map = NULL; }
 
 #else
-    uint32_t shm_id = atoi(id_str);
-    map = (uint8_t *)shmat(shm_id, NULL, 0);
+  (void)size;
+  uint32_t shm_id = atoi(id_str);
+  map = (uint8_t *)shmat(shm_id, NULL, 0);
+  if (map == (void *)-1) { map = NULL; }
 
 #endif
 
+  return map;
+}
+
+static void detach_input_shared_memory(void) {
+#ifdef USEMMAP
+  munmap(__afl_fuzz_map, __afl_fuzz_map_size);
+#else
+  shmdt(__afl_fuzz_map);
+#endif
+}
+
+static void use_input_shared_memory(uint8_t *map, size_t size) {
+  __afl_fuzz_map = map;
+  __afl_fuzz_map_size = size;
+  __afl_fuzz_len = (uint32_t *)map;
+  __afl_fuzz_ptr = map + sizeof(uint32_t);
+}
+
+static void map_input_shared_memory() {
+  char *id_str = getenv(SHM_FUZZ_ENV_VAR);
+
+  if (id_str) {
+    uint8_t *map =
+        attach_input_shared_memory(id_str, MAX_FILE + sizeof(uint32_t));
+
     /* Whooooops. */
 
-    if (!map || map == (void *)-1) {
+    if (!map) {
       perror("Could not access fuzzing shared memory");
       send_forkserver_error(FS_ERROR_SHM_OPEN);
       exit(1);
     }
 
-    __afl_fuzz_len = (uint32_t *)map;
-    __afl_fuzz_ptr = map + sizeof(uint32_t);
+    use_input_shared_memory(map, MAX_FILE + sizeof(uint32_t));
 
   } else {
     fprintf(stderr, "Error: variable for fuzzing shared memory is not set\n");
@@ -214,6 +245,42 @@ static void map_input_shared_memory() {
   }
 }
 
+static int read_all(int fd, void *buf, size_t len) {
+  uint8_t *ptr = (uint8_t *)buf;
+  while (len) {
+    ssize_t ret = read(fd, ptr, len);
+    if (ret < 1) { return -1; }
+    ptr += ret;
+    len -= ret;
+  }
+  return 0;
+}
+
+/* Replaces the testcase shared memory on request of the fuzzer, see
+   FS_CTL_SHDMEM_RESIZE */
+static void resize_input_shared_memory(void) {
+  uint32_t size, id_len;
+  char     id_str[256];
+
+  if (read_all(FORKSRV_FD, &size, 4) || read_all(FORKSRV_FD, &id_len, 4) ||
+      id_len >= sizeof(id_str) || read_all(FORKSRV_FD, id_str, id_len)) {
+    write_error("read shared memory resize request");
+    _exit(1);
+  }
+  id_str[id_len] = 0;
+
+  uint8_t *map = attach_input_shared_memory(id_str, size);
+  if (!map) {
+    write_error("map resized shared memory");
+    _exit(1);
+  }
+  detach_input_shared_memory();
+  use_input_shared_memory(map, size);
+
+  uint32_t ack = FS_CTL_SHDMEM_RESIZE;
+  if (write(FORKSRV_FD + 1, &ack, 4) != 4) { _exit(1); }
+}
+
 /* Fork server logic. */
 
 void __afl_start_forkserver(void) {
@@ -255,7 +322,9 @@ void __afl_start_forkserver(void) {
   }
 
   status = FS_NEW_OPT_MAPSIZE;
-  if (__afl_sharedmem_fuzzing) { status |= FS_NEW_OPT_SHDMEM_FUZZ; }
+  if (__afl_sharedmem_fuzzing) {
+    status |= FS_NEW_OPT_SHDMEM_FUZZ | FS_NEW_OPT_SHDMEM_RESIZE;
+  }
   if (autodict_on) { status |= FS_NEW_OPT_AUTODICT; }
 
   if (write(FORKSRV_FD + 1, msg, 4) != 4) { _exit(1); }
@@ -312,6 +381,19 @@ void __afl_start_forkserver(void) {
       }
     }
 
+    /* The fuzzer needs a larger testcase shared memory. A stopped persistent
+       child still maps the old region, so it is written off. */
+
+    if (was_killed == FS_CTL_SHDMEM_RESIZE) {
+      if (child_stopped) {
+        kill(child_pid, SIGKILL);
+        waitpid(child_pid, &status, 0);
+        child_stopped = 0;
+      }
+      resize_input_shared_memory();
+      continue;
+    }
+
     /* If we stopped the child in persistent mode, but there was a race
        condition and afl-fuzz already issued SIGKILL, write off the old
        process. */
@@ -0,0 +1,407 @@
+//! Function-level coverage, recorded by the `function-coverage-pass` of `libafl_cc`.
+//!
+//! The pass calls [`__libafl_target_function_coverage_hook`] on each function entry and writes a
+//! symbol table (`<id in hex> <function name>` lines) for each module to the directory in the
+//! `FUNCTION_SYMBOLS_OUTPUT_PATH` environment variable at compile time.
+//! The [`FunctionCoverageObserver`] collects the functions covered by an execution, and the
+//! [`NewFunctionsFeedback`] reports newly reached functions by name. Only the feedback keeps the
+//! [`FunctionSymbols`], so that the symbol table is not serialized with the observers.
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt::Write;
+use std::{fs, path::Path};
+
+use hashbrown::{HashMap, HashSet};
+use libafl::{
+    corpus::Testcase,
+    events::{EventFirer, LogSeverity},
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle},
+    inputs::UsesInput,
+    observers::{Observer, ObserversTuple},
+    state::State,
+    Error, HasMetadata,
+};
+use libafl_bolts::{
+    tuples::{Handle, Handled, MatchNameRef},
+    Named,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of function names listed in a single log message
+const MAX_LOGGED_FUNCTIONS: usize = 8;
+
+/// The ids of the functions covered by the current execution
+pub static mut FUNCTIONS_COVERED: Lazy<HashSet<u64>> = Lazy::new(HashSet::new);
+
+#[no_mangle]
+/// The runtime code inserted at every function entry (if you used the `function-coverage-pass.cc`)
+/// # Safety
+/// unsafe because it touches pub static mut
+pub unsafe extern "C" fn __libafl_target_function_coverage_hook(id: u64) {
+    FUNCTIONS_COVERED.insert(id);
+}
+
+/// The symbol table written by the `function-coverage-pass`, mapping function ids to names
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct FunctionSymbols {
+    names: HashMap<u64, String>,
+}
+
+impl FunctionSymbols {
+    /// Creates an empty symbol table
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads all `.funcs` symbol tables in the given directory
+    pub fn from_dir<P>(dir: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut symbols = Self::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "funcs") {
+                symbols.parse(&fs::read_to_string(path)?);
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Adds the symbols of a symbol table in the `<id in hex> <function name>` format
+    pub fn parse(&mut self, table: &str) {
+        for line in table.lines() {
+            let Some((id, name)) = line.split_once(' ') else {
+                continue;
+            };
+            if let Ok(id) = u64::from_str_radix(id, 16) {
+                self.names.insert(id, name.to_string());
+            }
+        }
+    }
+
+    /// The name of the function with the given id, or its id in hex, if unknown
+    #[must_use]
+    pub fn name(&self, id: u64) -> Cow<'_, str> {
+        match self.names.get(&id) {
+            Some(name) => Cow::Borrowed(name),
+            None => Cow::Owned(format!("{id:016x}")),
+        }
+    }
+
+    /// The number of known functions
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns `true` if no functions are known
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Observes the functions covered by an execution
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FunctionCoverageObserver {
+    name: Cow<'static, str>,
+    covered: Vec<u64>,
+}
+
+impl FunctionCoverageObserver {
+    /// Creates a new [`FunctionCoverageObserver`]
+    #[must_use]
+    pub fn new<N>(name: N) -> Self
+    where
+        N: Into<Cow<'static, str>>,
+    {
+        Self {
+            name: name.into(),
+            covered: Vec::new(),
+        }
+    }
+
+    /// The ids of the functions covered by the last execution
+    #[must_use]
+    pub fn covered(&self) -> &[u64] {
+        &self.covered
+    }
+}
+
+impl<S> Observer<S> for FunctionCoverageObserver
+where
+    S: UsesInput,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        unsafe { FUNCTIONS_COVERED.clear() }
+        self.covered.clear();
+        Ok(())
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        _exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.covered = unsafe { FUNCTIONS_COVERED.iter().copied().collect() };
+        self.covered.sort_unstable();
+        Ok(())
+    }
+}
+
+impl Named for FunctionCoverageObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+/// The functions reached so far, stored in the state by the [`NewFunctionsFeedback`]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[allow(clippy::unsafe_derive_deserialize)]
+pub struct FunctionCoverageMetadata {
+    /// The ids of all functions reached so far
+    pub reached: HashSet<u64>,
+}
+
+libafl_bolts::impl_serdeany!(FunctionCoverageMetadata);
+
+/// The functions first reached by a testcase
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct NewFunctionsMetadata {
+    /// The names of the newly reached functions
+    pub functions: Vec<String>,
+}
+
+libafl_bolts::impl_serdeany!(NewFunctionsMetadata);
+
+/// A feedback that is interesting if an execution reaches functions that were never reached before.
+///
+/// Once the testcase is added to a corpus, each new function is logged
+/// (`new function reached: parse_header`) to the monitors, and the names are added to the testcase
+/// as [`NewFunctionsMetadata`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NewFunctionsFeedback {
+    name: Cow<'static, str>,
+    o_ref: Handle<FunctionCoverageObserver>,
+    symbols: FunctionSymbols,
+    /// The functions first reached by the last execution
+    new_functions: Vec<u64>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    // The previous run's result of `Self::is_interesting`
+    last_result: Option<bool>,
+}
+
+impl NewFunctionsFeedback {
+    /// Creates a new [`NewFunctionsFeedback`], naming functions with the given symbol table
+    #[must_use]
+    pub fn new(observer: &FunctionCoverageObserver, symbols: FunctionSymbols) -> Self {
+        Self {
+            name: Cow::from("NewFunctionsFeedback"),
+            o_ref: observer.handle(),
+            symbols,
+            new_functions: Vec::new(),
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+}
+
+impl<S> Feedback<S> for NewFunctionsFeedback
+where
+    S: State + HasMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.metadata_or_insert_with(FunctionCoverageMetadata::default);
+        Ok(())
+    }
+
+    #[allow(clippy::wrong_self_convention)]
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers
+            .get(&self.o_ref)
+            .expect("A NewFunctionsFeedback needs a FunctionCoverageObserver");
+
+        let reached = &state
+            .metadata_or_insert_with(FunctionCoverageMetadata::default)
+            .reached;
+        self.new_functions = observer
+            .covered()
+            .iter()
+            .filter(|id| !reached.contains(*id))
+            .copied()
+            .collect();
+
+        let res = !self.new_functions.is_empty();
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    fn append_metadata<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+        EM: EventFirer<State = S>,
+    {
+        if self.new_functions.is_empty() {
+            return Ok(());
+        }
+        state
+            .metadata_or_insert_with(FunctionCoverageMetadata::default)
+            .reached
+            .extend(self.new_functions.iter().copied());
+        let functions: Vec<String> = self
+            .new_functions
+            .drain(..)
+            .map(|id| self.symbols.name(id).into_owned())
+            .collect();
+
+        let mut message = format!(
+            "new function{} reached: {}",
+            if functions.len() > 1 { "s" } else { "" },
+            functions[..functions.len().min(MAX_LOGGED_FUNCTIONS)].join(", ")
+        );
+        if functions.len() > MAX_LOGGED_FUNCTIONS {
+            let _ = write!(
+                message,
+                " and {} more",
+                functions.len() - MAX_LOGGED_FUNCTIONS
+            );
+        }
+        manager.log(state, LogSeverity::Info, message)?;
+
+        testcase.add_metadata(NewFunctionsMetadata { functions });
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.new_functions.clear();
+        Ok(())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or_else(|| {
+            Error::illegal_state("is_interesting has not been called on NewFunctionsFeedback")
+        })
+    }
+}
+
+impl Named for NewFunctionsFeedback {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl HasObserverHandle for NewFunctionsFeedback {
+    type Observer = FunctionCoverageObserver;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<FunctionCoverageObserver> {
+        &self.o_ref
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::{
+        corpus::{InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        executors::ExitKind,
+        feedbacks::{ConstFeedback, Feedback},
+        inputs::BytesInput,
+        state::StdState,
+        HasMetadata,
+    };
+    use libafl_bolts::{rands::StdRand, tuples::tuple_list};
+
+    use super::{
+        FunctionCoverageMetadata, FunctionCoverageObserver, FunctionSymbols, NewFunctionsFeedback,
+        NewFunctionsMetadata,
+    };
+
+    #[test]
+    fn test_parse_function_symbols() {
+        let mut symbols = FunctionSymbols::new();
+        symbols.parse("00000000000000ff parse_header\nnot a line\n0000000000000001 main\n");
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(symbols.name(0xff), "parse_header");
+        assert_eq!(symbols.name(2), "0000000000000002");
+    }
+
+    #[test]
+    fn test_new_functions_feedback() {
+        let mut symbols = FunctionSymbols::new();
+        symbols.parse("0000000000000001 main\n");
+        let mut observer = FunctionCoverageObserver::new("functions");
+        let mut feedback = NewFunctionsFeedback::new(&observer, symbols);
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut ConstFeedback::new(false),
+        )
+        .unwrap();
+        let mut manager = NopEventManager::new();
+        let input = BytesInput::new(vec![0]);
+
+        observer.covered = vec![1, 2];
+        let observers = tuple_list!(observer);
+        // Only inputs added to a corpus mark their functions as reached
+        for _ in 0..2 {
+            assert!(feedback
+                .is_interesting(&mut state, &mut manager, &input, &observers, &ExitKind::Ok)
+                .unwrap());
+        }
+        assert!(state
+            .metadata::<FunctionCoverageMetadata>()
+            .unwrap()
+            .reached
+            .is_empty());
+
+        let mut testcase = Testcase::new(input.clone());
+        feedback
+            .append_metadata(&mut state, &mut manager, &observers, &mut testcase)
+            .unwrap();
+        assert_eq!(
+            testcase
+                .metadata::<NewFunctionsMetadata>()
+                .unwrap()
+                .functions,
+            ["main", "0000000000000002"]
+        );
+        assert!(!feedback
+            .is_interesting(&mut state, &mut manager, &input, &observers, &ExitKind::Ok)
+            .unwrap());
+    }
+}
@@ -114,6 +114,15 @@ pub mod sancov_8bit;
 #[cfg(feature = "sancov_8bit")]
 pub use sancov_8bit::*;
 
+#[cfg(feature = "user_maps")]
+pub mod user_maps;
+#[cfg(feature = "user_maps")]
+pub use user_maps::*;
+
+#[cfg(feature = "user_maps")]
+#[doc(hidden)]
+pub use libafl_bolts::ctor;
+
 #[cfg(feature = "coverage")]
 pub mod coverage;
 #[cfg(feature = "coverage")]
@@ -128,10 +137,18 @@ pub mod call;
 #[cfg(feature = "function-logging")]
 pub use call::*;
 
+#[cfg(feature = "function-coverage")]
+pub mod function_coverage;
+#[cfg(feature = "function-coverage")]
+pub use function_coverage::*;
+
 /// runtime related to comparisons
 pub mod cmps;
 pub use cmps::*;
 
+pub mod sequence;
+pub use sequence::{DecodedOp, SequenceDecoder};
+
 #[cfg(feature = "std")]
 pub mod drcov;
 
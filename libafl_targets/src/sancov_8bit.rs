@@ -55,7 +55,10 @@ pub extern "C" fn __sanitizer_cov_8bit_counters_init(start: *mut u8, stop: *mut
 }
 
 #[cfg(feature = "observers")]
-pub use self::observers::{counters_maps_observer, CountersMultiMapObserver};
+pub use self::observers::{
+    counters_maps_dynamic_observer, counters_maps_hitcounts_observer, counters_maps_observer,
+    CountersMultiMapObserver,
+};
 
 #[cfg(feature = "observers")]
 mod observers {
@@ -71,8 +74,12 @@ mod observers {
 
     use ahash::RandomState;
     use libafl::{
+        executors::ExitKind,
         inputs::UsesInput,
-        observers::{DifferentialObserver, MapObserver, Observer, ObserversTuple},
+        observers::{
+            DifferentialObserver, DynamicMultiMapObserver, HitcountsIterableMapObserver,
+            MapObserver, Observer, ObserversTuple,
+        },
         Error,
     };
     use libafl_bolts::{
@@ -81,7 +88,7 @@ mod observers {
     use meminterval::IntervalTree;
     use serde::{Deserialize, Serialize};
 
-    use super::COUNTERS_MAPS;
+    use super::{extra_counters, COUNTERS_MAPS};
 
     #[must_use]
     #[export_name = "counters_maps_observer"]
@@ -112,6 +119,51 @@ mod observers {
         CountersMultiMapObserver::new(name)
     }
 
+    /// Create a new [`CountersMultiMapObserver`] of the [`COUNTERS_MAPS`], with AFL-style hitcount
+    /// bucketing applied after each execution.
+    ///
+    /// This is the counterpart of the `std_edges_map_observer` for targets compiled with
+    /// `-fsanitize-coverage=inline-8bit-counters` instead of `trace-pc-guard`.
+    /// Pass a `scrub_interval` to clear saturated counters periodically, see
+    /// [`CountersMultiMapObserver::with_saturation_scrubbing`].
+    ///
+    /// # Safety
+    ///
+    /// This function instantiates an observer of a `static mut` map whose contents are mutated by
+    /// `SanitizerCoverage` instrumentation. This is unsafe, and data in the map may be mutated from
+    /// under us at any time. It should never be assumed constant.
+    #[must_use]
+    pub unsafe fn counters_maps_hitcounts_observer(
+        name: &'static str,
+        scrub_interval: Option<u64>,
+    ) -> HitcountsIterableMapObserver<CountersMultiMapObserver<false>> {
+        let mut observer = CountersMultiMapObserver::new(name);
+        if let Some(interval) = scrub_interval {
+            observer = observer.with_saturation_scrubbing(interval);
+        }
+        HitcountsIterableMapObserver::new(observer)
+    }
+
+    /// Create a new [`DynamicMultiMapObserver`] of all the [`COUNTERS_MAPS`].
+    ///
+    /// Each instrumented module (the main binary and every shared object) registers its own
+    /// `8-bit-counters` region through [`super::__sanitizer_cov_8bit_counters_init`] when it is loaded.
+    /// Unlike [`counters_maps_observer`], which only sees the regions registered at creation, this
+    /// observer checks for newly registered regions before each execution, so modules loaded later
+    /// (e.g. using `dlopen`) are appended to the end of the observed map.
+    ///
+    /// # Safety
+    ///
+    /// This function instantiates an observer of `static mut` maps whose contents are mutated by
+    /// `SanitizerCoverage` instrumentation. This is unsafe, and data in the maps may be mutated from
+    /// under us at any time. It should never be assumed constant.
+    #[must_use]
+    pub unsafe fn counters_maps_dynamic_observer(
+        name: &'static str,
+    ) -> DynamicMultiMapObserver<'static, u8> {
+        DynamicMultiMapObserver::with_discovery(name, || unsafe { extra_counters() })
+    }
+
     /// The [`CountersMultiMapObserver`] observes all the counters that may be set by
     /// `SanitizerCoverage` in [`COUNTERS_MAPS`]
     #[derive(Serialize, Deserialize, Debug)]
@@ -122,6 +174,11 @@ mod observers {
         initial: u8,
         name: Cow<'static, str>,
         iter_idx: usize,
+        /// Scan for saturated counters every `scrub_interval` executions, if set
+        scrub_interval: Option<u64>,
+        executions: u64,
+        /// The sorted indexes of the counters found saturated so far
+        saturated: Vec<usize>,
     }
 
     impl<S> Observer<S> for CountersMultiMapObserver<false>
@@ -133,6 +190,17 @@ mod observers {
         fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
             self.reset_map()
         }
+
+        #[inline]
+        fn post_exec(
+            &mut self,
+            _state: &mut S,
+            _input: &S::Input,
+            _exit_kind: &ExitKind,
+        ) -> Result<(), Error> {
+            self.scrub();
+            Ok(())
+        }
     }
 
     impl<S> Observer<S> for CountersMultiMapObserver<true>
@@ -141,6 +209,17 @@ mod observers {
         Self: MapObserver,
     {
         // in differential mode, we are *not* responsible for resetting the map!
+
+        #[inline]
+        fn post_exec(
+            &mut self,
+            _state: &mut S,
+            _input: &S::Input,
+            _exit_kind: &ExitKind,
+        ) -> Result<(), Error> {
+            self.scrub();
+            Ok(())
+        }
     }
 
     impl<const DIFFERENTIAL: bool> Named for CountersMultiMapObserver<DIFFERENTIAL> {
@@ -278,6 +357,65 @@ mod observers {
                 name: Cow::from(name),
                 initial: u8::default(),
                 iter_idx: 0,
+                scrub_interval: None,
+                executions: 0,
+                saturated: Vec::new(),
+            }
+        }
+    }
+
+    impl<const DIFFERENTIAL: bool> CountersMultiMapObserver<DIFFERENTIAL> {
+        /// The lowest counter value considered saturated, i.e. the highest AFL hitcount bucket.
+        /// Since the `8-bit-counters` wrap around, the hitcount of such counters carries no information.
+        pub const SATURATION_THRESHOLD: u8 = 128;
+
+        /// Periodically clears saturated counters.
+        ///
+        /// Every `interval` executions, the map is scanned for counters at or above
+        /// [`Self::SATURATION_THRESHOLD`], e.g. those in hot loops. From then on, these counters are
+        /// cleared down to a single hit after each execution, so wrapping counts do not show up as
+        /// new coverage over and over.
+        #[must_use]
+        pub fn with_saturation_scrubbing(mut self, interval: u64) -> Self {
+            self.scrub_interval = Some(interval.max(1));
+            self
+        }
+
+        /// The indexes of the counters found saturated so far, sorted
+        #[must_use]
+        pub fn saturated(&self) -> &[usize] {
+            &self.saturated
+        }
+
+        /// Runs the periodic scan for saturated counters, if due, and clears all known saturated
+        /// counters down to a single hit
+        fn scrub(&mut self) {
+            let Some(interval) = self.scrub_interval else {
+                return;
+            };
+            self.executions += 1;
+            if self.executions % interval == 0 {
+                let found: Vec<usize> = self
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, x)| {
+                        **x >= Self::SATURATION_THRESHOLD
+                            && self.saturated.binary_search(idx).is_err()
+                    })
+                    .map(|(idx, _)| idx)
+                    .collect();
+                if !found.is_empty() {
+                    self.saturated.extend(found);
+                    self.saturated.sort_unstable();
+                }
+            }
+
+            let initial = self.initial();
+            for i in 0..self.saturated.len() {
+                let idx = self.saturated[i];
+                if idx < self.len && self.get(idx) != initial {
+                    self.set(idx, 1);
+                }
             }
         }
     }
@@ -317,6 +455,9 @@ mod observers {
                 name: Cow::from(name),
                 initial: u8::default(),
                 iter_idx: 0,
+                scrub_interval: None,
+                executions: 0,
+                saturated: Vec::new(),
             }
         }
     }
@@ -6,7 +6,7 @@ use core::{
     ptr,
 };
 
-use crate::CMPLOG_MAP_W;
+use crate::{cmps::CMPLOG_RTN_MAX_LEN, CMPLOG_MAP_W};
 
 extern "C" {
 
@@ -31,7 +31,7 @@ extern "C" {
     /// Trace a switch statement
     pub fn __sanitizer_cov_trace_switch(val: u64, cases: *const u64);
 
-    /// cmplog internal api
+    /// cmplog internal api, comparisons longer than `CMPLOG_RTN_LEN` are logged in chunks
     pub fn __libafl_targets_cmplog_routines_len(k: usize, s1: *const u8, s2: *const u8, len: usize);
 }
 
@@ -50,7 +50,12 @@ pub unsafe extern "C" fn __sanitizer_weak_hook_memcmp(
         let k: usize = called_pc as usize;
         let k = (k >> 4) ^ (k << 8);
         let k = k & (CMPLOG_MAP_W - 1);
-        __libafl_targets_cmplog_routines_len(k, s1 as *const u8, s2 as *const u8, cmp::min(n, 32));
+        __libafl_targets_cmplog_routines_len(
+            k,
+            s1 as *const u8,
+            s2 as *const u8,
+            cmp::min(n, CMPLOG_RTN_MAX_LEN),
+        );
     }
 }
 
@@ -66,7 +71,7 @@ pub unsafe extern "C" fn __sanitizer_weak_hook_strncmp(
     result: c_int,
 ) {
     if result != 0 {
-        let n = cmp::min(n, 32);
+        let n = cmp::min(n, CMPLOG_RTN_MAX_LEN);
         let k: usize = called_pc as usize;
         let k = (k >> 4) ^ (k << 8);
         let k = k & (CMPLOG_MAP_W - 1);
@@ -0,0 +1,101 @@
+//! Harness-side decoding of `libafl::inputs::SequenceInput`s.
+//!
+//! Each operation is encoded as its op code (`u32`), its number of arguments (`u32`), and the
+//! arguments (`u64` each), all little-endian. The [`SequenceDecoder`] iterates over the operations
+//! without allocating, so it can be used in `no_std` harnesses as well:
+//!
+//! ```rust,ignore
+//! for op in SequenceDecoder::new(data) {
+//!     match op.opcode() {
+//!         0 => do_open(op.arg(0).unwrap_or_default()),
+//!         1 => do_write(op.arg(0).unwrap_or_default(), op.arg(1).unwrap_or_default()),
+//!         _ => {}
+//!     }
+//! }
+//! ```
+
+/// A decoded operation, borrowing its arguments from the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedOp<'a> {
+    opcode: u32,
+    args: &'a [u8],
+}
+
+impl<'a> DecodedOp<'a> {
+    /// The op code of this operation
+    #[must_use]
+    pub fn opcode(&self) -> u32 {
+        self.opcode
+    }
+
+    /// The number of arguments
+    #[must_use]
+    pub fn arg_count(&self) -> usize {
+        self.args.len() / 8
+    }
+
+    /// The argument with the given index, if it exists
+    #[must_use]
+    pub fn arg(&self, idx: usize) -> Option<u64> {
+        let arg = self.args.get(idx * 8..idx * 8 + 8)?;
+        Some(u64::from_le_bytes(arg.try_into().unwrap()))
+    }
+
+    /// Iterates over the arguments
+    pub fn args(&self) -> impl Iterator<Item = u64> + 'a {
+        self.args
+            .chunks_exact(8)
+            .map(|arg| u64::from_le_bytes(arg.try_into().unwrap()))
+    }
+}
+
+/// Iterates over the operations of an encoded sequence.
+///
+/// Decoding stops at the first truncated operation, see [`SequenceDecoder::is_truncated`], so a
+/// harness never reads past the input.
+#[derive(Debug, Clone)]
+pub struct SequenceDecoder<'a> {
+    bytes: &'a [u8],
+    truncated: bool,
+}
+
+impl<'a> SequenceDecoder<'a> {
+    /// Creates a new [`SequenceDecoder`] for the encoded sequence
+    #[must_use]
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            truncated: false,
+        }
+    }
+
+    /// Checks if decoding stopped at a truncated operation
+    #[must_use]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<'a> Iterator for SequenceDecoder<'a> {
+    type Item = DecodedOp<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() || self.truncated {
+            return None;
+        }
+        if self.bytes.len() < 8 {
+            self.truncated = true;
+            return None;
+        }
+        let opcode = u32::from_le_bytes(self.bytes[..4].try_into().unwrap());
+        let argc = u32::from_le_bytes(self.bytes[4..8].try_into().unwrap()) as usize;
+        let rest = &self.bytes[8..];
+        if rest.len() / 8 < argc {
+            self.truncated = true;
+            return None;
+        }
+        let (args, rest) = rest.split_at(argc * 8);
+        self.bytes = rest;
+        Some(DecodedOp { opcode, args })
+    }
+}
@@ -0,0 +1,540 @@
+//! User-declared maps for harnesses.
+//!
+//! Harness code can declare additional named maps (or single counters) using the
+//! [`crate::libafl_create_map`] macro, or by calling [`libafl_register_user_map`] from C.
+//! All declared maps are registered at startup, and the fuzzer can discover them by name and
+//! turn them into observers, without any bespoke executor plumbing.
+//!
+//! If the fuzzer runs the target in another process, e.g. with a forkserver, it creates a
+//! [`UserMapsShMem`] before spawning the target. The target then allocates its maps in this
+//! shared memory at startup, instead of using its own memory, and lists them in the directory
+//! at the start of the shared memory, from where the fuzzer discovers them.
+
+#[cfg(feature = "std")]
+use alloc::string::String;
+use alloc::{borrow::Cow, vec::Vec};
+use core::{ffi::c_char, ptr::addr_of};
+#[cfg(feature = "std")]
+use core::{mem::size_of, ptr::addr_of_mut};
+
+use libafl::observers::StdMapObserver;
+use libafl_bolts::{ownedref::OwnedMutSlice, AsSlice};
+#[cfg(feature = "std")]
+use libafl_bolts::{
+    shmem::{ShMem, ShMemProvider, StdShMemProvider},
+    Error, Named,
+};
+
+/// The env var holding the id of the [`UserMapsShMem`] of the fuzzer
+pub const USER_MAPS_SHM_ENV: &str = "LIBAFL_USER_MAPS_SHM_ID";
+/// The magic number written by the fuzzer to the start of the [`UserMapsShMem`]
+pub const USER_MAPS_MAGIC: u64 = u64::from_le_bytes(*b"LAFLUMAP");
+/// The version of the layout of the [`UserMapsShMem`], checked by the target
+pub const USER_MAPS_VERSION: u32 = 1;
+/// The maximum number of maps in a [`UserMapsShMem`]
+pub const USER_MAPS_MAX: usize = 64;
+/// The maximum length of the name of a map in a [`UserMapsShMem`]
+pub const USER_MAP_NAME_LEN: usize = 64;
+
+/// A map declared by the harness, see [`crate::libafl_create_map`].
+#[derive(Debug)]
+pub struct UserMap {
+    /// The name of this map, also used as name for the observer
+    pub name: &'static str,
+    /// The map itself
+    pub map: OwnedMutSlice<'static, u8>,
+}
+
+/// All maps declared by the harness.
+/// They are registered by [`register_user_map`], usually through [`crate::libafl_create_map`].
+pub static mut USER_MAPS: Vec<UserMap> = Vec::new();
+
+/// The [`UserMapsShMem`] of the fuzzer, once the target attached to it
+#[cfg(feature = "std")]
+static mut USER_MAPS_SHMEM: Option<<StdShMemProvider as ShMemProvider>::ShMem> = None;
+
+/// An entry of the directory of a [`UserMapsShMem`]
+#[cfg(feature = "std")]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct UserMapEntry {
+    /// The name of the map, nul-padded
+    name: [u8; USER_MAP_NAME_LEN],
+    /// The offset of the map from the end of the [`UserMapsHeader`]
+    offset: u64,
+    len: u64,
+}
+
+/// The directory at the start of a [`UserMapsShMem`], followed by the maps
+#[cfg(feature = "std")]
+#[repr(C)]
+#[derive(Debug)]
+struct UserMapsHeader {
+    /// [`USER_MAPS_MAGIC`], written by the fuzzer
+    magic: u64,
+    /// [`USER_MAPS_VERSION`], written by the fuzzer
+    version: u32,
+    /// The pid of the target, written once it attached to the shared memory
+    target_pid: u32,
+    /// The number of maps allocated by the target
+    count: u32,
+    /// The number of bytes allocated by the target after the header
+    used: u64,
+    entries: [UserMapEntry; USER_MAPS_MAX],
+}
+
+/// Register a map declared by the harness.
+/// Registering the same name twice replaces the previous map.
+///
+/// If the fuzzer shares a [`UserMapsShMem`] with this process, the map is allocated in it and
+/// the returned pointer should be used instead of `map`. Otherwise, `map` itself is returned.
+///
+/// # Safety
+/// `map` must be valid for `len` bytes for the rest of the program.
+/// This must not be called concurrently to any other access of [`USER_MAPS`].
+pub unsafe fn register_user_map(name: &'static str, map: *mut u8, len: usize) -> *mut u8 {
+    #[cfg(feature = "std")]
+    let map = match attached_user_maps_shmem() {
+        Some(shmem) => match alloc_user_map(shmem, name, len) {
+            Ok(shared) => shared,
+            Err(err) => {
+                log::warn!("Could not share the user map {name} with the fuzzer: {err}");
+                map
+            }
+        },
+        None => map,
+    };
+
+    let user_map = OwnedMutSlice::from_raw_parts_mut(map, len);
+    let maps = &mut *core::ptr::addr_of_mut!(USER_MAPS);
+    if let Some(existing) = maps.iter_mut().find(|existing| existing.name == name) {
+        existing.map = user_map;
+    } else {
+        maps.push(UserMap {
+            name,
+            map: user_map,
+        });
+    }
+    map
+}
+
+/// Register a map declared by a C harness.
+/// Returns the pointer the harness should use for the map, see [`register_user_map`].
+///
+/// # Safety
+/// `name` must be a valid, nul-terminated, `'static` string and `map` must be valid for `len`
+/// bytes for the rest of the program.
+#[no_mangle]
+pub unsafe extern "C" fn libafl_register_user_map(
+    name: *const c_char,
+    map: *mut u8,
+    len: usize,
+) -> *mut u8 {
+    let name = core::ffi::CStr::from_ptr(name)
+        .to_str()
+        .expect("User map names must be valid UTF-8");
+    register_user_map(name, map, len)
+}
+
+/// Attaches to the [`UserMapsShMem`] of the fuzzer, if it passed one in [`USER_MAPS_SHM_ENV`]
+#[cfg(feature = "std")]
+unsafe fn attached_user_maps_shmem(
+) -> Option<&'static mut <StdShMemProvider as ShMemProvider>::ShMem> {
+    let shmem = &mut *addr_of_mut!(USER_MAPS_SHMEM);
+    if shmem.is_none() && std::env::var_os(USER_MAPS_SHM_ENV).is_some() {
+        match StdShMemProvider::new()
+            .and_then(|mut provider| provider.existing_from_env(USER_MAPS_SHM_ENV))
+        {
+            Ok(attached) => *shmem = Some(attached),
+            Err(err) => log::warn!("Could not attach to the user maps of the fuzzer: {err}"),
+        }
+    }
+    shmem.as_mut()
+}
+
+/// Allocates a map in the [`UserMapsShMem`] of the fuzzer and lists it in the directory,
+/// or returns the existing map of the same name and size.
+/// The first allocation acknowledges the handshake by writing the pid of this process.
+///
+/// # Safety
+/// The `shmem` must not be accessed concurrently by other threads of this process.
+#[cfg(feature = "std")]
+unsafe fn alloc_user_map<SHM>(shmem: &mut SHM, name: &str, len: usize) -> Result<*mut u8, Error>
+where
+    SHM: ShMem,
+{
+    let data_len = shmem.len().saturating_sub(size_of::<UserMapsHeader>());
+    let header = &mut *shmem
+        .as_mut_ptr_of::<UserMapsHeader>()
+        .ok_or_else(|| Error::illegal_state("The user maps shared memory is too small"))?;
+    if header.magic != USER_MAPS_MAGIC || header.version != USER_MAPS_VERSION {
+        return Err(Error::illegal_state(format!(
+            "The user maps shared memory has version {} (magic {:#x}), expected {USER_MAPS_VERSION}",
+            header.version, header.magic
+        )));
+    }
+    if name.len() > USER_MAP_NAME_LEN {
+        return Err(Error::illegal_argument(format!(
+            "User map names are limited to {USER_MAP_NAME_LEN} bytes"
+        )));
+    }
+    let data = shmem.as_mut_ptr().add(size_of::<UserMapsHeader>());
+
+    let count = (header.count as usize).min(USER_MAPS_MAX);
+    if let Some(entry) = header.entries[..count]
+        .iter()
+        .find(|entry| entry_name(entry) == Some(name))
+    {
+        if entry.len as usize != len {
+            return Err(Error::illegal_argument(format!(
+                "The user map {name} was already registered with size {}",
+                entry.len
+            )));
+        }
+        return Ok(data.add(entry.offset as usize));
+    }
+    if count >= USER_MAPS_MAX {
+        return Err(Error::illegal_state(format!(
+            "Only {USER_MAPS_MAX} user maps can be shared with the fuzzer"
+        )));
+    }
+    let offset = header.used as usize;
+    let left = data_len.saturating_sub(offset);
+    if left < len {
+        return Err(Error::illegal_state(format!(
+            "The user maps shared memory has only {left} bytes left"
+        )));
+    }
+
+    let entry = &mut header.entries[count];
+    entry.name = [0; USER_MAP_NAME_LEN];
+    entry.name[..name.len()].copy_from_slice(name.as_bytes());
+    entry.offset = offset as u64;
+    entry.len = len as u64;
+    header.used += len as u64;
+    header.count += 1;
+    header.target_pid = std::process::id();
+    Ok(data.add(offset))
+}
+
+/// The name of the entry, if it is valid utf-8
+#[cfg(feature = "std")]
+fn entry_name(entry: &UserMapEntry) -> Option<&str> {
+    let len = entry
+        .name
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(USER_MAP_NAME_LEN);
+    core::str::from_utf8(&entry.name[..len]).ok()
+}
+
+/// The names and sizes of all maps declared by the harness.
+#[must_use]
+pub fn user_maps() -> Vec<(&'static str, usize)> {
+    unsafe {
+        (*addr_of!(USER_MAPS))
+            .iter()
+            .map(|user_map| (user_map.name, user_map.map.as_slice().len()))
+            .collect()
+    }
+}
+
+/// Gets a new [`StdMapObserver`] for the user map with the given name, if it was declared.
+///
+/// # Safety
+/// The map is shared with the harness, which may write to it at any time.
+#[must_use]
+pub unsafe fn user_map_observer(name: &str) -> Option<StdMapObserver<'static, u8, false>> {
+    (*addr_of!(USER_MAPS))
+        .iter()
+        .find(|user_map| user_map.name == name)
+        .map(|user_map| user_map_to_observer(user_map))
+}
+
+/// Gets a [`StdMapObserver`] for each map declared by the harness.
+///
+/// ```rust,ignore
+/// use libafl::feedbacks::MaxMapFeedback;
+/// use libafl_targets::{libafl_create_map, user_map_observer};
+///
+/// libafl_create_map!(STATE_MAP, "state_map", 4096);
+///
+/// let state_observer = unsafe { user_map_observer("state_map").unwrap() };
+/// let state_feedback = MaxMapFeedback::new(&state_observer);
+/// ```
+///
+/// # Safety
+/// The maps are shared with the harness, which may write to them at any time.
+#[must_use]
+pub unsafe fn user_maps_observers() -> Vec<StdMapObserver<'static, u8, false>> {
+    (*addr_of!(USER_MAPS))
+        .iter()
+        .map(|user_map| user_map_to_observer(user_map))
+        .collect()
+}
+
+unsafe fn user_map_to_observer(user_map: &UserMap) -> StdMapObserver<'static, u8, false> {
+    StdMapObserver::from_mut_slice(
+        Cow::Borrowed(user_map.name),
+        OwnedMutSlice::from_raw_parts_mut(
+            user_map.map.as_slice().as_ptr().cast_mut(),
+            user_map.map.as_slice().len(),
+        ),
+    )
+}
+
+/// The fuzzer side of the maps declared by a harness running in another process.
+///
+/// Create it before spawning the target: it passes its id to the target in
+/// [`USER_MAPS_SHM_ENV`]. At startup, the target checks the magic and version written by the
+/// fuzzer, allocates its maps in the shared memory and acknowledges the handshake with its pid.
+/// Once the target started, e.g. after the forkserver handshake, [`UserMapsShMem::observers`]
+/// returns an observer for each map.
+///
+/// ```rust,ignore
+/// let mut user_maps = UserMapsShMem::new(&mut shmem_provider, 0x10000)?;
+/// let mut executor = ForkserverExecutor::builder()
+///     .program("./target")
+///     .shmem_provider(&mut shmem_provider)
+///     .build(tuple_list!(edges_observer))?;
+/// let state_observer = user_maps.observer("state_map")?.unwrap();
+/// ```
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct UserMapsShMem<SHM> {
+    shmem: SHM,
+}
+
+#[cfg(feature = "std")]
+impl<SHM> UserMapsShMem<SHM>
+where
+    SHM: ShMem,
+{
+    /// Creates a new [`UserMapsShMem`] with room for `maps_size` bytes of maps,
+    /// and passes it to targets spawned afterwards in [`USER_MAPS_SHM_ENV`]
+    pub fn new<SP>(provider: &mut SP, maps_size: usize) -> Result<Self, Error>
+    where
+        SP: ShMemProvider<ShMem = SHM>,
+    {
+        let mut shmem = provider.new_shmem(size_of::<UserMapsHeader>() + maps_size)?;
+        shmem.fill(0);
+        // # Safety
+        // The shared memory is large enough for the header, and the target did not attach yet
+        let header = unsafe { &mut *shmem.as_mut_ptr_of::<UserMapsHeader>().unwrap() };
+        header.magic = USER_MAPS_MAGIC;
+        header.version = USER_MAPS_VERSION;
+        shmem.write_to_env(USER_MAPS_SHM_ENV)?;
+        Ok(Self { shmem })
+    }
+
+    fn header(&self) -> &UserMapsHeader {
+        // # Safety
+        // The shared memory was created large enough for the header in `new`
+        unsafe { &*self.shmem.as_ptr_of::<UserMapsHeader>().unwrap() }
+    }
+
+    /// The pid of the target, once it attached to the shared memory
+    #[must_use]
+    pub fn target_pid(&self) -> Option<u32> {
+        let target_pid = self.header().target_pid;
+        (target_pid != 0).then_some(target_pid)
+    }
+
+    /// The names, offsets and sizes of the maps allocated by the target
+    fn entries(&self) -> Result<Vec<(String, usize, usize)>, Error> {
+        if self.target_pid().is_none() {
+            return Err(Error::illegal_state(
+                "No target attached to the user maps, was it started with this shared memory?",
+            ));
+        }
+        let header = self.header();
+        let data_len = self.shmem.len() - size_of::<UserMapsHeader>();
+        header.entries[..(header.count as usize).min(USER_MAPS_MAX)]
+            .iter()
+            .map(|entry| {
+                let name = entry_name(entry)
+                    .ok_or_else(|| Error::illegal_state("Invalid user map name"))?;
+                let (offset, len) = (entry.offset as usize, entry.len as usize);
+                if !matches!(offset.checked_add(len), Some(end) if end <= data_len) {
+                    return Err(Error::illegal_state(format!(
+                        "The user map {name} is out of bounds"
+                    )));
+                }
+                Ok((name.into(), offset, len))
+            })
+            .collect()
+    }
+
+    /// The names and sizes of the maps declared by the target
+    pub fn maps(&self) -> Result<Vec<(String, usize)>, Error> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .map(|(name, _, len)| (name, len))
+            .collect())
+    }
+
+    /// Gets a [`StdMapObserver`] for each map declared by the target
+    pub fn observers(&mut self) -> Result<Vec<StdMapObserver<'_, u8, false>>, Error> {
+        let entries = self.entries()?;
+        let data = self.shmem[size_of::<UserMapsHeader>()..].as_mut_ptr();
+        Ok(entries
+            .into_iter()
+            .map(|(name, offset, len)| {
+                // # Safety
+                // The maps were checked to be in bounds, and the target allocates them disjoint
+                unsafe {
+                    StdMapObserver::from_mut_slice(
+                        name,
+                        OwnedMutSlice::from_raw_parts_mut(data.add(offset), len),
+                    )
+                }
+            })
+            .collect())
+    }
+
+    /// Gets a [`StdMapObserver`] for the map of the target with the given name, if it was declared
+    pub fn observer(&mut self, name: &str) -> Result<Option<StdMapObserver<'_, u8, false>>, Error> {
+        Ok(self
+            .observers()?
+            .into_iter()
+            .find(|observer| observer.name() == name))
+    }
+}
+
+/// Declare a named map of `u8` entries in the harness, which is automatically registered in
+/// [`USER_MAPS`] at startup.
+/// Use `1` as size to declare a single counter.
+///
+/// The map can be written to by the harness using the given identifier, and observed from the
+/// fuzzer using [`user_map_observer`] or [`user_maps_observers`], or [`UserMapsShMem`] if the
+/// fuzzer runs in another process. In the latter case, the identifier points to the shared
+/// memory of the fuzzer after the registration.
+///
+/// ```rust,ignore
+/// libafl_targets::libafl_create_map!(STATE_MAP, "state_map", 4096);
+///
+/// unsafe { STATE_MAP[state as usize] = 1 };
+/// ```
+///
+/// If the identifier is omitted, the map can only be accessed through [`USER_MAPS`].
+#[macro_export]
+macro_rules! libafl_create_map {
+    ($ident:ident, $name:literal, $size:expr) => {
+        /// A map declared by the harness
+        #[allow(non_upper_case_globals)]
+        pub static mut $ident: &mut [u8] = &mut [0; $size];
+
+        const _: () = {
+            const NAME: &str = $name;
+            const SIZE: usize = $size;
+
+            /// The map, as declared by the harness
+            unsafe fn map() -> *mut &'static mut [u8] {
+                ::core::ptr::addr_of_mut!($ident)
+            }
+
+            /// Automatically register this map
+            #[$crate::ctor]
+            fn register() {
+                // # Safety
+                // This `register` call will always run at startup and never in parallel.
+                unsafe {
+                    let map = map();
+                    *map = ::core::slice::from_raw_parts_mut(
+                        $crate::user_maps::register_user_map(NAME, (*map).as_mut_ptr(), SIZE),
+                        SIZE,
+                    );
+                }
+            }
+        };
+    };
+    ($name:literal, $size:expr) => {
+        const _: () = {
+            const NAME: &str = $name;
+            const SIZE: usize = $size;
+            static mut USER_MAP: [u8; SIZE] = [0; SIZE];
+
+            /// Automatically register this map
+            #[$crate::ctor]
+            fn register() {
+                // # Safety
+                // This `register` call will always run at startup and never in parallel.
+                unsafe {
+                    $crate::user_maps::register_user_map(
+                        NAME,
+                        ::core::ptr::addr_of_mut!(USER_MAP).cast::<u8>(),
+                        SIZE,
+                    );
+                }
+            }
+        };
+    };
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use alloc::string::ToString;
+    use core::ptr::addr_of_mut;
+
+    use libafl_bolts::{
+        shmem::{ShMemProvider, StdShMemProvider},
+        AsSlice, Named,
+    };
+
+    use super::{
+        alloc_user_map, register_user_map, UserMapsShMem, USER_MAPS_MAX, USER_MAPS_SHM_ENV,
+    };
+
+    /// A single test, as the shared memory of the fuzzer is passed in the environment
+    #[test]
+    fn test_user_maps_shmem() {
+        static mut FALLBACK: [u8; 16] = [0; 16];
+
+        let mut provider = StdShMemProvider::new().unwrap();
+        let mut user_maps = UserMapsShMem::new(&mut provider, 64).unwrap();
+        assert!(std::env::var(USER_MAPS_SHM_ENV).is_ok());
+        // The target did not attach yet
+        assert!(user_maps.target_pid().is_none());
+        assert!(user_maps.maps().is_err());
+
+        // # Safety
+        // This test is the only one registering user maps
+        unsafe {
+            let fallback = addr_of_mut!(FALLBACK).cast::<u8>();
+            let map = register_user_map("state_map", fallback, 16);
+            assert_ne!(map, fallback, "the map should be in the shared memory");
+            *map.add(3) = 7;
+            // The same map again
+            assert_eq!(register_user_map("state_map", fallback, 16), map);
+            // Too large for the shared memory
+            assert_eq!(register_user_map("large_map", fallback, 1024), fallback);
+        }
+
+        assert_eq!(user_maps.target_pid(), Some(std::process::id()));
+        assert_eq!(user_maps.maps().unwrap(), [("state_map".to_string(), 16)]);
+        let observer = user_maps.observer("state_map").unwrap().unwrap();
+        assert_eq!(observer.name(), "state_map");
+        assert_eq!(observer.as_slice()[3], 7);
+        assert!(user_maps.observer("large_map").unwrap().is_none());
+        std::env::remove_var(USER_MAPS_SHM_ENV);
+
+        // Without the magic of the fuzzer, the target refuses to allocate maps
+        let mut shmem = provider.new_shmem(0x10000).unwrap();
+        shmem.fill(0);
+        assert!(unsafe { alloc_user_map(&mut shmem, "map", 8) }.is_err());
+
+        let mut user_maps = UserMapsShMem::new(&mut provider, 0x10000).unwrap();
+        std::env::remove_var(USER_MAPS_SHM_ENV);
+        let shmem = &mut user_maps.shmem;
+        let first = unsafe { alloc_user_map(shmem, "map_0", 8) }.unwrap();
+        for i in 1..USER_MAPS_MAX {
+            let map = unsafe { alloc_user_map(shmem, &format!("map_{i}"), 8) }.unwrap();
+            assert_eq!(map as usize, first as usize + i * 8);
+        }
+        // The directory is full, and a map can't change its size
+        assert!(unsafe { alloc_user_map(shmem, "one_too_many", 8) }.is_err());
+        assert!(unsafe { alloc_user_map(shmem, "map_0", 16) }.is_err());
+        assert_eq!(user_maps.maps().unwrap().len(), USER_MAPS_MAX);
+    }
+}
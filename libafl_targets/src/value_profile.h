@@ -15,6 +15,46 @@ extern uint8_t libafl_cmp_map[CMP_MAP_SIZE];
   #define __builtin_popcountll __popcnt64
 #endif
 
+#ifdef SANCOV_VALUE_PROFILE_BYTES
+// Bucketed mode: each comparison site records how many of the low-order
+// operand bytes already match (plus one, so that "executed, nothing matched"
+// is distinguishable from "never executed").
+static inline uint8_t __libafl_targets_value_profile_bucket(uint64_t arg1,
+                                                             uint64_t arg2,
+                                                             uint8_t  size) {
+  uint64_t diff = arg1 ^ arg2;
+  uint8_t  matched = 0;
+  while (matched < size && !(diff & 0xff)) {
+    diff >>= 8;
+    matched++;
+  }
+  return matched + 1;
+}
+
+static void __libafl_targets_value_profile1(uintptr_t k, uint8_t arg1,
+                                            uint8_t arg2) {
+  libafl_cmp_map[k] = MAX(libafl_cmp_map[k],
+                          __libafl_targets_value_profile_bucket(arg1, arg2, 1));
+}
+
+static void __libafl_targets_value_profile2(uintptr_t k, uint16_t arg1,
+                                            uint16_t arg2) {
+  libafl_cmp_map[k] = MAX(libafl_cmp_map[k],
+                          __libafl_targets_value_profile_bucket(arg1, arg2, 2));
+}
+
+static void __libafl_targets_value_profile4(uintptr_t k, uint32_t arg1,
+                                            uint32_t arg2) {
+  libafl_cmp_map[k] = MAX(libafl_cmp_map[k],
+                          __libafl_targets_value_profile_bucket(arg1, arg2, 4));
+}
+
+static void __libafl_targets_value_profile8(uintptr_t k, uint64_t arg1,
+                                            uint64_t arg2) {
+  libafl_cmp_map[k] = MAX(libafl_cmp_map[k],
+                          __libafl_targets_value_profile_bucket(arg1, arg2, 8));
+}
+#else
 static void __libafl_targets_value_profile1(uintptr_t k, uint8_t arg1,
                                             uint8_t arg2) {
   libafl_cmp_map[k] =
@@ -38,5 +78,6 @@ static void __libafl_targets_value_profile8(uintptr_t k, uint64_t arg1,
   libafl_cmp_map[k] =
       MAX(libafl_cmp_map[k], (__builtin_popcountll(~(arg1 ^ arg2))));
 }
+#endif
 
 #endif
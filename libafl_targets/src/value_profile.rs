@@ -1,5 +1,10 @@
 //! Value profile support for `LibAFL`
 
+use alloc::borrow::Cow;
+
+use libafl::{feedbacks::MaxMapFeedback, observers::StdMapObserver};
+use libafl_bolts::ownedref::OwnedMutSlice;
+
 use crate::CMP_MAP_SIZE;
 
 /// The constant cmplog map for the current `LibAFL` target
@@ -8,6 +13,45 @@ pub static mut libafl_cmp_map: [u8; CMP_MAP_SIZE] = [0; CMP_MAP_SIZE];
 
 pub use libafl_cmp_map as CMP_MAP;
 
+/// A [`MaxMapFeedback`] over the value profile map.
+///
+/// Each entry of the map holds the best "closeness" reached by the operands of one comparison
+/// site, so maximizing it rewards inputs that get closer to a magic value.
+pub type ValueProfileFeedback<'a> =
+    MaxMapFeedback<StdMapObserver<'a, u8, false>, StdMapObserver<'a, u8, false>, u8>;
+
+/// Gets the value profile map as [`OwnedMutSlice`].
+///
+/// # Safety
+/// The map is mutated by the `SanitizerCoverage` `trace-cmp` callbacks at any time.
+#[must_use]
+pub unsafe fn value_profile_map_mut_slice<'a>() -> OwnedMutSlice<'a, u8> {
+    OwnedMutSlice::from_raw_parts_mut(CMP_MAP.as_mut_ptr(), CMP_MAP.len())
+}
+
+/// Gets a new [`StdMapObserver`] of the value profile map ([`CMP_MAP`]).
+///
+/// The map is populated by the `sancov_value_profile` feature. By default, each comparison site
+/// stores the number of matching operand bits; with `sancov_value_profile_bytes`, it stores a
+/// bucketed count of the matching low-order operand bytes instead.
+///
+/// ```rust,ignore
+/// use libafl_targets::{value_profile_observer, ValueProfileFeedback};
+///
+/// let observer = unsafe { value_profile_observer("value_profile") };
+/// let feedback = ValueProfileFeedback::new(&observer);
+/// ```
+///
+/// # Safety
+/// This observes a `static mut` map that is written to by the instrumentation.
+#[must_use]
+pub unsafe fn value_profile_observer<'a, S>(name: S) -> StdMapObserver<'a, u8, false>
+where
+    S: Into<Cow<'static, str>>,
+{
+    StdMapObserver::from_mut_slice(name, value_profile_map_mut_slice())
+}
+
 /*
 extern {
     #[link_name = "llvm.returnaddress"]
@@ -24,3 +68,50 @@ pub unsafe extern "C" fn __sanitizer_cov_trace_cmp1(arg1: u8, arg2: u8) {
 */
 
 // TODO complete when linking to LLVM intrinsic will land to stable Rust
+
+#[cfg(all(test, feature = "sancov_value_profile_bytes"))]
+mod tests {
+    use core::hint::black_box;
+
+    use libafl::observers::MapObserver;
+
+    use super::{value_profile_observer, CMP_MAP};
+    use crate::sancov_cmp::__sanitizer_cov_trace_cmp4;
+
+    /// A single call site, so that all the comparisons land in the same map entry
+    #[inline(never)]
+    fn trace_cmp4(arg1: u32, arg2: u32) {
+        unsafe { __sanitizer_cov_trace_cmp4(arg1, arg2) };
+        // Keeps the call from becoming a tail call, which would move the call site to the caller
+        black_box(arg1);
+    }
+
+    #[test]
+    fn test_value_profile_bytes() {
+        unsafe { (*core::ptr::addr_of_mut!(CMP_MAP)).fill(0) };
+        let observer = unsafe { value_profile_observer("value_profile") };
+
+        // The entry is the number of matching low-order bytes, plus one
+        let mut entry = None;
+        for (arg2, expected) in [
+            (0x5566_7788, 1),
+            (0x5566_7744, 2),
+            (0x5566_3344, 3),
+            (0x5522_3344, 4),
+            (0x1122_3344, 5),
+        ] {
+            trace_cmp4(0x1122_3344, arg2);
+            let idx = *entry.get_or_insert_with(|| {
+                (0..observer.usable_count())
+                    .find(|&idx| observer.get(idx) != 0)
+                    .unwrap()
+            });
+            assert_eq!(observer.get(idx), expected);
+            assert_eq!(observer.count_bytes(), 1);
+        }
+
+        // A matching high-order byte does not count, and the entry keeps its maximum
+        trace_cmp4(0x1122_3344, 0x1122_3300);
+        assert_eq!(observer.get(entry.unwrap()), 5);
+    }
+}